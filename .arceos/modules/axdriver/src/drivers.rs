@@ -5,6 +5,9 @@
 use crate::AxDeviceEnum;
 use axdriver_base::DeviceType;
 
+#[cfg(feature = "block")]
+use crate::queue::BlockQueueOps;
+
 #[cfg(feature = "virtio")]
 use crate::virtio::{self, VirtIoDevMeta};
 
@@ -56,6 +59,8 @@ cfg_if::cfg_if! {
         pub struct RamDiskDriver;
         register_block_driver!(RamDiskDriver, axdriver_block::ramdisk::RamDisk);
 
+        impl BlockQueueOps for axdriver_block::ramdisk::RamDisk {}
+
         impl DriverProbe for RamDiskDriver {
             fn probe_global() -> Option<AxDeviceEnum> {
                 // TODO: format RAM disk
@@ -72,6 +77,8 @@ cfg_if::cfg_if! {
         pub struct BcmSdhciDriver;
         register_block_driver!(MmckDriver, axdriver_block::bcm2835sdhci::SDHCIDriver);
 
+        impl BlockQueueOps for axdriver_block::bcm2835sdhci::SDHCIDriver {}
+
         impl DriverProbe for BcmSdhciDriver {
             fn probe_global() -> Option<AxDeviceEnum> {
                 debug!("mmc probe");
@@ -47,9 +47,14 @@
 //!   devices is selected. If this feature is enabled without any network device
 //!   features, a dummy struct is used for [`AxNetDevice`].
 //! - `block`: use block storage devices. Similar to the `net` feature.
+//!   Reads can be queued on them with [`BlockQueueOps`], several at once
+//!   on a VirtIO block device.
 //! - `display`: use graphics display devices. Similar to the `net` feature.
+//! - `irq`: find the IRQs of VirtIO MMIO block devices, for the completion
+//!   of queued reads to be waited for rather than polled.
 //!
 //! [`VirtioNetDev`]: axdriver_virtio::VirtIoNetDev
+//! [`BlockQueueOps`]: prelude::BlockQueueOps
 //! [`Box<dyn NetDriverOps>`]: axdriver_net::NetDriverOps
 //! [trait objects]: https://doc.rust-lang.org/book/ch17-02-trait-objects.html
 //! [dyn]: https://doc.rust-lang.org/std/keyword.dyn.html
@@ -72,6 +77,9 @@ mod drivers;
 mod dummy;
 mod structs;
 
+#[cfg(feature = "block")]
+mod queue;
+
 #[cfg(feature = "virtio")]
 mod virtio;
 
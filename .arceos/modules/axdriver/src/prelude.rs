@@ -3,7 +3,9 @@
 pub use axdriver_base::{BaseDriverOps, DevError, DevResult, DeviceType};
 
 #[cfg(feature = "block")]
-pub use {crate::structs::AxBlockDevice, axdriver_block::BlockDriverOps};
+pub use {
+    crate::queue::BlockQueueOps, crate::structs::AxBlockDevice, axdriver_block::BlockDriverOps,
+};
 #[cfg(feature = "display")]
 pub use {crate::structs::AxDisplayDevice, axdriver_display::DisplayDriverOps};
 #[cfg(feature = "net")]
@@ -0,0 +1,49 @@
+//! Reads queued on block devices, several outstanding at once.
+
+use axdriver_base::{DevError, DevResult};
+use axdriver_block::BlockDriverOps;
+
+/// A block device on which reads can be queued, several outstanding at
+/// once and completing in any order, which may raise an IRQ as each one
+/// completes.
+///
+/// The default methods are those of a device which cannot queue reads, and
+/// only reads with [`BlockDriverOps::read_block`], one request at a time.
+pub trait BlockQueueOps: BlockDriverOps {
+    /// The most reads which can be outstanding at once, 1 if reads cannot
+    /// be queued.
+    fn queue_depth(&self) -> usize {
+        1
+    }
+
+    /// The IRQ raised as a queued read completes, if the device has one.
+    /// Without it, the completions have to be polled for.
+    fn completion_irq(&self) -> Option<usize> {
+        None
+    }
+
+    /// Queue a read of whole blocks from `block_id` into `buf`, returning
+    /// the token [`BlockQueueOps::poll_read`] tells the read by.
+    ///
+    /// Fails with [`DevError::Again`] if [`BlockQueueOps::queue_depth`]
+    /// reads are outstanding already.
+    ///
+    /// # Safety
+    ///
+    /// `buf` must stay valid, and be neither read nor written, and the
+    /// device must not be moved, until the read has completed.
+    unsafe fn start_read(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult<usize> {
+        let _ = (block_id, buf);
+        Err(DevError::Unsupported)
+    }
+
+    /// Finish a queued read which has completed, returning its token and
+    /// whether it succeeded, or `None` if none has completed yet.
+    fn poll_read(&mut self) -> Option<(usize, DevResult)> {
+        None
+    }
+
+    /// Acknowledge the IRQ of the completions so far, so that the device
+    /// raises it again for the next one.
+    fn ack_completions(&mut self) {}
+}
@@ -8,7 +8,7 @@ use alloc::{boxed::Box, vec, vec::Vec};
 pub type AxNetDevice = Box<dyn NetDriverOps>;
 /// The unified type of the block storage devices.
 #[cfg(feature = "block")]
-pub type AxBlockDevice = Box<dyn BlockDriverOps>;
+pub type AxBlockDevice = Box<dyn BlockQueueOps>;
 /// The unified type of the graphics display devices.
 #[cfg(feature = "display")]
 pub type AxDisplayDevice = Box<dyn DisplayDriverOps>;
@@ -22,7 +22,7 @@ impl super::AxDeviceEnum {
 
     /// Constructs a block device.
     #[cfg(feature = "block")]
-    pub fn from_block(dev: impl BlockDriverOps + 'static) -> Self {
+    pub fn from_block(dev: impl BlockQueueOps + 'static) -> Self {
         Self::Block(Box::new(dev))
     }
 
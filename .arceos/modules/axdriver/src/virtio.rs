@@ -9,6 +9,14 @@ use cfg_if::cfg_if;
 
 use crate::{AxDeviceEnum, drivers::DriverProbe};
 
+#[cfg(block_dev = "virtio-blk")]
+use {
+    crate::queue::BlockQueueOps,
+    axdriver_base::DevError,
+    axdriver_block::BlockDriverOps,
+    virtio_drivers::device::blk::{BlkReq, BlkResp, SECTOR_SIZE, VirtIOBlk},
+};
+
 cfg_if! {
     if #[cfg(bus = "pci")] {
         use axdriver_pci::{PciRoot, DeviceFunction, DeviceFunctionInfo};
@@ -25,7 +33,9 @@ pub trait VirtIoDevMeta {
     type Device: BaseDriverOps;
     type Driver = VirtIoDriver<Self>;
 
-    fn try_new(transport: VirtIoTransport) -> DevResult<AxDeviceEnum>;
+    /// Initializes the device on `transport`, which raises `irq`, if it is
+    /// known.
+    fn try_new(transport: VirtIoTransport, irq: Option<usize>) -> DevResult<AxDeviceEnum>;
 }
 
 cfg_if! {
@@ -36,7 +46,7 @@ cfg_if! {
             const DEVICE_TYPE: DeviceType = DeviceType::Net;
             type Device = axdriver_virtio::VirtIoNetDev<VirtIoHalImpl, VirtIoTransport, 64>;
 
-            fn try_new(transport: VirtIoTransport) -> DevResult<AxDeviceEnum> {
+            fn try_new(transport: VirtIoTransport, _irq: Option<usize>) -> DevResult<AxDeviceEnum> {
                 Ok(AxDeviceEnum::from_net(Self::Device::try_new(transport)?))
             }
         }
@@ -49,10 +59,154 @@ cfg_if! {
 
         impl VirtIoDevMeta for VirtIoBlk {
             const DEVICE_TYPE: DeviceType = DeviceType::Block;
-            type Device = axdriver_virtio::VirtIoBlkDev<VirtIoHalImpl, VirtIoTransport>;
+            type Device = VirtIoBlkDev;
 
-            fn try_new(transport: VirtIoTransport) -> DevResult<AxDeviceEnum> {
-                Ok(AxDeviceEnum::from_block(Self::Device::try_new(transport)?))
+            fn try_new(transport: VirtIoTransport, irq: Option<usize>) -> DevResult<AxDeviceEnum> {
+                Ok(AxDeviceEnum::from_block(Self::Device::try_new(transport, irq)?))
+            }
+        }
+
+        /// The most reads queued at once on a VirtIO block device. Each takes
+        /// 3 of the 16 descriptors of the queue `virtio-drivers` sets up.
+        const MAX_QUEUED_READS: usize = 4;
+
+        /// A read queued on a VirtIO block device. The device writes the
+        /// status of the read into `resp`, so it must not move until then.
+        struct QueuedRead {
+            token: u16,
+            req: BlkReq,
+            resp: BlkResp,
+            buf: NonNull<[u8]>,
+        }
+
+        /// A VirtIO block device, on which up to [`MAX_QUEUED_READS`] reads
+        /// can be queued at once.
+        pub struct VirtIoBlkDev {
+            inner: VirtIOBlk<VirtIoHalImpl, VirtIoTransport>,
+            irq: Option<usize>,
+            /// The reads queued, by the token `start_read` returned.
+            queued: [Option<QueuedRead>; MAX_QUEUED_READS],
+        }
+
+        // SAFETY: the buffers of the queued reads are only accessed through
+        // `&mut self`, and their callers keep them valid meanwhile.
+        unsafe impl Send for VirtIoBlkDev {}
+        unsafe impl Sync for VirtIoBlkDev {}
+
+        impl VirtIoBlkDev {
+            fn try_new(transport: VirtIoTransport, irq: Option<usize>) -> DevResult<Self> {
+                Ok(Self {
+                    inner: VirtIOBlk::new(transport).map_err(as_dev_err)?,
+                    irq,
+                    queued: Default::default(),
+                })
+            }
+        }
+
+        impl BaseDriverOps for VirtIoBlkDev {
+            fn device_name(&self) -> &str {
+                "virtio-blk"
+            }
+
+            fn device_type(&self) -> DeviceType {
+                DeviceType::Block
+            }
+        }
+
+        impl BlockDriverOps for VirtIoBlkDev {
+            fn num_blocks(&self) -> u64 {
+                self.inner.capacity()
+            }
+
+            fn block_size(&self) -> usize {
+                SECTOR_SIZE
+            }
+
+            fn read_block(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult {
+                self.inner.read_blocks(block_id as _, buf).map_err(as_dev_err)
+            }
+
+            fn write_block(&mut self, block_id: u64, buf: &[u8]) -> DevResult {
+                self.inner.write_blocks(block_id as _, buf).map_err(as_dev_err)
+            }
+
+            fn flush(&mut self) -> DevResult {
+                Ok(())
+            }
+        }
+
+        impl BlockQueueOps for VirtIoBlkDev {
+            fn queue_depth(&self) -> usize {
+                MAX_QUEUED_READS
+            }
+
+            fn completion_irq(&self) -> Option<usize> {
+                self.irq
+            }
+
+            unsafe fn start_read(&mut self, block_id: u64, buf: &mut [u8]) -> DevResult<usize> {
+                let slot = self
+                    .queued
+                    .iter()
+                    .position(Option::is_none)
+                    .ok_or(DevError::Again)?;
+                let read = self.queued[slot].insert(QueuedRead {
+                    token: 0,
+                    req: BlkReq::default(),
+                    resp: BlkResp::default(),
+                    buf: NonNull::from(&mut *buf),
+                });
+                // SAFETY: `req`, `resp` and `buf` stay where they are until the
+                // read completes, as the caller of `start_read` guarantees.
+                match unsafe {
+                    self.inner
+                        .read_blocks_nb(block_id as _, &mut read.req, buf, &mut read.resp)
+                } {
+                    Ok(token) => {
+                        read.token = token;
+                        Ok(slot)
+                    }
+                    Err(e) => {
+                        self.queued[slot] = None;
+                        Err(as_dev_err(e))
+                    }
+                }
+            }
+
+            fn poll_read(&mut self) -> Option<(usize, DevResult)> {
+                let token = self.inner.peek_used()?;
+                let slot = self
+                    .queued
+                    .iter()
+                    .position(|read| read.as_ref().is_some_and(|read| read.token == token))?;
+                let read = self.queued[slot].as_mut().unwrap();
+                // SAFETY: the read has completed, with the same buffers as it
+                // was queued with, which are still valid.
+                let res = unsafe {
+                    self.inner
+                        .complete_read_blocks(token, &read.req, read.buf.as_mut(), &mut read.resp)
+                };
+                self.queued[slot] = None;
+                Some((slot, res.map_err(as_dev_err)))
+            }
+
+            fn ack_completions(&mut self) {
+                self.inner.ack_interrupt();
+            }
+        }
+
+        fn as_dev_err(e: virtio_drivers::Error) -> DevError {
+            use virtio_drivers::Error::*;
+            match e {
+                QueueFull => DevError::BadState,
+                NotReady => DevError::Again,
+                WrongToken => DevError::BadState,
+                AlreadyUsed => DevError::AlreadyExists,
+                InvalidParam => DevError::InvalidParam,
+                DmaError => DevError::NoMemory,
+                IoError => DevError::Io,
+                Unsupported => DevError::Unsupported,
+                _ => DevError::BadState,
             }
         }
     }
@@ -66,7 +220,7 @@ cfg_if! {
             const DEVICE_TYPE: DeviceType = DeviceType::Display;
             type Device = axdriver_virtio::VirtIoGpuDev<VirtIoHalImpl, VirtIoTransport>;
 
-            fn try_new(transport: VirtIoTransport) -> DevResult<AxDeviceEnum> {
+            fn try_new(transport: VirtIoTransport, _irq: Option<usize>) -> DevResult<AxDeviceEnum> {
                 Ok(AxDeviceEnum::from_display(Self::Device::try_new(transport)?))
             }
         }
@@ -84,7 +238,7 @@ impl<D: VirtIoDevMeta> DriverProbe for VirtIoDriver<D> {
             axdriver_virtio::probe_mmio_device(base_vaddr.as_mut_ptr(), mmio_size)
             && ty == D::DEVICE_TYPE
         {
-            match D::try_new(transport) {
+            match D::try_new(transport, mmio_irq(mmio_base, mmio_size)) {
                 Ok(dev) => return Some(dev),
                 Err(e) => {
                     warn!(
@@ -120,7 +274,9 @@ impl<D: VirtIoDevMeta> DriverProbe for VirtIoDriver<D> {
             axdriver_virtio::probe_pci_device::<VirtIoHalImpl>(root, bdf, dev_info)
         {
             if ty == D::DEVICE_TYPE {
-                match D::try_new(transport) {
+                // The IRQs of PCI devices are not routed, their completions
+                // are polled for.
+                match D::try_new(transport, None) {
                     Ok(dev) => return Some(dev),
                     Err(e) => {
                         warn!(
@@ -136,6 +292,22 @@ impl<D: VirtIoDevMeta> DriverProbe for VirtIoDriver<D> {
     }
 }
 
+/// The IRQ of the VirtIO MMIO device at `mmio_base`, from its index in the
+/// `virtio-mmio-regions`, which are laid out `mmio_size` apart.
+#[cfg(bus = "mmio")]
+fn mmio_irq(mmio_base: usize, mmio_size: usize) -> Option<usize> {
+    #[cfg(feature = "irq")]
+    {
+        let first = axconfig::devices::VIRTIO_MMIO_REGIONS.first()?.0;
+        axhal::irq::virtio_mmio_irq(mmio_base.checked_sub(first)? / mmio_size)
+    }
+    #[cfg(not(feature = "irq"))]
+    {
+        let _ = (mmio_base, mmio_size);
+        None
+    }
+}
+
 pub struct VirtIoHalImpl;
 
 unsafe impl VirtIoHal for VirtIoHalImpl {
@@ -5,9 +5,12 @@ mod file;
 
 pub use self::dir::{DirBuilder, DirEntry, ReadDir};
 pub use self::file::{File, FileType, Metadata, OpenOptions, Permissions};
+pub use crate::root::DetachedFs;
+pub use crate::time::FileTimes;
 
 use alloc::{string::String, vec::Vec};
 use axio::{self as io, prelude::*};
+use core::time::Duration;
 
 /// Returns an iterator over the entries within a directory.
 pub fn read_dir(path: &str) -> io::Result<ReadDir> {
@@ -92,3 +95,48 @@ pub fn rename(old: &str, new: &str) -> io::Result<()> {
 pub fn absolute_path_exists(path: &str) -> bool {
     crate::root::lookup(None, path).is_ok()
 }
+
+/// Reads the times of the file or directory at `path` from its entry in
+/// its directory, if it is on a FAT filesystem.
+///
+/// Fails with `Unsupported` if it is not, or has no entry, as the root
+/// directory of a filesystem.
+pub fn fat_times(path: &str) -> io::Result<FileTimes> {
+    crate::root::fat_times(path)
+}
+
+/// The newest modification time of the files and directories under the
+/// directory `path`, at any depth, if it is on a FAT filesystem.
+///
+/// Fails with `Unsupported` if it is not. The filesystems mounted under it
+/// are not looked at.
+pub fn newest_fat_modified(path: &str) -> io::Result<Duration> {
+    crate::root::newest_fat_modified(path)
+}
+
+/// Mounts the FAT image in the regular file `image` on the directory `path`.
+///
+/// The filesystem reads and writes through the file, which is flushed on
+/// [`umount`].
+pub fn mount_fat_image(image: &str, path: &str) -> io::Result<()> {
+    crate::root::mount_fat_image(image, path)
+}
+
+/// Mounts an empty RAM filesystem on the directory `path`.
+pub fn mount_ramfs(path: &str) -> io::Result<()> {
+    crate::root::mount_ramfs(path)
+}
+
+/// Unmounts the filesystem mounted on `path`.
+pub fn umount(path: &str) -> io::Result<()> {
+    crate::root::umount(path)
+}
+
+/// Takes the filesystem mounted on `path` off the directory tree, so that
+/// lookups no longer reach it, and returns it.
+///
+/// Files already open on it keep working. It is flushed and unmounted once
+/// the returned [`DetachedFs`] is dropped.
+pub fn detach(path: &str) -> io::Result<DetachedFs> {
+    crate::root::detach(path)
+}
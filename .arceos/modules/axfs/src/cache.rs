@@ -0,0 +1,447 @@
+//! A write-back cache of the blocks of a block device, between the
+//! filesystem on the device and its driver.
+//!
+//! Reads and writes of single blocks, which the filesystem makes for the
+//! partial blocks of small writes and for its own metadata, like the FAT,
+//! go through the cache. A write only changes the cached block and marks it
+//! dirty, so appending a line at a time to a file costs no device access
+//! until the block is written back. Larger transfers, of whole clusters of
+//! file data, go to the device directly, keeping any cached copy in step.
+//!
+//! Dirty blocks are written back when they are evicted, least recently
+//! used first, and when the device is synced, as `fsync`, `fdatasync`,
+//! `sync`, unmounting and powering off do. Runs of adjacent dirty blocks are
+//! written back in a single device request. Blocks discarded, as the free
+//! clusters of a filesystem are, see [`crate::trim`], are dropped without
+//! being written back.
+//!
+//! # Crash consistency
+//!
+//! Data is only sure to be on the device once it has been synced. A crash
+//! or a power loss before loses the writes since the last sync, though they
+//! have returned, and may keep only some of them: blocks are written back
+//! as they are evicted and in the order of their numbers, not in the order
+//! they were written. The FAT, a directory entry and the data of a file can
+//! then be out of step, as after any unclean unmount of a FAT filesystem,
+//! which `fsck.vfat` repairs.
+//!
+//! # Readahead
+//!
+//! A run of blocks read right after the one before it, as a file is read
+//! from start to end, and an ELF binary loaded, is read ahead: the next
+//! `READAHEAD_BLOCKS` are read at once, in requests queued on the device
+//! together, see [`crate::queue`], and the next reads are served from them
+//! until they run past. Writes to the device drop what was read ahead of
+//! the blocks written.
+
+use alloc::{boxed::Box, collections::BTreeMap, vec, vec::Vec};
+
+use axdriver::prelude::*;
+use axsync::Mutex;
+
+#[cfg(feature = "fault-inject")]
+use crate::dev::{FaultPoint, inject_fault};
+use crate::{
+    dev::{BLOCK_SIZE, MAX_BATCH_BLOCKS, io_wait},
+    queue::read_queued,
+    trim::CleanMap,
+};
+
+/// The number of blocks cached for each device (2 MiB).
+const CACHE_BLOCKS: usize = 4096;
+
+/// The number of blocks read ahead of a sequential reader (512 KiB).
+const READAHEAD_BLOCKS: usize = 1024;
+
+/// Counters of the cache of a block device, shown in `/proc/starry/fscache`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    /// The most blocks the cache holds.
+    pub capacity: usize,
+    /// The blocks in the cache.
+    pub cached: usize,
+    /// The blocks in the cache not yet written back.
+    pub dirty: usize,
+    /// The blocks read or written which were in the cache.
+    pub hits: u64,
+    /// The blocks read or written which had to be fetched from the device.
+    pub misses: u64,
+    /// The dirty blocks written back.
+    pub written_back: u64,
+    /// The device requests the dirty blocks were written back with.
+    pub writeback_requests: u64,
+    /// The blocks read which had been read ahead.
+    pub read_ahead: u64,
+}
+
+/// Read whole blocks from `block_id` of `dev` into `buf`.
+fn read_blocks(dev: &Mutex<AxBlockDevice>, block_id: u64, buf: &mut [u8]) -> DevResult {
+    #[cfg(feature = "fault-inject")]
+    inject_fault(FaultPoint::BlockRead)?;
+    io_wait(|| dev.lock().read_block(block_id, buf))
+}
+
+/// Write whole blocks from `buf` at `block_id` of `dev`.
+fn write_blocks(dev: &Mutex<AxBlockDevice>, block_id: u64, buf: &[u8]) -> DevResult {
+    #[cfg(feature = "fault-inject")]
+    inject_fault(FaultPoint::BlockWrite)?;
+    io_wait(|| dev.lock().write_block(block_id, buf))
+}
+
+/// The blocks read ahead from `start`.
+struct Readahead {
+    start: u64,
+    data: Vec<u8>,
+}
+
+impl Readahead {
+    /// The range of blocks read ahead.
+    fn blocks(&self) -> core::ops::Range<u64> {
+        self.start..self.start + (self.data.len() / BLOCK_SIZE) as u64
+    }
+}
+
+struct CachedBlock {
+    data: Box<[u8; BLOCK_SIZE]>,
+    dirty: bool,
+    /// The tick of the last use, the key of the block in `BlockCache::lru`.
+    tick: u64,
+}
+
+/// The cached blocks of a device, bounded to `CACHE_BLOCKS`.
+pub(crate) struct BlockCache {
+    blocks: BTreeMap<u64, CachedBlock>,
+    /// The cached blocks by their last use, least recent first.
+    lru: BTreeMap<u64, u64>,
+    tick: u64,
+    stats: CacheStats,
+    /// Bounce buffer for writing back runs of blocks.
+    bounce: Vec<u8>,
+    /// The clusters clean of the filesystem on the device, if it is trimmed,
+    /// see [`crate::trim`].
+    clean: Option<CleanMap>,
+    readahead: Option<Readahead>,
+    /// The block after the last run read, which the next one starts at if
+    /// the device is read sequentially.
+    next_run: u64,
+}
+
+impl BlockCache {
+    pub fn new() -> Self {
+        Self {
+            blocks: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+            stats: CacheStats {
+                capacity: CACHE_BLOCKS,
+                ..Default::default()
+            },
+            bounce: Vec::new(),
+            clean: None,
+            readahead: None,
+            next_run: 0,
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            cached: self.blocks.len(),
+            ..self.stats
+        }
+    }
+
+    /// Mark `block_id` as the most recently used block.
+    fn touch(&mut self, block_id: u64) {
+        let Some(block) = self.blocks.get_mut(&block_id) else {
+            return;
+        };
+        self.lru.remove(&block.tick);
+        self.tick += 1;
+        block.tick = self.tick;
+        self.lru.insert(self.tick, block_id);
+    }
+
+    /// Get the cached block `block_id`, reading it from `dev` on a miss.
+    fn get(&mut self, dev: &Mutex<AxBlockDevice>, block_id: u64) -> DevResult<&mut CachedBlock> {
+        if self.blocks.contains_key(&block_id) {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+            #[cfg(feature = "fault-inject")]
+            inject_fault(FaultPoint::Cache)?;
+            let mut data = Box::new([0u8; BLOCK_SIZE]);
+            if !self.read_ahead(block_id, &mut data[..]) {
+                read_blocks(dev, block_id, &mut data[..])?;
+            }
+            self.insert(dev, block_id, data, false)?;
+        }
+        self.touch(block_id);
+        Ok(self.blocks.get_mut(&block_id).unwrap())
+    }
+
+    /// Add `block_id`, which is not cached, evicting the least recently used
+    /// block if the cache is full.
+    fn insert(
+        &mut self,
+        dev: &Mutex<AxBlockDevice>,
+        block_id: u64,
+        data: Box<[u8; BLOCK_SIZE]>,
+        dirty: bool,
+    ) -> DevResult {
+        if self.blocks.len() >= CACHE_BLOCKS {
+            let (_, &victim) = self.lru.first_key_value().unwrap();
+            if self.blocks[&victim].dirty {
+                self.write_back_run(dev, victim)?;
+            }
+            let block = self.blocks.remove(&victim).unwrap();
+            self.lru.remove(&block.tick);
+        }
+        if dirty {
+            self.stats.dirty += 1;
+        }
+        self.blocks.insert(
+            block_id,
+            CachedBlock {
+                data,
+                dirty,
+                tick: 0,
+            },
+        );
+        self.touch(block_id);
+        Ok(())
+    }
+
+    /// Write back the run of dirty blocks around `block_id` in a single
+    /// request, as long as it fits in one.
+    fn write_back_run(&mut self, dev: &Mutex<AxBlockDevice>, block_id: u64) -> DevResult {
+        let is_dirty = |id: u64| self.blocks.get(&id).is_some_and(|block| block.dirty);
+        let mut start = block_id;
+        while start > 0 && block_id - start + 1 < MAX_BATCH_BLOCKS as u64 && is_dirty(start - 1) {
+            start -= 1;
+        }
+        let mut end = block_id + 1;
+        while end - start < MAX_BATCH_BLOCKS as u64 && is_dirty(end) {
+            end += 1;
+        }
+        self.write_back(dev, start, end)
+    }
+
+    /// Write back the blocks from `start` to `end`, which are all cached and
+    /// dirty, in a single request.
+    fn write_back(&mut self, dev: &Mutex<AxBlockDevice>, start: u64, end: u64) -> DevResult {
+        let count = (end - start) as usize;
+        self.bounce.resize(count * BLOCK_SIZE, 0);
+        for (chunk, (_, block)) in self
+            .bounce
+            .chunks_exact_mut(BLOCK_SIZE)
+            .zip(self.blocks.range(start..end))
+        {
+            chunk.copy_from_slice(&block.data[..]);
+        }
+        self.drop_read_ahead(start, end);
+        write_blocks(dev, start, &self.bounce)?;
+        for (_, block) in self.blocks.range_mut(start..end) {
+            block.dirty = false;
+        }
+        self.stats.dirty -= count;
+        self.stats.written_back += count as u64;
+        self.stats.writeback_requests += 1;
+        Ok(())
+    }
+
+    /// Copy the blocks from `block_id` into `buf` if they were all read
+    /// ahead. Returns whether they were.
+    fn read_ahead(&mut self, block_id: u64, buf: &mut [u8]) -> bool {
+        let Some(readahead) = &self.readahead else {
+            return false;
+        };
+        let count = (buf.len() / BLOCK_SIZE) as u64;
+        let blocks = readahead.blocks();
+        if !blocks.contains(&block_id) || block_id + count > blocks.end {
+            return false;
+        }
+        let offset = (block_id - blocks.start) as usize * BLOCK_SIZE;
+        buf.copy_from_slice(&readahead.data[offset..offset + buf.len()]);
+        self.stats.read_ahead += count;
+        true
+    }
+
+    /// Read ahead the blocks from `block_id` past a run of `count` blocks
+    /// read from it, up to [`READAHEAD_BLOCKS`] and the end of the device.
+    fn fill_read_ahead(
+        &mut self,
+        dev: &Mutex<AxBlockDevice>,
+        block_id: u64,
+        count: u64,
+    ) -> DevResult {
+        let len = dev
+            .lock()
+            .num_blocks()
+            .saturating_sub(block_id)
+            .min(READAHEAD_BLOCKS as u64);
+        if len <= count {
+            return Ok(());
+        }
+        let mut data = self.readahead.take().map_or_else(Vec::new, |ra| ra.data);
+        data.resize(len as usize * BLOCK_SIZE, 0);
+        read_queued(dev, block_id, &mut data)?;
+        self.readahead = Some(Readahead {
+            start: block_id,
+            data,
+        });
+        Ok(())
+    }
+
+    /// Drop what was read ahead if it overlaps the blocks from `start` to
+    /// `end`, as they are about to be written to the device.
+    fn drop_read_ahead(&mut self, start: u64, end: u64) {
+        if self.readahead.as_ref().is_some_and(|readahead| {
+            let blocks = readahead.blocks();
+            blocks.start < end && start < blocks.end
+        }) {
+            self.readahead = None;
+        }
+    }
+
+    /// Read whole blocks from `block_id` into `buf`.
+    ///
+    /// A single block is cached, while a run of blocks is read from the
+    /// device, with the cached blocks in it copied over, so that reading a
+    /// large file does not push the metadata out of the cache. A run right
+    /// after the one before is read ahead, see the module docs.
+    pub fn read(&mut self, dev: &Mutex<AxBlockDevice>, block_id: u64, buf: &mut [u8]) -> DevResult {
+        if buf.len() == BLOCK_SIZE {
+            buf.copy_from_slice(&self.get(dev, block_id)?.data[..]);
+            return Ok(());
+        }
+        let count = (buf.len() / BLOCK_SIZE) as u64;
+        let end = block_id + count;
+        let sequential = block_id == self.next_run;
+        self.next_run = end;
+        let cached = self.blocks.range(block_id..end).count();
+        if cached < buf.len() / BLOCK_SIZE && !self.read_ahead(block_id, buf) {
+            if sequential {
+                self.fill_read_ahead(dev, block_id, count)?;
+            }
+            if !self.read_ahead(block_id, buf) {
+                read_blocks(dev, block_id, buf)?;
+            }
+        }
+        self.stats.hits += cached as u64;
+        self.stats.misses += (buf.len() / BLOCK_SIZE - cached) as u64;
+        for (&id, block) in self.blocks.range(block_id..end) {
+            let offset = (id - block_id) as usize * BLOCK_SIZE;
+            buf[offset..offset + BLOCK_SIZE].copy_from_slice(&block.data[..]);
+        }
+        Ok(())
+    }
+
+    /// Write whole blocks from `buf` at `block_id`.
+    ///
+    /// A single block is only written to the cache, and marked dirty, while
+    /// a run of blocks is written to the device, and the cached blocks in it
+    /// updated and marked clean.
+    pub fn write(&mut self, dev: &Mutex<AxBlockDevice>, block_id: u64, buf: &[u8]) -> DevResult {
+        if let Some(clean) = &mut self.clean {
+            clean.written(block_id, buf.len() / BLOCK_SIZE);
+        }
+        if buf.len() == BLOCK_SIZE {
+            let data: &[u8; BLOCK_SIZE] = buf.try_into().unwrap();
+            if let Some(block) = self.blocks.get_mut(&block_id) {
+                self.stats.hits += 1;
+                *block.data = *data;
+                if !block.dirty {
+                    block.dirty = true;
+                    self.stats.dirty += 1;
+                }
+                self.touch(block_id);
+            } else {
+                // Overwritten as a whole, so never read.
+                self.stats.misses += 1;
+                #[cfg(feature = "fault-inject")]
+                inject_fault(FaultPoint::Cache)?;
+                self.insert(dev, block_id, Box::new(*data), true)?;
+            }
+            return Ok(());
+        }
+        let end = block_id + (buf.len() / BLOCK_SIZE) as u64;
+        self.drop_read_ahead(block_id, end);
+        write_blocks(dev, block_id, buf)?;
+        for (&id, block) in self.blocks.range_mut(block_id..end) {
+            let offset = (id - block_id) as usize * BLOCK_SIZE;
+            block
+                .data
+                .copy_from_slice(&buf[offset..offset + BLOCK_SIZE]);
+            if block.dirty {
+                block.dirty = false;
+                self.stats.dirty -= 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write back every dirty block, in runs of adjacent blocks.
+    pub fn sync(&mut self, dev: &Mutex<AxBlockDevice>) -> DevResult {
+        let mut runs = vec![];
+        let mut run: Option<(u64, u64)> = None;
+        for (&id, block) in &self.blocks {
+            if !block.dirty {
+                continue;
+            }
+            match &mut run {
+                Some((start, end)) if *end == id && id - *start < MAX_BATCH_BLOCKS as u64 => {
+                    *end += 1;
+                }
+                _ => runs.extend(run.replace((id, id + 1))),
+            }
+        }
+        runs.extend(run);
+        for (start, end) in runs {
+            self.write_back(dev, start, end)?;
+        }
+        Ok(())
+    }
+    /// Discard `count` blocks from `block_id`, dropping their cached copies,
+    /// dirty or not, and writing zeros over them, in as few requests as
+    /// possible. Returns the number of requests.
+    pub fn discard(
+        &mut self,
+        dev: &Mutex<AxBlockDevice>,
+        block_id: u64,
+        count: u64,
+    ) -> DevResult<u64> {
+        let end = block_id + count;
+        let cached: Vec<u64> = self
+            .blocks
+            .range(block_id..end)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in cached {
+            let block = self.blocks.remove(&id).unwrap();
+            self.lru.remove(&block.tick);
+            if block.dirty {
+                self.stats.dirty -= 1;
+            }
+        }
+        self.drop_read_ahead(block_id, end);
+        self.bounce.clear();
+        self.bounce.resize(MAX_BATCH_BLOCKS * BLOCK_SIZE, 0);
+        let mut requests = 0;
+        for start in (block_id..end).step_by(MAX_BATCH_BLOCKS) {
+            let len = (end - start).min(MAX_BATCH_BLOCKS as u64) as usize * BLOCK_SIZE;
+            write_blocks(dev, start, &self.bounce[..len])?;
+            requests += 1;
+        }
+        Ok(requests)
+    }
+
+    /// Track the clusters clean with `clean` from now on.
+    pub fn set_clean_map(&mut self, clean: CleanMap) {
+        self.clean = Some(clean);
+    }
+
+    /// Stop tracking the clusters clean, returning what was tracked.
+    pub fn take_clean_map(&mut self) -> Option<CleanMap> {
+        self.clean.take()
+    }
+}
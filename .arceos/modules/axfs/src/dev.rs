@@ -1,25 +1,266 @@
+use alloc::{format, string::String, sync::Arc, vec, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use axdriver::prelude::*;
+use axfs_vfs::{VfsNodeRef, VfsResult};
+use axsync::Mutex;
+use lazyinit::LazyInit;
+use spin::Once;
+
+use crate::{
+    cache::{BlockCache, CacheStats},
+    trim::{Trim, TrimStats, discard_zeroes},
+};
+
+/// The size of a block, which all block devices must use.
+pub const BLOCK_SIZE: usize = 512;
+
+/// The maximum number of contiguous blocks transferred by a single device
+/// request (64 KiB).
+pub(crate) const MAX_BATCH_BLOCKS: usize = 128;
+
+/// A block device registered with the kernel, shared by the filesystem on
+/// it and raw accesses from user space.
+///
+/// Both go through a write-back cache of single blocks, so what is written
+/// is only sure to be on the device once it is synced. The free clusters of
+/// a FAT filesystem mounted from it are discarded, see [`BlockDevice::trim`].
+pub struct BlockDevice {
+    name: String,
+    dev: Mutex<AxBlockDevice>,
+    // Locked before `dev`.
+    cache: Mutex<BlockCache>,
+    // Locked before `cache`.
+    trim: Mutex<Option<Trim>>,
+    mounted: AtomicBool,
+}
+
+impl BlockDevice {
+    fn new(name: String, dev: AxBlockDevice) -> Self {
+        assert_eq!(BLOCK_SIZE, dev.block_size());
+        Self {
+            name,
+            dev: Mutex::new(dev),
+            cache: Mutex::new(BlockCache::new()),
+            trim: Mutex::new(None),
+            mounted: AtomicBool::new(false),
+        }
+    }
+
+    /// The name of the device, like `vda` for the first one.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The number of blocks of the device.
+    pub fn num_blocks(&self) -> u64 {
+        self.dev.lock().num_blocks()
+    }
+
+    /// The size of the device, in bytes.
+    pub fn size(&self) -> u64 {
+        self.num_blocks() * BLOCK_SIZE as u64
+    }
+
+    /// Whether a filesystem is mounted from the device.
+    pub fn is_mounted(&self) -> bool {
+        self.mounted.load(Ordering::Acquire)
+    }
+
+    /// Read whole blocks from `block_id` into `buf`.
+    pub fn read_block(&self, block_id: u64, buf: &mut [u8]) -> DevResult {
+        self.cache.lock().read(&self.dev, block_id, buf)
+    }
+
+    /// Write whole blocks from `buf` at `block_id`.
+    ///
+    /// A single block is written back later, when it is evicted from the
+    /// cache or the device is synced.
+    pub fn write_block(&self, block_id: u64, buf: &[u8]) -> DevResult {
+        self.cache.lock().write(&self.dev, block_id, buf)
+    }
+
+    /// Write back the blocks written to the cache, discarding first the
+    /// clusters the filesystem on the device freed since it was last synced.
+    ///
+    /// Failing to discard them is only logged, and they are discarded the
+    /// next time.
+    pub fn sync(&self) -> DevResult {
+        let mut trim = self.trim.lock();
+        let mut cache = self.cache.lock();
+        if let Some(trim) = trim.as_mut() {
+            if trim.freed_since() && discard_zeroes() {
+                if let Err(err) = trim.pass(&mut cache, &self.dev, false) {
+                    warn!("failed to trim {}: {:?}", self.name, err);
+                }
+            }
+        }
+        cache.sync(&self.dev)
+    }
+
+    /// Discard every free cluster of the filesystem on the device, as
+    /// `fstrim` does, returning the number of blocks discarded.
+    ///
+    /// Fails with [`DevError::Unsupported`] if no filesystem which can be
+    /// trimmed is mounted from the device, or blocks are not to be
+    /// discarded, see [`crate::set_discard_zeroes`].
+    pub fn trim(&self) -> DevResult<u64> {
+        let mut trim = self.trim.lock();
+        let trim = trim.as_mut().ok_or(DevError::Unsupported)?;
+        if !discard_zeroes() {
+            return Err(DevError::Unsupported);
+        }
+        trim.pass(&mut self.cache.lock(), &self.dev, true)
+    }
+
+    /// The counters of the passes discarding the free clusters on the
+    /// device, if the filesystem on it is trimmed.
+    pub fn trim_stats(&self) -> Option<TrimStats> {
+        self.trim.lock().as_ref().map(Trim::stats)
+    }
+
+    /// Start trimming the filesystem mounted from the device, if it is one
+    /// which can be.
+    pub(crate) fn init_trim(&self) -> DevResult {
+        let mut trim = self.trim.lock();
+        *trim = Trim::new(&mut self.cache.lock(), &self.dev)?;
+        Ok(())
+    }
+
+    /// Write back the blocks written to the cache, unless the cache or the
+    /// device is in use, as after a panic whoever uses them may never let
+    /// go. Returns `None` if they are in use.
+    pub fn try_sync(&self) -> Option<DevResult> {
+        let mut cache = self.cache.try_lock()?;
+        if self.dev.is_locked() {
+            return None;
+        }
+        Some(cache.sync(&self.dev))
+    }
+
+    /// The counters of the cache of the device.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.lock().stats()
+    }
+}
+
+/// The hooks called before and after every access to a block device.
+static IO_WAIT_HOOKS: Once<(fn(), fn())> = Once::new();
+
+/// Call `begin` before and `end` after every access to a block device,
+/// including the wait for another access to finish, so that the time tasks
+/// wait for I/O can be accounted for. The hooks can only be set once.
+pub fn set_io_wait_hooks(begin: fn(), end: fn()) {
+    IO_WAIT_HOOKS.call_once(|| (begin, end));
+}
+
+/// Run the access to a block device `f` between the hooks, if any.
+pub(crate) fn io_wait<T>(f: impl FnOnce() -> T) -> T {
+    let Some(&(begin, end)) = IO_WAIT_HOOKS.get() else {
+        return f();
+    };
+    begin();
+    let ret = f();
+    end();
+    ret
+}
+
+/// A point of the accesses to block devices where a fault can be injected,
+/// see [`set_fault_hook`].
+#[cfg(feature = "fault-inject")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultPoint {
+    /// Reading from a device, which fails with [`DevError::Io`].
+    BlockRead,
+    /// Writing to a device, which fails with [`DevError::Io`].
+    BlockWrite,
+    /// Adding a block to the cache, which fails with [`DevError::NoMemory`].
+    Cache,
+}
+
+#[cfg(feature = "fault-inject")]
+static FAULT_HOOK: Once<fn(FaultPoint) -> bool> = Once::new();
+
+/// Make each access to a block device at a [`FaultPoint`] fail when `hook`
+/// returns `true` for it, for testing the paths handling the error. The
+/// hook can only be set once.
+#[cfg(feature = "fault-inject")]
+pub fn set_fault_hook(hook: fn(FaultPoint) -> bool) {
+    FAULT_HOOK.call_once(|| hook);
+}
+
+/// Fail with the error of `point` if the hook asks for it.
+#[cfg(feature = "fault-inject")]
+pub(crate) fn inject_fault(point: FaultPoint) -> DevResult {
+    if FAULT_HOOK.get().is_some_and(|hook| hook(point)) {
+        return Err(match point {
+            FaultPoint::BlockRead | FaultPoint::BlockWrite => DevError::Io,
+            FaultPoint::Cache => DevError::NoMemory,
+        });
+    }
+    Ok(())
+}
 
-const BLOCK_SIZE: usize = 512;
+static BLOCK_DEVICES: LazyInit<Vec<Arc<BlockDevice>>> = LazyInit::new();
+
+/// Register the block devices found by the drivers, named `vda`, `vdb`,
+/// and so on in order.
+pub(crate) fn register_block_devices(devs: Vec<AxBlockDevice>) -> &'static [Arc<BlockDevice>] {
+    BLOCK_DEVICES.init_once(
+        devs.into_iter()
+            .enumerate()
+            .map(|(i, dev)| {
+                let name = format!("vd{}", (b'a' + i as u8) as char);
+                info!("  block device {}: {:?}", name, dev.device_name());
+                Arc::new(BlockDevice::new(name, dev))
+            })
+            .collect(),
+    )
+}
+
+/// The block devices registered with the kernel.
+pub fn block_devices() -> &'static [Arc<BlockDevice>] {
+    BLOCK_DEVICES.get().map_or(&[], Vec::as_slice)
+}
+
+/// Write back the blocks written to the cache of every block device, as
+/// `sync` and powering off do.
+pub fn sync_block_devices() -> DevResult {
+    block_devices().iter().try_for_each(|dev| dev.sync())
+}
 
 /// A disk device with a cursor.
 pub struct Disk {
     block_id: u64,
     offset: usize,
-    dev: AxBlockDevice,
+    dev: Arc<BlockDevice>,
+    /// Bounce buffer for multi-block requests. The caller's buffer may live
+    /// in user memory, which is not guaranteed to be physically contiguous.
+    bounce: Vec<u8>,
 }
 
 impl Disk {
-    /// Create a new disk.
-    pub fn new(dev: AxBlockDevice) -> Self {
-        assert_eq!(BLOCK_SIZE, dev.block_size());
+    /// Create a new disk for the filesystem mounted from `dev`.
+    pub fn new(dev: Arc<BlockDevice>) -> Self {
+        dev.mounted.store(true, Ordering::Release);
         Self {
             block_id: 0,
             offset: 0,
             dev,
+            bounce: vec![0; MAX_BATCH_BLOCKS * BLOCK_SIZE],
         }
     }
 
+    /// The device of the disk.
+    pub fn device(&self) -> &Arc<BlockDevice> {
+        &self.dev
+    }
+
+    /// Write back the blocks written to the cache of the device.
+    pub fn flush(&self) -> DevResult {
+        self.dev.sync()
+    }
+
     /// Get the size of the disk.
     pub fn size(&self) -> u64 {
         self.dev.num_blocks() * BLOCK_SIZE as u64
@@ -36,17 +277,35 @@ impl Disk {
         self.offset = pos as usize % BLOCK_SIZE;
     }
 
-    /// Read within one block, returns the number of bytes read.
+    /// Number of whole blocks a request of `len` bytes at the cursor can
+    /// transfer at once, bounded by the device size. It is 0 if `len` is
+    /// less than a block or the cursor is at the end of the device.
+    fn batch_blocks(&self, len: usize) -> usize {
+        let remaining = self.dev.num_blocks().saturating_sub(self.block_id);
+        (len / BLOCK_SIZE).min(remaining.min(MAX_BATCH_BLOCKS as u64) as usize)
+    }
+
+    /// Whether a request of `len` bytes at the cursor transfers nothing, as
+    /// it is empty or the cursor is at the end of the device.
+    fn at_end(&self, len: usize) -> bool {
+        len == 0 || self.block_id >= self.dev.num_blocks()
+    }
+
+    /// Read within one block, or a run of whole blocks if the cursor is
+    /// block-aligned and `buf` spans several blocks. Returns the number of
+    /// bytes read, which is 0 only if `buf` is empty or the cursor is at the
+    /// end of the device.
     pub fn read_one(&mut self, buf: &mut [u8]) -> DevResult<usize> {
+        if self.at_end(buf.len()) {
+            return Ok(0);
+        }
         let read_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
-            // whole block
-            let mut data = [0u8; BLOCK_SIZE];
-            self.dev.read_block(self.block_id, &mut data)?;
-            buf[0..BLOCK_SIZE].copy_from_slice(&data);
-            // self.dev
-            //     .read_block(self.block_id, &mut buf[0..BLOCK_SIZE])?;
-            self.block_id += 1;
-            BLOCK_SIZE
+            // whole blocks, in a single device request
+            let len = self.batch_blocks(buf.len()) * BLOCK_SIZE;
+            self.dev.read_block(self.block_id, &mut self.bounce[..len])?;
+            buf[..len].copy_from_slice(&self.bounce[..len]);
+            self.block_id += (len / BLOCK_SIZE) as u64;
+            len
         } else {
             // partial block
             let mut data = [0u8; BLOCK_SIZE];
@@ -66,13 +325,21 @@ impl Disk {
         Ok(read_size)
     }
 
-    /// Write within one block, returns the number of bytes written.
+    /// Write within one block, or a run of whole blocks if the cursor is
+    /// block-aligned and `buf` spans several blocks. Returns the number of
+    /// bytes written, which is 0 only if `buf` is empty or the cursor is at
+    /// the end of the device.
     pub fn write_one(&mut self, buf: &[u8]) -> DevResult<usize> {
+        if self.at_end(buf.len()) {
+            return Ok(0);
+        }
         let write_size = if self.offset == 0 && buf.len() >= BLOCK_SIZE {
-            // whole block
-            self.dev.write_block(self.block_id, &buf[0..BLOCK_SIZE])?;
-            self.block_id += 1;
-            BLOCK_SIZE
+            // whole blocks, in a single device request
+            let len = self.batch_blocks(buf.len()) * BLOCK_SIZE;
+            self.bounce[..len].copy_from_slice(&buf[..len]);
+            self.dev.write_block(self.block_id, &self.bounce[..len])?;
+            self.block_id += (len / BLOCK_SIZE) as u64;
+            len
         } else {
             // partial block
             let mut data = [0u8; BLOCK_SIZE];
@@ -117,3 +384,65 @@ impl Disk {
         Ok(buf.len())
     }
 }
+
+impl Drop for Disk {
+    /// Sync the device once the filesystem on it is unmounted.
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            error!("failed to sync {}: {:?}", self.dev.name(), err);
+        }
+    }
+}
+
+/// A disk backed by a regular file, for loop mounts.
+///
+/// The disk has the size of the file when it is created, and never grows.
+pub struct FileDisk {
+    file: VfsNodeRef,
+    pos: u64,
+    size: u64,
+}
+
+impl FileDisk {
+    /// Create a disk reading and writing through `file`.
+    pub fn new(file: VfsNodeRef) -> VfsResult<Self> {
+        let size = file.get_attr()?.size();
+        Ok(Self { file, pos: 0, size })
+    }
+
+    /// Get the size of the disk.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Get the position of the cursor.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Set the position of the cursor.
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
+    /// Read at the cursor, up to the end of the disk.
+    pub fn read(&mut self, buf: &mut [u8]) -> VfsResult<usize> {
+        let len = buf.len().min(self.size.saturating_sub(self.pos) as usize);
+        let n = self.file.read_at(self.pos, &mut buf[..len])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    /// Write at the cursor, up to the end of the disk.
+    pub fn write(&mut self, buf: &[u8]) -> VfsResult<usize> {
+        let len = buf.len().min(self.size.saturating_sub(self.pos) as usize);
+        let n = self.file.write_at(self.pos, &buf[..len])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    /// Flush the backing file.
+    pub fn flush(&self) -> VfsResult {
+        self.file.fsync()
+    }
+}
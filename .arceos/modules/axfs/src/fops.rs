@@ -4,7 +4,7 @@ use axerrno::{AxError, AxResult, ax_err, ax_err_type};
 use axfs_vfs::{VfsError, VfsNodeRef};
 use axio::SeekFrom;
 use cap_access::{Cap, WithCap};
-use core::fmt;
+use core::{fmt, time::Duration};
 
 #[cfg(feature = "myfs")]
 pub use crate::dev::Disk;
@@ -21,6 +21,13 @@ pub type FileAttr = axfs_vfs::VfsNodeAttr;
 pub type FilePerm = axfs_vfs::VfsNodePerm;
 
 /// An opened file object, with open permissions and a cursor.
+///
+/// The cursor only moves with [`read`](File::read), [`write`](File::write)
+/// and [`seek`](File::seek), which take `&mut self`. The positional forms,
+/// [`read_at`](File::read_at) and [`write_at`](File::write_at), take `&self`
+/// and never move it, so the kernel reads and writes a file at offsets of
+/// its own, e.g. to fill a mapping, without disturbing the reads of a
+/// program through the same descriptor.
 pub struct File {
     node: WithCap<VfsNodeRef>,
     is_append: bool,
@@ -228,6 +235,24 @@ impl File {
         Ok(read_len)
     }
 
+    /// Reads the file at the given position until `buf` is full or the end
+    /// of the file is reached, as a single [`read_at`](File::read_at) may
+    /// stop short, e.g. at the end of a cluster. Returns the number of bytes
+    /// read.
+    ///
+    /// It does not update the file cursor.
+    pub fn read_full_at(&self, offset: u64, buf: &mut [u8]) -> AxResult<usize> {
+        let node = self.access_node(Cap::READ)?;
+        let mut read_len = 0;
+        while read_len < buf.len() {
+            match node.read_at(offset + read_len as u64, &mut buf[read_len..])? {
+                0 => break,
+                n => read_len += n,
+            }
+        }
+        Ok(read_len)
+    }
+
     /// Writes the file at the current position. Returns the number of bytes
     /// written.
     ///
@@ -256,8 +281,11 @@ impl File {
     }
 
     /// Flushes the file, writes all buffered data to the underlying device.
+    ///
+    /// Like `fsync`, it needs no access right: the data may have been
+    /// written through another open of the file.
     pub fn flush(&self) -> AxResult {
-        self.access_node(Cap::WRITE)?.fsync()?;
+        self.access_node(Cap::empty())?.fsync()?;
         Ok(())
     }
 
@@ -279,6 +307,15 @@ impl File {
     pub fn get_attr(&self) -> AxResult<FileAttr> {
         self.access_node(Cap::empty())?.get_attr()
     }
+
+    /// Sets the access and modification times the filesystem stores for
+    /// the file, leaving a `None` one unchanged.
+    ///
+    /// Only FAT stores times, and the others fail with `Unsupported`. Like
+    /// `utimensat`, it needs no access right.
+    pub fn set_times(&self, accessed: Option<Duration>, modified: Option<Duration>) -> AxResult {
+        crate::root::set_fat_times(self.access_node(Cap::empty())?, accessed, modified)
+    }
 }
 
 impl Directory {
@@ -1,22 +1,102 @@
-use alloc::sync::Arc;
-use core::cell::UnsafeCell;
+use alloc::{boxed::Box, sync::Arc};
+use core::{any::Any, cell::UnsafeCell, ptr::NonNull, time::Duration};
 
 use axfs_vfs::{VfsDirEntry, VfsError, VfsNodePerm, VfsResult};
 use axfs_vfs::{VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
 use axsync::Mutex;
-use fatfs::{Dir, File, LossyOemCpConverter, NullTimeProvider, Read, Seek, SeekFrom, Write};
+use fatfs::{
+    Date, DateTime, Dir, File, LossyOemCpConverter, Read, Seek, SeekFrom, Time, TimeProvider, Write,
+};
 
-use crate::dev::Disk;
+use crate::{
+    dev::{Disk, FileDisk},
+    time::{FileTimes, civil_from_days, days_from_civil, now},
+    trim::note_clusters_freed,
+};
 
 const BLOCK_SIZE: usize = 512;
 
+/// The clusters file data leaves free, so that adding an entry to a
+/// directory, which may need a cluster to grow it, still works when the
+/// filesystem is full.
+const RESERVED_CLUSTERS: u32 = 2;
+
+type FatFs<IO> = fatfs::FileSystem<IO, WallTimeProvider, LossyOemCpConverter>;
+
+/// Dates the entries fatfs writes by the wall clock, see
+/// [`crate::set_wall_clock`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WallTimeProvider;
+
+impl TimeProvider for WallTimeProvider {
+    fn get_current_date(&self) -> Date {
+        to_fat(now()).date
+    }
+
+    fn get_current_date_time(&self) -> DateTime {
+        to_fat(now())
+    }
+}
+
+/// The options every FAT filesystem is opened with.
+fn fs_options() -> fatfs::FsOptions<WallTimeProvider, LossyOemCpConverter> {
+    fatfs::FsOptions::new().time_provider(WallTimeProvider)
+}
+
+const SECS_PER_DAY: u64 = 86400;
+
+/// The FAT date and time of `time`, clamped to the years FAT can tell,
+/// 1980 to 2107.
+fn to_fat(time: Duration) -> DateTime {
+    let secs = time.as_secs();
+    let (year, month, day) = civil_from_days((secs / SECS_PER_DAY) as i64);
+    let secs = secs % SECS_PER_DAY;
+    match year {
+        ..1980 => DateTime::new(Date::new(1980, 1, 1), Time::new(0, 0, 0, 0)),
+        2108.. => DateTime::new(Date::new(2107, 12, 31), Time::new(23, 59, 59, 0)),
+        _ => DateTime::new(
+            Date::new(year as u16, month as u16, day as u16),
+            Time::new(
+                (secs / 3600) as u16,
+                (secs / 60 % 60) as u16,
+                (secs % 60) as u16,
+                time.subsec_millis() as u16,
+            ),
+        ),
+    }
+}
+
+/// The time of the FAT date `date`, at midnight.
+fn from_fat_date(date: Date) -> Duration {
+    // A corrupt entry may have a month or day of 0.
+    let days = days_from_civil(
+        date.year as i64,
+        (date.month as u32).clamp(1, 12),
+        (date.day as u32).max(1),
+    );
+    Duration::from_secs(days.max(0) as u64 * SECS_PER_DAY)
+}
+
+/// The time of the FAT date and time `date_time`.
+fn from_fat(date_time: DateTime) -> Duration {
+    let time = date_time.time;
+    let secs = time.hour as u64 * 3600 + time.min as u64 * 60 + time.sec as u64;
+    from_fat_date(date_time.date) + Duration::new(secs, time.millis as u32 * 1_000_000)
+}
+
 pub struct FatFileSystem {
-    inner: fatfs::FileSystem<Disk, NullTimeProvider, LossyOemCpConverter>,
+    inner: FatFs<Disk>,
     root_dir: UnsafeCell<Option<VfsNodeRef>>,
 }
 
-pub struct FileWrapper<'a, IO: IoTrait>(Mutex<File<'a, IO, NullTimeProvider, LossyOemCpConverter>>);
-pub struct DirWrapper<'a, IO: IoTrait>(Dir<'a, IO, NullTimeProvider, LossyOemCpConverter>);
+pub struct FileWrapper<'a, IO: IoTrait>(
+    Mutex<File<'a, IO, WallTimeProvider, LossyOemCpConverter>>,
+    &'a FatFs<IO>,
+);
+pub struct DirWrapper<'a, IO: IoTrait>(
+    Dir<'a, IO, WallTimeProvider, LossyOemCpConverter>,
+    &'a FatFs<IO>,
+);
 
 pub trait IoTrait: Read + Write + Seek {}
 
@@ -32,8 +112,12 @@ impl FatFileSystem {
     pub fn new(mut disk: Disk) -> Self {
         let opts = fatfs::FormatVolumeOptions::new();
         fatfs::format_volume(&mut disk, opts).expect("failed to format volume");
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
+        let dev = disk.device().clone();
+        let inner = fatfs::FileSystem::new(disk, fs_options())
             .expect("failed to initialize FAT filesystem");
+        if let Err(err) = dev.init_trim() {
+            warn!("not trimming {}: {:?}", dev.name(), err);
+        }
         Self {
             inner,
             root_dir: UnsafeCell::new(None),
@@ -42,8 +126,12 @@ impl FatFileSystem {
 
     #[cfg(not(feature = "use-ramdisk"))]
     pub fn new(disk: Disk) -> Self {
-        let inner = fatfs::FileSystem::new(disk, fatfs::FsOptions::new())
+        let dev = disk.device().clone();
+        let inner = fatfs::FileSystem::new(disk, fs_options())
             .expect("failed to initialize FAT filesystem");
+        if let Err(err) = dev.init_trim() {
+            warn!("not trimming {}: {:?}", dev.name(), err);
+        }
         Self {
             inner,
             root_dir: UnsafeCell::new(None),
@@ -52,25 +140,122 @@ impl FatFileSystem {
 
     pub fn init(&'static self) {
         // must be called before later operations
-        unsafe { *self.root_dir.get() = Some(Self::new_dir(self.inner.root_dir())) }
+        unsafe { *self.root_dir.get() = Some(Self::new_dir(&self.inner, self.inner.root_dir())) }
     }
 
-    fn new_file<IO: IoTrait>(
-        file: File<'_, IO, NullTimeProvider, LossyOemCpConverter>,
-    ) -> Arc<FileWrapper<IO>> {
-        Arc::new(FileWrapper(Mutex::new(file)))
+    fn new_file<'a, IO: IoTrait>(
+        fs: &'a FatFs<IO>,
+        file: File<'a, IO, WallTimeProvider, LossyOemCpConverter>,
+    ) -> Arc<FileWrapper<'a, IO>> {
+        Arc::new(FileWrapper(Mutex::new(file), fs))
     }
 
-    fn new_dir<IO: IoTrait>(
-        dir: Dir<'_, IO, NullTimeProvider, LossyOemCpConverter>,
-    ) -> Arc<DirWrapper<IO>> {
-        Arc::new(DirWrapper(dir))
+    fn new_dir<'a, IO: IoTrait>(
+        fs: &'a FatFs<IO>,
+        dir: Dir<'a, IO, WallTimeProvider, LossyOemCpConverter>,
+    ) -> Arc<DirWrapper<'a, IO>> {
+        Arc::new(DirWrapper(dir, fs))
     }
 }
 
-impl<IO: IoTrait> VfsNodeOps for FileWrapper<'static, IO> {
+impl<IO: IoTrait> FileWrapper<'static, IO> {
+    /// Set the access and modification times of the file, leaving a `None`
+    /// one unchanged, and write them to its entry.
+    fn set_times(&self, accessed: Option<Duration>, modified: Option<Duration>) -> VfsResult {
+        let mut file = self.0.lock();
+        if let Some(accessed) = accessed {
+            file.set_accessed(to_fat(accessed).date);
+        }
+        if let Some(modified) = modified {
+            file.set_modified(to_fat(modified));
+        }
+        file.flush().map_err(as_vfs_err)
+    }
+}
+
+impl<IO: IoTrait> DirWrapper<'static, IO> {
+    /// The times of the entry `name` of the directory.
+    fn entry_times(&self, name: &str) -> VfsResult<FileTimes> {
+        let entry = self
+            .0
+            .iter()
+            .filter_map(Result::ok)
+            .find(|entry| entry.file_name().eq_ignore_ascii_case(name))
+            .ok_or(VfsError::NotFound)?;
+        Ok(FileTimes {
+            accessed: from_fat_date(entry.accessed()),
+            modified: from_fat(entry.modified()),
+            created: from_fat(entry.created()),
+        })
+    }
+}
+
+/// The newest modification time of the entries under `dir`, at any depth.
+fn newest_modified<IO: IoTrait>(
+    dir: &Dir<'_, IO, WallTimeProvider, LossyOemCpConverter>,
+) -> Duration {
+    dir.iter()
+        .filter_map(Result::ok)
+        .filter(|entry| !matches!(entry.file_name().as_str(), "." | ".."))
+        .map(|entry| {
+            let modified = from_fat(entry.modified());
+            if entry.is_dir() {
+                modified.max(newest_modified(&entry.to_dir()))
+            } else {
+                modified
+            }
+        })
+        .max()
+        .unwrap_or_default()
+}
+
+/// The times of the entry `name` of `dir`, or `None` if `dir` is not a
+/// directory of a FAT filesystem.
+pub fn entry_times(dir: &VfsNodeRef, name: &str) -> Option<VfsResult<FileTimes>> {
+    let any = dir.as_any();
+    if let Some(dir) = any.downcast_ref::<DirWrapper<'static, Disk>>() {
+        Some(dir.entry_times(name))
+    } else {
+        any.downcast_ref::<DirWrapper<'static, FileDisk>>()
+            .map(|dir| dir.entry_times(name))
+    }
+}
+
+/// Set the access and modification times of `file`, leaving a `None` one
+/// unchanged, or return `None` if `file` is not a file of a FAT filesystem.
+pub fn set_times(
+    file: &VfsNodeRef,
+    accessed: Option<Duration>,
+    modified: Option<Duration>,
+) -> Option<VfsResult> {
+    let any = file.as_any();
+    if let Some(file) = any.downcast_ref::<FileWrapper<'static, Disk>>() {
+        Some(file.set_times(accessed, modified))
+    } else {
+        any.downcast_ref::<FileWrapper<'static, FileDisk>>()
+            .map(|file| file.set_times(accessed, modified))
+    }
+}
+
+/// The newest modification time of the entries under `dir`, at any depth,
+/// or `None` if `dir` is not a directory of a FAT filesystem.
+pub fn newest_modified_under(dir: &VfsNodeRef) -> Option<Duration> {
+    let any = dir.as_any();
+    if let Some(dir) = any.downcast_ref::<DirWrapper<'static, Disk>>() {
+        Some(newest_modified(&dir.0))
+    } else {
+        any.downcast_ref::<DirWrapper<'static, FileDisk>>()
+            .map(|dir| newest_modified(&dir.0))
+    }
+}
+
+impl<IO: IoTrait + 'static> VfsNodeOps for FileWrapper<'static, IO> {
     axfs_vfs::impl_vfs_non_dir_default! {}
 
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
         let size = self.0.lock().seek(SeekFrom::End(0)).map_err(as_vfs_err)?;
         let blocks = (size + BLOCK_SIZE as u64 - 1) / BLOCK_SIZE as u64;
@@ -85,22 +270,46 @@ impl<IO: IoTrait> VfsNodeOps for FileWrapper<'static, IO> {
         file.read(buf).map_err(as_vfs_err)
     }
 
+    /// Writes what fits in the free clusters but [`RESERVED_CLUSTERS`], and
+    /// fails with `StorageFull` if nothing does. The size of the file only
+    /// ever covers what was written.
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
         let mut file = self.0.lock();
+        let size = file.seek(SeekFrom::End(0)).map_err(as_vfs_err)?;
+        let end = offset.saturating_add(buf.len() as u64);
+        let buf = if end > size {
+            let limit = writable_end(self.1, size)?;
+            if limit <= offset {
+                return Err(VfsError::StorageFull);
+            }
+            &buf[..(end.min(limit) - offset) as usize]
+        } else {
+            buf
+        };
         file.seek(SeekFrom::Start(offset)).map_err(as_vfs_err)?; // TODO: more efficient
         file.write(buf).map_err(as_vfs_err)
     }
 
+    fn fsync(&self) -> VfsResult {
+        self.0.lock().flush().map_err(as_vfs_err)
+    }
+
     fn truncate(&self, size: u64) -> VfsResult {
         let mut file = self.0.lock();
         file.seek(SeekFrom::Start(size)).map_err(as_vfs_err)?; // TODO: more efficient
-        file.truncate().map_err(as_vfs_err)
+        file.truncate().map_err(as_vfs_err)?;
+        note_clusters_freed();
+        Ok(())
     }
 }
 
-impl<IO: IoTrait> VfsNodeOps for DirWrapper<'static, IO> {
+impl<IO: IoTrait + 'static> VfsNodeOps for DirWrapper<'static, IO> {
     axfs_vfs::impl_vfs_dir_default! {}
 
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
         // FAT fs doesn't support permissions, we just set everything to 755
         Ok(VfsNodeAttr::new(
@@ -114,7 +323,7 @@ impl<IO: IoTrait> VfsNodeOps for DirWrapper<'static, IO> {
     fn parent(&self) -> Option<VfsNodeRef> {
         self.0
             .open_dir("..")
-            .map_or(None, |dir| Some(FatFileSystem::new_dir(dir)))
+            .map_or(None, |dir| Some(FatFileSystem::new_dir(self.1, dir)))
     }
 
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
@@ -129,9 +338,9 @@ impl<IO: IoTrait> VfsNodeOps for DirWrapper<'static, IO> {
 
         // TODO: use `fatfs::Dir::find_entry`, but it's not public.
         if let Ok(file) = self.0.open_file(path) {
-            Ok(FatFileSystem::new_file(file))
+            Ok(FatFileSystem::new_file(self.1, file))
         } else if let Ok(dir) = self.0.open_dir(path) {
-            Ok(FatFileSystem::new_dir(dir))
+            Ok(FatFileSystem::new_dir(self.1, dir))
         } else {
             Err(VfsError::NotFound)
         }
@@ -153,6 +362,12 @@ impl<IO: IoTrait> VfsNodeOps for DirWrapper<'static, IO> {
                 Ok(())
             }
             VfsNodeType::Dir => {
+                // The new directory takes a cluster before its entry is
+                // added, which may take another, and would be lost if that
+                // failed.
+                if self.1.stats().map_err(as_vfs_err)?.free_clusters() < 2 {
+                    return Err(VfsError::StorageFull);
+                }
                 self.0.create_dir(path).map_err(as_vfs_err)?;
                 Ok(())
             }
@@ -167,24 +382,34 @@ impl<IO: IoTrait> VfsNodeOps for DirWrapper<'static, IO> {
         if let Some(rest) = path.strip_prefix("./") {
             return self.remove(rest);
         }
-        self.0.remove(path).map_err(as_vfs_err)
+        self.0.remove(path).map_err(as_vfs_err)?;
+        note_clusters_freed();
+        Ok(())
     }
 
     fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
-        let mut iter = self.0.iter().skip(start_idx);
+        // Entries which are neither files nor directories are not listed.
+        // The iterator skips the volume label already, but a directory
+        // removed meanwhile may read as anything once its clusters are
+        // reused.
+        let mut iter = self
+            .0
+            .iter()
+            .filter(|entry| entry.as_ref().map_or(true, |e| e.is_dir() || e.is_file()))
+            .skip(start_idx);
         for (i, out_entry) in dirents.iter_mut().enumerate() {
-            let x = iter.next();
-            match x {
+            match iter.next() {
                 Some(Ok(entry)) => {
                     let ty = if entry.is_dir() {
                         VfsNodeType::Dir
-                    } else if entry.is_file() {
-                        VfsNodeType::File
                     } else {
-                        unreachable!()
+                        VfsNodeType::File
                     };
                     *out_entry = VfsDirEntry::new(&entry.file_name(), ty);
                 }
+                // The entries read so far are returned, and the error on the
+                // next call.
+                Some(Err(err)) if i == 0 => return Err(as_vfs_err(err)),
                 _ => return Ok(i),
             }
         }
@@ -251,7 +476,7 @@ impl Write for Disk {
         Ok(write_len)
     }
     fn flush(&mut self) -> Result<(), Self::Error> {
-        Ok(())
+        Disk::flush(self).map_err(|_| ())
     }
 }
 
@@ -272,6 +497,15 @@ impl Seek for Disk {
     }
 }
 
+/// The end up to which a file of `size` bytes can be written, in the
+/// clusters it has and the free ones but [`RESERVED_CLUSTERS`].
+fn writable_end<IO: IoTrait>(fs: &FatFs<IO>, size: u64) -> VfsResult<u64> {
+    let stats = fs.stats().map_err(as_vfs_err)?;
+    let cluster_size = stats.cluster_size() as u64;
+    let free = stats.free_clusters().saturating_sub(RESERVED_CLUSTERS) as u64;
+    Ok((size.div_ceil(cluster_size) + free) * cluster_size)
+}
+
 fn as_vfs_err<E>(err: fatfs::Error<E>) -> VfsError {
     use fatfs::Error::*;
     match err {
@@ -290,82 +524,110 @@ fn as_vfs_err<E>(err: fatfs::Error<E>) -> VfsError {
     }
 }
 
-impl Clone for FileWrapper<'static, Disk> {
-    fn clone(&self) -> Self {
-        let file = self.0.lock();
-        let cloned_file = file.clone();
-        Self(Mutex::new(cloned_file))
-    }
-}
-
+/// A FAT filesystem in an image file, for loop mounts.
+///
+/// Unlike the root filesystem, it is unmounted while the kernel runs, so it
+/// owns the filesystem its nodes borrow and frees it once dropped.
 pub struct FatFileSystemFromFile {
-    inner: fatfs::FileSystem<FileWrapper<'static, Disk>, NullTimeProvider, LossyOemCpConverter>,
+    /// The filesystem, on the heap so that the nodes can borrow it for
+    /// `'static`, see [`Drop`].
+    inner: NonNull<FatFs<FileDisk>>,
     root_dir: UnsafeCell<Option<VfsNodeRef>>,
+    /// The image file, flushed on unmount.
+    image: VfsNodeRef,
 }
 
 unsafe impl Sync for FatFileSystemFromFile {}
 unsafe impl Send for FatFileSystemFromFile {}
 
-#[allow(unused)]
 impl FatFileSystemFromFile {
-    pub fn new(file: FileWrapper<'static, Disk>) -> Self {
-        let inner = fatfs::FileSystem::new(file, fatfs::FsOptions::new())
-            .expect("failed to initialize FAT filesystem");
-        Self {
-            inner,
+    /// Open the filesystem in the image file `image`.
+    pub fn new(image: VfsNodeRef) -> VfsResult<Self> {
+        let disk = FileDisk::new(image.clone())?;
+        let inner = fatfs::FileSystem::new(disk, fs_options()).map_err(as_vfs_err)?;
+        Ok(Self {
+            inner: NonNull::from(Box::leak(Box::new(inner))),
             root_dir: UnsafeCell::new(None),
-        }
+            image,
+        })
     }
 
-    pub fn init(&'static self) {
+    fn fs(&self) -> &'static FatFs<FileDisk> {
+        // SAFETY: the filesystem is only freed when `self` is dropped, once
+        // no node borrowing it is left.
+        unsafe { self.inner.as_ref() }
+    }
+
+    pub fn init(&self) {
         // must be called before later operations
-        unsafe { *self.root_dir.get() = Some(FatFileSystem::new_dir(self.inner.root_dir())) }
+        let fs = self.fs();
+        unsafe { *self.root_dir.get() = Some(FatFileSystem::new_dir(fs, fs.root_dir())) }
     }
 }
 
 impl VfsOps for FatFileSystemFromFile {
+    fn umount(&self) -> VfsResult {
+        // Every write already went to the image, through the disk.
+        self.image.fsync()
+    }
+
     fn root_dir(&self) -> VfsNodeRef {
         let root_dir = unsafe { (*self.root_dir.get()).as_ref().unwrap() };
         root_dir.clone()
     }
 }
 
-impl<'a> fatfs::IoBase for FileWrapper<'a, Disk> {
+impl Drop for FatFileSystemFromFile {
+    /// Unmount the filesystem, which writes back its info sector and clears
+    /// its dirty flag, and flush the image.
+    ///
+    /// The root directory is the last node left by then: open files and
+    /// directories keep the filesystem from being unmounted, or from being
+    /// dropped if detached while in use.
+    fn drop(&mut self) {
+        self.root_dir.get_mut().take();
+        // SAFETY: allocated by `new`, and no node borrows it anymore.
+        let fs = unsafe { *Box::from_raw(self.inner.as_ptr()) };
+        if let Err(err) = fs.unmount() {
+            error!("failed to unmount FAT image: {:?}", as_vfs_err(err));
+        }
+        if let Err(err) = self.image.fsync() {
+            error!("failed to flush FAT image: {:?}", err);
+        }
+    }
+}
+
+impl fatfs::IoBase for FileDisk {
     type Error = ();
 }
 
-impl<'a> IoTrait for FileWrapper<'a, Disk> {}
+impl IoTrait for FileDisk {}
 
-impl<'a> fatfs::Read for FileWrapper<'a, Disk> {
+impl Read for FileDisk {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        let mut file = self.0.lock();
-        file.read(buf)
-            .inspect_err(|e| error!("read error: {e:?}"))
-            .map_err(|_| ())
+        FileDisk::read(self, buf).map_err(|e| error!("image read error: {e:?}"))
     }
 }
 
-impl<'a> fatfs::Write for FileWrapper<'a, Disk> {
+impl Write for FileDisk {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-        let mut file = self.0.lock();
-        file.write(buf)
-            .inspect_err(|e| error!("write error: {e:?}"))
-            .map_err(|_| ())
+        FileDisk::write(self, buf).map_err(|e| error!("image write error: {e:?}"))
     }
 
     fn flush(&mut self) -> Result<(), Self::Error> {
-        let mut file = self.0.lock();
-        file.flush()
-            .inspect_err(|e| error!("flush error: {e:?}"))
-            .map_err(|_| ())
+        FileDisk::flush(self).map_err(|e| error!("image flush error: {e:?}"))
     }
 }
 
-impl<'a> fatfs::Seek for FileWrapper<'a, Disk> {
-    fn seek(&mut self, pos: fatfs::SeekFrom) -> Result<u64, Self::Error> {
-        let mut file = self.0.lock();
-        file.seek(pos)
-            .inspect_err(|e| error!("seek error: {e:?}"))
-            .map_err(|_| ())
+impl Seek for FileDisk {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        let new_pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(off) => self.position().checked_add_signed(off),
+            SeekFrom::End(off) => self.size().checked_add_signed(off),
+        }
+        .ok_or(())?;
+        self.set_position(new_pos);
+        Ok(new_pos)
     }
 }
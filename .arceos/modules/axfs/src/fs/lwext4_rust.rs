@@ -41,6 +41,12 @@ impl Ext4FileSystem {
     }
 }
 
+/// The error of a failed call into lwext4, `Io` for an errno `AxError`
+/// has no counterpart of.
+fn ext4_err(err: i32) -> VfsError {
+    AxError::try_from(err).unwrap_or(VfsError::Io)
+}
+
 /// The [`VfsOps`] trait provides operations on a filesystem.
 impl VfsOps for Ext4FileSystem {
     // mount()
@@ -119,8 +125,7 @@ impl VfsNodeOps for FileWrapper {
         let size = if vtype == VfsNodeType::File {
             let path = file.get_path();
             let path = path.to_str().unwrap();
-            file.file_open(path, O_RDONLY)
-                .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
+            file.file_open(path, O_RDONLY).map_err(ext4_err)?;
             let fsize = file.file_size();
             let _ = file.file_close();
             fsize
@@ -163,15 +168,11 @@ impl VfsNodeOps for FileWrapper {
             Ok(())
         } else {
             if types == InodeTypes::EXT4_DE_DIR {
-                file.dir_mk(fpath)
-                    .map(|_v| ())
-                    .map_err(|e| e.try_into().unwrap())
+                file.dir_mk(fpath).map(|_v| ()).map_err(ext4_err)
             } else {
                 file.file_open(fpath, O_WRONLY | O_CREAT | O_TRUNC)
-                    .expect("create file failed");
-                file.file_close()
-                    .map(|_v| ())
-                    .map_err(|e| e.try_into().unwrap())
+                    .map_err(ext4_err)?;
+                file.file_close().map(|_v| ()).map_err(ext4_err)
             }
         }
     }
@@ -186,13 +187,9 @@ impl VfsNodeOps for FileWrapper {
         let mut file = self.0.lock();
         if file.check_inode_exist(fpath, InodeTypes::EXT4_DE_DIR) {
             // Recursive directory remove
-            file.dir_rm(fpath)
-                .map(|_v| ())
-                .map_err(|e| e.try_into().unwrap())
+            file.dir_rm(fpath).map(|_v| ()).map_err(ext4_err)
         } else {
-            file.file_remove(fpath)
-                .map(|_v| ())
-                .map_err(|e| e.try_into().unwrap())
+            file.file_remove(fpath).map(|_v| ()).map_err(ext4_err)
         }
     }
 
@@ -215,17 +212,14 @@ impl VfsNodeOps for FileWrapper {
     /// Read directory entries into `dirents`, starting from `start_idx`.
     fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
         let file = self.0.lock();
-        let (name, inode_type) = file.lwext4_dir_entries().unwrap();
+        // Fails rather than panics if the directory was removed meanwhile.
+        let (name, inode_type) = file.lwext4_dir_entries().map_err(ext4_err)?;
 
-        let mut name_iter = name.iter().skip(start_idx);
-        let mut inode_type_iter = inode_type.iter().skip(start_idx);
+        let mut iter = name.iter().zip(inode_type.iter()).skip(start_idx);
 
         for (i, out_entry) in dirents.iter_mut().enumerate() {
-            let iname = name_iter.next();
-            let itypes = inode_type_iter.next();
-
-            match itypes {
-                Some(t) => {
+            match iter.next() {
+                Some((iname, t)) => {
                     let ty = if *t == InodeTypes::EXT4_DE_DIR {
                         VfsNodeType::Dir
                     } else if *t == InodeTypes::EXT4_DE_REG_FILE {
@@ -233,14 +227,15 @@ impl VfsNodeOps for FileWrapper {
                     } else if *t == InodeTypes::EXT4_DE_SYMLINK {
                         VfsNodeType::SymLink
                     } else {
-                        error!("unknown file type: {:?}", itypes);
-                        unreachable!()
+                        warn!("unknown file type: {:?}", t);
+                        VfsNodeType::File
                     };
 
-                    *out_entry =
-                        VfsDirEntry::new(core::str::from_utf8(iname.unwrap()).unwrap(), ty);
+                    // Names which are not UTF-8 are escaped rather than
+                    // mangled, so that the bytes are listed as they are.
+                    *out_entry = VfsDirEntry::new(&crate::name::from_disk(iname), ty);
                 }
-                _ => return Ok(i),
+                None => return Ok(i),
             }
         }
 
@@ -275,50 +270,47 @@ impl VfsNodeOps for FileWrapper {
         let mut file = self.0.lock();
         let path = file.get_path();
         let path = path.to_str().unwrap();
-        file.file_open(path, O_RDONLY)
-            .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
+        file.file_open(path, O_RDONLY).map_err(ext4_err)?;
 
-        file.file_seek(offset as i64, SEEK_SET)
-            .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
+        file.file_seek(offset as i64, SEEK_SET).map_err(ext4_err)?;
         let r = file.file_read(buf);
 
         let _ = file.file_close();
-        r.map_err(|e| e.try_into().unwrap())
+        r.map_err(ext4_err)
     }
 
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
         let mut file = self.0.lock();
         let path = file.get_path();
         let path = path.to_str().unwrap();
-        file.file_open(path, O_RDWR)
-            .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
+        file.file_open(path, O_RDWR).map_err(ext4_err)?;
 
-        file.file_seek(offset as i64, SEEK_SET)
-            .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
+        file.file_seek(offset as i64, SEEK_SET).map_err(ext4_err)?;
         let r = file.file_write(buf);
 
         let _ = file.file_close();
-        r.map_err(|e| e.try_into().unwrap())
+        r.map_err(ext4_err)
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
         let mut file = self.0.lock();
         let path = file.get_path();
         let path = path.to_str().unwrap();
-        file.file_open(path, O_RDWR | O_CREAT | O_TRUNC)
-            .map_err(|e| <i32 as TryInto<AxError>>::try_into(e).unwrap())?;
+        // Without `O_CREAT`, so that a file removed meanwhile is not made
+        // again.
+        file.file_open(path, O_RDWR | O_TRUNC).map_err(ext4_err)?;
 
         let t = file.file_truncate(size);
 
         let _ = file.file_close();
-        t.map(|_v| ()).map_err(|e| e.try_into().unwrap())
+        t.map(|_v| ()).map_err(ext4_err)
     }
 
     fn rename(&self, src_path: &str, dst_path: &str) -> VfsResult {
         let mut file = self.0.lock();
         file.file_rename(src_path, dst_path)
             .map(|_v| ())
-            .map_err(|e| e.try_into().unwrap())
+            .map_err(ext4_err)
     }
 
     fn as_any(&self) -> &dyn core::any::Any {
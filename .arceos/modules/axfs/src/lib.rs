@@ -15,6 +15,8 @@
 //!   to create and initialize other filesystems. This feature is **disabled** by
 //!   by default, but it will override other filesystem selection features if
 //!   both are enabled.
+//! - `irq`: Sleep until a block device raises its IRQ while reads queued on
+//!   it are outstanding, rather than poll for their completion.
 //!
 //! [FAT]: https://en.wikipedia.org/wiki/File_Allocation_Table
 //! [`MyFileSystemIf`]: fops::MyFileSystemIf
@@ -26,22 +28,39 @@
 extern crate log;
 extern crate alloc;
 
+mod cache;
 mod dev;
 mod fs;
 mod mounts;
+mod queue;
 mod root;
+mod time;
+mod trim;
 
 pub mod api;
 pub mod fops;
-pub use root::{CURRENT_DIR, CURRENT_DIR_PATH};
+pub mod name;
+pub use cache::CacheStats;
+pub use dev::{BLOCK_SIZE, BlockDevice, block_devices, set_io_wait_hooks, sync_block_devices};
+#[cfg(feature = "fault-inject")]
+pub use dev::{FaultPoint, set_fault_hook};
+pub use root::{CURRENT_DIR, CURRENT_DIR_PATH, CwdRef};
+pub use time::{FileTimes, set_wall_clock};
+pub use trim::{TrimStats, discard_zeroes, set_discard_zeroes};
 
+use alloc::vec::Vec;
 use axdriver::{AxDeviceContainer, prelude::*};
 
 /// Initializes filesystems by block devices.
 pub fn init_filesystems(mut blk_devs: AxDeviceContainer<AxBlockDevice>) {
     info!("Initialize filesystems...");
 
-    let dev = blk_devs.take_one().expect("No block device found!");
-    info!("  use block device 0: {:?}", dev.device_name());
-    self::root::init_rootfs(self::dev::Disk::new(dev));
+    let mut devs = Vec::new();
+    while let Some(dev) = blk_devs.take_one() {
+        devs.push(dev);
+    }
+    let devs = self::dev::register_block_devices(devs);
+    let dev = devs.first().expect("No block device found!");
+    info!("  use block device {} as the root", dev.name());
+    self::root::init_rootfs(self::dev::Disk::new(dev.clone()));
 }
@@ -0,0 +1,77 @@
+//! Names which are not UTF-8.
+//!
+//! Linux takes any bytes but `/` and NUL in a name, while the filesystems
+//! here take names as `&str`. So a name is carried as UTF-8 where it is, and
+//! each byte which is not is carried as a character of its own, from U+EF80
+//! for 0x80 to U+EFFF for 0xFF, like the OPTU-8 of MirBSD. These are private
+//! use characters, which a name given as UTF-8 hardly has, but if it does,
+//! their bytes are escaped the same way, so that [`decode`] always gives back
+//! what [`encode`] was given.
+//!
+//! Each byte escaped takes 3 in UTF-8, which counts against the length of
+//! the name in the filesystem.
+
+use alloc::{borrow::Cow, string::String, vec::Vec};
+
+/// The character a byte `b` is escaped as is `ESCAPE_BASE + b`.
+const ESCAPE_BASE: u32 = 0xef00;
+
+fn escape(b: u8) -> char {
+    char::from_u32(ESCAPE_BASE + b as u32).unwrap()
+}
+
+fn unescape(c: char) -> Option<u8> {
+    (ESCAPE_BASE + 0x80..=ESCAPE_BASE + 0xff)
+        .contains(&(c as u32))
+        .then(|| (c as u32 - ESCAPE_BASE) as u8)
+}
+
+/// The name `bytes`, as the filesystems take it.
+pub fn encode(bytes: &[u8]) -> Cow<'_, str> {
+    if let Ok(name) = core::str::from_utf8(bytes) {
+        if !name.chars().any(|c| unescape(c).is_some()) {
+            return Cow::Borrowed(name);
+        }
+    }
+    let mut name = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if unescape(c).is_some() {
+                let mut buf = [0; 4];
+                name.extend(c.encode_utf8(&mut buf).bytes().map(escape));
+            } else {
+                name.push(c);
+            }
+        }
+        // Only bytes from 0x80 are not UTF-8.
+        name.extend(chunk.invalid().iter().copied().map(escape));
+    }
+    Cow::Owned(name)
+}
+
+/// The bytes of `name`, as the filesystems take it, as given to [`encode`].
+pub fn decode(name: &str) -> Cow<'_, [u8]> {
+    if !name.chars().any(|c| unescape(c).is_some()) {
+        return Cow::Borrowed(name.as_bytes());
+    }
+    let mut bytes = Vec::with_capacity(name.len());
+    for c in name.chars() {
+        match unescape(c) {
+            Some(b) => bytes.push(b),
+            None => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    Cow::Owned(bytes)
+}
+
+/// The name `bytes` read from a filesystem which stores bytes, as the
+/// filesystems take it.
+///
+/// Unlike [`encode`], UTF-8 is kept as it is, escaped characters included,
+/// as it is what [`encode`] made of a name given.
+pub fn from_disk(bytes: &[u8]) -> Cow<'_, str> {
+    match core::str::from_utf8(bytes) {
+        Ok(name) => Cow::Borrowed(name),
+        Err(_) => encode(bytes),
+    }
+}
@@ -0,0 +1,196 @@
+//! Reads of long runs of blocks, in requests of up to [`MAX_BATCH_BLOCKS`]
+//! queued on the device several at once, for the readahead of the cache,
+//! see [`crate::cache`].
+//!
+//! With the `irq` feature, the task reading sleeps until the device raises
+//! its IRQ as a read completes, if the device has one. Otherwise, as while
+//! booting, before IRQs are enabled, the completions are polled for.
+
+use axdriver::prelude::*;
+use axsync::Mutex;
+
+use crate::dev::{BLOCK_SIZE, MAX_BATCH_BLOCKS, io_wait};
+#[cfg(feature = "fault-inject")]
+use crate::dev::{FaultPoint, inject_fault};
+
+/// Read whole blocks from `block_id` of `dev` into `buf`, with as many
+/// requests outstanding at once as the device can take.
+///
+/// It only returns once every request queued has completed, even if one
+/// failed, as they read into `buf`.
+pub(crate) fn read_queued(dev: &Mutex<AxBlockDevice>, block_id: u64, buf: &mut [u8]) -> DevResult {
+    #[cfg(feature = "fault-inject")]
+    inject_fault(FaultPoint::BlockRead)?;
+    let depth = dev.lock().queue_depth();
+    let mut requests = buf
+        .chunks_mut(MAX_BATCH_BLOCKS * BLOCK_SIZE)
+        .enumerate()
+        .map(|(i, chunk)| (block_id + (i * MAX_BATCH_BLOCKS) as u64, chunk));
+    if depth <= 1 {
+        return requests.try_for_each(|(id, chunk)| io_wait(|| dev.lock().read_block(id, chunk)));
+    }
+    io_wait(|| {
+        let mut outstanding = 0;
+        let mut res = Ok(());
+        loop {
+            while outstanding < depth && res.is_ok() {
+                let Some((id, chunk)) = requests.next() else {
+                    break;
+                };
+                // SAFETY: `chunk` is borrowed from `buf` until the function
+                // returns, after every read queued has completed, and the
+                // device stays in its mutex.
+                match unsafe { dev.lock().start_read(id, chunk) } {
+                    Ok(_) => outstanding += 1,
+                    Err(err) => res = Err(err),
+                }
+            }
+            if outstanding == 0 {
+                return res;
+            }
+            let done = next_completion(dev);
+            outstanding -= 1;
+            if res.is_ok() {
+                res = done;
+            }
+        }
+    })
+}
+
+/// Wait for a read queued on `dev` to complete, and finish it.
+fn next_completion(dev: &Mutex<AxBlockDevice>) -> DevResult {
+    loop {
+        let waiter = Waiter::arm(dev);
+        if let Some((_, res)) = dev.lock().poll_read() {
+            return res;
+        }
+        waiter.wait();
+    }
+}
+
+/// Polls for the next completion.
+#[cfg(not(feature = "irq"))]
+struct Waiter;
+
+#[cfg(not(feature = "irq"))]
+impl Waiter {
+    fn arm(_dev: &Mutex<AxBlockDevice>) -> Self {
+        Self
+    }
+
+    fn wait(self) {
+        core::hint::spin_loop();
+    }
+}
+
+/// Sleeps until the next completion IRQ, if the device raises one and IRQs
+/// are enabled, or polls for the next completion otherwise.
+#[cfg(feature = "irq")]
+struct Waiter {
+    /// The count of completion IRQs when armed, if they are waited for.
+    armed: Option<u64>,
+}
+
+#[cfg(feature = "irq")]
+impl Waiter {
+    /// Prepare to wait for the completions after the ones `dev` has
+    /// signalled so far, which are acknowledged.
+    fn arm(dev: &Mutex<AxBlockDevice>) -> Self {
+        if !axhal::arch::irqs_enabled() {
+            return Self { armed: None };
+        }
+        let mut dev = dev.lock();
+        let Some(irq) = dev.completion_irq().filter(|&irq| irq::register(irq)) else {
+            return Self { armed: None };
+        };
+        // Counted before acknowledging, so that no completion raising the
+        // IRQ again after it is missed.
+        let armed = irq::count();
+        dev.ack_completions();
+        axhal::irq::set_enable(irq, true);
+        Self { armed: Some(armed) }
+    }
+
+    fn wait(self) {
+        match self.armed {
+            Some(armed) => irq::wait(armed),
+            None => core::hint::spin_loop(),
+        }
+    }
+}
+
+/// The completion IRQs of the block devices.
+///
+/// A device keeps its IRQ raised until it is acknowledged, which takes the
+/// device, locked by the task reading from it. So the handler masks the
+/// IRQs and wakes the tasks up, and each unmasks its device's after
+/// acknowledging it.
+#[cfg(feature = "irq")]
+mod irq {
+    use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+    use axtask::WaitQueue;
+
+    /// The most block devices whose IRQs are handled.
+    const MAX_IRQS: usize = 8;
+
+    const NO_IRQ: usize = usize::MAX;
+
+    /// Set in a slot of an IRQ whose handler could not be registered, so
+    /// that it is not tried again.
+    const REFUSED: usize = 1 << (usize::BITS - 1);
+
+    /// The IRQs handled, [`NO_IRQ`] in the free slots.
+    static IRQS: [AtomicUsize; MAX_IRQS] = [const { AtomicUsize::new(NO_IRQ) }; MAX_IRQS];
+
+    /// The count of completion IRQs taken.
+    static COUNT: AtomicU64 = AtomicU64::new(0);
+
+    /// The tasks waiting for a completion IRQ.
+    static WAIT_QUEUE: WaitQueue = WaitQueue::new();
+
+    fn handle_irq() {
+        for irq in &IRQS {
+            let irq = irq.load(Ordering::Acquire);
+            if irq & REFUSED == 0 {
+                axhal::irq::set_enable(irq, false);
+            }
+        }
+        COUNT.fetch_add(1, Ordering::Release);
+        WAIT_QUEUE.notify_all(false);
+    }
+
+    /// Handle `irq` as a completion IRQ, if it is not yet. Returns whether
+    /// it is handled.
+    pub fn register(irq: usize) -> bool {
+        if let Some(slot) = IRQS
+            .iter()
+            .map(|slot| slot.load(Ordering::Acquire))
+            .find(|&slot| slot & !REFUSED == irq)
+        {
+            return slot & REFUSED == 0;
+        }
+        let Some(slot) = IRQS.iter().find(|slot| {
+            slot.compare_exchange(NO_IRQ, irq, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        }) else {
+            warn!("too many block device IRQs, polling for IRQ {}", irq);
+            return false;
+        };
+        if !axhal::irq::register_handler(irq, handle_irq) {
+            slot.store(irq | REFUSED, Ordering::Release);
+            return false;
+        }
+        true
+    }
+
+    /// The count of completion IRQs taken so far.
+    pub fn count() -> u64 {
+        COUNT.load(Ordering::Acquire)
+    }
+
+    /// Sleep until a completion IRQ is taken after `count` were.
+    pub fn wait(count: u64) {
+        WAIT_QUEUE.wait_until(|| self::count() != count);
+    }
+}
@@ -16,27 +16,78 @@ use crate::{
     mounts,
 };
 
+/// A part of the working directory of a process.
+///
+/// Processes made with `CLONE_FS` refer to the same value, which one of them
+/// may later swap for a copy of its own, see [`CwdRef::unshare`]. The
+/// threads of a process always use the same one, as the namespace holding it
+/// belongs to the process.
+pub struct CwdRef<T>(RwLock<Arc<Mutex<T>>>);
+
+impl<T: Clone> CwdRef<T> {
+    /// Create a reference to a value of its own.
+    pub fn new(value: T) -> Self {
+        Self(RwLock::new(Arc::new(Mutex::new(value))))
+    }
+
+    /// The value in use.
+    pub fn get(&self) -> Arc<Mutex<T>> {
+        self.0.read().clone()
+    }
+
+    /// Return a reference to a copy of the value, e.g. on fork.
+    pub fn copy(&self) -> Self {
+        Self::new(self.get().lock().clone())
+    }
+
+    /// Return a reference to the same value, as with `CLONE_FS`.
+    pub fn share(&self) -> Self {
+        Self(RwLock::new(self.get()))
+    }
+
+    /// Swap the value for a copy, unless no other process uses it.
+    ///
+    /// Used by `unshare(CLONE_FS)`.
+    pub fn unshare(&self) {
+        let mut value = self.0.write();
+        if Arc::strong_count(&value) > 1 {
+            let copy = value.lock().clone();
+            *value = Arc::new(Mutex::new(copy));
+        }
+    }
+}
+
 def_resource! {
-    pub static CURRENT_DIR_PATH: ResArc<Mutex<String>> = ResArc::new();
-    pub static CURRENT_DIR: ResArc<Mutex<VfsNodeRef>> = ResArc::new();
+    pub static CURRENT_DIR_PATH: ResArc<CwdRef<String>> = ResArc::new();
+    pub static CURRENT_DIR: ResArc<CwdRef<VfsNodeRef>> = ResArc::new();
 }
 
 impl CURRENT_DIR_PATH {
-    /// Return a copy of the inner path.
-    pub fn copy_inner(&self) -> Mutex<String> {
-        Mutex::new(self.lock().clone())
+    /// Return a reference to a copy of the inner path.
+    pub fn copy_inner(&self) -> CwdRef<String> {
+        self.copy()
+    }
+
+    /// Return a reference to the same path, as with `CLONE_FS`.
+    pub fn share_inner(&self) -> CwdRef<String> {
+        self.share()
     }
 }
 
 impl CURRENT_DIR {
-    /// Return a copy of the CURRENT_DIR_NODE.
-    pub fn copy_inner(&self) -> Mutex<VfsNodeRef> {
-        Mutex::new(self.lock().clone())
+    /// Return a reference to a copy of the CURRENT_DIR_NODE.
+    pub fn copy_inner(&self) -> CwdRef<VfsNodeRef> {
+        self.copy()
+    }
+
+    /// Return a reference to the same node, as with `CLONE_FS`.
+    pub fn share_inner(&self) -> CwdRef<VfsNodeRef> {
+        self.share()
     }
 }
 
 struct MountPoint {
-    path: &'static str,
+    path: String,
     fs: Arc<dyn VfsOps>,
 }
 
@@ -48,11 +99,18 @@ struct RootDirectory {
 static ROOT_DIR: LazyInit<Arc<RootDirectory>> = LazyInit::new();
 
 impl MountPoint {
-    pub fn new(path: &'static str, fs: Arc<dyn VfsOps>) -> Self {
-        Self { path, fs }
+    pub fn new(path: &str, fs: Arc<dyn VfsOps>) -> Self {
+        Self {
+            path: path.into(),
+            fs,
+        }
     }
 }
 
+/// A filesystem taken off its mount point, which is unmounted, flushing it,
+/// once dropped.
+pub struct DetachedFs(#[allow(dead_code)] MountPoint);
+
 impl Drop for MountPoint {
     fn drop(&mut self) {
         self.fs.umount().ok();
@@ -67,7 +125,7 @@ impl RootDirectory {
         }
     }
 
-    pub fn mount(&self, path: &'static str, fs: Arc<dyn VfsOps>) -> AxResult {
+    pub fn mount(&self, path: &str, fs: Arc<dyn VfsOps>) -> AxResult {
         if path == "/" {
             return ax_err!(InvalidInput, "cannot mount root filesystem");
         }
@@ -84,8 +142,13 @@ impl RootDirectory {
         Ok(())
     }
 
-    pub fn _umount(&self, path: &str) {
-        self.mounts.write().retain(|mp| mp.path != path);
+    pub fn detach(&self, path: &str) -> AxResult<DetachedFs> {
+        let mut mounts = self.mounts.write();
+        let idx = mounts
+            .iter()
+            .position(|mp| mp.path == path)
+            .ok_or(AxError::NotFound)?;
+        Ok(DetachedFs(mounts.remove(idx)))
     }
 
     pub fn contains(&self, path: &str) -> bool {
@@ -207,16 +270,121 @@ pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
 
     ROOT_DIR.init_once(Arc::new(root_dir));
     info!("rootfs initialized");
-    CURRENT_DIR.init_new(Mutex::new(ROOT_DIR.clone()));
+    CURRENT_DIR.init_new(CwdRef::new(ROOT_DIR.clone()));
     info!("test");
-    CURRENT_DIR_PATH.init_new(Mutex::new("/".into()));
+    CURRENT_DIR_PATH.init_new(CwdRef::new("/".into()));
+}
+
+/// Mount `fs` on the directory `path`.
+pub(crate) fn mount(path: &str, fs: Arc<dyn VfsOps>) -> AxResult {
+    ROOT_DIR.mount(path, fs)
+}
+
+/// Unmount the filesystem mounted on `path`.
+pub(crate) fn umount(path: &str) -> AxResult {
+    // Dropping the mount point unmounts the filesystem.
+    ROOT_DIR.detach(path).map(drop)
+}
+
+/// Take the filesystem mounted on `path` off it, leaving it mounted until
+/// dropped.
+pub(crate) fn detach(path: &str) -> AxResult<DetachedFs> {
+    ROOT_DIR.detach(path)
+}
+
+/// Mount the FAT image in the regular file `image` on the directory `path`.
+pub(crate) fn mount_fat_image(image: &str, path: &str) -> AxResult {
+    cfg_if::cfg_if! {
+        if #[cfg(all(feature = "fatfs", not(feature = "myfs"), not(feature = "lwext4_rs")))] {
+            let image = lookup(None, image)?;
+            if !image.get_attr()?.is_file() {
+                return ax_err!(InvalidInput, "loop mount of a non-regular file");
+            }
+            let fs = Arc::new(fs::fatfs::FatFileSystemFromFile::new(image)?);
+            fs.init();
+            // Unmounted, flushed and freed once the mount point is dropped.
+            mount(path, fs)
+        } else {
+            let _ = (image, path);
+            ax_err!(Unsupported, "FAT images need the fatfs feature")
+        }
+    }
+}
+
+/// Read the times of the file or directory at `path` from its entry in its
+/// directory, if it is on a FAT filesystem.
+///
+/// Fails with `Unsupported` if it is not, or has no entry, as the root
+/// directory of a filesystem.
+pub(crate) fn fat_times(path: &str) -> AxResult<crate::FileTimes> {
+    cfg_if::cfg_if! {
+        if #[cfg(all(feature = "fatfs", not(feature = "myfs"), not(feature = "lwext4_rs")))] {
+            let path = path.trim_end_matches('/');
+            let Some((parent, name)) = path.rsplit_once('/') else {
+                return ax_err!(InvalidInput);
+            };
+            if name.is_empty() || ROOT_DIR.contains(path) {
+                return ax_err!(Unsupported);
+            }
+            let parent = lookup(None, if parent.is_empty() { "/" } else { parent })?;
+            fs::fatfs::entry_times(&parent, name).unwrap_or(Err(AxError::Unsupported))
+        } else {
+            let _ = path;
+            ax_err!(Unsupported)
+        }
+    }
+}
+
+/// Set the access and modification times of `file`, leaving a `None` one
+/// unchanged, in its entry in its directory, if it is a file of a FAT
+/// filesystem, or fail with `Unsupported`.
+pub(crate) fn set_fat_times(
+    file: &VfsNodeRef,
+    accessed: Option<core::time::Duration>,
+    modified: Option<core::time::Duration>,
+) -> AxResult {
+    cfg_if::cfg_if! {
+        if #[cfg(all(feature = "fatfs", not(feature = "myfs"), not(feature = "lwext4_rs")))] {
+            fs::fatfs::set_times(file, accessed, modified).unwrap_or(Err(AxError::Unsupported))
+        } else {
+            let _ = (file, accessed, modified);
+            ax_err!(Unsupported)
+        }
+    }
+}
+
+/// The newest modification time of the files and directories under the
+/// directory `path`, at any depth, if it is on a FAT filesystem, or fail
+/// with `Unsupported`. The filesystems mounted under it are not looked at.
+pub(crate) fn newest_fat_modified(path: &str) -> AxResult<core::time::Duration> {
+    cfg_if::cfg_if! {
+        if #[cfg(all(feature = "fatfs", not(feature = "myfs"), not(feature = "lwext4_rs")))] {
+            fs::fatfs::newest_modified_under(&lookup(None, path)?).ok_or(AxError::Unsupported)
+        } else {
+            let _ = path;
+            ax_err!(Unsupported)
+        }
+    }
+}
+
+/// Mount an empty RAM filesystem on the directory `path`.
+pub(crate) fn mount_ramfs(path: &str) -> AxResult {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "ramfs")] {
+            mount(path, mounts::ramfs())
+        } else {
+            let _ = path;
+            ax_err!(Unsupported, "RAM filesystems need the ramfs feature")
+        }
+    }
 }
 
 fn parent_node_of(dir: Option<&VfsNodeRef>, path: &str) -> VfsNodeRef {
     if path.starts_with('/') {
         ROOT_DIR.clone()
     } else {
-        dir.cloned().unwrap_or_else(|| CURRENT_DIR.lock().clone())
+        dir.cloned()
+            .unwrap_or_else(|| CURRENT_DIR.get().lock().clone())
     }
 }
 
@@ -224,7 +392,7 @@ pub(crate) fn absolute_path(path: &str) -> AxResult<String> {
     if path.starts_with('/') {
         Ok(axfs_vfs::path::canonicalize(path))
     } else {
-        let path = CURRENT_DIR_PATH.lock().clone() + path;
+        let path = CURRENT_DIR_PATH.get().lock().clone() + path;
         Ok(axfs_vfs::path::canonicalize(&path))
     }
 }
@@ -302,7 +470,7 @@ pub(crate) fn remove_dir(dir: Option<&VfsNodeRef>, path: &str) -> AxResult {
 }
 
 pub(crate) fn current_dir() -> AxResult<String> {
-    Ok(CURRENT_DIR_PATH.lock().clone())
+    Ok(CURRENT_DIR_PATH.get().lock().clone())
 }
 
 pub(crate) fn set_current_dir(path: &str) -> AxResult {
@@ -311,8 +479,8 @@ pub(crate) fn set_current_dir(path: &str) -> AxResult {
         abs_path += "/";
     }
     if abs_path == "/" {
-        *CURRENT_DIR.lock() = ROOT_DIR.clone();
-        *CURRENT_DIR_PATH.lock() = "/".into();
+        *CURRENT_DIR.get().lock() = ROOT_DIR.clone();
+        *CURRENT_DIR_PATH.get().lock() = "/".into();
         return Ok(());
     }
 
@@ -323,8 +491,8 @@ pub(crate) fn set_current_dir(path: &str) -> AxResult {
     } else if !attr.perm().owner_executable() {
         ax_err!(PermissionDenied)
     } else {
-        *CURRENT_DIR.lock() = node;
-        *CURRENT_DIR_PATH.lock() = abs_path;
+        *CURRENT_DIR.get().lock() = node;
+        *CURRENT_DIR_PATH.get().lock() = abs_path;
         Ok(())
     }
 }
@@ -0,0 +1,73 @@
+//! The times of files, for the filesystems which store them.
+//!
+//! Only FAT does among the ones here: each directory entry has a creation
+//! and modification time, to 2 seconds, and an access date. They are local
+//! times on disk, taken for UTC, as Linux does without a `tz` option.
+
+#![cfg_attr(
+    not(all(feature = "fatfs", not(feature = "myfs"), not(feature = "lwext4_rs"))),
+    allow(dead_code)
+)]
+
+use core::time::Duration;
+
+use spin::Once;
+
+/// The times of a file as its filesystem stores them, since the epoch.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FileTimes {
+    /// The last access.
+    pub accessed: Duration,
+    /// The last modification of the content.
+    pub modified: Duration,
+    /// The creation.
+    pub created: Duration,
+}
+
+static WALL_CLOCK: Once<fn() -> Duration> = Once::new();
+
+/// Date what the filesystems write with `clock`, which tells the time since
+/// the epoch. Until set, they date it at the epoch. The clock can only be
+/// set once.
+pub fn set_wall_clock(clock: fn() -> Duration) {
+    WALL_CLOCK.call_once(|| clock);
+}
+
+/// The time since the epoch, by the clock set with [`set_wall_clock`].
+pub(crate) fn now() -> Duration {
+    WALL_CLOCK.get().map_or(Duration::ZERO, |clock| clock())
+}
+
+/// The `(year, month, day)` of the day `days` after the epoch, in the
+/// proleptic Gregorian calendar.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // Days since 0000-03-01, in eras of 400 years, which start in March so
+    // that the leap day is the last of a year.
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// The days from the epoch to `year`-`month`-`day`, the inverse of
+/// [`civil_from_days`].
+pub(crate) fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_from_march = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
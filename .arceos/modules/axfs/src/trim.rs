@@ -0,0 +1,288 @@
+//! Discarding the free clusters of a FAT filesystem on a block device, so
+//! that the image backing the device does not keep growing with the data of
+//! files deleted long ago.
+//!
+//! The FAT driver frees clusters without telling which, so each cluster is
+//! tracked as clean once discarded, until a block of it is written again. A
+//! pass reads the FAT and discards the free clusters which are not clean,
+//! in runs of adjacent blocks. A pass runs when the device is synced after
+//! the filesystem removed or truncated a file, see [`note_clusters_freed`],
+//! and on request, see [`BlockDevice::trim`], which discards every free
+//! cluster. The clusters free when the filesystem is mounted are taken as
+//! clean.
+//!
+//! The virtio-blk driver takes no `DISCARD` or `WRITE_ZEROES` requests, so
+//! discarding writes zeros instead, if [`set_discard_zeroes`] allows it.
+//! QEMU turns them into holes in the image with `detect-zeroes=unmap`, and
+//! `qemu-img convert` leaves them out otherwise. Without it, no pass runs.
+//! The cached copies of the blocks discarded are dropped, dirty or not, so
+//! what a deleted file left in the cache is never written back.
+//!
+//! A pass holds the cache of the device throughout, so the filesystem, whose
+//! accesses all go through the cache, neither allocates a cluster nor writes
+//! to one in the middle of it. Only FAT16 and FAT32 are trimmed.
+//!
+//! [`BlockDevice::trim`]: crate::BlockDevice::trim
+
+use alloc::{vec, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use axdriver::prelude::*;
+use axsync::Mutex;
+
+use crate::{
+    cache::BlockCache,
+    dev::{BLOCK_SIZE, MAX_BATCH_BLOCKS},
+};
+
+/// Whether blocks are discarded by writing zeros, see [`set_discard_zeroes`].
+static DISCARD_ZEROES: AtomicBool = AtomicBool::new(true);
+
+/// Counts the times a filesystem freed clusters, see [`note_clusters_freed`].
+static FREED: AtomicU64 = AtomicU64::new(0);
+
+/// Whether blocks are discarded by writing zeros, as they are by default.
+pub fn discard_zeroes() -> bool {
+    DISCARD_ZEROES.load(Ordering::Relaxed)
+}
+
+/// Set whether blocks are discarded by writing zeros. Without it, nothing
+/// is discarded, as the devices take no other way.
+pub fn set_discard_zeroes(on: bool) {
+    DISCARD_ZEROES.store(on, Ordering::Relaxed);
+}
+
+/// Tell that a filesystem may have freed clusters, so that the devices are
+/// trimmed once synced.
+pub(crate) fn note_clusters_freed() {
+    FREED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counters of the passes over a device, shown in `/proc/starry/fstrim`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrimStats {
+    /// The passes.
+    pub passes: u64,
+    /// The blocks discarded.
+    pub discarded: u64,
+    /// The device requests they were discarded with.
+    pub requests: u64,
+}
+
+/// Where the FAT and the clusters are on a device.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FatLayout {
+    /// The first block of the first FAT.
+    fat_start: u64,
+    /// Whether the entries of the FAT are 32-bit, else 16-bit.
+    fat32: bool,
+    /// The block of cluster 2, the first one.
+    data_start: u64,
+    cluster_blocks: u64,
+    /// The number of clusters, from cluster 2.
+    clusters: u32,
+}
+
+impl FatLayout {
+    /// The layout in the boot sector `boot`, if it is one of a FAT16 or
+    /// FAT32 filesystem with sectors of [`BLOCK_SIZE`].
+    pub fn parse(boot: &[u8; BLOCK_SIZE]) -> Option<Self> {
+        let u16_at = |at: usize| u16::from_le_bytes([boot[at], boot[at + 1]]) as u64;
+        let u32_at = |at: usize| u32::from_le_bytes(boot[at..at + 4].try_into().unwrap()) as u64;
+        if boot[510..] != [0x55, 0xaa] || u16_at(11) != BLOCK_SIZE as u64 {
+            return None;
+        }
+        let cluster_blocks = boot[13] as u64;
+        let reserved = u16_at(14);
+        let fats = boot[16] as u64;
+        let root_blocks = (u16_at(17) * 32).div_ceil(BLOCK_SIZE as u64);
+        let total = match u16_at(19) {
+            0 => u32_at(32),
+            total => total,
+        };
+        let fat_blocks = match u16_at(22) {
+            0 => u32_at(36),
+            blocks => blocks,
+        };
+        if cluster_blocks == 0 || reserved == 0 || fats == 0 || fat_blocks == 0 {
+            return None;
+        }
+        let data_start = reserved + fats * fat_blocks + root_blocks;
+        let clusters = total.checked_sub(data_start)? / cluster_blocks;
+        // As the FAT type is told by the number of clusters.
+        let fat32 = match clusters {
+            0..4085 => return None,
+            4085..65525 => false,
+            _ => true,
+        };
+        // The FAT may be shorter than it should, if the filesystem is broken.
+        let entries = fat_blocks * BLOCK_SIZE as u64 / if fat32 { 4 } else { 2 };
+        Some(Self {
+            fat_start: reserved,
+            fat32,
+            data_start,
+            cluster_blocks,
+            clusters: clusters.min(entries.saturating_sub(2)) as u32,
+        })
+    }
+
+    /// Call `f` with each cluster, from 0 for cluster 2, and whether it is
+    /// free, reading the FAT through `cache`.
+    fn for_each_cluster(
+        &self,
+        cache: &mut BlockCache,
+        dev: &Mutex<AxBlockDevice>,
+        mut f: impl FnMut(u32, bool),
+    ) -> DevResult {
+        let entry_size = if self.fat32 { 4 } else { 2 };
+        let entries = self.clusters as usize + 2;
+        let blocks = (entries * entry_size).div_ceil(BLOCK_SIZE);
+        let mut buf = vec![0; MAX_BATCH_BLOCKS * BLOCK_SIZE];
+        let mut entry = 0;
+        for start in (0..blocks).step_by(MAX_BATCH_BLOCKS) {
+            let count = (blocks - start).min(MAX_BATCH_BLOCKS);
+            let buf = &mut buf[..count * BLOCK_SIZE];
+            cache.read(dev, self.fat_start + start as u64, buf)?;
+            for raw in buf.chunks_exact(entry_size) {
+                if entry >= 2 && entry < entries {
+                    let free = if self.fat32 {
+                        u32::from_le_bytes(raw.try_into().unwrap()) & 0x0fff_ffff == 0
+                    } else {
+                        raw == [0, 0]
+                    };
+                    f((entry - 2) as u32, free);
+                }
+                entry += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which clusters are clean, with nothing written to them since they were
+/// last discarded, or since the filesystem was mounted.
+pub(crate) struct CleanMap {
+    data_start: u64,
+    cluster_blocks: u64,
+    bits: Vec<u64>,
+}
+
+impl CleanMap {
+    fn new(layout: &FatLayout) -> Self {
+        Self {
+            data_start: layout.data_start,
+            cluster_blocks: layout.cluster_blocks,
+            bits: vec![0; (layout.clusters as usize).div_ceil(64)],
+        }
+    }
+
+    fn is_clean(&self, cluster: u32) -> bool {
+        self.bits[cluster as usize / 64] & (1 << (cluster % 64)) != 0
+    }
+
+    fn set_clean(&mut self, cluster: u32) {
+        self.bits[cluster as usize / 64] |= 1 << (cluster % 64);
+    }
+
+    /// Mark the clusters of `count` blocks from `block_id` as written to.
+    pub fn written(&mut self, block_id: u64, count: usize) {
+        let end = block_id + count as u64;
+        if end <= self.data_start {
+            return;
+        }
+        let first = (block_id.max(self.data_start) - self.data_start) / self.cluster_blocks;
+        let last = (end - 1 - self.data_start) / self.cluster_blocks;
+        for cluster in first..=last.min(self.bits.len() as u64 * 64 - 1) {
+            self.bits[cluster as usize / 64] &= !(1 << (cluster % 64));
+        }
+    }
+}
+
+/// What a device has to be trimmed, once the filesystem on it is mounted.
+pub(crate) struct Trim {
+    layout: FatLayout,
+    /// The count of [`FREED`] as of the last pass.
+    freed_seen: u64,
+    stats: TrimStats,
+}
+
+impl Trim {
+    /// Read the layout of the filesystem on the device, if it is one which
+    /// can be trimmed, and mark the clusters free now as clean in `cache`.
+    pub fn new(cache: &mut BlockCache, dev: &Mutex<AxBlockDevice>) -> DevResult<Option<Self>> {
+        let mut boot = [0; BLOCK_SIZE];
+        cache.read(dev, 0, &mut boot)?;
+        let Some(layout) = FatLayout::parse(&boot) else {
+            return Ok(None);
+        };
+        let mut clean = CleanMap::new(&layout);
+        layout.for_each_cluster(cache, dev, |cluster, free| {
+            if free {
+                clean.set_clean(cluster);
+            }
+        })?;
+        cache.set_clean_map(clean);
+        Ok(Some(Self {
+            layout,
+            freed_seen: FREED.load(Ordering::Relaxed),
+            stats: TrimStats::default(),
+        }))
+    }
+
+    pub fn stats(&self) -> TrimStats {
+        self.stats
+    }
+
+    /// Whether a filesystem freed clusters since the last pass.
+    pub fn freed_since(&self) -> bool {
+        FREED.load(Ordering::Relaxed) != self.freed_seen
+    }
+
+    /// Discard the free clusters which are not clean, or all free ones if
+    /// `all`, returning the number of blocks discarded.
+    ///
+    /// If it fails, the clusters not discarded are left for the next pass,
+    /// which still runs once the device is synced.
+    pub fn pass(
+        &mut self,
+        cache: &mut BlockCache,
+        dev: &Mutex<AxBlockDevice>,
+        all: bool,
+    ) -> DevResult<u64> {
+        let freed = FREED.load(Ordering::Relaxed);
+        let Some(mut clean) = cache.take_clean_map() else {
+            return Ok(0);
+        };
+        // The runs of clusters to discard, as `(first, end)`.
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+        let ret = self.layout.for_each_cluster(cache, dev, |cluster, free| {
+            if !free || (!all && clean.is_clean(cluster)) {
+                return;
+            }
+            match runs.last_mut() {
+                Some((_, end)) if *end == cluster => *end += 1,
+                _ => runs.push((cluster, cluster + 1)),
+            }
+        });
+        let ret = ret.and_then(|_| {
+            let mut discarded = 0;
+            for &(first, end) in &runs {
+                let block_id = self.layout.data_start + first as u64 * self.layout.cluster_blocks;
+                let count = (end - first) as u64 * self.layout.cluster_blocks;
+                self.stats.requests += cache.discard(dev, block_id, count)?;
+                self.stats.discarded += count;
+                discarded += count;
+                for cluster in first..end {
+                    clean.set_clean(cluster);
+                }
+            }
+            Ok(discarded)
+        });
+        cache.set_clean_map(clean);
+        self.stats.passes += 1;
+        if ret.is_ok() {
+            self.freed_seen = freed;
+        }
+        ret
+    }
+}
@@ -250,6 +250,45 @@ fn test_devfs_ramfs() -> Result<()> {
     Ok(())
 }
 
+fn test_positional_io() -> Result<()> {
+    use axfs::fops::{self, OpenOptions};
+    use io::SeekFrom;
+
+    let fname = "/positional.txt";
+    println!("positional reads and writes of {:?}:", fname);
+
+    let mut opts = OpenOptions::new();
+    opts.read(true);
+    opts.write(true);
+    opts.create(true);
+    opts.truncate(true);
+    let mut file = fops::File::open(fname, &opts)?;
+    let data: Vec<u8> = (0..20000u32).map(|i| (i % 251) as u8).collect();
+    assert_eq!(file.write(&data)?, data.len());
+    assert_eq!(file.seek(SeekFrom::Start(100))?, 100);
+
+    // Neither form moves the cursor.
+    let mut buf = [0; 10];
+    assert_eq!(file.read_at(5000, &mut buf)?, 10);
+    assert_eq!(buf[..], data[5000..5010]);
+    assert_eq!(file.write_at(7000, b"positional")?, 10);
+    let mut all = vec![0; data.len() + 10];
+    assert_eq!(file.read_full_at(0, &mut all)?, data.len());
+    assert_eq!(all[7000..7010], *b"positional");
+    assert_eq!(all[..7000], data[..7000]);
+    assert_eq!(file.seek(SeekFrom::Current(0))?, 100);
+
+    // The cursor goes on from where it was.
+    assert_eq!(file.read(&mut buf)?, 10);
+    assert_eq!(buf[..], data[100..110]);
+    assert_eq!(file.seek(SeekFrom::Current(0))?, 110);
+    drop(file);
+    fs::remove_file(fname)?;
+
+    println!("test_positional_io() OK!");
+    Ok(())
+}
+
 pub fn test_all() {
     test_read_write_file().expect("test_read_write_file() failed");
     test_read_dir().expect("test_read_dir() failed");
@@ -257,4 +296,5 @@ pub fn test_all() {
     test_create_file_dir().expect("test_create_file_dir() failed");
     test_remove_file_dir().expect("test_remove_file_dir() failed");
     test_devfs_ramfs().expect("test_devfs_ramfs() failed");
+    test_positional_io().expect("test_positional_io() failed");
 }
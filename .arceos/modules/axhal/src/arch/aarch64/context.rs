@@ -249,8 +249,42 @@ pub struct FpState {
 #[cfg(feature = "fp_simd")]
 impl FpState {
     fn switch_to(&mut self, next_fpstate: &FpState) {
-        unsafe { fpstate_switch(self, next_fpstate) }
+        unsafe {
+            save_fp_registers(self);
+            restore_fp_registers(next_fpstate);
+        }
+    }
+}
+
+/// Copy the FP/SIMD registers of the running task to `state`, as a signal
+/// frame is built.
+///
+/// Returns `false`, leaving `state` as it is, without the `fp_simd`
+/// feature.
+pub fn save_user_fp(state: &mut FpState) -> bool {
+    #[cfg(feature = "fp_simd")]
+    {
+        unsafe { save_fp_registers(state) };
+        true
+    }
+    #[cfg(not(feature = "fp_simd"))]
+    {
+        let _ = state;
+        false
+    }
+}
+
+/// Load `state`, from a signal frame returned from, into the FP/SIMD
+/// registers of the running task.
+///
+/// Does nothing without the `fp_simd` feature.
+pub fn restore_user_fp(state: &FpState) {
+    #[cfg(feature = "fp_simd")]
+    unsafe {
+        restore_fp_registers(state);
     }
+    #[cfg(not(feature = "fp_simd"))]
+    let _ = state;
 }
 
 /// Saved hardware states of a task.
@@ -373,7 +407,7 @@ unsafe extern "C" fn context_switch(_current_task: &mut TaskContext, _next_task:
 
 #[unsafe(naked)]
 #[cfg(feature = "fp_simd")]
-unsafe extern "C" fn fpstate_switch(_current_fpstate: &mut FpState, _next_fpstate: &FpState) {
+unsafe extern "C" fn save_fp_registers(_fpstate: &mut FpState) {
     naked_asm!(
         "
         // save fp/neon context
@@ -398,25 +432,34 @@ unsafe extern "C" fn fpstate_switch(_current_fpstate: &mut FpState, _next_fpstat
         str     x9, [x0, 64 *  8]
         str     x10, [x0, 65 * 8]
 
+        ret",
+    )
+}
+
+#[unsafe(naked)]
+#[cfg(feature = "fp_simd")]
+unsafe extern "C" fn restore_fp_registers(_fpstate: &FpState) {
+    naked_asm!(
+        "
         // restore fp/neon context
-        ldp     q0, q1, [x1, 0 * 16]
-        ldp     q2, q3, [x1, 2 * 16]
-        ldp     q4, q5, [x1, 4 * 16]
-        ldp     q6, q7, [x1, 6 * 16]
-        ldp     q8, q9, [x1, 8 * 16]
-        ldp     q10, q11, [x1, 10 * 16]
-        ldp     q12, q13, [x1, 12 * 16]
-        ldp     q14, q15, [x1, 14 * 16]
-        ldp     q16, q17, [x1, 16 * 16]
-        ldp     q18, q19, [x1, 18 * 16]
-        ldp     q20, q21, [x1, 20 * 16]
-        ldp     q22, q23, [x1, 22 * 16]
-        ldp     q24, q25, [x1, 24 * 16]
-        ldp     q26, q27, [x1, 26 * 16]
-        ldp     q28, q29, [x1, 28 * 16]
-        ldp     q30, q31, [x1, 30 * 16]
-        ldr     x9, [x1, 64 * 8]
-        ldr     x10, [x1, 65 * 8]
+        ldp     q0, q1, [x0, 0 * 16]
+        ldp     q2, q3, [x0, 2 * 16]
+        ldp     q4, q5, [x0, 4 * 16]
+        ldp     q6, q7, [x0, 6 * 16]
+        ldp     q8, q9, [x0, 8 * 16]
+        ldp     q10, q11, [x0, 10 * 16]
+        ldp     q12, q13, [x0, 12 * 16]
+        ldp     q14, q15, [x0, 14 * 16]
+        ldp     q16, q17, [x0, 16 * 16]
+        ldp     q18, q19, [x0, 18 * 16]
+        ldp     q20, q21, [x0, 20 * 16]
+        ldp     q22, q23, [x0, 22 * 16]
+        ldp     q24, q25, [x0, 24 * 16]
+        ldp     q26, q27, [x0, 26 * 16]
+        ldp     q28, q29, [x0, 28 * 16]
+        ldp     q30, q31, [x0, 30 * 16]
+        ldr     x9, [x0, 64 * 8]
+        ldr     x10, [x0, 65 * 8]
         msr     fpcr, x9
         msr     fpsr, x10
 
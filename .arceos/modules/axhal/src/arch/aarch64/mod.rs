@@ -11,7 +11,7 @@ use tock_registers::interfaces::{Readable, Writeable};
 
 #[cfg(feature = "uspace")]
 pub use self::context::UspaceContext;
-pub use self::context::{FpState, TaskContext, TrapFrame};
+pub use self::context::{FpState, TaskContext, TrapFrame, restore_user_fp, save_user_fp};
 
 /// Allows the current CPU to respond to interrupts.
 #[inline]
@@ -39,6 +39,19 @@ pub fn wait_for_irqs() {
     aarch64_cpu::asm::wfi();
 }
 
+/// Enables interrupts and waits for one, without missing one that arrives
+/// in between.
+///
+/// It is called with interrupts disabled, after checking that there is
+/// nothing to do but wait, and returns with them enabled.
+#[inline]
+pub fn enable_irqs_and_wait() {
+    // `wfi` wakes up on a pending interrupt even if it is masked, which is
+    // then taken once enabled.
+    aarch64_cpu::asm::wfi();
+    enable_irqs();
+}
+
 /// Halt the current CPU.
 #[inline]
 pub fn halt() {
@@ -119,6 +119,16 @@ fn handle_sync_exception(tf: &mut TrapFrame, source: TrapSource) {
         Some(ESR_EL1::EC::Value::InstrAbortCurrentEL) => handle_instruction_abort(tf, iss, false),
         Some(ESR_EL1::EC::Value::DataAbortLowerEL) => handle_data_abort(tf, iss, true),
         Some(ESR_EL1::EC::Value::DataAbortCurrentEL) => handle_data_abort(tf, iss, false),
+        // Undefined instructions, SVE among them as it is left disabled.
+        #[cfg(feature = "uspace")]
+        Some(ESR_EL1::EC::Value::Unknown) if source.is_from_user() => {
+            if !crate::trap::handle_illegal_instruction(tf) {
+                panic!(
+                    "Unhandled EL0 undefined instruction @ {:#x}:\n{:#x?}",
+                    tf.elr, tf
+                );
+            }
+        }
         Some(ESR_EL1::EC::Value::Brk64) => {
             debug!("BRK #{:#x} @ {:#x} ", iss, tf.elr);
             tf.elr += 4;
@@ -40,6 +40,19 @@ pub fn wait_for_irqs() {
     unsafe { loongArch64::asm::idle() }
 }
 
+/// Enables interrupts and waits for one, without missing one that arrives
+/// in between.
+///
+/// It is called with interrupts disabled, after checking that there is
+/// nothing to do but wait, and returns with them enabled.
+#[inline]
+pub fn enable_irqs_and_wait() {
+    // `idle` does not wake up on a masked interrupt, so one arriving just
+    // before it waits for the next, which the timer bounds.
+    enable_irqs();
+    unsafe { loongArch64::asm::idle() }
+}
+
 /// Halt the current CPU.
 #[inline]
 pub fn halt() {
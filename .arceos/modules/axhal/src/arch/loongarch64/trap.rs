@@ -61,6 +61,18 @@ fn loongarch64_trap_handler(tf: &mut TrapFrame, from_user: bool) {
             handle_page_fault(tf, MappingFlags::EXECUTE, from_user);
         }
         Trap::Exception(Exception::Breakpoint) => handle_breakpoint(&mut tf.era),
+        #[cfg(feature = "uspace")]
+        Trap::Exception(Exception::InstructionNotExist)
+        | Trap::Exception(Exception::InstructionPrivilegeIllegal)
+            if from_user =>
+        {
+            if !crate::trap::handle_illegal_instruction(tf) {
+                panic!(
+                    "Unhandled PLV3 illegal instruction @ {:#x}:\n{:#x?}",
+                    tf.era, tf
+                );
+            }
+        }
         Trap::Interrupt(_) => {
             let irq_num: usize = estat.is().trailing_zeros() as usize;
             handle_trap!(IRQ, irq_num);
@@ -1,7 +1,10 @@
 use core::arch::naked_asm;
 use memory_addr::VirtAddr;
 #[cfg(feature = "fp_simd")]
-use riscv::register::sstatus::FS;
+use {
+    core::sync::atomic::{AtomicUsize, Ordering},
+    riscv::register::sstatus::{self, FS},
+};
 
 /// General registers of RISC-V.
 #[allow(missing_docs)]
@@ -42,14 +45,26 @@ pub struct GeneralRegisters {
 }
 
 /// Floating-point registers of RISC-V.
+///
+/// The state is switched lazily. A task switched in finds the FPU off in
+/// `sstatus.FS`, unless the registers of the CPU still hold its state, and
+/// its state is only restored by the illegal instruction trap of its first
+/// FP instruction. So tasks which never touch the FPU never pay for it.
+///
+/// A task whose state is dirty is saved when switched out, since it may run
+/// on another CPU next. Its state stays in the registers as well, and is
+/// reused if it runs on the same CPU again before any other task takes the
+/// FPU there.
 #[cfg(feature = "fp_simd")]
 #[repr(C)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug)]
 pub struct FpStatus {
     /// the state of the RISC-V Floating-Point Unit (FPU)
     pub fp: [u64; 32],
     pub fcsr: usize,
     pub fs: FS,
+    /// The CPU which last saved or restored the state, or `usize::MAX`.
+    last_cpu: AtomicUsize,
 }
 
 #[cfg(feature = "fp_simd")]
@@ -59,10 +74,166 @@ impl Default for FpStatus {
             fs: FS::Initial,
             fp: [0; 32],
             fcsr: 0,
+            last_cpu: AtomicUsize::new(usize::MAX),
         }
     }
 }
 
+/// The address of the [`FpStatus`] the FP registers of the CPU hold, if its
+/// `last_cpu` is the CPU.
+#[cfg(feature = "fp_simd")]
+#[percpu::def_percpu]
+static FPU_OWNER: usize = 0;
+
+/// The address of the [`FpStatus`] of the running task, restored on its
+/// first FP instruction.
+#[cfg(feature = "fp_simd")]
+#[percpu::def_percpu]
+static FPU_CURRENT: usize = 0;
+
+#[cfg(feature = "fp_simd")]
+impl FpStatus {
+    /// Whether the FP registers of this CPU hold this state.
+    fn is_loaded(&self, cpu: usize) -> bool {
+        FPU_OWNER.read_current() == self as *const _ as usize
+            && self.last_cpu.load(Ordering::Relaxed) == cpu
+    }
+
+    /// Switch from this state to the one of the next task.
+    fn switch_to(&mut self, next: &Self) {
+        let cpu = crate::cpu::this_cpu_id();
+        let fs = sstatus::read().fs();
+        if fs == FS::Dirty {
+            // The task may run on another CPU next.
+            unsafe { save_fp_registers(&mut self.fp) };
+            self.fs = FS::Clean;
+        }
+        if fs != FS::Off {
+            // The FPU was on for this task, so the registers hold its state.
+            self.last_cpu.store(cpu, Ordering::Relaxed);
+            FPU_OWNER.write_current(self as *const _ as usize);
+        }
+        FPU_CURRENT.write_current(next as *const _ as usize);
+        let fs = if next.is_loaded(cpu) {
+            next.fs
+        } else {
+            FS::Off
+        };
+        unsafe { sstatus::set_fs(fs) };
+    }
+
+    /// Turn the FPU on and load this state into the registers.
+    fn load(&self) {
+        match self.fs {
+            FS::Clean => unsafe {
+                sstatus::set_fs(FS::Clean);
+                restore_fp_registers(&self.fp);
+                sstatus::set_fs(FS::Clean);
+            },
+            _ => unsafe {
+                // Never used, so all 0.
+                sstatus::set_fs(FS::Initial);
+                clear_fp_registers();
+                sstatus::set_fs(FS::Initial);
+            },
+        }
+    }
+}
+
+/// Turn the FPU on and restore the state of the running task, on the
+/// illegal instruction trap of its first FP instruction since it was
+/// switched in. Returns `false` if the FPU was on, so the instruction is
+/// illegal for another reason.
+///
+/// It must be called with IRQs disabled, so no switch comes in between.
+#[cfg(feature = "fp_simd")]
+pub(super) fn handle_fpu_trap() -> bool {
+    let current = FPU_CURRENT.read_current();
+    if sstatus::read().fs() != FS::Off || current == 0 {
+        return false;
+    }
+    let cpu = crate::cpu::this_cpu_id();
+    // SAFETY: the state of the running task lives as long as it runs.
+    let state = unsafe { &*(current as *const FpStatus) };
+    state.load();
+    state.last_cpu.store(cpu, Ordering::Relaxed);
+    FPU_OWNER.write_current(current);
+    true
+}
+
+/// The FP registers of user space, laid out like `__riscv_d_ext_state` in
+/// the signal frames of Linux.
+#[allow(missing_docs)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct UserFpState {
+    pub f: [u64; 32],
+    pub fcsr: u32,
+}
+
+/// Copy the FP state of the running task to `state`, saving it from the
+/// registers first if they hold it changed, as a signal frame is built.
+///
+/// Returns `false`, leaving `state` as it is, without the `fp_simd`
+/// feature.
+pub fn save_user_fp(state: &mut UserFpState) -> bool {
+    #[cfg(feature = "fp_simd")]
+    {
+        // No switch in between, which may take the FPU away.
+        let _guard = kernel_guard::NoPreemptIrqSave::new();
+        let current = FPU_CURRENT.read_current() as *mut FpStatus;
+        if current.is_null() {
+            return false;
+        }
+        // SAFETY: the state of the running task lives as long as it runs.
+        let current = unsafe { &mut *current };
+        if sstatus::read().fs() == FS::Dirty {
+            unsafe {
+                save_fp_registers(&mut current.fp);
+                sstatus::set_fs(FS::Clean);
+            }
+            current.fs = FS::Clean;
+        }
+        state.f = current.fp;
+        state.fcsr = current.fcsr as u32;
+        true
+    }
+    #[cfg(not(feature = "fp_simd"))]
+    {
+        let _ = state;
+        false
+    }
+}
+
+/// Make `state`, from a signal frame returned from, the FP state of the
+/// running task.
+///
+/// Does nothing without the `fp_simd` feature.
+pub fn restore_user_fp(state: &UserFpState) {
+    #[cfg(feature = "fp_simd")]
+    {
+        let _guard = kernel_guard::NoPreemptIrqSave::new();
+        let current = FPU_CURRENT.read_current() as *mut FpStatus;
+        if current.is_null() {
+            return;
+        }
+        // SAFETY: the state of the running task lives as long as it runs.
+        let current = unsafe { &mut *current };
+        current.fp = state.f;
+        current.fcsr = state.fcsr as usize;
+        current.fs = FS::Clean;
+        if sstatus::read().fs() == FS::Off {
+            // Registers left with the old state on another CPU must not be
+            // taken for it.
+            current.last_cpu.store(usize::MAX, Ordering::Relaxed);
+        } else {
+            current.load();
+        }
+    }
+    #[cfg(not(feature = "fp_simd"))]
+    let _ = state;
+}
+
 /// Saved registers when a trap (interrupt or exception) occurs.
 #[repr(C)]
 #[derive(Debug, Default, Clone, Copy)]
@@ -383,41 +554,7 @@ impl TaskContext {
             }
         }
         #[cfg(feature = "fp_simd")]
-        {
-            use riscv::register::sstatus;
-            use riscv::register::sstatus::FS;
-            // get the real FP state of the current task
-            let current_fs = sstatus::read().fs();
-            // save the current task's FP state
-            if current_fs == FS::Dirty {
-                // we need to save the current task's FP state
-                unsafe {
-                    save_fp_registers(&mut self.fp_status.fp);
-                }
-                // after saving, we set the FP state to clean
-                self.fp_status.fs = FS::Clean;
-            }
-            // restore the next task's FP state
-            match next_ctx.fp_status.fs {
-                FS::Clean => unsafe {
-                    // the next task's FP state is clean, we should restore it
-                    restore_fp_registers(&next_ctx.fp_status.fp);
-                    // after restoring, we set the FP state
-                    sstatus::set_fs(FS::Clean);
-                },
-                FS::Initial => unsafe {
-                    // restore the FP state as constant values(all 0)
-                    clear_fp_registers();
-                    // we set the FP state to initial
-                    sstatus::set_fs(FS::Initial);
-                },
-                FS::Dirty => {
-                    // should not happen, since we set FS to Clean after saving
-                    panic!("FP state of the next task should not be dirty");
-                }
-                _ => {}
-            }
-        }
+        self.fp_status.switch_to(&next_ctx.fp_status);
 
         unsafe { context_switch(self, next_ctx) }
     }
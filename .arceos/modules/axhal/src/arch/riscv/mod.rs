@@ -10,7 +10,9 @@ use riscv::register::{satp, sstatus, stvec};
 
 #[cfg(feature = "uspace")]
 pub use self::context::UspaceContext;
-pub use self::context::{GeneralRegisters, TaskContext, TrapFrame};
+pub use self::context::{
+    GeneralRegisters, TaskContext, TrapFrame, UserFpState, restore_user_fp, save_user_fp,
+};
 
 /// Allows the current CPU to respond to interrupts.
 #[inline]
@@ -38,6 +40,19 @@ pub fn wait_for_irqs() {
     riscv::asm::wfi()
 }
 
+/// Enables interrupts and waits for one, without missing one that arrives
+/// in between.
+///
+/// It is called with interrupts disabled, after checking that there is
+/// nothing to do but wait, and returns with them enabled.
+#[inline]
+pub fn enable_irqs_and_wait() {
+    // `wfi` wakes up on a pending interrupt even if `sstatus.SIE` masks
+    // it, which is then taken once enabled.
+    riscv::asm::wfi();
+    enable_irqs();
+}
+
 /// Halt the current CPU.
 #[inline]
 pub fn halt() {
@@ -45,6 +45,11 @@ fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
         // Interrupts modify the value of `stval`, which must be saved before the
         // interrupt is enabled
         let vaddr = va!(stval::read());
+        // Before IRQs are unmasked, so the task is not switched out while
+        // its state is half restored.
+        #[cfg(feature = "fp_simd")]
+        let fpu_restored = matches!(cause, Trap::Exception(E::IllegalInstruction))
+            && super::context::handle_fpu_trap();
         if scause.is_exception() {
             unmask_irqs(tf);
         }
@@ -64,6 +69,17 @@ fn riscv_trap_handler(tf: &mut TrapFrame, from_user: bool) {
                 handle_page_fault(tf, vaddr, MappingFlags::EXECUTE, from_user)
             }
             Trap::Exception(E::Breakpoint) => handle_breakpoint(&mut tf.sepc),
+            #[cfg(feature = "fp_simd")]
+            Trap::Exception(E::IllegalInstruction) if fpu_restored => {}
+            #[cfg(feature = "uspace")]
+            Trap::Exception(E::IllegalInstruction) if from_user => {
+                if !crate::trap::handle_illegal_instruction(tf) {
+                    panic!(
+                        "Unhandled user illegal instruction @ {:#x}:\n{:#x?}",
+                        tf.sepc, tf
+                    );
+                }
+            }
             Trap::Interrupt(_) => {
                 handle_trap!(IRQ, scause.bits());
             }
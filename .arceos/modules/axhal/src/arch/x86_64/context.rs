@@ -1,5 +1,10 @@
-use core::{arch::naked_asm, fmt};
+use core::{arch::naked_asm, fmt, sync::atomic::AtomicUsize};
 use memory_addr::VirtAddr;
+#[cfg(feature = "fp_simd")]
+use {
+    core::sync::atomic::Ordering,
+    x86_64::registers::control::{Cr0, Cr0Flags},
+};
 /// Saved registers when a trap (interrupt or exception) occurs.
 #[allow(missing_docs)]
 #[repr(C)]
@@ -272,7 +277,7 @@ struct ContextSwitchFrame {
 /// See <https://www.felixcloutier.com/x86/fxsave> for more details.
 #[allow(missing_docs)]
 #[repr(C, align(16))]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct FxsaveArea {
     pub fcw: u16,
     pub fsw: u16,
@@ -290,11 +295,35 @@ pub struct FxsaveArea {
 static_assertions::const_assert_eq!(core::mem::size_of::<FxsaveArea>(), 512);
 
 /// Extended state of a task, such as FP/SIMD states.
+///
+/// The state is switched lazily. A task switched in finds the FPU disabled
+/// by `CR0.TS`, unless the registers of the CPU still hold its state, and
+/// its state is only restored by the `#NM` trap of its first FP/SIMD
+/// instruction. So tasks which never touch the FPU never pay for it.
+///
+/// A task which had the FPU enabled is saved when switched out, since it may
+/// run on another CPU next. Its state stays in the registers as well, and is
+/// reused if it runs on the same CPU again before any other task takes the
+/// FPU there.
 pub struct ExtendedState {
     /// Memory region for the FXSAVE/FXRSTOR instruction.
     pub fxsave_area: FxsaveArea,
+    /// The CPU which last saved or restored the state, or `usize::MAX`.
+    last_cpu: AtomicUsize,
 }
 
+/// The address of the [`ExtendedState`] the FP/SIMD registers of the CPU
+/// hold, if its `last_cpu` is the CPU.
+#[cfg(feature = "fp_simd")]
+#[percpu::def_percpu]
+static FPU_OWNER: usize = 0;
+
+/// The address of the [`ExtendedState`] of the running task, restored on
+/// its first FP/SIMD instruction.
+#[cfg(feature = "fp_simd")]
+#[percpu::def_percpu]
+static FPU_CURRENT: usize = 0;
+
 #[cfg(feature = "fp_simd")]
 impl ExtendedState {
     #[inline]
@@ -312,10 +341,117 @@ impl ExtendedState {
         area.fcw = 0x37f;
         area.ftw = 0xffff;
         area.mxcsr = 0x1f80;
-        Self { fxsave_area: area }
+        Self {
+            fxsave_area: area,
+            last_cpu: AtomicUsize::new(usize::MAX),
+        }
+    }
+
+    /// Whether the FP/SIMD registers of this CPU hold this state.
+    fn is_loaded(&self, cpu: usize) -> bool {
+        FPU_OWNER.read_current() == self as *const _ as usize
+            && self.last_cpu.load(Ordering::Relaxed) == cpu
+    }
+
+    /// Switch from this state to the one of the next task.
+    fn switch_to(&mut self, next: &Self) {
+        let cpu = crate::cpu::this_cpu_id();
+        let enabled = !Cr0::read().contains(Cr0Flags::TASK_SWITCHED);
+        if enabled {
+            // The FPU was enabled for this task, which may have changed it.
+            self.save();
+            self.last_cpu.store(cpu, Ordering::Relaxed);
+            FPU_OWNER.write_current(self as *const _ as usize);
+        }
+        FPU_CURRENT.write_current(next as *const _ as usize);
+        let loaded = next.is_loaded(cpu);
+        if loaded != enabled {
+            unsafe { Cr0::update(|cr0| cr0.set(Cr0Flags::TASK_SWITCHED, !loaded)) };
+        }
+    }
+}
+
+/// Enable the FPU and restore the state of the running task, on the `#NM`
+/// trap of its first FP/SIMD instruction since it was switched in.
+///
+/// It must be called with IRQs disabled, so no switch comes in between.
+#[cfg(feature = "fp_simd")]
+pub(super) fn handle_fpu_trap() {
+    let cpu = crate::cpu::this_cpu_id();
+    unsafe { core::arch::asm!("clts") };
+    // SAFETY: the state of the running task lives as long as it runs.
+    let state = unsafe { &*(FPU_CURRENT.read_current() as *const ExtendedState) };
+    state.restore();
+    state.last_cpu.store(cpu, Ordering::Relaxed);
+    FPU_OWNER.write_current(state as *const _ as usize);
+}
+
+/// The `MXCSR` bits `FXRSTOR` takes if the CPU reports no mask.
+#[cfg(feature = "fp_simd")]
+const DEFAULT_MXCSR_MASK: u32 = 0xffbf;
+
+/// Copy the FP/SIMD state of the running task to `area`, saving it from the
+/// registers first if they hold it, as a signal frame is built.
+///
+/// Returns `false`, leaving `area` as it is, without the `fp_simd` feature.
+pub fn save_user_fp(area: &mut FxsaveArea) -> bool {
+    #[cfg(feature = "fp_simd")]
+    {
+        // No switch in between, which may take the FPU away.
+        let _guard = kernel_guard::NoPreemptIrqSave::new();
+        let current = FPU_CURRENT.read_current() as *mut ExtendedState;
+        if current.is_null() {
+            return false;
+        }
+        // SAFETY: the state of the running task lives as long as it runs.
+        let state = unsafe { &mut *current };
+        if !Cr0::read().contains(Cr0Flags::TASK_SWITCHED) {
+            state.save();
+        }
+        *area = state.fxsave_area;
+        true
+    }
+    #[cfg(not(feature = "fp_simd"))]
+    {
+        let _ = area;
+        false
     }
 }
 
+/// Make `area`, from a signal frame returned from, the FP/SIMD state of the
+/// running task. The `MXCSR` bits the CPU does not take are cleared, so
+/// that restoring it cannot fault.
+///
+/// Does nothing without the `fp_simd` feature.
+pub fn restore_user_fp(area: &FxsaveArea) {
+    #[cfg(feature = "fp_simd")]
+    {
+        let _guard = kernel_guard::NoPreemptIrqSave::new();
+        let current = FPU_CURRENT.read_current() as *mut ExtendedState;
+        if current.is_null() {
+            return;
+        }
+        // SAFETY: the state of the running task lives as long as it runs.
+        let state = unsafe { &mut *current };
+        let mxcsr_mask = state.fxsave_area.mxcsr_mask;
+        state.fxsave_area = *area;
+        state.fxsave_area.mxcsr_mask = mxcsr_mask;
+        state.fxsave_area.mxcsr &= match mxcsr_mask {
+            0 => DEFAULT_MXCSR_MASK,
+            mask => mask,
+        };
+        if Cr0::read().contains(Cr0Flags::TASK_SWITCHED) {
+            // Registers left with the old state on another CPU must not be
+            // taken for it.
+            state.last_cpu.store(usize::MAX, Ordering::Relaxed);
+        } else {
+            state.restore();
+        }
+    }
+    #[cfg(not(feature = "fp_simd"))]
+    let _ = area;
+}
+
 impl fmt::Debug for ExtendedState {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("ExtendedState")
@@ -425,10 +561,7 @@ impl TaskContext {
     /// restores the next task's context from `next_ctx` to CPU.
     pub fn switch_to(&mut self, next_ctx: &Self) {
         #[cfg(feature = "fp_simd")]
-        {
-            self.ext_state.save();
-            next_ctx.ext_state.restore();
-        }
+        self.ext_state.switch_to(&next_ctx.ext_state);
         #[cfg(any(feature = "tls"))]
         unsafe {
             self.fs_base = super::read_thread_pointer();
@@ -17,7 +17,9 @@ use memory_addr::{MemoryAddr, PhysAddr, VirtAddr};
 use x86::{controlregs, msr, tlb};
 use x86_64::instructions::interrupts;
 
-pub use self::context::{ExtendedState, FxsaveArea, TaskContext, TrapFrame};
+pub use self::context::{
+    ExtendedState, FxsaveArea, TaskContext, TrapFrame, restore_user_fp, save_user_fp,
+};
 pub use self::gdt::{GdtStruct, init_gdt, tss_get_rsp0, tss_set_rsp0};
 pub use self::idt::{IdtStruct, init_idt};
 
@@ -64,6 +66,22 @@ pub fn wait_for_irqs() {
     }
 }
 
+/// Enables interrupts and waits for one, without missing one that arrives
+/// in between.
+///
+/// It is called with interrupts disabled, after checking that there is
+/// nothing to do but wait, and returns with them enabled.
+#[inline]
+pub fn enable_irqs_and_wait() {
+    if cfg!(target_os = "none") {
+        // `sti` takes effect after the next instruction, so no interrupt
+        // is taken before `hlt`.
+        unsafe { asm!("sti; hlt") }
+    } else {
+        core::hint::spin_loop()
+    }
+}
+
 /// Halt the current CPU.
 #[inline]
 pub fn halt() {
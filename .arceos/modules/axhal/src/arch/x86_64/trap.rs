@@ -9,6 +9,11 @@ core::arch::global_asm!(include_str!("trap.S"));
 #[cfg(feature = "uspace")]
 const LEGACY_SYSCALL_VECTOR: u8 = 0x80;
 
+/// The trap flag of `RFLAGS`, which makes the CPU raise `#DB` after each
+/// instruction.
+#[cfg(feature = "uspace")]
+const TRAP_FLAG: u64 = 1 << 8;
+
 const IRQ_VECTOR_START: u8 = 0x20;
 const IRQ_VECTOR_END: u8 = 0xff;
 
@@ -33,12 +38,41 @@ fn handle_page_fault(tf: &TrapFrame) {
 fn x86_trap_handler(tf: &mut TrapFrame) {
     #[cfg(feature = "uspace")]
     super::tls::switch_to_kernel_fs_base(tf);
+    #[cfg(feature = "fp_simd")]
+    if tf.vector as u8 == DEVICE_NOT_AVAILABLE_VECTOR {
+        // Before IRQs are unmasked, so the task is not switched out while
+        // its state is half restored.
+        super::context::handle_fpu_trap();
+    }
     if !matches!(tf.vector as u8, IRQ_VECTOR_START..=IRQ_VECTOR_END) {
         unmask_irqs(tf);
     }
     match tf.vector as u8 {
         PAGE_FAULT_VECTOR => handle_page_fault(tf),
-        BREAKPOINT_VECTOR => debug!("#BP @ {:#x} ", tf.rip),
+        BREAKPOINT_VECTOR => {
+            #[cfg(feature = "uspace")]
+            let handled = tf.is_user() && crate::trap::handle_user_debug(tf, false);
+            #[cfg(not(feature = "uspace"))]
+            let handled = false;
+            if !handled {
+                debug!("#BP @ {:#x} ", tf.rip);
+            }
+        }
+        #[cfg(feature = "uspace")]
+        DEBUG_VECTOR if tf.is_user() => {
+            if !crate::trap::handle_user_debug(tf, true) {
+                warn!("Unexpected user #DB @ {:#x}", tf.rip);
+                tf.rflags &= !TRAP_FLAG;
+            }
+        }
+        #[cfg(feature = "uspace")]
+        INVALID_OPCODE_VECTOR if tf.is_user() => {
+            if !crate::trap::handle_illegal_instruction(tf) {
+                panic!("Unhandled user #UD @ {:#x}:\n{:#x?}", tf.rip, tf);
+            }
+        }
+        #[cfg(feature = "fp_simd")]
+        DEVICE_NOT_AVAILABLE_VECTOR => {}
         GENERAL_PROTECTION_FAULT_VECTOR => {
             panic!(
                 "#GP @ {:#x}, error_code={:#x}:\n{:#x?}",
@@ -0,0 +1,367 @@
+//! Console input and output.
+//!
+//! The console is shared by the kernel, for its log, and by user programs,
+//! for their standard output, which [`write_kernel`] and [`write_user`]
+//! write. They take turns at line boundaries only, so that neither splits a
+//! line, an escape sequence or a UTF-8 character of the other:
+//!
+//! - The unfinished last line of a write is held back until it is finished,
+//!   for at most [`HOLD_TIME`], or until [`HOLD_CAPACITY`] bytes are held.
+//! - Once part of an unfinished line went out, what the other source writes
+//!   is held back until the line is finished. If it is not within
+//!   [`HOLD_TIME`], a line break is put in for the other source to go on.
+//!
+//! [`flush_stale`] is to be called periodically, e.g. on timer ticks, for
+//! held output to go out in time, and [`flush`] before output must be seen,
+//! e.g. when a program waits for input after a prompt. [`write_bytes`]
+//! writes raw, bypassing all this.
+//!
+//! The lines of the kernel may be tagged with the time and `kernel`, see
+//! [`set_kernel_tag`], or go to a port of their own, see
+//! [`set_kernel_port`].
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use kspin::SpinNoIrq;
+use lazyinit::LazyInit;
+
+pub use crate::platform::console::*;
+use crate::time::monotonic_time_nanos;
+
+/// How long the unfinished line of a source is held back, or keeps the
+/// other source waiting.
+pub const HOLD_TIME: Duration = Duration::from_millis(20);
+
+/// How many bytes of an unfinished line are held back at most.
+pub const HOLD_CAPACITY: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Kernel,
+    User,
+}
+
+impl Source {
+    const fn other(self) -> Self {
+        match self {
+            Self::Kernel => Self::User,
+            Self::User => Self::Kernel,
+        }
+    }
+}
+
+/// The output of a source held back.
+struct Held {
+    buf: [u8; HOLD_CAPACITY],
+    len: usize,
+    /// When the first byte held was written, in nanoseconds.
+    since: u64,
+}
+
+impl Held {
+    const fn new() -> Self {
+        Self {
+            buf: [0; HOLD_CAPACITY],
+            len: 0,
+            since: 0,
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Hold `bytes` too, if there is room for them.
+    fn push(&mut self, bytes: &[u8], now: u64) -> bool {
+        if self.len + bytes.len() > HOLD_CAPACITY {
+            return false;
+        }
+        if self.len == 0 {
+            self.since = now;
+        }
+        self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+        true
+    }
+
+    /// Stop holding the first `len` bytes.
+    fn consume(&mut self, len: usize) {
+        self.buf.copy_within(len..self.len, 0);
+        self.len -= len;
+    }
+}
+
+struct Writer {
+    /// What the kernel and user programs hold back, in this order.
+    held: [Held; 2],
+    /// The source whose unfinished line went out in part, and when it began
+    /// to, in nanoseconds.
+    mid_line: Option<(Source, u64)>,
+    /// Whether the next byte of the kernel starts a line, for the tag.
+    kernel_line_start: bool,
+}
+
+static WRITER: SpinNoIrq<Writer> = SpinNoIrq::new(Writer {
+    held: [Held::new(), Held::new()],
+    mid_line: None,
+    kernel_line_start: true,
+});
+
+/// Whether [`WRITER`] holds anything, to check without locking it.
+static HOLDING: AtomicBool = AtomicBool::new(false);
+
+static KERNEL_TAG: AtomicBool = AtomicBool::new(false);
+
+static KERNEL_PORT: LazyInit<fn(&[u8])> = LazyInit::new();
+
+/// The length of `bytes` without the UTF-8 character left unfinished at its
+/// end, if any.
+fn complete_chars(bytes: &[u8]) -> usize {
+    for back in 1..=bytes.len().min(3) {
+        let c = bytes[bytes.len() - back];
+        if c & 0xc0 == 0x80 {
+            // A continuation byte.
+            continue;
+        }
+        let char_len = match c {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => 1,
+        };
+        return if char_len > back {
+            bytes.len() - back
+        } else {
+            bytes.len()
+        };
+    }
+    bytes.len()
+}
+
+/// Writes into a buffer on the stack, for the tag of kernel lines.
+struct TagBuf {
+    buf: [u8; 32],
+    len: usize,
+}
+
+impl Write for TagBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.buf.len() {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+impl Writer {
+    fn held(&mut self, source: Source) -> &mut Held {
+        &mut self.held[source as usize]
+    }
+
+    /// Whether `source` must wait for the other one to finish its line.
+    fn blocked(&self, source: Source) -> bool {
+        self.mid_line.is_some_and(|(owner, _)| owner != source)
+    }
+
+    /// Write `bytes` of `source` out, tagging the lines of the kernel if
+    /// asked to.
+    fn put(&mut self, source: Source, bytes: &[u8]) {
+        if source == Source::User {
+            write_bytes(bytes);
+            return;
+        }
+        let port = KERNEL_PORT.get().copied().unwrap_or(write_bytes);
+        if !KERNEL_TAG.load(Ordering::Relaxed) {
+            port(bytes);
+            return;
+        }
+        for line in bytes.split_inclusive(|&c| c == b'\n') {
+            if self.kernel_line_start {
+                let now = Duration::from_nanos(monotonic_time_nanos());
+                let mut tag = TagBuf {
+                    buf: [0; 32],
+                    len: 0,
+                };
+                let _ = write!(
+                    tag,
+                    "[{:>5}.{:06} kernel] ",
+                    now.as_secs(),
+                    now.subsec_micros()
+                );
+                port(&tag.buf[..tag.len]);
+            }
+            port(line);
+            self.kernel_line_start = line.ends_with(b"\n");
+        }
+    }
+
+    /// Write out the finished lines `source` holds, if it may.
+    fn release(&mut self, source: Source) {
+        if self.blocked(source) {
+            return;
+        }
+        let held = self.held(source);
+        let Some(end) = held.bytes().iter().rposition(|&c| c == b'\n') else {
+            return;
+        };
+        let mut lines = [0; HOLD_CAPACITY];
+        lines[..=end].copy_from_slice(&held.bytes()[..=end]);
+        held.consume(end + 1);
+        self.put(source, &lines[..=end]);
+        self.mid_line = None;
+    }
+
+    /// Write out all that `source` holds, except for an unfinished UTF-8
+    /// character, leaving the console in the middle of its line if the last
+    /// one is unfinished.
+    fn release_all(&mut self, source: Source, now: u64) {
+        let held = self.held(source);
+        let len = complete_chars(held.bytes());
+        if len == 0 {
+            return;
+        }
+        let mut bytes = [0; HOLD_CAPACITY];
+        bytes[..len].copy_from_slice(&held.bytes()[..len]);
+        held.consume(len);
+        self.put(source, &bytes[..len]);
+        self.mid_line = (bytes[len - 1] != b'\n').then(|| match self.mid_line {
+            // The line went out in part already, keeping the other source
+            // waiting since then.
+            Some((owner, since)) if owner == source => (source, since),
+            _ => (source, now),
+        });
+    }
+
+    /// End the unfinished line on the console, for the other source to go
+    /// on, and write out its finished lines.
+    fn break_line(&mut self) {
+        if let Some((owner, _)) = self.mid_line.take() {
+            self.put(owner, b"\n");
+            self.release(owner.other());
+        }
+    }
+
+    fn write(&mut self, source: Source, bytes: &[u8], now: u64) {
+        if source == Source::Kernel && KERNEL_PORT.is_inited() {
+            self.put(source, bytes);
+            return;
+        }
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            if self.blocked(source) {
+                let room = HOLD_CAPACITY - self.held(source).len;
+                let (now_held, later) = rest.split_at(rest.len().min(room));
+                self.held(source).push(now_held, now);
+                rest = later;
+                if !rest.is_empty() {
+                    self.break_line();
+                }
+                continue;
+            }
+            match rest.iter().rposition(|&c| c == b'\n') {
+                Some(end) => {
+                    let len = self.held(source).len;
+                    let mut held = [0; HOLD_CAPACITY];
+                    held[..len].copy_from_slice(self.held(source).bytes());
+                    self.held(source).consume(len);
+                    self.put(source, &held[..len]);
+                    self.put(source, &rest[..=end]);
+                    self.mid_line = None;
+                    self.release(source.other());
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    if !self.held(source).push(rest, now) {
+                        // Too long a line to hold.
+                        self.release_all(source, now);
+                        if !self.held(source).push(rest, now) {
+                            let len = complete_chars(rest);
+                            self.put(source, &rest[..len]);
+                            if self.mid_line.is_none() {
+                                self.mid_line = Some((source, now));
+                            }
+                            self.held(source).push(&rest[len..], now);
+                        }
+                    } else if self.mid_line.is_some() {
+                        // The line went out in part already, no use holding
+                        // the rest of it.
+                        self.release_all(source, now);
+                    }
+                    rest = &[];
+                }
+            }
+        }
+        HOLDING.store(self.held.iter().any(|held| held.len > 0), Ordering::Relaxed);
+    }
+
+    fn flush_stale(&mut self, now: u64, force: bool) {
+        let stale = |since: u64| force || now.saturating_sub(since) >= HOLD_TIME.as_nanos() as u64;
+        if let Some((owner, since)) = self.mid_line {
+            if self.held(owner.other()).len > 0 && stale(since) {
+                self.break_line();
+            }
+        }
+        for source in [Source::Kernel, Source::User] {
+            let held = self.held(source);
+            if held.len == 0 || !stale(held.since) {
+                continue;
+            }
+            if force && self.blocked(source) {
+                self.break_line();
+            }
+            if !self.blocked(source) {
+                self.release_all(source, now);
+            }
+        }
+        HOLDING.store(self.held.iter().any(|held| held.len > 0), Ordering::Relaxed);
+    }
+}
+
+/// Writes bytes of the kernel, e.g. of its log, to the console.
+pub fn write_kernel(bytes: &[u8]) {
+    WRITER
+        .lock()
+        .write(Source::Kernel, bytes, monotonic_time_nanos());
+}
+
+/// Writes bytes of user programs to the console. They must be in kernel
+/// memory.
+pub fn write_user(bytes: &[u8]) {
+    WRITER
+        .lock()
+        .write(Source::User, bytes, monotonic_time_nanos());
+}
+
+/// Writes out what was held back for longer than [`HOLD_TIME`], or kept
+/// the other source waiting for as long.
+pub fn flush_stale() {
+    if HOLDING.load(Ordering::Relaxed) {
+        WRITER.lock().flush_stale(monotonic_time_nanos(), false);
+    }
+}
+
+/// Writes out all that is held back, putting in a line break if both
+/// sources have unfinished lines.
+pub fn flush() {
+    if HOLDING.load(Ordering::Relaxed) {
+        WRITER.lock().flush_stale(monotonic_time_nanos(), true);
+    }
+}
+
+/// Tags each line of the kernel with the time since boot and `kernel`, as
+/// `[    1.000000 kernel] `, for them to be told apart from the output of
+/// user programs.
+pub fn set_kernel_tag(tag: bool) {
+    KERNEL_TAG.store(tag, Ordering::Relaxed);
+}
+
+/// Writes the output of the kernel with `write` rather than to the console,
+/// e.g. to a second serial port. It can only be set once.
+pub fn set_kernel_port(write: fn(&[u8])) {
+    KERNEL_PORT.init_once(write);
+}
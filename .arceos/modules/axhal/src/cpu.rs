@@ -1,5 +1,7 @@
 //! CPU-related operations.
 
+use core::sync::atomic::{AtomicU64, Ordering};
+
 #[percpu::def_percpu]
 static CPU_ID: usize = 0;
 
@@ -15,6 +17,48 @@ pub fn this_cpu_id() -> usize {
     CPU_ID.read_current()
 }
 
+/// A counter with a part for each CPU, each padded to its own cache line,
+/// so that CPUs updating their own parts do not contend.
+pub struct PerCpuCounter([CounterPart; axconfig::SMP]);
+
+#[repr(align(64))]
+struct CounterPart(AtomicU64);
+
+impl PerCpuCounter {
+    /// Creates a counter at 0 on every CPU.
+    pub const fn new() -> Self {
+        Self([const { CounterPart(AtomicU64::new(0)) }; axconfig::SMP])
+    }
+
+    /// Adds `n` to the part of the current CPU.
+    #[inline]
+    pub fn add(&self, n: u64) {
+        self.this_cpu().fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Returns the part of the current CPU.
+    #[inline]
+    pub fn this_cpu(&self) -> &AtomicU64 {
+        &self.0[this_cpu_id()].0
+    }
+
+    /// Returns the parts of all CPUs, in the order of their IDs.
+    pub fn parts(&self) -> impl Iterator<Item = u64> + '_ {
+        self.0.iter().map(|part| part.0.load(Ordering::Relaxed))
+    }
+
+    /// Returns the sum of the parts of all CPUs.
+    pub fn sum(&self) -> u64 {
+        self.parts().sum()
+    }
+}
+
+impl Default for PerCpuCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Returns whether the current CPU is the primary CPU (aka the bootstrap
 /// processor or BSP)
 #[inline]
@@ -0,0 +1,194 @@
+//! The ID registers, whose fields Linux turns into the bits of `AT_HWCAP`
+//! and `AT_HWCAP2`.
+
+use core::arch::asm;
+use core::fmt;
+
+/// An ID register.
+#[derive(Clone, Copy)]
+enum IdReg {
+    Isar0,
+    Isar1,
+    /// Whose fields used here are signed, `0xf` meaning not implemented.
+    Pfr0,
+}
+
+impl IdReg {
+    fn read(self) -> u64 {
+        let value: u64;
+        // SAFETY: the ID registers can always be read at EL1.
+        unsafe {
+            match self {
+                Self::Isar0 => asm!("mrs {}, ID_AA64ISAR0_EL1", out(reg) value),
+                Self::Isar1 => asm!("mrs {}, ID_AA64ISAR1_EL1", out(reg) value),
+                Self::Pfr0 => asm!("mrs {}, ID_AA64PFR0_EL1", out(reg) value),
+            }
+        }
+        value
+    }
+
+    /// The 4-bit field at `shift`.
+    fn field(self, shift: u32) -> i8 {
+        let field = ((self.read() >> shift) & 0xf) as i8;
+        match self {
+            Self::Pfr0 => field << 4 >> 4,
+            _ => field,
+        }
+    }
+}
+
+/// A feature, present if a field of an ID register is at least some value.
+struct Feature {
+    /// The bit of `AT_HWCAP`, or of `AT_HWCAP2` from 32 on.
+    bit: u32,
+    reg: IdReg,
+    shift: u32,
+    min: i8,
+    /// Whether it works on the FP/SIMD registers, which user space may
+    /// only use with `fp_simd`.
+    simd: bool,
+}
+
+impl Feature {
+    const fn new(bit: u32, reg: IdReg, shift: u32, min: i8, simd: bool) -> Self {
+        Self {
+            bit,
+            reg,
+            shift,
+            min,
+            simd,
+        }
+    }
+
+    fn is_present(&self) -> bool {
+        (!self.simd || cfg!(feature = "fp_simd")) && self.reg.field(self.shift) >= self.min
+    }
+}
+
+/// The features detected. `evtstrm`, `cpuid`, `uscat`, `ssbs`, the pointer
+/// authentication and SVE are left out, as the kernel enables none of them.
+#[rustfmt::skip]
+const FEATURES: &[Feature] = {
+    use IdReg::*;
+    &[
+        Feature::new(0, Pfr0, 16, 0, true),     // fp
+        Feature::new(1, Pfr0, 20, 0, true),     // asimd
+        Feature::new(3, Isar0, 4, 1, true),     // aes
+        Feature::new(4, Isar0, 4, 2, true),     // pmull
+        Feature::new(5, Isar0, 8, 1, true),     // sha1
+        Feature::new(6, Isar0, 12, 1, true),    // sha2
+        Feature::new(7, Isar0, 16, 1, false),   // crc32
+        Feature::new(8, Isar0, 20, 2, false),   // atomics
+        Feature::new(9, Pfr0, 16, 1, true),     // fphp
+        Feature::new(10, Pfr0, 20, 1, true),    // asimdhp
+        Feature::new(12, Isar0, 28, 1, true),   // asimdrdm
+        Feature::new(13, Isar1, 12, 1, true),   // jscvt
+        Feature::new(14, Isar1, 16, 1, true),   // fcma
+        Feature::new(15, Isar1, 20, 1, false),  // lrcpc
+        Feature::new(16, Isar1, 0, 1, false),   // dcpop
+        Feature::new(17, Isar0, 32, 1, true),   // sha3
+        Feature::new(18, Isar0, 36, 1, true),   // sm3
+        Feature::new(19, Isar0, 40, 1, true),   // sm4
+        Feature::new(20, Isar0, 44, 1, true),   // asimddp
+        Feature::new(21, Isar0, 12, 2, true),   // sha512
+        Feature::new(23, Isar0, 48, 1, true),   // asimdfhm
+        Feature::new(24, Pfr0, 48, 1, false),   // dit
+        Feature::new(26, Isar1, 20, 2, false),  // ilrcpc
+        Feature::new(27, Isar0, 52, 1, false),  // flagm
+        Feature::new(29, Isar1, 36, 1, false),  // sb
+        Feature::new(32, Isar1, 0, 2, false),   // dcpodp
+        Feature::new(39, Isar0, 52, 2, false),  // flagm2
+        Feature::new(40, Isar1, 32, 1, true),   // frint
+        Feature::new(45, Isar1, 52, 1, true),   // i8mm
+        Feature::new(46, Isar1, 44, 1, true),   // bf16
+        Feature::new(47, Isar1, 48, 1, false),  // dgh
+        Feature::new(48, Isar0, 60, 1, false),  // rng
+    ]
+};
+
+/// The names of the bits of `AT_HWCAP` and `AT_HWCAP2`, in the order Linux
+/// shows them in `Features`.
+#[rustfmt::skip]
+const NAMES: [[&str; 32]; 2] = [
+    [
+        "fp", "asimd", "evtstrm", "aes", "pmull", "sha1", "sha2", "crc32",
+        "atomics", "fphp", "asimdhp", "cpuid", "asimdrdm", "jscvt", "fcma", "lrcpc",
+        "dcpop", "sha3", "sm3", "sm4", "asimddp", "sha512", "sve", "asimdfhm",
+        "dit", "uscat", "ilrcpc", "flagm", "ssbs", "sb", "paca", "pacg",
+    ],
+    [
+        "dcpodp", "sve2", "sveaes", "svepmull", "svebitperm", "svesha3", "svesm4", "flagm2",
+        "frint", "svei8mm", "svef32mm", "svef64mm", "svebf16", "i8mm", "bf16", "dgh",
+        "rng", "bti", "mte", "", "", "", "", "",
+        "", "", "", "", "", "", "", "",
+    ],
+];
+
+/// The names Linux gives the parts of Arm Ltd., by their number in
+/// `MIDR_EL1`.
+const ARM_PARTS: &[(u64, &str)] = &[
+    (0xd03, "Cortex-A53"),
+    (0xd04, "Cortex-A35"),
+    (0xd05, "Cortex-A55"),
+    (0xd07, "Cortex-A57"),
+    (0xd08, "Cortex-A72"),
+    (0xd09, "Cortex-A73"),
+    (0xd0a, "Cortex-A75"),
+    (0xd0b, "Cortex-A76"),
+    (0xd0c, "Neoverse-N1"),
+    (0xd40, "Neoverse-V1"),
+    (0xd49, "Neoverse-N2"),
+];
+
+/// Both words, `AT_HWCAP2` in the upper half.
+fn hwcaps() -> u64 {
+    FEATURES
+        .iter()
+        .filter(|feature| feature.is_present())
+        .fold(0, |caps, feature| caps | 1 << feature.bit)
+}
+
+pub fn hwcap() -> usize {
+    hwcaps() as u32 as usize
+}
+
+pub fn hwcap2() -> usize {
+    (hwcaps() >> 32) as usize
+}
+
+pub fn write_cpuinfo(out: &mut dyn fmt::Write, cpu: usize) -> fmt::Result {
+    let (midr, freq): (u64, u64);
+    // SAFETY: both registers can always be read at EL1.
+    unsafe {
+        asm!("mrs {}, MIDR_EL1", out(reg) midr);
+        asm!("mrs {}, CNTFRQ_EL0", out(reg) freq);
+    }
+    let implementer = (midr >> 24) & 0xff;
+    let part = (midr >> 4) & 0xfff;
+    let revision = midr & 0xf;
+    // Like Linux, which counts two loops a tick of the timer.
+    let centi_mips = freq * 2 / 10_000;
+
+    writeln!(out, "processor\t: {}", cpu)?;
+    match ARM_PARTS
+        .iter()
+        .find(|&&(number, _)| implementer == 0x41 && number == part)
+    {
+        Some((_, name)) => writeln!(out, "model name\t: {}", name)?,
+        None => writeln!(out, "model name\t: ARMv8 Processor rev {}", revision)?,
+    }
+    writeln!(
+        out,
+        "BogoMIPS\t: {}.{:02}",
+        centi_mips / 100,
+        centi_mips % 100
+    )?;
+    write!(out, "Features\t:")?;
+    super::write_features(out, &NAMES, &[hwcap() as u32, hwcap2() as u32])?;
+    writeln!(out)?;
+    writeln!(out, "CPU implementer\t: {:#x}", implementer)?;
+    writeln!(out, "CPU architecture: 8")?;
+    writeln!(out, "CPU variant\t: {:#x}", (midr >> 20) & 0xf)?;
+    writeln!(out, "CPU part\t: {:#05x}", part)?;
+    writeln!(out, "CPU revision\t: {}\n", revision)
+}
@@ -0,0 +1,101 @@
+//! The configuration words read by `cpucfg`, which user space may also
+//! read itself, hence the `cpucfg` bit always set in `AT_HWCAP`. `AT_HWCAP2`
+//! is not used.
+
+use core::arch::asm;
+use core::fmt;
+
+/// The names of the bits of `AT_HWCAP`, in the order Linux shows them in
+/// `Features`.
+#[rustfmt::skip]
+const NAMES: [[&str; 32]; 1] = [[
+    "cpucfg", "lam", "ual", "fpu", "lsx", "lasx", "crc32", "complex",
+    "crypto", "lvz", "lbt_x86", "lbt_arm", "lbt_mips", "ptw", "", "",
+    "", "", "", "", "", "", "", "",
+    "", "", "", "", "", "", "", "",
+]];
+
+/// The bits of `AT_HWCAP` detected, each with the word of `cpucfg` and the
+/// bit of it telling whether the CPU has it.
+///
+/// The vector extensions, and `complex` and `crypto` on vectors, are left
+/// out, as only the scalar FP registers are kept across context switches.
+/// So are the binary translation extensions, which the kernel does not
+/// enable, and `lvz`, which user space cannot use.
+#[rustfmt::skip]
+const FEATURES: &[(u32, usize, u32)] = &[
+    (1, 2, 22),         // lam
+    (2, 1, 20),         // ual
+    (HWCAP_FPU, 2, 0),  // fpu
+    (6, 1, 25),         // crc32
+    (13, 2, 24),        // ptw
+];
+
+/// The bit of the FPU, which user space may only use with `fp_simd`.
+const HWCAP_FPU: u32 = 3;
+
+fn cpucfg(word: usize) -> u32 {
+    let value: usize;
+    // SAFETY: `cpucfg` only reads the configuration.
+    unsafe { asm!("cpucfg {}, {}", out(reg) value, in(reg) word) };
+    value as u32
+}
+
+pub fn hwcap() -> usize {
+    FEATURES
+        .iter()
+        .filter(|&&(bit, _, _)| bit != HWCAP_FPU || cfg!(feature = "fp_simd"))
+        .filter(|&&(_, word, cfg_bit)| cpucfg(word) & (1 << cfg_bit) != 0)
+        .fold(1, |caps, &(bit, _, _)| caps | 1 << bit)
+}
+
+pub fn hwcap2() -> usize {
+    0
+}
+
+/// The names of the cores of Loongson, by the series in `PRID`.
+fn core_name(prid: u32) -> &'static str {
+    match prid & 0xf000 {
+        0x8000 => "LA132",
+        0xa000 => "LA264",
+        0xb000 => "LA364",
+        0xc000 => "LA464",
+        0xd000 => "LA664",
+        _ => "unknown",
+    }
+}
+
+pub fn write_cpuinfo(out: &mut dyn fmt::Write, cpu: usize) -> fmt::Result {
+    let (prid, cfg1, cfg2) = (cpucfg(0), cpucfg(1), cpucfg(2));
+    let arch = cfg1 & 0x3;
+    if cpu == 0 {
+        writeln!(out, "system type\t\t: generic-loongson-machine\n")?;
+    }
+    writeln!(out, "processor\t\t: {}", cpu)?;
+    writeln!(out, "package\t\t\t: 0")?;
+    writeln!(out, "core\t\t\t: {}", cpu)?;
+    writeln!(
+        out,
+        "CPU Family\t\t: Loongson-{}bit",
+        if arch == 2 { 64 } else { 32 }
+    )?;
+    writeln!(out, "Model Name\t\t: Loongson-{}", core_name(prid))?;
+    writeln!(out, "CPU Revision\t\t: {:#04x}", prid & 0xff)?;
+    writeln!(out, "FPU Revision\t\t: {:#04x}", (cfg2 >> 3) & 0x7)?;
+    writeln!(
+        out,
+        "Address Sizes\t\t: {} bits physical, {} bits virtual",
+        ((cfg1 >> 4) & 0xff) + 1,
+        ((cfg1 >> 12) & 0xff) + 1
+    )?;
+    write!(out, "ISA\t\t\t: loongarch32r")?;
+    if arch >= 1 {
+        write!(out, " loongarch32s")?;
+    }
+    if arch == 2 {
+        write!(out, " loongarch64")?;
+    }
+    write!(out, "\nFeatures\t\t:")?;
+    super::write_features(out, &NAMES, &[hwcap() as u32])?;
+    writeln!(out, "\nHardware Watchpoint\t: no\n")
+}
@@ -0,0 +1,59 @@
+//! Hardware capabilities of the CPU, as Linux shows them to user space: in
+//! the `AT_HWCAP` and `AT_HWCAP2` entries of the auxiliary vector, in the
+//! layout of the architecture, and in `/proc/cpuinfo`.
+//!
+//! Only the features user space can use are reported, those the CPU has
+//! and the kernel both enables and keeps across context switches. Using any
+//! other raises an illegal instruction trap, which goes to the handlers of
+//! [`crate::trap::ILLEGAL_INSTRUCTION`].
+//!
+//! Every CPU is taken to have the features of the one asking.
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        mod x86_64;
+        use self::x86_64 as imp;
+    } else if #[cfg(target_arch = "riscv64")] {
+        mod riscv;
+        use self::riscv as imp;
+        pub(crate) use self::riscv::init;
+    } else if #[cfg(target_arch = "aarch64")] {
+        mod aarch64;
+        use self::aarch64 as imp;
+    } else if #[cfg(target_arch = "loongarch64")] {
+        mod loongarch64;
+        use self::loongarch64 as imp;
+    }
+}
+
+use core::fmt;
+
+/// The value of `AT_HWCAP`.
+pub fn hwcap() -> usize {
+    imp::hwcap()
+}
+
+/// The value of `AT_HWCAP2`.
+pub fn hwcap2() -> usize {
+    imp::hwcap2()
+}
+
+/// Write the block of `/proc/cpuinfo` for CPU `cpu`, with the fields Linux
+/// shows on this architecture, followed by an empty line.
+pub fn write_cpuinfo(out: &mut dyn fmt::Write, cpu: usize) -> fmt::Result {
+    imp::write_cpuinfo(out, cpu)
+}
+
+/// Write, each after a space, the names of the bits set in `words`, given
+/// 32 names for each word. Bits named `""` are not shown.
+#[cfg(not(target_arch = "riscv64"))]
+fn write_features(out: &mut dyn fmt::Write, names: &[[&str; 32]], words: &[u32]) -> fmt::Result {
+    for (names, word) in names.iter().zip(words) {
+        for (bit, name) in names.iter().enumerate() {
+            if !name.is_empty() && word & (1 << bit) != 0 {
+                write!(out, " {}", name)?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,199 @@
+//! The `riscv,isa` string of the first CPU in the device tree, as `misa`
+//! can only be read in M-mode. `AT_HWCAP` has the bit `1 << (x - 'a')` set
+//! for each of the extensions I, M, A, F, D, C and V the CPU has, and
+//! `AT_HWCAP2` is not used.
+
+use core::fmt;
+
+use lazyinit::LazyInit;
+
+use crate::mem::phys_to_virt;
+
+/// The ISA the kernel itself is built for, for want of a device tree.
+const DEFAULT_ISA: &str = "rv64imafdc";
+
+const FDT_MAGIC: usize = 0xd00d_feed;
+const FDT_BEGIN_NODE: usize = 1;
+const FDT_END_NODE: usize = 2;
+const FDT_PROP: usize = 3;
+const FDT_NOP: usize = 4;
+
+/// A string property of the device tree, cut to `N` bytes.
+struct Prop<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> Prop<N> {
+    const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+        }
+    }
+
+    /// Keep the first string of `value`, a list of them.
+    fn set(&mut self, value: &[u8]) {
+        let value = c_str(value).unwrap_or(value);
+        self.len = value.len().min(N);
+        self.buf[..self.len].copy_from_slice(&value[..self.len]);
+    }
+
+    fn get(&self) -> Option<&str> {
+        core::str::from_utf8(&self.buf[..self.len])
+            .ok()
+            .filter(|s| !s.is_empty())
+    }
+}
+
+/// What the device tree says of the first CPU.
+struct CpuNode {
+    isa: Prop<256>,
+    compatible: Prop<64>,
+}
+
+static CPU_NODE: LazyInit<CpuNode> = LazyInit::new();
+
+/// The bytes of `bytes` before the first NUL.
+fn c_str(bytes: &[u8]) -> Option<&[u8]> {
+    let end = bytes.iter().position(|&b| b == 0)?;
+    Some(&bytes[..end])
+}
+
+/// Fill in `node` from the first `/cpus/cpu@*` node of the flattened device
+/// tree `fdt`. Returns `None` if the tree is malformed.
+fn parse(fdt: &[u8], node: &mut CpuNode) -> Option<()> {
+    let word = |off: usize| {
+        let bytes = fdt.get(off..off + 4)?;
+        Some(u32::from_be_bytes(bytes.try_into().ok()?) as usize)
+    };
+    if word(0)? != FDT_MAGIC {
+        return None;
+    }
+    let strings = word(12)?;
+    let mut off = word(8)?;
+    let mut depth = 0;
+    let mut in_cpus = false;
+    let mut in_cpu = false;
+    loop {
+        let token = word(off)?;
+        off += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = c_str(fdt.get(off..)?)?;
+                off += (name.len() + 1).next_multiple_of(4);
+                // The root is at depth 1.
+                depth += 1;
+                match depth {
+                    2 => in_cpus = name == b"cpus",
+                    3 => in_cpu = in_cpus && name.starts_with(b"cpu@"),
+                    _ => {}
+                }
+            }
+            FDT_END_NODE => {
+                if in_cpu && depth == 3 {
+                    return Some(());
+                }
+                depth -= 1;
+            }
+            FDT_PROP => {
+                let len = word(off)?;
+                let name = c_str(fdt.get(strings + word(off + 4)?..)?)?;
+                let value = fdt.get(off + 8..off + 8 + len)?;
+                off += 8 + len.next_multiple_of(4);
+                if in_cpu && depth == 3 {
+                    match name {
+                        b"riscv,isa" => node.isa.set(value),
+                        b"compatible" => node.compatible.set(value),
+                        _ => {}
+                    }
+                }
+            }
+            FDT_NOP => {}
+            _ => return Some(()),
+        }
+    }
+}
+
+/// Read the description of the CPU off the device tree at the physical
+/// address `dtb`, on the primary CPU at boot.
+pub(crate) fn init(dtb: usize) {
+    let mut node = CpuNode {
+        isa: Prop::new(),
+        compatible: Prop::new(),
+    };
+    if dtb != 0 {
+        let fdt = phys_to_virt(pa!(dtb)).as_ptr();
+        // SAFETY: the boot loader leaves a device tree at `dtb`, in memory
+        // mapped linearly from boot on, whose header starts with its size.
+        let size = u32::from_be(unsafe { fdt.add(4).cast::<u32>().read_unaligned() });
+        let fdt = unsafe { core::slice::from_raw_parts(fdt, size as usize) };
+        if parse(fdt, &mut node).is_none() {
+            warn!("Malformed device tree at {:#x}", dtb);
+        }
+    }
+    CPU_NODE.init_once(node);
+}
+
+fn isa() -> &'static str {
+    CPU_NODE
+        .get()
+        .and_then(|node| node.isa.get())
+        .unwrap_or(DEFAULT_ISA)
+}
+
+/// The single-letter extensions of the ISA, with `g` spelled out.
+fn base_extensions() -> impl Iterator<Item = char> {
+    let base = isa().get(4..).unwrap_or("").split('_').next().unwrap_or("");
+    base.chars().flat_map(|ext| {
+        let exts = if ext == 'g' { "imafd" } else { "" };
+        exts.chars().chain((ext != 'g').then_some(ext))
+    })
+}
+
+/// Whether user space may use the single-letter extension `ext`: the
+/// vector unit is left off, and so is the FPU without `fp_simd`.
+fn is_enabled(ext: char) -> bool {
+    match ext {
+        'v' => false,
+        'f' | 'd' | 'q' => cfg!(feature = "fp_simd"),
+        _ => true,
+    }
+}
+
+/// Whether user space may use the multi-letter extension `ext`, of which
+/// those on vectors or floating-point registers are left off like the
+/// single-letter ones.
+fn is_enabled_multi(ext: &str) -> bool {
+    !ext.starts_with("zv")
+        && (cfg!(feature = "fp_simd") || !["zf", "zd", "zq"].iter().any(|p| ext.starts_with(p)))
+}
+
+pub fn hwcap() -> usize {
+    base_extensions()
+        .filter(|&ext| "imafdcv".contains(ext) && is_enabled(ext))
+        .fold(0, |caps, ext| caps | 1 << (ext as u8 - b'a'))
+}
+
+pub fn hwcap2() -> usize {
+    0
+}
+
+pub fn write_cpuinfo(out: &mut dyn fmt::Write, cpu: usize) -> fmt::Result {
+    writeln!(out, "processor\t: {}", cpu)?;
+    writeln!(out, "hart\t\t: {}", cpu)?;
+    write!(out, "isa\t\t: {}", isa().get(..4).unwrap_or("rv64"))?;
+    for ext in base_extensions().filter(|&ext| is_enabled(ext)) {
+        write!(out, "{}", ext)?;
+    }
+    for ext in isa().split('_').skip(1).filter(|ext| is_enabled_multi(ext)) {
+        write!(out, "_{}", ext)?;
+    }
+    writeln!(out)?;
+    writeln!(out, "mmu\t\t: sv39")?;
+    writeln!(out, "mvendorid\t: {:#x}", sbi_rt::get_mvendorid())?;
+    writeln!(out, "marchid\t\t: {:#x}", sbi_rt::get_marchid())?;
+    writeln!(out, "mimpid\t\t: {:#x}", sbi_rt::get_mimpid())?;
+    let model = CPU_NODE.get().and_then(|node| node.compatible.get());
+    writeln!(out, "model name\t: {}\n", model.unwrap_or("riscv"))
+}
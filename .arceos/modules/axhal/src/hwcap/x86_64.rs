@@ -0,0 +1,141 @@
+//! `cpuid`, whose leaf 1 `EDX` is `AT_HWCAP` as is.
+
+use core::fmt;
+
+use raw_cpuid::{CpuIdResult, cpuid};
+
+/// The names of the bits of leaf 1 `EDX`, leaf `0x8000_0001` `EDX`, leaf 1
+/// `ECX`, leaf `0x8000_0001` `ECX` and leaf 7 `EBX`, in the order Linux
+/// shows them in `flags`.
+///
+/// Features which need state the kernel does not enable, the `XSAVE` area
+/// for AVX and the like, or `CR4.FSGSBASE`, are left unnamed, as are the
+/// bits of the second word which repeat the first.
+#[rustfmt::skip]
+const FLAGS: [[&str; 32]; 5] = [
+    [
+        "fpu", "vme", "de", "pse", "tsc", "msr", "pae", "mce",
+        "cx8", "apic", "", "sep", "mtrr", "pge", "mca", "cmov",
+        "pat", "pse36", "pn", "clflush", "", "dts", "acpi", "mmx",
+        "fxsr", "sse", "sse2", "ss", "ht", "tm", "ia64", "pbe",
+    ],
+    [
+        "", "", "", "", "", "", "", "",
+        "", "", "", "syscall", "", "", "", "",
+        "", "", "", "mp", "nx", "", "mmxext", "",
+        "", "fxsr_opt", "pdpe1gb", "rdtscp", "", "lm", "3dnowext", "3dnow",
+    ],
+    [
+        "pni", "pclmulqdq", "dtes64", "monitor", "ds_cpl", "vmx", "smx", "est",
+        "tm2", "ssse3", "cid", "sdbg", "", "cx16", "xtpr", "pdcm",
+        "", "pcid", "dca", "sse4_1", "sse4_2", "x2apic", "movbe", "popcnt",
+        "tsc_deadline_timer", "aes", "", "", "", "", "rdrand", "hypervisor",
+    ],
+    [
+        "lahf_lm", "cmp_legacy", "svm", "extapic", "cr8_legacy", "abm", "sse4a", "misalignsse",
+        "3dnowprefetch", "osvw", "ibs", "", "skinit", "wdt", "", "lwp",
+        "", "tce", "", "nodeid_msr", "", "tbm", "topoext", "perfctr_core",
+        "perfctr_nb", "", "bpext", "ptsc", "perfctr_llc", "mwaitx", "", "",
+    ],
+    [
+        "", "tsc_adjust", "sgx", "bmi1", "hle", "", "", "smep",
+        "bmi2", "erms", "invpcid", "rtm", "cqm", "", "", "rdt_a",
+        "", "", "rdseed", "adx", "smap", "", "", "clflushopt",
+        "clwb", "intel_pt", "", "", "", "sha_ni", "", "",
+    ],
+];
+
+/// The highest standard leaf and the highest extended one.
+fn max_leaves() -> (u32, u32) {
+    (cpuid!(0).eax, cpuid!(0x8000_0000).eax)
+}
+
+/// The result of `leaf`, or zeros if the CPU does not have it.
+fn leaf(leaf: u32) -> CpuIdResult {
+    let (max, max_ext) = max_leaves();
+    let max = if leaf >= 0x8000_0000 { max_ext } else { max };
+    if leaf <= max {
+        cpuid!(leaf, 0)
+    } else {
+        CpuIdResult {
+            eax: 0,
+            ebx: 0,
+            ecx: 0,
+            edx: 0,
+        }
+    }
+}
+
+pub fn hwcap() -> usize {
+    leaf(1).edx as usize
+}
+
+/// `HWCAP2_RING3MWAIT` and `HWCAP2_FSGSBASE`, neither of which the kernel
+/// enables.
+pub fn hwcap2() -> usize {
+    0
+}
+
+pub fn write_cpuinfo(out: &mut dyn fmt::Write, cpu: usize) -> fmt::Result {
+    let vendor = cpuid!(0);
+    let mut vendor_id = [0; 12];
+    for (bytes, reg) in vendor_id
+        .chunks_exact_mut(4)
+        .zip([vendor.ebx, vendor.edx, vendor.ecx])
+    {
+        bytes.copy_from_slice(&reg.to_le_bytes());
+    }
+    let mut brand = [0; 48];
+    for (bytes, number) in brand.chunks_exact_mut(16).zip(0x8000_0002..) {
+        let regs = leaf(number);
+        for (bytes, reg) in bytes
+            .chunks_exact_mut(4)
+            .zip([regs.eax, regs.ebx, regs.ecx, regs.edx])
+        {
+            bytes.copy_from_slice(&reg.to_le_bytes());
+        }
+    }
+    let as_str = |bytes: &[u8]| {
+        core::str::from_utf8(bytes)
+            .unwrap_or("")
+            .trim_matches(|c: char| c == '\0' || c == ' ')
+    };
+
+    let signature = leaf(1).eax;
+    let mut family = (signature >> 8) & 0xf;
+    let mut model = (signature >> 4) & 0xf;
+    if family == 0xf {
+        family += (signature >> 20) & 0xff;
+    }
+    if family >= 6 {
+        model += ((signature >> 16) & 0xf) << 4;
+    }
+    let sizes = leaf(0x8000_0008).eax;
+
+    writeln!(out, "processor\t: {}", cpu)?;
+    writeln!(out, "vendor_id\t: {}", as_str(&vendor_id))?;
+    writeln!(out, "cpu family\t: {}", family)?;
+    writeln!(out, "model\t\t: {}", model)?;
+    writeln!(out, "model name\t: {}", as_str(&brand))?;
+    writeln!(out, "stepping\t: {}", signature & 0xf)?;
+    writeln!(out, "fpu\t\t: yes")?;
+    writeln!(out, "fpu_exception\t: yes")?;
+    writeln!(out, "cpuid level\t: {}", max_leaves().0)?;
+    writeln!(out, "wp\t\t: yes")?;
+    write!(out, "flags\t\t:")?;
+    let words = [
+        leaf(1).edx,
+        leaf(0x8000_0001).edx,
+        leaf(1).ecx,
+        leaf(0x8000_0001).ecx,
+        leaf(7).ebx,
+    ];
+    super::write_features(out, &FLAGS, &words)?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "address sizes\t: {} bits physical, {} bits virtual\n",
+        sizes & 0xff,
+        (sizes >> 8) & 0xff
+    )
+}
@@ -1,17 +1,40 @@
 //! Interrupt management.
 
+use core::sync::atomic::Ordering;
+
 use handler_table::HandlerTable;
 
+use crate::cpu::PerCpuCounter;
 use crate::platform::irq::{MAX_IRQ_COUNT, dispatch_irq};
 use crate::trap::{IRQ, register_trap_handler};
 
-pub use crate::platform::irq::{register_handler, set_enable};
+pub use crate::platform::irq::{kick_cpu, register_handler, set_enable, virtio_mmio_irq};
 
 /// The type if an IRQ handler.
 pub type IrqHandler = handler_table::Handler;
 
 static IRQ_HANDLER_TABLE: HandlerTable<MAX_IRQ_COUNT> = HandlerTable::new();
 
+/// IRQs handled on each CPU.
+static IRQ_COUNTS: PerCpuCounter = PerCpuCounter::new();
+
+/// Returns the number of IRQs handled on all CPUs since boot.
+pub fn irq_count() -> u64 {
+    IRQ_COUNTS.sum()
+}
+
+/// The times IRQs were handled at on each CPU, each folded into the last,
+/// for the random number generator to take entropy from.
+static IRQ_JITTER: PerCpuCounter = PerCpuCounter::new();
+
+/// Returns the timings of the IRQs handled on all CPUs so far, folded into
+/// 64 bits.
+pub fn irq_jitter() -> u64 {
+    IRQ_JITTER
+        .parts()
+        .fold(0, |acc, part| acc.rotate_left(17) ^ part)
+}
+
 /// Platform-independent IRQ dispatching.
 #[allow(dead_code)]
 pub(crate) fn dispatch_irq_common(irq_num: usize) {
@@ -38,6 +61,11 @@ pub(crate) fn register_handler_common(irq_num: usize, handler: IrqHandler) -> bo
 #[register_trap_handler(IRQ)]
 fn handler_irq(irq_num: usize) -> bool {
     let guard = kernel_guard::NoPreempt::new();
+    IRQ_COUNTS.add(1);
+    // Only this CPU writes its pool, with IRQs off, so no update is lost.
+    let jitter = IRQ_JITTER.this_cpu();
+    let pool = jitter.load(Ordering::Relaxed).rotate_left(7) ^ crate::time::current_ticks();
+    jitter.store(pool, Ordering::Relaxed);
     dispatch_irq(irq_num);
     drop(guard); // rescheduling may occur when preemption is re-enabled.
     true
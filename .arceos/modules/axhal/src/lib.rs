@@ -43,6 +43,7 @@ pub mod trap;
 
 pub mod arch;
 pub mod cpu;
+pub mod hwcap;
 pub mod mem;
 pub mod time;
 
@@ -55,10 +56,7 @@ pub mod irq;
 #[cfg(feature = "paging")]
 pub mod paging;
 
-/// Console input and output.
-pub mod console {
-    pub use super::platform::console::*;
-}
+pub mod console;
 
 /// Miscellaneous operation, e.g. terminate the system.
 pub mod misc {
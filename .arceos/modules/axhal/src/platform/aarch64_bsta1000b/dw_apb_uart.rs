@@ -33,6 +33,12 @@ pub fn write_bytes(bytes: &[u8]) {
     }
 }
 
+/// Returns the IRQ number raised when console input arrives, or [`None`] if
+/// the console can only be polled.
+pub fn irq_num() -> Option<usize> {
+    None
+}
+
 /// Reads bytes from the console into the given mutable slice.
 /// Returns the number of bytes read.
 pub fn read_bytes(bytes: &mut [u8]) -> usize {
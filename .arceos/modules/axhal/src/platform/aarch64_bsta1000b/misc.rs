@@ -1,4 +1,6 @@
-pub use crate::platform::aarch64_common::psci::system_off as terminate;
+pub use crate::platform::aarch64_common::psci::{
+    system_off as terminate, system_off_failure as terminate_failure, system_reset as reboot,
+};
 
 use crate::mem::phys_to_virt;
 use crate::time::{Duration, busy_wait};
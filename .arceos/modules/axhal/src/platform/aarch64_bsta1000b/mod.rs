@@ -9,6 +9,12 @@ pub mod mp;
 #[cfg(feature = "irq")]
 pub mod irq {
     pub use crate::platform::aarch64_common::gic::*;
+
+    /// The IRQ of the VirtIO MMIO device in the `index`th of the
+    /// `virtio-mmio-regions`. There are none on this platform.
+    pub fn virtio_mmio_irq(_index: usize) -> Option<usize> {
+        None
+    }
 }
 
 pub mod console {
@@ -10,6 +10,9 @@ pub const MAX_IRQ_COUNT: usize = 1024;
 /// The timer IRQ number.
 pub const TIMER_IRQ_NUM: usize = translate_irq(14, InterruptType::PPI).unwrap();
 
+/// The SGI sent by [`kick_cpu`].
+const KICK_SGI_NUM: usize = 1;
+
 /// The UART IRQ number.
 pub const UART_IRQ_NUM: usize = translate_irq(UART_IRQ, InterruptType::SPI).unwrap();
 
@@ -43,7 +46,19 @@ pub fn register_handler(irq_num: usize, handler: IrqHandler) -> bool {
 /// up in the IRQ handler table and calls the corresponding handler. If
 /// necessary, it also acknowledges the interrupt controller after handling.
 pub fn dispatch_irq(_unused: usize) {
-    GICC.handle_irq(|irq_num| crate::irq::dispatch_irq_common(irq_num as _));
+    GICC.handle_irq(|irq_num| {
+        // A kick from `kick_cpu`: taking the trap was all it was for.
+        if irq_num as usize != KICK_SGI_NUM {
+            crate::irq::dispatch_irq_common(irq_num as _)
+        }
+    });
+}
+
+/// Interrupts the CPU `cpu_id`, so that it traps into the kernel soon if it
+/// runs in user space. It returns whether the interrupt was sent.
+pub fn kick_cpu(cpu_id: usize) -> bool {
+    GICD.lock().send_sgi(cpu_id, KICK_SGI_NUM);
+    true
 }
 
 /// Initializes GICD, GICC on the primary CPU.
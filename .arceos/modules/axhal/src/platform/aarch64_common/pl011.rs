@@ -43,6 +43,9 @@ pub fn read_bytes(bytes: &mut [u8]) -> usize {
         if let Some(c) = getchar() {
             bytes[read_len] = c;
         } else {
+            // The receive FIFO is drained, so the pending receive (timeout)
+            // interrupt can be cleared.
+            UART.lock().ack_interrupts();
             break;
         }
         read_len += 1;
@@ -50,6 +53,15 @@ pub fn read_bytes(bytes: &mut [u8]) -> usize {
     read_len
 }
 
+/// Returns the IRQ number raised when console input arrives, or [`None`] if
+/// the console can only be polled.
+pub fn irq_num() -> Option<usize> {
+    #[cfg(feature = "irq")]
+    return Some(crate::platform::irq::UART_IRQ_NUM);
+    #[cfg(not(feature = "irq"))]
+    None
+}
+
 /// Initialize the UART
 pub fn init_early() {
     UART.lock().init();
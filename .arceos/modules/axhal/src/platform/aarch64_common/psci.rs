@@ -103,6 +103,22 @@ pub fn system_off() -> ! {
     }
 }
 
+/// Reboot the whole system.
+pub fn system_reset() -> ! {
+    info!("Rebooting...");
+    psci_call(PSCI_0_2_FN_SYSTEM_RESET, 0, 0, 0).ok();
+    warn!("It should reboot!");
+    loop {
+        crate::arch::halt();
+    }
+}
+
+/// Shutdown the whole system after the failure `code`. PSCI has no way to
+/// tell, so it is the same as [`system_off`].
+pub fn system_off_failure(_code: u8) -> ! {
+    system_off()
+}
+
 /// Power up a core. This call is used to power up cores that either:
 ///
 /// * Have not yet been booted into the calling supervisory software.
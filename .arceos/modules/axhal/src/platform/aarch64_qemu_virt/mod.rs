@@ -6,6 +6,17 @@ pub mod mp;
 #[cfg(feature = "irq")]
 pub mod irq {
     pub use crate::platform::aarch64_common::gic::*;
+
+    use arm_gicv2::{InterruptType, translate_irq};
+
+    /// The IRQ of the VirtIO MMIO device in the `index`th of the
+    /// `virtio-mmio-regions`, as QEMU wires them from SPI 16.
+    pub fn virtio_mmio_irq(index: usize) -> Option<usize> {
+        if index >= axconfig::devices::VIRTIO_MMIO_REGIONS.len() {
+            return None;
+        }
+        translate_irq(16 + index, InterruptType::SPI)
+    }
 }
 
 pub mod console {
@@ -17,7 +28,9 @@ pub mod time {
 }
 
 pub mod misc {
-    pub use crate::platform::aarch64_common::psci::system_off as terminate;
+    pub use crate::platform::aarch64_common::psci::{
+        system_off as terminate, system_off_failure as terminate_failure, system_reset as reboot,
+    };
 }
 
 unsafe extern "C" {
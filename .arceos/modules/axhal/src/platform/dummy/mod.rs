@@ -12,6 +12,12 @@ pub mod console {
     pub fn read_bytes(_bytes: &mut [u8]) -> usize {
         unimplemented!()
     }
+
+    /// Returns the IRQ number raised when console input arrives, or [`None`]
+    /// if the console can only be polled.
+    pub fn irq_num() -> Option<usize> {
+        unimplemented!()
+    }
 }
 
 pub mod misc {
@@ -19,6 +25,16 @@ pub mod misc {
     pub fn terminate() -> ! {
         unimplemented!()
     }
+
+    /// Shutdown the whole system after the failure `code`.
+    pub fn terminate_failure(_code: u8) -> ! {
+        unimplemented!()
+    }
+
+    /// Reboot the whole system.
+    pub fn reboot() -> ! {
+        unimplemented!()
+    }
 }
 
 #[cfg(feature = "smp")]
@@ -59,6 +75,9 @@ pub mod time {
     pub fn epochoffset_nanos() -> u64 {
         0
     }
+
+    /// Set the epoch offset in nanoseconds, in place of what the RTC said.
+    pub fn set_epochoffset_nanos(_nanos: u64) {}
 }
 
 #[cfg(feature = "irq")]
@@ -83,6 +102,18 @@ pub mod irq {
     /// up in the IRQ handler table and calls the corresponding handler. If
     /// necessary, it also acknowledges the interrupt controller after handling.
     pub fn dispatch_irq(irq_num: usize) {}
+
+    /// Interrupts the CPU `cpu_id`, so that it traps into the kernel soon
+    /// if it runs in user space. It returns whether the interrupt was sent.
+    pub fn kick_cpu(cpu_id: usize) -> bool {
+        false
+    }
+
+    /// The IRQ of the VirtIO MMIO device in the `index`th of the
+    /// `virtio-mmio-regions`.
+    pub fn virtio_mmio_irq(index: usize) -> Option<usize> {
+        None
+    }
 }
 
 /// Initializes the platform devices for the primary CPU.
@@ -24,6 +24,16 @@ pub fn write_bytes(bytes: &[u8]) {
     }
 }
 
+/// Returns the IRQ number raised when console input arrives, or [`None`] if
+/// the console can only be polled.
+///
+/// Always [`None`] here: the UART interrupt reaches the CPU through the
+/// PCH-PIC and the extended I/O interrupt controller, which are not driven
+/// yet, so the console is polled.
+pub fn irq_num() -> Option<usize> {
+    None
+}
+
 /// Reads bytes from the console into the given mutable slice.
 /// Returns the number of bytes read.
 pub fn read_bytes(bytes: &mut [u8]) -> usize {
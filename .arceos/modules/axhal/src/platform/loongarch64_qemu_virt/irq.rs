@@ -26,6 +26,20 @@ pub fn register_handler(irq_num: usize, handler: crate::irq::IrqHandler) -> bool
     crate::irq::register_handler_common(irq_num, handler)
 }
 
+/// Interrupts the CPU `cpu_id`, so that it traps into the kernel soon if it
+/// runs in user space. It returns whether the interrupt was sent.
+///
+/// Not supported yet: the CPU only notices at its next timer tick.
+pub fn kick_cpu(_cpu_id: usize) -> bool {
+    false
+}
+
+/// The IRQ of the VirtIO MMIO device in the `index`th of the
+/// `virtio-mmio-regions`. There are none on this platform.
+pub fn virtio_mmio_irq(_index: usize) -> Option<usize> {
+    None
+}
+
 /// Dispatches the IRQ.
 ///
 /// This function is called by the common interrupt handler. It looks
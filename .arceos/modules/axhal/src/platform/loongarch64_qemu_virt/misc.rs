@@ -2,6 +2,9 @@ use crate::mem::phys_to_virt;
 use memory_addr::pa;
 
 const HALT_ADDR: *mut u8 = phys_to_virt(pa!(axconfig::devices::GED_PADDR)).as_mut_ptr();
+/// The reset register of the GED, and the value which resets.
+const RESET_OFFSET: usize = 2;
+const RESET_VALUE: u8 = 0x42;
 
 /// Shutdown the whole system, including all CPUs.
 pub fn terminate() -> ! {
@@ -13,3 +16,19 @@ pub fn terminate() -> ! {
         crate::arch::halt();
     }
 }
+
+/// Shutdown the whole system after the failure `code`. The machine has no
+/// way to tell, so it is the same as [`terminate`].
+pub fn terminate_failure(_code: u8) -> ! {
+    terminate()
+}
+
+/// Reboot the whole system.
+pub fn reboot() -> ! {
+    info!("Rebooting...");
+    unsafe { HALT_ADDR.add(RESET_OFFSET).write_volatile(RESET_VALUE) };
+    warn!("It should reboot!");
+    loop {
+        crate::arch::halt();
+    }
+}
@@ -18,6 +18,13 @@ pub fn epochoffset_nanos() -> u64 {
     unsafe { RTC_EPOCHOFFSET_NANOS }
 }
 
+/// Set the epoch offset in nanoseconds, in place of what the RTC said.
+///
+/// Meant for boot, before the wall time is read.
+pub fn set_epochoffset_nanos(nanos: u64) {
+    unsafe { RTC_EPOCHOFFSET_NANOS = nanos }
+}
+
 /// Converts hardware ticks to nanoseconds.
 #[inline]
 pub fn ticks_to_nanos(ticks: u64) -> u64 {
@@ -41,7 +48,8 @@ pub fn set_oneshot_timer(deadline_ns: u64) {
 
     let ticks_now = current_ticks();
     let ticks_deadline = nanos_to_ticks(deadline_ns);
-    let init_value = ticks_deadline - ticks_now;
+    // A deadline already passed fires at once.
+    let init_value = ticks_deadline.saturating_sub(ticks_now).max(1);
     tcfg::set_init_val(init_value as _);
     tcfg::set_en(true);
 }
@@ -2,6 +2,26 @@ use memory_addr::VirtAddr;
 
 use crate::mem::virt_to_phys;
 
+/// The ns16550a UART the SBI console goes to. Only its receive interrupt is
+/// set up here, the bytes are still read and written through the SBI.
+#[cfg(feature = "irq")]
+mod uart {
+    use memory_addr::PhysAddr;
+
+    use crate::mem::phys_to_virt;
+
+    const UART_BASE: PhysAddr = pa!(axconfig::devices::UART_PADDR);
+    /// The interrupt enable register.
+    const IER: usize = 1;
+
+    /// Raise the IRQ of the UART when a byte is received, and until the
+    /// receive FIFO is drained.
+    pub fn enable_rx_interrupt() {
+        // SAFETY: the registers of the UART are mapped.
+        unsafe { ((phys_to_virt(UART_BASE).as_usize() + IER) as *mut u8).write_volatile(1) };
+    }
+}
+
 /// The maximum number of bytes that can be read at once.
 const MAX_RW_SIZE: usize = 256;
 
@@ -50,6 +70,22 @@ pub fn write_bytes(bytes: &[u8]) {
     }
 }
 
+/// Returns the IRQ number raised when console input arrives, or [`None`] if
+/// the console can only be polled.
+pub fn irq_num() -> Option<usize> {
+    #[cfg(feature = "irq")]
+    return Some(axconfig::devices::UART_IRQ);
+    #[cfg(not(feature = "irq"))]
+    None
+}
+
+/// Initializes the console input IRQ, which stays disabled in the PLIC
+/// until a handler is registered.
+#[cfg(feature = "irq")]
+pub(super) fn init_irq() {
+    uart::enable_rx_interrupt();
+}
+
 /// Reads bytes from the console into the given mutable slice.
 /// Returns the number of bytes read.
 pub fn read_bytes(bytes: &mut [u8]) -> usize {
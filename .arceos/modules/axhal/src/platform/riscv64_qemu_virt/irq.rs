@@ -1,4 +1,5 @@
-//! TODO: PLIC
+//! The local interrupts of each hart, taken by their cause in `scause`, and
+//! the external ones, taken by their source number in the PLIC.
 
 use crate::irq::IrqHandler;
 use lazyinit::LazyInit;
@@ -8,7 +9,6 @@ use riscv::register::sie;
 pub(super) const INTC_IRQ_BASE: usize = 1 << (usize::BITS - 1);
 
 /// Supervisor software interrupt in `scause`
-#[allow(unused)]
 pub(super) const S_SOFT: usize = INTC_IRQ_BASE + 1;
 
 /// Supervisor timer interrupt in `scause`
@@ -25,38 +25,91 @@ pub const MAX_IRQ_COUNT: usize = 1024;
 /// The timer IRQ number (supervisor timer interrupt in `scause`).
 pub const TIMER_IRQ_NUM: usize = S_TIMER;
 
-macro_rules! with_cause {
-    ($cause: expr, @TIMER => $timer_op: expr, @EXT => $ext_op: expr $(,)?) => {
-        match $cause {
-            S_TIMER => $timer_op,
-            S_EXT => $ext_op,
-            _ => panic!("invalid trap cause: {:#x}", $cause),
+/// The platform-level interrupt controller, through which the external
+/// interrupts come, each from its source `1..MAX_IRQ_COUNT`.
+mod plic {
+    use memory_addr::PhysAddr;
+
+    use crate::mem::phys_to_virt;
+
+    const PLIC_BASE: PhysAddr = pa!(axconfig::devices::PLIC_PADDR);
+
+    const PRIORITY: usize = 0;
+    const ENABLE: usize = 0x2000;
+    const ENABLE_STRIDE: usize = 0x80;
+    const CONTEXT: usize = 0x20_0000;
+    const CONTEXT_STRIDE: usize = 0x1000;
+    const THRESHOLD: usize = 0;
+    const CLAIM: usize = 4;
+
+    fn reg(offset: usize) -> *mut u32 {
+        (phys_to_virt(PLIC_BASE).as_usize() + offset) as *mut u32
+    }
+
+    /// The context of the supervisor mode of `hart`, as QEMU numbers them.
+    fn context(hart: usize) -> usize {
+        2 * hart + 1
+    }
+
+    /// Enables or disables the source `irq` on every hart.
+    pub fn set_enable(irq: usize, enabled: bool) {
+        // SAFETY: the registers of the PLIC are mapped, and `irq` is below
+        // the number of sources.
+        unsafe {
+            reg(PRIORITY + irq * 4).write_volatile(enabled as u32);
+            for hart in 0..axconfig::SMP {
+                let word = reg(ENABLE + context(hart) * ENABLE_STRIDE + irq / 32 * 4);
+                let bit = 1 << (irq % 32);
+                let value = word.read_volatile();
+                word.write_volatile(if enabled { value | bit } else { value & !bit });
+            }
         }
-    };
+    }
+
+    /// Lets any enabled source with a priority interrupt this hart.
+    pub fn init_percpu() {
+        let context = context(crate::cpu::this_cpu_id());
+        // SAFETY: the registers of the PLIC are mapped.
+        unsafe { reg(CONTEXT + context * CONTEXT_STRIDE + THRESHOLD).write_volatile(0) };
+    }
+
+    /// Takes the source of a pending interrupt for this hart, if any.
+    pub fn claim() -> Option<usize> {
+        let context = context(crate::cpu::this_cpu_id());
+        // SAFETY: the registers of the PLIC are mapped.
+        let irq = unsafe { reg(CONTEXT + context * CONTEXT_STRIDE + CLAIM).read_volatile() };
+        (irq != 0).then_some(irq as usize)
+    }
+
+    /// Tells the source `irq`, claimed by this hart, has been handled.
+    pub fn complete(irq: usize) {
+        let context = context(crate::cpu::this_cpu_id());
+        // SAFETY: the registers of the PLIC are mapped.
+        unsafe { reg(CONTEXT + context * CONTEXT_STRIDE + CLAIM).write_volatile(irq as u32) };
+    }
 }
 
-/// Enables or disables the given IRQ.
-pub fn set_enable(scause: usize, _enabled: bool) {
-    if scause == S_EXT {
-        // TODO: set enable in PLIC
+/// Enables or disables the given IRQ, a source of the PLIC.
+pub fn set_enable(irq: usize, enabled: bool) {
+    if (1..MAX_IRQ_COUNT).contains(&irq) {
+        plic::set_enable(irq, enabled);
     }
 }
 
-/// Registers an IRQ handler for the given IRQ.
+/// Registers an IRQ handler for the given IRQ, [`TIMER_IRQ_NUM`] or a
+/// source of the PLIC.
 ///
 /// It also enables the IRQ if the registration succeeds. It returns `false` if
 /// the registration failed.
-pub fn register_handler(scause: usize, handler: IrqHandler) -> bool {
-    with_cause!(
-        scause,
-        @TIMER => if !TIMER_HANDLER.is_inited() {
+pub fn register_handler(irq: usize, handler: IrqHandler) -> bool {
+    match irq {
+        S_TIMER if !TIMER_HANDLER.is_inited() => {
             TIMER_HANDLER.init_once(handler);
             true
-        } else {
-            false
-        },
-        @EXT => crate::irq::register_handler_common(scause & !INTC_IRQ_BASE, handler),
-    )
+        }
+        1..MAX_IRQ_COUNT => crate::irq::register_handler_common(irq, handler),
+        _ => false,
+    }
 }
 
 /// Dispatches the IRQ.
@@ -65,17 +118,39 @@ pub fn register_handler(scause: usize, handler: IrqHandler) -> bool {
 /// up in the IRQ handler table and calls the corresponding handler. If
 /// necessary, it also acknowledges the interrupt controller after handling.
 pub fn dispatch_irq(scause: usize) {
-    with_cause!(
-        scause,
-        @TIMER => {
+    match scause {
+        S_SOFT => {
+            // A kick from `kick_cpu`: taking the trap was all it was for.
+            unsafe { riscv::register::sip::clear_ssoft() };
+        }
+        S_TIMER => {
             trace!("IRQ: timer");
             TIMER_HANDLER();
-        },
-        @EXT => crate::irq::dispatch_irq_common(0), // TODO: get IRQ number from PLIC
-    );
+        }
+        S_EXT => {
+            while let Some(irq) = plic::claim() {
+                crate::irq::dispatch_irq_common(irq);
+                plic::complete(irq);
+            }
+        }
+        _ => panic!("invalid trap cause: {:#x}", scause),
+    }
+}
+
+/// The IRQ of the VirtIO MMIO device in the `index`th of the
+/// `virtio-mmio-regions`, a source of the PLIC, as QEMU wires them from 1.
+pub fn virtio_mmio_irq(index: usize) -> Option<usize> {
+    (index < axconfig::devices::VIRTIO_MMIO_REGIONS.len()).then_some(1 + index)
+}
+
+/// Interrupts the CPU `cpu_id`, so that it traps into the kernel soon if it
+/// runs in user space. It returns whether the interrupt was sent.
+pub fn kick_cpu(cpu_id: usize) -> bool {
+    sbi_rt::send_ipi(sbi_rt::HartMask::from_mask_base(1, cpu_id)).is_ok()
 }
 
 pub(super) fn init_percpu() {
+    plic::init_percpu();
     // enable soft interrupts, timer interrupts, and external interrupts
     unsafe {
         sie::set_ssoft();
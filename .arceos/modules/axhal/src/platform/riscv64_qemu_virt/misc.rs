@@ -7,3 +7,21 @@ pub fn terminate() -> ! {
         crate::arch::halt();
     }
 }
+
+/// Shutdown the whole system, telling the machine that the run failed,
+/// which makes QEMU exit with 1, whatever `code` is.
+pub fn terminate_failure(code: u8) -> ! {
+    info!("Shutting down on failure {}...", code);
+    sbi_rt::system_reset(sbi_rt::Shutdown, sbi_rt::SystemFailure);
+    terminate()
+}
+
+/// Reboot the whole system.
+pub fn reboot() -> ! {
+    info!("Rebooting...");
+    sbi_rt::system_reset(sbi_rt::ColdReboot, sbi_rt::NoReason);
+    warn!("It should reboot!");
+    loop {
+        crate::arch::halt();
+    }
+}
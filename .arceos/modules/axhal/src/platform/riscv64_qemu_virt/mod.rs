@@ -23,6 +23,7 @@ unsafe extern "C" fn rust_entry(cpu_id: usize, dtb: usize) {
     #[cfg(feature = "uspace")]
     riscv::register::sstatus::set_sum();
     self::time::init_early();
+    crate::hwcap::init(dtb);
     rust_main(cpu_id, dtb);
 }
 
@@ -39,7 +40,10 @@ unsafe extern "C" fn rust_entry_secondary(cpu_id: usize) {
 /// For example, the interrupt controller and the timer.
 pub fn platform_init() {
     #[cfg(feature = "irq")]
-    self::irq::init_percpu();
+    {
+        self::irq::init_percpu();
+        self::console::init_irq();
+    }
     self::time::init_percpu();
 }
 
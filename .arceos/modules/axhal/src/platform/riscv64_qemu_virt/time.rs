@@ -27,6 +27,13 @@ pub fn epochoffset_nanos() -> u64 {
     unsafe { RTC_EPOCHOFFSET_NANOS }
 }
 
+/// Set the epoch offset in nanoseconds, in place of what the RTC said.
+///
+/// Meant for boot, before the wall time is read.
+pub fn set_epochoffset_nanos(nanos: u64) {
+    unsafe { RTC_EPOCHOFFSET_NANOS = nanos }
+}
+
 /// Set a one-shot timer.
 ///
 /// A timer interrupt will be triggered at the specified monotonic time deadline (in nanoseconds).
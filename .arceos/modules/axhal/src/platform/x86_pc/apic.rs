@@ -16,6 +16,7 @@ pub(super) mod vectors {
     pub const APIC_TIMER_VECTOR: u8 = 0xf0;
     pub const APIC_SPURIOUS_VECTOR: u8 = 0xf1;
     pub const APIC_ERROR_VECTOR: u8 = 0xf2;
+    pub const APIC_KICK_VECTOR: u8 = 0xf3;
 }
 
 /// The maximum number of IRQs.
@@ -26,6 +27,15 @@ pub const TIMER_IRQ_NUM: usize = APIC_TIMER_VECTOR as usize;
 
 const IO_APIC_BASE: PhysAddr = pa!(0xFEC0_0000);
 
+/// The vector of the first input of the I/O APIC: the input `n`, the ISA
+/// IRQ `n` but for the timer, raises the vector `IO_APIC_VECTOR_BASE + n`.
+const IO_APIC_VECTOR_BASE: u8 = 0x20;
+
+/// The vector the ISA IRQ `irq` raises through the I/O APIC.
+pub(super) const fn io_apic_vector(irq: u8) -> usize {
+    (IO_APIC_VECTOR_BASE + irq) as usize
+}
+
 static LOCAL_APIC: SyncUnsafeCell<MaybeUninit<LocalApic>> =
     SyncUnsafeCell::new(MaybeUninit::uninit());
 static mut IS_X2APIC: bool = false;
@@ -36,11 +46,14 @@ static IO_APIC: LazyInit<SpinNoIrq<IoApic>> = LazyInit::new();
 pub fn set_enable(vector: usize, enabled: bool) {
     // should not affect LAPIC interrupts
     if vector < APIC_TIMER_VECTOR as _ {
+        let Some(irq) = (vector as u8).checked_sub(IO_APIC_VECTOR_BASE) else {
+            return;
+        };
         unsafe {
             if enabled {
-                IO_APIC.lock().enable_irq(vector as u8);
+                IO_APIC.lock().enable_irq(irq);
             } else {
-                IO_APIC.lock().disable_irq(vector as u8);
+                IO_APIC.lock().disable_irq(irq);
             }
         }
     }
@@ -62,10 +75,21 @@ pub fn register_handler(vector: usize, handler: crate::irq::IrqHandler) -> bool
 /// necessary, it also acknowledges the interrupt controller after handling.
 #[cfg(feature = "irq")]
 pub fn dispatch_irq(vector: usize) {
-    crate::irq::dispatch_irq_common(vector);
+    // A kick from `kick_cpu`: taking the trap was all it was for.
+    if vector != APIC_KICK_VECTOR as usize {
+        crate::irq::dispatch_irq_common(vector);
+    }
     unsafe { local_apic().end_of_interrupt() };
 }
 
+/// Interrupts the CPU `cpu_id`, so that it traps into the kernel soon if it
+/// runs in user space. It returns whether the interrupt was sent.
+#[cfg(feature = "irq")]
+pub fn kick_cpu(cpu_id: usize) -> bool {
+    unsafe { local_apic().send_ipi(APIC_KICK_VECTOR, raw_apic_id(cpu_id as u8)) };
+    true
+}
+
 pub(super) fn local_apic<'a>() -> &'a mut LocalApic {
     // It's safe as `LOCAL_APIC` is initialized in `init_primary`.
     unsafe { LOCAL_APIC.get().as_mut().unwrap().assume_init_mut() }
@@ -117,7 +141,9 @@ pub(super) fn init_primary() {
     }
 
     info!("Initialize IO APIC...");
-    let io_apic = unsafe { IoApic::new(phys_to_virt(IO_APIC_BASE).as_usize() as u64) };
+    let mut io_apic = unsafe { IoApic::new(phys_to_virt(IO_APIC_BASE).as_usize() as u64) };
+    // Every input masked, each raising its own vector once enabled.
+    unsafe { io_apic.init(IO_APIC_VECTOR_BASE) };
     IO_APIC.init_once(SpinNoIrq::new(io_apic));
 }
 
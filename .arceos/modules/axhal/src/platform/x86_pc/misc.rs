@@ -1,5 +1,9 @@
 use x86_64::instructions::port::PortWriteOnly;
 
+/// The port of the `isa-debug-exit` device of QEMU, if it is given one,
+/// which exits with `(value << 1) | 1` for a value written.
+const DEBUG_EXIT_PORT: u16 = 0xf4;
+
 /// Shutdown the whole system (in QEMU), including all CPUs.
 ///
 /// See <https://wiki.osdev.org/Shutdown> for more information.
@@ -26,3 +30,22 @@ pub fn terminate() -> ! {
         crate::arch::halt();
     }
 }
+
+/// Shutdown the whole system, telling QEMU that the run failed with
+/// `code`: it exits with `code * 2 + 1` through `isa-debug-exit`, or shuts
+/// down as [`terminate`] does without the device.
+pub fn terminate_failure(code: u8) -> ! {
+    info!("Shutting down on failure {}...", code);
+    unsafe { PortWriteOnly::new(DEBUG_EXIT_PORT).write(code as u32) };
+    terminate()
+}
+
+/// Reboot the whole system, through the keyboard controller.
+pub fn reboot() -> ! {
+    info!("Rebooting...");
+    unsafe { PortWriteOnly::new(0x64).write(0xfeu8) };
+    warn!("It should reboot!");
+    loop {
+        crate::arch::halt();
+    }
+}
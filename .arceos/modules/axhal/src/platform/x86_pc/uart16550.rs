@@ -7,6 +7,11 @@ const UART_CLOCK_FACTOR: usize = 16;
 const OSC_FREQ: usize = 1_843_200;
 
 static COM1: SpinNoIrq<Uart16550> = SpinNoIrq::new(Uart16550::new(0x3f8));
+static COM2: SpinNoIrq<Uart16550> = SpinNoIrq::new(Uart16550::new(0x2f8));
+
+/// The ISA IRQ of COM1.
+#[cfg(feature = "irq")]
+const COM1_IRQ: u8 = 4;
 
 bitflags::bitflags! {
     /// Line status flags
@@ -65,6 +70,13 @@ impl Uart16550 {
         }
     }
 
+    /// Raise the IRQ of the port when a byte is received, and until the
+    /// receive FIFO is drained.
+    #[cfg(feature = "irq")]
+    fn enable_rx_interrupt(&mut self) {
+        unsafe { self.int_en.write(0x01) };
+    }
+
     fn line_sts(&mut self) -> LineStsFlags {
         unsafe { LineStsFlags::from_bits_truncate(self.line_sts.read()) }
     }
@@ -122,6 +134,44 @@ pub fn read_bytes(bytes: &mut [u8]) -> usize {
     read_len
 }
 
+/// Returns the IRQ number raised when console input arrives, or [`None`] if
+/// the console can only be polled.
+pub fn irq_num() -> Option<usize> {
+    #[cfg(feature = "irq")]
+    return Some(super::apic::io_apic_vector(COM1_IRQ));
+    #[cfg(not(feature = "irq"))]
+    None
+}
+
+/// The second serial port, COM2, left to whoever wants a channel apart from
+/// the console, such as a debugger. It is raw: no byte is translated.
+pub mod aux {
+    use super::COM2;
+
+    /// Initializes the port. Nothing else here may be called before.
+    pub fn init() {
+        COM2.lock().init(115200);
+    }
+
+    /// Writes bytes to the port.
+    pub fn write_bytes(bytes: &[u8]) {
+        let mut uart = COM2.lock();
+        for &c in bytes {
+            uart.putchar(c);
+        }
+    }
+
+    /// Reads a byte from the port, or returns [`None`] if no input is
+    /// available.
+    pub fn getchar() -> Option<u8> {
+        COM2.lock().getchar()
+    }
+}
+
 pub(super) fn init() {
-    COM1.lock().init(115200);
+    let mut com1 = COM1.lock();
+    com1.init(115200);
+    // The IRQ stays masked in the I/O APIC until a handler is registered.
+    #[cfg(feature = "irq")]
+    com1.enable_rx_interrupt();
 }
@@ -12,7 +12,9 @@ pub type TimeValue = Duration;
 pub use crate::platform::irq::TIMER_IRQ_NUM;
 #[cfg(feature = "irq")]
 pub use crate::platform::time::set_oneshot_timer;
-pub use crate::platform::time::{current_ticks, epochoffset_nanos, nanos_to_ticks, ticks_to_nanos};
+pub use crate::platform::time::{
+    current_ticks, epochoffset_nanos, nanos_to_ticks, set_epochoffset_nanos, ticks_to_nanos,
+};
 
 /// Number of milliseconds in a second.
 pub const MILLIS_PER_SEC: u64 = 1_000;
@@ -21,6 +21,20 @@ pub static PAGE_FAULT: [fn(VirtAddr, MappingFlags, bool) -> bool];
 #[def_trap_handler]
 pub static SYSCALL: [fn(&mut TrapFrame, usize) -> isize];
 
+/// A slice of handlers of breakpoints (`int3` and the like) and single steps
+/// in user space, given whether it is a single step. They return `false` if
+/// they leave the trap alone.
+#[cfg(feature = "uspace")]
+#[def_trap_handler]
+pub static USER_DEBUG: [fn(&mut TrapFrame, bool) -> bool];
+
+/// A slice of handlers of illegal instructions in user space: undefined
+/// ones, and those of features the kernel leaves disabled, see
+/// [`crate::hwcap`]. They return `false` if they leave the trap alone.
+#[cfg(feature = "uspace")]
+#[def_trap_handler]
+pub static ILLEGAL_INSTRUCTION: [fn(&mut TrapFrame) -> bool];
+
 /// A slice of callbacks to be invoked after a trap.
 #[linkme::distributed_slice]
 pub static POST_TRAP: [fn(&mut TrapFrame, bool)];
@@ -48,6 +62,21 @@ pub(crate) fn post_trap_callback(tf: &mut TrapFrame, from_user: bool) {
     }
 }
 
+/// Call the handlers of a breakpoint, or a single step if `single_step` is
+/// set, in user space. Returns `false` if none of them took it.
+#[cfg(feature = "uspace")]
+#[allow(dead_code)]
+pub(crate) fn handle_user_debug(tf: &mut TrapFrame, single_step: bool) -> bool {
+    USER_DEBUG.iter().any(|f| f(tf, single_step))
+}
+
+/// Call the handlers of an illegal instruction in user space. Returns
+/// `false` if none of them took it.
+#[cfg(feature = "uspace")]
+pub(crate) fn handle_illegal_instruction(tf: &mut TrapFrame) -> bool {
+    ILLEGAL_INSTRUCTION.iter().any(|f| f(tf))
+}
+
 /// Call the external syscall handler.
 #[cfg(feature = "uspace")]
 pub(crate) fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
@@ -1,3 +1,4 @@
+use alloc::vec::Vec;
 use core::fmt;
 
 use axerrno::{AxError, AxResult, ax_err};
@@ -6,11 +7,96 @@ use axhal::paging::{MappingFlags, PageTable, PagingError};
 use memory_addr::{
     MemoryAddr, PAGE_SIZE_4K, PageIter4K, PhysAddr, VirtAddr, VirtAddrRange, is_aligned,
 };
-use memory_set::{MemoryArea, MemorySet};
+use memory_set::{MappingError, MemoryArea, MemorySet};
 use page_table_multiarch::PageSize;
 
-use crate::backend::{Backend, PageIterWrapper};
-use crate::mapping_err_to_ax_err;
+use crate::backend::alloc::{alloc_frame, dealloc_frame};
+use crate::backend::{AreaKind, Backend, PageIterWrapper};
+use crate::{mapping_err_to_ax_err, max_map_count};
+
+/// The largest run copied at once by [`AddrSpace::read`] and
+/// [`AddrSpace::write`].
+const MAX_COPY_CHUNK: usize = 0x10_0000;
+
+/// How many pages [`AddrSpace::clone_or_err`] goes through between calls to
+/// give up the CPU.
+const CLONE_RESCHED_PAGES: usize = 64;
+
+/// A run of physically contiguous memory being gathered for one copy.
+#[derive(Default)]
+struct ContiguousRun {
+    paddr: PhysAddr,
+    offset: usize,
+    len: usize,
+}
+
+impl ContiguousRun {
+    /// Extend the run with `len` bytes at `paddr`, which are at `offset` in
+    /// the range, or pass the run to `f` and start a new one.
+    fn push<F>(&mut self, paddr: PhysAddr, offset: usize, len: usize, f: &mut F)
+    where
+        F: FnMut(VirtAddr, usize, usize),
+    {
+        if self.len > 0 && self.paddr + self.len == paddr && self.len + len <= MAX_COPY_CHUNK {
+            self.len += len;
+            return;
+        }
+        self.flush(f);
+        *self = Self { paddr, offset, len };
+    }
+
+    fn flush<F>(&mut self, f: &mut F)
+    where
+        F: FnMut(VirtAddr, usize, usize),
+    {
+        if self.len > 0 {
+            f(phys_to_virt(self.paddr), self.offset, self.len);
+            self.len = 0;
+        }
+    }
+}
+
+/// A page fault in a lazily allocated area, see
+/// [`AddrSpace::prepare_page_fault`].
+///
+/// The frame for the page is allocated and zeroed without holding the
+/// address space, so that threads faulting in different pages do not wait
+/// for each other. A frame which ends up unused is freed on drop.
+pub struct LazyFault {
+    vaddr: VirtAddr,
+    access_flags: MappingFlags,
+    align: PageSize,
+    frame: Option<PhysAddr>,
+}
+
+impl LazyFault {
+    /// Allocates the zeroed frame for the page.
+    ///
+    /// Returns `false` if out of memory.
+    pub fn alloc_frame(&mut self) -> bool {
+        if self.frame.is_none() {
+            self.frame = alloc_frame(true, self.align);
+        }
+        self.frame.is_some()
+    }
+}
+
+impl Drop for LazyFault {
+    fn drop(&mut self) {
+        if let Some(frame) = self.frame.take() {
+            dealloc_frame(frame, self.align);
+        }
+    }
+}
+
+/// The error of mapping an area which may be populated: the backend fails
+/// to map it only if out of frames, leaving nothing of it mapped.
+fn populate_err(err: MappingError) -> AxError {
+    match err {
+        MappingError::BadState => AxError::NoMemory,
+        err => mapping_err_to_ax_err(err),
+    }
+}
 
 /// The virtual memory address space.
 pub struct AddrSpace {
@@ -188,6 +274,7 @@ impl AddrSpace {
             return ax_err!(InvalidInput, "address not aligned");
         }
 
+        self.reserve_areas(1)?;
         let offset = start_vaddr.as_usize() - start_paddr.as_usize();
         let area = MemoryArea::new(start_vaddr, size, flags, Backend::new_linear(offset, align));
         self.areas
@@ -200,10 +287,16 @@ impl AddrSpace {
     ///
     /// See [`Backend`] for more details about the mapping backends.
     ///
-    /// The `flags` parameter indicates the mapping permissions and attributes.
+    /// The `flags` parameter indicates the mapping permissions and attributes,
+    /// and `kind` what the mapping is used for.
+    ///
+    /// The area is merged with the areas next to it if they have the same
+    /// flags and kind, and allocate lazily too.
     ///
     /// Returns an error if the address range is out of the address space or not
-    /// aligned.
+    /// aligned, or if the address space has [`max_map_count`] areas already.
+    /// Running out of frames to populate the area fails with
+    /// [`AxError::NoMemory`], leaving nothing of it mapped.
     pub fn map_alloc(
         &mut self,
         start: VirtAddr,
@@ -211,16 +304,96 @@ impl AddrSpace {
         flags: MappingFlags,
         populate: bool,
         align: PageSize,
+        kind: AreaKind,
     ) -> AxResult {
         self.validate_region(start, size, align)?;
+        self.reserve_areas(1)?;
 
-        let area = MemoryArea::new(start, size, flags, Backend::new_alloc(populate, align));
+        let backend = Backend::new_alloc(populate, align, kind);
+        let area = MemoryArea::new(start, size, flags, backend);
         self.areas
             .map(area, &mut self.pt, false)
-            .map_err(mapping_err_to_ax_err)?;
+            .map_err(populate_err)?;
+        self.merge_areas(start, start + size);
         Ok(())
     }
 
+    /// Fails with [`AxError::NoMemory`] if `extra` more areas would take
+    /// the address space past [`max_map_count`] areas.
+    fn reserve_areas(&self, extra: usize) -> AxResult {
+        if extra > 0 && self.areas.len() + extra > max_map_count() {
+            return ax_err!(NoMemory, "too many memory areas");
+        }
+        Ok(())
+    }
+
+    /// Checks that changing the flags of `[start, start + size)` to `flags`
+    /// keeps the address space within [`max_map_count`] areas, counting
+    /// the areas with other flags which are split at the ends of the range.
+    pub fn check_protect(&self, start: VirtAddr, size: usize, flags: MappingFlags) -> AxResult {
+        let splits = [start, start + size]
+            .into_iter()
+            .filter(|&addr| {
+                self.areas
+                    .find(addr)
+                    .is_some_and(|area| area.start() < addr && area.flags() != flags)
+            })
+            .count();
+        self.reserve_areas(splits)
+    }
+
+    /// Merges the areas from the one ending at `start` to the one starting
+    /// at `end` which are next to each other, have the same flags, and
+    /// backends which [`Backend::can_merge`], so that mapping or protecting
+    /// pages one at a time, then back, ends with the areas it started with.
+    ///
+    /// The areas being merged are taken out with an empty page table in
+    /// place of theirs, so that their pages stay mapped, then replaced with
+    /// one area. Their backends allocate lazily, so it maps nothing.
+    fn merge_areas(&mut self, start: VirtAddr, end: VirtAddr) {
+        // The runs of areas to merge, with the number of areas in each.
+        let mut runs: Vec<(VirtAddr, VirtAddr, MappingFlags, Backend, usize)> = Vec::new();
+        for area in self
+            .areas
+            .iter()
+            .skip_while(move |a| a.end() < start)
+            .take_while(move |a| a.start() <= end)
+        {
+            match runs.last_mut() {
+                Some((_, run_end, flags, backend, count))
+                    if *run_end == area.start()
+                        && *flags == area.flags()
+                        && backend.can_merge(area.backend()) =>
+                {
+                    *run_end = area.end();
+                    *count += 1;
+                }
+                _ => runs.push((
+                    area.start(),
+                    area.end(),
+                    area.flags(),
+                    area.backend().clone(),
+                    1,
+                )),
+            }
+        }
+        runs.retain(|run| run.4 > 1);
+        if runs.is_empty() {
+            return;
+        }
+        let Ok(mut detached) = PageTable::try_new() else {
+            // Merging is only an optimization.
+            return;
+        };
+        for (start, end, flags, backend, _) in runs {
+            let area = MemoryArea::new(start, end - start, flags, backend);
+            self.areas
+                .unmap(start, end - start, &mut detached)
+                .and_then(|_| self.areas.map(area, &mut self.pt, false))
+                .expect("failed to merge memory areas");
+        }
+    }
+
     /// Populates the area with physical frames, returning false if the area
     /// contains unmapped area.
     pub fn populate_area(&mut self, mut start: VirtAddr, size: usize, align: PageSize) -> AxResult {
@@ -229,7 +402,10 @@ impl AddrSpace {
 
         while let Some(area) = self.areas.find(start) {
             let backend = area.backend();
-            if let Backend::Alloc { populate, align } = *backend {
+            if let Backend::Alloc {
+                populate, align, ..
+            } = *backend
+            {
                 if !populate {
                     for addr in PageIterWrapper::new(start, area.end().min(end), align).unwrap() {
                         match self.pt.query(addr) {
@@ -263,7 +439,8 @@ impl AddrSpace {
     /// Removes mappings within the specified virtual address range.
     ///
     /// Returns an error if the address range is out of the address space or not
-    /// aligned.
+    /// aligned, or if it would split an area in two with the address space at
+    /// [`max_map_count`] areas.
     pub fn unmap(&mut self, start: VirtAddr, size: usize) -> AxResult {
         self.validate_region(start, size, PageSize::Size4K)?;
 
@@ -275,7 +452,7 @@ impl AddrSpace {
             .take_while(move |a| a.start() < end)
         {
             let area_align = match *area.backend() {
-                Backend::Alloc { populate: _, align } => align,
+                Backend::Alloc { align, .. } => align,
                 Backend::Linear {
                     pa_va_offset: _,
                     align,
@@ -288,6 +465,12 @@ impl AddrSpace {
                 return ax_err!(InvalidInput, "address not aligned");
             }
         }
+        // Only a hole in the middle of an area adds one.
+        let hole = self
+            .areas
+            .find(start)
+            .is_some_and(|area| area.start() < start && area.end() > end);
+        self.reserve_areas(hole as usize)?;
 
         self.areas
             .unmap(start, size, &mut self.pt)
@@ -295,6 +478,40 @@ impl AddrSpace {
         Ok(())
     }
 
+    /// Returns the range, the flags and the kind of each mapped area, in the
+    /// order of their addresses.
+    pub fn areas(&self) -> impl Iterator<Item = (VirtAddrRange, MappingFlags, AreaKind)> + '_ {
+        self.areas
+            .iter()
+            .map(|area| (area.va_range(), area.flags(), area.backend().kind()))
+    }
+
+    /// Returns whether `vaddr` is in a mapped area.
+    pub fn is_mapped(&self, vaddr: VirtAddr) -> bool {
+        self.areas.find(vaddr).is_some()
+    }
+
+    /// Returns the number of mapped areas, which [`max_map_count`] limits.
+    pub fn area_count(&self) -> usize {
+        self.areas.len()
+    }
+
+    /// Returns the total size of the mapped areas.
+    pub fn mapped_size(&self) -> usize {
+        self.areas.iter().map(|area| area.size()).sum()
+    }
+
+    /// Returns the total size of the mapped pages which are backed by
+    /// physical frames.
+    pub fn resident_size(&self) -> usize {
+        self.areas
+            .iter()
+            .flat_map(|area| PageIter4K::new(area.start(), area.end()).unwrap())
+            .filter(|vaddr| self.pt.query(*vaddr).is_ok())
+            .count()
+            * PAGE_SIZE_4K
+    }
+
     /// To remove user area mappings from address space.
     pub fn unmap_user_areas(&mut self) -> AxResult {
         self.areas.clear(&mut self.pt).unwrap();
@@ -308,54 +525,53 @@ impl AddrSpace {
     /// # Arguments
     /// - `start`: The start virtual address to process.
     /// - `size`: The size of the data to process.
-    /// - `f`: The function to process the data, whose arguments are the start virtual address,
+    /// - `f`: The function to process the data, whose arguments are the kernel virtual address,
     ///   the offset and the size of the data.
     ///
     /// # Notes
     ///   The caller must ensure that the permission of the operation is allowed.
-    fn process_area_data<F>(&self, start: VirtAddr, size: usize, align: PageSize, f: F) -> AxResult
-    where
-        F: FnMut(VirtAddr, usize, usize),
-    {
-        Self::process_area_data_with_page_table(&self.pt, &self.va_range, start, size, align, f)
-    }
-
-    fn process_area_data_with_page_table<F>(
-        pt: &PageTable,
-        va_range: &VirtAddrRange,
-        start: VirtAddr,
-        size: usize,
-        align: PageSize,
-        mut f: F,
-    ) -> AxResult
+    ///
+    /// `f` is called once for each physically contiguous run. The areas are looked up once per area rather than per page. Linear
+    /// areas are translated without walking the page table, and the pages of
+    /// other areas are walked once each (or once per huge page) and merged
+    /// while their frames are adjacent, which freshly populated allocations
+    /// often are. Runs are capped at [`MAX_COPY_CHUNK`] bytes.
+    fn process_area_data<F>(&self, start: VirtAddr, size: usize, mut f: F) -> AxResult
     where
         F: FnMut(VirtAddr, usize, usize),
     {
-        if !va_range.contains_range(VirtAddrRange::from_start_size(start, size)) {
+        if !self
+            .va_range
+            .contains_range(VirtAddrRange::from_start_size(start, size))
+        {
             return ax_err!(InvalidInput, "address out of range");
         }
-        let mut cnt = 0;
-        // If start is aligned to 4K, start_align_down will be equal to start_align_up.
-        let end_align_up = (start + size).align_up(align);
-        let start_addr = start.align_down(align);
-        for vaddr in PageIterWrapper::new(start_addr, end_align_up, align)
-            .expect("Failed to create page iterator")
-        {
-            let (mut paddr, _, _) = pt.query(vaddr).map_err(|_| AxError::BadAddress)?;
-
-            let mut copy_size = (size - cnt).min(PAGE_SIZE_4K);
 
-            if copy_size == 0 {
-                break;
-            }
-            if vaddr == start.align_down(align) && start.align_offset(align) != 0 {
-                let align_offset = start.align_offset(align);
-                copy_size = copy_size.min(align as usize - align_offset);
-                paddr += align_offset;
+        let end = start + size;
+        let mut run = ContiguousRun::default();
+        let mut vaddr = start;
+        while vaddr < end {
+            let area = self.areas.find(vaddr).ok_or(AxError::BadAddress)?;
+            let area_end = area.end().min(end);
+            match *area.backend() {
+                Backend::Linear { pa_va_offset } => {
+                    let paddr = PhysAddr::from(vaddr.as_usize() - pa_va_offset);
+                    run.push(paddr, vaddr - start, area_end - vaddr, &mut f);
+                    vaddr = area_end;
+                }
+                Backend::Alloc { .. } => {
+                    while vaddr < area_end {
+                        let (paddr, _, page_size) =
+                            self.pt.query(vaddr).map_err(|_| AxError::BadAddress)?;
+                        let page_end = vaddr.align_down(page_size) + page_size as usize;
+                        let len = page_end.min(area_end) - vaddr;
+                        run.push(paddr, vaddr - start, len, &mut f);
+                        vaddr += len;
+                    }
+                }
             }
-            f(phys_to_virt(paddr), cnt, copy_size);
-            cnt += copy_size;
         }
+        run.flush(&mut f);
         Ok(())
     }
 
@@ -365,8 +581,8 @@ impl AddrSpace {
     ///
     /// * `start` - The start virtual address to read.
     /// * `buf` - The buffer to store the data.
-    pub fn read(&self, start: VirtAddr, align: PageSize, buf: &mut [u8]) -> AxResult {
-        self.process_area_data(start, buf.len(), align, |src, offset, read_size| unsafe {
+    pub fn read(&self, start: VirtAddr, buf: &mut [u8]) -> AxResult {
+        self.process_area_data(start, buf.len(), |src, offset, read_size| unsafe {
             core::ptr::copy_nonoverlapping(src.as_ptr(), buf.as_mut_ptr().add(offset), read_size);
         })
     }
@@ -377,16 +593,21 @@ impl AddrSpace {
     ///
     /// * `start_vaddr` - The start virtual address to write.
     /// * `buf` - The buffer to write to the address space.
-    pub fn write(&self, start: VirtAddr, align: PageSize, buf: &[u8]) -> AxResult {
-        self.process_area_data(start, buf.len(), align, |dst, offset, write_size| unsafe {
+    pub fn write(&self, start: VirtAddr, buf: &[u8]) -> AxResult {
+        self.process_area_data(start, buf.len(), |dst, offset, write_size| unsafe {
             core::ptr::copy_nonoverlapping(buf.as_ptr().add(offset), dst.as_mut_ptr(), write_size);
         })
     }
 
     /// Updates mapping within the specified virtual address range.
     ///
+    /// Only the areas with other flags are split at the ends of the range,
+    /// and the areas are then merged with their neighbours like those of
+    /// [`AddrSpace::map_alloc`].
+    ///
     /// Returns an error if the address range is out of the address space or not
-    /// aligned.
+    /// aligned, or if splitting the areas would take the address space past
+    /// [`max_map_count`] areas, see [`AddrSpace::check_protect`].
     pub fn protect(
         &mut self,
         start: VirtAddr,
@@ -394,12 +615,19 @@ impl AddrSpace {
         flags: MappingFlags,
         align: PageSize,
     ) -> AxResult {
+        self.check_protect(start, size, flags)?;
         // Populate the area first, which also checks the address range for us.
         self.populate_area(start, size, align)?;
 
         self.areas
-            .protect(start, size, |_| Some(flags), &mut self.pt)
+            .protect(
+                start,
+                size,
+                |old| (old != flags).then_some(flags),
+                &mut self.pt,
+            )
             .map_err(mapping_err_to_ax_err)?;
+        self.merge_areas(start, start + size);
 
         Ok(())
     }
@@ -450,6 +678,10 @@ impl AddrSpace {
         if !self.va_range.contains(vaddr) {
             return false;
         }
+        if self.is_mapped_for(vaddr, access_flags) {
+            // Another thread faulted the page in first.
+            return true;
+        }
         if let Some(area) = self.areas.find(vaddr) {
             let orig_flags = area.flags();
             if orig_flags.contains(access_flags) {
@@ -461,10 +693,82 @@ impl AddrSpace {
         false
     }
 
+    /// Whether `vaddr` is mapped with flags allowing `access_flags`.
+    fn is_mapped_for(&self, vaddr: VirtAddr, access_flags: MappingFlags) -> bool {
+        self.pt
+            .query(vaddr)
+            .is_ok_and(|(_, flags, _)| flags.contains(access_flags))
+    }
+
+    /// Starts handling a page fault at `vaddr` in a lazily allocated area,
+    /// which [`AddrSpace::finish_page_fault`] completes once the frame is
+    /// allocated with [`LazyFault::alloc_frame`].
+    ///
+    /// Returns `None` if the fault is of another kind, which is left to
+    /// [`AddrSpace::handle_page_fault`].
+    pub fn prepare_page_fault(
+        &self,
+        vaddr: VirtAddr,
+        access_flags: MappingFlags,
+    ) -> Option<LazyFault> {
+        if !self.va_range.contains(vaddr) || self.pt.query(vaddr).is_ok() {
+            return None;
+        }
+        let area = self.areas.find(vaddr)?;
+        if !area.flags().contains(access_flags) {
+            return None;
+        }
+        Some(LazyFault {
+            vaddr,
+            access_flags,
+            align: area.backend().lazy_align()?,
+            frame: None,
+        })
+    }
+
+    /// Maps the frame of `fault` at the fault address.
+    ///
+    /// The address space may have changed since the fault was prepared. If
+    /// another thread mapped the page meanwhile, the frame is freed and the
+    /// fault is handled; if the area is gone or no longer allows the access,
+    /// the fault is a real one.
+    ///
+    /// Returns `true` if the page fault is handled successfully.
+    pub fn finish_page_fault(&mut self, mut fault: LazyFault) -> bool {
+        if self.pt.query(fault.vaddr).is_ok() {
+            return self.is_mapped_for(fault.vaddr, fault.access_flags);
+        }
+        let Some(area) = self.areas.find(fault.vaddr) else {
+            return false;
+        };
+        let flags = area.flags();
+        if !flags.contains(fault.access_flags) || area.backend().lazy_align() != Some(fault.align)
+        {
+            return false;
+        }
+        let Some(frame) = fault.frame.take() else {
+            return false;
+        };
+        match self.pt.map(fault.vaddr, frame, fault.align, flags) {
+            Ok(tlb) => {
+                tlb.flush();
+                true
+            }
+            Err(_) => {
+                fault.frame = Some(frame);
+                false
+            }
+        }
+    }
+
     /// Clone a [`AddrSpace`] by re-mapping all [`MemoryArea`]s in a new page table and copying data in user space.
-    pub fn clone_or_err(&mut self) -> AxResult<Self> {
+    ///
+    /// `cond_resched` is called every few pages, to give up the CPU if need
+    /// be, as a large address space takes long to copy.
+    pub fn clone_or_err(&mut self, mut cond_resched: impl FnMut()) -> AxResult<Self> {
         let mut new_aspace = Self::new_empty(self.base(), self.size())?;
 
+        let mut pages = 0usize;
         for area in self.areas.iter() {
             let backend = area.backend();
             // Remap the memory area in the new address space.
@@ -473,7 +777,7 @@ impl AddrSpace {
             new_aspace
                 .areas
                 .map(new_area, &mut new_aspace.pt, false)
-                .map_err(mapping_err_to_ax_err)?;
+                .map_err(populate_err)?;
 
             if matches!(backend, Backend::Linear { .. }) {
                 continue;
@@ -482,6 +786,10 @@ impl AddrSpace {
             for vaddr in
                 PageIter4K::new(area.start(), area.end()).expect("Failed to create page iterator")
             {
+                pages += 1;
+                if pages % CLONE_RESCHED_PAGES == 0 {
+                    cond_resched();
+                }
                 let addr = match self.pt.query(vaddr) {
                     Ok((paddr, _, _)) => paddr,
                     // If the page is not mapped, skip it.
@@ -4,7 +4,7 @@ use axhal::mem::{phys_to_virt, virt_to_phys};
 use axhal::paging::{MappingFlags, PageSize, PageTable};
 use memory_addr::{PAGE_SIZE_4K, PhysAddr, VirtAddr};
 
-use super::Backend;
+use super::{AreaKind, Backend};
 
 /// Allocates a physical frame, with an option to zero it out.
 ///
@@ -25,7 +25,11 @@ use super::Backend;
 /// - If `zeroed` is `true`, the function uses `unsafe` operations to zero out the memory.
 /// - The allocated memory must be accessed via its physical address, which requires
 ///   conversion using `virt_to_phys`.
-fn alloc_frame(zeroed: bool, align: PageSize) -> Option<PhysAddr> {
+pub(crate) fn alloc_frame(zeroed: bool, align: PageSize) -> Option<PhysAddr> {
+    #[cfg(feature = "fault-inject")]
+    if crate::frame_fault() {
+        return None;
+    }
     let page_size: usize = align.into();
     let num_pages = page_size / PAGE_SIZE_4K;
     let vaddr = VirtAddr::from(global_allocator().alloc_pages(num_pages, page_size).ok()?);
@@ -52,7 +56,7 @@ fn alloc_frame(zeroed: bool, align: PageSize) -> Option<PhysAddr> {
 ///   otherwise undefined behavior may occur.
 /// - If the deallocation fails, the function will call `panic!`. Details about
 ///   the failure can be obtained from the global memory allocator’s error messages.
-fn dealloc_frame(frame: PhysAddr, align: PageSize) {
+pub(crate) fn dealloc_frame(frame: PhysAddr, align: PageSize) {
     let page_size: usize = align.into();
     let num_pages = page_size / PAGE_SIZE_4K;
     let vaddr = phys_to_virt(frame);
@@ -60,9 +64,14 @@ fn dealloc_frame(frame: PhysAddr, align: PageSize) {
 }
 
 impl Backend {
-    /// Creates a new allocation mapping backend.
-    pub const fn new_alloc(populate: bool, align: PageSize) -> Self {
-        Self::Alloc { populate, align }
+    /// Creates a new allocation mapping backend, for a mapping used as
+    /// `kind` says.
+    pub const fn new_alloc(populate: bool, align: PageSize, kind: AreaKind) -> Self {
+        Self::Alloc {
+            populate,
+            align,
+            kind,
+        }
     }
 
     pub(crate) fn map_alloc(
@@ -84,12 +93,18 @@ impl Backend {
             // allocate all possible physical frames for populated mapping.
             if let Some(iter) = PageIterWrapper::new(start, start + size, align) {
                 for addr in iter {
-                    if let Some(frame) = alloc_frame(true, align) {
-                        if let Ok(tlb) = pt.map(addr, frame, align, flags) {
-                            tlb.ignore(); // TLB flush on map is unnecessary, as there are no outdated mappings.
-                        } else {
-                            return false;
-                        }
+                    let Some(frame) = alloc_frame(true, align) else {
+                        // Out of memory: free the frames mapped so far, as
+                        // the area is not added.
+                        Self::unmap_alloc(start, addr - start, pt, populate, align);
+                        return false;
+                    };
+                    if let Ok(tlb) = pt.map(addr, frame, align, flags) {
+                        tlb.ignore(); // TLB flush on map is unnecessary, as there are no outdated mappings.
+                    } else {
+                        dealloc_frame(frame, align);
+                        Self::unmap_alloc(start, addr - start, pt, populate, align);
+                        return false;
                     }
                 }
             }
@@ -1,15 +1,39 @@
 //! Memory mapping backends.
 
+use ::alloc::sync::Arc;
 use axhal::paging::{MappingFlags, PageTable};
 use memory_addr::VirtAddr;
 use memory_set::MappingBackend;
 pub use page_iter_wrapper::PageIterWrapper;
 use page_table_multiarch::PageSize;
 
-mod alloc;
+pub(crate) mod alloc;
 mod linear;
 mod page_iter_wrapper;
 
+/// What an allocation mapping is used for, as shown in `/proc/<pid>/maps`.
+///
+/// The kind is kept by the backend, so the parts of an area split by
+/// unmapping or protecting part of it keep it too.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum AreaKind {
+    /// Private anonymous memory.
+    #[default]
+    Anonymous,
+    /// Anonymous memory mapped with `MAP_SHARED`.
+    Shared,
+    /// The heap, moved by `brk`.
+    Heap,
+    /// The stack of the main thread.
+    Stack,
+    /// The stack of another thread, mapped with `MAP_STACK`.
+    ThreadStack,
+    /// The content of the file at the path, like the segments of a program.
+    File(Arc<str>),
+    /// Physical memory mapped as is, by a linear mapping.
+    Linear,
+}
+
 /// A unified enum type for different memory mapping backends.
 ///
 /// Currently, two backends are implemented:
@@ -42,6 +66,8 @@ pub enum Backend {
         populate: bool,
         /// Alignment parameters for the starting address and memory range.
         align: PageSize,
+        /// What the mapping is used for.
+        kind: AreaKind,
     },
 }
 
@@ -55,9 +81,9 @@ impl MappingBackend for Backend {
                 pa_va_offset,
                 align: _,
             } => Self::map_linear(start, size, flags, pt, pa_va_offset),
-            Self::Alloc { populate, align } => {
-                Self::map_alloc(start, size, flags, pt, populate, align)
-            }
+            Self::Alloc {
+                populate, align, ..
+            } => Self::map_alloc(start, size, flags, pt, populate, align),
         }
     }
 
@@ -67,7 +93,9 @@ impl MappingBackend for Backend {
                 pa_va_offset,
                 align: _,
             } => Self::unmap_linear(start, size, pt, pa_va_offset),
-            Self::Alloc { populate, align } => Self::unmap_alloc(start, size, pt, populate, align),
+            Self::Alloc {
+                populate, align, ..
+            } => Self::unmap_alloc(start, size, pt, populate, align),
         }
     }
 
@@ -86,6 +114,60 @@ impl MappingBackend for Backend {
 }
 
 impl Backend {
+    /// What the mapping is used for.
+    pub fn kind(&self) -> AreaKind {
+        match self {
+            Self::Linear { .. } => AreaKind::Linear,
+            Self::Alloc { kind, .. } => kind.clone(),
+        }
+    }
+
+    /// Whether an area with this backend and one with `other` right after
+    /// it can be merged into one area with this backend, if their flags are
+    /// the same.
+    ///
+    /// Only lazy allocation areas of the same kind merge, as merging must
+    /// not map anything. Neither do the areas of files, whose offsets are
+    /// not kept, shared ones, each of which is memory of its own, nor thread
+    /// stacks, which are told apart by the thread using each.
+    pub(crate) fn can_merge(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Self::Alloc {
+                    populate: false,
+                    align,
+                    kind,
+                },
+                Self::Alloc {
+                    populate: false,
+                    align: other_align,
+                    kind: other_kind,
+                },
+            ) => {
+                align == other_align
+                    && kind == other_kind
+                    && !matches!(
+                        kind,
+                        AreaKind::File(_) | AreaKind::Shared | AreaKind::ThreadStack
+                    )
+            }
+            _ => false,
+        }
+    }
+
+    /// The size of the frames allocated on page faults, if the backend
+    /// allocates them lazily.
+    pub(crate) const fn lazy_align(&self) -> Option<PageSize> {
+        match *self {
+            Self::Alloc {
+                populate: false,
+                align,
+                ..
+            } => Some(align),
+            _ => None,
+        }
+    }
+
     pub(crate) fn handle_page_fault(
         &self,
         vaddr: VirtAddr,
@@ -94,9 +176,9 @@ impl Backend {
     ) -> bool {
         match *self {
             Self::Linear { .. } => false, // Linear mappings should not trigger page faults.
-            Self::Alloc { populate, align } => {
-                Self::handle_page_fault_alloc(vaddr, orig_flags, page_table, populate, align)
-            }
+            Self::Alloc {
+                populate, align, ..
+            } => Self::handle_page_fault_alloc(vaddr, orig_flags, page_table, populate, align),
         }
     }
 }
@@ -9,8 +9,10 @@ extern crate alloc;
 mod aspace;
 mod backend;
 
-pub use self::aspace::AddrSpace;
-pub use self::backend::Backend;
+pub use self::aspace::{AddrSpace, LazyFault};
+pub use self::backend::{AreaKind, Backend};
+
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use axerrno::{AxError, AxResult};
 use axhal::mem::phys_to_virt;
@@ -22,6 +24,44 @@ pub use page_table_multiarch::PageSize;
 
 static KERNEL_ASPACE: LazyInit<SpinNoIrq<AddrSpace>> = LazyInit::new();
 
+/// The default of [`max_map_count`], as on Linux.
+pub const DEFAULT_MAX_MAP_COUNT: usize = 65530;
+
+static MAX_MAP_COUNT: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_MAP_COUNT);
+
+/// The most areas an address space may have, like `vm.max_map_count`.
+///
+/// Mapping an area, or splitting one by unmapping or protecting part of it,
+/// fails with [`AxError::NoMemory`] past it, so that a program cannot use up
+/// the kernel heap with areas.
+pub fn max_map_count() -> usize {
+    MAX_MAP_COUNT.load(Ordering::Relaxed)
+}
+
+/// Set [`max_map_count`]. The address spaces which already have more areas
+/// keep them.
+pub fn set_max_map_count(count: usize) {
+    MAX_MAP_COUNT.store(count, Ordering::Relaxed);
+}
+
+/// Whether an allocation of a frame is to fail, see [`set_frame_fault_hook`].
+#[cfg(feature = "fault-inject")]
+static FRAME_FAULT_HOOK: LazyInit<fn() -> bool> = LazyInit::new();
+
+/// Make each allocation of a frame for an address space fail, as if out of
+/// memory, when `hook` returns `true`, for testing the paths handling it.
+/// The hook can only be set once.
+#[cfg(feature = "fault-inject")]
+pub fn set_frame_fault_hook(hook: fn() -> bool) {
+    FRAME_FAULT_HOOK.init_once(hook);
+}
+
+/// Whether the hook asks for the allocation of a frame at hand to fail.
+#[cfg(feature = "fault-inject")]
+pub(crate) fn frame_fault() -> bool {
+    FRAME_FAULT_HOOK.get().is_some_and(|hook| hook())
+}
+
 fn mapping_err_to_ax_err(err: MappingError) -> AxError {
     warn!("Mapping error: {:?}", err);
     match err {
@@ -0,0 +1,111 @@
+//! What the network interfaces are configured with, and what went through
+//! them, for the `netdevice` ioctls and `/proc/net/dev`.
+
+use alloc::{vec, vec::Vec};
+use core::net::Ipv4Addr;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use smoltcp::wire::IpAddress;
+
+use super::{ETH0, STANDARD_MTU};
+
+/// The MTU of the loopback interface, as in Linux.
+const LOOPBACK_MTU: usize = 65536;
+
+/// The bytes and packets an interface received and sent.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InterfaceStats {
+    /// Bytes received, counting the link layer header.
+    pub rx_bytes: u64,
+    /// Packets received.
+    pub rx_packets: u64,
+    /// Bytes sent, counting the link layer header.
+    pub tx_bytes: u64,
+    /// Packets sent.
+    pub tx_packets: u64,
+}
+
+/// A network interface.
+#[derive(Debug, Clone)]
+pub struct InterfaceInfo {
+    /// The name, e.g. `eth0`.
+    pub name: &'static str,
+    /// The index, from 1.
+    pub index: u32,
+    /// The hardware address, all zero for the loopback.
+    pub mac: [u8; 6],
+    /// The IPv4 address and its prefix length.
+    pub ipv4: Option<(Ipv4Addr, u8)>,
+    /// The largest packet it sends, without the link layer header.
+    pub mtu: usize,
+    /// Whether it is the loopback interface.
+    pub loopback: bool,
+    /// What went through it.
+    pub stats: InterfaceStats,
+}
+
+/// The counters of a device, bumped by the tokens it hands to smoltcp.
+#[derive(Default)]
+pub(super) struct DeviceStats {
+    rx_bytes: AtomicU64,
+    rx_packets: AtomicU64,
+    tx_bytes: AtomicU64,
+    tx_packets: AtomicU64,
+}
+
+impl DeviceStats {
+    pub(super) fn received(&self, len: usize) {
+        self.rx_bytes.fetch_add(len as u64, Ordering::Relaxed);
+        self.rx_packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn sent(&self, len: usize) {
+        self.tx_bytes.fetch_add(len as u64, Ordering::Relaxed);
+        self.tx_packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> InterfaceStats {
+        InterfaceStats {
+            rx_bytes: self.rx_bytes.load(Ordering::Relaxed),
+            rx_packets: self.rx_packets.load(Ordering::Relaxed),
+            tx_bytes: self.tx_bytes.load(Ordering::Relaxed),
+            tx_packets: self.tx_packets.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The network interfaces, in the order of their indices.
+///
+/// The loopback `lo` comes first. It has no device of its own, since
+/// smoltcp handles packets to a local address on the interface holding it,
+/// so its counters stay 0.
+pub fn interfaces() -> Vec<InterfaceInfo> {
+    let ipv4 = ETH0
+        .iface
+        .lock()
+        .ip_addrs()
+        .first()
+        .map(|cidr| match cidr.address() {
+            IpAddress::Ipv4(addr) => (Ipv4Addr::from(addr.0), cidr.prefix_len()),
+        });
+    vec![
+        InterfaceInfo {
+            name: "lo",
+            index: 1,
+            mac: [0; 6],
+            ipv4: Some((Ipv4Addr::LOCALHOST, 8)),
+            mtu: LOOPBACK_MTU,
+            loopback: true,
+            stats: InterfaceStats::default(),
+        },
+        InterfaceInfo {
+            name: ETH0.name,
+            index: 2,
+            mac: ETH0.ether_addr.0,
+            ipv4,
+            mtu: STANDARD_MTU,
+            loopback: false,
+            stats: ETH0.stats.snapshot(),
+        },
+    ]
+}
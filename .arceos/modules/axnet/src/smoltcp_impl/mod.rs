@@ -1,17 +1,18 @@
 mod addr;
 mod bench;
 mod dns;
+mod info;
 mod listen_table;
 mod tcp;
 mod udp;
 
-use alloc::vec;
+use alloc::{sync::Arc, vec, vec::Vec};
 use core::cell::RefCell;
 use core::ops::DerefMut;
 
 use axdriver::prelude::*;
 use axdriver_net::{DevError, NetBufPtr};
-use axhal::time::{NANOS_PER_MICROS, wall_time_nanos};
+use axhal::time::{NANOS_PER_MICROS, NANOS_PER_SEC, monotonic_time_nanos, wall_time_nanos};
 use axsync::Mutex;
 use lazyinit::LazyInit;
 use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
@@ -20,9 +21,11 @@ use smoltcp::socket::{self, AnySocket};
 use smoltcp::time::Instant;
 use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr};
 
+use self::info::DeviceStats;
 use self::listen_table::ListenTable;
 
 pub use self::dns::dns_query;
+pub use self::info::{InterfaceInfo, InterfaceStats, interfaces};
 pub use self::tcp::TcpSocket;
 pub use self::udp::UdpSocket;
 
@@ -50,26 +53,38 @@ const UDP_RX_BUF_LEN: usize = 64 * 1024;
 const UDP_TX_BUF_LEN: usize = 64 * 1024;
 const LISTEN_QUEUE_SIZE: usize = 512;
 
+/// How long a closed TCP socket may keep sending what was written to it,
+/// like `tcp_fin_timeout` of Linux.
+const ORPHAN_TIMEOUT_NANOS: u64 = 60 * NANOS_PER_SEC;
+
 static LISTEN_TABLE: LazyInit<ListenTable> = LazyInit::new();
 static SOCKET_SET: LazyInit<SocketSetWrapper> = LazyInit::new();
 static ETH0: LazyInit<InterfaceWrapper> = LazyInit::new();
 
-struct SocketSetWrapper<'a>(Mutex<SocketSet<'a>>);
+struct SocketSetWrapper<'a>(
+    Mutex<SocketSet<'a>>,
+    /// The TCP sockets closed while still sending, with the time they are
+    /// given up at, see [`SocketSetWrapper::remove_when_closed`].
+    Mutex<Vec<(SocketHandle, u64)>>,
+);
 
 struct DeviceWrapper {
     inner: RefCell<AxNetDevice>, // use `RefCell` is enough since it's wrapped in `Mutex` in `InterfaceWrapper`.
+    stats: Arc<DeviceStats>,
 }
 
 struct InterfaceWrapper {
     name: &'static str,
     ether_addr: EthernetAddress,
+    /// The counters of `dev`, readable without locking it.
+    stats: Arc<DeviceStats>,
     dev: Mutex<DeviceWrapper>,
     iface: Mutex<Interface>,
 }
 
 impl<'a> SocketSetWrapper<'a> {
     fn new() -> Self {
-        Self(Mutex::new(SocketSet::new(vec![])))
+        Self(Mutex::new(SocketSet::new(vec![])), Mutex::new(Vec::new()))
     }
 
     pub fn new_tcp_socket() -> socket::tcp::Socket<'a> {
@@ -121,12 +136,56 @@ impl<'a> SocketSetWrapper<'a> {
 
     pub fn poll_interfaces(&self) {
         ETH0.poll(&self.0);
+        self.reap_orphans();
     }
 
     pub fn remove(&self, handle: SocketHandle) {
         self.0.lock().remove(handle);
         debug!("socket {}: destroyed", handle);
     }
+
+    /// Remove the TCP socket `handle`, which its owner closed, once it is
+    /// closed.
+    ///
+    /// Until then it is kept, so that what was written to it is still sent
+    /// and its FIN acknowledged, as polling the interfaces goes on. It is
+    /// reset and removed if that takes longer than `ORPHAN_TIMEOUT_NANOS`.
+    pub fn remove_when_closed(&self, handle: SocketHandle) {
+        if self.with_socket::<socket::tcp::Socket, _, _>(handle, is_closed) {
+            self.remove(handle);
+        } else {
+            let deadline = monotonic_time_nanos() + ORPHAN_TIMEOUT_NANOS;
+            self.1.lock().push((handle, deadline));
+        }
+    }
+
+    /// Remove the closed sockets whose owner is gone, and reset those past
+    /// their deadline, which are removed on the next poll, once the reset
+    /// is sent.
+    fn reap_orphans(&self) {
+        let now = monotonic_time_nanos();
+        self.1.lock().retain(|&(handle, deadline)| {
+            let closed = self.with_socket_mut::<socket::tcp::Socket, _, _>(handle, |socket| {
+                let closed = is_closed(socket);
+                if !closed && now >= deadline {
+                    socket.abort();
+                }
+                closed
+            });
+            if closed {
+                self.remove(handle);
+            }
+            !closed
+        });
+    }
+}
+
+/// Whether `socket` is done, with nothing left to send or to acknowledge.
+fn is_closed(socket: &socket::tcp::Socket) -> bool {
+    matches!(
+        socket.state(),
+        socket::tcp::State::Closed | socket::tcp::State::TimeWait
+    )
 }
 
 impl InterfaceWrapper {
@@ -139,6 +198,7 @@ impl InterfaceWrapper {
         Self {
             name,
             ether_addr,
+            stats: dev.stats.clone(),
             dev: Mutex::new(dev),
             iface,
         }
@@ -183,6 +243,7 @@ impl DeviceWrapper {
     fn new(inner: AxNetDevice) -> Self {
         Self {
             inner: RefCell::new(inner),
+            stats: Arc::default(),
         }
     }
 }
@@ -216,7 +277,10 @@ impl Device for DeviceWrapper {
                 return None;
             }
         };
-        Some((AxNetRxToken(&self.inner, rx_buf), AxNetTxToken(&self.inner)))
+        Some((
+            AxNetRxToken(&self.inner, rx_buf, &self.stats),
+            AxNetTxToken(&self.inner, &self.stats),
+        ))
     }
 
     fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
@@ -226,7 +290,7 @@ impl Device for DeviceWrapper {
             return None;
         }
         if dev.can_transmit() {
-            Some(AxNetTxToken(&self.inner))
+            Some(AxNetTxToken(&self.inner, &self.stats))
         } else {
             None
         }
@@ -241,8 +305,8 @@ impl Device for DeviceWrapper {
     }
 }
 
-struct AxNetRxToken<'a>(&'a RefCell<AxNetDevice>, NetBufPtr);
-struct AxNetTxToken<'a>(&'a RefCell<AxNetDevice>);
+struct AxNetRxToken<'a>(&'a RefCell<AxNetDevice>, NetBufPtr, &'a DeviceStats);
+struct AxNetTxToken<'a>(&'a RefCell<AxNetDevice>, &'a DeviceStats);
 
 impl RxToken for AxNetRxToken<'_> {
     fn preprocess(&self, sockets: &mut SocketSet<'_>) {
@@ -259,6 +323,7 @@ impl RxToken for AxNetRxToken<'_> {
             rx_buf.packet_len(),
             rx_buf.packet()
         );
+        self.2.received(rx_buf.packet_len());
         let result = f(rx_buf.packet_mut());
         self.0.borrow_mut().recycle_rx_buffer(rx_buf).unwrap();
         result
@@ -275,6 +340,7 @@ impl TxToken for AxNetTxToken<'_> {
         let ret = f(tx_buf.packet_mut());
         trace!("SEND {} bytes: {:02X?}", len, tx_buf.packet());
         dev.transmit(tx_buf).unwrap();
+        self.1.sent(len);
         ret
     }
 }
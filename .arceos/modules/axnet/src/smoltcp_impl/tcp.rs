@@ -1,8 +1,10 @@
 use core::cell::UnsafeCell;
 use core::net::SocketAddr;
 use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use core::time::Duration;
 
 use axerrno::{AxError, AxResult, ax_err, ax_err_type};
+use axhal::time::monotonic_time;
 use axio::PollState;
 use axsync::Mutex;
 
@@ -41,6 +43,11 @@ pub struct TcpSocket {
     local_addr: UnsafeCell<IpEndpoint>,
     peer_addr: UnsafeCell<IpEndpoint>,
     nonblock: AtomicBool,
+    /// Whether Nagle's algorithm is off, like `TCP_NODELAY`.
+    nodelay: AtomicBool,
+    /// How dropping the socket treats unsent data, see
+    /// [`TcpSocket::set_linger`].
+    linger: Mutex<Option<Duration>>,
 }
 
 unsafe impl Sync for TcpSocket {}
@@ -54,6 +61,8 @@ impl TcpSocket {
             local_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT),
             peer_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT),
             nonblock: AtomicBool::new(false),
+            nodelay: AtomicBool::new(false),
+            linger: Mutex::new(None),
         }
     }
 
@@ -69,6 +78,8 @@ impl TcpSocket {
             local_addr: UnsafeCell::new(local_addr),
             peer_addr: UnsafeCell::new(peer_addr),
             nonblock: AtomicBool::new(false),
+            nodelay: AtomicBool::new(false),
+            linger: Mutex::new(None),
         }
     }
 
@@ -115,6 +126,43 @@ impl TcpSocket {
         self.nonblock.store(nonblocking, Ordering::Release);
     }
 
+    /// Returns whether small segments are sent at once, rather than held
+    /// back by Nagle's algorithm while earlier data is unacknowledged.
+    #[inline]
+    pub fn nodelay(&self) -> bool {
+        self.nodelay.load(Ordering::Acquire)
+    }
+
+    /// Turns Nagle's algorithm off if `nodelay`, or back on, like
+    /// `TCP_NODELAY`.
+    pub fn set_nodelay(&self, nodelay: bool) {
+        self.nodelay.store(nodelay, Ordering::Release);
+        // SAFETY: `self.handle` is only written by `connect`, which does not
+        // run at the same time.
+        if let Some(handle) = unsafe { self.handle.get().read() } {
+            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                socket.set_nagle_enabled(!nodelay)
+            });
+        }
+    }
+
+    /// Returns how long dropping the socket waits for unsent data, if it
+    /// does, like `SO_LINGER`.
+    pub fn linger(&self) -> Option<Duration> {
+        *self.linger.lock()
+    }
+
+    /// Sets how dropping a connected socket treats the data not sent yet.
+    ///
+    /// With `None`, the default, dropping returns at once, and the data is
+    /// still sent, followed by a FIN, as the interfaces are polled. With a
+    /// timeout, dropping waits up to it for the data and the FIN to be
+    /// acknowledged. With a zero timeout, the connection is reset at once,
+    /// and the data discarded.
+    pub fn set_linger(&self, linger: Option<Duration>) {
+        *self.linger.lock() = linger;
+    }
+
     /// Connects to the given address and port.
     ///
     /// The local port is generated automatically.
@@ -130,6 +178,7 @@ impl TcpSocket {
             let iface = &ETH0.iface;
             let (local_endpoint, remote_endpoint) = SOCKET_SET
                 .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+                    socket.set_nagle_enabled(!self.nodelay());
                     socket
                         .connect(iface.lock().context(), remote_endpoint, bound_endpoint)
                         .or_else(|e| match e {
@@ -232,7 +281,11 @@ impl TcpSocket {
         self.block_on(|| {
             let (handle, (local_addr, peer_addr)) = LISTEN_TABLE.accept(local_port)?;
             debug!("TCP socket accepted a new connection {}", peer_addr);
-            Ok(TcpSocket::new_connected(handle, local_addr, peer_addr))
+            // The options of the listener are inherited, like on Linux.
+            let socket = TcpSocket::new_connected(handle, local_addr, peer_addr);
+            socket.set_nodelay(self.nodelay());
+            socket.set_linger(self.linger());
+            Ok(socket)
         })
     }
 
@@ -269,6 +322,22 @@ impl TcpSocket {
         Ok(())
     }
 
+    /// Shut down writing: what was written is still sent, followed by a FIN,
+    /// while the socket keeps receiving until the peer shuts down too.
+    pub fn shutdown_write(&self) -> AxResult {
+        if !self.is_connected() {
+            return ax_err!(NotConnected, "socket shutdown() failed");
+        }
+        // SAFETY: `self.handle` should be initialized in a connected socket.
+        let handle = unsafe { self.handle.get().read().unwrap() };
+        SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
+            debug!("TCP socket {}: shutting down writing", handle);
+            socket.close();
+        });
+        SOCKET_SET.poll_interfaces();
+        Ok(())
+    }
+
     /// Receives data from the socket, stores it in the given buffer.
     pub fn recv(&self, buf: &mut [u8]) -> AxResult<usize> {
         if self.is_connecting() {
@@ -281,19 +350,22 @@ impl TcpSocket {
         let handle = unsafe { self.handle.get().read().unwrap() };
         self.block_on(|| {
             SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                if !socket.is_active() {
-                    // not open
-                    ax_err!(ConnectionRefused, "socket recv() failed")
-                } else if !socket.may_recv() {
-                    // connection closed
-                    Ok(0)
-                } else if socket.recv_queue() > 0 {
-                    // data available
+                if socket.recv_queue() > 0 {
+                    // data available, even once closed
                     // TODO: use socket.recv(|buf| {...})
                     let len = socket
                         .recv_slice(buf)
                         .map_err(|_| ax_err_type!(BadState, "socket recv() failed"))?;
                     Ok(len)
+                } else if socket.state() == State::TimeWait {
+                    // closed by both sides, after a `shutdown_write`
+                    Ok(0)
+                } else if !socket.is_active() {
+                    // reset
+                    ax_err!(ConnectionReset, "socket recv() failed")
+                } else if !socket.may_recv() {
+                    // closed by the peer
+                    Ok(0)
                 } else {
                     // no more data
                     Err(AxError::WouldBlock)
@@ -491,14 +563,44 @@ impl TcpSocket {
             }
         }
     }
+
+    /// Wait up to `timeout` for what was written to the socket `handle`, and
+    /// its FIN, to be acknowledged.
+    fn linger_on(&self, handle: SocketHandle, timeout: Duration) {
+        let deadline = monotonic_time() + timeout;
+        while monotonic_time() < deadline {
+            SOCKET_SET.poll_interfaces();
+            let flushed = SOCKET_SET.with_socket::<tcp::Socket, _, _>(handle, |socket| {
+                matches!(
+                    socket.state(),
+                    State::FinWait2 | State::TimeWait | State::Closed
+                )
+            });
+            if flushed {
+                return;
+            }
+            axtask::yield_now();
+        }
+    }
 }
 
 impl Drop for TcpSocket {
     fn drop(&mut self) {
-        self.shutdown().ok();
+        let linger = self.linger();
+        let connected = self.is_connected();
         // Safe because we have mut reference to `self`.
-        if let Some(handle) = unsafe { self.handle.get().read() } {
-            SOCKET_SET.remove(handle);
+        let handle = unsafe { self.handle.get().read() };
+        if let (Some(handle), true, Some(Duration::ZERO)) = (handle, connected, linger) {
+            // The reset is sent as `shutdown` polls the interfaces.
+            SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| socket.abort());
+        }
+        self.shutdown().ok();
+        if let Some(handle) = handle {
+            if let (true, Some(timeout)) = (connected, linger) {
+                self.linger_on(handle, timeout);
+            }
+            // Unsent data is still sent once the socket is gone.
+            SOCKET_SET.remove_when_closed(handle);
         }
     }
 }
@@ -3,5 +3,9 @@ use core::panic::PanicInfo;
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     error!("{}", info);
-    axhal::misc::terminate()
+    axhal::console::flush();
+    if let Some(hook) = crate::panic::take_panic_hook() {
+        hook(info);
+    }
+    axhal::misc::terminate_failure(crate::PANIC_FAILURE)
 }
@@ -25,8 +25,11 @@ extern crate axlog;
 #[cfg(all(target_os = "none", not(test)))]
 mod lang_items;
 
+pub use self::panic::{PANIC_FAILURE, PanicHook, set_panic_hook};
+
 #[cfg(feature = "smp")]
 mod mp;
+mod panic;
 
 #[cfg(feature = "smp")]
 pub use self::mp::rust_main_secondary;
@@ -51,7 +54,7 @@ struct LogIfImpl;
 #[crate_interface::impl_interface]
 impl axlog::LogIf for LogIfImpl {
     fn console_write_str(s: &str) {
-        axhal::console::write_bytes(s.as_bytes());
+        axhal::console::write_kernel(s.as_bytes());
     }
 
     fn current_time() -> core::time::Duration {
@@ -102,6 +105,7 @@ fn is_init_ok() -> bool {
 /// and the secondary CPUs call [`rust_main_secondary`].
 #[cfg_attr(not(test), unsafe(no_mangle))]
 pub extern "C" fn rust_main(cpu_id: usize, dtb: usize) -> ! {
+    init_console();
     ax_println!("{}", LOGO);
     ax_println!(
         "\
@@ -258,12 +262,25 @@ fn init_interrupt() {
         update_timer();
         #[cfg(feature = "multitask")]
         axtask::on_timer_tick();
+        axhal::console::flush_stale();
     });
 
     // Enable IRQs before starting app
     axhal::arch::enable_irqs();
 }
 
+/// Sets up the console as the build asks: `AX_CONSOLE_TAG=y` tags the lines
+/// of the kernel, and `AX_LOG_PORT=aux` writes them to the second serial
+/// port, where there is one.
+fn init_console() {
+    axhal::console::set_kernel_tag(option_env!("AX_CONSOLE_TAG") == Some("y"));
+    #[cfg(target_arch = "x86_64")]
+    if option_env!("AX_LOG_PORT") == Some("aux") {
+        axhal::console::aux::init();
+        axhal::console::set_kernel_port(axhal::console::aux::write_bytes);
+    }
+}
+
 #[cfg(all(feature = "tls", not(feature = "multitask")))]
 fn init_tls() {
     let main_tls = axhal::tls::TlsArea::alloc();
@@ -0,0 +1,31 @@
+//! What the panic handler does after reporting the panic.
+
+use core::{
+    panic::PanicInfo,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+/// The failure code the machine is powered off with after a panic, unless
+/// a hook does otherwise, see [`axhal::misc::terminate_failure`].
+pub const PANIC_FAILURE: u8 = 2;
+
+/// A function taking the machine down after a panic, in place of powering
+/// it off with [`PANIC_FAILURE`].
+pub type PanicHook = fn(&PanicInfo) -> !;
+
+static PANIC_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Call `hook` on a panic, once the panic is reported.
+///
+/// A panic in the hook itself powers the machine off as if there was none.
+pub fn set_panic_hook(hook: PanicHook) {
+    PANIC_HOOK.store(hook as *mut (), Ordering::Release);
+}
+
+/// Take the hook, so that it runs for the first panic only.
+#[cfg_attr(any(test, not(target_os = "none")), allow(dead_code))]
+pub(crate) fn take_panic_hook() -> Option<PanicHook> {
+    let hook = PANIC_HOOK.swap(core::ptr::null_mut(), Ordering::AcqRel);
+    // SAFETY: only `PanicHook`s are stored.
+    (!hook.is_null()).then(|| unsafe { core::mem::transmute::<*mut (), PanicHook>(hook) })
+}
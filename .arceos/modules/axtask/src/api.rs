@@ -9,6 +9,9 @@ use kernel_guard::NoPreemptIrqSave;
 
 pub(crate) use crate::run_queue::{current_run_queue, select_run_queue};
 
+#[doc(cfg(feature = "multitask"))]
+pub use crate::run_queue::{context_switches, idle_time_nanos};
+
 #[doc(cfg(feature = "multitask"))]
 pub use crate::task::{CurrentTask, TaskId, TaskInner};
 #[doc(cfg(feature = "multitask"))]
@@ -190,6 +193,16 @@ pub fn yield_now() {
     current_run_queue::<NoPreemptIrqSave>().yield_current()
 }
 
+/// Returns whether the current task should give up the CPU at the next
+/// chance, as it is pending to be preempted, or it has run for a while
+/// without switching out.
+///
+/// The latter matters with a cooperative scheduler, which never preempts a
+/// task, so that long loops can yield now and then to let others run.
+pub fn need_resched() -> bool {
+    current().should_resched()
+}
+
 /// Current task is going to sleep for the given duration.
 ///
 /// If the feature `irq` is not enabled, it uses busy-wait instead.
@@ -214,12 +227,53 @@ pub fn exit(exit_code: i32) -> ! {
 
 /// The idle task routine.
 ///
-/// It runs an infinite loop that keeps calling [`yield_now()`].
+/// It runs an infinite loop that keeps calling [`yield_now()`], and with
+/// the `irq` feature, waits for IRQs whenever no other task is ready.
 pub fn run_idle() -> ! {
     loop {
-        yield_now();
-        debug!("idle task: waiting for IRQs...");
         #[cfg(feature = "irq")]
-        axhal::arch::wait_for_irqs();
+        {
+            // With IRQs disabled from the moment the scheduler finds no
+            // task ready, none can be woken up before the wait.
+            let _guard = NoPreemptIrqSave::new();
+            yield_now();
+            debug!("idle task: waiting for IRQs...");
+            wait_idle();
+        }
+        #[cfg(not(feature = "irq"))]
+        yield_now();
     }
 }
+
+/// Waits for IRQs with nothing to run, which is called with IRQs disabled.
+///
+/// The timer is programmed for the earliest wakeup instead of the next
+/// periodic tick, so that an idle CPU is not woken up for nothing, and the
+/// periodic tick is programmed again afterwards, since the IRQ may have
+/// woken up a task. Time keeps counting meanwhile, as it is read from the
+/// hardware counter rather than counted in ticks.
+#[cfg(feature = "irq")]
+fn wait_idle() {
+    use axhal::time::{NANOS_PER_SEC, monotonic_time_nanos, set_oneshot_timer};
+
+    const PERIODIC_INTERVAL_NANOS: u64 = NANOS_PER_SEC / axconfig::TICKS_PER_SEC as u64;
+    // At most this long without an interrupt, as some timers cannot count
+    // much longer.
+    const MAX_IDLE_NANOS: u64 = NANOS_PER_SEC;
+
+    let start = monotonic_time_nanos();
+    // With SMP, another CPU may make a task ready on this one without an
+    // IRQ to tell, so only the periodic tick notices it.
+    if !cfg!(feature = "smp") {
+        let deadline = crate::timers::next_deadline()
+            .unwrap_or(u64::MAX)
+            .min(start + MAX_IDLE_NANOS);
+        set_oneshot_timer(deadline);
+    }
+    axhal::arch::enable_irqs_and_wait();
+    let end = monotonic_time_nanos();
+    crate::run_queue::add_idle_time(end - start);
+
+    axhal::arch::disable_irqs();
+    set_oneshot_timer(end + PERIODIC_INTERVAL_NANOS);
+}
@@ -10,7 +10,7 @@ use kspin::SpinRaw;
 use lazyinit::LazyInit;
 use scheduler::BaseScheduler;
 
-use axhal::cpu::this_cpu_id;
+use axhal::cpu::{PerCpuCounter, this_cpu_id};
 
 use crate::task::{CurrentTask, TaskState};
 use crate::wait_queue::WaitQueueGuard;
@@ -39,6 +39,31 @@ percpu_static! {
     PREV_TASK: Weak<crate::AxTask> = Weak::new(),
 }
 
+/// Context switches done on each CPU.
+static CONTEXT_SWITCHES: PerCpuCounter = PerCpuCounter::new();
+
+/// Returns the number of context switches done on all CPUs since boot.
+pub fn context_switches() -> u64 {
+    CONTEXT_SWITCHES.sum()
+}
+
+/// Time each CPU spent waiting for IRQs in the idle task, in nanoseconds.
+static IDLE_NANOS: PerCpuCounter = PerCpuCounter::new();
+
+/// Add `nanos` to the idle time of this CPU.
+#[cfg(feature = "irq")]
+pub(crate) fn add_idle_time(nanos: u64) {
+    IDLE_NANOS.add(nanos);
+}
+
+/// Returns the time all CPUs spent idle since boot, in nanoseconds.
+///
+/// Only the time waiting for IRQs counts, so it is always 0 without the
+/// `irq` feature, where the idle task keeps yielding instead.
+pub fn idle_time_nanos() -> u64 {
+    IDLE_NANOS.sum()
+}
+
 /// An array of references to run queues, one for each CPU, indexed by cpu_id.
 ///
 /// This static variable holds references to the run queues for each CPU in the system.
@@ -540,10 +565,18 @@ impl AxRunQueue {
             return;
         }
 
+        let now = axhal::time::monotonic_time_nanos();
+        prev_task.account_switch_out(now);
+        next_task.account_switch_in(now);
+        CONTEXT_SWITCHES.add(1);
+
         // Claim the task as running, we do this before switching to it
         // such that any running task will have this set.
         #[cfg(feature = "smp")]
-        next_task.set_on_cpu(true);
+        {
+            next_task.set_cpu_id(this_cpu_id());
+            next_task.set_on_cpu(true);
+        }
 
         unsafe {
             let prev_ctx_ptr = prev_task.ctx_mut_ptr();
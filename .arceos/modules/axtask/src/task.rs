@@ -1,9 +1,10 @@
 use alloc::{boxed::Box, string::String, sync::Arc};
 use core::ops::Deref;
+use core::time::Duration;
 use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU8, AtomicU64, Ordering};
 use core::{alloc::Layout, cell::UnsafeCell, fmt, ptr::NonNull};
 
-#[cfg(feature = "preempt")]
+#[cfg(any(feature = "preempt", feature = "smp"))]
 use core::sync::atomic::AtomicUsize;
 
 use kspin::SpinNoIrq;
@@ -16,6 +17,10 @@ use axhal::tls::TlsArea;
 use crate::task_ext::AxTaskExt;
 use crate::{AxCpuMask, AxTask, AxTaskRef, WaitQueue};
 
+/// How long a task may run without switching out before it should give up
+/// the CPU at the next [`crate::need_resched`] check, in nanoseconds.
+const RESCHED_SLICE_NANOS: u64 = 1_000_000;
+
 /// A unique identifier for a thread.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct TaskId(u64);
@@ -54,6 +59,9 @@ pub struct TaskInner {
     /// Used to indicate whether the task is running on a CPU.
     #[cfg(feature = "smp")]
     on_cpu: AtomicBool,
+    /// The CPU the task last ran on, set along with `on_cpu`.
+    #[cfg(feature = "smp")]
+    cpu_id: AtomicUsize,
 
     /// A ticket ID used to identify the timer event.
     /// Set by `set_timer_ticket()` when creating a timer event in `set_alarm_wakeup()`,
@@ -69,6 +77,15 @@ pub struct TaskInner {
     exit_code: AtomicI32,
     wait_for_exit: WaitQueue,
 
+    /// The time spent on a CPU before the current run, in nanoseconds.
+    cpu_time_ns: AtomicU64,
+    /// When the task was last switched to, in nanoseconds.
+    switched_in_ns: AtomicU64,
+    /// Times the task gave up the CPU by blocking or exiting.
+    nvcsw: AtomicU64,
+    /// Times the task was switched out while still ready to run.
+    nivcsw: AtomicU64,
+
     kstack: Option<TaskStack>,
     ctx: UnsafeCell<TaskContext>,
     task_ext: AxTaskExt,
@@ -160,6 +177,15 @@ impl TaskInner {
         Some(self.exit_code.load(Ordering::Acquire))
     }
 
+    /// Wait for the task to exit for at most `dur`, and return its exit
+    /// code, or `None` if it is still running then.
+    #[cfg(feature = "irq")]
+    pub fn join_timeout(&self, dur: Duration) -> Option<i32> {
+        self.wait_for_exit
+            .wait_timeout_until(dur, || self.state() == TaskState::Exited);
+        (self.state() == TaskState::Exited).then(|| self.exit_code.load(Ordering::Acquire))
+    }
+
     /// Returns the pointer to the user-defined task extended data.
     ///
     /// # Safety
@@ -230,6 +256,29 @@ impl TaskInner {
     pub fn exit_code(&self) -> i32 {
         self.exit_code.load(Ordering::Acquire)
     }
+
+    /// Returns the time the task has spent running on a CPU, including the
+    /// current run if it is running.
+    pub fn cpu_time(&self) -> Duration {
+        let mut ns = self.cpu_time_ns.load(Ordering::Acquire);
+        if self.is_running() {
+            let now = axhal::time::monotonic_time_nanos();
+            ns += now.saturating_sub(self.switched_in_ns.load(Ordering::Acquire));
+        }
+        Duration::from_nanos(ns)
+    }
+
+    /// Returns how many times the task has been switched out, as the numbers
+    /// of voluntary and involuntary context switches.
+    ///
+    /// A switch is voluntary if the task blocked or exited, and involuntary
+    /// if it was preempted or yielded while still ready to run.
+    pub fn context_switches(&self) -> (u64, u64) {
+        (
+            self.nvcsw.load(Ordering::Relaxed),
+            self.nivcsw.load(Ordering::Relaxed),
+        )
+    }
 }
 
 // private methods
@@ -249,12 +298,18 @@ impl TaskInner {
             timer_ticket_id: AtomicU64::new(0),
             #[cfg(feature = "smp")]
             on_cpu: AtomicBool::new(false),
+            #[cfg(feature = "smp")]
+            cpu_id: AtomicUsize::new(0),
             #[cfg(feature = "preempt")]
             need_resched: AtomicBool::new(false),
             #[cfg(feature = "preempt")]
             preempt_disable_count: AtomicUsize::new(0),
             exit_code: AtomicI32::new(0),
             wait_for_exit: WaitQueue::new(),
+            cpu_time_ns: AtomicU64::new(0),
+            switched_in_ns: AtomicU64::new(0),
+            nvcsw: AtomicU64::new(0),
+            nivcsw: AtomicU64::new(0),
             kstack: None,
             ctx: UnsafeCell::new(TaskContext::new()),
             task_ext: AxTaskExt::empty(),
@@ -275,7 +330,10 @@ impl TaskInner {
         let mut t = Self::new_common(TaskId::new(), name);
         t.is_init = true;
         #[cfg(feature = "smp")]
-        t.set_on_cpu(true);
+        {
+            t.set_cpu_id(axhal::cpu::this_cpu_id());
+            t.set_on_cpu(true);
+        }
         if t.name() == "idle" {
             t.is_idle = true;
         }
@@ -313,6 +371,33 @@ impl TaskInner {
             .is_ok()
     }
 
+    /// Record that the task is switched to at `now`, in nanoseconds.
+    pub(crate) fn account_switch_in(&self, now: u64) {
+        self.switched_in_ns.store(now, Ordering::Release);
+    }
+
+    /// Record that the task is switched out at `now`, in nanoseconds.
+    pub(crate) fn account_switch_out(&self, now: u64) {
+        let ran = now.saturating_sub(self.switched_in_ns.load(Ordering::Acquire));
+        self.cpu_time_ns.fetch_add(ran, Ordering::AcqRel);
+        if self.state() == TaskState::Ready {
+            self.nivcsw.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.nvcsw.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns whether the task, running, should give up the CPU. See
+    /// [`crate::need_resched`].
+    pub(crate) fn should_resched(&self) -> bool {
+        #[cfg(feature = "preempt")]
+        if self.need_resched.load(Ordering::Acquire) {
+            return true;
+        }
+        let now = axhal::time::monotonic_time_nanos();
+        now.saturating_sub(self.switched_in_ns.load(Ordering::Acquire)) >= RESCHED_SLICE_NANOS
+    }
+
     #[inline]
     pub(crate) fn is_running(&self) -> bool {
         matches!(self.state(), TaskState::Running)
@@ -439,6 +524,27 @@ impl TaskInner {
     pub(crate) fn set_on_cpu(&self, on_cpu: bool) {
         self.on_cpu.store(on_cpu, Ordering::Release)
     }
+
+    /// Records that the task is about to run on the CPU `cpu_id`.
+    #[cfg(feature = "smp")]
+    #[inline]
+    pub(crate) fn set_cpu_id(&self, cpu_id: usize) {
+        self.cpu_id.store(cpu_id, Ordering::Relaxed)
+    }
+
+    /// Returns the CPU the task is running on, or `None` if it is not
+    /// running, or there is only one CPU.
+    ///
+    /// The task may have moved on by the time the caller looks, so this is
+    /// only a hint, e.g. of which CPU to interrupt for the task to notice
+    /// something sooner.
+    pub fn running_cpu(&self) -> Option<usize> {
+        #[cfg(feature = "smp")]
+        if self.on_cpu() {
+            return Some(self.cpu_id.load(Ordering::Relaxed));
+        }
+        None
+    }
 }
 
 impl fmt::Debug for TaskInner {
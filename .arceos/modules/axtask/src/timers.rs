@@ -4,7 +4,7 @@ use kernel_guard::NoOp;
 use lazyinit::LazyInit;
 use timer_list::{TimeValue, TimerEvent, TimerList};
 
-use axhal::time::wall_time;
+use axhal::time::{epochoffset_nanos, wall_time};
 
 use crate::{AxTaskRef, select_run_queue};
 
@@ -59,6 +59,17 @@ pub fn check_events() {
     }
 }
 
+/// Returns the deadline of the earliest wakeup on this CPU, in monotonic
+/// nanoseconds, or `None` if no task is waiting for one.
+pub fn next_deadline() -> Option<u64> {
+    let deadline = unsafe {
+        // Safety: IRQs are disabled at this time.
+        TIMER_LIST.current_ref_raw()
+    }
+    .next_deadline()?;
+    Some((deadline.as_nanos() as u64).saturating_sub(epochoffset_nanos()))
+}
+
 pub fn init() {
     TIMER_LIST.with_current(|timer_list| {
         timer_list.init_once(TimerList::new());
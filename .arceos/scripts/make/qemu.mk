@@ -28,7 +28,8 @@ endif
 
 qemu_args-x86_64 := \
   -machine $(machine) \
-  -kernel $(OUT_ELF)
+  -kernel $(OUT_ELF) \
+  -device isa-debug-exit,iobase=0xf4,iosize=0x04
 
 qemu_args-riscv64 := \
   -machine $(machine) \
@@ -46,9 +47,11 @@ qemu_args-loongarch64 := \
 
 qemu_args-y := -m $(MEM) -smp $(SMP) $(qemu_args-$(ARCH))
 
+# The kernel discards the free clusters of the disk by writing zeros, which
+# `detect-zeroes=unmap` turns into holes in the image, so it stays sparse.
 qemu_args-$(BLK) += \
   -device virtio-blk-$(vdev-suffix),drive=disk0 \
-  -drive id=disk0,if=none,format=raw,file=$(DISK_IMG)
+  -drive id=disk0,if=none,format=raw,file=$(DISK_IMG),discard=unmap,detect-zeroes=unmap
 
 qemu_args-$(NET) += \
   -device virtio-net-$(vdev-suffix),netdev=net0
@@ -82,6 +85,22 @@ ifeq ($(GRAPHIC), n)
   qemu_args-y += -nographic
 endif
 
+# A second serial port on a TCP port, for a GDB stub in the kernel
+ifeq ($(GDBSTUB), y)
+  ifeq ($(GRAPHIC), n)
+    qemu_args-y += -serial mon:stdio
+  endif
+  qemu_args-y += -serial tcp::$(GDBSTUB_PORT),server,nowait
+endif
+
+# A second serial port written to a file, for the kernel log
+ifeq ($(LOG_PORT), aux)
+  ifeq ($(GRAPHIC), n)
+    qemu_args-y += -serial mon:stdio
+  endif
+  qemu_args-y += -serial file:$(LOG_FILE)
+endif
+
 ifeq ($(QEMU_LOG), y)
   qemu_args-y += -D qemu.log -d in_asm,int,mmu,pcall,cpu_reset,guest_errors
 endif
@@ -0,0 +1,399 @@
+//! The sizes of the user structs whose layout is ambiguous across
+//! architectures and libc versions.
+//!
+//! Every size is checked against `linux_raw_sys` at compile time, so the
+//! bindings cannot drift from what userspace is expected to pass. Syscalls
+//! taking the size of a struct as an argument compare it against these and
+//! fail with `EINVAL` instead of reading a struct of another size.
+//!
+//! Structs without a size argument, like `timespec`, are validated by shape
+//! instead, see [`TimeValueLike::try_to_time_value`].
+//!
+//! Every struct the kernel reads from or writes to userspace also has its
+//! size and the offset of each field checked at compile time, against the
+//! layout the ABI of the target documents, with [`check_layout!`] below. A
+//! new user struct gets its check here as well. With the `kernel-tests`
+//! feature, [`self_test`] additionally checks at boot that the code filling
+//! the structs puts each value in its field.
+//!
+//! [`TimeValueLike::try_to_time_value`]: crate::time::TimeValueLike::try_to_time_value
+
+use core::mem::offset_of;
+
+use axerrno::{LinuxError, LinuxResult};
+use axsignal::SignalSet;
+use linux_raw_sys::{
+    general::{
+        __kernel_timespec, __user_cap_data_struct, __user_cap_header_struct, iovec,
+        kernel_sigaction, linux_dirent64, pollfd, rlimit64, rusage, siginfo, stat, statx,
+        statx_timestamp, timespec, timeval,
+    },
+    net::{cmsghdr, msghdr, sockaddr_in, sockaddr_in6, ucred},
+    system::new_utsname,
+};
+
+use crate::file::UCred;
+
+/// The size of the kernel `sigset_t`, which is smaller than the one of libc.
+pub const SIGSET_SIZE: usize = 8;
+
+/// The size of the kernel `struct sigaction`, which only has `sa_restorer`
+/// on some architectures.
+pub const SIGACTION_SIZE: usize = if cfg!(any(target_arch = "x86_64", target_arch = "aarch64")) {
+    32
+} else {
+    24
+};
+
+/// The size of `struct stat`, which x86_64 lays out differently from the
+/// generic one.
+pub const STAT_SIZE: usize = if cfg!(target_arch = "x86_64") {
+    144
+} else {
+    128
+};
+
+/// The size of `struct statx`, which is the same everywhere.
+pub const STATX_SIZE: usize = 256;
+
+/// The size of `struct timespec`, which is `__kernel_timespec` on 64-bit
+/// targets. 32-bit ABIs with a 32-bit `time_t` pass 8 bytes instead.
+pub const TIMESPEC_SIZE: usize = 16;
+
+/// The size of `struct timeval`.
+pub const TIMEVAL_SIZE: usize = 16;
+
+/// The length of the syscall instruction: `syscall` on x86_64, `svc` on
+/// aarch64, `ecall` on riscv64 and `syscall` on loongarch64.
+///
+/// On every architecture, the PC in the trap frame of a syscall points past
+/// the syscall instruction by the time the syscall runs: the hardware does
+/// so on x86_64 and aarch64, and the trap handler does on riscv64 and
+/// loongarch64. A copy of the frame, like the one a child of `clone` starts
+/// from, resumes after the syscall as is, and a syscall is restarted by
+/// moving the PC back by this length.
+pub const SYSCALL_INSN_SIZE: usize = if cfg!(target_arch = "x86_64") { 2 } else { 4 };
+
+/// The size of the `struct linux_dirent64` header, before the name.
+pub const DIRENT64_HEADER_SIZE: usize = 19;
+
+const _: () = {
+    assert!(size_of::<SignalSet>() == SIGSET_SIZE);
+    assert!(size_of::<kernel_sigaction>() == SIGACTION_SIZE);
+    assert!(size_of::<stat>() == STAT_SIZE);
+    assert!(size_of::<statx>() == STATX_SIZE);
+    assert!(size_of::<timespec>() == TIMESPEC_SIZE);
+    assert!(size_of::<__kernel_timespec>() == TIMESPEC_SIZE);
+    assert!(size_of::<timeval>() == TIMEVAL_SIZE);
+};
+
+/// Check at compile time that `$ty` has `$size` bytes, and each of the
+/// fields listed the offset given.
+macro_rules! check_layout {
+    ($ty:ty, $size:expr, { $($field:ident: $offset:expr),* $(,)? }) => {
+        const _: () = {
+            assert!(size_of::<$ty>() == $size);
+            $(assert!(offset_of!($ty, $field) == $offset);)*
+        };
+    };
+}
+
+#[cfg(target_arch = "x86_64")]
+check_layout!(stat, STAT_SIZE, {
+    st_dev: 0, st_ino: 8, st_nlink: 16, st_mode: 24, st_uid: 28, st_gid: 32,
+    st_rdev: 40, st_size: 48, st_blksize: 56, st_blocks: 64,
+    st_atime: 72, st_atime_nsec: 80, st_mtime: 88, st_mtime_nsec: 96,
+    st_ctime: 104, st_ctime_nsec: 112,
+});
+#[cfg(not(target_arch = "x86_64"))]
+check_layout!(stat, STAT_SIZE, {
+    st_dev: 0, st_ino: 8, st_mode: 16, st_nlink: 20, st_uid: 24, st_gid: 28,
+    st_rdev: 32, st_size: 48, st_blksize: 56, st_blocks: 64,
+    st_atime: 72, st_atime_nsec: 80, st_mtime: 88, st_mtime_nsec: 96,
+    st_ctime: 104, st_ctime_nsec: 112,
+});
+check_layout!(statx, STATX_SIZE, {
+    stx_mask: 0, stx_blksize: 4, stx_attributes: 8, stx_nlink: 16, stx_uid: 20,
+    stx_gid: 24, stx_mode: 28, stx_ino: 32, stx_size: 40, stx_blocks: 48,
+    stx_attributes_mask: 56, stx_atime: 64, stx_btime: 80, stx_ctime: 96,
+    stx_mtime: 112, stx_rdev_major: 128, stx_rdev_minor: 132,
+    stx_dev_major: 136, stx_dev_minor: 140, stx_mnt_id: 144,
+});
+check_layout!(statx_timestamp, 16, { tv_sec: 0, tv_nsec: 8 });
+check_layout!(linux_dirent64, 24, {
+    d_ino: 0, d_off: 8, d_reclen: 16, d_type: 18, d_name: DIRENT64_HEADER_SIZE,
+});
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+check_layout!(kernel_sigaction, SIGACTION_SIZE, {
+    sa_handler_kernel: 0, sa_flags: 8, sa_restorer: 16, sa_mask: 24,
+});
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+check_layout!(kernel_sigaction, SIGACTION_SIZE, {
+    sa_handler_kernel: 0, sa_flags: 8, sa_mask: 16,
+});
+check_layout!(siginfo, 128, {});
+check_layout!(timespec, TIMESPEC_SIZE, { tv_sec: 0, tv_nsec: 8 });
+check_layout!(__kernel_timespec, TIMESPEC_SIZE, { tv_sec: 0, tv_nsec: 8 });
+check_layout!(timeval, TIMEVAL_SIZE, { tv_sec: 0, tv_usec: 8 });
+check_layout!(pollfd, 8, { fd: 0, events: 4, revents: 6 });
+check_layout!(iovec, 16, { iov_base: 0, iov_len: 8 });
+check_layout!(msghdr, 56, {
+    msg_name: 0, msg_namelen: 8, msg_iov: 16, msg_iovlen: 24,
+    msg_control: 32, msg_controllen: 40, msg_flags: 48,
+});
+check_layout!(cmsghdr, 16, { cmsg_len: 0, cmsg_level: 8, cmsg_type: 12 });
+check_layout!(sockaddr_in, 16, { sin_family: 0, sin_port: 2, sin_addr: 4 });
+check_layout!(sockaddr_in6, 28, {
+    sin6_family: 0, sin6_port: 2, sin6_flowinfo: 4, sin6_addr: 8, sin6_scope_id: 24,
+});
+check_layout!(ucred, 12, { pid: 0, uid: 4, gid: 8 });
+check_layout!(UCred, 12, { pid: 0, uid: 4, gid: 8 });
+check_layout!(rlimit64, 16, { rlim_cur: 0, rlim_max: 8 });
+check_layout!(rusage, 144, { ru_utime: 0, ru_stime: 16, ru_maxrss: 32 });
+check_layout!(new_utsname, 390, {
+    sysname: 0, nodename: 65, release: 130, version: 195, machine: 260, domainname: 325,
+});
+check_layout!(__user_cap_header_struct, 8, { version: 0, pid: 4 });
+check_layout!(__user_cap_data_struct, 12, { effective: 0, permitted: 4, inheritable: 8 });
+#[cfg(target_arch = "x86_64")]
+check_layout!(axhal::arch::FxsaveArea, 512, { mxcsr: 24, st: 32, xmm: 160 });
+#[cfg(target_arch = "aarch64")]
+check_layout!(crate::fpstate::FpsimdContext, 528, {
+    magic: 0, size: 4, fpsr: 8, fpcr: 12, vregs: 16,
+});
+#[cfg(target_arch = "riscv64")]
+check_layout!(axhal::arch::UserFpState, 264, { f: 0, fcsr: 256 });
+
+/// Check the `sigsetsize` argument of the `rt_sig*` syscalls.
+pub fn check_sigset_size(size: usize) -> LinuxResult {
+    if size != SIGSET_SIZE {
+        return Err(LinuxError::EINVAL);
+    }
+    Ok(())
+}
+
+/// Write a `struct linux_dirent64` of `name` at the start of `buf`, with
+/// `off` the offset of the next entry, and return its length, or `None` if
+/// it does not fit.
+///
+/// The entry is padded to the alignment of the struct, but written byte by
+/// byte, since the buffer of `getdents64` need not be aligned.
+pub fn write_dirent64(
+    buf: &mut [u8],
+    ino: u64,
+    off: i64,
+    d_type: u8,
+    name: &[u8],
+) -> Option<usize> {
+    let len =
+        (DIRENT64_HEADER_SIZE + name.len() + 1).next_multiple_of(align_of::<linux_dirent64>());
+    let entry = buf.get_mut(..len)?;
+    let reclen = u16::try_from(len).ok()?;
+    entry[offset_of!(linux_dirent64, d_ino)..][..8].copy_from_slice(&ino.to_ne_bytes());
+    entry[offset_of!(linux_dirent64, d_off)..][..8].copy_from_slice(&off.to_ne_bytes());
+    entry[offset_of!(linux_dirent64, d_reclen)..][..2].copy_from_slice(&reclen.to_ne_bytes());
+    entry[offset_of!(linux_dirent64, d_type)] = d_type;
+    let (name_buf, padding) = entry[DIRENT64_HEADER_SIZE..].split_at_mut(name.len());
+    name_buf.copy_from_slice(name);
+    // The terminating NUL, and the padding.
+    padding.fill(0);
+    Some(len)
+}
+
+/// Check that the code filling the user structs puts each value at the
+/// offset of its field, by filling them with canary values and reading the
+/// bytes back.
+///
+/// Panics on the first field out of place.
+#[cfg(feature = "kernel-tests")]
+pub fn self_test() {
+    use core::{
+        net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6},
+        time::Duration,
+    };
+
+    use linux_raw_sys::net::{AF_INET, AF_INET6};
+
+    use crate::{file::Kstat, sockaddr::SockAddr};
+
+    /// The `len` bytes at `off` of `bytes`, as an integer in native order.
+    fn read_int(bytes: &[u8], off: usize, len: usize) -> u64 {
+        let mut buf = [0; 8];
+        if cfg!(target_endian = "little") {
+            buf[..len].copy_from_slice(&bytes[off..][..len]);
+        } else {
+            buf[8 - len..].copy_from_slice(&bytes[off..][..len]);
+        }
+        u64::from_ne_bytes(buf)
+    }
+
+    fn bytes_of<T>(value: &T) -> &[u8] {
+        // SAFETY: the structs checked are plain old data, zeroed before being
+        // filled, so all their bytes are initialized.
+        unsafe { core::slice::from_raw_parts((value as *const T).cast(), size_of::<T>()) }
+    }
+
+    macro_rules! check_field {
+        ($value:expr, $ty:ty, $field:ident, $expected:expr) => {{
+            let value: &$ty = &$value;
+            let len = size_of_val(&{ value.$field });
+            assert_eq!(
+                read_int(bytes_of(value), offset_of!($ty, $field), len),
+                $expected as u64,
+                concat!("misplaced ", stringify!($ty), ".", stringify!($field)),
+            );
+        }};
+    }
+
+    let kstat = Kstat {
+        ino: 0x0102_0304_0506_0708,
+        nlink: 0x1112_1314,
+        uid: 0x2122_2324,
+        gid: 0x3132_3334,
+        mode: 0o100_644,
+        size: 0x4142_4344_4546_4748,
+        blocks: 0x5152_5354_5556_5758,
+        blksize: 0x6162_6364,
+        atime: Duration::new(0x7172_7374, 0x0a0b_0c0d),
+        mtime: Duration::new(0x7576_7778, 0x1a1b_1c1d),
+        ctime: Duration::new(0x797a_7b7c, 0x2a2b_2c2d),
+    };
+
+    let st = stat::from(kstat);
+    check_field!(st, stat, st_ino, kstat.ino);
+    check_field!(st, stat, st_nlink, kstat.nlink);
+    check_field!(st, stat, st_mode, kstat.mode);
+    check_field!(st, stat, st_uid, kstat.uid);
+    check_field!(st, stat, st_gid, kstat.gid);
+    check_field!(st, stat, st_size, kstat.size);
+    check_field!(st, stat, st_blksize, kstat.blksize);
+    check_field!(st, stat, st_blocks, kstat.blocks);
+    check_field!(st, stat, st_atime, kstat.atime.as_secs());
+    check_field!(st, stat, st_atime_nsec, kstat.atime.subsec_nanos());
+    check_field!(st, stat, st_mtime, kstat.mtime.as_secs());
+    check_field!(st, stat, st_mtime_nsec, kstat.mtime.subsec_nanos());
+    check_field!(st, stat, st_ctime, kstat.ctime.as_secs());
+    check_field!(st, stat, st_ctime_nsec, kstat.ctime.subsec_nanos());
+
+    let stx = statx::from(kstat);
+    check_field!(stx, statx, stx_ino, kstat.ino);
+    check_field!(stx, statx, stx_nlink, kstat.nlink);
+    check_field!(stx, statx, stx_mode, kstat.mode);
+    check_field!(stx, statx, stx_uid, kstat.uid);
+    check_field!(stx, statx, stx_gid, kstat.gid);
+    check_field!(stx, statx, stx_size, kstat.size);
+    check_field!(stx, statx, stx_blksize, kstat.blksize);
+    check_field!(stx, statx, stx_blocks, kstat.blocks);
+    // The mode only goes to `stx_mode`, never to the attributes.
+    check_field!(stx, statx, stx_attributes, 0);
+    for (ts, time) in [
+        (stx.stx_atime, kstat.atime),
+        (stx.stx_mtime, kstat.mtime),
+        (stx.stx_ctime, kstat.ctime),
+    ] {
+        check_field!(ts, statx_timestamp, tv_sec, time.as_secs());
+        check_field!(ts, statx_timestamp, tv_nsec, time.subsec_nanos());
+    }
+
+    // An entry of 19 + 7 bytes, padded to 32, in a buffer it does not fill.
+    let mut buf = [0xff; 40];
+    let name = b"canary";
+    let len = write_dirent64(&mut buf, 0x0102_0304_0506_0708, 0x1112_1314, 0x21, name);
+    assert_eq!(len, Some(32), "wrong linux_dirent64 length");
+    for (off, len, expected, field) in [
+        (
+            offset_of!(linux_dirent64, d_ino),
+            8,
+            0x0102_0304_0506_0708,
+            "d_ino",
+        ),
+        (offset_of!(linux_dirent64, d_off), 8, 0x1112_1314, "d_off"),
+        (offset_of!(linux_dirent64, d_reclen), 2, 32, "d_reclen"),
+        (offset_of!(linux_dirent64, d_type), 1, 0x21, "d_type"),
+    ] {
+        assert_eq!(
+            read_int(&buf, off, len),
+            expected,
+            "misplaced linux_dirent64.{field}"
+        );
+    }
+    assert_eq!(
+        &buf[DIRENT64_HEADER_SIZE..][..name.len()],
+        name,
+        "misplaced linux_dirent64.d_name"
+    );
+    assert!(
+        buf[DIRENT64_HEADER_SIZE + name.len()..32]
+            .iter()
+            .all(|&b| b == 0),
+        "linux_dirent64 not terminated or padded"
+    );
+    assert!(
+        buf[32..].iter().all(|&b| b == 0xff),
+        "linux_dirent64 written past its length"
+    );
+    assert_eq!(
+        write_dirent64(&mut buf[..31], 0, 0, 0, name),
+        None,
+        "linux_dirent64 overflow"
+    );
+
+    let addr = SockAddr::from(SocketAddrV4::new(Ipv4Addr::new(10, 1, 2, 3), 0x1234));
+    let bytes = addr.bytes();
+    assert_eq!(
+        bytes.len(),
+        size_of::<sockaddr_in>(),
+        "wrong sockaddr_in length"
+    );
+    assert_eq!(
+        read_int(bytes, offset_of!(sockaddr_in, sin_family), 2),
+        AF_INET as u64,
+        "misplaced sockaddr_in.sin_family"
+    );
+    assert_eq!(
+        bytes[offset_of!(sockaddr_in, sin_port)..][..2],
+        [0x12, 0x34],
+        "misplaced sockaddr_in.sin_port"
+    );
+    assert_eq!(
+        bytes[offset_of!(sockaddr_in, sin_addr)..][..4],
+        [10, 1, 2, 3],
+        "misplaced sockaddr_in.sin_addr"
+    );
+
+    let ip = Ipv6Addr::new(0xfe80, 1, 2, 3, 4, 5, 6, 7);
+    let addr = SockAddr::from(SocketAddrV6::new(ip, 0x1234, 0x0506_0708, 0x0a0b_0c0d));
+    let bytes = addr.bytes();
+    assert_eq!(
+        bytes.len(),
+        size_of::<sockaddr_in6>(),
+        "wrong sockaddr_in6 length"
+    );
+    assert_eq!(
+        read_int(bytes, offset_of!(sockaddr_in6, sin6_family), 2),
+        AF_INET6 as u64,
+        "misplaced sockaddr_in6.sin6_family"
+    );
+    assert_eq!(
+        bytes[offset_of!(sockaddr_in6, sin6_port)..][..2],
+        [0x12, 0x34],
+        "misplaced sockaddr_in6.sin6_port"
+    );
+    assert_eq!(
+        bytes[offset_of!(sockaddr_in6, sin6_flowinfo)..][..4],
+        [0x05, 0x06, 0x07, 0x08],
+        "misplaced sockaddr_in6.sin6_flowinfo"
+    );
+    assert_eq!(
+        bytes[offset_of!(sockaddr_in6, sin6_addr)..][..16],
+        ip.octets(),
+        "misplaced sockaddr_in6.sin6_addr"
+    );
+    assert_eq!(
+        read_int(bytes, offset_of!(sockaddr_in6, sin6_scope_id), 4),
+        0x0a0b_0c0d,
+        "misplaced sockaddr_in6.sin6_scope_id"
+    );
+
+    info!("syscall ABI self test passed");
+}
@@ -0,0 +1,148 @@
+//! Typed decoding of the arguments of a syscall.
+//!
+//! Every argument arrives as a whole register, whatever its C type. The
+//! dispatcher decodes each with the method of [`SyscallArgs`] for that type,
+//! so that the rules for widths and signs live here, rather than in casts
+//! spread over the handlers:
+//!
+//! - A 32-bit argument, like an `int` fd or `unsigned int` flags, is the low
+//!   half of its register, and the upper half is ignored, as Linux does. It
+//!   is undefined on x86_64 and aarch64, and a copy of the sign bit on
+//!   riscv64 and loongarch64, which sign-extend 32-bit values. Either way, a
+//!   negative fd stays negative, and flags get no stray bits.
+//! - The length of a user buffer is a whole register, and fails with
+//!   `EFAULT` if it is larger than the user address space, which no buffer
+//!   fits in.
+//! - Pointers, addresses, offsets and other 64-bit values are whole
+//!   registers.
+
+use core::ffi::{c_int, c_uint};
+
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::TrapFrame;
+use linux_raw_sys::general::__kernel_off_t;
+
+use crate::ptr::{UserConstPtr, UserPtr};
+
+/// The raw arguments of a syscall, copied out of its trap frame.
+#[derive(Debug, Clone, Copy)]
+pub struct SyscallArgs([usize; 6]);
+
+impl SyscallArgs {
+    /// The arguments of the syscall trapping with `tf`.
+    pub const fn new(tf: &TrapFrame) -> Self {
+        Self([
+            tf.arg0(),
+            tf.arg1(),
+            tf.arg2(),
+            tf.arg3(),
+            tf.arg4(),
+            tf.arg5(),
+        ])
+    }
+
+    /// The arguments in the registers `raw`.
+    pub const fn from_raw(raw: [usize; 6]) -> Self {
+        Self(raw)
+    }
+
+    /// The argument `n`, as a whole register.
+    pub const fn usize(&self, n: usize) -> usize {
+        self.0[n]
+    }
+
+    /// The argument `n`, as a whole signed register.
+    pub const fn isize(&self, n: usize) -> isize {
+        self.0[n] as isize
+    }
+
+    /// The argument `n`, as an `off_t`.
+    pub const fn off(&self, n: usize) -> __kernel_off_t {
+        self.0[n] as __kernel_off_t
+    }
+
+    /// The argument `n`, as an `int`.
+    pub const fn int(&self, n: usize) -> c_int {
+        self.0[n] as u32 as c_int
+    }
+
+    /// The argument `n`, as an `unsigned int`.
+    pub const fn uint(&self, n: usize) -> c_uint {
+        self.0[n] as u32
+    }
+
+    /// The argument `n`, as an fd, which is an `int`.
+    pub const fn fd(&self, n: usize) -> c_int {
+        self.int(n)
+    }
+
+    /// The argument `n`, as 32-bit flags.
+    pub const fn flags32(&self, n: usize) -> u32 {
+        self.uint(n)
+    }
+
+    /// The argument `n`, as the length of a user buffer.
+    pub fn len(&self, n: usize) -> LinuxResult<usize> {
+        if self.0[n] > axconfig::plat::USER_SPACE_SIZE {
+            return Err(LinuxError::EFAULT);
+        }
+        Ok(self.0[n])
+    }
+
+    /// The argument `n`, as a pointer to user memory.
+    pub fn uptr<T>(&self, n: usize) -> UserPtr<T> {
+        self.0[n].into()
+    }
+
+    /// The argument `n`, as a pointer to read-only user memory.
+    pub fn cuptr<T>(&self, n: usize) -> UserConstPtr<T> {
+        self.0[n].into()
+    }
+}
+
+/// Check the decoding of arguments at the edges of their widths and signs.
+///
+/// Panics on the first argument decoded wrong.
+#[cfg(feature = "kernel-tests")]
+pub fn self_test() {
+    let args = SyscallArgs::from_raw([
+        // -1, sign-extended as riscv64 and loongarch64 pass it.
+        usize::MAX,
+        // -1, zero-extended.
+        0xffff_ffff,
+        // 3, with garbage in the upper half.
+        0xdead_beef_0000_0003,
+        // `int` minimum and maximum, the latter with garbage.
+        0x8000_0000,
+        0x1234_5678_7fff_ffff,
+        axconfig::plat::USER_SPACE_SIZE,
+    ]);
+    assert_eq!(args.fd(0), -1, "sign-extended fd");
+    assert_eq!(args.fd(1), -1, "zero-extended fd");
+    assert_eq!(args.fd(2), 3, "fd with a garbage upper half");
+    assert_eq!(args.int(3), c_int::MIN, "int minimum");
+    assert_eq!(args.int(4), c_int::MAX, "int maximum with garbage");
+    assert_eq!(args.flags32(0), u32::MAX, "sign-extended flags");
+    assert_eq!(args.flags32(2), 3, "flags with a garbage upper half");
+    assert_eq!(args.uint(3), 0x8000_0000, "unsigned int with the sign bit");
+
+    assert_eq!(args.isize(0), -1, "negative isize");
+    assert_eq!(args.off(0), -1, "negative off_t");
+    assert_eq!(args.off(1), 0xffff_ffff, "off_t above 32 bits");
+    assert_eq!(args.usize(2), 0xdead_beef_0000_0003, "whole register");
+
+    assert_eq!(args.len(1), Ok(0xffff_ffff), "length within user space");
+    assert_eq!(
+        args.len(5),
+        Ok(axconfig::plat::USER_SPACE_SIZE),
+        "length of all user space"
+    );
+    assert_eq!(args.len(0), Err(LinuxError::EFAULT), "length of -1");
+    assert_eq!(
+        args.len(2),
+        Err(LinuxError::EFAULT),
+        "length above user space"
+    );
+
+    info!("syscall argument self test passed");
+}
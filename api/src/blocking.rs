@@ -0,0 +1,115 @@
+//! Blocking operations of syscalls, and what becomes of them when a signal
+//! interrupts them.
+//!
+//! A signal the thread does not block ends the wait of a blocking operation,
+//! which then fails with `EINTR`, so that the signal is delivered. Some
+//! operations are restarted instead once the signal is handled, if its
+//! handler was installed with `SA_RESTART`, as `signal(7)` lists them:
+//!
+//! | Operation                           | Restarted                       |
+//! |-------------------------------------|---------------------------------|
+//! | `accept`, `recv*`, `send*`          | unless the socket has a timeout |
+//! | `connect`                           | never                           |
+//! | `flock`, `F_SETLKW`, `F_OFD_SETLKW` | always                          |
+//! | `mq_send`, `mq_receive`, timed too  | always                          |
+//! | `FUTEX_LOCK_PI`, timed too          | always                          |
+//!
+//! Sockets have no timeouts yet, `SO_RCVTIMEO` and `SO_SNDTIMEO` being
+//! unsupported. `connect` is not restarted, as it would find the connection
+//! it started in progress, and fail with `EALREADY`. The deadline of a timed
+//! message queue operation or `FUTEX_LOCK_PI` is absolute, so it is the same
+//! when restarted.
+//!
+//! A stop signal, or one whose handler has `SA_RESTART`, interrupts the
+//! operation all the same: it fails with `EINTR`, and the thread is marked
+//! for the syscall to be restarted, see [`ThreadData::restart_syscall`].
+//! Rather than returning the error, the syscall entry then moves the PC back
+//! to the syscall instruction, see [`crate::abi::SYSCALL_INSN_SIZE`], which
+//! runs again once the signal is handled.
+//!
+//! [`ThreadData::restart_syscall`]: starry_core::task::ThreadData::restart_syscall
+
+use core::time::Duration;
+
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{TaskExtRef, current};
+use starry_core::task::{WaitMode, WaitQueueWrapper, WaitResult};
+
+use crate::signal::{has_pending_signal, signals_restart};
+
+/// A blocking operation, which tells whether it is restarted after a
+/// signal, see the module documentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blocking {
+    Accept,
+    Connect,
+    Recv,
+    Send,
+    Flock,
+    RecordLock,
+    MqSend,
+    MqReceive,
+    FutexLockPi,
+}
+
+impl Blocking {
+    /// Whether the operation is restarted after a handler with
+    /// `SA_RESTART`, if it waits with a timeout or not.
+    pub const fn restartable(self, timeout: bool) -> bool {
+        match self {
+            Self::Accept | Self::Recv | Self::Send => !timeout,
+            Self::Connect => false,
+            Self::Flock | Self::RecordLock | Self::MqSend | Self::MqReceive | Self::FutexLockPi => {
+                true
+            }
+        }
+    }
+
+    /// The error of the operation when a pending signal interrupts it,
+    /// `EINTR`, marking the syscall to be restarted if it may be.
+    pub fn interrupted(self, timeout: bool) -> LinuxError {
+        if self.restartable(timeout) && signals_restart() {
+            current().task_ext().thread_data().restart_syscall();
+        }
+        LinuxError::EINTR
+    }
+
+    /// Wait on `wq` until `cond` holds, for at most `timeout`.
+    ///
+    /// Returns whether `cond` holds, which it does not once timed out, and
+    /// fails with `EINTR` if a signal interrupts the wait.
+    pub fn wait_until(
+        self,
+        wq: &WaitQueueWrapper,
+        timeout: Option<Duration>,
+        cond: impl Fn() -> bool,
+    ) -> LinuxResult<bool> {
+        match wq.wait_until(WaitMode::Interruptible, timeout, cond) {
+            WaitResult::Woken => Ok(true),
+            WaitResult::TimedOut => Ok(false),
+            WaitResult::Interrupted => Err(self.interrupted(timeout.is_some())),
+        }
+    }
+
+    /// Try `attempt` again while it fails with `EAGAIN`, unless
+    /// `nonblocking`, giving up the CPU in between, for operations nothing
+    /// notifies the end of.
+    ///
+    /// Fails with `EINTR` if a signal is pending meanwhile.
+    pub fn retry<T>(
+        self,
+        nonblocking: bool,
+        mut attempt: impl FnMut() -> LinuxResult<T>,
+    ) -> LinuxResult<T> {
+        loop {
+            match attempt() {
+                Err(LinuxError::EAGAIN) if !nonblocking => {}
+                result => return result,
+            }
+            if has_pending_signal() {
+                return Err(self.interrupted(false));
+            }
+            axtask::yield_now();
+        }
+    }
+}
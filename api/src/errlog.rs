@@ -0,0 +1,112 @@
+//! How loudly a syscall failing is logged, by the error it fails with.
+//!
+//! Most errors are answers a program expects and handles, like `ENOENT`
+//! when it looks for a program along `PATH`, `EINTR` when a signal came, or
+//! `ECHILD` when it has waited for all its children. Logging them as loudly
+//! as the rest drowns the few which point at a bug of the kernel, like
+//! `EFAULT` when a pointer the kernel checked cannot be accessed after all.
+//!
+//! So each error has a [`Severity`], looked up in [`SEVERITIES`], which
+//! [`log_result`] logs it with. The errors not listed there are
+//! [`Severity::Unusual`].
+
+use axerrno::{LinuxError, LinuxResult};
+use syscalls::Sysno;
+
+use crate::args::SyscallArgs;
+
+/// How loudly an error is logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// An answer programs expect, logged at the debug level.
+    Expected,
+    /// Logged at the info level.
+    Unusual,
+    /// A hint of a bug of the kernel, logged at the warn level, with the
+    /// arguments of the syscall.
+    Suspicious,
+}
+
+/// The severity of each error which is not [`Severity::Unusual`].
+pub static SEVERITIES: [(LinuxError, Severity); 8] = [
+    (LinuxError::ENOENT, Severity::Expected),
+    (LinuxError::EAGAIN, Severity::Expected),
+    (LinuxError::EINTR, Severity::Expected),
+    (LinuxError::ECHILD, Severity::Expected),
+    (LinuxError::ETIMEDOUT, Severity::Expected),
+    (LinuxError::EEXIST, Severity::Expected),
+    // The pointers are checked before they are accessed.
+    (LinuxError::EFAULT, Severity::Suspicious),
+    // For a syscall which is implemented.
+    (LinuxError::ENOSYS, Severity::Suspicious),
+];
+
+/// The severity of `err`, returned by a syscall which is `implemented` or
+/// not.
+///
+/// `ENOSYS` from a syscall which is not implemented is
+/// [`Severity::Expected`], as the dispatcher warns about it already.
+pub fn severity(err: LinuxError, implemented: bool) -> Severity {
+    if err == LinuxError::ENOSYS && !implemented {
+        return Severity::Expected;
+    }
+    SEVERITIES
+        .iter()
+        .find(|(it, _)| *it == err)
+        .map_or(Severity::Unusual, |&(_, severity)| severity)
+}
+
+/// Log what the syscall `sysno`, called with `args`, returned: its result
+/// at the debug level, or its error at the level of its [`severity`].
+pub fn log_result(
+    sysno: Sysno,
+    args: &SyscallArgs,
+    result: &LinuxResult<isize>,
+    implemented: bool,
+) {
+    let err = match result {
+        Ok(ret) => {
+            debug!("Syscall {:?} return {}", sysno, ret);
+            return;
+        }
+        Err(err) => *err,
+    };
+    match severity(err, implemented) {
+        Severity::Expected => debug!("Syscall {:?} failed: {:?}", sysno, err),
+        Severity::Unusual => info!("Syscall {:?} failed: {:?}", sysno, err),
+        Severity::Suspicious => {
+            warn!("Syscall {:?} failed: {:?}, with {:x?}", sysno, err, args)
+        }
+    }
+}
+
+/// Check the severity of a sample of errors, so that editing the table
+/// keeps to the policy.
+///
+/// Panics on the first error of the wrong severity.
+#[cfg(feature = "kernel-tests")]
+pub fn self_test() {
+    use LinuxError::*;
+
+    for err in [ENOENT, EAGAIN, EINTR, ECHILD, ETIMEDOUT, EEXIST] {
+        assert_eq!(severity(err, true), Severity::Expected, "{:?}", err);
+    }
+    for err in [EINVAL, EPERM, EBADF, ENOMEM, EIO, ENOSPC] {
+        assert_eq!(severity(err, true), Severity::Unusual, "{:?}", err);
+    }
+    assert_eq!(severity(EFAULT, true), Severity::Suspicious);
+    assert_eq!(severity(ENOSYS, true), Severity::Suspicious);
+    assert_eq!(
+        severity(ENOSYS, false),
+        Severity::Expected,
+        "ENOSYS of a syscall not implemented"
+    );
+    for (i, (err, _)) in SEVERITIES.iter().enumerate() {
+        assert!(
+            SEVERITIES[..i].iter().all(|(it, _)| it != err),
+            "{:?} listed twice",
+            err
+        );
+    }
+    info!("errno severity self test passed");
+}
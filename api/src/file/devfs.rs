@@ -0,0 +1,306 @@
+//! The `/dev` tree.
+
+use core::{any::Any, ffi::c_void, ops::Range};
+
+use alloc::{sync::Arc, vec::Vec};
+use axerrno::{LinuxError, LinuxResult};
+use axfs::{BLOCK_SIZE, BlockDevice, block_devices, fops::FileType};
+use axio::{PollState, SeekFrom};
+use axsync::Mutex;
+
+use super::{
+    FileLike, Kstat,
+    virt::{StaticDir, StaticEntry, VirtualDir, VirtualDirEntry, VirtualNode},
+};
+use crate::ptr::UserPtr;
+
+/// Generate a character device which reads with `$read` and discards writes.
+macro_rules! char_device {
+    ($name:ident, |$buf:ident| $read:expr) => {
+        pub struct $name;
+
+        impl FileLike for $name {
+            fn read(&self, $buf: &mut [u8]) -> LinuxResult<usize> {
+                $read
+            }
+
+            fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+                Ok(buf.len())
+            }
+
+            fn stat(&self) -> LinuxResult<Kstat> {
+                Ok(Kstat {
+                    mode: ((FileType::CharDevice as u32) << 12) | 0o666, // rw-rw-rw-
+                    ..Default::default()
+                })
+            }
+
+            fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+                self
+            }
+
+            fn poll(&self) -> LinuxResult<PollState> {
+                Ok(PollState {
+                    readable: true,
+                    writable: true,
+                })
+            }
+
+            fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+                Ok(())
+            }
+        }
+    };
+}
+
+char_device!(DevNull, |_buf| Ok(0));
+char_device!(DevZero, |buf| {
+    buf.fill(0);
+    Ok(buf.len())
+});
+// Both never block, as the generator is seeded at boot.
+char_device!(DevRandom, |buf| {
+    starry_core::random::random_bytes(buf);
+    Ok(buf.len())
+});
+char_device!(DevUrandom, |buf| {
+    starry_core::random::random_bytes(buf);
+    Ok(buf.len())
+});
+
+/// The most bytes of a `/dev/kmsg` record copied onto the stack at once.
+const KMSG_CHUNK_SIZE: usize = 256;
+
+/// `/dev/kmsg`, to which each write adds a line to the kernel log, written
+/// to the console as kernel output. The log is not kept, so it cannot be
+/// read back.
+pub struct DevKmsg;
+
+impl FileLike for DevKmsg {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        // The console writes with IRQs disabled, which must not fault.
+        let mut chunk = [0; KMSG_CHUNK_SIZE];
+        for part in buf.chunks(KMSG_CHUNK_SIZE) {
+            chunk[..part.len()].copy_from_slice(part);
+            axhal::console::write_kernel(&chunk[..part.len()]);
+        }
+        if !buf.ends_with(b"\n") {
+            axhal::console::write_kernel(b"\n");
+        }
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: ((FileType::CharDevice as u32) << 12) | 0o644, // rw-r--r--
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: false,
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+static ROOT: [StaticEntry; 6] = [
+    ("null", FileType::CharDevice, || {
+        VirtualNode::File(Arc::new(DevNull))
+    }),
+    ("zero", FileType::CharDevice, || {
+        VirtualNode::File(Arc::new(DevZero))
+    }),
+    ("random", FileType::CharDevice, || {
+        VirtualNode::File(Arc::new(DevRandom))
+    }),
+    ("urandom", FileType::CharDevice, || {
+        VirtualNode::File(Arc::new(DevUrandom))
+    }),
+    ("kmsg", FileType::CharDevice, || {
+        VirtualNode::File(Arc::new(DevKmsg))
+    }),
+    ("mqueue", FileType::Dir, || {
+        VirtualNode::Dir(Arc::new(super::mqueue::MqueueDir))
+    }),
+];
+
+/// `BLKGETSIZE64` from `linux/fs.h`, `_IOR(0x12, 114, size_t)`.
+const BLKGETSIZE64: usize = 0x8008_1272;
+
+/// A block device, like `/dev/vda`, read and written directly, bypassing
+/// any filesystem on it.
+///
+/// Each open has its own position. Accesses need not be aligned to blocks,
+/// but partial blocks cost a read-modify-write.
+pub struct BlockFile {
+    dev: Arc<BlockDevice>,
+    pos: Mutex<u64>,
+}
+
+impl BlockFile {
+    fn new(dev: Arc<BlockDevice>) -> Self {
+        Self {
+            dev,
+            pos: Mutex::new(0),
+        }
+    }
+
+    /// The name of the device under `/dev`.
+    pub fn name(&self) -> &str {
+        self.dev.name()
+    }
+
+    /// Move the position as `lseek` does, returning the new one.
+    pub fn seek(&self, pos: SeekFrom) -> LinuxResult<u64> {
+        let mut cur = self.pos.lock();
+        let new = match pos {
+            SeekFrom::Start(off) => Some(off),
+            SeekFrom::Current(off) => cur.checked_add_signed(off),
+            SeekFrom::End(off) => self.dev.size().checked_add_signed(off),
+        };
+        *cur = new.ok_or(LinuxError::EINVAL)?;
+        Ok(*cur)
+    }
+
+    /// Write back the blocks written to the device, as `fsync` does.
+    pub fn sync(&self) -> LinuxResult {
+        self.dev.sync().map_err(|_| LinuxError::EIO)
+    }
+
+    /// Handle the `ioctl` request `op`.
+    pub fn ioctl(&self, op: usize, arg: UserPtr<c_void>) -> LinuxResult<isize> {
+        match op {
+            BLKGETSIZE64 => {
+                let size = UserPtr::<u64>::from(arg.address().as_usize());
+                *size.get_as_mut()? = self.dev.size();
+                Ok(0)
+            }
+            _ => Err(LinuxError::ENOTTY),
+        }
+    }
+
+    /// Transfer `len` bytes block by block, from the current position up to
+    /// the end of the device. `copy(block, offset, range)` moves the bytes
+    /// `range` of the user buffer from or to `block` at `offset`, and the
+    /// block is written back if `write` is set. Returns the number of bytes
+    /// transferred.
+    fn transfer(
+        &self,
+        len: usize,
+        write: bool,
+        mut copy: impl FnMut(&mut [u8; BLOCK_SIZE], usize, Range<usize>),
+    ) -> LinuxResult<usize> {
+        let mut pos = self.pos.lock();
+        let len = len.min(self.dev.size().saturating_sub(*pos) as usize);
+        let mut block = [0u8; BLOCK_SIZE];
+        let mut done = 0;
+        while done < len {
+            let block_id = *pos / BLOCK_SIZE as u64;
+            let offset = *pos as usize % BLOCK_SIZE;
+            let n = (BLOCK_SIZE - offset).min(len - done);
+            // A whole block to write needs not be read first.
+            if !write || n < BLOCK_SIZE {
+                self.dev
+                    .read_block(block_id, &mut block)
+                    .map_err(|_| LinuxError::EIO)?;
+            }
+            copy(&mut block, offset, done..done + n);
+            if write {
+                self.dev
+                    .write_block(block_id, &block)
+                    .map_err(|_| LinuxError::EIO)?;
+            }
+            done += n;
+            *pos += n as u64;
+        }
+        Ok(done)
+    }
+}
+
+impl FileLike for BlockFile {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.transfer(buf.len(), false, |block, offset, range| {
+            let n = range.len();
+            buf[range].copy_from_slice(&block[offset..offset + n]);
+        })
+    }
+
+    /// Writing to a device a filesystem is mounted from fails with `EBUSY`,
+    /// since it would corrupt the filesystem.
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        if self.dev.is_mounted() {
+            return Err(LinuxError::EBUSY);
+        }
+        self.transfer(buf.len(), true, |block, offset, range| {
+            let n = range.len();
+            block[offset..offset + n].copy_from_slice(&buf[range]);
+        })
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: ((FileType::BlockDevice as u32) << 12) | 0o660, // rw-rw----
+            size: self.dev.size(),
+            blksize: BLOCK_SIZE as _,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+/// The root of `/dev`: the fixed devices and the block devices.
+pub(super) struct DevRoot;
+
+impl VirtualDir for DevRoot {
+    fn list_entries(&self) -> LinuxResult<Vec<VirtualDirEntry>> {
+        let mut entries = StaticDir(&ROOT).list_entries()?;
+        entries.extend(
+            block_devices()
+                .iter()
+                .map(|dev| VirtualDirEntry::new(dev.name(), FileType::BlockDevice)),
+        );
+        Ok(entries)
+    }
+
+    fn lookup(&self, name: &str) -> LinuxResult<VirtualNode> {
+        if let Some(dev) = block_devices().iter().find(|dev| dev.name() == name) {
+            return Ok(VirtualNode::File(Arc::new(BlockFile::new(dev.clone()))));
+        }
+        StaticDir(&ROOT).lookup(name)
+    }
+}
+
+pub(super) fn root() -> DevRoot {
+    DevRoot
+}
@@ -0,0 +1,195 @@
+//! The flags of the syscalls creating descriptors, and adding the files they
+//! create to the file descriptor table.
+//!
+//! `SOCK_CLOEXEC`, `IN_CLOEXEC`, `SFD_CLOEXEC` and the other `*_CLOEXEC`
+//! flags have the value of `O_CLOEXEC`, and likewise for `O_NONBLOCK`, so
+//! all such syscalls take them apart with [`FdFlags::parse`], and add the
+//! file with [`install_fd`]. `open` takes its other flags apart with
+//! [`OpenFlags`].
+
+use core::ffi::c_int;
+
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axfs::fops::OpenOptions;
+use bitflags::bitflags;
+use linux_raw_sys::general::{
+    O_ACCMODE, O_APPEND, O_CLOEXEC, O_CREAT, O_DIRECTORY, O_EXCL, O_NONBLOCK, O_PATH, O_RDONLY,
+    O_RDWR, O_TMPFILE, O_TRUNC, O_WRONLY, R_OK, W_OK,
+};
+use starry_core::sandbox::PathAccess;
+
+use super::{FD_TABLE, FileLike, nofile_limit};
+
+bitflags! {
+    /// The flags of a syscall creating a descriptor which apply to the new
+    /// descriptor, or to the file if it is a new one.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct FdFlags: u32 {
+        /// Close the descriptor on `execve`.
+        const CLOEXEC = O_CLOEXEC;
+        /// Make the file non-blocking.
+        const NONBLOCK = O_NONBLOCK;
+    }
+}
+
+impl FdFlags {
+    /// Split the flags off `flags`, and return them with the other bits,
+    /// which must be in `other` or it fails with `EINVAL`.
+    pub fn parse(flags: u32, other: u32) -> LinuxResult<(Self, u32)> {
+        let this = Self::from_bits_truncate(flags);
+        let rest = flags & !Self::all().bits();
+        if rest & !other != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok((this, rest))
+    }
+}
+
+bitflags! {
+    /// The flags of `open`, as far as they are looked at. The others are
+    /// kept, and ignored like in Linux.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct OpenFlags: u32 {
+        const WRONLY = O_WRONLY;
+        const RDWR = O_RDWR;
+        const CREAT = O_CREAT;
+        const EXCL = O_EXCL;
+        const TRUNC = O_TRUNC;
+        const APPEND = O_APPEND;
+        const NONBLOCK = O_NONBLOCK;
+        const DIRECTORY = O_DIRECTORY;
+        const CLOEXEC = O_CLOEXEC;
+        const PATH = O_PATH;
+        /// Includes [`OpenFlags::DIRECTORY`].
+        const TMPFILE = O_TMPFILE;
+    }
+}
+
+impl OpenFlags {
+    /// The flags `flags` as given to `open`.
+    pub fn from_raw(flags: c_int) -> Self {
+        Self::from_bits_retain(flags as u32)
+    }
+
+    /// The access mode, `O_RDONLY`, `O_WRONLY` or `O_RDWR`.
+    pub fn access_mode(self) -> u32 {
+        self.bits() & O_ACCMODE
+    }
+
+    /// The flags which apply to the new descriptor.
+    pub fn fd_flags(self) -> FdFlags {
+        FdFlags::from_bits_truncate(self.bits())
+    }
+
+    /// Whether opening needs write access, which a directory refuses with
+    /// `EISDIR`.
+    pub fn writes(self) -> bool {
+        self.access_mode() != O_RDONLY || self.intersects(Self::CREAT | Self::TRUNC)
+    }
+
+    /// The access to a file opening it needs, as a mask of `R_OK` and
+    /// `W_OK`.
+    pub fn access(self) -> u32 {
+        if self.contains(Self::PATH) {
+            return 0;
+        }
+        let access = match self.access_mode() {
+            O_RDONLY => R_OK,
+            O_WRONLY => W_OK,
+            _ => R_OK | W_OK,
+        };
+        if self.contains(Self::TRUNC) {
+            access | W_OK
+        } else {
+            access
+        }
+    }
+
+    /// The access to the path the sandbox must allow for opening it, that
+    /// of [`OpenFlags::access`], but a lookup for `O_PATH`, and making a
+    /// file in the directory for `O_TMPFILE`.
+    ///
+    /// Making the file for `O_CREAT` is checked apart, as it depends on
+    /// whether it exists.
+    pub fn path_access(self) -> PathAccess {
+        if self.contains(Self::TMPFILE) {
+            return PathAccess::CREATE;
+        }
+        let access = self.access();
+        let mut path_access = PathAccess::empty();
+        path_access.set(PathAccess::READ, access & R_OK != 0 || access == 0);
+        path_access.set(PathAccess::WRITE, access & W_OK != 0);
+        path_access
+    }
+
+    /// The [`OpenOptions`] to open with.
+    pub fn options(self) -> OpenOptions {
+        let mut options = OpenOptions::new();
+        match self.access_mode() {
+            O_RDONLY => options.read(true),
+            O_WRONLY => options.write(true),
+            _ => {
+                options.read(true);
+                options.write(true);
+            }
+        };
+        if self.contains(Self::APPEND) {
+            options.append(true);
+        }
+        if self.contains(Self::TRUNC) {
+            options.truncate(true);
+        }
+        if self.contains(Self::CREAT) {
+            options.create(true);
+            if self.contains(Self::EXCL) {
+                options.create_new(true);
+            }
+        }
+        // `O_EXEC` has the value of `O_PATH`.
+        if self.contains(Self::PATH) {
+            options.execute(true);
+        }
+        if self.contains(Self::DIRECTORY) {
+            options.directory(true);
+        }
+        options
+    }
+}
+
+/// Fail to allocate a descriptor with `EMFILE`, as if the table were full,
+/// if a fault is injected.
+fn fd_alloc_fault() -> LinuxResult {
+    #[cfg(feature = "fault-inject")]
+    if starry_core::fault::should_fail(starry_core::fault::FaultPoint::FdAlloc) {
+        return Err(LinuxError::EMFILE);
+    }
+    Ok(())
+}
+
+/// Add `f` to the file descriptor table at the lowest free descriptor,
+/// non-blocking if `flags` has [`FdFlags::NONBLOCK`], and closed on
+/// `execve` if it has [`FdFlags::CLOEXEC`].
+///
+/// This is where every descriptor but those of `dup2` and `dup3` is
+/// allocated, below the `RLIMIT_NOFILE` of the process, else it fails with
+/// `EMFILE`. It is also where `f` starts being counted as a live file, see
+/// [`LiveFile`](super::LiveFile). The close-on-exec flag is set under the
+/// same lock of the table as the descriptor is added, so it never lands on
+/// a descriptor another thread closed and reused meanwhile.
+pub fn install_fd(f: Arc<dyn FileLike>, flags: FdFlags) -> LinuxResult<c_int> {
+    if flags.contains(FdFlags::NONBLOCK) {
+        f.set_nonblocking(true)?;
+    }
+    fd_alloc_fault()?;
+    // Counted down at once if the table is full, as `f` is dropped then.
+    if let Some(live) = f.live() {
+        live.count();
+    }
+    let limit = nofile_limit();
+    FD_TABLE.with_mut(|table| {
+        let fd = table.add(f, limit).map_err(|_| LinuxError::EMFILE)?;
+        table.set_cloexec(fd, flags.contains(FdFlags::CLOEXEC));
+        Ok(fd as c_int)
+    })
+}
@@ -1,60 +1,348 @@
 use core::{any::Any, ffi::c_int};
 
-use alloc::{string::String, sync::Arc};
-use axerrno::{LinuxError, LinuxResult};
+use alloc::{collections::btree_set::BTreeSet, format, string::String, sync::Arc};
+use axerrno::{AxError, LinuxError, LinuxResult};
 use axfs::fops::DirEntry;
-use axio::PollState;
+use axio::{PollState, SeekFrom};
 use axsync::{Mutex, MutexGuard};
-use linux_raw_sys::general::S_IFDIR;
+use linux_raw_sys::general::{
+    IN_CREATE, IN_MODIFY, O_ACCMODE, O_APPEND, O_DIRECTORY, O_RDONLY, O_WRONLY, S_IFDIR,
+};
+use memory_addr::PAGE_SIZE_4K;
+use spin::Once;
+use starry_core::mm::PAGE_SIZE;
 
-use super::{FileLike, Kstat, get_file_like};
+use super::{
+    FileKind, FileLike, IoCounters, Kstat, LiveFile, alloc_anon_ino, get_mode, init_times, inode,
+    lock, move_inode, move_mode, move_xattrs, notify, remove_inode, remove_mode, remove_xattrs,
+    timestamps, update_mtime,
+};
+use crate::{
+    imp::{MountRef, mount_options, mount_ref, space_left},
+    path::{DirHandle, HARDLINK_MANAGER, invalidate_path_cache},
+};
+
+/// Get the metadata of the file or directory at `path`, following links.
+pub fn stat_at_path(path: &str) -> LinuxResult<Kstat> {
+    if let Some(target) = super::resolve_virtual_link(path) {
+        return stat_at_path(&target);
+    }
+    if let Some(stat) = super::stat_virtual(path, true) {
+        return stat;
+    }
+    let opts = axfs::fops::OpenOptions::new().set_read(true);
+    match axfs::fops::File::open(path, &opts) {
+        Ok(file) => File::new(file, path.into(), O_RDONLY).stat(),
+        Err(AxError::IsADirectory) => {
+            let dir = axfs::fops::Directory::open_dir(path, &opts)?;
+            Directory::new(dir, path.into()).stat()
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Get the metadata of the file or directory at `path`, without following
+/// a link in the final component.
+pub fn lstat_at_path(path: &str) -> LinuxResult<Kstat> {
+    match super::stat_virtual(path, false) {
+        Some(stat) => stat,
+        None => stat_at_path(path),
+    }
+}
+
+/// The paths of the unlinked files made with `O_TMPFILE`, which
+/// `getdents64` skips.
+static TMPFILES: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Whether `path` is an unlinked file made with `O_TMPFILE`.
+pub fn is_unlinked_tmpfile(path: &str) -> bool {
+    TMPFILES.lock().contains(path)
+}
+
+/// The state of a file made with `O_TMPFILE`.
+///
+/// Such a file is created under a hidden name in its directory, since the
+/// filesystems below cannot have files without a name. It is removed when
+/// the last descriptor is closed unless linked meanwhile.
+struct TmpFile {
+    /// The hidden path.
+    path: String,
+    /// The path the file was linked at, if linked.
+    linked: Once<String>,
+    /// Whether the file can be linked, which `O_EXCL` prevents.
+    linkable: bool,
+}
+
+impl Drop for TmpFile {
+    fn drop(&mut self) {
+        if self.linked.get().is_none() {
+            TMPFILES.lock().remove(&self.path);
+            let _ = axfs::api::remove_file(&self.path);
+            remove_inode(&self.path);
+            remove_mode(&self.path);
+            remove_xattrs(&self.path);
+        }
+    }
+}
 
 /// File wrapper for `axfs::fops::File`.
 pub struct File {
     inner: Mutex<axfs::fops::File>,
     path: String,
+    /// The access mode and `O_APPEND` of the open flags.
+    flags: u32,
+    // Dropped after `inner`, so the file is closed before it is removed.
+    tmpfile: Option<TmpFile>,
+    counters: IoCounters,
+    live: LiveFile,
+    // Dropped last, so the filesystem is released after the file is closed.
+    _mount: Option<MountRef>,
 }
 
 impl File {
-    pub fn new(inner: axfs::fops::File, path: String) -> Self {
+    /// Wrap `inner` opened at `path` with the open flags `flags`, of which
+    /// the access mode and `O_APPEND` are kept.
+    pub fn new(inner: axfs::fops::File, path: String, flags: u32) -> Self {
         Self {
             inner: Mutex::new(inner),
+            _mount: mount_ref(&path),
             path,
+            flags: flags & (O_ACCMODE | O_APPEND),
+            tmpfile: None,
+            counters: IoCounters::default(),
+            live: LiveFile::new(FileKind::File),
         }
     }
 
+    /// Create an unnamed file in the directory `dir`, as `O_TMPFILE` does.
+    ///
+    /// The file can be linked with [`File::link_tmpfile`] if `linkable`.
+    pub fn new_tmpfile(
+        dir: &str,
+        opts: &axfs::fops::OpenOptions,
+        flags: u32,
+        linkable: bool,
+    ) -> LinuxResult<Self> {
+        let path = format!(
+            "{}/.tmpfile.{}",
+            dir.trim_end_matches('/'),
+            alloc_anon_ino()
+        );
+        let inner = axfs::fops::File::open(&path, opts)?;
+        TMPFILES.lock().insert(path.clone());
+        let mut file = Self::new(inner, path.clone(), flags);
+        file.tmpfile = Some(TmpFile {
+            path,
+            linked: Once::new(),
+            linkable,
+        });
+        Ok(file)
+    }
+
+    /// Whether the file was made with `O_TMPFILE` and has not been linked.
+    pub fn is_unlinked(&self) -> bool {
+        self.tmpfile
+            .as_ref()
+            .is_some_and(|tmp| tmp.linked.get().is_none())
+    }
+
+    /// Give the unnamed file the name `path`, as `linkat` with
+    /// `AT_EMPTY_PATH` does.
+    ///
+    /// Fails with `ENOENT` if the file was opened with `O_EXCL` or is
+    /// already linked, and with `EEXIST` if `path` exists.
+    pub fn link_tmpfile(&self, path: &str) -> LinuxResult {
+        let tmp = self
+            .tmpfile
+            .as_ref()
+            .filter(|tmp| tmp.linkable && tmp.linked.get().is_none())
+            .ok_or(LinuxError::ENOENT)?;
+        if axfs::api::metadata(path).is_ok() {
+            return Err(LinuxError::EEXIST);
+        }
+        let mut tmpfiles = TMPFILES.lock();
+        axfs::api::rename(&tmp.path, path)?;
+        invalidate_path_cache();
+        tmpfiles.remove(&tmp.path);
+        move_inode(&tmp.path, path);
+        move_mode(&tmp.path, path);
+        move_xattrs(&tmp.path, path);
+        tmp.linked.call_once(|| path.into());
+        drop(tmpfiles);
+        init_times(path);
+        notify(path, IN_CREATE);
+        Ok(())
+    }
+
     /// Get the path of the file.
     pub fn path(&self) -> &str {
-        &self.path
+        match self.tmpfile.as_ref().and_then(|tmp| tmp.linked.get()) {
+            Some(linked) => linked,
+            None => &self.path,
+        }
     }
 
     /// Get the inner node of the file.
     pub fn inner(&self) -> MutexGuard<axfs::fops::File> {
         self.inner.lock()
     }
+
+    /// Fail with `EBADF` unless the file was opened for reading.
+    fn check_readable(&self) -> LinuxResult {
+        if self.flags & O_ACCMODE == O_WRONLY {
+            return Err(LinuxError::EBADF);
+        }
+        Ok(())
+    }
+
+    /// Fail with `EBADF` unless the file was opened for writing.
+    fn check_writable(&self) -> LinuxResult {
+        if self.flags & O_ACCMODE == O_RDONLY {
+            return Err(LinuxError::EBADF);
+        }
+        Ok(())
+    }
+
+    /// Read at `offset`, without moving the file position.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.check_readable()?;
+        self.inner().read_at(offset, buf).map_err(access_error)
+    }
+
+    /// Write at `offset`, without moving the file position.
+    pub fn write_at(&self, offset: u64, buf: &[u8]) -> LinuxResult<usize> {
+        self.check_writable()?;
+        let inner = self.inner();
+        let buf = self.fit(&inner, offset, buf)?;
+        let n = inner.write_at(offset, buf).map_err(access_error)?;
+        drop(inner);
+        self.written(n);
+        Ok(n)
+    }
+
+    /// Cut `buf`, to be written at `offset` of `inner`, to the space left
+    /// on a filesystem with a size limit, failing with `ENOSPC` if there is
+    /// none.
+    fn fit<'a>(
+        &self,
+        inner: &axfs::fops::File,
+        offset: u64,
+        buf: &'a [u8],
+    ) -> LinuxResult<&'a [u8]> {
+        let end = offset.saturating_add(buf.len() as u64);
+        let size = inner.get_attr()?.size().next_multiple_of(PAGE_SIZE as u64);
+        if end <= size {
+            return Ok(buf);
+        }
+        let Some(left) = space_left(self.path()) else {
+            return Ok(buf);
+        };
+        let limit = size + left / PAGE_SIZE as u64 * PAGE_SIZE as u64;
+        if limit <= offset {
+            return Err(LinuxError::ENOSPC);
+        }
+        Ok(&buf[..(end.min(limit) - offset) as usize])
+    }
+
+    /// Allocate the bytes in `[offset, offset + len)`, writing zeros past
+    /// the end of the file, as `fallocate` without flags does.
+    ///
+    /// If they do not fit, the file is cut back to its size before, and
+    /// this fails with `ENOSPC`.
+    pub fn allocate(&self, offset: u64, len: u64) -> LinuxResult {
+        static ZEROS: [u8; PAGE_SIZE_4K] = [0; PAGE_SIZE_4K];
+        self.check_writable()?;
+        let end = offset.checked_add(len).ok_or(LinuxError::EFBIG)?;
+        let inner = self.inner();
+        let size = inner.get_attr()?.size();
+        let mut pos = size;
+        while pos < end {
+            let chunk = &ZEROS[..(end - pos).min(ZEROS.len() as u64) as usize];
+            let written = self
+                .fit(&inner, pos, chunk)
+                .and_then(|chunk| inner.write_at(pos, chunk).map_err(access_error));
+            match written {
+                Ok(n) if n > 0 => pos += n as u64,
+                result => {
+                    inner.truncate(size)?;
+                    return Err(result.err().unwrap_or(LinuxError::ENOSPC));
+                }
+            }
+        }
+        drop(inner);
+        self.written((pos - size) as usize);
+        Ok(())
+    }
+
+    /// Record a write of `written` bytes.
+    fn written(&self, written: usize) {
+        if written > 0 {
+            update_mtime(self.path());
+            notify(self.path(), IN_MODIFY);
+        }
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        lock::file_dropped(self);
+    }
+}
+
+/// `axfs` fails a read or write the file was not opened for with
+/// `PermissionDenied`, where Linux reports `EBADF`.
+fn access_error(err: AxError) -> LinuxError {
+    match err {
+        AxError::PermissionDenied => LinuxError::EBADF,
+        err => err.into(),
+    }
 }
 
 impl FileLike for File {
     fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
-        Ok(self.inner().read(buf)?)
+        self.check_readable()?;
+        self.inner().read(buf).map_err(access_error)
     }
 
     fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
-        Ok(self.inner().write(buf)?)
+        self.check_writable()?;
+        let mut inner = self.inner();
+        let offset = if self.flags & O_APPEND != 0 {
+            inner.get_attr()?.size()
+        } else {
+            inner.seek(SeekFrom::Current(0))?
+        };
+        let buf = self.fit(&inner, offset, buf)?;
+        let n = inner.write(buf).map_err(access_error)?;
+        drop(inner);
+        self.written(n);
+        Ok(n)
     }
 
     fn stat(&self) -> LinuxResult<Kstat> {
         let metadata = self.inner().get_attr()?;
         let ty = metadata.file_type() as u8;
-        let perm = metadata.perm().bits() as u32;
+        let perm = get_mode(self.path()).unwrap_or_else(|| {
+            match mount_options(self.path()).and_then(|it| it.fmask) {
+                Some(mask) => 0o777 & !mask,
+                None => metadata.perm().bits() as u32,
+            }
+        });
 
         Ok(Kstat {
+            ino: inode(self.path()),
             mode: ((ty as u32) << 12) | perm,
+            nlink: if self.is_unlinked() {
+                0
+            } else {
+                HARDLINK_MANAGER.link_count(self.path()) as _
+            },
             size: metadata.size(),
             blocks: metadata.blocks(),
             blksize: 512,
             ..Default::default()
-        })
+        }
+        .with_times(timestamps(self.path())))
     }
 
     fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
@@ -71,6 +359,18 @@ impl FileLike for File {
     fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
         Ok(())
     }
+
+    fn live(&self) -> Option<&LiveFile> {
+        Some(&self.live)
+    }
+
+    fn status_flags(&self) -> u32 {
+        self.flags
+    }
+
+    fn io_counters(&self) -> Option<&IoCounters> {
+        Some(&self.counters)
+    }
 }
 
 /// Directory wrapper for `axfs::fops::Directory`.
@@ -78,36 +378,107 @@ pub struct Directory {
     inner: Mutex<axfs::fops::Directory>,
     path: String,
     last_dirent: Mutex<Option<DirEntry>>,
+    /// The number of entries read by `getdents64`, which is the offset of
+    /// the directory.
+    pos: Mutex<usize>,
+    /// Taken when opened, to tell whether the directory was removed since.
+    handle: DirHandle,
+    live: LiveFile,
+    // Dropped last, so the filesystem is released after the directory is
+    // closed.
+    _mount: Option<MountRef>,
 }
 
 impl Directory {
     pub fn new(inner: axfs::fops::Directory, path: String) -> Self {
         Self {
             inner: Mutex::new(inner),
+            handle: DirHandle::new(&path),
+            _mount: mount_ref(&path),
             path,
             last_dirent: Mutex::new(None),
+            pos: Mutex::new(0),
+            live: LiveFile::new(FileKind::Directory),
         }
     }
 
+    /// Whether the directory has been removed since it was opened.
+    pub fn is_removed(&self) -> bool {
+        self.handle.is_removed()
+    }
+
     /// Get the path of the directory.
     pub fn path(&self) -> &str {
         &self.path
     }
 
     /// Get the inner node of the directory.
+    ///
+    /// The directory may be removed while the node is held, so reading it
+    /// goes through [`Directory::read_entry`].
     pub fn inner(&self) -> MutexGuard<axfs::fops::Directory> {
         self.inner.lock()
     }
 
+    /// Read the next entry from `inner`, the node of the directory, or
+    /// `None` at the end.
+    ///
+    /// Like Linux, a directory removed since it was opened has no entries
+    /// left, even if removed in the middle of the read and the filesystem
+    /// fails to read it.
+    pub fn read_entry(&self, inner: &mut axfs::fops::Directory) -> LinuxResult<Option<DirEntry>> {
+        if self.is_removed() {
+            return Ok(None);
+        }
+        let mut dirents = [DirEntry::default()];
+        match inner.read_dir(&mut dirents) {
+            Ok(0) => Ok(None),
+            Ok(_) if self.is_removed() => Ok(None),
+            Ok(_) => {
+                let [ent] = dirents;
+                Ok(Some(ent))
+            }
+            Err(_) if self.is_removed() => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
     /// Get the last directory entry.
     pub fn last_dirent(&self) -> MutexGuard<Option<DirEntry>> {
         self.last_dirent.lock()
     }
+
+    /// Get the number of entries read.
+    pub fn pos(&self) -> &Mutex<usize> {
+        &self.pos
+    }
+
+    /// Move to the entry at index `pos`, forgetting the saved entry.
+    ///
+    /// The directory is reopened to rewind it, since it can only be read
+    /// forward.
+    pub fn seek(&self, pos: usize) -> LinuxResult {
+        let mut last_dirent = self.last_dirent.lock();
+        let mut inner = self.inner.lock();
+        // Reopening a removed directory would open whatever is at its path
+        // now, while there is nothing left to read anyway.
+        if !self.is_removed() {
+            let opts = axfs::fops::OpenOptions::new().set_read(true);
+            *inner = axfs::fops::Directory::open_dir(&self.path, &opts)?;
+            let mut skipped = 0;
+            while skipped < pos && self.read_entry(&mut inner)?.is_some() {
+                skipped += 1;
+            }
+        }
+        *last_dirent = None;
+        *self.pos.lock() = pos;
+        Ok(())
+    }
 }
 
 impl FileLike for Directory {
     fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
-        Err(LinuxError::EBADF)
+        Err(LinuxError::EISDIR)
     }
 
     fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
@@ -115,10 +486,18 @@ impl FileLike for Directory {
     }
 
     fn stat(&self) -> LinuxResult<Kstat> {
+        let perm = get_mode(&self.path).unwrap_or_else(|| {
+            match mount_options(&self.path).and_then(|it| it.dmask) {
+                Some(mask) => 0o777 & !mask,
+                None => 0o755, // rwxr-xr-x
+            }
+        });
         Ok(Kstat {
-            mode: S_IFDIR | 0o755u32, // rwxr-xr-x
+            ino: inode(&self.path),
+            mode: S_IFDIR | perm,
             ..Default::default()
-        })
+        }
+        .with_times(timestamps(&self.path)))
     }
 
     fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
@@ -136,10 +515,15 @@ impl FileLike for Directory {
         Ok(())
     }
 
+    fn live(&self) -> Option<&LiveFile> {
+        Some(&self.live)
+    }
+
+    fn status_flags(&self) -> u32 {
+        O_RDONLY | O_DIRECTORY
+    }
+
     fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
-        get_file_like(fd)?
-            .into_any()
-            .downcast::<Self>()
-            .map_err(|_| LinuxError::ENOTDIR)
+        Self::from_fd_or(fd, LinuxError::ENOTDIR)
     }
 }
@@ -0,0 +1,176 @@
+//! File inode numbers.
+//!
+//! The filesystems below have no stable inode numbers, so the number of a
+//! file is derived from its real path, a hash with the top bit set, apart
+//! from the numbers of anonymous files, which count up from 1. Hard links
+//! resolve to the real path of their file, so they share its number.
+//!
+//! A file keeps its number when moved, so only those are kept here, by the
+//! path they moved to, along with the numbers they took with them. The
+//! files under a moved directory move with it, and are kept here too. A file
+//! made where one of those was gets a fresh number from the counter of
+//! anonymous files, rather than the hash of its path. What is kept goes
+//! when its file is removed, or its filesystem unmounted, so there are
+//! never more entries than moved files.
+//!
+//! Two real paths of one run could still hash to the same number, with a
+//! chance negligible next to the number of files there can be.
+
+use alloc::{
+    collections::{btree_map::BTreeMap, btree_set::BTreeSet},
+    format,
+    string::String,
+    vec::Vec,
+};
+use spin::RwLock;
+
+use super::{alloc_anon_ino, times::key};
+
+/// The numbers kept by path, and the hashed numbers moved files took.
+struct Inodes {
+    by_path: BTreeMap<String, u64>,
+    taken: BTreeSet<u64>,
+}
+
+static INODES: RwLock<Inodes> = RwLock::new(Inodes {
+    by_path: BTreeMap::new(),
+    taken: BTreeSet::new(),
+});
+
+const HASHED: u64 = 1 << 63;
+
+/// The number derived from the real path `path`, FNV-1a of its bytes.
+fn hashed(path: &str) -> u64 {
+    let hash = path.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    hash | HASHED
+}
+
+impl Inodes {
+    /// Forget the number of the file at `path`, gone from there.
+    fn forget(&mut self, path: &str) {
+        if let Some(ino) = self.by_path.remove(path) {
+            self.taken.remove(&ino);
+        }
+    }
+
+    /// Keep `ino`, the number of the file moved from `from`, at `to`.
+    fn moved(&mut self, from: &str, to: &str, ino: u64) {
+        if ino == hashed(to) {
+            // Back where it was made.
+            self.taken.remove(&ino);
+            return;
+        }
+        if ino == hashed(from) {
+            self.taken.insert(ino);
+        }
+        self.by_path.insert(to.into(), ino);
+    }
+}
+
+/// Add the paths of the files under the directory `dir`, at any depth,
+/// relative to it, to `files`.
+fn files_under(dir: &str, rest: &str, files: &mut Vec<String>) {
+    let Ok(entries) = axfs::api::read_dir(&format!("{}{}", dir, rest)) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        if matches!(name.as_str(), "." | "..") {
+            continue;
+        }
+        let path = format!("{}/{}", rest, name);
+        if entry.file_type().is_dir() {
+            files_under(dir, &path, files);
+        }
+        files.push(path);
+    }
+}
+
+/// Get the inode number of the file at the real path `path`.
+pub fn inode(path: &str) -> u64 {
+    let path = key(path);
+    let ino = hashed(path);
+    let inodes = INODES.read();
+    if let Some(&ino) = inodes.by_path.get(path) {
+        return ino;
+    }
+    if !inodes.taken.contains(&ino) {
+        return ino;
+    }
+    drop(inodes);
+    *INODES
+        .write()
+        .by_path
+        .entry(path.into())
+        .or_insert_with(alloc_anon_ino)
+}
+
+/// Keep the inode number of the file moved from `from` to `to`.
+pub fn move_inode(from: &str, to: &str) {
+    let ino = inode(from);
+    let (from, to) = (key(from), key(to));
+    let mut inodes = INODES.write();
+    inodes.by_path.remove(from);
+    inodes.forget(to);
+    inodes.moved(from, to, ino);
+}
+
+/// Keep the inode numbers of the files under the directory moved from
+/// `from` to `to`, once the filesystem has moved it.
+///
+/// Their paths all change, so every one of them is kept here from then on.
+pub fn move_inodes_under(from: &str, to: &str) {
+    let (from, to) = (key(from), key(to));
+    let mut files = Vec::new();
+    files_under(to, "", &mut files);
+    let mut inodes = INODES.write();
+    let mut kept = BTreeMap::new();
+    let Inodes { by_path, taken } = &mut *inodes;
+    by_path.retain(|path, ino| {
+        let rest = path.strip_prefix(from).filter(|rest| rest.starts_with('/'));
+        if let Some(rest) = rest {
+            kept.insert(String::from(rest), *ino);
+            return false;
+        }
+        if path
+            .strip_prefix(to)
+            .is_some_and(|rest| rest.starts_with('/'))
+        {
+            taken.remove(ino);
+            return false;
+        }
+        true
+    });
+    for rest in files {
+        let (old, new) = (format!("{}{}", from, rest), format!("{}{}", to, rest));
+        let ino = kept.remove(&rest).unwrap_or_else(|| hashed(&old));
+        inodes.moved(&old, &new, ino);
+    }
+    for ino in kept.values() {
+        inodes.taken.remove(ino);
+    }
+}
+
+/// Forget the inode number of the removed file at `path`.
+pub fn remove_inode(path: &str) {
+    INODES.write().forget(key(path));
+}
+
+/// Forget the inode numbers of the files under the directory `dir`, whose
+/// filesystem is unmounted.
+pub fn forget_inodes_under(dir: &str) {
+    let dir = key(dir);
+    let mut inodes = INODES.write();
+    let Inodes { by_path, taken } = &mut *inodes;
+    by_path.retain(|path, ino| {
+        let kept = path
+            .strip_prefix(dir)
+            .is_none_or(|rest| !rest.is_empty() && !rest.starts_with('/'));
+        if !kept {
+            taken.remove(ino);
+        }
+        kept
+    });
+}
@@ -0,0 +1,299 @@
+//! A minimal `inotify`, see `inotify(7)`.
+//!
+//! Only `IN_CREATE`, `IN_DELETE` and `IN_MODIFY` are generated, by the
+//! syscalls changing the filesystem calling [`notify`]. Watches match the
+//! canonical path of the changed entry or of its parent directory, so they
+//! are neither recursive nor aware of mounts, and renames are not reported.
+
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::{
+    collections::{btree_map::BTreeMap, vec_deque::VecDeque},
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use axsync::Mutex;
+use axtask::WaitQueue;
+use linux_raw_sys::general::{
+    IN_ALL_EVENTS, IN_IGNORED, IN_MASK_ADD, IN_MASK_CREATE, IN_Q_OVERFLOW, O_NONBLOCK, O_RDONLY,
+    inotify_event,
+};
+
+use super::{FileKind, FileLike, Kstat, LiveFile, alloc_anon_ino};
+
+/// The largest number of queued events, like the default of
+/// `/proc/sys/fs/inotify/max_queued_events`.
+const MAX_QUEUED_EVENTS: usize = 16384;
+
+struct Event {
+    wd: i32,
+    mask: u32,
+    name: String,
+}
+
+impl Event {
+    /// The size of the `inotify_event` record, with the name padded to a
+    /// multiple of the header size.
+    fn size(&self) -> usize {
+        size_of::<inotify_event>() + self.name_len()
+    }
+
+    fn name_len(&self) -> usize {
+        if self.name.is_empty() {
+            0
+        } else {
+            (self.name.len() + 1).next_multiple_of(size_of::<inotify_event>())
+        }
+    }
+
+    fn write_to(&self, buf: &mut [u8]) {
+        let header = [
+            self.wd as u32,
+            self.mask,
+            0, // cookie
+            self.name_len() as u32,
+        ];
+        for (chunk, value) in buf.chunks_exact_mut(4).zip(header) {
+            chunk.copy_from_slice(&value.to_ne_bytes());
+        }
+        let name = &mut buf[size_of::<inotify_event>()..self.size()];
+        name.fill(0);
+        name[..self.name.len()].copy_from_slice(self.name.as_bytes());
+    }
+}
+
+#[derive(Default)]
+struct InotifyInner {
+    /// The watched path and the events of interest of each watch.
+    watches: BTreeMap<i32, (String, u32)>,
+    next_wd: i32,
+    events: VecDeque<Event>,
+}
+
+impl InotifyInner {
+    fn push(&mut self, event: Event) {
+        // Identical events in a row are merged, which also keeps a stream of
+        // writes from flooding the queue.
+        if let Some(last) = self.events.back() {
+            if last.wd == event.wd && last.mask == event.mask && last.name == event.name {
+                return;
+            }
+        }
+        if self.events.len() + 1 >= MAX_QUEUED_EVENTS {
+            // The last slot is kept for the overflow event.
+            if self.events.len() < MAX_QUEUED_EVENTS {
+                self.events.push_back(Event {
+                    wd: -1,
+                    mask: IN_Q_OVERFLOW,
+                    name: String::new(),
+                });
+            }
+            return;
+        }
+        self.events.push_back(event);
+    }
+}
+
+/// An `inotify` instance.
+pub struct Inotify {
+    inner: Mutex<InotifyInner>,
+    wq: WaitQueue,
+    nonblocking: AtomicBool,
+    ino: u64,
+    live: LiveFile,
+}
+
+/// The instances with at least one watch.
+static INSTANCES: Mutex<Vec<Weak<Inotify>>> = Mutex::new(Vec::new());
+
+impl Inotify {
+    pub fn new(nonblocking: bool) -> Self {
+        Self {
+            inner: Mutex::new(InotifyInner {
+                next_wd: 1,
+                ..Default::default()
+            }),
+            wq: WaitQueue::new(),
+            nonblocking: AtomicBool::new(nonblocking),
+            ino: alloc_anon_ino(),
+            live: LiveFile::new(FileKind::Inotify),
+        }
+    }
+
+    /// Watch `path` for the events in `mask`, or change the events of the
+    /// existing watch of `path` as `IN_MASK_ADD` and `IN_MASK_CREATE` say.
+    ///
+    /// Returns the watch descriptor.
+    pub fn add_watch(self: &Arc<Self>, path: &str, mask: u32) -> LinuxResult<i32> {
+        let path = strip_slash(path);
+        let events = mask & IN_ALL_EVENTS;
+        let mut inner = self.inner.lock();
+        if let Some((wd, (_, old))) = inner.watches.iter_mut().find(|(_, (p, _))| p == path) {
+            if mask & IN_MASK_CREATE != 0 {
+                return Err(LinuxError::EEXIST);
+            }
+            *old = if mask & IN_MASK_ADD != 0 {
+                *old | events
+            } else {
+                events
+            };
+            return Ok(*wd);
+        }
+        let wd = inner.next_wd;
+        inner.next_wd += 1;
+        inner.watches.insert(wd, (path.into(), events));
+        if inner.watches.len() == 1 {
+            let mut instances = INSTANCES.lock();
+            instances.retain(|it| it.strong_count() > 0);
+            instances.push(Arc::downgrade(self));
+        }
+        Ok(wd)
+    }
+
+    /// Remove the watch `wd`, queueing `IN_IGNORED` for it.
+    pub fn rm_watch(&self, wd: i32) -> LinuxResult {
+        let mut inner = self.inner.lock();
+        inner.watches.remove(&wd).ok_or(LinuxError::EINVAL)?;
+        inner.push(Event {
+            wd,
+            mask: IN_IGNORED,
+            name: String::new(),
+        });
+        drop(inner);
+        self.wq.notify_all(false);
+        Ok(())
+    }
+
+    /// Queue `mask` for the watches of `path`, naming the entry `name` of
+    /// it if not empty.
+    fn queue(&self, path: &str, name: &str, mask: u32) {
+        let mut inner = self.inner.lock();
+        let matched = inner
+            .watches
+            .iter()
+            .filter(|(_, (p, m))| p == path && m & mask != 0)
+            .map(|(wd, _)| *wd)
+            .collect::<Vec<_>>();
+        if matched.is_empty() {
+            return;
+        }
+        for wd in matched {
+            inner.push(Event {
+                wd,
+                mask,
+                name: name.into(),
+            });
+        }
+        drop(inner);
+        self.wq.notify_all(false);
+    }
+}
+
+/// Strip the trailing slash a directory path may have.
+fn strip_slash(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    }
+}
+
+/// Report the event `mask` on the canonical absolute `path` to the watches
+/// of the path itself and of its parent directory.
+pub fn notify(path: &str, mask: u32) {
+    let instances = {
+        let instances = INSTANCES.lock();
+        if instances.is_empty() {
+            return;
+        }
+        instances
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect::<Vec<_>>()
+    };
+    let path = strip_slash(path);
+    let (parent, name) = match path.rsplit_once('/') {
+        Some(("", name)) => ("/", name),
+        Some((parent, name)) => (parent, name),
+        None => return,
+    };
+    for instance in instances {
+        instance.queue(path, "", mask);
+        if !name.is_empty() {
+            instance.queue(parent, name, mask);
+        }
+    }
+}
+
+impl FileLike for Inotify {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        loop {
+            let mut inner = self.inner.lock();
+            if let Some(first) = inner.events.front() {
+                if first.size() > buf.len() {
+                    return Err(LinuxError::EINVAL);
+                }
+                let mut len = 0;
+                while let Some(event) = inner.events.front() {
+                    if len + event.size() > buf.len() {
+                        break;
+                    }
+                    event.write_to(&mut buf[len..]);
+                    len += event.size();
+                    inner.events.pop_front();
+                }
+                return Ok(len);
+            }
+            drop(inner);
+            if self.nonblocking.load(Ordering::Relaxed) {
+                return Err(LinuxError::EAGAIN);
+            }
+            self.wq.wait_until(|| !self.inner.lock().events.is_empty());
+        }
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            ino: self.ino,
+            mode: 0o600, // rw-------
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: !self.inner.lock().events.is_empty(),
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn live(&self) -> Option<&LiveFile> {
+        Some(&self.live)
+    }
+
+    fn status_flags(&self) -> u32 {
+        if self.nonblocking.load(Ordering::Relaxed) {
+            O_RDONLY | O_NONBLOCK
+        } else {
+            O_RDONLY
+        }
+    }
+}
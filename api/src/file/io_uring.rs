@@ -0,0 +1,294 @@
+//! A minimal `io_uring`.
+//!
+//! Requests are run synchronously by `io_uring_enter`, but a whole batch is
+//! consumed per call, which is what saves the syscalls. Only `READ`,
+//! `WRITE`, `FSYNC` and `NOP` are supported, without any `IOSQE_*` flag.
+//!
+//! The rings live in ordinary user memory: mmapping the ring fd allocates
+//! the pages in the caller and records where they are, and the kernel then
+//! reads and writes them through the user mapping. The submission and
+//! completion rings share one region (`IORING_FEAT_SINGLE_MMAP`), so
+//! mapping `IORING_OFF_CQ_RING` on its own is not supported, and the rings
+//! are only usable by the process which mapped them.
+
+use core::{
+    any::Any,
+    mem::offset_of,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use axsync::Mutex;
+use linux_raw_sys::io_uring::{
+    IORING_FEAT_SINGLE_MMAP, IORING_OFF_SQ_RING, IORING_OFF_SQES, io_uring_cqe, io_uring_op,
+    io_uring_params, io_uring_sqe,
+};
+use memory_addr::{VirtAddr, align_up};
+use starry_core::mm::PAGE_SIZE;
+
+use super::{File, FileKind, FileLike, Kstat, LiveFile, alloc_anon_ino, get_file_like};
+use crate::ptr::{UserConstPtr, UserPtr};
+
+/// The offset of the submission queue index array in the ring region.
+const SQ_ARRAY_OFFSET: usize = 64;
+
+/// The start of the ring region, with the indices of both rings.
+#[repr(C)]
+struct RingHeader {
+    sq_head: AtomicU32,
+    sq_tail: AtomicU32,
+    sq_ring_mask: u32,
+    sq_ring_entries: u32,
+    sq_flags: u32,
+    sq_dropped: AtomicU32,
+    cq_head: AtomicU32,
+    cq_tail: AtomicU32,
+    cq_ring_mask: u32,
+    cq_ring_entries: u32,
+    cq_overflow: AtomicU32,
+    cq_flags: u32,
+}
+
+const _: () = assert!(size_of::<RingHeader>() <= SQ_ARRAY_OFFSET);
+
+/// Where the regions of a ring are mapped.
+#[derive(Default)]
+struct Regions {
+    ring: Option<VirtAddr>,
+    sqes: Option<VirtAddr>,
+}
+
+/// An `io_uring` instance.
+pub struct IoUring {
+    sq_entries: u32,
+    cq_entries: u32,
+    /// Also serializes `io_uring_enter`.
+    regions: Mutex<Regions>,
+    ino: u64,
+    live: LiveFile,
+}
+
+impl IoUring {
+    /// The largest number of submission queue entries.
+    pub const MAX_ENTRIES: u32 = 4096;
+
+    /// Create a ring. Both sizes must be powers of two.
+    pub fn new(sq_entries: u32, cq_entries: u32) -> Self {
+        Self {
+            sq_entries,
+            cq_entries,
+            regions: Mutex::new(Regions::default()),
+            ino: alloc_anon_ino(),
+            live: LiveFile::new(FileKind::IoUring),
+        }
+    }
+
+    fn cqes_offset(&self) -> usize {
+        (SQ_ARRAY_OFFSET + self.sq_entries as usize * size_of::<u32>()).next_multiple_of(16)
+    }
+
+    fn ring_size(&self) -> usize {
+        self.cqes_offset() + self.cq_entries as usize * size_of::<io_uring_cqe>()
+    }
+
+    fn sqes_size(&self) -> usize {
+        self.sq_entries as usize * size_of::<io_uring_sqe>()
+    }
+
+    /// Fill in the sizes, features and ring offsets reported by
+    /// `io_uring_setup`.
+    pub fn fill_params(&self, params: &mut io_uring_params) {
+        params.sq_entries = self.sq_entries;
+        params.cq_entries = self.cq_entries;
+        params.features = IORING_FEAT_SINGLE_MMAP;
+
+        let sq_off = &mut params.sq_off;
+        sq_off.head = offset_of!(RingHeader, sq_head) as _;
+        sq_off.tail = offset_of!(RingHeader, sq_tail) as _;
+        sq_off.ring_mask = offset_of!(RingHeader, sq_ring_mask) as _;
+        sq_off.ring_entries = offset_of!(RingHeader, sq_ring_entries) as _;
+        sq_off.flags = offset_of!(RingHeader, sq_flags) as _;
+        sq_off.dropped = offset_of!(RingHeader, sq_dropped) as _;
+        sq_off.array = SQ_ARRAY_OFFSET as _;
+
+        let cq_off = &mut params.cq_off;
+        cq_off.head = offset_of!(RingHeader, cq_head) as _;
+        cq_off.tail = offset_of!(RingHeader, cq_tail) as _;
+        cq_off.ring_mask = offset_of!(RingHeader, cq_ring_mask) as _;
+        cq_off.ring_entries = offset_of!(RingHeader, cq_ring_entries) as _;
+        cq_off.overflow = offset_of!(RingHeader, cq_overflow) as _;
+        cq_off.flags = offset_of!(RingHeader, cq_flags) as _;
+        cq_off.cqes = self.cqes_offset() as _;
+    }
+
+    /// Check an mmap of `length` bytes of the region at `offset`, and get
+    /// the initial content of the region.
+    pub fn region_content(&self, offset: usize, length: usize) -> LinuxResult<Vec<u8>> {
+        let (size, content) = match offset as u32 {
+            IORING_OFF_SQ_RING => {
+                let mut content = vec![0; SQ_ARRAY_OFFSET];
+                for (field, value) in [
+                    (offset_of!(RingHeader, sq_ring_mask), self.sq_entries - 1),
+                    (offset_of!(RingHeader, sq_ring_entries), self.sq_entries),
+                    (offset_of!(RingHeader, cq_ring_mask), self.cq_entries - 1),
+                    (offset_of!(RingHeader, cq_ring_entries), self.cq_entries),
+                ] {
+                    content[field..field + 4].copy_from_slice(&value.to_ne_bytes());
+                }
+                (self.ring_size(), content)
+            }
+            IORING_OFF_SQES => (self.sqes_size(), Vec::new()),
+            _ => return Err(LinuxError::EINVAL),
+        };
+        if length < size || length > align_up(size, PAGE_SIZE) {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(content)
+    }
+
+    /// Record that the region at `offset` is mapped at `addr`.
+    pub fn set_region_addr(&self, offset: usize, addr: VirtAddr) {
+        let mut regions = self.regions.lock();
+        match offset as u32 {
+            IORING_OFF_SQ_RING => regions.ring = Some(addr),
+            IORING_OFF_SQES => regions.sqes = Some(addr),
+            _ => {}
+        }
+    }
+
+    /// Run up to `to_submit` submitted requests, posting their completions.
+    ///
+    /// Returns the number of requests consumed.
+    pub fn enter(&self, to_submit: u32) -> LinuxResult<usize> {
+        let regions = self.regions.lock();
+        let (Some(ring), Some(sqes)) = (regions.ring, regions.sqes) else {
+            return Err(LinuxError::EFAULT);
+        };
+        let header = UserPtr::<RingHeader>::from(ring.as_usize()).get_as_mut()?;
+        let array = UserConstPtr::<u32>::from(ring.as_usize() + SQ_ARRAY_OFFSET)
+            .get_as_slice(self.sq_entries as _)?;
+        let sqes = UserConstPtr::<io_uring_sqe>::from(sqes.as_usize())
+            .get_as_slice(self.sq_entries as _)?;
+        let cqes = UserPtr::<io_uring_cqe>::from(ring.as_usize() + self.cqes_offset())
+            .get_as_mut_slice(self.cq_entries as _)?;
+
+        let head = header.sq_head.load(Ordering::Relaxed);
+        let tail = header.sq_tail.load(Ordering::Acquire);
+        let count = tail.wrapping_sub(head).min(self.sq_entries).min(to_submit);
+        for i in 0..count {
+            let index = array[(head.wrapping_add(i) & (self.sq_entries - 1)) as usize];
+            let Some(sqe) = sqes.get(index as usize) else {
+                header.sq_dropped.fetch_add(1, Ordering::Relaxed);
+                continue;
+            };
+            let res = match execute(sqe) {
+                Ok(n) => n as i32,
+                Err(e) => -e.code(),
+            };
+            self.complete(header, cqes, sqe.user_data, res);
+        }
+        header
+            .sq_head
+            .store(head.wrapping_add(count), Ordering::Release);
+        Ok(count as _)
+    }
+
+    fn complete(&self, header: &RingHeader, cqes: &mut [io_uring_cqe], user_data: u64, res: i32) {
+        let tail = header.cq_tail.load(Ordering::Relaxed);
+        let head = header.cq_head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) >= self.cq_entries {
+            header.cq_overflow.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let cqe = &mut cqes[(tail & (self.cq_entries - 1)) as usize];
+        cqe.user_data = user_data;
+        cqe.res = res;
+        cqe.flags = 0;
+        header
+            .cq_tail
+            .store(tail.wrapping_add(1), Ordering::Release);
+    }
+}
+
+/// Run a single request.
+fn execute(sqe: &io_uring_sqe) -> LinuxResult<usize> {
+    // No linked, drained or fixed-file requests.
+    if sqe.flags != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    // SAFETY: `off` and `addr` are the members used by the supported
+    // opcodes.
+    let (offset, addr) = unsafe { (sqe.__bindgen_anon_1.off, sqe.__bindgen_anon_2.addr) };
+    let len = sqe.len as usize;
+
+    const NOP: u8 = io_uring_op::IORING_OP_NOP as _;
+    const READ: u8 = io_uring_op::IORING_OP_READ as _;
+    const WRITE: u8 = io_uring_op::IORING_OP_WRITE as _;
+    const FSYNC: u8 = io_uring_op::IORING_OP_FSYNC as _;
+    match sqe.opcode {
+        NOP => Ok(0),
+        READ => {
+            let buf = UserPtr::<u8>::from(addr as usize).get_as_mut_slice(len)?;
+            // An offset of -1 means the current file position.
+            if offset == u64::MAX {
+                get_file_like(sqe.fd)?.read(buf)
+            } else {
+                File::from_fd(sqe.fd)?.read_at(offset, buf)
+            }
+        }
+        WRITE => {
+            let buf = UserConstPtr::<u8>::from(addr as usize).get_as_slice(len)?;
+            if offset == u64::MAX {
+                get_file_like(sqe.fd)?.write(buf)
+            } else {
+                File::from_fd(sqe.fd)?.write_at(offset, buf)
+            }
+        }
+        FSYNC => {
+            File::from_fd(sqe.fd)?.inner().flush()?;
+            Ok(0)
+        }
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+impl FileLike for IoUring {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            ino: self.ino,
+            mode: 0o600, // rw-------
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        // Completions are posted before `io_uring_enter` returns, so there is
+        // never anything to wait for.
+        Ok(PollState {
+            readable: false,
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+
+    fn live(&self) -> Option<&LiveFile> {
+        Some(&self.live)
+    }
+}
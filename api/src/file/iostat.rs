@@ -0,0 +1,77 @@
+//! Counters of the reads and writes through each open file description,
+//! which `/proc/<pid>/fdinfo/<fd>` shows, and `/proc/starry/pressure` for
+//! the busiest ones, to tell which file a slow program hammers.
+//!
+//! Regular files, pipes and sockets have them, see
+//! [`FileLike::io_counters`], and the `read`, `readv`, `write` and `writev`
+//! syscalls which succeed count on them, with two relaxed atomic adds: one
+//! for the call and one for the bytes. Descriptors made by `dup` or a fork
+//! share the counters of the file they refer to.
+//!
+//! The adds wrap rather than saturate, as a saturating add on an atomic takes
+//! a compare-and-swap loop, and 64 bits of bytes take centuries to wrap.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::FileLike;
+
+/// The counters of a file, see the [module docs](self).
+#[derive(Debug, Default)]
+pub struct IoCounters {
+    reads: AtomicU64,
+    read_bytes: AtomicU64,
+    writes: AtomicU64,
+    write_bytes: AtomicU64,
+}
+
+/// What [`IoCounters`] counted so far.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IoStats {
+    /// The calls reading.
+    pub reads: u64,
+    /// The bytes they read.
+    pub read_bytes: u64,
+    /// The calls writing.
+    pub writes: u64,
+    /// The bytes they wrote.
+    pub write_bytes: u64,
+}
+
+impl IoStats {
+    /// The bytes read and written.
+    pub fn bytes(&self) -> u64 {
+        self.read_bytes.saturating_add(self.write_bytes)
+    }
+}
+
+impl IoCounters {
+    /// What was counted so far.
+    pub fn stats(&self) -> IoStats {
+        IoStats {
+            reads: self.reads.load(Ordering::Relaxed),
+            read_bytes: self.read_bytes.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+            write_bytes: self.write_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Count a call which read `bytes` from `f`, if it has counters.
+pub fn count_read(f: &dyn FileLike, bytes: usize) {
+    if let Some(counters) = f.io_counters() {
+        counters.reads.fetch_add(1, Ordering::Relaxed);
+        counters
+            .read_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+/// Count a call which wrote `bytes` to `f`, if it has counters.
+pub fn count_write(f: &dyn FileLike, bytes: usize) {
+    if let Some(counters) = f.io_counters() {
+        counters.writes.fetch_add(1, Ordering::Relaxed);
+        counters
+            .write_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,232 @@
+//! Record locks, which `fcntl` takes on ranges of regular files.
+//!
+//! There are two kinds, which only differ in what owns them:
+//!
+//! - Classic locks, `F_SETLK`, belong to the process. Closing any of its
+//!   descriptors for the file releases all of them, even if another still
+//!   refers to the same open file, and so does exiting.
+//! - Open file description locks, `F_OFD_SETLK`, belong to the open file,
+//!   so they are shared by the descriptors `dup` and fork made of it, and
+//!   only released when the last of them is closed.
+//!
+//! Locks of different owners conflict on the bytes they share if one of
+//! them is a write lock, so a classic lock and an open file description
+//! lock of the same process conflict too. The locks of an owner never
+//! conflict with each other: taking one over a range the owner has locked
+//! replaces what it had there.
+//!
+//! The locks of `flock` are kept apart: they lock the whole file, and
+//! belong to the open file like open file description locks, but neither
+//! conflicts with record locks, as on Linux.
+//!
+//! Files are told apart by their inode numbers, see [`inode`].
+
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+use axerrno::{LinuxError, LinuxResult};
+use axprocess::Pid;
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::{F_RDLCK, F_UNLCK, F_WRLCK};
+use spin::Mutex;
+use starry_core::task::WaitQueueWrapper;
+
+use super::{File, FileLike, inode};
+use crate::blocking::Blocking;
+
+/// What owns a record lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockOwner {
+    /// A process, for classic locks.
+    Process(Pid),
+    /// An open file description, by the address of its [`File`].
+    File(usize),
+}
+
+impl LockOwner {
+    /// The owner of the classic locks the current process takes.
+    pub fn current_process() -> Self {
+        Self::Process(current().task_ext().thread.process().pid())
+    }
+
+    /// The owner of the open file description locks taken through `file`.
+    pub fn file(file: &Arc<File>) -> Self {
+        Self::File(Arc::as_ptr(file) as usize)
+    }
+
+    /// The pid `F_GETLK` reports for the owner, -1 for an open file.
+    pub fn pid(self) -> i32 {
+        match self {
+            Self::Process(pid) => pid as _,
+            Self::File(_) => -1,
+        }
+    }
+}
+
+/// A lock on the bytes `start..end` of a file, where an `end` of
+/// `u64::MAX` stands for the end of the file however far it grows.
+#[derive(Debug, Clone, Copy)]
+pub struct RecordLock {
+    pub owner: LockOwner,
+    /// `F_RDLCK` or `F_WRLCK`, or `F_UNLCK` to release the range.
+    pub kind: u32,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl RecordLock {
+    fn overlaps(&self, other: &Self) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+
+    fn conflicts(&self, other: &Self) -> bool {
+        self.owner != other.owner
+            && self.overlaps(other)
+            && (self.kind == F_WRLCK || other.kind == F_WRLCK)
+    }
+}
+
+/// The locks of each file, by inode number.
+type LockTable = Mutex<BTreeMap<u64, Vec<RecordLock>>>;
+
+/// The record locks of `fcntl`.
+static LOCKS: LockTable = Mutex::new(BTreeMap::new());
+
+/// The locks of `flock`, over the whole file.
+static FLOCKS: LockTable = Mutex::new(BTreeMap::new());
+
+/// Woken whenever locks are released, for `F_SETLKW` and `flock` to try
+/// again.
+static RELEASED: WaitQueueWrapper = WaitQueueWrapper::new();
+
+/// Get the first lock of another owner on `file` which `lock` conflicts
+/// with, as `F_GETLK` does.
+pub fn conflicting_lock(file: &File, lock: &RecordLock) -> Option<RecordLock> {
+    let ino = inode(file.path());
+    LOCKS
+        .lock()
+        .get(&ino)?
+        .iter()
+        .find(|held| held.conflicts(lock))
+        .copied()
+}
+
+/// Take `lock` on `file`, or release its range if it is `F_UNLCK`.
+///
+/// Fails with `EAGAIN` if another owner holds a conflicting lock, unless
+/// `wait` is set, in which case it waits for the lock to be released, or
+/// for a signal to end the wait with `EINTR`.
+pub fn set_lock(file: &File, lock: RecordLock, wait: bool) -> LinuxResult {
+    set_in(&LOCKS, inode(file.path()), lock, wait, Blocking::RecordLock)
+}
+
+/// Take a `flock` lock of `kind` on `file`, or release it if `kind` is
+/// `F_UNLCK`, failing or waiting like [`set_lock`] if another open file
+/// holds a conflicting one.
+pub fn set_flock(file: &Arc<File>, kind: u32, wait: bool) -> LinuxResult {
+    let lock = RecordLock {
+        owner: LockOwner::file(file),
+        kind,
+        start: 0,
+        end: u64::MAX,
+    };
+    set_in(&FLOCKS, inode(file.path()), lock, wait, Blocking::Flock)
+}
+
+fn set_in(table: &LockTable, ino: u64, lock: RecordLock, wait: bool, op: Blocking) -> LinuxResult {
+    loop {
+        if try_set_lock(table, ino, lock) {
+            return Ok(());
+        }
+        if !wait {
+            return Err(LinuxError::EAGAIN);
+        }
+        let free = || {
+            table
+                .lock()
+                .get(&ino)
+                .is_none_or(|locks| !locks.iter().any(|held| held.conflicts(&lock)))
+        };
+        op.wait_until(&RELEASED, None, free)?;
+    }
+}
+
+fn try_set_lock(table: &LockTable, ino: u64, lock: RecordLock) -> bool {
+    let mut all = table.lock();
+    let locks = all.entry(ino).or_default();
+    if lock.kind != F_UNLCK && locks.iter().any(|held| held.conflicts(&lock)) {
+        return false;
+    }
+    // What the owner held in the range is replaced, keeping the parts of
+    // its locks outside of it.
+    let mut kept = Vec::with_capacity(locks.len() + 2);
+    for held in locks.drain(..) {
+        if held.owner != lock.owner || !held.overlaps(&lock) {
+            kept.push(held);
+            continue;
+        }
+        if held.start < lock.start {
+            kept.push(RecordLock {
+                end: lock.start,
+                ..held
+            });
+        }
+        if held.end > lock.end {
+            kept.push(RecordLock {
+                start: lock.end,
+                ..held
+            });
+        }
+    }
+    if lock.kind != F_UNLCK {
+        kept.push(lock);
+    }
+    if kept.is_empty() {
+        all.remove(&ino);
+    } else {
+        *locks = kept;
+    }
+    drop(all);
+    RELEASED.notify_all(false);
+    true
+}
+
+/// Release the locks of `owner` in `table`, on the file with the inode
+/// number `ino` if given, and on all files otherwise.
+fn release(table: &LockTable, owner: LockOwner, ino: Option<u64>) {
+    let mut all = table.lock();
+    if all.is_empty() {
+        return;
+    }
+    all.retain(|&key, locks| {
+        if ino.is_none_or(|ino| ino == key) {
+            locks.retain(|held| held.owner != owner);
+        }
+        !locks.is_empty()
+    });
+    drop(all);
+    RELEASED.notify_all(false);
+}
+
+/// Release the classic locks of the current process on `file`, which one of
+/// its descriptors was just closed for.
+pub fn file_closed(file: &Arc<dyn FileLike>) {
+    if LOCKS.lock().is_empty() {
+        return;
+    }
+    if let Ok(file) = file.clone().into_any().downcast::<File>() {
+        let ino = inode(file.path());
+        release(&LOCKS, LockOwner::current_process(), Some(ino));
+    }
+}
+
+/// Release the open file description locks and the `flock` locks of
+/// `file`, which is being dropped.
+pub(super) fn file_dropped(file: &File) {
+    let owner = LockOwner::File(file as *const File as usize);
+    release(&LOCKS, owner, None);
+    release(&FLOCKS, owner, None);
+}
+
+/// Release the classic locks of the process `pid`, which is exiting.
+pub fn process_exited(pid: Pid) {
+    release(&LOCKS, LockOwner::Process(pid), None);
+}
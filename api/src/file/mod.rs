@@ -1,36 +1,98 @@
+mod devfs;
+mod flags;
 mod fs;
+mod inode;
+mod inotify;
+#[cfg(feature = "io_uring")]
+mod io_uring;
+mod iostat;
+mod lock;
+mod mode;
+mod mqueue;
 mod net;
+mod owner;
 mod pipe;
+mod procfs;
+mod signalfd;
 mod stdio;
+mod sysfs;
+mod table;
+mod times;
+mod tty;
+mod unix;
+mod virt;
+mod xattr;
 
-use core::{any::Any, ffi::c_int};
+use core::{
+    any::Any,
+    ffi::c_int,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
 
 use alloc::{sync::Arc, vec::Vec};
 use axerrno::{LinuxError, LinuxResult};
 use axio::PollState;
 use axns::{ResArc, def_resource};
-use flatten_objects::FlattenObjects;
-use linux_raw_sys::general::{stat, statx};
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::{O_RDWR, STATX_BASIC_STATS, stat, statx, statx_timestamp};
 use spin::RwLock;
+use starry_core::{mm::PAGE_SIZE, task::ProcessData};
 
+#[cfg(feature = "io_uring")]
+pub use self::io_uring::IoUring;
+#[cfg(feature = "kernel-tests")]
+pub use self::pipe::self_test as pipe_self_test;
 pub use self::{
-    fs::{Directory, File},
+    devfs::BlockFile,
+    flags::{FdFlags, OpenFlags, install_fd},
+    fs::{Directory, File, is_unlinked_tmpfile, lstat_at_path, stat_at_path},
+    inode::{forget_inodes_under, inode, move_inode, move_inodes_under, remove_inode},
+    inotify::{Inotify, notify},
+    iostat::{IoCounters, IoStats, count_read, count_write},
+    lock::{
+        LockOwner, RecordLock, conflicting_lock, file_closed, process_exited, set_flock, set_lock,
+    },
+    mode::{forget_modes_under, get_mode, move_mode, move_modes_under, remove_mode, set_mode},
+    mqueue::{MQ_PRIO_MAX, MessageQueue, MqAttr, MqFd},
     net::Socket,
+    owner::{FileOwner, Readiness},
     pipe::Pipe,
+    signalfd::SignalFd,
+    stdio::{Stdout, flush_output, flush_output_on_panic},
+    table::FileTable,
+    times::{
+        Timestamps, forget_times_under, init_times, move_times, move_times_under, remove_times,
+        set_times, timestamps, update_ctime, update_mtime, update_parent_mtime,
+    },
+    tty::{CONSOLE_TTY, Tty, tty_from_fd},
+    unix::{Ancillary, UCred, UnixStream},
+    virt::{
+        StaticDir, StaticEntry, SynthFile, VirtualDir, VirtualDirEntry, VirtualDirFile,
+        VirtualNode, open_virtual, read_link_virtual, register_virtual_tree, resolve_virtual_link,
+        stat_virtual,
+    },
+    xattr::{
+        XATTR_COUNT_MAX, get_xattr, list_xattrs, move_xattrs, move_xattrs_under, remove_xattr,
+        remove_xattrs, set_xattr, xattrs_supported,
+    },
 };
 
-pub const AX_FILE_LIMIT: usize = 1024;
+pub use starry_core::resources::AX_FILE_LIMIT;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Kstat {
-    ino: u64,
-    nlink: u32,
-    uid: u32,
-    gid: u32,
-    mode: u32,
-    size: u64,
-    blocks: u64,
-    blksize: u32,
+    pub(crate) ino: u64,
+    pub(crate) nlink: u32,
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) mode: u32,
+    pub(crate) size: u64,
+    pub(crate) blocks: u64,
+    pub(crate) blksize: u32,
+    pub(crate) atime: Duration,
+    pub(crate) mtime: Duration,
+    pub(crate) ctime: Duration,
 }
 
 impl Default for Kstat {
@@ -43,7 +105,37 @@ impl Default for Kstat {
             mode: 0,
             size: 0,
             blocks: 0,
-            blksize: 4096,
+            blksize: PAGE_SIZE as _,
+            atime: Duration::ZERO,
+            mtime: Duration::ZERO,
+            ctime: Duration::ZERO,
+        }
+    }
+}
+
+impl Kstat {
+    /// The file type and mode bits, as in `st_mode`.
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    /// The user id of the owner.
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    /// The group id of the owner.
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// Set the timestamps.
+    pub fn with_times(self, times: Timestamps) -> Self {
+        Self {
+            atime: times.atime,
+            mtime: times.mtime,
+            ctime: times.ctime,
+            ..self
         }
     }
 }
@@ -60,6 +152,12 @@ impl From<Kstat> for stat {
         stat.st_size = value.size as _;
         stat.st_blksize = value.blksize as _;
         stat.st_blocks = value.blocks as _;
+        stat.st_atime = value.atime.as_secs() as _;
+        stat.st_atime_nsec = value.atime.subsec_nanos() as _;
+        stat.st_mtime = value.mtime.as_secs() as _;
+        stat.st_mtime_nsec = value.mtime.subsec_nanos() as _;
+        stat.st_ctime = value.ctime.as_secs() as _;
+        stat.st_ctime_nsec = value.ctime.subsec_nanos() as _;
 
         stat
     }
@@ -69,8 +167,8 @@ impl From<Kstat> for statx {
     fn from(value: Kstat) -> Self {
         // SAFETY: valid for statx
         let mut statx: statx = unsafe { core::mem::zeroed() };
+        statx.stx_mask = STATX_BASIC_STATS;
         statx.stx_blksize = value.blksize as _;
-        statx.stx_attributes = value.mode as _;
         statx.stx_nlink = value.nlink as _;
         statx.stx_uid = value.uid as _;
         statx.stx_gid = value.gid as _;
@@ -78,11 +176,32 @@ impl From<Kstat> for statx {
         statx.stx_ino = value.ino as _;
         statx.stx_size = value.size as _;
         statx.stx_blocks = value.blocks as _;
+        statx.stx_atime = statx_time(value.atime);
+        statx.stx_mtime = statx_time(value.mtime);
+        statx.stx_ctime = statx_time(value.ctime);
 
         statx
     }
 }
 
+fn statx_time(time: Duration) -> statx_timestamp {
+    statx_timestamp {
+        tv_sec: time.as_secs() as _,
+        tv_nsec: time.subsec_nanos(),
+        __reserved: 0,
+    }
+}
+
+/// What `poll` reports of a file besides it being readable or writable,
+/// whether asked for or not.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PollStatus {
+    /// The other end is gone, `POLLHUP`.
+    pub hangup: bool,
+    /// An error is pending, `POLLERR`.
+    pub error: bool,
+}
+
 #[allow(dead_code)]
 pub trait FileLike: Send + Sync {
     fn read(&self, buf: &mut [u8]) -> LinuxResult<usize>;
@@ -92,83 +211,316 @@ pub trait FileLike: Send + Sync {
     fn poll(&self) -> LinuxResult<PollState>;
     fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult;
 
-    fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>>
+    /// The status flags of the file as `F_GETFL` reports them: the access
+    /// mode, and `O_APPEND` and `O_NONBLOCK` where they apply.
+    fn status_flags(&self) -> u32 {
+        O_RDWR
+    }
+
+    /// The owner of the file, if it can send `SIGIO`.
+    fn owner(&self) -> Option<&FileOwner> {
+        None
+    }
+
+    /// The counters of the reads and writes through the file, if it keeps
+    /// them, see [`IoCounters`].
+    fn io_counters(&self) -> Option<&IoCounters> {
+        None
+    }
+
+    /// The counter of the live files of its kind the file is in once it
+    /// gets a descriptor, if it is counted, see [`LiveFile`].
+    fn live(&self) -> Option<&LiveFile> {
+        None
+    }
+
+    /// The hangup and error conditions of the file, which `poll` reports
+    /// along with [`FileLike::poll`].
+    fn poll_status(&self) -> PollStatus {
+        PollStatus::default()
+    }
+
+    /// Get the file of this type `fd` refers to.
+    ///
+    /// Fails with `EBADF` if `fd` is not open, and with `wrong_type` if it
+    /// refers to a file of another type, which depends on the syscall, e.g.
+    /// `ENOTDIR` for `getdents64` or `ESPIPE` for `lseek`.
+    fn from_fd_or(fd: c_int, wrong_type: LinuxError) -> LinuxResult<Arc<Self>>
     where
         Self: Sized + 'static,
     {
         get_file_like(fd)?
             .into_any()
             .downcast::<Self>()
-            .map_err(|_| LinuxError::EINVAL)
+            .map_err(|_| wrong_type)
     }
 
-    fn add_to_fd_table(self) -> LinuxResult<c_int>
+    /// Get the file of this type `fd` refers to, failing with `EBADF` if
+    /// `fd` is not open and with the error of the type otherwise, `EINVAL`
+    /// unless overridden.
+    fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>>
     where
         Self: Sized + 'static,
     {
-        add_file_like(Arc::new(self))
+        Self::from_fd_or(fd, LinuxError::EINVAL)
+    }
+}
+
+/// A file descriptor table.
+///
+/// The table itself is shared by the processes a fork copied it to, and
+/// only copied by the first change of one of them, see
+/// [`FdTableRef::with_mut`]. A fork which is followed by `execve` thus never
+/// copies it, unless there are close-on-exec descriptors to close.
+pub type FdTable = RwLock<Arc<FileTable>>;
+
+/// The file descriptor table a process uses.
+///
+/// Processes made with `CLONE_FILES` refer to the same table, which one of
+/// them may later swap for a copy of its own, see [`FdTableRef::unshare`].
+/// The threads of a process always use the same one, as the namespace
+/// holding it belongs to the process.
+///
+/// The table is used through [`with`](Self::with) and
+/// [`with_mut`](Self::with_mut), which keep the reference locked meanwhile.
+/// A change racing with a swap thus lands in the old table before it is
+/// copied, or in the new one, never in the old one after the copy, where it
+/// would be lost.
+pub struct FdTableRef(RwLock<Arc<FdTable>>);
+
+impl FdTableRef {
+    fn new(table: Arc<FdTable>) -> Self {
+        Self(RwLock::new(table))
+    }
+
+    /// The table in use, e.g. to list it from another process.
+    pub fn table(&self) -> Arc<FdTable> {
+        self.0.read().clone()
+    }
+
+    /// Call `f` with the table in use.
+    pub fn with<R>(&self, f: impl FnOnce(&FileTable) -> R) -> R {
+        f(&self.0.read().read())
+    }
+
+    /// Call `f` with the table in use, to change it.
+    ///
+    /// The table is copied first if a fork shares it with another process.
+    pub fn with_mut<R>(&self, f: impl FnOnce(&mut FileTable) -> R) -> R {
+        f(Arc::make_mut(&mut self.0.read().write()))
+    }
+
+    /// Swap the table for a copy, unless no other process uses it.
+    ///
+    /// Used by `unshare(CLONE_FILES)`, and by `execve` before closing the
+    /// close-on-exec descriptors, so that they stay open in the processes
+    /// sharing the table, like in Linux. The copy is made lazily, like on
+    /// fork.
+    pub fn unshare(&self) {
+        let mut table = self.0.write();
+        if Arc::strong_count(&table) > 1 {
+            *table = Arc::new(RwLock::new(table.read().clone()));
+        }
+    }
+
+    /// Remove the descriptors which are closed on `execve`, returning their
+    /// files so that they are dropped outside of the lock.
+    ///
+    /// A table without any is left shared.
+    pub fn take_cloexec(&self) -> Vec<Arc<dyn FileLike>> {
+        let files = self.0.read();
+        let mut table = files.write();
+        if !table.has_cloexec() {
+            return Vec::new();
+        }
+        Arc::make_mut(&mut table).take_cloexec()
+    }
+
+    /// Drop the table as the process exits, closing its files unless
+    /// another process still uses it.
+    pub fn release(&self) {
+        // Dropped after the reference is unlocked, since closing may block.
+        let table = core::mem::replace(&mut *self.0.write(), Arc::default());
+        drop(table);
     }
 }
 
 def_resource! {
-    pub static FD_TABLE: ResArc<RwLock<FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>>> = ResArc::new();
+    pub static FD_TABLE: ResArc<FdTableRef> = ResArc::new();
 }
 
 impl FD_TABLE {
-    /// Return a copy of the inner table.
-    pub fn copy_inner(&self) -> RwLock<FlattenObjects<Arc<dyn FileLike>, AX_FILE_LIMIT>> {
-        let table = self.read();
-        let mut new_table = FlattenObjects::new();
-        for id in table.ids() {
-            let _ = new_table.add_at(id, table.get(id).unwrap().clone());
+    /// Return a reference to a copy of the table, e.g. on fork, which is
+    /// only made once either process changes its table.
+    pub fn copy_inner(&self) -> FdTableRef {
+        FdTableRef::new(Arc::new(RwLock::new(self.table().read().clone())))
+    }
+
+    /// Return a reference to the same table, as with `CLONE_FILES`.
+    pub fn share_inner(&self) -> FdTableRef {
+        FdTableRef::new(self.table())
+    }
+
+    /// Get the table of the process owning `proc_data`.
+    ///
+    /// The returned table stays usable even if the process exits meanwhile.
+    /// Returns `None` if the process has not set up its table yet.
+    pub fn of(&self, proc_data: &ProcessData) -> Option<Arc<FdTable>> {
+        let table = self.deref_from(&proc_data.ns);
+        table.is_inited().then(|| table.table())
+    }
+}
+
+/// The kinds of file-like objects whose live instances are counted, to tell
+/// leaked files apart from closed ones.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum FileKind {
+    File,
+    Directory,
+    Pipe,
+    Socket,
+    Inotify,
+    IoUring,
+    MessageQueue,
+    SignalFd,
+}
+
+impl FileKind {
+    const ALL: [FileKind; 8] = [
+        FileKind::File,
+        FileKind::Directory,
+        FileKind::Pipe,
+        FileKind::Socket,
+        FileKind::Inotify,
+        FileKind::IoUring,
+        FileKind::MessageQueue,
+        FileKind::SignalFd,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            FileKind::File => "file",
+            FileKind::Directory => "directory",
+            FileKind::Pipe => "pipe",
+            FileKind::Socket => "socket",
+            FileKind::Inotify => "inotify",
+            FileKind::IoUring => "io_uring",
+            FileKind::MessageQueue => "mqueue",
+            FileKind::SignalFd => "signalfd",
+        }
+    }
+}
+
+static LIVE_FILES: [AtomicUsize; FileKind::ALL.len()] =
+    [const { AtomicUsize::new(0) }; FileKind::ALL.len()];
+
+/// Counts one live file-like object of its kind, from when
+/// [`install_fd`] gives it its first descriptor until it is dropped.
+///
+/// Objects the kernel makes for itself, which never get a descriptor, are
+/// thus not counted.
+pub struct LiveFile {
+    kind: FileKind,
+    counted: AtomicBool,
+}
+
+impl LiveFile {
+    pub(crate) fn new(kind: FileKind) -> Self {
+        Self {
+            kind,
+            counted: AtomicBool::new(false),
         }
-        RwLock::new(new_table)
     }
 
-    pub fn clear(&self) {
-        let mut table = self.write();
-        let ids = table.ids().collect::<Vec<_>>();
-        for id in ids {
-            let _ = table.remove(id);
+    /// Count the object, unless it is already.
+    pub(crate) fn count(&self) {
+        if !self.counted.swap(true, Ordering::Relaxed) {
+            LIVE_FILES[self.kind as usize].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for LiveFile {
+    fn drop(&mut self) {
+        if *self.counted.get_mut() {
+            LIVE_FILES[self.kind as usize].fetch_sub(1, Ordering::Relaxed);
         }
     }
 }
 
+/// The number of live file-like objects of each kind, by name.
+pub(crate) fn live_files() -> impl Iterator<Item = (&'static str, usize)> {
+    FileKind::ALL.into_iter().map(|kind| {
+        (
+            kind.name(),
+            LIVE_FILES[kind as usize].load(Ordering::Relaxed),
+        )
+    })
+}
+
+/// Allocate an inode number for an anonymous file, e.g. a pipe or a socket.
+pub(crate) fn alloc_anon_ino() -> u64 {
+    static NEXT_INO: AtomicU64 = AtomicU64::new(1);
+    NEXT_INO.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The slot of `fd` in the table, failing with `EBADF` if it is negative,
+/// like `-1` or `AT_FDCWD`, rather than wrapping to a huge index.
+pub fn fd_index(fd: c_int) -> LinuxResult<usize> {
+    usize::try_from(fd).map_err(|_| LinuxError::EBADF)
+}
+
 /// Get a file-like object by `fd`.
 pub fn get_file_like(fd: c_int) -> LinuxResult<Arc<dyn FileLike>> {
+    let fd = fd_index(fd)?;
     FD_TABLE
-        .read()
-        .get(fd as usize)
-        .cloned()
+        .with(|table| table.get(fd).cloned())
         .ok_or(LinuxError::EBADF)
 }
 
-/// Add a file to the file descriptor table.
-pub fn add_file_like(f: Arc<dyn FileLike>) -> LinuxResult<c_int> {
-    Ok(FD_TABLE.write().add(f).map_err(|_| LinuxError::EMFILE)? as c_int)
+/// Get the limit of file descriptors of the current process.
+pub fn nofile_limit() -> usize {
+    current().task_ext().process_data().rlimits.read().nofile()
 }
 
 /// Close a file by `fd`.
 pub fn close_file_like(fd: c_int) -> LinuxResult {
+    let fd = fd_index(fd)?;
     let f = FD_TABLE
-        .write()
-        .remove(fd as usize)
+        .with_mut(|table| table.remove(fd))
         .ok_or(LinuxError::EBADF)?;
     debug!("close_file_like <= count: {}", Arc::strong_count(&f));
+    file_closed(&f);
     Ok(())
 }
 
+/// Check that negative fds, and those past the end of the table, fail with
+/// `EBADF` rather than wrapping to a slot of the table.
+///
+/// Panics on the first fd which does not.
+#[cfg(feature = "kernel-tests")]
+pub fn fd_self_test() {
+    use linux_raw_sys::general::AT_FDCWD;
+
+    for fd in [-1, -2, AT_FDCWD, c_int::MIN, c_int::MAX] {
+        assert_eq!(fd_index(fd).is_ok(), fd >= 0, "{}", fd);
+        assert_eq!(get_file_like(fd).err(), Some(LinuxError::EBADF), "{}", fd);
+        assert_eq!(close_file_like(fd).err(), Some(LinuxError::EBADF), "{}", fd);
+    }
+    info!("fd self test passed");
+}
+
 #[ctor_bare::register_ctor]
 fn init_stdio() {
-    let mut fd_table = flatten_objects::FlattenObjects::new();
+    let mut fd_table = FileTable::new();
     fd_table
-        .add_at(0, Arc::new(stdio::stdin()) as _)
+        .add_at(0, Arc::new(stdio::stdin()) as _, AX_FILE_LIMIT as _)
         .unwrap_or_else(|_| panic!()); // stdin
     fd_table
-        .add_at(1, Arc::new(stdio::stdout()) as _)
+        .add_at(1, Arc::new(stdio::stdout()) as _, AX_FILE_LIMIT as _)
         .unwrap_or_else(|_| panic!()); // stdout
     fd_table
-        .add_at(2, Arc::new(stdio::stdout()) as _)
+        .add_at(2, Arc::new(stdio::stdout()) as _, AX_FILE_LIMIT as _)
         .unwrap_or_else(|_| panic!()); // stderr
-    FD_TABLE.init_new(spin::RwLock::new(fd_table));
+    FD_TABLE.init_new(FdTableRef::new(Arc::new(RwLock::new(fd_table))));
 }
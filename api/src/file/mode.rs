@@ -0,0 +1,52 @@
+//! File permission bits set with `chmod`.
+//!
+//! The filesystems below have nowhere to store them, so they are kept here
+//! by real path, like the timestamps, and lost when the file is removed or
+//! its filesystem unmounted. A file never changed has the bits of its
+//! filesystem, or of the `fmask` and `dmask` options of its mount.
+
+use alloc::{collections::btree_map::BTreeMap, string::String};
+use spin::RwLock;
+
+use super::times::{key, move_under};
+
+static MODES: RwLock<BTreeMap<String, u32>> = RwLock::new(BTreeMap::new());
+
+/// The permission bits set for the file at `path`, if any.
+pub fn get_mode(path: &str) -> Option<u32> {
+    MODES.read().get(key(path)).copied()
+}
+
+/// Set the permission bits of the file at `path` to those of `mode`.
+pub fn set_mode(path: &str, mode: u32) {
+    MODES.write().insert(key(path).into(), mode & 0o7777);
+}
+
+/// Keep the permission bits of the file moved from `from` to `to`.
+pub fn move_mode(from: &str, to: &str) {
+    let mut modes = MODES.write();
+    if let Some(mode) = modes.remove(key(from)) {
+        modes.insert(key(to).into(), mode);
+    }
+}
+
+/// Keep the permission bits of the files under the directory moved from
+/// `from` to `to`.
+pub fn move_modes_under(from: &str, to: &str) {
+    move_under(&mut MODES.write(), from, to);
+}
+
+/// Forget the permission bits of the removed file at `path`.
+pub fn remove_mode(path: &str) {
+    MODES.write().remove(key(path));
+}
+
+/// Forget the permission bits of the files under the directory `dir`, whose
+/// filesystem is unmounted.
+pub fn forget_modes_under(dir: &str) {
+    let dir = key(dir);
+    MODES.write().retain(|path, _| {
+        path.strip_prefix(dir)
+            .is_none_or(|rest| !rest.is_empty() && !rest.starts_with('/'))
+    });
+}
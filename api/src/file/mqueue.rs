@@ -0,0 +1,406 @@
+//! POSIX message queues, see `mq_overview(7)`.
+//!
+//! Queues live in a registry keyed by name, apart from any filesystem, and
+//! are listed in `/dev/mqueue` for debugging. An unlinked queue is removed
+//! from the registry at once, but lives on until its last descriptor is
+//! closed.
+//!
+//! A queue counts against `RLIMIT_MSGQUEUE` of the process creating it from
+//! creation until it is gone, for as many bytes as it can hold, like Linux
+//! does for the user creating it. Every process runs as root, so there is
+//! only one user, which all queues count for.
+
+use core::{
+    any::Any,
+    ffi::c_long,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use alloc::{
+    collections::{btree_map::BTreeMap, vec_deque::VecDeque},
+    format,
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
+use axerrno::{LinuxError, LinuxResult};
+use axfs::fops::FileType;
+use axhal::time::{TimeValue, wall_time};
+use axio::PollState;
+use axsync::{Mutex, MutexGuard};
+use linux_raw_sys::general::{O_NONBLOCK, O_RDONLY, O_RDWR, O_WRONLY};
+use starry_core::task::WaitQueueWrapper;
+
+use super::{
+    FileKind, FileLike, Kstat, LiveFile, SynthFile, VirtualDir, VirtualDirEntry, VirtualNode,
+    alloc_anon_ino,
+};
+use crate::blocking::Blocking;
+
+/// The default of `mq_maxmsg`, like `/proc/sys/fs/mqueue/msg_default`.
+const DEFAULT_MAXMSG: c_long = 10;
+/// The default of `mq_msgsize`, like `/proc/sys/fs/mqueue/msgsize_default`.
+const DEFAULT_MSGSIZE: c_long = 8192;
+/// The largest `mq_maxmsg`, `HARD_MSGMAX` of Linux. Every process runs as
+/// root, so the lower `msg_max` never applies.
+const HARD_MSGMAX: c_long = 65536;
+/// The largest `mq_msgsize`, `HARD_MSGSIZEMAX` of Linux.
+const HARD_MSGSIZEMAX: c_long = 16 * 1024 * 1024;
+/// Priorities must be below this, `MQ_PRIO_MAX` of Linux.
+pub const MQ_PRIO_MAX: u32 = 32768;
+/// The longest name of a queue, `NAME_MAX`.
+const NAME_MAX: usize = 255;
+/// What Linux counts for the header of each message, `struct msg_msg`.
+const MSG_HEADER_SIZE: u64 = 48;
+/// What Linux counts for each priority a queue can hold messages of,
+/// `struct posix_msg_tree_node`.
+const PRIO_NODE_SIZE: u64 = 48;
+
+/// The bytes all queues count against `RLIMIT_MSGQUEUE`.
+static QUEUE_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// `struct mq_attr`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MqAttr {
+    pub mq_flags: c_long,
+    pub mq_maxmsg: c_long,
+    pub mq_msgsize: c_long,
+    pub mq_curmsgs: c_long,
+    pub __reserved: [c_long; 4],
+}
+
+#[derive(Default)]
+struct Messages {
+    /// The messages of each priority, oldest first.
+    by_prio: BTreeMap<u32, VecDeque<Vec<u8>>>,
+    count: usize,
+    /// The total size of the messages, `QSIZE` in the status.
+    bytes: usize,
+}
+
+/// A message queue, shared by the registry and the descriptors opening it.
+pub struct MessageQueue {
+    name: String,
+    maxmsg: usize,
+    msgsize: usize,
+    /// What the queue counts against `RLIMIT_MSGQUEUE`.
+    bytes: u64,
+    mode: u32,
+    ino: u64,
+    messages: Mutex<Messages>,
+    /// Woken when a message is sent or received.
+    wq: WaitQueueWrapper,
+}
+
+static QUEUES: Mutex<BTreeMap<String, Arc<MessageQueue>>> = Mutex::new(BTreeMap::new());
+
+/// Check the name of a queue, which is passed to the kernel without the
+/// leading slash.
+fn check_name(name: &str) -> LinuxResult {
+    if name.is_empty() {
+        Err(LinuxError::ENOENT)
+    } else if name.len() > NAME_MAX {
+        Err(LinuxError::ENAMETOOLONG)
+    } else if name.contains('/') || name == "." || name == ".." {
+        Err(LinuxError::EACCES)
+    } else {
+        Ok(())
+    }
+}
+
+impl MessageQueue {
+    /// Open the queue `name`, creating it with `mode` and `attr` if it does
+    /// not exist and `create` is set. `exclusive` fails with `EEXIST` if it
+    /// exists.
+    ///
+    /// Without `attr`, the queue gets the default limits. Creating it fails
+    /// with `EMFILE` if it would take the bytes of all queues above
+    /// `bytes_limit`, the `RLIMIT_MSGQUEUE` of the caller.
+    pub fn open(
+        name: &str,
+        create: bool,
+        exclusive: bool,
+        mode: u32,
+        attr: Option<&MqAttr>,
+        bytes_limit: u64,
+    ) -> LinuxResult<Arc<Self>> {
+        check_name(name)?;
+        let mut queues = QUEUES.lock();
+        if let Some(queue) = queues.get(name) {
+            if create && exclusive {
+                return Err(LinuxError::EEXIST);
+            }
+            return Ok(queue.clone());
+        }
+        if !create {
+            return Err(LinuxError::ENOENT);
+        }
+        let (maxmsg, msgsize) = match attr {
+            Some(attr) => (attr.mq_maxmsg, attr.mq_msgsize),
+            None => (DEFAULT_MAXMSG, DEFAULT_MSGSIZE),
+        };
+        if !(1..=HARD_MSGMAX).contains(&maxmsg) || !(1..=HARD_MSGSIZEMAX).contains(&msgsize) {
+            return Err(LinuxError::EINVAL);
+        }
+        let bytes = queue_bytes(maxmsg as u64, msgsize as u64);
+        QUEUE_BYTES
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+                total.checked_add(bytes).filter(|&it| it <= bytes_limit)
+            })
+            .map_err(|_| LinuxError::EMFILE)?;
+        let queue = Arc::new(Self {
+            name: name.into(),
+            maxmsg: maxmsg as usize,
+            msgsize: msgsize as usize,
+            bytes,
+            mode: mode & 0o777,
+            ino: alloc_anon_ino(),
+            messages: Mutex::new(Messages::default()),
+            wq: WaitQueueWrapper::new(),
+        });
+        queues.insert(name.into(), queue.clone());
+        Ok(queue)
+    }
+
+    /// Remove the queue `name` from the registry.
+    pub fn unlink(name: &str) -> LinuxResult {
+        check_name(name)?;
+        QUEUES
+            .lock()
+            .remove(name)
+            .map(drop)
+            .ok_or(LinuxError::ENOENT)
+    }
+
+    /// The name of the queue, without the leading slash.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The status read from the queue, like on Linux.
+    fn status(&self) -> String {
+        format!(
+            "QSIZE:{:<10} NOTIFY:0     SIGNO:0     NOTIFY_PID:0     \n",
+            self.messages.lock().bytes
+        )
+    }
+
+    /// Wait on the queue as `op` until the messages are `ready`, for at
+    /// most until the wall clock time `deadline`, and return them locked.
+    fn wait(
+        &self,
+        op: Blocking,
+        nonblocking: bool,
+        deadline: Option<TimeValue>,
+        ready: impl Fn(&Messages) -> bool,
+    ) -> LinuxResult<MutexGuard<'_, Messages>> {
+        loop {
+            let messages = self.messages.lock();
+            if ready(&messages) {
+                return Ok(messages);
+            }
+            drop(messages);
+            if nonblocking {
+                return Err(LinuxError::EAGAIN);
+            }
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let now = wall_time();
+                    if now >= deadline {
+                        return Err(LinuxError::ETIMEDOUT);
+                    }
+                    Some(deadline - now)
+                }
+                None => None,
+            };
+            op.wait_until(&self.wq, timeout, || ready(&self.messages.lock()))?;
+        }
+    }
+}
+
+impl Drop for MessageQueue {
+    fn drop(&mut self) {
+        QUEUE_BYTES.fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+/// The bytes a queue of `maxmsg` messages of `msgsize` bytes counts against
+/// `RLIMIT_MSGQUEUE`: the messages and their headers, like Linux.
+fn queue_bytes(maxmsg: u64, msgsize: u64) -> u64 {
+    maxmsg * (MSG_HEADER_SIZE + msgsize) + maxmsg.min(MQ_PRIO_MAX as u64) * PRIO_NODE_SIZE
+}
+
+/// A descriptor of a [`MessageQueue`].
+pub struct MqFd {
+    queue: Arc<MessageQueue>,
+    readable: bool,
+    writable: bool,
+    nonblocking: AtomicBool,
+    live: LiveFile,
+}
+
+impl MqFd {
+    pub fn new(queue: Arc<MessageQueue>, readable: bool, writable: bool) -> Self {
+        Self {
+            queue,
+            readable,
+            writable,
+            nonblocking: AtomicBool::new(false),
+            live: LiveFile::new(FileKind::MessageQueue),
+        }
+    }
+
+    /// The queue this descriptor opens.
+    pub fn queue(&self) -> &MessageQueue {
+        &self.queue
+    }
+
+    /// Send `msg` with priority `prio`, waiting until the wall clock time
+    /// `deadline` for room if the queue is full.
+    pub fn send(&self, msg: &[u8], prio: u32, deadline: Option<TimeValue>) -> LinuxResult {
+        if !self.writable {
+            return Err(LinuxError::EBADF);
+        }
+        if msg.len() > self.queue.msgsize {
+            return Err(LinuxError::EMSGSIZE);
+        }
+        let nonblocking = self.nonblocking.load(Ordering::Relaxed);
+        let room = |messages: &Messages| messages.count < self.queue.maxmsg;
+        let mut messages = self
+            .queue
+            .wait(Blocking::MqSend, nonblocking, deadline, room)?;
+        messages
+            .by_prio
+            .entry(prio)
+            .or_default()
+            .push_back(msg.into());
+        messages.count += 1;
+        messages.bytes += msg.len();
+        drop(messages);
+        self.queue.wq.notify_all(false);
+        Ok(())
+    }
+
+    /// Receive the oldest message of the highest priority into `buf`,
+    /// waiting until the wall clock time `deadline` for one if the queue is
+    /// empty.
+    ///
+    /// Returns the length and the priority of the message.
+    pub fn receive(
+        &self,
+        buf: &mut [u8],
+        deadline: Option<TimeValue>,
+    ) -> LinuxResult<(usize, u32)> {
+        if !self.readable {
+            return Err(LinuxError::EBADF);
+        }
+        if buf.len() < self.queue.msgsize {
+            return Err(LinuxError::EMSGSIZE);
+        }
+        let nonblocking = self.nonblocking.load(Ordering::Relaxed);
+        let any = |messages: &Messages| messages.count > 0;
+        let mut messages = self
+            .queue
+            .wait(Blocking::MqReceive, nonblocking, deadline, any)?;
+        let mut entry = messages.by_prio.last_entry().unwrap();
+        let prio = *entry.key();
+        let msg = entry.get_mut().pop_front().unwrap();
+        if entry.get().is_empty() {
+            entry.remove();
+        }
+        messages.count -= 1;
+        messages.bytes -= msg.len();
+        drop(messages);
+        self.queue.wq.notify_all(false);
+        buf[..msg.len()].copy_from_slice(&msg);
+        Ok((msg.len(), prio))
+    }
+
+    /// The attributes of the queue, with the flags of this descriptor.
+    pub fn attr(&self) -> MqAttr {
+        MqAttr {
+            mq_flags: if self.nonblocking.load(Ordering::Relaxed) {
+                O_NONBLOCK as _
+            } else {
+                0
+            },
+            mq_maxmsg: self.queue.maxmsg as _,
+            mq_msgsize: self.queue.msgsize as _,
+            mq_curmsgs: self.queue.messages.lock().count as _,
+            ..Default::default()
+        }
+    }
+}
+
+impl FileLike for MqFd {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        // Like Linux, reading gives the status, from the start every time.
+        let status = self.queue.status();
+        let len = status.len().min(buf.len());
+        buf[..len].copy_from_slice(&status.as_bytes()[..len]);
+        Ok(len)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            ino: self.queue.ino,
+            mode: ((FileType::File as u32) << 12) | self.queue.mode,
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        let count = self.queue.messages.lock().count;
+        Ok(PollState {
+            readable: self.readable && count > 0,
+            writable: self.writable && count < self.queue.maxmsg,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn live(&self) -> Option<&LiveFile> {
+        Some(&self.live)
+    }
+
+    fn status_flags(&self) -> u32 {
+        let access = match (self.readable, self.writable) {
+            (true, false) => O_RDONLY,
+            (false, true) => O_WRONLY,
+            _ => O_RDWR,
+        };
+        access | self.attr().mq_flags as u32
+    }
+}
+
+/// `/dev/mqueue`, listing the queues with their status.
+pub(super) struct MqueueDir;
+
+impl VirtualDir for MqueueDir {
+    fn list_entries(&self) -> LinuxResult<Vec<VirtualDirEntry>> {
+        Ok(QUEUES
+            .lock()
+            .keys()
+            .map(|name| VirtualDirEntry::new(name.as_str(), FileType::File))
+            .collect())
+    }
+
+    fn lookup(&self, name: &str) -> LinuxResult<VirtualNode> {
+        let queue = QUEUES.lock().get(name).cloned();
+        queue
+            .map(|queue| SynthFile::node(queue.status()))
+            .ok_or(LinuxError::ENOENT)
+    }
+}
@@ -1,78 +1,528 @@
-use core::net::SocketAddr;
+use core::{
+    ffi::{c_int, c_void},
+    net::{Ipv4Addr, SocketAddr},
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-use alloc::sync::Arc;
-use axerrno::{LinuxError, LinuxResult};
+use alloc::{sync::Arc, vec::Vec};
+use axerrno::{AxError, AxResult, LinuxError, LinuxResult};
 use axio::PollState;
-use axnet::{TcpSocket, UdpSocket};
+use axnet::{InterfaceInfo, TcpSocket, UdpSocket};
 use axsync::Mutex;
-use linux_raw_sys::general::S_IFSOCK;
+use linux_raw_sys::{
+    general::S_IFSOCK,
+    ioctl::{
+        SIOCGIFADDR, SIOCGIFCONF, SIOCGIFFLAGS, SIOCGIFHWADDR, SIOCGIFINDEX, SIOCGIFMTU,
+        SIOCGIFNAME, SIOCGIFNETMASK,
+    },
+    net::{
+        AF_INET, IFNAMSIZ, SHUT_RD, SHUT_RDWR, SHUT_WR, SOCK_DGRAM, SOCK_STREAM, net_device_flags,
+    },
+};
 
-use super::{FileLike, Kstat};
+use super::{
+    FileKind, FileLike, FileOwner, IoCounters, Kstat, LiveFile, PollStatus, UnixStream,
+    alloc_anon_ino,
+};
+use crate::{blocking::Blocking, ptr::UserPtr};
 
-pub enum Socket {
+/// `ARPHRD_ETHER` from `linux/if_arp.h`, the hardware type of Ethernet.
+const ARPHRD_ETHER: u16 = 1;
+/// `ARPHRD_LOOPBACK` from `linux/if_arp.h`.
+const ARPHRD_LOOPBACK: u16 = 772;
+
+/// `struct ifreq` of the `netdevice` ioctls: the name of an interface, and
+/// what is got of it, a `sockaddr` at most.
+///
+/// Not the one of `linux_raw_sys`, whose `sockaddr` is as large as
+/// `sockaddr_storage`.
+#[repr(C)]
+struct IfReq {
+    name: [u8; IFNAMSIZ as usize],
+    data: [u8; 24],
+}
+
+/// `struct ifconf` of `SIOCGIFCONF`.
+#[repr(C)]
+struct IfConf {
+    len: c_int,
+    buf: usize,
+}
+
+/// A `sockaddr_in` of `addr` and port 0.
+fn inet_sockaddr(addr: Ipv4Addr) -> [u8; 16] {
+    let mut sockaddr = [0; 16];
+    sockaddr[..2].copy_from_slice(&(AF_INET as u16).to_ne_bytes());
+    sockaddr[4..8].copy_from_slice(&addr.octets());
+    sockaddr
+}
+
+impl IfReq {
+    /// The interface named in the request. Fails with `ENODEV` if there is
+    /// none.
+    fn interface(&self) -> LinuxResult<InterfaceInfo> {
+        let len = self
+            .name
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(IFNAMSIZ as usize);
+        axnet::interfaces()
+            .into_iter()
+            .find(|iface| iface.name.as_bytes() == &self.name[..len])
+            .ok_or(LinuxError::ENODEV)
+    }
+
+    fn set_name(&mut self, name: &str) {
+        self.name = [0; IFNAMSIZ as usize];
+        self.name[..name.len()].copy_from_slice(name.as_bytes());
+    }
+
+    fn set_data(&mut self, data: &[u8]) {
+        self.data = [0; 24];
+        self.data[..data.len()].copy_from_slice(data);
+    }
+}
+
+/// Handle `SIOCGIFCONF`, filling the buffer of `conf` with a request for
+/// each interface having an IPv4 address, or only telling the length needed
+/// if there is no buffer.
+fn get_if_conf(conf: &mut IfConf) -> LinuxResult {
+    let ifaces = axnet::interfaces()
+        .into_iter()
+        .filter_map(|iface| Some((iface.name, iface.ipv4?.0)))
+        .collect::<Vec<_>>();
+    if conf.buf == 0 {
+        conf.len = (ifaces.len() * size_of::<IfReq>()) as c_int;
+        return Ok(());
+    }
+    // As many requests as fit whole.
+    let count = (conf.len.max(0) as usize / size_of::<IfReq>()).min(ifaces.len());
+    let reqs = UserPtr::<IfReq>::from(conf.buf).get_as_mut_slice(count)?;
+    for (req, (name, ipv4)) in reqs.iter_mut().zip(ifaces) {
+        req.set_name(name);
+        req.set_data(&inet_sockaddr(ipv4));
+    }
+    conf.len = (count * size_of::<IfReq>()) as c_int;
+    Ok(())
+}
+
+/// Handle `SIOCGIFNAME`, naming the interface of the index in `req`.
+fn get_if_name(req: &mut IfReq) -> LinuxResult {
+    let index = c_int::from_ne_bytes(req.data[..4].try_into().unwrap());
+    let iface = axnet::interfaces()
+        .into_iter()
+        .find(|iface| iface.index as c_int == index)
+        .ok_or(LinuxError::ENODEV)?;
+    req.set_name(iface.name);
+    Ok(())
+}
+
+/// Handle the `netdevice` ioctl `op` getting something of the interface
+/// named in `req`.
+fn get_if_info(op: u32, req: &mut IfReq) -> LinuxResult {
+    let iface = req.interface()?;
+    match op {
+        SIOCGIFADDR => {
+            let (ipv4, _) = iface.ipv4.ok_or(LinuxError::EADDRNOTAVAIL)?;
+            req.set_data(&inet_sockaddr(ipv4));
+        }
+        SIOCGIFNETMASK => {
+            let (_, prefix) = iface.ipv4.ok_or(LinuxError::EADDRNOTAVAIL)?;
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            req.set_data(&inet_sockaddr(Ipv4Addr::from_bits(mask)));
+        }
+        SIOCGIFHWADDR => {
+            let family = if iface.loopback {
+                ARPHRD_LOOPBACK
+            } else {
+                ARPHRD_ETHER
+            };
+            let mut sockaddr = [0; 16];
+            sockaddr[..2].copy_from_slice(&family.to_ne_bytes());
+            sockaddr[2..8].copy_from_slice(&iface.mac);
+            req.set_data(&sockaddr);
+        }
+        SIOCGIFFLAGS => {
+            use net_device_flags::*;
+            let kind = if iface.loopback {
+                IFF_LOOPBACK as u16
+            } else {
+                IFF_BROADCAST as u16 | IFF_MULTICAST as u16
+            };
+            req.set_data(&(IFF_UP as u16 | IFF_RUNNING as u16 | kind).to_ne_bytes());
+        }
+        SIOCGIFMTU => req.set_data(&(iface.mtu as c_int).to_ne_bytes()),
+        SIOCGIFINDEX => req.set_data(&(iface.index as c_int).to_ne_bytes()),
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+enum SocketInner {
     Udp(Mutex<UdpSocket>),
     Tcp(Mutex<TcpSocket>),
+    Unix(UnixStream),
 }
 
-macro_rules! impl_socket {
-    ($pub:vis fn $name:ident(&self $(,$arg:ident: $arg_ty:ty)*) -> $ret:ty) => {
-        $pub fn $name(&self, $($arg: $arg_ty),*) -> $ret {
-            match self {
-                Socket::Udp(udpsocket) => Ok(udpsocket.lock().$name($($arg),*)?),
-                Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().$name($($arg),*)?),
-            }
-        }
-    };
+pub struct Socket {
+    inner: SocketInner,
+    /// The inode number, as in `socket:[<ino>]`.
+    ino: u64,
+    /// Whether the socket is non-blocking. The TCP and UDP sockets of
+    /// `axnet` always are, and blocking is done here, for signals to
+    /// interrupt it.
+    nonblocking: AtomicBool,
+    /// Whether a `connect` is in progress, in the background.
+    connecting: AtomicBool,
+    /// The error of a failed non-blocking `connect`, until `SO_ERROR`
+    /// takes it.
+    error: Mutex<Option<LinuxError>>,
+    /// Whether the TCP connection is over, because it failed or was reset.
+    hung_up: AtomicBool,
+    /// Whether reading was shut down, after which reads find the end.
+    read_shut: AtomicBool,
+    /// Whether writing was shut down, after which writes fail with `EPIPE`.
+    write_shut: AtomicBool,
+    // TODO: send `SIGIO` to the owner once `axnet` reports readiness changes
+    owner: FileOwner,
+    counters: IoCounters,
+    live: LiveFile,
 }
 
 impl Socket {
+    pub fn udp(socket: UdpSocket) -> Self {
+        socket.set_nonblocking(true);
+        Self::new(SocketInner::Udp(Mutex::new(socket)))
+    }
+
+    pub fn tcp(socket: TcpSocket) -> Self {
+        socket.set_nonblocking(true);
+        Self::new(SocketInner::Tcp(Mutex::new(socket)))
+    }
+
+    pub fn unix(socket: UnixStream) -> Self {
+        Self::new(SocketInner::Unix(socket))
+    }
+
+    fn new(inner: SocketInner) -> Self {
+        Self {
+            inner,
+            ino: alloc_anon_ino(),
+            nonblocking: AtomicBool::new(false),
+            connecting: AtomicBool::new(false),
+            error: Mutex::new(None),
+            hung_up: AtomicBool::new(false),
+            read_shut: AtomicBool::new(false),
+            write_shut: AtomicBool::new(false),
+            owner: FileOwner::new(),
+            counters: IoCounters::default(),
+            live: LiveFile::new(FileKind::Socket),
+        }
+    }
+
+    /// Get the inode number of the socket.
+    pub const fn ino(&self) -> u64 {
+        self.ino
+    }
+
+    /// The Unix stream of the socket, if it is one.
+    pub fn as_unix(&self) -> Option<&UnixStream> {
+        match &self.inner {
+            SocketInner::Unix(unix) => Some(unix),
+            _ => None,
+        }
+    }
+
+    /// The TCP socket of the socket, if it is one.
+    pub fn as_tcp(&self) -> Option<&Mutex<TcpSocket>> {
+        match &self.inner {
+            SocketInner::Tcp(tcpsocket) => Some(tcpsocket),
+            _ => None,
+        }
+    }
+
+    /// Try `attempt` as `op` until it does not fail with `WouldBlock`,
+    /// unless the socket is non-blocking, driving the network in between.
+    fn block_on<T>(
+        &self,
+        op: Blocking,
+        mut attempt: impl FnMut() -> AxResult<T>,
+    ) -> LinuxResult<T> {
+        op.retry(self.nonblocking.load(Ordering::Relaxed), || {
+            axnet::poll_interfaces();
+            Ok(attempt()?)
+        })
+    }
+
     pub fn recv(&self, buf: &mut [u8]) -> LinuxResult<usize> {
-        match self {
-            Socket::Udp(udpsocket) => Ok(udpsocket.lock().recv_from(buf).map(|e| e.0)?),
-            Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().recv(buf)?),
+        if self.read_shut.load(Ordering::Relaxed) {
+            return Ok(0);
+        }
+        match &self.inner {
+            SocketInner::Udp(udpsocket) => self.block_on(Blocking::Recv, || {
+                udpsocket.lock().recv_from(buf).map(|e| e.0)
+            }),
+            SocketInner::Tcp(tcpsocket) => self.block_on(Blocking::Recv, || {
+                let res = tcpsocket.lock().recv(buf);
+                self.check_hangup(res)
+            }),
+            SocketInner::Unix(unix) => unix.recv(buf),
+        }
+    }
+
+    pub fn send(&self, buf: &[u8]) -> LinuxResult<usize> {
+        if self.write_shut.load(Ordering::Relaxed) {
+            return Err(LinuxError::EPIPE);
+        }
+        match &self.inner {
+            SocketInner::Udp(udpsocket) => {
+                self.block_on(Blocking::Send, || udpsocket.lock().send(buf))
+            }
+            SocketInner::Tcp(tcpsocket) => self.block_on(Blocking::Send, || {
+                let res = tcpsocket.lock().send(buf);
+                self.check_hangup(res)
+            }),
+            SocketInner::Unix(unix) => unix.send(buf),
         }
     }
 
+    /// Record the end of the TCP connection if `res` tells it is over: a
+    /// receive finds it closed, or a send finds it reset.
+    fn check_hangup<T>(&self, res: AxResult<T>) -> AxResult<T> {
+        if let Err(AxError::ConnectionRefused | AxError::ConnectionReset) = res {
+            self.hung_up.store(true, Ordering::Relaxed);
+        }
+        res
+    }
+
     pub fn sendto(&self, buf: &[u8], addr: SocketAddr) -> LinuxResult<usize> {
-        match self {
+        match &self.inner {
             // diff: must bind before sendto
-            Socket::Udp(udpsocket) => Ok(udpsocket.lock().send_to(buf, addr)?),
-            Socket::Tcp(_) => Err(LinuxError::EISCONN),
+            SocketInner::Udp(udpsocket) => {
+                self.block_on(Blocking::Send, || udpsocket.lock().send_to(buf, addr))
+            }
+            SocketInner::Tcp(_) | SocketInner::Unix(_) => Err(LinuxError::EISCONN),
         }
     }
 
     pub fn recvfrom(&self, buf: &mut [u8]) -> LinuxResult<(usize, Option<SocketAddr>)> {
-        match self {
+        match &self.inner {
             // diff: must bind before recvfrom
-            Socket::Udp(udpsocket) => Ok(udpsocket
-                .lock()
-                .recv_from(buf)
-                .map(|res| (res.0, Some(res.1)))?),
-            Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().recv(buf).map(|res| (res, None))?),
+            SocketInner::Udp(udpsocket) => self.block_on(Blocking::Recv, || {
+                udpsocket
+                    .lock()
+                    .recv_from(buf)
+                    .map(|res| (res.0, Some(res.1)))
+            }),
+            SocketInner::Tcp(tcpsocket) => self.block_on(Blocking::Recv, || {
+                tcpsocket.lock().recv(buf).map(|res| (res, None))
+            }),
+            SocketInner::Unix(unix) => Ok((unix.recv(buf)?, None)),
         }
     }
 
     pub fn listen(&self) -> LinuxResult {
-        match self {
-            Socket::Udp(_) => Err(LinuxError::EOPNOTSUPP),
-            Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().listen()?),
+        match &self.inner {
+            SocketInner::Udp(_) | SocketInner::Unix(_) => Err(LinuxError::EOPNOTSUPP),
+            SocketInner::Tcp(tcpsocket) => Ok(tcpsocket.lock().listen()?),
         }
     }
 
     pub fn accept(&self) -> LinuxResult<TcpSocket> {
-        match self {
-            Socket::Udp(_) => Err(LinuxError::EOPNOTSUPP),
-            Socket::Tcp(tcpsocket) => Ok(tcpsocket.lock().accept()?),
+        match &self.inner {
+            SocketInner::Udp(_) | SocketInner::Unix(_) => Err(LinuxError::EOPNOTSUPP),
+            SocketInner::Tcp(tcpsocket) => {
+                self.block_on(Blocking::Accept, || tcpsocket.lock().accept())
+            }
+        }
+    }
+
+    /// Shut down reading, writing or both, as `how` of `shutdown` tells.
+    ///
+    /// Shutting down writing a stream sends what was written, then its end,
+    /// while reading goes on until the peer shuts down too.
+    pub fn shutdown(&self, how: u32) -> LinuxResult {
+        let (read, write) = match how {
+            SHUT_RD => (true, false),
+            SHUT_WR => (false, true),
+            SHUT_RDWR => (true, true),
+            _ => return Err(LinuxError::EINVAL),
+        };
+        match &self.inner {
+            SocketInner::Udp(udpsocket) => {
+                udpsocket.lock().peer_addr()?;
+            }
+            SocketInner::Tcp(tcpsocket) => {
+                let tcpsocket = tcpsocket.lock();
+                if write {
+                    tcpsocket.shutdown_write()?;
+                } else {
+                    tcpsocket.peer_addr()?;
+                }
+            }
+            SocketInner::Unix(unix) if write => unix.shutdown()?,
+            SocketInner::Unix(_) => {}
+        }
+        self.read_shut.fetch_or(read, Ordering::Relaxed);
+        self.write_shut.fetch_or(write, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn local_addr(&self) -> LinuxResult<SocketAddr> {
+        match &self.inner {
+            SocketInner::Udp(udpsocket) => Ok(udpsocket.lock().local_addr()?),
+            SocketInner::Tcp(tcpsocket) => Ok(tcpsocket.lock().local_addr()?),
+            // Unix addresses are not supported yet.
+            SocketInner::Unix(_) => Err(LinuxError::EAFNOSUPPORT),
         }
     }
 
-    impl_socket!(pub fn send(&self, buf: &[u8]) -> LinuxResult<usize>);
-    impl_socket!(pub fn poll(&self) -> LinuxResult<PollState>);
-    impl_socket!(pub fn local_addr(&self) -> LinuxResult<SocketAddr>);
-    impl_socket!(pub fn peer_addr(&self) -> LinuxResult<SocketAddr>);
-    impl_socket!(pub fn bind(&self, addr: SocketAddr) -> LinuxResult);
-    impl_socket!(pub fn connect(&self, addr: SocketAddr) -> LinuxResult);
-    impl_socket!(pub fn shutdown(&self) -> LinuxResult);
+    pub fn peer_addr(&self) -> LinuxResult<SocketAddr> {
+        match &self.inner {
+            SocketInner::Udp(udpsocket) => Ok(udpsocket.lock().peer_addr()?),
+            SocketInner::Tcp(tcpsocket) => Ok(tcpsocket.lock().peer_addr()?),
+            SocketInner::Unix(_) => Err(LinuxError::EAFNOSUPPORT),
+        }
+    }
+
+    pub fn bind(&self, addr: SocketAddr) -> LinuxResult {
+        match &self.inner {
+            SocketInner::Udp(udpsocket) => Ok(udpsocket.lock().bind(addr)?),
+            SocketInner::Tcp(tcpsocket) => Ok(tcpsocket.lock().bind(addr)?),
+            SocketInner::Unix(_) => Err(LinuxError::EAFNOSUPPORT),
+        }
+    }
+
+    /// Handle the `ioctl` request `op`.
+    ///
+    /// Only the `netdevice` requests getting the configuration of the
+    /// network interfaces are supported. Other requests succeed without
+    /// doing anything.
+    pub fn ioctl(&self, op: usize, arg: UserPtr<c_void>) -> LinuxResult<isize> {
+        let addr = arg.address().as_usize();
+        match op as u32 {
+            SIOCGIFCONF => get_if_conf(UserPtr::from(addr).get_as_mut()?)?,
+            SIOCGIFNAME => get_if_name(UserPtr::from(addr).get_as_mut()?)?,
+            op @ (SIOCGIFADDR | SIOCGIFNETMASK | SIOCGIFHWADDR | SIOCGIFFLAGS | SIOCGIFMTU
+            | SIOCGIFINDEX) => get_if_info(op, UserPtr::from(addr).get_as_mut()?)?,
+            _ => warn!("Unimplemented socket ioctl: {:#x}", op),
+        }
+        Ok(0)
+    }
+
+    /// The type of the socket, as `SO_TYPE` reports it.
+    pub fn socket_type(&self) -> u32 {
+        match &self.inner {
+            SocketInner::Udp(_) => SOCK_DGRAM,
+            SocketInner::Tcp(_) | SocketInner::Unix(_) => SOCK_STREAM,
+        }
+    }
+
+    /// Connect to `addr`.
+    ///
+    /// A non-blocking TCP socket fails with `EINPROGRESS` and connects in
+    /// the background. It polls writable once done, and `SO_ERROR` then
+    /// reports how it went. Connecting again fails with `EALREADY` while in
+    /// progress, with the error if it failed, and with `EISCONN` once
+    /// connected.
+    ///
+    /// A blocking one waits for the connection. A signal interrupting the
+    /// wait fails it with `EINTR`, leaving the connection to go on in the
+    /// background, as for a non-blocking one.
+    pub fn connect(&self, addr: SocketAddr) -> LinuxResult {
+        let socket = match &self.inner {
+            SocketInner::Udp(udpsocket) => return Ok(udpsocket.lock().connect(addr)?),
+            SocketInner::Tcp(tcpsocket) => tcpsocket,
+            SocketInner::Unix(_) => return Err(LinuxError::EISCONN),
+        };
+        let tcpsocket = socket.lock();
+        if self.update_connecting(&tcpsocket) {
+            return Err(LinuxError::EALREADY);
+        }
+        // Like Linux, report a failure not taken by `SO_ERROR` yet.
+        if let Some(err) = self.error.lock().take() {
+            return Err(err);
+        }
+        match tcpsocket.connect(addr) {
+            Ok(()) => Ok(()),
+            Err(AxError::WouldBlock) => {
+                self.connecting.store(true, Ordering::Relaxed);
+                drop(tcpsocket);
+                if self.nonblocking.load(Ordering::Relaxed) {
+                    return Err(LinuxError::EINPROGRESS);
+                }
+                Blocking::Connect.retry(false, || {
+                    if self.update_connecting(&socket.lock()) {
+                        return Err(LinuxError::EAGAIN);
+                    }
+                    self.error.lock().take().map_or(Ok(()), Err)
+                })
+            }
+            Err(AxError::AlreadyExists) => Err(LinuxError::EISCONN),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Check on a non-blocking `connect` in progress, recording its error
+    /// if it failed. Returns whether it is still in progress.
+    fn update_connecting(&self, tcpsocket: &TcpSocket) -> bool {
+        if !self.connecting.load(Ordering::Relaxed) {
+            return false;
+        }
+        // Nothing else drives the handshake.
+        axnet::poll_interfaces();
+        // Polling a connecting socket moves it on once the handshake is over.
+        if !tcpsocket.poll().is_ok_and(|it| it.writable) {
+            return true;
+        }
+        self.connecting.store(false, Ordering::Relaxed);
+        if tcpsocket.peer_addr().is_err() {
+            *self.error.lock() = Some(LinuxError::ECONNREFUSED);
+            self.hung_up.store(true, Ordering::Relaxed);
+        }
+        false
+    }
+
+    /// Take the pending error of the socket, as `SO_ERROR` does.
+    pub fn take_error(&self) -> Option<LinuxError> {
+        if let SocketInner::Tcp(tcpsocket) = &self.inner {
+            self.update_connecting(&tcpsocket.lock());
+        }
+        self.error.lock().take()
+    }
+
+    /// Whether the socket is readable or writable.
+    ///
+    /// A socket whose `connect` failed is both, like a hung up one, and one
+    /// whose reading was shut down is readable.
+    pub fn poll(&self) -> LinuxResult<PollState> {
+        let mut state = self.poll_inner()?;
+        state.readable |= self.read_shut.load(Ordering::Relaxed);
+        Ok(state)
+    }
+
+    fn poll_inner(&self) -> LinuxResult<PollState> {
+        match &self.inner {
+            SocketInner::Udp(udpsocket) => Ok(udpsocket.lock().poll()?),
+            SocketInner::Unix(unix) => Ok(unix.poll()),
+            SocketInner::Tcp(tcpsocket) => {
+                let tcpsocket = tcpsocket.lock();
+                if self.update_connecting(&tcpsocket) {
+                    return Ok(PollState {
+                        readable: false,
+                        writable: false,
+                    });
+                }
+                if self.error.lock().is_some() {
+                    return Ok(PollState {
+                        readable: true,
+                        writable: true,
+                    });
+                }
+                Ok(tcpsocket.poll()?)
+            }
+        }
+    }
 }
 
 impl FileLike for Socket {
@@ -87,8 +537,8 @@ impl FileLike for Socket {
     fn stat(&self) -> LinuxResult<Kstat> {
         // not really implemented
         Ok(Kstat {
+            ino: self.ino,
             mode: S_IFSOCK | 0o777u32, // rwxrwxrwx
-            blksize: 4096,
             ..Default::default()
         })
     }
@@ -101,11 +551,40 @@ impl FileLike for Socket {
         self.poll()
     }
 
+    /// A Unix stream hangs up once its peer is closed, and a TCP one once
+    /// its connection failed or was seen closed or reset. Only a pending
+    /// error of `connect` is reported as an error.
+    fn poll_status(&self) -> PollStatus {
+        let hangup = match &self.inner {
+            SocketInner::Udp(_) => false,
+            SocketInner::Tcp(_) => self.hung_up.load(Ordering::Relaxed),
+            SocketInner::Unix(unix) => unix.peer_closed(),
+        };
+        PollStatus {
+            hangup,
+            error: self.error.lock().is_some(),
+        }
+    }
+
     fn set_nonblocking(&self, nonblock: bool) -> LinuxResult {
-        match self {
-            Socket::Udp(udpsocket) => udpsocket.lock().set_nonblocking(nonblock),
-            Socket::Tcp(tcpsocket) => tcpsocket.lock().set_nonblocking(nonblock),
+        match &self.inner {
+            SocketInner::Udp(_) | SocketInner::Tcp(_) => {
+                self.nonblocking.store(nonblock, Ordering::Relaxed)
+            }
+            SocketInner::Unix(unix) => unix.set_nonblocking(nonblock),
         }
         Ok(())
     }
+
+    fn live(&self) -> Option<&LiveFile> {
+        Some(&self.live)
+    }
+
+    fn owner(&self) -> Option<&FileOwner> {
+        Some(&self.owner)
+    }
+
+    fn io_counters(&self) -> Option<&IoCounters> {
+        Some(&self.counters)
+    }
 }
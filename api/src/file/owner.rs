@@ -0,0 +1,102 @@
+//! Owners of open files, which get `SIGIO` when a file with `O_ASYNC` set
+//! becomes ready, see `F_SETOWN` in `fcntl(2)`.
+
+use core::{
+    ffi::c_int,
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+use axprocess::Pid;
+use axsignal::{SignalInfo, Signo};
+use linux_raw_sys::general::{POLL_IN, POLL_OUT, POLLIN, POLLOUT, POLLRDNORM, POLLWRNORM};
+use starry_core::task::{get_process, get_process_group};
+
+use crate::signal::{send_signal_process, send_signal_process_group};
+
+/// How a file became ready.
+#[derive(Debug, Clone, Copy)]
+pub enum Readiness {
+    /// There is data to read.
+    Readable,
+    /// There is room to write.
+    Writable,
+}
+
+/// The owner of an open file description, and whether it is in async mode.
+///
+/// It belongs to the description, so it is shared by duplicated descriptors
+/// and inherited across `fork`.
+pub struct FileOwner {
+    /// A process id if positive, the negated id of a process group if
+    /// negative, and 0 if there is no owner.
+    owner: AtomicI32,
+    /// The descriptor `O_ASYNC` was set on, reported in the signal, or -1 if
+    /// it is not set.
+    async_fd: AtomicI32,
+}
+
+impl Default for FileOwner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileOwner {
+    pub const fn new() -> Self {
+        Self {
+            owner: AtomicI32::new(0),
+            async_fd: AtomicI32::new(-1),
+        }
+    }
+
+    /// The owner, as returned by `F_GETOWN`.
+    pub fn owner(&self) -> i32 {
+        self.owner.load(Ordering::Acquire)
+    }
+
+    /// Set the owner, as by `F_SETOWN`.
+    pub fn set_owner(&self, owner: i32) {
+        self.owner.store(owner, Ordering::Release);
+    }
+
+    /// Whether `O_ASYNC` is set.
+    pub fn is_async(&self) -> bool {
+        self.async_fd.load(Ordering::Acquire) >= 0
+    }
+
+    /// Set or clear `O_ASYNC`, as by `F_SETFL` on `fd`.
+    pub fn set_async(&self, fd: c_int, on: bool) {
+        self.async_fd
+            .store(if on { fd } else { -1 }, Ordering::Release);
+    }
+
+    /// Send `SIGIO` to the owner if `O_ASYNC` is set, since the file became
+    /// ready as `readiness` says.
+    ///
+    /// An owner which has exited is silently dropped.
+    pub fn notify(&self, readiness: Readiness) {
+        let fd = self.async_fd.load(Ordering::Acquire);
+        let owner = self.owner();
+        if fd < 0 || owner == 0 {
+            return;
+        }
+        let (code, band) = match readiness {
+            Readiness::Readable => (POLL_IN, POLLIN | POLLRDNORM),
+            Readiness::Writable => (POLL_OUT, POLLOUT | POLLWRNORM),
+        };
+        let mut sig = SignalInfo::new(Signo::SIGIO, code as _);
+        // SAFETY: `_sigpoll` is the member used by `SIGIO`.
+        unsafe {
+            let sigpoll = &mut sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._sigpoll;
+            sigpoll._band = band as _;
+            sigpoll._fd = fd;
+        }
+        if owner > 0 {
+            if let Ok(proc) = get_process(owner as Pid) {
+                let _ = send_signal_process(&proc, sig);
+            }
+        } else if let Ok(group) = get_process_group(owner.unsigned_abs() as Pid) {
+            send_signal_process_group(&group, sig);
+        }
+    }
+}
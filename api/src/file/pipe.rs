@@ -1,12 +1,20 @@
-use core::any::Any;
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-use alloc::sync::Arc;
+use alloc::{boxed::Box, sync::Arc, vec};
 use axerrno::{LinuxError, LinuxResult};
 use axio::PollState;
 use axsync::Mutex;
-use linux_raw_sys::general::S_IFIFO;
+use linux_raw_sys::general::{O_NONBLOCK, O_RDONLY, O_WRONLY, S_IFIFO};
+use starry_core::pressure::{self, Pressure};
 
-use super::{FileLike, Kstat};
+use super::{
+    FileKind, FileLike, FileOwner, IoCounters, Kstat, LiveFile, PollStatus, Readiness,
+    alloc_anon_ino,
+};
+use crate::signal::has_pending_signal;
 
 #[derive(Copy, Clone, PartialEq)]
 enum RingBufferStatus {
@@ -15,19 +23,25 @@ enum RingBufferStatus {
     Normal,
 }
 
-const RING_BUFFER_SIZE: usize = 256;
+/// The capacity of a pipe, like the default one of Linux.
+const RING_BUFFER_SIZE: usize = 65536;
+
+/// Writes of at most this many bytes are atomic, i.e. not interleaved with
+/// the data of other writers.
+const PIPE_BUF: usize = 4096;
 
 struct PipeRingBuffer {
-    arr: [u8; RING_BUFFER_SIZE],
+    arr: Box<[u8]>,
     head: usize,
     tail: usize,
     status: RingBufferStatus,
 }
 
 impl PipeRingBuffer {
-    const fn new() -> Self {
+    fn new() -> Self {
+        pressure::add(Pressure::Pipes, 1);
         Self {
-            arr: [0; RING_BUFFER_SIZE],
+            arr: vec![0; RING_BUFFER_SIZE].into_boxed_slice(),
             head: 0,
             tail: 0,
             status: RingBufferStatus::Empty,
@@ -74,25 +88,62 @@ impl PipeRingBuffer {
     }
 }
 
+impl Drop for PipeRingBuffer {
+    fn drop(&mut self) {
+        pressure::sub(Pressure::PipeBytes, self.available_read());
+        pressure::sub(Pressure::Pipes, 1);
+    }
+}
+
 pub struct Pipe {
     readable: bool,
     buffer: Arc<Mutex<PipeRingBuffer>>,
+    /// The inode number, shared by both ends.
+    ino: u64,
+    nonblocking: AtomicBool,
+    owner: Arc<FileOwner>,
+    /// The owner of the other end, which is notified when this end makes it
+    /// ready.
+    peer_owner: Arc<FileOwner>,
+    /// The counters of this end.
+    counters: IoCounters,
+    live: LiveFile,
 }
 
 impl Pipe {
     pub fn new() -> (Pipe, Pipe) {
         let buffer = Arc::new(Mutex::new(PipeRingBuffer::new()));
+        let ino = alloc_anon_ino();
+        let read_owner = Arc::new(FileOwner::new());
+        let write_owner = Arc::new(FileOwner::new());
         let read_end = Pipe {
             readable: true,
             buffer: buffer.clone(),
+            ino,
+            nonblocking: AtomicBool::new(false),
+            owner: read_owner.clone(),
+            peer_owner: write_owner.clone(),
+            counters: IoCounters::default(),
+            live: LiveFile::new(FileKind::Pipe),
         };
         let write_end = Pipe {
             readable: false,
             buffer,
+            ino,
+            nonblocking: AtomicBool::new(false),
+            owner: write_owner,
+            peer_owner: read_owner,
+            counters: IoCounters::default(),
+            live: LiveFile::new(FileKind::Pipe),
         };
         (read_end, write_end)
     }
 
+    /// Get the inode number of the pipe, as in `pipe:[<ino>]`.
+    pub const fn ino(&self) -> u64 {
+        self.ino
+    }
+
     pub const fn readable(&self) -> bool {
         self.readable
     }
@@ -109,7 +160,7 @@ impl Pipe {
 impl FileLike for Pipe {
     fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
         if !self.readable() {
-            return Err(LinuxError::EPERM);
+            return Err(LinuxError::EBADF);
         }
         if buf.is_empty() {
             return Ok(0);
@@ -122,21 +173,33 @@ impl FileLike for Pipe {
                 if self.closed() {
                     return Ok(0);
                 }
+                if self.nonblocking.load(Ordering::Relaxed) {
+                    return Err(LinuxError::EAGAIN);
+                }
+                if has_pending_signal() {
+                    return Err(LinuxError::EINTR);
+                }
                 drop(ring_buffer);
                 // Data not ready, wait for write end
                 axtask::yield_now(); // TODO: use synconize primitive
                 continue;
             }
+            let was_full = ring_buffer.available_write() == 0;
             for c in buf.iter_mut().take(read_size) {
                 *c = ring_buffer.read_byte();
             }
+            drop(ring_buffer);
+            pressure::sub(Pressure::PipeBytes, read_size);
+            if was_full {
+                self.peer_owner.notify(Readiness::Writable);
+            }
             return Ok(read_size);
         }
     }
 
     fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
         if !self.writable() {
-            return Err(LinuxError::EPERM);
+            return Err(LinuxError::EBADF);
         }
         if self.closed() {
             return Err(LinuxError::EPIPE);
@@ -147,30 +210,53 @@ impl FileLike for Pipe {
 
         let mut write_size = 0usize;
         let total_len = buf.len();
+        // A write of up to `PIPE_BUF` bytes waits for room for all of them.
+        let min_write = if total_len <= PIPE_BUF { total_len } else { 1 };
         loop {
             let mut ring_buffer = self.buffer.lock();
             let loop_write = ring_buffer.available_write();
-            if loop_write == 0 {
+            if loop_write < min_write {
                 if self.closed() {
                     return Ok(write_size);
                 }
+                if self.nonblocking.load(Ordering::Relaxed) {
+                    return match write_size {
+                        0 => Err(LinuxError::EAGAIN),
+                        n => Ok(n),
+                    };
+                }
+                // An interrupted write returns what it has written so far.
+                if has_pending_signal() {
+                    return match write_size {
+                        0 => Err(LinuxError::EINTR),
+                        n => Ok(n),
+                    };
+                }
                 drop(ring_buffer);
                 // Buffer is full, wait for read end to consume
                 axtask::yield_now(); // TODO: use synconize primitive
                 continue;
             }
-            for _ in 0..loop_write {
-                if write_size == total_len {
-                    return Ok(write_size);
-                }
-                ring_buffer.write_byte(buf[write_size]);
-                write_size += 1;
+            let was_empty = ring_buffer.available_read() == 0;
+            let end = total_len.min(write_size + loop_write);
+            for &c in &buf[write_size..end] {
+                ring_buffer.write_byte(c);
+            }
+            drop(ring_buffer);
+            pressure::add(Pressure::PipeBytes, end - write_size);
+            if was_empty {
+                self.peer_owner.notify(Readiness::Readable);
+            }
+            write_size = end;
+            if write_size == total_len {
+                return Ok(write_size);
             }
         }
     }
 
     fn stat(&self) -> LinuxResult<Kstat> {
         Ok(Kstat {
+            ino: self.ino,
             mode: S_IFIFO | 0o600u32, // rw-------
             ..Default::default()
         })
@@ -180,15 +266,69 @@ impl FileLike for Pipe {
         self
     }
 
+    /// A write end whose read end is closed is writable, as a write fails
+    /// at once with `EPIPE`.
     fn poll(&self) -> LinuxResult<PollState> {
         let buf = self.buffer.lock();
         Ok(PollState {
             readable: self.readable() && buf.available_read() > 0,
-            writable: self.writable() && buf.available_write() > 0,
+            writable: self.writable() && (buf.available_write() > 0 || self.closed()),
         })
     }
 
-    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+    /// The read end hangs up once the write end is closed, even with data
+    /// left, and the write end has an error once the read end is closed.
+    fn poll_status(&self) -> PollStatus {
+        PollStatus {
+            hangup: self.readable() && self.closed(),
+            error: self.writable() && self.closed(),
+        }
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
         Ok(())
     }
+
+    fn live(&self) -> Option<&LiveFile> {
+        Some(&self.live)
+    }
+
+    fn status_flags(&self) -> u32 {
+        let access = if self.readable() { O_RDONLY } else { O_WRONLY };
+        if self.nonblocking.load(Ordering::Relaxed) {
+            access | O_NONBLOCK
+        } else {
+            access
+        }
+    }
+
+    fn owner(&self) -> Option<&FileOwner> {
+        Some(&self.owner)
+    }
+
+    fn io_counters(&self) -> Option<&IoCounters> {
+        Some(&self.counters)
+    }
+}
+
+/// Check that a pipe is counted in [`starry_core::pressure`] while either
+/// end is open, and so are the bytes written to it until read.
+#[cfg(feature = "kernel-tests")]
+pub fn self_test() {
+    use starry_core::pressure::count;
+
+    let (pipes, bytes) = (count(Pressure::Pipes), count(Pressure::PipeBytes));
+    let (read_end, write_end) = Pipe::new();
+    assert_eq!(count(Pressure::Pipes), pipes + 1);
+    assert_eq!(write_end.write(b"pressure").unwrap(), 8);
+    assert_eq!(count(Pressure::PipeBytes), bytes + 8);
+    let mut buf = [0; 3];
+    assert_eq!(read_end.read(&mut buf).unwrap(), 3);
+    assert_eq!(count(Pressure::PipeBytes), bytes + 5);
+    drop(read_end);
+    assert_eq!(count(Pressure::Pipes), pipes + 1);
+    drop(write_end);
+    assert_eq!(count(Pressure::Pipes), pipes);
+    assert_eq!(count(Pressure::PipeBytes), bytes);
 }
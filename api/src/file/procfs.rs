@@ -0,0 +1,1630 @@
+//! The `/proc` tree.
+
+use core::{any::Any, fmt::Write};
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use axerrno::{LinuxError, LinuxResult};
+use axfs::{CURRENT_DIR_PATH, fops::FileType};
+use axhal::{
+    paging::MappingFlags,
+    time::{NANOS_PER_MICROS, NANOS_PER_SEC},
+};
+use axio::{PollState, SeekFrom};
+use axmm::{AreaKind, max_map_count, set_max_map_count};
+use axprocess::{Pid, Process, Thread};
+use axtask::{TaskExtRef, TaskState, current};
+use linux_raw_sys::general::O_CLOEXEC;
+use starry_core::{
+    audit::{audit_records, exec_audit_enabled, set_exec_audit},
+    cred::{CAP_AUDIT_CONTROL, CAP_AUDIT_READ, CAP_SYS_ADMIN, dac_enforcing, set_dac_enforcing},
+    latency::{self, SyscallLatency},
+    mm::PAGE_SIZE,
+    resources::{RLIM_INFINITY, RLIM_NLIMITS, Rlimits},
+    stats,
+    task::{ProcessData, ThreadData, get_process, processes, table_stats},
+    uts::{RELEASE, SYSNAME, UTS_NAME_LEN, UtsNamespace},
+};
+use syscalls::Sysno;
+
+use super::{
+    AX_FILE_LIMIT, BlockFile, Directory, FD_TABLE, FdTable, File, FileLike, IoCounters, Kstat,
+    MqFd, Pipe, Socket,
+    devfs::{DevNull, DevRandom, DevUrandom, DevZero},
+    live_files,
+    stdio::{Stdin, Stdout},
+    virt::{
+        StaticDir, StaticEntry, SynthFile, VirtualDir, VirtualDirEntry, VirtualDirFile, VirtualNode,
+    },
+};
+use crate::{EFFECTIVE_ID, MountInfo, REAL_ID, mounts_of, released_mounts, require_capability};
+
+static NET: [StaticEntry; 1] = [("dev", FileType::File, || SynthFile::node(net_dev()))];
+
+static SYS: [StaticEntry; 4] = [
+    ("fs", FileType::Dir, || {
+        VirtualNode::Dir(Arc::new(StaticDir(&SYS_FS)))
+    }),
+    ("kernel", FileType::Dir, || {
+        VirtualNode::Dir(Arc::new(StaticDir(&SYS_KERNEL)))
+    }),
+    ("net", FileType::Dir, || {
+        VirtualNode::Dir(Arc::new(StaticDir(&SYS_NET)))
+    }),
+    ("vm", FileType::Dir, || {
+        VirtualNode::Dir(Arc::new(StaticDir(&SYS_VM)))
+    }),
+];
+
+static SYS_FS: [StaticEntry; 2] = [
+    ("file-nr", FileType::File, || {
+        let total = live_files().map(|(_, count)| count).sum::<usize>();
+        SynthFile::node(format!("{}\t0\t{}\n", total, AX_FILE_LIMIT))
+    }),
+    // Not in Linux, the live files of each kind for finding leaks.
+    ("live-files", FileType::File, || {
+        let mut content = String::new();
+        for (name, count) in live_files() {
+            writeln!(content, "{}\t{}", name, count).unwrap();
+        }
+        SynthFile::node(content)
+    }),
+];
+
+static SYS_KERNEL: [StaticEntry; 4] = [
+    ("domainname", FileType::File, || {
+        UtsNameFile::node(UtsNameKind::Domainname)
+    }),
+    ("hostname", FileType::File, || {
+        UtsNameFile::node(UtsNameKind::Hostname)
+    }),
+    ("osrelease", FileType::File, || {
+        SynthFile::node(format!("{}\n", RELEASE))
+    }),
+    ("ostype", FileType::File, || {
+        SynthFile::node(format!("{}\n", SYSNAME))
+    }),
+];
+
+static SYS_NET: [StaticEntry; 1] = [("core", FileType::Dir, || {
+    VirtualNode::Dir(Arc::new(StaticDir(&SYS_NET_CORE)))
+})];
+
+static SYS_NET_CORE: [StaticEntry; 1] =
+    [("somaxconn", FileType::File, || SynthFile::node("4096\n"))];
+
+static SYS_VM: [StaticEntry; 2] = [
+    ("max_map_count", FileType::File, || {
+        Tunable::node(max_map_count(), set_max_map_count, CAP_SYS_ADMIN)
+    }),
+    ("overcommit_memory", FileType::File, || {
+        SynthFile::node("0\n")
+    }),
+];
+
+/// `/proc/starry`, which is not in Linux: knobs and records of the kernel.
+struct StarryDir;
+
+impl VirtualDir for StarryDir {
+    fn list_entries(&self) -> LinuxResult<Vec<VirtualDirEntry>> {
+        let mut entries = Vec::from([
+            VirtualDirEntry::new("audit", FileType::File),
+            VirtualDirEntry::new("audit_exec", FileType::File),
+            VirtualDirEntry::new("dac_enforce", FileType::File),
+            VirtualDirEntry::new("discard_zeroes", FileType::File),
+            VirtualDirEntry::new("fscache", FileType::File),
+            VirtualDirEntry::new("fstrim", FileType::File),
+            VirtualDirEntry::new("pressure", FileType::File),
+            VirtualDirEntry::new("released_mounts", FileType::File),
+            VirtualDirEntry::new("syscall_latency", FileType::File),
+        ]);
+        if cfg!(feature = "fault-inject") {
+            entries.push(VirtualDirEntry::new("fault_inject", FileType::File));
+        }
+        Ok(entries)
+    }
+
+    fn lookup(&self, name: &str) -> LinuxResult<VirtualNode> {
+        match name {
+            "audit" => {
+                // Like the audit log of Linux, which only the audit daemon
+                // reads.
+                require_capability(CAP_AUDIT_READ).map_err(|_| LinuxError::EACCES)?;
+                Ok(SynthFile::node(audit_records()))
+            }
+            "audit_exec" => Ok(Toggle::node(
+                exec_audit_enabled(),
+                set_exec_audit,
+                CAP_AUDIT_CONTROL,
+            )),
+            "dac_enforce" => Ok(Toggle::node(
+                dac_enforcing(),
+                set_dac_enforcing,
+                CAP_SYS_ADMIN,
+            )),
+            "discard_zeroes" => Ok(Toggle::node(
+                axfs::discard_zeroes(),
+                axfs::set_discard_zeroes,
+                CAP_SYS_ADMIN,
+            )),
+            #[cfg(feature = "fault-inject")]
+            "fault_inject" => Ok(FaultInjectFile::node()),
+            "fscache" => Ok(SynthFile::node(fscache())),
+            "fstrim" => Ok(FstrimFile::node()),
+            "pressure" => Ok(PressureFile::node()),
+            "released_mounts" => Ok(SynthFile::node(format!("{}\n", released_mounts()))),
+            "syscall_latency" => Ok(LatencyFile::node(None)),
+            _ => Err(LinuxError::ENOENT),
+        }
+    }
+}
+
+/// A knob of `/proc/starry` which reads `1` if it is on, and is turned on
+/// or off when `1` or `0` is written, with the capability `cap`.
+///
+/// `/proc/starry/audit_exec` turns auditing `execve` on,
+/// `/proc/starry/dac_enforce` enforcing file permission checks, and
+/// `/proc/starry/discard_zeroes` discarding the free clusters of the
+/// filesystems on block devices by writing zeros, see
+/// [`axfs::set_discard_zeroes`].
+struct Toggle {
+    content: SynthFile,
+    set: fn(bool),
+    cap: u32,
+}
+
+impl Toggle {
+    fn node(on: bool, set: fn(bool), cap: u32) -> VirtualNode {
+        VirtualNode::File(Arc::new(Self {
+            content: SynthFile::new(if on { "1\n" } else { "0\n" }),
+            set,
+            cap,
+        }))
+    }
+}
+
+impl FileLike for Toggle {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.content.read(buf)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        require_capability(self.cap)?;
+        match buf.trim_ascii() {
+            b"0" => (self.set)(false),
+            b"1" => (self.set)(true),
+            _ => return Err(LinuxError::EINVAL),
+        }
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: ((FileType::File as u32) << 12) | 0o644, // rw-r--r--
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+/// A number of `/proc/sys` which is set by writing it, with the capability
+/// `cap`, like `/proc/sys/vm/max_map_count`.
+struct Tunable {
+    content: SynthFile,
+    set: fn(usize),
+    cap: u32,
+}
+
+impl Tunable {
+    fn node(value: usize, set: fn(usize), cap: u32) -> VirtualNode {
+        VirtualNode::File(Arc::new(Self {
+            content: SynthFile::new(format!("{}\n", value)),
+            set,
+            cap,
+        }))
+    }
+}
+
+impl FileLike for Tunable {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.content.read(buf)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        require_capability(self.cap)?;
+        let value = core::str::from_utf8(buf.trim_ascii())
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .ok_or(LinuxError::EINVAL)?;
+        (self.set)(value);
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: ((FileType::File as u32) << 12) | 0o644, // rw-r--r--
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum UtsNameKind {
+    Hostname,
+    Domainname,
+}
+
+/// `/proc/sys/kernel/hostname` or `/proc/sys/kernel/domainname`, the name in
+/// the UTS namespace of the process which opened it, set by writing it, like
+/// `sethostname` and `setdomainname`.
+struct UtsNameFile {
+    uts: Arc<UtsNamespace>,
+    kind: UtsNameKind,
+    content: SynthFile,
+}
+
+impl UtsNameFile {
+    fn node(kind: UtsNameKind) -> VirtualNode {
+        let uts = current().task_ext().process_data().uts.read().clone();
+        let names = uts.names();
+        let mut content = match kind {
+            UtsNameKind::Hostname => names.hostname,
+            UtsNameKind::Domainname => names.domainname,
+        };
+        content.push(b'\n');
+        VirtualNode::File(Arc::new(Self {
+            uts,
+            kind,
+            content: SynthFile::new(content),
+        }))
+    }
+}
+
+impl FileLike for UtsNameFile {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.content.read(buf)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        require_capability(CAP_SYS_ADMIN)?;
+        let name = buf.strip_suffix(b"\n").unwrap_or(buf);
+        if name.len() > UTS_NAME_LEN {
+            return Err(LinuxError::EINVAL);
+        }
+        match self.kind {
+            UtsNameKind::Hostname => self.uts.set_hostname(name),
+            UtsNameKind::Domainname => self.uts.set_domainname(name),
+        }
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: ((FileType::File as u32) << 12) | 0o644, // rw-r--r--
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+/// `/proc/<pid>/syscall_latency` of a process, or
+/// `/proc/starry/syscall_latency` of the system, which are not in Linux: the
+/// latency histograms of the syscalls, see [`starry_core::latency`].
+///
+/// A line for each syscall made gives its name, the number of calls, their
+/// total time in nanoseconds, and then each bucket with calls, as `k:count`
+/// for the calls taking `[2^k, 2^(k+1))` nanoseconds, e.g.
+/// `read 12 50311 10:3 11:8 16:1`.
+///
+/// Writing `1` to the file of a process turns recording on, from empty
+/// histograms, and `0` turns it off. Writing `0` to the file of the system
+/// empties it.
+struct LatencyFile {
+    proc: Option<Arc<Process>>,
+    content: SynthFile,
+}
+
+impl LatencyFile {
+    fn node(proc: Option<Arc<Process>>) -> VirtualNode {
+        let content = SynthFile::new(syscall_latency(latency_of(&proc)));
+        VirtualNode::File(Arc::new(Self { proc, content }))
+    }
+}
+
+/// The histograms of `proc`, or of the system.
+fn latency_of(proc: &Option<Arc<Process>>) -> &SyscallLatency {
+    match proc {
+        Some(proc) => &proc.data::<ProcessData>().unwrap().syscall_latency,
+        None => &latency::GLOBAL,
+    }
+}
+
+fn syscall_latency(latency: &SyscallLatency) -> String {
+    let mut out = String::new();
+    for (nr, histogram) in latency.histograms() {
+        match Sysno::new(nr) {
+            Some(sysno) => write!(out, "{}", sysno.name()),
+            None => write!(out, "syscall_{}", nr),
+        }
+        .unwrap();
+        write!(out, " {} {}", histogram.count, histogram.total_ns).unwrap();
+        for (k, count) in histogram.buckets.iter().enumerate() {
+            if *count > 0 {
+                write!(out, " {}:{}", k, count).unwrap();
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+impl FileLike for LatencyFile {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.content.read(buf)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        let latency = latency_of(&self.proc);
+        match (buf.trim_ascii(), self.proc.is_some()) {
+            (b"0", true) => latency.set_enabled(false),
+            (b"1", true) => latency.set_enabled(true),
+            (b"0", false) => latency.clear(),
+            _ => return Err(LinuxError::EINVAL),
+        }
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: ((FileType::File as u32) << 12) | 0o644, // rw-r--r--
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+/// `/proc/starry/fault_inject`, with the `fault-inject` feature: a line for
+/// each point of [`starry_core::fault`] gives its name, the calls counted
+/// and failed since its policy was set, and the policy, e.g.
+/// `frame_alloc 12 4 every 3 pid 7`.
+///
+/// Writing a line of a point name and a policy, as `block_read after 10` or
+/// `fd_alloc off`, sets the policy of the point, with `CAP_SYS_ADMIN`.
+#[cfg(feature = "fault-inject")]
+struct FaultInjectFile {
+    content: SynthFile,
+}
+
+#[cfg(feature = "fault-inject")]
+impl FaultInjectFile {
+    fn node() -> VirtualNode {
+        use starry_core::fault::{FaultPoint, state};
+
+        let mut out = String::from("point calls failures policy\n");
+        for point in FaultPoint::ALL {
+            let state = state(point);
+            let _ = writeln!(
+                out,
+                "{} {} {} {}",
+                point.name(),
+                state.calls,
+                state.failures,
+                state.policy
+            );
+        }
+        VirtualNode::File(Arc::new(Self {
+            content: SynthFile::new(out),
+        }))
+    }
+}
+
+#[cfg(feature = "fault-inject")]
+impl FileLike for FaultInjectFile {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.content.read(buf)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        use starry_core::fault::{FaultPoint, set_policy};
+
+        require_capability(CAP_SYS_ADMIN)?;
+        let line = core::str::from_utf8(buf).map_err(|_| LinuxError::EINVAL)?;
+        let (name, policy) = line
+            .trim_ascii()
+            .split_once(' ')
+            .ok_or(LinuxError::EINVAL)?;
+        let point = FaultPoint::from_name(name).ok_or(LinuxError::EINVAL)?;
+        set_policy(point, policy.parse().map_err(|_| LinuxError::EINVAL)?);
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: ((FileType::File as u32) << 12) | 0o644, // rw-r--r--
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+/// `/proc/starry/pressure`: a line for each count of
+/// [`starry_core::pressure`] gives its name, the count and its soft limit,
+/// e.g. `zombies 3 256`. A blank line and a header follow, then a line for
+/// each of the [`PRESSURE_TOP`] processes holding the most, of those it can
+/// tell apart: the pipe ends open, the children not reaped and the signals
+/// pending. Another blank line and header follow, then a line for each of
+/// the [`PRESSURE_TOP`] descriptors which read and wrote the most bytes, with
+/// their counters, see [`IoCounters`]. A last blank line and header follow,
+/// then a line for each table of threads, processes, process groups and
+/// sessions by ID, with its live and dead entries and how many dead ones
+/// were scrubbed so far, see [`starry_core::pid_table`].
+///
+/// Writing a line of a name and a number, as `zombies 16`, sets the soft
+/// limit, 0 for none, with `CAP_SYS_ADMIN`.
+struct PressureFile {
+    content: SynthFile,
+}
+
+/// The most processes `/proc/starry/pressure` lists.
+const PRESSURE_TOP: usize = 5;
+
+impl PressureFile {
+    fn node() -> VirtualNode {
+        use starry_core::pressure::{Pressure, count, pending_signals_of, soft_limit};
+
+        let mut out = String::from("name count soft_limit\n");
+        for kind in Pressure::ALL {
+            let _ = writeln!(out, "{} {} {}", kind.name(), count(kind), soft_limit(kind));
+        }
+        let mut top: Vec<_> = processes()
+            .iter()
+            .map(|proc| {
+                let pipes = proc
+                    .data::<ProcessData>()
+                    .and_then(|data| FD_TABLE.of(data))
+                    .map_or(0, |table| {
+                        let table = table.read();
+                        table
+                            .ids()
+                            .filter(|&fd| {
+                                table
+                                    .get(fd)
+                                    .is_some_and(|f| f.clone().into_any().is::<Pipe>())
+                            })
+                            .count()
+                    });
+                let zombies = proc
+                    .children()
+                    .iter()
+                    .filter(|child| child.is_zombie())
+                    .count();
+                (proc.pid(), pipes, zombies, pending_signals_of(proc))
+            })
+            .filter(|&(_, pipes, zombies, signals)| pipes + zombies + signals > 0)
+            .collect();
+        top.sort_by_key(|&(pid, pipes, zombies, signals)| {
+            (core::cmp::Reverse(pipes + zombies + signals), pid)
+        });
+        out.push_str("\npid pipes zombies pending_signals\n");
+        for (pid, pipes, zombies, signals) in top.into_iter().take(PRESSURE_TOP) {
+            let _ = writeln!(out, "{} {} {} {}", pid, pipes, zombies, signals);
+        }
+        let mut busiest = Vec::new();
+        for proc in processes() {
+            let Some(table) = proc
+                .data::<ProcessData>()
+                .and_then(|data| FD_TABLE.of(data))
+            else {
+                continue;
+            };
+            let table = table.read();
+            busiest.extend(table.ids().filter_map(|fd| {
+                let stats = table.get(fd)?.io_counters()?.stats();
+                (stats.bytes() > 0).then_some((proc.pid(), fd, stats))
+            }));
+        }
+        busiest.sort_by_key(|&(pid, fd, stats)| (core::cmp::Reverse(stats.bytes()), pid, fd));
+        out.push_str("\npid fd reads read_bytes writes write_bytes\n");
+        for (pid, fd, stats) in busiest.into_iter().take(PRESSURE_TOP) {
+            let _ = writeln!(
+                out,
+                "{} {} {} {} {} {}",
+                pid, fd, stats.reads, stats.read_bytes, stats.writes, stats.write_bytes
+            );
+        }
+        out.push_str("\ntable live dead scrubbed\n");
+        for (name, stats) in table_stats() {
+            let _ = writeln!(
+                out,
+                "{} {} {} {}",
+                name, stats.live, stats.dead, stats.scrubbed
+            );
+        }
+        VirtualNode::File(Arc::new(Self {
+            content: SynthFile::new(out),
+        }))
+    }
+}
+
+impl FileLike for PressureFile {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.content.read(buf)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        use starry_core::pressure::{Pressure, set_soft_limit};
+
+        require_capability(CAP_SYS_ADMIN)?;
+        let line = core::str::from_utf8(buf).map_err(|_| LinuxError::EINVAL)?;
+        let (name, limit) = line
+            .trim_ascii()
+            .split_once(' ')
+            .ok_or(LinuxError::EINVAL)?;
+        let kind = Pressure::from_name(name).ok_or(LinuxError::EINVAL)?;
+        set_soft_limit(
+            kind,
+            limit.trim_ascii().parse().map_err(|_| LinuxError::EINVAL)?,
+        );
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: ((FileType::File as u32) << 12) | 0o644, // rw-r--r--
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+/// The root of `/proc`.
+pub struct ProcRoot;
+
+impl VirtualDir for ProcRoot {
+    fn list_entries(&self) -> LinuxResult<Vec<VirtualDirEntry>> {
+        let mut entries = Vec::from([
+            VirtualDirEntry::new("cpuinfo", FileType::File),
+            VirtualDirEntry::new("mounts", FileType::SymLink),
+            VirtualDirEntry::new("net", FileType::Dir),
+            VirtualDirEntry::new("self", FileType::Dir),
+            VirtualDirEntry::new("starry", FileType::Dir),
+            VirtualDirEntry::new("stat", FileType::File),
+            VirtualDirEntry::new("sys", FileType::Dir),
+            VirtualDirEntry::new("uptime", FileType::File),
+            VirtualDirEntry::new("vmstat", FileType::File),
+        ]);
+        entries.extend(
+            processes()
+                .iter()
+                .map(|proc| VirtualDirEntry::new(proc.pid().to_string(), FileType::Dir)),
+        );
+        Ok(entries)
+    }
+
+    fn lookup(&self, name: &str) -> LinuxResult<VirtualNode> {
+        let pid = match name {
+            "cpuinfo" => return Ok(SynthFile::node(cpuinfo())),
+            // Like Linux, which has moved it to each process.
+            "mounts" => {
+                return Ok(VirtualNode::Link {
+                    target: "/proc/self/mounts".into(),
+                    file: None,
+                });
+            }
+            "net" => return Ok(VirtualNode::Dir(Arc::new(StaticDir(&NET)))),
+            "self" => current().task_ext().thread.process().pid(),
+            "starry" => return Ok(VirtualNode::Dir(Arc::new(StarryDir))),
+            "stat" => return Ok(SynthFile::node(system_stat())),
+            "sys" => return Ok(VirtualNode::Dir(Arc::new(StaticDir(&SYS)))),
+            "uptime" => return Ok(SynthFile::node(uptime())),
+            "vmstat" => {
+                return Ok(SynthFile::node(format!(
+                    "pgfault {}\npgmajfault 0\n",
+                    stats::page_faults()
+                )));
+            }
+            _ => name.parse().map_err(|_| LinuxError::ENOENT)?,
+        };
+        // Make sure the process exists
+        get_user_process(pid)?;
+        Ok(VirtualNode::Dir(Arc::new(ProcessDir { pid })))
+    }
+}
+
+/// Get the process `pid`, making sure it has [`ProcessData`].
+fn get_user_process(pid: Pid) -> LinuxResult<Arc<Process>> {
+    let proc = get_process(pid).map_err(|_| LinuxError::ENOENT)?;
+    proc.data::<ProcessData>().ok_or(LinuxError::ENOENT)?;
+    Ok(proc)
+}
+
+/// The content of `/proc/net/dev`, the traffic of each network interface.
+///
+/// Errors, drops and the like are not counted, and are always 0.
+fn net_dev() -> String {
+    let mut dev = String::from(
+        "Inter-|   Receive                                                |  Transmit\n \
+         face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets \
+         errs drop fifo colls carrier compressed\n",
+    );
+    for iface in axnet::interfaces() {
+        let stats = iface.stats;
+        writeln!(
+            dev,
+            "{:>6}:{:>8} {:>7}    0    0    0     0          0         0 {:>8} {:>7}    0    0    0     0       0          0",
+            iface.name, stats.rx_bytes, stats.rx_packets, stats.tx_bytes, stats.tx_packets
+        )
+        .unwrap();
+    }
+    dev
+}
+
+/// The content of `/proc/stat`, see `proc_stat(5)`.
+///
+/// The time of each CPU not spent by user tasks is reported as idle, and
+/// `procs_blocked` is always 0, since waits for I/O are not told apart from
+/// other sleeps.
+fn system_stat() -> String {
+    let uptime = stats::uptime_nanos();
+    let cpus = (0..axconfig::SMP)
+        .map(|cpu| {
+            let (user, system) = stats::cpu_time(cpu);
+            (
+                user,
+                system,
+                uptime.saturating_sub(user + system),
+                stats::io_wait_time(cpu),
+            )
+        })
+        .collect::<Vec<_>>();
+    let (user, system, idle, iowait) = cpus.iter().fold((0, 0, 0, 0), |acc, cpu| {
+        (acc.0 + cpu.0, acc.1 + cpu.1, acc.2 + cpu.2, acc.3 + cpu.3)
+    });
+    let ticks = stats::nanos_to_user_ticks;
+
+    let mut stat = format!(
+        "cpu  {} 0 {} {} {} 0 0 0 0 0\n",
+        ticks(user),
+        ticks(system),
+        ticks(idle),
+        ticks(iowait)
+    );
+    for (cpu, (user, system, idle, iowait)) in cpus.into_iter().enumerate() {
+        writeln!(
+            stat,
+            "cpu{} {} 0 {} {} {} 0 0 0 0 0",
+            cpu,
+            ticks(user),
+            ticks(system),
+            ticks(idle),
+            ticks(iowait)
+        )
+        .unwrap();
+    }
+    let threads = processes()
+        .iter()
+        .flat_map(|proc| proc.threads())
+        .collect::<Vec<_>>();
+    let running = threads
+        .iter()
+        .filter_map(|thread| thread.data::<ThreadData>()?.task())
+        .filter(|task| matches!(task.state(), TaskState::Running | TaskState::Ready))
+        .count();
+    let blocked = threads
+        .iter()
+        .filter(|thread| {
+            thread
+                .data::<ThreadData>()
+                .is_some_and(ThreadData::in_io_wait)
+        })
+        .count();
+    write!(
+        stat,
+        "intr {}\nctxt {}\nbtime {}\nprocesses {}\nprocs_running {}\nprocs_blocked {}\n",
+        axhal::irq::irq_count(),
+        axtask::context_switches(),
+        stats::boot_time_nanos() / NANOS_PER_SEC,
+        stats::forks(),
+        running,
+        blocked,
+    )
+    .unwrap();
+    stat
+}
+
+/// The content of `/proc/cpuinfo`, a block for each CPU in the format of
+/// the architecture, see [`axhal::hwcap`].
+fn cpuinfo() -> String {
+    let mut out = String::new();
+    for cpu in 0..axconfig::SMP {
+        axhal::hwcap::write_cpuinfo(&mut out, cpu).unwrap();
+    }
+    out
+}
+
+/// The content of `/proc/uptime`, see `proc_uptime(5)`.
+///
+/// The idle time is summed over all CPUs, like on Linux, so it may be larger
+/// than the uptime.
+fn uptime() -> String {
+    let secs = |ns: u64| {
+        let centis = ns / (NANOS_PER_SEC / 100);
+        format!("{}.{:02}", centis / 100, centis % 100)
+    };
+    format!(
+        "{} {}\n",
+        secs(stats::uptime_nanos()),
+        secs(stats::idle_time_nanos())
+    )
+}
+
+/// `/proc/starry/fscache`: the counters of the block cache of each block
+/// device, one device a line after a header.
+fn fscache() -> String {
+    let mut out = String::from(
+        "device capacity cached dirty hits misses written_back writeback_requests read_ahead\n",
+    );
+    for dev in axfs::block_devices() {
+        let stats = dev.cache_stats();
+        let _ = writeln!(
+            out,
+            "{} {} {} {} {} {} {} {} {}",
+            dev.name(),
+            stats.capacity,
+            stats.cached,
+            stats.dirty,
+            stats.hits,
+            stats.misses,
+            stats.written_back,
+            stats.writeback_requests,
+            stats.read_ahead
+        );
+    }
+    out
+}
+
+/// `/proc/starry/fstrim`: the counters of the passes discarding the free
+/// clusters of the filesystem on each block device which has one that can
+/// be trimmed, one device a line after a header.
+///
+/// Writing the name of a device, as `vda`, discards every free cluster on
+/// it, as `fstrim` does, with `CAP_SYS_ADMIN`. It fails with `EOPNOTSUPP`
+/// if the device cannot be trimmed, or `/proc/starry/discard_zeroes` is 0.
+struct FstrimFile {
+    content: SynthFile,
+}
+
+impl FstrimFile {
+    fn node() -> VirtualNode {
+        let mut out = String::from("device passes discarded requests\n");
+        for dev in axfs::block_devices() {
+            if let Some(stats) = dev.trim_stats() {
+                let _ = writeln!(
+                    out,
+                    "{} {} {} {}",
+                    dev.name(),
+                    stats.passes,
+                    stats.discarded,
+                    stats.requests
+                );
+            }
+        }
+        VirtualNode::File(Arc::new(Self {
+            content: SynthFile::new(out),
+        }))
+    }
+}
+
+impl FileLike for FstrimFile {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.content.read(buf)
+    }
+
+    fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
+        require_capability(CAP_SYS_ADMIN)?;
+        let name = core::str::from_utf8(buf).map_err(|_| LinuxError::EINVAL)?;
+        let dev = axfs::block_devices()
+            .iter()
+            .find(|dev| dev.name() == name.trim_ascii())
+            .ok_or(LinuxError::EINVAL)?;
+        if dev.trim_stats().is_none() || !axfs::discard_zeroes() {
+            return Err(LinuxError::EOPNOTSUPP);
+        }
+        dev.trim().map_err(|_| LinuxError::EIO)?;
+        Ok(buf.len())
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            mode: ((FileType::File as u32) << 12) | 0o644, // rw-r--r--
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: true,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+/// `/proc/<pid>`.
+struct ProcessDir {
+    pid: Pid,
+}
+
+impl VirtualDir for ProcessDir {
+    fn list_entries(&self) -> LinuxResult<Vec<VirtualDirEntry>> {
+        Ok(Vec::from([
+            VirtualDirEntry::new("cmdline", FileType::File),
+            VirtualDirEntry::new("cwd", FileType::SymLink),
+            VirtualDirEntry::new("environ", FileType::File),
+            VirtualDirEntry::new("exe", FileType::SymLink),
+            VirtualDirEntry::new("fd", FileType::Dir),
+            VirtualDirEntry::new("fdinfo", FileType::Dir),
+            VirtualDirEntry::new("limits", FileType::File),
+            VirtualDirEntry::new("maps", FileType::File),
+            VirtualDirEntry::new("mountinfo", FileType::File),
+            VirtualDirEntry::new("mounts", FileType::File),
+            VirtualDirEntry::new("stat", FileType::File),
+            VirtualDirEntry::new("status", FileType::File),
+            VirtualDirEntry::new("syscall_latency", FileType::File),
+            VirtualDirEntry::new("task", FileType::Dir),
+        ]))
+    }
+
+    fn lookup(&self, name: &str) -> LinuxResult<VirtualNode> {
+        let proc = get_user_process(self.pid)?;
+        let data = proc.data::<ProcessData>().unwrap();
+        match name {
+            "cmdline" => Ok(SynthFile::node(data.exec_args.read().argv.clone())),
+            "cwd" => {
+                let cwd = CURRENT_DIR_PATH.deref_from(&data.ns);
+                if !cwd.is_inited() {
+                    return Err(LinuxError::ENOENT);
+                }
+                let cwd = cwd.get().lock().clone();
+                Ok(VirtualNode::Link {
+                    target: match cwd.trim_end_matches('/') {
+                        "" => "/".into(),
+                        cwd => cwd.into(),
+                    },
+                    file: None,
+                })
+            }
+            "environ" => Ok(SynthFile::node(data.exec_args.read().envp.clone())),
+            "exe" => Ok(VirtualNode::Link {
+                target: data.exe_path.read().clone(),
+                file: None,
+            }),
+            "fd" => Ok(VirtualNode::Dir(Arc::new(FdDir { pid: self.pid }))),
+            "fdinfo" => Ok(VirtualNode::Dir(Arc::new(FdInfoDir {
+                fds: FdDir { pid: self.pid },
+            }))),
+            "limits" => Ok(SynthFile::node(limits(&data.rlimits.read()))),
+            "maps" => Ok(SynthFile::node(maps(&proc))),
+            "mountinfo" => Ok(SynthFile::node(mountinfo(&proc))),
+            "mounts" => Ok(SynthFile::node(mounts(&proc))),
+            "stat" => Ok(SynthFile::node(ProcessInfo::new(&proc).stat())),
+            "status" => Ok(SynthFile::node(ProcessInfo::new(&proc).status())),
+            "syscall_latency" => Ok(LatencyFile::node(Some(proc.clone()))),
+            "task" => Ok(VirtualNode::Dir(Arc::new(TaskDir { pid: self.pid }))),
+            _ => Err(LinuxError::ENOENT),
+        }
+    }
+}
+
+/// The names and units of the resources in `/proc/<pid>/limits`, in the
+/// order of their numbers.
+const LIMIT_NAMES: [(&str, &str); RLIM_NLIMITS] = [
+    ("Max cpu time", "seconds"),
+    ("Max file size", "bytes"),
+    ("Max data size", "bytes"),
+    ("Max stack size", "bytes"),
+    ("Max core file size", "bytes"),
+    ("Max resident set", "bytes"),
+    ("Max processes", "processes"),
+    ("Max open files", "files"),
+    ("Max locked memory", "bytes"),
+    ("Max address space", "bytes"),
+    ("Max file locks", "locks"),
+    ("Max pending signals", "signals"),
+    ("Max msgqueue size", "bytes"),
+    ("Max nice priority", ""),
+    ("Max realtime priority", ""),
+    ("Max realtime timeout", "us"),
+];
+
+/// The content of `/proc/<pid>/limits`, a row of the soft and hard limit
+/// of each resource.
+fn limits(rlimits: &Rlimits) -> String {
+    let value = |limit: u64| {
+        if limit == RLIM_INFINITY {
+            "unlimited".to_string()
+        } else {
+            limit.to_string()
+        }
+    };
+    let mut content = format!(
+        "{:<25} {:<20} {:<20} {:<10}\n",
+        "Limit", "Soft Limit", "Hard Limit", "Units"
+    );
+    for (resource, (name, unit)) in LIMIT_NAMES.iter().enumerate() {
+        let limit = rlimits.get(resource as u32).unwrap();
+        write!(
+            content,
+            "{:<25} {:<20} {:<20} ",
+            name,
+            value(limit.cur),
+            value(limit.max)
+        )
+        .unwrap();
+        if !unit.is_empty() {
+            write!(content, "{:<10}", unit).unwrap();
+        }
+        content.push('\n');
+    }
+    content
+}
+
+/// The content of `/proc/<pid>/maps`, a line for each mapped area, named
+/// after its kind.
+///
+/// The area holding the stack pointer a thread was created with is named
+/// after the thread, as `[stack:<tid>]`, whether or not it was mapped with
+/// `MAP_STACK`. The heap is one `[heap]` line, as in Linux, since the areas
+/// `brk` grows it by are merged, and only the signal trampoline is mapped
+/// linearly. Nothing is known of the device and inode of a mapped file, and
+/// file mappings are copies, so the offset is always 0.
+fn maps(proc: &Process) -> String {
+    // The address space of a zombie is gone as far as user space knows.
+    if proc.is_zombie() {
+        return String::new();
+    }
+    let stacks: Vec<_> = proc
+        .threads()
+        .iter()
+        .filter_map(|thread| {
+            let sp = thread.data::<ThreadData>()?.initial_sp();
+            (sp != 0).then_some((sp, thread.tid()))
+        })
+        .collect();
+    let data = proc.data::<ProcessData>().unwrap();
+    let mut content = String::new();
+    for (range, flags, kind) in data.lock_aspace().areas() {
+        let perm = |flag, c| if flags.contains(flag) { c } else { '-' };
+        write!(
+            content,
+            "{:08x}-{:08x} {}{}{}{} 00000000 00:00 0",
+            range.start.as_usize(),
+            range.end.as_usize(),
+            perm(MappingFlags::READ, 'r'),
+            perm(MappingFlags::WRITE, 'w'),
+            perm(MappingFlags::EXECUTE, 'x'),
+            if kind == AreaKind::Shared { 's' } else { 'p' },
+        )
+        .unwrap();
+        let thread = stacks
+            .iter()
+            .find(|(sp, _)| range.start.as_usize() < *sp && *sp <= range.end.as_usize());
+        let name = match (thread, kind) {
+            (Some((_, tid)), _) => format!("[stack:{}]", tid),
+            (None, AreaKind::Heap) => "[heap]".into(),
+            (None, AreaKind::Stack) => "[stack]".into(),
+            (None, AreaKind::ThreadStack) => "[thread stack]".into(),
+            (None, AreaKind::File(path)) => path.to_string(),
+            (None, AreaKind::Linear) => "[sigpage]".into(),
+            (None, _) => String::new(),
+        };
+        if !name.is_empty() {
+            write!(content, " {}", name).unwrap();
+        }
+        content.push('\n');
+    }
+    content
+}
+
+/// Escape the blanks and backslashes of `field` of a mount table line, as
+/// octal like Linux, e.g. a space as `\040`.
+fn mount_field(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            ' ' | '\t' | '\n' | '\\' => write!(out, "\\{:03o}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// The options of `mount` as a mount table lists them, `rw` or `ro` first.
+fn mount_options_field(mount: &MountInfo) -> String {
+    let mode = if mount.read_only { "ro" } else { "rw" };
+    match mount.fs_options.as_str() {
+        "" => mode.into(),
+        options => format!("{},{}", mode, options),
+    }
+}
+
+/// `/proc/<pid>/mounts`, which `/proc/mounts` links to: a line for each
+/// mount `proc` sees, as `<source> <mount point> <type> <options> 0 0`.
+fn mounts(proc: &Process) -> String {
+    let mut content = String::new();
+    for mount in mounts_of(proc.data::<ProcessData>().unwrap()) {
+        writeln!(
+            content,
+            "{} {} {} {} 0 0",
+            mount_field(&mount.source),
+            mount_field(&mount.mount_point),
+            mount.fs_type,
+            mount_options_field(&mount),
+        )
+        .unwrap();
+    }
+    content
+}
+
+/// `/proc/<pid>/mountinfo`: a line for each mount `proc` sees, as
+/// `<id> <parent id> <major>:<minor> <root> <mount point> <mount options>
+/// - <type> <source> <options>`. The root of each mount is that of its
+/// filesystem, and the mount options are only `rw` or `ro`, which the
+/// options of the filesystem repeat.
+fn mountinfo(proc: &Process) -> String {
+    let mut content = String::new();
+    for mount in mounts_of(proc.data::<ProcessData>().unwrap()) {
+        writeln!(
+            content,
+            "{} {} {}:{} / {} {} - {} {} {}",
+            mount.id,
+            mount.parent_id,
+            mount.dev.0,
+            mount.dev.1,
+            mount_field(&mount.mount_point),
+            if mount.read_only { "ro" } else { "rw" },
+            mount.fs_type,
+            mount_field(&mount.source),
+            mount_options_field(&mount),
+        )
+        .unwrap();
+    }
+    content
+}
+
+/// The bytes mapped for each kind of area, which add up to the mapped
+/// size, as reported by `/proc/<pid>/status`.
+#[derive(Default)]
+struct VmKinds {
+    heap: usize,
+    stack: usize,
+    thread_stack: usize,
+    file: usize,
+    anonymous: usize,
+    shared: usize,
+    linear: usize,
+}
+
+impl VmKinds {
+    fn add(&mut self, kind: &AreaKind, size: usize) {
+        *match kind {
+            AreaKind::Heap => &mut self.heap,
+            AreaKind::Stack => &mut self.stack,
+            AreaKind::ThreadStack => &mut self.thread_stack,
+            AreaKind::File(_) => &mut self.file,
+            AreaKind::Anonymous => &mut self.anonymous,
+            AreaKind::Shared => &mut self.shared,
+            AreaKind::Linear => &mut self.linear,
+        } += size;
+    }
+}
+
+/// `/proc/<pid>/task`.
+struct TaskDir {
+    pid: Pid,
+}
+
+impl VirtualDir for TaskDir {
+    fn list_entries(&self) -> LinuxResult<Vec<VirtualDirEntry>> {
+        let proc = get_user_process(self.pid)?;
+        Ok(proc
+            .threads()
+            .iter()
+            .map(|thread| VirtualDirEntry::new(thread.tid().to_string(), FileType::Dir))
+            .collect())
+    }
+
+    fn lookup(&self, name: &str) -> LinuxResult<VirtualNode> {
+        let tid: Pid = name.parse().map_err(|_| LinuxError::ENOENT)?;
+        let proc = get_user_process(self.pid)?;
+        // Make sure the thread belongs to the process
+        if !proc.threads().iter().any(|thread| thread.tid() == tid) {
+            return Err(LinuxError::ENOENT);
+        }
+        Ok(VirtualNode::Dir(Arc::new(ThreadDir { pid: self.pid, tid })))
+    }
+}
+
+/// `/proc/<pid>/task/<tid>`.
+struct ThreadDir {
+    pid: Pid,
+    tid: Pid,
+}
+
+impl VirtualDir for ThreadDir {
+    fn list_entries(&self) -> LinuxResult<Vec<VirtualDirEntry>> {
+        Ok(Vec::from([
+            VirtualDirEntry::new("stat", FileType::File),
+            VirtualDirEntry::new("status", FileType::File),
+        ]))
+    }
+
+    fn lookup(&self, name: &str) -> LinuxResult<VirtualNode> {
+        let proc = get_user_process(self.pid)?;
+        let thread = proc
+            .threads()
+            .into_iter()
+            .find(|thread| thread.tid() == self.tid)
+            .ok_or(LinuxError::ENOENT)?;
+        match name {
+            "stat" => Ok(SynthFile::node(
+                ProcessInfo::of_thread(&proc, &thread).stat(),
+            )),
+            "status" => Ok(SynthFile::node(
+                ProcessInfo::of_thread(&proc, &thread).status(),
+            )),
+            _ => Err(LinuxError::ENOENT),
+        }
+    }
+}
+
+/// A snapshot of a process or one of its threads, as reported by
+/// `/proc/<pid>/stat` and `/proc/<pid>/status`.
+struct ProcessInfo {
+    pid: Pid,
+    /// The thread id, which is the process id for the process itself.
+    tid: Pid,
+    comm: String,
+    state: char,
+    ppid: Pid,
+    pgrp: Pid,
+    session: Pid,
+    utime: usize,
+    stime: usize,
+    /// The user and system time of the reaped children, in clock ticks.
+    cutime: usize,
+    cstime: usize,
+    num_threads: usize,
+    /// The time the process started after boot, in clock ticks.
+    start_time: u64,
+    vsize: usize,
+    rss: usize,
+    vm_kinds: VmKinds,
+    /// The number of areas of the address space.
+    map_count: usize,
+    /// The number of slots of the file descriptor table.
+    fd_size: usize,
+    filtered: bool,
+    /// The voluntary and involuntary context switches.
+    ctxt_switches: (u64, u64),
+    /// The time spent waiting for block devices, in nanoseconds.
+    io_delay_ns: u64,
+}
+
+/// Whether `thread` is waiting for a block device.
+fn in_io_wait(thread: &Thread) -> bool {
+    thread
+        .data::<ThreadData>()
+        .is_some_and(ThreadData::in_io_wait)
+}
+
+/// The voluntary and involuntary context switches of `thread`.
+fn context_switches(thread: &Thread) -> (u64, u64) {
+    thread
+        .data::<ThreadData>()
+        .and_then(ThreadData::task)
+        .map_or((0, 0), |task| task.context_switches())
+}
+
+impl ProcessInfo {
+    fn new(proc: &Process) -> Self {
+        let data = proc.data::<ProcessData>().unwrap();
+        let exe_path = data.exe_path.read();
+        let name = exe_path.rsplit('/').next().unwrap_or_default();
+        let curr_pid = current().task_ext().thread.process().pid();
+        // TODO: tell sleeping processes from runnable ones
+        let state = if proc.is_zombie() {
+            'Z'
+        } else if proc.threads().iter().any(|thread| in_io_wait(thread)) {
+            'D'
+        } else if proc.pid() == curr_pid {
+            'R'
+        } else {
+            'S'
+        };
+        // The address space of a zombie is gone as far as user space knows.
+        let mut vm_kinds = VmKinds::default();
+        let (vsize, rss, map_count) = if proc.is_zombie() {
+            (0, 0, 0)
+        } else {
+            let aspace = data.lock_aspace();
+            for (range, _, kind) in aspace.areas() {
+                vm_kinds.add(&kind, range.size());
+            }
+            (
+                aspace.mapped_size(),
+                aspace.resident_size(),
+                aspace.area_count(),
+            )
+        };
+        let (utime_ns, stime_ns) = data.times().own();
+        let (cutime_ns, cstime_ns) = data.times().children();
+        let ctxt_switches = proc
+            .threads()
+            .iter()
+            .map(|thread| context_switches(thread))
+            .fold((0, 0), |acc, it| (acc.0 + it.0, acc.1 + it.1));
+        let group = proc.group();
+        Self {
+            pid: proc.pid(),
+            tid: proc.pid(),
+            // Like Linux, truncate to `TASK_COMM_LEN - 1` bytes.
+            comm: name
+                .chars()
+                .scan(0, |len, c| {
+                    *len += c.len_utf8();
+                    (*len <= 15).then_some(c)
+                })
+                .collect(),
+            state,
+            ppid: proc.parent().map_or(0, |p| p.pid()),
+            pgrp: group.pgid(),
+            session: group.session().sid(),
+            utime: stats::nanos_to_user_ticks(utime_ns as u64) as usize,
+            stime: stats::nanos_to_user_ticks(stime_ns as u64) as usize,
+            cutime: stats::nanos_to_user_ticks(cutime_ns as u64) as usize,
+            cstime: stats::nanos_to_user_ticks(cstime_ns as u64) as usize,
+            num_threads: proc.threads().len(),
+            start_time: stats::nanos_to_user_ticks(data.start_time_ns),
+            vsize,
+            rss,
+            vm_kinds,
+            map_count,
+            fd_size: FD_TABLE.of(data).map_or(0, |table| table.read().capacity()),
+            filtered: !data.syscall_filters.read().is_empty(),
+            ctxt_switches,
+            io_delay_ns: data.io_delay_ns(),
+        }
+    }
+
+    /// Take a snapshot of the thread `thread` of `proc`, which only differs
+    /// from the one of the process in its id, state, times and context
+    /// switches.
+    fn of_thread(proc: &Process, thread: &Thread) -> Self {
+        let mut info = Self::new(proc);
+        info.tid = thread.tid();
+        if in_io_wait(thread) {
+            info.state = 'D';
+        } else if matches!(info.state, 'R' | 'D') {
+            info.state = if thread.tid() == current().task_ext().thread.tid() {
+                'R'
+            } else {
+                'S'
+            };
+        }
+        let (utime_ns, stime_ns) = thread
+            .data::<ThreadData>()
+            .map_or((0, 0), ThreadData::cpu_time);
+        info.utime = stats::nanos_to_user_ticks(utime_ns as u64) as usize;
+        info.stime = stats::nanos_to_user_ticks(stime_ns as u64) as usize;
+        info.ctxt_switches = context_switches(thread);
+        info
+    }
+
+    /// The content of `/proc/<pid>/stat`, see `proc_pid_stat(5)`.
+    fn stat(&self) -> String {
+        let mut fields = [0usize; 52];
+        fields[13] = self.utime;
+        fields[14] = self.stime;
+        fields[15] = self.cutime;
+        fields[16] = self.cstime;
+        fields[19] = self.num_threads;
+        fields[21] = self.start_time as usize;
+        fields[22] = self.vsize;
+        fields[23] = self.rss / PAGE_SIZE;
+        fields[41] = stats::nanos_to_user_ticks(self.io_delay_ns) as usize;
+        // Fields 1 to 6 are written below, since they are not all numbers.
+        let mut stat = format!(
+            "{} ({}) {} {} {} {}",
+            self.tid, self.comm, self.state, self.ppid, self.pgrp, self.session
+        );
+        for field in &fields[6..] {
+            write!(stat, " {}", field).unwrap();
+        }
+        stat.push('\n');
+        stat
+    }
+
+    /// The content of `/proc/<pid>/status`, see `proc_pid_status(5)`.
+    ///
+    /// `VmSize` is broken down by the kinds of the areas, into `VmHeap`,
+    /// `VmStk`, `VmThreadStk`, `VmFile`, `VmAnon`, `VmShared` and `VmLinear`.
+    /// `MapCount`, which is not in Linux either, is the number of areas,
+    /// which `/proc/sys/vm/max_map_count` limits.
+    fn status(&self) -> String {
+        let kinds = &self.vm_kinds;
+        let state = match self.state {
+            'R' => "R (running)",
+            'S' => "S (sleeping)",
+            'D' => "D (disk sleep)",
+            _ => "Z (zombie)",
+        };
+        // The saved and filesystem ids follow the effective ones.
+        format!(
+            "Name:\t{}\nState:\t{}\nTgid:\t{}\nPid:\t{}\nPPid:\t{}\n\
+             Uid:\t{real}\t{effective}\t{effective}\t{effective}\n\
+             Gid:\t{real}\t{effective}\t{effective}\t{effective}\nFDSize:\t{}\n\
+             VmSize:\t{:8} kB\nVmRSS:\t{:8} kB\nVmHeap:\t{:8} kB\nVmStk:\t{:8} kB\n\
+             VmThreadStk:\t{:8} kB\nVmFile:\t{:8} kB\nVmAnon:\t{:8} kB\n\
+             VmShared:\t{:8} kB\nVmLinear:\t{:8} kB\nMapCount:\t{}\nThreads:\t{}\nSeccomp:\t{}\n\
+             voluntary_ctxt_switches:\t{}\nnonvoluntary_ctxt_switches:\t{}\n\
+             IoDelay:\t{} us\n",
+            self.comm,
+            state,
+            self.pid,
+            self.tid,
+            self.ppid,
+            self.fd_size,
+            self.vsize / 1024,
+            self.rss / 1024,
+            kinds.heap / 1024,
+            kinds.stack / 1024,
+            kinds.thread_stack / 1024,
+            kinds.file / 1024,
+            kinds.anonymous / 1024,
+            kinds.shared / 1024,
+            kinds.linear / 1024,
+            self.map_count,
+            self.num_threads,
+            if self.filtered { 2 } else { 0 },
+            self.ctxt_switches.0,
+            self.ctxt_switches.1,
+            self.io_delay_ns / NANOS_PER_MICROS,
+            real = REAL_ID,
+            effective = EFFECTIVE_ID,
+        )
+    }
+}
+
+/// `/proc/<pid>/fd`.
+struct FdDir {
+    pid: Pid,
+}
+
+impl FdDir {
+    fn table(&self) -> LinuxResult<Arc<FdTable>> {
+        let proc = get_user_process(self.pid)?;
+        FD_TABLE
+            .of(proc.data::<ProcessData>().unwrap())
+            .ok_or(LinuxError::ENOENT)
+    }
+}
+
+impl VirtualDir for FdDir {
+    fn list_entries(&self) -> LinuxResult<Vec<VirtualDirEntry>> {
+        Ok(self
+            .table()?
+            .read()
+            .ids()
+            .map(|fd| VirtualDirEntry::new(fd.to_string(), FileType::SymLink))
+            .collect())
+    }
+
+    fn lookup(&self, name: &str) -> LinuxResult<VirtualNode> {
+        let fd: usize = name.parse().map_err(|_| LinuxError::ENOENT)?;
+        let file = self
+            .table()?
+            .read()
+            .get(fd)
+            .cloned()
+            .ok_or(LinuxError::ENOENT)?;
+        Ok(VirtualNode::Link {
+            target: link_target(file.clone()),
+            file: Some(file),
+        })
+    }
+}
+
+/// `/proc/<pid>/fdinfo`, with a file for each descriptor of `fds`.
+struct FdInfoDir {
+    fds: FdDir,
+}
+
+impl VirtualDir for FdInfoDir {
+    fn list_entries(&self) -> LinuxResult<Vec<VirtualDirEntry>> {
+        Ok(self
+            .fds
+            .table()?
+            .read()
+            .ids()
+            .map(|fd| VirtualDirEntry::new(fd.to_string(), FileType::File))
+            .collect())
+    }
+
+    fn lookup(&self, name: &str) -> LinuxResult<VirtualNode> {
+        let fd: usize = name.parse().map_err(|_| LinuxError::ENOENT)?;
+        let table = self.fds.table()?;
+        let table = table.read();
+        let file = table.get(fd).cloned().ok_or(LinuxError::ENOENT)?;
+        let cloexec = table.cloexec(fd) == Some(true);
+        drop(table);
+        Ok(SynthFile::node(fdinfo(&file, cloexec)))
+    }
+}
+
+/// The content of `/proc/<pid>/fdinfo/<fd>` for `file`: the offset, the
+/// flags in octal and a `mnt_id` of 0, as on Linux, then the counters of
+/// the reads and writes through it, if it keeps them, see [`IoCounters`].
+fn fdinfo(file: &Arc<dyn FileLike>, cloexec: bool) -> String {
+    let mut flags = file.status_flags();
+    if cloexec {
+        flags |= O_CLOEXEC;
+    }
+    let mut out = format!(
+        "pos:\t{}\nflags:\t0{:o}\nmnt_id:\t0\n",
+        file_pos(file),
+        flags
+    );
+    if let Some(stats) = file.io_counters().map(IoCounters::stats) {
+        let _ = write!(
+            out,
+            "reads:\t{}\nread_bytes:\t{}\nwrites:\t{}\nwrite_bytes:\t{}\n",
+            stats.reads, stats.read_bytes, stats.writes, stats.write_bytes
+        );
+    }
+    out
+}
+
+/// The offset of `file`, 0 for the files which have none.
+fn file_pos(file: &Arc<dyn FileLike>) -> u64 {
+    let any = file.clone().into_any();
+    if let Some(file) = any.downcast_ref::<File>() {
+        file.inner().seek(SeekFrom::Current(0)).unwrap_or(0)
+    } else if let Some(dir) = any.downcast_ref::<Directory>() {
+        *dir.pos().lock() as u64
+    } else {
+        0
+    }
+}
+
+/// The target of the link to `file` in `/proc/<pid>/fd`.
+fn link_target(file: Arc<dyn FileLike>) -> String {
+    let any = file.into_any();
+    #[cfg(feature = "io_uring")]
+    if any.is::<super::IoUring>() {
+        return "anon_inode:[io_uring]".into();
+    }
+    if let Some(file) = any.downcast_ref::<File>() {
+        file.path().into()
+    } else if let Some(dir) = any.downcast_ref::<Directory>() {
+        dir.path().into()
+    } else if let Some(dir) = any.downcast_ref::<VirtualDirFile>() {
+        dir.path().into()
+    } else if let Some(pipe) = any.downcast_ref::<Pipe>() {
+        format!("pipe:[{}]", pipe.ino())
+    } else if let Some(socket) = any.downcast_ref::<Socket>() {
+        format!("socket:[{}]", socket.ino())
+    } else if any.is::<Stdin>() || any.is::<Stdout>() {
+        "/dev/console".into()
+    } else if any.is::<DevNull>() {
+        "/dev/null".into()
+    } else if any.is::<DevZero>() {
+        "/dev/zero".into()
+    } else if any.is::<DevRandom>() {
+        "/dev/random".into()
+    } else if any.is::<DevUrandom>() {
+        "/dev/urandom".into()
+    } else if let Some(dev) = any.downcast_ref::<BlockFile>() {
+        format!("/dev/{}", dev.name())
+    } else if let Some(mq) = any.downcast_ref::<MqFd>() {
+        format!("/dev/mqueue/{}", mq.queue().name())
+    } else {
+        "anon_inode:[unknown]".into()
+    }
+}
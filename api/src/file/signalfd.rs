@@ -0,0 +1,192 @@
+//! `signalfd`, which takes signals by reading a file rather than by running
+//! handlers, see `signalfd(2)`.
+//!
+//! A read takes the signals of the mask pending for the thread reading, or
+//! for its process, as `sigtimedwait` does, so it is the reader which
+//! matters, not the thread which created the file. The signals of the mask
+//! are still delivered as usual unless they are blocked, which callers do
+//! first; one delivered before the read is gone for the read.
+
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::monotonic_time;
+use axio::PollState;
+use axsignal::{SignalInfo, SignalSet, Signo};
+use axsync::Mutex;
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::{O_NONBLOCK, O_RDWR, SI_MESGQ, SI_QUEUE, SI_TIMER};
+
+use super::{FileKind, FileLike, Kstat, LiveFile, alloc_anon_ino};
+use crate::signal::{dequeue_signal_in, signal_dequeued, take_signal_in};
+
+/// `struct signalfd_siginfo`, the record read for each signal.
+#[repr(C)]
+#[derive(Default)]
+struct SignalfdSiginfo {
+    ssi_signo: u32,
+    ssi_errno: i32,
+    ssi_code: i32,
+    ssi_pid: u32,
+    ssi_uid: u32,
+    ssi_fd: i32,
+    ssi_tid: u32,
+    ssi_band: u32,
+    ssi_overrun: u32,
+    ssi_trapno: u32,
+    ssi_status: i32,
+    ssi_int: i32,
+    ssi_ptr: u64,
+    ssi_utime: u64,
+    ssi_stime: u64,
+    ssi_addr: u64,
+    ssi_addr_lsb: u16,
+    _pad2: u16,
+    ssi_syscall: i32,
+    ssi_call_addr: u64,
+    ssi_arch: u32,
+    _pad: [u8; 28],
+}
+
+const _: () = assert!(size_of::<SignalfdSiginfo>() == 128);
+
+impl From<&SignalInfo> for SignalfdSiginfo {
+    fn from(sig: &SignalInfo) -> Self {
+        // SAFETY: the fields read are those of the kind of signal `si_code`
+        // and the number say it is.
+        unsafe {
+            let info = &sig.0.__bindgen_anon_1.__bindgen_anon_1;
+            let fields = &info._sifields;
+            let mut record = Self {
+                ssi_signo: info.si_signo as _,
+                ssi_errno: info.si_errno,
+                ssi_code: info.si_code,
+                // The sender is first in the fields of all the kinds with one.
+                ssi_pid: fields._kill._pid as _,
+                ssi_uid: fields._kill._uid,
+                ..Default::default()
+            };
+            match info.si_code {
+                SI_TIMER => {
+                    record.ssi_tid = fields._timer._tid as _;
+                    record.ssi_overrun = fields._timer._overrun as _;
+                    record.ssi_int = fields._timer._sigval.sival_int;
+                    record.ssi_ptr = fields._timer._sigval.sival_ptr as _;
+                }
+                SI_QUEUE | SI_MESGQ => {
+                    record.ssi_int = fields._rt._sigval.sival_int;
+                    record.ssi_ptr = fields._rt._sigval.sival_ptr as _;
+                }
+                _ if sig.signo() == Signo::SIGCHLD => {
+                    record.ssi_status = fields._sigchld._status;
+                    record.ssi_utime = fields._sigchld._utime as _;
+                    record.ssi_stime = fields._sigchld._stime as _;
+                }
+                _ => {}
+            }
+            record
+        }
+    }
+}
+
+/// A `signalfd`, which reads the signals of its mask.
+pub struct SignalFd {
+    mask: Mutex<SignalSet>,
+    nonblocking: AtomicBool,
+    ino: u64,
+    live: LiveFile,
+}
+
+impl SignalFd {
+    pub fn new(mask: SignalSet, nonblocking: bool) -> Self {
+        Self {
+            mask: Mutex::new(mask),
+            nonblocking: AtomicBool::new(nonblocking),
+            ino: alloc_anon_ino(),
+            live: LiveFile::new(FileKind::SignalFd),
+        }
+    }
+
+    /// Read the signals of `mask` instead from now on.
+    pub fn set_mask(&self, mask: SignalSet) {
+        *self.mask.lock() = mask;
+    }
+}
+
+impl FileLike for SignalFd {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        const RECORD_SIZE: usize = size_of::<SignalfdSiginfo>();
+        if buf.len() < RECORD_SIZE {
+            return Err(LinuxError::EINVAL);
+        }
+        let mask = *self.mask.lock();
+        // Waits for the first signal only, and takes what else is pending.
+        let deadline = self
+            .nonblocking
+            .load(Ordering::Relaxed)
+            .then(monotonic_time);
+        let mut sig = Some(dequeue_signal_in(mask, deadline)?);
+        let mut len = 0;
+        while let Some(taken) = sig {
+            signal_dequeued(&taken);
+            let record = SignalfdSiginfo::from(&taken);
+            // SAFETY: the record is plain old data.
+            let bytes: &[u8] =
+                unsafe { core::slice::from_raw_parts((&raw const record).cast(), RECORD_SIZE) };
+            buf[len..len + RECORD_SIZE].copy_from_slice(bytes);
+            len += RECORD_SIZE;
+            sig = if buf.len() - len >= RECORD_SIZE {
+                take_signal_in(mask)
+            } else {
+                None
+            };
+        }
+        Ok(len)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EINVAL)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            ino: self.ino,
+            mode: 0o600, // rw-------
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        let mask = *self.mask.lock();
+        let pending = current().task_ext().thread_data().signal.pending();
+        Ok(PollState {
+            readable: pending & mask != SignalSet::default(),
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> LinuxResult {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn live(&self) -> Option<&LiveFile> {
+        Some(&self.live)
+    }
+
+    fn status_flags(&self) -> u32 {
+        if self.nonblocking.load(Ordering::Relaxed) {
+            O_RDWR | O_NONBLOCK
+        } else {
+            O_RDWR
+        }
+    }
+}
@@ -1,26 +1,236 @@
-use core::any::Any;
+use core::{
+    any::Any,
+    cell::UnsafeCell,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::Duration,
+};
 
-use alloc::sync::Arc;
+use alloc::{collections::vec_deque::VecDeque, sync::Arc};
 use axerrno::{AxResult, LinuxError, LinuxResult};
-use axio::{BufReader, PollState, prelude::*};
+use axio::{PollState, prelude::*};
 use axsync::Mutex;
-use linux_raw_sys::general::S_IFCHR;
+use axtask::WaitQueue;
+use linux_raw_sys::general::{O_RDONLY, O_WRONLY, S_IFCHR};
+use spin::Once;
+use starry_core::{
+    task::{WaitMode, WaitQueueWrapper, WaitResult},
+    workqueue::{Priority, queue_work},
+};
 
-use super::Kstat;
+use super::{CONSOLE_TTY, FileOwner, Kstat, PollStatus, Readiness};
+use crate::signal::has_pending_signal;
 
-fn console_read_bytes(buf: &mut [u8]) -> AxResult<usize> {
-    let len = axhal::console::read_bytes(buf);
-    for c in &mut buf[..len] {
-        if *c == b'\r' {
-            *c = b'\n';
+/// Capacity of [`INPUT`], large enough to absorb a pasted block of text.
+const INPUT_BUF_SIZE: usize = 4096;
+
+/// How often the console is polled when it has no input IRQ, as on
+/// loongarch64.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How many bytes of user output are copied onto the stack at once, as
+/// the console writes them with IRQs disabled, which must not fault.
+const OUTPUT_CHUNK_SIZE: usize = 256;
+
+/// Capacity of [`OUTPUT`], for a program writing a lot not to wait for the
+/// console to keep up until this much is pending.
+const OUTPUT_BUF_SIZE: usize = 256 * 1024;
+
+/// How many bytes a worker writes to the console before it queues itself
+/// again, for other work to go in between.
+const DRAIN_BATCH: usize = 16 * 1024;
+
+/// Bytes received from the console but not read yet.
+///
+/// It is filled by the console IRQ handler, or by the reader itself if the
+/// console can only be polled, and drained by [`Stdin`]. There is a single
+/// producer and a single consumer, so the indices are enough to synchronize.
+struct InputBuffer {
+    buf: UnsafeCell<[u8; INPUT_BUF_SIZE]>,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+unsafe impl Sync for InputBuffer {}
+
+impl InputBuffer {
+    const fn new() -> Self {
+        Self {
+            buf: UnsafeCell::new([0; INPUT_BUF_SIZE]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire) == self.tail.load(Ordering::Acquire)
+    }
+
+    /// Producer side. Returns `false` if the buffer is full.
+    fn push(&self, c: u8) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail.wrapping_sub(self.head.load(Ordering::Acquire)) == INPUT_BUF_SIZE {
+            return false;
+        }
+        unsafe { (*self.buf.get())[tail % INPUT_BUF_SIZE] = c };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Consumer side. Returns the number of bytes copied into `buf`.
+    fn pop(&self, buf: &mut [u8]) -> usize {
+        let head = self.head.load(Ordering::Relaxed);
+        let len = self
+            .tail
+            .load(Ordering::Acquire)
+            .wrapping_sub(head)
+            .min(buf.len());
+        for (i, c) in buf[..len].iter_mut().enumerate() {
+            *c = unsafe { (*self.buf.get())[head.wrapping_add(i) % INPUT_BUF_SIZE] };
+        }
+        self.head.store(head.wrapping_add(len), Ordering::Release);
+        len
+    }
+}
+
+static INPUT: InputBuffer = InputBuffer::new();
+static INPUT_WQ: WaitQueue = WaitQueue::new();
+
+/// The owner of the console, shared by all descriptions of it, like the
+/// owner of a terminal.
+static CONSOLE_OWNER: FileOwner = FileOwner::new();
+
+/// Move all pending bytes from the console into [`INPUT`].
+fn drain_console() {
+    let mut buf = [0u8; 64];
+    loop {
+        let len = axhal::console::read_bytes(&mut buf);
+        for &c in &buf[..len] {
+            // Signals cannot be sent from the IRQ handler.
+            if let Some(signo) = CONSOLE_TTY.input_signal(c) {
+                queue_work(Priority::High, move || CONSOLE_TTY.signal_foreground(signo));
+                continue;
+            }
+            let c = if c == b'\r' { b'\n' } else { c };
+            if !INPUT.push(c) {
+                warn!("console input buffer full, dropping input");
+            }
+        }
+        if len < buf.len() {
+            break;
+        }
+    }
+}
+
+fn console_irq_handler() {
+    let was_empty = INPUT.is_empty();
+    drain_console();
+    if !INPUT.is_empty() {
+        INPUT_WQ.notify_all(false);
+        // Signals cannot be sent from the IRQ handler.
+        if was_empty && CONSOLE_OWNER.is_async() {
+            queue_work(Priority::High, || CONSOLE_OWNER.notify(Readiness::Readable));
+        }
+    }
+}
+
+/// Whether console input is delivered by IRQ. The handler is registered on
+/// first use.
+fn console_irq_enabled() -> bool {
+    static ENABLED: Once<bool> = Once::new();
+    *ENABLED.call_once(|| {
+        axhal::console::irq_num()
+            .is_some_and(|irq| axhal::irq::register_handler(irq, console_irq_handler))
+    })
+}
+
+/// Output of user programs not written to the console yet.
+///
+/// Writers append to it, waiting on [`OUTPUT_SPACE`] only once it is full,
+/// and a worker drains it to the console, see [`queue_drain`]. A write is
+/// appended as a whole before the next one, and the console takes turns
+/// with the kernel log at line boundaries, so lines are kept whole as
+/// before, only written out later.
+static OUTPUT: spin::Mutex<VecDeque<u8>> = spin::Mutex::new(VecDeque::new());
+
+/// Writers wait here for room in [`OUTPUT`].
+static OUTPUT_SPACE: WaitQueueWrapper = WaitQueueWrapper::new();
+
+/// Held while writing out what was taken from [`OUTPUT`], so that two
+/// drains do not reorder it.
+static DRAINER: Mutex<()> = Mutex::new(());
+
+/// Whether a worker is queued to drain [`OUTPUT`].
+static DRAIN_QUEUED: AtomicBool = AtomicBool::new(false);
+
+fn output_room() -> usize {
+    OUTPUT_BUF_SIZE - OUTPUT.lock().len()
+}
+
+/// Write what [`OUTPUT`] holds to the console, about `limit` bytes at most.
+/// Returns whether it holds more.
+fn drain_output(limit: usize) -> bool {
+    let _drainer = DRAINER.lock();
+    let mut chunk = [0; OUTPUT_CHUNK_SIZE];
+    let mut written = 0;
+    while written < limit {
+        let len = {
+            let mut output = OUTPUT.lock();
+            let len = output.len().min(chunk.len());
+            for (c, byte) in chunk.iter_mut().zip(output.drain(..len)) {
+                *c = byte;
+            }
+            len
+        };
+        if len == 0 {
+            return false;
         }
+        axhal::console::write_user(&chunk[..len]);
+        OUTPUT_SPACE.notify_all(false);
+        written += len;
+    }
+    !OUTPUT.lock().is_empty()
+}
+
+/// Have a worker drain [`OUTPUT`], unless one is queued already.
+fn queue_drain() {
+    if !DRAIN_QUEUED.swap(true, Ordering::AcqRel) {
+        queue_work(Priority::Normal, drain_work);
+    }
+}
+
+fn drain_work() {
+    if drain_output(DRAIN_BATCH) {
+        queue_work(Priority::Normal, drain_work);
+        return;
+    }
+    DRAIN_QUEUED.store(false, Ordering::Release);
+    // What was written since it was found empty.
+    if !OUTPUT.lock().is_empty() {
+        queue_drain();
     }
-    Ok(len)
 }
 
-fn console_write_bytes(buf: &[u8]) -> AxResult<usize> {
-    axhal::console::write_bytes(buf);
-    Ok(buf.len())
+/// Write all the output of user programs pending to the console, e.g. before
+/// powering off, or on `fsync` of the console.
+pub fn flush_output() {
+    drain_output(usize::MAX);
+    axhal::console::flush();
+}
+
+/// Like [`flush_output`], on a panic. Whoever holds the locks may never let
+/// go of them, so nothing is written unless they are free.
+pub fn flush_output_on_panic() {
+    let Some(_drainer) = DRAINER.try_lock() else {
+        return;
+    };
+    let Some(mut output) = OUTPUT.try_lock() else {
+        return;
+    };
+    let (front, back) = output.as_slices();
+    axhal::console::write_user(front);
+    axhal::console::write_user(back);
+    output.clear();
+    axhal::console::flush();
 }
 
 struct StdinRaw;
@@ -29,73 +239,85 @@ struct StdoutRaw;
 impl Read for StdinRaw {
     // Non-blocking read, returns number of bytes read.
     fn read(&mut self, buf: &mut [u8]) -> AxResult<usize> {
-        let mut read_len = 0;
-        while read_len < buf.len() {
-            let len = console_read_bytes(buf[read_len..].as_mut())?;
-            if len == 0 {
-                break;
-            }
-            read_len += len;
+        if !console_irq_enabled() {
+            drain_console();
         }
-        Ok(read_len)
+        Ok(INPUT.pop(buf))
     }
 }
 
-impl Write for StdoutRaw {
-    fn write(&mut self, buf: &[u8]) -> AxResult<usize> {
-        console_write_bytes(buf)
-    }
-
-    fn flush(&mut self) -> AxResult {
-        Ok(())
+impl StdoutRaw {
+    /// Append `buf` to [`OUTPUT`], waiting for room as long as it is full.
+    ///
+    /// An interrupted write returns what it has written so far.
+    fn write(&mut self, buf: &[u8]) -> LinuxResult<usize> {
+        let mut chunk = [0; OUTPUT_CHUNK_SIZE];
+        let mut written = 0;
+        while written < buf.len() {
+            // `buf` is user memory, which may fault, so not under the lock.
+            let len = (buf.len() - written).min(chunk.len());
+            chunk[..len].copy_from_slice(&buf[written..written + len]);
+            let pushed = {
+                let mut output = OUTPUT.lock();
+                let pushed = len.min(OUTPUT_BUF_SIZE - output.len());
+                output.extend(&chunk[..pushed]);
+                pushed
+            };
+            written += pushed;
+            if pushed < len {
+                queue_drain();
+                let room = || output_room() > 0;
+                if OUTPUT_SPACE.wait_until(WaitMode::Interruptible, None, room)
+                    == WaitResult::Interrupted
+                {
+                    return match written {
+                        0 => Err(LinuxError::EINTR),
+                        n => Ok(n),
+                    };
+                }
+            }
+        }
+        queue_drain();
+        Ok(written)
     }
 }
 
 pub struct Stdin {
-    inner: &'static Mutex<BufReader<StdinRaw>>,
+    inner: &'static Mutex<StdinRaw>,
 }
 
 impl Stdin {
-    // Block until at least one byte is read.
-    fn read_blocked(&self, buf: &mut [u8]) -> AxResult<usize> {
-        let read_len = self.inner.lock().read(buf)?;
-        if buf.is_empty() || read_len > 0 {
-            return Ok(read_len);
-        }
-        // try again until we get something
+    // Block until at least one byte is read, or a signal is pending.
+    fn read_blocked(&self, buf: &mut [u8]) -> LinuxResult<usize> {
         loop {
+            // The foreground process group may change while waiting.
+            CONSOLE_TTY.check_read()?;
             let read_len = self.inner.lock().read(buf)?;
-            if read_len > 0 {
+            if buf.is_empty() || read_len > 0 {
                 return Ok(read_len);
             }
-            axtask::yield_now();
+            if has_pending_signal() {
+                return Err(LinuxError::EINTR);
+            }
+            // Show a prompt held back for not ending its line.
+            flush_output();
+            if console_irq_enabled() {
+                // Wake up now and then, since signals do not notify the queue.
+                INPUT_WQ.wait_timeout_until(POLL_INTERVAL, || !INPUT.is_empty());
+            } else {
+                INPUT_WQ.wait_timeout(POLL_INTERVAL);
+            }
         }
     }
 }
 
-impl Read for Stdin {
-    fn read(&mut self, buf: &mut [u8]) -> AxResult<usize> {
-        self.read_blocked(buf)
-    }
-}
-
 pub struct Stdout {
     inner: &'static Mutex<StdoutRaw>,
 }
 
-impl Write for Stdout {
-    fn write(&mut self, buf: &[u8]) -> AxResult<usize> {
-        self.inner.lock().write(buf)
-    }
-
-    fn flush(&mut self) -> AxResult {
-        self.inner.lock().flush()
-    }
-}
-
 /// Constructs a new handle to the standard input of the current process.
 pub fn stdin() -> Stdin {
-    static INSTANCE: Mutex<BufReader<StdinRaw>> = Mutex::new(BufReader::new(StdinRaw));
+    static INSTANCE: Mutex<StdinRaw> = Mutex::new(StdinRaw);
     Stdin { inner: &INSTANCE }
 }
 
@@ -107,11 +329,11 @@ pub fn stdout() -> Stdout {
 
 impl super::FileLike for Stdin {
     fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
-        Ok(self.read_blocked(buf)?)
+        self.read_blocked(buf)
     }
 
     fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
-        Err(LinuxError::EPERM)
+        Err(LinuxError::EBADF)
     }
 
     fn stat(&self) -> LinuxResult<Kstat> {
@@ -126,8 +348,12 @@ impl super::FileLike for Stdin {
     }
 
     fn poll(&self) -> LinuxResult<PollState> {
+        if !console_irq_enabled() {
+            let _inner = self.inner.lock();
+            drain_console();
+        }
         Ok(PollState {
-            readable: true,
+            readable: !INPUT.is_empty(),
             writable: true,
         })
     }
@@ -135,15 +361,33 @@ impl super::FileLike for Stdin {
     fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
         Ok(())
     }
+
+    fn status_flags(&self) -> u32 {
+        O_RDONLY
+    }
+
+    fn poll_status(&self) -> PollStatus {
+        PollStatus {
+            hangup: CONSOLE_TTY.hung_up(),
+            error: false,
+        }
+    }
+
+    /// Only sends `SIGIO` if the console has an input IRQ.
+    fn owner(&self) -> Option<&FileOwner> {
+        Some(&CONSOLE_OWNER)
+    }
 }
 
 impl super::FileLike for Stdout {
     fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
-        Err(LinuxError::EPERM)
+        Err(LinuxError::EBADF)
     }
 
     fn write(&self, buf: &[u8]) -> LinuxResult<usize> {
-        Ok(self.inner.lock().write(buf)?)
+        CONSOLE_TTY.check_write()?;
+        // Held for the whole write, for the writes not to mix.
+        self.inner.lock().write(buf)
     }
 
     fn stat(&self) -> LinuxResult<Kstat> {
@@ -160,11 +404,22 @@ impl super::FileLike for Stdout {
     fn poll(&self) -> LinuxResult<PollState> {
         Ok(PollState {
             readable: true,
-            writable: true,
+            writable: output_room() > 0,
         })
     }
 
     fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
         Ok(())
     }
+
+    fn status_flags(&self) -> u32 {
+        O_WRONLY
+    }
+
+    fn poll_status(&self) -> PollStatus {
+        PollStatus {
+            hangup: CONSOLE_TTY.hung_up(),
+            error: false,
+        }
+    }
 }
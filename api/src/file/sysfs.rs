@@ -0,0 +1,124 @@
+//! The `/sys` tree, of which only `/sys/devices/system/cpu` is there, for
+//! the programs which count the CPUs in it, like `nproc` and glibc when
+//! `sched_getaffinity` is not enough.
+//!
+//! The CPUs are counted by [`online_cpus`] on each lookup, not when the tree
+//! is built, so the tree would follow CPUs brought up after boot.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use axerrno::{LinuxError, LinuxResult};
+use axfs::fops::FileType;
+
+use super::virt::{StaticDir, StaticEntry, SynthFile, VirtualDir, VirtualDirEntry, VirtualNode};
+
+static ROOT: [StaticEntry; 1] = [("devices", FileType::Dir, || {
+    VirtualNode::Dir(Arc::new(StaticDir(&DEVICES)))
+})];
+
+static DEVICES: [StaticEntry; 1] = [("system", FileType::Dir, || {
+    VirtualNode::Dir(Arc::new(StaticDir(&SYSTEM)))
+})];
+
+static SYSTEM: [StaticEntry; 1] = [("cpu", FileType::Dir, || VirtualNode::Dir(Arc::new(CpuDir)))];
+
+/// The root of `/sys`.
+pub fn root() -> StaticDir {
+    StaticDir(&ROOT)
+}
+
+/// The number of CPUs up, numbered from 0.
+///
+/// All the CPUs configured are brought up at boot, and none is taken down.
+fn online_cpus() -> usize {
+    axconfig::SMP
+}
+
+/// The CPUs `0..count` as a list of `cpulist(7)`, like `0-3`.
+fn cpu_list(count: usize) -> String {
+    match count {
+        0 => String::from("\n"),
+        1 => String::from("0\n"),
+        _ => format!("0-{}\n", count - 1),
+    }
+}
+
+/// `/sys/devices/system/cpu`, with a directory for each CPU.
+///
+/// `possible` and `present` are the CPUs up, as no other one can come.
+struct CpuDir;
+
+impl VirtualDir for CpuDir {
+    fn list_entries(&self) -> LinuxResult<Vec<VirtualDirEntry>> {
+        let mut entries = Vec::from([
+            VirtualDirEntry::new("online", FileType::File),
+            VirtualDirEntry::new("possible", FileType::File),
+            VirtualDirEntry::new("present", FileType::File),
+        ]);
+        entries.extend(
+            (0..online_cpus())
+                .map(|cpu| VirtualDirEntry::new(format!("cpu{}", cpu), FileType::Dir)),
+        );
+        Ok(entries)
+    }
+
+    fn lookup(&self, name: &str) -> LinuxResult<VirtualNode> {
+        let count = online_cpus();
+        match name {
+            "online" | "possible" | "present" => return Ok(SynthFile::node(cpu_list(count))),
+            _ => {}
+        }
+        // Neither `cpu+1` nor `cpu01`.
+        let cpu = name
+            .strip_prefix("cpu")
+            .and_then(|id| id.parse::<usize>().ok().filter(|cpu| cpu.to_string() == id))
+            .filter(|&cpu| cpu < count)
+            .ok_or(LinuxError::ENOENT)?;
+        Ok(VirtualNode::Dir(Arc::new(CpuNDir { cpu })))
+    }
+}
+
+/// `/sys/devices/system/cpu/cpuN`, of which only the topology is there.
+struct CpuNDir {
+    cpu: usize,
+}
+
+impl VirtualDir for CpuNDir {
+    fn list_entries(&self) -> LinuxResult<Vec<VirtualDirEntry>> {
+        Ok(Vec::from([VirtualDirEntry::new("topology", FileType::Dir)]))
+    }
+
+    fn lookup(&self, name: &str) -> LinuxResult<VirtualNode> {
+        match name {
+            "topology" => Ok(VirtualNode::Dir(Arc::new(TopologyDir { cpu: self.cpu }))),
+            _ => Err(LinuxError::ENOENT),
+        }
+    }
+}
+
+/// `/sys/devices/system/cpu/cpuN/topology`: each CPU is a core of its own,
+/// with the number of the CPU, in a single package.
+struct TopologyDir {
+    cpu: usize,
+}
+
+impl VirtualDir for TopologyDir {
+    fn list_entries(&self) -> LinuxResult<Vec<VirtualDirEntry>> {
+        Ok(Vec::from([
+            VirtualDirEntry::new("core_id", FileType::File),
+            VirtualDirEntry::new("physical_package_id", FileType::File),
+        ]))
+    }
+
+    fn lookup(&self, name: &str) -> LinuxResult<VirtualNode> {
+        match name {
+            "core_id" => Ok(SynthFile::node(format!("{}\n", self.cpu))),
+            "physical_package_id" => Ok(SynthFile::node("0\n")),
+            _ => Err(LinuxError::ENOENT),
+        }
+    }
+}
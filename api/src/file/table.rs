@@ -0,0 +1,169 @@
+//! The file descriptor table.
+
+use alloc::{sync::Arc, vec::Vec};
+
+use super::FileLike;
+
+/// The number of slots a table gets when its first file is added.
+const INITIAL_SLOTS: usize = 64;
+
+/// A file descriptor table, growing as descriptors are used.
+///
+/// The slots are doubled when a descriptor past the end is needed, so the
+/// size follows the largest descriptor in use rather than the limit of
+/// descriptors.
+#[derive(Default)]
+pub struct FileTable {
+    slots: Vec<Option<Arc<dyn FileLike>>>,
+    /// The close-on-exec flag of each slot, which belongs to the descriptor
+    /// rather than to the file it refers to.
+    cloexec: Vec<bool>,
+    count: usize,
+    /// One past the largest open descriptor, or 0 if none is open.
+    end: usize,
+}
+
+impl FileTable {
+    pub const fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            cloexec: Vec::new(),
+            count: 0,
+            end: 0,
+        }
+    }
+
+    /// The number of slots, which is what `FDSize` in `/proc/<pid>/status`
+    /// reports.
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// The number of open descriptors.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// One past the largest open descriptor, or 0 if none is open, which is
+    /// as far as a scan for open descriptors needs to go, e.g. by `select`.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// The open descriptors, in ascending order.
+    pub fn ids(&self) -> impl Iterator<Item = usize> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(fd, slot)| slot.as_ref().map(|_| fd))
+    }
+
+    pub fn get(&self, fd: usize) -> Option<&Arc<dyn FileLike>> {
+        self.slots.get(fd)?.as_ref()
+    }
+
+    /// Make room for the descriptor `fd`.
+    fn reserve(&mut self, fd: usize) {
+        if fd >= self.slots.len() {
+            let len = (fd + 1)
+                .next_power_of_two()
+                .max(self.slots.len() * 2)
+                .max(INITIAL_SLOTS);
+            self.slots.resize(len, None);
+            self.cloexec.resize(len, false);
+        }
+    }
+
+    /// Add `f` at the lowest free descriptor below `limit`.
+    ///
+    /// Returns `f` back if every descriptor below `limit` is in use.
+    pub fn add(&mut self, f: Arc<dyn FileLike>, limit: usize) -> Result<usize, Arc<dyn FileLike>> {
+        let fd = self
+            .slots
+            .iter()
+            .position(Option::is_none)
+            .unwrap_or(self.slots.len());
+        self.add_at(fd, f, limit)
+    }
+
+    /// Add `f` at the free descriptor `fd`, which must be below `limit`.
+    ///
+    /// Returns `f` back if `fd` is in use or not below `limit`.
+    pub fn add_at(
+        &mut self,
+        fd: usize,
+        f: Arc<dyn FileLike>,
+        limit: usize,
+    ) -> Result<usize, Arc<dyn FileLike>> {
+        if fd >= limit || self.get(fd).is_some() {
+            return Err(f);
+        }
+        self.reserve(fd);
+        self.slots[fd] = Some(f);
+        self.cloexec[fd] = false;
+        self.count += 1;
+        self.end = self.end.max(fd + 1);
+        Ok(fd)
+    }
+
+    pub fn remove(&mut self, fd: usize) -> Option<Arc<dyn FileLike>> {
+        let f = self.slots.get_mut(fd)?.take()?;
+        self.count -= 1;
+        if fd + 1 == self.end {
+            self.end = self.slots[..fd]
+                .iter()
+                .rposition(Option::is_some)
+                .map_or(0, |last| last + 1);
+        }
+        Some(f)
+    }
+
+    /// Whether the open descriptor `fd` is closed on `execve`.
+    pub fn cloexec(&self, fd: usize) -> Option<bool> {
+        self.get(fd)?;
+        Some(self.cloexec[fd])
+    }
+
+    /// Set whether the open descriptor `fd` is closed on `execve`.
+    ///
+    /// Returns `None` if `fd` is not open.
+    pub fn set_cloexec(&mut self, fd: usize, cloexec: bool) -> Option<()> {
+        self.get(fd)?;
+        self.cloexec[fd] = cloexec;
+        Some(())
+    }
+
+    /// Whether any open descriptor is closed on `execve`.
+    pub fn has_cloexec(&self) -> bool {
+        self.ids().any(|fd| self.cloexec[fd])
+    }
+
+    /// Remove the descriptors which are closed on `execve`, returning their
+    /// files so that they are dropped outside of the lock.
+    pub fn take_cloexec(&mut self) -> Vec<Arc<dyn FileLike>> {
+        let fds = self
+            .ids()
+            .filter(|&fd| self.cloexec[fd])
+            .collect::<Vec<_>>();
+        fds.into_iter().filter_map(|fd| self.remove(fd)).collect()
+    }
+}
+
+impl Clone for FileTable {
+    /// Copy the table with the same descriptors, e.g. on the first change
+    /// after a fork.
+    ///
+    /// The copy is only as large as the largest descriptor needs, and is
+    /// sized once before the slots up to it are copied.
+    fn clone(&self) -> Self {
+        let mut table = Self::new();
+        if let Some(last) = self.end.checked_sub(1) {
+            table.reserve(last);
+            table.slots[..=last].clone_from_slice(&self.slots[..=last]);
+            table.cloexec[..=last].copy_from_slice(&self.cloexec[..=last]);
+        }
+        table.count = self.count;
+        table.end = self.end;
+        table
+    }
+}
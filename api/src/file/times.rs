@@ -0,0 +1,251 @@
+//! File timestamps.
+//!
+//! vfat stores the modification time and the access date of a file in its
+//! directory entry, which fatfs updates as the file is created and written,
+//! and [`set_times`] writes. It has no change time, so that is the
+//! modification time, as on Linux. tmpfs, and ext4 as `axfs` mounts it,
+//! store none, and a file there not created, written or touched since boot
+//! has all of them at the epoch.
+//!
+//! The times changed since boot are kept here, by real path, so that every
+//! name of a file sees the same ones. On vfat they are only there too
+//! because an open file writes its entry back once closed, and fatfs cannot
+//! set the times of a directory; the entry is read for the others. An entry
+//! goes when its file is removed, or its filesystem unmounted, so there are
+//! never more than the files there are.
+//!
+//! Times are stored at the granularity of the filesystem the file is on,
+//! rounded toward zero: on vfat, 2 seconds for the modification time and a
+//! day for the access time, and nanoseconds on tmpfs and ext4.
+
+use core::time::Duration;
+
+use alloc::{collections::btree_map::BTreeMap, format, string::String, vec::Vec};
+use axhal::time::wall_time;
+use spin::RwLock;
+
+use crate::imp::mount_options;
+
+/// The timestamps of a file.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Timestamps {
+    /// The last access.
+    pub atime: Duration,
+    /// The last modification of the content.
+    pub mtime: Duration,
+    /// The last change of the content or of the metadata.
+    pub ctime: Duration,
+}
+
+static TIMESTAMPS: RwLock<BTreeMap<String, Timestamps>> = RwLock::new(BTreeMap::new());
+
+/// The filesystem type of the root filesystem.
+#[cfg(feature = "lwext4_rs")]
+const ROOT_FS_TYPE: &str = "ext4";
+#[cfg(not(feature = "lwext4_rs"))]
+const ROOT_FS_TYPE: &str = "vfat";
+
+const SECS_PER_DAY: u64 = 86400;
+
+pub(super) fn key(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    }
+}
+
+/// Move the entries of `map` for the files under the directory moved from
+/// `from` to `to` along with it, dropping any left under `to`.
+pub(super) fn move_under<V>(map: &mut BTreeMap<String, V>, from: &str, to: &str) {
+    let (from, to) = (key(from), key(to));
+    let under = |path: &str, dir: &str| {
+        path.strip_prefix(dir)
+            .is_some_and(|rest| rest.starts_with('/'))
+    };
+    map.retain(|path, _| !under(path, to));
+    let moved: Vec<String> = map
+        .keys()
+        .filter(|path| under(path, from))
+        .cloned()
+        .collect();
+    for path in moved {
+        let value = map.remove(&path).unwrap();
+        map.insert(format!("{}{}", to, &path[from.len()..]), value);
+    }
+}
+
+/// The type of the filesystem `path` is on.
+pub(super) fn fs_type(path: &str) -> &'static str {
+    mount_options(path).map_or(ROOT_FS_TYPE, |options| options.fs_type)
+}
+
+/// Round the modification or change time `time` toward zero to what the
+/// filesystem of type `fs_type` can store.
+fn round(fs_type: &str, time: Duration) -> Duration {
+    match fs_type {
+        "vfat" => Duration::from_secs(time.as_secs() & !1),
+        _ => time,
+    }
+}
+
+/// Round the access time `time` toward zero to what the filesystem of type
+/// `fs_type` can store.
+fn round_atime(fs_type: &str, time: Duration) -> Duration {
+    match fs_type {
+        "vfat" => Duration::from_secs(time.as_secs() / SECS_PER_DAY * SECS_PER_DAY),
+        _ => time,
+    }
+}
+
+/// The timestamps the filesystem stores for the file at `path`, or the
+/// epoch if it stores none.
+fn stored(path: &str) -> Timestamps {
+    match axfs::api::fat_times(path) {
+        Ok(times) => Timestamps {
+            atime: times.accessed,
+            mtime: times.modified,
+            ctime: times.modified,
+        },
+        Err(_) => Timestamps::default(),
+    }
+}
+
+/// Change the timestamps of the file at `path` kept here with `f`, starting
+/// from the stored ones if none are.
+fn update(path: &str, f: impl FnOnce(&mut Timestamps)) {
+    let key = key(path);
+    let mut timestamps = TIMESTAMPS.write();
+    if let Some(times) = timestamps.get_mut(key) {
+        f(times);
+        return;
+    }
+    drop(timestamps);
+    let mut times = stored(key);
+    f(&mut times);
+    TIMESTAMPS.write().insert(key.into(), times);
+}
+
+/// Get the timestamps of the file at `path`.
+pub fn timestamps(path: &str) -> Timestamps {
+    let times = TIMESTAMPS.read().get(key(path)).copied();
+    times.unwrap_or_else(|| stored(key(path)))
+}
+
+/// Set the access and modification times of the file at `path` to the
+/// given ones, leaving a `None` one unchanged, as `utimensat` does.
+///
+/// On vfat, they are written to the entry of a regular file through `file`,
+/// an open file of it, or one opened for the purpose if `None`. An open
+/// file writes its entry back once closed, over what was written through
+/// another.
+pub fn set_times(
+    path: &str,
+    file: Option<&axfs::fops::File>,
+    atime: Option<Duration>,
+    mtime: Option<Duration>,
+) {
+    let fs_type = fs_type(path);
+    if fs_type == "vfat" {
+        let written = match file {
+            Some(file) => file.set_times(atime, mtime),
+            None => {
+                let opts = axfs::fops::OpenOptions::new().set_read(true);
+                axfs::fops::File::open(key(path), &opts)
+                    .and_then(|file| file.set_times(atime, mtime))
+            }
+        };
+        // A directory keeps its times here only.
+        if let Err(err) = written {
+            debug!("times of {} not written: {:?}", path, err);
+        }
+    }
+    let now = round(fs_type, wall_time());
+    update(path, |times| {
+        if let Some(atime) = atime {
+            times.atime = round_atime(fs_type, atime);
+        }
+        if let Some(mtime) = mtime {
+            times.mtime = round(fs_type, mtime);
+        }
+        times.ctime = now;
+    });
+}
+
+/// Record the modification of the content of the file at `path`.
+pub fn update_mtime(path: &str) {
+    let now = round(fs_type(path), wall_time());
+    update(path, |times| {
+        times.mtime = now;
+        times.ctime = now;
+    });
+}
+
+/// Record the change of the metadata of the file at `path`, like a new
+/// link to it.
+pub fn update_ctime(path: &str) {
+    let now = round(fs_type(path), wall_time());
+    update(path, |times| times.ctime = now);
+}
+
+/// Keep the timestamps of the file moved from `from` to `to`.
+pub fn move_times(from: &str, to: &str) {
+    let mut timestamps = TIMESTAMPS.write();
+    if let Some(times) = timestamps.remove(key(from)) {
+        timestamps.insert(key(to).into(), times);
+    }
+}
+
+/// Keep the timestamps of the files under the directory moved from `from`
+/// to `to`.
+pub fn move_times_under(from: &str, to: &str) {
+    move_under(&mut TIMESTAMPS.write(), from, to);
+}
+
+/// Record the creation of the file at `path`, which modifies its parent.
+///
+/// On vfat, fatfs dates the entry of the new file itself.
+pub fn init_times(path: &str) {
+    let fs_type = fs_type(path);
+    let now = wall_time();
+    let mut timestamps = TIMESTAMPS.write();
+    if fs_type == "vfat" {
+        timestamps.remove(key(path));
+    } else {
+        timestamps.insert(
+            key(path).into(),
+            Timestamps {
+                atime: now,
+                mtime: now,
+                ctime: now,
+            },
+        );
+    }
+    drop(timestamps);
+    update_parent_mtime(path);
+}
+
+/// Forget the timestamps of the removed file at `path`, which modifies its
+/// parent.
+pub fn remove_times(path: &str) {
+    TIMESTAMPS.write().remove(key(path));
+    update_parent_mtime(path);
+}
+
+/// Forget the timestamps of the files under the directory `dir`, whose
+/// filesystem is unmounted.
+pub fn forget_times_under(dir: &str) {
+    let dir = key(dir);
+    TIMESTAMPS.write().retain(|path, _| {
+        path.strip_prefix(dir)
+            .is_none_or(|rest| !rest.is_empty() && !rest.starts_with('/'))
+    });
+}
+
+/// Record the change of the entries of the directory `path` is in.
+pub fn update_parent_mtime(path: &str) {
+    match key(path).rsplit_once('/') {
+        Some(("", "")) | None => {}
+        Some(("", _)) => update_mtime("/"),
+        Some((parent, _)) => update_mtime(parent),
+    }
+}
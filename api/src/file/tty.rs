@@ -0,0 +1,343 @@
+//! The console as a terminal: its settings, and the session it controls,
+//! see `credentials(7)` and `termios(3)`.
+//!
+//! The console is the only terminal. Only `ISIG` and `TOSTOP` of its
+//! settings take effect; input is never edited or echoed.
+
+use core::ffi::{c_int, c_void};
+
+use alloc::collections::btree_set::BTreeSet;
+
+use axerrno::{LinuxError, LinuxResult};
+use axprocess::{Pid, Process};
+use axsignal::{SignalInfo, Signo};
+use axsync::{Mutex, spin::SpinNoIrq};
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::{
+    general::{
+        B38400, CREAD, CS8, ECHO, ECHOCTL, ECHOE, ECHOK, ECHOKE, HUPCL, ICANON, ICRNL, IEXTEN,
+        ISIG, IXON, ONLCR, OPOST, SI_KERNEL, TOSTOP, VEOF, VERASE, VINTR, VKILL, VMIN, VQUIT,
+        VSTART, VSTOP, VSUSP, termios, winsize,
+    },
+    ioctl::{
+        TCGETS, TCSETS, TCSETSF, TCSETSW, TIOCGPGRP, TIOCGSID, TIOCGWINSZ, TIOCNOTTY, TIOCSCTTY,
+        TIOCSPGRP,
+    },
+};
+use starry_core::{
+    cred::CAP_SYS_ADMIN,
+    job::{is_orphaned_group, is_session_leader},
+    task::get_process_group,
+};
+
+use super::{
+    FileLike,
+    stdio::{Stdin, Stdout},
+};
+use crate::{
+    ptr::UserPtr,
+    require_capability,
+    signal::{is_ignored_or_blocked, send_signal_process_group},
+};
+
+/// The settings of a fresh terminal, like `stty sane`.
+const DEFAULT_TERMIOS: termios = {
+    let mut c_cc = [0; 19];
+    c_cc[VINTR as usize] = 0x03;
+    c_cc[VQUIT as usize] = 0x1c;
+    c_cc[VERASE as usize] = 0x7f;
+    c_cc[VKILL as usize] = 0x15;
+    c_cc[VEOF as usize] = 0x04;
+    c_cc[VMIN as usize] = 1;
+    c_cc[VSTART as usize] = 0x11;
+    c_cc[VSTOP as usize] = 0x13;
+    c_cc[VSUSP as usize] = 0x1a;
+    termios {
+        c_iflag: ICRNL | IXON,
+        c_oflag: OPOST | ONLCR,
+        c_cflag: B38400 | CS8 | CREAD | HUPCL,
+        c_lflag: ISIG | ICANON | ECHO | ECHOE | ECHOK | IEXTEN | ECHOCTL | ECHOKE,
+        c_line: 0,
+        c_cc,
+    }
+};
+
+/// The session a terminal controls.
+#[derive(Clone, Copy)]
+struct Control {
+    sid: Pid,
+    /// The foreground process group, which may read from the terminal.
+    foreground: Pid,
+}
+
+impl Control {
+    /// Make the session leader's process group the foreground one if the
+    /// foreground process group has no live member left.
+    ///
+    /// Linux leaves the terminal to the group that is gone, until the shell
+    /// which made it the foreground one takes it back. But a shell which
+    /// exits first, like a nested one, never does, and its parent shell
+    /// would then be stopped by `SIGTTIN` reading its next command.
+    fn fall_back(&mut self) {
+        let alive = get_process_group(self.foreground)
+            .is_ok_and(|group| group.processes().iter().any(|proc| !proc.is_zombie()));
+        if !alive {
+            self.foreground = self.sid;
+        }
+    }
+}
+
+/// A terminal.
+pub struct Tty {
+    control: Mutex<Option<Control>>,
+    /// The sessions the terminal hung up on, until they control it again.
+    hung_up: Mutex<BTreeSet<Pid>>,
+    /// The settings, which the console IRQ handler reads.
+    termios: SpinNoIrq<termios>,
+}
+
+/// The console.
+pub static CONSOLE_TTY: Tty = Tty::new();
+
+/// Get the terminal `fd` refers to, if it is one.
+pub fn tty_from_fd(fd: c_int) -> Option<&'static Tty> {
+    (Stdin::from_fd(fd).is_ok() || Stdout::from_fd(fd).is_ok()).then_some(&CONSOLE_TTY)
+}
+
+impl Tty {
+    const fn new() -> Self {
+        Self {
+            control: Mutex::new(None),
+            hung_up: Mutex::new(BTreeSet::new()),
+            termios: SpinNoIrq::new(DEFAULT_TERMIOS),
+        }
+    }
+
+    /// The control of the terminal, if it is the controlling terminal of
+    /// the session of `proc`.
+    fn control_of(&self, proc: &Process) -> Option<Control> {
+        let sid = proc.group().session().sid();
+        let mut control = self.control.lock();
+        let control = control.as_mut().filter(|it| it.sid == sid)?;
+        control.fall_back();
+        Some(*control)
+    }
+
+    /// Check that the current process may access the terminal: it may if it
+    /// is in the foreground, or the terminal does not control its session.
+    ///
+    /// Otherwise its process group gets `signo` and the access fails with
+    /// `EINTR`, unless the signal is ignored or blocked, where reads fail
+    /// with `EIO` and other accesses go ahead. An orphaned process group,
+    /// which nothing would continue once stopped, gets no signal and fails
+    /// with `EIO`.
+    fn check_job(&self, signo: Signo) -> LinuxResult {
+        let curr = current();
+        let proc = curr.task_ext().thread.process();
+        let group = proc.group();
+        if self
+            .control_of(proc)
+            .is_none_or(|it| it.foreground == group.pgid())
+        {
+            return Ok(());
+        }
+        if is_ignored_or_blocked(signo) {
+            return if signo == Signo::SIGTTIN {
+                Err(LinuxError::EIO)
+            } else {
+                Ok(())
+            };
+        }
+        if is_orphaned_group(&group) {
+            return Err(LinuxError::EIO);
+        }
+        send_signal_process_group(&group, SignalInfo::new(signo, SI_KERNEL as _));
+        Err(LinuxError::EINTR)
+    }
+
+    /// Check that the current process may read, see [`Self::check_job`].
+    pub fn check_read(&self) -> LinuxResult {
+        self.check_job(Signo::SIGTTIN)
+    }
+
+    /// Check that the current process may write, which background process
+    /// groups may unless `TOSTOP` is set.
+    pub fn check_write(&self) -> LinuxResult {
+        if self.termios.lock().c_lflag & TOSTOP == 0 {
+            return Ok(());
+        }
+        self.check_job(Signo::SIGTTOU)
+    }
+
+    /// The signal the input character `c` generates, if `ISIG` is set and
+    /// `c` is the `INTR`, `QUIT` or `SUSP` character. A control character
+    /// set to 0 is disabled, as `_POSIX_VDISABLE` of Linux is 0.
+    pub fn input_signal(&self, c: u8) -> Option<Signo> {
+        let termios = self.termios.lock();
+        if termios.c_lflag & ISIG == 0 || c == 0 {
+            return None;
+        }
+        [
+            (VINTR, Signo::SIGINT),
+            (VQUIT, Signo::SIGQUIT),
+            (VSUSP, Signo::SIGTSTP),
+        ]
+        .into_iter()
+        .find(|&(index, _)| termios.c_cc[index as usize] == c)
+        .map(|(_, signo)| signo)
+    }
+
+    /// Send `signo` to the foreground process group, as typing a character
+    /// found by [`Self::input_signal`] does.
+    pub fn signal_foreground(&self, signo: Signo) {
+        let Some(foreground) = self.control.lock().as_mut().map(|control| {
+            control.fall_back();
+            control.foreground
+        }) else {
+            return;
+        };
+        if let Ok(group) = get_process_group(foreground) {
+            send_signal_process_group(&group, SignalInfo::new(signo, SI_KERNEL as _));
+        }
+    }
+
+    /// Stop controlling the session `sid`, and send `SIGHUP` and `SIGCONT`
+    /// to its foreground process group.
+    fn hang_up(&self, sid: Pid) {
+        let control = {
+            let mut control = self.control.lock();
+            match *control {
+                Some(it) if it.sid == sid => control.take(),
+                _ => None,
+            }
+        };
+        let Some(control) = control else {
+            return;
+        };
+        self.hung_up.lock().insert(sid);
+        if let Ok(group) = get_process_group(control.foreground) {
+            send_signal_process_group(&group, SignalInfo::new(Signo::SIGHUP, SI_KERNEL as _));
+            send_signal_process_group(&group, SignalInfo::new(Signo::SIGCONT, SI_KERNEL as _));
+        }
+    }
+
+    /// Handle the exit of `proc`, which hangs up the terminal if `proc`
+    /// leads the session it controls.
+    ///
+    /// The console itself is never closed, since the kernel keeps it open,
+    /// so this is the only way it hangs up.
+    pub fn process_exited(&self, proc: &Process) {
+        if is_session_leader(proc) {
+            self.hang_up(proc.pid());
+        }
+    }
+
+    /// Make the terminal control the session of the current process, which
+    /// must lead it, as `TIOCSCTTY`. If the terminal controls another
+    /// session, it is taken from it only if `steal` is set and the caller
+    /// has `CAP_SYS_ADMIN`.
+    fn set_controlling(&self, steal: bool) -> LinuxResult {
+        let curr = current();
+        let proc = curr.task_ext().thread.process();
+        if !is_session_leader(proc) {
+            return Err(LinuxError::EPERM);
+        }
+        let mut control = self.control.lock();
+        match *control {
+            Some(it) if it.sid == proc.pid() => return Ok(()),
+            Some(_) if !steal => return Err(LinuxError::EPERM),
+            Some(_) => require_capability(CAP_SYS_ADMIN)?,
+            None => {}
+        }
+        *control = Some(Control {
+            sid: proc.pid(),
+            foreground: proc.group().pgid(),
+        });
+        self.hung_up.lock().remove(&proc.pid());
+        Ok(())
+    }
+
+    /// Whether the terminal hung up on the session of the current process,
+    /// which `poll` reports on it.
+    pub fn hung_up(&self) -> bool {
+        let curr = current();
+        let sid = curr.task_ext().thread.process().group().session().sid();
+        self.hung_up.lock().contains(&sid)
+    }
+
+    /// Give up the terminal, as `TIOCNOTTY`. Only a session leader releases
+    /// it for the session, hanging it up.
+    fn release(&self) -> LinuxResult {
+        let curr = current();
+        let proc = curr.task_ext().thread.process();
+        if self.control_of(proc).is_none() {
+            return Err(LinuxError::ENOTTY);
+        }
+        if is_session_leader(proc) {
+            self.hang_up(proc.pid());
+        }
+        Ok(())
+    }
+
+    /// Set the foreground process group of the session of the current
+    /// process to `pgid`, as `TIOCSPGRP`.
+    fn set_foreground(&self, pgid: i32) -> LinuxResult {
+        self.check_job(Signo::SIGTTOU)?;
+        let curr = current();
+        let proc = curr.task_ext().thread.process();
+        let sid = proc.group().session().sid();
+        if self.control_of(proc).is_none() {
+            return Err(LinuxError::ENOTTY);
+        }
+        if pgid < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let group = get_process_group(pgid as Pid)?;
+        if group.session().sid() != sid {
+            return Err(LinuxError::EPERM);
+        }
+        if let Some(control) = self.control.lock().as_mut().filter(|it| it.sid == sid) {
+            control.foreground = group.pgid();
+        }
+        Ok(())
+    }
+
+    /// Handle the `ioctl` request `op`.
+    ///
+    /// Other requests succeed without doing anything, so that programs
+    /// probing the terminal keep working.
+    pub fn ioctl(&self, op: usize, arg: UserPtr<c_void>) -> LinuxResult<isize> {
+        let addr = arg.address().as_usize();
+        let curr = current();
+        let proc = curr.task_ext().thread.process();
+        match op as u32 {
+            TCGETS => *UserPtr::<termios>::from(addr).get_as_mut()? = *self.termios.lock(),
+            TCSETS | TCSETSW | TCSETSF => {
+                let new = *UserPtr::<termios>::from(addr).get_as_mut()?;
+                self.check_job(Signo::SIGTTOU)?;
+                *self.termios.lock() = new;
+            }
+            TIOCGWINSZ => {
+                *UserPtr::<winsize>::from(addr).get_as_mut()? = winsize {
+                    ws_row: 24,
+                    ws_col: 80,
+                    ws_xpixel: 0,
+                    ws_ypixel: 0,
+                }
+            }
+            TIOCSCTTY => self.set_controlling(addr == 1)?,
+            TIOCNOTTY => self.release()?,
+            TIOCGPGRP => {
+                let control = self.control_of(proc).ok_or(LinuxError::ENOTTY)?;
+                *UserPtr::<Pid>::from(addr).get_as_mut()? = control.foreground;
+            }
+            TIOCSPGRP => self.set_foreground(*UserPtr::<i32>::from(addr).get_as_mut()?)?,
+            TIOCGSID => {
+                let control = self.control_of(proc).ok_or(LinuxError::ENOTTY)?;
+                *UserPtr::<Pid>::from(addr).get_as_mut()? = control.sid;
+            }
+            _ => warn!("Unimplemented tty ioctl: {:#x}", op),
+        }
+        Ok(0)
+    }
+}
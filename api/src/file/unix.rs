@@ -0,0 +1,302 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use axerrno::{LinuxError, LinuxResult};
+use axio::PollState;
+use axsync::Mutex;
+use axtask::{TaskExtRef, current};
+use starry_core::pressure::{self, Pressure};
+
+use super::FileLike;
+use crate::{blocking::Blocking, signal::has_pending_signal};
+
+/// How many bytes may wait in one direction of a pair, like the default
+/// `SO_SNDBUF` of Linux.
+const CHANNEL_SIZE: usize = 212992;
+
+/// The credentials of a process, as in `struct ucred`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UCred {
+    pub pid: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl UCred {
+    /// The credentials of the current process.
+    ///
+    /// Every process runs as root for now, so only the pid tells them apart.
+    pub fn current() -> Self {
+        Self {
+            pid: current().task_ext().thread.process().pid(),
+            uid: 0,
+            gid: 0,
+        }
+    }
+
+    /// The bytes of the `struct ucred`.
+    pub fn to_bytes(self) -> [u8; 12] {
+        let mut bytes = [0; 12];
+        bytes[..4].copy_from_slice(&self.pid.to_ne_bytes());
+        bytes[4..8].copy_from_slice(&self.uid.to_ne_bytes());
+        bytes[8..].copy_from_slice(&self.gid.to_ne_bytes());
+        bytes
+    }
+
+    /// Read a `struct ucred` from its bytes.
+    pub fn from_bytes(bytes: &[u8; 12]) -> Self {
+        Self {
+            pid: u32::from_ne_bytes(bytes[..4].try_into().unwrap()),
+            uid: u32::from_ne_bytes(bytes[4..8].try_into().unwrap()),
+            gid: u32::from_ne_bytes(bytes[8..].try_into().unwrap()),
+        }
+    }
+}
+
+/// What comes with the data of a `recv_with`.
+pub struct Ancillary {
+    /// The credentials the data was sent with.
+    pub cred: UCred,
+    /// The files passed with the data by `SCM_RIGHTS`.
+    pub files: Vec<Arc<dyn FileLike>>,
+}
+
+/// What one `send` wrote, with the credentials it was sent with.
+struct Segment {
+    data: Vec<u8>,
+    /// How much of `data` has been read.
+    read: usize,
+    cred: UCred,
+    /// The files in flight with the data, until the first read of it takes
+    /// them.
+    files: Vec<Arc<dyn FileLike>>,
+}
+
+/// The data going one way between the two ends of a pair.
+#[derive(Default)]
+struct Channel {
+    segments: VecDeque<Segment>,
+    /// The bytes not read yet, over all the segments.
+    len: usize,
+    /// Whether the writing end has shut down, so that reads see the end of
+    /// the stream.
+    shut_down: bool,
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        pressure::sub(Pressure::SocketBytes, self.len);
+    }
+}
+
+/// One end of a connected Unix stream socket, as made by `socketpair`.
+///
+/// Files in flight are released when the end they are sent to is dropped.
+/// There is no garbage collection like in Linux though, so a socket sent to
+/// itself, directly or through other sockets, and then closed, is never
+/// dropped.
+///
+/// Unix sockets bound to a path are not supported yet.
+pub struct UnixStream {
+    /// What the peer writes to this end.
+    rx: Arc<Mutex<Channel>>,
+    /// What this end writes to the peer.
+    tx: Arc<Mutex<Channel>>,
+    /// The credentials of the peer when the pair was made, as `SO_PEERCRED`
+    /// reports them.
+    peer_cred: UCred,
+    /// Whether `SO_PASSCRED` is set, so that reads report the credentials
+    /// of the data.
+    passcred: AtomicBool,
+    nonblocking: AtomicBool,
+}
+
+impl UnixStream {
+    /// Make a pair of connected ends, both belonging to the current process.
+    pub fn pair() -> (Self, Self) {
+        let cred = UCred::current();
+        let a = Arc::new(Mutex::new(Channel::default()));
+        let b = Arc::new(Mutex::new(Channel::default()));
+        let end = |rx, tx| Self {
+            rx,
+            tx,
+            peer_cred: cred,
+            passcred: AtomicBool::new(false),
+            nonblocking: AtomicBool::new(false),
+        };
+        (end(a.clone(), b.clone()), end(b, a))
+    }
+
+    /// The credentials of the peer, as `SO_PEERCRED` reports them.
+    pub fn peer_cred(&self) -> UCred {
+        self.peer_cred
+    }
+
+    pub fn passcred(&self) -> bool {
+        self.passcred.load(Ordering::Relaxed)
+    }
+
+    pub fn set_passcred(&self, passcred: bool) {
+        self.passcred.store(passcred, Ordering::Relaxed);
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
+    }
+
+    /// Whether the peer has been closed, which `poll` reports as a hangup.
+    pub fn peer_closed(&self) -> bool {
+        Arc::strong_count(&self.rx) == 1
+    }
+
+    /// Send `buf` with the credentials `cred`, which are those of the
+    /// current process if `None`, and pass `files` with it.
+    ///
+    /// The files are dropped if `buf` is empty, as there is nothing to pass
+    /// them with.
+    pub fn send_with(
+        &self,
+        buf: &[u8],
+        cred: Option<UCred>,
+        mut files: Vec<Arc<dyn FileLike>>,
+    ) -> LinuxResult<usize> {
+        let cred = cred.unwrap_or_else(UCred::current);
+        let mut written = 0;
+        loop {
+            let mut channel = self.tx.lock();
+            if channel.shut_down || self.peer_closed() {
+                return Err(LinuxError::EPIPE);
+            }
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            let room = CHANNEL_SIZE - channel.len;
+            if room == 0 {
+                if self.nonblocking.load(Ordering::Relaxed) {
+                    return match written {
+                        0 => Err(LinuxError::EAGAIN),
+                        n => Ok(n),
+                    };
+                }
+                if has_pending_signal() {
+                    return match written {
+                        0 => Err(Blocking::Send.interrupted(false)),
+                        n => Ok(n),
+                    };
+                }
+                drop(channel);
+                axtask::yield_now();
+                continue;
+            }
+            let end = buf.len().min(written + room);
+            channel.segments.push_back(Segment {
+                data: buf[written..end].to_vec(),
+                read: 0,
+                cred,
+                // With the first part, if it takes more than one.
+                files: core::mem::take(&mut files),
+            });
+            channel.len += end - written;
+            pressure::add(Pressure::SocketBytes, end - written);
+            written = end;
+            if written == buf.len() {
+                return Ok(written);
+            }
+        }
+    }
+
+    /// Receive into `buf`, and return how much was received with what came
+    /// with it, which is `None` at the end of the stream.
+    ///
+    /// With `SO_PASSCRED` set, a read stops where the credentials change,
+    /// so that all it returns was sent with the same ones. A read returns the
+    /// files of one send at most, and stops after the data they came with.
+    pub fn recv_with(&self, buf: &mut [u8]) -> LinuxResult<(usize, Option<Ancillary>)> {
+        loop {
+            let mut channel = self.rx.lock();
+            let Some(first) = channel.segments.front() else {
+                if channel.shut_down || self.peer_closed() || buf.is_empty() {
+                    return Ok((0, None));
+                }
+                if self.nonblocking.load(Ordering::Relaxed) {
+                    return Err(LinuxError::EAGAIN);
+                }
+                if has_pending_signal() {
+                    return Err(Blocking::Recv.interrupted(false));
+                }
+                drop(channel);
+                axtask::yield_now();
+                continue;
+            };
+            let cred = first.cred;
+            let passcred = self.passcred();
+            let mut read = 0;
+            let mut files = Vec::new();
+            while read < buf.len() {
+                let Some(segment) = channel.segments.front_mut() else {
+                    break;
+                };
+                if passcred && segment.cred != cred {
+                    break;
+                }
+                if read > 0 && !segment.files.is_empty() {
+                    break;
+                }
+                files.append(&mut segment.files);
+                let n = (segment.data.len() - segment.read).min(buf.len() - read);
+                buf[read..read + n].copy_from_slice(&segment.data[segment.read..][..n]);
+                segment.read += n;
+                read += n;
+                if segment.read == segment.data.len() {
+                    channel.segments.pop_front();
+                }
+                if !files.is_empty() {
+                    break;
+                }
+            }
+            channel.len -= read;
+            pressure::sub(Pressure::SocketBytes, read);
+            return Ok((read, Some(Ancillary { cred, files })));
+        }
+    }
+
+    pub fn send(&self, buf: &[u8]) -> LinuxResult<usize> {
+        self.send_with(buf, None, Vec::new())
+    }
+
+    pub fn recv(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        self.recv_with(buf).map(|res| res.0)
+    }
+
+    /// Shut down writing, so that the peer reads the end of the stream.
+    pub fn shutdown(&self) -> LinuxResult {
+        self.tx.lock().shut_down = true;
+        Ok(())
+    }
+
+    pub fn poll(&self) -> PollState {
+        // One at a time, as the peer locks them the other way round.
+        let readable = {
+            let rx = self.rx.lock();
+            rx.len > 0 || rx.shut_down
+        };
+        let writable = {
+            let tx = self.tx.lock();
+            tx.len < CHANNEL_SIZE || tx.shut_down
+        };
+        let hung_up = self.peer_closed();
+        PollState {
+            readable: readable || hung_up,
+            writable: writable || hung_up,
+        }
+    }
+}
+
+impl Drop for UnixStream {
+    fn drop(&mut self) {
+        // Release the files in flight to this end, which cannot be received
+        // anymore.
+        self.rx.lock().segments.clear();
+    }
+}
@@ -0,0 +1,352 @@
+//! Synthetic file trees, such as `/dev`, `/proc` and `/sys`, which are
+//! generated by the kernel instead of being stored on a filesystem.
+//!
+//! Each tree is a [`VirtualDir`] registered under a mount prefix. Path based
+//! syscalls (`openat`, `fstatat`, ...) consult [`lookup_virtual`] before
+//! falling back to `axfs`, and `getdents64` lists a [`VirtualDirFile`]
+//! through [`VirtualDir::list_entries`], so every tree gets the same `.`
+//! and `..` entries, file types and inode numbers.
+
+use core::{any::Any, ffi::c_int};
+
+use alloc::{collections::btree_map::BTreeMap, format, string::String, sync::Arc, vec::Vec};
+use axerrno::{LinuxError, LinuxResult};
+use axfs::fops::FileType;
+use axio::PollState;
+use axsync::Mutex;
+use linux_raw_sys::general::{O_DIRECTORY, O_RDONLY};
+use spin::RwLock;
+
+use super::{FileLike, Kstat};
+
+/// An entry of a [`VirtualDir`].
+pub struct VirtualDirEntry {
+    /// The name of the entry.
+    pub name: String,
+    /// The type of the entry.
+    pub ty: FileType,
+}
+
+impl VirtualDirEntry {
+    pub fn new(name: impl Into<String>, ty: FileType) -> Self {
+        Self {
+            name: name.into(),
+            ty,
+        }
+    }
+}
+
+/// A node found by [`VirtualDir::lookup`].
+pub enum VirtualNode {
+    /// A sub-directory.
+    Dir(Arc<dyn VirtualDir>),
+    /// A file, freshly opened for this lookup.
+    File(Arc<dyn FileLike>),
+    /// A symbolic link to `target`.
+    ///
+    /// If `file` is set, following the link opens `file` itself instead of
+    /// resolving `target`, like the links in `/proc/<pid>/fd`.
+    Link {
+        target: String,
+        file: Option<Arc<dyn FileLike>>,
+    },
+}
+
+/// A directory of a synthetic file tree.
+pub trait VirtualDir: Send + Sync {
+    /// List the entries of the directory, without `.` and `..`.
+    fn list_entries(&self) -> LinuxResult<Vec<VirtualDirEntry>>;
+
+    /// Look up the entry named `name`.
+    fn lookup(&self, name: &str) -> LinuxResult<VirtualNode>;
+}
+
+/// An entry of a [`StaticDir`]: its name, type and a function creating the
+/// node on lookup.
+pub type StaticEntry = (&'static str, FileType, fn() -> VirtualNode);
+
+/// A [`VirtualDir`] with a fixed set of entries.
+pub struct StaticDir(pub &'static [StaticEntry]);
+
+impl VirtualDir for StaticDir {
+    fn list_entries(&self) -> LinuxResult<Vec<VirtualDirEntry>> {
+        Ok(self
+            .0
+            .iter()
+            .map(|(name, ty, _)| VirtualDirEntry::new(*name, *ty))
+            .collect())
+    }
+
+    fn lookup(&self, name: &str) -> LinuxResult<VirtualNode> {
+        self.0
+            .iter()
+            .find(|(n, ..)| *n == name)
+            .map(|(_, _, open)| open())
+            .ok_or(LinuxError::ENOENT)
+    }
+}
+
+/// A stable inode number for the synthetic file at `path`.
+pub fn synth_ino(path: &str) -> u64 {
+    // FNV-1a, with the top bit set to stay clear of on-disk inode numbers.
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for b in path.trim_end_matches('/').bytes() {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash | (1 << 63)
+}
+
+static VIRTUAL_TREES: RwLock<BTreeMap<&'static str, Arc<dyn VirtualDir>>> =
+    RwLock::new(BTreeMap::new());
+
+/// Register `root` as the synthetic tree mounted at `prefix`.
+pub fn register_virtual_tree(prefix: &'static str, root: Arc<dyn VirtualDir>) {
+    VIRTUAL_TREES.write().insert(prefix, root);
+}
+
+/// Look up the canonical absolute `path` in the synthetic trees.
+///
+/// Returns `None` if `path` is not inside any of them.
+pub fn lookup_virtual(path: &str) -> Option<LinuxResult<VirtualNode>> {
+    let path = path.trim_end_matches('/');
+    let (root, rest) = VIRTUAL_TREES.read().iter().find_map(|(prefix, root)| {
+        let rest = path.strip_prefix(prefix)?;
+        (rest.is_empty() || rest.starts_with('/')).then(|| (root.clone(), rest))
+    })?;
+
+    let mut node = VirtualNode::Dir(root);
+    for name in rest.split('/').filter(|s| !s.is_empty()) {
+        node = match node {
+            VirtualNode::Dir(dir) => match dir.lookup(name) {
+                Ok(node) => node,
+                Err(e) => return Some(Err(e)),
+            },
+            // TODO: follow links in the middle of a path
+            VirtualNode::File(_) | VirtualNode::Link { .. } => {
+                return Some(Err(LinuxError::ENOTDIR));
+            }
+        };
+    }
+    Some(Ok(node))
+}
+
+/// Get the target of the synthetic link at `path` if it refers to a path,
+/// e.g. `/proc/self/cwd`.
+///
+/// Path based syscalls should continue with the returned path, since the
+/// target may be outside of the synthetic trees.
+pub fn resolve_virtual_link(path: &str) -> Option<String> {
+    match lookup_virtual(path)? {
+        Ok(VirtualNode::Link { target, file: None }) => Some(target),
+        _ => None,
+    }
+}
+
+/// Read the synthetic link at `path`.
+///
+/// Returns `None` if `path` is not inside any synthetic tree, and `EINVAL`
+/// if it is not a link.
+pub fn read_link_virtual(path: &str) -> Option<LinuxResult<String>> {
+    Some(lookup_virtual(path)?.and_then(|node| match node {
+        VirtualNode::Link { target, .. } => Ok(target),
+        _ => Err(LinuxError::EINVAL),
+    }))
+}
+
+/// Open the synthetic file or directory at `path`.
+///
+/// Returns `None` if `path` is not inside any synthetic tree. Links to a
+/// path fail with `ELOOP`; resolve them with [`resolve_virtual_link`] first.
+pub fn open_virtual(path: &str) -> Option<LinuxResult<Arc<dyn FileLike>>> {
+    Some(lookup_virtual(path)?.and_then(|node| match node {
+        VirtualNode::Dir(dir) => Ok(Arc::new(VirtualDirFile::new(dir, path.into())) as _),
+        VirtualNode::File(file)
+        | VirtualNode::Link {
+            file: Some(file), ..
+        } => Ok(file),
+        VirtualNode::Link { file: None, .. } => Err(LinuxError::ELOOP),
+    }))
+}
+
+/// Get the metadata of the synthetic file or directory at `path`.
+///
+/// If `follow` is false, a link reports itself instead of its target.
+/// Returns `None` if `path` is not inside any synthetic tree.
+pub fn stat_virtual(path: &str, follow: bool) -> Option<LinuxResult<Kstat>> {
+    Some(lookup_virtual(path)?.and_then(|node| match node {
+        VirtualNode::Dir(dir) => VirtualDirFile::new(dir, path.into()).stat(),
+        VirtualNode::File(file) => Ok(Kstat {
+            ino: synth_ino(path),
+            ..file.stat()?
+        }),
+        VirtualNode::Link { target, .. } if !follow => Ok(Kstat {
+            ino: synth_ino(path),
+            mode: ((FileType::SymLink as u32) << 12) | 0o777, // rwxrwxrwx
+            size: target.len() as _,
+            ..Default::default()
+        }),
+        // The file behind a link keeps its own inode number.
+        VirtualNode::Link {
+            file: Some(file), ..
+        } => file.stat(),
+        VirtualNode::Link { file: None, .. } => Err(LinuxError::ELOOP),
+    }))
+}
+
+/// An opened directory of a synthetic tree.
+pub struct VirtualDirFile {
+    dir: Arc<dyn VirtualDir>,
+    path: String,
+    /// The number of entries already returned by `getdents64`.
+    pos: Mutex<usize>,
+}
+
+impl VirtualDirFile {
+    pub fn new(dir: Arc<dyn VirtualDir>, path: String) -> Self {
+        Self {
+            dir,
+            path,
+            pos: Mutex::new(0),
+        }
+    }
+
+    /// Get the path of the directory.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Get the entries of the directory, including `.` and `..`, each with
+    /// its inode number.
+    pub fn entries(&self) -> LinuxResult<Vec<(u64, VirtualDirEntry)>> {
+        let path = self.path.trim_end_matches('/');
+        let parent = match path.rfind('/') {
+            Some(0) | None => "/",
+            Some(pos) => &path[..pos],
+        };
+        let mut entries = Vec::from([
+            (
+                synth_ino(&self.path),
+                VirtualDirEntry::new(".", FileType::Dir),
+            ),
+            (synth_ino(parent), VirtualDirEntry::new("..", FileType::Dir)),
+        ]);
+        for entry in self.dir.list_entries()? {
+            let ino = synth_ino(&format!("{}/{}", path, entry.name));
+            entries.push((ino, entry));
+        }
+        Ok(entries)
+    }
+
+    /// Get the `getdents64` cursor.
+    pub fn pos(&self) -> &Mutex<usize> {
+        &self.pos
+    }
+}
+
+impl FileLike for VirtualDirFile {
+    fn read(&self, _buf: &mut [u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EISDIR)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EBADF)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        Ok(Kstat {
+            ino: synth_ino(&self.path),
+            nlink: 2,
+            mode: ((FileType::Dir as u32) << 12) | 0o555, // r-xr-xr-x
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+
+    fn status_flags(&self) -> u32 {
+        O_RDONLY | O_DIRECTORY
+    }
+
+    fn from_fd(fd: c_int) -> LinuxResult<Arc<Self>> {
+        Self::from_fd_or(fd, LinuxError::ENOTDIR)
+    }
+}
+
+/// A read-only regular file whose content is generated when it is opened.
+pub struct SynthFile {
+    content: Vec<u8>,
+    offset: Mutex<usize>,
+}
+
+impl SynthFile {
+    pub fn new(content: impl Into<Vec<u8>>) -> Self {
+        Self {
+            content: content.into(),
+            offset: Mutex::new(0),
+        }
+    }
+
+    /// Wrap the file into a [`VirtualNode`].
+    pub fn node(content: impl Into<Vec<u8>>) -> VirtualNode {
+        VirtualNode::File(Arc::new(Self::new(content)))
+    }
+}
+
+impl FileLike for SynthFile {
+    fn read(&self, buf: &mut [u8]) -> LinuxResult<usize> {
+        let mut offset = self.offset.lock();
+        let data = self.content.get(*offset..).unwrap_or_default();
+        let len = data.len().min(buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        *offset += len;
+        Ok(len)
+    }
+
+    fn write(&self, _buf: &[u8]) -> LinuxResult<usize> {
+        Err(LinuxError::EACCES)
+    }
+
+    fn stat(&self) -> LinuxResult<Kstat> {
+        // Like procfs on Linux, report an empty size.
+        Ok(Kstat {
+            mode: ((FileType::File as u32) << 12) | 0o444, // r--r--r--
+            ..Default::default()
+        })
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+
+    fn poll(&self) -> LinuxResult<PollState> {
+        Ok(PollState {
+            readable: true,
+            writable: false,
+        })
+    }
+
+    fn set_nonblocking(&self, _nonblocking: bool) -> LinuxResult {
+        Ok(())
+    }
+}
+
+#[ctor_bare::register_ctor]
+fn init_virtual_trees() {
+    register_virtual_tree("/dev", Arc::new(super::devfs::root()));
+    register_virtual_tree("/proc", Arc::new(super::procfs::ProcRoot));
+    register_virtual_tree("/sys", Arc::new(super::sysfs::root()));
+}
@@ -0,0 +1,104 @@
+//! Extended attributes.
+//!
+//! Only tmpfs supports them, and only in the `user.` namespace. The
+//! filesystems below have nowhere to store them, so they are kept here by
+//! real path, like the timestamps, and lost when the file is removed.
+
+use alloc::{collections::btree_map::BTreeMap, string::String, vec::Vec};
+use axerrno::{LinuxError, LinuxResult};
+use linux_raw_sys::general::{XATTR_CREATE, XATTR_REPLACE};
+use spin::RwLock;
+
+use super::times::{fs_type, key, move_under};
+
+/// The most attributes a file can have.
+pub const XATTR_COUNT_MAX: usize = 1000;
+
+type Xattrs = BTreeMap<String, Vec<u8>>;
+
+static XATTRS: RwLock<BTreeMap<String, Xattrs>> = RwLock::new(BTreeMap::new());
+
+/// Whether the filesystem `path` is on supports extended attributes.
+pub fn xattrs_supported(path: &str) -> bool {
+    fs_type(path) == "tmpfs"
+}
+
+/// Get the value of the attribute `name` of the file at `path`.
+///
+/// Fails with `ENODATA` if it has no such attribute.
+pub fn get_xattr(path: &str, name: &str) -> LinuxResult<Vec<u8>> {
+    XATTRS
+        .read()
+        .get(key(path))
+        .and_then(|xattrs| xattrs.get(name))
+        .cloned()
+        .ok_or(LinuxError::ENODATA)
+}
+
+/// Set the attribute `name` of the file at `path` to `value`.
+///
+/// With `XATTR_CREATE`, fails with `EEXIST` if it exists, and with
+/// `XATTR_REPLACE`, fails with `ENODATA` if it does not. A new attribute
+/// beyond [`XATTR_COUNT_MAX`] fails with `ENOSPC`.
+pub fn set_xattr(path: &str, name: &str, value: &[u8], flags: u32) -> LinuxResult {
+    let mut all = XATTRS.write();
+    let xattrs = all.entry(key(path).into()).or_default();
+    match xattrs.get_mut(name) {
+        Some(_) if flags & XATTR_CREATE != 0 => Err(LinuxError::EEXIST),
+        Some(old) => {
+            *old = value.into();
+            Ok(())
+        }
+        None if flags & XATTR_REPLACE != 0 => Err(LinuxError::ENODATA),
+        None if xattrs.len() >= XATTR_COUNT_MAX => Err(LinuxError::ENOSPC),
+        None => {
+            xattrs.insert(name.into(), value.into());
+            Ok(())
+        }
+    }
+}
+
+/// Remove the attribute `name` of the file at `path`.
+///
+/// Fails with `ENODATA` if it has no such attribute.
+pub fn remove_xattr(path: &str, name: &str) -> LinuxResult {
+    let mut all = XATTRS.write();
+    let xattrs = all.get_mut(key(path)).ok_or(LinuxError::ENODATA)?;
+    xattrs.remove(name).ok_or(LinuxError::ENODATA)?;
+    if xattrs.is_empty() {
+        all.remove(key(path));
+    }
+    Ok(())
+}
+
+/// The names of the attributes of the file at `path`, each followed by a
+/// NUL, as `listxattr` returns them.
+pub fn list_xattrs(path: &str) -> Vec<u8> {
+    let mut list = Vec::new();
+    if let Some(xattrs) = XATTRS.read().get(key(path)) {
+        for name in xattrs.keys() {
+            list.extend_from_slice(name.as_bytes());
+            list.push(0);
+        }
+    }
+    list
+}
+
+/// Keep the attributes of the file moved from `from` to `to`.
+pub fn move_xattrs(from: &str, to: &str) {
+    let mut all = XATTRS.write();
+    if let Some(xattrs) = all.remove(key(from)) {
+        all.insert(key(to).into(), xattrs);
+    }
+}
+
+/// Keep the attributes of the files under the directory moved from `from`
+/// to `to`.
+pub fn move_xattrs_under(from: &str, to: &str) {
+    move_under(&mut XATTRS.write(), from, to);
+}
+
+/// Forget the attributes of the removed file at `path`.
+pub fn remove_xattrs(path: &str) {
+    XATTRS.write().remove(key(path));
+}
@@ -0,0 +1,171 @@
+//! The FP/SIMD state of user space in signal frames.
+//!
+//! The frame `axsignal` builds for a handler holds the general registers
+//! only, so the FP/SIMD registers of the code the signal interrupted are
+//! saved next to it here, where the `ucontext` of Linux has them, and
+//! loaded back from there by `rt_sigreturn`. A handler can read and change
+//! them there, and is free to use the FPU itself meanwhile.
+//!
+//! The state is saved from the registers first if they hold it, as tasks
+//! switch it lazily, see [`axhal::arch::save_user_fp`].
+//!
+//! - On x86_64, the `FXSAVE` area `uc_mcontext.fpregs` points to sits below
+//!   the frame, in the `FP_GAP` bytes the handler starts under it.
+//! - On aarch64, an `fpsimd_context` record is the first of
+//!   `uc_mcontext.__reserved`.
+//! - On riscv64, the state is `uc_mcontext.sc_fpregs`.
+//! - On loongarch64, the records would follow the frame, where `axsignal`
+//!   puts the `siginfo`, so no state is saved yet, and a handler using the
+//!   FPU changes the registers of the code it interrupted.
+
+use axhal::arch::TrapFrame;
+
+use crate::ptr::{UserConstPtr, UserPtr};
+
+/// The bytes between the frame and the stack the handler starts with, on
+/// x86_64, for the `FXSAVE` area aligned to 64 bytes, like Linux.
+#[cfg(target_arch = "x86_64")]
+const FP_GAP: usize = 576;
+
+/// The offset of `uc_mcontext.fpregs` in a `ucontext`.
+#[cfg(target_arch = "x86_64")]
+const UC_FPREGS_OFFSET: usize = 224;
+
+/// The offset of `uc_mcontext.__reserved` in a `ucontext`.
+#[cfg(target_arch = "aarch64")]
+const UC_RESERVED_OFFSET: usize = 464;
+
+/// The `magic` of an `fpsimd_context` record.
+#[cfg(target_arch = "aarch64")]
+const FPSIMD_MAGIC: u32 = 0x4650_8001;
+
+/// The offset of `uc_mcontext.sc_fpregs` in a `ucontext`.
+#[cfg(target_arch = "riscv64")]
+const UC_FPREGS_OFFSET: usize = 432;
+
+/// The `fpsimd_context` record of aarch64.
+#[cfg(target_arch = "aarch64")]
+#[repr(C)]
+pub(crate) struct FpsimdContext {
+    pub(crate) magic: u32,
+    pub(crate) size: u32,
+    pub(crate) fpsr: u32,
+    pub(crate) fpcr: u32,
+    pub(crate) vregs: [u128; 32],
+}
+
+/// Save the FP/SIMD registers in the frame of the handler `tf` has just
+/// been set up to run, whose `ucontext` is at `ucontext`.
+///
+/// If the frame cannot be written, the state is left out, as the handler
+/// faults on the frame anyway.
+pub fn save(tf: &mut TrapFrame, ucontext: usize) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        // The return address of the handler is at the top of its stack.
+        let ra_addr = tf.sp();
+        let area_addr = (ra_addr + size_of::<usize>() - 512) & !63;
+        let sp = ra_addr - FP_GAP;
+        let Ok(ra) = UserConstPtr::<usize>::from(ra_addr).get_as_ref().copied() else {
+            return;
+        };
+        let Ok(new_ra) = UserPtr::<usize>::from(sp).get_as_mut() else {
+            return;
+        };
+        *new_ra = ra;
+        tf.set_sp(sp);
+        let fpregs = match UserPtr::<axhal::arch::FxsaveArea>::from(area_addr).get_as_mut() {
+            Ok(area) if axhal::arch::save_user_fp(area) => area_addr,
+            _ => 0,
+        };
+        if let Ok(ptr) = UserPtr::<usize>::from(ucontext + UC_FPREGS_OFFSET).get_as_mut() {
+            *ptr = fpregs;
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        let _ = tf;
+        let mut state = axhal::arch::FpState::default();
+        if !axhal::arch::save_user_fp(&mut state) {
+            return;
+        }
+        let record = ucontext + UC_RESERVED_OFFSET;
+        let Ok(context) = UserPtr::<FpsimdContext>::from(record).get_as_mut() else {
+            return;
+        };
+        *context = FpsimdContext {
+            magic: FPSIMD_MAGIC,
+            size: size_of::<FpsimdContext>() as u32,
+            fpsr: state.fpsr,
+            fpcr: state.fpcr,
+            vregs: state.regs,
+        };
+        // The record ending the list.
+        if let Ok(end) = UserPtr::<[u32; 2]>::from(record + size_of::<FpsimdContext>()).get_as_mut()
+        {
+            *end = [0; 2];
+        }
+    }
+    #[cfg(target_arch = "riscv64")]
+    {
+        let _ = tf;
+        if let Ok(state) =
+            UserPtr::<axhal::arch::UserFpState>::from(ucontext + UC_FPREGS_OFFSET).get_as_mut()
+        {
+            axhal::arch::save_user_fp(state);
+        }
+    }
+    #[cfg(target_arch = "loongarch64")]
+    let _ = (tf, ucontext);
+}
+
+/// Load the FP/SIMD registers from the frame `rt_sigreturn` returns from,
+/// whose `ucontext` is at `ucontext`, as the handler left them, before the
+/// general registers are restored from `tf`.
+///
+/// On x86_64, this also moves the stack back up to the frame, over the
+/// `FXSAVE` area. A state which cannot be read is left as it is.
+pub fn restore(tf: &mut TrapFrame, ucontext: usize) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        tf.set_sp(tf.sp() + FP_GAP);
+        let Ok(&fpregs) = UserConstPtr::<usize>::from(ucontext + UC_FPREGS_OFFSET).get_as_ref()
+        else {
+            return;
+        };
+        if fpregs == 0 {
+            return;
+        }
+        if let Ok(area) = UserConstPtr::<axhal::arch::FxsaveArea>::from(fpregs).get_as_ref() {
+            axhal::arch::restore_user_fp(area);
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        let _ = tf;
+        let Ok(context) =
+            UserConstPtr::<FpsimdContext>::from(ucontext + UC_RESERVED_OFFSET).get_as_ref()
+        else {
+            return;
+        };
+        if context.magic != FPSIMD_MAGIC || context.size != size_of::<FpsimdContext>() as u32 {
+            return;
+        }
+        axhal::arch::restore_user_fp(&axhal::arch::FpState {
+            regs: context.vregs,
+            fpcr: context.fpcr,
+            fpsr: context.fpsr,
+        });
+    }
+    #[cfg(target_arch = "riscv64")]
+    {
+        let _ = tf;
+        if let Ok(state) =
+            UserConstPtr::<axhal::arch::UserFpState>::from(ucontext + UC_FPREGS_OFFSET).get_as_ref()
+        {
+            axhal::arch::restore_user_fp(state);
+        }
+    }
+    #[cfg(target_arch = "loongarch64")]
+    let _ = (tf, ucontext);
+}
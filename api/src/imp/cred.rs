@@ -0,0 +1,208 @@
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::{
+    __user_cap_data_struct, __user_cap_header_struct, _LINUX_CAPABILITY_VERSION_1,
+    _LINUX_CAPABILITY_VERSION_2, _LINUX_CAPABILITY_VERSION_3, S_IFDIR, S_IFMT, W_OK, X_OK,
+};
+use starry_core::{
+    cred::{CAP_DAC_OVERRIDE, CAP_DAC_READ_SEARCH, Credentials, dac_enforcing},
+    task::{ProcessData, get_thread},
+};
+
+use crate::{
+    file::{Kstat, stat_at_path},
+    path::FilePath,
+    ptr::{UserConstPtr, UserPtr},
+};
+
+/// The user and group id of every process, as `getuid` and `getgid`
+/// report them.
+pub(crate) const REAL_ID: u32 = 0;
+/// The effective user and group id of every process, as `geteuid` and
+/// `getegid` report them, which every file is owned by as well.
+pub(crate) const EFFECTIVE_ID: u32 = 1;
+
+/// Fail with `EPERM` unless the capability `cap` is effective in the current
+/// process.
+pub fn require_capability(cap: u32) -> LinuxResult {
+    if current()
+        .task_ext()
+        .process_data()
+        .cred
+        .read()
+        .has_capability(cap)
+    {
+        Ok(())
+    } else {
+        Err(LinuxError::EPERM)
+    }
+}
+
+/// Fail with `EPERM` unless the current process may act on the process of
+/// `target` as on one of its own user, or the capability `cap` is effective
+/// in it.
+///
+/// Linux compares the user and group ids for this, but every process has
+/// the same ones here, so the capabilities stand for them: a process with a
+/// permitted capability the current one lacks is out of its reach.
+pub fn require_same_cred(target: &ProcessData, cap: u32) -> LinuxResult {
+    let cred = current().task_ext().process_data().cred.read().clone();
+    if target.cred.read().cap_permitted & !cred.cap_permitted == 0 || cred.has_capability(cap) {
+        Ok(())
+    } else {
+        Err(LinuxError::EPERM)
+    }
+}
+
+/// Whether `cred` grants `access`, a mask of `R_OK`, `W_OK` and `X_OK`, to
+/// the file of `stat`, for the user and group id `id`.
+///
+/// The permission bits of the owner are used if `id` owns the file, else
+/// those of the group if it is the group of the file, else those of others.
+/// `CAP_DAC_OVERRIDE` grants any access but executing a file without any
+/// execute bit, and `CAP_DAC_READ_SEARCH` reading any file and searching
+/// any directory.
+pub fn may_access(cred: &Credentials, stat: &Kstat, access: u32, id: u32) -> bool {
+    let mode = stat.mode();
+    let bits = if stat.uid() == id {
+        mode >> 6
+    } else if stat.gid() == id {
+        mode >> 3
+    } else {
+        mode
+    };
+    if access & !bits & 0o7 == 0 {
+        return true;
+    }
+    let is_dir = mode & S_IFMT == S_IFDIR;
+    if cred.has_capability(CAP_DAC_OVERRIDE) && (access & X_OK == 0 || is_dir || mode & 0o111 != 0)
+    {
+        return true;
+    }
+    cred.has_capability(CAP_DAC_READ_SEARCH) && access & W_OK == 0 && (access & X_OK == 0 || is_dir)
+}
+
+/// Check that the current process may `access` the file at `path`, whose
+/// metadata is `stat`, with its effective ids, or its real ones if `real`.
+///
+/// A denial fails with `EACCES` if permission checks are enforced, and is
+/// only logged otherwise.
+pub fn check_access(path: &str, stat: &Kstat, access: u32, real: bool) -> LinuxResult {
+    let id = if real { REAL_ID } else { EFFECTIVE_ID };
+    let cred = current().task_ext().process_data().cred.read().clone();
+    if may_access(&cred, stat, access, id) {
+        return Ok(());
+    }
+    if dac_enforcing() {
+        return Err(LinuxError::EACCES);
+    }
+    warn!(
+        "permissive: would deny access {:#o} to {:?} of mode {:#o}",
+        access,
+        path,
+        stat.mode()
+    );
+    Ok(())
+}
+
+/// Like [`check_access`] with the effective ids, getting the metadata of
+/// the file at `path` only if the capabilities do not grant `access` anyway.
+pub fn check_path_access(path: &str, access: u32) -> LinuxResult {
+    let overridden = current()
+        .task_ext()
+        .process_data()
+        .cred
+        .read()
+        .has_capability(CAP_DAC_OVERRIDE);
+    if overridden && access & X_OK == 0 {
+        return Ok(());
+    }
+    check_access(path, &stat_at_path(path)?, access, false)
+}
+
+/// Check that the current process may add or remove entries in the
+/// directory holding `path`, which needs write and search access to it.
+pub fn check_parent_access(path: &FilePath) -> LinuxResult {
+    check_path_access(path.parent()?, W_OK | X_OK)
+}
+
+/// Get the number of `__user_cap_data_struct`s of the version in `header`.
+///
+/// An unknown version is replaced with the preferred one.
+fn cap_data_len(header: &mut __user_cap_header_struct) -> LinuxResult<usize> {
+    match header.version {
+        _LINUX_CAPABILITY_VERSION_1 => Ok(1),
+        _LINUX_CAPABILITY_VERSION_2 | _LINUX_CAPABILITY_VERSION_3 => Ok(2),
+        _ => {
+            header.version = _LINUX_CAPABILITY_VERSION_3;
+            Err(LinuxError::EINVAL)
+        }
+    }
+}
+
+pub fn sys_capget(
+    header: UserPtr<__user_cap_header_struct>,
+    data: UserPtr<__user_cap_data_struct>,
+) -> LinuxResult<isize> {
+    let header = header.get_as_mut()?;
+    let len = match cap_data_len(header) {
+        Ok(len) => len,
+        // Probing for the preferred version.
+        Err(_) if data.is_null() => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    if data.is_null() {
+        return Ok(0);
+    }
+
+    let cred = if header.pid == 0 {
+        current().task_ext().process_data().cred.read().clone()
+    } else {
+        let thread = get_thread(header.pid as _)?;
+        let proc_data = thread
+            .process()
+            .data::<ProcessData>()
+            .ok_or(LinuxError::ESRCH)?;
+        proc_data.cred.read().clone()
+    };
+    for (i, data) in data.get_as_mut_slice(len)?.iter_mut().enumerate() {
+        *data = __user_cap_data_struct {
+            effective: (cred.cap_effective >> (32 * i)) as u32,
+            permitted: (cred.cap_permitted >> (32 * i)) as u32,
+            inheritable: (cred.cap_inheritable >> (32 * i)) as u32,
+        };
+    }
+    Ok(0)
+}
+
+pub fn sys_capset(
+    header: UserPtr<__user_cap_header_struct>,
+    data: UserConstPtr<__user_cap_data_struct>,
+) -> LinuxResult<isize> {
+    let header = header.get_as_mut()?;
+    let len = cap_data_len(header)?;
+
+    let curr = current();
+    let thread = &curr.task_ext().thread;
+    // Only the capabilities of the caller can be changed.
+    if header.pid != 0 && header.pid as u32 != thread.tid() {
+        return Err(LinuxError::EPERM);
+    }
+
+    let (mut effective, mut permitted, mut inheritable) = (0u64, 0u64, 0u64);
+    for (i, data) in data.get_as_slice(len)?.iter().enumerate() {
+        effective |= (data.effective as u64) << (32 * i);
+        permitted |= (data.permitted as u64) << (32 * i);
+        inheritable |= (data.inheritable as u64) << (32 * i);
+    }
+    if !curr
+        .task_ext()
+        .process_data()
+        .cred
+        .write()
+        .set_capabilities(effective, permitted, inheritable)
+    {
+        return Err(LinuxError::EPERM);
+    }
+    Ok(0)
+}
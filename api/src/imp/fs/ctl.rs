@@ -1,19 +1,33 @@
 use core::{
     ffi::{c_char, c_int, c_void},
-    mem::offset_of,
+    time::Duration,
 };
 
-use alloc::ffi::CString;
+use alloc::{borrow::Cow, ffi::CString, format, string::String};
 use axerrno::{LinuxError, LinuxResult};
 use axfs::fops::DirEntry;
+use axhal::time::wall_time;
 use linux_raw_sys::general::{
-    AT_FDCWD, AT_REMOVEDIR, DT_BLK, DT_CHR, DT_DIR, DT_FIFO, DT_LNK, DT_REG, DT_SOCK, DT_UNKNOWN,
-    linux_dirent64,
+    AT_FDCWD, DT_BLK, DT_CHR, DT_DIR, DT_FIFO, DT_LNK, DT_REG, DT_SOCK, DT_UNKNOWN, IN_CREATE,
+    IN_DELETE, IN_ISDIR, R_OK, RENAME_NOREPLACE, S_IFDIR, S_IFLNK, S_IFMT, UTIME_NOW, UTIME_OMIT,
+    W_OK, X_OK, timespec,
 };
+use starry_core::sandbox::PathAccess;
 
+use super::{CWD_MOUNT, check_writable, is_mount_point, mount_ref};
 use crate::{
-    file::{Directory, FileLike},
-    path::{HARDLINK_MANAGER, handle_file_path},
+    abi::write_dirent64,
+    check_access, check_parent_access,
+    file::{
+        BlockFile, Directory, File, FileLike, Socket, VirtualDirFile, get_file_like, init_times,
+        inode, is_unlinked_tmpfile, lstat_at_path, notify, read_link_virtual, remove_inode,
+        remove_mode, remove_times, remove_xattrs, set_mode, set_times, stat_virtual, tty_from_fd,
+        update_ctime, update_parent_mtime,
+    },
+    path::{
+        AtFlags, AtTarget, FilePath, HARDLINK_MANAGER, bump_dir_generation, cwd_removed, enter_cwd,
+        handle_file_path, handle_name_path, invalidate_path_cache, resolve_at,
+    },
     ptr::{UserConstPtr, UserPtr, nullable},
 };
 
@@ -25,21 +39,53 @@ use crate::{
 /// * `op` - The request code. It is of type unsigned long in glibc and BSD,
 ///   and of type int in musl and other UNIX systems.
 /// * `argp` - The argument to the request. It is a pointer to a memory location
-pub fn sys_ioctl(_fd: i32, _op: usize, _argp: UserPtr<c_void>) -> LinuxResult<isize> {
-    warn!("Unimplemented syscall: SYS_IOCTL");
+pub fn sys_ioctl(fd: i32, op: usize, argp: UserPtr<c_void>) -> LinuxResult<isize> {
+    // The request code is 32 bits, whichever type the libc passes it as.
+    let op = op as u32 as usize;
+    if let Ok(dev) = BlockFile::from_fd(fd) {
+        return dev.ioctl(op, argp);
+    }
+    if let Some(tty) = tty_from_fd(fd) {
+        return tty.ioctl(op, argp);
+    }
+    if let Ok(socket) = Socket::from_fd(fd) {
+        return socket.ioctl(op, argp);
+    }
+    warn!("Unimplemented ioctl: fd {} op {:#x}", fd, op);
     Ok(0)
 }
 
 pub fn sys_chdir(path: UserConstPtr<c_char>) -> LinuxResult<isize> {
-    let path = path.get_as_str()?;
+    let path = path.get_as_path()?;
     debug!("sys_chdir <= {:?}", path);
 
+    let path = handle_file_path(AT_FDCWD, &path, PathAccess::READ)?;
+    change_dir(path.as_str())
+}
+
+pub fn sys_fchdir(fd: c_int) -> LinuxResult<isize> {
+    debug!("sys_fchdir <= {}", fd);
+
+    let dir = Directory::from_fd_or(fd, LinuxError::ENOTDIR)?;
+    if dir.is_removed() {
+        return Err(LinuxError::ENOENT);
+    }
+    change_dir(dir.path())
+}
+
+/// Enter the directory at the absolute, canonical `path`.
+///
+/// The working directory is only ever set to such a path, so what `getcwd`
+/// returns resolves back to the same directory when passed to `openat`.
+fn change_dir(path: &str) -> LinuxResult<isize> {
     axfs::api::set_current_dir(path)?;
+    enter_cwd()?;
+    *CWD_MOUNT.get().lock() = mount_ref(path);
     Ok(0)
 }
 
 pub fn sys_mkdirat(dirfd: i32, path: UserConstPtr<c_char>, mode: u32) -> LinuxResult<isize> {
-    let path = path.get_as_str()?;
+    let path = path.get_as_path()?;
     debug!(
         "sys_mkdirat <= dirfd: {}, path: {}, mode: {}",
         dirfd, path, mode
@@ -49,8 +95,13 @@ pub fn sys_mkdirat(dirfd: i32, path: UserConstPtr<c_char>, mode: u32) -> LinuxRe
         warn!("directory mode not supported.");
     }
 
-    let path = handle_file_path(dirfd, path)?;
+    let path = handle_file_path(dirfd, &path, PathAccess::CREATE)?;
+    check_writable(path.as_str())?;
+    check_parent_access(&path)?;
     axfs::api::create_dir(path.as_str())?;
+    invalidate_path_cache();
+    init_times(path.as_str());
+    notify(path.as_str(), IN_CREATE | IN_ISDIR);
 
     Ok(0)
 }
@@ -71,10 +122,15 @@ pub enum FileType {
 
 impl From<axfs::api::FileType> for FileType {
     fn from(ft: axfs::api::FileType) -> Self {
+        use axfs::api::FileType as Ty;
         match ft {
-            ft if ft.is_dir() => FileType::Dir,
-            ft if ft.is_file() => FileType::Reg,
-            _ => FileType::Unknown,
+            Ty::Fifo => FileType::Fifo,
+            Ty::CharDevice => FileType::Chr,
+            Ty::Dir => FileType::Dir,
+            Ty::BlockDevice => FileType::Blk,
+            Ty::File => FileType::Reg,
+            Ty::SymLink => FileType::Lnk,
+            Ty::Socket => FileType::Socket,
         }
     }
 }
@@ -90,38 +146,16 @@ impl<'a> DirBuffer<'a> {
         Self { buf, offset: 0 }
     }
 
-    fn remaining_space(&self) -> usize {
-        self.buf.len().saturating_sub(self.offset)
-    }
-
-    fn write_entry(&mut self, d_type: FileType, name: &[u8]) -> bool {
-        const NAME_OFFSET: usize = offset_of!(linux_dirent64, d_name);
-
-        let len = NAME_OFFSET + name.len() + 1;
-        // alignment
-        let len = len.next_multiple_of(align_of::<linux_dirent64>());
-        if self.remaining_space() < len {
-            return false;
-        }
-
-        unsafe {
-            let entry_ptr = self.buf.as_mut_ptr().add(self.offset);
-            entry_ptr.cast::<linux_dirent64>().write(linux_dirent64 {
-                // FIXME: real inode number
-                d_ino: 1,
-                d_off: 0,
-                d_reclen: len as _,
-                d_type: d_type as _,
-                d_name: Default::default(),
-            });
-
-            let name_ptr = entry_ptr.add(NAME_OFFSET);
-            name_ptr.copy_from_nonoverlapping(name.as_ptr(), name.len());
-            name_ptr.add(name.len()).write(0);
+    /// Write an entry, with `off` the offset of the next one.
+    fn write_entry(&mut self, ino: u64, off: usize, d_type: FileType, name: &[u8]) -> bool {
+        let buf = &mut self.buf[self.offset..];
+        match write_dirent64(buf, ino, off as _, d_type as _, name) {
+            Some(len) => {
+                self.offset += len;
+                true
+            }
+            None => false,
         }
-
-        self.offset += len;
-        true
     }
 }
 
@@ -136,33 +170,82 @@ pub fn sys_getdents64(fd: i32, buf: UserPtr<u8>, len: usize) -> LinuxResult<isiz
 
     let mut buffer = DirBuffer::new(buf);
 
-    let dir = Directory::from_fd(fd)?;
-
-    let mut last_dirent = dir.last_dirent();
-    if let Some(ent) = last_dirent.take()
-        && !buffer.write_entry(ent.entry_type().into(), ent.name_as_bytes())
-    {
-        *last_dirent = Some(ent);
-        return Err(LinuxError::EINVAL);
+    if let Ok(dir) = VirtualDirFile::from_fd(fd) {
+        return getdents_virtual(&dir, &mut buffer);
+    }
+    let dir = Directory::from_fd_or(fd, LinuxError::ENOTDIR)?;
+    // Like Linux, a removed directory has no entries left, even one saved.
+    if dir.is_removed() {
+        return Ok(0);
     }
 
+    // An entry read from the directory that did not fit in the buffer is
+    // saved, to be the first one of the next call. At the end, nothing is
+    // saved and 0 is returned.
+    let mut last_dirent = dir.last_dirent();
     let mut inner = dir.inner();
+    let mut pos = dir.pos().lock();
     loop {
-        let mut dirents = [DirEntry::default()];
-        let cnt = inner.read_dir(&mut dirents)?;
-        if cnt == 0 {
-            break;
+        let ent = match last_dirent.take() {
+            Some(ent) => ent,
+            // Nor once removed while reading it.
+            None => match dir.read_entry(&mut inner)? {
+                Some(ent) => ent,
+                None => break,
+            },
+        };
+        if is_tmpfile_entry(&dir, &ent) {
+            *pos += 1;
+            continue;
         }
-
-        let [ent] = dirents;
-        if !buffer.write_entry(ent.entry_type().into(), ent.name_as_bytes()) {
+        let ino = entry_ino(&dir, &ent);
+        if !buffer.write_entry(ino, *pos + 1, ent.entry_type().into(), &entry_name(&ent)) {
             *last_dirent = Some(ent);
+            // Not even one entry fits.
+            if buffer.offset == 0 {
+                return Err(LinuxError::EINVAL);
+            }
             break;
         }
+        *pos += 1;
     }
+    Ok(buffer.offset as _)
+}
 
-    if last_dirent.is_some() && buffer.offset == 0 {
-        return Err(LinuxError::EINVAL);
+/// The name of `ent`, as the bytes it was made with, see [`axfs::name`].
+fn entry_name(ent: &DirEntry) -> Cow<'_, [u8]> {
+    match core::str::from_utf8(ent.name_as_bytes()) {
+        Ok(name) => axfs::name::decode(name),
+        Err(_) => Cow::Borrowed(ent.name_as_bytes()),
+    }
+}
+
+/// Whether `ent` of `dir` is the hidden name of an unlinked `O_TMPFILE`
+/// file, which is not listed.
+fn is_tmpfile_entry(dir: &Directory, ent: &DirEntry) -> bool {
+    let Ok(name) = core::str::from_utf8(ent.name_as_bytes()) else {
+        return false;
+    };
+    is_unlinked_tmpfile(&format!("{}/{}", dir.path().trim_end_matches('/'), name))
+}
+
+/// Get the inode number of `ent` of `dir`, the one `stat` reports for it.
+fn entry_ino(dir: &Directory, ent: &DirEntry) -> u64 {
+    let name = String::from_utf8_lossy(ent.name_as_bytes());
+    FilePath::new(format!("{}/{}", dir.path().trim_end_matches('/'), name))
+        .map_or(1, |path| inode(path.as_str()))
+}
+
+fn getdents_virtual(dir: &VirtualDirFile, buffer: &mut DirBuffer) -> LinuxResult<isize> {
+    let mut pos = dir.pos().lock();
+    for (ino, ent) in dir.entries()?.into_iter().skip(*pos) {
+        if !buffer.write_entry(ino, *pos + 1, ent.ty.into(), ent.name.as_bytes()) {
+            if buffer.offset == 0 {
+                return Err(LinuxError::EINVAL);
+            }
+            break;
+        }
+        *pos += 1;
     }
     Ok(buffer.offset as _)
 }
@@ -179,23 +262,35 @@ pub fn sys_linkat(
     new_path: UserConstPtr<c_char>,
     flags: i32,
 ) -> LinuxResult<isize> {
-    let old_path = old_path.get_as_str()?;
-    let new_path = new_path.get_as_str()?;
+    let old_path = old_path.get_as_path()?;
+    let new_path = new_path.get_as_path()?;
     debug!(
         "sys_linkat <= old_dirfd: {}, old_path: {}, new_dirfd: {}, new_path: {}, flags: {}",
         old_dirfd, old_path, new_dirfd, new_path, flags
     );
 
-    if flags != 0 {
-        warn!("Unsupported flags: {flags}");
-    }
+    let flags = AtFlags::parse(flags as _, AtFlags::EMPTY_PATH | AtFlags::SYMLINK_FOLLOW)?;
 
-    // handle old path
-    let old_path = handle_file_path(old_dirfd, old_path)?;
-    // handle new path
-    let new_path = handle_file_path(new_dirfd, new_path)?;
+    let old = resolve_at(old_dirfd, Some(&old_path), flags, PathAccess::READ)?;
+    let new_path = handle_name_path(new_dirfd, &new_path, PathAccess::CREATE)?;
+
+    // Linking an `O_TMPFILE` file gives it its first name, which the file of
+    // a link has already.
+    if let AtTarget::Fd(f) = &old {
+        if let Some(file) = f.clone().into_any().downcast_ref::<File>() {
+            if file.is_unlinked() {
+                check_writable(new_path.as_str())?;
+                file.link_tmpfile(&HARDLINK_MANAGER.real_path(new_path.as_str()))?;
+                return Ok(0);
+            }
+        }
+    }
+    let old_path = old.path()?;
 
     HARDLINK_MANAGER.create_link(&new_path, &old_path)?;
+    // Both names see the times of the file, kept by its real path.
+    update_ctime(old_path.as_str());
+    update_parent_mtime(new_path.as_str());
 
     Ok(0)
 }
@@ -213,25 +308,39 @@ pub fn sys_link(
 /// flags: can be 0 or AT_REMOVEDIR
 /// return 0 when success, else return -1
 pub fn sys_unlinkat(dirfd: c_int, path: UserConstPtr<c_char>, flags: u32) -> LinuxResult<isize> {
-    let path = path.get_as_str()?;
+    let path = path.get_as_path()?;
     debug!(
         "sys_unlinkat <= dirfd: {}, path: {}, flags: {}",
         dirfd, path, flags
     );
 
-    let path = handle_file_path(dirfd, path)?;
+    let flags = AtFlags::parse(flags, AtFlags::REMOVEDIR)?;
+    let path = handle_name_path(dirfd, &path, PathAccess::WRITE)?;
+    check_writable(path.as_str())?;
+    check_parent_access(&path)?;
 
-    if flags == AT_REMOVEDIR {
-        axfs::api::remove_dir(path.as_str())?;
+    if flags.contains(AtFlags::REMOVEDIR) {
+        if path.is_root() || is_mount_point(&path) {
+            return Err(LinuxError::EBUSY);
+        }
+        // The filesystem takes a directory with only links in it for empty.
+        HARDLINK_MANAGER.without_links_under(path.as_str(), LinuxError::ENOTEMPTY, || {
+            Ok(axfs::api::remove_dir(path.as_str())?)
+        })?;
+        bump_dir_generation(path.as_str());
+        remove_times(path.as_str());
+        remove_inode(path.as_str());
+        remove_mode(path.as_str());
+        remove_xattrs(path.as_str());
+        notify(path.as_str(), IN_DELETE | IN_ISDIR);
     } else {
-        let metadata = axfs::api::metadata(path.as_str())?;
+        let metadata = axfs::api::metadata(&HARDLINK_MANAGER.real_path(path.as_str()))?;
         if metadata.is_dir() {
             return Err(LinuxError::EISDIR);
         } else {
             debug!("unlink file: {:?}", path);
-            HARDLINK_MANAGER
-                .remove_link(&path)
-                .ok_or(LinuxError::ENOENT)?;
+            HARDLINK_MANAGER.unlink(&path)?;
+            notify(path.as_str(), IN_DELETE);
         }
     }
     Ok(0)
@@ -241,20 +350,300 @@ pub fn sys_unlink(path: UserConstPtr<c_char>) -> LinuxResult<isize> {
     sys_unlinkat(AT_FDCWD, path, 0)
 }
 
+/// Move the name `old_path` to `new_path`, replacing what it names unless
+/// `flags` has `RENAME_NOREPLACE`, see
+/// [`HardlinkManager::rename`](crate::path::HardlinkManager::rename).
+///
+/// `RENAME_EXCHANGE` and `RENAME_WHITEOUT` are not supported.
+pub fn sys_renameat2(
+    old_dirfd: c_int,
+    old_path: UserConstPtr<c_char>,
+    new_dirfd: c_int,
+    new_path: UserConstPtr<c_char>,
+    flags: u32,
+) -> LinuxResult<isize> {
+    let old_path = old_path.get_as_path()?;
+    let new_path = new_path.get_as_path()?;
+    debug!(
+        "sys_renameat2 <= old_dirfd: {}, old_path: {}, new_dirfd: {}, new_path: {}, flags: {}",
+        old_dirfd, old_path, new_dirfd, new_path, flags
+    );
+
+    if flags & !RENAME_NOREPLACE != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let old = handle_name_path(old_dirfd, &old_path, PathAccess::WRITE)?;
+    let new = handle_name_path(new_dirfd, &new_path, PathAccess::CREATE)?;
+    for path in [&old, &new] {
+        check_writable(path.as_str())?;
+        check_parent_access(path)?;
+        if path.is_root() || is_mount_point(path) {
+            return Err(LinuxError::EBUSY);
+        }
+    }
+    HARDLINK_MANAGER.rename(&old, &new, flags & RENAME_NOREPLACE != 0)?;
+    Ok(0)
+}
+
+pub fn sys_renameat(
+    old_dirfd: c_int,
+    old_path: UserConstPtr<c_char>,
+    new_dirfd: c_int,
+    new_path: UserConstPtr<c_char>,
+) -> LinuxResult<isize> {
+    sys_renameat2(old_dirfd, old_path, new_dirfd, new_path, 0)
+}
+
+pub fn sys_rename(
+    old_path: UserConstPtr<c_char>,
+    new_path: UserConstPtr<c_char>,
+) -> LinuxResult<isize> {
+    sys_renameat2(AT_FDCWD, old_path, AT_FDCWD, new_path, 0)
+}
+
+/// Store the path of the current directory, with its NUL, in the `size`
+/// bytes at `buf`, which need not hold anything yet.
+///
+/// Returns the length of the path with the NUL, like the raw syscall of
+/// Linux, rather than `buf` as the libc function does.
 pub fn sys_getcwd(buf: UserPtr<u8>, size: usize) -> LinuxResult<isize> {
-    let buf = nullable!(buf.get_as_mut_slice(size))?;
+    if cwd_removed() {
+        return Err(LinuxError::ENOENT);
+    }
 
-    let Some(buf) = buf else {
-        return Ok(0);
+    // `axfs` keeps a trailing slash, which Linux does not report.
+    let cwd = axfs::api::current_dir()?;
+    let cwd = match cwd.trim_end_matches('/') {
+        "" => "/",
+        cwd => cwd,
     };
-
-    let cwd = CString::new(axfs::api::current_dir()?).map_err(|_| LinuxError::EINVAL)?;
+    let cwd = CString::new(axfs::name::decode(cwd)).map_err(|_| LinuxError::EINVAL)?;
     let cwd = cwd.as_bytes_with_nul();
+    if cwd.len() > size {
+        return Err(LinuxError::ERANGE);
+    }
 
-    if cwd.len() <= buf.len() {
-        buf[..cwd.len()].copy_from_slice(cwd);
-        Ok(buf.as_ptr() as _)
-    } else {
-        Err(LinuxError::ERANGE)
+    let buf = buf.get_as_mut_slice(size)?;
+    buf[..cwd.len()].copy_from_slice(cwd);
+    Ok(cwd.len() as _)
+}
+
+/// Read the target of the symbolic link at `path` into `buf`, without a
+/// terminating NUL.
+///
+/// Only the synthetic trees have links, so any other existing file fails
+/// with `EINVAL`.
+pub fn sys_readlinkat(
+    dirfd: c_int,
+    path: UserConstPtr<c_char>,
+    buf: UserPtr<u8>,
+    size: usize,
+) -> LinuxResult<isize> {
+    let path = path.get_as_path()?;
+    debug!(
+        "sys_readlinkat <= dirfd: {}, path: {}, size: {}",
+        dirfd, path, size
+    );
+
+    if size as isize <= 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let path = handle_file_path(dirfd, &path, PathAccess::READ)?;
+    let Some(target) = read_link_virtual(path.as_str()) else {
+        lstat_at_path(path.as_str())?;
+        return Err(LinuxError::EINVAL);
+    };
+    let target = target?;
+    let target = axfs::name::decode(&target);
+
+    let buf = buf.get_as_mut_slice(size)?;
+    let len = target.len().min(buf.len());
+    buf[..len].copy_from_slice(&target[..len]);
+    Ok(len as _)
+}
+
+pub fn sys_readlink(
+    path: UserConstPtr<c_char>,
+    buf: UserPtr<u8>,
+    size: usize,
+) -> LinuxResult<isize> {
+    sys_readlinkat(AT_FDCWD, path, buf, size)
+}
+
+/// Check the accessibility of the file at `path`.
+///
+/// `mode` is either `F_OK` or a mask of `R_OK`, `W_OK` and `X_OK`, checked
+/// with the real ids of the process unless `AT_EACCESS` is given, see
+/// [`check_access`]. `X_OK` always fails on a regular file without any
+/// execute bit.
+pub fn sys_faccessat(
+    dirfd: c_int,
+    path: UserConstPtr<c_char>,
+    mode: u32,
+    flags: u32,
+) -> LinuxResult<isize> {
+    let path = nullable!(path.get_as_path())?;
+    debug!(
+        "sys_faccessat <= dirfd: {}, path: {:?}, mode: {:#o}, flags: {}",
+        dirfd, path, mode, flags
+    );
+
+    let flags = AtFlags::parse(
+        flags,
+        AtFlags::EACCESS | AtFlags::SYMLINK_NOFOLLOW | AtFlags::EMPTY_PATH,
+    )?;
+    if mode & !(R_OK | W_OK | X_OK) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+
+    let target = resolve_at(dirfd, path.as_deref(), flags, PathAccess::READ)?;
+    let stat = target.stat_with(flags)?;
+    let st_mode = stat.mode();
+    if mode & X_OK != 0 && st_mode & S_IFMT != S_IFDIR && st_mode & 0o111 == 0 {
+        return Err(LinuxError::EACCES);
+    }
+    if mode != 0 {
+        let path = target.path().ok();
+        let path = path.as_ref().map_or("", |it| it.as_str());
+        check_access(path, &stat, mode, !flags.contains(AtFlags::EACCESS))?;
+    }
+    Ok(0)
+}
+
+pub fn sys_access(path: UserConstPtr<c_char>, mode: u32) -> LinuxResult<isize> {
+    sys_faccessat(AT_FDCWD, path, mode, 0)
+}
+
+/// Change the timestamps of the file at `path`.
+///
+/// A NULL `path` refers to `dirfd` itself, as `futimens` does. The times are
+/// rounded to what the filesystem can store, see [`set_times`].
+pub fn sys_utimensat(
+    dirfd: c_int,
+    path: UserConstPtr<c_char>,
+    times: UserConstPtr<timespec>,
+    flags: u32,
+) -> LinuxResult<isize> {
+    let path = nullable!(path.get_as_path())?;
+    debug!(
+        "sys_utimensat <= dirfd: {}, path: {:?}, flags: {}",
+        dirfd, path, flags
+    );
+
+    let mut flags = AtFlags::parse(flags, AtFlags::SYMLINK_NOFOLLOW | AtFlags::EMPTY_PATH)?;
+    if path.is_none() {
+        if dirfd == AT_FDCWD {
+            return Err(LinuxError::EFAULT);
+        }
+        flags |= AtFlags::EMPTY_PATH;
+    }
+
+    let now = wall_time();
+    let (atime, mtime) = match nullable!(times.get_as_slice(2))? {
+        Some(times) => (utime(&times[0], now)?, utime(&times[1], now)?),
+        None => (Some(now), Some(now)),
+    };
+
+    let target = resolve_at(dirfd, path.as_deref(), flags, PathAccess::WRITE)?;
+    target.stat()?;
+    // Files without a path, e.g. pipes, have no timestamps to change.
+    if let Ok(path) = target.path() {
+        check_writable(path.as_str())?;
+        // Through the open file itself, which would write its own times back
+        // over any set through another once closed.
+        let file = match &target {
+            AtTarget::Fd(file) => file.clone().into_any().downcast::<File>().ok(),
+            AtTarget::Path(_) => None,
+        };
+        let inner = file.as_ref().map(|file| file.inner());
+        set_times(path.as_str(), inner.as_deref(), atime, mtime);
+    }
+    Ok(0)
+}
+
+/// Convert a `timespec` of `utimensat` to the time to set, if any.
+fn utime(ts: &timespec, now: Duration) -> LinuxResult<Option<Duration>> {
+    match ts.tv_nsec {
+        nsec if nsec == UTIME_NOW as _ => Ok(Some(now)),
+        nsec if nsec == UTIME_OMIT as _ => Ok(None),
+        nsec if (0..1_000_000_000).contains(&nsec) && ts.tv_sec >= 0 => {
+            Ok(Some(Duration::new(ts.tv_sec as _, nsec as _)))
+        }
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Change the owner and group of the file at `path`.
+///
+/// Ownership is not stored by the underlying filesystems, so this only
+/// checks that the file exists.
+pub fn sys_fchownat(
+    dirfd: c_int,
+    path: UserConstPtr<c_char>,
+    owner: u32,
+    group: u32,
+    flags: u32,
+) -> LinuxResult<isize> {
+    let path = nullable!(path.get_as_path())?;
+    debug!(
+        "sys_fchownat <= dirfd: {}, path: {:?}, owner: {}, group: {}, flags: {}",
+        dirfd, path, owner, group, flags
+    );
+
+    let flags = AtFlags::parse(flags, AtFlags::SYMLINK_NOFOLLOW | AtFlags::EMPTY_PATH)?;
+    resolve_at(dirfd, path.as_deref(), flags, PathAccess::WRITE)?.stat()?;
+    warn!("file ownership not supported.");
+    Ok(0)
+}
+
+/// Change the permission bits of the file at `path` (`fchmodat2`).
+///
+/// The underlying filesystems do not store them, so they are kept aside,
+/// see [`set_mode`]. Every file is owned by the effective id of every
+/// process, so any process may change them.
+pub fn sys_fchmodat(
+    dirfd: c_int,
+    path: UserConstPtr<c_char>,
+    mode: u32,
+    flags: u32,
+) -> LinuxResult<isize> {
+    let path = nullable!(path.get_as_path())?;
+    debug!(
+        "sys_fchmodat <= dirfd: {}, path: {:?}, mode: {:#o}, flags: {}",
+        dirfd, path, mode, flags
+    );
+
+    let flags = AtFlags::parse(flags, AtFlags::SYMLINK_NOFOLLOW | AtFlags::EMPTY_PATH)?;
+    let target = resolve_at(dirfd, path.as_deref(), flags, PathAccess::WRITE)?;
+    change_mode(&target, flags, mode)
+}
+
+pub fn sys_fchmod(fd: c_int, mode: u32) -> LinuxResult<isize> {
+    debug!("sys_fchmod <= fd: {}, mode: {:#o}", fd, mode);
+    change_mode(&AtTarget::Fd(get_file_like(fd)?), AtFlags::empty(), mode)
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn sys_chmod(path: UserConstPtr<c_char>, mode: u32) -> LinuxResult<isize> {
+    sys_fchmodat(AT_FDCWD, path, mode, 0)
+}
+
+/// Set the permission bits of `target` to those of `mode`.
+fn change_mode(target: &AtTarget, flags: AtFlags, mode: u32) -> LinuxResult<isize> {
+    let stat = target.stat_with(flags)?;
+    if stat.mode() & S_IFMT == S_IFLNK {
+        // Like Linux, a link itself has no mode to change.
+        return Err(LinuxError::EOPNOTSUPP);
     }
+    // Files without a path, e.g. pipes, have no permission bits to keep.
+    let Ok(path) = target.path() else {
+        return Ok(0);
+    };
+    if stat_virtual(path.as_str(), true).is_some() {
+        return Err(LinuxError::EPERM);
+    }
+    check_writable(path.as_str())?;
+    set_mode(path.as_str(), mode);
+    update_ctime(path.as_str());
+    Ok(0)
 }
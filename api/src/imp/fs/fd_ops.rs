@@ -3,53 +3,35 @@ use core::{
     panic,
 };
 
-use alloc::string::ToString;
+use alloc::{string::ToString, sync::Arc};
 use axerrno::{AxError, LinuxError, LinuxResult};
-use axfs::fops::OpenOptions;
+use axio::SeekFrom;
+use axprocess::Pid;
 use linux_raw_sys::general::{
-    __kernel_mode_t, AT_FDCWD, F_DUPFD, F_DUPFD_CLOEXEC, F_SETFL, O_APPEND, O_CREAT, O_DIRECTORY,
-    O_NONBLOCK, O_PATH, O_RDONLY, O_TRUNC, O_WRONLY,
+    __kernel_mode_t, AT_FDCWD, F_DUPFD, F_DUPFD_CLOEXEC, F_GETFD, F_GETFL, F_GETLK, F_GETOWN,
+    F_OFD_GETLK, F_OFD_SETLK, F_OFD_SETLKW, F_RDLCK, F_SETFD, F_SETFL, F_SETLK, F_SETLKW, F_SETOWN,
+    F_UNLCK, F_WRLCK, FASYNC, FD_CLOEXEC, IN_CREATE, LOCK_EX, LOCK_NB, LOCK_SH, LOCK_UN, O_ACCMODE,
+    O_CLOEXEC, O_NONBLOCK, O_RDONLY, O_WRONLY, SEEK_CUR, SEEK_END, SEEK_SET, W_OK, X_OK, flock,
+};
+use starry_core::{
+    sandbox::PathAccess,
+    task::{get_process, get_process_group},
 };
 
+use super::check_writable;
 use crate::{
-    file::{Directory, FD_TABLE, File, FileLike, add_file_like, close_file_like, get_file_like},
-    path::handle_file_path,
-    ptr::UserConstPtr,
+    check_parent_access, check_path_access,
+    file::{
+        Directory, FD_TABLE, FdFlags, File, FileLike, LockOwner, OpenFlags, RecordLock,
+        VirtualDirFile, close_file_like, conflicting_lock, fd_index, file_closed, get_file_like,
+        init_times, install_fd, nofile_limit, notify, open_virtual, resolve_virtual_link,
+        set_flock, set_lock, update_mtime,
+    },
+    path::{FilePath, handle_file_path},
+    ptr::{UserConstPtr, UserPtr},
+    sandbox::check_path,
 };
 
-const O_EXEC: u32 = O_PATH;
-
-/// Convert open flags to [`OpenOptions`].
-fn flags_to_options(flags: c_int, _mode: __kernel_mode_t) -> OpenOptions {
-    let flags = flags as u32;
-    let mut options = OpenOptions::new();
-    match flags & 0b11 {
-        O_RDONLY => options.read(true),
-        O_WRONLY => options.write(true),
-        _ => {
-            options.read(true);
-            options.write(true);
-        }
-    };
-    if flags & O_APPEND != 0 {
-        options.append(true);
-    }
-    if flags & O_TRUNC != 0 {
-        options.truncate(true);
-    }
-    if flags & O_CREAT != 0 {
-        options.create(true);
-    }
-    if flags & O_EXEC != 0 {
-        //options.create_new(true);
-        options.execute(true);
-    }
-    if flags & O_DIRECTORY != 0 {
-        options.directory(true);
-    }
-    options
-}
-
 /// Open or create a file.
 /// fd: file descriptor
 /// filename: file path to be opened or created
@@ -62,16 +44,59 @@ pub fn sys_openat(
     flags: i32,
     mode: __kernel_mode_t,
 ) -> LinuxResult<isize> {
-    let path = path.get_as_str()?;
-    let opts = flags_to_options(flags, mode);
-    debug!("sys_openat <= {} {} {:?}", dirfd, path, opts);
+    let path = path.get_as_path()?;
+    let flags = OpenFlags::from_raw(flags);
+    let opts = flags.options();
+    debug!(
+        "sys_openat <= {} {} {:?} mode: {:#o}",
+        dirfd, path, opts, mode
+    );
+
+    let mut real_path = handle_file_path(dirfd, &path, flags.path_access())?;
+    // Follow synthetic links to a path, e.g. `/proc/self/exe`.
+    let link_target = resolve_virtual_link(real_path.as_str());
+    let path = match &link_target {
+        Some(target) => {
+            real_path = FilePath::new(target)?;
+            check_path(&real_path, flags.path_access())?;
+            target.as_str()
+        }
+        None => &*path,
+    };
+    if let Some(f) = open_virtual(real_path.as_str()) {
+        let f = f?;
+        let is_dir = f.clone().into_any().is::<VirtualDirFile>();
+        if opts.has_directory() && !is_dir {
+            return Err(LinuxError::ENOTDIR);
+        }
+        if is_dir && flags.writes() {
+            return Err(LinuxError::EISDIR);
+        }
+        return Ok(install_fd(f, flags.fd_flags())? as _);
+    }
+
+    if flags.contains(OpenFlags::TMPFILE) {
+        return open_tmpfile(real_path.as_str(), flags);
+    }
+
+    // Let a directory fail with `EISDIR` below instead.
+    if flags.writes() && !axfs::api::metadata(real_path.as_str()).is_ok_and(|it| it.is_dir()) {
+        check_writable(real_path.as_str())?;
+    }
+    let exists = axfs::api::metadata(real_path.as_str()).is_ok();
+    let creates = flags.contains(OpenFlags::CREAT) && !exists;
+    if creates {
+        check_path(&real_path, PathAccess::CREATE)?;
+        check_parent_access(&real_path)?;
+    } else if exists {
+        check_path_access(real_path.as_str(), flags.access())?;
+    }
 
     let dir = if path.starts_with('/') || dirfd == AT_FDCWD {
         None
     } else {
         Some(Directory::from_fd(dirfd)?)
     };
-    let real_path = handle_file_path(dirfd, path)?;
 
     if !opts.has_directory() {
         match dir.as_ref().map_or_else(
@@ -80,21 +105,47 @@ pub fn sys_openat(
         ) {
             Err(AxError::IsADirectory) => {}
             r => {
-                let fd = File::new(r?, real_path.to_string()).add_to_fd_table()?;
-                return Ok(fd as _);
+                let file = r?;
+                if creates {
+                    init_times(real_path.as_str());
+                    notify(real_path.as_str(), IN_CREATE);
+                } else if flags.contains(OpenFlags::TRUNC) {
+                    update_mtime(real_path.as_str());
+                }
+                let file = File::new(file, real_path.to_string(), flags.bits());
+                return Ok(install_fd(Arc::new(file), flags.fd_flags())? as _);
             }
         }
     }
 
-    let fd = Directory::new(
-        dir.map_or_else(
-            || axfs::fops::Directory::open_dir(path, &opts),
-            |dir| dir.inner().open_dir_at(path, &opts),
-        )?,
-        real_path.to_string(),
-    )
-    .add_to_fd_table()?;
-    Ok(fd as _)
+    let dir = dir.map_or_else(
+        || axfs::fops::Directory::open_dir(path, &opts),
+        |dir| dir.inner().open_dir_at(path, &opts),
+    )?;
+    if flags.writes() {
+        return Err(LinuxError::EISDIR);
+    }
+    let dir = Directory::new(dir, real_path.to_string());
+    Ok(install_fd(Arc::new(dir), flags.fd_flags())? as _)
+}
+
+/// Create an unnamed file in the directory `dir`, as `O_TMPFILE` does.
+///
+/// The file can later be linked with `linkat` and `AT_EMPTY_PATH`, unless
+/// `O_EXCL` is given.
+fn open_tmpfile(dir: &str, flags: OpenFlags) -> LinuxResult<isize> {
+    if flags.access_mode() == O_RDONLY {
+        return Err(LinuxError::EINVAL);
+    }
+    if !axfs::api::metadata(dir)?.is_dir() {
+        return Err(LinuxError::ENOTDIR);
+    }
+    check_writable(dir)?;
+    check_path_access(dir, W_OK | X_OK)?;
+    let opts = (flags - OpenFlags::TMPFILE | OpenFlags::CREAT | OpenFlags::EXCL).options();
+    let linkable = !flags.contains(OpenFlags::EXCL);
+    let file = File::new_tmpfile(dir, &opts, flags.bits(), linkable)?;
+    Ok(install_fd(Arc::new(file), flags.fd_flags())? as _)
 }
 
 /// Open a file by `filename` and insert it into the file descriptor table.
@@ -115,54 +166,231 @@ pub fn sys_close(fd: c_int) -> LinuxResult<isize> {
     Ok(0)
 }
 
-fn dup_fd(old_fd: c_int) -> LinuxResult<isize> {
+/// Duplicate `old_fd` to the lowest free descriptor, which is closed on
+/// `execve` if `cloexec` is set.
+fn dup_fd(old_fd: c_int, cloexec: bool) -> LinuxResult<isize> {
     let f = get_file_like(old_fd)?;
-    let new_fd = add_file_like(f)?;
-    Ok(new_fd as _)
+    let flags = if cloexec {
+        FdFlags::CLOEXEC
+    } else {
+        FdFlags::empty()
+    };
+    Ok(install_fd(f, flags)? as _)
 }
 
 pub fn sys_dup(old_fd: c_int) -> LinuxResult<isize> {
     debug!("sys_dup <= {}", old_fd);
-    dup_fd(old_fd)
+    dup_fd(old_fd, false)
 }
 
 pub fn sys_dup2(old_fd: c_int, new_fd: c_int) -> LinuxResult<isize> {
     debug!("sys_dup2 <= old_fd: {}, new_fd: {}", old_fd, new_fd);
-    let mut fd_table = FD_TABLE.write();
-    let f = fd_table
-        .get(old_fd as _)
-        .cloned()
-        .ok_or(LinuxError::EBADF)?;
-
-    if old_fd != new_fd {
-        fd_table.remove(new_fd as _);
-        fd_table
-            .add_at(new_fd as _, f)
-            .unwrap_or_else(|_| panic!("new_fd should be valid"));
+    dup_to(old_fd, new_fd, false)
+}
+
+/// Duplicate `old_fd` to `new_fd`, closing what `new_fd` referred to, and
+/// set the close-on-exec flag of `new_fd` to `cloexec`.
+fn dup_to(old_fd: c_int, new_fd: c_int, cloexec: bool) -> LinuxResult<isize> {
+    let (old, new) = (fd_index(old_fd)?, fd_index(new_fd)?);
+    let limit = nofile_limit();
+    FD_TABLE.with_mut(|fd_table| {
+        let f = fd_table.get(old).cloned().ok_or(LinuxError::EBADF)?;
+
+        if old != new {
+            if new >= limit {
+                return Err(LinuxError::EBADF);
+            }
+            if let Some(closed) = fd_table.remove(new) {
+                file_closed(&closed);
+            }
+            fd_table
+                .add_at(new, f, usize::MAX)
+                .unwrap_or_else(|_| panic!("new_fd should be valid"));
+            fd_table.set_cloexec(new, cloexec);
+        }
+
+        Ok(new as _)
+    })
+}
+
+/// Like [`sys_dup2`], but `old_fd == new_fd` is an error and `flags` may
+/// only contain `O_CLOEXEC`.
+pub fn sys_dup3(old_fd: c_int, new_fd: c_int, flags: c_int) -> LinuxResult<isize> {
+    debug!(
+        "sys_dup3 <= old_fd: {}, new_fd: {}, flags: {:#x}",
+        old_fd, new_fd, flags
+    );
+    if old_fd == new_fd || flags as u32 & !O_CLOEXEC != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    dup_to(old_fd, new_fd, flags as u32 & O_CLOEXEC != 0)
+}
+
+/// Get, take or release a record lock on the file `fd` refers to, as
+/// described by the `flock` at `arg`, see [`crate::file::RecordLock`].
+fn record_lock(fd: c_int, cmd: u32, arg: usize) -> LinuxResult<isize> {
+    let file = File::from_fd(fd)?;
+    let flock = UserPtr::<flock>::from(arg).get_as_mut()?;
+    let ofd = matches!(cmd, F_OFD_GETLK | F_OFD_SETLK | F_OFD_SETLKW);
+    if ofd && flock.l_pid != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let kind = flock.l_type as u32;
+    if !matches!(kind, F_RDLCK | F_WRLCK | F_UNLCK) {
+        return Err(LinuxError::EINVAL);
     }
 
-    Ok(new_fd as _)
+    let base = match flock.l_whence as u32 {
+        SEEK_SET => 0,
+        SEEK_CUR => file.inner().seek(SeekFrom::Current(0))? as i64,
+        SEEK_END => file.inner().get_attr()?.size() as i64,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let start = base
+        .checked_add(flock.l_start)
+        .ok_or(LinuxError::EOVERFLOW)?;
+    // A negative length locks the bytes before the start, and no length
+    // those up to the end of the file, however far it grows.
+    let (start, end) = match flock.l_len {
+        0 => (start, None),
+        len if len < 0 => (
+            start.checked_add(len).ok_or(LinuxError::EINVAL)?,
+            Some(start),
+        ),
+        len => (
+            start,
+            Some(start.checked_add(len).ok_or(LinuxError::EOVERFLOW)?),
+        ),
+    };
+    if start < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let lock = RecordLock {
+        owner: if ofd {
+            LockOwner::file(&file)
+        } else {
+            LockOwner::current_process()
+        },
+        kind,
+        start: start as u64,
+        end: end.map_or(u64::MAX, |end| end as u64),
+    };
+
+    if matches!(cmd, F_GETLK | F_OFD_GETLK) {
+        if kind == F_UNLCK {
+            return Err(LinuxError::EINVAL);
+        }
+        match conflicting_lock(&file, &lock) {
+            Some(held) => {
+                flock.l_type = held.kind as _;
+                flock.l_whence = SEEK_SET as _;
+                flock.l_start = held.start as _;
+                flock.l_len = if held.end == u64::MAX {
+                    0
+                } else {
+                    (held.end - held.start) as _
+                };
+                flock.l_pid = held.owner.pid();
+            }
+            None => flock.l_type = F_UNLCK as _,
+        }
+        return Ok(0);
+    }
+
+    // A lock needs the file open for the access it keeps others from.
+    let mode = file.status_flags() & O_ACCMODE;
+    if (kind == F_RDLCK && mode == O_WRONLY) || (kind == F_WRLCK && mode == O_RDONLY) {
+        return Err(LinuxError::EBADF);
+    }
+    set_lock(&file, lock, matches!(cmd, F_SETLKW | F_OFD_SETLKW))?;
+    Ok(0)
 }
 
 pub fn sys_fcntl(fd: c_int, cmd: c_int, arg: usize) -> LinuxResult<isize> {
     debug!("sys_fcntl <= fd: {} cmd: {} arg: {}", fd, cmd, arg);
 
     match cmd as u32 {
-        F_DUPFD => dup_fd(fd),
-        F_DUPFD_CLOEXEC => {
-            warn!("sys_fcntl: treat F_DUPFD_CLOEXEC as F_DUPFD");
-            dup_fd(fd)
+        F_DUPFD => dup_fd(fd, false),
+        F_DUPFD_CLOEXEC => dup_fd(fd, true),
+        F_GETFD => {
+            let fd = fd_index(fd)?;
+            let cloexec = FD_TABLE
+                .with(|table| table.cloexec(fd))
+                .ok_or(LinuxError::EBADF)?;
+            Ok(if cloexec { FD_CLOEXEC as _ } else { 0 })
+        }
+        F_SETFD => {
+            let fd = fd_index(fd)?;
+            FD_TABLE
+                .with_mut(|table| table.set_cloexec(fd, arg & FD_CLOEXEC as usize != 0))
+                .ok_or(LinuxError::EBADF)?;
+            Ok(0)
+        }
+        F_GETFL => {
+            let file = get_file_like(fd)?;
+            let flags = match file.owner() {
+                Some(owner) if owner.is_async() => file.status_flags() | FASYNC,
+                _ => file.status_flags(),
+            };
+            Ok(flags as _)
         }
         F_SETFL => {
+            let file = get_file_like(fd)?;
+            if let Some(owner) = file.owner() {
+                owner.set_async(fd, arg & (FASYNC as usize) != 0);
+            }
             if fd == 0 || fd == 1 || fd == 2 {
                 return Ok(0);
             }
-            get_file_like(fd)?.set_nonblocking(arg & (O_NONBLOCK as usize) > 0)?;
+            file.set_nonblocking(arg & (O_NONBLOCK as usize) > 0)?;
             Ok(0)
         }
+        F_GETOWN => {
+            let file = get_file_like(fd)?;
+            Ok(file.owner().map_or(0, |owner| owner.owner()) as _)
+        }
+        F_SETOWN => {
+            let file = get_file_like(fd)?;
+            let owner = file.owner().ok_or(LinuxError::EINVAL)?;
+            // A positive owner is a process, and a negative one a process
+            // group.
+            let who = arg as c_int;
+            if who > 0 {
+                get_process(who as Pid)?;
+            } else if who < 0 {
+                get_process_group(who.unsigned_abs() as Pid)?;
+            }
+            owner.set_owner(who);
+            Ok(0)
+        }
+        F_GETLK | F_SETLK | F_SETLKW | F_OFD_GETLK | F_OFD_SETLK | F_OFD_SETLKW => {
+            record_lock(fd, cmd as u32, arg)
+        }
         _ => {
+            // The fd is checked first, whatever the command.
+            get_file_like(fd)?;
             warn!("unsupported fcntl parameters: cmd: {}", cmd);
             Ok(0)
         }
     }
 }
+
+/// Take or release a lock on the whole of the file `fd` refers to, shared
+/// or exclusive as `operation` tells, see [`set_flock`]. Only regular files
+/// can be locked.
+///
+/// The lock belongs to the open file, and is released once the last of its
+/// descriptors is closed. With `LOCK_NB`, a conflicting lock fails with
+/// `EWOULDBLOCK` rather than waiting.
+pub fn sys_flock(fd: c_int, operation: u32) -> LinuxResult<isize> {
+    debug!("sys_flock <= fd: {}, operation: {:#x}", fd, operation);
+    let file = File::from_fd(fd)?;
+    let kind = match operation & !LOCK_NB {
+        LOCK_SH => F_RDLCK,
+        LOCK_EX => F_WRLCK,
+        LOCK_UN => F_UNLCK,
+        _ => return Err(LinuxError::EINVAL),
+    };
+    set_flock(&file, kind, operation & LOCK_NB == 0)?;
+    Ok(0)
+}
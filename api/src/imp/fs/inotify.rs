@@ -0,0 +1,54 @@
+use core::ffi::{c_char, c_int};
+
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use linux_raw_sys::general::{AT_FDCWD, IN_ALL_EVENTS, IN_MASK_ADD, IN_MASK_CREATE, IN_ONLYDIR};
+use starry_core::sandbox::PathAccess;
+
+use crate::{
+    file::{FdFlags, FileLike, Inotify, install_fd},
+    path::handle_file_path,
+    ptr::UserConstPtr,
+};
+
+/// Create an `inotify` instance.
+pub fn sys_inotify_init1(flags: u32) -> LinuxResult<isize> {
+    debug!("sys_inotify_init1 <= flags: {:#x}", flags);
+    let (flags, _) = FdFlags::parse(flags, 0)?;
+    let inotify = Inotify::new(flags.contains(FdFlags::NONBLOCK));
+    Ok(install_fd(Arc::new(inotify), flags)? as _)
+}
+
+/// Watch the file or directory at `path` for the events in `mask`.
+///
+/// Only `IN_CREATE`, `IN_DELETE` and `IN_MODIFY` are ever reported.
+pub fn sys_inotify_add_watch(
+    fd: c_int,
+    path: UserConstPtr<c_char>,
+    mask: u32,
+) -> LinuxResult<isize> {
+    let path = path.get_as_path()?;
+    debug!(
+        "sys_inotify_add_watch <= fd: {}, path: {}, mask: {:#x}",
+        fd, path, mask
+    );
+
+    let inotify = Inotify::from_fd(fd)?;
+    if mask & IN_ALL_EVENTS == 0 || (mask & IN_MASK_ADD != 0 && mask & IN_MASK_CREATE != 0) {
+        return Err(LinuxError::EINVAL);
+    }
+    let path = handle_file_path(AT_FDCWD, &path, PathAccess::READ)?;
+    let metadata = axfs::api::metadata(path.as_str())?;
+    if mask & IN_ONLYDIR != 0 && !metadata.is_dir() {
+        return Err(LinuxError::ENOTDIR);
+    }
+
+    Ok(inotify.add_watch(path.as_str(), mask)? as _)
+}
+
+/// Remove the watch `wd` from the `inotify` instance at `fd`.
+pub fn sys_inotify_rm_watch(fd: c_int, wd: c_int) -> LinuxResult<isize> {
+    debug!("sys_inotify_rm_watch <= fd: {}, wd: {}", fd, wd);
+    Inotify::from_fd(fd)?.rm_watch(wd)?;
+    Ok(0)
+}
@@ -1,12 +1,18 @@
 use core::ffi::c_int;
 
+use alloc::sync::Arc;
 use axerrno::{LinuxError, LinuxResult};
 use axio::SeekFrom;
 use linux_raw_sys::general::{__kernel_off_t, iovec};
+use starry_core::workqueue::run_work;
 
 use crate::{
-    file::{File, FileLike, get_file_like},
+    file::{
+        BlockFile, Directory, File, FileLike, Stdout, VirtualDirFile, count_read, count_write,
+        flush_output, get_file_like,
+    },
     ptr::{UserConstPtr, UserPtr},
+    signal::has_pending_signal,
 };
 
 /// Read data from the file indicated by `fd`.
@@ -20,7 +26,22 @@ pub fn sys_read(fd: i32, buf: UserPtr<u8>, len: usize) -> LinuxResult<isize> {
         buf.as_ptr(),
         buf.len()
     );
-    Ok(get_file_like(fd)?.read(buf)? as _)
+    let f = get_file_like(fd)?;
+    let read = read_chunked(&f, buf)?;
+    count_read(&*f, read);
+    Ok(read as _)
+}
+
+/// Check that the total length of `iovs` fits in the returned `ssize_t`.
+fn check_iov_total(iovs: &[iovec]) -> LinuxResult<()> {
+    iovs.iter()
+        .try_fold(0usize, |total, iov| {
+            total
+                .checked_add(iov.iov_len as usize)
+                .filter(|&it| it <= isize::MAX as usize)
+        })
+        .map(drop)
+        .ok_or(LinuxError::EINVAL)
 }
 
 pub fn sys_readv(fd: i32, iov: UserPtr<iovec>, iocnt: usize) -> LinuxResult<isize> {
@@ -29,6 +50,8 @@ pub fn sys_readv(fd: i32, iov: UserPtr<iovec>, iocnt: usize) -> LinuxResult<isiz
     }
 
     let iovs = iov.get_as_mut_slice(iocnt)?;
+    check_iov_total(iovs)?;
+    let f = get_file_like(fd)?;
     let mut ret = 0;
     for iov in iovs {
         if iov.iov_len == 0 {
@@ -43,7 +66,7 @@ pub fn sys_readv(fd: i32, iov: UserPtr<iovec>, iocnt: usize) -> LinuxResult<isiz
             buf.len()
         );
 
-        let read = get_file_like(fd)?.read(buf)?;
+        let read = f.read(buf)?;
         ret += read as isize;
 
         if read < buf.len() {
@@ -51,6 +74,7 @@ pub fn sys_readv(fd: i32, iov: UserPtr<iovec>, iocnt: usize) -> LinuxResult<isiz
         }
     }
 
+    count_read(&*f, ret as _);
     Ok(ret)
 }
 
@@ -65,7 +89,73 @@ pub fn sys_write(fd: i32, buf: UserConstPtr<u8>, len: usize) -> LinuxResult<isiz
         buf.as_ptr(),
         buf.len()
     );
-    Ok(get_file_like(fd)?.write(buf)? as _)
+    let f = get_file_like(fd)?;
+    let written = write_chunked(&f, buf)?;
+    count_write(&*f, written);
+    Ok(written as _)
+}
+
+/// The most bytes a regular file or the console transfers at once.
+///
+/// Their reads and writes never wait, but a large one still takes a while,
+/// so it is split into chunks and stops early if a signal becomes pending.
+/// The other files wait for signals themselves, and some of them, like
+/// datagram sockets, must get the whole buffer at once.
+const IO_CHUNK_SIZE: usize = 1 << 20;
+
+fn is_chunked(f: &Arc<dyn FileLike>) -> bool {
+    let any = f.clone().into_any();
+    any.is::<File>() || any.is::<Stdout>()
+}
+
+/// Read into `buf` from `f`, returning what was read so far once a signal is
+/// pending or an error occurs after some bytes.
+fn read_chunked(f: &Arc<dyn FileLike>, buf: &mut [u8]) -> LinuxResult<usize> {
+    if !is_chunked(f) {
+        return f.read(buf);
+    }
+    let mut done = 0;
+    for chunk in buf.chunks_mut(IO_CHUNK_SIZE) {
+        if done > 0 && has_pending_signal() {
+            break;
+        }
+        match f.read(chunk) {
+            Ok(n) => {
+                done += n;
+                if n < chunk.len() {
+                    break;
+                }
+            }
+            Err(e) if done == 0 => return Err(e),
+            Err(_) => break,
+        }
+    }
+    Ok(done)
+}
+
+/// Write `buf` to `f`, returning what was written so far once a signal is
+/// pending or an error occurs after some bytes.
+fn write_chunked(f: &Arc<dyn FileLike>, buf: &[u8]) -> LinuxResult<usize> {
+    if !is_chunked(f) {
+        return f.write(buf);
+    }
+    let mut done = 0;
+    for chunk in buf.chunks(IO_CHUNK_SIZE) {
+        if done > 0 && has_pending_signal() {
+            break;
+        }
+        match f.write(chunk) {
+            Ok(n) => {
+                done += n;
+                if n < chunk.len() {
+                    break;
+                }
+            }
+            Err(e) if done == 0 => return Err(e),
+            Err(_) => break,
+        }
+    }
+    Ok(done)
 }
 
 pub fn sys_writev(fd: i32, iov: UserConstPtr<iovec>, iocnt: usize) -> LinuxResult<isize> {
@@ -74,6 +164,8 @@ pub fn sys_writev(fd: i32, iov: UserConstPtr<iovec>, iocnt: usize) -> LinuxResul
     }
 
     let iovs = iov.get_as_slice(iocnt)?;
+    check_iov_total(iovs)?;
+    let f = get_file_like(fd)?;
     let mut ret = 0;
     for iov in iovs {
         if iov.iov_len == 0 {
@@ -88,7 +180,7 @@ pub fn sys_writev(fd: i32, iov: UserConstPtr<iovec>, iocnt: usize) -> LinuxResul
             buf.len()
         );
 
-        let written = get_file_like(fd)?.write(buf)?;
+        let written = f.write(buf)?;
         ret += written as isize;
 
         if written < buf.len() {
@@ -96,6 +188,7 @@ pub fn sys_writev(fd: i32, iov: UserConstPtr<iovec>, iocnt: usize) -> LinuxResul
         }
     }
 
+    count_write(&*f, ret as _);
     Ok(ret)
 }
 
@@ -107,6 +200,118 @@ pub fn sys_lseek(fd: c_int, offset: __kernel_off_t, whence: c_int) -> LinuxResul
         2 => SeekFrom::End(offset as _),
         _ => return Err(LinuxError::EINVAL),
     };
-    let off = File::from_fd(fd)?.inner().seek(pos)?;
+    // The offset of a directory is the number of entries read, as the
+    // `d_off` of the entries says.
+    if let Ok(dir) = Directory::from_fd(fd) {
+        let off = dir_offset(pos, *dir.pos().lock())?;
+        dir.seek(off)?;
+        return Ok(off as _);
+    }
+    if let Ok(dir) = VirtualDirFile::from_fd(fd) {
+        let mut cur = dir.pos().lock();
+        *cur = dir_offset(pos, *cur)?;
+        return Ok(*cur as _);
+    }
+    if let Ok(dev) = BlockFile::from_fd(fd) {
+        return Ok(dev.seek(pos)? as _);
+    }
+    // Pipes, sockets and the like cannot seek.
+    let off = File::from_fd_or(fd, LinuxError::ESPIPE)?
+        .inner()
+        .seek(pos)?;
     Ok(off as _)
 }
+
+/// Get the directory offset `pos` seeks to from `cur`.
+fn dir_offset(pos: SeekFrom, cur: usize) -> LinuxResult<usize> {
+    let off = match pos {
+        SeekFrom::Start(off) => off as i64,
+        SeekFrom::Current(off) => cur as i64 + off,
+        SeekFrom::End(_) => return Err(LinuxError::EINVAL),
+    };
+    usize::try_from(off).map_err(|_| LinuxError::EINVAL)
+}
+
+/// Write the data and metadata of the file `fd` refers to on its device, as
+/// `fsync` does.
+///
+/// Directories and block devices are synced as a whole; pipes, sockets and
+/// the like fail with `EINVAL`.
+pub fn sys_fsync(fd: c_int) -> LinuxResult<isize> {
+    debug!("sys_fsync <= {}", fd);
+    if let Ok(file) = File::from_fd(fd) {
+        file.inner().flush()?;
+    } else if let Ok(dev) = BlockFile::from_fd(fd) {
+        dev.sync()?;
+    } else if Stdout::from_fd(fd).is_ok() {
+        flush_output();
+    } else {
+        // The entries of a directory are written through the cache of the
+        // device, so it is synced as a whole.
+        Directory::from_fd(fd)?;
+        sync_block_devices()?;
+    }
+    Ok(0)
+}
+
+/// Like `fsync`, but only the metadata needed to read the data back has to
+/// be written.
+///
+/// On FAT, that is the size in the directory entry and the clusters in the
+/// FAT, which leaves nothing out but the times, so it syncs as much.
+pub fn sys_fdatasync(fd: c_int) -> LinuxResult<isize> {
+    debug!("sys_fdatasync <= {}", fd);
+    sys_fsync(fd)
+}
+
+/// Write back what is cached for every block device, as `sync` does.
+///
+/// Like on Linux, it never fails, and errors are only logged.
+pub fn sys_sync() -> LinuxResult<isize> {
+    debug!("sys_sync");
+    if let Err(err) = sync_block_devices() {
+        warn!("sys_sync: {:?}", err);
+    }
+    Ok(0)
+}
+
+/// Write back what is cached for every block device, on a worker, since
+/// it goes deep into the filesystems.
+fn sync_block_devices() -> LinuxResult {
+    run_work(axfs::sync_block_devices).map_err(|_| LinuxError::EIO)
+}
+
+/// Sync the filesystem `fd` is on, as `syncfs` does, which is done by
+/// syncing every block device.
+pub fn sys_syncfs(fd: c_int) -> LinuxResult<isize> {
+    debug!("sys_syncfs <= {}", fd);
+    get_file_like(fd)?;
+    sync_block_devices()?;
+    Ok(0)
+}
+
+/// Allocate the bytes in `[offset, offset + len)` of the file `fd`, as
+/// `fallocate` with a `mode` of 0 does, failing with `ENOSPC` if they do not
+/// fit in the filesystem.
+///
+/// Other modes, which keep the size or punch holes, fail with `EOPNOTSUPP`.
+pub fn sys_fallocate(
+    fd: c_int,
+    mode: u32,
+    offset: __kernel_off_t,
+    len: __kernel_off_t,
+) -> LinuxResult<isize> {
+    debug!(
+        "sys_fallocate <= fd: {}, mode: {:#x}, offset: {}, len: {}",
+        fd, mode, offset, len
+    );
+    if offset < 0 || len <= 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let file = File::from_fd_or(fd, LinuxError::ENODEV)?;
+    if mode != 0 {
+        return Err(LinuxError::EOPNOTSUPP);
+    }
+    file.allocate(offset as u64, len as u64)?;
+    Ok(0)
+}
@@ -0,0 +1,71 @@
+use core::ffi::c_int;
+
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use linux_raw_sys::io_uring::{IORING_ENTER_GETEVENTS, IORING_SETUP_CQSIZE, io_uring_params};
+
+use crate::{
+    file::{FdFlags, FileLike, IoUring, install_fd},
+    ptr::UserPtr,
+};
+
+/// Create an `io_uring` with at least `entries` submission queue entries.
+///
+/// Only `IORING_SETUP_CQSIZE` is supported in `params.flags`.
+pub fn sys_io_uring_setup(entries: u32, params: UserPtr<io_uring_params>) -> LinuxResult<isize> {
+    let params = params.get_as_mut()?;
+    debug!(
+        "sys_io_uring_setup <= entries: {}, flags: {:#x}",
+        entries, params.flags
+    );
+
+    if entries == 0 || entries > IoUring::MAX_ENTRIES {
+        return Err(LinuxError::EINVAL);
+    }
+    if params.flags & !IORING_SETUP_CQSIZE != 0 {
+        warn!("sys_io_uring_setup: unsupported flags: {:#x}", params.flags);
+        return Err(LinuxError::EINVAL);
+    }
+
+    let sq_entries = entries.next_power_of_two();
+    let cq_entries = if params.flags & IORING_SETUP_CQSIZE != 0 {
+        if params.cq_entries == 0 || params.cq_entries > 2 * IoUring::MAX_ENTRIES {
+            return Err(LinuxError::EINVAL);
+        }
+        let cq_entries = params.cq_entries.next_power_of_two();
+        if cq_entries < sq_entries {
+            return Err(LinuxError::EINVAL);
+        }
+        cq_entries
+    } else {
+        2 * sq_entries
+    };
+
+    let ring = IoUring::new(sq_entries, cq_entries);
+    ring.fill_params(params);
+    // Like Linux, the descriptor is always closed on `execve`.
+    Ok(install_fd(Arc::new(ring), FdFlags::CLOEXEC)? as _)
+}
+
+/// Submit up to `to_submit` requests to the `io_uring` at `fd`.
+///
+/// The requests complete before returning, so `min_complete` never has to
+/// be waited for.
+pub fn sys_io_uring_enter(
+    fd: c_int,
+    to_submit: u32,
+    min_complete: u32,
+    flags: u32,
+) -> LinuxResult<isize> {
+    debug!(
+        "sys_io_uring_enter <= fd: {}, to_submit: {}, min_complete: {}, flags: {:#x}",
+        fd, to_submit, min_complete, flags
+    );
+
+    if flags & !IORING_ENTER_GETEVENTS != 0 {
+        warn!("sys_io_uring_enter: unsupported flags: {:#x}", flags);
+        return Err(LinuxError::EINVAL);
+    }
+    let ring = IoUring::from_fd(fd).map_err(|_| LinuxError::EOPNOTSUPP)?;
+    Ok(ring.enter(to_submit)? as _)
+}
@@ -1,13 +1,92 @@
+//! Filesystem syscalls.
+//!
+//! Errors from `axfs` are converted with the generic `AxError` to
+//! `LinuxError` mapping, which is right for most lookups (`ENOENT`,
+//! `ENOTDIR`, `EEXIST`, `ENOTEMPTY`). Where it is not, the syscalls pick the
+//! errno themselves:
+//!
+//! | Case                                           | errno        |
+//! |------------------------------------------------|--------------|
+//! | `open` of a directory for writing or `O_TRUNC` | `EISDIR`     |
+//! | `read` or `write` the fd was not opened for    | `EBADF`      |
+//! | `read` of a directory                          | `EISDIR`     |
+//! | `unlink` of a directory                        | `EISDIR`     |
+//! | `rmdir` of `/` or of a mount point             | `EBUSY`      |
+//! | `rmdir` of a directory with only hard links    | `ENOTEMPTY`  |
+//! | `rename` of `/` or of a mount point            | `EBUSY`      |
+//! | `rename` with `RENAME_EXCHANGE` or `WHITEOUT`  | `EINVAL`     |
+//! | `mount` of a type other than vfat or tmpfs     | `ENODEV`     |
+//! | `mount` with an unknown option                 | `EINVAL`     |
+//! | `mount` of a file which is not a FAT image     | `EINVAL`     |
+//! | `mount` on a missing path                      | `ENOENT`     |
+//! | `mount` on a file                              | `ENOTDIR`    |
+//! | `mount` on or below a mount point              | `EBUSY`      |
+//! | `mount` on a directory with hard links in it   | `EBUSY`      |
+//! | `umount2` of a path that is not mounted        | `EINVAL`     |
+//! | `umount2` of a mount in use                    | `EBUSY`      |
+//! | `umount2` with a flag other than `MNT_DETACH`  | `EINVAL`     |
+//! | Modifying a read-only mount                    | `EROFS`      |
+//! | Paths relative to a removed directory          | `ENOENT`     |
+//! | `getcwd` after the directory was removed       | `ENOENT`     |
+//! | `O_TMPFILE` without write access               | `EINVAL`     |
+//! | `linkat` of an `O_TMPFILE` file with `O_EXCL`  | `ENOENT`     |
+//! | Attributes other than `user.` ones on tmpfs    | `EOPNOTSUPP` |
+//! | Writing past the `size` of tmpfs, or full vfat | `ENOSPC`     |
+//! | `fallocate` of a file that is not regular      | `ENODEV`     |
+//! | `fallocate` with a mode other than 0           | `EOPNOTSUPP` |
+//!
+//! Known deviations from Linux:
+//!
+//! - `open` with `O_CREAT` but without write access fails with `EINVAL`,
+//!   since `axfs` refuses to create a file it cannot write.
+//! - Opening a synthetic file, e.g. in `/proc`, for writing succeeds and the
+//!   write fails with `EACCES`, instead of the open failing.
+//! - `mount` of vfat only attaches the filesystem if `source` is a regular
+//!   file, which is loop-mounted. Block devices are only recorded.
+//! - Memory maps of files do not keep their filesystem in use, so it may
+//!   be unmounted under them.
+//! - Mounting below a mount point is refused instead of stacking the
+//!   filesystems.
+//! - Timestamps are kept in memory rather than by the filesystems, so they
+//!   do not survive a reboot.
+//! - Extended attributes are kept in memory too, and only on tmpfs.
+//! - `inotify` only reports `IN_CREATE`, `IN_DELETE` and `IN_MODIFY`, not
+//!   renames, and merges identical events in a row.
+//! - Hard links are names kept in memory, see
+//!   [`HardlinkManager`](crate::path::HardlinkManager), which `getdents64`
+//!   does not list. Removing the first name of a file with links renames
+//!   the file to one of them.
+//! - `rename` of a directory leaves behind the timestamps, inode numbers and
+//!   attributes of the files in it, which are kept by path.
+//! - An open file keeps the path it was opened at, where its timestamps,
+//!   inode number and count of links are still looked up after a `rename`.
+//! - An `O_TMPFILE` file has a hidden name in its directory until closed or
+//!   linked, which `getdents64` skips, and linking it renames that name.
+//! - vfat keeps two clusters free of file data, so that directories can
+//!   still grow when it is full, and `fallocate` writes zeros.
+//! - The `size` of a tmpfs does not count the files removed while open.
+//! - `statfs` only reports the space of a tmpfs with a `size`, which those
+//!   mounted on `/tmp` and `/run` at boot have, and none for the others.
+//! - `flock` only locks regular files, and fails with `EINVAL` on others.
+
 mod ctl;
 mod fd_ops;
+mod inotify;
 mod io;
+#[cfg(feature = "io_uring")]
+mod io_uring;
 mod mount;
 mod pipe;
 mod stat;
+mod xattr;
 
 pub use self::ctl::*;
 pub use self::fd_ops::*;
+pub use self::inotify::*;
 pub use self::io::*;
+#[cfg(feature = "io_uring")]
+pub use self::io_uring::*;
 pub use self::mount::*;
 pub use self::pipe::*;
 pub use self::stat::*;
+pub use self::xattr::*;
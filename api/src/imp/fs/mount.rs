@@ -1,13 +1,23 @@
-use core::ffi::{c_char, c_void};
+use core::{
+    ffi::{c_char, c_void},
+    sync::atomic::{AtomicU64, Ordering},
+};
 
-use alloc::vec::Vec;
+use alloc::{format, string::String, sync::Arc, vec::Vec};
 use axerrno::{LinuxError, LinuxResult};
+use axfs::{CwdRef, api::DetachedFs};
+use axns::{ResArc, def_resource};
 use axsync::Mutex;
-use linux_raw_sys::general::AT_FDCWD;
+use linux_raw_sys::general::{
+    AT_FDCWD, MNT_DETACH, MS_RDONLY, MSDOS_SUPER_MAGIC, PROC_SUPER_MAGIC, SYSFS_MAGIC, TMPFS_MAGIC,
+    statfs,
+};
+use starry_core::{mm::PAGE_SIZE, sandbox::PathAccess, task::ProcessData, workqueue::run_work};
 
 use crate::{
-    path::{FilePath, handle_file_path},
-    ptr::UserConstPtr,
+    file::{forget_inodes_under, forget_modes_under, forget_times_under},
+    path::{FilePath, HARDLINK_MANAGER, handle_file_path, invalidate_path_cache},
+    ptr::{UserConstPtr, nullable},
 };
 
 pub fn sys_mount(
@@ -15,130 +25,600 @@ pub fn sys_mount(
     target: UserConstPtr<c_char>,
     fs_type: UserConstPtr<c_char>,
     flags: i32,
-    _data: UserConstPtr<c_void>,
+    data: UserConstPtr<c_void>,
 ) -> LinuxResult<isize> {
-    let source = source.get_as_str()?;
-    let target = target.get_as_str()?;
+    let source = source.get_as_path()?;
+    let target = target.get_as_path()?;
     let fs_type = fs_type.get_as_str()?;
+    let data = UserConstPtr::<c_char>::from(data.address().as_usize());
+    let data = nullable!(data.get_as_str())?.unwrap_or_default();
     info!(
-        "sys_mount <= source: {}, target: {}, fs_type: {}, flags: {}",
-        source, target, fs_type, flags
-    );
-
-    let device_path = handle_file_path(AT_FDCWD, source)?;
-    let mount_path = handle_file_path(AT_FDCWD, target)?;
-    info!(
-        "mount {:?} to {:?} with fs_type={:?}",
-        device_path, mount_path, fs_type
+        "sys_mount <= source: {}, target: {}, fs_type: {}, flags: {}, data: {:?}",
+        source, target, fs_type, flags, data
     );
 
-    if fs_type != "vfat" {
-        debug!("fs_type can only be vfat.");
-        return Err(LinuxError::EPERM);
+    if fs_type != "vfat" && fs_type != "tmpfs" {
+        debug!("fs_type can only be vfat or tmpfs.");
+        return Err(LinuxError::ENODEV);
     }
+    let options = MountOptions::parse(fs_type, flags as u32, data)?;
 
+    let mount_path = handle_file_path(AT_FDCWD, &target, PathAccess::WRITE)?;
     if !mount_path.exists() {
         debug!("mount path not exist");
-        return Err(LinuxError::EPERM);
+        return Err(LinuxError::ENOENT);
+    }
+    if !axfs::api::metadata(mount_path.as_str())?.is_dir() {
+        debug!("mount path is not a directory");
+        return Err(LinuxError::ENOTDIR);
     }
 
     if check_mounted(&mount_path) {
         debug!("mount path includes mounted fs");
-        return Err(LinuxError::EPERM);
+        return Err(LinuxError::EBUSY);
     }
 
-    if !mount_fat_fs(&device_path, &mount_path) {
-        debug!("mount error");
-        return Err(LinuxError::EPERM);
+    let mnt_dir = mount_dir(&mount_path);
+    let device_path = match fs_type {
+        "tmpfs" => None,
+        _ => Some(handle_file_path(AT_FDCWD, &source, PathAccess::READ)?),
+    };
+    // The links in the directory would outlive the filesystem hiding them.
+    HARDLINK_MANAGER.without_links_under(mount_path.as_str(), LinuxError::EBUSY, || {
+        let (attached, source) = match device_path {
+            None => {
+                axfs::api::mount_ramfs(mnt_dir)?;
+                (true, source.into_owned())
+            }
+            // A regular file is an image, mounted through an implicit loop
+            // device.
+            Some(device_path)
+                if axfs::api::metadata(device_path.as_str()).is_ok_and(|it| it.is_file()) =>
+            {
+                axfs::api::mount_fat_image(device_path.as_str(), mnt_dir).map_err(|e| {
+                    debug!("mount image error: {:?}", e);
+                    LinuxError::EINVAL
+                })?;
+                (true, String::from(device_path.as_str()))
+            }
+            // TODO: mount block devices other than the root one
+            Some(device_path) => (false, String::from(device_path.as_str())),
+        };
+        info!("mounted {} to {}", source, mnt_dir);
+        MOUNTED.lock().push(Arc::new(MountedFs::new(
+            mount_path.clone(),
+            source,
+            options,
+            attached,
+        )));
+        Ok(0)
+    })
+}
+
+/// The directories a tmpfs is mounted on at boot.
+const BOOT_TMPFS_DIRS: [&str; 2] = ["/tmp", "/run"];
+
+/// The size of each tmpfs mounted at boot, unless set at build time with
+/// the `AX_TMPFS_SIZE` environment variable, in the format of the `size`
+/// option.
+const DEFAULT_BOOT_TMPFS_SIZE: u64 = 16 << 20;
+
+/// Mount a tmpfs on `/tmp` and on `/run`, registered as if by `mount`,
+/// so that scratch files stay off the disk and are gone after a reboot.
+///
+/// `axfs` mounts a RAM filesystem on `/tmp` itself, which is replaced, so
+/// that it has a size and can be unmounted like the others.
+pub fn mount_boot_tmpfs() {
+    let size = option_env!("AX_TMPFS_SIZE")
+        .filter(|size| !size.is_empty())
+        .map_or(Ok(DEFAULT_BOOT_TMPFS_SIZE), parse_size)
+        .expect("AX_TMPFS_SIZE is not a size");
+    for dir in BOOT_TMPFS_DIRS {
+        drop(axfs::api::detach(dir));
+        if let Err(err) = axfs::api::mount_ramfs(dir) {
+            warn!("Failed to mount tmpfs on {}: {:?}", dir, err);
+            continue;
+        }
+        let mnt_dir = FilePath::new(dir).expect("mount point should be a valid path");
+        let options = MountOptions {
+            fs_type: "tmpfs",
+            size: Some(size),
+            ..Default::default()
+        };
+        MOUNTED.lock().push(Arc::new(MountedFs::new(
+            mnt_dir,
+            "tmpfs".into(),
+            options,
+            true,
+        )));
+        info!("mounted tmpfs of {} bytes to {}", size, dir);
     }
-    Ok(0)
+    invalidate_path_cache();
 }
 
+/// Unmount the filesystem mounted on `target`, which fails with `EBUSY`
+/// while it is in use, see [`MountRef`].
+///
+/// With `MNT_DETACH`, it is taken off `target` even if in use, and only
+/// flushed and released once no longer in use.
 pub fn sys_umount2(target: UserConstPtr<c_char>, flags: i32) -> LinuxResult<isize> {
-    let target = target.get_as_str()?;
+    let target = target.get_as_path()?;
     info!("sys_umount2 <= target: {}, flags: {}", target, flags);
 
-    let mount_path = handle_file_path(AT_FDCWD, target)?;
-    if flags != 0 {
+    let mount_path = handle_file_path(AT_FDCWD, &target, PathAccess::WRITE)?;
+    if flags as u32 & !MNT_DETACH != 0 {
         debug!("flags unimplemented");
-        return Err(LinuxError::EPERM);
+        return Err(LinuxError::EINVAL);
     }
 
     if !mount_path.exists() {
         debug!("mount path not exist");
-        return Err(LinuxError::EPERM);
+        return Err(LinuxError::ENOENT);
     }
 
-    if !umount_fat_fs(&mount_path) {
+    let mut mounted = MOUNTED.lock();
+    let Some(idx) = mounted.iter().position(|m| m.mnt_dir == mount_path) else {
         debug!("umount error");
-        return Err(LinuxError::EPERM);
+        return Err(LinuxError::EINVAL);
+    };
+    // The list holds one reference.
+    if flags as u32 & MNT_DETACH == 0 && Arc::strong_count(&mounted[idx]) > 1 {
+        return Err(LinuxError::EBUSY);
+    }
+    if mounted[idx].attached {
+        *mounted[idx].detached.lock() = Some(axfs::api::detach(mount_dir(&mount_path))?);
     }
+    let fs = mounted.remove(idx);
+    drop(mounted);
+    HARDLINK_MANAGER.forget_under(fs.mnt_dir.as_str());
+    forget_times_under(fs.mnt_dir.as_str());
+    forget_inodes_under(fs.mnt_dir.as_str());
+    forget_modes_under(fs.mnt_dir.as_str());
+    // Released here, unless still in use.
+    drop(fs);
     Ok(0)
 }
 
-/// Mounted File System
-/// "Mount" means read&write a file as a file system now
+/// The options of a mount, from its flags and its `data` string.
+#[derive(Debug, Default, Clone)]
+pub struct MountOptions {
+    /// The filesystem type, `vfat` or `tmpfs`.
+    pub fs_type: &'static str,
+    /// Refuse to modify the filesystem.
+    pub read_only: bool,
+    /// The permission bits cleared from regular files, for vfat.
+    pub fmask: Option<u32>,
+    /// The permission bits cleared from directories, for vfat.
+    pub dmask: Option<u32>,
+    /// The size limit in bytes, for tmpfs, past which writing fails with
+    /// `ENOSPC`, see [`space_left`].
+    pub size: Option<u64>,
+}
+
+impl MountOptions {
+    /// Parse the mount `flags` and the comma-separated `key[=value]` options
+    /// of `fs_type` in `data`.
+    ///
+    /// Unknown options fail with `EINVAL`, like they do on Linux. Some
+    /// options are only checked, see the fields for the ones taking effect.
+    pub fn parse(fs_type: &str, flags: u32, data: &str) -> LinuxResult<Self> {
+        let mut options = Self {
+            fs_type: match fs_type {
+                "vfat" => "vfat",
+                "tmpfs" => "tmpfs",
+                _ => return Err(LinuxError::ENODEV),
+            },
+            read_only: flags & MS_RDONLY != 0,
+            ..Default::default()
+        };
+        for option in data.split(',').filter(|it| !it.is_empty()) {
+            let (key, value) = match option.split_once('=') {
+                Some((key, value)) => (key, Some(value)),
+                None => (option, None),
+            };
+            match (fs_type, key, value) {
+                (_, "ro", None) => options.read_only = true,
+                (_, "rw", None) => options.read_only = false,
+                ("vfat", "umask", Some(mask)) => {
+                    let mask = parse_mode(mask)?;
+                    options.fmask = Some(mask);
+                    options.dmask = Some(mask);
+                }
+                ("vfat", "fmask", Some(mask)) => options.fmask = Some(parse_mode(mask)?),
+                ("vfat", "dmask", Some(mask)) => options.dmask = Some(parse_mode(mask)?),
+                // Names are always decoded as code page 437.
+                ("vfat", "codepage", Some("437")) => {}
+                ("vfat", "iocharset", Some("utf8" | "ascii")) => {}
+                // TODO: use the owner once credentials have ids
+                ("vfat" | "tmpfs", "uid" | "gid", Some(id)) => {
+                    id.parse::<u32>().map_err(|_| LinuxError::EINVAL)?;
+                }
+                ("tmpfs", "size", Some(size)) => options.size = Some(parse_size(size)?),
+                ("tmpfs", "mode", Some(mode)) => {
+                    parse_mode(mode)?;
+                }
+                ("tmpfs", "nr_inodes", Some(count)) => {
+                    parse_size(count)?;
+                }
+                _ => {
+                    debug!("unsupported mount option {:?} for {}", option, fs_type);
+                    return Err(LinuxError::EINVAL);
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+impl MountOptions {
+    /// The options of the filesystem as `/proc/mounts` lists them, e.g.
+    /// `size=16384k` for a tmpfs.
+    fn fs_options(&self) -> String {
+        let mut options = Vec::new();
+        if let Some(mask) = self.fmask {
+            options.push(format!("fmask={:04o}", mask));
+        }
+        if let Some(mask) = self.dmask {
+            options.push(format!("dmask={:04o}", mask));
+        }
+        if let Some(size) = self.size {
+            options.push(format!("size={}k", size / 1024));
+        }
+        options.join(",")
+    }
+}
+
+/// Parse an octal permission mode.
+fn parse_mode(mode: &str) -> LinuxResult<u32> {
+    match u32::from_str_radix(mode, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Parse a size, with an optional `k`, `m` or `g` suffix.
+fn parse_size(size: &str) -> LinuxResult<u64> {
+    let (digits, shift) = match size.as_bytes().last() {
+        Some(b'k' | b'K') => (&size[..size.len() - 1], 10),
+        Some(b'm' | b'M') => (&size[..size.len() - 1], 20),
+        Some(b'g' | b'G') => (&size[..size.len() - 1], 30),
+        _ => (size, 0),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|size| size.checked_mul(1 << shift))
+        .ok_or(LinuxError::EINVAL)
+}
+
+/// The path of a mount point, as `axfs` knows it.
+fn mount_dir(path: &FilePath) -> &str {
+    path.as_str().trim_end_matches('/')
+}
+
+/// A mounted filesystem.
 struct MountedFs {
-    //pub inner: Arc<Mutex<FATFileSystem>>,
-    pub device: FilePath,
-    pub mnt_dir: FilePath,
+    /// The mount ID, unique among the mounts since boot.
+    id: u64,
+    mnt_dir: FilePath,
+    /// What was mounted, a path or a name like `tmpfs`.
+    source: String,
+    options: MountOptions,
+    /// Whether the filesystem is attached to `axfs`, rather than only
+    /// recorded.
+    attached: bool,
+    /// The filesystem once taken off `axfs` by unmounting it.
+    detached: Mutex<Option<DetachedFs>>,
 }
 
 impl MountedFs {
-    pub fn new(device: &FilePath, mnt_dir: &FilePath) -> Self {
+    fn new(mnt_dir: FilePath, source: String, options: MountOptions, attached: bool) -> Self {
         Self {
-            device: device.clone(),
-            mnt_dir: mnt_dir.clone(),
+            id: NEXT_MOUNT_ID.fetch_add(1, Ordering::Relaxed),
+            mnt_dir,
+            source,
+            options,
+            attached,
+            detached: Mutex::new(None),
         }
     }
+}
 
-    #[allow(unused)]
-    pub fn device(&self) -> FilePath {
-        self.device.clone()
+impl Drop for MountedFs {
+    fn drop(&mut self) {
+        if let Some(fs) = self.detached.get_mut().take() {
+            // Unmounting flushes the filesystem, which is deep work.
+            run_work(move || drop(fs));
+            RELEASED_MOUNTS.fetch_add(1, Ordering::Relaxed);
+        }
     }
+}
 
-    pub fn mnt_dir(&self) -> FilePath {
-        self.mnt_dir.clone()
-    }
+/// A reference keeping a mounted filesystem in use, so that it cannot be
+/// unmounted, and is released only once the last one drops if it was
+/// detached.
+///
+/// Open files and directories hold one, and so does the working directory.
+#[derive(Clone)]
+pub struct MountRef {
+    _fs: Arc<MountedFs>,
 }
 
 /// List of mounted file system
 /// Note that the startup file system is not in the vec, but in mod.rs
-static MOUNTED: Mutex<Vec<MountedFs>> = Mutex::new(Vec::new());
-
-/// Mount a fatfs device
-pub fn mount_fat_fs(device_path: &FilePath, mount_path: &FilePath) -> bool {
-    // device_path needs symlink lookup, but mount_path does not
-    // only opened files will be added to the symlink table for now, so do not convert now
-    // debug!("mounting {} to {}", device_path.path(), mount_path.path());
-    // if let Some(true_device_path) = real_path(device_path) {
-    if mount_path.exists() {
-        MOUNTED.lock().push(MountedFs::new(device_path, mount_path));
-        info!(
-            "mounted {} to {}",
-            device_path.as_str(),
-            mount_path.as_str()
-        );
-        return true;
+static MOUNTED: Mutex<Vec<Arc<MountedFs>>> = Mutex::new(Vec::new());
+
+/// The mounts made at boot by `axfs`, with their mount IDs, which come
+/// first: the root filesystem, and the ones not in [`MOUNTED`], as
+/// `(id, mount point, type, source)`.
+const SYSTEM_MOUNTS: [(u64, &str, &str, &str); 4] = [
+    (
+        1,
+        "/",
+        if cfg!(feature = "lwext4_rs") {
+            "ext4"
+        } else {
+            "vfat"
+        },
+        "/dev/vda",
+    ),
+    (2, "/dev", "devtmpfs", "devtmpfs"),
+    (3, "/proc", "proc", "proc"),
+    (4, "/sys", "sysfs", "sysfs"),
+];
+
+/// The ID of the next mount, see [`MountedFs::id`].
+static NEXT_MOUNT_ID: AtomicU64 = AtomicU64::new(SYSTEM_MOUNTS.len() as u64 + 1);
+
+/// The number of filesystems flushed and released after being unmounted.
+static RELEASED_MOUNTS: AtomicU64 = AtomicU64::new(0);
+
+/// The number of filesystems flushed and released after being unmounted,
+/// shown in `/proc/starry/released_mounts`.
+pub fn released_mounts() -> u64 {
+    RELEASED_MOUNTS.load(Ordering::Relaxed)
+}
+
+/// A mount, as `/proc/<pid>/mountinfo` shows it.
+#[derive(Debug, Clone)]
+pub struct MountInfo {
+    /// The mount ID, which stays the same while mounted.
+    pub id: u64,
+    /// The ID of the mount the mount point is on, or its own for the root.
+    pub parent_id: u64,
+    /// The major and minor device numbers of the filesystem.
+    pub dev: (u32, u32),
+    /// The mount point.
+    pub mount_point: String,
+    /// Whether mounted read-only.
+    pub read_only: bool,
+    /// The filesystem type.
+    pub fs_type: &'static str,
+    /// What was mounted.
+    pub source: String,
+    /// The options of the filesystem, comma-separated, without `ro` or
+    /// `rw`.
+    pub fs_options: String,
+}
+
+/// The major device number of virtio block devices on Linux.
+const VIRTIO_BLK_MAJOR: u32 = 254;
+
+/// The mounts `proc` sees, in the order they were made.
+///
+/// All processes see the same mounts, as there are no mount namespaces.
+/// Should they come, this is where the mounts of the namespace of `proc`
+/// are picked.
+pub fn mounts_of(_proc: &ProcessData) -> Vec<MountInfo> {
+    let system = SYSTEM_MOUNTS
+        .iter()
+        .map(|&(id, dir, fs_type, source)| MountInfo {
+            id,
+            parent_id: 1,
+            dev: if id == 1 {
+                (VIRTIO_BLK_MAJOR, 0)
+            } else {
+                (0, id as u32)
+            },
+            mount_point: dir.into(),
+            read_only: false,
+            fs_type,
+            source: source.into(),
+            fs_options: String::new(),
+        });
+    let mounted = MOUNTED.lock();
+    let mut mounts: Vec<MountInfo> = system
+        .chain(mounted.iter().map(|m| MountInfo {
+            id: m.id,
+            parent_id: 1,
+            dev: (0, m.id as u32),
+            mount_point: match mount_dir(&m.mnt_dir) {
+                "" => "/".into(),
+                dir => dir.into(),
+            },
+            read_only: m.options.read_only,
+            fs_type: m.options.fs_type,
+            source: m.source.clone(),
+            fs_options: m.options.fs_options(),
+        }))
+        .collect();
+    drop(mounted);
+    // The parent is the latest mount of the longest mount point above.
+    for i in 1..mounts.len() {
+        let point = &mounts[i].mount_point;
+        let parent = mounts[..i]
+            .iter()
+            .filter(|m| {
+                let dir = m.mount_point.trim_end_matches('/');
+                point
+                    .strip_prefix(dir)
+                    .is_some_and(|rest| rest.starts_with('/'))
+            })
+            .max_by_key(|m| m.mount_point.len())
+            .map_or(1, |m| m.id);
+        mounts[i].parent_id = parent;
     }
-    info!(
-        "mount failed: {} to {}",
-        device_path.as_str(),
-        mount_path.as_str()
-    );
-    false
+    mounts
 }
 
-/// unmount a fatfs device
-pub fn umount_fat_fs(mount_path: &FilePath) -> bool {
-    let mut mounted = MOUNTED.lock();
-    let length_before_deletion = mounted.len();
-    mounted.retain(|m| m.mnt_dir() != *mount_path);
-    length_before_deletion > mounted.len()
+/// Find the filesystem `path` is on among `mounted`, unless it is the root
+/// filesystem.
+fn mount_of<'a>(mounted: &'a [Arc<MountedFs>], path: &str) -> Option<&'a Arc<MountedFs>> {
+    mounted
+        .iter()
+        .filter(|m| {
+            let dir = mount_dir(&m.mnt_dir);
+            path.strip_prefix(dir)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+        .max_by_key(|m| m.mnt_dir.as_str().len())
+}
+
+/// Take a reference to the filesystem the absolute `path` is on, unless it
+/// is the root filesystem.
+pub fn mount_ref(path: &str) -> Option<MountRef> {
+    mount_of(&MOUNTED.lock(), path).map(|fs| MountRef { _fs: fs.clone() })
+}
+
+/// Whether the absolute paths `a` and `b` are on the same filesystem.
+pub fn same_mount(a: &str, b: &str) -> bool {
+    let mounted = MOUNTED.lock();
+    match (mount_of(&mounted, a), mount_of(&mounted, b)) {
+        (None, None) => true,
+        (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+        _ => false,
+    }
 }
 
 /// check if a path is mounted
 pub fn check_mounted(path: &FilePath) -> bool {
     let mounted = MOUNTED.lock();
-    mounted.iter().any(|m| path.starts_with(&m.mnt_dir()))
+    mounted.iter().any(|m| path.starts_with(&m.mnt_dir))
+}
+
+/// check if a path is exactly a mount point
+pub fn is_mount_point(path: &FilePath) -> bool {
+    let mounted = MOUNTED.lock();
+    mounted.iter().any(|m| m.mnt_dir == *path)
+}
+
+/// Get the options of the filesystem `path` is on, unless it is the root
+/// filesystem.
+pub fn mount_options(path: &str) -> Option<MountOptions> {
+    mount_of(&MOUNTED.lock(), path).map(|m| m.options.clone())
+}
+
+/// Get the bytes left on the filesystem `path` is on, if it has a size
+/// limit, like a tmpfs mounted with `size`.
+///
+/// The space used is counted by walking the filesystem, with each file
+/// taking whole pages as on Linux. The files removed while still open are
+/// not counted.
+pub fn space_left(path: &str) -> Option<u64> {
+    let mounted = MOUNTED.lock();
+    let fs = mount_of(&mounted, path)?;
+    let size = fs.options.size?;
+    Some(size.saturating_sub(used_bytes(mount_dir(&fs.mnt_dir), &mounted)))
+}
+
+/// The bytes taken by the files under `dir`, not counting the filesystems
+/// in `mounted` below it.
+fn used_bytes(dir: &str, mounted: &[Arc<MountedFs>]) -> u64 {
+    let Ok(entries) = axfs::api::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .filter(|entry| !matches!(entry.file_name().as_str(), "." | ".."))
+        .map(|entry| {
+            let path = entry.path();
+            if mounted.iter().any(|m| mount_dir(&m.mnt_dir) == path) {
+                return 0;
+            }
+            match axfs::api::metadata(&path) {
+                Ok(metadata) if metadata.is_dir() => used_bytes(&path, mounted),
+                Ok(metadata) => metadata.size().next_multiple_of(PAGE_SIZE as u64),
+                Err(_) => 0,
+            }
+        })
+        .sum()
+}
+
+/// The `f_flags` bit of `statfs` for a read-only mount.
+const ST_RDONLY: u32 = 1;
+
+/// Describe the filesystem the absolute `path` is on, as `statfs` does.
+///
+/// The space is only known for a tmpfs with a size, and reported as none
+/// otherwise, like for the filesystems without a backing store on Linux.
+pub fn statfs_at(path: &str) -> statfs {
+    // SAFETY: valid for statfs
+    let mut buf: statfs = unsafe { core::mem::zeroed() };
+    buf.f_bsize = PAGE_SIZE as _;
+    buf.f_frsize = PAGE_SIZE as _;
+    buf.f_namelen = 255;
+
+    let mounted = MOUNTED.lock();
+    let Some(fs) = mount_of(&mounted, path) else {
+        // The root filesystem, or one `axfs` mounts itself.
+        let under = |dir: &str| {
+            path.strip_prefix(dir)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        };
+        buf.f_type = if under("/proc") {
+            PROC_SUPER_MAGIC
+        } else if under("/sys") {
+            SYSFS_MAGIC
+        } else if under("/dev") {
+            TMPFS_MAGIC
+        } else {
+            MSDOS_SUPER_MAGIC
+        } as _;
+        return buf;
+    };
+    buf.f_type = match fs.options.fs_type {
+        "tmpfs" => TMPFS_MAGIC,
+        _ => MSDOS_SUPER_MAGIC,
+    } as _;
+    if fs.options.read_only {
+        buf.f_flags = ST_RDONLY as _;
+    }
+    if let Some(size) = fs.options.size {
+        let used = used_bytes(mount_dir(&fs.mnt_dir), &mounted);
+        let free = size.saturating_sub(used) / PAGE_SIZE as u64;
+        buf.f_blocks = (size / PAGE_SIZE as u64) as _;
+        buf.f_bfree = free as _;
+        buf.f_bavail = free as _;
+    }
+    buf
+}
+
+/// Fail with `EROFS` if `path` is on a read-only filesystem.
+pub fn check_writable(path: &str) -> LinuxResult {
+    if mount_options(path).is_some_and(|it| it.read_only) {
+        return Err(LinuxError::EROFS);
+    }
+    Ok(())
+}
+
+def_resource! {
+    /// The filesystem the working directory is on, kept in use.
+    pub static CWD_MOUNT: ResArc<CwdRef<Option<MountRef>>> = ResArc::new();
+}
+
+impl CWD_MOUNT {
+    /// Return a reference to a copy of the inner reference.
+    pub fn copy_inner(&self) -> CwdRef<Option<MountRef>> {
+        self.copy()
+    }
+
+    /// Return a reference to the same reference, as with `CLONE_FS`.
+    pub fn share_inner(&self) -> CwdRef<Option<MountRef>> {
+        self.share()
+    }
+}
+
+#[ctor_bare::register_ctor]
+fn init_cwd_mount() {
+    CWD_MOUNT.init_new(CwdRef::new(None));
 }
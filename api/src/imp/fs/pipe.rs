@@ -1,23 +1,25 @@
 use core::ffi::c_int;
 
+use alloc::sync::Arc;
 use axerrno::LinuxResult;
 
 use crate::{
-    file::{FileLike, Pipe, close_file_like},
+    file::{FdFlags, Pipe, close_file_like, install_fd},
     ptr::UserPtr,
 };
 
+/// Create a pipe. Only `O_CLOEXEC` and `O_NONBLOCK` are supported in
+/// `flags`, which apply to both ends.
 pub fn sys_pipe2(fds: UserPtr<[c_int; 2]>, flags: i32) -> LinuxResult<isize> {
-    if flags != 0 {
-        warn!("sys_pipe2: unsupported flags: {}", flags);
-    }
+    let (flags, _) = FdFlags::parse(flags as _, 0)?;
 
+    // Check the user memory before any fd is allocated, so that a bad
+    // pointer cannot leave the pipe open.
     let fds = fds.get_as_mut()?;
 
     let (read_end, write_end) = Pipe::new();
-    let read_fd = read_end.add_to_fd_table()?;
-    let write_fd = write_end
-        .add_to_fd_table()
+    let read_fd = install_fd(Arc::new(read_end), flags)?;
+    let write_fd = install_fd(Arc::new(write_end), flags)
         .inspect_err(|_| close_file_like(read_fd).unwrap())?;
 
     fds[0] = read_fd;
@@ -1,35 +1,25 @@
 use core::ffi::{c_char, c_int};
 
-use axerrno::{AxError, LinuxError, LinuxResult};
-use axfs::fops::OpenOptions;
-use linux_raw_sys::general::{AT_EMPTY_PATH, stat, statx};
+use axerrno::{LinuxError, LinuxResult};
+use linux_raw_sys::general::{AT_FDCWD, STATX__RESERVED, stat, statfs, statx};
+use starry_core::sandbox::PathAccess;
 
+use super::statfs_at;
 use crate::{
-    file::{Directory, File, FileLike, Kstat, get_file_like},
-    path::handle_file_path,
+    file::{Directory, File, VirtualDirFile, get_file_like, lstat_at_path, stat_at_path},
+    path::{AtFlags, handle_file_path, resolve_at},
     ptr::{UserConstPtr, UserPtr, nullable},
 };
 
-fn stat_at_path(path: &str) -> LinuxResult<Kstat> {
-    let opts = OpenOptions::new().set_read(true);
-    match axfs::fops::File::open(path, &opts) {
-        Ok(file) => File::new(file, path.into()).stat(),
-        Err(AxError::IsADirectory) => {
-            let dir = axfs::fops::Directory::open_dir(path, &opts)?;
-            Directory::new(dir, path.into()).stat()
-        }
-        Err(e) => Err(e.into()),
-    }
-}
-
 /// Get the file metadata by `path` and write into `statbuf`.
 ///
 /// Return 0 if success.
 pub fn sys_stat(path: UserConstPtr<c_char>, statbuf: UserPtr<stat>) -> LinuxResult<isize> {
-    let path = path.get_as_str()?;
+    let path = path.get_as_path()?;
     debug!("sys_stat <= path: {}", path);
 
-    *statbuf.get_as_mut()? = stat_at_path(path)?.into();
+    let path = handle_file_path(AT_FDCWD, &path, PathAccess::READ)?;
+    *statbuf.get_as_mut()? = stat_at_path(path.as_str())?.into();
 
     Ok(0)
 }
@@ -39,7 +29,8 @@ pub fn sys_stat(path: UserConstPtr<c_char>, statbuf: UserPtr<stat>) -> LinuxResu
 /// Return 0 if success.
 pub fn sys_fstat(fd: i32, statbuf: UserPtr<stat>) -> LinuxResult<isize> {
     debug!("sys_fstat <= fd: {}", fd);
-    *statbuf.get_as_mut()? = get_file_like(fd)?.stat()?.into();
+    let stat = get_file_like(fd)?.stat()?;
+    *statbuf.get_as_mut()? = stat.into();
     Ok(0)
 }
 
@@ -47,8 +38,13 @@ pub fn sys_fstat(fd: i32, statbuf: UserPtr<stat>) -> LinuxResult<isize> {
 ///
 /// Return 0 if success.
 pub fn sys_lstat(path: UserConstPtr<c_char>, statbuf: UserPtr<stat>) -> LinuxResult<isize> {
-    // TODO: symlink
-    sys_stat(path, statbuf)
+    let path = path.get_as_path()?;
+    debug!("sys_lstat <= path: {}", path);
+
+    let path = handle_file_path(AT_FDCWD, &path, PathAccess::READ)?;
+    *statbuf.get_as_mut()? = lstat_at_path(path.as_str())?.into();
+
+    Ok(0)
 }
 
 pub fn sys_fstatat(
@@ -57,22 +53,19 @@ pub fn sys_fstatat(
     statbuf: UserPtr<stat>,
     flags: u32,
 ) -> LinuxResult<isize> {
-    let path = nullable!(path.get_as_str())?;
+    let path = nullable!(path.get_as_path())?;
     debug!(
         "sys_fstatat <= dirfd: {}, path: {:?}, flags: {}",
         dirfd, path, flags
     );
 
-    *statbuf.get_as_mut()? = if path.is_none_or(|s| s.is_empty()) {
-        if (flags & AT_EMPTY_PATH) == 0 {
-            return Err(LinuxError::ENOENT);
-        }
-        let f = get_file_like(dirfd)?;
-        f.stat()?.into()
-    } else {
-        let path = handle_file_path(dirfd, path.unwrap_or_default())?;
-        stat_at_path(path.as_str())?.into()
-    };
+    let flags = AtFlags::parse(
+        flags,
+        AtFlags::EMPTY_PATH | AtFlags::NO_AUTOMOUNT | AtFlags::SYMLINK_NOFOLLOW,
+    )?;
+    *statbuf.get_as_mut()? = resolve_at(dirfd, path.as_deref(), flags, PathAccess::READ)?
+        .stat_with(flags)?
+        .into();
 
     Ok(0)
 }
@@ -81,7 +74,7 @@ pub fn sys_statx(
     dirfd: c_int,
     path: UserConstPtr<c_char>,
     flags: u32,
-    _mask: u32,
+    mask: u32,
     statxbuf: UserPtr<statx>,
 ) -> LinuxResult<isize> {
     // `statx()` uses pathname, dirfd, and flags to identify the target
@@ -111,22 +104,66 @@ pub fn sys_statx(
     //        below), then the target file is the one referred to by the
     //        file descriptor dirfd.
 
-    let path = nullable!(path.get_as_str())?;
+    let path = nullable!(path.get_as_path())?;
     debug!(
         "sys_statx <= dirfd: {}, path: {:?}, flags: {}",
         dirfd, path, flags
     );
 
-    *statxbuf.get_as_mut()? = if path.is_none_or(|s| s.is_empty()) {
-        if (flags & AT_EMPTY_PATH) == 0 {
-            return Err(LinuxError::ENOENT);
-        }
-        let f = get_file_like(dirfd)?;
-        f.stat()?.into()
+    let flags = AtFlags::parse(
+        flags,
+        AtFlags::EMPTY_PATH
+            | AtFlags::NO_AUTOMOUNT
+            | AtFlags::SYMLINK_NOFOLLOW
+            | AtFlags::STATX_FORCE_SYNC
+            | AtFlags::STATX_DONT_SYNC,
+    )?;
+    // The sync type is a two-bit field, and 0b11 is reserved.
+    if flags.contains(AtFlags::STATX_FORCE_SYNC | AtFlags::STATX_DONT_SYNC) {
+        return Err(LinuxError::EINVAL);
+    }
+    // `statx` has a fixed size and grows through `mask` instead, so only the
+    // bit kept for extending the struct is refused.
+    if mask & STATX__RESERVED != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    *statxbuf.get_as_mut()? = resolve_at(dirfd, path.as_deref(), flags, PathAccess::READ)?
+        .stat_with(flags)?
+        .into();
+
+    Ok(0)
+}
+
+/// Describe the filesystem `path` is on into `buf`.
+pub fn sys_statfs(path: UserConstPtr<c_char>, buf: UserPtr<statfs>) -> LinuxResult<isize> {
+    let path = path.get_as_path()?;
+    debug!("sys_statfs <= path: {}", path);
+
+    let path = handle_file_path(AT_FDCWD, &path, PathAccess::READ)?;
+    if !path.exists() {
+        return Err(LinuxError::ENOENT);
+    }
+    *buf.get_as_mut()? = statfs_at(path.as_str());
+    Ok(0)
+}
+
+/// Describe the filesystem the file `fd` refers to is on into `buf`.
+///
+/// Files which are not on a filesystem, like pipes, are described as on
+/// the root one.
+pub fn sys_fstatfs(fd: c_int, buf: UserPtr<statfs>) -> LinuxResult<isize> {
+    debug!("sys_fstatfs <= fd: {}", fd);
+
+    let any = get_file_like(fd)?.into_any();
+    let path = if let Some(file) = any.downcast_ref::<File>() {
+        file.path()
+    } else if let Some(dir) = any.downcast_ref::<Directory>() {
+        dir.path()
+    } else if let Some(dir) = any.downcast_ref::<VirtualDirFile>() {
+        dir.path()
     } else {
-        let path = handle_file_path(dirfd, path.unwrap_or_default())?;
-        stat_at_path(path.as_str())?.into()
+        "/"
     };
-
+    *buf.get_as_mut()? = statfs_at(path);
     Ok(0)
 }
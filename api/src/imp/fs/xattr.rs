@@ -0,0 +1,299 @@
+//! Extended attribute syscalls.
+//!
+//! Only `user.` attributes on tmpfs are supported, see [`crate::file`].
+//! Any other namespace, or any other filesystem, fails with `EOPNOTSUPP`,
+//! which tools copying attributes take as nothing to copy.
+
+use core::ffi::{c_char, c_int};
+
+use axerrno::{LinuxError, LinuxResult};
+use linux_raw_sys::general::{
+    AT_FDCWD, R_OK, W_OK, XATTR_CREATE, XATTR_LIST_MAX, XATTR_NAME_MAX, XATTR_REPLACE,
+    XATTR_SIZE_MAX,
+};
+use starry_core::sandbox::PathAccess;
+
+use super::check_writable;
+use crate::{
+    check_path_access,
+    file::{
+        get_file_like, get_xattr, list_xattrs, lstat_at_path, remove_xattr, set_xattr,
+        stat_at_path, update_ctime, xattrs_supported,
+    },
+    path::{AtTarget, FilePath, handle_file_path},
+    ptr::{UserConstPtr, UserPtr},
+};
+
+/// Resolve the `path` of a syscall, which must exist, following a link in
+/// the final component if `follow`, to `access` it.
+fn xattr_path(
+    path: UserConstPtr<c_char>,
+    follow: bool,
+    access: PathAccess,
+) -> LinuxResult<FilePath> {
+    let path = handle_file_path(AT_FDCWD, &path.get_as_path()?, access)?;
+    if follow {
+        stat_at_path(path.as_str())?;
+    } else {
+        lstat_at_path(path.as_str())?;
+    }
+    Ok(path)
+}
+
+/// Get the path of the file `fd` refers to. A file without one, e.g. a
+/// pipe, has no attributes.
+fn xattr_fd_path(fd: c_int) -> LinuxResult<FilePath> {
+    AtTarget::Fd(get_file_like(fd)?)
+        .path()
+        .map_err(|_| LinuxError::EOPNOTSUPP)
+}
+
+/// Read the attribute name at `name`, which fails with `ERANGE` if empty or
+/// too long.
+fn xattr_name(name: UserConstPtr<c_char>) -> LinuxResult<&'static str> {
+    let name = name.get_as_str()?;
+    if name.is_empty() || name.len() > XATTR_NAME_MAX as usize {
+        return Err(LinuxError::ERANGE);
+    }
+    Ok(name)
+}
+
+/// Fail with `EOPNOTSUPP` unless the attribute `name` can be kept for the
+/// file at `path`.
+fn check_supported(path: &FilePath, name: &str) -> LinuxResult {
+    match name.strip_prefix("user.") {
+        Some("") => Err(LinuxError::EINVAL),
+        Some(_) if xattrs_supported(path.as_str()) => Ok(()),
+        // `security.`, `trusted.` and `system.` included.
+        _ => Err(LinuxError::EOPNOTSUPP),
+    }
+}
+
+/// Copy `data` to the `size` bytes at `buf`, or only return its length if
+/// `size` is 0.
+fn copy_out(data: &[u8], buf: UserPtr<u8>, size: usize) -> LinuxResult<isize> {
+    if size == 0 {
+        return Ok(data.len() as _);
+    }
+    if data.len() > size {
+        return Err(LinuxError::ERANGE);
+    }
+    buf.get_as_mut_slice(data.len())?.copy_from_slice(data);
+    Ok(data.len() as _)
+}
+
+fn getxattr(path: FilePath, name: &str, value: UserPtr<u8>, size: usize) -> LinuxResult<isize> {
+    check_supported(&path, name)?;
+    check_path_access(path.as_str(), R_OK)?;
+    copy_out(&get_xattr(path.as_str(), name)?, value, size)
+}
+
+fn setxattr(
+    path: FilePath,
+    name: &str,
+    value: UserConstPtr<u8>,
+    size: usize,
+    flags: u32,
+) -> LinuxResult<isize> {
+    if flags & !(XATTR_CREATE | XATTR_REPLACE) != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    if size > XATTR_SIZE_MAX as usize {
+        return Err(LinuxError::E2BIG);
+    }
+    let value: &[u8] = if size == 0 {
+        &[]
+    } else {
+        value.get_as_slice(size)?
+    };
+    check_supported(&path, name)?;
+    check_writable(path.as_str())?;
+    check_path_access(path.as_str(), W_OK)?;
+    set_xattr(path.as_str(), name, value, flags)?;
+    update_ctime(path.as_str());
+    Ok(0)
+}
+
+fn listxattr(path: FilePath, list: UserPtr<u8>, size: usize) -> LinuxResult<isize> {
+    if !xattrs_supported(path.as_str()) {
+        return Err(LinuxError::EOPNOTSUPP);
+    }
+    let names = list_xattrs(path.as_str());
+    if names.len() > XATTR_LIST_MAX as usize {
+        return Err(LinuxError::E2BIG);
+    }
+    copy_out(&names, list, size)
+}
+
+fn removexattr(path: FilePath, name: &str) -> LinuxResult<isize> {
+    check_supported(&path, name)?;
+    check_writable(path.as_str())?;
+    check_path_access(path.as_str(), W_OK)?;
+    remove_xattr(path.as_str(), name)?;
+    update_ctime(path.as_str());
+    Ok(0)
+}
+
+/// Get the value of the attribute `name` of the file at `path`, or only its
+/// length if `size` is 0.
+pub fn sys_getxattr(
+    path: UserConstPtr<c_char>,
+    name: UserConstPtr<c_char>,
+    value: UserPtr<u8>,
+    size: usize,
+) -> LinuxResult<isize> {
+    let name = xattr_name(name)?;
+    debug!("sys_getxattr <= name: {}, size: {}", name, size);
+    getxattr(xattr_path(path, true, PathAccess::READ)?, name, value, size)
+}
+
+/// Like [`sys_getxattr`], without following a link in the final component.
+pub fn sys_lgetxattr(
+    path: UserConstPtr<c_char>,
+    name: UserConstPtr<c_char>,
+    value: UserPtr<u8>,
+    size: usize,
+) -> LinuxResult<isize> {
+    let name = xattr_name(name)?;
+    debug!("sys_lgetxattr <= name: {}, size: {}", name, size);
+    getxattr(
+        xattr_path(path, false, PathAccess::READ)?,
+        name,
+        value,
+        size,
+    )
+}
+
+/// Like [`sys_getxattr`], for the file `fd` refers to.
+pub fn sys_fgetxattr(
+    fd: c_int,
+    name: UserConstPtr<c_char>,
+    value: UserPtr<u8>,
+    size: usize,
+) -> LinuxResult<isize> {
+    let name = xattr_name(name)?;
+    debug!(
+        "sys_fgetxattr <= fd: {}, name: {}, size: {}",
+        fd, name, size
+    );
+    getxattr(xattr_fd_path(fd)?, name, value, size)
+}
+
+/// Set the attribute `name` of the file at `path` to the `size` bytes at
+/// `value`.
+///
+/// `flags` may be `XATTR_CREATE` to fail if it exists, or `XATTR_REPLACE`
+/// to fail if it does not.
+pub fn sys_setxattr(
+    path: UserConstPtr<c_char>,
+    name: UserConstPtr<c_char>,
+    value: UserConstPtr<u8>,
+    size: usize,
+    flags: u32,
+) -> LinuxResult<isize> {
+    let name = xattr_name(name)?;
+    debug!(
+        "sys_setxattr <= name: {}, size: {}, flags: {:#x}",
+        name, size, flags
+    );
+    setxattr(
+        xattr_path(path, true, PathAccess::WRITE)?,
+        name,
+        value,
+        size,
+        flags,
+    )
+}
+
+/// Like [`sys_setxattr`], without following a link in the final component.
+pub fn sys_lsetxattr(
+    path: UserConstPtr<c_char>,
+    name: UserConstPtr<c_char>,
+    value: UserConstPtr<u8>,
+    size: usize,
+    flags: u32,
+) -> LinuxResult<isize> {
+    let name = xattr_name(name)?;
+    debug!(
+        "sys_lsetxattr <= name: {}, size: {}, flags: {:#x}",
+        name, size, flags
+    );
+    setxattr(
+        xattr_path(path, false, PathAccess::WRITE)?,
+        name,
+        value,
+        size,
+        flags,
+    )
+}
+
+/// Like [`sys_setxattr`], for the file `fd` refers to.
+pub fn sys_fsetxattr(
+    fd: c_int,
+    name: UserConstPtr<c_char>,
+    value: UserConstPtr<u8>,
+    size: usize,
+    flags: u32,
+) -> LinuxResult<isize> {
+    let name = xattr_name(name)?;
+    debug!(
+        "sys_fsetxattr <= fd: {}, name: {}, size: {}, flags: {:#x}",
+        fd, name, size, flags
+    );
+    setxattr(xattr_fd_path(fd)?, name, value, size, flags)
+}
+
+/// List the names of the attributes of the file at `path`, each followed by
+/// a NUL, or only return the length of the list if `size` is 0.
+pub fn sys_listxattr(
+    path: UserConstPtr<c_char>,
+    list: UserPtr<u8>,
+    size: usize,
+) -> LinuxResult<isize> {
+    debug!("sys_listxattr <= size: {}", size);
+    listxattr(xattr_path(path, true, PathAccess::READ)?, list, size)
+}
+
+/// Like [`sys_listxattr`], without following a link in the final component.
+pub fn sys_llistxattr(
+    path: UserConstPtr<c_char>,
+    list: UserPtr<u8>,
+    size: usize,
+) -> LinuxResult<isize> {
+    debug!("sys_llistxattr <= size: {}", size);
+    listxattr(xattr_path(path, false, PathAccess::READ)?, list, size)
+}
+
+/// Like [`sys_listxattr`], for the file `fd` refers to.
+pub fn sys_flistxattr(fd: c_int, list: UserPtr<u8>, size: usize) -> LinuxResult<isize> {
+    debug!("sys_flistxattr <= fd: {}, size: {}", fd, size);
+    listxattr(xattr_fd_path(fd)?, list, size)
+}
+
+/// Remove the attribute `name` of the file at `path`.
+pub fn sys_removexattr(
+    path: UserConstPtr<c_char>,
+    name: UserConstPtr<c_char>,
+) -> LinuxResult<isize> {
+    let name = xattr_name(name)?;
+    debug!("sys_removexattr <= name: {}", name);
+    removexattr(xattr_path(path, true, PathAccess::WRITE)?, name)
+}
+
+/// Like [`sys_removexattr`], without following a link in the final
+/// component.
+pub fn sys_lremovexattr(
+    path: UserConstPtr<c_char>,
+    name: UserConstPtr<c_char>,
+) -> LinuxResult<isize> {
+    let name = xattr_name(name)?;
+    debug!("sys_lremovexattr <= name: {}", name);
+    removexattr(xattr_path(path, false, PathAccess::WRITE)?, name)
+}
+
+/// Like [`sys_removexattr`], for the file `fd` refers to.
+pub fn sys_fremovexattr(fd: c_int, name: UserConstPtr<c_char>) -> LinuxResult<isize> {
+    let name = xattr_name(name)?;
+    debug!("sys_fremovexattr <= fd: {}, name: {}", fd, name);
+    removexattr(xattr_fd_path(fd)?, name)
+}
@@ -1,46 +1,90 @@
+use core::{
+    ffi::c_int,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
 use axerrno::{LinuxError, LinuxResult};
+use axhal::time::{monotonic_time, wall_time};
+use axprocess::Pid;
 use axtask::{TaskExtRef, current};
 use linux_raw_sys::general::{
-    FUTEX_CMD_MASK, FUTEX_CMP_REQUEUE, FUTEX_REQUEUE, FUTEX_WAIT, FUTEX_WAKE, timespec,
+    FUTEX_CLOCK_REALTIME, FUTEX_CMD_MASK, FUTEX_CMP_REQUEUE, FUTEX_LOCK_PI, FUTEX_LOCK_PI2,
+    FUTEX_PRIVATE_FLAG, FUTEX_REQUEUE, FUTEX_TRYLOCK_PI, FUTEX_UNLOCK_PI, FUTEX_WAIT, FUTEX_WAKE,
+    ROBUST_LIST_LIMIT, robust_list_head, timespec,
+};
+use starry_core::{
+    futex::{
+        FUTEX_OWNER_DIED, FUTEX_TABLE, FUTEX_TID_MASK, FUTEX_WAITERS, FutexKey, exit_pi_futexes,
+        lock_pi, unlock_pi,
+    },
+    task::{ThreadData, WaitMode, WaitResult, get_thread},
 };
 
 use crate::{
+    blocking::Blocking,
     ptr::{UserConstPtr, UserPtr, nullable},
     time::TimeValueLike,
 };
 
+/// The futex word at `addr`, for the kernel to change it atomically, as
+/// user space does.
+fn futex_word(addr: usize) -> LinuxResult<&'static AtomicU32> {
+    let word = UserPtr::<u32>::from(addr).get_as_mut()?;
+    // Safety: The word is mapped and aligned, as checked above.
+    Ok(unsafe { AtomicU32::from_ptr(word) })
+}
+
+/// Wake a thread waiting on the futex `key`, if any.
+fn wake_one(key: FutexKey) {
+    if let Some(futex) = FUTEX_TABLE.get(key) {
+        futex.notify_one(false);
+    }
+}
+
 pub fn sys_futex(
     uaddr: UserConstPtr<u32>,
     futex_op: u32,
     value: u32,
     timeout: UserConstPtr<timespec>,
-    uaddr2: UserPtr<u32>,
+    _uaddr2: UserPtr<u32>,
     value3: u32,
 ) -> LinuxResult<isize> {
     info!("futex {:?} {} {}", uaddr.address(), futex_op, value);
 
-    let curr = current();
-    let futex_table = &curr.task_ext().process_data().futex_table;
-
     let addr = uaddr.address().as_usize();
+    if addr % size_of::<u32>() != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    // Like Linux deriving the key of a shared futex, the word must be
+    // mapped, even to be woken. That of a private one is its address alone.
+    if futex_op & FUTEX_PRIVATE_FLAG == 0 {
+        uaddr.get_as_ref()?;
+    }
+    // Keyed by address space and address, never by frame, see
+    // `starry_core::futex`.
+    let key = current().task_ext().process_data().futex_key(addr);
+
     let command = futex_op & (FUTEX_CMD_MASK as u32);
     match command {
         FUTEX_WAIT => {
+            let timeout = nullable!(timeout.get_as_ref())?
+                .map(|ts| ts.try_to_time_value())
+                .transpose()?;
             if *uaddr.get_as_ref()? != value {
                 return Err(LinuxError::EAGAIN);
             }
-            let wq = futex_table.get_or_insert(addr);
+            let wq = FUTEX_TABLE.get_or_insert(key);
 
-            if let Some(timeout) = nullable!(timeout.get_as_ref())? {
-                wq.wait_timeout(timeout.to_time_value());
-            } else {
-                wq.wait();
+            // Interruptible, so that a handler runs and the caller decides
+            // whether to wait again.
+            match wq.futex_wait(WaitMode::Interruptible, timeout) {
+                WaitResult::Woken => Ok(0),
+                WaitResult::TimedOut => Err(LinuxError::ETIMEDOUT),
+                WaitResult::Interrupted => Err(LinuxError::EINTR),
             }
-
-            Ok(0)
         }
         FUTEX_WAKE => {
-            let wq = futex_table.get(addr);
+            let wq = FUTEX_TABLE.get(key);
             let mut count = 0;
             if let Some(wq) = wq {
                 for _ in 0..value {
@@ -59,23 +103,176 @@ pub fn sys_futex(
             }
             let value2 = timeout.address().as_usize() as u32;
 
-            let wq = futex_table.get(addr);
-            let wq2 = futex_table.get_or_insert(uaddr2.address().as_usize());
+            let wq = FUTEX_TABLE.get(key);
 
             let mut count = 0;
             if let Some(wq) = wq {
-                for _ in 0..value {
+                // A waiter only sees the notifications of the queue it
+                // started waiting on, so rather than moved to the queue of
+                // `uaddr2`, those past the first `value` are woken as well.
+                // Futex users take it as a spurious wakeup, and wait again
+                // on `uaddr2` themselves.
+                for _ in 0..value.saturating_add(value2) {
                     if !wq.notify_one(false) {
                         break;
                     }
                     count += 1;
                 }
-                if count == value as isize {
-                    count += wq.requeue(value2 as usize, &wq2) as isize;
-                }
             }
             Ok(count)
         }
+        FUTEX_LOCK_PI | FUTEX_LOCK_PI2 | FUTEX_TRYLOCK_PI => {
+            // The deadline is absolute, on the real time clock but for
+            // `FUTEX_LOCK_PI2`, which takes the monotonic one by default.
+            let deadline = nullable!(timeout.get_as_ref())?
+                .map(|ts| ts.try_to_time_value())
+                .transpose()?;
+            let realtime = command == FUTEX_LOCK_PI || futex_op & FUTEX_CLOCK_REALTIME != 0;
+            let word = futex_word(addr)?;
+            let tid = current().id().as_u64() as Pid;
+            lock_pi(key, word, tid, command == FUTEX_TRYLOCK_PI, |wq, handed| {
+                let timeout = match deadline {
+                    Some(deadline) => {
+                        let now = if realtime {
+                            wall_time()
+                        } else {
+                            monotonic_time()
+                        };
+                        if now >= deadline {
+                            return Ok(false);
+                        }
+                        Some(deadline - now)
+                    }
+                    None => None,
+                };
+                Blocking::FutexLockPi.wait_until(wq, timeout, handed)
+            })?;
+            Ok(0)
+        }
+        FUTEX_UNLOCK_PI => {
+            let word = futex_word(addr)?;
+            unlock_pi(key, word, current().id().as_u64() as Pid)?;
+            Ok(0)
+        }
         _ => Err(LinuxError::ENOSYS),
     }
 }
+
+/// Set the head of the robust futex list of the calling thread, see
+/// [`release_futexes`].
+pub fn sys_set_robust_list(head: UserConstPtr<robust_list_head>, len: usize) -> LinuxResult<isize> {
+    if len != size_of::<robust_list_head>() {
+        return Err(LinuxError::EINVAL);
+    }
+    current()
+        .task_ext()
+        .thread_data()
+        .set_robust_list(head.address().as_usize());
+    Ok(0)
+}
+
+/// Get the head of the robust futex list of the thread `tid`, or of the
+/// calling one if 0. Every process runs as root, so may read that of any
+/// thread.
+pub fn sys_get_robust_list(
+    tid: c_int,
+    head: UserPtr<usize>,
+    len: UserPtr<usize>,
+) -> LinuxResult<isize> {
+    let list = if tid == 0 {
+        current().task_ext().thread_data().robust_list()
+    } else {
+        let thread = get_thread(Pid::try_from(tid).map_err(|_| LinuxError::ESRCH)?)?;
+        thread
+            .data::<ThreadData>()
+            .ok_or(LinuxError::ESRCH)?
+            .robust_list()
+    };
+    *head.get_as_mut()? = list;
+    *len.get_as_mut()? = size_of::<robust_list_head>();
+    Ok(0)
+}
+
+/// Release the futexes the current thread holds, as it exits or runs
+/// `execve`, like Linux does.
+///
+/// Those on its robust list are marked with [`FUTEX_OWNER_DIED`], for the
+/// next owner to tell the state they protect may be inconsistent, and a
+/// waiter of each is woken. Then the PI futexes others wait for are handed
+/// to them, with the mark if the robust list left it.
+pub fn release_futexes() {
+    let curr = current();
+    let tid = curr.id().as_u64() as Pid;
+    let thread_data = curr.task_ext().thread_data();
+    let head = thread_data.robust_list();
+    thread_data.set_robust_list(0);
+    if head != 0 {
+        // A list user space broke is walked as far as it goes.
+        let _ = exit_robust_list(head, tid);
+    }
+    exit_pi_futexes(&curr.task_ext().process_data().aspace, tid, |addr| {
+        futex_word(addr).ok()
+    });
+}
+
+/// Split a pointer of a robust list into the address of the entry, and
+/// whether it is for a PI futex, which is told by the low bit.
+fn robust_entry(ptr: usize) -> (usize, bool) {
+    (ptr & !1, ptr & 1 != 0)
+}
+
+/// Mark the futexes on the robust list at `head` which the thread `tid`
+/// owns as their owner died, at most `ROBUST_LIST_LIMIT` of them.
+fn exit_robust_list(head: usize, tid: Pid) -> LinuxResult {
+    let list = UserConstPtr::<robust_list_head>::from(head).get_as_ref()?;
+    let offset = list.futex_offset as isize;
+    let (mut entry, mut pi) = robust_entry(list.list.next as usize);
+    let (pending, pending_pi) = robust_entry(list.list_op_pending as usize);
+    for _ in 0..ROBUST_LIST_LIMIT {
+        if entry == head {
+            break;
+        }
+        // Read before the futex is released, after which it may be freed.
+        let next = *UserConstPtr::<usize>::from(entry).get_as_ref()?;
+        // The one being taken or released, if any, is done last.
+        if entry != pending {
+            owner_died(entry.wrapping_add_signed(offset), tid, pi, false);
+        }
+        (entry, pi) = robust_entry(next);
+    }
+    if pending != 0 {
+        owner_died(pending.wrapping_add_signed(offset), tid, pending_pi, true);
+    }
+    Ok(())
+}
+
+/// Mark the futex at `addr` as its owner died, if the thread `tid` owns it,
+/// waking a waiter of it, unless it is a PI one, which is handed over by
+/// [`exit_pi_futexes`] instead.
+///
+/// A futex the thread was taking, as `pending`, and which is free, wakes a
+/// waiter too, which it may have been woken for in its place.
+fn owner_died(addr: usize, tid: Pid, pi: bool, pending: bool) {
+    let Ok(word) = futex_word(addr) else {
+        return;
+    };
+    let key = current().task_ext().process_data().futex_key(addr);
+    let mut val = word.load(Ordering::Acquire);
+    loop {
+        if pending && !pi && val == 0 {
+            wake_one(key);
+            return;
+        }
+        if val & FUTEX_TID_MASK != tid {
+            return;
+        }
+        let new = (val & FUTEX_WAITERS) | FUTEX_OWNER_DIED;
+        match word.compare_exchange(val, new, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => break,
+            Err(now) => val = now,
+        }
+    }
+    if !pi && val & FUTEX_WAITERS != 0 {
+        wake_one(key);
+    }
+}
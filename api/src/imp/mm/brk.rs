@@ -1,14 +1,42 @@
 use axerrno::LinuxResult;
+use axhal::paging::MappingFlags;
+use axmm::AreaKind;
 use axtask::{TaskExtRef, current};
+use memory_addr::{VirtAddr, align_up};
+use starry_core::mm::{PAGE_SIZE, heap_range};
 
+/// Move the program break to `addr`, mapping or unmapping the heap pages
+/// in between.
+///
+/// The break only moves once the pages are mapped, and stays where it was
+/// if `addr` is outside of the heap or the pages cannot be mapped, e.g.
+/// because a fixed mapping is in the way. Either way the current break is
+/// returned, as the raw syscall does.
 pub fn sys_brk(addr: usize) -> LinuxResult<isize> {
     let task = current();
     let process_data = task.task_ext().process_data();
-    let mut return_val: isize = process_data.get_heap_top() as isize;
-    let heap_bottom = process_data.get_heap_bottom() as usize;
-    if addr != 0 && addr >= heap_bottom && addr <= heap_bottom + axconfig::plat::USER_HEAP_SIZE {
-        process_data.set_heap_top(addr);
-        return_val = addr as isize;
+    let top = process_data.heap.top();
+    let heap = heap_range();
+    if addr == 0 || addr < process_data.heap.bottom() || addr > heap.end.as_usize() {
+        return Ok(top as isize);
     }
-    Ok(return_val)
+
+    let (old_end, new_end) = (align_up(top, PAGE_SIZE), align_up(addr, PAGE_SIZE));
+    let mut aspace = process_data.lock_aspace();
+    let mapped = if new_end > old_end {
+        aspace.map_alloc(
+            VirtAddr::from(old_end),
+            new_end - old_end,
+            MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
+            false,
+            AreaKind::Heap,
+        )
+    } else {
+        aspace.unmap(VirtAddr::from(new_end), old_end - new_end)
+    };
+    if mapped.is_err() {
+        return Ok(top as isize);
+    }
+    process_data.heap.set_top(addr);
+    Ok(addr as isize)
 }
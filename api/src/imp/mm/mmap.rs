@@ -1,14 +1,25 @@
-use alloc::vec;
+use alloc::{vec, vec::Vec};
 use axerrno::{LinuxError, LinuxResult};
 use axhal::paging::MappingFlags;
+use axmm::{AddrSpace, AreaKind};
 use axtask::{TaskExtRef, current};
 use linux_raw_sys::general::{
-    MAP_ANONYMOUS, MAP_FIXED, MAP_NORESERVE, MAP_PRIVATE, MAP_SHARED, MAP_STACK, PROT_EXEC,
-    PROT_GROWSDOWN, PROT_GROWSUP, PROT_READ, PROT_WRITE,
+    MAP_ANONYMOUS, MAP_FIXED, MAP_GROWSDOWN, MAP_LOCKED, MAP_NORESERVE, MAP_POPULATE, MAP_PRIVATE,
+    MAP_SHARED, MAP_SHARED_VALIDATE, MAP_STACK, MAP_TYPE, MS_ASYNC, MS_INVALIDATE, MS_SYNC,
+    PROT_EXEC, PROT_GROWSDOWN, PROT_GROWSUP, PROT_READ, PROT_WRITE,
+};
+use memory_addr::{PageIter4K, VirtAddr, VirtAddrRange, align_down, is_aligned};
+use starry_core::{
+    cred::CAP_IPC_LOCK,
+    mm::{GrowsDownAreas, PAGE_SIZE, USER_SPACE_END},
+    resources::RLIMIT_MEMLOCK,
 };
-use memory_addr::{VirtAddr, VirtAddrRange};
 
-use crate::file::{File, FileLike};
+use crate::{
+    file::{File, FileLike},
+    ptr::{UserPtr, check_user_region},
+    require_capability,
+};
 
 bitflags::bitflags! {
     /// `PROT_*` flags for use with [`sys_mmap`].
@@ -29,6 +40,15 @@ bitflags::bitflags! {
     }
 }
 
+impl MmapProt {
+    /// Whether the pages can be accessed at all, unlike with `PROT_NONE`.
+    fn is_accessible(&self) -> bool {
+        self.intersects(Self::READ | Self::WRITE | Self::EXEC)
+    }
+}
+
+/// `PROT_NONE` is only [`MappingFlags::USER`], an area in which any access
+/// faults.
 impl From<MmapProt> for MappingFlags {
     fn from(value: MmapProt) -> Self {
         let mut flags = MappingFlags::USER;
@@ -63,9 +83,50 @@ bitflags::bitflags! {
         const NORESERVE = MAP_NORESERVE;
         /// Allocation is for a stack.
         const STACK = MAP_STACK;
+        /// The mapping grows down on faults below it, like a stack.
+        const GROWSDOWN = MAP_GROWSDOWN;
+        /// Allocate the pages of the mapping now, instead of on faults.
+        const POPULATE = MAP_POPULATE;
+        /// Lock the pages of the mapping in memory, like `mlock`.
+        const LOCKED = MAP_LOCKED;
+    }
+}
+
+/// Whether the current process may lock `length` more bytes in memory, up
+/// to `RLIMIT_MEMLOCK` unless it has `CAP_IPC_LOCK`.
+fn may_lock(length: usize) -> bool {
+    if require_capability(CAP_IPC_LOCK).is_ok() {
+        return true;
+    }
+    let curr = current();
+    let limit = curr
+        .task_ext()
+        .process_data()
+        .rlimits
+        .read()
+        .get(RLIMIT_MEMLOCK);
+    limit.is_some_and(|it| length as u64 <= it.cur)
+}
+
+/// Round `length` up to whole pages, failing with `err` if the `length`
+/// bytes at `addr` wrap around or end above the user address space.
+fn page_length(addr: usize, length: usize, err: LinuxError) -> LinuxResult<usize> {
+    let length = length.checked_next_multiple_of(PAGE_SIZE).ok_or(err)?;
+    let end = addr.checked_add(length).ok_or(err)?;
+    if end > USER_SPACE_END {
+        return Err(err);
     }
+    Ok(length)
 }
 
+/// Map `length` bytes of the file `fd` at `offset`, or of anonymous memory,
+/// at `addr` or near it.
+///
+/// Anonymous pages are allocated on the first fault, unless `MAP_POPULATE`
+/// or `MAP_LOCKED` is set. Nothing is ever swapped out, so a locked mapping
+/// only needs its pages allocated now. Like Linux, a mapping which cannot be
+/// locked, above `RLIMIT_MEMLOCK` without `CAP_IPC_LOCK`, is mapped anyway,
+/// just not populated.
 pub fn sys_mmap(
     addr: usize,
     length: usize,
@@ -74,104 +135,372 @@ pub fn sys_mmap(
     fd: i32,
     offset: isize,
 ) -> LinuxResult<isize> {
-    let curr = current();
-    let process_data = curr.task_ext().process_data();
-    let mut aspace = process_data.aspace.lock();
     let permission_flags = MmapProt::from_bits_truncate(prot);
-    // TODO: check illegal flags for mmap
-    // An example is the flags contained none of MAP_PRIVATE, MAP_SHARED, or MAP_SHARED_VALIDATE.
+    // Unknown flags are ignored, unless the type is `MAP_SHARED_VALIDATE`.
+    match flags & MAP_TYPE {
+        MAP_SHARED | MAP_PRIVATE => {}
+        MAP_SHARED_VALIDATE => {
+            if MmapFlags::from_bits(flags & !MAP_TYPE).is_none() {
+                return Err(LinuxError::EOPNOTSUPP);
+            }
+        }
+        _ => return Err(LinuxError::EINVAL),
+    }
     let map_flags = MmapFlags::from_bits_truncate(flags);
 
     info!(
         "sys_mmap: addr: {:x?}, length: {:x?}, prot: {:?}, flags: {:?}, fd: {:?}, offset: {:?}",
         addr, length, permission_flags, map_flags, fd, offset
     );
+    if length == 0 || offset < 0 || !is_aligned(offset as usize, PAGE_SIZE) {
+        return Err(LinuxError::EINVAL);
+    }
+    let fixed = map_flags.contains(MmapFlags::FIXED);
+    if fixed && !is_aligned(addr, PAGE_SIZE) {
+        return Err(LinuxError::EINVAL);
+    }
+    // Like below `mmap_min_addr` on Linux.
+    if fixed && addr < axconfig::plat::USER_SPACE_BASE {
+        return Err(LinuxError::EPERM);
+    }
+    // A fixed mapping must fit where it is asked for, below the end of the
+    // user address space, while a hint which does not is ignored, and one
+    // below its base taken as its base.
+    let aligned_length = page_length(0, length, LinuxError::ENOMEM)?;
+    let start = if fixed {
+        page_length(addr, length, LinuxError::ENOMEM)?;
+        addr
+    } else if page_length(addr, length, LinuxError::ENOMEM).is_ok() {
+        align_down(addr, PAGE_SIZE)
+    } else {
+        0
+    };
 
-    let start = memory_addr::align_down_4k(addr);
-    let end = memory_addr::align_up_4k(addr + length);
-    let aligned_length = end - start;
-    debug!(
-        "start: {:x?}, end: {:x?}, aligned_length: {:x?}",
-        start, end, aligned_length
-    );
+    // The fd of an anonymous mapping is ignored, whatever it is, and only
+    // looked up for others, where `-1` fails with `EBADF` as on Linux.
+    let file_backed = !map_flags.contains(MmapFlags::ANONYMOUS);
 
-    let start_addr = if map_flags.contains(MmapFlags::FIXED) {
-        if start == 0 {
-            return Err(LinuxError::EINVAL);
-        }
+    // The regions of an io_uring get their initial content instead of a
+    // file's.
+    #[cfg(feature = "io_uring")]
+    let io_uring = match crate::file::IoUring::from_fd(fd) {
+        Ok(ring) if file_backed => Some((ring.region_content(offset as _, length)?, ring)),
+        _ => None,
+    };
+
+    // Look the file up before anything is mapped. Only regular files can
+    // be mapped, others fail with `ENODEV` like Linux.
+    #[cfg(feature = "io_uring")]
+    let file_backed = file_backed && io_uring.is_none();
+    let file = if file_backed {
+        Some(File::from_fd_or(fd, LinuxError::ENODEV)?)
+    } else {
+        None
+    };
+    // File content is read in at once, so its pages are needed now anyway.
+    // A `PROT_NONE` anonymous mapping only reserves the addresses, and
+    // allocates nothing until made accessible and touched.
+    let populate = file_backed
+        || (permission_flags.is_accessible()
+            && (map_flags.contains(MmapFlags::POPULATE)
+                || (map_flags.contains(MmapFlags::LOCKED) && may_lock(aligned_length))));
+
+    // The kind names the mapping in `/proc/<pid>/maps`. `MAP_STACK` is only
+    // a hint, which tags private anonymous memory as a thread stack. Such a
+    // stack is never grown, nor kept a guard gap below, unless it is also
+    // mapped with `MAP_GROWSDOWN`.
+    let kind = match &file {
+        Some(file) => AreaKind::File(file.path().into()),
+        None if flags & MAP_TYPE != MAP_PRIVATE => AreaKind::Shared,
+        None if map_flags.contains(MmapFlags::STACK) => AreaKind::ThreadStack,
+        None => AreaKind::Anonymous,
+    };
+
+    debug!("start: {:x?}, aligned_length: {:x?}", start, aligned_length);
+
+    let curr = current();
+    let process_data = curr.task_ext().process_data();
+    let mut aspace = process_data.lock_aspace();
+    let mut grows_down = process_data.lock_grows_down();
+    let start_addr = if fixed {
         let dst_addr = VirtAddr::from(start);
         aspace.unmap(dst_addr, aligned_length)?;
+        grows_down.unmap(dst_addr, aligned_length);
         dst_addr
     } else {
-        aspace
-            .find_free_area(
-                VirtAddr::from(start),
-                aligned_length,
-                VirtAddrRange::new(aspace.base(), aspace.end()),
-            )
-            .or(aspace.find_free_area(
-                aspace.base(),
-                aligned_length,
-                VirtAddrRange::new(aspace.base(), aspace.end()),
-            ))
+        grows_down
+            .find_free_area(&aspace, VirtAddr::from(start), aligned_length)
+            .or(grows_down.find_free_area(&aspace, aspace.base(), aligned_length))
             .ok_or(LinuxError::ENOMEM)?
     };
 
-    let populate = if fd == -1 {
-        false
-    } else {
-        !map_flags.contains(MmapFlags::ANONYMOUS)
-    };
+    #[cfg(feature = "io_uring")]
+    if let Some((content, ring)) = io_uring {
+        aspace.map_alloc(
+            start_addr,
+            aligned_length,
+            permission_flags.into(),
+            true,
+            AreaKind::Shared,
+        )?;
+        aspace.write(start_addr, &content)?;
+        ring.set_region_addr(offset as _, start_addr);
+        return Ok(start_addr.as_usize() as _);
+    }
 
+    // Running out of frames fails with `ENOMEM`.
     aspace.map_alloc(
         start_addr,
         aligned_length,
         permission_flags.into(),
         populate,
+        kind.clone(),
     )?;
+    if map_flags.contains(MmapFlags::GROWSDOWN) {
+        grows_down.insert(start_addr, aligned_length, permission_flags.into(), kind);
+    }
 
-    if populate {
-        let file = File::from_fd(fd)?;
-        let file = file.inner();
-        let file_size = file.get_attr()?.size() as usize;
-        if offset < 0 || offset as usize >= file_size {
-            return Err(LinuxError::EINVAL);
+    if let Some(file) = file {
+        // Nothing is left mapped if the file cannot be read, e.g. as it was
+        // removed meanwhile.
+        if let Err(err) = fill_from_file(&aspace, start_addr, &file, offset as usize, length) {
+            aspace.unmap(start_addr, aligned_length)?;
+            grows_down.unmap(start_addr, aligned_length);
+            return Err(err);
         }
-        let offset = offset as usize;
-        let length = core::cmp::min(length, file_size - offset);
-        let mut buf = vec![0u8; length];
-        file.read_at(offset as u64, &mut buf)?;
-        aspace.write(start_addr, &buf)?;
     }
     Ok(start_addr.as_usize() as _)
 }
 
+/// Copy `length` bytes of `file` from `offset`, or up to its end, to the
+/// mapping at `start`.
+///
+/// The file is read at `offset` without moving its position, which a
+/// program may be reading the file from through the same descriptor.
+fn fill_from_file(
+    aspace: &AddrSpace,
+    start: VirtAddr,
+    file: &File,
+    offset: usize,
+    length: usize,
+) -> LinuxResult {
+    let file = file.inner();
+    let file_size = file.get_attr()?.size() as usize;
+    if offset >= file_size {
+        return Err(LinuxError::EINVAL);
+    }
+    let length = core::cmp::min(length, file_size - offset);
+    let mut buf = vec![0u8; length];
+    file.read_full_at(offset as u64, &mut buf)?;
+    aspace.write(start, &buf)?;
+    Ok(())
+}
+
+/// Check that the `length` bytes at `addr`, rounded to whole pages, are
+/// mapped, as `msync`, `mincore` and `mlock` need, failing with `ENOMEM`
+/// otherwise.
+///
+/// The range is checked by [`check_user_region`], like a syscall buffer. A
+/// `PROT_NONE` mapping in it thus fails as a hole does, unlike in Linux, and
+/// one just below a grows-down mapping grows it.
+fn check_mapped(
+    aspace: &mut AddrSpace,
+    grows_down: &mut GrowsDownAreas,
+    addr: usize,
+    length: usize,
+) -> LinuxResult<VirtAddrRange> {
+    let length = page_length(addr, length, LinuxError::ENOMEM)?;
+    let range = VirtAddrRange::from_start_size(addr.into(), length);
+    check_user_region(aspace, grows_down, range, MappingFlags::empty())
+        .map_err(|_| LinuxError::ENOMEM)?;
+    Ok(range)
+}
+
+/// Write back the `length` bytes of mappings at `addr`.
+///
+/// File mappings are copies of the file, never written back, so there is
+/// nothing to do once the range and `flags` are checked.
+pub fn sys_msync(addr: usize, length: usize, flags: u32) -> LinuxResult<isize> {
+    if flags & !(MS_ASYNC | MS_INVALIDATE | MS_SYNC) != 0
+        || flags & (MS_ASYNC | MS_SYNC) == MS_ASYNC | MS_SYNC
+        || !is_aligned(addr, PAGE_SIZE)
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    let curr = current();
+    let process_data = curr.task_ext().process_data();
+    check_mapped(
+        &mut process_data.lock_aspace(),
+        &mut process_data.lock_grows_down(),
+        addr,
+        length,
+    )?;
+    Ok(0)
+}
+
+/// Report in `vec` which pages of the `length` bytes at `addr` are
+/// resident, one byte per page, with the lowest bit set if it is.
+///
+/// A page is resident once allocated, as nothing is ever swapped out.
+pub fn sys_mincore(addr: usize, length: usize, vec: UserPtr<u8>) -> LinuxResult<isize> {
+    if !is_aligned(addr, PAGE_SIZE) {
+        return Err(LinuxError::EINVAL);
+    }
+    let resident: Vec<u8> = {
+        let curr = current();
+        let process_data = curr.task_ext().process_data();
+        let mut aspace = process_data.lock_aspace();
+        let range = check_mapped(
+            &mut aspace,
+            &mut process_data.lock_grows_down(),
+            addr,
+            length,
+        )?;
+        (range.start.as_usize()..range.end.as_usize())
+            .step_by(PAGE_SIZE)
+            .map(|page| aspace.page_table().query(page.into()).is_ok() as u8)
+            .collect()
+    };
+    // Written once the address space is unlocked, as checking `vec` locks it.
+    vec.get_as_mut_slice(resident.len())?
+        .copy_from_slice(&resident);
+    Ok(0)
+}
+
+/// Lock the pages of the `length` bytes at `addr` in memory.
+///
+/// Nothing is ever swapped out, so the pages only need to be allocated now,
+/// within `RLIMIT_MEMLOCK` unless `CAP_IPC_LOCK` is effective.
+pub fn sys_mlock(addr: usize, length: usize) -> LinuxResult<isize> {
+    let start = align_down(addr, PAGE_SIZE);
+    let length = length.checked_add(addr - start).ok_or(LinuxError::ENOMEM)?;
+    let curr = current();
+    let process_data = curr.task_ext().process_data();
+    let mut aspace = process_data.lock_aspace();
+    let range = check_mapped(
+        &mut aspace,
+        &mut process_data.lock_grows_down(),
+        start,
+        length,
+    )?;
+    if !may_lock(range.size()) {
+        return Err(LinuxError::ENOMEM);
+    }
+    aspace.populate_area(range.start, range.size())?;
+    Ok(0)
+}
+
+/// Unlock the pages of the `length` bytes at `addr`, which stay allocated.
+pub fn sys_munlock(addr: usize, length: usize) -> LinuxResult<isize> {
+    let start = align_down(addr, PAGE_SIZE);
+    let length = length.checked_add(addr - start).ok_or(LinuxError::ENOMEM)?;
+    let curr = current();
+    let process_data = curr.task_ext().process_data();
+    check_mapped(
+        &mut process_data.lock_aspace(),
+        &mut process_data.lock_grows_down(),
+        start,
+        length,
+    )?;
+    Ok(0)
+}
+
 pub fn sys_munmap(addr: usize, length: usize) -> LinuxResult<isize> {
     let curr = current();
     let process_data = curr.task_ext().process_data();
-    let mut aspace = process_data.aspace.lock();
-    let length = memory_addr::align_up_4k(length);
+    if length == 0 || !is_aligned(addr, PAGE_SIZE) {
+        return Err(LinuxError::EINVAL);
+    }
+    let length = page_length(addr, length, LinuxError::EINVAL)?;
+    let mut aspace = process_data.lock_aspace();
     let start_addr = VirtAddr::from(addr);
     aspace.unmap(start_addr, length)?;
+    process_data.lock_grows_down().unmap(start_addr, length);
     axhal::arch::flush_tlb(None);
     Ok(0)
 }
 
 pub fn sys_mprotect(addr: usize, length: usize, prot: u32) -> LinuxResult<isize> {
-    // TODO: implement PROT_GROWSUP & PROT_GROWSDOWN
     let Some(permission_flags) = MmapProt::from_bits(prot) else {
         return Err(LinuxError::EINVAL);
     };
-    if permission_flags.contains(MmapProt::GROWDOWN | MmapProt::GROWSUP) {
+    if permission_flags.contains(MmapProt::GROWDOWN | MmapProt::GROWSUP)
+        || !is_aligned(addr, PAGE_SIZE)
+    {
         return Err(LinuxError::EINVAL);
     }
+    if length == 0 {
+        return Ok(0);
+    }
+    let mut length = page_length(addr, length, LinuxError::ENOMEM)?;
 
     let curr = current();
     let process_data = curr.task_ext().process_data();
-    let mut aspace = process_data.aspace.lock();
-    let length = memory_addr::align_up_4k(length);
-    let start_addr = VirtAddr::from(addr);
-    aspace.protect(start_addr, length, permission_flags.into())?;
+    let mut aspace = process_data.lock_aspace();
+    let mut grows_down = process_data.lock_grows_down();
+    let mut start_addr = VirtAddr::from(addr);
+    if permission_flags.contains(MmapProt::GROWDOWN) {
+        // Extend the change down to the start of the grows-down mapping.
+        let start = grows_down.start_of(start_addr).ok_or(LinuxError::EINVAL)?;
+        length += start_addr - start;
+        start_addr = start;
+    } else if permission_flags.contains(MmapProt::GROWSUP) {
+        // No mapping grows up.
+        return Err(LinuxError::EINVAL);
+    }
+    protect(&mut aspace, start_addr, length, permission_flags.into())?;
+    grows_down.protect(start_addr, length, permission_flags.into());
 
     Ok(0)
 }
+
+/// Change the permissions of the `size` bytes at `start` to `flags`.
+///
+/// [`AddrSpace::protect`] populates the range first, which allocates every
+/// page of it, and fails for those of a `PROT_NONE` mapping. A range with
+/// no page allocated yet, e.g. reserved with `PROT_NONE` and now made
+/// accessible, is mapped afresh with `flags` instead, so that its pages are
+/// still allocated on the first fault. Each area in the range keeps its kind.
+///
+/// The signal trampoline, the only linear area, maps kernel text shared by
+/// all processes, so it fails with `EACCES` to be made writable.
+///
+/// Splitting areas past `vm.max_map_count` fails with `ENOMEM`.
+fn protect(
+    aspace: &mut AddrSpace,
+    start: VirtAddr,
+    size: usize,
+    flags: MappingFlags,
+) -> LinuxResult {
+    let range = VirtAddrRange::from_start_size(start, size);
+    if !aspace.check_region_access(range, MappingFlags::empty()) {
+        return Err(LinuxError::ENOMEM);
+    }
+    let overlaps = |area: &VirtAddrRange| area.start < range.end && area.end > range.start;
+    if flags.contains(MappingFlags::WRITE)
+        && aspace
+            .areas()
+            .any(|(area, _, kind)| kind == AreaKind::Linear && overlaps(&area))
+    {
+        return Err(LinuxError::EACCES);
+    }
+    let untouched = PageIter4K::new(range.start, range.end)
+        .unwrap()
+        .all(|page| aspace.page_table().query(page).is_err());
+    if untouched {
+        // Checked first, for a failure not to leave the range unmapped.
+        aspace.check_protect(start, size, flags)?;
+        let parts: Vec<_> = aspace
+            .areas()
+            .filter(|(area, ..)| overlaps(area))
+            .map(|(area, _, kind)| (area.start.max(range.start), area.end.min(range.end), kind))
+            .collect();
+        aspace.unmap(start, size)?;
+        for (start, end, kind) in parts {
+            aspace.map_alloc(start, end - start, flags, false, kind)?;
+        }
+    } else {
+        aspace.protect(start, size, flags)?;
+    }
+    Ok(())
+}
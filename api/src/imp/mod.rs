@@ -1,9 +1,17 @@
+mod cred;
 mod fs;
 mod futex;
 mod mm;
+mod mqueue;
+mod net;
+mod poll;
+mod resources;
 mod signal;
 mod sys;
 mod task;
 mod time;
 
-pub use self::{fs::*, futex::*, mm::*, signal::*, sys::*, task::*, time::*};
+pub use self::{
+    cred::*, fs::*, futex::*, mm::*, mqueue::*, net::*, poll::*, resources::*, signal::*, sys::*,
+    task::*, time::*,
+};
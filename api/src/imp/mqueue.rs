@@ -0,0 +1,146 @@
+use core::ffi::{c_char, c_int, c_long, c_void};
+
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::{
+    O_ACCMODE, O_CREAT, O_EXCL, O_NONBLOCK, O_RDONLY, O_RDWR, O_WRONLY, timespec,
+};
+use starry_core::resources::RLIMIT_MSGQUEUE;
+
+use crate::{
+    file::{FdFlags, FileLike, MQ_PRIO_MAX, MessageQueue, MqAttr, MqFd, install_fd},
+    ptr::{UserConstPtr, UserPtr, nullable},
+    time::TimeValueLike,
+};
+
+/// Open the message queue `name`, creating it with `mode` and the limits in
+/// `attr` if `O_CREAT` is set, within `RLIMIT_MSGQUEUE`.
+///
+/// Like on Linux, the descriptor is always closed on `execve`.
+pub fn sys_mq_open(
+    name: UserConstPtr<c_char>,
+    oflag: u32,
+    mode: u32,
+    attr: UserConstPtr<MqAttr>,
+) -> LinuxResult<isize> {
+    let name = name.get_as_str()?;
+    debug!(
+        "sys_mq_open <= name: {:?}, oflag: {:#o}, mode: {:#o}",
+        name, oflag, mode
+    );
+    let (flags, oflag) = FdFlags::parse(oflag, u32::MAX)?;
+    let (readable, writable) = match oflag & O_ACCMODE {
+        O_RDONLY => (true, false),
+        O_WRONLY => (false, true),
+        O_RDWR => (true, true),
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let create = oflag & O_CREAT != 0;
+    let attr = if create {
+        nullable!(attr.get_as_ref())?
+    } else {
+        None
+    };
+    let bytes_limit = current()
+        .task_ext()
+        .process_data()
+        .rlimits
+        .read()
+        .get(RLIMIT_MSGQUEUE)
+        .map_or(0, |it| it.cur);
+    let queue = MessageQueue::open(name, create, oflag & O_EXCL != 0, mode, attr, bytes_limit)?;
+    let mq = MqFd::new(queue, readable, writable);
+    Ok(install_fd(Arc::new(mq), flags | FdFlags::CLOEXEC)? as _)
+}
+
+/// Remove the message queue `name`. Descriptors of it stay usable until
+/// closed.
+pub fn sys_mq_unlink(name: UserConstPtr<c_char>) -> LinuxResult<isize> {
+    let name = name.get_as_str()?;
+    debug!("sys_mq_unlink <= name: {:?}", name);
+    MessageQueue::unlink(name)?;
+    Ok(0)
+}
+
+pub fn sys_mq_timedsend(
+    mqdes: c_int,
+    msg: UserConstPtr<u8>,
+    msg_len: usize,
+    msg_prio: u32,
+    abs_timeout: UserConstPtr<timespec>,
+) -> LinuxResult<isize> {
+    debug!(
+        "sys_mq_timedsend <= mqdes: {}, msg_len: {}, msg_prio: {}",
+        mqdes, msg_len, msg_prio
+    );
+    let mq = MqFd::from_fd(mqdes).map_err(|_| LinuxError::EBADF)?;
+    if msg_prio >= MQ_PRIO_MAX {
+        return Err(LinuxError::EINVAL);
+    }
+    let deadline = nullable!(abs_timeout.get_as_ref())?
+        .map(|ts| ts.try_to_time_value())
+        .transpose()?;
+    let msg = msg.get_as_slice(msg_len)?;
+    mq.send(msg, msg_prio, deadline)?;
+    Ok(0)
+}
+
+/// Receive the oldest message of the highest priority.
+///
+/// Returns the length of the message, and stores its priority in
+/// `msg_prio` if not null.
+pub fn sys_mq_timedreceive(
+    mqdes: c_int,
+    msg: UserPtr<u8>,
+    msg_len: usize,
+    msg_prio: UserPtr<u32>,
+    abs_timeout: UserConstPtr<timespec>,
+) -> LinuxResult<isize> {
+    debug!(
+        "sys_mq_timedreceive <= mqdes: {}, msg_len: {}",
+        mqdes, msg_len
+    );
+    let mq = MqFd::from_fd(mqdes).map_err(|_| LinuxError::EBADF)?;
+    let deadline = nullable!(abs_timeout.get_as_ref())?
+        .map(|ts| ts.try_to_time_value())
+        .transpose()?;
+    let buf = msg.get_as_mut_slice(msg_len)?;
+    let msg_prio = nullable!(msg_prio.get_as_mut())?;
+    let (len, prio) = mq.receive(buf, deadline)?;
+    if let Some(msg_prio) = msg_prio {
+        *msg_prio = prio;
+    }
+    Ok(len as _)
+}
+
+/// Not supported yet.
+pub fn sys_mq_notify(mqdes: c_int, _sevp: UserConstPtr<c_void>) -> LinuxResult<isize> {
+    debug!("sys_mq_notify <= mqdes: {}", mqdes);
+    Err(LinuxError::ENOSYS)
+}
+
+/// Get the attributes of a queue into `oldattr`, and set the `O_NONBLOCK`
+/// flag of the descriptor from `newattr`, which is the only one that can be
+/// changed.
+pub fn sys_mq_getsetattr(
+    mqdes: c_int,
+    newattr: UserConstPtr<MqAttr>,
+    oldattr: UserPtr<MqAttr>,
+) -> LinuxResult<isize> {
+    debug!("sys_mq_getsetattr <= mqdes: {}", mqdes);
+    let mq = MqFd::from_fd(mqdes).map_err(|_| LinuxError::EBADF)?;
+    let newattr = nullable!(newattr.get_as_ref())?;
+    if let Some(newattr) = newattr {
+        if newattr.mq_flags & !(O_NONBLOCK as c_long) != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+    }
+    if let Some(oldattr) = nullable!(oldattr.get_as_mut())? {
+        *oldattr = mq.attr();
+    }
+    if let Some(newattr) = newattr {
+        mq.set_nonblocking(newattr.mq_flags & O_NONBLOCK as c_long != 0)?;
+    }
+    Ok(0)
+}
@@ -0,0 +1,691 @@
+use core::{ffi::c_int, net::SocketAddr, time::Duration};
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use axerrno::{LinuxError, LinuxResult};
+use axnet::{TcpSocket, UdpSocket};
+use linux_raw_sys::{
+    general::iovec,
+    net::{
+        AF_INET, AF_INET6, AF_UNIX, IPPROTO_TCP, IPPROTO_UDP, MSG_CMSG_CLOEXEC, MSG_CTRUNC,
+        SCM_CREDENTIALS, SCM_RIGHTS, SO_ERROR, SO_LINGER, SO_PASSCRED, SO_PEERCRED, SO_TYPE,
+        SOCK_DGRAM, SOCK_STREAM, SOL_SOCKET, SOL_TCP, TCP_NODELAY, cmsghdr, linger, msghdr,
+        sockaddr, sockaddr_in6, socklen_t,
+    },
+};
+use starry_core::cred::{CAP_SETGID, CAP_SETUID, CAP_SYS_ADMIN};
+
+use crate::{
+    file::{
+        FdFlags, FileLike, Socket, UCred, UnixStream, close_file_like, get_file_like, install_fd,
+    },
+    ptr::{UserConstPtr, UserPtr},
+    require_capability,
+    sockaddr::SockAddr,
+};
+
+/// The bits of a socket type which are the type itself, the rest being
+/// `SOCK_CLOEXEC` and `SOCK_NONBLOCK`.
+const SOCK_TYPE_MASK: u32 = 0xf;
+
+/// The most iovecs a message may have, `UIO_MAXIOV` of Linux.
+const UIO_MAXIOV: usize = 1024;
+
+/// The most bytes a `recvmsg` receives at once.
+const RECVMSG_MAX: usize = 65536;
+
+/// `CMSG_ALIGN` of Linux: control messages are aligned to `usize`.
+const fn cmsg_align(len: usize) -> usize {
+    len.next_multiple_of(size_of::<usize>())
+}
+
+/// The length of the header of a control message, before its data.
+const CMSG_HDR_LEN: usize = cmsg_align(size_of::<cmsghdr>());
+
+/// `SCM_MAX_FD` of Linux, the most files a message may pass.
+const SCM_MAX_FD: usize = 253;
+
+/// The most bytes of control messages a `recvmsg` writes: credentials, and
+/// `SCM_MAX_FD` files.
+const CMSG_MAX: usize = CMSG_HDR_LEN
+    + cmsg_align(size_of::<UCred>())
+    + CMSG_HDR_LEN
+    + cmsg_align(SCM_MAX_FD * size_of::<c_int>());
+
+/// The longest address a socket here has.
+const ADDR_MAX: usize = size_of::<sockaddr_in6>();
+
+fn socket_from_fd(fd: c_int) -> LinuxResult<Arc<Socket>> {
+    get_file_like(fd)?
+        .into_any()
+        .downcast::<Socket>()
+        .map_err(|_| LinuxError::ENOTSOCK)
+}
+
+fn read_addr(addr: UserConstPtr<u8>, addrlen: socklen_t) -> LinuxResult<SocketAddr> {
+    let addr = addr.get_as_slice(addrlen as usize)?;
+    // SAFETY: `addr` holds `addrlen` readable bytes.
+    unsafe { SockAddr::read(addr.as_ptr().cast::<sockaddr>(), addrlen) }?.try_into()
+}
+
+/// Writes a socket address back to the program, in a buffer whose size
+/// comes in a value-result length, which the full length of the address
+/// goes back in, as `accept`, `getsockname`, `recvfrom` and `recvmsg` do.
+///
+/// The length is read once, when the writer is made, into a local that
+/// bounds the buffer checked and written, so another thread changing it
+/// meanwhile cannot make the kernel write past the buffer. A negative length
+/// fails with `EINVAL`, and one longer than [`ADDR_MAX`] is as long as it,
+/// so that a huge length with a small buffer works as on Linux. The bytes
+/// written are the address itself, truncated to the buffer, never more.
+struct AddrWriter {
+    buf: &'static mut [u8],
+    /// Where the full length goes back, unless it goes in a `msghdr`.
+    len: Option<&'static mut socklen_t>,
+}
+
+impl AddrWriter {
+    /// A writer to `addr`, of the length at `len`.
+    fn new(addr: UserPtr<u8>, len: UserPtr<socklen_t>) -> LinuxResult<Self> {
+        let len = len.get_as_mut()?;
+        let mut writer = Self::with_len(addr, *len)?;
+        writer.len = Some(len);
+        Ok(writer)
+    }
+
+    /// A writer to `addr`, of the length `len` already read.
+    fn with_len(addr: UserPtr<u8>, len: socklen_t) -> LinuxResult<Self> {
+        if (len as c_int) < 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        let len = (len as usize).min(ADDR_MAX);
+        let buf = if len == 0 {
+            &mut []
+        } else {
+            addr.get_as_mut_slice(len)?
+        };
+        Ok(Self { buf, len: None })
+    }
+
+    /// Write as much of `addr` as fits, or no address, and return its full
+    /// length, after writing it back to the length it was made with if it
+    /// was made by [`AddrWriter::new`].
+    fn write(self, addr: Option<&SockAddr>) -> socklen_t {
+        let bytes = addr.map_or(&[][..], SockAddr::bytes);
+        let n = self.buf.len().min(bytes.len());
+        self.buf[..n].copy_from_slice(&bytes[..n]);
+        let full = bytes.len() as socklen_t;
+        if let Some(len) = self.len {
+            *len = full;
+        }
+        full
+    }
+}
+
+/// Create a socket.
+///
+/// Only TCP and UDP over IPv4 and IPv6 are supported. `SOCK_CLOEXEC` and
+/// `SOCK_NONBLOCK` may be or'ed into `ty`.
+pub fn sys_socket(domain: u32, ty: u32, protocol: u32) -> LinuxResult<isize> {
+    debug!(
+        "sys_socket <= domain: {}, ty: {:#x}, protocol: {}",
+        domain, ty, protocol
+    );
+    let (flags, ty) = FdFlags::parse(ty, SOCK_TYPE_MASK)?;
+    if domain != AF_INET && domain != AF_INET6 {
+        return Err(LinuxError::EAFNOSUPPORT);
+    }
+    let socket = match (ty, protocol) {
+        (SOCK_STREAM, 0) => Socket::tcp(TcpSocket::new()),
+        (SOCK_STREAM, p) if p == IPPROTO_TCP as u32 => Socket::tcp(TcpSocket::new()),
+        (SOCK_DGRAM, 0) => Socket::udp(UdpSocket::new()),
+        (SOCK_DGRAM, p) if p == IPPROTO_UDP as u32 => Socket::udp(UdpSocket::new()),
+        (SOCK_STREAM | SOCK_DGRAM, _) => return Err(LinuxError::EPROTONOSUPPORT),
+        _ => return Err(LinuxError::ESOCKTNOSUPPORT),
+    };
+    Ok(install_fd(Arc::new(socket), flags)? as _)
+}
+
+/// Create a pair of connected sockets, and store their fds in `fds`.
+///
+/// Only Unix stream sockets are supported. `SOCK_CLOEXEC` and
+/// `SOCK_NONBLOCK` may be or'ed into `ty`.
+pub fn sys_socketpair(
+    domain: u32,
+    ty: u32,
+    protocol: u32,
+    fds: UserPtr<[c_int; 2]>,
+) -> LinuxResult<isize> {
+    debug!(
+        "sys_socketpair <= domain: {}, ty: {:#x}, protocol: {}",
+        domain, ty, protocol
+    );
+    let (flags, ty) = FdFlags::parse(ty, SOCK_TYPE_MASK)?;
+    match domain {
+        AF_UNIX => {}
+        AF_INET | AF_INET6 => return Err(LinuxError::EOPNOTSUPP),
+        _ => return Err(LinuxError::EAFNOSUPPORT),
+    }
+    if ty != SOCK_STREAM {
+        return Err(LinuxError::ESOCKTNOSUPPORT);
+    }
+    if protocol != 0 {
+        return Err(LinuxError::EPROTONOSUPPORT);
+    }
+    // Check the user memory before any fd is allocated, so that a bad
+    // pointer cannot leave the sockets open.
+    let fds = fds.get_as_mut()?;
+
+    let (a, b) = UnixStream::pair();
+    let fd_a = install_fd(Arc::new(Socket::unix(a)), flags)?;
+    let fd_b = install_fd(Arc::new(Socket::unix(b)), flags)
+        .inspect_err(|_| close_file_like(fd_a).unwrap())?;
+    fds[0] = fd_a;
+    fds[1] = fd_b;
+    Ok(0)
+}
+
+pub fn sys_bind(fd: c_int, addr: UserConstPtr<u8>, addrlen: socklen_t) -> LinuxResult<isize> {
+    let addr = read_addr(addr, addrlen)?;
+    debug!("sys_bind <= fd: {}, addr: {:?}", fd, addr);
+    socket_from_fd(fd)?.bind(addr)?;
+    Ok(0)
+}
+
+pub fn sys_connect(fd: c_int, addr: UserConstPtr<u8>, addrlen: socklen_t) -> LinuxResult<isize> {
+    let addr = read_addr(addr, addrlen)?;
+    debug!("sys_connect <= fd: {}, addr: {:?}", fd, addr);
+    socket_from_fd(fd)?.connect(addr)?;
+    Ok(0)
+}
+
+/// Listen on a socket. The backlog is not limited.
+pub fn sys_listen(fd: c_int, backlog: c_int) -> LinuxResult<isize> {
+    debug!("sys_listen <= fd: {}, backlog: {}", fd, backlog);
+    socket_from_fd(fd)?.listen()?;
+    Ok(0)
+}
+
+/// Shut down reading, writing or both of a socket, as `how` tells.
+pub fn sys_shutdown(fd: c_int, how: u32) -> LinuxResult<isize> {
+    debug!("sys_shutdown <= fd: {}, how: {}", fd, how);
+    socket_from_fd(fd)?.shutdown(how)?;
+    Ok(0)
+}
+
+/// Accept a connection, and store the address of the peer in `addr` if it is
+/// not null, truncated to `*addrlen` bytes. `*addrlen` is set to the full
+/// length of the address.
+///
+/// Only `SOCK_CLOEXEC` and `SOCK_NONBLOCK` are supported in `flags`, which
+/// apply to the new socket rather than being inherited from the listener.
+pub fn sys_accept4(
+    fd: c_int,
+    addr: UserPtr<u8>,
+    addrlen: UserPtr<socklen_t>,
+    flags: u32,
+) -> LinuxResult<isize> {
+    debug!("sys_accept4 <= fd: {}, flags: {:#x}", fd, flags);
+    let (flags, _) = FdFlags::parse(flags, 0)?;
+    let listener = socket_from_fd(fd)?;
+    // Check the user memory before accepting, so that a bad pointer does not
+    // drop the connection.
+    let writer = if addr.is_null() {
+        None
+    } else {
+        Some(AddrWriter::new(addr, addrlen)?)
+    };
+
+    let socket = Socket::tcp(listener.accept()?);
+    let peer = SockAddr::from(socket.peer_addr()?);
+    let new_fd = install_fd(Arc::new(socket), flags)?;
+    if let Some(writer) = writer {
+        writer.write(Some(&peer));
+    }
+    Ok(new_fd as _)
+}
+
+pub fn sys_accept(fd: c_int, addr: UserPtr<u8>, addrlen: UserPtr<socklen_t>) -> LinuxResult<isize> {
+    sys_accept4(fd, addr, addrlen, 0)
+}
+
+/// Store the address a socket is bound to in `addr`, truncated to `*addrlen`
+/// bytes. `*addrlen` is set to the full length of the address.
+pub fn sys_getsockname(
+    fd: c_int,
+    addr: UserPtr<u8>,
+    addrlen: UserPtr<socklen_t>,
+) -> LinuxResult<isize> {
+    debug!("sys_getsockname <= fd: {}", fd);
+    let socket = socket_from_fd(fd)?;
+    let writer = AddrWriter::new(addr, addrlen)?;
+    writer.write(Some(&SockAddr::from(socket.local_addr()?)));
+    Ok(0)
+}
+
+/// Store the address of the peer of a socket in `addr`, truncated to
+/// `*addrlen` bytes. `*addrlen` is set to the full length of the address.
+pub fn sys_getpeername(
+    fd: c_int,
+    addr: UserPtr<u8>,
+    addrlen: UserPtr<socklen_t>,
+) -> LinuxResult<isize> {
+    debug!("sys_getpeername <= fd: {}", fd);
+    let socket = socket_from_fd(fd)?;
+    let writer = AddrWriter::new(addr, addrlen)?;
+    writer.write(Some(&SockAddr::from(socket.peer_addr()?)));
+    Ok(0)
+}
+
+/// Get an option of a socket.
+///
+/// Only `SO_ERROR`, which takes the pending error, and `SO_TYPE` of
+/// `SOL_SOCKET` are supported, on Unix sockets `SO_PASSCRED` and
+/// `SO_PEERCRED`, and on TCP sockets `SO_LINGER` and `TCP_NODELAY` of
+/// `SOL_TCP`. The value is truncated to `*optlen` bytes, and `*optlen`
+/// is set to its full length.
+pub fn sys_getsockopt(
+    fd: c_int,
+    level: u32,
+    optname: u32,
+    optval: UserPtr<u8>,
+    optlen: UserPtr<socklen_t>,
+) -> LinuxResult<isize> {
+    debug!(
+        "sys_getsockopt <= fd: {}, level: {}, optname: {}",
+        fd, level, optname
+    );
+    let socket = socket_from_fd(fd)?;
+    let optlen = optlen.get_as_mut()?;
+    if (*optlen as c_int) < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let (unix, tcp) = (socket.as_unix(), socket.as_tcp());
+    let size = match (level, optname) {
+        (SOL_SOCKET, SO_ERROR | SO_TYPE) => size_of::<c_int>(),
+        (SOL_SOCKET, SO_PASSCRED) if unix.is_some() => size_of::<c_int>(),
+        (SOL_SOCKET, SO_PEERCRED) if unix.is_some() => size_of::<UCred>(),
+        (SOL_SOCKET, SO_LINGER) if tcp.is_some() => size_of::<linger>(),
+        (SOL_TCP, TCP_NODELAY) if tcp.is_some() => size_of::<c_int>(),
+        _ => return Err(LinuxError::ENOPROTOOPT),
+    };
+    // Check the user memory first, so that a bad pointer does not lose the
+    // error.
+    let len = (*optlen as usize).min(size);
+    let buf = optval.get_as_mut_slice(len)?;
+    let value: Vec<u8> = match (level, optname, unix, tcp) {
+        (SOL_SOCKET, SO_ERROR, ..) => socket
+            .take_error()
+            .map_or(0, |err| err.code())
+            .to_ne_bytes()
+            .into(),
+        (SOL_SOCKET, SO_TYPE, ..) => (socket.socket_type() as c_int).to_ne_bytes().into(),
+        (SOL_SOCKET, SO_PASSCRED, Some(unix), _) => (unix.passcred() as c_int).to_ne_bytes().into(),
+        (SOL_SOCKET, SO_PEERCRED, Some(unix), _) => unix.peer_cred().to_bytes().into(),
+        (SOL_SOCKET, SO_LINGER, _, Some(tcp)) => {
+            let linger = tcp.lock().linger();
+            let onoff = linger.is_some() as c_int;
+            let secs = linger.map_or(0, |it| it.as_secs().min(c_int::MAX as u64) as c_int);
+            [onoff.to_ne_bytes(), secs.to_ne_bytes()].concat()
+        }
+        (SOL_TCP, TCP_NODELAY, _, Some(tcp)) => {
+            (tcp.lock().nodelay() as c_int).to_ne_bytes().into()
+        }
+        _ => unreachable!(),
+    };
+    buf.copy_from_slice(&value[..len]);
+    *optlen = size as _;
+    Ok(0)
+}
+
+/// Set an option of a socket.
+///
+/// Only `SO_PASSCRED` of `SOL_SOCKET` on Unix sockets is supported, and
+/// `SO_LINGER` and `TCP_NODELAY` of `SOL_TCP` on TCP sockets. A linger time
+/// of zero resets the connection on close, discarding unsent data.
+pub fn sys_setsockopt(
+    fd: c_int,
+    level: u32,
+    optname: u32,
+    optval: UserConstPtr<u8>,
+    optlen: socklen_t,
+) -> LinuxResult<isize> {
+    debug!(
+        "sys_setsockopt <= fd: {}, level: {}, optname: {}",
+        fd, level, optname
+    );
+    let socket = socket_from_fd(fd)?;
+    let (unix, tcp) = (socket.as_unix(), socket.as_tcp());
+    let size = match (level, optname) {
+        (SOL_SOCKET, SO_PASSCRED) if unix.is_some() => size_of::<c_int>(),
+        (SOL_SOCKET, SO_LINGER) if tcp.is_some() => size_of::<linger>(),
+        (SOL_TCP, TCP_NODELAY) if tcp.is_some() => size_of::<c_int>(),
+        _ => return Err(LinuxError::ENOPROTOOPT),
+    };
+    if (optlen as usize) < size {
+        return Err(LinuxError::EINVAL);
+    }
+    let value = optval.get_as_slice(size)?;
+    let int_at = |i: usize| {
+        let bytes = &value[i * size_of::<c_int>()..][..size_of::<c_int>()];
+        c_int::from_ne_bytes(bytes.try_into().unwrap())
+    };
+    match (unix, tcp) {
+        (Some(unix), _) => unix.set_passcred(int_at(0) != 0),
+        (_, Some(tcp)) if optname == SO_LINGER => {
+            let linger = (int_at(0) != 0).then(|| Duration::from_secs(int_at(1).max(0) as u64));
+            tcp.lock().set_linger(linger);
+        }
+        (_, Some(tcp)) => tcp.lock().set_nodelay(int_at(0) != 0),
+        _ => unreachable!(),
+    }
+    Ok(0)
+}
+
+/// The iovecs of `msg`, whose total length must fit in the returned
+/// `ssize_t`.
+fn msg_iovs(msg: &msghdr) -> LinuxResult<&'static [iovec]> {
+    if msg.msg_iovlen > UIO_MAXIOV {
+        return Err(LinuxError::EMSGSIZE);
+    }
+    let iovs = UserConstPtr::<iovec>::from(msg.msg_iov as usize).get_as_slice(msg.msg_iovlen)?;
+    iovs.iter()
+        .try_fold(0usize, |total, iov| {
+            total
+                .checked_add(iov.iov_len as usize)
+                .filter(|&it| it <= isize::MAX as usize)
+        })
+        .ok_or(LinuxError::EINVAL)?;
+    Ok(iovs)
+}
+
+/// Check that the current process may send `cred`: only its own ids, unless
+/// it has the capability to claim others.
+fn check_cred(cred: UCred) -> LinuxResult {
+    let own = UCred::current();
+    if cred.pid != own.pid {
+        require_capability(CAP_SYS_ADMIN)?;
+    }
+    if cred.uid != own.uid {
+        require_capability(CAP_SETUID)?;
+    }
+    if cred.gid != own.gid {
+        require_capability(CAP_SETGID)?;
+    }
+    Ok(())
+}
+
+/// Read the control messages of `msg`: the credentials of
+/// `SCM_CREDENTIALS`, and the files of `SCM_RIGHTS`. Others fail with
+/// `EINVAL`.
+fn read_cmsgs(msg: &msghdr) -> LinuxResult<(Option<UCred>, Vec<Arc<dyn FileLike>>)> {
+    let mut cred = None;
+    let mut files = Vec::new();
+    if msg.msg_controllen == 0 {
+        return Ok((cred, files));
+    }
+    let control =
+        UserConstPtr::<u8>::from(msg.msg_control as usize).get_as_slice(msg.msg_controllen)?;
+    let mut offset = 0;
+    while offset + size_of::<cmsghdr>() <= control.len() {
+        // SAFETY: `control` holds a `cmsghdr` at `offset`.
+        let hdr = unsafe {
+            control[offset..]
+                .as_ptr()
+                .cast::<cmsghdr>()
+                .read_unaligned()
+        };
+        let len = hdr.cmsg_len;
+        if len < CMSG_HDR_LEN || len > control.len() - offset {
+            return Err(LinuxError::EINVAL);
+        }
+        if hdr.cmsg_level != SOL_SOCKET as c_int {
+            return Err(LinuxError::EINVAL);
+        }
+        let data = &control[offset + CMSG_HDR_LEN..offset + len];
+        match hdr.cmsg_type as u32 {
+            SCM_CREDENTIALS => {
+                let data = data.try_into().map_err(|_| LinuxError::EINVAL)?;
+                let claimed = UCred::from_bytes(data);
+                check_cred(claimed)?;
+                cred = Some(claimed);
+            }
+            SCM_RIGHTS => {
+                let fds = data.chunks_exact(size_of::<c_int>());
+                if files.len() + fds.len() > SCM_MAX_FD {
+                    return Err(LinuxError::EINVAL);
+                }
+                for fd in fds {
+                    files.push(get_file_like(c_int::from_ne_bytes(fd.try_into().unwrap()))?);
+                }
+            }
+            _ => return Err(LinuxError::EINVAL),
+        }
+        offset += cmsg_align(len);
+    }
+    Ok((cred, files))
+}
+
+/// Send a message on a socket.
+///
+/// On Unix sockets, an `SCM_CREDENTIALS` control message sets the
+/// credentials the peer receives, instead of those of the sender. Claiming
+/// another pid needs `CAP_SYS_ADMIN`, another user or group id `CAP_SETUID`
+/// or `CAP_SETGID`. An `SCM_RIGHTS` one passes up to `SCM_MAX_FD` files,
+/// which stay open until received even if the sender closes them. Control
+/// messages are ignored on other sockets, and `flags` are ignored.
+pub fn sys_sendmsg(fd: c_int, msg: UserConstPtr<msghdr>, flags: u32) -> LinuxResult<isize> {
+    debug!("sys_sendmsg <= fd: {}, flags: {:#x}", fd, flags);
+    let socket = socket_from_fd(fd)?;
+    let msg = msg.get_as_ref()?;
+    let mut data = Vec::new();
+    for iov in msg_iovs(msg)? {
+        if iov.iov_len == 0 {
+            continue;
+        }
+        data.extend_from_slice(
+            UserConstPtr::<u8>::from(iov.iov_base as usize).get_as_slice(iov.iov_len as _)?,
+        );
+    }
+    let sent = match socket.as_unix() {
+        Some(_) if !msg.msg_name.is_null() => return Err(LinuxError::EISCONN),
+        Some(unix) => {
+            let (cred, files) = read_cmsgs(msg)?;
+            unix.send_with(&data, cred, files)?
+        }
+        None if !msg.msg_name.is_null() => {
+            let addr = read_addr(
+                UserConstPtr::from(msg.msg_name as usize),
+                msg.msg_namelen as _,
+            )?;
+            socket.sendto(&data, addr)?
+        }
+        None => socket.send(&data)?,
+    };
+    Ok(sent as _)
+}
+
+/// Writes the control messages of a `recvmsg` to its control buffer.
+struct CmsgWriter {
+    buf: &'static mut [u8],
+    /// How much of `buf` is written.
+    len: usize,
+    /// Whether some control message did not fit, as `MSG_CTRUNC` reports.
+    truncated: bool,
+}
+
+impl CmsgWriter {
+    fn new(msg: &msghdr) -> LinuxResult<Self> {
+        // Read once, and no more than can be written, so that a huge length
+        // with a small buffer works as on Linux.
+        let len = msg.msg_controllen.min(CMSG_MAX);
+        let buf = if msg.msg_control.is_null() || len == 0 {
+            &mut []
+        } else {
+            UserPtr::<u8>::from(msg.msg_control as usize).get_as_mut_slice(len)?
+        };
+        Ok(Self {
+            buf,
+            len: 0,
+            truncated: false,
+        })
+    }
+
+    /// How many bytes of data fit in one more control message.
+    fn room(&self) -> usize {
+        (self.buf.len() - self.len).saturating_sub(CMSG_HDR_LEN)
+    }
+
+    /// Write a control message of `SOL_SOCKET` with `data`, which must fit.
+    fn put(&mut self, ty: u32, data: &[u8]) {
+        let len = CMSG_HDR_LEN + data.len();
+        let buf = &mut self.buf[self.len..];
+        let hdr = cmsghdr {
+            cmsg_len: len,
+            cmsg_level: SOL_SOCKET as _,
+            cmsg_type: ty as _,
+        };
+        // SAFETY: `buf` has room for a `cmsghdr`.
+        unsafe { buf.as_mut_ptr().cast::<cmsghdr>().write_unaligned(hdr) };
+        buf[CMSG_HDR_LEN..len].copy_from_slice(data);
+        self.len = (self.len + cmsg_align(len)).min(self.buf.len());
+    }
+
+    fn put_cred(&mut self, cred: UCred) {
+        if self.room() < size_of::<UCred>() {
+            self.truncated = true;
+        } else {
+            self.put(SCM_CREDENTIALS, &cred.to_bytes());
+        }
+    }
+
+    /// Add `files` to the fd table, as many as fit in the buffer and the
+    /// table, and write their fds. The others are closed.
+    fn put_files(&mut self, files: Vec<Arc<dyn FileLike>>, cloexec: bool) {
+        let fit = self.room() / size_of::<c_int>();
+        if files.len() > fit {
+            self.truncated = true;
+        }
+        let flags = if cloexec {
+            FdFlags::CLOEXEC
+        } else {
+            FdFlags::empty()
+        };
+        let mut fds = Vec::new();
+        for file in files.into_iter().take(fit) {
+            match install_fd(file, flags) {
+                Ok(fd) => fds.extend_from_slice(&fd.to_ne_bytes()),
+                Err(_) => {
+                    self.truncated = true;
+                    break;
+                }
+            }
+        }
+        if !fds.is_empty() {
+            self.put(SCM_RIGHTS, &fds);
+        }
+    }
+
+    /// Report what was written in `msg`.
+    fn finish(self, msg: &mut msghdr) {
+        msg.msg_controllen = self.len;
+        if self.truncated {
+            msg.msg_flags |= MSG_CTRUNC;
+        }
+    }
+}
+
+/// Receive a message from a socket.
+///
+/// At most `RECVMSG_MAX` bytes are received at once. On Unix sockets with
+/// `SO_PASSCRED` set, the credentials the data was sent with come in an
+/// `SCM_CREDENTIALS` control message, and files passed with it in an
+/// `SCM_RIGHTS` one, with new fds. Files which do not fit in the control
+/// buffer or the fd table are closed, and `MSG_CTRUNC` is set. Only
+/// `MSG_CMSG_CLOEXEC` is supported in `flags`.
+pub fn sys_recvmsg(fd: c_int, msg: UserPtr<msghdr>, flags: u32) -> LinuxResult<isize> {
+    debug!("sys_recvmsg <= fd: {}, flags: {:#x}", fd, flags);
+    let socket = socket_from_fd(fd)?;
+    let msg = msg.get_as_mut()?;
+    let iovs = msg_iovs(msg)?;
+    let mut control = CmsgWriter::new(msg)?;
+    let name = if msg.msg_name.is_null() {
+        None
+    } else {
+        let addr = UserPtr::from(msg.msg_name as usize);
+        Some(AddrWriter::with_len(addr, msg.msg_namelen as _)?)
+    };
+    let total = iovs.iter().map(|iov| iov.iov_len as usize).sum::<usize>();
+    let mut buf = vec![0; total.min(RECVMSG_MAX)];
+    let (len, ancillary, addr) = match socket.as_unix() {
+        Some(unix) => {
+            let (len, ancillary) = unix.recv_with(&mut buf)?;
+            (len, ancillary, None)
+        }
+        None => {
+            let (len, addr) = socket.recvfrom(&mut buf)?;
+            (len, None, addr)
+        }
+    };
+
+    let mut data = &buf[..len];
+    for iov in iovs {
+        if data.is_empty() {
+            break;
+        }
+        let n = data.len().min(iov.iov_len as usize);
+        UserPtr::<u8>::from(iov.iov_base as usize)
+            .get_as_mut_slice(n)?
+            .copy_from_slice(&data[..n]);
+        data = &data[n..];
+    }
+    let addr = addr.map(SockAddr::from);
+    msg.msg_namelen = name.map_or(0, |name| name.write(addr.as_ref()) as _);
+    msg.msg_flags = 0;
+    if let (Some(ancillary), Some(unix)) = (ancillary, socket.as_unix()) {
+        if unix.passcred() {
+            control.put_cred(ancillary.cred);
+        }
+        if !ancillary.files.is_empty() {
+            control.put_files(ancillary.files, flags & MSG_CMSG_CLOEXEC != 0);
+        }
+    }
+    control.finish(msg);
+    Ok(len as _)
+}
+
+/// Receive data from a socket, and store the address it came from in `addr`
+/// if it is not null, truncated to `*addrlen` bytes. `*addrlen` is set to
+/// the full length of the address, or 0 on sockets which do not tell it.
+///
+/// At most `RECVMSG_MAX` bytes are received at once, and `flags` are
+/// ignored.
+pub fn sys_recvfrom(
+    fd: c_int,
+    buf: UserPtr<u8>,
+    len: usize,
+    flags: u32,
+    addr: UserPtr<u8>,
+    addrlen: UserPtr<socklen_t>,
+) -> LinuxResult<isize> {
+    debug!(
+        "sys_recvfrom <= fd: {}, len: {}, flags: {:#x}",
+        fd, len, flags
+    );
+    let socket = socket_from_fd(fd)?;
+    // Check the user memory before receiving, so that a bad pointer does not
+    // lose the data.
+    let buf = buf.get_as_mut_slice(len)?;
+    let writer = if addr.is_null() {
+        None
+    } else {
+        Some(AddrWriter::new(addr, addrlen)?)
+    };
+
+    let mut data = vec![0; len.min(RECVMSG_MAX)];
+    let (len, from) = socket.recvfrom(&mut data)?;
+    buf[..len].copy_from_slice(&data[..len]);
+    if let Some(writer) = writer {
+        writer.write(from.map(SockAddr::from).as_ref());
+    }
+    Ok(len as _)
+}
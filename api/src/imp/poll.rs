@@ -0,0 +1,364 @@
+use core::{ffi::c_int, mem, time::Duration};
+
+use alloc::vec::Vec;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::{arch::TrapFrame, time::monotonic_time};
+use axsignal::{SignalSet, Signo};
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::{POLLERR, POLLHUP, POLLIN, POLLNVAL, POLLOUT, pollfd, timespec};
+
+use crate::{
+    abi::check_sigset_size,
+    file::{FD_TABLE, get_file_like, nofile_limit},
+    ptr::{UserConstPtr, UserPtr, nullable},
+    signal::{check_signals, has_pending_signal},
+    time::TimeValueLike,
+};
+
+/// How often a waiting `poll` checks the files again, since they do not
+/// notify it.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Fill in the `revents` of `fds`, and return how many have any.
+fn poll_once(fds: &mut [pollfd]) -> LinuxResult<usize> {
+    let mut ready = 0;
+    for pfd in fds.iter_mut() {
+        pfd.revents = 0;
+        if pfd.fd < 0 {
+            continue;
+        }
+        let events = pfd.events as u32;
+        let revents = match get_file_like(pfd.fd) {
+            Ok(f) => {
+                let state = f.poll()?;
+                let mut revents = 0;
+                if state.readable {
+                    revents |= events & POLLIN;
+                }
+                if state.writable {
+                    revents |= events & POLLOUT;
+                }
+                // Reported even if not asked for.
+                let status = f.poll_status();
+                if status.hangup {
+                    revents |= POLLHUP;
+                }
+                if status.error {
+                    revents |= POLLERR;
+                }
+                revents
+            }
+            Err(_) => POLLNVAL,
+        };
+        pfd.revents = revents as _;
+        if revents != 0 {
+            ready += 1;
+        }
+    }
+    Ok(ready)
+}
+
+/// Wait until `check` finds something ready, for at most `timeout`, and
+/// return what it found.
+fn wait_ready(
+    timeout: Option<Duration>,
+    mut check: impl FnMut() -> LinuxResult<usize>,
+) -> LinuxResult<isize> {
+    let deadline = timeout.map(|it| monotonic_time() + it);
+    loop {
+        // Let the network stack move on, e.g. finish a handshake.
+        axnet::poll_interfaces();
+        let ready = check()?;
+        if ready > 0 {
+            return Ok(ready as _);
+        }
+        let wait = match deadline {
+            Some(deadline) => {
+                let now = monotonic_time();
+                if now >= deadline {
+                    return Ok(0);
+                }
+                (deadline - now).min(POLL_INTERVAL)
+            }
+            None => POLL_INTERVAL,
+        };
+        if has_pending_signal() {
+            return Err(LinuxError::EINTR);
+        }
+        axtask::sleep(wait);
+    }
+}
+
+/// Wait until a file of `fds` is ready, for at most `timeout`.
+fn poll_fds(fds: &mut [pollfd], timeout: Option<Duration>) -> LinuxResult<isize> {
+    wait_ready(timeout, || poll_once(fds))
+}
+
+/// Run `f` with the signal mask replaced by `sigmask`, if any, as `ppoll`
+/// and `pselect6` do.
+fn with_sigmask(
+    tf: &mut TrapFrame,
+    sigmask: Option<SignalSet>,
+    f: impl FnOnce() -> LinuxResult<isize>,
+) -> LinuxResult<isize> {
+    let Some(mut set) = sigmask else {
+        return f();
+    };
+    set.remove(Signo::SIGKILL);
+    set.remove(Signo::SIGSTOP);
+    let curr = current();
+    let signal = &curr.task_ext().thread_data().signal;
+    let old_blocked = signal.with_blocked_mut(|blocked| mem::replace(blocked, set));
+
+    let result = f();
+    if matches!(result, Err(LinuxError::EINTR)) {
+        tf.set_retval(-LinuxError::EINTR.code() as usize);
+        // Like `sigsuspend`, the handler runs with `sigmask`, and the old
+        // mask comes back when it returns.
+        if check_signals(tf, Some(old_blocked)) {
+            return Ok(tf.retval() as _);
+        }
+    }
+    signal.with_blocked_mut(|blocked| *blocked = old_blocked);
+    result
+}
+
+/// Get the `pollfd`s of a `poll` syscall.
+fn get_fds(fds: UserPtr<pollfd>, nfds: usize) -> LinuxResult<&'static mut [pollfd]> {
+    if nfds > nofile_limit() {
+        return Err(LinuxError::EINVAL);
+    }
+    fds.get_as_mut_slice(nfds)
+}
+
+/// Wait for one of a set of files to become ready, for at most `timeout`,
+/// with the signal mask replaced by `sigmask` meanwhile if it is not null.
+///
+/// Only `POLLIN` and `POLLOUT` are reported if asked for, and `POLLHUP` and
+/// `POLLERR` always, see [`FileLike::poll_status`]. A closed descriptor gets
+/// `POLLNVAL`.
+///
+/// [`FileLike::poll_status`]: crate::file::FileLike::poll_status
+pub fn sys_ppoll(
+    tf: &mut TrapFrame,
+    fds: UserPtr<pollfd>,
+    nfds: usize,
+    timeout: UserConstPtr<timespec>,
+    sigmask: UserConstPtr<SignalSet>,
+    sigsetsize: usize,
+) -> LinuxResult<isize> {
+    debug!("sys_ppoll <= nfds: {}", nfds);
+    let fds = get_fds(fds, nfds)?;
+    let timeout = nullable!(timeout.get_as_ref())?
+        .map(|it| it.try_to_time_value())
+        .transpose()?;
+    let sigmask = nullable!(sigmask.get_as_ref())?.copied();
+    if sigmask.is_some() {
+        check_sigset_size(sigsetsize)?;
+    }
+    with_sigmask(tf, sigmask, || poll_fds(fds, timeout))
+}
+
+/// Like [`sys_ppoll`], with the timeout in milliseconds, where a negative
+/// one waits forever.
+#[cfg(target_arch = "x86_64")]
+pub fn sys_poll(fds: UserPtr<pollfd>, nfds: usize, timeout: i32) -> LinuxResult<isize> {
+    debug!("sys_poll <= nfds: {}, timeout: {}", nfds, timeout);
+    let fds = get_fds(fds, nfds)?;
+    let timeout = (timeout >= 0).then(|| Duration::from_millis(timeout as u64));
+    poll_fds(fds, timeout)
+}
+
+/// The bits in a word of an `fd_set`.
+const NFDBITS: usize = usize::BITS as usize;
+
+/// The `fd_set`s of a `select` syscall, each only as long as `nfds` needs,
+/// so that no more is read or written than the caller has to provide.
+struct FdSets {
+    nfds: usize,
+    read: Option<&'static mut [usize]>,
+    write: Option<&'static mut [usize]>,
+    except: Option<&'static mut [usize]>,
+}
+
+impl FdSets {
+    fn new(
+        nfds: c_int,
+        read: UserPtr<usize>,
+        write: UserPtr<usize>,
+        except: UserPtr<usize>,
+    ) -> LinuxResult<Self> {
+        if nfds < 0 || nfds as usize > nofile_limit() {
+            return Err(LinuxError::EINVAL);
+        }
+        let nfds = nfds as usize;
+        let words = nfds.div_ceil(NFDBITS);
+        Ok(Self {
+            nfds,
+            read: nullable!(read.get_as_mut_slice(words))?,
+            write: nullable!(write.get_as_mut_slice(words))?,
+            except: nullable!(except.get_as_mut_slice(words))?,
+        })
+    }
+
+    /// The bits of word `i` of `set`, without those past `nfds`.
+    fn word(&self, set: &Option<&mut [usize]>, i: usize) -> usize {
+        let Some(set) = set else {
+            return 0;
+        };
+        let bits = self.nfds - i * NFDBITS;
+        if bits < NFDBITS {
+            set[i] & ((1 << bits) - 1)
+        } else {
+            set[i]
+        }
+    }
+
+    /// Get a `pollfd` for each descriptor in a set, asking for `POLLIN` if
+    /// it is in the read set and `POLLOUT` if in the write set.
+    ///
+    /// Only the words up to the largest open descriptor are scanned bit by
+    /// bit, so a large `nfds` with few descriptors costs little. Fails with
+    /// `EBADF` if a set has a descriptor which is not open.
+    fn to_pollfds(&self) -> LinuxResult<Vec<pollfd>> {
+        let end = FD_TABLE.with(|table| table.end());
+        let mut fds = Vec::new();
+        for i in 0..self.nfds.div_ceil(NFDBITS) {
+            let read = self.word(&self.read, i);
+            let write = self.word(&self.write, i);
+            let mut all = read | write | self.word(&self.except, i);
+            if all == 0 {
+                continue;
+            }
+            if i * NFDBITS >= end {
+                return Err(LinuxError::EBADF);
+            }
+            while all != 0 {
+                let bit = all.trailing_zeros() as usize;
+                all &= all - 1;
+                let mut events = 0;
+                if read & (1 << bit) != 0 {
+                    events |= POLLIN;
+                }
+                if write & (1 << bit) != 0 {
+                    events |= POLLOUT;
+                }
+                fds.push(pollfd {
+                    fd: (i * NFDBITS + bit) as _,
+                    events: events as _,
+                    revents: 0,
+                });
+            }
+        }
+        Ok(fds)
+    }
+
+    /// Leave in the sets only the descriptors `fds` found ready, and return
+    /// how many bits are left.
+    ///
+    /// Hangups and errors count as readable, and errors as writable, like
+    /// on Linux. Nothing is ever exceptional.
+    fn set_ready(&mut self, fds: &[pollfd]) -> LinuxResult<usize> {
+        for set in [&mut self.read, &mut self.write, &mut self.except]
+            .into_iter()
+            .flatten()
+        {
+            set.fill(0);
+        }
+        let mut ready = 0;
+        for pfd in fds {
+            let (events, revents) = (pfd.events as u32, pfd.revents as u32);
+            if revents & POLLNVAL != 0 {
+                return Err(LinuxError::EBADF);
+            }
+            let (i, bit) = (pfd.fd as usize / NFDBITS, pfd.fd as usize % NFDBITS);
+            if events & POLLIN != 0 && revents & (POLLIN | POLLHUP | POLLERR) != 0 {
+                if let Some(set) = &mut self.read {
+                    set[i] |= 1 << bit;
+                }
+                ready += 1;
+            }
+            if events & POLLOUT != 0 && revents & (POLLOUT | POLLERR) != 0 {
+                if let Some(set) = &mut self.write {
+                    set[i] |= 1 << bit;
+                }
+                ready += 1;
+            }
+        }
+        Ok(ready)
+    }
+}
+
+/// Wait until a descriptor of `sets` is ready, for at most `timeout`, and
+/// leave only the ready ones in the sets.
+fn select_fds(sets: &mut FdSets, timeout: Option<Duration>) -> LinuxResult<isize> {
+    let mut fds = sets.to_pollfds()?;
+    wait_ready(timeout, || {
+        poll_once(&mut fds)?;
+        // Checked without touching the sets, which keep what was asked
+        // until something is ready.
+        Ok(fds.iter().filter(|pfd| select_ready(pfd)).count())
+    })?;
+    Ok(sets.set_ready(&fds)? as _)
+}
+
+/// Whether `select` reports `pfd` in one of its sets, see
+/// [`FdSets::set_ready`].
+fn select_ready(pfd: &pollfd) -> bool {
+    let (events, revents) = (pfd.events as u32, pfd.revents as u32);
+    revents & POLLNVAL != 0
+        || (events & POLLIN != 0 && revents & (POLLIN | POLLHUP | POLLERR) != 0)
+        || (events & POLLOUT != 0 && revents & (POLLOUT | POLLERR) != 0)
+}
+
+/// Wait for one of the descriptors in the sets to become ready, for at most
+/// `timeout`, with the signal mask replaced meanwhile by the one `sigmask`
+/// points to, along with its size, if not null.
+///
+/// `nfds` above the `RLIMIT_NOFILE` soft limit fails with `EINVAL`. The
+/// time left is not written back to `timeout`.
+pub fn sys_pselect6(
+    tf: &mut TrapFrame,
+    nfds: c_int,
+    readfds: UserPtr<usize>,
+    writefds: UserPtr<usize>,
+    exceptfds: UserPtr<usize>,
+    timeout: UserConstPtr<timespec>,
+    sigmask: UserConstPtr<[usize; 2]>,
+) -> LinuxResult<isize> {
+    debug!("sys_pselect6 <= nfds: {}", nfds);
+    let mut sets = FdSets::new(nfds, readfds, writefds, exceptfds)?;
+    let timeout = nullable!(timeout.get_as_ref())?
+        .map(|it| it.try_to_time_value())
+        .transpose()?;
+    let sigmask = match nullable!(sigmask.get_as_ref())? {
+        Some(&[set, size]) => {
+            let set = UserConstPtr::<SignalSet>::from(set);
+            let set = nullable!(set.get_as_ref())?.copied();
+            if set.is_some() {
+                check_sigset_size(size)?;
+            }
+            set
+        }
+        None => None,
+    };
+    with_sigmask(tf, sigmask, || select_fds(&mut sets, timeout))
+}
+
+/// Like [`sys_pselect6`], with the timeout as a `timeval`, and without a
+/// signal mask.
+#[cfg(target_arch = "x86_64")]
+pub fn sys_select(
+    nfds: c_int,
+    readfds: UserPtr<usize>,
+    writefds: UserPtr<usize>,
+    exceptfds: UserPtr<usize>,
+    timeout: UserConstPtr<linux_raw_sys::general::timeval>,
+) -> LinuxResult<isize> {
+    debug!("sys_select <= nfds: {}", nfds);
+    let mut sets = FdSets::new(nfds, readfds, writefds, exceptfds)?;
+    let timeout = nullable!(timeout.get_as_ref())?
+        .map(|it| it.try_to_time_value())
+        .transpose()?;
+    select_fds(&mut sets, timeout)
+}
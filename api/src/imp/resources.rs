@@ -0,0 +1,169 @@
+use core::{sync::atomic::Ordering, time::Duration};
+
+use alloc::{sync::Arc, vec, vec::Vec};
+use axerrno::{LinuxError, LinuxResult};
+use axprocess::{Pid, Process};
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::{
+    __kernel_old_timeval, PRIO_PGRP, PRIO_PROCESS, RUSAGE_CHILDREN, RUSAGE_SELF, RUSAGE_THREAD,
+    rlimit64, rusage,
+};
+use starry_core::{
+    cred::{CAP_SYS_NICE, CAP_SYS_RESOURCE},
+    resources::{NR_OPEN, RLIM_NLIMITS, RLIMIT_CPU, RLIMIT_NOFILE, Rlimit},
+    task::{ProcessData, get_process, get_process_group},
+};
+
+use super::{require_capability, require_same_cred};
+use crate::{
+    ptr::{UserConstPtr, UserPtr, nullable},
+    time::TimeValueLike,
+};
+
+/// Get and set the resource limits of the process `pid`, or of the current
+/// one if `pid` is 0.
+///
+/// Lowering `RLIMIT_NOFILE` below an open descriptor is allowed, like Linux,
+/// and only stops new descriptors from being allocated above the limit.
+/// A new `RLIMIT_CPU` takes effect at once, also on a running process.
+///
+/// Another process is only in reach as [`require_same_cred`] tells, or with
+/// `CAP_SYS_RESOURCE`.
+pub fn sys_prlimit64(
+    pid: Pid,
+    resource: u32,
+    new_limit: UserConstPtr<rlimit64>,
+    old_limit: UserPtr<rlimit64>,
+) -> LinuxResult<isize> {
+    debug!("sys_prlimit64 <= pid: {}, resource: {}", pid, resource);
+    if resource as usize >= RLIM_NLIMITS {
+        return Err(LinuxError::EINVAL);
+    }
+    let new_limit = nullable!(new_limit.get_as_ref())?.map(|it| Rlimit {
+        cur: it.rlim_cur,
+        max: it.rlim_max,
+    });
+
+    let proc = if pid == 0 {
+        current().task_ext().thread.process().clone()
+    } else {
+        get_process(pid)?
+    };
+    let data = proc.data::<ProcessData>().ok_or(LinuxError::ESRCH)?;
+    if !Arc::ptr_eq(&proc, current().task_ext().thread.process()) {
+        require_same_cred(data, CAP_SYS_RESOURCE)?;
+    }
+    let mut rlimits = data.rlimits.write();
+    let old = rlimits.get(resource).ok_or(LinuxError::EINVAL)?;
+    if let Some(new) = new_limit {
+        if new.cur > new.max {
+            return Err(LinuxError::EINVAL);
+        }
+        if new.max > old.max {
+            require_capability(CAP_SYS_RESOURCE)?;
+        }
+        if resource == RLIMIT_NOFILE && new.max > NR_OPEN {
+            return Err(LinuxError::EPERM);
+        }
+    }
+    if let Some(old_limit) = nullable!(old_limit.get_as_mut())? {
+        *old_limit = rlimit64 {
+            rlim_cur: old.cur,
+            rlim_max: old.max,
+        };
+    }
+    if let Some(new) = new_limit {
+        rlimits.set(resource, new);
+        if resource == RLIMIT_CPU {
+            data.cpu_limit.set(new);
+        }
+    }
+    Ok(0)
+}
+
+/// Get the resource usage of the current process, of its reaped children or
+/// of the current thread.
+///
+/// Only the user and system time are reported, the other fields are zero.
+pub fn sys_getrusage(who: i32, usage: UserPtr<rusage>) -> LinuxResult<isize> {
+    debug!("sys_getrusage <= who: {}", who);
+    let curr = current();
+    let (utime_ns, stime_ns) = match who {
+        RUSAGE_CHILDREN => curr.task_ext().process_data().times().children(),
+        _ if who == RUSAGE_SELF as i32 => curr.task_ext().process_data().times().own(),
+        _ if who == RUSAGE_THREAD as i32 => curr.task_ext().thread_data().cpu_time(),
+        _ => return Err(LinuxError::EINVAL),
+    };
+    let usage = usage.get_as_mut()?;
+    // SAFETY: `rusage` is plain old data, for which zero is valid.
+    *usage = unsafe { core::mem::zeroed() };
+    usage.ru_utime = __kernel_old_timeval::from_time_value(Duration::from_nanos(utime_ns as _));
+    usage.ru_stime = __kernel_old_timeval::from_time_value(Duration::from_nanos(stime_ns as _));
+    Ok(0)
+}
+
+/// The lowest nice value, the highest priority.
+const MIN_NICE: i32 = -20;
+/// The highest nice value, the lowest priority.
+const MAX_NICE: i32 = 19;
+
+/// The processes `which` and `who` of `getpriority` and `setpriority` name.
+///
+/// Only `PRIO_PROCESS` and `PRIO_PGRP` are supported; every process runs as
+/// root, so `PRIO_USER` would name them all.
+fn priority_targets(which: u32, who: Pid) -> LinuxResult<Vec<Arc<Process>>> {
+    let curr = current();
+    let proc = curr.task_ext().thread.process();
+    match which {
+        PRIO_PROCESS if who == 0 => Ok(vec![proc.clone()]),
+        PRIO_PROCESS => Ok(vec![get_process(who)?]),
+        PRIO_PGRP if who == 0 => Ok(proc.group().processes()),
+        PRIO_PGRP => Ok(get_process_group(who)?.processes()),
+        _ => Err(LinuxError::EINVAL),
+    }
+}
+
+/// Get the highest priority of the processes `which` and `who` name, as
+/// `20 - nice` so that it is never negative.
+pub fn sys_getpriority(which: u32, who: Pid) -> LinuxResult<isize> {
+    priority_targets(which, who)?
+        .iter()
+        .filter_map(|proc| proc.data::<ProcessData>())
+        .map(|data| (20 - data.nice.load(Ordering::Relaxed)) as isize)
+        .max()
+        .ok_or(LinuxError::ESRCH)
+}
+
+/// Set the nice value of the processes `which` and `who` name to `nice`,
+/// clamped to -20..=19.
+///
+/// Lowering the nice value of a process, which raises its priority, needs
+/// `CAP_SYS_NICE`. The scheduler only applies it to the calling thread,
+/// and only if it is the CFS one.
+pub fn sys_setpriority(which: u32, who: Pid, nice: i32) -> LinuxResult<isize> {
+    debug!(
+        "sys_setpriority <= which: {}, who: {}, nice: {}",
+        which, who, nice
+    );
+    let nice = nice.clamp(MIN_NICE, MAX_NICE);
+    let targets = priority_targets(which, who)?;
+    if targets.is_empty() {
+        return Err(LinuxError::ESRCH);
+    }
+    for proc in &targets {
+        let data = proc.data::<ProcessData>().ok_or(LinuxError::ESRCH)?;
+        if nice < data.nice.load(Ordering::Relaxed) {
+            require_capability(CAP_SYS_NICE)?;
+        }
+    }
+    let curr = current();
+    for proc in &targets {
+        if let Some(data) = proc.data::<ProcessData>() {
+            data.nice.store(nice, Ordering::Relaxed);
+        }
+        if Arc::ptr_eq(proc, curr.task_ext().thread.process()) {
+            axtask::set_priority(nice as isize);
+        }
+    }
+    Ok(0)
+}
@@ -1,30 +1,31 @@
-use core::{mem, time::Duration};
+use core::{ffi::c_int, mem, time::Duration};
 
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec, vec::Vec};
 use axerrno::{LinuxError, LinuxResult};
-use axhal::arch::TrapFrame;
-use axprocess::{Pid, Thread};
-use axsignal::{SignalInfo, SignalSet, SignalStack, Signo};
+use axhal::{arch::TrapFrame, time::monotonic_time};
+use axprocess::{Pid, Process, Thread};
+use axsignal::{SignalDisposition, SignalInfo, SignalSet, SignalStack, Signo};
 use axtask::{TaskExtRef, current};
 use linux_raw_sys::general::{
-    MINSIGSTKSZ, SI_TKILL, SI_USER, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK, kernel_sigaction, siginfo,
-    timespec,
+    MINSIGSTKSZ, SI_TKILL, SI_USER, SIG_BLOCK, SIG_SETMASK, SIG_UNBLOCK, SS_ONSTACK,
+    kernel_sigaction, siginfo, timespec,
 };
-use starry_core::task::{get_process, get_process_group, get_thread, processes};
+use starry_core::task::{ProcessData, get_process, get_process_group, get_thread, processes};
 
+use super::REAL_ID;
 use crate::{
+    abi::check_sigset_size,
+    file::{FdFlags, FileLike, SignalFd, install_fd},
+    fpstate,
     ptr::{UserConstPtr, UserPtr, nullable},
-    signal::{check_signals, send_signal_process, send_signal_process_group, send_signal_thread},
+    signal::{
+        check_kill_permission, check_signals, check_sigpending_limit, dequeue_signal_in,
+        drop_ignored_signals, is_on_sigaltstack, leave_signal_frame, restore_altstack,
+        send_signal_process, send_signal_thread, signal_dequeued,
+    },
     time::TimeValueLike,
 };
 
-fn check_sigset_size(size: usize) -> LinuxResult<()> {
-    if size != size_of::<SignalSet>() {
-        return Err(LinuxError::EINVAL);
-    }
-    Ok(())
-}
-
 fn parse_signo(signo: u32) -> LinuxResult<Signo> {
     Signo::from_repr(signo as u8).ok_or(LinuxError::EINVAL)
 }
@@ -80,6 +81,10 @@ pub fn sys_rt_sigaction(
     }
     if let Some(act) = nullable!(act.get_as_ref())? {
         actions[signo] = (*act).try_into()?;
+        if matches!(actions[signo].disposition, SignalDisposition::Ignore) {
+            drop(actions);
+            drop_ignored_signals(signo);
+        }
     }
     Ok(0)
 }
@@ -90,69 +95,87 @@ pub fn sys_rt_sigpending(set: UserPtr<SignalSet>, sigsetsize: usize) -> LinuxRes
     Ok(0)
 }
 
+/// Record the current process as the sender of `sig`.
+fn set_sender(sig: &mut SignalInfo) {
+    // SAFETY: `_kill` is the member used by `kill`-family signals.
+    let kill = unsafe { &mut sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._kill };
+    kill._pid = current().task_ext().thread.process().pid() as _;
+    kill._uid = REAL_ID;
+}
+
 fn make_siginfo(signo: u32, code: i32) -> LinuxResult<Option<SignalInfo>> {
     if signo == 0 {
         return Ok(None);
     }
     let signo = parse_signo(signo)?;
-    Ok(Some(SignalInfo::new(signo, code)))
+    let mut sig = SignalInfo::new(signo, code);
+    set_sender(&mut sig);
+    Ok(Some(sig))
+}
+
+/// Send `sig` to each of `procs`, or only check the permission if `sig` is
+/// `None`.
+///
+/// Succeeds if any process could be signaled, otherwise fails with the last
+/// error, or `ESRCH` if there is no process.
+fn kill_processes(procs: Vec<Arc<Process>>, sig: Option<SignalInfo>) -> LinuxResult<isize> {
+    let signo = sig.as_ref().map(SignalInfo::signo);
+    let mut result = Err(LinuxError::ESRCH);
+    for proc in procs {
+        let res = check_kill_permission(&proc, signo).and_then(|_| match &sig {
+            Some(sig) => send_signal_process(&proc, sig.clone()),
+            None => Ok(()),
+        });
+        match res {
+            Ok(()) => result = Ok(0),
+            Err(e) if result.is_err() => result = Err(e),
+            Err(_) => {}
+        }
+    }
+    result
 }
 
 pub fn sys_kill(pid: i32, signo: u32) -> LinuxResult<isize> {
-    let Some(sig) = make_siginfo(signo, SI_USER as _)? else {
-        // TODO: should also check permissions
-        return Ok(0);
-    };
+    let sig = make_siginfo(signo, SI_USER as _)?;
 
     let curr = current();
-    match pid {
-        1.. => {
-            let proc = get_process(pid as Pid)?;
-            send_signal_process(&proc, sig)?;
-            Ok(1)
-        }
-        0 => {
-            let pg = curr.task_ext().thread.process().group();
-            Ok(send_signal_process_group(&pg, sig) as _)
-        }
+    let procs = match pid {
+        1.. => vec![get_process(pid as Pid)?],
+        0 => curr.task_ext().thread.process().group().processes(),
         -1 => {
-            let mut count = 0;
-            for proc in processes() {
-                if proc.is_init() {
-                    // init process
-                    continue;
-                }
-                send_signal_process(&proc, sig.clone())?;
-                count += 1;
-            }
-            Ok(count)
-        }
-        ..-1 => {
-            let pg = get_process_group((-pid) as Pid)?;
-            Ok(send_signal_process_group(&pg, sig) as _)
+            // Everything but init, the kernel and the caller itself.
+            let curr_pid = curr.task_ext().thread.process().pid();
+            processes()
+                .into_iter()
+                .filter(|proc| {
+                    !proc.is_init()
+                        && proc.pid() != curr_pid
+                        && proc.data::<ProcessData>().is_some()
+                })
+                .collect()
         }
+        ..-1 => get_process_group((-pid) as Pid)?.processes(),
+    };
+    kill_processes(procs, sig)
+}
+
+/// Send `sig` to `thr`, or only check the permission if `sig` is `None`.
+fn kill_thread(thr: &Thread, sig: Option<SignalInfo>) -> LinuxResult<isize> {
+    check_kill_permission(&thr.process(), sig.as_ref().map(SignalInfo::signo))?;
+    if let Some(sig) = sig {
+        send_signal_thread(thr, sig)?;
     }
+    Ok(0)
 }
 
 pub fn sys_tkill(tid: Pid, signo: u32) -> LinuxResult<isize> {
-    let Some(sig) = make_siginfo(signo, SI_TKILL)? else {
-        // TODO: should also check permissions
-        return Ok(0);
-    };
-
-    let thr = get_thread(tid)?;
-    send_signal_thread(&thr, sig)?;
-    Ok(0)
+    let sig = make_siginfo(signo, SI_TKILL)?;
+    kill_thread(&get_thread(tid)?, sig)
 }
 
 pub fn sys_tgkill(tgid: Pid, tid: Pid, signo: u32) -> LinuxResult<isize> {
-    let Some(sig) = make_siginfo(signo, SI_TKILL)? else {
-        // TODO: should also check permissions
-        return Ok(0);
-    };
-
-    send_signal_thread(find_thread_in_group(tgid, tid)?.as_ref(), sig)?;
-    Ok(0)
+    let sig = make_siginfo(signo, SI_TKILL)?;
+    kill_thread(&find_thread_in_group(tgid, tid)?, sig)
 }
 
 fn find_thread_in_group(tgid: Pid, tid: Pid) -> LinuxResult<Arc<Thread>> {
@@ -176,6 +199,7 @@ fn make_queue_signal_info(
     {
         return Err(LinuxError::EPERM);
     }
+    set_sender(&mut sig);
     Ok(sig)
 }
 
@@ -188,8 +212,9 @@ pub fn sys_rt_sigqueueinfo(
     check_sigset_size(sigsetsize)?;
 
     let sig = make_queue_signal_info(tgid, signo, sig)?;
-    send_signal_process(get_process(tgid)?.as_ref(), sig)?;
-    Ok(0)
+    let proc = get_process(tgid)?;
+    check_sigpending_limit(&proc, sig.signo())?;
+    kill_processes(vec![proc], Some(sig))
 }
 
 pub fn sys_rt_tgsigqueueinfo(
@@ -202,16 +227,30 @@ pub fn sys_rt_tgsigqueueinfo(
     check_sigset_size(sigsetsize)?;
 
     let sig = make_queue_signal_info(tgid, signo, sig)?;
-    send_signal_thread(find_thread_in_group(tgid, tid)?.as_ref(), sig)?;
-    Ok(0)
+    let thr = find_thread_in_group(tgid, tid)?;
+    check_sigpending_limit(&thr.process(), sig.signo())?;
+    kill_thread(&thr, Some(sig))
 }
 
 pub fn sys_rt_sigreturn(tf: &mut TrapFrame) -> LinuxResult<isize> {
     let curr = current();
+    let frame = leave_signal_frame(tf.sp());
+    if let Some((ucontext, _)) = frame {
+        fpstate::restore(tf, ucontext);
+    }
     curr.task_ext().thread_data().signal.restore(tf);
+    if let Some((ucontext, true)) = frame {
+        restore_altstack(ucontext, tf.sp());
+    }
     Ok(tf.retval() as isize)
 }
 
+/// Wait for a signal of `set`, for at most `timeout`, and take it instead of
+/// delivering it. Returns its number.
+///
+/// Like Linux, the signals of `set` are taken even if the thread does not
+/// block them; they are blocked meanwhile so that they stay pending until
+/// taken here.
 pub fn sys_rt_sigtimedwait(
     set: UserConstPtr<SignalSet>,
     info: UserPtr<siginfo>,
@@ -220,23 +259,27 @@ pub fn sys_rt_sigtimedwait(
 ) -> LinuxResult<isize> {
     check_sigset_size(sigsetsize)?;
 
-    let set = *set.get_as_ref()?;
-    let timeout: Option<Duration> = nullable!(timeout.get_as_ref())?.map(|ts| ts.to_time_value());
-
-    let Some(sig) = current()
-        .task_ext()
-        .thread_data()
-        .signal
-        .wait_timeout(set, timeout)
-    else {
-        return Err(LinuxError::EAGAIN);
-    };
+    let mut set = *set.get_as_ref()?;
+    set.remove(Signo::SIGKILL);
+    set.remove(Signo::SIGSTOP);
+    let deadline = nullable!(timeout.get_as_ref())?
+        .map(|ts| ts.try_to_time_value())
+        .transpose()?
+        .map(|timeout: Duration| monotonic_time() + timeout);
+    let info = nullable!(info.get_as_mut())?;
 
-    if let Some(info) = nullable!(info.get_as_mut())? {
+    let curr = current();
+    let signal = &curr.task_ext().thread_data().signal;
+    let old_blocked = signal.with_blocked_mut(|blocked| mem::replace(blocked, *blocked | set));
+    let result = dequeue_signal_in(set, deadline);
+    signal.with_blocked_mut(|blocked| *blocked = old_blocked);
+
+    let sig = result?;
+    signal_dequeued(&sig);
+    if let Some(info) = info {
         *info = sig.0;
     }
-
-    Ok(0)
+    Ok(sig.signo() as isize)
 }
 
 pub fn sys_rt_sigsuspend(
@@ -263,13 +306,44 @@ pub fn sys_rt_sigsuspend(
         if check_signals(tf, Some(old_blocked)) {
             break;
         }
+        // Interruptible, see `WaitQueueWrapper`: a signal the new mask does
+        // not block ends the wait, and is delivered on the next round.
         curr.task_ext().process_data().signal.wait_signal();
     }
 
     Ok(0)
 }
 
+/// Create a `signalfd` reading the signals of `mask`, or change the mask of
+/// the one `fd` refers to, if `fd` is not -1.
+pub fn sys_signalfd4(
+    fd: c_int,
+    mask: UserConstPtr<SignalSet>,
+    sizemask: usize,
+    flags: u32,
+) -> LinuxResult<isize> {
+    debug!("sys_signalfd4 <= fd: {}, flags: {:#x}", fd, flags);
+    check_sigset_size(sizemask)?;
+    let (flags, _) = FdFlags::parse(flags, 0)?;
+
+    let mut mask = *mask.get_as_ref()?;
+    mask.remove(Signo::SIGKILL);
+    mask.remove(Signo::SIGSTOP);
+    if fd == -1 {
+        let signalfd = SignalFd::new(mask, flags.contains(FdFlags::NONBLOCK));
+        Ok(install_fd(Arc::new(signalfd), flags)? as _)
+    } else {
+        SignalFd::from_fd(fd)?.set_mask(mask);
+        Ok(fd as _)
+    }
+}
+
+/// Set or get the alternate signal stack of the current thread.
+///
+/// Like Linux, the old stack is reported with `SS_ONSTACK` while the thread
+/// runs on it, and cannot be changed then.
 pub fn sys_sigaltstack(
+    tf: &TrapFrame,
     ss: UserConstPtr<SignalStack>,
     old_ss: UserPtr<SignalStack>,
 ) -> LinuxResult<isize> {
@@ -278,10 +352,17 @@ pub fn sys_sigaltstack(
         .thread_data()
         .signal
         .with_stack_mut(|stack| {
+            let on_stack = is_on_sigaltstack(stack, tf.sp());
             if let Some(old_ss) = nullable!(old_ss.get_as_mut())? {
                 *old_ss = stack.clone();
+                if on_stack {
+                    old_ss.flags |= SS_ONSTACK as _;
+                }
             }
             if let Some(ss) = nullable!(ss.get_as_ref())? {
+                if on_stack {
+                    return Err(LinuxError::EPERM);
+                }
                 if ss.size <= MINSIGSTKSZ as usize {
                     return Err(LinuxError::ENOMEM);
                 }
@@ -1,46 +1,184 @@
-use core::ffi::c_char;
+use core::ffi::{c_char, c_long, c_ulong};
 
-use axerrno::LinuxResult;
-use linux_raw_sys::system::new_utsname;
+use axerrno::{LinuxError, LinuxResult};
+use axhal::time::NANOS_PER_SEC;
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::{
+    general::{
+        GRND_INSECURE, GRND_NONBLOCK, GRND_RANDOM, LINUX_REBOOT_CMD_CAD_OFF,
+        LINUX_REBOOT_CMD_CAD_ON, LINUX_REBOOT_CMD_HALT, LINUX_REBOOT_CMD_POWER_OFF,
+        LINUX_REBOOT_MAGIC1, LINUX_REBOOT_MAGIC2, LINUX_REBOOT_MAGIC2A, LINUX_REBOOT_MAGIC2B,
+        LINUX_REBOOT_MAGIC2C,
+    },
+    system::new_utsname,
+};
+use memory_addr::PAGE_SIZE_4K;
+use starry_core::{
+    cred::{CAP_SYS_ADMIN, CAP_SYS_BOOT},
+    stats,
+    task::processes,
+    uts::{RELEASE, SYSNAME, UTS_NAME_LEN, VERSION},
+};
 
-use crate::ptr::UserPtr;
+use super::{EFFECTIVE_ID, REAL_ID, require_capability};
+use crate::ptr::{UserConstPtr, UserPtr};
 
 pub fn sys_getuid() -> LinuxResult<isize> {
-    Ok(0)
+    Ok(REAL_ID as _)
 }
 
 pub fn sys_geteuid() -> LinuxResult<isize> {
-    Ok(1)
+    Ok(EFFECTIVE_ID as _)
 }
 
 pub fn sys_getgid() -> LinuxResult<isize> {
-    Ok(0)
+    Ok(REAL_ID as _)
 }
 
 pub fn sys_getegid() -> LinuxResult<isize> {
-    Ok(1)
+    Ok(EFFECTIVE_ID as _)
 }
 
-const fn pad_str(info: &str) -> [c_char; 65] {
+/// Copy `info` into a field of `struct new_utsname`, which ends with a
+/// null byte.
+fn pad_str(info: &[u8]) -> [c_char; 65] {
     let mut data: [c_char; 65] = [0; 65];
-    // this needs #![feature(const_copy_from_slice)]
-    // data[..info.len()].copy_from_slice(info.as_bytes());
-    unsafe {
-        core::ptr::copy_nonoverlapping(info.as_ptr().cast(), data.as_mut_ptr(), info.len());
+    for (dst, &src) in data.iter_mut().zip(&info[..info.len().min(UTS_NAME_LEN)]) {
+        *dst = src as c_char;
     }
     data
 }
 
-const UTSNAME: new_utsname = new_utsname {
-    sysname: pad_str("Starry"),
-    nodename: pad_str("Starry - machine[0]"),
-    release: pad_str("10.0.0"),
-    version: pad_str("10.0.0"),
-    machine: pad_str("10.0.0"),
-    domainname: pad_str("https://github.com/oscomp/starry-next"),
-};
-
+/// Get the names of the kernel, and the host and domain names of the UTS
+/// namespace of the caller.
 pub fn sys_uname(name: UserPtr<new_utsname>) -> LinuxResult<isize> {
-    *name.get_as_mut()? = UTSNAME;
+    let names = current().task_ext().process_data().uts.read().names();
+    *name.get_as_mut()? = new_utsname {
+        sysname: pad_str(SYSNAME.as_bytes()),
+        nodename: pad_str(&names.hostname),
+        release: pad_str(RELEASE.as_bytes()),
+        version: pad_str(VERSION.as_bytes()),
+        machine: pad_str(b"10.0.0"),
+        domainname: pad_str(&names.domainname),
+    };
+    Ok(0)
+}
+
+/// Read a host or domain name of `len` bytes for `sethostname` or
+/// `setdomainname`, which need `CAP_SYS_ADMIN`.
+fn read_uts_name(name: UserConstPtr<u8>, len: usize) -> LinuxResult<&'static [u8]> {
+    require_capability(CAP_SYS_ADMIN)?;
+    if len > UTS_NAME_LEN {
+        return Err(LinuxError::EINVAL);
+    }
+    name.get_as_slice(len)
+}
+
+pub fn sys_sethostname(name: UserConstPtr<u8>, len: usize) -> LinuxResult<isize> {
+    let name = read_uts_name(name, len)?;
+    current()
+        .task_ext()
+        .process_data()
+        .uts
+        .read()
+        .set_hostname(name);
     Ok(0)
 }
+
+pub fn sys_setdomainname(name: UserConstPtr<u8>, len: usize) -> LinuxResult<isize> {
+    let name = read_uts_name(name, len)?;
+    current()
+        .task_ext()
+        .process_data()
+        .uts
+        .read()
+        .set_domainname(name);
+    Ok(0)
+}
+
+/// `struct sysinfo`, of a 64-bit system, where the trailing padding is
+/// empty.
+#[repr(C)]
+#[derive(Default)]
+pub struct SysInfo {
+    uptime: c_long,
+    loads: [c_ulong; 3],
+    totalram: c_ulong,
+    freeram: c_ulong,
+    sharedram: c_ulong,
+    bufferram: c_ulong,
+    totalswap: c_ulong,
+    freeswap: c_ulong,
+    procs: u16,
+    pad: u16,
+    totalhigh: c_ulong,
+    freehigh: c_ulong,
+    mem_unit: u32,
+}
+
+/// Get the uptime, which agrees with `/proc/uptime`, the number of
+/// processes, and the memory the page allocator has free.
+///
+/// The load averages are not tracked and reported as 0, and there is no
+/// swap.
+pub fn sys_sysinfo(info: UserPtr<SysInfo>) -> LinuxResult<isize> {
+    *info.get_as_mut()? = SysInfo {
+        uptime: (stats::uptime_nanos() / NANOS_PER_SEC) as _,
+        totalram: axconfig::plat::PHYS_MEMORY_SIZE as _,
+        freeram: (axalloc::global_allocator().available_pages() * PAGE_SIZE_4K) as _,
+        procs: processes().len().min(u16::MAX as usize) as _,
+        mem_unit: 1,
+        ..Default::default()
+    };
+    Ok(0)
+}
+
+pub fn sys_reboot(magic1: u32, magic2: u32, cmd: u32, _arg: usize) -> LinuxResult<isize> {
+    require_capability(CAP_SYS_BOOT)?;
+    if magic1 != LINUX_REBOOT_MAGIC1
+        || ![
+            LINUX_REBOOT_MAGIC2,
+            LINUX_REBOOT_MAGIC2A,
+            LINUX_REBOOT_MAGIC2B,
+            LINUX_REBOOT_MAGIC2C,
+        ]
+        .contains(&magic2)
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    match cmd {
+        // There is no Ctrl-Alt-Del to handle.
+        LINUX_REBOOT_CMD_CAD_ON | LINUX_REBOOT_CMD_CAD_OFF => Ok(0),
+        LINUX_REBOOT_CMD_HALT | LINUX_REBOOT_CMD_POWER_OFF => {
+            info!("Powering off on request of reboot");
+            // Unlike Linux, which leaves it to the caller, what is cached
+            // for the block devices is written back first.
+            if let Err(err) = axfs::sync_block_devices() {
+                warn!("sys_reboot: failed to sync: {:?}", err);
+            }
+            crate::file::flush_output();
+            axhal::misc::terminate()
+        }
+        _ => {
+            warn!("sys_reboot: unsupported command {:#x}", cmd);
+            Err(LinuxError::EINVAL)
+        }
+    }
+}
+
+/// Fill `buf` with `len` random bytes, from [`starry_core::random`].
+///
+/// Neither `GRND_RANDOM` nor `GRND_NONBLOCK` changes anything, as the
+/// generator is seeded at boot and never blocks.
+pub fn sys_getrandom(buf: UserPtr<u8>, len: usize, flags: u32) -> LinuxResult<isize> {
+    if flags & !(GRND_NONBLOCK | GRND_RANDOM | GRND_INSECURE) != 0
+        || flags & (GRND_RANDOM | GRND_INSECURE) == GRND_RANDOM | GRND_INSECURE
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    // Like Linux, at most `i32::MAX` bytes at once.
+    let len = len.min(i32::MAX as usize);
+    let buf = buf.get_as_mut_slice(len)?;
+    starry_core::random::random_bytes(buf);
+    Ok(len as _)
+}
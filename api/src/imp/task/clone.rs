@@ -1,3 +1,5 @@
+use core::sync::atomic::Ordering;
+
 use alloc::sync::Arc;
 use axerrno::{LinuxError, LinuxResult};
 use axfs::{CURRENT_DIR, CURRENT_DIR_PATH};
@@ -9,14 +11,25 @@ use axtask::{TaskExtRef, current};
 use bitflags::bitflags;
 use linux_raw_sys::general::*;
 use starry_core::{
-    mm::copy_from_kernel,
-    task::{ProcessData, TaskExt, ThreadData, add_thread_to_table, new_user_task},
+    cred::CAP_SYS_ADMIN,
+    lockcheck::assert_lock_clean,
+    mm::{PAGE_SIZE, copy_from_kernel},
+    resources::RLIMIT_CPU,
+    task::{
+        ProcessData, TaskExt, ThreadData, add_thread_to_table, cond_resched, new_tid, new_user_task,
+    },
 };
 
-use crate::{file::FD_TABLE, ptr::UserPtr};
+use crate::{
+    file::FD_TABLE,
+    imp::CWD_MOUNT,
+    path::CWD_HANDLE,
+    ptr::{UserConstPtr, UserPtr},
+    require_capability,
+};
 
 bitflags! {
-    /// Options for use with [`sys_clone`].
+    /// Options for use with [`sys_clone`] and [`sys_clone3`].
     #[derive(Debug, Clone, Copy, Default)]
     struct CloneFlags: u32 {
         /// The calling process and the child process run in the same
@@ -96,13 +109,159 @@ pub fn sys_clone(
         flags, exit_signal, stack, parent_tid, child_tid, tls
     );
 
-    if exit_signal != 0 && flags.contains(CloneFlags::THREAD | CloneFlags::PARENT) {
+    // A thread sends no exit signal, so the one asked for is ignored, unlike
+    // with `clone3`.
+    let exit_signal = if flags.contains(CloneFlags::THREAD) {
+        None
+    } else {
+        Signo::from_repr(exit_signal as u8)
+    };
+    do_clone(tf, flags, exit_signal, stack, parent_tid, child_tid, tls)
+}
+
+/// The flags `clone3` rejects: the exit signal, which has its own field,
+/// and what is not supported, namely pidfds, namespaces other than UTS
+/// namespaces, cgroups and clearing the signal handlers.
+const CLONE3_UNSUPPORTED: u64 = (CSIGNAL
+    | CLONE_DETACHED
+    | CLONE_PIDFD
+    | CLONE_NEWNS
+    | CLONE_NEWCGROUP
+    | CLONE_NEWIPC
+    | CLONE_NEWUSER
+    | CLONE_NEWPID
+    | CLONE_NEWNET
+    | CLONE_NEWTIME) as u64
+    | CLONE_CLEAR_SIGHAND
+    | CLONE_INTO_CGROUP;
+
+/// Read the `clone_args` of `clone3`, which has `size` bytes.
+///
+/// Like Linux, a smaller struct of an older version gets the fields it does
+/// not have zeroed, and a larger one of a newer version is accepted if the
+/// fields it adds are zero.
+fn read_clone_args(uargs: UserConstPtr<u8>, size: usize) -> LinuxResult<clone_args> {
+    if size < CLONE_ARGS_SIZE_VER0 as usize {
+        return Err(LinuxError::EINVAL);
+    }
+    if size > PAGE_SIZE {
+        return Err(LinuxError::E2BIG);
+    }
+    let bytes = uargs.get_as_slice(size)?;
+    let known = size.min(size_of::<clone_args>());
+    if bytes[known..].iter().any(|&b| b != 0) {
+        return Err(LinuxError::E2BIG);
+    }
+    // SAFETY: `clone_args` is plain old data.
+    let mut args: clone_args = unsafe { core::mem::zeroed() };
+    // SAFETY: `known` bytes fit in both.
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            bytes.as_ptr(),
+            (&mut args as *mut clone_args).cast::<u8>(),
+            known,
+        );
+    }
+    Ok(args)
+}
+
+/// The initial stack pointer for the stack `clone3` gets as its lowest
+/// address and size, rather than as the stack pointer like `clone`.
+///
+/// The stack grows down on every supported architecture, so it starts at the
+/// top, which the user aligns.
+fn clone3_stack_pointer(stack: u64, stack_size: u64) -> LinuxResult<usize> {
+    match (stack, stack_size) {
+        (0, 0) => Ok(0),
+        (0, _) | (_, 0) => Err(LinuxError::EINVAL),
+        (stack, size) => stack
+            .checked_add(size)
+            .map(|top| top as usize)
+            .ok_or(LinuxError::EINVAL),
+    }
+}
+
+/// Like [`sys_clone`], with the arguments in a `struct clone_args` of `size`
+/// bytes at `uargs`.
+///
+/// Setting the thread IDs with `set_tid` is not supported, nor are the flags
+/// of [`CLONE3_UNSUPPORTED`].
+pub fn sys_clone3(tf: &TrapFrame, uargs: UserConstPtr<u8>, size: usize) -> LinuxResult<isize> {
+    let args = read_clone_args(uargs, size)?;
+    info!("sys_clone3 <= {:?}", args);
+
+    if args.flags & CLONE3_UNSUPPORTED != 0 || args.set_tid != 0 || args.set_tid_size != 0 {
         return Err(LinuxError::EINVAL);
     }
-    if flags.contains(CloneFlags::THREAD) && !flags.contains(CloneFlags::VM | CloneFlags::SIGHAND) {
+    let flags = CloneFlags::from_bits(args.flags as u32).ok_or(LinuxError::EINVAL)?;
+    let exit_signal = match args.exit_signal {
+        0 => None,
+        signo => Some(
+            u8::try_from(signo)
+                .ok()
+                .and_then(Signo::from_repr)
+                .ok_or(LinuxError::EINVAL)?,
+        ),
+    };
+    if exit_signal.is_some() && flags.intersects(CloneFlags::THREAD | CloneFlags::PARENT) {
         return Err(LinuxError::EINVAL);
     }
-    let exit_signal = Signo::from_repr(exit_signal as u8);
+    let stack = clone3_stack_pointer(args.stack, args.stack_size)?;
+    do_clone(
+        tf,
+        flags,
+        exit_signal,
+        stack,
+        args.parent_tid as _,
+        args.child_tid as _,
+        args.tls as _,
+    )
+}
+
+/// Create a task, as `clone` and `clone3` do with their arguments checked.
+///
+/// The descriptor table and the working directory live in the namespace of
+/// the process, so they are shared or copied as follows:
+///
+/// | flags                     | fd table | cwd    | after `execve` in either    |
+/// |---------------------------|----------|--------|-----------------------------|
+/// | `CLONE_THREAD`            | same     | same   | other threads are killed    |
+/// | `CLONE_FILES`             | shared   | copied | fd table copied by the exec |
+/// | `CLONE_FS`                | copied   | shared | cwd still shared            |
+/// | `CLONE_FILES \| CLONE_FS` | shared   | shared | fd table copied, cwd shared |
+/// | neither                   | copied   | copied | nothing shared              |
+///
+/// Like in Linux, `execve` gives the process a table of its own before
+/// closing the close-on-exec descriptors, which the processes it shared the
+/// table with keep. A thread cannot have a table or a working directory of
+/// its own, so `CLONE_THREAD` without `CLONE_FILES` and `CLONE_FS` fails
+/// with `EINVAL`, though Linux allows it.
+fn do_clone(
+    tf: &TrapFrame,
+    flags: CloneFlags,
+    exit_signal: Option<Signo>,
+    stack: usize,
+    parent_tid: usize,
+    child_tid: usize,
+    tls: usize,
+) -> LinuxResult<isize> {
+    if flags.contains(CloneFlags::THREAD)
+        && !flags
+            .contains(CloneFlags::VM | CloneFlags::SIGHAND | CloneFlags::FILES | CloneFlags::FS)
+    {
+        return Err(LinuxError::EINVAL);
+    }
+    // Namespaces belong to processes, not threads.
+    if flags.contains(CloneFlags::NEWUTS) {
+        if flags.contains(CloneFlags::THREAD) {
+            return Err(LinuxError::EINVAL);
+        }
+        require_capability(CAP_SYS_ADMIN)?;
+    }
+
+    let curr = current();
+    // Held until the new thread is in the process, which an `execve` kills.
+    let _clone = curr.task_ext().process_data().exec_gate.begin_clone()?;
 
     let mut new_uctx = UspaceContext::from(tf);
     if stack != 0 {
@@ -111,6 +270,8 @@ pub fn sys_clone(
     if flags.contains(CloneFlags::SETTLS) {
         new_uctx.set_tls(tls);
     }
+    // The child resumes where the parent does, past the syscall, see
+    // `SYSCALL_INSN_SIZE`.
     new_uctx.set_retval(0);
 
     let set_child_tid = if flags.contains(CloneFlags::CHILD_SETTID) {
@@ -119,10 +280,9 @@ pub fn sys_clone(
         None
     };
 
-    let curr = current();
     let mut new_task = new_user_task(curr.name(), new_uctx, set_child_tid);
 
-    let tid = new_task.id().as_u64() as Pid;
+    let tid = new_tid(new_task.id().as_u64())?;
     if flags.contains(CloneFlags::PARENT_SETTID) {
         *UserPtr::<Pid>::from(parent_tid).get_as_mut()? = tid;
     }
@@ -131,54 +291,94 @@ pub fn sys_clone(
         new_task.ctx_mut().set_page_table_root(
             curr.task_ext()
                 .process_data()
-                .aspace
-                .lock()
+                .lock_aspace()
                 .page_table_root(),
         );
 
         curr.task_ext().thread.process()
     } else {
+        // With `CLONE_PARENT`, the child is a sibling of the caller: the
+        // parent of the caller gets its exit signal, may wait for it and is
+        // what `getppid` returns, all through `Process::parent`, while the
+        // caller cannot wait for it. The rest, like the process group and
+        // the signal handlers, still comes from the caller.
+        let caller = curr.task_ext().thread.process();
         let parent = if flags.contains(CloneFlags::PARENT) {
-            curr.task_ext()
-                .thread
-                .process()
-                .parent()
-                .ok_or(LinuxError::EINVAL)?
+            caller.parent().ok_or(LinuxError::EINVAL)?
         } else {
-            curr.task_ext().thread.process().clone()
+            caller.clone()
         };
-        let builder = parent.fork(tid);
 
-        let aspace = if flags.contains(CloneFlags::VM) {
-            curr.task_ext().process_data().aspace.clone()
+        // The memory is copied before anything of the child exists, so that
+        // the threads in `mmap` or faulting meanwhile only wait for the copy
+        // to end, never the other way round.
+        let proc_data = curr.task_ext().process_data();
+        let (aspace, heap, grows_down) = if flags.contains(CloneFlags::VM) {
+            (
+                proc_data.aspace.clone(),
+                proc_data.heap.clone(),
+                proc_data.grows_down.clone(),
+            )
         } else {
-            let mut aspace = curr.task_ext().process_data().aspace.lock();
-            let mut aspace = aspace.clone_or_err()?;
+            let aspace = proc_data.lock_aspace();
+            // The heap bounds and the grows-down mappings are copied under
+            // the lock, so they match the copied areas even if another
+            // thread moves the break or maps meanwhile.
+            let heap = Arc::new(proc_data.heap.fork());
+            let grows_down = Arc::new(Mutex::new(proc_data.lock_grows_down().clone()));
+            let mut aspace = aspace.clone_or_err(cond_resched)?;
             copy_from_kernel(&mut aspace)?;
-            Arc::new(Mutex::new(aspace))
+            (Arc::new(Mutex::new(aspace)), heap, grows_down)
         };
+        let builder = parent.fork(tid);
         new_task
             .ctx_mut()
             .set_page_table_root(aspace.lock().page_table_root());
 
         let signal_actions = if flags.contains(CloneFlags::SIGHAND) {
-            parent
-                .data::<ProcessData>()
-                .map_or_else(Arc::default, |it| it.signal.actions.clone())
+            curr.task_ext().process_data().signal.actions.clone()
         } else {
             Arc::default()
         };
         let process_data = ProcessData::new(
             curr.task_ext().process_data().exe_path.read().clone(),
             aspace,
+            heap,
+            grows_down,
             signal_actions,
             exit_signal,
         );
+        *process_data.syscall_filters.write() = curr
+            .task_ext()
+            .process_data()
+            .syscall_filters
+            .read()
+            .clone();
+        *process_data.sandbox.write() = curr.task_ext().process_data().sandbox.read().clone();
+        *process_data.cred.write() = curr.task_ext().process_data().cred.read().clone();
+        *process_data.exec_args.write() = curr.task_ext().process_data().exec_args.read().clone();
+        let rlimits = curr.task_ext().process_data().rlimits.read().clone();
+        // The child starts with no CPU time, and the limit counts from there.
+        process_data.cpu_limit.set(rlimits.get(RLIMIT_CPU).unwrap());
+        *process_data.rlimits.write() = rlimits;
+        process_data.nice.store(
+            curr.task_ext().process_data().nice.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        let uts = curr.task_ext().process_data().uts.read().clone();
+        *process_data.uts.write() = if flags.contains(CloneFlags::NEWUTS) {
+            uts.copy()
+        } else {
+            uts
+        };
+        process_data
+            .syscall_latency
+            .set_enabled(curr.task_ext().process_data().syscall_latency.is_enabled());
 
         if flags.contains(CloneFlags::FILES) {
             FD_TABLE
                 .deref_from(&process_data.ns)
-                .init_shared(FD_TABLE.share());
+                .init_new(FD_TABLE.share_inner());
         } else {
             FD_TABLE
                 .deref_from(&process_data.ns)
@@ -188,10 +388,16 @@ pub fn sys_clone(
         if flags.contains(CloneFlags::FS) {
             CURRENT_DIR
                 .deref_from(&process_data.ns)
-                .init_shared(CURRENT_DIR.share());
+                .init_new(CURRENT_DIR.share_inner());
             CURRENT_DIR_PATH
                 .deref_from(&process_data.ns)
-                .init_shared(CURRENT_DIR_PATH.share());
+                .init_new(CURRENT_DIR_PATH.share_inner());
+            CWD_HANDLE
+                .deref_from(&process_data.ns)
+                .init_new(CWD_HANDLE.share_inner());
+            CWD_MOUNT
+                .deref_from(&process_data.ns)
+                .init_new(CWD_MOUNT.share_inner());
         } else {
             CURRENT_DIR
                 .deref_from(&process_data.ns)
@@ -199,19 +405,32 @@ pub fn sys_clone(
             CURRENT_DIR_PATH
                 .deref_from(&process_data.ns)
                 .init_new(CURRENT_DIR_PATH.copy_inner());
+            CWD_HANDLE
+                .deref_from(&process_data.ns)
+                .init_new(CWD_HANDLE.copy_inner());
+            CWD_MOUNT
+                .deref_from(&process_data.ns)
+                .init_new(CWD_MOUNT.copy_inner());
         }
         &builder.data(process_data).build()
     };
+    // The builder puts a child in the group of its parent, not the caller.
+    if flags.contains(CloneFlags::PARENT) && !flags.contains(CloneFlags::THREAD) {
+        process.move_to_group(&curr.task_ext().thread.process().group());
+    }
 
     let thread_data = ThreadData::new(process.data().unwrap());
     if flags.contains(CloneFlags::CHILD_CLEARTID) {
         thread_data.set_clear_child_tid(child_tid);
     }
+    thread_data.set_initial_sp(stack);
 
     let thread = process.new_thread(tid).data(thread_data).build();
     add_thread_to_table(&thread);
     new_task.init_task_ext(TaskExt::new(thread));
-    axtask::spawn_task(new_task);
+    assert_lock_clean("clone");
+    let new_task = axtask::spawn_task(new_task);
+    new_task.task_ext().thread_data().set_task(&new_task);
 
     Ok(tid as _)
 }
@@ -219,3 +438,42 @@ pub fn sys_clone(
 pub fn sys_fork(tf: &TrapFrame) -> LinuxResult<isize> {
     sys_clone(tf, SIGCHLD, 0, 0, 0, 0)
 }
+
+/// Stop sharing the resources of `flags` with other processes, which may be
+/// `CLONE_FILES`, `CLONE_FS` and `CLONE_NEWUTS`.
+///
+/// The other flags fail with `EINVAL`. The resources belong to the process,
+/// so all its threads are detached, unlike in Linux where only the calling
+/// one is. As the threads of a process share its working directory,
+/// `CLONE_FS` fails with `EINVAL` if the process has other threads.
+pub fn sys_unshare(flags: u32) -> LinuxResult<isize> {
+    let flags = CloneFlags::from_bits(flags).ok_or(LinuxError::EINVAL)?;
+    info!("sys_unshare <= flags: {:?}", flags);
+
+    if !(CloneFlags::FILES | CloneFlags::FS | CloneFlags::NEWUTS).contains(flags) {
+        return Err(LinuxError::EINVAL);
+    }
+    if flags.contains(CloneFlags::NEWUTS) {
+        require_capability(CAP_SYS_ADMIN)?;
+    }
+    let curr = current();
+    if flags.contains(CloneFlags::FS) && curr.task_ext().thread.process().threads().len() > 1 {
+        return Err(LinuxError::EINVAL);
+    }
+
+    if flags.contains(CloneFlags::FILES) {
+        FD_TABLE.unshare();
+    }
+    if flags.contains(CloneFlags::FS) {
+        CURRENT_DIR.unshare();
+        CURRENT_DIR_PATH.unshare();
+        CWD_HANDLE.unshare();
+        CWD_MOUNT.unshare();
+    }
+    if flags.contains(CloneFlags::NEWUTS) {
+        let uts = &curr.task_ext().process_data().uts;
+        let copy = uts.read().copy();
+        *uts.write() = copy;
+    }
+    Ok(0)
+}
@@ -1,20 +1,98 @@
 use core::ffi::c_char;
 
-use alloc::{string::ToString, vec::Vec};
-use axerrno::{LinuxError, LinuxResult};
+use alloc::{sync::Arc, vec::Vec};
+use axerrno::{AxError, AxResult, LinuxError, LinuxResult};
 use axhal::arch::TrapFrame;
+use axio::Read;
+use axsignal::{SignalInfo, Signo};
 use axtask::{TaskExtRef, current};
-use starry_core::mm::{load_user_app, map_trampoline};
+use linux_raw_sys::general::{AT_FDCWD, SI_KERNEL, X_OK};
+use starry_core::{
+    audit::audit_exec,
+    lockcheck::assert_lock_clean,
+    mm::{load_user_app, map_trampoline},
+    observer::{ProcessEvent, notify_process_event},
+    sandbox::PathAccess,
+    task::{ExecArgs, SignalFrames},
+};
 
-use crate::ptr::UserConstPtr;
+use crate::{
+    check_path_access,
+    file::{FD_TABLE, file_closed},
+    imp::release_futexes,
+    path::handle_file_path,
+    ptr::UserConstPtr,
+    signal::send_signal_thread,
+};
 
+/// Kill the other threads of the current process, and wait for them to be
+/// gone, as `execve` does.
+///
+/// Unlike Linux, the thread calling `execve` keeps its own thread ID when
+/// it is not the main thread, rather than taking the ID of the process.
+fn kill_other_threads() -> LinuxResult<()> {
+    let curr = current();
+    let thread = &curr.task_ext().thread;
+    let proc = thread.process();
+    let sig = SignalInfo::new(Signo::SIGKILL, SI_KERNEL as _);
+    for other in proc.threads() {
+        if other.tid() != thread.tid() {
+            let _ = send_signal_thread(&other, sig.clone());
+        }
+    }
+    let alone = curr
+        .task_ext()
+        .process_data()
+        .exec_gate
+        .wait_until(|| proc.threads().len() == 1);
+    // Killed itself, by an `exit_group` which came first.
+    if !alone {
+        return Err(LinuxError::EAGAIN);
+    }
+    Ok(())
+}
+
+/// Check that the file at `path` is a program the loader takes, an ELF file
+/// or a script starting with `#!`, before the old program is gone.
+fn probe_program(path: &str) -> AxResult {
+    let mut file = axfs::api::File::open(path)?;
+    let mut head = [0; 4];
+    let mut len = 0;
+    while len < head.len() {
+        match file.read(&mut head[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    let head = &head[..len];
+    if head.starts_with(b"#!") || head == b"\x7fELF" {
+        Ok(())
+    } else {
+        Err(AxError::InvalidData)
+    }
+}
+
+/// The error of `execve` for a program which fails to load.
+fn load_error(err: AxError) -> LinuxError {
+    match err {
+        AxError::InvalidData | AxError::Unsupported => LinuxError::ENOEXEC,
+        err => err.into(),
+    }
+}
+
+/// Run the program at `path`, resolved against the cwd if relative, with
+/// the arguments `argv` and the environment `envp`.
+///
+/// Fails without touching the calling program with `ENOENT` if `path` does
+/// not exist, `EACCES` if it is a directory or not executable, and
+/// `ENOEXEC` if it is neither an ELF file nor a script.
 pub fn sys_execve(
     tf: &mut TrapFrame,
     path: UserConstPtr<c_char>,
     argv: UserConstPtr<UserConstPtr<c_char>>,
     envp: UserConstPtr<UserConstPtr<c_char>>,
 ) -> LinuxResult<isize> {
-    let path = path.get_as_str()?.to_string();
+    let path = path.get_as_path()?.into_owned();
 
     let args = argv
         .get_as_null_terminated()?
@@ -32,24 +110,39 @@ pub fn sys_execve(
         path, args, envs
     );
 
+    let real_path = handle_file_path(AT_FDCWD, &path, PathAccess::EXEC)?;
+    if axfs::api::metadata(real_path.as_str())?.is_dir() {
+        return Err(LinuxError::EACCES);
+    }
+    check_path_access(real_path.as_str(), X_OK)?;
+    probe_program(real_path.as_str()).map_err(load_error)?;
+    let exe_path = axfs::api::canonicalize(real_path.as_str())?;
+
     let curr = current();
     let curr_ext = curr.task_ext();
+    let proc = curr_ext.thread.process();
 
-    if curr_ext.thread.process().threads().len() > 1 {
-        // TODO: handle multi-thread case
-        error!("sys_execve: multi-thread not supported");
-        return Err(LinuxError::EAGAIN);
-    }
+    let _exec = curr_ext.process_data().exec_gate.begin_exec()?;
+    kill_other_threads()?;
+    assert_lock_clean("execve");
+    // Like on exit, as the robust list is gone with the old program.
+    release_futexes();
 
-    let mut aspace = curr_ext.process_data().aspace.lock();
+    let ppid = proc.parent().map_or(0, |parent| parent.pid());
+    // Every process runs as root.
+    audit_exec(proc.pid(), ppid, 0, &path, &args);
+
+    let mut aspace = curr_ext.process_data().lock_aspace();
     aspace.unmap_user_areas()?;
+    curr_ext.process_data().lock_grows_down().clear();
+    curr_ext.process_data().heap.reset();
     map_trampoline(&mut aspace)?;
     axhal::arch::flush_tlb(None);
 
     let (entry_point, user_stack_base) =
-        load_user_app(&mut aspace, &args, &envs).map_err(|_| {
-            error!("Failed to load app {}", path);
-            LinuxError::ENOENT
+        load_user_app(&mut aspace, real_path.as_str(), &args, &envs).map_err(|err| {
+            error!("Failed to load app {}: {:?}", path, err);
+            load_error(err)
         })?;
     drop(aspace);
 
@@ -57,9 +150,23 @@ pub fn sys_execve(
         .rsplit_once('/')
         .map_or(path.as_str(), |(_, name)| name);
     curr.set_name(name);
-    *curr_ext.process_data().exe_path.write() = path;
+    *curr_ext.process_data().exe_path.write() = exe_path;
+    *curr_ext.process_data().exec_args.write() = Arc::new(ExecArgs::new(&args, &envs));
+    curr_ext.process_data().cred.write().on_exec();
+    // The handlers are gone with the old program.
+    *curr_ext.thread_data().signal_frames.lock() = SignalFrames::default();
+    // The stack it was created with is gone with the old image too.
+    curr_ext.thread_data().set_initial_sp(0);
 
-    // TODO: fd close-on-exec
+    // The processes sharing the table keep the descriptors closed here.
+    FD_TABLE.unshare();
+    // Dropped after the table is unlocked, since closing may block.
+    let closed = FD_TABLE.take_cloexec();
+    for file in &closed {
+        file_closed(file);
+    }
+    drop(closed);
+    notify_process_event(curr_ext.thread.process().pid(), ProcessEvent::Exec);
 
     tf.set_ip(entry_point.as_usize());
     tf.set_sp(user_stack_base.as_usize());
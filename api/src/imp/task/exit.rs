@@ -1,54 +1,151 @@
-use axprocess::Pid;
+use alloc::{sync::Arc, vec::Vec};
+use axprocess::{Pid, Process, ProcessGroup};
 use axsignal::{SignalInfo, Signo};
 use axtask::{TaskExtRef, current};
 use linux_raw_sys::general::SI_KERNEL;
-use starry_core::task::ProcessData;
+use starry_core::{
+    exit::{ExitStage, make_zombie, tear_down},
+    futex::FUTEX_TABLE,
+    job::{has_stopped_member, is_orphaned_group},
+    lockcheck::assert_lock_clean,
+    task::ProcessData,
+    wait::WaitStatus,
+};
 
 use crate::{
-    file::FD_TABLE,
+    file::{CONSOLE_TTY, FD_TABLE, process_exited},
+    imp::{CWD_MOUNT, release_futexes},
     ptr::UserPtr,
-    signal::{send_signal_process, send_signal_thread},
+    signal::{
+        drop_thread_signals, send_signal_process, send_signal_process_group, send_signal_thread,
+    },
 };
 
-pub fn do_exit(exit_code: i32, group_exit: bool) -> ! {
+/// Send `SIGHUP` and `SIGCONT` to the process groups orphaned by the exit of
+/// `process`, as POSIX requires, if they have stopped members which nothing
+/// could continue otherwise.
+///
+/// `process` may have been the last link to the session of its own group,
+/// through its parent, and of the groups of its `children`, which init
+/// adopted.
+fn hang_up_orphaned_groups(process: &Process, children: &[Arc<Process>]) {
+    let group = process.group();
+    let sid = group.session().sid();
+    let linked_to =
+        |other: &ProcessGroup| other.pgid() != group.pgid() && other.session().sid() == sid;
+
+    let mut groups = Vec::new();
+    if process
+        .parent()
+        .is_some_and(|parent| linked_to(&parent.group()))
+    {
+        groups.push(group.clone());
+    }
+    for child in children {
+        let child_group = child.group();
+        if linked_to(&child_group) && !groups.iter().any(|it| it.pgid() == child_group.pgid()) {
+            groups.push(child_group);
+        }
+    }
+
+    for group in groups {
+        if is_orphaned_group(&group) && has_stopped_member(&group) {
+            send_signal_process_group(&group, SignalInfo::new(Signo::SIGHUP, SI_KERNEL as _));
+            send_signal_process_group(&group, SignalInfo::new(Signo::SIGCONT, SI_KERNEL as _));
+        }
+    }
+}
+
+/// Do the work built into `stage` of the teardown of `process`, see
+/// [`starry_core::exit`].
+fn tear_down_stage(process: &Process, stage: ExitStage) {
+    match stage {
+        ExitStage::Files => {
+            // TODO: clear namespace resources
+            // FIXME: axns should drop all the resources
+            FD_TABLE.release();
+            process_exited(process.pid());
+            // The working directory no longer keeps its filesystem in use.
+            CWD_MOUNT.get().lock().take();
+        }
+        ExitStage::Memory => {
+            let Some(data) = process.data::<ProcessData>() else {
+                return;
+            };
+            if Arc::strong_count(&data.aspace) > 1 {
+                return;
+            }
+            // Only the kernel part is left, which the thread exiting still
+            // runs in.
+            if let Err(err) = data.lock_aspace().unmap_user_areas() {
+                warn!("Failed to unmap the memory of {}: {:?}", process.pid(), err);
+            }
+            data.lock_grows_down().clear();
+            data.heap.reset();
+            axhal::arch::flush_tlb(None);
+        }
+        ExitStage::Terminal => CONSOLE_TTY.process_exited(process),
+        ExitStage::Children => {
+            let children = process.children();
+            make_zombie(process);
+            hang_up_orphaned_groups(process, &children);
+        }
+        ExitStage::Zombie => {
+            let Some(parent) = process.parent() else {
+                return;
+            };
+            if let Some(signo) = process.data::<ProcessData>().and_then(|it| it.exit_signal) {
+                let _ = send_signal_process(&parent, SignalInfo::new(signo, SI_KERNEL as _));
+            }
+            if let Some(data) = parent.data::<ProcessData>() {
+                data.child_exit_wq.notify_all(false)
+            }
+        }
+        ExitStage::Vfork | ExitStage::SharedMemory => {}
+    }
+}
+
+/// Exit the current thread, and the whole process if `group_exit` is set,
+/// with `status` as the exit code of the process once its last thread is
+/// gone.
+pub fn do_exit(status: WaitStatus, group_exit: bool) -> ! {
+    let exit_code = status.encode();
     let curr = current();
     let curr_ext = curr.task_ext();
 
     let thread = &curr_ext.thread;
-    info!("{:?} exit with code: {}", thread, exit_code);
+    info!("{:?} exit with status: {:?}", thread, status);
+    assert_lock_clean("exit");
+    curr_ext.thread_data().mark_exited();
+    drop_thread_signals();
 
+    release_futexes();
     let clear_child_tid = UserPtr::<Pid>::from(curr_ext.thread_data().clear_child_tid());
     if let Ok(clear_tid) = clear_child_tid.get_as_mut() {
         *clear_tid = 0;
 
-        let guard = curr_ext
+        // The queue is let go of before yielding, as its guard locks the
+        // futex table when dropped.
+        let key = curr_ext
             .process_data()
-            .futex_table
-            .get(clear_tid as *const _ as usize);
-        if let Some(futex) = guard {
+            .futex_key(clear_tid as *const _ as usize);
+        if let Some(futex) = FUTEX_TABLE.get(key) {
             futex.notify_one(false);
         }
         axtask::yield_now();
     }
 
     let process = thread.process();
+    curr_ext.process_data().add_exited_run_time(curr.cpu_time());
     if thread.exit(exit_code) {
-        process.exit();
-        if let Some(parent) = process.parent() {
-            if let Some(signo) = process.data::<ProcessData>().and_then(|it| it.exit_signal) {
-                let _ = send_signal_process(&parent, SignalInfo::new(signo, SI_KERNEL as _));
-            }
-            if let Some(data) = parent.data::<ProcessData>() {
-                data.child_exit_wq.notify_all(false)
-            }
-        }
-
-        process.exit();
-        // TODO: clear namespace resources
-        // FIXME: axns should drop all the resources
-        FD_TABLE.clear();
+        tear_down(process, |stage| tear_down_stage(process, stage));
     }
-    if group_exit && !process.is_group_exited() {
+    curr_ext.process_data().exec_gate.thread_exited();
+    // During an `execve`, which kills the other threads, they exit alone.
+    if group_exit
+        && curr_ext.process_data().exec_gate.begin_group_exit()
+        && !process.is_group_exited()
+    {
         process.group_exit();
         let sig = SignalInfo::new(Signo::SIGKILL, SI_KERNEL as _);
         for thr in process.threads() {
@@ -59,9 +156,9 @@ pub fn do_exit(exit_code: i32, group_exit: bool) -> ! {
 }
 
 pub fn sys_exit(exit_code: i32) -> ! {
-    do_exit(exit_code << 8, false)
+    do_exit(WaitStatus::exited(exit_code), false)
 }
 
 pub fn sys_exit_group(exit_code: i32) -> ! {
-    do_exit(exit_code << 8, true)
+    do_exit(WaitStatus::exited(exit_code), true)
 }
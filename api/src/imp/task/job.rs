@@ -0,0 +1,83 @@
+use alloc::sync::Arc;
+use axerrno::{LinuxError, LinuxResult};
+use axprocess::{Pid, Process};
+use axtask::{TaskExtRef, current};
+use starry_core::{
+    job::is_session_leader,
+    task::{add_process_group_to_table, get_process, get_process_group},
+};
+
+/// Get the process `pid`, or the current one if `pid` is 0.
+fn process_or_current(pid: Pid) -> LinuxResult<Arc<Process>> {
+    if pid == 0 {
+        Ok(current().task_ext().thread.process().clone())
+    } else {
+        get_process(pid)
+    }
+}
+
+pub fn sys_getpgid(pid: Pid) -> LinuxResult<isize> {
+    Ok(process_or_current(pid)?.group().pgid() as _)
+}
+
+pub fn sys_getsid(pid: Pid) -> LinuxResult<isize> {
+    Ok(process_or_current(pid)?.group().session().sid() as _)
+}
+
+/// Move the process `pid` to the process group `pgid`, creating it if
+/// `pgid` is the id of the process.
+///
+/// The process must be the caller or one of its children in the same
+/// session, and not a session leader. Whether a child has called `execve`
+/// is not tracked, so it never fails with `EACCES`.
+pub fn sys_setpgid(pid: Pid, pgid: i32) -> LinuxResult<isize> {
+    debug!("sys_setpgid <= pid: {}, pgid: {}", pid, pgid);
+    if pgid < 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let curr = current();
+    let caller = curr.task_ext().thread.process();
+    let proc = process_or_current(pid)?;
+    if proc.pid() != caller.pid() {
+        if proc
+            .parent()
+            .is_none_or(|parent| parent.pid() != caller.pid())
+        {
+            return Err(LinuxError::ESRCH);
+        }
+        if proc.group().session().sid() != caller.group().session().sid() {
+            return Err(LinuxError::EPERM);
+        }
+    }
+    if is_session_leader(&proc) {
+        return Err(LinuxError::EPERM);
+    }
+
+    let pgid = if pgid == 0 { proc.pid() } else { pgid as Pid };
+    if pgid == proc.pid() {
+        // The process may lead its group already.
+        if let Some(group) = proc.create_group() {
+            add_process_group_to_table(&group);
+        }
+        return Ok(0);
+    }
+    let group = get_process_group(pgid).map_err(|_| LinuxError::EPERM)?;
+    if group.session().sid() != proc.group().session().sid() || !proc.move_to_group(&group) {
+        return Err(LinuxError::EPERM);
+    }
+    Ok(0)
+}
+
+/// Start a new session led by the caller, in a new process group, without
+/// a controlling terminal.
+pub fn sys_setsid() -> LinuxResult<isize> {
+    let curr = current();
+    let proc = curr.task_ext().thread.process();
+    if proc.group().pgid() == proc.pid() {
+        return Err(LinuxError::EPERM);
+    }
+    let (session, group) = proc.create_session().ok_or(LinuxError::EPERM)?;
+    add_process_group_to_table(&group);
+    debug!("sys_setsid => {}", session.sid());
+    Ok(session.sid() as _)
+}
@@ -1,5 +1,10 @@
+use core::mem::size_of;
+
 use axerrno::{LinuxError, LinuxResult};
+use axhal::time::monotonic_time;
+use axtask::{AxCpuMask, current};
 use linux_raw_sys::general::timespec;
+use starry_core::task::{ThreadData, WaitQueueWrapper, WaitResult, get_thread};
 
 use crate::{
     ptr::{UserConstPtr, UserPtr, nullable},
@@ -13,30 +18,56 @@ pub fn sys_sched_yield() -> LinuxResult<isize> {
 
 /// Sleep some nanoseconds
 ///
-/// TODO: should be woken by signals, and set errno
+/// A signal the thread does not block ends the sleep early with `EINTR`,
+/// and the time left in `rem`, e.g. to sleep again after a handler.
 pub fn sys_nanosleep(req: UserConstPtr<timespec>, rem: UserPtr<timespec>) -> LinuxResult<isize> {
-    let req = req.get_as_ref()?;
-
-    if req.tv_nsec < 0 || req.tv_nsec > 999_999_999 || req.tv_sec < 0 {
-        return Err(LinuxError::EINVAL);
-    }
-
-    let dur = req.to_time_value();
+    let dur = req.get_as_ref()?.try_to_time_value()?;
     debug!("sys_nanosleep <= {:?}", dur);
 
-    let now = axhal::time::monotonic_time();
+    let deadline = monotonic_time() + dur;
+    // Nothing notifies the queue, only a signal ends the wait early.
+    if WaitQueueWrapper::new().wait_interruptible(Some(dur)) != WaitResult::Interrupted {
+        return Ok(0);
+    }
+    if let Some(rem) = nullable!(rem.get_as_mut())? {
+        let left = deadline.saturating_sub(monotonic_time());
+        *rem = timespec::from_time_value(left);
+    }
+    Err(LinuxError::EINTR)
+}
 
-    axtask::sleep(dur);
+/// The size of a CPU mask passed to user space, in bytes: a bit for each
+/// CPU, rounded up to whole `long`s, like `cpumask_size()` of Linux.
+const CPUMASK_SIZE: usize = axconfig::SMP.div_ceil(usize::BITS as usize) * size_of::<usize>();
 
-    let after = axhal::time::monotonic_time();
-    let actual = after - now;
+/// The CPUs the thread `tid`, or the calling thread if 0, may run on.
+fn cpumask_of(tid: i32) -> LinuxResult<AxCpuMask> {
+    match tid {
+        0 => Ok(current().cpumask()),
+        1.. => get_thread(tid as _)?
+            .data::<ThreadData>()
+            .and_then(ThreadData::task)
+            .map(|task| task.cpumask())
+            .ok_or(LinuxError::ESRCH),
+        _ => Err(LinuxError::ESRCH),
+    }
+}
 
-    if let Some(diff) = dur.checked_sub(actual) {
-        if let Some(rem) = nullable!(rem.get_as_mut())? {
-            *rem = timespec::from_time_value(diff);
-        }
-        Err(LinuxError::EINTR)
-    } else {
-        Ok(0)
+/// Get the CPUs the thread `tid` may run on, as a bitmap in `len` bytes at
+/// `user_mask`.
+///
+/// As on Linux, `len` must be a whole number of `long`s holding a bit for
+/// each CPU, and the size of the bitmap written is returned. musl counts the
+/// bits for `sysconf(_SC_NPROCESSORS_ONLN)`.
+pub fn sys_sched_getaffinity(tid: i32, len: usize, user_mask: UserPtr<u8>) -> LinuxResult<isize> {
+    if len * 8 < axconfig::SMP || len % size_of::<usize>() != 0 {
+        return Err(LinuxError::EINVAL);
+    }
+    let cpumask = cpumask_of(tid)?;
+    let bytes = user_mask.get_as_mut_slice(CPUMASK_SIZE)?;
+    bytes.fill(0);
+    for cpu in (0..axconfig::SMP).filter(|&cpu| cpumask.get(cpu)) {
+        bytes[cpu / 8] |= 1 << (cpu % 8);
     }
+    Ok(CPUMASK_SIZE as _)
 }
@@ -1,6 +1,14 @@
-use axerrno::LinuxResult;
+use axerrno::{LinuxError, LinuxResult};
 use axtask::{TaskExtRef, current};
+use linux_raw_sys::prctl::PR_GET_SECCOMP;
 use num_enum::TryFromPrimitive;
+use starry_core::seccomp::{FilterAction, SyscallFilter};
+
+use crate::{
+    ptr::UserConstPtr,
+    sandbox::{PR_SET_PATH_SANDBOX, set_path_sandbox},
+    seccomp::PR_SET_SYSCALL_FILTER,
+};
 
 pub fn sys_getpid() -> LinuxResult<isize> {
     Ok(axtask::current().task_ext().thread.process().pid() as _)
@@ -52,6 +60,51 @@ pub fn sys_set_tid_address(clear_child_tid: usize) -> LinuxResult<isize> {
     Ok(curr.id().as_u64() as isize)
 }
 
+pub fn sys_prctl(
+    option: u32,
+    arg2: usize,
+    arg3: usize,
+    arg4: usize,
+    _arg5: usize,
+) -> LinuxResult<isize> {
+    debug!(
+        "sys_prctl <= option: {:#x}, args: {:#x}, {:#x}, {:#x}",
+        option, arg2, arg3, arg4
+    );
+
+    let curr = current();
+    match option {
+        PR_GET_SECCOMP => {
+            let filtered = !curr
+                .task_ext()
+                .process_data()
+                .syscall_filters
+                .read()
+                .is_empty();
+            // SECCOMP_MODE_FILTER
+            Ok(if filtered { 2 } else { 0 })
+        }
+        PR_SET_SYSCALL_FILTER => {
+            let action = FilterAction::try_from(arg2 as u32).map_err(|_| LinuxError::EINVAL)?;
+            let bitmap = UserConstPtr::<u8>::from(arg3).get_as_slice(arg4)?;
+            curr.task_ext()
+                .process_data()
+                .syscall_filters
+                .write()
+                .push(SyscallFilter::from_bitmap(bitmap, action))?;
+            Ok(0)
+        }
+        PR_SET_PATH_SANDBOX => {
+            set_path_sandbox(arg2.into(), arg3)?;
+            Ok(0)
+        }
+        _ => {
+            warn!("sys_prctl: unsupported option {}", option);
+            Err(LinuxError::EINVAL)
+        }
+    }
+}
+
 #[cfg(target_arch = "x86_64")]
 pub fn sys_arch_prctl(
     tf: &mut axhal::arch::TrapFrame,
@@ -6,7 +6,12 @@ use bitflags::bitflags;
 use linux_raw_sys::general::{
     __WALL, __WCLONE, __WNOTHREAD, WCONTINUED, WEXITED, WNOHANG, WNOWAIT, WUNTRACED,
 };
-use starry_core::task::ProcessData;
+use starry_core::{
+    exit::reap,
+    job::JobEvent,
+    task::{ProcessData, WaitResult},
+    wait::WaitStatus,
+};
 
 use crate::ptr::{UserPtr, nullable};
 
@@ -88,19 +93,44 @@ pub fn sys_waitpid(pid: i32, exit_code_ptr: UserPtr<i32>, options: u32) -> Linux
     }
 
     let exit_code = nullable!(exit_code_ptr.get_as_mut())?;
+    let mut interrupted = false;
     loop {
         if let Some(child) = children.iter().find(|child| child.is_zombie()) {
             if !options.contains(WaitOptions::WNOWAIT) {
-                child.free();
+                if let Some(child_data) = child.data::<ProcessData>() {
+                    proc_data.times().add_reaped_child(child_data.times());
+                }
+                reap(child);
             }
             if let Some(exit_code) = exit_code {
+                // Already encoded, see `do_exit`.
                 *exit_code = child.exit_code();
             }
             return Ok(child.pid() as _);
+        } else if let Some((child, event)) = children.iter().find_map(|child| {
+            let event = child.data::<ProcessData>()?.job.take_event(
+                |event| match event {
+                    JobEvent::Stopped(_) => options.contains(WaitOptions::WUNTRACED),
+                    JobEvent::Continued => options.contains(WaitOptions::WCONTINUED),
+                },
+                options.contains(WaitOptions::WNOWAIT),
+            )?;
+            Some((child, event))
+        }) {
+            if let Some(exit_code) = exit_code {
+                *exit_code = WaitStatus::from(event).encode();
+            }
+            return Ok(child.pid() as _);
         } else if options.contains(WaitOptions::WNOHANG) {
             return Ok(0);
+        } else if interrupted {
+            return Err(LinuxError::EINTR);
         } else {
-            proc_data.child_exit_wq.wait();
+            // Interruptible, like in Linux, but the children are checked
+            // once more first, as `SIGCHLD` comes just before their change
+            // is seen.
+            interrupted =
+                proc_data.child_exit_wq.wait_interruptible(None) == WaitResult::Interrupted;
         }
     }
 }
@@ -1,31 +1,94 @@
+use core::time::Duration;
+
 use axerrno::{LinuxError, LinuxResult};
-use axhal::time::{monotonic_time, monotonic_time_nanos, nanos_to_ticks, wall_time};
+use axhal::time::{TimeValue, monotonic_time, wall_time};
+use axprocess::Pid;
+use axtask::{TaskExtRef, current};
 use linux_raw_sys::general::{
-    __kernel_clockid_t, CLOCK_MONOTONIC, CLOCK_REALTIME, timespec, timeval,
+    __kernel_clockid_t, CLOCK_BOOTTIME, CLOCK_MONOTONIC, CLOCK_PROCESS_CPUTIME_ID, CLOCK_REALTIME,
+    CLOCK_THREAD_CPUTIME_ID, timespec, timeval,
+};
+use starry_core::{
+    stats,
+    task::{get_process, get_thread, process_run_time, thread_run_time},
 };
-use starry_core::task::time_stat_output;
 
-use crate::{ptr::UserPtr, time::TimeValueLike};
+use crate::{
+    ptr::{UserPtr, nullable},
+    time::TimeValueLike,
+};
 
-pub fn sys_clock_gettime(
-    clock_id: __kernel_clockid_t,
-    ts: UserPtr<timespec>,
-) -> LinuxResult<isize> {
-    let now = match clock_id as u32 {
+/// Read the CPU time clock `clock_id`, as made by `clock_getcpuclockid` and
+/// `pthread_getcpuclockid`.
+///
+/// The id of the thread or process is stored inverted above bit 3, bit 2
+/// tells a thread clock from a process clock and the lowest two bits select
+/// the kind of time, where 3 is invalid. An id of 0 stands for the caller.
+///
+/// Unknown ids fail with `EINVAL`, which libc turns into `ESRCH` where it
+/// should.
+fn cpu_clock_time(clock_id: __kernel_clockid_t) -> LinuxResult<Duration> {
+    if clock_id & 3 == 3 {
+        return Err(LinuxError::EINVAL);
+    }
+    // TODO: tell user time (`CPUCLOCK_VIRT`) apart from the total time
+    let id = !(clock_id >> 3) as Pid;
+    let curr = current();
+    let thread = &curr.task_ext().thread;
+    if clock_id & 4 != 0 {
+        if id == 0 {
+            return Ok(curr.cpu_time());
+        }
+        let target = get_thread(id).map_err(|_| LinuxError::EINVAL)?;
+        // Like Linux, only threads of the caller's process can be read.
+        if target.process().pid() != thread.process().pid() {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(thread_run_time(&target))
+    } else if id == 0 {
+        Ok(process_run_time(thread.process()))
+    } else {
+        let proc = get_process(id).map_err(|_| LinuxError::EINVAL)?;
+        Ok(process_run_time(&proc))
+    }
+}
+
+fn clock_time(clock_id: __kernel_clockid_t) -> LinuxResult<TimeValue> {
+    Ok(match clock_id as u32 {
         CLOCK_REALTIME => wall_time(),
         CLOCK_MONOTONIC => monotonic_time(),
+        CLOCK_BOOTTIME => Duration::from_nanos(stats::uptime_nanos()),
+        CLOCK_PROCESS_CPUTIME_ID => cpu_clock_time(!0 << 3)?,
+        CLOCK_THREAD_CPUTIME_ID => cpu_clock_time((!0 << 3) | 4)?,
+        _ if clock_id < 0 => cpu_clock_time(clock_id)?,
         _ => {
-            warn!(
-                "Called sys_clock_gettime for unsupported clock {}",
-                clock_id
-            );
+            warn!("Unsupported clock {}", clock_id);
             return Err(LinuxError::EINVAL);
         }
-    };
+    })
+}
+
+pub fn sys_clock_gettime(
+    clock_id: __kernel_clockid_t,
+    ts: UserPtr<timespec>,
+) -> LinuxResult<isize> {
+    let now = clock_time(clock_id)?;
     *ts.get_as_mut()? = timespec::from_time_value(now);
     Ok(0)
 }
 
+pub fn sys_clock_getres(
+    clock_id: __kernel_clockid_t,
+    res: UserPtr<timespec>,
+) -> LinuxResult<isize> {
+    // Make sure the clock exists.
+    clock_time(clock_id)?;
+    if let Some(res) = nullable!(res.get_as_mut())? {
+        *res = timespec::from_time_value(Duration::from_nanos(1));
+    }
+    Ok(0)
+}
+
 pub fn sys_gettimeofday(ts: UserPtr<timeval>) -> LinuxResult<isize> {
     *ts.get_as_mut()? = timeval::from_time_value(wall_time());
     Ok(0)
@@ -43,13 +106,18 @@ pub struct Tms {
     tms_cstime: usize,
 }
 
+/// Get the CPU time of the process and of its reaped children, in clock
+/// ticks, and return the ticks since boot.
 pub fn sys_times(tms: UserPtr<Tms>) -> LinuxResult<isize> {
-    let (_, utime_us, _, stime_us) = time_stat_output();
+    let times = current().task_ext().process_data().times();
+    let ticks = |ns: usize| stats::nanos_to_user_ticks(ns as u64) as usize;
+    let (utime, stime) = times.own();
+    let (cutime, cstime) = times.children();
     *tms.get_as_mut()? = Tms {
-        tms_utime: utime_us,
-        tms_stime: stime_us,
-        tms_cutime: utime_us,
-        tms_cstime: stime_us,
+        tms_utime: ticks(utime),
+        tms_stime: ticks(stime),
+        tms_cutime: ticks(cutime),
+        tms_cstime: ticks(cstime),
     };
-    Ok(nanos_to_ticks(monotonic_time_nanos()) as _)
+    Ok(stats::nanos_to_user_ticks(stats::uptime_nanos()) as _)
 }
@@ -5,9 +5,16 @@
 extern crate axlog;
 extern crate alloc;
 
+pub mod abi;
+pub mod args;
+pub mod blocking;
+pub mod errlog;
 pub mod file;
+pub mod fpstate;
 pub mod path;
 pub mod ptr;
+pub mod sandbox;
+pub mod seccomp;
 pub mod signal;
 pub mod sockaddr;
 pub mod time;
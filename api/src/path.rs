@@ -1,30 +1,100 @@
-use core::{ffi::c_int, fmt, ops::Deref};
+use core::{
+    ffi::c_int,
+    fmt, mem,
+    ops::Deref,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use alloc::{
-    collections::btree_map::BTreeMap,
+    collections::{btree_map::BTreeMap, vec_deque::VecDeque},
+    format,
     string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
 };
 use axerrno::{AxError, AxResult, LinuxError, LinuxResult};
-use axfs::api::canonicalize;
-use linux_raw_sys::general::AT_FDCWD;
-use spin::RwLock;
-
-use crate::file::{Directory, File, FileLike};
+use axfs::{CwdRef, api::canonicalize};
+use axns::{ResArc, def_resource};
+use linux_raw_sys::general::{
+    AT_EACCESS, AT_EMPTY_PATH, AT_FDCWD, AT_NO_AUTOMOUNT, AT_REMOVEDIR, AT_STATX_DONT_SYNC,
+    AT_STATX_FORCE_SYNC, AT_SYMLINK_FOLLOW, AT_SYMLINK_NOFOLLOW,
+};
+use spin::{Mutex, RwLock};
+use starry_core::sandbox::PathAccess;
+
+use crate::{
+    file::{
+        Directory, File, FileLike, Kstat, VirtualDirFile, get_file_like, lstat_at_path, move_inode,
+        move_inodes_under, move_mode, move_modes_under, move_times, move_times_under, move_xattrs,
+        move_xattrs_under, remove_inode, remove_mode, remove_times, remove_xattrs, stat_at_path,
+    },
+    imp::same_mount,
+    sandbox::check_path,
+};
 
 /// 一个规范化的文件路径表示
+///
+/// Paths are kept as the filesystems take them, with the bytes which are
+/// not UTF-8 escaped, see [`axfs::name`]. Syscalls take them so through
+/// [`UserConstPtr::get_as_path`](crate::ptr::UserConstPtr::get_as_path), and
+/// give the bytes back through [`axfs::name::decode`].
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
 pub struct FilePath(String);
 
 impl FilePath {
     /// 从路径字符串创建一个新的 `FilePath`，路径将被规范化。
     /// 输入路径可以是绝对路径或相对路径。
+    ///
+    /// Resolutions are cached until the names in the filesystem change, see
+    /// [`invalidate_path_cache`].
     pub fn new<P: AsRef<str>>(path: P) -> AxResult<Self> {
         let path = path.as_ref();
+        // The result of a relative path depends on the working directory.
+        let absolute = if path.starts_with('/') {
+            path.to_string()
+        } else {
+            axfs::api::current_dir()? + path
+        };
+        // An empty path is the working directory, without a trailing slash.
+        let key = (absolute, path.ends_with('/'));
+        let generation = PATH_GENERATION.load(Ordering::Acquire);
+        if let Some(resolved) = PATH_CACHE.lock().get(&key, generation) {
+            return Ok(Self(resolved));
+        }
+        let resolved = Self::resolve(&key.0, key.1)?;
+        PATH_CACHE
+            .lock()
+            .insert(key, resolved.0.clone(), generation);
+        Ok(resolved)
+    }
+
+    /// Like [`FilePath::new`], but a link name is kept rather than replaced by
+    /// the real path of its file, for the syscalls which act on the name
+    /// itself, like `unlink` and `rename`, see [`HardlinkManager`].
+    pub fn new_name<P: AsRef<str>>(path: P) -> AxResult<Self> {
+        let path = path.as_ref();
+        let absolute = if path.starts_with('/') {
+            path.to_string()
+        } else {
+            axfs::api::current_dir()? + path
+        };
+        Self::lexical(&absolute, path.ends_with('/'))
+    }
+
+    /// Resolve the absolute path `path`, uncached. The result ends with a
+    /// slash if `trailing_slash` is set.
+    fn resolve(path: &str, trailing_slash: bool) -> AxResult<Self> {
+        let name = Self::lexical(path, trailing_slash)?;
+        Ok(Self(HARDLINK_MANAGER.real_path(&name.0)))
+    }
+
+    /// Canonicalize the absolute path `path`, keeping a link name.
+    fn lexical(path: &str, trailing_slash: bool) -> AxResult<Self> {
         let canonical = canonicalize(path).map_err(|_| AxError::NotFound)?;
         let mut new_path = canonical.trim().to_string();
 
         // 如果原始路径以 '/' 结尾，那么规范化后的路径也应以 '/' 结尾
-        if path.ends_with('/') && !new_path.ends_with('/') {
+        if trailing_slash && !new_path.ends_with('/') {
             new_path.push('/');
         }
 
@@ -33,7 +103,7 @@ impl FilePath {
             "canonical path should start with /"
         );
 
-        Ok(Self(HARDLINK_MANAGER.real_path(&new_path)))
+        Ok(Self(new_path))
     }
 
     /// 返回底层路径的字符串切片
@@ -117,6 +187,17 @@ impl FilePath {
         FilePath::new(new_path)
     }
 
+    /// Like [`FilePath::join`], but keeping a link name, see
+    /// [`FilePath::new_name`].
+    pub fn join_name<P: AsRef<str>>(&self, path: P) -> AxResult<Self> {
+        let mut new_path = self.0.clone();
+        if !new_path.ends_with('/') {
+            new_path.push('/');
+        }
+        new_path.push_str(path.as_ref());
+        FilePath::new_name(new_path)
+    }
+
     /// 返回此路径组件的迭代器
     pub fn components(&self) -> impl Iterator<Item = &str> {
         self.0.trim_matches('/').split('/')
@@ -149,6 +230,71 @@ impl Deref for FilePath {
     }
 }
 
+/// How many resolutions [`PATH_CACHE`] keeps.
+const PATH_CACHE_SIZE: usize = 64;
+
+/// The generation of the names in the filesystem, bumped by every change to
+/// them, see [`invalidate_path_cache`].
+static PATH_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// The recent resolutions of [`FilePath::new`], least recently used first,
+/// keyed by the absolute path and whether the path ends with a slash.
+///
+/// Every entry is only valid for the generation it was made at, so a change
+/// to the names in the filesystem drops them all at once. The cache is
+/// global, which is fine while every process has the same root.
+type PathKey = (String, bool);
+
+struct PathCache {
+    generation: u64,
+    entries: VecDeque<(PathKey, String)>,
+}
+
+static PATH_CACHE: Mutex<PathCache> = Mutex::new(PathCache {
+    generation: 0,
+    entries: VecDeque::new(),
+});
+
+impl PathCache {
+    /// Drop the entries if they are older than `generation`.
+    fn refresh(&mut self, generation: u64) {
+        if self.generation != generation {
+            self.entries.clear();
+            self.generation = generation;
+        }
+    }
+
+    /// Get the resolution of `key`, made at `generation`.
+    fn get(&mut self, key: &PathKey, generation: u64) -> Option<String> {
+        self.refresh(generation);
+        let pos = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(pos).unwrap();
+        let resolved = entry.1.clone();
+        self.entries.push_back(entry);
+        Some(resolved)
+    }
+
+    /// Remember the resolution of `key` made at `generation`, unless the
+    /// names have changed since.
+    fn insert(&mut self, key: PathKey, resolved: String, generation: u64) {
+        if PATH_GENERATION.load(Ordering::Acquire) != generation {
+            return;
+        }
+        self.refresh(generation);
+        if self.entries.len() >= PATH_CACHE_SIZE {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((key, resolved));
+    }
+}
+
+/// Record a change to the names in the filesystem, e.g. a new directory, a
+/// removed link or a mount, which makes the cached resolutions of
+/// [`FilePath::new`] stale.
+pub fn invalidate_path_cache() {
+    PATH_GENERATION.fetch_add(1, Ordering::Release);
+}
+
 /// 错误类型
 #[derive(Debug)]
 pub enum LinkError {
@@ -156,6 +302,8 @@ pub enum LinkError {
     InvalidPath, // 无效路径
     NotFound,    // 文件不存在
     NotFile,     // 不是文件
+    IsDir,       // 是目录
+    CrossDevice, // 不在同一个文件系统
 }
 
 impl From<LinkError> for AxError {
@@ -165,13 +313,20 @@ impl From<LinkError> for AxError {
             LinkError::InvalidPath => AxError::InvalidInput,
             LinkError::NotFound => AxError::NotFound,
             LinkError::NotFile => AxError::InvalidInput,
+            LinkError::IsDir => AxError::IsADirectory,
+            LinkError::CrossDevice => AxError::InvalidInput,
         }
     }
 }
 
 impl From<LinkError> for LinuxError {
     fn from(err: LinkError) -> LinuxError {
-        AxError::from(err).into()
+        match err {
+            // Like Linux, which does not link directories.
+            LinkError::IsDir => LinuxError::EPERM,
+            LinkError::CrossDevice => LinuxError::EXDEV,
+            _ => AxError::from(err).into(),
+        }
     }
 }
 
@@ -179,6 +334,35 @@ impl From<LinkError> for LinuxError {
 pub static HARDLINK_MANAGER: HardlinkManager = HardlinkManager::new();
 
 /// A manager for hardlinks
+///
+/// The filesystems below have no hard links, so a link is a name which is
+/// not on the filesystem, mapped to the real path of its file, which is.
+/// [`FilePath::new`] replaces a link name with that real path, whose count
+/// of names includes the links to it.
+///
+/// A change of the names in the filesystem must not leave a link to a file
+/// which is gone or moved, nor a link name which a new file of that name
+/// would be taken for. So every such change goes through the manager,
+/// which, holding its lock throughout:
+///
+/// 1. changes the filesystem, and returns if that fails;
+/// 2. updates the links: drops the names removed, and moves the names and
+///    the files moved;
+/// 3. moves or drops what is kept of the files by real path, like their
+///    timestamps and inode numbers;
+/// 4. calls [`invalidate_path_cache`].
+///
+/// A resolution reads the generation of the cache before it reads the
+/// links, so one which read them before step 2 has an older generation than
+/// step 4 makes, and is never taken from the cache.
+///
+/// `unlink` is [`HardlinkManager::unlink`], `rename` is
+/// [`HardlinkManager::rename`], and `rmdir` and `mount`, which would remove
+/// or hide link names, go through [`HardlinkManager::without_links_under`].
+/// `umount` drops the links of the filesystem it takes away with
+/// [`HardlinkManager::forget_under`]. The syscalls which act on a name,
+/// rather than on the file it refers to, take it with
+/// [`FilePath::new_name`], which keeps a link name as it is.
 pub struct HardlinkManager {
     inner: RwLock<LinkManagerInner>,
 }
@@ -187,6 +371,37 @@ struct LinkManagerInner {
     ref_counts: BTreeMap<String, usize>,
 }
 
+/// Whether `path` is in the directory `dir`, at any depth.
+fn is_under(path: &str, dir: &str) -> bool {
+    path.strip_prefix(dir.trim_end_matches('/'))
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Move what is kept of the file at the real path `from` by path to `to`.
+fn move_metadata(from: &str, to: &str) {
+    move_inode(from, to);
+    move_mode(from, to);
+    move_xattrs(from, to);
+    move_times(from, to);
+}
+
+/// Move what is kept of the files under the directory moved from the real
+/// path `from` to `to`.
+fn move_metadata_under(from: &str, to: &str) {
+    move_inodes_under(from, to);
+    move_modes_under(from, to);
+    move_xattrs_under(from, to);
+    move_times_under(from, to);
+}
+
+/// Forget what is kept of the removed file at the real path `path`.
+fn remove_metadata(path: &str) {
+    remove_times(path);
+    remove_inode(path);
+    remove_mode(path);
+    remove_xattrs(path);
+}
+
 // 关于innner的操作都在atomic_开头的函数中
 impl HardlinkManager {
     const fn new() -> Self {
@@ -198,33 +413,159 @@ impl HardlinkManager {
         }
     }
 
-    /// 创建链接
+    /// 创建链接：新名字 `src` 指向已有的文件 `dst`
     /// 如果目标路径不存在，则返回 `LinkError::NotFound`
-    /// 如果目标路径不是文件，则返回 `LinkError::NotFile`
+    /// 如果新名字已存在，则返回 `LinkError::LinkExists`
+    /// 如果目标路径是目录，则返回 `LinkError::IsDir`
+    /// 如果不在同一个文件系统，则返回 `LinkError::CrossDevice`
     pub fn create_link(&self, src: &FilePath, dst: &FilePath) -> Result<(), LinkError> {
+        let mut inner = self.inner.write();
         if !dst.exists() {
             return Err(LinkError::NotFound);
         }
-        if !dst.is_dir() {
-            return Err(LinkError::NotFile);
+        if src.exists() || inner.links.contains_key(src.as_str()) {
+            return Err(LinkError::LinkExists);
+        }
+        if axfs::api::metadata(dst.as_str()).is_ok_and(|it| it.is_dir()) {
+            return Err(LinkError::IsDir);
+        }
+        // Else unmounting the filesystem of one would leave the other.
+        if !same_mount(src.as_str(), dst.as_str()) {
+            return Err(LinkError::CrossDevice);
         }
 
-        let mut inner = self.inner.write();
         self.atomic_link_update(&mut inner, src, dst);
+        invalidate_path_cache();
         Ok(())
     }
 
-    /// 移除链接
-    /// 链接数量为零 或 没有链接时， 删除文件
-    /// 如果路径对应的链接不存在 或 路径对应的文件不存在，则返回 `None`
-    /// 否则返回链接的目标路径
-    pub fn remove_link(&self, src: &FilePath) -> Option<String> {
+    /// Remove the name `name`, as `unlink` does.
+    ///
+    /// A link name is dropped. A file with links is renamed to the name of
+    /// one of them, which is no longer a link. Only a file without links is
+    /// removed from the filesystem, and then forgotten.
+    pub fn unlink(&self, name: &FilePath) -> AxResult {
         let mut inner = self.inner.write();
-        self.atomic_link_remove(&mut inner, src).or_else(|| {
-            axfs::api::remove_file(src.as_str())
-                .ok()
-                .map(|_| src.to_string())
-        })
+        if self.atomic_link_remove(&mut inner, name.as_str()).is_none()
+            && self.atomic_promote(&mut inner, name.as_str())?.is_none()
+        {
+            axfs::api::remove_file(name.as_str())?;
+            remove_metadata(name.as_str());
+        }
+        invalidate_path_cache();
+        Ok(())
+    }
+
+    /// Move the name `old` to `new`, as `rename` does, replacing what `new`
+    /// names, unless `no_replace`.
+    ///
+    /// A link name is moved among the links only. A file or directory is
+    /// moved on the filesystem, and the links to it, or to the files in it,
+    /// follow it. What `new` names is removed first, like [`unlink`] does,
+    /// so a file with links keeps the name of one of them. Nothing changes
+    /// if both are names of the same file.
+    ///
+    /// [`unlink`]: HardlinkManager::unlink
+    pub fn rename(&self, old: &FilePath, new: &FilePath, no_replace: bool) -> LinuxResult {
+        let (old, new) = (old.as_str(), new.as_str());
+        let mut inner = self.inner.write();
+        let old_link = inner.links.get(old).cloned();
+        let new_link = inner.links.get(new).cloned();
+        let old_dir = match &old_link {
+            Some(_) => false,
+            None => axfs::api::metadata(old)?.is_dir(),
+        };
+        let new_dir = match &new_link {
+            Some(_) => Some(false),
+            None => axfs::api::metadata(new).ok().map(|it| it.is_dir()),
+        };
+        if old_link.as_deref().unwrap_or(old) == new_link.as_deref().unwrap_or(new) {
+            return Ok(());
+        }
+        if is_under(new, old) {
+            return Err(LinuxError::EINVAL);
+        }
+        if !same_mount(old_link.as_deref().unwrap_or(old), new) {
+            return Err(LinuxError::EXDEV);
+        }
+        match new_dir {
+            Some(_) if no_replace => return Err(LinuxError::EEXIST),
+            Some(true) if !old_dir => return Err(LinuxError::EISDIR),
+            Some(false) if old_dir => return Err(LinuxError::ENOTDIR),
+            Some(true) => {
+                if self.atomic_has_links_under(&inner, new) {
+                    return Err(LinuxError::ENOTEMPTY);
+                }
+                axfs::api::remove_dir(new)?;
+                remove_metadata(new);
+            }
+            Some(false) => {
+                if self.atomic_link_remove(&mut inner, new).is_none()
+                    && self.atomic_promote(&mut inner, new)?.is_none()
+                {
+                    axfs::api::remove_file(new)?;
+                    remove_metadata(new);
+                }
+            }
+            None => {}
+        }
+
+        match old_link {
+            // The count of the file is the same.
+            Some(target) => {
+                inner.links.remove(old);
+                inner.links.insert(new.to_string(), target);
+            }
+            None => {
+                axfs::api::rename(old, new)?;
+                if old_dir {
+                    self.atomic_move_under(&mut inner, old, new);
+                    move_metadata_under(old, new);
+                } else {
+                    self.atomic_retarget(&mut inner, old, new);
+                }
+                move_metadata(old, new);
+            }
+        }
+        invalidate_path_cache();
+        Ok(())
+    }
+
+    /// Run `f`, which removes or hides the directory `dir`, like `rmdir` or
+    /// `mount` on it, unless a link name or the file of a link is in it,
+    /// which fails with `busy`.
+    ///
+    /// `f` must not resolve paths, which takes the lock held meanwhile.
+    pub fn without_links_under<T>(
+        &self,
+        dir: &str,
+        busy: LinuxError,
+        f: impl FnOnce() -> LinuxResult<T>,
+    ) -> LinuxResult<T> {
+        let inner = self.inner.write();
+        if self.atomic_has_links_under(&inner, dir) {
+            return Err(busy);
+        }
+        let ret = f();
+        invalidate_path_cache();
+        drop(inner);
+        ret
+    }
+
+    /// Forget the links whose name or file is in the directory `dir`, which
+    /// the filesystem unmounted from it takes away.
+    pub fn forget_under(&self, dir: &str) {
+        let mut inner = self.inner.write();
+        let gone: Vec<String> = inner
+            .links
+            .iter()
+            .filter(|(src, dst)| is_under(src, dir) || is_under(dst, dir))
+            .map(|(src, _)| src.clone())
+            .collect();
+        for src in gone {
+            self.atomic_link_remove(&mut inner, &src);
+        }
+        invalidate_path_cache();
     }
 
     pub fn real_path(&self, path: &str) -> String {
@@ -236,13 +577,17 @@ impl HardlinkManager {
             .unwrap_or_else(|| path.to_string())
     }
 
-    pub fn link_count(&self, path: &FilePath) -> usize {
+    /// The number of names of the file at the real path `path`, its own
+    /// and those of the links to it, or 0 if it does not exist.
+    pub fn link_count(&self, path: &str) -> usize {
         let inner = self.inner.read();
-        inner
-            .ref_counts
-            .get(path.as_str())
-            .copied()
-            .unwrap_or_else(|| if path.exists() { 1 } else { 0 })
+        inner.ref_counts.get(path).copied().unwrap_or_else(|| {
+            if axfs::api::absolute_path_exists(path) {
+                1
+            } else {
+                0
+            }
+        })
     }
 
     // 原子操作helpers
@@ -258,27 +603,89 @@ impl HardlinkManager {
             self.decrease_ref_count(inner, &old_dst.to_string());
         }
         inner.links.insert(src.to_string(), dst.to_string());
-        *inner.ref_counts.entry(dst.to_string()).or_insert(0) += 1;
+        // The count includes the name of the file itself.
+        *inner.ref_counts.entry(dst.to_string()).or_insert(1) += 1;
     }
 
     /// 移除链接
     /// 如果链接不存在，则返回 `None`，否则返回链接的目标路径
-    fn atomic_link_remove(&self, inner: &mut LinkManagerInner, src: &FilePath) -> Option<String> {
-        inner.links.remove(src.as_str()).inspect(|dst| {
+    fn atomic_link_remove(&self, inner: &mut LinkManagerInner, src: &str) -> Option<String> {
+        inner.links.remove(src).inspect(|dst| {
             self.decrease_ref_count(inner, dst);
         })
     }
 
+    /// Give the file at the real path `path`, which loses that name, the
+    /// name of one of its links instead, which is no longer a link, and
+    /// point the other links to it.
+    ///
+    /// Returns the new real path of the file, or `None` if it has no links.
+    fn atomic_promote(&self, inner: &mut LinkManagerInner, path: &str) -> AxResult<Option<String>> {
+        let Some(heir) = inner
+            .links
+            .iter()
+            .find(|(_, dst)| *dst == path)
+            .map(|(src, _)| src.clone())
+        else {
+            return Ok(None);
+        };
+        axfs::api::rename(path, &heir)?;
+        inner.links.remove(&heir);
+        self.atomic_retarget(inner, path, &heir);
+        self.decrease_ref_count(inner, &heir);
+        move_metadata(path, &heir);
+        Ok(Some(heir))
+    }
+
+    /// Point the links to the file moved from the real path `from` to `to`,
+    /// and its count of names, to `to`.
+    fn atomic_retarget(&self, inner: &mut LinkManagerInner, from: &str, to: &str) {
+        for dst in inner.links.values_mut() {
+            if dst == from {
+                *dst = to.to_string();
+            }
+        }
+        if let Some(count) = inner.ref_counts.remove(from) {
+            inner.ref_counts.insert(to.to_string(), count);
+        }
+    }
+
+    /// Move the link names and the files of links in the directory moved
+    /// from `from` to `to`.
+    fn atomic_move_under(&self, inner: &mut LinkManagerInner, from: &str, to: &str) {
+        let from = from.trim_end_matches('/');
+        let to = to.trim_end_matches('/');
+        let moved = |path: String| match path.strip_prefix(from) {
+            Some(rest) if rest.starts_with('/') => format!("{}{}", to, rest),
+            _ => path,
+        };
+        inner.links = mem::take(&mut inner.links)
+            .into_iter()
+            .map(|(src, dst)| (moved(src), moved(dst)))
+            .collect();
+        inner.ref_counts = mem::take(&mut inner.ref_counts)
+            .into_iter()
+            .map(|(path, count)| (moved(path), count))
+            .collect();
+    }
+
+    /// Whether a link name or the file of a link is in the directory `dir`.
+    fn atomic_has_links_under(&self, inner: &LinkManagerInner, dir: &str) -> bool {
+        inner
+            .links
+            .iter()
+            .any(|(src, dst)| is_under(src, dir) || is_under(dst, dir))
+    }
+
     /// 减少引用计数
-    /// 如果引用计数为零，则删除链接，并删除文件，如果删除文件失败，则返回 `None`
+    /// 只剩文件本身的名字时，删除计数
     /// 如果链接不存在，则返回 `None`
     fn decrease_ref_count(&self, inner: &mut LinkManagerInner, path: &str) -> Option<()> {
         match inner.ref_counts.get_mut(path) {
             Some(count) => {
                 *count -= 1;
-                if *count == 0 {
+                if *count <= 1 {
                     inner.ref_counts.remove(path);
-                    axfs::api::remove_file(path).ok()?
                 }
                 Some(())
             }
@@ -290,17 +697,323 @@ impl HardlinkManager {
     }
 }
 
-pub fn handle_file_path(dirfd: c_int, path: &str) -> LinuxResult<FilePath> {
-    if path.starts_with('/') {
-        Ok(FilePath::new(path)?)
+/// Resolve the `path` of a syscall against `dirfd`, checking that the path
+/// sandbox of the process allows `access` to it, see [`check_path`].
+///
+/// The sandbox is checked on the path resolved, so that neither `..` nor a
+/// `dirfd` opened before it was set up leads out of it.
+pub fn handle_file_path(dirfd: c_int, path: &str, access: PathAccess) -> LinuxResult<FilePath> {
+    let path = if path.starts_with('/') {
+        FilePath::new(path)?
     } else if path.is_empty() {
-        Ok(FilePath::new(File::from_fd(dirfd)?.path())?)
+        FilePath::new(File::from_fd(dirfd)?.path())?
+    } else {
+        base_path(dirfd)?.join(path)?
+    };
+    check_path(&path, access)?;
+    Ok(path)
+}
+
+/// Like [`handle_file_path`], but keeping a link name, for the syscalls
+/// which act on the name itself, see [`FilePath::new_name`].
+///
+/// An empty `path` is `ENOENT`, as a name is never empty.
+pub fn handle_name_path(dirfd: c_int, path: &str, access: PathAccess) -> LinuxResult<FilePath> {
+    if path.is_empty() {
+        return Err(LinuxError::ENOENT);
+    }
+    let path = if path.starts_with('/') {
+        FilePath::new_name(path)?
+    } else {
+        base_path(dirfd)?.join_name(path)?
+    };
+    check_path(&path, access)?;
+    Ok(path)
+}
+
+/// Get the path a relative path is resolved against: the working directory
+/// if `dirfd` is `AT_FDCWD`, else the directory referred to by `dirfd`.
+fn base_path(dirfd: c_int) -> LinuxResult<FilePath> {
+    if dirfd == AT_FDCWD {
+        if cwd_removed() {
+            return Err(LinuxError::ENOENT);
+        }
+        Ok(FilePath::new("")?)
     } else {
-        let base = if dirfd == AT_FDCWD {
-            FilePath::new("")?
+        dir_path(dirfd)
+    }
+}
+
+/// Get the path of the directory referred to by `dirfd`.
+fn dir_path(dirfd: c_int) -> LinuxResult<FilePath> {
+    let any = get_file_like(dirfd)?.into_any();
+    if let Some(dir) = any.downcast_ref::<Directory>() {
+        if dir.is_removed() {
+            return Err(LinuxError::ENOENT);
+        }
+        Ok(FilePath::new(dir.path())?)
+    } else if let Some(dir) = any.downcast_ref::<VirtualDirFile>() {
+        Ok(FilePath::new(dir.path())?)
+    } else {
+        Err(LinuxError::ENOTDIR)
+    }
+}
+
+/// The directories there are handles on, by path: an open [`Directory`] or
+/// the working directory of a process. Each entry has the generation of the
+/// directory at the path, which changes when it is removed, and the number
+/// of handles on it, and goes with the last of them, so there are never
+/// more entries than handles.
+///
+/// A handle remembers the generation it was taken at, and refers to a
+/// removed directory once that changed, even if a new one was made at the
+/// same path. Generations come from [`NEXT_GENERATION`], so a path never
+/// gets back one it had.
+static DIR_GENERATIONS: RwLock<BTreeMap<String, DirGeneration>> = RwLock::new(BTreeMap::new());
+
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+struct DirGeneration {
+    generation: u64,
+    handles: usize,
+}
+
+fn generation_key(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    }
+}
+
+/// A handle on the directory at a path, which tells whether it has been
+/// removed since.
+pub struct DirHandle {
+    path: String,
+    generation: u64,
+}
+
+impl DirHandle {
+    /// Take a handle on the directory at `path`.
+    pub fn new(path: &str) -> Self {
+        let path = String::from(generation_key(path));
+        let mut generations = DIR_GENERATIONS.write();
+        let entry = generations
+            .entry(path.clone())
+            .or_insert_with(|| DirGeneration {
+                generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
+                handles: 0,
+            });
+        entry.handles += 1;
+        let generation = entry.generation;
+        drop(generations);
+        Self { path, generation }
+    }
+
+    /// Whether the directory has been removed since the handle was taken.
+    pub fn is_removed(&self) -> bool {
+        DIR_GENERATIONS
+            .read()
+            .get(&self.path)
+            .is_none_or(|entry| entry.generation != self.generation)
+    }
+}
+
+impl Clone for DirHandle {
+    fn clone(&self) -> Self {
+        if let Some(entry) = DIR_GENERATIONS.write().get_mut(&self.path) {
+            entry.handles += 1;
+        }
+        Self {
+            path: self.path.clone(),
+            generation: self.generation,
+        }
+    }
+}
+
+impl Drop for DirHandle {
+    fn drop(&mut self) {
+        let mut generations = DIR_GENERATIONS.write();
+        if let Some(entry) = generations.get_mut(&self.path) {
+            entry.handles -= 1;
+            if entry.handles == 0 {
+                generations.remove(&self.path);
+            }
+        }
+    }
+}
+
+/// Record the removal of the directory at `path`.
+pub fn bump_dir_generation(path: &str) {
+    invalidate_path_cache();
+    if let Some(entry) = DIR_GENERATIONS.write().get_mut(generation_key(path)) {
+        entry.generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+def_resource! {
+    /// The handle on the working directory, taken when it was entered.
+    pub static CWD_HANDLE: ResArc<CwdRef<DirHandle>> = ResArc::new();
+}
+
+impl CWD_HANDLE {
+    /// Return a reference to a copy of the inner handle.
+    pub fn copy_inner(&self) -> CwdRef<DirHandle> {
+        self.copy()
+    }
+
+    /// Return a reference to the same handle, as with `CLONE_FS`.
+    pub fn share_inner(&self) -> CwdRef<DirHandle> {
+        self.share()
+    }
+}
+
+#[ctor_bare::register_ctor]
+fn init_cwd_handle() {
+    CWD_HANDLE.init_new(CwdRef::new(DirHandle::new("/")));
+}
+
+/// Record that the working directory was just entered.
+pub fn enter_cwd() -> LinuxResult {
+    let cwd = axfs::api::current_dir()?;
+    *CWD_HANDLE.get().lock() = DirHandle::new(&cwd);
+    Ok(())
+}
+
+/// Whether the working directory has been removed.
+pub fn cwd_removed() -> bool {
+    CWD_HANDLE.get().lock().is_removed()
+}
+
+bitflags::bitflags! {
+    /// Flags accepted by the `*at` family of syscalls.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct AtFlags: u32 {
+        /// Do not dereference the final component if it is a symbolic link.
+        const SYMLINK_NOFOLLOW = AT_SYMLINK_NOFOLLOW;
+        /// Check access with the effective ids (`faccessat`).
+        const EACCESS = AT_EACCESS;
+        /// Remove a directory instead of a file (`unlinkat`).
+        const REMOVEDIR = AT_REMOVEDIR;
+        /// Dereference the final component if it is a symbolic link (`linkat`).
+        const SYMLINK_FOLLOW = AT_SYMLINK_FOLLOW;
+        /// Do not trigger automounts on the final component.
+        const NO_AUTOMOUNT = AT_NO_AUTOMOUNT;
+        /// Operate on `dirfd` itself when the path is empty.
+        const EMPTY_PATH = AT_EMPTY_PATH;
+        /// Force `statx` to sync attributes with the backing store.
+        const STATX_FORCE_SYNC = AT_STATX_FORCE_SYNC;
+        /// Let `statx` return whatever attributes are cached.
+        const STATX_DONT_SYNC = AT_STATX_DONT_SYNC;
+    }
+}
+
+impl AtFlags {
+    /// Parse the raw `flags` argument of an `*at` syscall, only accepting
+    /// the bits in `allowed`.
+    ///
+    /// Returns `EINVAL` if any other bit is set.
+    pub fn parse(flags: u32, allowed: AtFlags) -> LinuxResult<Self> {
+        if flags & !allowed.bits() != 0 {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(Self::from_bits_retain(flags))
+    }
+}
+
+/// The object an `*at` syscall operates on.
+pub enum AtTarget {
+    /// The file referred to by `dirfd` itself (`AT_EMPTY_PATH`).
+    Fd(Arc<dyn FileLike>),
+    /// A path resolved relative to `dirfd`.
+    Path(FilePath),
+}
+
+impl AtTarget {
+    /// Get the metadata of the target.
+    pub fn stat(&self) -> LinuxResult<Kstat> {
+        match self {
+            AtTarget::Fd(f) => f.stat(),
+            AtTarget::Path(path) => stat_at_path(path.as_str()),
+        }
+    }
+
+    /// Get the metadata of the target, without following a link in the
+    /// final component.
+    pub fn lstat(&self) -> LinuxResult<Kstat> {
+        match self {
+            AtTarget::Fd(f) => f.stat(),
+            AtTarget::Path(path) => lstat_at_path(path.as_str()),
+        }
+    }
+
+    /// Get the metadata of the target, following a link in the final
+    /// component unless `flags` contains `AT_SYMLINK_NOFOLLOW`.
+    pub fn stat_with(&self, flags: AtFlags) -> LinuxResult<Kstat> {
+        if flags.contains(AtFlags::SYMLINK_NOFOLLOW) {
+            self.lstat()
         } else {
-            FilePath::new(Directory::from_fd(dirfd)?.path())?
-        };
-        Ok(base.join(path)?)
+            self.stat()
+        }
+    }
+
+    /// Get the path of the target.
+    ///
+    /// Returns `ENOENT` if the target is a file descriptor without a path,
+    /// e.g. a pipe or a socket.
+    pub fn path(&self) -> LinuxResult<FilePath> {
+        match self {
+            AtTarget::Fd(f) => {
+                let any = f.clone().into_any();
+                if let Some(file) = any.downcast_ref::<File>() {
+                    Ok(FilePath::new(file.path())?)
+                } else if let Some(dir) = any.downcast_ref::<Directory>() {
+                    Ok(FilePath::new(dir.path())?)
+                } else if let Some(dir) = any.downcast_ref::<VirtualDirFile>() {
+                    Ok(FilePath::new(dir.path())?)
+                } else {
+                    Err(LinuxError::ENOENT)
+                }
+            }
+            AtTarget::Path(path) => Ok(path.clone()),
+        }
+    }
+}
+
+/// Resolve the `(dirfd, path, flags)` triple of an `*at` syscall.
+///
+/// - An absolute `path` ignores `dirfd`.
+/// - A relative `path` is resolved against the working directory if `dirfd`
+///   is `AT_FDCWD`, or against the directory referred to by `dirfd`.
+/// - An empty (or NULL) `path` refers to `dirfd` itself if `AT_EMPTY_PATH`
+///   is set, and is `ENOENT` otherwise.
+///
+/// The path sandbox is checked for `access` to a path, as in
+/// [`handle_file_path`], but not to `dirfd` itself, which is open already.
+///
+/// `AT_SYMLINK_NOFOLLOW` is left to the caller, see [`AtTarget::stat_with`].
+/// Only the synthetic trees have symbolic links so far. Callers are expected
+/// to have validated `flags` with [`AtFlags::parse`].
+pub fn resolve_at(
+    dirfd: c_int,
+    path: Option<&str>,
+    flags: AtFlags,
+    access: PathAccess,
+) -> LinuxResult<AtTarget> {
+    match path {
+        Some(path) if !path.is_empty() => {
+            Ok(AtTarget::Path(handle_file_path(dirfd, path, access)?))
+        }
+        _ => {
+            if !flags.contains(AtFlags::EMPTY_PATH) {
+                return Err(LinuxError::ENOENT);
+            }
+            if dirfd == AT_FDCWD {
+                let cwd = FilePath::new("")?;
+                check_path(&cwd, access)?;
+                Ok(AtTarget::Path(cwd))
+            } else {
+                Ok(AtTarget::Fd(get_file_like(dirfd)?))
+            }
+        }
     }
 }
@@ -1,34 +1,111 @@
 use core::{alloc::Layout, ffi::c_char, mem::transmute, ptr, slice, str};
 
+use alloc::borrow::Cow;
+
 use axerrno::{LinuxError, LinuxResult};
 use axhal::paging::MappingFlags;
+use axmm::AddrSpace;
 use axtask::{TaskExtRef, current};
-use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr, VirtAddrRange};
-use starry_core::mm::access_user_memory;
+use memory_addr::{MemoryAddr, VirtAddr, VirtAddrRange};
+use starry_core::{
+    mm::{GrowsDownAreas, PAGE_SIZE, access_user_memory},
+    task::cond_resched,
+};
+
+/// Checks that a syscall may access `range` of the user memory of `aspace`
+/// with `access_flags`, which every check of a user pointer goes through:
+///
+/// | `range`                                             | result        |
+/// |-----------------------------------------------------|---------------|
+/// | empty, wherever it is                               | `Ok`          |
+/// | in areas with `access_flags`, up to the end of one  | `Ok`          |
+/// | below a grows-down mapping, within its growth limit | `Ok`, grown   |
+/// | touching a `PROT_NONE` area                         | `EFAULT`      |
+/// | touching an area without `access_flags`             | `EFAULT`      |
+/// | spanning a hole between areas, or past the last one | `EFAULT`      |
+///
+/// A range below a grows-down mapping grows it down to its start, as a
+/// page fault there would.
+pub fn check_user_region(
+    aspace: &mut AddrSpace,
+    grows_down: &mut GrowsDownAreas,
+    range: VirtAddrRange,
+    access_flags: MappingFlags,
+) -> LinuxResult<()> {
+    if range.is_empty() {
+        return Ok(());
+    }
+    if areas_allow(aspace, range, access_flags)
+        || (grows_down.grow(aspace, range.start) && areas_allow(aspace, range, access_flags))
+    {
+        Ok(())
+    } else {
+        Err(LinuxError::EFAULT)
+    }
+}
+
+/// Whether the areas of `aspace` cover the non-empty `range` without a
+/// hole, each with `access_flags`.
+fn areas_allow(aspace: &AddrSpace, range: VirtAddrRange, access_flags: MappingFlags) -> bool {
+    const ACCESS: MappingFlags = MappingFlags::READ
+        .union(MappingFlags::WRITE)
+        .union(MappingFlags::EXECUTE);
+
+    let mut start = range.start;
+    for (area, flags, _) in aspace.areas() {
+        if area.end <= start {
+            continue;
+        }
+        // A hole before the area fails, and so does a `PROT_NONE` area,
+        // whose flags are only `USER`, whatever `access_flags` are.
+        if area.start > start || !flags.intersects(ACCESS) || !flags.contains(access_flags) {
+            return false;
+        }
+        start = area.end;
+        if start >= range.end {
+            return true;
+        }
+    }
+    false
+}
 
 fn check_region(start: VirtAddr, layout: Layout, access_flags: MappingFlags) -> LinuxResult<()> {
     let align = layout.align();
     if start.as_usize() & (align - 1) != 0 {
         return Err(LinuxError::EFAULT);
     }
+    // A region wrapping around the address space is never user memory.
+    let end = start
+        .as_usize()
+        .checked_add(layout.size())
+        .ok_or(LinuxError::EFAULT)?;
+    if layout.size() == 0 {
+        return Ok(());
+    }
 
     let task = current();
-    let mut aspace = task.task_ext().process_data().aspace.lock();
-
-    if !aspace.check_region_access(
+    let process_data = task.task_ext().process_data();
+    let mut aspace = process_data.lock_aspace();
+    check_user_region(
+        &mut aspace,
+        &mut process_data.lock_grows_down(),
         VirtAddrRange::from_start_size(start, layout.size()),
         access_flags,
-    ) {
-        return Err(LinuxError::EFAULT);
-    }
+    )?;
 
     let page_start = start.align_down_4k();
-    let page_end = (start + layout.size()).align_up_4k();
+    let page_end = VirtAddr::from(end).align_up_4k();
     aspace.populate_area(page_start, page_end - page_start)?;
 
     Ok(())
 }
 
+/// The layout of `len` values of `T`, failing with `EFAULT` if it is too
+/// large for any memory.
+fn array_layout<T>(len: usize) -> LinuxResult<Layout> {
+    Layout::array::<T>(len).map_err(|_| LinuxError::EFAULT)
+}
+
 fn check_null_terminated<T: PartialEq + Default>(
     start: VirtAddr,
     access_flags: MappingFlags,
@@ -40,45 +117,54 @@ fn check_null_terminated<T: PartialEq + Default>(
 
     let zero = T::default();
 
-    let mut page = start.align_down_4k();
+    let mut page = start.align_down(PAGE_SIZE);
 
     let start = start.as_ptr_of::<T>();
     let mut len = 0;
 
-    access_user_memory(|| {
-        loop {
-            // SAFETY: This won't overflow the address space since we'll check
-            // it below.
-            let ptr = unsafe { start.add(len) };
-            while ptr as usize >= page.as_ptr() as usize {
-                // We cannot prepare `aspace` outside of the loop, since holding
-                // aspace requires a mutex which would be required on page
-                // fault, and page faults can trigger inside the loop.
-
-                // TODO: this is inefficient, but we have to do this instead of
-                // querying the page table since the page might has not been
-                // allocated yet.
-                let task = current();
-                let aspace = task.task_ext().process_data().aspace.lock();
-                if !aspace.check_region_access(
-                    VirtAddrRange::from_start_size(page, PAGE_SIZE_4K),
-                    access_flags,
-                ) {
-                    return Err(LinuxError::EFAULT);
+    // A page at a time, as the string can be long enough for other tasks to
+    // need the CPU in between, which cannot be given up while accessing user
+    // memory.
+    loop {
+        // We cannot hold `aspace` while reading, since page faults on the
+        // page require it.
+
+        // TODO: this is inefficient, but we have to do this instead of
+        // querying the page table since the page might has not been
+        // allocated yet.
+        {
+            let task = current();
+            let process_data = task.task_ext().process_data();
+            check_user_region(
+                &mut process_data.lock_aspace(),
+                &mut process_data.lock_grows_down(),
+                VirtAddrRange::from_start_size(page, PAGE_SIZE),
+                access_flags,
+            )?;
+        }
+        page += PAGE_SIZE;
+
+        let found = access_user_memory(|| {
+            loop {
+                // SAFETY: This won't overflow the address space since the
+                // page was checked above.
+                let ptr = unsafe { start.add(len) };
+                if ptr as usize >= page.as_usize() {
+                    return false;
                 }
-
-                page += PAGE_SIZE_4K;
-            }
-
-            // This might trigger a page fault
-            // SAFETY: The pointer is valid and points to a valid memory region.
-            if unsafe { ptr.read_volatile() } == zero {
-                break;
+                // This might trigger a page fault
+                // SAFETY: The pointer is valid and points to a valid memory region.
+                if unsafe { ptr.read_volatile() } == zero {
+                    return true;
+                }
+                len += 1;
             }
-            len += 1;
+        });
+        if found {
+            break;
         }
-        Ok(())
-    })?;
+        cond_resched();
+    }
 
     Ok(len)
 }
@@ -117,11 +203,11 @@ impl<T> UserPtr<T> {
     }
 
     pub fn get_as_mut_slice(self, len: usize) -> LinuxResult<&'static mut [T]> {
-        check_region(
-            self.address(),
-            Layout::array::<T>(len).unwrap(),
-            Self::ACCESS_FLAGS,
-        )?;
+        // Wherever the pointer is, as nothing is accessed.
+        if len == 0 {
+            return Ok(&mut []);
+        }
+        check_region(self.address(), array_layout::<T>(len)?, Self::ACCESS_FLAGS)?;
         Ok(unsafe { slice::from_raw_parts_mut(self.0, len) })
     }
 
@@ -168,11 +254,11 @@ impl<T> UserConstPtr<T> {
     }
 
     pub fn get_as_slice(self, len: usize) -> LinuxResult<&'static [T]> {
-        check_region(
-            self.address(),
-            Layout::array::<T>(len).unwrap(),
-            Self::ACCESS_FLAGS,
-        )?;
+        // Wherever the pointer is, as nothing is accessed.
+        if len == 0 {
+            return Ok(&[]);
+        }
+        check_region(self.address(), array_layout::<T>(len)?, Self::ACCESS_FLAGS)?;
         Ok(unsafe { slice::from_raw_parts(self.0, len) })
     }
 
@@ -186,13 +272,26 @@ impl<T> UserConstPtr<T> {
 }
 
 impl UserConstPtr<c_char> {
-    /// Get the pointer as `&str`, validating the memory region.
-    pub fn get_as_str(self) -> LinuxResult<&'static str> {
+    /// Get the pointer as the bytes before its NUL, validating the memory
+    /// region.
+    pub fn get_as_bytes_nul(self) -> LinuxResult<&'static [u8]> {
         let slice = self.get_as_null_terminated()?;
         // SAFETY: c_char is u8
-        let slice = unsafe { transmute::<&[c_char], &[u8]>(slice) };
+        Ok(unsafe { transmute::<&[c_char], &[u8]>(slice) })
+    }
+
+    /// Get the pointer as `&str`, validating the memory region.
+    pub fn get_as_str(self) -> LinuxResult<&'static str> {
+        str::from_utf8(self.get_as_bytes_nul()?).map_err(|_| LinuxError::EILSEQ)
+    }
 
-        str::from_utf8(slice).map_err(|_| LinuxError::EILSEQ)
+    /// Get the pointer as a path, as the filesystems take it, validating the
+    /// memory region.
+    ///
+    /// Unlike [`get_as_str`](Self::get_as_str), bytes which are not UTF-8
+    /// are taken, escaped as [`axfs::name`] does.
+    pub fn get_as_path(self) -> LinuxResult<Cow<'static, str>> {
+        Ok(axfs::name::encode(self.get_as_bytes_nul()?))
     }
 }
 
@@ -206,3 +305,83 @@ macro_rules! nullable {
     };
 }
 pub(crate) use nullable;
+
+/// Check [`check_user_region`] against each row of its table, in an
+/// address space of its own.
+///
+/// Panics on the first row answered wrong.
+#[cfg(feature = "kernel-tests")]
+pub fn self_test() {
+    use axmm::AreaKind;
+    use starry_core::mm::MAX_STACK_GROWTH;
+
+    const RW: MappingFlags = MappingFlags::READ
+        .union(MappingFlags::WRITE)
+        .union(MappingFlags::USER);
+    const RO: MappingFlags = MappingFlags::READ.union(MappingFlags::USER);
+    const PAGE: usize = PAGE_SIZE;
+
+    let base = VirtAddr::from(axconfig::plat::USER_SPACE_BASE);
+    let mut aspace = AddrSpace::new_empty(base, axconfig::plat::USER_SPACE_SIZE).unwrap();
+    let mut grows_down = GrowsDownAreas::default();
+    let rw = base + 0x10_0000;
+    let hole = rw + 2 * PAGE;
+    let rw2 = hole + PAGE;
+    let none = rw2 + PAGE;
+    let ro = none + PAGE;
+    let stack = base + 0x1000_0000;
+    for (start, size, flags) in [
+        (rw, 2 * PAGE, RW),
+        (rw2, PAGE, RW),
+        (none, PAGE, MappingFlags::USER),
+        (ro, PAGE, RO),
+        (stack, PAGE, RW),
+    ] {
+        aspace
+            .map_alloc(start, size, flags, false, AreaKind::Anonymous)
+            .unwrap();
+    }
+    grows_down.insert(stack, PAGE, RW, AreaKind::Stack);
+
+    let mut check = |start: VirtAddr, size: usize, access_flags: MappingFlags| {
+        check_user_region(
+            &mut aspace,
+            &mut grows_down,
+            VirtAddrRange::from_start_size(start, size),
+            access_flags,
+        )
+    };
+    let read = MappingFlags::READ;
+    let write = MappingFlags::READ | MappingFlags::WRITE;
+    let efault = Err(LinuxError::EFAULT);
+
+    assert_eq!(check(hole, 0, write), Ok(()), "empty, in a hole");
+    assert_eq!(check(rw, 2 * PAGE, write), Ok(()), "a whole area");
+    assert_eq!(
+        check(rw + PAGE, PAGE, write),
+        Ok(()),
+        "to the end of an area"
+    );
+    assert_eq!(check(rw2 - 8, 8, read), efault, "the end of a hole");
+    assert_eq!(check(rw + PAGE, 3 * PAGE, read), efault, "across a hole");
+    assert_eq!(check(none, 8, read), efault, "PROT_NONE");
+    assert_eq!(check(rw2, 2 * PAGE, read), efault, "into PROT_NONE");
+    assert_eq!(check(ro, PAGE, read), Ok(()), "reading a read-only area");
+    assert_eq!(check(ro, 8, write), efault, "writing a read-only area");
+    assert_eq!(check(ro + PAGE, 8, read), efault, "past the last area");
+
+    let limit = stack - MAX_STACK_GROWTH;
+    assert_eq!(check(limit - 8, 8, write), efault, "below the growth limit");
+    assert_eq!(
+        check(stack - PAGE - 8, 16, write),
+        Ok(()),
+        "below a grows-down mapping"
+    );
+    assert_eq!(
+        grows_down.start_of(stack - PAGE),
+        Some(stack - 2 * PAGE),
+        "grown down to the range"
+    );
+
+    info!("user region self test passed");
+}
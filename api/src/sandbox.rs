@@ -0,0 +1,90 @@
+use core::ffi::c_char;
+
+use alloc::{string::ToString, vec::Vec};
+use axerrno::{LinuxError, LinuxResult};
+use axtask::{TaskExtRef, current};
+use starry_core::{
+    audit::audit_denial,
+    sandbox::{MAX_RULES, PathAccess, PathRule, Ruleset},
+};
+
+use crate::{path::FilePath, ptr::UserConstPtr};
+
+/// The `prctl` option restricting the process to some paths, for good:
+/// `prctl(PR_SET_PATH_SANDBOX, rules, count)`.
+///
+/// `rules` points to `count` [`UserPathRule`]s. Every access they do not
+/// grant fails with `EACCES`, in this process and in the children it makes
+/// from then on. Another call may only take accesses away.
+pub const PR_SET_PATH_SANDBOX: u32 = 0x5359_5301;
+
+/// A rule as [`PR_SET_PATH_SANDBOX`] takes it.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct UserPathRule {
+    /// The absolute path the rule applies under.
+    pub prefix: UserConstPtr<c_char>,
+    /// The [`PathAccess`] bits granted there.
+    pub access: u32,
+}
+
+/// Install the `count` rules at `rules` as a ruleset of the current
+/// process, on top of those it has.
+///
+/// Fails with `EINVAL` if a prefix is not absolute or a rule grants no
+/// access or an unknown one, and with `E2BIG` if there are more than
+/// [`MAX_RULES`] rules or the process has as many rulesets as it may.
+pub fn set_path_sandbox(rules: UserConstPtr<UserPathRule>, count: usize) -> LinuxResult {
+    if count > MAX_RULES {
+        return Err(LinuxError::E2BIG);
+    }
+    let rules = rules
+        .get_as_slice(count)?
+        .iter()
+        .map(|rule| {
+            let access = PathAccess::from_bits(rule.access)
+                .filter(|access| !access.is_empty())
+                .ok_or(LinuxError::EINVAL)?;
+            let prefix = rule.prefix.get_as_path()?;
+            if !prefix.starts_with('/') {
+                return Err(LinuxError::EINVAL);
+            }
+            // Lexically, as the prefix need not exist.
+            let prefix = FilePath::new_name(&prefix)?;
+            Ok(PathRule::new(prefix.to_string(), access))
+        })
+        .collect::<LinuxResult<Vec<_>>>()?;
+
+    let curr = current();
+    let mut sandbox = curr.task_ext().process_data().sandbox.write();
+    if sandbox.is_full() {
+        return Err(LinuxError::E2BIG);
+    }
+    sandbox.push(Ruleset::new(rules));
+    Ok(())
+}
+
+/// Check that the path sandbox of the current process allows `access` to
+/// the canonical `path`.
+///
+/// A denial is audited, and fails with `EACCES`.
+pub fn check_path(path: &FilePath, access: PathAccess) -> LinuxResult {
+    let curr = current();
+    if curr
+        .task_ext()
+        .process_data()
+        .sandbox
+        .read()
+        .allows(path.as_str(), access)
+    {
+        return Ok(());
+    }
+    // Every process runs as root.
+    audit_denial(
+        curr.task_ext().thread.process().pid(),
+        0,
+        path.as_str(),
+        access,
+    );
+    Err(LinuxError::EACCES)
+}
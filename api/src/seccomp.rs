@@ -0,0 +1,59 @@
+use axerrno::{LinuxError, LinuxResult};
+use axhal::arch::TrapFrame;
+use axsignal::{SignalInfo, Signo};
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::SYS_SECCOMP;
+use starry_core::seccomp::FilterAction;
+
+use crate::signal::send_signal_thread;
+
+/// The `prctl` option installing a syscall filter:
+/// `prctl(PR_SET_SYSCALL_FILTER, action, bitmap, len)`.
+///
+/// `bitmap` points to `len` bytes, where bit `n % 8` of byte `n / 8` allows
+/// syscall `n`, and `action` is the [`FilterAction`] for every other
+/// syscall.
+pub const PR_SET_SYSCALL_FILTER: u32 = 0x5359_5300;
+
+/// The `AUDIT_ARCH_*` value reported in `SIGSYS`.
+const AUDIT_ARCH: u32 = if cfg!(target_arch = "x86_64") {
+    0xc000_003e
+} else if cfg!(target_arch = "riscv64") {
+    0xc000_00f3
+} else if cfg!(target_arch = "aarch64") {
+    0xc000_00b7
+} else {
+    0xc000_0102 // loongarch64
+};
+
+/// Check the syscall `sysno` against the filters of the current process.
+///
+/// Returns `ENOSYS` if the syscall must not run. A `SIGSYS` carrying the
+/// syscall number and architecture is sent as well if the filter says so.
+pub fn check_syscall(tf: &TrapFrame, sysno: usize) -> LinuxResult {
+    let curr = current();
+    let action = curr
+        .task_ext()
+        .process_data()
+        .syscall_filters
+        .read()
+        .action(sysno);
+    match action {
+        FilterAction::Allow => return Ok(()),
+        FilterAction::Errno => {}
+        FilterAction::Trap => {
+            let mut sig = SignalInfo::new(Signo::SIGSYS, SYS_SECCOMP as _);
+            // SAFETY: `_sigsys` is the member used by `SIGSYS`.
+            let sigsys = unsafe { &mut sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._sigsys };
+            sigsys._call_addr = tf.ip() as _;
+            sigsys._syscall = sysno as _;
+            sigsys._arch = AUDIT_ARCH;
+            // The syscall is denied all the same if the signal is lost.
+            if let Err(err) = send_signal_thread(&curr.task_ext().thread, sig) {
+                warn!("Failed to send SIGSYS for syscall {}: {:?}", sysno, err);
+            }
+        }
+    }
+    info!("Syscall {} denied by the filter: {:?}", sysno, action);
+    Err(LinuxError::ENOSYS)
+}
@@ -1,72 +1,628 @@
+use core::{mem, sync::atomic::Ordering, time::Duration};
+
 use axerrno::{LinuxError, LinuxResult};
 use axhal::{
     arch::TrapFrame,
+    time::{TimeValue, monotonic_time},
     trap::{POST_TRAP, register_trap_handler},
 };
 use axprocess::{Process, ProcessGroup, Thread};
-use axsignal::{SignalInfo, SignalOSAction, SignalSet};
+use axsignal::{
+    SignalActionFlags, SignalDisposition, SignalInfo, SignalOSAction, SignalSet, SignalStack, Signo,
+};
 use axtask::{TaskExtRef, current};
-use starry_core::task::{ProcessData, ThreadData};
+use linux_raw_sys::general::{
+    CLD_CONTINUED, CLD_STOPPED, MINSIGSTKSZ, SI_KERNEL, SS_DISABLE, SS_ONSTACK,
+};
+use starry_core::{
+    pressure::check_pending_signals,
+    resources::RLIMIT_SIGPENDING,
+    task::{
+        MAX_SIGNAL_NESTING, ProcessData, ThreadData, WaitMode, WaitQueueWrapper,
+        time_stat_on_user_trap, user_trap_frame,
+    },
+    wait::WaitStatus,
+};
 
-use crate::do_exit;
+use crate::{
+    do_exit, fpstate,
+    ptr::{UserConstPtr, UserPtr},
+};
 
 pub fn check_signals(tf: &mut TrapFrame, restore_blocked: Option<SignalSet>) -> bool {
-    let Some((sig, os_action)) = current()
-        .task_ext()
-        .thread_data()
-        .signal
-        .check_signals(tf, restore_blocked)
-    else {
+    let curr = current();
+    let signal = &curr.task_ext().thread_data().signal;
+    let sp = tf.sp();
+    // A handler for a signal taken on the alternate stack runs below where
+    // it was taken, rather than from the top of the stack again over the
+    // frames there, so the stack is disabled meanwhile.
+    let on_altstack = signal.with_stack_mut(|stack| is_on_sigaltstack(stack, sp));
+    let altstack = on_altstack.then(|| {
+        signal.with_stack_mut(|stack| {
+            let disabled = SignalStack {
+                flags: SS_DISABLE as _,
+                ..stack.clone()
+            };
+            mem::replace(stack, disabled)
+        })
+    });
+    let checked = signal.check_signals(tf, restore_blocked);
+    // What a handler finds in `uc_stack`, as the thread has it.
+    let saved_stack = match altstack {
+        Some(altstack) => {
+            signal.with_stack_mut(|stack| *stack = altstack.clone());
+            altstack
+        }
+        None => signal.with_stack_mut(|stack| stack.clone()),
+    };
+    let Some((sig, os_action)) = checked else {
         return false;
     };
 
+    signal_dequeued(&sig);
     let signo = sig.signo();
     match os_action {
         SignalOSAction::Terminate => {
-            do_exit(128 + signo as i32, true);
+            do_exit(WaitStatus::signaled(signo), true);
         }
         SignalOSAction::CoreDump => {
-            // TODO: implement core dump
-            do_exit(128 + signo as i32, true);
+            // TODO: implement core dump, and report it in the status
+            do_exit(WaitStatus::signaled(signo), true);
         }
         SignalOSAction::Stop => {
-            // TODO: implement stop
-            do_exit(1, true);
+            let curr = current();
+            let proc = curr.task_ext().thread.process();
+            if curr.task_ext().process_data().job.stop(signo) {
+                notify_parent_job(proc, CLD_STOPPED, signo);
+            }
+            wait_while_stopped(tf);
         }
         SignalOSAction::Continue => {
-            // TODO: implement continue
+            // The process was continued when the signal was sent.
         }
         SignalOSAction::Handler => {
-            // do nothing
+            let ucontext = tf.arg2();
+            let siginfo = save_altstack(ucontext, signo, saved_stack, on_altstack);
+            fpstate::save(tf, ucontext);
+            enter_signal_frame(signo, sp, on_altstack, tf.sp(), ucontext, siginfo);
         }
     }
     true
 }
 
+/// Whether the user stack pointer `sp` is on the alternate signal stack
+/// `stack`, which grows down from its end.
+pub fn is_on_sigaltstack(stack: &SignalStack, sp: usize) -> bool {
+    stack.flags & SS_DISABLE as _ == 0 && sp > stack.sp && sp - stack.sp <= stack.size
+}
+
+/// The offset of `uc_stack` in a `ucontext`, after `uc_flags` and `uc_link`,
+/// on every architecture.
+const UC_STACK_OFFSET: usize = 2 * size_of::<usize>();
+
+/// Fill in `uc_stack` of the `ucontext` at `ucontext` given to the handler
+/// for `signo`, if it takes one, with `stack`, the alternate signal stack of
+/// the thread, which the signal was taken on if `on_altstack`.
+///
+/// Returns whether the handler takes the `ucontext`.
+fn save_altstack(ucontext: usize, signo: Signo, mut stack: SignalStack, on_altstack: bool) -> bool {
+    let takes_ucontext = current().task_ext().process_data().signal.actions.lock()[signo]
+        .flags
+        .contains(SignalActionFlags::SIGINFO);
+    if !takes_ucontext {
+        return false;
+    }
+    if on_altstack {
+        stack.flags |= SS_ONSTACK as _;
+    }
+    if let Ok(saved) = UserPtr::<SignalStack>::from(ucontext + UC_STACK_OFFSET).get_as_mut() {
+        *saved = stack;
+    }
+    true
+}
+
+/// Set the alternate signal stack from `uc_stack` of the `ucontext` at
+/// `ucontext`, which the handler returning with `rt_sigreturn` may have
+/// changed, like Linux.
+///
+/// As `sigaltstack` would, it is left as it is while the thread runs on it
+/// at `sp`, or if `uc_stack` is not a valid stack.
+pub fn restore_altstack(ucontext: usize, sp: usize) {
+    let Ok(saved) = UserConstPtr::<SignalStack>::from(ucontext + UC_STACK_OFFSET).get_as_ref()
+    else {
+        return;
+    };
+    let disabled = saved.flags & SS_DISABLE as _ != 0;
+    if !disabled && saved.size <= MINSIGSTKSZ as usize {
+        return;
+    }
+    let saved = SignalStack {
+        flags: saved.flags & SS_DISABLE as _,
+        ..saved.clone()
+    };
+    current()
+        .task_ext()
+        .thread_data()
+        .signal
+        .with_stack_mut(|stack| {
+            if !is_on_sigaltstack(stack, sp) {
+                *stack = saved;
+            }
+        });
+}
+
+/// Record the frame of the handler for `signo` starting at `frame_sp`, for
+/// the signal taken at `sp`, and kill the process if handlers nest deeper
+/// than [`MAX_SIGNAL_NESTING`].
+///
+/// Without the limit, signals sent back and forth from handlers which do
+/// not block them pile up frames until the stack overflows, and the process
+/// dies of a fault which says nothing of why.
+fn enter_signal_frame(
+    signo: Signo,
+    sp: usize,
+    on_altstack: bool,
+    frame_sp: usize,
+    ucontext: usize,
+    siginfo: bool,
+) {
+    let curr = current();
+    let thr_data = curr.task_ext().thread_data();
+    let frame_on_altstack = thr_data
+        .signal
+        .with_stack_mut(|stack| is_on_sigaltstack(stack, frame_sp));
+    let depth = thr_data.signal_frames.lock().enter(
+        sp,
+        on_altstack,
+        frame_sp,
+        frame_on_altstack,
+        ucontext,
+        siginfo,
+    );
+    if depth > MAX_SIGNAL_NESTING {
+        error!(
+            "Signal storm: thread {} entered {} nested signal handlers, the last for {:?}, killing it with SIGSEGV",
+            curr.task_ext().thread.tid(),
+            depth,
+            signo
+        );
+        do_exit(WaitStatus::signaled(Signo::SIGSEGV), true);
+    }
+}
+
+/// Forget the signal frame returned from by `rt_sigreturn`, which the
+/// current thread made at `sp`.
+///
+/// Returns the address of the `ucontext` of the frame, and whether the
+/// handler took it, if the thread is in such a frame.
+pub fn leave_signal_frame(sp: usize) -> Option<(usize, bool)> {
+    let curr = current();
+    let thr_data = curr.task_ext().thread_data();
+    let on_altstack = thr_data
+        .signal
+        .with_stack_mut(|stack| is_on_sigaltstack(stack, sp));
+    thr_data.signal_frames.lock().leave(sp, on_altstack)
+}
+
 #[register_trap_handler(POST_TRAP)]
 fn post_trap_callback(tf: &mut TrapFrame, from_user: bool) {
     if !from_user {
         return;
     }
+    // Signals are delivered on `tf`, which must be the one returned to
+    // user space with, see `user_trap_frame`.
+    debug_assert_eq!(tf as *mut TrapFrame, user_trap_frame(&current()));
 
+    time_stat_on_user_trap();
+    check_cpu_limit();
     check_signals(tf, None);
+    // Another thread may have stopped the process.
+    if current().task_ext().process_data().job.is_stopped() {
+        wait_while_stopped(tf);
+        check_signals(tf, None);
+    }
+}
+
+/// Send the current process `SIGXCPU` or `SIGKILL` if it has used up its
+/// `RLIMIT_CPU`.
+fn check_cpu_limit() {
+    let curr = current();
+    let data = curr.task_ext().process_data();
+    let (utime_ns, stime_ns) = data.times().own();
+    if let Some(signo) = data.cpu_limit.check((utime_ns + stime_ns) as u64) {
+        let proc = curr.task_ext().thread.process();
+        let _ = send_signal_process(proc, SignalInfo::new(signo, SI_KERNEL as _));
+    }
+}
+
+/// Tell the parent of `proc` that it was stopped or continued, as `code`
+/// says, by `signo`.
+fn notify_parent_job(proc: &Process, code: u32, signo: Signo) {
+    let Some(parent) = proc.parent() else {
+        return;
+    };
+    let mut sig = SignalInfo::new(Signo::SIGCHLD, code as _);
+    // SAFETY: `_sigchld` is the member used by `SIGCHLD`.
+    unsafe {
+        let sigchld = &mut sig.0.__bindgen_anon_1.__bindgen_anon_1._sifields._sigchld;
+        sigchld._pid = proc.pid() as _;
+        sigchld._status = signo as _;
+    }
+    let _ = send_signal_process(&parent, sig);
+    if let Some(data) = parent.data::<ProcessData>() {
+        data.child_exit_wq.notify_all(false);
+    }
+}
+
+/// Wait until the current process is continued, or gets `SIGKILL`, with
+/// the user registers in `tf` open to a debugger meanwhile.
+fn wait_while_stopped(tf: &mut TrapFrame) {
+    let curr = current();
+    let thread_data = curr.task_ext().thread_data();
+    thread_data.while_stopped(tf, || curr.task_ext().process_data().job.wait_resumed());
+}
+
+/// Continue `proc` if `sig` is `SIGCONT`, which takes effect when it is
+/// sent, whether it is blocked, ignored or handled.
+fn continue_on_sigcont(proc: &Process, sig: &SignalInfo) {
+    if sig.signo() != Signo::SIGCONT {
+        return;
+    }
+    if let Some(data) = proc.data::<ProcessData>() {
+        if data.job.resume() {
+            notify_parent_job(proc, CLD_CONTINUED, Signo::SIGCONT);
+        }
+    }
+}
+
+/// Whether the current thread has a pending signal it does not block.
+///
+/// Blocking syscalls check this while they wait, and return early so the
+/// signal is delivered: with what they have done so far, or `EINTR` if
+/// nothing.
+pub fn has_pending_signal() -> bool {
+    current()
+        .task_ext()
+        .thread_data()
+        .interrupts_wait(WaitMode::Interruptible)
+}
+
+/// Whether a syscall the pending signals interrupted may be restarted once
+/// they are delivered: unless one of them runs a handler without
+/// `SA_RESTART`, as those stopping the process or ignored do not make it
+/// fail either.
+///
+/// The signals are checked when the syscall is interrupted rather than
+/// when they are delivered, so a handler installed in between is not taken
+/// into account.
+pub fn signals_restart() -> bool {
+    let curr = current();
+    let signal = &curr.task_ext().thread_data().signal;
+    let pending = signal.pending() & !signal.with_blocked_mut(|blocked| *blocked);
+    let actions = curr.task_ext().process_data().signal.actions.lock();
+    (1..=64)
+        .filter_map(Signo::from_repr)
+        .filter(|&signo| pending.has(signo))
+        .all(|signo| {
+            let action = &actions[signo];
+            !matches!(action.disposition, SignalDisposition::Handler(_))
+                || action.flags.contains(SignalActionFlags::RESTART)
+        })
 }
 
+/// Take a pending signal of `set`, from the current thread or from its
+/// process, without waiting.
+pub fn take_signal_in(set: SignalSet) -> Option<SignalInfo> {
+    let curr = current();
+    let signal = &curr.task_ext().thread_data().signal;
+    // `pending` covers the signals sent to the process as well, which any
+    // thread may take.
+    if signal.pending() & set == SignalSet::default() {
+        return None;
+    }
+    signal.wait_timeout(set, Some(Duration::ZERO))
+}
+
+/// Take a pending signal of `set`, from the thread or from the process,
+/// waiting until the monotonic time `deadline` for one.
+///
+/// Fails with `EINTR` if a signal outside `set` the thread does not block
+/// comes first, and with `EAGAIN` at the deadline.
+pub fn dequeue_signal_in(set: SignalSet, deadline: Option<TimeValue>) -> LinuxResult<SignalInfo> {
+    let curr = current();
+    let signal = &curr.task_ext().thread_data().signal;
+    // Nothing notifies the queue: a signal sent to the thread wakes it,
+    // which ends the wait if it is of `set`, or another it does not block.
+    let wq = WaitQueueWrapper::new();
+    loop {
+        if let Some(sig) = take_signal_in(set) {
+            return Ok(sig);
+        }
+        if has_pending_signal() {
+            return Err(LinuxError::EINTR);
+        }
+        let timeout = match deadline {
+            Some(deadline) => {
+                let now = monotonic_time();
+                if now >= deadline {
+                    return Err(LinuxError::EAGAIN);
+                }
+                Some(deadline - now)
+            }
+            None => None,
+        };
+        wq.wait_until(WaitMode::Interruptible, timeout, || {
+            signal.pending() & set != SignalSet::default()
+        });
+    }
+}
+
+/// Whether the current thread blocks `signo`, or its process ignores it.
+pub fn is_ignored_or_blocked(signo: Signo) -> bool {
+    let curr = current();
+    let ignored = matches!(
+        curr.task_ext().process_data().signal.actions.lock()[signo].disposition,
+        SignalDisposition::Ignore
+    );
+    ignored
+        || curr
+            .task_ext()
+            .thread_data()
+            .signal
+            .with_blocked_mut(|blocked| blocked.has(signo))
+}
+
+/// Whether `signo` is a realtime signal.
+///
+/// Every instance of a realtime signal is queued with its own
+/// [`SignalInfo`], and they are taken from the queue in order, lower numbers
+/// first. A standard signal is pending at most once.
+pub fn is_realtime(signo: Signo) -> bool {
+    signo as u8 >= Signo::SIGRTMIN as u8
+}
+
+/// Count `sig` as queued for `proc`, and for its thread `thr` if sent to it
+/// alone, if it is a realtime signal which is not ignored, and so stays
+/// queued until taken or dropped.
+fn signal_queued(proc: &ProcessData, thr: Option<&ThreadData>, sig: &SignalInfo) {
+    let signo = sig.signo();
+    if is_realtime(signo)
+        && !matches!(
+            proc.signal.actions.lock()[signo].disposition,
+            SignalDisposition::Ignore
+        )
+    {
+        proc.queued_rt_signals.fetch_add(1, Ordering::Relaxed);
+        if let Some(thr) = thr {
+            thr.queued_rt_signals.lock()[rt_index(signo)] += 1;
+        }
+    }
+}
+
+/// The index of the realtime signal `signo` in
+/// [`ThreadData::queued_rt_signals`].
+fn rt_index(signo: Signo) -> usize {
+    signo as usize - Signo::SIGRTMIN as usize
+}
+
+/// Uncount `sig`, taken from the queue of the thread `thr` of `proc`, or
+/// from that of the process.
+fn uncount(proc: &ProcessData, thr: &ThreadData, sig: &SignalInfo) {
+    let signo = sig.signo();
+    if !is_realtime(signo) {
+        return;
+    }
+    // A thread takes from its own queue first, so `sig` came from there if
+    // it holds any of its number.
+    let mut queued = thr.queued_rt_signals.lock();
+    queued[rt_index(signo)] = queued[rt_index(signo)].saturating_sub(1);
+    drop(queued);
+    let _ = proc
+        .queued_rt_signals
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+}
+
+/// Uncount `sig`, which the current thread took from its queue.
+pub fn signal_dequeued(sig: &SignalInfo) {
+    let curr = current();
+    let ext = curr.task_ext();
+    uncount(ext.process_data(), ext.thread_data(), sig);
+}
+
+/// Uncount the realtime signals left in the queue of the current thread,
+/// which has started exiting, so that they are dropped with it.
+pub fn drop_thread_signals() {
+    let curr = current();
+    let queued = mem::take(&mut *curr.task_ext().thread_data().queued_rt_signals.lock());
+    let dropped: usize = queued.iter().sum();
+    let _ = curr
+        .task_ext()
+        .process_data()
+        .queued_rt_signals
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+            Some(n.saturating_sub(dropped))
+        });
+}
+
+/// Drop the pending instances of `signo` in the current process, which
+/// ignores it now, as Linux does.
+///
+/// Each thread takes them from its own queue and from that of the process,
+/// blocking `signo` meanwhile as taking needs, unless it is gone already.
+pub fn drop_ignored_signals(signo: Signo) {
+    let curr = current();
+    let proc = curr.task_ext().process_data();
+    let mut set = SignalSet::default();
+    set.add(signo);
+    for thr in curr.task_ext().thread.process().threads() {
+        let Some(thr) = thr.data::<ThreadData>() else {
+            continue;
+        };
+        let signal = &thr.signal;
+        let was_blocked = signal.with_blocked_mut(|blocked| {
+            let was_blocked = blocked.has(signo);
+            blocked.add(signo);
+            was_blocked
+        });
+        while signal.pending().has(signo) {
+            let Some(sig) = signal.wait_timeout(set, Some(Duration::ZERO)) else {
+                break;
+            };
+            uncount(proc, thr, &sig);
+        }
+        if !was_blocked {
+            signal.with_blocked_mut(|blocked| blocked.remove(signo));
+        }
+    }
+}
+
+/// Fail with `EAGAIN` if `signo` is a realtime signal and `proc` has as many
+/// of them queued as `RLIMIT_SIGPENDING` allows.
+///
+/// Like Linux, this only limits signals queued with `rt_sigqueueinfo`, not
+/// those sent by `kill`.
+pub fn check_sigpending_limit(proc: &Process, signo: Signo) -> LinuxResult<()> {
+    let Some(data) = proc.data::<ProcessData>() else {
+        return Ok(());
+    };
+    let limit = data
+        .rlimits
+        .read()
+        .get(RLIMIT_SIGPENDING)
+        .map_or(0, |it| it.cur);
+    if is_realtime(signo) && data.queued_rt_signals.load(Ordering::Relaxed) as u64 >= limit {
+        return Err(LinuxError::EAGAIN);
+    }
+    Ok(())
+}
+
+/// Send `signo` with `code` for a fault at `addr` to the current thread, if
+/// it has a handler for the signal which is not blocked.
+///
+/// Returns `false` otherwise, and the caller is expected to terminate the
+/// process, like Linux forcing the default action of a fault signal it
+/// cannot deliver.
+///
+/// The handler can rewrite the saved context to resume elsewhere, e.g. past
+/// the faulting instruction, or leave with `siglongjmp`.
+pub fn send_fault_signal(signo: Signo, code: u32, addr: usize) -> bool {
+    let curr = current();
+    let caught = matches!(
+        curr.task_ext().process_data().signal.actions.lock()[signo].disposition,
+        SignalDisposition::Handler(_)
+    );
+    let blocked = curr
+        .task_ext()
+        .thread_data()
+        .signal
+        .with_blocked_mut(|blocked| blocked.has(signo));
+    if !caught || blocked {
+        return false;
+    }
+    let mut sig = SignalInfo::new(signo, code as _);
+    // SAFETY: `_sigfault` is the member used by fault signals.
+    unsafe {
+        sig.0
+            .__bindgen_anon_1
+            .__bindgen_anon_1
+            ._sifields
+            ._sigfault
+            ._addr = addr as _;
+    }
+    send_signal_thread(&curr.task_ext().thread, sig).is_ok()
+}
+
+/// Send a signal to a thread.
+///
+/// Returns `ESRCH` if the thread has already started exiting.
 pub fn send_signal_thread(thr: &Thread, sig: SignalInfo) -> LinuxResult<()> {
     info!("Send signal {:?} to thread {}", sig.signo(), thr.tid());
+    continue_on_sigcont(thr.process(), &sig);
+    let proc = thr.process().data::<ProcessData>();
     let Some(thr) = thr.data::<ThreadData>() else {
         return Err(LinuxError::EPERM);
     };
-    thr.signal.send_signal(sig);
+    let kick = proc.is_some_and(|proc| stops_or_kills(proc, sig.signo()));
+    thr.with_alive(|| {
+        if let Some(proc) = proc {
+            signal_queued(proc, Some(thr), &sig);
+        }
+        thr.signal.send_signal(sig);
+        thr.wake_for_signal();
+        if kick {
+            thr.kick();
+        }
+    })
+    .ok_or(LinuxError::ESRCH)?;
+    check_pending_signals();
+    Ok(())
+}
+
+/// Check that the current process may send `signo` to `proc`, or only that
+/// it may signal `proc` at all if `signo` is `None`.
+///
+/// Processes without [`ProcessData`] belong to the kernel and are never
+/// signaled. Like Linux, init only gets the signals it has installed a
+/// handler for, or which do nothing by default, unless it sends them itself.
+/// Every process runs as root, so the uid check of `kill(2)` always passes.
+pub fn check_kill_permission(proc: &Process, signo: Option<Signo>) -> LinuxResult<()> {
+    let Some(data) = proc.data::<ProcessData>() else {
+        return Err(LinuxError::EPERM);
+    };
+    let Some(signo) = signo else {
+        return Ok(());
+    };
+    if proc.is_init()
+        && proc.pid() != current().task_ext().thread.process().pid()
+        && !ignored_by_default(signo)
+        && !matches!(
+            data.signal.actions.lock()[signo].disposition,
+            SignalDisposition::Handler(_)
+        )
+    {
+        return Err(LinuxError::EPERM);
+    }
     Ok(())
 }
 
+/// Whether `signo` does nothing by default.
+fn ignored_by_default(signo: Signo) -> bool {
+    matches!(
+        signo,
+        Signo::SIGCHLD | Signo::SIGCONT | Signo::SIGURG | Signo::SIGWINCH
+    )
+}
+
+/// Whether `signo` stops or kills the thread which takes it in `proc`, so
+/// that a thread running on another CPU is kicked to take it at once.
+fn stops_or_kills(proc: &ProcessData, signo: Signo) -> bool {
+    matches!(signo, Signo::SIGKILL | Signo::SIGSTOP)
+        || (!ignored_by_default(signo)
+            && matches!(
+                proc.signal.actions.lock()[signo].disposition,
+                SignalDisposition::Default
+            ))
+}
+
 pub fn send_signal_process(proc: &Process, sig: SignalInfo) -> LinuxResult<()> {
     info!("Send signal {:?} to process {}", sig.signo(), proc.pid());
-    let Some(proc) = proc.data::<ProcessData>() else {
+    continue_on_sigcont(proc, &sig);
+    let Some(data) = proc.data::<ProcessData>() else {
         return Err(LinuxError::EPERM);
     };
-    proc.signal.send_signal(sig);
+    let kick = stops_or_kills(data, sig.signo());
+    signal_queued(data, None, &sig);
+    data.signal.send_signal(sig);
+    // Any thread which does not block it may take the signal.
+    for thr in proc.threads() {
+        if let Some(thr) = thr.data::<ThreadData>() {
+            thr.wake_for_signal();
+            if kick {
+                thr.kick();
+            }
+        }
+    }
+    check_pending_signals();
     Ok(())
 }
 
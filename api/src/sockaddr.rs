@@ -50,7 +50,8 @@ impl SockAddr {
     ///  - `ptr` must be a pointer to memory containing a valid socket address.
     ///  - `len` bytes must be initialized.
     pub unsafe fn read(ptr: *const sockaddr, len: socklen_t) -> LinuxResult<Self> {
-        if size_of::<__kernel_sa_family_t>() < len as usize || len as usize > size_of::<sockaddr>()
+        if (len as usize) < size_of::<__kernel_sa_family_t>()
+            || len as usize > size_of::<sockaddr>()
         {
             return Err(LinuxError::EINVAL);
         }
@@ -1,3 +1,4 @@
+use axerrno::{LinuxError, LinuxResult};
 use axhal::time::TimeValue;
 use linux_raw_sys::general::{
     __kernel_old_timespec, __kernel_old_timeval, __kernel_sock_timeval, __kernel_timespec,
@@ -11,6 +12,25 @@ pub trait TimeValueLike {
 
     /// Converts to `TimeValue`.
     fn to_time_value(self) -> TimeValue;
+
+    /// Whether the value is a valid time: not negative, and with less than a
+    /// second in the sub-second field.
+    ///
+    /// A struct of another size, e.g. a 32-bit `timespec`, usually fails
+    /// this check, since its halves end up in the wrong fields.
+    fn is_valid(&self) -> bool;
+
+    /// Converts to `TimeValue`, or returns `EINVAL` if the value is not
+    /// [valid](TimeValueLike::is_valid).
+    fn try_to_time_value(self) -> LinuxResult<TimeValue>
+    where
+        Self: Sized,
+    {
+        if !self.is_valid() {
+            return Err(LinuxError::EINVAL);
+        }
+        Ok(self.to_time_value())
+    }
 }
 
 impl TimeValueLike for TimeValue {
@@ -21,6 +41,10 @@ impl TimeValueLike for TimeValue {
     fn to_time_value(self) -> TimeValue {
         self
     }
+
+    fn is_valid(&self) -> bool {
+        true
+    }
 }
 
 impl TimeValueLike for timespec {
@@ -34,6 +58,10 @@ impl TimeValueLike for timespec {
     fn to_time_value(self) -> TimeValue {
         TimeValue::new(self.tv_sec as u64, self.tv_nsec as u32)
     }
+
+    fn is_valid(&self) -> bool {
+        self.tv_sec >= 0 && (0..1_000_000_000).contains(&self.tv_nsec)
+    }
 }
 
 impl TimeValueLike for __kernel_timespec {
@@ -47,6 +75,10 @@ impl TimeValueLike for __kernel_timespec {
     fn to_time_value(self) -> TimeValue {
         TimeValue::new(self.tv_sec as u64, self.tv_nsec as u32)
     }
+
+    fn is_valid(&self) -> bool {
+        self.tv_sec >= 0 && (0..1_000_000_000).contains(&self.tv_nsec)
+    }
 }
 
 impl TimeValueLike for __kernel_old_timespec {
@@ -60,6 +92,10 @@ impl TimeValueLike for __kernel_old_timespec {
     fn to_time_value(self) -> TimeValue {
         TimeValue::new(self.tv_sec as u64, self.tv_nsec as u32)
     }
+
+    fn is_valid(&self) -> bool {
+        self.tv_sec >= 0 && (0..1_000_000_000).contains(&self.tv_nsec)
+    }
 }
 
 impl TimeValueLike for timeval {
@@ -73,6 +109,10 @@ impl TimeValueLike for timeval {
     fn to_time_value(self) -> TimeValue {
         TimeValue::new(self.tv_sec as u64, self.tv_usec as u32 * 1000)
     }
+
+    fn is_valid(&self) -> bool {
+        self.tv_sec >= 0 && (0..1_000_000).contains(&self.tv_usec)
+    }
 }
 
 impl TimeValueLike for __kernel_old_timeval {
@@ -86,6 +126,10 @@ impl TimeValueLike for __kernel_old_timeval {
     fn to_time_value(self) -> TimeValue {
         TimeValue::new(self.tv_sec as u64, self.tv_usec as u32 * 1000)
     }
+
+    fn is_valid(&self) -> bool {
+        self.tv_sec >= 0 && (0..1_000_000).contains(&self.tv_usec)
+    }
 }
 
 impl TimeValueLike for __kernel_sock_timeval {
@@ -99,4 +143,8 @@ impl TimeValueLike for __kernel_sock_timeval {
     fn to_time_value(self) -> TimeValue {
         TimeValue::new(self.tv_sec as u64, self.tv_usec as u32 * 1000)
     }
+
+    fn is_valid(&self) -> bool {
+        self.tv_sec >= 0 && (0..1_000_000).contains(&self.tv_usec)
+    }
 }
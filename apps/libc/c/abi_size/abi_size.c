@@ -0,0 +1,97 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <linux/futex.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <time.h>
+#include <unistd.h>
+
+#define KERNEL_SIGSET_SIZE 8
+#define STATX_RESERVED 0x80000000U
+
+// A `timespec` of a 32-bit ABI, followed by bytes the kernel must not use.
+struct timespec32 {
+  int32_t tv_sec;
+  int32_t tv_nsec;
+  int32_t junk[2];
+};
+
+static int expect_einval(long ret) { return ret == -1 && errno == EINVAL; }
+
+void test_sigset_size() {
+  uint64_t set = 0;
+  errno = 0;
+  if (expect_einval(
+          syscall(SYS_rt_sigprocmask, SIG_BLOCK, &set, NULL, 4)) &&
+      expect_einval(syscall(SYS_rt_sigprocmask, SIG_BLOCK, &set, NULL,
+                            sizeof(sigset_t))) &&
+      expect_einval(syscall(SYS_rt_sigpending, &set, 4)) &&
+      syscall(SYS_rt_sigprocmask, SIG_BLOCK, &set, NULL, KERNEL_SIGSET_SIZE) ==
+          0) {
+    puts("test_sigset_size ok");
+  }
+}
+
+void test_sigaction_size() {
+  char act[64] = {0};
+  errno = 0;
+  if (expect_einval(syscall(SYS_rt_sigaction, SIGUSR1, NULL, act, 16)) &&
+      syscall(SYS_rt_sigaction, SIGUSR1, NULL, act, KERNEL_SIGSET_SIZE) == 0) {
+    puts("test_sigaction_size ok");
+  }
+}
+
+void test_timespec32() {
+  struct timespec32 ts = {1, 0, {-1, -1}};
+  struct timespec bad = {0, 1000000000};
+  errno = 0;
+  if (expect_einval(syscall(SYS_nanosleep, &ts, NULL)) &&
+      expect_einval(syscall(SYS_nanosleep, &bad, NULL))) {
+    puts("test_timespec32 ok");
+  }
+}
+
+void test_futex_timeout() {
+  uint32_t word = 0;
+  struct timespec bad = {0, -1};
+  errno = 0;
+  // The timeout is checked before the futex word.
+  if (expect_einval(
+          syscall(SYS_futex, &word, FUTEX_WAIT, 1, &bad, NULL, 0))) {
+    puts("test_futex_timeout ok");
+  }
+}
+
+void test_sigtimedwait_timeout() {
+  uint64_t set = 1ULL << (SIGUSR2 - 1);
+  struct timespec32 ts = {0, 1, {-1, -1}};
+  errno = 0;
+  if (expect_einval(syscall(SYS_rt_sigtimedwait, &set, NULL, &ts,
+                            KERNEL_SIGSET_SIZE))) {
+    puts("test_sigtimedwait_timeout ok");
+  }
+}
+
+void test_statx_mask() {
+  char buf[256];
+  errno = 0;
+  if (expect_einval(
+          syscall(SYS_statx, AT_FDCWD, "/", 0, STATX_RESERVED, buf)) &&
+      syscall(SYS_statx, AT_FDCWD, "/", 0, 0x7ff, buf) == 0) {
+    puts("test_statx_mask ok");
+  }
+}
+
+int main() {
+  test_sigset_size();
+  test_sigaction_size();
+  test_timespec32();
+  test_futex_timeout();
+  test_sigtimedwait_timeout();
+  test_statx_mask();
+  return 0;
+}
@@ -0,0 +1,93 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+#ifndef SYS_faccessat2
+#define SYS_faccessat2 439
+#endif
+#ifndef SYS_fchmodat2
+#define SYS_fchmodat2 452
+#endif
+
+// A bit that no *at syscall accepts
+#define BAD_FLAG 0x80000000
+
+static const char *path = "/atflags_test";
+
+static int do_fstatat(int flags) {
+  struct stat st;
+  return fstatat(AT_FDCWD, path, &st, flags);
+}
+
+static int do_statx(int flags) {
+  char buf[256];
+  return syscall(SYS_statx, AT_FDCWD, path, flags, 0, buf);
+}
+
+static int do_unlinkat(int flags) { return unlinkat(AT_FDCWD, path, flags); }
+
+static int do_linkat(int flags) {
+  return linkat(AT_FDCWD, path, AT_FDCWD, "/atflags_link", flags);
+}
+
+static int do_faccessat(int flags) {
+  return syscall(SYS_faccessat2, AT_FDCWD, path, F_OK, flags);
+}
+
+static int do_utimensat(int flags) {
+  return utimensat(AT_FDCWD, path, NULL, flags);
+}
+
+static int do_fchownat(int flags) { return fchownat(AT_FDCWD, path, 0, 0, flags); }
+
+static int do_fchmodat(int flags) {
+  return syscall(SYS_fchmodat2, AT_FDCWD, path, 0644, flags);
+}
+
+struct testcase {
+  const char *name;
+  int (*func)(int flags);
+  int bad_flags;
+};
+
+static struct testcase cases[] = {
+    {"fstatat", do_fstatat, BAD_FLAG},
+    {"statx", do_statx, BAD_FLAG},
+    {"statx_sync", do_statx, AT_STATX_FORCE_SYNC | AT_STATX_DONT_SYNC},
+    {"unlinkat", do_unlinkat, BAD_FLAG},
+    {"unlinkat_nofollow", do_unlinkat, AT_SYMLINK_NOFOLLOW},
+    {"linkat", do_linkat, BAD_FLAG},
+    {"faccessat", do_faccessat, BAD_FLAG},
+    {"utimensat", do_utimensat, BAD_FLAG},
+    {"fchownat", do_fchownat, BAD_FLAG},
+    {"fchmodat", do_fchmodat, BAD_FLAG},
+};
+
+int main() {
+  int fd = open(path, O_CREAT | O_RDWR, 0644);
+  if (fd < 0) {
+    perror("open");
+    return 1;
+  }
+  close(fd);
+
+  for (int i = 0; i < sizeof(cases) / sizeof(cases[0]); i++) {
+    errno = 0;
+    int ret = cases[i].func(cases[i].bad_flags);
+    if (ret == -1 && errno == EINVAL) {
+      printf("test_%s ok\n", cases[i].name);
+    } else {
+      printf("test_%s failed: ret=%d errno=%d\n", cases[i].name, ret, errno);
+    }
+  }
+
+  if (do_fstatat(0) == 0 && do_faccessat(0) == 0) {
+    puts("test_valid ok");
+  }
+  unlink(path);
+  return 0;
+}
@@ -0,0 +1,125 @@
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <limits.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define SCRIPT "audit_exec_script"
+
+static char self[PATH_MAX];
+static char records[1 << 16];
+
+static void set_audit(const char *value) {
+  int fd = open("/proc/starry/audit_exec", O_WRONLY);
+  CHECK(fd >= 0);
+  CHECK(write(fd, value, 1) == 1);
+  close(fd);
+}
+
+static void read_records(void) {
+  int fd = open("/proc/starry/audit", O_RDONLY);
+  CHECK(fd >= 0);
+  size_t len = 0;
+  ssize_t n;
+  while ((n = read(fd, records + len, sizeof(records) - 1 - len)) > 0) {
+    len += n;
+  }
+  CHECK(n == 0);
+  records[len] = '\0';
+  close(fd);
+}
+
+static void run(char *const argv[]) {
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    execv(argv[0], argv);
+    _exit(127);
+  }
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+static void test_direct(void) {
+  char long_arg[1024];
+  memset(long_arg, 'x', sizeof(long_arg) - 1);
+  long_arg[sizeof(long_arg) - 1] = '\0';
+  char *argv[] = {self, "child", "two words", "ctl\x01\"q\"", long_arg, NULL};
+  set_audit("1");
+  run(argv);
+  read_records();
+
+  char expect[PATH_MAX + 64];
+  snprintf(expect, sizeof(expect), "exe=\"%s\" argc=5", self);
+  char *record = strstr(records, expect);
+  CHECK(record != NULL);
+  CHECK(strstr(records, "type=EXECVE pid=") != NULL);
+  CHECK(strstr(record, " a1=\"child\" a2=\"two words\"") != NULL);
+  // Control characters and quotes are escaped.
+  CHECK(strstr(record, " a3=\"ctl\\x01\\\"q\\\"\"") != NULL);
+  // Long arguments are cut.
+  CHECK(strstr(record, "xxx...\"") != NULL);
+  printf("test_direct ok\n");
+}
+
+static void test_shebang(void) {
+  int fd = open(SCRIPT, O_WRONLY | O_CREAT | O_TRUNC, 0755);
+  CHECK(fd >= 0);
+  dprintf(fd, "#!%s\n", self);
+  close(fd);
+  char script[PATH_MAX];
+  CHECK(getcwd(script, sizeof(script) - sizeof(SCRIPT) - 1) != NULL);
+  strcat(script, strcmp(script, "/") == 0 ? SCRIPT : "/" SCRIPT);
+
+  char *argv[] = {script, "from-script", NULL};
+  run(argv);
+  read_records();
+  char expect[PATH_MAX + 64];
+  snprintf(expect, sizeof(expect), "exe=\"%s\" argc=2", script);
+  char *record = strstr(records, expect);
+  CHECK(record != NULL);
+  CHECK(strstr(record, " a1=\"from-script\"") != NULL);
+  CHECK(unlink(SCRIPT) == 0);
+  printf("test_shebang ok\n");
+}
+
+static void test_disabled(void) {
+  char *argv[] = {self, "child", "while-off", NULL};
+  set_audit("0");
+  run(argv);
+  read_records();
+  CHECK(strstr(records, "while-off") == NULL);
+  printf("test_disabled ok\n");
+}
+
+int main(int argc, char **argv) {
+  // Run again as a child, or by the script.
+  if (argc > 1) {
+    return 0;
+  }
+  if (argv[0][0] == '/') {
+    snprintf(self, sizeof(self), "%s", argv[0]);
+  } else {
+    char cwd[PATH_MAX / 2];
+    CHECK(getcwd(cwd, sizeof(cwd)) != NULL);
+    snprintf(self, sizeof(self), "%s/%s", strcmp(cwd, "/") ? cwd : "",
+             argv[0]);
+  }
+  test_direct();
+  test_shebang();
+  test_disabled();
+  return 0;
+}
@@ -0,0 +1,111 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <limits.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/file.h>
+#include <sys/mman.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <sys/uio.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define EBADF_OF(call) ((call) == -1 && errno == EBADF)
+
+// Negative fds, which must not wrap to a slot of the table, and one past
+// any limit.
+static const int BAD_FDS[] = {-1, -2, AT_FDCWD, INT_MIN, INT_MAX};
+#define NR_BAD_FDS (sizeof(BAD_FDS) / sizeof(BAD_FDS[0]))
+
+// The syscalls taking an fd fail with EBADF for each of them.
+void test_fd_ops() {
+  char buf[16];
+  struct iovec iov = {buf, sizeof(buf)};
+  struct stat st;
+  for (size_t i = 0; i < NR_BAD_FDS; i++) {
+    int fd = BAD_FDS[i];
+    CHECK(EBADF_OF(read(fd, buf, sizeof(buf))));
+    CHECK(EBADF_OF(write(fd, buf, sizeof(buf))));
+    CHECK(EBADF_OF(readv(fd, &iov, 1)));
+    CHECK(EBADF_OF(writev(fd, &iov, 1)));
+    CHECK(EBADF_OF(lseek(fd, 0, SEEK_SET)));
+    CHECK(EBADF_OF(fstat(fd, &st)));
+    CHECK(EBADF_OF(syscall(SYS_getdents64, fd, buf, sizeof(buf))));
+    CHECK(EBADF_OF(fsync(fd)));
+    CHECK(EBADF_OF(ftruncate(fd, 0)));
+    CHECK(EBADF_OF(flock(fd, LOCK_SH)));
+    CHECK(EBADF_OF(fchdir(fd)));
+    CHECK(EBADF_OF(close(fd)));
+  }
+  puts("test_fd_ops ok");
+}
+
+// dup and fcntl fail with EBADF for a bad old fd, and the dup2 family for a
+// bad new one too, leaving the table alone.
+void test_dup_fcntl() {
+  for (size_t i = 0; i < NR_BAD_FDS; i++) {
+    int fd = BAD_FDS[i];
+    CHECK(EBADF_OF(dup(fd)));
+    CHECK(EBADF_OF(dup2(fd, 10)));
+    CHECK(EBADF_OF(dup3(fd, 10, 0)));
+    CHECK(EBADF_OF(dup2(STDOUT_FILENO, fd)));
+    CHECK(EBADF_OF(dup3(STDOUT_FILENO, fd, O_CLOEXEC)));
+    CHECK(EBADF_OF(fcntl(fd, F_GETFD)));
+    CHECK(EBADF_OF(fcntl(fd, F_SETFD, FD_CLOEXEC)));
+    CHECK(EBADF_OF(fcntl(fd, F_GETFL)));
+    CHECK(EBADF_OF(fcntl(fd, F_DUPFD, 0)));
+    // An unknown command checks the fd first.
+    CHECK(EBADF_OF(fcntl(fd, 0x7fff)));
+  }
+  CHECK(fcntl(10, F_GETFD) == -1 && errno == EBADF);
+  puts("test_dup_fcntl ok");
+}
+
+// A file mapping fails with EBADF, while an anonymous one ignores the fd.
+void test_mmap() {
+  for (size_t i = 0; i < NR_BAD_FDS; i++) {
+    int fd = BAD_FDS[i];
+    void *p = mmap(NULL, 4096, PROT_READ, MAP_PRIVATE, fd, 0);
+    CHECK(p == MAP_FAILED && errno == EBADF);
+    p = mmap(NULL, 4096, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, fd, 0);
+    CHECK(p != MAP_FAILED);
+    CHECK(munmap(p, 4096) == 0);
+  }
+  puts("test_mmap ok");
+}
+
+// A relative path resolves from the dirfd, which must be open unless it is
+// AT_FDCWD, and an absolute one ignores it.
+void test_dirfd() {
+  struct stat st;
+  for (size_t i = 0; i < NR_BAD_FDS; i++) {
+    int fd = BAD_FDS[i];
+    if (fd == AT_FDCWD) {
+      CHECK(fstatat(fd, ".", &st, 0) == 0);
+      continue;
+    }
+    CHECK(EBADF_OF(openat(fd, "bad_fd.tmp", O_RDONLY)));
+    CHECK(EBADF_OF(fstatat(fd, "bad_fd.tmp", &st, 0)));
+    CHECK(EBADF_OF(mkdirat(fd, "bad_fd.tmp", 0755)));
+    CHECK(EBADF_OF(unlinkat(fd, "bad_fd.tmp", 0)));
+    CHECK(fstatat(fd, "/", &st, 0) == 0);
+  }
+  puts("test_dirfd ok");
+}
+
+int main() {
+  test_fd_ops();
+  test_dup_fcntl();
+  test_mmap();
+  test_dirfd();
+  return 0;
+}
@@ -0,0 +1,51 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <linux/fs.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <sys/ioctl.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+// The first sector of the root disk ends with the FAT boot signature, and
+// the device reports its size.
+void test_boot_sector() {
+  int fd = open("/dev/vda", O_RDONLY);
+  if (fd < 0) {
+    return;
+  }
+  unsigned char sector[512];
+  struct stat st;
+  uint64_t size = 0;
+  if (read(fd, sector, sizeof(sector)) == sizeof(sector) &&
+      sector[510] == 0x55 && sector[511] == 0xaa && fstat(fd, &st) == 0 &&
+      S_ISBLK(st.st_mode) && ioctl(fd, BLKGETSIZE64, &size) == 0 &&
+      size == (uint64_t)st.st_size && size % 512 == 0 &&
+      lseek(fd, 0, SEEK_END) == (off_t)size) {
+    puts("test_boot_sector ok");
+  }
+  close(fd);
+}
+
+// Unaligned reads see the same bytes, and the mounted root disk cannot be
+// written.
+void test_unaligned() {
+  int fd = open("/dev/vda", O_RDWR);
+  if (fd < 0) {
+    return;
+  }
+  unsigned char sector[512], tail[4];
+  if (read(fd, sector, sizeof(sector)) == sizeof(sector) &&
+      lseek(fd, 509, SEEK_SET) == 509 && read(fd, tail, 4) == 4 &&
+      tail[0] == sector[509] && tail[1] == 0x55 && tail[2] == 0xaa &&
+      write(fd, sector, sizeof(sector)) < 0 && errno == EBUSY) {
+    puts("test_unaligned ok");
+  }
+  close(fd);
+}
+
+int main() {
+  test_boot_sector();
+  test_unaligned();
+  return 0;
+}
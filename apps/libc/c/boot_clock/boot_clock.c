@@ -0,0 +1,50 @@
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/stat.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define FILE_PATH "boot_clock.tmp"
+
+static int newer(struct timespec a, struct timespec b) {
+  return a.tv_sec > b.tv_sec || (a.tv_sec == b.tv_sec && a.tv_nsec > b.tv_nsec);
+}
+
+// Even without an RTC, the machine did not boot at the epoch.
+void test_boot_time() {
+  struct timespec real, boot;
+  CHECK(clock_gettime(CLOCK_REALTIME, &real) == 0);
+  CHECK(clock_gettime(CLOCK_BOOTTIME, &boot) == 0);
+  CHECK(real.tv_sec - boot.tv_sec >= 1);
+  puts("test_boot_time ok");
+}
+
+// A file made next to this program, on the root filesystem, is newer than
+// the program, which came with the disk image.
+void test_newer_than_image(const char *self) {
+  struct stat image, made;
+  CHECK(stat(self, &image) == 0);
+  int fd = open(FILE_PATH, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  CHECK(close(fd) == 0);
+  CHECK(stat(FILE_PATH, &made) == 0);
+  CHECK(newer(made.st_mtim, image.st_mtim));
+  CHECK(unlink(FILE_PATH) == 0);
+  puts("test_newer_than_image ok");
+}
+
+int main(int argc, char **argv) {
+  (void)argc;
+  test_boot_time();
+  test_newer_than_image(argv[0]);
+  return 0;
+}
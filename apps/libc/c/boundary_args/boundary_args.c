@@ -0,0 +1,124 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+#include <sys/uio.h>
+#include <unistd.h>
+
+static long page;
+
+// The lengths every syscall is tried with, besides the valid ones, set up
+// for the page size in `main`.
+#define NUM_HUGE 4
+static size_t HUGE_LENGTHS[NUM_HUGE];
+
+static int failures;
+
+// Check that a raw syscall failed with `err`, or with any error if `err` is
+// 0.
+static void expect_error(const char *what, size_t len, long ret, int err) {
+  if (ret != -1 || (err != 0 && errno != err)) {
+    printf("%s(len=%#zx): ret %ld, errno %d, want errno %d\n", what, len, ret,
+           errno, err);
+    failures++;
+  }
+}
+
+static void test_mmap(void) {
+  for (size_t i = 0; i < NUM_HUGE; i++) {
+    size_t len = HUGE_LENGTHS[i];
+    long ret = syscall(SYS_mmap, 0, len, PROT_READ | PROT_WRITE,
+                       MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    expect_error("mmap", len, ret, ENOMEM);
+    // The end of a fixed mapping wraps around.
+    ret = syscall(SYS_mmap, 0x10000000, len, PROT_READ,
+                  MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, -1, 0);
+    expect_error("mmap fixed", len, ret, ENOMEM);
+  }
+  long ret = syscall(SYS_mmap, 0, 0, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS,
+                     -1, 0);
+  expect_error("mmap", 0, ret, EINVAL);
+  ret = syscall(SYS_mmap, SIZE_MAX - page + 1, page, PROT_READ,
+                MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, -1, 0);
+  expect_error("mmap at top", page, ret, ENOMEM);
+
+  // A hint which does not fit is only a hint.
+  void *p = (void *)syscall(SYS_mmap, SIZE_MAX - page + 1, 1, PROT_READ,
+                            MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  if (p == MAP_FAILED) {
+    printf("mmap with a bad hint failed: %d\n", errno);
+    failures++;
+  } else {
+    munmap(p, page);
+  }
+  printf("test_mmap_bounds ok\n");
+}
+
+static void test_munmap_mprotect(void) {
+  char *p = mmap(NULL, page, PROT_READ | PROT_WRITE,
+                 MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  if (p == MAP_FAILED) {
+    printf("mmap failed\n");
+    failures++;
+    return;
+  }
+  for (size_t i = 0; i < NUM_HUGE; i++) {
+    size_t len = HUGE_LENGTHS[i];
+    expect_error("munmap", len, syscall(SYS_munmap, p, len), EINVAL);
+    expect_error("mprotect", len, syscall(SYS_mprotect, p, len, PROT_READ),
+                 ENOMEM);
+  }
+  expect_error("munmap", 0, syscall(SYS_munmap, p, 0), EINVAL);
+  expect_error("munmap unaligned", 1, syscall(SYS_munmap, p + 1, 1), EINVAL);
+  expect_error("mprotect unaligned", 1,
+               syscall(SYS_mprotect, p + 1, 1, PROT_READ), EINVAL);
+  if (syscall(SYS_mprotect, p, 0, PROT_READ) != 0) {
+    printf("mprotect of 0 bytes failed\n");
+    failures++;
+  }
+  // The mapping is untouched.
+  p[page - 1] = 1;
+  munmap(p, page);
+  printf("test_munmap_mprotect_bounds ok\n");
+}
+
+static void test_io(void) {
+  int fd = open("/dev/zero", O_RDWR);
+  char buf[16];
+  for (size_t i = 0; i < NUM_HUGE; i++) {
+    size_t len = HUGE_LENGTHS[i];
+    expect_error("read", len, syscall(SYS_read, fd, buf, len), EFAULT);
+    expect_error("write", len, syscall(SYS_write, fd, buf, len), EFAULT);
+    expect_error("read at top", len,
+                 syscall(SYS_read, fd, SIZE_MAX - page + 1, len), EFAULT);
+
+    struct iovec iov[2] = {{buf, len}, {buf, len}};
+    expect_error("readv", len, syscall(SYS_readv, fd, iov, 2), 0);
+    expect_error("writev", len, syscall(SYS_writev, fd, iov, 2), EINVAL);
+    expect_error("readv count", len, syscall(SYS_readv, fd, iov, len), EINVAL);
+  }
+  if (read(fd, buf, 1) != 1 || read(fd, buf, 0) != 0) {
+    printf("small reads failed\n");
+    failures++;
+  }
+  close(fd);
+  printf("test_io_bounds ok\n");
+}
+
+int main(void) {
+  page = sysconf(_SC_PAGESIZE);
+  size_t huge[NUM_HUGE] = {SIZE_MAX, SIZE_MAX - page + 1, SIZE_MAX / 2,
+                           SIZE_MAX / 2 + 1};
+  memcpy(HUGE_LENGTHS, huge, sizeof(huge));
+  test_mmap();
+  test_munmap_mprotect();
+  test_io();
+  if (failures == 0) {
+    printf("test_boundary_args ok\n");
+  }
+  return 0;
+}
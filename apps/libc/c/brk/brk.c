@@ -0,0 +1,71 @@
+#include <stdint.h>
+#include <stdio.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+#define STEP (1 << 20)
+#define MAX_STEPS 4096
+
+static uintptr_t brk_to(uintptr_t addr) { return syscall(SYS_brk, addr); }
+
+// Growing the heap while mapping memory never makes them overlap, and the
+// heap can grow until its limit.
+void test_interleaved() {
+  uintptr_t base = brk_to(0), top = base;
+  int ok = 1, steps = 0;
+  for (; steps < MAX_STEPS; steps++) {
+    char *map = mmap(NULL, STEP, PROT_READ | PROT_WRITE,
+                     MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    if (map == MAP_FAILED) {
+      ok = 0;
+      break;
+    }
+    uintptr_t next = brk_to(top + STEP);
+    if (next == top) {
+      munmap(map, STEP);
+      break;
+    }
+    if (next != top + STEP ||
+        ((uintptr_t)map < next && (uintptr_t)map + STEP > base)) {
+      ok = 0;
+      break;
+    }
+    // Both are usable and hold their own data.
+    *(volatile char *)top = 1;
+    map[0] = 2;
+    if (*(volatile char *)top != 1) {
+      ok = 0;
+      break;
+    }
+    top = next;
+  }
+  // The break stays where it was past the limit.
+  if (ok && steps > 0 && steps < MAX_STEPS && brk_to(0) == top) {
+    puts("test_interleaved ok");
+  }
+  brk_to(base);
+}
+
+// A fixed mapping in the way makes `brk` fail without moving the break.
+void test_blocked() {
+  uintptr_t base = brk_to(0);
+  void *block = mmap((void *)(base + STEP), STEP, PROT_READ,
+                     MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, -1, 0);
+  if (block == MAP_FAILED) {
+    return;
+  }
+  int blocked = brk_to(base + 2 * STEP) == base && brk_to(0) == base;
+  munmap(block, STEP);
+  if (blocked && brk_to(base + 2 * STEP) == base + 2 * STEP) {
+    *(volatile char *)(base + 2 * STEP - 1) = 1;
+    puts("test_blocked ok");
+  }
+  brk_to(base);
+}
+
+int main() {
+  test_interleaved();
+  test_blocked();
+  return 0;
+}
@@ -0,0 +1,44 @@
+#include <stdio.h>
+#include <string.h>
+
+// Big enough to notice if the whole BSS were populated at load time.
+#define BIG_BSS_SIZE (32 << 20)
+
+static int data_var = 42;
+static char small_bss[100];
+static char big_bss[BIG_BSS_SIZE];
+
+// The BSS sharing a page with .data must be zeroed as well.
+void test_bss_zeroed() {
+  for (int i = 0; i < sizeof(small_bss); i++) {
+    if (small_bss[i] != 0) {
+      return;
+    }
+  }
+  if (data_var == 42) {
+    puts("test_bss_zeroed ok");
+  }
+}
+
+// Only the touched pages of a big BSS need memory.
+void test_big_bss() {
+  for (int i = 0; i < 16; i++) {
+    char *p = big_bss + (long)i * (BIG_BSS_SIZE / 16);
+    if (*p != 0) {
+      return;
+    }
+    *p = i + 1;
+  }
+  for (int i = 0; i < 16; i++) {
+    if (big_bss[(long)i * (BIG_BSS_SIZE / 16)] != i + 1) {
+      return;
+    }
+  }
+  puts("test_big_bss ok");
+}
+
+int main() {
+  test_bss_zeroed();
+  test_big_bss();
+  return 0;
+}
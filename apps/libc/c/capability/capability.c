@@ -0,0 +1,153 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <linux/capability.h>
+#include <linux/reboot.h>
+#include <signal.h>
+#include <stdio.h>
+#include <sys/resource.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static int capget(struct __user_cap_header_struct *header,
+                  struct __user_cap_data_struct *data) {
+  return syscall(SYS_capget, header, data);
+}
+
+static int capset(struct __user_cap_header_struct *header,
+                  struct __user_cap_data_struct *data) {
+  return syscall(SYS_capset, header, data);
+}
+
+// A harmless `reboot` command, which still needs `CAP_SYS_BOOT`.
+static int reboot_cad_off() {
+  return syscall(SYS_reboot, LINUX_REBOOT_MAGIC1, LINUX_REBOOT_MAGIC2,
+                 LINUX_REBOOT_CMD_CAD_OFF, NULL);
+}
+
+void test_capget() {
+  struct __user_cap_header_struct header = {0, 0};
+  struct __user_cap_data_struct data[2];
+  // A bad version is replaced with the preferred one.
+  if (capget(&header, NULL) != 0 ||
+      header.version != _LINUX_CAPABILITY_VERSION_3) {
+    return;
+  }
+  if (capget(&header, data) == 0 &&
+      (data[0].effective & CAP_TO_MASK(CAP_SYS_BOOT)) &&
+      (data[0].permitted & CAP_TO_MASK(CAP_SYS_BOOT))) {
+    puts("test_capget ok");
+  }
+}
+
+void test_drop_cap() {
+  pid_t pid = fork();
+  if (pid == 0) {
+    struct __user_cap_header_struct header = {_LINUX_CAPABILITY_VERSION_3, 0};
+    struct __user_cap_data_struct data[2];
+    if (capget(&header, data) != 0) {
+      _exit(1);
+    }
+    data[0].effective &= ~CAP_TO_MASK(CAP_SYS_BOOT);
+    data[0].permitted &= ~CAP_TO_MASK(CAP_SYS_BOOT);
+    if (capset(&header, data) != 0) {
+      _exit(2);
+    }
+    if (reboot_cad_off() != -1 || errno != EPERM) {
+      _exit(3);
+    }
+    // Dropped capabilities cannot be regained.
+    data[0].effective |= CAP_TO_MASK(CAP_SYS_BOOT);
+    data[0].permitted |= CAP_TO_MASK(CAP_SYS_BOOT);
+    if (capset(&header, data) != -1 || errno != EPERM) {
+      _exit(4);
+    }
+    _exit(0);
+  }
+  int status;
+  if (waitpid(pid, &status, 0) == pid && WIFEXITED(status) &&
+      WEXITSTATUS(status) == 0 && reboot_cad_off() == 0) {
+    puts("test_drop_cap ok");
+  }
+}
+
+// Raising the priority needs `CAP_SYS_NICE`, lowering it does not.
+void test_nice() {
+  pid_t pid = fork();
+  if (pid == 0) {
+    struct __user_cap_header_struct header = {_LINUX_CAPABILITY_VERSION_3, 0};
+    struct __user_cap_data_struct data[2];
+    if (capget(&header, data) != 0) {
+      _exit(1);
+    }
+    data[0].effective &= ~CAP_TO_MASK(CAP_SYS_NICE);
+    data[0].permitted &= ~CAP_TO_MASK(CAP_SYS_NICE);
+    if (capset(&header, data) != 0) {
+      _exit(2);
+    }
+    if (setpriority(PRIO_PROCESS, 0, 5) != 0) {
+      _exit(3);
+    }
+    errno = 0;
+    if (getpriority(PRIO_PROCESS, 0) != 5 || errno != 0) {
+      _exit(4);
+    }
+    if (setpriority(PRIO_PROCESS, 0, 0) != -1 || errno != EPERM) {
+      _exit(5);
+    }
+    _exit(0);
+  }
+  int status;
+  if (waitpid(pid, &status, 0) == pid && WIFEXITED(status) &&
+      WEXITSTATUS(status) == 0 && setpriority(PRIO_PROCESS, 0, -5) == 0 &&
+      getpriority(PRIO_PROCESS, 0) == -5 &&
+      setpriority(PRIO_PROCESS, 0, 0) == 0) {
+    puts("test_nice ok");
+  }
+}
+
+// Without `CAP_SYS_RESOURCE`, a process can only reach the limits of the
+// processes with no capability it lacks.
+void test_prlimit_other() {
+  pid_t parent = getpid();
+  pid_t pid = fork();
+  if (pid == 0) {
+    struct __user_cap_header_struct header = {_LINUX_CAPABILITY_VERSION_3, 0};
+    struct __user_cap_data_struct data[2];
+    struct rlimit limit;
+    if (capget(&header, data) != 0) {
+      _exit(1);
+    }
+    data[0].effective &= ~CAP_TO_MASK(CAP_SYS_RESOURCE);
+    data[0].permitted &= ~CAP_TO_MASK(CAP_SYS_RESOURCE);
+    if (capset(&header, data) != 0) {
+      _exit(2);
+    }
+    if (prlimit(parent, RLIMIT_NOFILE, NULL, &limit) != -1 || errno != EPERM) {
+      _exit(3);
+    }
+    pid_t child = fork();
+    if (child == 0) {
+      pause();
+      _exit(0);
+    }
+    int ok = prlimit(child, RLIMIT_NOFILE, NULL, &limit) == 0 &&
+             prlimit(0, RLIMIT_NOFILE, &limit, NULL) == 0;
+    kill(child, SIGKILL);
+    waitpid(child, NULL, 0);
+    _exit(ok ? 0 : 4);
+  }
+  int status;
+  if (waitpid(pid, &status, 0) == pid && WIFEXITED(status) &&
+      WEXITSTATUS(status) == 0) {
+    puts("test_prlimit_other ok");
+  }
+}
+
+int main() {
+  test_capget();
+  test_drop_cap();
+  test_nice();
+  test_prlimit_other();
+  return 0;
+}
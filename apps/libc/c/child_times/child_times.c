@@ -0,0 +1,72 @@
+#define _GNU_SOURCE
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/resource.h>
+#include <sys/times.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+// Spin until the process has used `ms` milliseconds of CPU time.
+static void burn(long ms) {
+  struct timespec ts;
+  volatile unsigned long n = 0;
+  do {
+    for (int i = 0; i < 100000; i++) {
+      n++;
+    }
+    CHECK(clock_gettime(CLOCK_PROCESS_CPUTIME_ID, &ts) == 0);
+  } while (ts.tv_sec * 1000 + ts.tv_nsec / 1000000 < ms);
+}
+
+static void test_child_times(void) {
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    burn(300);
+    write(fds[1], "x", 1);
+    _exit(0);
+  }
+  char c;
+  CHECK(read(fds[0], &c, 1) == 1);
+  // Let the child finish exiting, so that it is a zombie.
+  usleep(100000);
+
+  struct tms tms;
+  struct rusage usage;
+  times(&tms);
+  CHECK(tms.tms_cutime == 0 && tms.tms_cstime == 0);
+  CHECK(getrusage(RUSAGE_CHILDREN, &usage) == 0);
+  CHECK(usage.ru_utime.tv_sec == 0 && usage.ru_utime.tv_usec == 0);
+
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status));
+  long ticks = sysconf(_SC_CLK_TCK);
+  times(&tms);
+  // At least two thirds of the time burnt, whatever the split.
+  CHECK(tms.tms_cutime + tms.tms_cstime >= ticks / 5);
+  CHECK(getrusage(RUSAGE_CHILDREN, &usage) == 0);
+  CHECK(usage.ru_utime.tv_sec * 1000000 + usage.ru_utime.tv_usec +
+            usage.ru_stime.tv_sec * 1000000 + usage.ru_stime.tv_usec >=
+        200000);
+  // The own time is not mixed up with the children's.
+  CHECK(getrusage(RUSAGE_SELF, &usage) == 0);
+  CHECK(usage.ru_utime.tv_sec * 1000000 + usage.ru_utime.tv_usec < 200000);
+  printf("test_child_times ok\n");
+}
+
+int main(void) {
+  test_child_times();
+  return 0;
+}
@@ -0,0 +1,207 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <linux/sched.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define STACK_SIZE (64 * 1024)
+
+static long clone3(struct clone_args *args, size_t size) {
+  return syscall(SYS_clone3, args, size);
+}
+
+// Call clone3, and have the child run `fn` on its new stack, then exit the
+// thread. The child cannot return into C code, whose frame is on the old
+// stack.
+static long clone3_run(struct clone_args *args, size_t size, void (*fn)(void)) {
+#if defined(__x86_64__)
+  register long rax __asm__("rax") = SYS_clone3;
+  register long rdi __asm__("rdi") = (long)args;
+  register long rsi __asm__("rsi") = (long)size;
+  __asm__ volatile("syscall\n"
+                   "test %%rax, %%rax\n"
+                   "jnz 1f\n"
+                   "xor %%ebp, %%ebp\n"
+                   "call *%[fn]\n"
+                   "mov %[exit], %%eax\n"
+                   "xor %%edi, %%edi\n"
+                   "syscall\n"
+                   "1:\n"
+                   : "+r"(rax)
+                   : "r"(rdi), "r"(rsi), [fn] "r"(fn), [exit] "i"(SYS_exit)
+                   : "rcx", "r11", "memory");
+  return rax;
+#elif defined(__riscv)
+  register long a7 __asm__("a7") = SYS_clone3;
+  register long a0 __asm__("a0") = (long)args;
+  register long a1 __asm__("a1") = (long)size;
+  __asm__ volatile("ecall\n"
+                   "bnez a0, 1f\n"
+                   "jalr %[fn]\n"
+                   "li a7, %[exit]\n"
+                   "li a0, 0\n"
+                   "ecall\n"
+                   "1:\n"
+                   : "+r"(a0)
+                   : "r"(a7), "r"(a1), [fn] "r"(fn), [exit] "i"(SYS_exit)
+                   : "memory");
+  return a0;
+#elif defined(__aarch64__)
+  register long x8 __asm__("x8") = SYS_clone3;
+  register long x0 __asm__("x0") = (long)args;
+  register long x1 __asm__("x1") = (long)size;
+  __asm__ volatile("svc #0\n"
+                   "cbnz x0, 1f\n"
+                   "blr %[fn]\n"
+                   "mov x8, %[exit]\n"
+                   "mov x0, #0\n"
+                   "svc #0\n"
+                   "1:\n"
+                   : "+r"(x0)
+                   : "r"(x8), "r"(x1), [fn] "r"(fn), [exit] "i"(SYS_exit)
+                   : "memory");
+  return x0;
+#elif defined(__loongarch__)
+  register long a7 __asm__("$a7") = SYS_clone3;
+  register long a0 __asm__("$a0") = (long)args;
+  register long a1 __asm__("$a1") = (long)size;
+  __asm__ volatile("syscall 0\n"
+                   "bnez $a0, 1f\n"
+                   "jirl $ra, %[fn], 0\n"
+                   "li.d $a7, %[exit]\n"
+                   "li.d $a0, 0\n"
+                   "syscall 0\n"
+                   "1:\n"
+                   : "+r"(a0)
+                   : "r"(a7), "r"(a1), [fn] "r"(fn), [exit] "i"(SYS_exit)
+                   : "memory");
+  return a0;
+#else
+#error "unsupported architecture"
+#endif
+}
+
+static volatile int thread_ran;
+static volatile pid_t thread_pid;
+
+static void thread_main(void) {
+  thread_ran = 1;
+  thread_pid = getpid();
+}
+
+static void test_thread(void) {
+  char *stack = malloc(STACK_SIZE);
+  CHECK(stack != NULL);
+  volatile pid_t ptid = 0, ctid = -1;
+  struct clone_args args;
+  memset(&args, 0, sizeof(args));
+  args.flags = CLONE_VM | CLONE_FS | CLONE_FILES | CLONE_SIGHAND |
+               CLONE_THREAD | CLONE_SYSVSEM | CLONE_PARENT_SETTID |
+               CLONE_CHILD_CLEARTID;
+  args.parent_tid = (uintptr_t)&ptid;
+  args.child_tid = (uintptr_t)&ctid;
+  // The base and size of the stack, not the initial stack pointer.
+  args.stack = (uintptr_t)stack;
+  args.stack_size = STACK_SIZE;
+  long tid = clone3_run(&args, sizeof(args), thread_main);
+  CHECK(tid > 0);
+  CHECK(ptid == tid);
+  // The child clears `ctid` when it exits.
+  while (ctid != 0) {
+    usleep(1000);
+  }
+  CHECK(thread_ran);
+  CHECK(thread_pid == getpid());
+  free(stack);
+  printf("test_thread ok\n");
+}
+
+static void test_fork(void) {
+  struct clone_args args;
+  memset(&args, 0, sizeof(args));
+  args.exit_signal = SIGCHLD;
+  long pid = clone3(&args, sizeof(args));
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    _exit(7);
+  }
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 7);
+
+  // Without an exit signal, the child is only waited for with __WCLONE.
+  args.exit_signal = 0;
+  pid = clone3(&args, sizeof(args));
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    _exit(8);
+  }
+  CHECK(waitpid(pid, &status, __WCLONE) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 8);
+  printf("test_fork ok\n");
+}
+
+static void test_args(void) {
+  struct {
+    struct clone_args args;
+    uint64_t extra;
+  } big;
+  memset(&big, 0, sizeof(big));
+
+  errno = 0;
+  CHECK(clone3(&big.args, CLONE_ARGS_SIZE_VER0 - 8) == -1 && errno == EINVAL);
+
+  // A newer struct is fine while the fields it adds are zero.
+  big.args.exit_signal = SIGCHLD;
+  long pid = clone3(&big.args, sizeof(big));
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    _exit(0);
+  }
+  CHECK(waitpid(pid, NULL, 0) == pid);
+  big.extra = 1;
+  errno = 0;
+  CHECK(clone3(&big.args, sizeof(big)) == -1 && errno == E2BIG);
+
+  struct clone_args args;
+  memset(&args, 0, sizeof(args));
+  args.exit_signal = SIGCHLD;
+  args.flags = CLONE_DETACHED;
+  errno = 0;
+  CHECK(clone3(&args, sizeof(args)) == -1 && errno == EINVAL);
+  // The exit signal has its own field.
+  args.flags = SIGCHLD;
+  errno = 0;
+  CHECK(clone3(&args, sizeof(args)) == -1 && errno == EINVAL);
+  args.flags = 0;
+  args.exit_signal = 100;
+  errno = 0;
+  CHECK(clone3(&args, sizeof(args)) == -1 && errno == EINVAL);
+  // A stack needs its size.
+  args.exit_signal = SIGCHLD;
+  args.stack = (uintptr_t)&args;
+  errno = 0;
+  CHECK(clone3(&args, sizeof(args)) == -1 && errno == EINVAL);
+  printf("test_args ok\n");
+}
+
+int main(void) {
+  test_thread();
+  test_fork();
+  test_args();
+  return 0;
+}
@@ -0,0 +1,182 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <sched.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define STACK_SIZE (64 * 1024)
+#define THREAD_FLAGS                                                           \
+  (CLONE_VM | CLONE_SIGHAND | CLONE_FILES | CLONE_FS | CLONE_THREAD)
+
+static char stack[STACK_SIZE] __attribute__((aligned(16)));
+
+// What a child saw, filled in where the caller sees it, through a pipe for
+// a process and directly for a thread.
+struct seen {
+  pid_t ppid;
+  pid_t pgid;
+  int handler;
+  volatile int done;
+};
+
+static volatile sig_atomic_t sigchld_count;
+
+static void on_sigchld(int sig) {
+  (void)sig;
+  sigchld_count++;
+}
+
+static void on_sigusr1(int sig) { (void)sig; }
+
+static void fill_seen(struct seen *seen) {
+  seen->ppid = getppid();
+  seen->pgid = getpgid(0);
+  struct sigaction sa;
+  seen->handler = sigaction(SIGUSR1, NULL, &sa) == 0 &&
+                  sa.sa_handler == on_sigusr1;
+}
+
+static int report_to_pipe(void *arg) {
+  struct seen seen;
+  fill_seen(&seen);
+  int fd = *(int *)arg;
+  return write(fd, &seen, sizeof(seen)) == sizeof(seen) ? 0 : 1;
+}
+
+static int report_in_memory(void *arg) {
+  struct seen *seen = arg;
+  fill_seen(seen);
+  seen->done = 1;
+  // Only this thread exits.
+  syscall(SYS_exit, 0);
+  return 0;
+}
+
+static struct seen read_seen(int fd) {
+  struct seen seen;
+  CHECK(read(fd, &seen, sizeof(seen)) == sizeof(seen));
+  return seen;
+}
+
+static void check_exited(pid_t pid) {
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+// Without `CLONE_PARENT`, the caller is the parent of the child, which it
+// waits for.
+void test_child() {
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  pid_t pid = clone(report_to_pipe, stack + STACK_SIZE, SIGCHLD, &fds[1]);
+  CHECK(pid > 0);
+  struct seen seen = read_seen(fds[0]);
+  CHECK(seen.ppid == getpid());
+  CHECK(seen.pgid == getpgid(0));
+  check_exited(pid);
+  close(fds[0]);
+  close(fds[1]);
+  puts("test_child ok");
+}
+
+// With `CLONE_PARENT`, the child is a sibling of the caller: the parent of
+// the caller gets `SIGCHLD` and waits for it, the caller cannot, and
+// `getppid` in the child agrees. The process group and the handlers shared
+// with `CLONE_SIGHAND` are those of the caller.
+void test_sibling() {
+  struct sigaction sa = {0};
+  sa.sa_handler = on_sigchld;
+  CHECK(sigaction(SIGCHLD, &sa, NULL) == 0);
+  sigchld_count = 0;
+
+  int fds[2], pid_fds[2];
+  CHECK(pipe(fds) == 0 && pipe(pid_fds) == 0);
+  pid_t middle = fork();
+  CHECK(middle >= 0);
+  if (middle == 0) {
+    CHECK(setpgid(0, 0) == 0);
+    signal(SIGUSR1, on_sigusr1);
+    int flags = CLONE_PARENT | CLONE_VM | CLONE_SIGHAND | SIGCHLD;
+    pid_t pid = clone(report_to_pipe, stack + STACK_SIZE, flags, &fds[1]);
+    CHECK(pid > 0);
+    CHECK(write(pid_fds[1], &pid, sizeof(pid)) == sizeof(pid));
+    // Let the sibling exit before looking for it.
+    usleep(100000);
+    CHECK(waitpid(pid, NULL, WNOHANG) == -1 && errno == ECHILD);
+    CHECK(waitpid(-1, NULL, WNOHANG | __WALL) == -1 && errno == ECHILD);
+    _exit(0);
+  }
+
+  struct seen seen = read_seen(fds[0]);
+  pid_t pid;
+  CHECK(read(pid_fds[0], &pid, sizeof(pid)) == sizeof(pid));
+  CHECK(seen.ppid == getpid());
+  CHECK(seen.pgid == middle);
+  CHECK(seen.handler);
+  check_exited(pid);
+  check_exited(middle);
+  CHECK(sigchld_count >= 1);
+  close(fds[0]);
+  close(fds[1]);
+  close(pid_fds[0]);
+  close(pid_fds[1]);
+  signal(SIGCHLD, SIG_DFL);
+  puts("test_sibling ok");
+}
+
+// A thread, with `CLONE_PARENT` or not, is no child of anyone, and its
+// parent is that of its process.
+static void check_thread(int flags) {
+  static struct seen seen;
+  memset(&seen, 0, sizeof(seen));
+  pid_t tid = clone(report_in_memory, stack + STACK_SIZE, flags, &seen);
+  CHECK(tid > 0);
+  while (!seen.done) {
+    sched_yield();
+  }
+  // Let the thread finish exiting.
+  usleep(10000);
+  CHECK(seen.ppid == getppid());
+  CHECK(seen.pgid == getpgid(0));
+  CHECK(waitpid(-1, NULL, WNOHANG | __WALL) == -1 && errno == ECHILD);
+}
+
+void test_thread() {
+  check_thread(THREAD_FLAGS);
+  puts("test_thread ok");
+}
+
+// A thread sends no exit signal, even one asked for, and `CLONE_PARENT`
+// does not change that.
+void test_thread_parent() {
+  struct sigaction sa = {0};
+  sa.sa_handler = on_sigchld;
+  CHECK(sigaction(SIGCHLD, &sa, NULL) == 0);
+  sigchld_count = 0;
+  check_thread(THREAD_FLAGS | CLONE_PARENT | SIGCHLD);
+  CHECK(sigchld_count == 0);
+  signal(SIGCHLD, SIG_DFL);
+  puts("test_thread_parent ok");
+}
+
+int main() {
+  test_child();
+  test_sibling();
+  test_thread();
+  test_thread_parent();
+  return 0;
+}
@@ -0,0 +1,157 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <pthread.h>
+#include <sched.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define DIR_PATH "/clone_share_dir"
+
+static char self[256];
+
+static int is_open(int fd) { return fcntl(fd, F_GETFD) != -1; }
+
+static int in_dir(void) {
+  char cwd[256];
+  return getcwd(cwd, sizeof(cwd)) && strcmp(cwd, DIR_PATH) == 0;
+}
+
+// Like fork, with `flags` as well.
+static pid_t clone_with(int flags) {
+  return syscall(SYS_clone, flags | SIGCHLD, 0, 0, 0, 0);
+}
+
+static void wait_ok(pid_t pid) {
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+// Let a child made with `flags` open a descriptor, close `fd` and change
+// directory, and check which of it the parent sees.
+static void check_clone(int flags) {
+  int fd = dup(0);
+  CHECK(fd >= 0);
+  int pipes[2];
+  CHECK(pipe(pipes) == 0);
+  pid_t pid = clone_with(flags);
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    int opened = dup(0);
+    close(fd);
+    CHECK(write(pipes[1], &opened, sizeof(opened)) == sizeof(opened));
+    CHECK(chdir(DIR_PATH) == 0);
+    _exit(0);
+  }
+  wait_ok(pid);
+  int opened;
+  CHECK(read(pipes[0], &opened, sizeof(opened)) == sizeof(opened));
+  int shared = flags & CLONE_FILES;
+  CHECK(is_open(fd) == !shared);
+  CHECK(is_open(opened) == !!shared);
+  CHECK(in_dir() == !!(flags & CLONE_FS));
+  close(shared ? opened : fd);
+  close(pipes[0]);
+  close(pipes[1]);
+  CHECK(chdir("/") == 0);
+}
+
+static void test_clone(void) {
+  check_clone(0);
+  check_clone(CLONE_FILES);
+  check_clone(CLONE_FS);
+  check_clone(CLONE_FILES | CLONE_FS);
+  printf("test_clone ok\n");
+}
+
+static void *thread_main(void *arg) {
+  int *fds = arg;
+  fds[1] = dup(0);
+  close(fds[0]);
+  CHECK(chdir(DIR_PATH) == 0);
+  return NULL;
+}
+
+static void test_thread(void) {
+  int fds[2];
+  CHECK((fds[0] = dup(0)) >= 0);
+  pthread_t thread;
+  CHECK(pthread_create(&thread, NULL, thread_main, fds) == 0);
+  CHECK(pthread_join(thread, NULL) == 0);
+  CHECK(!is_open(fds[0]) && is_open(fds[1]) && in_dir());
+  close(fds[1]);
+  CHECK(chdir("/") == 0);
+  printf("test_thread ok\n");
+}
+
+// Run after `execve` by a child sharing the descriptors and the working
+// directory of its parent.
+static int exec_child(int cloexec_fd, int kept_fd) {
+  if (is_open(cloexec_fd) || !is_open(kept_fd))
+    return 1;
+  // In the copy of the table, not the one of the parent.
+  close(kept_fd);
+  dup2(0, cloexec_fd);
+  // Still shared.
+  return chdir(DIR_PATH) == 0 ? 0 : 1;
+}
+
+static void test_exec(void) {
+  int cloexec_fd = open("/", O_RDONLY | O_CLOEXEC);
+  int kept_fd = dup(0);
+  CHECK(cloexec_fd >= 0 && kept_fd >= 0);
+  pid_t pid = clone_with(CLONE_FILES | CLONE_FS);
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    char arg1[16], arg2[16];
+    snprintf(arg1, sizeof(arg1), "%d", cloexec_fd);
+    snprintf(arg2, sizeof(arg2), "%d", kept_fd);
+    char *argv[] = {self, "exec_child", arg1, arg2, NULL};
+    execv(self, argv);
+    _exit(2);
+  }
+  wait_ok(pid);
+  // Neither the closing on exec nor what came after touched our table.
+  CHECK(is_open(cloexec_fd) && fcntl(cloexec_fd, F_GETFD) == FD_CLOEXEC);
+  CHECK(is_open(kept_fd) && fcntl(kept_fd, F_GETFD) == 0);
+  struct stat a, b;
+  CHECK(fstat(cloexec_fd, &a) == 0 && stat("/", &b) == 0);
+  CHECK(a.st_ino == b.st_ino);
+  CHECK(in_dir());
+  close(cloexec_fd);
+  close(kept_fd);
+  CHECK(chdir("/") == 0);
+  printf("test_exec ok\n");
+}
+
+int main(int argc, char **argv) {
+  if (argc == 4 && strcmp(argv[1], "exec_child") == 0)
+    return exec_child(atoi(argv[2]), atoi(argv[3]));
+  ssize_t len = readlink("/proc/self/exe", self, sizeof(self) - 1);
+  CHECK(len > 0);
+  self[len] = '\0';
+  CHECK(mkdir(DIR_PATH, 0755) == 0);
+  CHECK(chdir("/") == 0);
+
+  test_clone();
+  test_thread();
+  test_exec();
+
+  CHECK(rmdir(DIR_PATH) == 0);
+  return 0;
+}
@@ -0,0 +1,61 @@
+#include <errno.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+// What is written by default. Run as `console_flood <MiB>` to measure with
+// more, e.g. 20.
+#define DEFAULT_BYTES (128 * 1024)
+#define LINE_LEN 64
+#define BATCH_LINES 64
+
+static long now_ns(void) {
+  struct timespec ts;
+  CHECK(clock_gettime(CLOCK_MONOTONIC, &ts) == 0);
+  return ts.tv_sec * 1000000000L + ts.tv_nsec;
+}
+
+// Write `total` bytes of numbered lines to the console, `BATCH_LINES` at a
+// time, then force them out with fsync, reporting how long each took. Every
+// write must be taken whole.
+void test_flood(long total) {
+  char batch[LINE_LEN * BATCH_LINES];
+  long lines = total / LINE_LEN, line = 0;
+  long start = now_ns();
+  while (line < lines) {
+    int n = 0;
+    for (; n < BATCH_LINES && line < lines; n++, line++) {
+      char *p = batch + n * LINE_LEN;
+      int len = snprintf(p, LINE_LEN, "flood %08ld ", line);
+      memset(p + len, '.', LINE_LEN - 1 - len);
+      p[LINE_LEN - 1] = '\n';
+    }
+    ssize_t len = n * LINE_LEN;
+    CHECK(write(STDOUT_FILENO, batch, len) == len);
+  }
+  long written = now_ns();
+  CHECK(fsync(STDOUT_FILENO) == 0);
+  long drained = now_ns();
+  printf("flood: %ld bytes written in %ld ms, out in %ld ms\n",
+         lines * LINE_LEN, (written - start) / 1000000,
+         (drained - start) / 1000000);
+  puts("test_flood ok");
+}
+
+int main(int argc, char **argv) {
+  long total = DEFAULT_BYTES;
+  if (argc > 1)
+    total = atol(argv[1]) * 1024 * 1024;
+  test_flood(total);
+  return 0;
+}
@@ -0,0 +1,76 @@
+#include <fcntl.h>
+#include <sched.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define LINES 200
+
+// "é" is two bytes, split by the pieces below.
+#define PAYLOAD "the quick brown fox jumps over the lazy dog, café au lait"
+
+// A child writing LINES lines to the kernel log as fast as it can.
+static pid_t log_heavily(void) {
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    int fd = open("/dev/kmsg", O_WRONLY);
+    if (fd < 0)
+      _exit(1);
+    for (int i = 0; i < LINES; i++) {
+      char line[128];
+      int len = snprintf(line, sizeof(line), "kmsg line %03d: %s\n", i,
+                         PAYLOAD);
+      if (write(fd, line, len) != len)
+        _exit(1);
+    }
+    close(fd);
+    _exit(0);
+  }
+  return pid;
+}
+
+// Lines written in pieces to the console while the kernel logs come out
+// whole, the kernel lines between them.
+void test_interleaved_lines() {
+  pid_t pid = log_heavily();
+  for (int i = 0; i < LINES; i++) {
+    char line[128];
+    int len = snprintf(line, sizeof(line), "user line %03d: %s\n", i, PAYLOAD);
+    // Cut in the middle of "é" and of the words.
+    int cuts[] = {0, 7, len - 10, len};
+    for (int j = 0; j < 3; j++) {
+      int n = cuts[j + 1] - cuts[j];
+      CHECK(write(STDOUT_FILENO, line + cuts[j], n) == n);
+      sched_yield();
+    }
+  }
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  puts("test_interleaved_lines ok");
+}
+
+// An unfinished line, like a prompt, is not held back forever.
+void test_unfinished_line() {
+  CHECK(write(STDOUT_FILENO, "prompt> ", 8) == 8);
+  usleep(100000);
+  CHECK(write(STDOUT_FILENO, "answered\n", 9) == 9);
+  puts("test_unfinished_line ok");
+}
+
+int main() {
+  test_interleaved_lines();
+  test_unfinished_line();
+  return 0;
+}
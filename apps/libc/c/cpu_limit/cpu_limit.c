@@ -0,0 +1,95 @@
+#define _GNU_SOURCE
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/resource.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+static int report_fd;
+
+static void on_sigxcpu(int sig) {
+  (void)sig;
+  write(report_fd, "x", 1);
+}
+
+// Spin without making any syscall, so only the timer sees the time used.
+static void spin(void) {
+  volatile unsigned long n = 0;
+  for (;;) {
+    n++;
+  }
+}
+
+static void set_cpu_limit(rlim_t soft, rlim_t hard) {
+  struct rlimit limit = {.rlim_cur = soft, .rlim_max = hard};
+  CHECK(setrlimit(RLIMIT_CPU, &limit) == 0);
+}
+
+static int wait_signal(pid_t pid) {
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFSIGNALED(status));
+  return WTERMSIG(status);
+}
+
+static void test_soft_limit(void) {
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    set_cpu_limit(1, RLIM_INFINITY);
+    spin();
+  }
+  CHECK(wait_signal(pid) == SIGXCPU);
+  printf("test_soft_limit ok\n");
+}
+
+static void test_hard_limit(void) {
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    close(fds[0]);
+    report_fd = fds[1];
+    signal(SIGXCPU, on_sigxcpu);
+    set_cpu_limit(1, 3);
+    spin();
+  }
+  close(fds[1]);
+  CHECK(wait_signal(pid) == SIGKILL);
+  // SIGXCPU at 1 and 2 seconds, then SIGKILL at 3.
+  char buf[16];
+  ssize_t n = read(fds[0], buf, sizeof(buf));
+  CHECK(n == 2);
+  close(fds[0]);
+  printf("test_hard_limit ok\n");
+}
+
+static void test_prlimit_running(void) {
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    spin();
+  }
+  usleep(100000);
+  struct rlimit limit = {.rlim_cur = 1, .rlim_max = 1};
+  CHECK(prlimit(pid, RLIMIT_CPU, &limit, NULL) == 0);
+  CHECK(wait_signal(pid) == SIGKILL);
+  printf("test_prlimit_running ok\n");
+}
+
+int main(void) {
+  test_soft_limit();
+  test_hard_limit();
+  test_prlimit_running();
+  return 0;
+}
@@ -0,0 +1,43 @@
+#include <limits.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+extern char **environ;
+
+// The testcase list starts this program with `cwd=/tmp`.
+void test_cwd() {
+  char cwd[PATH_MAX];
+  CHECK(getcwd(cwd, sizeof(cwd)) != NULL);
+  CHECK(strcmp(cwd, "/tmp") == 0);
+  puts("test_cwd ok");
+}
+
+// And with `env STARRY_GREETING=hello STARRY_EMPTY=`, which is all of the
+// environment.
+void test_env() {
+  const char *greeting = getenv("STARRY_GREETING");
+  CHECK(greeting != NULL && strcmp(greeting, "hello") == 0);
+  const char *empty = getenv("STARRY_EMPTY");
+  CHECK(empty != NULL && *empty == '\0');
+  int count = 0;
+  for (char **env = environ; *env; env++)
+    count++;
+  CHECK(count == 2);
+  puts("test_env ok");
+}
+
+int main() {
+  test_cwd();
+  test_env();
+  return 0;
+}
@@ -0,0 +1,165 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <linux/capability.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define DIR_PATH "/dac_enforce_dir"
+#define FILE_PATH DIR_PATH "/secret"
+#define MOVED_DIR "/dac_enforce_moved_dir"
+#define MOVED_FILE MOVED_DIR "/secret"
+
+static void set_enforcing(int on) {
+  int fd = open("/proc/starry/dac_enforce", O_WRONLY);
+  CHECK(fd >= 0);
+  CHECK(write(fd, on ? "1" : "0", 1) == 1);
+  close(fd);
+}
+
+// Drop the capabilities overriding file permissions, for good.
+static void drop_dac_override(void) {
+  struct __user_cap_header_struct header = {_LINUX_CAPABILITY_VERSION_3, 0};
+  struct __user_cap_data_struct data[2];
+  CHECK(syscall(SYS_capget, &header, data) == 0);
+  uint32_t mask =
+      CAP_TO_MASK(CAP_DAC_OVERRIDE) | CAP_TO_MASK(CAP_DAC_READ_SEARCH);
+  data[0].effective &= ~mask;
+  data[0].permitted &= ~mask;
+  CHECK(syscall(SYS_capset, &header, data) == 0);
+}
+
+// Run `check` in a child without the overriding capabilities.
+static void run_unprivileged(void (*check)(void)) {
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    drop_dac_override();
+    check();
+    _exit(0);
+  }
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+static void check_denied(void) {
+  struct stat st;
+  CHECK(stat(FILE_PATH, &st) == 0 && (st.st_mode & 0777) == 0600);
+  CHECK(open(FILE_PATH, O_RDONLY) == -1 && errno == EACCES);
+  CHECK(open(FILE_PATH, O_WRONLY) == -1 && errno == EACCES);
+  CHECK(faccessat(AT_FDCWD, FILE_PATH, R_OK, AT_EACCESS) == -1 &&
+        errno == EACCES);
+}
+
+static void check_allowed(void) {
+  struct stat st;
+  CHECK(stat(FILE_PATH, &st) == 0 && (st.st_mode & 0777) == 0644);
+  int fd = open(FILE_PATH, O_RDWR);
+  CHECK(fd >= 0);
+  char buf[16] = {0};
+  CHECK(read(fd, buf, sizeof(buf)) == 6 && strcmp(buf, "secret") == 0);
+  close(fd);
+  CHECK(faccessat(AT_FDCWD, FILE_PATH, R_OK | W_OK, AT_EACCESS) == 0);
+}
+
+static void check_dir_denied(void) {
+  CHECK(unlink(FILE_PATH) == -1 && errno == EACCES);
+  CHECK(open(DIR_PATH "/new", O_WRONLY | O_CREAT, 0644) == -1 &&
+        errno == EACCES);
+  CHECK(mkdir(DIR_PATH "/dir", 0755) == -1 && errno == EACCES);
+  CHECK(rename(FILE_PATH, DIR_PATH "/moved") == -1 && errno == EACCES);
+  // Out of the directory, or into it, needs write access to it too.
+  CHECK(rename(FILE_PATH, "/dac_enforce_moved") == -1 && errno == EACCES);
+  // The file itself can still be read.
+  int fd = open(FILE_PATH, O_RDONLY);
+  CHECK(fd >= 0);
+  close(fd);
+}
+
+static void check_moved_denied(void) {
+  CHECK(open(MOVED_FILE, O_RDONLY) == -1 && errno == EACCES);
+}
+
+static void check_permissive(void) {
+  int fd = open(FILE_PATH, O_RDWR);
+  CHECK(fd >= 0);
+  close(fd);
+  CHECK(unlink(FILE_PATH) == 0);
+}
+
+static void test_file_mode(void) {
+  set_enforcing(1);
+  CHECK(chmod(FILE_PATH, 0600) == 0);
+  run_unprivileged(check_denied);
+  CHECK(chmod(FILE_PATH, 0644) == 0);
+  run_unprivileged(check_allowed);
+  printf("test_file_mode ok\n");
+}
+
+static void test_dir_mode(void) {
+  CHECK(chmod(DIR_PATH, 0555) == 0);
+  run_unprivileged(check_dir_denied);
+  CHECK(chmod(DIR_PATH, 0755) == 0);
+  printf("test_dir_mode ok\n");
+}
+
+// The mode of a file goes with the directory it is in when that is
+// renamed, and a new file where it was does not get it.
+static void test_dir_rename(void) {
+  struct stat st;
+  CHECK(chmod(FILE_PATH, 0600) == 0);
+  CHECK(rename(DIR_PATH, MOVED_DIR) == 0);
+  CHECK(stat(MOVED_FILE, &st) == 0 && (st.st_mode & 0777) == 0600);
+  run_unprivileged(check_moved_denied);
+  CHECK(mkdir(DIR_PATH, 0755) == 0);
+  int fd = open(FILE_PATH, O_WRONLY | O_CREAT, 0644);
+  CHECK(fd >= 0);
+  close(fd);
+  CHECK(stat(FILE_PATH, &st) == 0 && (st.st_mode & 0777) != 0600);
+  CHECK(unlink(FILE_PATH) == 0);
+  CHECK(rmdir(DIR_PATH) == 0);
+  CHECK(rename(MOVED_DIR, DIR_PATH) == 0);
+  CHECK(stat(FILE_PATH, &st) == 0 && (st.st_mode & 0777) == 0600);
+  printf("test_dir_rename_mode ok\n");
+}
+
+static void test_permissive(void) {
+  // Only logged.
+  set_enforcing(0);
+  CHECK(chmod(FILE_PATH, 0) == 0);
+  CHECK(chmod(DIR_PATH, 0) == 0);
+  run_unprivileged(check_permissive);
+  CHECK(chmod(DIR_PATH, 0755) == 0);
+  printf("test_permissive ok\n");
+}
+
+int main(void) {
+  CHECK(mkdir(DIR_PATH, 0755) == 0);
+  int fd = open(FILE_PATH, O_WRONLY | O_CREAT, 0600);
+  CHECK(fd >= 0);
+  CHECK(write(fd, "secret", 6) == 6);
+  close(fd);
+
+  test_file_mode();
+  test_dir_mode();
+  test_dir_rename();
+  test_permissive();
+
+  CHECK(rmdir(DIR_PATH) == 0);
+  return 0;
+}
@@ -0,0 +1,46 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+
+void test_dup3_same_fd() {
+  errno = 0;
+  if (dup3(1, 1, 0) == -1 && errno == EINVAL) {
+    puts("test_dup3_same_fd ok");
+  }
+}
+
+void test_dup3_bad_flags() {
+  errno = 0;
+  if (dup3(1, 10, O_NONBLOCK) == -1 && errno == EINVAL) {
+    puts("test_dup3_bad_flags ok");
+  }
+}
+
+void test_dup3_bad_newfd() {
+  errno = 0;
+  if (dup3(1, -1, 0) == -1 && errno == EBADF) {
+    puts("test_dup3_bad_newfd ok");
+  }
+}
+
+void test_dup3() {
+  int fd = dup3(1, 10, O_CLOEXEC);
+  if (fd != 10) {
+    printf("dup3 failed: ret=%d errno=%d\n", fd, errno);
+    return;
+  }
+  const char *msg = "test_dup3 ok\n";
+  write(fd, msg, strlen(msg));
+  close(fd);
+}
+
+int main() {
+  test_dup3_same_fd();
+  test_dup3_bad_flags();
+  test_dup3_bad_newfd();
+  test_dup3();
+  return 0;
+}
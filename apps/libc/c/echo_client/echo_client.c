@@ -0,0 +1,35 @@
+// Talks to `echo_server`, which runs in the background meanwhile.
+#include <errno.h>
+#include <fcntl.h>
+#include <mqueue.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+
+int main() {
+  mqd_t req = (mqd_t)-1, resp = (mqd_t)-1;
+  // The server may not have created the queues yet.
+  for (int i = 0; i < 500 && req == (mqd_t)-1; i++) {
+    req = mq_open("/echo_req", O_WRONLY);
+    if (req == (mqd_t)-1 && errno == ENOENT) {
+      usleep(10000);
+    }
+  }
+  resp = mq_open("/echo_resp", O_RDONLY);
+  if (req == (mqd_t)-1 || resp == (mqd_t)-1) {
+    return 1;
+  }
+  int ok = 1;
+  char msg[16], buf[64];
+  for (int i = 0; i < 100; i++) {
+    int len = snprintf(msg, sizeof(msg), "ping %d", i);
+    ok &= mq_send(req, msg, len, 0) == 0 &&
+          mq_receive(resp, buf, sizeof(buf), NULL) == len &&
+          memcmp(buf, msg, len) == 0;
+  }
+  mq_send(req, "quit", 4, 0);
+  if (ok) {
+    puts("test_echo ok");
+  }
+  return !ok;
+}
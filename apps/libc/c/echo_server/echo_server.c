@@ -0,0 +1,29 @@
+// Echoes the messages of `/echo_req` back to `/echo_resp` until it gets
+// "quit". Started in the background before `echo_client`.
+#include <fcntl.h>
+#include <mqueue.h>
+#include <string.h>
+
+int main() {
+  struct mq_attr attr = {.mq_maxmsg = 4, .mq_msgsize = 64};
+  mqd_t resp = mq_open("/echo_resp", O_CREAT | O_WRONLY, 0600, &attr);
+  mqd_t req = mq_open("/echo_req", O_CREAT | O_RDONLY, 0600, &attr);
+  if (req == (mqd_t)-1 || resp == (mqd_t)-1) {
+    return 1;
+  }
+  char buf[64];
+  ssize_t len;
+  while ((len = mq_receive(req, buf, sizeof(buf), NULL)) >= 0) {
+    if (len == 4 && memcmp(buf, "quit", 4) == 0) {
+      break;
+    }
+    if (mq_send(resp, buf, len, 0) < 0) {
+      return 1;
+    }
+  }
+  mq_close(req);
+  mq_close(resp);
+  mq_unlink("/echo_req");
+  mq_unlink("/echo_resp");
+  return len < 0;
+}
@@ -0,0 +1,165 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define TMP_MNT "/enospc_tmp"
+#define IMAGE "/enospc.img"
+#define FAT_MNT "/enospc_fat"
+#define TMP_SIZE (64 * 1024)
+#define SECTORS 2048
+#define CHUNK 4096
+
+static uint8_t chunk[CHUNK];
+
+static void put16(uint8_t *p, uint16_t v) {
+  p[0] = v;
+  p[1] = v >> 8;
+}
+
+// Write an empty 1 MiB FAT12 image, with 4 sectors per cluster.
+static void make_image(const char *path) {
+  static uint8_t sector[512];
+  int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  for (int i = 0; i < SECTORS; i++) {
+    memset(sector, 0, sizeof(sector));
+    if (i == 0) {
+      memcpy(sector, "\xeb\x3c\x90MSDOS5.0", 11);
+      put16(sector + 11, 512);  // bytes per sector
+      sector[13] = 4;           // sectors per cluster
+      put16(sector + 14, 1);    // reserved sectors
+      sector[16] = 2;           // FATs
+      put16(sector + 17, 512);  // root entries
+      put16(sector + 19, SECTORS);
+      sector[21] = 0xf8;        // media
+      put16(sector + 22, 2);    // sectors per FAT
+      put16(sector + 24, 32);   // sectors per track
+      put16(sector + 26, 64);   // heads
+      sector[36] = 0x80;        // drive number
+      sector[38] = 0x29;        // extended boot signature
+      memcpy(sector + 39, "\x78\x56\x34\x12NO NAME    FAT12   ", 23);
+      sector[510] = 0x55;
+      sector[511] = 0xaa;
+    } else if (i == 1 || i == 3) {
+      memcpy(sector, "\xf8\xff\xff", 3);
+    }
+    CHECK(write(fd, sector, sizeof(sector)) == sizeof(sector));
+  }
+  CHECK(close(fd) == 0);
+}
+
+// The byte at `offset` of a file written by `fill`.
+static uint8_t pattern(long offset) { return offset / CHUNK * 7 + offset; }
+
+// Write to a new file at `path` until the filesystem is full, which fails
+// with `ENOSPC`, after which the size is what was written. Returns it.
+static long fill(const char *path) {
+  int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  long total = 0;
+  ssize_t n;
+  do {
+    for (int i = 0; i < CHUNK; i++) {
+      chunk[i] = pattern(total + i);
+    }
+    n = write(fd, chunk, CHUNK);
+    total += n > 0 ? n : 0;
+    CHECK(total <= SECTORS * 512);
+  } while (n == CHUNK);
+  CHECK(n == -1 && errno == ENOSPC);
+  struct stat st;
+  CHECK(fstat(fd, &st) == 0 && st.st_size == total);
+  CHECK(fallocate(fd, 0, total, CHUNK) == -1 && errno == ENOSPC);
+  CHECK(fstat(fd, &st) == 0 && st.st_size == total);
+  CHECK(close(fd) == 0);
+  return total;
+}
+
+// Check that the file at `path` holds the `size` bytes `fill` wrote.
+static void check_filled(const char *path, long size) {
+  int fd = open(path, O_RDONLY);
+  CHECK(fd >= 0);
+  long offset = 0;
+  ssize_t n;
+  while ((n = read(fd, chunk, CHUNK)) > 0) {
+    for (int i = 0; i < n; i++) {
+      CHECK(chunk[i] == pattern(offset + i));
+    }
+    offset += n;
+  }
+  CHECK(n == 0 && offset == size);
+  CHECK(close(fd) == 0);
+}
+
+// A tmpfs takes no more than its size, and the space of a removed file can
+// be used again.
+void test_tmpfs_full() {
+  CHECK(mkdir(TMP_MNT, 0755) == 0);
+  CHECK(mount("tmpfs", TMP_MNT, "tmpfs", 0, "size=64k") == 0);
+  CHECK(fill(TMP_MNT "/a") == TMP_SIZE);
+  check_filled(TMP_MNT "/a", TMP_SIZE);
+
+  int fd = open(TMP_MNT "/b", O_WRONLY | O_CREAT, 0644);
+  CHECK(fd >= 0);
+  CHECK(fallocate(fd, 0, 0, CHUNK) == -1 && errno == ENOSPC);
+  CHECK(fallocate(fd, FALLOC_FL_PUNCH_HOLE, 0, CHUNK) == -1 &&
+        errno == EOPNOTSUPP);
+  CHECK(fallocate(fd, 0, 0, 0) == -1 && errno == EINVAL);
+  CHECK(unlink(TMP_MNT "/a") == 0);
+  CHECK(fallocate(fd, 0, 0, 2 * CHUNK) == 0);
+  struct stat st;
+  CHECK(fstat(fd, &st) == 0 && st.st_size == 2 * CHUNK);
+  CHECK(write(fd, chunk, CHUNK) == CHUNK);
+  CHECK(close(fd) == 0);
+
+  CHECK(umount(TMP_MNT) == 0);
+  CHECK(rmdir(TMP_MNT) == 0);
+  puts("test_tmpfs_full ok");
+}
+
+// A full vfat keeps what was written, still makes a directory, and frees
+// every cluster of a removed file.
+void test_vfat_full() {
+  make_image(IMAGE);
+  CHECK(mkdir(FAT_MNT, 0755) == 0);
+  CHECK(mount(IMAGE, FAT_MNT, "vfat", 0, NULL) == 0);
+  long total = fill(FAT_MNT "/a");
+  CHECK(total > 900 * 1024);
+  CHECK(mkdir(FAT_MNT "/dir", 0755) == 0);
+  CHECK(mkdir(FAT_MNT "/dir2", 0755) == -1 && errno == ENOSPC);
+  CHECK(rmdir(FAT_MNT "/dir") == 0);
+  CHECK(umount(FAT_MNT) == 0);
+
+  // What the image holds after remounting it.
+  CHECK(mount(IMAGE, FAT_MNT, "vfat", 0, NULL) == 0);
+  check_filled(FAT_MNT "/a", total);
+  CHECK(unlink(FAT_MNT "/a") == 0);
+  CHECK(fill(FAT_MNT "/b") == total);
+  check_filled(FAT_MNT "/b", total);
+  CHECK(umount(FAT_MNT) == 0);
+
+  CHECK(rmdir(FAT_MNT) == 0);
+  CHECK(unlink(IMAGE) == 0);
+  puts("test_vfat_full ok");
+}
+
+int main() {
+  test_tmpfs_full();
+  test_vfat_full();
+  return 0;
+}
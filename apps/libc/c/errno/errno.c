@@ -0,0 +1,115 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+static const char *dir = "/errno_test";
+static const char *file = "/errno_test/file";
+static const char *sub = "/errno_test/sub";
+static const char *missing = "/errno_test/missing";
+
+static int failed;
+
+// Check that `ret` is a failure with `expected`, as set by the syscall.
+static void expect(const char *what, long ret, int expected) {
+  int err = errno;
+  if (ret != -1 || err != expected) {
+    printf("%s: ret=%ld errno=%s, expected %s\n", what, ret, strerror(err),
+           strerror(expected));
+    failed = 1;
+  }
+}
+
+#define EXPECT(call, expected)                                                 \
+  do {                                                                         \
+    errno = 0;                                                                 \
+    expect(#call, (long)(call), expected);                                     \
+  } while (0)
+
+static void report(const char *name) {
+  if (!failed) {
+    printf("%s ok\n", name);
+  }
+  failed = 0;
+}
+
+void test_open_errno() {
+  EXPECT(open(missing, O_RDONLY), ENOENT);
+  EXPECT(open(dir, O_WRONLY), EISDIR);
+  EXPECT(open(dir, O_RDWR), EISDIR);
+  EXPECT(open(dir, O_RDWR | O_DIRECTORY), EISDIR);
+  EXPECT(open(dir, O_WRONLY | O_CREAT, 0644), EISDIR);
+  EXPECT(open(file, O_WRONLY | O_CREAT | O_EXCL, 0644), EEXIST);
+  EXPECT(open(file, O_RDONLY | O_DIRECTORY), ENOTDIR);
+  report("test_open_errno");
+}
+
+void test_rw_errno() {
+  char buf[4] = "abc";
+  int fd = open(file, O_WRONLY);
+  EXPECT(read(fd, buf, sizeof(buf)), EBADF);
+  close(fd);
+  fd = open(file, O_RDONLY);
+  EXPECT(write(fd, buf, sizeof(buf)), EBADF);
+  close(fd);
+  EXPECT(read(fd, buf, sizeof(buf)), EBADF);
+
+  fd = open(dir, O_RDONLY);
+  EXPECT(read(fd, buf, sizeof(buf)), EISDIR);
+  close(fd);
+
+  int fds[2];
+  pipe(fds);
+  EXPECT(read(fds[1], buf, sizeof(buf)), EBADF);
+  EXPECT(write(fds[0], buf, sizeof(buf)), EBADF);
+  close(fds[0]);
+  close(fds[1]);
+  report("test_rw_errno");
+}
+
+void test_unlink_errno() {
+  EXPECT(unlink(missing), ENOENT);
+  EXPECT(unlink("/errno_test/missing/file"), ENOENT);
+  EXPECT(unlink(sub), EISDIR);
+  report("test_unlink_errno");
+}
+
+void test_dir_errno() {
+  EXPECT(mkdir(sub, 0755), EEXIST);
+  EXPECT(mkdir("/errno_test/missing/sub", 0755), ENOENT);
+  EXPECT(rmdir(missing), ENOENT);
+  EXPECT(rmdir(file), ENOTDIR);
+  EXPECT(rmdir(dir), ENOTEMPTY);
+  EXPECT(rmdir("/"), EBUSY);
+  report("test_dir_errno");
+}
+
+void test_mount_errno() {
+  EXPECT(mount("/dev/vda2", sub, "nosuchfs", 0, NULL), ENODEV);
+  EXPECT(mount("/dev/vda2", missing, "vfat", 0, NULL), ENOENT);
+  EXPECT(mount("/dev/vda2", file, "vfat", 0, NULL), ENOTDIR);
+  EXPECT(umount2(missing, 0), ENOENT);
+  EXPECT(umount2(sub, 0), EINVAL);
+  report("test_mount_errno");
+}
+
+int main() {
+  mkdir(dir, 0755);
+  mkdir(sub, 0755);
+  close(open(file, O_CREAT | O_WRONLY, 0644));
+
+  test_open_errno();
+  test_rw_errno();
+  test_unlink_errno();
+  test_dir_errno();
+  test_mount_errno();
+
+  unlink(file);
+  rmdir(sub);
+  rmdir(dir);
+  return 0;
+}
@@ -0,0 +1,118 @@
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+// A copy of this program, padded to about 10 MiB. The loader reads the
+// whole file, padding included, while the ELF headers only map the copy.
+#define BIN_PATH "/exec_bench.bin"
+#define BIN_SIZE (10 << 20)
+#define EXECS 5
+#define CHUNK (64 << 10)
+
+static double now(void) {
+  struct timespec ts;
+  clock_gettime(CLOCK_MONOTONIC, &ts);
+  return ts.tv_sec + ts.tv_nsec / 1e9;
+}
+
+// The blocks of the root device read ahead so far, from the last column of
+// /proc/starry/fscache, or -1 if there is no such column, as on kernels
+// without readahead, to compare the timings with.
+static long read_ahead(void) {
+  FILE *f = fopen("/proc/starry/fscache", "r");
+  CHECK(f != NULL);
+  char line[256];
+  CHECK(fgets(line, sizeof(line), f) != NULL);
+  long stats[8];
+  int n = fscanf(f, "vda %ld %ld %ld %ld %ld %ld %ld %ld", &stats[0],
+                 &stats[1], &stats[2], &stats[3], &stats[4], &stats[5],
+                 &stats[6], &stats[7]);
+  fclose(f);
+  return n == 8 ? stats[7] : -1;
+}
+
+static void make_binary(void) {
+  int src = open("/proc/self/exe", O_RDONLY);
+  CHECK(src >= 0);
+  int dst = open(BIN_PATH, O_WRONLY | O_CREAT | O_TRUNC, 0755);
+  CHECK(dst >= 0);
+  char *buf = calloc(1, CHUNK);
+  CHECK(buf != NULL);
+  ssize_t len;
+  off_t size = 0;
+  while ((len = read(src, buf, CHUNK)) > 0) {
+    CHECK(write(dst, buf, len) == len);
+    size += len;
+  }
+  CHECK(len == 0);
+  memset(buf, 0, CHUNK);
+  while (size < BIN_SIZE) {
+    len = BIN_SIZE - size < CHUNK ? BIN_SIZE - size : CHUNK;
+    CHECK(write(dst, buf, len) == len);
+    size += len;
+  }
+  free(buf);
+  CHECK(close(src) == 0);
+  CHECK(fsync(dst) == 0);
+  CHECK(close(dst) == 0);
+}
+
+// Runs the copy, which exits at once, and returns how long it took.
+static double run_binary(void) {
+  double start = now();
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    execl(BIN_PATH, BIN_PATH, "child", NULL);
+    _exit(127);
+  }
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  return now() - start;
+}
+
+// Times exec of a 10 MiB binary, which is far larger than the block cache,
+// so that every exec reads it from the device again. The binary is read
+// sequentially, so most of it comes from the blocks read ahead.
+void test_exec_readahead() {
+  make_binary();
+  long before = read_ahead();
+  double total = 0, best = 0;
+  for (int i = 0; i < EXECS; i++) {
+    double took = run_binary();
+    total += took;
+    if (i == 0 || took < best) {
+      best = took;
+    }
+  }
+  long blocks = read_ahead() - before;
+  printf("exec_bench: %d MiB binary, %d execs, %.1fms mean, %.1fms best, "
+         "%ld blocks read ahead\n",
+         BIN_SIZE >> 20, EXECS, total / EXECS * 1e3, best * 1e3, blocks);
+  CHECK(before >= 0 && blocks > 0);
+  CHECK(unlink(BIN_PATH) == 0);
+  puts("test_exec_readahead ok");
+}
+
+int main(int argc, char **argv) {
+  if (argc > 1 && strcmp(argv[1], "child") == 0) {
+    return 0;
+  }
+  test_exec_readahead();
+  return 0;
+}
@@ -0,0 +1,126 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <limits.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define SCRIPT_PATH "/tmp/exec_path_script"
+#define TEXT_PATH "/tmp/exec_path_text"
+
+static char self[PATH_MAX];
+
+static void write_file(const char *path, const char *text, mode_t mode) {
+  int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, mode);
+  CHECK(fd >= 0);
+  CHECK(write(fd, text, strlen(text)) == (ssize_t)strlen(text));
+  CHECK(close(fd) == 0);
+  CHECK(chmod(path, mode) == 0);
+}
+
+// Run `path` with `argv` in a child, and check that it exits with 0.
+static void run(const char *path, char *const argv[]) {
+  char *envp[] = {"EXEC_PATH=1", NULL};
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    execve(path, argv, envp);
+    printf("execve(%s): %s\n", path, strerror(errno));
+    _exit(1);
+  }
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+// The program at an absolute path runs, with its arguments and
+// environment.
+void test_absolute() {
+  char *argv[] = {"renamed", "child", NULL};
+  run(self, argv);
+  puts("test_absolute ok");
+}
+
+// A relative path is resolved against the cwd, with or without a slash.
+void test_relative() {
+  char dir[PATH_MAX];
+  strcpy(dir, self);
+  char *base = strrchr(dir, '/');
+  *base++ = '\0';
+  char *cwd = getcwd(NULL, 0);
+  CHECK(cwd != NULL);
+  CHECK(chdir(dir[0] ? dir : "/") == 0);
+  char dotted[PATH_MAX];
+  snprintf(dotted, sizeof(dotted), "./%s", base);
+  char *argv[] = {base, "child", NULL};
+  run(dotted, argv);
+  run(base, argv);
+  CHECK(chdir(cwd) == 0);
+  free(cwd);
+  puts("test_relative ok");
+}
+
+// A script runs its interpreter with the optional argument, the path of
+// the script, and the arguments after the first.
+void test_script() {
+  char text[PATH_MAX + 32];
+  snprintf(text, sizeof(text), "#!%s script\n", self);
+  write_file(SCRIPT_PATH, text, 0755);
+  char *argv[] = {"ignored", "arg", NULL};
+  run(SCRIPT_PATH, argv);
+  CHECK(unlink(SCRIPT_PATH) == 0);
+  puts("test_script ok");
+}
+
+// What cannot run fails, and leaves the caller running.
+void test_errors() {
+  char *argv[] = {"x", NULL};
+  char *envp[] = {NULL};
+  CHECK(execve("/nonexistent/exec_path", argv, envp) == -1 &&
+        errno == ENOENT);
+  CHECK(execve("/tmp", argv, envp) == -1 && errno == EACCES);
+  write_file(TEXT_PATH, "not a program\n", 0644);
+  CHECK(execve(TEXT_PATH, argv, envp) == -1 && errno == EACCES);
+  CHECK(chmod(TEXT_PATH, 0755) == 0);
+  CHECK(execve(TEXT_PATH, argv, envp) == -1 && errno == ENOEXEC);
+  CHECK(unlink(TEXT_PATH) == 0);
+  puts("test_errors ok");
+}
+
+int main(int argc, char *argv[]) {
+  if (argc == 2 && strcmp(argv[1], "child") == 0) {
+    const char *env = getenv("EXEC_PATH");
+    return env && strcmp(env, "1") == 0 ? 0 : 1;
+  }
+  if (argc > 1 && strcmp(argv[1], "script") == 0) {
+    return argc == 4 && strcmp(argv[2], SCRIPT_PATH) == 0 &&
+                   strcmp(argv[3], "arg") == 0
+               ? 0
+               : 1;
+  }
+  ssize_t len = readlink("/proc/self/exe", self, sizeof(self) - 1);
+  CHECK(len > 0);
+  self[len] = '\0';
+  // Made absolute, in case the program was started by a relative path.
+  char *abs = realpath(self, NULL);
+  CHECK(abs != NULL);
+  snprintf(self, sizeof(self), "%s", abs);
+  free(abs);
+  test_absolute();
+  test_relative();
+  test_script();
+  test_errors();
+  return 0;
+}
@@ -0,0 +1,120 @@
+#include <errno.h>
+#include <pthread.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define ROUNDS 50
+#define SPAWNERS 4
+
+static const char *self;
+
+static void *idle(void *arg) {
+  (void)arg;
+  return NULL;
+}
+
+// Create threads as fast as possible, until killed by `execve`.
+static void *spawner(void *arg) {
+  (void)arg;
+  for (;;) {
+    pthread_t thread;
+    if (pthread_create(&thread, NULL, idle, NULL) == 0) {
+      pthread_join(thread, NULL);
+    }
+  }
+  return NULL;
+}
+
+// Run the program again, with an argument which makes it exit at once.
+static void *exec_self(void *arg) {
+  (void)arg;
+  char *argv[] = {(char *)self, "done", NULL};
+  execv(self, argv);
+  // Another thread is already at it, and kills this one.
+  while (errno == EAGAIN) {
+    pause();
+  }
+  printf("execv: %s\n", strerror(errno));
+  exit(1);
+}
+
+// Start `SPAWNERS` threads and `execve` in the main thread, or in another
+// thread if `from_thread` is set.
+static void race(int from_thread) {
+  pthread_t thread;
+  for (int i = 0; i < SPAWNERS; i++) {
+    CHECK(pthread_create(&thread, NULL, spawner, NULL) == 0);
+  }
+  usleep(2000);
+  if (from_thread) {
+    CHECK(pthread_create(&thread, NULL, exec_self, NULL) == 0);
+    for (;;) {
+      pause();
+    }
+  }
+  exec_self(NULL);
+}
+
+// Each round, a child races `execve` against threads creating threads. The
+// program run by `execve` exits with 0 at once.
+static void test_rounds(int from_thread) {
+  for (int i = 0; i < ROUNDS; i++) {
+    pid_t pid = fork();
+    CHECK(pid >= 0);
+    if (pid == 0) {
+      race(from_thread);
+    }
+    int status;
+    CHECK(waitpid(pid, &status, 0) == pid);
+    CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  }
+}
+
+static void test_exec_main(void) {
+  test_rounds(0);
+  puts("test_exec_main ok");
+}
+
+static void test_exec_thread(void) {
+  test_rounds(1);
+  puts("test_exec_thread ok");
+}
+
+// Two threads call `execve` at once: one of them wins.
+static void test_exec_twice(void) {
+  for (int i = 0; i < ROUNDS; i++) {
+    pid_t pid = fork();
+    CHECK(pid >= 0);
+    if (pid == 0) {
+      pthread_t thread;
+      CHECK(pthread_create(&thread, NULL, exec_self, NULL) == 0);
+      exec_self(NULL);
+    }
+    int status;
+    CHECK(waitpid(pid, &status, 0) == pid);
+    CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  }
+  puts("test_exec_twice ok");
+}
+
+int main(int argc, char **argv) {
+  if (argc > 1 && strcmp(argv[1], "done") == 0) {
+    return 0;
+  }
+  self = argv[0];
+  test_exec_main();
+  test_exec_thread();
+  test_exec_twice();
+  return 0;
+}
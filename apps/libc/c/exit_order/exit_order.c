@@ -0,0 +1,122 @@
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <poll.h>
+#include <sched.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+// Fork a child which writes a byte to a pipe and exits with the write end
+// still open. Returns the pid, with the read end in `*rfd`.
+static pid_t fork_writer(int *rfd) {
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    close(fds[0]);
+    write(fds[1], "x", 1);
+    _exit(0);
+  }
+  close(fds[1]);
+  *rfd = fds[0];
+  return pid;
+}
+
+// Whether all write ends of the pipe are closed, without waiting.
+static int hung_up(int rfd) {
+  struct pollfd pfd = {.fd = rfd, .events = POLLIN};
+  return poll(&pfd, 1, 0) == 1 && (pfd.revents & POLLHUP);
+}
+
+// The files of a child are closed by the time `wait` sees it.
+static void test_files_before_wait(void) {
+  int rfd;
+  pid_t pid = fork_writer(&rfd);
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  CHECK(hung_up(rfd));
+  char c;
+  CHECK(read(rfd, &c, 1) == 1 && c == 'x');
+  CHECK(read(rfd, &c, 1) == 0);
+  close(rfd);
+  puts("test_files_before_wait ok");
+}
+
+static int sigchld_rfd;
+static volatile sig_atomic_t sigchld_seen;
+static volatile sig_atomic_t sigchld_hung_up;
+
+static void on_sigchld(int signo) {
+  (void)signo;
+  sigchld_hung_up = hung_up(sigchld_rfd);
+  sigchld_seen = 1;
+}
+
+// ... and by the time `SIGCHLD` arrives.
+static void test_files_before_sigchld(void) {
+  sigset_t block, old;
+  sigemptyset(&block);
+  sigaddset(&block, SIGCHLD);
+  CHECK(sigprocmask(SIG_BLOCK, &block, &old) == 0);
+  struct sigaction sa;
+  memset(&sa, 0, sizeof(sa));
+  sa.sa_handler = on_sigchld;
+  CHECK(sigaction(SIGCHLD, &sa, NULL) == 0);
+
+  pid_t pid = fork_writer(&sigchld_rfd);
+  while (!sigchld_seen) {
+    sigsuspend(&old);
+  }
+  CHECK(sigchld_hung_up);
+  CHECK(waitpid(pid, NULL, 0) == pid);
+  close(sigchld_rfd);
+
+  sa.sa_handler = SIG_DFL;
+  CHECK(sigaction(SIGCHLD, &sa, NULL) == 0);
+  CHECK(sigprocmask(SIG_SETMASK, &old, NULL) == 0);
+  puts("test_files_before_sigchld ok");
+}
+
+static char stack[64 << 10];
+static volatile pid_t child_tid;
+
+static int sleeper(void *arg) {
+  (void)arg;
+  usleep(10000);
+  return 0;
+}
+
+// `clear_child_tid` is cleared, which is what wakes `pthread_join`, before
+// `wait` sees the child.
+static void test_clear_tid_before_wait(void) {
+  pid_t pid = clone(sleeper, stack + sizeof(stack),
+                    CLONE_VM | CLONE_CHILD_SETTID | CLONE_CHILD_CLEARTID |
+                        SIGCHLD,
+                    NULL, NULL, NULL, &child_tid);
+  CHECK(pid > 0);
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status));
+  CHECK(child_tid == 0);
+  puts("test_clear_tid_before_wait ok");
+}
+
+int main(void) {
+  test_files_before_wait();
+  test_files_before_sigchld();
+  test_clear_tid_before_wait();
+  return 0;
+}
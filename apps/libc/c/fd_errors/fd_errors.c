@@ -0,0 +1,102 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <sys/mman.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+static int try_lseek(int fd) { return lseek(fd, 0, SEEK_SET) < 0 ? -1 : 0; }
+
+static int try_fstat(int fd) {
+  struct stat st;
+  return fstat(fd, &st);
+}
+
+static int try_getdents(int fd) {
+  char buf[1024];
+  return syscall(SYS_getdents64, fd, buf, sizeof(buf)) < 0 ? -1 : 0;
+}
+
+static int try_fchdir(int fd) {
+  int ret = fchdir(fd);
+  if (ret == 0) {
+    chdir("/");
+  }
+  return ret;
+}
+
+static int try_mmap(int fd) {
+  void *p = mmap(NULL, 4096, PROT_READ, MAP_PRIVATE, fd, 0);
+  if (p == MAP_FAILED) {
+    return -1;
+  }
+  munmap(p, 4096);
+  return 0;
+}
+
+enum { CLOSED, WRONG, VALID };
+
+struct fd_case {
+  const char *name;
+  int (*call)(int fd);
+  // The kind of file the valid fd is, and the error of the wrong one.
+  int valid_is_dir;
+  int wrong_is_dir;
+  int wrong_errno;
+};
+
+static const struct fd_case CASES[] = {
+    {"lseek", try_lseek, 0, 0, ESPIPE},
+    {"fstat", try_fstat, 0, 0, 0},
+    {"getdents64", try_getdents, 1, 0, ENOTDIR},
+    {"fchdir", try_fchdir, 1, 0, ENOTDIR},
+    {"mmap", try_mmap, 0, 0, ENODEV},
+};
+
+// Each syscall fails with `EBADF` on a closed fd, with its own error on an
+// fd of the wrong type, and succeeds on a valid one.
+void test_fd_errors() {
+  int pipes[2];
+  pipe(pipes);
+  int file = open("/tmp/fd_errors", O_RDWR | O_CREAT | O_TRUNC, 0644);
+  write(file, "data", 4);
+  int dir = open("/", O_RDONLY | O_DIRECTORY);
+  int closed = open("/", O_RDONLY);
+  close(closed);
+  if (file < 0 || dir < 0) {
+    return;
+  }
+
+  int ok = 1;
+  for (size_t i = 0; i < sizeof(CASES) / sizeof(CASES[0]); i++) {
+    const struct fd_case *c = &CASES[i];
+    int fds[] = {closed, c->wrong_is_dir ? dir : pipes[0],
+                 c->valid_is_dir ? dir : file};
+    int expect[] = {EBADF, c->wrong_errno, 0};
+    for (int kind = CLOSED; kind <= VALID; kind++) {
+      errno = 0;
+      int ret = c->call(fds[kind]);
+      int err = ret < 0 ? errno : 0;
+      if (err != expect[kind]) {
+        printf("%s on fd kind %d: errno %d, expected %d\n", c->name, kind, err,
+               expect[kind]);
+        ok = 0;
+      }
+    }
+  }
+  if (ok) {
+    puts("test_fd_errors ok");
+  }
+  close(pipes[0]);
+  close(pipes[1]);
+  close(file);
+  close(dir);
+  unlink("/tmp/fd_errors");
+}
+
+int main() {
+  test_fd_errors();
+  return 0;
+}
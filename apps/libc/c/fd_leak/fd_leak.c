@@ -0,0 +1,72 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+
+#define ROUNDS 64
+#define REGULAR "/fd_leak_file"
+
+// Get the number of live files from `/proc/sys/fs/file-nr`.
+static long live_files() {
+  char buf[64];
+  int fd = open("/proc/sys/fs/file-nr", O_RDONLY);
+  if (fd < 0) {
+    return -1;
+  }
+  ssize_t len = read(fd, buf, sizeof(buf) - 1);
+  close(fd);
+  if (len <= 0) {
+    return -1;
+  }
+  buf[len] = 0;
+  long count;
+  return sscanf(buf, "%ld", &count) == 1 ? count : -1;
+}
+
+// Get the lowest free fd.
+static int lowest_fd() {
+  int fd = dup(0);
+  close(fd);
+  return fd;
+}
+
+void test_pipe2_fault() {
+  long before = live_files();
+  int fd = lowest_fd();
+  for (int i = 0; i < ROUNDS; i++) {
+    if (pipe2((int *)8, 0) != -1 || errno != EFAULT) {
+      return;
+    }
+  }
+  if (before >= 0 && live_files() == before && lowest_fd() == fd) {
+    puts("test_pipe2_fault ok");
+  }
+}
+
+void test_open_directory() {
+  int file = open(REGULAR, O_WRONLY | O_CREAT, 0644);
+  if (file < 0) {
+    return;
+  }
+  close(file);
+  long before = live_files();
+  int fd = lowest_fd();
+  for (int i = 0; i < ROUNDS; i++) {
+    if (open(REGULAR, O_RDONLY | O_DIRECTORY) != -1 || errno != ENOTDIR) {
+      unlink(REGULAR);
+      return;
+    }
+  }
+  unlink(REGULAR);
+  if (before >= 0 && live_files() == before && lowest_fd() == fd) {
+    puts("test_open_directory ok");
+  }
+}
+
+int main() {
+  test_pipe2_fault();
+  test_open_directory();
+  return 0;
+}
@@ -0,0 +1,160 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <netinet/in.h>
+#include <pthread.h>
+#include <stdatomic.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+// `pipe2` applies both flags to both ends.
+void test_pipe2_flags() {
+  int fds[2];
+  char c;
+  if (pipe2(fds, O_CLOEXEC | O_NONBLOCK) < 0) {
+    return;
+  }
+  if (read(fds[0], &c, 1) == -1 && errno == EAGAIN &&
+      fcntl(fds[0], F_GETFD) == FD_CLOEXEC &&
+      fcntl(fds[1], F_GETFD) == FD_CLOEXEC) {
+    puts("test_pipe2_flags ok");
+  }
+  close(fds[0]);
+  close(fds[1]);
+}
+
+// `SOCK_CLOEXEC` and `SOCK_NONBLOCK` are masked off the socket type, and a
+// non-blocking listener has nothing to accept yet.
+void test_socket_flags() {
+  int fd = socket(AF_INET, SOCK_STREAM | SOCK_CLOEXEC | SOCK_NONBLOCK, 0);
+  if (fd < 0 || fcntl(fd, F_GETFD) != FD_CLOEXEC) {
+    return;
+  }
+  struct sockaddr_in addr = {0};
+  addr.sin_family = AF_INET;
+  addr.sin_port = htons(5555);
+  addr.sin_addr.s_addr = htonl(INADDR_ANY);
+  if (bind(fd, (struct sockaddr *)&addr, sizeof(addr)) == 0 &&
+      listen(fd, 1) == 0 &&
+      accept4(fd, NULL, NULL, SOCK_CLOEXEC) == -1 && errno == EAGAIN) {
+    puts("test_socket_flags ok");
+  }
+  close(fd);
+}
+
+// Unknown bits are rejected rather than ignored.
+void test_bad_flags() {
+  int fds[2];
+  if (socket(AF_INET, SOCK_STREAM | 0x100, 0) == -1 && errno == EINVAL &&
+      accept4(0, NULL, NULL, 1) == -1 && errno == EINVAL &&
+      pipe2(fds, O_APPEND) == -1 && errno == EINVAL) {
+    puts("test_bad_flags ok");
+  }
+}
+
+// Only the descriptors with `FD_CLOEXEC` are closed by `execve`.
+void test_exec_closes(const char *self) {
+  int fds[2];
+  if (pipe2(fds, O_CLOEXEC) < 0 || fcntl(fds[1], F_SETFD, 0) < 0) {
+    return;
+  }
+  pid_t pid = fork();
+  if (pid == 0) {
+    char closed[16], kept[16];
+    snprintf(closed, sizeof(closed), "%d", fds[0]);
+    snprintf(kept, sizeof(kept), "%d", fds[1]);
+    char *argv[] = {(char *)self, "check", closed, kept, NULL};
+    execv(self, argv);
+    _exit(1);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 0) {
+    puts("test_exec_closes ok");
+  }
+  close(fds[0]);
+  close(fds[1]);
+}
+
+#define STORM_THREADS 4
+#define STORM_ROUNDS 2000
+
+static atomic_int storm_done;
+static atomic_int wrong_flag;
+
+// Open and close descriptors without `FD_CLOEXEC`, checking each one has
+// none.
+static void *storm(void *arg) {
+  (void)arg;
+  for (int i = 0; i < STORM_ROUNDS; i++) {
+    int fd = open("/dev/null", O_RDONLY);
+    if (fd < 0)
+      continue;
+    if (fcntl(fd, F_GETFD) != 0)
+      atomic_store(&wrong_flag, 1);
+    close(fd);
+  }
+  atomic_fetch_add(&storm_done, 1);
+  return NULL;
+}
+
+// Check the descriptor `fd` was made with `FD_CLOEXEC`, and close it.
+static void check_cloexec(int fd) {
+  if (fd < 0)
+    return;
+  if (fcntl(fd, F_GETFD) != FD_CLOEXEC)
+    atomic_store(&wrong_flag, 1);
+  close(fd);
+}
+
+// While other threads close descriptors and get them back, the flags of a
+// new descriptor land on it and on no other.
+void test_cloexec_race() {
+  pthread_t threads[STORM_THREADS];
+  for (int i = 0; i < STORM_THREADS; i++) {
+    if (pthread_create(&threads[i], NULL, storm, NULL) != 0) {
+      return;
+    }
+  }
+  while (atomic_load(&storm_done) < STORM_THREADS) {
+    int fds[2];
+    if (pipe2(fds, O_CLOEXEC) == 0) {
+      check_cloexec(fds[0]);
+      check_cloexec(fds[1]);
+    }
+    check_cloexec(open("/dev/null", O_RDONLY | O_CLOEXEC));
+    check_cloexec(socket(AF_INET, SOCK_DGRAM | SOCK_CLOEXEC, 0));
+    check_cloexec(fcntl(0, F_DUPFD_CLOEXEC, 0));
+  }
+  for (int i = 0; i < STORM_THREADS; i++) {
+    pthread_join(threads[i], NULL);
+  }
+  if (!atomic_load(&wrong_flag)) {
+    puts("test_cloexec_race ok");
+  }
+}
+
+int main(int argc, char **argv) {
+  if (argc == 4 && strcmp(argv[1], "check") == 0) {
+    int closed = fcntl(atoi(argv[2]), F_GETFD) == -1 && errno == EBADF;
+    int kept = fcntl(atoi(argv[3]), F_GETFD) == 0;
+    return closed && kept ? 0 : 1;
+  }
+  char self[256];
+  ssize_t len = readlink("/proc/self/exe", self, sizeof(self) - 1);
+  if (len <= 0) {
+    return 1;
+  }
+  self[len] = '\0';
+
+  test_pipe2_flags();
+  test_socket_flags();
+  test_bad_flags();
+  test_exec_closes(self);
+  test_cloexec_race();
+  return 0;
+}
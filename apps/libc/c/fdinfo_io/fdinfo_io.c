@@ -0,0 +1,133 @@
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/uio.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define FILE_PATH "/fdinfo_io.tmp"
+
+struct fdinfo {
+  long pos, flags, reads, read_bytes, writes, write_bytes;
+};
+
+// The fields of /proc/self/fdinfo/<fd>, -1 for those not there.
+static struct fdinfo fdinfo(int fd) {
+  char path[64];
+  snprintf(path, sizeof(path), "/proc/self/fdinfo/%d", fd);
+  FILE *f = fopen(path, "r");
+  CHECK(f != NULL);
+  struct fdinfo info = {-1, -1, -1, -1, -1, -1};
+  char line[128];
+  while (fgets(line, sizeof(line), f)) {
+    sscanf(line, "pos: %ld", &info.pos);
+    sscanf(line, "flags: %lo", &info.flags);
+    sscanf(line, "reads: %ld", &info.reads);
+    sscanf(line, "read_bytes: %ld", &info.read_bytes);
+    sscanf(line, "writes: %ld", &info.writes);
+    sscanf(line, "write_bytes: %ld", &info.write_bytes);
+  }
+  fclose(f);
+  return info;
+}
+
+// Each end of a pipe counts the calls and bytes through it, of `readv` and
+// `writev` too, and a failed read counts nothing.
+void test_pipe() {
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  char buf[100] = {0};
+  CHECK(write(fds[1], buf, 40) == 40);
+  struct iovec iov[2] = {{buf, 10}, {buf + 10, 50}};
+  CHECK(writev(fds[1], iov, 2) == 60);
+  CHECK(read(fds[0], buf, 30) == 30);
+  CHECK(read(fds[0], buf, 30) == 30);
+  iov[1].iov_len = 30;
+  CHECK(readv(fds[0], iov, 2) == 40);
+  CHECK(fcntl(fds[0], F_SETFL, O_NONBLOCK) == 0);
+  CHECK(read(fds[0], buf, 1) == -1);
+
+  struct fdinfo r = fdinfo(fds[0]), w = fdinfo(fds[1]);
+  CHECK(r.reads == 3 && r.read_bytes == 100);
+  CHECK(r.writes == 0 && r.write_bytes == 0);
+  CHECK(w.writes == 2 && w.write_bytes == 100);
+  CHECK(w.reads == 0 && w.read_bytes == 0);
+  CHECK(r.pos == 0 && w.pos == 0);
+  CHECK(r.flags == (O_RDONLY | O_NONBLOCK) && w.flags == O_WRONLY);
+
+  // A duplicate shares the counters of the file.
+  int dup_fd = dup(fds[1]);
+  CHECK(dup_fd >= 0);
+  CHECK(write(dup_fd, buf, 5) == 5);
+  CHECK(fdinfo(fds[1]).write_bytes == 105);
+  CHECK(close(dup_fd) == 0);
+  CHECK(close(fds[0]) == 0);
+  CHECK(close(fds[1]) == 0);
+  puts("test_pipe ok");
+}
+
+// A regular file shows its offset and close-on-exec among the flags.
+void test_file() {
+  int fd = open(FILE_PATH, O_RDWR | O_CREAT | O_TRUNC | O_CLOEXEC, 0644);
+  CHECK(fd >= 0);
+  char buf[64] = {0};
+  CHECK(write(fd, buf, sizeof(buf)) == sizeof(buf));
+  CHECK(lseek(fd, 10, SEEK_SET) == 10);
+  CHECK(read(fd, buf, 4) == 4);
+  struct fdinfo info = fdinfo(fd);
+  CHECK(info.pos == 14);
+  CHECK(info.flags == (O_RDWR | O_CLOEXEC));
+  CHECK(info.writes == 1 && info.write_bytes == sizeof(buf));
+  CHECK(info.reads == 1 && info.read_bytes == 4);
+  CHECK(close(fd) == 0);
+  CHECK(unlink(FILE_PATH) == 0);
+  puts("test_file ok");
+}
+
+// The descriptors which moved the most bytes are listed in
+// /proc/starry/pressure.
+void test_pressure() {
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  char buf[4096] = {0};
+  for (int i = 0; i < 256; i++) {
+    CHECK(write(fds[1], buf, sizeof(buf)) == sizeof(buf));
+    CHECK(read(fds[0], buf, sizeof(buf)) == sizeof(buf));
+  }
+  FILE *f = fopen("/proc/starry/pressure", "r");
+  CHECK(f != NULL);
+  char line[256];
+  int in_fds = 0, found = 0;
+  while (fgets(line, sizeof(line), f)) {
+    if (strncmp(line, "pid fd ", 7) == 0) {
+      in_fds = 1;
+      continue;
+    }
+    long pid, fd, reads, read_bytes, writes, write_bytes;
+    if (in_fds && sscanf(line, "%ld %ld %ld %ld %ld %ld", &pid, &fd, &reads,
+                         &read_bytes, &writes, &write_bytes) == 6 &&
+        pid == getpid() && fd == fds[1])
+      found = writes == 256 && write_bytes == 256 * sizeof(buf);
+  }
+  fclose(f);
+  CHECK(found);
+  CHECK(close(fds[0]) == 0);
+  CHECK(close(fds[1]) == 0);
+  puts("test_pressure ok");
+}
+
+int main() {
+  test_pipe();
+  test_file();
+  test_pressure();
+  return 0;
+}
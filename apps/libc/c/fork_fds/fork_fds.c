@@ -0,0 +1,115 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/resource.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define ROUNDS 100
+#define MANY_FDS 500
+
+static double now(void) {
+  struct timespec ts;
+  clock_gettime(CLOCK_MONOTONIC, &ts);
+  return ts.tv_sec + ts.tv_nsec / 1e9;
+}
+
+// Fork a child that exits at once, `ROUNDS` times. Returns the average
+// microseconds per fork, wait included.
+static double fork_latency(void) {
+  double start = now();
+  for (int i = 0; i < ROUNDS; i++) {
+    pid_t pid = fork();
+    CHECK(pid >= 0);
+    if (pid == 0) {
+      _exit(0);
+    }
+    int status;
+    CHECK(waitpid(pid, &status, 0) == pid);
+    CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  }
+  return (now() - start) / ROUNDS * 1e6;
+}
+
+static int is_open(int fd) { return fcntl(fd, F_GETFD) >= 0 || errno != EBADF; }
+
+// Changes made to the table on either side after a fork must not show on the
+// other.
+void test_isolation() {
+  int a = open("/dev/null", O_RDONLY);
+  int b = open("/dev/null", O_RDONLY);
+  CHECK(a >= 0 && b >= 0);
+  int sync[2];
+  CHECK(pipe(sync) == 0);
+
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    char c;
+    // Wait for the parent to change its table first.
+    CHECK(read(sync[0], &c, 1) == 1);
+    CHECK(is_open(a));
+    CHECK(fcntl(b, F_GETFD) == 0);
+    // Then change our own.
+    close(a);
+    int c_fd = open("/dev/null", O_RDONLY);
+    _exit(c_fd == a ? 0 : 2);
+  }
+  close(a);
+  CHECK(fcntl(b, F_SETFD, FD_CLOEXEC) == 0);
+  int extra = open("/dev/null", O_RDONLY);
+  CHECK(extra == a);
+  CHECK(write(sync[1], "x", 1) == 1);
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  CHECK(fcntl(b, F_GETFD) == FD_CLOEXEC);
+  CHECK(is_open(extra));
+
+  close(extra);
+  close(b);
+  close(sync[0]);
+  close(sync[1]);
+  puts("test_isolation ok");
+}
+
+void test_latency() {
+  double few = fork_latency();
+
+  struct rlimit lim;
+  CHECK(getrlimit(RLIMIT_NOFILE, &lim) == 0);
+  if (lim.rlim_cur < MANY_FDS + 16) {
+    lim.rlim_cur = MANY_FDS + 16;
+    CHECK(setrlimit(RLIMIT_NOFILE, &lim) == 0);
+  }
+  int fds[MANY_FDS];
+  for (int i = 0; i < MANY_FDS; i++) {
+    fds[i] = open("/dev/null", O_RDONLY);
+    CHECK(fds[i] >= 0);
+  }
+  double many = fork_latency();
+  for (int i = 0; i < MANY_FDS; i++) {
+    close(fds[i]);
+  }
+
+  printf("fork_fds: %d rounds, 3 fds %.1fus, %d fds %.1fus per fork\n",
+         ROUNDS, few, MANY_FDS + 3, many);
+  puts("test_latency ok");
+}
+
+int main() {
+  test_isolation();
+  test_latency();
+  return 0;
+}
@@ -0,0 +1,84 @@
+#define _GNU_SOURCE
+#include <sched.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define BLOCKS 64
+
+static int check(unsigned char **blocks, int n, size_t size) {
+  for (int i = 0; i < n; i++) {
+    for (size_t j = 0; j < size; j++) {
+      if (blocks[i][j] != (unsigned char)i) {
+        return 0;
+      }
+    }
+  }
+  return 1;
+}
+
+// A forked child starts with the break of its parent, so allocating more
+// does not overwrite what it inherited.
+void test_fork_break() {
+  unsigned char *blocks[BLOCKS];
+  for (int i = 0; i < BLOCKS; i++) {
+    blocks[i] = malloc(1024);
+    memset(blocks[i], i, 1024);
+  }
+  long brk_before = syscall(SYS_brk, 0);
+  pid_t pid = fork();
+  if (pid == 0) {
+    if (syscall(SYS_brk, 0) != brk_before) {
+      _exit(1);
+    }
+    unsigned char *more[BLOCKS];
+    for (int i = 0; i < BLOCKS; i++) {
+      more[i] = malloc(64 * 1024);
+      memset(more[i], i, 64 * 1024);
+    }
+    _exit(check(blocks, BLOCKS, 1024) && check(more, BLOCKS, 64 * 1024) ? 0
+                                                                         : 1);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 0 &&
+      check(blocks, BLOCKS, 1024)) {
+    puts("test_fork_break ok");
+  }
+  for (int i = 0; i < BLOCKS; i++) {
+    free(blocks[i]);
+  }
+}
+
+static volatile long child_brk;
+
+static int move_break(void *arg) {
+  child_brk = syscall(SYS_brk, (long)arg);
+  return 0;
+}
+
+// A child sharing the address space with `CLONE_VM` also shares the break.
+void test_vm_break() {
+  static char stack[16384] __attribute__((aligned(16)));
+  long page = sysconf(_SC_PAGESIZE);
+  long before = syscall(SYS_brk, 0);
+  pid_t pid = clone(move_break, stack + sizeof(stack), CLONE_VM | SIGCHLD,
+                    (void *)(before + page));
+  if (pid < 0) {
+    return;
+  }
+  waitpid(pid, NULL, 0);
+  if (child_brk == before + page && syscall(SYS_brk, 0) == before + page) {
+    puts("test_vm_break ok");
+  }
+}
+
+int main() {
+  test_fork_break();
+  test_vm_break();
+  return 0;
+}
@@ -0,0 +1,127 @@
+#define _GNU_SOURCE
+#include <pthread.h>
+#include <stdatomic.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+// How long each test keeps forking, long enough for the threads to meet
+// inside clone many times.
+#define DURATION_S 2
+#define PAGES 16
+
+static atomic_int stop;
+static long page_size;
+
+static double now() {
+  struct timespec ts;
+  CHECK(clock_gettime(CLOCK_MONOTONIC, &ts) == 0);
+  return ts.tv_sec + ts.tv_nsec / 1e9;
+}
+
+// Map, touch and unmap memory until told to stop, faulting in every page
+// while the other thread forks.
+static void *churn_mappings(void *arg) {
+  (void)arg;
+  long rounds = 0;
+  while (!atomic_load(&stop)) {
+    size_t len = PAGES * page_size;
+    char *p = mmap(NULL, len, PROT_READ | PROT_WRITE,
+                   MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    CHECK(p != MAP_FAILED);
+    for (size_t off = 0; off < len; off += page_size) {
+      p[off] = (char)rounds;
+    }
+    CHECK(mprotect(p, page_size, PROT_READ) == 0);
+    CHECK(munmap(p, len) == 0);
+    rounds++;
+  }
+  return (void *)rounds;
+}
+
+// Create and join threads until told to stop, so that threads exit and
+// wake their joiner through the futex of clear_child_tid meanwhile.
+static void *noop(void *arg) { return arg; }
+
+static void *churn_threads(void *arg) {
+  (void)arg;
+  long rounds = 0;
+  while (!atomic_load(&stop)) {
+    pthread_t t;
+    CHECK(pthread_create(&t, NULL, noop, (void *)rounds) == 0);
+    void *ret;
+    CHECK(pthread_join(t, &ret) == 0);
+    CHECK(ret == (void *)rounds);
+    rounds++;
+  }
+  return (void *)rounds;
+}
+
+// Fork for DURATION_S while `churn` runs in another thread, with children
+// reading `probe`, which the copy of the memory has to have. Returns the
+// number of forks.
+static long fork_against(void *(*churn)(void *), const char *probe) {
+  atomic_store(&stop, 0);
+  pthread_t t;
+  CHECK(pthread_create(&t, NULL, churn, NULL) == 0);
+
+  long forks = 0;
+  double end = now() + DURATION_S;
+  while (now() < end) {
+    pid_t pid = fork();
+    CHECK(pid >= 0);
+    if (pid == 0) {
+      _exit(strcmp(probe, "probe") == 0 ? 0 : 1);
+    }
+    int status;
+    CHECK(waitpid(pid, &status, 0) == pid);
+    CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    forks++;
+  }
+
+  atomic_store(&stop, 1);
+  void *rounds;
+  CHECK(pthread_join(t, &rounds) == 0);
+  CHECK((long)rounds > 0);
+  return forks;
+}
+
+// Forking while another thread maps, faults and unmaps neither hangs nor
+// gives the child a broken copy.
+void test_mmap_vs_fork() {
+  char *probe = malloc(8);
+  CHECK(probe != NULL);
+  strcpy(probe, "probe");
+  long forks = fork_against(churn_mappings, probe);
+  printf("fork_mmap: %ld forks against mmap\n", forks);
+  CHECK(forks > 0);
+  free(probe);
+  puts("test_mmap_vs_fork ok");
+}
+
+// Forking while other threads exit and are joined does not hang.
+void test_exit_vs_fork() {
+  long forks = fork_against(churn_threads, "probe");
+  printf("fork_mmap: %ld forks against exits\n", forks);
+  CHECK(forks > 0);
+  puts("test_exit_vs_fork ok");
+}
+
+int main() {
+  page_size = sysconf(_SC_PAGESIZE);
+  test_mmap_vs_fork();
+  test_exit_vs_fork();
+  return 0;
+}
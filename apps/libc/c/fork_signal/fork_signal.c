@@ -0,0 +1,51 @@
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define FORKS 200
+
+static void handler(int signo) {}
+
+// Fork while SIGALRM keeps interrupting the parent. Every child must resume
+// right after `fork`, which it reports through its exit status.
+void test_fork_under_signals() {
+  signal(SIGALRM, handler);
+  pid_t parent = getpid();
+  pid_t sender = fork();
+  if (sender == 0) {
+    while (kill(parent, SIGALRM) == 0) {
+    }
+    _exit(0);
+  }
+
+  int ok = 1;
+  for (int i = 0; i < FORKS; i++) {
+    pid_t pid = fork();
+    if (pid == 0) {
+      _exit(i & 0x7f);
+    }
+    if (pid < 0) {
+      ok = 0;
+      break;
+    }
+    int status;
+    while (waitpid(pid, &status, 0) < 0 && errno == EINTR) {
+    }
+    if (!WIFEXITED(status) || WEXITSTATUS(status) != (i & 0x7f)) {
+      ok = 0;
+    }
+  }
+
+  kill(sender, SIGKILL);
+  waitpid(sender, NULL, 0);
+  if (ok) {
+    puts("test_fork_under_signals ok");
+  }
+}
+
+int main() {
+  test_fork_under_signals();
+  return 0;
+}
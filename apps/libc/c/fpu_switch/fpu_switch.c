@@ -0,0 +1,90 @@
+#include <sched.h>
+#include <stdio.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define WORKERS 4
+#define ROUNDS 2000
+
+// A computation which keeps its values in FP registers across yields, so a
+// state lost or mixed up on a switch changes the result.
+static double compute(double seed) {
+  double x = seed, sum = 0.0;
+  for (int i = 0; i < ROUNDS; i++) {
+    x = x * 1.000001 + 0.5 / (i + 1);
+    sum += x;
+    if (i % 100 == 0) {
+      sched_yield();
+    }
+  }
+  return sum;
+}
+
+// Tasks using the FPU each keep their own state, while running alongside
+// each other on any CPU.
+void test_fpu_state() {
+  int fds[WORKERS][2];
+  for (int i = 0; i < WORKERS; i++) {
+    pipe(fds[i]);
+    if (fork() == 0) {
+      double result = compute(i + 1.0);
+      write(fds[i][1], &result, sizeof(result));
+      _exit(0);
+    }
+  }
+  int ok = 1;
+  for (int i = 0; i < WORKERS; i++) {
+    double result = 0;
+    read(fds[i][0], &result, sizeof(result));
+    ok &= result == compute(i + 1.0);
+    close(fds[i][0]);
+    close(fds[i][1]);
+  }
+  while (wait(NULL) > 0) {
+  }
+  if (ok) {
+    puts("test_fpu_state ok");
+  }
+}
+
+static long now_ns(void) {
+  struct timespec ts;
+  clock_gettime(CLOCK_MONOTONIC, &ts);
+  return ts.tv_sec * 1000000000L + ts.tv_nsec;
+}
+
+// Tasks which never touch the FPU ping-pong over pipes, which is timed as a
+// benchmark of context switches without FP state to switch.
+void test_pipe_ping_pong() {
+  int ping[2], pong[2];
+  char c = 0;
+  pipe(ping);
+  pipe(pong);
+  if (fork() == 0) {
+    while (read(ping[0], &c, 1) == 1 && c != 'q') {
+      write(pong[1], &c, 1);
+    }
+    _exit(0);
+  }
+  int n = 0;
+  long start = now_ns();
+  for (; n < 10000; n++) {
+    if (write(ping[1], "p", 1) != 1 || read(pong[0], &c, 1) != 1) {
+      break;
+    }
+  }
+  long elapsed = now_ns() - start;
+  write(ping[1], "q", 1);
+  wait(NULL);
+  if (n == 10000) {
+    printf("pipe ping-pong: %ld ns per round trip\n", elapsed / n);
+    puts("test_pipe_ping_pong ok");
+  }
+}
+
+int main() {
+  test_fpu_state();
+  test_pipe_ping_pong();
+  return 0;
+}
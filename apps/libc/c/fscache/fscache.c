@@ -0,0 +1,218 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define FILE_PATH "/fscache.tmp"
+// Kept from one boot to the next, to check that what was synced survives.
+#define DURABLE_PATH "/fscache.durable"
+#define APPENDS 10000
+#define LINE_LEN 16
+
+struct cache_stats {
+  long cached, dirty, hits, misses, written_back, requests, read_ahead;
+};
+
+// The counters of the cache of the root device, from /proc/starry/fscache.
+static struct cache_stats root_stats(void) {
+  FILE *f = fopen("/proc/starry/fscache", "r");
+  CHECK(f != NULL);
+  char line[256];
+  CHECK(fgets(line, sizeof(line), f) != NULL);
+  CHECK(strncmp(line, "device capacity", 15) == 0);
+  struct cache_stats stats;
+  long capacity;
+  CHECK(fscanf(f, "vda %ld %ld %ld %ld %ld %ld %ld %ld", &capacity,
+               &stats.cached, &stats.dirty, &stats.hits, &stats.misses,
+               &stats.written_back, &stats.requests, &stats.read_ahead) == 8);
+  fclose(f);
+  CHECK(stats.cached <= capacity && stats.dirty <= stats.cached);
+  return stats;
+}
+
+static double now(void) {
+  struct timespec ts;
+  clock_gettime(CLOCK_MONOTONIC, &ts);
+  return ts.tv_sec + ts.tv_nsec / 1e9;
+}
+
+static void make_line(char *line, int i) {
+  snprintf(line, LINE_LEN + 1, "line %09d\n", i);
+}
+
+// Appending a line at a time only dirties the cache, and `fsync` writes
+// the lines back in a few requests.
+void test_appends() {
+  int fd = open(FILE_PATH, O_WRONLY | O_CREAT | O_TRUNC | O_APPEND, 0644);
+  CHECK(fd >= 0);
+  CHECK(fsync(fd) == 0);
+  struct cache_stats before = root_stats();
+  char line[LINE_LEN + 1];
+  double start = now();
+  for (int i = 0; i < APPENDS; i++) {
+    make_line(line, i);
+    CHECK(write(fd, line, LINE_LEN) == LINE_LEN);
+  }
+  double appended = now();
+  CHECK(root_stats().dirty > 0);
+  CHECK(fsync(fd) == 0);
+  double synced = now();
+  struct cache_stats after = root_stats();
+  CHECK(after.dirty == 0);
+  CHECK(after.written_back > before.written_back);
+  // Far fewer requests than appends.
+  CHECK(after.requests - before.requests < APPENDS / 10);
+  CHECK(close(fd) == 0);
+
+  fd = open(FILE_PATH, O_RDONLY);
+  CHECK(fd >= 0);
+  char got[LINE_LEN];
+  for (int i = 0; i < APPENDS; i++) {
+    make_line(line, i);
+    CHECK(read(fd, got, LINE_LEN) == LINE_LEN);
+    CHECK(memcmp(got, line, LINE_LEN) == 0);
+  }
+  CHECK(read(fd, got, 1) == 0);
+  CHECK(close(fd) == 0);
+  CHECK(unlink(FILE_PATH) == 0);
+
+  printf("fscache: %d appends, %.1fus each, fsync %.1fus, %ld requests\n",
+         APPENDS, (appended - start) / APPENDS * 1e6, (synced - appended) * 1e6,
+         after.requests - before.requests);
+  puts("test_appends ok");
+}
+
+void test_sync() {
+  int fd = open(FILE_PATH, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  CHECK(write(fd, "dirty\n", 6) == 6);
+  CHECK(close(fd) == 0);
+  sync();
+  CHECK(root_stats().dirty == 0);
+
+  // Reading back a synced file hits the cache.
+  struct cache_stats before = root_stats();
+  char buf[8];
+  fd = open(FILE_PATH, O_RDONLY);
+  CHECK(fd >= 0);
+  CHECK(read(fd, buf, sizeof(buf)) == 6 && memcmp(buf, "dirty\n", 6) == 0);
+  CHECK(close(fd) == 0);
+  CHECK(root_stats().hits > before.hits);
+
+  fd = open(FILE_PATH, O_RDONLY);
+  CHECK(fd >= 0);
+  CHECK(syncfs(fd) == 0);
+  CHECK(close(fd) == 0);
+  CHECK(unlink(FILE_PATH) == 0);
+  puts("test_sync ok");
+}
+
+void test_errors() {
+  int fd = open("/", O_RDONLY | O_DIRECTORY);
+  CHECK(fd >= 0);
+  CHECK(fsync(fd) == 0);
+  CHECK(close(fd) == 0);
+  // A read-only file can be synced too.
+  fd = open(FILE_PATH, O_RDONLY | O_CREAT, 0644);
+  CHECK(fd >= 0);
+  CHECK(fdatasync(fd) == 0);
+  CHECK(close(fd) == 0);
+  CHECK(unlink(FILE_PATH) == 0);
+
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  CHECK(fsync(fds[0]) == -1 && errno == EINVAL);
+  CHECK(fdatasync(fds[1]) == -1 && errno == EINVAL);
+  close(fds[0]);
+  close(fds[1]);
+  CHECK(fsync(fds[0]) == -1 && errno == EBADF);
+  CHECK(syncfs(fds[0]) == -1 && errno == EBADF);
+  puts("test_errors ok");
+}
+
+static void fill_chunk(char *buf, int i, int len) {
+  for (int j = 0; j < len; j++) {
+    buf[j] = (char)(i * 31 + j);
+  }
+}
+
+// Reading a file through from start to end reads it ahead, and what was
+// read ahead is dropped when the blocks are written.
+void test_readahead() {
+  enum { CHUNKS = 256, CHUNK_LEN = 4096 };
+  static char buf[CHUNK_LEN], got[CHUNK_LEN];
+  int fd = open(FILE_PATH, O_RDWR | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  for (int i = 0; i < CHUNKS; i++) {
+    fill_chunk(buf, i, CHUNK_LEN);
+    CHECK(write(fd, buf, CHUNK_LEN) == CHUNK_LEN);
+  }
+  CHECK(fsync(fd) == 0);
+
+  struct cache_stats before = root_stats();
+  CHECK(lseek(fd, 0, SEEK_SET) == 0);
+  for (int i = 0; i < CHUNKS; i++) {
+    fill_chunk(buf, i, CHUNK_LEN);
+    CHECK(read(fd, got, CHUNK_LEN) == CHUNK_LEN);
+    CHECK(memcmp(got, buf, CHUNK_LEN) == 0);
+  }
+  CHECK(root_stats().read_ahead > before.read_ahead);
+
+  // Overwritten after it was read ahead, then read through again.
+  memset(buf, 'x', CHUNK_LEN);
+  CHECK(pwrite(fd, buf, CHUNK_LEN, CHUNKS / 2 * CHUNK_LEN) == CHUNK_LEN);
+  CHECK(fsync(fd) == 0);
+  CHECK(lseek(fd, 0, SEEK_SET) == 0);
+  for (int i = 0; i < CHUNKS; i++) {
+    if (i == CHUNKS / 2) {
+      memset(buf, 'x', CHUNK_LEN);
+    } else {
+      fill_chunk(buf, i, CHUNK_LEN);
+    }
+    CHECK(read(fd, got, CHUNK_LEN) == CHUNK_LEN);
+    CHECK(memcmp(got, buf, CHUNK_LEN) == 0);
+  }
+  CHECK(close(fd) == 0);
+  CHECK(unlink(FILE_PATH) == 0);
+  puts("test_readahead ok");
+}
+
+// What is synced before a reboot is read back after it: each boot checks
+// the count the last one left, then leaves its own.
+void test_durable() {
+  long boots = 0;
+  int fd = open(DURABLE_PATH, O_RDONLY);
+  if (fd >= 0) {
+    char buf[32] = {0};
+    CHECK(read(fd, buf, sizeof(buf) - 1) > 0);
+    CHECK(sscanf(buf, "boots %ld\n", &boots) == 1 && boots > 0);
+    CHECK(close(fd) == 0);
+  }
+  fd = open(DURABLE_PATH, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  CHECK(dprintf(fd, "boots %ld\n", boots + 1) > 0);
+  CHECK(fdatasync(fd) == 0);
+  CHECK(close(fd) == 0);
+  puts("test_durable ok");
+}
+
+int main() {
+  test_appends();
+  test_sync();
+  test_errors();
+  test_readahead();
+  test_durable();
+  return 0;
+}
@@ -0,0 +1,153 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define DIR_PATH "/fstrim.dir"
+#define FILE_PATH "/fstrim.tmp"
+#define DIRS 100
+#define FILES_PER_DIR 100
+#define FILE_SIZE 512
+
+struct trim_stats {
+  long passes, discarded, requests;
+};
+
+// The counters of the passes over the root device, from
+// /proc/starry/fstrim.
+static struct trim_stats root_stats(void) {
+  FILE *f = fopen("/proc/starry/fstrim", "r");
+  CHECK(f != NULL);
+  char line[256];
+  CHECK(fgets(line, sizeof(line), f) != NULL);
+  CHECK(strncmp(line, "device passes", 13) == 0);
+  struct trim_stats stats;
+  CHECK(fscanf(f, "vda %ld %ld %ld", &stats.passes, &stats.discarded,
+               &stats.requests) == 3);
+  fclose(f);
+  return stats;
+}
+
+// Write `text` to the file `path` under /proc/starry, returning what
+// `write` does.
+static ssize_t write_knob(const char *path, const char *text) {
+  int fd = open(path, O_WRONLY);
+  CHECK(fd >= 0);
+  ssize_t ret = write(fd, text, strlen(text));
+  CHECK(close(fd) == 0);
+  return ret;
+}
+
+// Write `size` bytes of `fill` to a new file at `path`.
+static void make_file(const char *path, int fill, size_t size) {
+  char buf[4096];
+  memset(buf, fill, sizeof(buf));
+  int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  for (size_t done = 0; done < size;) {
+    size_t n = size - done < sizeof(buf) ? size - done : sizeof(buf);
+    CHECK(write(fd, buf, n) == (ssize_t)n);
+    done += n;
+  }
+  CHECK(close(fd) == 0);
+}
+
+// The clusters of files created and deleted between two syncs are
+// discarded by the second one, a block at least for each file.
+void test_delete() {
+  char path[64];
+  CHECK(mkdir(DIR_PATH, 0755) == 0);
+  for (int d = 0; d < DIRS; d++) {
+    snprintf(path, sizeof(path), DIR_PATH "/%d", d);
+    CHECK(mkdir(path, 0755) == 0);
+    for (int i = 0; i < FILES_PER_DIR; i++) {
+      snprintf(path, sizeof(path), DIR_PATH "/%d/%d", d, i);
+      make_file(path, 'a' + i % 26, FILE_SIZE);
+    }
+  }
+  sync();
+  struct trim_stats before = root_stats();
+  for (int d = 0; d < DIRS; d++) {
+    for (int i = 0; i < FILES_PER_DIR; i++) {
+      snprintf(path, sizeof(path), DIR_PATH "/%d/%d", d, i);
+      CHECK(unlink(path) == 0);
+    }
+    snprintf(path, sizeof(path), DIR_PATH "/%d", d);
+    CHECK(rmdir(path) == 0);
+  }
+  CHECK(rmdir(DIR_PATH) == 0);
+  sync();
+  struct trim_stats after = root_stats();
+  CHECK(after.passes > before.passes);
+  CHECK(after.discarded - before.discarded >=
+        (long)DIRS * FILES_PER_DIR * FILE_SIZE / 512);
+  CHECK(after.requests > before.requests);
+  // Runs of blocks go in one request.
+  CHECK(after.requests - before.requests <
+        after.discarded - before.discarded);
+  printf("fstrim: %d files deleted, %ld blocks discarded in %ld requests\n",
+         DIRS * FILES_PER_DIR, after.discarded - before.discarded,
+         after.requests - before.requests);
+  puts("test_delete ok");
+}
+
+// Discarding every free cluster on request leaves the files there as they
+// were.
+void test_fstrim() {
+  make_file(FILE_PATH, 'k', 3 * 4096 + 100);
+  CHECK(truncate(FILE_PATH, 4096) == 0);
+  struct trim_stats before = root_stats();
+  CHECK(write_knob("/proc/starry/fstrim", "vda\n") > 0);
+  struct trim_stats after = root_stats();
+  CHECK(after.passes == before.passes + 1);
+  CHECK(after.discarded > before.discarded);
+
+  char buf[4096 + 1];
+  int fd = open(FILE_PATH, O_RDONLY);
+  CHECK(fd >= 0);
+  CHECK(read(fd, buf, sizeof(buf)) == 4096);
+  for (int i = 0; i < 4096; i++)
+    CHECK(buf[i] == 'k');
+  CHECK(close(fd) == 0);
+  CHECK(unlink(FILE_PATH) == 0);
+
+  CHECK(write_knob("/proc/starry/fstrim", "vdz\n") == -1 && errno == EINVAL);
+  puts("test_fstrim ok");
+}
+
+// Without writing zeros nothing is discarded, until it is turned back on.
+void test_zeroes_off() {
+  sync();
+  CHECK(write_knob("/proc/starry/discard_zeroes", "0\n") > 0);
+  struct trim_stats before = root_stats();
+  make_file(FILE_PATH, 'z', 64 * 1024);
+  CHECK(unlink(FILE_PATH) == 0);
+  sync();
+  CHECK(root_stats().discarded == before.discarded);
+  CHECK(write_knob("/proc/starry/fstrim", "vda\n") == -1 &&
+        errno == EOPNOTSUPP);
+
+  CHECK(write_knob("/proc/starry/discard_zeroes", "1\n") > 0);
+  sync();
+  CHECK(root_stats().discarded - before.discarded >= 64 * 1024 / 512);
+  puts("test_zeroes_off ok");
+}
+
+int main() {
+  test_delete();
+  test_fstrim();
+  test_zeroes_off();
+  return 0;
+}
@@ -0,0 +1,112 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <linux/futex.h>
+#include <pthread.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define ROUNDS 100
+
+// Written before each fork, so that the child gets it as it was.
+static volatile uint32_t word;
+
+static long futex(volatile uint32_t *uaddr, int op, uint32_t val,
+                  const struct timespec *timeout) {
+  return syscall(SYS_futex, uaddr, op, val, timeout, NULL, 0);
+}
+
+// Waits for `word` to become nonzero, giving up after a second.
+static void *waiter(void *arg) {
+  (void)arg;
+  struct timespec timeout = {1, 0};
+  while (word == 0) {
+    // Only reads the word, which the other thread then writes.
+    if (futex(&word, FUTEX_WAIT, 0, &timeout) == -1 && errno == ETIMEDOUT)
+      return (void *)1;
+  }
+  return NULL;
+}
+
+static void reap(pid_t pid) {
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+// In a child fresh from fork, a thread waits on a word of memory from
+// before the fork, which the main thread writes before waking it. The
+// waiter must be found however the write moved the memory of the child.
+void test_wake_after_fork() {
+  for (int i = 0; i < ROUNDS; i++) {
+    word = 0;
+    pid_t pid = fork();
+    CHECK(pid >= 0);
+    if (pid == 0) {
+      pthread_t thread;
+      if (pthread_create(&thread, NULL, waiter, NULL) != 0)
+        _exit(1);
+      usleep(1000);
+      word = 1;
+      futex(&word, FUTEX_WAKE, 1, NULL);
+      void *timed_out;
+      pthread_join(thread, &timed_out);
+      _exit(timed_out != NULL);
+    }
+    reap(pid);
+  }
+  puts("test_wake_after_fork ok");
+}
+
+// The parent and the child both use the word from before the fork, but
+// each its own copy: waking it in one does not wake a waiter in the other.
+void test_fork_apart() {
+  word = 0;
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    struct timespec timeout = {0, 200000000};
+    _exit(!(futex(&word, FUTEX_WAIT, 0, &timeout) == -1 &&
+            errno == ETIMEDOUT));
+  }
+  for (int i = 0; i < ROUNDS; i++) {
+    CHECK(futex(&word, FUTEX_WAKE, 1, NULL) == 0);
+    usleep(1000);
+  }
+  reap(pid);
+  puts("test_fork_apart ok");
+}
+
+// A misaligned word fails, and so does an unmapped one, unless private.
+void test_bad_words() {
+  CHECK(futex((uint32_t *)((char *)&word + 1), FUTEX_WAKE, 1, NULL) == -1 &&
+        errno == EINVAL);
+  long page = sysconf(_SC_PAGESIZE);
+  void *p = mmap(NULL, page, PROT_READ | PROT_WRITE,
+                 MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  CHECK(p != MAP_FAILED);
+  CHECK(munmap(p, page) == 0);
+  CHECK(futex(p, FUTEX_WAKE, 1, NULL) == -1 && errno == EFAULT);
+  CHECK(futex(p, FUTEX_WAKE_PRIVATE, 1, NULL) == 0);
+  puts("test_bad_words ok");
+}
+
+int main() {
+  test_wake_after_fork();
+  test_fork_apart();
+  test_bad_words();
+  return 0;
+}
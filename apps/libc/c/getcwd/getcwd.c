@@ -0,0 +1,49 @@
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define TOP "/getcwd_dir"
+#define SUB TOP "/sub"
+
+// The working directory is reported canonical, and resolves back to itself.
+void test_canonical_cwd() {
+  char cwd[256];
+  mkdir(TOP, 0755);
+  mkdir(SUB, 0755);
+  chdir("/");
+  if (chdir("getcwd_dir//./sub/../sub/") != 0 ||
+      getcwd(cwd, sizeof(cwd)) == NULL || strcmp(cwd, SUB) != 0) {
+    return;
+  }
+  close(open("marker", O_WRONLY | O_CREAT, 0644));
+  int dirfd = open(cwd, O_RDONLY | O_DIRECTORY);
+  if (dirfd >= 0 && faccessat(dirfd, "marker", F_OK, 0) == 0) {
+    puts("test_canonical_cwd ok");
+  }
+  close(dirfd);
+  unlink("marker");
+}
+
+// `fchdir` enters the directory of a descriptor, as its canonical path.
+void test_fchdir() {
+  char cwd[256];
+  chdir(SUB);
+  int dirfd = open("..", O_RDONLY | O_DIRECTORY);
+  chdir("/");
+  if (fchdir(dirfd) == 0 && getcwd(cwd, sizeof(cwd)) != NULL &&
+      strcmp(cwd, TOP) == 0 && access("sub", F_OK) == 0) {
+    puts("test_fchdir ok");
+  }
+  close(dirfd);
+  chdir("/");
+  rmdir(SUB);
+  rmdir(TOP);
+}
+
+int main() {
+  test_canonical_cwd();
+  test_fchdir();
+  return 0;
+}
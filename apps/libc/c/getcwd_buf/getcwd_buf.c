@@ -0,0 +1,74 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define DIR_PATH "/tmp_getcwd_buf"
+
+// Call getcwd on a heap buffer of `size` bytes holding stale `fill` bytes,
+// with or without a NUL in the middle.
+static long getcwd_stale(char **out, size_t size, int fill, int early_nul) {
+  char *buf = malloc(size);
+  CHECK(buf != NULL);
+  memset(buf, fill, size);
+  if (early_nul && size > 1) {
+    buf[1] = '\0';
+  }
+  *out = buf;
+  return syscall(SYS_getcwd, buf, size);
+}
+
+static void test_stale_buffers(void) {
+  static const size_t SIZES[] = {sizeof(DIR_PATH), sizeof(DIR_PATH) + 1, 64,
+                                 4096, 4097, 65536};
+  for (size_t i = 0; i < sizeof(SIZES) / sizeof(SIZES[0]); i++) {
+    for (int early_nul = 0; early_nul < 2; early_nul++) {
+      char *buf;
+      long ret = getcwd_stale(&buf, SIZES[i], 'x', early_nul);
+      CHECK(ret == sizeof(DIR_PATH));
+      CHECK(strcmp(buf, DIR_PATH) == 0);
+      free(buf);
+    }
+  }
+  printf("test_getcwd_stale ok\n");
+}
+
+static void test_small_buffers(void) {
+  char *buf;
+  errno = 0;
+  CHECK(getcwd_stale(&buf, sizeof(DIR_PATH) - 1, 'x', 0) == -1);
+  CHECK(errno == ERANGE);
+  // Nothing is written when the path does not fit.
+  CHECK(buf[0] == 'x');
+  free(buf);
+  CHECK(syscall(SYS_getcwd, NULL, 0) == -1 && errno == ERANGE);
+  CHECK(syscall(SYS_getcwd, NULL, 4096) == -1 && errno == EFAULT);
+
+  // The libc allocates a buffer of the right size itself.
+  char *cwd = getcwd(NULL, 0);
+  CHECK(cwd != NULL && strcmp(cwd, DIR_PATH) == 0);
+  free(cwd);
+  printf("test_getcwd_small ok\n");
+}
+
+int main(void) {
+  mkdir(DIR_PATH, 0755);
+  CHECK(chdir(DIR_PATH) == 0);
+  test_stale_buffers();
+  test_small_buffers();
+  chdir("/");
+  rmdir(DIR_PATH);
+  return 0;
+}
@@ -0,0 +1,141 @@
+#define _GNU_SOURCE
+#include <dirent.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+#define DIR_PATH "/tmp/getdents"
+#define FILES 24
+#define MAX_ENTRIES 64
+#define NAME_MAX_LEN 64
+#define HUGE 65536
+
+struct linux_dirent64 {
+  unsigned long long d_ino;
+  long long d_off;
+  unsigned short d_reclen;
+  unsigned char d_type;
+  char d_name[];
+};
+
+static char names[MAX_ENTRIES][NAME_MAX_LEN];
+static int count;
+
+static int getdents(int fd, char *buf, size_t len) {
+  return syscall(SYS_getdents64, fd, buf, len);
+}
+
+// Read the remaining entries with calls of `len` bytes into `out`, falling
+// back to a huge buffer for one call when an entry does not fit.
+static int read_all(int fd, size_t len, char out[][NAME_MAX_LEN]) {
+  static char buf[HUGE];
+  int n = 0;
+  for (;;) {
+    int ret = getdents(fd, buf, len);
+    if (ret < 0 && errno == EINVAL) {
+      ret = getdents(fd, buf, HUGE);
+    }
+    if (ret < 0) {
+      return -1;
+    }
+    if (ret == 0) {
+      return n;
+    }
+    for (int off = 0; off < ret;) {
+      struct linux_dirent64 *ent = (struct linux_dirent64 *)(buf + off);
+      if (n == MAX_ENTRIES) {
+        return -1;
+      }
+      strncpy(out[n++], ent->d_name, NAME_MAX_LEN - 1);
+      off += ent->d_reclen;
+    }
+  }
+}
+
+static int setup() {
+  mkdir(DIR_PATH, 0755);
+  for (int i = 0; i < FILES; i++) {
+    char path[128];
+    // Names of varied lengths, so that every buffer size ends somewhere
+    // different.
+    snprintf(path, sizeof(path), DIR_PATH "/%.*s%d", i % 13 + 1,
+             "abcdefghijklmnopqrstuvwxyz", i);
+    int fd = open(path, O_CREAT | O_WRONLY, 0644);
+    if (fd < 0) {
+      return -1;
+    }
+    close(fd);
+  }
+  int fd = open(DIR_PATH, O_RDONLY | O_DIRECTORY);
+  count = read_all(fd, HUGE, names);
+  close(fd);
+  return count;
+}
+
+void test_every_size() {
+  static char got[MAX_ENTRIES][NAME_MAX_LEN];
+  int fd = open(DIR_PATH, O_RDONLY | O_DIRECTORY);
+  for (size_t len = 1; len <= 2048; len++) {
+    memset(got, 0, sizeof(got));
+    if (lseek(fd, 0, SEEK_SET) != 0 || read_all(fd, len, got) != count) {
+      printf("test_every_size: %zu bytes\n", len);
+      close(fd);
+      return;
+    }
+    for (int i = 0; i < count; i++) {
+      if (strcmp(got[i], names[i]) != 0) {
+        printf("test_every_size: %zu bytes, entry %d\n", len, i);
+        close(fd);
+        return;
+      }
+    }
+    // At the end, the directory stays at the end.
+    char buf[256];
+    if (getdents(fd, buf, sizeof(buf)) != 0) {
+      close(fd);
+      return;
+    }
+  }
+  close(fd);
+  puts("test_every_size ok");
+}
+
+void test_seekdir() {
+  DIR *dir = opendir(DIR_PATH);
+  if (dir == NULL) {
+    return;
+  }
+  for (int i = 0; i < count / 2; i++) {
+    readdir(dir);
+  }
+  long pos = telldir(dir);
+  struct dirent *ent = readdir(dir);
+  char name[NAME_MAX_LEN];
+  strncpy(name, ent ? ent->d_name : "", sizeof(name) - 1);
+  name[sizeof(name) - 1] = 0;
+  while (readdir(dir) != NULL) {
+  }
+  seekdir(dir, pos);
+  ent = readdir(dir);
+  int ok = ent != NULL && strcmp(ent->d_name, name) == 0;
+  rewinddir(dir);
+  ent = readdir(dir);
+  ok = ok && ent != NULL && strcmp(ent->d_name, names[0]) == 0;
+  closedir(dir);
+  if (ok) {
+    puts("test_seekdir ok");
+  }
+}
+
+int main() {
+  if (setup() < FILES) {
+    return 1;
+  }
+  test_every_size();
+  test_seekdir();
+  return 0;
+}
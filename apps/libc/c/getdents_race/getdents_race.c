@@ -0,0 +1,115 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <pthread.h>
+#include <sched.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+// On the disk rather than in the tmpfs, whose directories are what broke.
+#define DIR_PATH "/getdents_race"
+#define FILES 16
+#define ROUNDS 200
+
+static int getdents(int fd, char *buf, size_t len) {
+  return syscall(SYS_getdents64, fd, buf, len);
+}
+
+static void make_dir(void) {
+  CHECK(mkdir(DIR_PATH, 0755) == 0);
+  for (int i = 0; i < FILES; i++) {
+    char path[64];
+    snprintf(path, sizeof(path), DIR_PATH "/file%d", i);
+    int fd = open(path, O_CREAT | O_WRONLY, 0644);
+    CHECK(fd >= 0);
+    close(fd);
+  }
+}
+
+static void remove_dir(void) {
+  for (int i = 0; i < FILES; i++) {
+    char path[64];
+    snprintf(path, sizeof(path), DIR_PATH "/file%d", i);
+    CHECK(unlink(path) == 0);
+  }
+  CHECK(rmdir(DIR_PATH) == 0);
+}
+
+// Whether reading `fd` finds nothing more, as in a removed directory. Linux
+// fails with ENOENT instead of returning 0.
+static int at_end(int fd) {
+  char buf[256];
+  int ret = getdents(fd, buf, sizeof(buf));
+  return ret == 0 || (ret < 0 && errno == ENOENT);
+}
+
+static volatile int shared_fd;
+
+// Reads the directory a few entries at a time until the end, or until the
+// descriptor is closed under it.
+static void *reader(void *arg) {
+  (void)arg;
+  char buf[64];
+  for (;;) {
+    int ret = getdents(shared_fd, buf, sizeof(buf));
+    if (ret == 0 || (ret < 0 && (errno == EBADF || errno == ENOENT)))
+      return NULL;
+    if (ret < 0)
+      return (void *)1;
+  }
+}
+
+// One thread reads a directory while another, sharing the descriptor
+// table, closes it and removes the directory.
+void test_close_during_getdents() {
+  for (int i = 0; i < ROUNDS; i++) {
+    make_dir();
+    shared_fd = open(DIR_PATH, O_RDONLY | O_DIRECTORY);
+    CHECK(shared_fd >= 0);
+    pthread_t thread;
+    CHECK(pthread_create(&thread, NULL, reader, NULL) == 0);
+    for (int j = 0; j < i % 8; j++)
+      sched_yield();
+    close(shared_fd);
+    remove_dir();
+    void *failed;
+    CHECK(pthread_join(thread, &failed) == 0);
+    CHECK(failed == NULL);
+  }
+  puts("test_close_during_getdents ok");
+}
+
+// A directory removed while open has no entries left, even after seeking
+// back, and once another is made at its path.
+void test_read_removed() {
+  make_dir();
+  int fd = open(DIR_PATH, O_RDONLY | O_DIRECTORY);
+  CHECK(fd >= 0);
+  char buf[256];
+  CHECK(getdents(fd, buf, 64) > 0);
+  remove_dir();
+  CHECK(at_end(fd));
+  make_dir();
+  CHECK(lseek(fd, 0, SEEK_SET) == 0);
+  CHECK(at_end(fd));
+  close(fd);
+  remove_dir();
+  puts("test_read_removed ok");
+}
+
+int main() {
+  test_close_during_getdents();
+  test_read_removed();
+  return 0;
+}
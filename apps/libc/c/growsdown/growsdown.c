@@ -0,0 +1,146 @@
+#define _GNU_SOURCE
+#include <pthread.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/uio.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static long page;
+#define INITIAL (16 * page)
+#define RESERVED (16 << 20)
+#define MAX_GROWTH (8 << 20)
+#define GUARD_GAP (256 * page)
+
+// Map a small grows-down stack at the top of a free 16 MiB region, so that
+// nothing else is in the way of its growth.
+static char *map_stack() {
+  char *region = mmap(NULL, RESERVED, PROT_NONE, MAP_PRIVATE | MAP_ANONYMOUS,
+                      -1, 0);
+  if (region == MAP_FAILED) {
+    return NULL;
+  }
+  munmap(region, RESERVED);
+  char *stack = mmap(region + RESERVED - INITIAL, INITIAL,
+                     PROT_READ | PROT_WRITE,
+                     MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED | MAP_GROWSDOWN,
+                     -1, 0);
+  return stack == MAP_FAILED ? NULL : stack;
+}
+
+// Use the stack downward a page at a time like a deep recursion would,
+// down to `depth` bytes below its initial start.
+static int descend(char *stack, size_t depth) {
+  for (size_t off = page; off <= depth; off += page) {
+    volatile char *frame = stack - off;
+    *frame = (char)off;
+    if (*frame != (char)off) {
+      return 0;
+    }
+  }
+  return 1;
+}
+
+void test_grow() {
+  pid_t pid = fork();
+  if (pid == 0) {
+    char *stack = map_stack();
+    if (stack == NULL || !descend(stack, 1 << 20)) {
+      _exit(1);
+    }
+    // Past the cap, the stack stops growing.
+    *(volatile char *)(stack - MAX_GROWTH - page) = 1;
+    _exit(2);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFSIGNALED(status) && WTERMSIG(status) == SIGSEGV) {
+    puts("test_grow ok");
+  }
+}
+
+void test_guard_gap() {
+  pid_t pid = fork();
+  if (pid == 0) {
+    char *stack = map_stack();
+    if (stack == NULL) {
+      _exit(1);
+    }
+    char *other = mmap(stack - 2 * page, page, PROT_READ | PROT_WRITE,
+                       MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    if (other == MAP_FAILED) {
+      _exit(1);
+    }
+    _exit(other + page > stack - GUARD_GAP && other < stack ? 1 : 0);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 0) {
+    puts("test_guard_gap ok");
+  }
+}
+
+// A syscall buffer below the start of a grows-down stack grows it, as
+// using the buffer from user space would.
+void test_syscall_buffer() {
+  pid_t pid = fork();
+  if (pid == 0) {
+    char *stack = map_stack();
+    int fds[2];
+    if (stack == NULL || pipe(fds) != 0 || write(fds[1], "grown", 5) != 5) {
+      _exit(1);
+    }
+    char *buf = stack - 3 * page;
+    if (read(fds[0], buf, 5) != 5) {
+      _exit(2);
+    }
+    struct iovec iov[] = {{buf, 5}, {stack - 5 * page, 3}};
+    char got[8];
+    if (writev(fds[1], iov, 2) != 8 || read(fds[0], got, 8) != 8 ||
+        memcmp(got, "grown\0\0\0", 8) != 0) {
+      _exit(3);
+    }
+    _exit(0);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 0) {
+    puts("test_syscall_buffer ok");
+  }
+}
+
+static void *map_stack_thread(void *arg) {
+  (void)arg;
+  return map_stack();
+}
+
+// A grows-down stack mapped by one thread grows when another thread of the
+// process uses it, as they share the address space.
+void test_other_thread() {
+  pid_t pid = fork();
+  if (pid == 0) {
+    pthread_t thread;
+    void *stack;
+    if (pthread_create(&thread, NULL, map_stack_thread, NULL) != 0 ||
+        pthread_join(thread, &stack) != 0 || stack == NULL) {
+      _exit(1);
+    }
+    _exit(descend(stack, 4 * INITIAL) ? 0 : 2);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 0) {
+    puts("test_other_thread ok");
+  }
+}
+
+int main() {
+  page = sysconf(_SC_PAGESIZE);
+  test_grow();
+  test_guard_gap();
+  test_syscall_buffer();
+  test_other_thread();
+  return 0;
+}
@@ -0,0 +1,69 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define PID_PATH "/hang.pid"
+
+// Never exit, nor let the helper forked exit, so that the runner has to
+// kill both once the time of the test is up.
+static void hang(void) {
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    for (;;) {
+      pause();
+    }
+  }
+  FILE *f = fopen(PID_PATH, "w");
+  CHECK(f != NULL);
+  fprintf(f, "%d\n", pid);
+  CHECK(fclose(f) == 0);
+  puts("hang: waiting to be killed");
+  fflush(stdout);
+  for (;;) {
+    pause();
+  }
+}
+
+// The helper forked by the last run is gone, or only left to be reaped.
+void test_helper_killed() {
+  FILE *f = fopen(PID_PATH, "r");
+  CHECK(f != NULL);
+  int pid;
+  CHECK(fscanf(f, "%d", &pid) == 1);
+  fclose(f);
+  CHECK(unlink(PID_PATH) == 0);
+
+  char path[64], stat[256];
+  snprintf(path, sizeof(path), "/proc/%d/stat", pid);
+  f = fopen(path, "r");
+  if (f == NULL) {
+    CHECK(errno == ENOENT);
+  } else {
+    CHECK(fgets(stat, sizeof(stat), f) != NULL);
+    fclose(f);
+    char *state = strrchr(stat, ')');
+    CHECK(state != NULL && state[1] == ' ' && state[2] == 'Z');
+  }
+  puts("test_helper_killed ok");
+}
+
+int main(int argc, char **argv) {
+  if (argc > 1 && strcmp(argv[1], "check") == 0) {
+    test_helper_killed();
+  } else {
+    hang();
+  }
+  return 0;
+}
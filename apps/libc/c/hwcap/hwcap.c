@@ -0,0 +1,221 @@
+#define _GNU_SOURCE
+#include <elf.h>
+#include <setjmp.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#ifndef AT_HWCAP2
+#define AT_HWCAP2 26
+#endif
+
+extern char **environ;
+
+// The entry `type` of the auxiliary vector, which follows the environment
+// on the initial stack. Read from there rather than through getauxval, as
+// glibc puts bits of its own in AT_HWCAP on x86_64.
+static unsigned long auxval(unsigned long type) {
+  char **env = environ;
+  while (*env) {
+    env++;
+  }
+  for (unsigned long *aux = (unsigned long *)(env + 1); aux[0] != AT_NULL;
+       aux += 2) {
+    if (aux[0] == type) {
+      return aux[1];
+    }
+  }
+  return 0;
+}
+
+// For each architecture: the line of /proc/cpuinfo listing the features, a
+// feature every CPU has, and one the kernel does not enable, with their
+// bits in the auxiliary vector and an instruction of the absent one.
+#if defined(__x86_64__)
+#define FEATURES_KEY "flags"
+#define PRESENT "sse2"
+#define PRESENT_AT AT_HWCAP
+#define PRESENT_BIT (1UL << 26)
+#define ABSENT "fsgsbase"
+#define ABSENT_AT AT_HWCAP2
+#define ABSENT_BIT (1UL << 1)
+static void absent_insn(void) { __asm__ volatile("rdfsbase %%rax" ::: "rax"); }
+#elif defined(__aarch64__)
+#define FEATURES_KEY "Features"
+#define PRESENT "fp"
+#define PRESENT_AT AT_HWCAP
+#define PRESENT_BIT (1UL << 0)
+#define ABSENT "sve"
+#define ABSENT_AT AT_HWCAP
+#define ABSENT_BIT (1UL << 22)
+// rdvl x0, #1
+static void absent_insn(void) { __asm__ volatile(".inst 0x04bf5020" ::: "x0"); }
+#elif defined(__riscv)
+// The single-letter extensions of the `isa` line.
+#define FEATURES_KEY "isa"
+#define PRESENT "i"
+#define PRESENT_AT AT_HWCAP
+#define PRESENT_BIT (1UL << ('i' - 'a'))
+#define ABSENT "v"
+#define ABSENT_AT AT_HWCAP
+#define ABSENT_BIT (1UL << ('v' - 'a'))
+// vsetvli t0, zero, e8, m1, ta, ma
+static void absent_insn(void) { __asm__ volatile(".word 0x0c0072d7" ::: "t0"); }
+#elif defined(__loongarch64)
+#define FEATURES_KEY "Features"
+#define PRESENT "fpu"
+#define PRESENT_AT AT_HWCAP
+#define PRESENT_BIT (1UL << 3)
+#define ABSENT "lvz"
+#define ABSENT_AT AT_HWCAP
+#define ABSENT_BIT (1UL << 9)
+// hvcl 0
+static void absent_insn(void) { __asm__ volatile(".word 0x002b8000"); }
+#else
+#error "unsupported architecture"
+#endif
+
+static char cpuinfo[16384];
+
+static void read_cpuinfo(void) {
+  FILE *f = fopen("/proc/cpuinfo", "r");
+  CHECK(f != NULL);
+  size_t len = fread(cpuinfo, 1, sizeof(cpuinfo) - 1, f);
+  CHECK(len > 0);
+  cpuinfo[len] = '\0';
+  fclose(f);
+}
+
+// Whether the features line of the first CPU lists `name`.
+static int cpuinfo_has(const char *name) {
+  char *line = cpuinfo;
+  while (strncmp(line, FEATURES_KEY, strlen(FEATURES_KEY)) != 0 ||
+         !strchr(" \t", line[strlen(FEATURES_KEY)])) {
+    line = strchr(line, '\n');
+    CHECK(line != NULL);
+    line++;
+  }
+  char *value = strchr(line, ':');
+  CHECK(value != NULL);
+  value += 2;
+  size_t len = strcspn(value, "\n");
+#if defined(__riscv)
+  // As `rv64imafdc_zicsr`.
+  CHECK(strncmp(value, "rv64", 4) == 0);
+  len = strcspn(value, "_\n");
+  return memchr(value + 4, name[0], len - 4) != NULL;
+#else
+  size_t name_len = strlen(name);
+  for (char *p = value; p < value + len;) {
+    size_t word = strcspn(p, " \n");
+    if (word == name_len && strncmp(p, name, name_len) == 0) {
+      return 1;
+    }
+    p += word + 1;
+  }
+  return 0;
+#endif
+}
+
+static sigjmp_buf ill_jmp;
+static volatile int ill_code;
+
+static void on_sigill(int sig, siginfo_t *info, void *ctx) {
+  (void)ctx;
+  CHECK(sig == SIGILL);
+  ill_code = info->si_code;
+  siglongjmp(ill_jmp, 1);
+}
+
+// Whether the absent instruction raises SIGILL, caught.
+static int raises_sigill(void) {
+  struct sigaction sa = {0};
+  sa.sa_sigaction = on_sigill;
+  sa.sa_flags = SA_SIGINFO;
+  CHECK(sigaction(SIGILL, &sa, NULL) == 0);
+  ill_code = 0;
+  int raised = sigsetjmp(ill_jmp, 1);
+  if (!raised) {
+    absent_insn();
+  }
+  signal(SIGILL, SIG_DFL);
+  return raised;
+}
+
+void test_present() {
+  CHECK(auxval(PRESENT_AT) & PRESENT_BIT);
+  CHECK(cpuinfo_has(PRESENT));
+  puts("test_present ok");
+}
+
+// The absent feature is absent from both, and its instruction raises SIGILL,
+// which the process survives.
+void test_absent() {
+  int has = (auxval(ABSENT_AT) & ABSENT_BIT) != 0;
+  CHECK(has == cpuinfo_has(ABSENT));
+  if (!has) {
+    CHECK(raises_sigill());
+    // ILL_ILLOPN on x86_64, which Linux gives for #UD, ILL_ILLOPC elsewhere.
+    CHECK(ill_code == ILL_ILLOPN || ill_code == ILL_ILLOPC);
+    // And again, the handler having returned through siglongjmp.
+    CHECK(raises_sigill());
+  } else {
+    CHECK(!raises_sigill());
+  }
+  puts("test_absent ok");
+}
+
+// Without a handler, SIGILL kills.
+void test_killed() {
+  if (auxval(ABSENT_AT) & ABSENT_BIT) {
+    puts("test_killed ok");
+    return;
+  }
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    absent_insn();
+    _exit(0);
+  }
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFSIGNALED(status) && WTERMSIG(status) == SIGILL);
+  puts("test_killed ok");
+}
+
+// A block for each CPU, numbered from 0.
+void test_cpuinfo() {
+  int cpus = 0;
+  for (char *line = cpuinfo; line; line = strchr(line, '\n')) {
+    if (*line == '\n') {
+      line++;
+    }
+    int n;
+    if (sscanf(line, "processor : %d", &n) == 1) {
+      CHECK(n == cpus);
+      cpus++;
+    }
+  }
+  CHECK(cpus >= 1);
+  puts("test_cpuinfo ok");
+}
+
+int main() {
+  read_cpuinfo();
+  test_present();
+  test_absent();
+  test_killed();
+  test_cpuinfo();
+  return 0;
+}
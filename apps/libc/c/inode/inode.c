@@ -0,0 +1,130 @@
+#define _GNU_SOURCE
+#include <dirent.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define DIR_PATH "/inode_dir"
+#define FILES 200
+
+static void touch(const char *path) { close(open(path, O_WRONLY | O_CREAT, 0644)); }
+
+// Two links to a file are the same file.
+void test_hardlink() {
+  mkdir(DIR_PATH, 0755);
+  touch(DIR_PATH "/file");
+  link(DIR_PATH "/file", DIR_PATH "/link");
+  touch(DIR_PATH "/other");
+  struct stat file, link, other;
+  stat(DIR_PATH "/file", &file);
+  stat(DIR_PATH "/link", &link);
+  stat(DIR_PATH "/other", &other);
+  if (file.st_ino == link.st_ino && file.st_dev == link.st_dev &&
+      file.st_ino != other.st_ino) {
+    puts("test_hardlink ok");
+  }
+  unlink(DIR_PATH "/link");
+  unlink(DIR_PATH "/file");
+  unlink(DIR_PATH "/other");
+}
+
+// A moved file keeps its inode number, which a file made where it was,
+// or moved there, does not get.
+void test_rename() {
+  mkdir(DIR_PATH, 0755);
+  touch(DIR_PATH "/a");
+  struct stat a, moved, made, other;
+  stat(DIR_PATH "/a", &a);
+  rename(DIR_PATH "/a", DIR_PATH "/b");
+  stat(DIR_PATH "/b", &moved);
+  touch(DIR_PATH "/a");
+  stat(DIR_PATH "/a", &made);
+  rename(DIR_PATH "/a", DIR_PATH "/c");
+  touch(DIR_PATH "/a");
+  stat(DIR_PATH "/a", &other);
+  if (moved.st_ino == a.st_ino && made.st_ino != a.st_ino &&
+      other.st_ino != a.st_ino && other.st_ino != made.st_ino) {
+    puts("test_rename ok");
+  }
+  unlink(DIR_PATH "/a");
+  unlink(DIR_PATH "/b");
+  unlink(DIR_PATH "/c");
+}
+
+// The files under a renamed directory keep their inode numbers, which new
+// files where they were do not get.
+void test_dir_rename() {
+  mkdir(DIR_PATH "/d", 0755);
+  touch(DIR_PATH "/d/f");
+  struct stat f, moved, made;
+  stat(DIR_PATH "/d/f", &f);
+  rename(DIR_PATH "/d", DIR_PATH "/e");
+  stat(DIR_PATH "/e/f", &moved);
+  mkdir(DIR_PATH "/d", 0755);
+  touch(DIR_PATH "/d/f");
+  stat(DIR_PATH "/d/f", &made);
+  if (moved.st_ino == f.st_ino && made.st_ino != f.st_ino) {
+    puts("test_dir_rename_ino ok");
+  }
+  unlink(DIR_PATH "/d/f");
+  unlink(DIR_PATH "/e/f");
+  rmdir(DIR_PATH "/d");
+  rmdir(DIR_PATH "/e");
+}
+
+static int cmp(const void *a, const void *b) {
+  ino_t x = *(const ino_t *)a, y = *(const ino_t *)b;
+  return x < y ? -1 : x > y;
+}
+
+// Every file of a large directory has its own inode number, which
+// `getdents64` reports like `stat` does.
+void test_unique() {
+  static ino_t inos[FILES];
+  char path[64];
+  for (int i = 0; i < FILES; i++) {
+    sprintf(path, DIR_PATH "/f%d", i);
+    touch(path);
+  }
+  int ok = 1, count = 0;
+  DIR *dir = opendir(DIR_PATH);
+  struct dirent *ent;
+  while ((ent = readdir(dir)) != NULL) {
+    if (ent->d_name[0] != 'f') {
+      continue;
+    }
+    struct stat st;
+    if (fstatat(dirfd(dir), ent->d_name, &st, 0) != 0 ||
+        st.st_ino != ent->d_ino || count == FILES) {
+      ok = 0;
+      break;
+    }
+    inos[count++] = st.st_ino;
+  }
+  closedir(dir);
+  qsort(inos, count, sizeof(ino_t), cmp);
+  for (int i = 1; i < count; i++) {
+    if (inos[i] == inos[i - 1]) {
+      ok = 0;
+    }
+  }
+  if (ok && count == FILES) {
+    puts("test_unique ok");
+  }
+  for (int i = 0; i < FILES; i++) {
+    sprintf(path, DIR_PATH "/f%d", i);
+    unlink(path);
+  }
+  rmdir(DIR_PATH);
+}
+
+int main() {
+  test_hardlink();
+  test_rename();
+  test_dir_rename();
+  test_unique();
+  return 0;
+}
@@ -0,0 +1,93 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <poll.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/inotify.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define DIR "/inotify_dir"
+#define WATCHED DIR "/file"
+
+// Read one event, checking its mask and name.
+static int expect_event(int fd, int wd, uint32_t mask, const char *name) {
+  static char buf[sizeof(struct inotify_event) + 256]
+      __attribute__((aligned(__alignof__(struct inotify_event))));
+  static size_t len = 0, pos = 0;
+  if (pos == len) {
+    ssize_t n = read(fd, buf, sizeof(buf));
+    if (n <= 0) {
+      return 0;
+    }
+    len = n;
+    pos = 0;
+  }
+  struct inotify_event *event = (struct inotify_event *)(buf + pos);
+  pos += sizeof(struct inotify_event) + event->len;
+  return event->wd == wd && event->mask == mask && event->len > 0 &&
+         strcmp(event->name, name) == 0;
+}
+
+void test_create_delete() {
+  if (mkdir(DIR, 0755) != 0) {
+    return;
+  }
+  int fd = inotify_init1(IN_NONBLOCK);
+  if (fd < 0) {
+    return;
+  }
+  int wd = inotify_add_watch(fd, DIR, IN_CREATE | IN_DELETE);
+  char byte;
+  if (wd < 0 || read(fd, &byte, 1) != -1 || errno != EAGAIN) {
+    close(fd);
+    return;
+  }
+  pid_t pid = fork();
+  if (pid == 0) {
+    int file = open(WATCHED, O_WRONLY | O_CREAT, 0644);
+    if (file < 0) {
+      _exit(1);
+    }
+    close(file);
+    _exit(unlink(WATCHED) == 0 ? 0 : 2);
+  }
+  int status;
+  if (waitpid(pid, &status, 0) != pid || !WIFEXITED(status) ||
+      WEXITSTATUS(status) != 0) {
+    close(fd);
+    return;
+  }
+  struct pollfd pfd = {fd, POLLIN, 0};
+  if (poll(&pfd, 1, 0) == 1 && (pfd.revents & POLLIN) &&
+      expect_event(fd, wd, IN_CREATE, "file") &&
+      expect_event(fd, wd, IN_DELETE, "file")) {
+    puts("test_create_delete ok");
+  }
+  close(fd);
+}
+
+void test_modify() {
+  int fd = inotify_init();
+  int wd = inotify_add_watch(fd, DIR, IN_MODIFY);
+  int file = open(WATCHED, O_WRONLY | O_CREAT, 0644);
+  // Repeated writes are merged into one event.
+  int ok = wd >= 0 && file >= 0 && write(file, "a", 1) == 1 &&
+           write(file, "b", 1) == 1 && expect_event(fd, wd, IN_MODIFY, "file");
+  close(file);
+  unlink(WATCHED);
+  if (ok && inotify_rm_watch(fd, wd) == 0 &&
+      inotify_rm_watch(fd, wd) == -1 && errno == EINVAL) {
+    puts("test_modify ok");
+  }
+  close(fd);
+}
+
+int main() {
+  test_create_delete();
+  test_modify();
+  rmdir(DIR);
+  return 0;
+}
@@ -0,0 +1,111 @@
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define FILE_PATH "/io_delay.tmp"
+#define FILE_SIZE (4 << 20)
+#define CHUNK (64 << 10)
+
+static char buf[CHUNK];
+
+// The time the process waited for block devices, from `IoDelay` of
+// /proc/self/status, in microseconds.
+static long io_delay(void) {
+  FILE *f = fopen("/proc/self/status", "r");
+  CHECK(f != NULL);
+  char line[128];
+  long delay = -1;
+  while (fgets(line, sizeof(line), f)) {
+    if (sscanf(line, "IoDelay: %ld us", &delay) == 1) {
+      break;
+    }
+  }
+  fclose(f);
+  CHECK(delay >= 0);
+  return delay;
+}
+
+// Run `fn` in a child and return the I/O delay it saw.
+static long in_child(void (*fn)(void)) {
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    fn();
+    long delay = io_delay();
+    write(fds[1], &delay, sizeof(delay));
+    _exit(0);
+  }
+  close(fds[1]);
+  long delay;
+  CHECK(read(fds[0], &delay, sizeof(delay)) == sizeof(delay));
+  close(fds[0]);
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  return delay;
+}
+
+static void read_file(void) {
+  int fd = open(FILE_PATH, O_RDONLY);
+  CHECK(fd >= 0);
+  ssize_t len;
+  long total = 0;
+  while ((len = read(fd, buf, sizeof(buf))) > 0) {
+    total += len;
+  }
+  CHECK(len == 0 && total == FILE_SIZE);
+  close(fd);
+}
+
+static void spin(void) {
+  struct timespec start, now;
+  clock_gettime(CLOCK_MONOTONIC, &start);
+  volatile unsigned long x = 0;
+  do {
+    for (int i = 0; i < 100000; i++) {
+      x += i;
+    }
+    clock_gettime(CLOCK_MONOTONIC, &now);
+  } while ((now.tv_sec - start.tv_sec) * 1000000000L +
+               (now.tv_nsec - start.tv_nsec) <
+           50000000L);
+}
+
+static void test_reader(void) {
+  int fd = open(FILE_PATH, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  memset(buf, 'x', sizeof(buf));
+  for (int i = 0; i < FILE_SIZE / CHUNK; i++) {
+    CHECK(write(fd, buf, sizeof(buf)) == sizeof(buf));
+  }
+  CHECK(close(fd) == 0);
+  CHECK(in_child(read_file) > 0);
+  CHECK(unlink(FILE_PATH) == 0);
+  puts("test_reader ok");
+}
+
+static void test_spinner(void) {
+  CHECK(in_child(spin) == 0);
+  puts("test_spinner ok");
+}
+
+int main(void) {
+  test_reader();
+  test_spinner();
+  return 0;
+}
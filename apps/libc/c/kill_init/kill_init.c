@@ -0,0 +1,69 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <linux/capability.h>
+#include <signal.h>
+#include <stdio.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+// Drop every capability of the calling process.
+static int drop_caps() {
+  struct __user_cap_header_struct header = {_LINUX_CAPABILITY_VERSION_3, 0};
+  struct __user_cap_data_struct data[2] = {{0, 0, 0}, {0, 0, 0}};
+  return syscall(SYS_capset, &header, data);
+}
+
+static int run_child(int (*f)()) {
+  pid_t pid = fork();
+  if (pid == 0) {
+    _exit(f());
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  return WIFEXITED(status) ? WEXITSTATUS(status) : -1;
+}
+
+static int kill_init() {
+  if (drop_caps() != 0) {
+    return 1;
+  }
+  if (kill(1, SIGKILL) != -1 || errno != EPERM) {
+    return 2;
+  }
+  if (kill(1, SIGTERM) != -1 || errno != EPERM) {
+    return 3;
+  }
+  return 0;
+}
+
+void test_kill_init() {
+  if (run_child(kill_init) == 0) {
+    // Still running, so init is too.
+    puts("test_kill_init ok");
+  }
+}
+
+static int kill_all() {
+  // The parent is there to be signaled.
+  if (kill(-1, 0) != 0) {
+    return 1;
+  }
+  // A group without processes.
+  if (kill(-0x7fff, 0) != -1 || errno != ESRCH) {
+    return 2;
+  }
+  return 0;
+}
+
+void test_kill_all() {
+  if (run_child(kill_all) == 0) {
+    puts("test_kill_all ok");
+  }
+}
+
+int main() {
+  test_kill_init();
+  test_kill_all();
+  return 0;
+}
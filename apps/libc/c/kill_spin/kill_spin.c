@@ -0,0 +1,93 @@
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define ROUNDS 20
+// Three ticks at 100 Hz: a spinning child on another CPU has to be
+// interrupted to see the signal, not wait for that CPU to reschedule.
+#define BOUND_NS 30000000L
+
+static long now_ns(void) {
+  struct timespec ts;
+  CHECK(clock_gettime(CLOCK_MONOTONIC, &ts) == 0);
+  return ts.tv_sec * 1000000000L + ts.tv_nsec;
+}
+
+// A child spinning in user space, without a system call, once it has
+// told the parent it runs.
+static pid_t start_spinner(void) {
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    close(fds[0]);
+    CHECK(write(fds[1], "", 1) == 1);
+    for (volatile unsigned long i = 0;; i++)
+      ;
+  }
+  close(fds[1]);
+  char c;
+  CHECK(read(fds[0], &c, 1) == 1);
+  close(fds[0]);
+  return pid;
+}
+
+// SIGKILL ends a spinning child within a few ticks.
+void test_kill_spinning() {
+  long max = 0;
+  for (int i = 0; i < ROUNDS; i++) {
+    pid_t pid = start_spinner();
+    int status;
+    long start = now_ns();
+    CHECK(kill(pid, SIGKILL) == 0);
+    CHECK(waitpid(pid, &status, 0) == pid);
+    long took = now_ns() - start;
+    CHECK(WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+    if (took > max)
+      max = took;
+  }
+  if (max > BOUND_NS)
+    printf("kill took %ld ns, over %ld\n", max, BOUND_NS);
+  CHECK(max <= BOUND_NS);
+  puts("test_kill_spinning ok");
+}
+
+// SIGSTOP stops a spinning child within a few ticks too.
+void test_stop_spinning() {
+  long max = 0;
+  for (int i = 0; i < ROUNDS; i++) {
+    pid_t pid = start_spinner();
+    int status;
+    long start = now_ns();
+    CHECK(kill(pid, SIGSTOP) == 0);
+    CHECK(waitpid(pid, &status, WUNTRACED) == pid);
+    long took = now_ns() - start;
+    CHECK(WIFSTOPPED(status) && WSTOPSIG(status) == SIGSTOP);
+    if (took > max)
+      max = took;
+    CHECK(kill(pid, SIGKILL) == 0);
+    CHECK(waitpid(pid, &status, 0) == pid);
+  }
+  if (max > BOUND_NS)
+    printf("stop took %ld ns, over %ld\n", max, BOUND_NS);
+  CHECK(max <= BOUND_NS);
+  puts("test_stop_spinning ok");
+}
+
+int main() {
+  test_kill_spinning();
+  test_stop_spinning();
+  return 0;
+}
@@ -0,0 +1,190 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define DIR_PATH "/link_test"
+#define FILE_PATH DIR_PATH "/file"
+#define LINK_PATH DIR_PATH "/link"
+
+static void write_file(const char *path, const char *data) {
+  int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  CHECK(write(fd, data, strlen(data)) == (ssize_t)strlen(data));
+  close(fd);
+}
+
+static void touch(const char *path) { write_file(path, "data"); }
+
+// Whether the file at `path` holds `data`, and has `nlink` names.
+static int holds(const char *path, const char *data, nlink_t nlink) {
+  char buf[64];
+  int fd = open(path, O_RDONLY);
+  if (fd < 0)
+    return 0;
+  ssize_t n = read(fd, buf, sizeof(buf));
+  struct stat st;
+  int ok = fstat(fd, &st) == 0 && stat(path, &st) == 0;
+  close(fd);
+  return ok && n == (ssize_t)strlen(data) && memcmp(buf, data, n) == 0 &&
+         st.st_nlink == nlink;
+}
+
+void test_link_file() {
+  struct stat st;
+  CHECK(link(FILE_PATH, LINK_PATH) == 0);
+  CHECK(stat(FILE_PATH, &st) == 0 && st.st_nlink == 2);
+  CHECK(stat(LINK_PATH, &st) == 0 && st.st_nlink == 2);
+  CHECK(st.st_size == 4);
+  // Removing the new name leaves the file, with its own name only.
+  CHECK(unlink(LINK_PATH) == 0);
+  CHECK(stat(LINK_PATH, &st) == -1 && errno == ENOENT);
+  CHECK(stat(FILE_PATH, &st) == 0 && st.st_nlink == 1);
+  puts("test_link_file ok");
+}
+
+void test_link_missing() {
+  CHECK(link(DIR_PATH "/missing", LINK_PATH) == -1 && errno == ENOENT);
+  puts("test_link_missing ok");
+}
+
+void test_link_exists() {
+  touch(DIR_PATH "/other");
+  CHECK(link(FILE_PATH, DIR_PATH "/other") == -1 && errno == EEXIST);
+  unlink(DIR_PATH "/other");
+  // Nor over a link.
+  CHECK(link(FILE_PATH, LINK_PATH) == 0);
+  CHECK(link(FILE_PATH, LINK_PATH) == -1 && errno == EEXIST);
+  CHECK(unlink(LINK_PATH) == 0);
+  puts("test_link_exists ok");
+}
+
+void test_link_dir() {
+  CHECK(mkdir(DIR_PATH "/sub", 0755) == 0);
+  CHECK(link(DIR_PATH "/sub", LINK_PATH) == -1 && errno == EPERM);
+  CHECK(rmdir(DIR_PATH "/sub") == 0);
+  puts("test_link_dir ok");
+}
+
+// A new file made at the name of a removed link is a file of its own, not
+// the file of the link.
+void test_unlink_recreate() {
+  write_file(DIR_PATH "/b", "old");
+  CHECK(link(DIR_PATH "/b", DIR_PATH "/a") == 0);
+  CHECK(unlink(DIR_PATH "/a") == 0);
+  CHECK(holds(DIR_PATH "/b", "old", 1));
+  write_file(DIR_PATH "/a", "new");
+  CHECK(holds(DIR_PATH "/a", "new", 1));
+  CHECK(holds(DIR_PATH "/b", "old", 1));
+  CHECK(unlink(DIR_PATH "/a") == 0);
+  CHECK(unlink(DIR_PATH "/b") == 0);
+  puts("test_unlink_recreate ok");
+}
+
+// Removing the first name of a file leaves it to its links.
+void test_unlink_target() {
+  struct stat st;
+  write_file(DIR_PATH "/b", "kept");
+  CHECK(link(DIR_PATH "/b", DIR_PATH "/a") == 0);
+  CHECK(link(DIR_PATH "/b", DIR_PATH "/c") == 0);
+  CHECK(unlink(DIR_PATH "/b") == 0);
+  CHECK(stat(DIR_PATH "/b", &st) == -1 && errno == ENOENT);
+  CHECK(holds(DIR_PATH "/a", "kept", 2));
+  CHECK(holds(DIR_PATH "/c", "kept", 2));
+  write_file(DIR_PATH "/b", "other");
+  CHECK(holds(DIR_PATH "/a", "kept", 2));
+  CHECK(unlink(DIR_PATH "/a") == 0);
+  CHECK(holds(DIR_PATH "/c", "kept", 1));
+  CHECK(unlink(DIR_PATH "/c") == 0);
+  CHECK(unlink(DIR_PATH "/b") == 0);
+  puts("test_unlink_target ok");
+}
+
+// Renaming onto a link replaces the link, not the file of the link, and a
+// link can be renamed itself.
+void test_rename_over_link() {
+  struct stat st;
+  write_file(DIR_PATH "/b", "linked");
+  write_file(DIR_PATH "/c", "moved");
+  CHECK(link(DIR_PATH "/b", DIR_PATH "/a") == 0);
+  CHECK(rename(DIR_PATH "/c", DIR_PATH "/a") == 0);
+  CHECK(stat(DIR_PATH "/c", &st) == -1 && errno == ENOENT);
+  CHECK(holds(DIR_PATH "/a", "moved", 1));
+  CHECK(holds(DIR_PATH "/b", "linked", 1));
+
+  CHECK(link(DIR_PATH "/b", DIR_PATH "/d") == 0);
+  CHECK(rename(DIR_PATH "/d", DIR_PATH "/e") == 0);
+  CHECK(stat(DIR_PATH "/d", &st) == -1 && errno == ENOENT);
+  CHECK(holds(DIR_PATH "/e", "linked", 2));
+  // Both names of one file.
+  CHECK(rename(DIR_PATH "/e", DIR_PATH "/b") == 0);
+  CHECK(holds(DIR_PATH "/e", "linked", 2));
+  CHECK(unlink(DIR_PATH "/e") == 0);
+  CHECK(unlink(DIR_PATH "/a") == 0);
+  CHECK(unlink(DIR_PATH "/b") == 0);
+  puts("test_rename_over_link ok");
+}
+
+// The links to a renamed file, or to a file in a renamed directory, follow
+// it.
+void test_rename_link_target() {
+  struct stat st;
+  write_file(DIR_PATH "/b", "target");
+  CHECK(link(DIR_PATH "/b", DIR_PATH "/a") == 0);
+  CHECK(rename(DIR_PATH "/b", DIR_PATH "/d") == 0);
+  CHECK(stat(DIR_PATH "/b", &st) == -1 && errno == ENOENT);
+  CHECK(holds(DIR_PATH "/a", "target", 2));
+  CHECK(holds(DIR_PATH "/d", "target", 2));
+  write_file(DIR_PATH "/b", "unrelated");
+  CHECK(holds(DIR_PATH "/a", "target", 2));
+  CHECK(unlink(DIR_PATH "/b") == 0);
+
+  CHECK(mkdir(DIR_PATH "/sub", 0755) == 0);
+  CHECK(rename(DIR_PATH "/d", DIR_PATH "/sub/d") == 0);
+  CHECK(rename(DIR_PATH "/sub", DIR_PATH "/moved") == 0);
+  CHECK(holds(DIR_PATH "/a", "target", 2));
+  CHECK(holds(DIR_PATH "/moved/d", "target", 2));
+  CHECK(unlink(DIR_PATH "/moved/d") == 0);
+  CHECK(holds(DIR_PATH "/a", "target", 1));
+  CHECK(rmdir(DIR_PATH "/moved") == 0);
+  CHECK(unlink(DIR_PATH "/a") == 0);
+  puts("test_rename_link_target ok");
+}
+
+// A directory with a link in it is not empty.
+void test_rmdir_link() {
+  CHECK(mkdir(DIR_PATH "/sub", 0755) == 0);
+  CHECK(link(FILE_PATH, DIR_PATH "/sub/link") == 0);
+  CHECK(rmdir(DIR_PATH "/sub") == -1 && errno == ENOTEMPTY);
+  CHECK(unlink(DIR_PATH "/sub/link") == 0);
+  CHECK(rmdir(DIR_PATH "/sub") == 0);
+  puts("test_rmdir_link ok");
+}
+
+int main() {
+  CHECK(mkdir(DIR_PATH, 0755) == 0);
+  touch(FILE_PATH);
+  test_link_file();
+  test_link_missing();
+  test_link_exists();
+  test_link_dir();
+  test_unlink_recreate();
+  test_unlink_target();
+  test_rename_over_link();
+  test_rename_link_target();
+  test_rmdir_link();
+  unlink(FILE_PATH);
+  rmdir(DIR_PATH);
+  return 0;
+}
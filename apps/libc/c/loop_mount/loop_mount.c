@@ -0,0 +1,153 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define IMAGE "/loop_mount.img"
+#define MNT "/loop_mount_mnt"
+#define TMP_MNT "/loop_mount_tmp"
+#define SECTORS 2048
+
+static void put16(uint8_t *p, uint16_t v) {
+  p[0] = v;
+  p[1] = v >> 8;
+}
+
+// Write an empty 1 MiB FAT12 image, with 4 sectors per cluster.
+static int make_image(const char *path) {
+  static uint8_t sector[512];
+  int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  if (fd < 0) {
+    return -1;
+  }
+  for (int i = 0; i < SECTORS; i++) {
+    memset(sector, 0, sizeof(sector));
+    if (i == 0) {
+      memcpy(sector, "\xeb\x3c\x90MSDOS5.0", 11);
+      put16(sector + 11, 512);  // bytes per sector
+      sector[13] = 4;           // sectors per cluster
+      put16(sector + 14, 1);    // reserved sectors
+      sector[16] = 2;           // FATs
+      put16(sector + 17, 512);  // root entries
+      put16(sector + 19, SECTORS);
+      sector[21] = 0xf8;        // media
+      put16(sector + 22, 2);    // sectors per FAT
+      put16(sector + 24, 32);   // sectors per track
+      put16(sector + 26, 64);   // heads
+      sector[36] = 0x80;        // drive number
+      sector[38] = 0x29;        // extended boot signature
+      memcpy(sector + 39, "\x78\x56\x34\x12NO NAME    FAT12   ", 23);
+      sector[510] = 0x55;
+      sector[511] = 0xaa;
+    } else if (i == 1 || i == 3) {
+      memcpy(sector, "\xf8\xff\xff", 3);
+    }
+    if (write(fd, sector, sizeof(sector)) != sizeof(sector)) {
+      close(fd);
+      return -1;
+    }
+  }
+  return close(fd);
+}
+
+static int write_file(const char *path, const char *content) {
+  int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  if (fd < 0) {
+    return -1;
+  }
+  ssize_t len = strlen(content);
+  int ret = write(fd, content, len) == len ? 0 : -1;
+  close(fd);
+  return ret;
+}
+
+static int read_file(const char *path, char *buf, size_t size) {
+  int fd = open(path, O_RDONLY);
+  if (fd < 0) {
+    return -1;
+  }
+  ssize_t len = read(fd, buf, size - 1);
+  close(fd);
+  if (len < 0) {
+    return -1;
+  }
+  buf[len] = 0;
+  return 0;
+}
+
+void test_loop_mount() {
+  char buf[64];
+  if (make_image(IMAGE) != 0 || mkdir(MNT, 0755) != 0) {
+    return;
+  }
+  if (mount(IMAGE, MNT, "vfat", 0, "codepage=437") != 0 ||
+      write_file(MNT "/hello.txt", "hello loop\n") != 0 ||
+      umount(MNT) != 0) {
+    return;
+  }
+  // The file went to the image, not to the directory below.
+  if (access(MNT "/hello.txt", F_OK) != -1 || errno != ENOENT) {
+    return;
+  }
+  if (mount(IMAGE, MNT, "vfat", 0, NULL) != 0) {
+    return;
+  }
+  int ok = read_file(MNT "/hello.txt", buf, sizeof(buf)) == 0 &&
+           strcmp(buf, "hello loop\n") == 0;
+  if (umount(MNT) == 0 && ok) {
+    puts("test_loop_mount ok");
+  }
+}
+
+void test_mount_options() {
+  struct stat st;
+  errno = 0;
+  if (mount(IMAGE, MNT, "vfat", 0, "bogus") != -1 || errno != EINVAL) {
+    return;
+  }
+  if (mount(IMAGE, MNT, "vfat", 0, "ro,umask=077") != 0) {
+    return;
+  }
+  int ok = stat(MNT "/hello.txt", &st) == 0 && (st.st_mode & 0777) == 0700 &&
+           open(MNT "/hello.txt", O_WRONLY) == -1 && errno == EROFS &&
+           unlink(MNT "/hello.txt") == -1 && errno == EROFS;
+  if (umount(MNT) == 0 && ok) {
+    puts("test_mount_options ok");
+  }
+}
+
+void test_tmpfs() {
+  if (mkdir(TMP_MNT, 0755) != 0 ||
+      mount("tmpfs", TMP_MNT, "tmpfs", 0, "size=1m,mode=755") != 0) {
+    return;
+  }
+  int ok = write_file(TMP_MNT "/file", "tmpfs\n") == 0 &&
+           access(TMP_MNT "/file", F_OK) == 0;
+  // Writing stops at the size of the filesystem.
+  static char chunk[64 * 1024];
+  int fd = open(TMP_MNT "/big", O_WRONLY | O_CREAT, 0644);
+  ssize_t total = 0, n;
+  while (fd >= 0 && (n = write(fd, chunk, sizeof(chunk))) > 0) {
+    total += n;
+  }
+  ok = ok && fd >= 0 && n == -1 && errno == ENOSPC && total <= 1024 * 1024;
+  close(fd);
+  if (umount(TMP_MNT) == 0 && ok && access(TMP_MNT "/file", F_OK) == -1) {
+    puts("test_tmpfs ok");
+  }
+}
+
+int main() {
+  test_loop_mount();
+  test_mount_options();
+  test_tmpfs();
+  rmdir(MNT);
+  rmdir(TMP_MNT);
+  unlink(IMAGE);
+  return 0;
+}
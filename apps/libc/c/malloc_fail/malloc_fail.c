@@ -0,0 +1,125 @@
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+static long page;
+#define GROWS 3
+#define BLOCKS 4000
+#define ROUNDS 4
+
+static uintptr_t brk_to(uintptr_t addr) { return syscall(SYS_brk, addr); }
+
+static uintptr_t page_up(uintptr_t addr) {
+  return (addr + page - 1) & ~(uintptr_t)(page - 1);
+}
+
+// The `[heap]` lines of `/proc/self/maps`, with the range of the last.
+//
+// Read without stdio, which may allocate, and so move the break.
+static int heap_lines(uintptr_t *start, uintptr_t *end) {
+  static char maps[64 * 1024];
+  int fd = open("/proc/self/maps", O_RDONLY);
+  CHECK(fd >= 0);
+  size_t len = 0;
+  ssize_t n;
+  while ((n = read(fd, maps + len, sizeof(maps) - 1 - len)) > 0) {
+    len += n;
+  }
+  CHECK(n == 0);
+  close(fd);
+  maps[len] = '\0';
+  int lines = 0;
+  for (char *line = maps; (line = strstr(line, "[heap]")) != NULL;
+       line++, lines++) {
+    char *begin = line;
+    while (begin > maps && begin[-1] != '\n') {
+      begin--;
+    }
+    *start = strtoul(begin, &begin, 16);
+    *end = strtoul(begin + 1, NULL, 16);
+  }
+  return lines;
+}
+
+// `brk(0)` only reads the break, and the heap grown by several calls is one
+// `[heap]` mapping, up to the break.
+void test_query_and_maps() {
+  uintptr_t base = brk_to(0);
+  CHECK(base != 0 && brk_to(0) == base);
+  uintptr_t top = base;
+  for (int i = 0; i < GROWS; i++) {
+    CHECK(brk_to(top + 16 * page) == top + 16 * page);
+    top += 16 * page;
+    *(volatile char *)(top - 1) = 1;
+  }
+  CHECK(brk_to(0) == top);
+  uintptr_t start, end;
+  CHECK(heap_lines(&start, &end) == 1);
+  CHECK(start <= base && end == page_up(top));
+
+  CHECK(brk_to(base) == base);
+  CHECK(brk_to(0) == base);
+  puts("test_query_and_maps ok");
+}
+
+// With the heap unable to grow, `brk` fails by returning the break it left
+// alone, never an error, and `malloc` goes on with `mmap`.
+void test_malloc_capped() {
+  uintptr_t top = page_up(brk_to(0));
+  void *cap = mmap((void *)top, page, PROT_NONE,
+                   MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, -1, 0);
+  CHECK(cap == (void *)top);
+  uintptr_t now = brk_to(0);
+  CHECK(brk_to(top + page) == now);
+  CHECK(brk_to(top + 1024 * page) == now);
+  CHECK(brk_to(0) == now);
+
+  static unsigned char *blocks[BLOCKS];
+  static size_t sizes[BLOCKS];
+  unsigned seed = 1;
+  for (int round = 0; round < ROUNDS; round++) {
+    for (int i = 0; i < BLOCKS; i++) {
+      if (blocks[i] != NULL && (rand_r(&seed) & 1)) {
+        continue;
+      }
+      for (size_t j = 0; j < sizes[i]; j++) {
+        CHECK(blocks[i][j] == (unsigned char)(i + j));
+      }
+      free(blocks[i]);
+      seed = seed * 1103515245 + 12345;
+      sizes[i] = 1 + rand_r(&seed) % (i % 64 == 0 ? 256 * 1024 : 2048);
+      blocks[i] = malloc(sizes[i]);
+      CHECK(blocks[i] != NULL);
+      for (size_t j = 0; j < sizes[i]; j++) {
+        blocks[i][j] = (unsigned char)(i + j);
+      }
+    }
+  }
+  for (int i = 0; i < BLOCKS; i++) {
+    free(blocks[i]);
+  }
+  CHECK(brk_to(0) == now);
+  munmap(cap, page);
+  puts("test_malloc_capped ok");
+}
+
+int main() {
+  page = sysconf(_SC_PAGESIZE);
+  test_query_and_maps();
+  test_malloc_capped();
+  return 0;
+}
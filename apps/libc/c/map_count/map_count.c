@@ -0,0 +1,155 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+static long page;
+#define LIMIT_PATH "/proc/sys/vm/max_map_count"
+
+static char status[4096];
+
+// The number of areas of this process, from /proc/self/status.
+static long map_count(void) {
+  int fd = open("/proc/self/status", O_RDONLY);
+  CHECK(fd >= 0);
+  ssize_t len = read(fd, status, sizeof(status) - 1);
+  CHECK(len > 0);
+  status[len] = '\0';
+  close(fd);
+  const char *line = strstr(status, "\nMapCount:");
+  CHECK(line != NULL);
+  return strtol(line + strlen("\nMapCount:"), NULL, 10);
+}
+
+static long read_limit(void) {
+  char buf[32] = {0};
+  int fd = open(LIMIT_PATH, O_RDONLY);
+  CHECK(fd >= 0);
+  CHECK(read(fd, buf, sizeof(buf) - 1) > 0);
+  close(fd);
+  return strtol(buf, NULL, 10);
+}
+
+static void write_limit(long limit) {
+  char buf[32];
+  int len = snprintf(buf, sizeof(buf), "%ld\n", limit);
+  int fd = open(LIMIT_PATH, O_WRONLY);
+  CHECK(fd >= 0);
+  CHECK(write(fd, buf, len) == len);
+  close(fd);
+}
+
+// `pages` pages of private anonymous memory mapped with `prot` at the
+// given page of a PROT_NONE mapping, which keeps them apart from the other
+// mappings. Returns the PROT_NONE mapping.
+static char *isolated(size_t first, size_t pages, int prot) {
+  char *guard = mmap(NULL, (pages + 2 * first) * page, PROT_NONE,
+                     MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  CHECK(guard != MAP_FAILED);
+  CHECK(mmap(guard + first * page, pages * page, prot,
+             MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, -1, 0) ==
+        guard + first * page);
+  return guard;
+}
+
+// Adjacent mappings with the same permissions are one area.
+void test_merge_adjacent() {
+  long before = map_count();
+  char *guard = isolated(4, 32, PROT_READ | PROT_WRITE);
+  char *mem = guard + 4 * page;
+  // The PROT_NONE mapping split in two around the pages.
+  CHECK(map_count() == before + 3);
+  CHECK(munmap(mem + 16 * page, 16 * page) == 0);
+  CHECK(map_count() == before + 3);
+  CHECK(mmap(mem + 16 * page, 16 * page, PROT_READ | PROT_WRITE,
+             MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, -1, 0) == mem + 16 * page);
+  // One area, not another one after the first.
+  CHECK(map_count() == before + 3);
+  CHECK(munmap(guard, 40 * page) == 0);
+  CHECK(map_count() == before);
+  puts("test_merge_adjacent ok");
+}
+
+// Protecting every other page, then all of them back, ends with the areas
+// it started with, and with the data.
+void test_protect_cycle() {
+  char *guard = isolated(4, 32, PROT_READ | PROT_WRITE);
+  char *mem = guard + 4 * page;
+  for (int i = 0; i < 32; i++)
+    mem[i * page] = i;
+  long before = map_count();
+  for (int i = 1; i < 32; i += 2)
+    CHECK(mprotect(mem + i * page, page, PROT_READ) == 0);
+  CHECK(map_count() == before + 31);
+  // Already read-only, so nothing is split.
+  CHECK(mprotect(mem + page, page, PROT_READ) == 0);
+  CHECK(map_count() == before + 31);
+  CHECK(mprotect(mem, 32 * page, PROT_READ | PROT_WRITE) == 0);
+  CHECK(map_count() == before);
+  for (int i = 0; i < 32; i++)
+    CHECK(mem[i * page] == i);
+  // Back to PROT_NONE, the pages merge with the mapping around them.
+  CHECK(mprotect(mem, 32 * page, PROT_NONE) == 0);
+  CHECK(map_count() == before - 2);
+  CHECK(munmap(guard, 40 * page) == 0);
+  puts("test_protect_cycle ok");
+}
+
+// Splitting areas page by page fails with ENOMEM at max_map_count, and
+// merging them back works at the limit.
+void test_limit() {
+  long old_limit = read_limit();
+  long before = map_count();
+  long limit = before + 102;
+  write_limit(limit);
+  CHECK(read_limit() == limit);
+
+  size_t pages = 512;
+  char *guard = isolated(1, pages, PROT_READ | PROT_WRITE);
+  char *mem = guard + page;
+  CHECK(map_count() == before + 3);
+  size_t i;
+  for (i = 1; i < pages - 1; i += 2) {
+    if (mprotect(mem + i * page, page, PROT_READ) != 0)
+      break;
+  }
+  CHECK(i < pages - 1 && errno == ENOMEM);
+  CHECK(map_count() == limit - 1);
+  // The last page only splits one area.
+  CHECK(mprotect(mem + (pages - 1) * page, page, PROT_READ) == 0);
+  CHECK(map_count() == limit);
+  // At the limit, neither may an area be mapped, nor a hole made in one.
+  CHECK(mmap(NULL, page, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0) ==
+            MAP_FAILED &&
+        errno == ENOMEM);
+  CHECK(munmap(mem + i * page, page) == -1 && errno == ENOMEM);
+  CHECK(map_count() == limit);
+
+  CHECK(mprotect(mem, pages * page, PROT_READ | PROT_WRITE) == 0);
+  CHECK(map_count() == before + 3);
+  mem[0] = 1;
+  write_limit(old_limit);
+  CHECK(munmap(guard, (pages + 2) * page) == 0);
+  CHECK(map_count() == before);
+  puts("test_limit ok");
+}
+
+int main() {
+  page = sysconf(_SC_PAGESIZE);
+  test_merge_adjacent();
+  test_protect_cycle();
+  test_limit();
+  return 0;
+}
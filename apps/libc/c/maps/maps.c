@@ -0,0 +1,213 @@
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <pthread.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+static long page;
+#define STACK_SIZE (64 * page)
+
+struct area {
+  unsigned long start, end;
+  char perms[5];
+  char name[256];
+};
+
+static char maps[16384];
+static char status[4096];
+
+// Read the file at `path` into `buf`, without allocating, so that the
+// mappings do not change in between.
+static void read_file(const char *path, char *buf, size_t size) {
+  int fd = open(path, O_RDONLY);
+  CHECK(fd >= 0);
+  size_t len = 0;
+  ssize_t n;
+  while ((n = read(fd, buf + len, size - 1 - len)) > 0) {
+    len += n;
+  }
+  CHECK(n == 0 && len < size - 1);
+  buf[len] = '\0';
+  close(fd);
+}
+
+// The area of /proc/self/maps containing `addr`.
+static struct area area_of(const void *addr) {
+  read_file("/proc/self/maps", maps, sizeof(maps));
+  struct area area;
+  for (char *line = strtok(maps, "\n"); line; line = strtok(NULL, "\n")) {
+    int name_at = 0;
+    CHECK(sscanf(line, "%lx-%lx %4s %*s %*s %*s%n", &area.start, &area.end,
+                 area.perms, &name_at) == 3);
+    const char *name = line + name_at;
+    name += strspn(name, " ");
+    snprintf(area.name, sizeof(area.name), "%s", name);
+    if (area.start <= (unsigned long)addr && (unsigned long)addr < area.end) {
+      return area;
+    }
+  }
+  printf("no area contains %p\n", addr);
+  exit(1);
+}
+
+// A field of the last read /proc/self/status, in kB.
+static long status_kb(const char *field) {
+  size_t len = strlen(field);
+  for (const char *line = status; *line; line = strchr(line, '\n') + 1) {
+    if (strncmp(line, field, len) == 0 && line[len] == ':') {
+      long value;
+      CHECK(sscanf(line + len + 1, "%ld", &value) == 1);
+      return value;
+    }
+  }
+  printf("no %s in status\n", field);
+  exit(1);
+}
+
+static pthread_barrier_t barrier;
+
+struct thread_info {
+  pid_t tid;
+  char *sp;
+};
+
+static void *record(void *arg) {
+  struct thread_info *info = arg;
+  char local;
+  info->tid = syscall(SYS_gettid);
+  info->sp = &local;
+  // Stay alive while the main thread reads the maps.
+  pthread_barrier_wait(&barrier);
+  pthread_barrier_wait(&barrier);
+  return NULL;
+}
+
+// The heap, the main stack, the program and each thread stack are named,
+// whether or not the stack was mapped with `MAP_STACK`.
+void test_labels() {
+  // Moved before anything is allocated, since `malloc` may use the heap
+  // above, and never moved back.
+  char *heap = (char *)syscall(SYS_brk, 0);
+  CHECK(syscall(SYS_brk, heap + page) == (long)(heap + page));
+  heap[0] = 1;
+  CHECK(strcmp(area_of(heap).name, "[heap]") == 0);
+  char local;
+  CHECK(strcmp(area_of(&local).name, "[stack]") == 0);
+  struct area text = area_of((void *)test_labels);
+  CHECK(text.name[0] == '/' && text.perms[2] == 'x');
+
+  char *stack = mmap(NULL, STACK_SIZE, PROT_READ | PROT_WRITE,
+                     MAP_PRIVATE | MAP_ANONYMOUS | MAP_STACK, -1, 0);
+  CHECK(stack != MAP_FAILED);
+  CHECK(strcmp(area_of(stack).name, "[thread stack]") == 0);
+
+  CHECK(pthread_barrier_init(&barrier, NULL, 3) == 0);
+  struct thread_info infos[2];
+  pthread_t threads[2];
+  pthread_attr_t attr;
+  CHECK(pthread_attr_init(&attr) == 0);
+  CHECK(pthread_attr_setstack(&attr, stack, STACK_SIZE) == 0);
+  CHECK(pthread_create(&threads[0], &attr, record, &infos[0]) == 0);
+  CHECK(pthread_create(&threads[1], NULL, record, &infos[1]) == 0);
+  pthread_barrier_wait(&barrier);
+
+  CHECK(infos[0].sp >= stack && infos[0].sp < stack + STACK_SIZE);
+  char expected[32];
+  for (int i = 0; i < 2; i++) {
+    CHECK(infos[i].tid != getpid() && infos[i].tid != infos[1 - i].tid);
+    snprintf(expected, sizeof(expected), "[stack:%d]", infos[i].tid);
+    CHECK(strcmp(area_of(infos[i].sp).name, expected) == 0);
+  }
+  CHECK(strcmp(area_of(&local).name, "[stack]") == 0);
+
+  pthread_barrier_wait(&barrier);
+  for (int i = 0; i < 2; i++) {
+    CHECK(pthread_join(threads[i], NULL) == 0);
+  }
+  CHECK(pthread_barrier_destroy(&barrier) == 0);
+  CHECK(munmap(stack, STACK_SIZE) == 0);
+  puts("test_labels ok");
+}
+
+// The parts of a split area keep its kind.
+void test_split() {
+  char *stack = mmap(NULL, STACK_SIZE, PROT_NONE,
+                     MAP_PRIVATE | MAP_ANONYMOUS | MAP_STACK, -1, 0);
+  CHECK(stack != MAP_FAILED);
+  size_t inner = STACK_SIZE - 2 * page;
+  CHECK(mprotect(stack + page, inner, PROT_READ | PROT_WRITE) == 0);
+  stack[2 * page] = 1;
+  CHECK(munmap(stack + 4 * page, page) == 0);
+  char *parts[] = {stack, stack + page, stack + 5 * page,
+                   stack + STACK_SIZE - page};
+  for (int i = 0; i < 4; i++) {
+    CHECK(strcmp(area_of(parts[i]).name, "[thread stack]") == 0);
+  }
+  CHECK(area_of(stack).perms[0] == '-');
+  CHECK(strcmp(area_of(stack + page).perms, "rw-p") == 0);
+  CHECK(munmap(stack, STACK_SIZE) == 0);
+
+  char *shared = mmap(NULL, 2 * page, PROT_READ | PROT_WRITE,
+                      MAP_SHARED | MAP_ANONYMOUS, -1, 0);
+  CHECK(shared != MAP_FAILED);
+  CHECK(mprotect(shared, page, PROT_READ) == 0);
+  CHECK(strcmp(area_of(shared).perms, "r--s") == 0);
+  CHECK(strcmp(area_of(shared + page).perms, "rw-s") == 0);
+  CHECK(munmap(shared, 2 * page) == 0);
+  puts("test_split ok");
+}
+
+static long kinds_kb(void) {
+  return status_kb("VmHeap") + status_kb("VmStk") + status_kb("VmThreadStk") +
+         status_kb("VmFile") + status_kb("VmAnon") + status_kb("VmShared") +
+         status_kb("VmLinear");
+}
+
+// The bytes of each kind add up to the mapped size, and to the areas of
+// /proc/self/maps.
+void test_accounting() {
+  read_file("/proc/self/status", status, sizeof(status));
+  CHECK(kinds_kb() == status_kb("VmSize"));
+  long thread_stacks = status_kb("VmThreadStk");
+  long anon = status_kb("VmAnon");
+  char *stack = mmap(NULL, STACK_SIZE, PROT_READ | PROT_WRITE,
+                     MAP_PRIVATE | MAP_ANONYMOUS | MAP_STACK, -1, 0);
+  CHECK(stack != MAP_FAILED);
+
+  read_file("/proc/self/maps", maps, sizeof(maps));
+  read_file("/proc/self/status", status, sizeof(status));
+  CHECK(status_kb("VmThreadStk") == thread_stacks + STACK_SIZE / 1024);
+  CHECK(status_kb("VmAnon") == anon);
+  CHECK(kinds_kb() == status_kb("VmSize"));
+  unsigned long start, end, total = 0;
+  for (char *line = strtok(maps, "\n"); line; line = strtok(NULL, "\n")) {
+    CHECK(sscanf(line, "%lx-%lx", &start, &end) == 2);
+    total += end - start;
+  }
+  CHECK((long)(total / 1024) == status_kb("VmSize"));
+
+  CHECK(munmap(stack, STACK_SIZE) == 0);
+  read_file("/proc/self/status", status, sizeof(status));
+  CHECK(status_kb("VmThreadStk") == thread_stacks);
+  puts("test_accounting ok");
+}
+
+int main() {
+  page = sysconf(_SC_PAGESIZE);
+  test_labels();
+  test_split();
+  test_accounting();
+  return 0;
+}
@@ -0,0 +1,76 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define PAGES 4
+
+static long page;
+
+// A mapping of `PAGES` pages, with the one after it unmapped.
+static char *map_pages(void) {
+  char *p = mmap(NULL, (PAGES + 1) * page, PROT_READ | PROT_WRITE,
+                 MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  CHECK(p != MAP_FAILED);
+  CHECK(munmap(p + PAGES * page, page) == 0);
+  return p;
+}
+
+void test_mincore() {
+  char *p = map_pages();
+  unsigned char vec[PAGES];
+  CHECK(mincore(p, PAGES * page, vec) == 0);
+  for (int i = 0; i < PAGES; i++)
+    CHECK(!(vec[i] & 1));
+  p[page] = 1;
+  CHECK(mincore(p, PAGES * page, vec) == 0);
+  CHECK(!(vec[0] & 1) && (vec[1] & 1));
+  CHECK(mincore(p + 1, page, vec) == -1 && errno == EINVAL);
+  CHECK(mincore(p, (PAGES + 1) * page, vec) == -1 && errno == ENOMEM);
+  CHECK(mincore(p, page, NULL) == -1 && errno == EFAULT);
+  CHECK(munmap(p, PAGES * page) == 0);
+  puts("test_mincore ok");
+}
+
+void test_msync() {
+  char *p = map_pages();
+  CHECK(msync(p, PAGES * page, MS_SYNC) == 0);
+  CHECK(msync(p, page, MS_ASYNC | MS_INVALIDATE) == 0);
+  CHECK(msync(p, page, MS_ASYNC | MS_SYNC) == -1 && errno == EINVAL);
+  CHECK(msync(p + 1, page, MS_SYNC) == -1 && errno == EINVAL);
+  CHECK(msync(p, (PAGES + 1) * page, MS_SYNC) == -1 && errno == ENOMEM);
+  CHECK(munmap(p, PAGES * page) == 0);
+  puts("test_msync ok");
+}
+
+void test_mlock() {
+  char *p = map_pages();
+  unsigned char vec[PAGES];
+  // Rounded out to whole pages, and allocated at once.
+  CHECK(mlock(p + 1, page) == 0);
+  CHECK(mincore(p, PAGES * page, vec) == 0);
+  CHECK((vec[0] & 1) && (vec[1] & 1) && !(vec[2] & 1));
+  CHECK(munlock(p, 2 * page) == 0);
+  CHECK(mlock(p, (PAGES + 1) * page) == -1 && errno == ENOMEM);
+  CHECK(munlock(p + PAGES * page, page) == -1 && errno == ENOMEM);
+  CHECK(munmap(p, PAGES * page) == 0);
+  puts("test_mlock ok");
+}
+
+int main() {
+  page = sysconf(_SC_PAGESIZE);
+  test_mincore();
+  test_msync();
+  test_mlock();
+  return 0;
+}
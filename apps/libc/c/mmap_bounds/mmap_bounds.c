@@ -0,0 +1,106 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+// The end of the user address space: 256 GiB with Sv39 on riscv64, and as
+// configured on loongarch64, 128 TiB on aarch64, and a page less on x86_64
+// like on Linux.
+#if defined(__riscv) || defined(__loongarch__)
+#define TOP 0x4000000000UL
+#elif defined(__x86_64__)
+#define TOP 0x7ffffffff000UL
+#else
+#define TOP 0x800000000000UL
+#endif
+
+#define PROT (PROT_READ | PROT_WRITE)
+#define ANON (MAP_PRIVATE | MAP_ANONYMOUS)
+
+static unsigned long page;
+
+// Map `length` bytes at `addr` with `flags` besides `ANON`.
+static void *map(unsigned long addr, unsigned long length, int flags) {
+  return mmap((void *)addr, length, PROT, ANON | flags, -1, 0);
+}
+
+// A mapping from `hint` is where it is, if it fits, and anywhere below the
+// top otherwise.
+static void check_hinted(unsigned long hint, unsigned long length) {
+  char *p = map(hint, length, 0);
+  CHECK(p != MAP_FAILED);
+  CHECK((uintptr_t)p + length <= TOP);
+  CHECK((uintptr_t)p + length > (uintptr_t)p);
+  // Not on another mapping, which would have been replaced.
+  p[0] = 1;
+  p[length - 1] = 2;
+  CHECK(munmap(p, length) == 0);
+}
+
+// Fixed mappings fit below the top or fail with `ENOMEM`.
+void test_fixed() {
+  char *p = map(TOP - page, page, MAP_FIXED);
+  CHECK(p == (char *)(TOP - page));
+  p[page - 1] = 1;
+  CHECK(munmap(p, page) == 0);
+
+  CHECK(map(TOP - page, 2 * page, MAP_FIXED) == MAP_FAILED && errno == ENOMEM);
+  CHECK(map(TOP, page, MAP_FIXED) == MAP_FAILED && errno == ENOMEM);
+  CHECK(map(TOP + page, page, MAP_FIXED) == MAP_FAILED && errno == ENOMEM);
+  CHECK(map(-page, page, MAP_FIXED) == MAP_FAILED && errno == ENOMEM);
+  CHECK(map(0, page, MAP_FIXED) == MAP_FAILED && errno == EPERM);
+  CHECK(map(TOP - 2 * page + 1, page, MAP_FIXED) == MAP_FAILED &&
+        errno == EINVAL);
+  puts("test_fixed ok");
+}
+
+// Hints at or above the top are ignored, like ones which do not fit.
+void test_hints() {
+  char *p = map(TOP - page, page, 0);
+  CHECK(p == (char *)(TOP - page));
+  CHECK(munmap(p, page) == 0);
+
+  check_hinted(TOP - page, 2 * page);
+  check_hinted(TOP, page);
+  check_hinted(TOP + page, page);
+  check_hinted(-page, page);
+  check_hinted(-page, 16 * page);
+  check_hinted(0, page);
+
+  // With the top page taken, a hint there goes elsewhere.
+  char *top = map(TOP - page, page, MAP_FIXED);
+  CHECK(top == (char *)(TOP - page));
+  check_hinted(TOP - page, page);
+  CHECK(munmap(top, page) == 0);
+  puts("test_hints ok");
+}
+
+// No length larger than the address space fits.
+void test_lengths() {
+  CHECK(map(0, TOP, 0) == MAP_FAILED && errno == ENOMEM);
+  CHECK(map(0, -page, 0) == MAP_FAILED && errno == ENOMEM);
+  CHECK(map(0, -1UL, 0) == MAP_FAILED && errno == ENOMEM);
+  CHECK(map(page, -page, MAP_FIXED) == MAP_FAILED && errno == ENOMEM);
+  CHECK(munmap((void *)(TOP - page), 2 * page) == -1 && errno == EINVAL);
+  CHECK(munmap((void *)TOP, page) == -1 && errno == EINVAL);
+  puts("test_lengths ok");
+}
+
+int main() {
+  page = sysconf(_SC_PAGESIZE);
+  test_fixed();
+  test_hints();
+  test_lengths();
+  return 0;
+}
@@ -0,0 +1,90 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+static long page;
+#define PAGES 64
+
+// The page faults of the whole system so far, from /proc/vmstat.
+static unsigned long page_faults(void) {
+  char buf[4096];
+  int fd = open("/proc/vmstat", O_RDONLY);
+  CHECK(fd >= 0);
+  ssize_t n = read(fd, buf, sizeof(buf) - 1);
+  CHECK(n > 0);
+  buf[n] = '\0';
+  close(fd);
+  char *line = strstr(buf, "pgfault ");
+  CHECK(line != NULL);
+  return strtoul(line + strlen("pgfault "), NULL, 10);
+}
+
+// Map `PAGES` anonymous pages with `flags`, and count the faults taken by
+// touching each of them once.
+static unsigned long faults_on_touch(int flags) {
+  char *map = mmap(NULL, PAGES * page, PROT_READ | PROT_WRITE,
+                   MAP_PRIVATE | MAP_ANONYMOUS | flags, -1, 0);
+  CHECK(map != MAP_FAILED);
+  unsigned long before = page_faults();
+  for (int i = 0; i < PAGES; i++) {
+    map[i * page] = 1;
+  }
+  unsigned long after = page_faults();
+  CHECK(munmap(map, PAGES * page) == 0);
+  return after - before;
+}
+
+static void test_populate(void) {
+  // Warm up, so that the code and stack touched below are mapped.
+  faults_on_touch(0);
+  CHECK(faults_on_touch(0) >= PAGES);
+  CHECK(faults_on_touch(MAP_POPULATE) == 0);
+  printf("test_populate ok\n");
+}
+
+static void test_locked(void) {
+  CHECK(faults_on_touch(MAP_LOCKED) == 0);
+  printf("test_locked ok\n");
+}
+
+static void test_flags(void) {
+  errno = 0;
+  CHECK(mmap(NULL, page, PROT_READ, MAP_ANONYMOUS, -1, 0) == MAP_FAILED &&
+        errno == EINVAL);
+  char *map = mmap(NULL, page, PROT_READ | PROT_WRITE,
+                   MAP_SHARED_VALIDATE | MAP_ANONYMOUS | MAP_POPULATE, -1, 0);
+  CHECK(map != MAP_FAILED);
+  CHECK(munmap(map, page) == 0);
+  // Unknown flags are only rejected with MAP_SHARED_VALIDATE.
+  int unknown = 0x200000;
+  map = mmap(NULL, page, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS | unknown, -1,
+             0);
+  CHECK(map != MAP_FAILED);
+  CHECK(munmap(map, page) == 0);
+  errno = 0;
+  CHECK(mmap(NULL, page, PROT_READ, MAP_SHARED_VALIDATE | MAP_ANONYMOUS | unknown,
+             -1, 0) == MAP_FAILED &&
+        errno == EOPNOTSUPP);
+  printf("test_flags ok\n");
+}
+
+int main(void) {
+  page = sysconf(_SC_PAGESIZE);
+  test_populate();
+  test_locked();
+  test_flags();
+  return 0;
+}
@@ -0,0 +1,121 @@
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <pthread.h>
+#include <stdatomic.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define FILE_PATH "/mmap_read.tmp"
+#define FILE_SIZE (256 * 1024)
+// Not a divisor of a page nor of a cluster.
+#define CHUNK 1000
+
+static long page;
+static int fd;
+static atomic_int done;
+
+// The byte at `offset` of the file.
+static unsigned char byte_at(long offset) {
+  return (unsigned char)(offset * 7 + offset / 251);
+}
+
+static void make_file(void) {
+  unsigned char *buf = malloc(FILE_SIZE);
+  CHECK(buf != NULL);
+  for (long i = 0; i < FILE_SIZE; i++)
+    buf[i] = byte_at(i);
+  int out = open(FILE_PATH, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  CHECK(out >= 0);
+  CHECK(write(out, buf, FILE_SIZE) == FILE_SIZE);
+  CHECK(close(out) == 0);
+  free(buf);
+}
+
+// Map `pages` pages of the file from page `first`, and check them.
+static void map_and_check(long first, long pages) {
+  long offset = first * page;
+  long length = pages * page;
+  unsigned char *map =
+      mmap(NULL, length, PROT_READ, MAP_PRIVATE, fd, (off_t)offset);
+  CHECK(map != MAP_FAILED);
+  for (long i = 0; i < length && offset + i < FILE_SIZE; i++)
+    CHECK(map[i] == byte_at(offset + i));
+  CHECK(munmap(map, length) == 0);
+}
+
+// Read the whole file in chunks, checking each byte is where it should be,
+// and calling `between` after each chunk.
+static void stream(void (*between)(long pos)) {
+  unsigned char buf[CHUNK];
+  long pos = 0;
+  for (;;) {
+    ssize_t n = read(fd, buf, sizeof(buf));
+    CHECK(n >= 0);
+    if (n == 0)
+      break;
+    for (ssize_t i = 0; i < n; i++)
+      CHECK(buf[i] == byte_at(pos + i));
+    pos += n;
+    if (between)
+      between(pos);
+  }
+  CHECK(pos == FILE_SIZE);
+}
+
+static void map_between(long pos) {
+  long pages = FILE_SIZE / page;
+  map_and_check((pos / CHUNK * 13) % pages, 1 + pos / CHUNK % 5);
+  CHECK(lseek(fd, 0, SEEK_CUR) == pos);
+}
+
+// Mapping the file between reads neither moves nor disturbs the position.
+void test_interleaved() {
+  CHECK(lseek(fd, 0, SEEK_SET) == 0);
+  stream(map_between);
+  puts("test_interleaved ok");
+}
+
+static void *map_loop(void *arg) {
+  (void)arg;
+  long pages = FILE_SIZE / page;
+  for (long i = 0; !atomic_load(&done); i++)
+    map_and_check((i * 17) % pages, pages - (i * 17) % pages);
+  return NULL;
+}
+
+// Nor does mapping the file from another thread while it is read.
+void test_concurrent() {
+  pthread_t thread;
+  for (int round = 0; round < 4; round++) {
+    atomic_store(&done, 0);
+    CHECK(lseek(fd, 0, SEEK_SET) == 0);
+    CHECK(pthread_create(&thread, NULL, map_loop, NULL) == 0);
+    stream(NULL);
+    atomic_store(&done, 1);
+    CHECK(pthread_join(thread, NULL) == 0);
+  }
+  puts("test_concurrent ok");
+}
+
+int main() {
+  page = sysconf(_SC_PAGESIZE);
+  make_file();
+  fd = open(FILE_PATH, O_RDONLY);
+  CHECK(fd >= 0);
+  test_interleaved();
+  test_concurrent();
+  CHECK(close(fd) == 0);
+  CHECK(unlink(FILE_PATH) == 0);
+  return 0;
+}
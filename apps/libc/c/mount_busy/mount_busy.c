@@ -0,0 +1,88 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define MNT "/mount_busy_mnt"
+
+// The number of filesystems flushed and released after being unmounted.
+static long released_mounts(void) {
+  char buf[32];
+  int fd = open("/proc/starry/released_mounts", O_RDONLY);
+  CHECK(fd >= 0);
+  ssize_t len = read(fd, buf, sizeof(buf) - 1);
+  CHECK(len > 0);
+  buf[len] = '\0';
+  close(fd);
+  return atol(buf);
+}
+
+static void mount_tmpfs(void) {
+  CHECK(mount("tmpfs", MNT, "tmpfs", 0, NULL) == 0);
+  int fd = open(MNT "/file", O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  CHECK(write(fd, "busy\n", 5) == 5);
+  CHECK(close(fd) == 0);
+}
+
+static void test_open_file(void) {
+  mount_tmpfs();
+  int fd = open(MNT "/file", O_RDONLY);
+  CHECK(fd >= 0);
+  errno = 0;
+  CHECK(umount2(MNT, 0) == -1 && errno == EBUSY);
+  CHECK(close(fd) == 0);
+  CHECK(umount2(MNT, 0) == 0);
+  puts("test_open_file ok");
+}
+
+static void test_cwd(void) {
+  mount_tmpfs();
+  CHECK(chdir(MNT) == 0);
+  errno = 0;
+  CHECK(umount2(MNT, 0) == -1 && errno == EBUSY);
+  CHECK(chdir("/") == 0);
+  CHECK(umount2(MNT, 0) == 0);
+  puts("test_cwd ok");
+}
+
+static void test_detach(void) {
+  mount_tmpfs();
+  int fd = open(MNT "/file", O_RDONLY);
+  CHECK(fd >= 0);
+  long released = released_mounts();
+  CHECK(umount2(MNT, MNT_DETACH) == 0);
+
+  // Gone for new lookups, but still there for the open file.
+  errno = 0;
+  CHECK(open(MNT "/file", O_RDONLY) == -1 && errno == ENOENT);
+  char buf[8] = {0};
+  CHECK(read(fd, buf, sizeof(buf)) == 5 && strcmp(buf, "busy\n") == 0);
+  CHECK(released_mounts() == released);
+
+  CHECK(close(fd) == 0);
+  CHECK(released_mounts() == released + 1);
+  puts("test_detach ok");
+}
+
+int main(void) {
+  CHECK(mkdir(MNT, 0755) == 0);
+  test_open_file();
+  test_cwd();
+  test_detach();
+  CHECK(rmdir(MNT) == 0);
+  return 0;
+}
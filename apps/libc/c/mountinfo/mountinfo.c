@@ -0,0 +1,153 @@
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <mntent.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define IMAGE "/mountinfo.img"
+#define MNT "/mountinfo_mnt"
+#define SECTORS 2048
+
+static void put16(uint8_t *p, uint16_t v) {
+  p[0] = v;
+  p[1] = v >> 8;
+}
+
+// Write an empty 1 MiB FAT12 image, with 4 sectors per cluster.
+static void make_image(const char *path) {
+  static uint8_t sector[512];
+  int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  for (int i = 0; i < SECTORS; i++) {
+    memset(sector, 0, sizeof(sector));
+    if (i == 0) {
+      memcpy(sector, "\xeb\x3c\x90MSDOS5.0", 11);
+      put16(sector + 11, 512);  // bytes per sector
+      sector[13] = 4;           // sectors per cluster
+      put16(sector + 14, 1);    // reserved sectors
+      sector[16] = 2;           // FATs
+      put16(sector + 17, 512);  // root entries
+      put16(sector + 19, SECTORS);
+      sector[21] = 0xf8;        // media
+      put16(sector + 22, 2);    // sectors per FAT
+      put16(sector + 24, 32);   // sectors per track
+      put16(sector + 26, 64);   // heads
+      sector[36] = 0x80;        // drive number
+      sector[38] = 0x29;        // extended boot signature
+      memcpy(sector + 39, "\x78\x56\x34\x12NO NAME    FAT12   ", 23);
+      sector[510] = 0x55;
+      sector[511] = 0xaa;
+    } else if (i == 1 || i == 3) {
+      memcpy(sector, "\xf8\xff\xff", 3);
+    }
+    CHECK(write(fd, sector, sizeof(sector)) == sizeof(sector));
+  }
+  CHECK(close(fd) == 0);
+}
+
+// Find the last mount on `dir` in /proc/mounts, as `getmntent` parses it,
+// copying its type and options.
+static int find_mount(const char *dir, char *type, char *opts) {
+  FILE *f = setmntent("/proc/mounts", "r");
+  CHECK(f != NULL);
+  int found = 0;
+  struct mntent *ent;
+  while ((ent = getmntent(f)) != NULL) {
+    if (strcmp(ent->mnt_dir, dir) == 0) {
+      strcpy(type, ent->mnt_type);
+      strcpy(opts, ent->mnt_opts);
+      found = 1;
+    }
+  }
+  endmntent(f);
+  return found;
+}
+
+// The mount ID and parent ID of the last mount on `dir` in mountinfo, or
+// -1 if there is none.
+static int mount_id(const char *dir, int *parent) {
+  FILE *f = fopen("/proc/self/mountinfo", "r");
+  CHECK(f != NULL);
+  char line[512];
+  int id = -1;
+  while (fgets(line, sizeof(line), f)) {
+    int this_id, this_parent;
+    char point[256];
+    if (sscanf(line, "%d %d %*s %*s %255s", &this_id, &this_parent, point) ==
+            3 &&
+        strcmp(point, dir) == 0) {
+      id = this_id;
+      if (parent)
+        *parent = this_parent;
+    }
+    // Every line has the separator before the type.
+    CHECK(strstr(line, " - ") != NULL);
+  }
+  fclose(f);
+  return id;
+}
+
+// The root and the tmpfs on /tmp are listed, as df and mount read them.
+void test_boot_mounts() {
+  char type[64], opts[256];
+  CHECK(find_mount("/", type, opts));
+  CHECK(strncmp(opts, "rw", 2) == 0);
+  CHECK(find_mount("/tmp", type, opts));
+  CHECK(strcmp(type, "tmpfs") == 0);
+  CHECK(strstr(opts, "size=") != NULL);
+  CHECK(find_mount("/proc", type, opts));
+  CHECK(strcmp(type, "proc") == 0);
+
+  int root = mount_id("/", NULL);
+  int parent;
+  int tmp = mount_id("/tmp", &parent);
+  CHECK(root > 0 && tmp > 0 && tmp != root);
+  CHECK(parent == root);
+  puts("test_boot_mounts ok");
+}
+
+// A loop mount shows with its options while mounted, keeping its ID, and
+// is gone once unmounted. Mounting again gives another ID.
+void test_loop_mount() {
+  make_image(IMAGE);
+  mkdir(MNT, 0755);
+  CHECK(mount(IMAGE, MNT, "vfat", MS_RDONLY, "fmask=0133") == 0);
+  char type[64], opts[256];
+  CHECK(find_mount(MNT, type, opts));
+  CHECK(strcmp(type, "vfat") == 0);
+  CHECK(strncmp(opts, "ro", 2) == 0);
+  CHECK(strstr(opts, "fmask=0133") != NULL);
+  int id = mount_id(MNT, NULL);
+  CHECK(id > 0);
+  CHECK(mount_id(MNT, NULL) == id);
+  CHECK(umount(MNT) == 0);
+  CHECK(!find_mount(MNT, type, opts));
+  CHECK(mount_id(MNT, NULL) == -1);
+
+  CHECK(mount(IMAGE, MNT, "vfat", 0, NULL) == 0);
+  int again = mount_id(MNT, NULL);
+  CHECK(again > 0 && again != id);
+  CHECK(umount(MNT) == 0);
+  rmdir(MNT);
+  unlink(IMAGE);
+  puts("test_loop_mount ok");
+}
+
+int main() {
+  test_boot_mounts();
+  test_loop_mount();
+  return 0;
+}
@@ -0,0 +1,153 @@
+#define _GNU_SOURCE
+#include <dirent.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <mqueue.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/resource.h>
+#include <time.h>
+#include <unistd.h>
+
+// Messages come out highest priority first, and in order within a priority.
+void test_priority() {
+  struct mq_attr attr = {.mq_maxmsg = 4, .mq_msgsize = 16};
+  mqd_t mq = mq_open("/test_prio", O_CREAT | O_EXCL | O_RDWR, 0600, &attr);
+  if (mq == (mqd_t)-1) {
+    return;
+  }
+  mq_send(mq, "a", 2, 1);
+  mq_send(mq, "b", 2, 5);
+  mq_send(mq, "c", 2, 1);
+  mq_send(mq, "d", 2, 5);
+  char buf[16], got[5] = {0};
+  unsigned prio, prios = 0;
+  for (int i = 0; i < 4; i++) {
+    if (mq_receive(mq, buf, sizeof(buf), &prio) != 2) {
+      return;
+    }
+    got[i] = buf[0];
+    prios = prios * 10 + prio;
+  }
+  if (strcmp(got, "bdac") == 0 && prios == 5511) {
+    puts("test_priority ok");
+  }
+  mq_close(mq);
+  mq_unlink("/test_prio");
+}
+
+// The limits of the attributes are enforced.
+void test_limits() {
+  struct mq_attr attr = {.mq_maxmsg = 1, .mq_msgsize = 8}, bad = {0};
+  if (mq_open("/test_bad", O_CREAT | O_RDWR, 0600, &bad) != (mqd_t)-1 ||
+      errno != EINVAL) {
+    return;
+  }
+  mqd_t mq =
+      mq_open("/test_limits", O_CREAT | O_RDWR | O_NONBLOCK, 0600, &attr);
+  if (mq == (mqd_t)-1) {
+    return;
+  }
+  char buf[8];
+  struct timespec ts;
+  clock_gettime(CLOCK_REALTIME, &ts);
+  ts.tv_nsec += 10000000;
+  if (ts.tv_nsec >= 1000000000) {
+    ts.tv_sec++;
+    ts.tv_nsec -= 1000000000;
+  }
+  int too_big = mq_send(mq, "123456789", 9, 0) == -1 && errno == EMSGSIZE;
+  int empty = mq_receive(mq, buf, sizeof(buf), NULL) == -1 && errno == EAGAIN;
+  int small = mq_receive(mq, buf, 4, NULL) == -1 && errno == EMSGSIZE;
+  mq_send(mq, "x", 1, 0);
+  int full = mq_send(mq, "y", 1, 0) == -1 && errno == EAGAIN;
+
+  struct mq_attr blocking = {0}, old;
+  mq_setattr(mq, &blocking, &old);
+  int timed_out =
+      mq_timedsend(mq, "y", 1, 0, &ts) == -1 && errno == ETIMEDOUT;
+  mq_getattr(mq, &attr);
+  if (too_big && empty && small && full && timed_out &&
+      (old.mq_flags & O_NONBLOCK) && !(attr.mq_flags & O_NONBLOCK) &&
+      attr.mq_maxmsg == 1 && attr.mq_msgsize == 8 && attr.mq_curmsgs == 1) {
+    puts("test_limits ok");
+  }
+  mq_close(mq);
+  mq_unlink("/test_limits");
+}
+
+// An unlinked queue works until closed, and a new one can take its name.
+void test_unlink() {
+  mqd_t mq = mq_open("/test_unlink", O_CREAT | O_RDWR, 0600, NULL);
+  if (mq == (mqd_t)-1 || mq_unlink("/test_unlink") < 0) {
+    return;
+  }
+  char buf[8192];
+  int gone = mq_open("/test_unlink", O_RDWR) == (mqd_t)-1 && errno == ENOENT;
+  int works = mq_send(mq, "z", 1, 0) == 0 &&
+              mq_receive(mq, buf, sizeof(buf), NULL) == 1 && buf[0] == 'z';
+  mqd_t again = mq_open("/test_unlink", O_CREAT | O_EXCL | O_RDWR, 0600, NULL);
+  if (gone && works && again != (mqd_t)-1 &&
+      mq_unlink("/test_unlink") == 0 &&
+      mq_unlink("/test_unlink") == -1 && errno == ENOENT) {
+    puts("test_unlink ok");
+  }
+  mq_close(again);
+  mq_close(mq);
+}
+
+// Queues are listed in `/dev/mqueue`.
+void test_dev_mqueue() {
+  mqd_t mq = mq_open("/test_dev", O_CREAT | O_RDONLY, 0600, NULL);
+  if (mq == (mqd_t)-1) {
+    return;
+  }
+  DIR *dir = opendir("/dev/mqueue");
+  int found = 0;
+  struct dirent *ent;
+  while (dir && (ent = readdir(dir))) {
+    found |= strcmp(ent->d_name, "test_dev") == 0;
+  }
+  if (dir) {
+    closedir(dir);
+  }
+  int wronly = mq_send(mq, "x", 1, 0) == -1 && errno == EBADF;
+  if (found && wronly) {
+    puts("test_dev_mqueue ok");
+  }
+  mq_close(mq);
+  mq_unlink("/test_dev");
+}
+
+// Creating a queue fails with EMFILE once the queues would hold more than
+// RLIMIT_MSGQUEUE, and what a queue held is given back once it is gone.
+void test_rlimit() {
+  struct rlimit old, small = {.rlim_cur = 4096, .rlim_max = RLIM_INFINITY};
+  getrlimit(RLIMIT_MSGQUEUE, &old);
+  setrlimit(RLIMIT_MSGQUEUE, &small);
+  struct mq_attr big = {.mq_maxmsg = 4, .mq_msgsize = 1024};
+  struct mq_attr fits = {.mq_maxmsg = 2, .mq_msgsize = 1024};
+  int too_big = mq_open("/test_rlimit", O_CREAT | O_RDWR, 0600, &big) == (mqd_t)-1 &&
+                errno == EMFILE;
+  mqd_t first = mq_open("/test_rlimit", O_CREAT | O_RDWR, 0600, &fits);
+  int full = mq_open("/test_rlimit2", O_CREAT | O_RDWR, 0600, &fits) == (mqd_t)-1 &&
+             errno == EMFILE;
+  mq_unlink("/test_rlimit");
+  mq_close(first);
+  mqd_t again = mq_open("/test_rlimit2", O_CREAT | O_RDWR, 0600, &fits);
+  if (too_big && first != (mqd_t)-1 && full && again != (mqd_t)-1) {
+    puts("test_rlimit ok");
+  }
+  mq_unlink("/test_rlimit2");
+  mq_close(again);
+  setrlimit(RLIMIT_MSGQUEUE, &old);
+}
+
+int main() {
+  test_priority();
+  test_limits();
+  test_unlink();
+  test_dev_mqueue();
+  test_rlimit();
+  return 0;
+}
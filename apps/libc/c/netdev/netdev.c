@@ -0,0 +1,170 @@
+#define _GNU_SOURCE
+#include <arpa/inet.h>
+#include <errno.h>
+#include <net/if.h>
+#include <net/if_arp.h>
+#include <netinet/in.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/ioctl.h>
+#include <sys/socket.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+// What QEMU is run with, `IP` and the prefix of `axnet`.
+#define ETH0_ADDR "10.0.2.15"
+#define ETH0_NETMASK "255.255.255.0"
+#define GATEWAY_ADDR "10.0.2.2"
+
+static const char *inet_str(const struct sockaddr *addr) {
+  CHECK(addr->sa_family == AF_INET);
+  return inet_ntoa(((const struct sockaddr_in *)addr)->sin_addr);
+}
+
+// Make `op` on the interface `name`, returning the request.
+static struct ifreq if_request(int fd, const char *name, unsigned long op) {
+  struct ifreq req = {0};
+  strncpy(req.ifr_name, name, IFNAMSIZ - 1);
+  CHECK(ioctl(fd, op, &req) == 0);
+  return req;
+}
+
+static void test_ifconf(void) {
+  int fd = socket(AF_INET, SOCK_DGRAM, 0);
+  CHECK(fd >= 0);
+
+  // Without a buffer, only the length needed.
+  struct ifconf conf = {.ifc_len = 0, .ifc_buf = NULL};
+  CHECK(ioctl(fd, SIOCGIFCONF, &conf) == 0);
+  CHECK(conf.ifc_len == 2 * (int)sizeof(struct ifreq));
+
+  struct ifreq reqs[4];
+  memset(reqs, 0xff, sizeof(reqs));
+  conf.ifc_len = sizeof(reqs);
+  conf.ifc_req = reqs;
+  CHECK(ioctl(fd, SIOCGIFCONF, &conf) == 0);
+  CHECK(conf.ifc_len == 2 * (int)sizeof(struct ifreq));
+  CHECK(strcmp(reqs[0].ifr_name, "lo") == 0);
+  CHECK(strcmp(inet_str(&reqs[0].ifr_addr), "127.0.0.1") == 0);
+  CHECK(strcmp(reqs[1].ifr_name, "eth0") == 0);
+  CHECK(strcmp(inet_str(&reqs[1].ifr_addr), ETH0_ADDR) == 0);
+
+  // Only whole requests fit.
+  conf.ifc_len = sizeof(struct ifreq) + 1;
+  CHECK(ioctl(fd, SIOCGIFCONF, &conf) == 0);
+  CHECK(conf.ifc_len == (int)sizeof(struct ifreq));
+
+  close(fd);
+  printf("test_ifconf ok\n");
+}
+
+static void test_named(void) {
+  int fd = socket(AF_INET, SOCK_STREAM, 0);
+  CHECK(fd >= 0);
+
+  struct ifreq req = if_request(fd, "eth0", SIOCGIFADDR);
+  CHECK(strcmp(inet_str(&req.ifr_addr), ETH0_ADDR) == 0);
+  req = if_request(fd, "eth0", SIOCGIFNETMASK);
+  CHECK(strcmp(inet_str(&req.ifr_netmask), ETH0_NETMASK) == 0);
+  req = if_request(fd, "lo", SIOCGIFNETMASK);
+  CHECK(strcmp(inet_str(&req.ifr_netmask), "255.0.0.0") == 0);
+
+  req = if_request(fd, "eth0", SIOCGIFHWADDR);
+  CHECK(req.ifr_hwaddr.sa_family == ARPHRD_ETHER);
+  static const char zero[6];
+  CHECK(memcmp(req.ifr_hwaddr.sa_data, zero, 6) != 0);
+  req = if_request(fd, "lo", SIOCGIFHWADDR);
+  CHECK(req.ifr_hwaddr.sa_family == ARPHRD_LOOPBACK);
+
+  req = if_request(fd, "lo", SIOCGIFFLAGS);
+  CHECK((req.ifr_flags & (IFF_UP | IFF_LOOPBACK)) == (IFF_UP | IFF_LOOPBACK));
+  req = if_request(fd, "eth0", SIOCGIFFLAGS);
+  CHECK(req.ifr_flags & IFF_UP);
+  CHECK(!(req.ifr_flags & IFF_LOOPBACK));
+
+  req = if_request(fd, "eth0", SIOCGIFMTU);
+  CHECK(req.ifr_mtu == 1500);
+
+  req = if_request(fd, "lo", SIOCGIFINDEX);
+  CHECK(req.ifr_ifindex == 1);
+  req = if_request(fd, "eth0", SIOCGIFINDEX);
+  CHECK(req.ifr_ifindex == 2);
+  struct ifreq by_index = {.ifr_ifindex = 2};
+  CHECK(ioctl(fd, SIOCGIFNAME, &by_index) == 0);
+  CHECK(strcmp(by_index.ifr_name, "eth0") == 0);
+
+  struct ifreq bad = {0};
+  strcpy(bad.ifr_name, "nope0");
+  CHECK(ioctl(fd, SIOCGIFADDR, &bad) == -1 && errno == ENODEV);
+  bad.ifr_ifindex = 3;
+  CHECK(ioctl(fd, SIOCGIFNAME, &bad) == -1 && errno == ENODEV);
+
+  close(fd);
+  printf("test_named ok\n");
+}
+
+// Read the packets `name` sent from /proc/net/dev.
+static unsigned long tx_packets(const char *name) {
+  FILE *file = fopen("/proc/net/dev", "r");
+  CHECK(file != NULL);
+  char line[256];
+  // The two lines of the header.
+  CHECK(fgets(line, sizeof(line), file) != NULL);
+  CHECK(strncmp(line, "Inter-|", 7) == 0);
+  CHECK(fgets(line, sizeof(line), file) != NULL);
+  long packets = -1;
+  while (fgets(line, sizeof(line), file) != NULL) {
+    char iface[16];
+    unsigned long rx_bytes, rx_packets, tx_bytes, tx;
+    CHECK(sscanf(line, " %15[^:]: %lu %lu %*u %*u %*u %*u %*u %*u %lu %lu",
+                 iface, &rx_bytes, &rx_packets, &tx_bytes, &tx) == 5);
+    if (strcmp(iface, name) == 0)
+      packets = tx;
+  }
+  fclose(file);
+  CHECK(packets >= 0);
+  return packets;
+}
+
+static void test_proc_net_dev(void) {
+  CHECK(tx_packets("lo") == 0);
+  unsigned long before = tx_packets("eth0");
+
+  // Sent out of eth0, after an ARP request for the gateway if need be.
+  int fd = socket(AF_INET, SOCK_DGRAM, 0);
+  CHECK(fd >= 0);
+  struct sockaddr_in addr = {0};
+  addr.sin_family = AF_INET;
+  addr.sin_port = htons(9);
+  addr.sin_addr.s_addr = inet_addr(GATEWAY_ADDR);
+  struct sockaddr_in local = {0};
+  local.sin_family = AF_INET;
+  CHECK(bind(fd, (struct sockaddr *)&local, sizeof(local)) == 0);
+  CHECK(connect(fd, (struct sockaddr *)&addr, sizeof(addr)) == 0);
+  CHECK(write(fd, "x", 1) == 1);
+
+  int sent = 0;
+  for (int i = 0; i < 100 && !sent; i++) {
+    sent = tx_packets("eth0") > before;
+    usleep(10000);
+  }
+  CHECK(sent);
+
+  close(fd);
+  printf("test_proc_net_dev ok\n");
+}
+
+int main(void) {
+  test_ifconf();
+  test_named();
+  test_proc_net_dev();
+  return 0;
+}
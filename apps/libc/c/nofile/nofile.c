@@ -0,0 +1,133 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/resource.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define MANY 5000
+
+// Get `FDSize` from `/proc/self/status`, the slots of the fd table.
+static long fd_size() {
+  FILE *f = fopen("/proc/self/status", "r");
+  if (f == NULL) {
+    return -1;
+  }
+  char line[128];
+  long size = -1;
+  while (fgets(line, sizeof(line), f) != NULL) {
+    if (strncmp(line, "FDSize:", 7) == 0) {
+      size = strtol(line + 7, NULL, 10);
+    }
+  }
+  fclose(f);
+  return size;
+}
+
+static int run_child(int (*f)()) {
+  pid_t pid = fork();
+  if (pid == 0) {
+    _exit(f());
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  return WIFEXITED(status) ? WEXITSTATUS(status) : -1;
+}
+
+static int default_limit() {
+  struct rlimit limit;
+  if (getrlimit(RLIMIT_NOFILE, &limit) != 0 || limit.rlim_cur != 1024) {
+    return 1;
+  }
+  for (;;) {
+    int fd = dup(0);
+    if (fd < 0) {
+      return errno == EMFILE ? 0 : 2;
+    }
+    if (fd >= 1024) {
+      return 3;
+    }
+  }
+}
+
+void test_default_limit() {
+  if (run_child(default_limit) == 0) {
+    puts("test_default_limit ok");
+  }
+}
+
+static int many_fds() {
+  long before = fd_size();
+  if (before <= 0 || before > 1024) {
+    return 1;
+  }
+  struct rlimit limit = {8192, 8192};
+  if (setrlimit(RLIMIT_NOFILE, &limit) != 0) {
+    return 2;
+  }
+  int last = -1;
+  for (int i = 0; i < MANY; i++) {
+    last = dup(0);
+    if (last < 0) {
+      return 3;
+    }
+  }
+  long after = fd_size();
+  // The table grew to the descriptors in use, not to the limit.
+  if (after <= last || after >= 8192) {
+    return 4;
+  }
+  // Lowering the limit below open descriptors only affects new ones.
+  limit.rlim_cur = 100;
+  if (setrlimit(RLIMIT_NOFILE, &limit) != 0) {
+    return 5;
+  }
+  if (dup(0) != -1 || errno != EMFILE || dup2(0, 200) != -1 ||
+      errno != EBADF) {
+    return 6;
+  }
+  if (close(last) != 0 || close(50) != 0 || dup(0) != 50) {
+    return 7;
+  }
+  return 0;
+}
+
+void test_many_fds() {
+  if (run_child(many_fds) == 0) {
+    puts("test_many_fds ok");
+  }
+}
+
+static int raise_hard() {
+  struct rlimit limit = {1 << 21, 1 << 21};
+  // Above nr_open.
+  if (setrlimit(RLIMIT_NOFILE, &limit) != -1 || errno != EPERM) {
+    return 1;
+  }
+  limit.rlim_cur = 4096;
+  limit.rlim_max = 2048;
+  if (setrlimit(RLIMIT_NOFILE, &limit) != -1 || errno != EINVAL) {
+    return 2;
+  }
+  struct rlimit old;
+  if (prlimit(getppid(), RLIMIT_NOFILE, NULL, &old) != 0 ||
+      old.rlim_cur != 1024) {
+    return 3;
+  }
+  return 0;
+}
+
+void test_invalid_limits() {
+  if (run_child(raise_hard) == 0) {
+    puts("test_invalid_limits ok");
+  }
+}
+
+int main() {
+  test_default_limit();
+  test_many_fds();
+  test_invalid_limits();
+  return 0;
+}
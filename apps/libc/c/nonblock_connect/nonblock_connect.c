@@ -0,0 +1,104 @@
+#define _GNU_SOURCE
+#include <arpa/inet.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <netinet/in.h>
+#include <poll.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/socket.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define LISTEN_PORT 5555
+#define REFUSED_PORT 5556
+
+static struct sockaddr_in loopback(int port) {
+  struct sockaddr_in addr = {0};
+  addr.sin_family = AF_INET;
+  addr.sin_port = htons(port);
+  addr.sin_addr.s_addr = htonl(INADDR_LOOPBACK);
+  return addr;
+}
+
+static int so_error(int fd) {
+  int err = -1;
+  socklen_t len = sizeof(err);
+  CHECK(getsockopt(fd, SOL_SOCKET, SO_ERROR, &err, &len) == 0);
+  CHECK(len == sizeof(err));
+  return err;
+}
+
+// Start a non-blocking connect to `port`, and wait for it to finish with
+// ppoll, trying to connect again meanwhile if `again`. Returns the socket.
+static int connect_and_wait(int port, int again) {
+  int fd = socket(AF_INET, SOCK_STREAM | SOCK_NONBLOCK, 0);
+  CHECK(fd >= 0);
+  struct sockaddr_in addr = loopback(port);
+  int ret = connect(fd, (struct sockaddr *)&addr, sizeof(addr));
+  if (ret == -1 && errno == EINPROGRESS) {
+    if (again) {
+      // Refused while in progress, unless the handshake is over already.
+      ret = connect(fd, (struct sockaddr *)&addr, sizeof(addr));
+      CHECK(ret == -1 && (errno == EALREADY || errno == EISCONN));
+    }
+    struct pollfd pfd = {.fd = fd, .events = POLLOUT};
+    struct timespec timeout = {.tv_sec = 5};
+    CHECK(ppoll(&pfd, 1, &timeout, NULL) == 1);
+    CHECK(pfd.revents & POLLOUT);
+  }
+  return fd;
+}
+
+static void test_connect_listening(void) {
+  int listener = socket(AF_INET, SOCK_STREAM, 0);
+  CHECK(listener >= 0);
+  struct sockaddr_in addr = loopback(LISTEN_PORT);
+  CHECK(bind(listener, (struct sockaddr *)&addr, sizeof(addr)) == 0);
+  CHECK(listen(listener, 1) == 0);
+
+  int fd = connect_and_wait(LISTEN_PORT, 1);
+  CHECK(so_error(fd) == 0);
+  errno = 0;
+  CHECK(connect(fd, (struct sockaddr *)&addr, sizeof(addr)) == -1 &&
+        errno == EISCONN);
+  int peer = accept(listener, NULL, NULL);
+  CHECK(peer >= 0);
+  close(peer);
+  close(fd);
+  close(listener);
+  printf("test_connect_listening ok\n");
+}
+
+static void test_connect_refused(void) {
+  int fd = connect_and_wait(REFUSED_PORT, 0);
+  // The error is reported once, then cleared.
+  CHECK(so_error(fd) == ECONNREFUSED);
+  CHECK(so_error(fd) == 0);
+  close(fd);
+
+  int type;
+  socklen_t len = sizeof(type);
+  fd = socket(AF_INET, SOCK_DGRAM, 0);
+  CHECK(getsockopt(fd, SOL_SOCKET, SO_TYPE, &type, &len) == 0);
+  CHECK(type == SOCK_DGRAM);
+  errno = 0;
+  CHECK(getsockopt(fd, SOL_SOCKET, 0x7fff, &type, &len) == -1 &&
+        errno == ENOPROTOOPT);
+  close(fd);
+  printf("test_connect_refused ok\n");
+}
+
+int main(void) {
+  test_connect_listening();
+  test_connect_refused();
+  return 0;
+}
@@ -0,0 +1,152 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define PATH "/ofd_lock_test"
+
+enum { UNLOCKED, LOCKED_BY_PARENT, LOCKED_BY_FILE, LOCKED_OTHER };
+
+static int lock(int fd, int cmd, short type, off_t start, off_t len) {
+  struct flock fl = {
+      .l_type = type,
+      .l_whence = SEEK_SET,
+      .l_start = start,
+      .l_len = len,
+  };
+  return fcntl(fd, cmd, &fl);
+}
+
+// How another process sees the whole file, through F_GETLK.
+static int probe(void) {
+  pid_t parent = getpid();
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    int fd = open(PATH, O_RDWR);
+    struct flock fl = {.l_type = F_WRLCK, .l_whence = SEEK_SET};
+    if (fd < 0 || fcntl(fd, F_GETLK, &fl) != 0)
+      _exit(LOCKED_OTHER);
+    if (fl.l_type == F_UNLCK)
+      _exit(UNLOCKED);
+    if (fl.l_pid == parent)
+      _exit(LOCKED_BY_PARENT);
+    _exit(fl.l_pid == -1 ? LOCKED_BY_FILE : LOCKED_OTHER);
+  }
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status));
+  return WEXITSTATUS(status);
+}
+
+// Closing any descriptor of the file releases the classic locks of the
+// process, even through another descriptor.
+void test_classic_released() {
+  int a = open(PATH, O_RDWR | O_CREAT | O_TRUNC, 0644);
+  int b = open(PATH, O_RDWR);
+  CHECK(a >= 0 && b >= 0);
+  CHECK(lock(a, F_SETLK, F_WRLCK, 0, 0) == 0);
+  CHECK(probe() == LOCKED_BY_PARENT);
+  CHECK(close(b) == 0);
+  CHECK(probe() == UNLOCKED);
+  close(a);
+  unlink(PATH);
+  puts("test_classic_released ok");
+}
+
+// An open file description lock survives closing other descriptors, and
+// is released with the last descriptor of the description.
+void test_ofd_survives() {
+  int a = open(PATH, O_RDWR | O_CREAT | O_TRUNC, 0644);
+  int b = open(PATH, O_RDWR);
+  CHECK(a >= 0 && b >= 0);
+  CHECK(lock(a, F_OFD_SETLK, F_WRLCK, 0, 0) == 0);
+  CHECK(probe() == LOCKED_BY_FILE);
+  CHECK(close(b) == 0);
+  CHECK(probe() == LOCKED_BY_FILE);
+  int c = dup(a);
+  CHECK(c >= 0);
+  CHECK(close(a) == 0);
+  CHECK(probe() == LOCKED_BY_FILE);
+  CHECK(close(c) == 0);
+  CHECK(probe() == UNLOCKED);
+  unlink(PATH);
+  puts("test_ofd_survives ok");
+}
+
+// Open file description locks conflict between descriptions, and with the
+// classic locks of the same process.
+void test_conflicts() {
+  int a = open(PATH, O_RDWR | O_CREAT | O_TRUNC, 0644);
+  int b = open(PATH, O_RDWR);
+  CHECK(a >= 0 && b >= 0);
+  CHECK(lock(a, F_OFD_SETLK, F_WRLCK, 0, 10) == 0);
+  CHECK(lock(b, F_OFD_SETLK, F_WRLCK, 5, 10) == -1 && errno == EAGAIN);
+  CHECK(lock(b, F_OFD_SETLK, F_RDLCK, 10, 10) == 0);
+  CHECK(lock(b, F_SETLK, F_WRLCK, 0, 5) == -1 && errno == EAGAIN);
+
+  struct flock fl = {.l_type = F_RDLCK, .l_whence = SEEK_SET, .l_len = 1};
+  CHECK(fcntl(b, F_OFD_GETLK, &fl) == 0);
+  CHECK(fl.l_type == F_WRLCK);
+  CHECK(fl.l_start == 0 && fl.l_len == 10);
+  CHECK(fl.l_pid == -1);
+
+  struct flock with_pid = {.l_type = F_WRLCK, .l_whence = SEEK_SET, .l_pid = 1};
+  CHECK(fcntl(a, F_OFD_SETLK, &with_pid) == -1 && errno == EINVAL);
+
+  CHECK(lock(a, F_OFD_SETLK, F_UNLCK, 0, 0) == 0);
+  CHECK(lock(b, F_OFD_SETLK, F_WRLCK, 0, 20) == 0);
+  close(a);
+  close(b);
+  unlink(PATH);
+  puts("test_conflicts ok");
+}
+
+// F_SETLKW waits for the lock of another process to be released.
+void test_wait() {
+  int fd = open(PATH, O_RDWR | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  int pipefd[2];
+  CHECK(pipe(pipefd) == 0);
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    int own = open(PATH, O_RDWR);
+    if (own < 0 || lock(own, F_SETLK, F_WRLCK, 0, 0) != 0)
+      _exit(1);
+    write(pipefd[1], "x", 1);
+    usleep(200000);
+    _exit(0);
+  }
+  char c;
+  CHECK(read(pipefd[0], &c, 1) == 1);
+  CHECK(lock(fd, F_SETLK, F_WRLCK, 0, 0) == -1 && errno == EAGAIN);
+  CHECK(lock(fd, F_SETLKW, F_WRLCK, 0, 0) == 0);
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  close(pipefd[0]);
+  close(pipefd[1]);
+  close(fd);
+  unlink(PATH);
+  puts("test_wait ok");
+}
+
+int main() {
+  test_classic_released();
+  test_ofd_survives();
+  test_conflicts();
+  test_wait();
+  return 0;
+}
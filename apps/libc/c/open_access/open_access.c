@@ -0,0 +1,74 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define FILE_PATH "/tmp_open_access"
+
+static void test_access_mode(void) {
+  char buf[8];
+  int fd = open(FILE_PATH, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  CHECK((fcntl(fd, F_GETFL) & O_ACCMODE) == O_WRONLY);
+  CHECK(write(fd, "abc", 3) == 3);
+  errno = 0;
+  CHECK(read(fd, buf, sizeof(buf)) == -1 && errno == EBADF);
+  errno = 0;
+  CHECK(pread(fd, buf, sizeof(buf), 0) == -1 && errno == EBADF);
+  close(fd);
+
+  fd = open(FILE_PATH, O_RDONLY);
+  CHECK(fd >= 0);
+  CHECK((fcntl(fd, F_GETFL) & O_ACCMODE) == O_RDONLY);
+  errno = 0;
+  CHECK(write(fd, "x", 1) == -1 && errno == EBADF);
+  errno = 0;
+  CHECK(pwrite(fd, "x", 1, 0) == -1 && errno == EBADF);
+  CHECK(read(fd, buf, sizeof(buf)) == 3 && memcmp(buf, "abc", 3) == 0);
+  close(fd);
+
+  fd = open(FILE_PATH, O_RDWR | O_APPEND);
+  CHECK(fd >= 0);
+  int flags = fcntl(fd, F_GETFL);
+  CHECK((flags & O_ACCMODE) == O_RDWR && (flags & O_APPEND) != 0);
+  close(fd);
+  unlink(FILE_PATH);
+  printf("test_access_mode ok\n");
+}
+
+static void test_other_files(void) {
+  int fds[2];
+  CHECK(pipe2(fds, O_NONBLOCK) == 0);
+  CHECK((fcntl(fds[0], F_GETFL) & (O_ACCMODE | O_NONBLOCK)) ==
+        (O_RDONLY | O_NONBLOCK));
+  CHECK((fcntl(fds[1], F_GETFL) & O_ACCMODE) == O_WRONLY);
+  close(fds[0]);
+  close(fds[1]);
+
+  errno = 0;
+  CHECK(open("/", O_RDWR) == -1 && errno == EISDIR);
+  errno = 0;
+  CHECK(open("/", O_WRONLY) == -1 && errno == EISDIR);
+  int fd = open("/", O_RDONLY | O_DIRECTORY);
+  CHECK(fd >= 0);
+  CHECK((fcntl(fd, F_GETFL) & O_ACCMODE) == O_RDONLY);
+  close(fd);
+  printf("test_other_files ok\n");
+}
+
+int main(void) {
+  test_access_mode();
+  test_other_files();
+  return 0;
+}
@@ -0,0 +1,105 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define EXECS 200
+
+static void write_file(const char *path, const char *content) {
+  int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  CHECK(write(fd, content, strlen(content)) == (ssize_t)strlen(content));
+  close(fd);
+}
+
+static void check_file(const char *path, const char *content) {
+  char buf[32] = {0};
+  int fd = open(path, O_RDONLY);
+  CHECK(fd >= 0);
+  CHECK(read(fd, buf, sizeof(buf) - 1) == (ssize_t)strlen(content));
+  CHECK(strcmp(buf, content) == 0);
+  close(fd);
+}
+
+// Resolve the same paths before and after changing the tree.
+static void test_mutations(void) {
+  struct stat st;
+  CHECK(mkdir("pc_dir", 0755) == 0);
+  write_file("pc_dir/a", "one");
+  check_file("pc_dir/./a", "one");
+  check_file("pc_dir/../pc_dir/a", "one");
+
+  CHECK(unlink("pc_dir/a") == 0);
+  errno = 0;
+  CHECK(open("pc_dir/../pc_dir/a", O_RDONLY) == -1 && errno == ENOENT);
+  write_file("pc_dir/a", "two");
+  check_file("pc_dir/./a", "two");
+  check_file("pc_dir/../pc_dir/a", "two");
+
+  // A directory in place of a file.
+  CHECK(unlink("pc_dir/a") == 0);
+  CHECK(mkdir("pc_dir/a", 0755) == 0);
+  CHECK(stat("pc_dir/./a", &st) == 0 && S_ISDIR(st.st_mode));
+  CHECK(rmdir("pc_dir/a") == 0);
+  errno = 0;
+  CHECK(stat("pc_dir/./a", &st) == -1 && errno == ENOENT);
+
+  // The same relative path from another working directory.
+  write_file("pc_dir/b", "three");
+  errno = 0;
+  CHECK(stat("b", &st) == -1 && errno == ENOENT);
+  CHECK(chdir("pc_dir") == 0);
+  check_file("b", "three");
+  CHECK(chdir("..") == 0);
+  errno = 0;
+  CHECK(stat("b", &st) == -1 && errno == ENOENT);
+
+  CHECK(unlink("pc_dir/b") == 0);
+  CHECK(rmdir("pc_dir") == 0);
+  printf("test_mutations ok\n");
+}
+
+// Run the binary again and again, which resolves the same paths every time.
+static void test_exec_many(const char *self) {
+  struct timespec start, end;
+  clock_gettime(CLOCK_MONOTONIC, &start);
+  for (int i = 0; i < EXECS; i++) {
+    pid_t pid = fork();
+    CHECK(pid >= 0);
+    if (pid == 0) {
+      execl(self, self, "child", (char *)NULL);
+      _exit(127);
+    }
+    int status;
+    CHECK(waitpid(pid, &status, 0) == pid);
+    CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  }
+  clock_gettime(CLOCK_MONOTONIC, &end);
+  double secs =
+      (end.tv_sec - start.tv_sec) + (end.tv_nsec - start.tv_nsec) / 1e9;
+  printf("path_cache: %d execs in %.3fs\n", EXECS, secs);
+  printf("test_exec_many ok\n");
+}
+
+int main(int argc, char **argv) {
+  if (argc > 1 && strcmp(argv[1], "child") == 0) {
+    return 0;
+  }
+  test_mutations();
+  test_exec_many(argv[0]);
+  return 0;
+}
@@ -0,0 +1,175 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/prctl.h>
+#include <sys/stat.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define PR_SET_PATH_SANDBOX 0x53595301
+#define PATH_READ 1
+#define PATH_WRITE 2
+#define PATH_CREATE 4
+#define PATH_EXEC 8
+
+struct path_rule {
+  const char *prefix;
+  unsigned int access;
+};
+
+#define OUTSIDE "/etc/path_sandbox.tmp"
+#define INSIDE "/tmp/path_sandbox.tmp"
+
+#define EACCES_OF(call) ((call) == -1 && errno == EACCES)
+
+static char self_path[256];
+
+static int restrict_to(const char *prefix, unsigned int access) {
+  struct path_rule rule = {prefix, access};
+  return prctl(PR_SET_PATH_SANDBOX, &rule, 1, 0, 0);
+}
+
+// Run `func` in a child and check it exits with 0.
+static void run_child(void (*func)(void)) {
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    func();
+    _exit(0);
+  }
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+// What a process confined to /tmp may and may not do.
+static void check_confined(void) {
+  int fd = open(INSIDE, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  CHECK(write(fd, "x", 1) == 1);
+  CHECK(close(fd) == 0);
+  CHECK(mkdir("/tmp/path_sandbox.d", 0755) == 0);
+  CHECK(rmdir("/tmp/path_sandbox.d") == 0);
+  CHECK(unlink(INSIDE) == 0);
+
+  struct stat st;
+  CHECK(EACCES_OF(open(OUTSIDE, O_RDONLY)));
+  CHECK(EACCES_OF(stat(OUTSIDE, &st)));
+  CHECK(EACCES_OF(open("/tmp/../etc/path_sandbox.tmp", O_RDONLY)));
+  CHECK(EACCES_OF(open("/etc/path_sandbox.new", O_WRONLY | O_CREAT, 0644)));
+  CHECK(EACCES_OF(mkdir("/path_sandbox.d", 0755)));
+  CHECK(EACCES_OF(chdir("/")));
+  CHECK(chdir("/tmp") == 0);
+  CHECK(EACCES_OF(open("../etc/path_sandbox.tmp", O_RDONLY)));
+}
+
+// A child confined to /tmp can make files there only, even through a
+// directory it opened before, cannot run programs, nor widen its sandbox,
+// and its children inherit it.
+static void child_confined(void) {
+  int etc = open("/etc", O_RDONLY | O_DIRECTORY);
+  CHECK(etc >= 0);
+  CHECK(restrict_to("/tmp", PATH_READ | PATH_WRITE | PATH_CREATE) == 0);
+  check_confined();
+  CHECK(EACCES_OF(openat(etc, "path_sandbox.tmp", O_RDONLY)));
+
+  char *args[] = {self_path, "child", NULL};
+  CHECK(EACCES_OF(execv(self_path, args)));
+
+  CHECK(restrict_to("/", PATH_READ | PATH_WRITE | PATH_CREATE | PATH_EXEC) ==
+        0);
+  CHECK(EACCES_OF(open(OUTSIDE, O_RDONLY)));
+
+  run_child(check_confined);
+}
+
+// Another ruleset can take away what the first one grants.
+static void child_layers(void) {
+  CHECK(restrict_to("/tmp", PATH_READ | PATH_WRITE | PATH_CREATE) == 0);
+  int fd = open(INSIDE, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  CHECK(close(fd) == 0);
+  CHECK(restrict_to("/tmp", PATH_READ) == 0);
+  CHECK(EACCES_OF(open("/tmp/path_sandbox.new", O_WRONLY | O_CREAT, 0644)));
+  CHECK(EACCES_OF(open(INSIDE, O_WRONLY)));
+  CHECK(EACCES_OF(unlink(INSIDE)));
+  fd = open(INSIDE, O_RDONLY);
+  CHECK(fd >= 0);
+  CHECK(close(fd) == 0);
+}
+
+void test_confined() {
+  run_child(child_confined);
+  puts("test_confined ok");
+}
+
+void test_layers() {
+  run_child(child_layers);
+  CHECK(unlink(INSIDE) == 0);
+  puts("test_layers ok");
+}
+
+// Invalid rules are refused, and leave the process unconfined.
+void test_invalid() {
+  CHECK(restrict_to("tmp", PATH_READ) == -1 && errno == EINVAL);
+  CHECK(restrict_to("/tmp", 0) == -1 && errno == EINVAL);
+  CHECK(restrict_to("/tmp", PATH_EXEC << 1) == -1 && errno == EINVAL);
+  static struct path_rule rules[65];
+  for (int i = 0; i < 65; i++) {
+    rules[i].prefix = "/tmp";
+    rules[i].access = PATH_READ;
+  }
+  CHECK(prctl(PR_SET_PATH_SANDBOX, rules, 65, 0, 0) == -1 && errno == E2BIG);
+  CHECK(prctl(PR_SET_PATH_SANDBOX, NULL, 1, 0, 0) == -1 && errno == EFAULT);
+  int fd = open(OUTSIDE, O_RDONLY);
+  CHECK(fd >= 0);
+  CHECK(close(fd) == 0);
+  puts("test_invalid ok");
+}
+
+// The denials are audited.
+void test_audit() {
+  FILE *f = fopen("/proc/starry/audit", "r");
+  CHECK(f != NULL);
+  char line[512];
+  int found = 0;
+  while (fgets(line, sizeof(line), f)) {
+    if (strstr(line, "type=SANDBOX") && strstr(line, "access=r---") &&
+        strstr(line, "path=\"" OUTSIDE "\"")) {
+      found = 1;
+    }
+  }
+  fclose(f);
+  CHECK(found);
+  puts("test_audit ok");
+}
+
+int main() {
+  ssize_t len = readlink("/proc/self/exe", self_path, sizeof(self_path) - 1);
+  CHECK(len > 0);
+  self_path[len] = '\0';
+  CHECK(mkdir("/etc", 0755) == 0 || errno == EEXIST);
+  CHECK(mkdir("/tmp", 0755) == 0 || errno == EEXIST);
+  int fd = open(OUTSIDE, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  CHECK(close(fd) == 0);
+
+  test_confined();
+  test_layers();
+  test_invalid();
+  test_audit();
+
+  CHECK(unlink(OUTSIDE) == 0);
+  return 0;
+}
@@ -0,0 +1,210 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <linux/futex.h>
+#include <pthread.h>
+#include <stdatomic.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/syscall.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define ROUNDS 1000
+
+static long futex(atomic_uint *word, int op, const struct timespec *timeout) {
+  return syscall(SYS_futex, word, op, 0, timeout, NULL, 0);
+}
+
+static unsigned gettid_(void) { return syscall(SYS_gettid); }
+
+static void sleep_ms(long ms) {
+  struct timespec ts = {ms / 1000, ms % 1000 * 1000000};
+  nanosleep(&ts, NULL);
+}
+
+static pthread_mutex_t alternating;
+static int turn, rounds_done;
+
+// Take the mutex in turns with the other thread, `ROUNDS` times each.
+static void *alternate(void *arg) {
+  int me = (intptr_t)arg;
+  int mine = 0;
+  while (mine < ROUNDS) {
+    CHECK(pthread_mutex_lock(&alternating) == 0);
+    if (turn == me) {
+      turn = !me;
+      mine++;
+      rounds_done++;
+    }
+    CHECK(pthread_mutex_unlock(&alternating) == 0);
+  }
+  return NULL;
+}
+
+// Two threads alternate holding a priority inheritance mutex.
+void test_pi_alternate() {
+  pthread_mutexattr_t attr;
+  CHECK(pthread_mutexattr_init(&attr) == 0);
+  CHECK(pthread_mutexattr_setprotocol(&attr, PTHREAD_PRIO_INHERIT) == 0);
+  CHECK(pthread_mutex_init(&alternating, &attr) == 0);
+  pthread_t threads[2];
+  for (intptr_t i = 0; i < 2; i++)
+    CHECK(pthread_create(&threads[i], NULL, alternate, (void *)i) == 0);
+  for (int i = 0; i < 2; i++)
+    CHECK(pthread_join(threads[i], NULL) == 0);
+  CHECK(rounds_done == 2 * ROUNDS);
+  CHECK(pthread_mutex_destroy(&alternating) == 0);
+  puts("test_pi_alternate ok");
+}
+
+static atomic_uint word;
+static atomic_uint waiter_tid;
+
+// Wait in the kernel for `word`, then check it was handed over, and
+// release it.
+static void *lock_word(void *arg) {
+  (void)arg;
+  atomic_store(&waiter_tid, gettid_());
+  CHECK(futex(&word, FUTEX_LOCK_PI_PRIVATE, NULL) == 0);
+  CHECK((atomic_load(&word) & FUTEX_TID_MASK) == gettid_());
+  CHECK(futex(&word, FUTEX_UNLOCK_PI_PRIVATE, NULL) == 0);
+  return NULL;
+}
+
+// Try to take `word`, which the main thread holds.
+static void *trylock_word(void *arg) {
+  (void)arg;
+  CHECK(futex(&word, FUTEX_TRYLOCK_PI_PRIVATE, NULL) == -1 &&
+        errno == EAGAIN);
+  CHECK(futex(&word, FUTEX_UNLOCK_PI_PRIVATE, NULL) == -1 && errno == EPERM);
+  struct timespec deadline;
+  clock_gettime(CLOCK_REALTIME, &deadline);
+  deadline.tv_nsec += 20 * 1000000;
+  if (deadline.tv_nsec >= 1000000000) {
+    deadline.tv_sec++;
+    deadline.tv_nsec -= 1000000000;
+  }
+  CHECK(futex(&word, FUTEX_LOCK_PI_PRIVATE, &deadline) == -1 &&
+        errno == ETIMEDOUT);
+  return NULL;
+}
+
+// The kernel keeps the word as the ABI has it: the tid of the owner, with
+// `FUTEX_WAITERS` once a thread waits, and hands it to the waiter.
+void test_pi_word() {
+  unsigned tid = gettid_();
+  CHECK(futex(&word, FUTEX_LOCK_PI_PRIVATE, NULL) == 0);
+  CHECK(atomic_load(&word) == tid);
+  CHECK(futex(&word, FUTEX_LOCK_PI_PRIVATE, NULL) == -1 && errno == EDEADLK);
+
+  pthread_t thread;
+  CHECK(pthread_create(&thread, NULL, trylock_word, NULL) == 0);
+  CHECK(pthread_join(thread, NULL) == 0);
+
+  CHECK(pthread_create(&thread, NULL, lock_word, NULL) == 0);
+  while (!(atomic_load(&word) & FUTEX_WAITERS))
+    sleep_ms(1);
+  CHECK((atomic_load(&word) & FUTEX_TID_MASK) == tid);
+  CHECK(futex(&word, FUTEX_UNLOCK_PI_PRIVATE, NULL) == 0);
+  CHECK(pthread_join(thread, NULL) == 0);
+  CHECK(atomic_load(&word) == 0);
+  puts("test_pi_word ok");
+}
+
+static pthread_mutex_t robust;
+static atomic_int robust_taken;
+
+static void *die_holding(void *arg) {
+  (void)arg;
+  CHECK(pthread_mutex_lock(&robust) == 0);
+  atomic_store(&robust_taken, 1);
+  sleep_ms(20);
+  pthread_exit(NULL);
+}
+
+// A robust PI mutex whose owner exits goes to the thread waiting for it,
+// which is told the owner died.
+void test_owner_dies() {
+  pthread_mutexattr_t attr;
+  CHECK(pthread_mutexattr_init(&attr) == 0);
+  CHECK(pthread_mutexattr_setprotocol(&attr, PTHREAD_PRIO_INHERIT) == 0);
+  CHECK(pthread_mutexattr_setrobust(&attr, PTHREAD_MUTEX_ROBUST) == 0);
+  CHECK(pthread_mutex_init(&robust, &attr) == 0);
+  pthread_t thread;
+  CHECK(pthread_create(&thread, NULL, die_holding, NULL) == 0);
+  while (!atomic_load(&robust_taken))
+    sleep_ms(1);
+  CHECK(pthread_mutex_lock(&robust) == EOWNERDEAD);
+  CHECK(pthread_mutex_consistent(&robust) == 0);
+  CHECK(pthread_mutex_unlock(&robust) == 0);
+  CHECK(pthread_join(thread, NULL) == 0);
+  CHECK(pthread_mutex_lock(&robust) == 0);
+  CHECK(pthread_mutex_unlock(&robust) == 0);
+  puts("test_owner_dies ok");
+}
+
+// A robust list of one entry, a PI futex, as the kernel walks it.
+struct robust_entry {
+  struct robust_list list;
+  atomic_uint lock;
+};
+
+static struct robust_entry entry;
+static struct robust_list_head head;
+
+// Take the futex of `entry` with its robust list set, then exit without
+// leaving it or cleaning up the list, as if killed.
+static void *die_raw(void *arg) {
+  (void)arg;
+  head.list.next = (struct robust_list *)((uintptr_t)&entry.list | 1);
+  entry.list.next = &head.list;
+  head.futex_offset = offsetof(struct robust_entry, lock);
+  head.list_op_pending = NULL;
+  CHECK(syscall(SYS_set_robust_list, &head, sizeof(head)) == 0);
+  CHECK(futex(&entry.lock, FUTEX_LOCK_PI_PRIVATE, NULL) == 0);
+  while (!(atomic_load(&entry.lock) & FUTEX_WAITERS))
+    sleep_ms(1);
+  syscall(SYS_exit, 0);
+  return NULL;
+}
+
+// The kernel walks the robust list of an exiting thread, marking the PI
+// futexes it held with `FUTEX_OWNER_DIED` and handing them to a waiter.
+void test_robust_list() {
+  struct robust_list_head *got;
+  size_t len;
+  CHECK(syscall(SYS_get_robust_list, 0, &got, &len) == 0);
+  CHECK(len == sizeof(struct robust_list_head));
+  CHECK(syscall(SYS_set_robust_list, &head, sizeof(head) + 1) == -1 &&
+        errno == EINVAL);
+
+  pthread_t thread;
+  CHECK(pthread_create(&thread, NULL, die_raw, NULL) == 0);
+  while (!(atomic_load(&entry.lock) & FUTEX_TID_MASK))
+    sleep_ms(1);
+  CHECK(futex(&entry.lock, FUTEX_LOCK_PI_PRIVATE, NULL) == 0);
+  unsigned val = atomic_load(&entry.lock);
+  CHECK((val & FUTEX_TID_MASK) == gettid_());
+  CHECK(val & FUTEX_OWNER_DIED);
+  CHECK(futex(&entry.lock, FUTEX_UNLOCK_PI_PRIVATE, NULL) == 0);
+  CHECK(atomic_load(&entry.lock) == 0);
+  puts("test_robust_list ok");
+}
+
+int main() {
+  test_pi_alternate();
+  test_pi_word();
+  test_owner_dies();
+  test_robust_list();
+  return 0;
+}
@@ -0,0 +1,103 @@
+#define _GNU_SOURCE
+#include <dirent.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+// The fork/exits to churn through. The default keeps the test short on
+// QEMU; pass 100000 to measure the listing of /proc after many more.
+static long churn = 512;
+
+static double now(void) {
+  struct timespec ts;
+  clock_gettime(CLOCK_MONOTONIC, &ts);
+  return ts.tv_sec + ts.tv_nsec / 1e9;
+}
+
+struct table {
+  long live, dead, scrubbed;
+};
+
+// The line of the table `name` in /proc/starry/pressure.
+static struct table table_stats(const char *name) {
+  FILE *f = fopen("/proc/starry/pressure", "r");
+  CHECK(f != NULL);
+  char line[256], key[64];
+  struct table t;
+  int found = 0;
+  while (fgets(line, sizeof(line), f)) {
+    if (sscanf(line, "%63s %ld %ld %ld", key, &t.live, &t.dead, &t.scrubbed) ==
+            4 &&
+        strcmp(key, name) == 0)
+      found = 1;
+  }
+  fclose(f);
+  CHECK(found);
+  return t;
+}
+
+// Seconds taken to list the processes in /proc, a few times over.
+static double list_proc(void) {
+  double start = now();
+  for (int i = 0; i < 16; i++) {
+    DIR *dir = opendir("/proc");
+    CHECK(dir != NULL);
+    int pids = 0;
+    struct dirent *ent;
+    while ((ent = readdir(dir)))
+      pids += ent->d_name[0] >= '1' && ent->d_name[0] <= '9';
+    closedir(dir);
+    CHECK(pids >= 1);
+  }
+  return (now() - start) / 16;
+}
+
+// Children which come and go leave few dead entries behind, each gets a
+// PID of its own, and listing /proc does not slow down with them.
+void test_churn() {
+  double before = list_proc();
+  struct table start = table_stats("processes");
+  pid_t last = getpid();
+  for (long i = 0; i < churn; i++) {
+    pid_t pid = fork();
+    CHECK(pid >= 0);
+    if (pid == 0)
+      _exit(0);
+    CHECK(pid != last);
+    int status;
+    CHECK(waitpid(pid, &status, 0) == pid);
+    CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    last = pid;
+  }
+  double after = list_proc();
+  struct table end = table_stats("processes");
+  struct table threads = table_stats("threads");
+  // Scrubbed at least every 64 inserts, or as many as were live.
+  CHECK(end.dead <= 64 + end.live);
+  CHECK(threads.dead <= 64 + threads.live);
+  CHECK(end.scrubbed > start.scrubbed);
+  printf("pid_churn: %ld fork/exits, /proc listed in %.3f ms before and "
+         "%.3f ms after, %ld dead entries left\n",
+         churn, before * 1e3, after * 1e3, end.dead);
+  puts("test_churn ok");
+}
+
+int main(int argc, char **argv) {
+  if (argc > 1) {
+    churn = atol(argv[1]);
+    CHECK(churn >= 128);
+  }
+  test_churn();
+  return 0;
+}
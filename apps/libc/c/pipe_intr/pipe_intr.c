@@ -0,0 +1,93 @@
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define TOTAL (8 << 20)
+#define FIRST 100000
+
+static void handler(int signo) {}
+
+static void catch_sigusr1() {
+  struct sigaction sa = {0};
+  sa.sa_handler = handler;
+  sigaction(SIGUSR1, &sa, NULL);
+}
+
+// Read from `fd` until EOF, returning the number of bytes.
+static long drain(int fd, long limit) {
+  static char buf[65536];
+  long total = 0;
+  while (total < limit) {
+    long want = limit - total < (long)sizeof(buf) ? limit - total : sizeof(buf);
+    long n = read(fd, buf, want);
+    if (n <= 0) {
+      break;
+    }
+    total += n;
+  }
+  return total;
+}
+
+// A signal interrupting a large pipe write makes it return the bytes written
+// so far, which are exactly the bytes the reader gets.
+void test_interrupted_write() {
+  int data[2], result[2];
+  pipe(data);
+  pipe(result);
+  catch_sigusr1();
+  pid_t parent = getpid();
+  pid_t pid = fork();
+  if (pid == 0) {
+    close(data[1]);
+    long total = drain(data[0], FIRST);
+    kill(parent, SIGUSR1);
+    // Let the writer fill the pipe and notice the signal.
+    usleep(100000);
+    total += drain(data[0], TOTAL);
+    write(result[1], &total, sizeof(total));
+    _exit(0);
+  }
+  close(data[0]);
+  char *buf = malloc(TOTAL);
+  long written = write(data[1], buf, TOTAL);
+  close(data[1]);
+  long total = -1;
+  read(result[0], &total, sizeof(total));
+  waitpid(pid, NULL, 0);
+  if (written > FIRST && written < TOTAL && total == written) {
+    puts("test_interrupted_write ok");
+  }
+  free(buf);
+  close(result[0]);
+  close(result[1]);
+}
+
+// A read interrupted before any data comes fails with EINTR.
+void test_interrupted_read() {
+  int fds[2];
+  pipe(fds);
+  catch_sigusr1();
+  pid_t parent = getpid();
+  pid_t pid = fork();
+  if (pid == 0) {
+    usleep(50000);
+    kill(parent, SIGUSR1);
+    _exit(0);
+  }
+  char c;
+  if (read(fds[0], &c, 1) == -1 && errno == EINTR) {
+    puts("test_interrupted_read ok");
+  }
+  waitpid(pid, NULL, 0);
+  close(fds[0]);
+  close(fds[1]);
+}
+
+int main() {
+  test_interrupted_write();
+  test_interrupted_read();
+  return 0;
+}
@@ -0,0 +1,149 @@
+#define _GNU_SOURCE
+#include <arpa/inet.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <netinet/in.h>
+#include <poll.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define REFUSED_PORT 5557
+
+// Poll `fd` for `events` without waiting, and return its revents.
+static short poll_now(int fd, short events) {
+  struct pollfd pfd = {.fd = fd, .events = events};
+  CHECK(poll(&pfd, 1, 0) >= 0);
+  return pfd.revents;
+}
+
+void test_pipe_read_end() {
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  CHECK(poll_now(fds[0], POLLIN) == 0);
+  // Data, then the writer gone: both.
+  CHECK(write(fds[1], "x", 1) == 1);
+  close(fds[1]);
+  CHECK(poll_now(fds[0], POLLIN) == (POLLIN | POLLHUP));
+  char c;
+  CHECK(read(fds[0], &c, 1) == 1);
+  // No data left: only the hangup, reported even if not asked for.
+  CHECK(poll_now(fds[0], POLLIN) == POLLHUP);
+  CHECK(poll_now(fds[0], 0) == POLLHUP);
+  CHECK(read(fds[0], &c, 1) == 0);
+  close(fds[0]);
+  puts("test_pipe_read_end ok");
+}
+
+void test_pipe_write_end() {
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  CHECK(poll_now(fds[1], POLLOUT) == POLLOUT);
+  close(fds[0]);
+  CHECK(poll_now(fds[1], POLLOUT) == (POLLOUT | POLLERR));
+  CHECK(poll_now(fds[1], 0) == POLLERR);
+  signal(SIGPIPE, SIG_IGN);
+  CHECK(write(fds[1], "x", 1) == -1 && errno == EPIPE);
+  signal(SIGPIPE, SIG_DFL);
+  close(fds[1]);
+  puts("test_pipe_write_end ok");
+}
+
+// Like a shell `while read` loop: wait with ppoll and read until the end,
+// which must come once the writer exits.
+void test_read_until_hup() {
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    close(fds[0]);
+    for (int i = 0; i < 3; i++) {
+      CHECK(write(fds[1], "line\n", 5) == 5);
+      usleep(10000);
+    }
+    _exit(0);
+  }
+  close(fds[1]);
+  int total = 0, polls = 0;
+  for (;;) {
+    struct pollfd pfd = {.fd = fds[0], .events = POLLIN};
+    struct timespec timeout = {.tv_sec = 5};
+    CHECK(ppoll(&pfd, 1, &timeout, NULL) == 1);
+    CHECK(++polls < 100);
+    char buf[16];
+    ssize_t n = read(fds[0], buf, sizeof(buf));
+    CHECK(n >= 0);
+    if (n == 0) {
+      CHECK(pfd.revents & POLLHUP);
+      break;
+    }
+    total += n;
+  }
+  CHECK(total == 15);
+  CHECK(waitpid(pid, NULL, 0) == pid);
+  close(fds[0]);
+  puts("test_read_until_hup ok");
+}
+
+void test_unix_hup() {
+  int sv[2];
+  CHECK(socketpair(AF_UNIX, SOCK_STREAM, 0, sv) == 0);
+  CHECK(poll_now(sv[0], POLLIN) == 0);
+  CHECK(write(sv[1], "x", 1) == 1);
+  close(sv[1]);
+  CHECK(poll_now(sv[0], POLLIN) & POLLHUP);
+  char c;
+  CHECK(read(sv[0], &c, 1) == 1);
+  CHECK(read(sv[0], &c, 1) == 0);
+  CHECK(poll_now(sv[0], 0) == POLLHUP);
+  close(sv[0]);
+  puts("test_unix_hup ok");
+}
+
+void test_tcp_refused() {
+  int fd = socket(AF_INET, SOCK_STREAM | SOCK_NONBLOCK, 0);
+  CHECK(fd >= 0);
+  struct sockaddr_in addr = {0};
+  addr.sin_family = AF_INET;
+  addr.sin_port = htons(REFUSED_PORT);
+  addr.sin_addr.s_addr = htonl(INADDR_LOOPBACK);
+  int ret = connect(fd, (struct sockaddr *)&addr, sizeof(addr));
+  CHECK(ret == -1 && (errno == EINPROGRESS || errno == ECONNREFUSED));
+  if (errno == EINPROGRESS) {
+    struct pollfd pfd = {.fd = fd, .events = POLLOUT};
+    struct timespec timeout = {.tv_sec = 5};
+    CHECK(ppoll(&pfd, 1, &timeout, NULL) == 1);
+    CHECK((pfd.revents & (POLLERR | POLLHUP)) == (POLLERR | POLLHUP));
+    int err = 0;
+    socklen_t len = sizeof(err);
+    CHECK(getsockopt(fd, SOL_SOCKET, SO_ERROR, &err, &len) == 0);
+    CHECK(err == ECONNREFUSED);
+    // The error is taken, the hangup stays.
+    CHECK((poll_now(fd, 0) & (POLLERR | POLLHUP)) == POLLHUP);
+  }
+  close(fd);
+  puts("test_tcp_refused ok");
+}
+
+int main() {
+  test_pipe_read_end();
+  test_pipe_write_end();
+  test_read_until_hup();
+  test_unix_hup();
+  test_tcp_refused();
+  return 0;
+}
@@ -0,0 +1,121 @@
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define PRESSURE "/proc/starry/pressure"
+
+// The count named `name` in /proc/starry/pressure.
+static long count(const char *name) {
+  FILE *f = fopen(PRESSURE, "r");
+  CHECK(f != NULL);
+  char line[256], key[64];
+  long value = -1, n;
+  while (fgets(line, sizeof(line), f)) {
+    if (sscanf(line, "%63s %ld", key, &n) == 2 && strcmp(key, name) == 0) {
+      value = n;
+      break;
+    }
+  }
+  fclose(f);
+  CHECK(value >= 0);
+  return value;
+}
+
+// Whether the process `pid` is listed with `zombies` children not reaped.
+static int listed_with_zombies(pid_t pid, long zombies) {
+  FILE *f = fopen(PRESSURE, "r");
+  CHECK(f != NULL);
+  char line[256];
+  int found = 0;
+  long p, pipes, z, signals;
+  while (fgets(line, sizeof(line), f)) {
+    // The descriptors follow the processes.
+    if (strncmp(line, "pid fd ", 7) == 0)
+      break;
+    if (sscanf(line, "%ld %ld %ld %ld", &p, &pipes, &z, &signals) == 4 &&
+        p == pid && z == zombies)
+      found = 1;
+  }
+  fclose(f);
+  return found;
+}
+
+// A pipe counts until both ends are closed, and its bytes until read.
+void test_pipes() {
+  long pipes = count("pipes"), bytes = count("pipe_bytes");
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  CHECK(count("pipes") == pipes + 1);
+  CHECK(write(fds[1], "pressure", 8) == 8);
+  CHECK(count("pipe_bytes") == bytes + 8);
+  char buf[8];
+  CHECK(read(fds[0], buf, 3) == 3);
+  CHECK(count("pipe_bytes") == bytes + 5);
+  CHECK(close(fds[0]) == 0);
+  CHECK(count("pipes") == pipes + 1);
+  CHECK(close(fds[1]) == 0);
+  CHECK(count("pipes") == pipes);
+  CHECK(count("pipe_bytes") == bytes);
+  puts("test_pipes ok");
+}
+
+// A child which exited counts as a zombie, to its parent too, until it is
+// waited for.
+void test_zombies() {
+  long zombies = count("zombies");
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0)
+    _exit(0);
+  CHECK(waitid(P_PID, pid, NULL, WEXITED | WNOWAIT) == 0);
+  CHECK(count("zombies") == zombies + 1);
+  CHECK(listed_with_zombies(getpid(), 1));
+  CHECK(waitpid(pid, NULL, 0) == pid);
+  CHECK(count("zombies") == zombies);
+  puts("test_zombies ok");
+}
+
+// Write `line` to /proc/starry/pressure, returning what `write` does.
+static ssize_t set_limit(const char *line) {
+  int fd = open(PRESSURE, O_WRONLY);
+  CHECK(fd >= 0);
+  ssize_t ret = write(fd, line, strlen(line));
+  CHECK(close(fd) == 0);
+  return ret;
+}
+
+// Soft limits are set by name, and unknown names are rejected.
+void test_soft_limit() {
+  CHECK(set_limit("zombies 16\n") > 0);
+  FILE *f = fopen(PRESSURE, "r");
+  CHECK(f != NULL);
+  char line[256];
+  long limit = -1;
+  while (fgets(line, sizeof(line), f))
+    if (sscanf(line, "zombies %*s %ld", &limit) == 1)
+      break;
+  fclose(f);
+  CHECK(limit == 16);
+  CHECK(set_limit("zombies 256\n") > 0);
+  CHECK(set_limit("leaks 1\n") == -1 && errno == EINVAL);
+  puts("test_soft_limit ok");
+}
+
+int main() {
+  test_pipes();
+  test_zombies();
+  test_soft_limit();
+  return 0;
+}
@@ -0,0 +1,168 @@
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/resource.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+// Read all of /proc/<pid>/<name> into `buf`, returning the length.
+static size_t read_proc(pid_t pid, const char *name, char *buf, size_t size) {
+  char path[64];
+  snprintf(path, sizeof(path), "/proc/%d/%s", pid, name);
+  int fd = open(path, O_RDONLY);
+  CHECK(fd >= 0);
+  size_t len = 0;
+  ssize_t n;
+  while ((n = read(fd, buf + len, size - 1 - len)) > 0)
+    len += n;
+  CHECK(n == 0);
+  close(fd);
+  buf[len] = 0;
+  return len;
+}
+
+// Find the row of `limit` in `limits`, and check its columns.
+static void check_limit(const char *limits, const char *limit,
+                        const char *soft, const char *hard, const char *unit) {
+  char want[128];
+  snprintf(want, sizeof(want), "%-25s %-20s %-20s %-10s\n", limit, soft, hard,
+           unit);
+  const char *row = strstr(limits, limit);
+  CHECK(row != NULL);
+  CHECK(strncmp(row, want, strlen(want)) == 0);
+}
+
+static const char ARGS[] = "proc_limits\0child\0two words\0";
+static const char ENVS[] = "FOO=bar\0EMPTY=\0PATH=/bin\0";
+
+// Start a child with the limits changed, exec'ed with ARGS and ENVS, which
+// waits on a pipe. Returns its pid.
+static pid_t start_child(const char *self, int *pipe_w) {
+  int ready[2], hold[2];
+  CHECK(pipe2(ready, O_CLOEXEC) == 0 && pipe2(hold, O_CLOEXEC) == 0);
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    struct rlimit nofile = {.rlim_cur = 100, .rlim_max = 200};
+    CHECK(setrlimit(RLIMIT_NOFILE, &nofile) == 0);
+    struct rlimit core = {.rlim_cur = 4096, .rlim_max = RLIM_INFINITY};
+    CHECK(setrlimit(RLIMIT_CORE, &core) == 0);
+    // The child end of the pipes, at known numbers.
+    CHECK(dup2(ready[1], 10) == 10 && dup2(hold[0], 11) == 11);
+    char *argv[] = {"proc_limits", "child", "two words", NULL};
+    char *envp[] = {"FOO=bar", "EMPTY=", "PATH=/bin", NULL};
+    execve(self, argv, envp);
+    _exit(127);
+  }
+  close(ready[1]);
+  close(hold[0]);
+  char c;
+  CHECK(read(ready[0], &c, 1) == 1);
+  close(ready[0]);
+  *pipe_w = hold[1];
+  return pid;
+}
+
+static void run_child(void) {
+  CHECK(write(10, "r", 1) == 1);
+  char c;
+  read(11, &c, 1);
+  _exit(0);
+}
+
+// Wait for `pid` to be a zombie, without reaping it.
+static void wait_zombie(pid_t pid) {
+  for (;;) {
+    char stat[512];
+    read_proc(pid, "stat", stat, sizeof(stat));
+    char *state = strrchr(stat, ')');
+    CHECK(state != NULL);
+    if (state[2] == 'Z')
+      return;
+    usleep(1000);
+  }
+}
+
+static void check_args(pid_t pid) {
+  char buf[256];
+  CHECK(read_proc(pid, "cmdline", buf, sizeof(buf)) == sizeof(ARGS) - 1);
+  CHECK(memcmp(buf, ARGS, sizeof(ARGS) - 1) == 0);
+  CHECK(read_proc(pid, "environ", buf, sizeof(buf)) == sizeof(ENVS) - 1);
+  CHECK(memcmp(buf, ENVS, sizeof(ENVS) - 1) == 0);
+}
+
+static void test_limits(const char *self) {
+  int hold;
+  pid_t pid = start_child(self, &hold);
+
+  char limits[4096];
+  read_proc(pid, "limits", limits, sizeof(limits));
+  CHECK(strncmp(limits, "Limit                     Soft Limit           "
+                        "Hard Limit           Units     \n",
+                72) == 0);
+  check_limit(limits, "Max open files", "100", "200", "files");
+  check_limit(limits, "Max core file size", "4096", "unlimited", "bytes");
+  check_limit(limits, "Max cpu time", "unlimited", "unlimited", "seconds");
+  // No unit, and nothing after the hard limit.
+  CHECK(strstr(limits, "Max nice priority") != NULL);
+  // One row a resource.
+  int rows = 0;
+  for (const char *p = limits; *p; p++)
+    rows += *p == '\n';
+  CHECK(rows == 17);
+
+  close(hold);
+  CHECK(waitpid(pid, NULL, 0) == pid);
+  printf("test_limits ok\n");
+}
+
+static void test_environ(const char *self) {
+  int hold;
+  pid_t pid = start_child(self, &hold);
+  check_args(pid);
+
+  // Still there once it is a zombie.
+  close(hold);
+  wait_zombie(pid);
+  check_args(pid);
+  CHECK(waitpid(pid, NULL, 0) == pid);
+  printf("test_environ ok\n");
+}
+
+static void test_forked(void) {
+  // A forked child has the arguments of its parent.
+  char parent[256], child[256];
+  size_t len = read_proc(getpid(), "cmdline", parent, sizeof(parent));
+  CHECK(len > 0);
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    pause();
+    _exit(0);
+  }
+  CHECK(read_proc(pid, "cmdline", child, sizeof(child)) == len);
+  CHECK(memcmp(parent, child, len) == 0);
+  kill(pid, SIGKILL);
+  CHECK(waitpid(pid, NULL, 0) == pid);
+  printf("test_forked ok\n");
+}
+
+int main(int argc, char **argv) {
+  if (argc > 1 && strcmp(argv[1], "child") == 0)
+    run_child();
+  test_limits(argv[0]);
+  test_environ(argv[0]);
+  test_forked();
+  return 0;
+}
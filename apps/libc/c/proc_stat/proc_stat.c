@@ -0,0 +1,62 @@
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+
+// The value of the line of `path` starting with `key`, or -1.
+static long long read_field(const char *path, const char *key) {
+  FILE *f = fopen(path, "r");
+  if (!f) {
+    return -1;
+  }
+  char line[256];
+  long long value = -1;
+  size_t len = strlen(key);
+  while (fgets(line, sizeof(line), f)) {
+    if (strncmp(line, key, len) == 0) {
+      sscanf(line + len, "%lld", &value);
+      break;
+    }
+  }
+  fclose(f);
+  return value;
+}
+
+// The system-wide counters only grow, and sleeping switches context.
+void test_system_stat() {
+  long long ctxt = read_field("/proc/stat", "ctxt ");
+  long long processes = read_field("/proc/stat", "processes ");
+  long long running = read_field("/proc/stat", "procs_running ");
+  long long user, nice, system, idle;
+  FILE *f = fopen("/proc/stat", "r");
+  int n = f ? fscanf(f, "cpu %lld %lld %lld %lld", &user, &nice, &system,
+                     &idle)
+            : 0;
+  if (f) {
+    fclose(f);
+  }
+  usleep(10000);
+  // At least the reading process is running.
+  if (n == 4 && ctxt > 0 && processes > 0 && running >= 1 &&
+      read_field("/proc/stat", "ctxt ") > ctxt &&
+      read_field("/proc/vmstat", "pgfault ") > 0) {
+    puts("test_system_stat ok");
+  }
+}
+
+// Sleeping counts as a voluntary context switch.
+void test_ctxt_switches() {
+  const char *status = "/proc/self/status";
+  long long voluntary = read_field(status, "voluntary_ctxt_switches:");
+  long long nonvoluntary = read_field(status, "nonvoluntary_ctxt_switches:");
+  usleep(10000);
+  if (voluntary >= 0 && nonvoluntary >= 0 &&
+      read_field(status, "voluntary_ctxt_switches:") > voluntary) {
+    puts("test_ctxt_switches ok");
+  }
+}
+
+int main() {
+  test_system_stat();
+  test_ctxt_switches();
+  return 0;
+}
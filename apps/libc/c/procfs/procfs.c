@@ -0,0 +1,74 @@
+#include <dirent.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+// List `path`, checking "." and ".." are present, every entry can be
+// lstat'ed with a matching inode number and type, and `want` is listed with
+// type `want_type`.
+static int check_dir(const char *path, const char *want, int want_type) {
+  DIR *dir = opendir(path);
+  if (!dir) {
+    return 0;
+  }
+  int dot = 0, dotdot = 0, found = 0, ok = 1;
+  struct dirent *ent;
+  while ((ent = readdir(dir))) {
+    char buf[512];
+    struct stat st;
+    if (!strcmp(ent->d_name, ".")) {
+      dot = 1;
+      continue;
+    }
+    if (!strcmp(ent->d_name, "..")) {
+      dotdot = 1;
+      continue;
+    }
+    snprintf(buf, sizeof(buf), "%s/%s", path, ent->d_name);
+    if (lstat(buf, &st) != 0 || st.st_ino != ent->d_ino) {
+      printf("bad entry %s\n", buf);
+      ok = 0;
+    } else if ((ent->d_type == DT_DIR) != S_ISDIR(st.st_mode)) {
+      printf("bad type %s\n", buf);
+      ok = 0;
+    }
+    if (!strcmp(ent->d_name, want) && ent->d_type == want_type) {
+      found = 1;
+    }
+  }
+  closedir(dir);
+  return dot && dotdot && found && ok;
+}
+
+int main() {
+  if (check_dir("/proc", "self", DT_DIR)) {
+    puts("test_ls_proc ok");
+  }
+
+  char buf[32];
+  snprintf(buf, sizeof(buf), "/proc/%d", getpid());
+  struct stat st1, st2;
+  if (stat(buf, &st1) == 0 && S_ISDIR(st1.st_mode) && check_dir(buf, "stat", DT_REG)) {
+    puts("test_ls_pid ok");
+  }
+
+  if (check_dir("/dev", "null", DT_CHR) && check_dir("/dev", "zero", DT_CHR)) {
+    puts("test_ls_dev ok");
+  }
+
+  if (stat("/dev/null", &st1) == 0 && stat("/dev/null", &st2) == 0 &&
+      S_ISCHR(st1.st_mode) && st1.st_ino == st2.st_ino) {
+    puts("test_stable_ino ok");
+  }
+
+  FILE *f = fopen("/proc/sys/net/core/somaxconn", "r");
+  int somaxconn = 0;
+  if (f && fscanf(f, "%d", &somaxconn) == 1 && somaxconn == 4096) {
+    puts("test_read_proc ok");
+  }
+  if (f) {
+    fclose(f);
+  }
+  return 0;
+}
@@ -0,0 +1,121 @@
+#include <dirent.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+static const char *path = "/procfs_fd_test";
+
+// Read the link `/proc/self/fd/<fd>` into `buf`.
+static int read_fd_link(int fd, char *buf, size_t size) {
+  char link[64];
+  snprintf(link, sizeof(link), "/proc/self/fd/%d", fd);
+  ssize_t len = readlink(link, buf, size - 1);
+  if (len < 0) {
+    return 0;
+  }
+  buf[len] = '\0';
+  return 1;
+}
+
+// Check that `/proc/self/fd` lists `fd` as a link.
+static int fd_listed(int fd) {
+  DIR *dir = opendir("/proc/self/fd");
+  if (!dir) {
+    return 0;
+  }
+  char name[16];
+  snprintf(name, sizeof(name), "%d", fd);
+  int found = 0;
+  struct dirent *ent;
+  while ((ent = readdir(dir))) {
+    if (!strcmp(ent->d_name, name) && ent->d_type == DT_LNK) {
+      found = 1;
+    }
+  }
+  closedir(dir);
+  return found;
+}
+
+void test_fd_file() {
+  char buf[256];
+  int fd = open(path, O_CREAT | O_RDWR, 0644);
+  if (fd < 0) {
+    return;
+  }
+  if (fd_listed(fd) && read_fd_link(fd, buf, sizeof(buf)) && !strcmp(buf, path)) {
+    puts("test_fd_file ok");
+  }
+  close(fd);
+  unlink(path);
+}
+
+void test_fd_pipe() {
+  char buf1[64], buf2[64];
+  int fds[2];
+  if (pipe(fds) != 0) {
+    return;
+  }
+  if (read_fd_link(fds[0], buf1, sizeof(buf1)) && read_fd_link(fds[1], buf2, sizeof(buf2)) &&
+      !strncmp(buf1, "pipe:[", 6) && !strcmp(buf1, buf2)) {
+    puts("test_fd_pipe ok");
+  }
+  close(fds[0]);
+  close(fds[1]);
+}
+
+void test_fd_reopen() {
+  char link[64];
+  int fds[2];
+  if (pipe(fds) != 0) {
+    return;
+  }
+  snprintf(link, sizeof(link), "/proc/self/fd/%d", fds[1]);
+  int fd = open(link, O_WRONLY);
+  char c = 0;
+  if (fd >= 0 && write(fd, "x", 1) == 1 && read(fds[0], &c, 1) == 1 && c == 'x') {
+    puts("test_fd_reopen ok");
+  }
+  close(fd);
+  close(fds[0]);
+  close(fds[1]);
+}
+
+void test_cwd() {
+  char buf[256], cwd[256];
+  ssize_t len = readlink("/proc/self/cwd", buf, sizeof(buf) - 1);
+  if (len < 0 || !getcwd(cwd, sizeof(cwd))) {
+    return;
+  }
+  buf[len] = '\0';
+  size_t cwd_len = strlen(cwd);
+  if (cwd_len > 1 && cwd[cwd_len - 1] == '/') {
+    cwd[cwd_len - 1] = '\0';
+  }
+  if (!strcmp(buf, cwd)) {
+    puts("test_cwd ok");
+  }
+}
+
+void test_exe() {
+  char buf[256];
+  struct stat st, lst;
+  ssize_t len = readlink("/proc/self/exe", buf, sizeof(buf) - 1);
+  if (len <= 0 || buf[0] != '/') {
+    return;
+  }
+  if (stat("/proc/self/exe", &st) == 0 && S_ISREG(st.st_mode) &&
+      lstat("/proc/self/exe", &lst) == 0 && S_ISLNK(lst.st_mode)) {
+    puts("test_exe ok");
+  }
+}
+
+int main() {
+  test_fd_file();
+  test_fd_pipe();
+  test_fd_reopen();
+  test_cwd();
+  test_exe();
+  return 0;
+}
@@ -0,0 +1,92 @@
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static int read_file(const char *path, char *buf, size_t size) {
+  int fd = open(path, O_RDONLY);
+  if (fd < 0) {
+    return 0;
+  }
+  ssize_t len = read(fd, buf, size - 1);
+  close(fd);
+  if (len <= 0) {
+    return 0;
+  }
+  buf[len] = '\0';
+  return 1;
+}
+
+// Parse the first fields of `/proc/<pid>/stat` and count all of them.
+static int read_stat(int pid, int *out_pid, char *state, int *ppid, int *num_fields) {
+  char path[64], buf[1024];
+  snprintf(path, sizeof(path), "/proc/%d/stat", pid);
+  if (!read_file(path, buf, sizeof(buf))) {
+    return 0;
+  }
+  char comm[32];
+  if (sscanf(buf, "%d (%31[^)]) %c %d", out_pid, comm, state, ppid) != 4) {
+    return 0;
+  }
+  // comm has no spaces here, so every space separates two fields.
+  *num_fields = 1;
+  for (char *p = buf; *p; p++) {
+    *num_fields += *p == ' ';
+  }
+  return 1;
+}
+
+void test_stat_self() {
+  int pid, ppid, num_fields;
+  char state;
+  if (read_stat(getpid(), &pid, &state, &ppid, &num_fields) && pid == getpid() &&
+      state == 'R' && ppid == getppid() && num_fields == 52) {
+    puts("test_stat_self ok");
+  }
+}
+
+void test_stat_zombie() {
+  pid_t child = fork();
+  if (child == 0) {
+    _exit(0);
+  }
+  int pid, ppid, num_fields;
+  char state = 0;
+  // Wait until the child has exited without reaping it.
+  for (int i = 0; i < 1000 && state != 'Z'; i++) {
+    if (!read_stat(child, &pid, &state, &ppid, &num_fields)) {
+      break;
+    }
+    usleep(1000);
+  }
+  if (state == 'Z' && pid == child && ppid == getpid()) {
+    puts("test_stat_zombie ok");
+  }
+  waitpid(child, NULL, 0);
+}
+
+void test_status() {
+  char buf[1024];
+  char want[64], uid[64], gid[64];
+  if (!read_file("/proc/self/status", buf, sizeof(buf))) {
+    return;
+  }
+  snprintf(want, sizeof(want), "\nPid:\t%d\n", getpid());
+  snprintf(uid, sizeof(uid), "\nUid:\t%d\t%d\t%d\t%d\n", getuid(), geteuid(), geteuid(),
+           geteuid());
+  snprintf(gid, sizeof(gid), "\nGid:\t%d\t%d\t%d\t%d\n", getgid(), getegid(), getegid(),
+           getegid());
+  if (!strncmp(buf, "Name:\t", 6) && strstr(buf, want) && strstr(buf, "\nThreads:\t1\n") &&
+      strstr(buf, "\nVmSize:") && strstr(buf, "\nVmRSS:") && strstr(buf, uid) &&
+      strstr(buf, gid)) {
+    puts("test_status ok");
+  }
+}
+
+int main() {
+  test_stat_self();
+  test_stat_zombie();
+  test_status();
+  return 0;
+}
@@ -0,0 +1,116 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <setjmp.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+static long page;
+#define RESERVE_SIZE (256 << 20)
+#define COMMIT_SIZE (1 << 20)
+
+static sigjmp_buf fault_jmp;
+
+static void on_segv(int sig) {
+  (void)sig;
+  siglongjmp(fault_jmp, 1);
+}
+
+// Whether reading the byte at `p` faults.
+static int faults(volatile char *p) {
+  if (sigsetjmp(fault_jmp, 1)) {
+    return 1;
+  }
+  (void)*p;
+  return 0;
+}
+
+// What allocators like jemalloc do: reserve a large range of addresses,
+// then make the parts they use accessible as they go.
+void test_reserve_commit() {
+  char *base = mmap(NULL, RESERVE_SIZE, PROT_NONE,
+                    MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE, -1, 0);
+  CHECK(base != MAP_FAILED);
+  CHECK(faults(base));
+  CHECK(faults(base + RESERVE_SIZE / 2));
+
+  char *commit = base + RESERVE_SIZE / 2;
+  CHECK(mprotect(commit, COMMIT_SIZE, PROT_READ | PROT_WRITE) == 0);
+  for (size_t off = 0; off < COMMIT_SIZE; off += page) {
+    CHECK(commit[off] == 0);
+    commit[off] = 1;
+  }
+  for (size_t off = 0; off < COMMIT_SIZE; off += page) {
+    CHECK(commit[off] == 1);
+  }
+
+  // The rest is still reserved only.
+  CHECK(faults(base));
+  CHECK(faults(commit - page));
+  CHECK(faults(commit + COMMIT_SIZE));
+  CHECK(faults(base + RESERVE_SIZE - 1));
+
+  // And can be given back and committed again, zeroed.
+  CHECK(munmap(commit, COMMIT_SIZE) == 0);
+  CHECK(faults(commit));
+  CHECK(mmap(commit, COMMIT_SIZE, PROT_READ | PROT_WRITE,
+             MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, -1, 0) == commit);
+  CHECK(commit[0] == 0);
+
+  CHECK(munmap(base, RESERVE_SIZE) == 0);
+  puts("test_reserve_commit ok");
+}
+
+// `MAP_POPULATE` has nothing to allocate for a `PROT_NONE` mapping.
+void test_populate_none() {
+  char *p = mmap(NULL, 16 * page, PROT_NONE,
+                 MAP_PRIVATE | MAP_ANONYMOUS | MAP_POPULATE, -1, 0);
+  CHECK(p != MAP_FAILED);
+  CHECK(faults(p));
+  CHECK(mprotect(p, 16 * page, PROT_READ) == 0);
+  CHECK(!faults(p + 15 * page));
+  CHECK(munmap(p, 16 * page) == 0);
+  puts("test_populate_none ok");
+}
+
+// A syscall buffer in a `PROT_NONE` mapping fails with `EFAULT`, one
+// ending right at the end of an accessible mapping does not, and an empty
+// one is never accessed.
+void test_syscall_buffer() {
+  char *p = mmap(NULL, 3 * page, PROT_READ | PROT_WRITE,
+                 MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  CHECK(p != MAP_FAILED);
+  CHECK(mprotect(p + page, page, PROT_NONE) == 0);
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  CHECK(write(fds[1], p + page, 8) == -1 && errno == EFAULT);
+  CHECK(write(fds[1], p + page - 8, 8) == 8);
+  CHECK(write(fds[1], p + 3 * page - 8, 8) == 8);
+  CHECK(read(fds[0], p + page, 8) == -1 && errno == EFAULT);
+  CHECK(write(fds[1], NULL, 0) == 0);
+  CHECK(write(fds[1], p + page, 0) == 0);
+  close(fds[0]);
+  close(fds[1]);
+  CHECK(munmap(p, 3 * page) == 0);
+  puts("test_syscall_buffer ok");
+}
+
+int main() {
+  page = sysconf(_SC_PAGESIZE);
+  struct sigaction sa = {.sa_handler = on_segv};
+  CHECK(sigaction(SIGSEGV, &sa, NULL) == 0);
+  test_reserve_commit();
+  test_populate_none();
+  test_syscall_buffer();
+  return 0;
+}
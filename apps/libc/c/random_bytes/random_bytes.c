@@ -0,0 +1,148 @@
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/auxv.h>
+#include <sys/random.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define BLOCKS 1024
+
+static int block_cmp(const void *a, const void *b) { return memcmp(a, b, 16); }
+
+// No two of the 16-byte blocks in `buf` are the same.
+static int distinct_blocks(unsigned char (*buf)[16], size_t n) {
+  qsort(buf, n, 16, block_cmp);
+  for (size_t i = 1; i < n; i++)
+    if (memcmp(buf[i - 1], buf[i], 16) == 0)
+      return 0;
+  return 1;
+}
+
+// Draws of getrandom do not repeat, however they are split up.
+void test_getrandom() {
+  static unsigned char buf[BLOCKS][16];
+  CHECK(getrandom(buf, sizeof(buf) / 2, 0) == sizeof(buf) / 2);
+  for (int i = BLOCKS / 2; i < BLOCKS; i++)
+    CHECK(getrandom(buf[i], 16, GRND_NONBLOCK) == 16);
+  CHECK(distinct_blocks(buf, BLOCKS));
+  puts("test_getrandom ok");
+}
+
+// /dev/urandom and /dev/random read random bytes at once.
+void test_dev_random() {
+  const char *paths[] = {"/dev/urandom", "/dev/random"};
+  for (int i = 0; i < 2; i++) {
+    static unsigned char buf[BLOCKS][16];
+    int fd = open(paths[i], O_RDONLY);
+    CHECK(fd >= 0);
+    CHECK(read(fd, buf, sizeof(buf)) == sizeof(buf));
+    CHECK(distinct_blocks(buf, BLOCKS));
+    close(fd);
+  }
+  puts("test_dev_random ok");
+}
+
+// The AT_RANDOM bytes of this program, as hex.
+static void at_random_hex(char out[33]) {
+  const unsigned char *bytes = (const unsigned char *)getauxval(AT_RANDOM);
+  CHECK(bytes != NULL);
+  for (int i = 0; i < 16; i++)
+    sprintf(out + i * 2, "%02x", bytes[i]);
+}
+
+// Whether the AT_RANDOM bytes overlap any of the strings of `argv` and
+// `environ`, which are copied to the stack next to them.
+static int at_random_clobbers(char **argv) {
+  extern char **environ;
+  const char *bytes = (const char *)getauxval(AT_RANDOM);
+  char **lists[] = {argv, environ};
+  for (int i = 0; i < 2; i++)
+    for (char **it = lists[i]; *it; it++)
+      if (bytes < *it + strlen(*it) + 1 && *it < bytes + 16)
+        return 1;
+  return 0;
+}
+
+// The AT_RANDOM bytes of `self` run by `execv` in a child, or those of the
+// child itself, as hex, if `self` is NULL.
+static void child_at_random_hex(const char *self, char out[33]) {
+  int p[2];
+  CHECK(pipe(p) == 0);
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    if (self == NULL) {
+      char hex[33];
+      at_random_hex(hex);
+      _exit(write(p[1], hex, 32) != 32);
+    }
+    dup2(p[1], STDOUT_FILENO);
+    char *argv[] = {(char *)self, "at_random", NULL};
+    execv(self, argv);
+    _exit(1);
+  }
+  close(p[1]);
+  memset(out, 0, 33);
+  CHECK(read(p[0], out, 32) == 32);
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  close(p[0]);
+}
+
+// Every program gets AT_RANDOM bytes of its own, even the same one run
+// twice, apart from its arguments and environment.
+void test_at_random(const char *self) {
+  char mine[33], first[33], second[33];
+  at_random_hex(mine);
+  child_at_random_hex(self, first);
+  child_at_random_hex(self, second);
+  CHECK(strcmp(mine, first) != 0);
+  CHECK(strcmp(mine, second) != 0);
+  CHECK(strcmp(first, second) != 0);
+  puts("test_at_random ok");
+}
+
+// A child of fork keeps the AT_RANDOM bytes of its parent, as it keeps the
+// stack canary seeded from them.
+void test_at_random_fork() {
+  char mine[33], child[33];
+  at_random_hex(mine);
+  child_at_random_hex(NULL, child);
+  CHECK(strcmp(mine, child) == 0);
+  puts("test_at_random_fork ok");
+}
+
+int main(int argc, char **argv) {
+  if (argc == 2 && strcmp(argv[1], "at_random") == 0) {
+    if (at_random_clobbers(argv))
+      return 2;
+    char hex[33];
+    at_random_hex(hex);
+    fputs(hex, stdout);
+    return 0;
+  }
+  char self[256];
+  ssize_t len = readlink("/proc/self/exe", self, sizeof(self) - 1);
+  if (len <= 0) {
+    return 1;
+  }
+  self[len] = '\0';
+
+  test_getrandom();
+  test_dev_random();
+  test_at_random(self);
+  test_at_random_fork();
+  return 0;
+}
@@ -0,0 +1,97 @@
+#include <dirent.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define DIR_NAME "raw_names_dir"
+// "café" in Latin-1, which is not UTF-8.
+#define LATIN1 "caf\xe9"
+// "café" in UTF-8.
+#define UTF8 "caf\xc3\xa9"
+// A private use character in UTF-8, which the kernel escapes bytes with.
+#define PRIVATE "caf\xee\xbf\xa9"
+
+// Whether `dir` lists an entry named exactly `name`.
+static int listed(const char *dir, const char *name) {
+  DIR *d = opendir(dir);
+  CHECK(d != NULL);
+  int found = 0;
+  struct dirent *ent;
+  while ((ent = readdir(d)) != NULL)
+    if (strcmp(ent->d_name, name) == 0)
+      found = 1;
+  closedir(d);
+  return found;
+}
+
+static void create(const char *path) {
+  int fd = openat(AT_FDCWD, path, O_CREAT | O_WRONLY | O_EXCL, 0644);
+  CHECK(fd >= 0);
+  CHECK(close(fd) == 0);
+}
+
+// A name which is not UTF-8 is made, listed, stat'ed and removed by the
+// same bytes.
+void test_latin1_name() {
+  CHECK(mkdir(DIR_NAME, 0755) == 0);
+  create(DIR_NAME "/" LATIN1);
+  CHECK(listed(DIR_NAME, LATIN1));
+  CHECK(!listed(DIR_NAME, UTF8));
+  struct stat st;
+  CHECK(stat(DIR_NAME "/" LATIN1, &st) == 0 && S_ISREG(st.st_mode));
+  CHECK(unlink(DIR_NAME "/" LATIN1) == 0);
+  CHECK(stat(DIR_NAME "/" LATIN1, &st) == -1 && errno == ENOENT);
+  CHECK(!listed(DIR_NAME, LATIN1));
+  puts("test_latin1_name ok");
+}
+
+// Names whose bytes differ are different files, whichever is UTF-8.
+void test_distinct_names() {
+  create(DIR_NAME "/" LATIN1);
+  create(DIR_NAME "/" UTF8);
+  create(DIR_NAME "/" PRIVATE);
+  CHECK(listed(DIR_NAME, LATIN1));
+  CHECK(listed(DIR_NAME, UTF8));
+  CHECK(listed(DIR_NAME, PRIVATE));
+  CHECK(unlink(DIR_NAME "/" UTF8) == 0);
+  CHECK(access(DIR_NAME "/" LATIN1, F_OK) == 0);
+  CHECK(access(DIR_NAME "/" PRIVATE, F_OK) == 0);
+  CHECK(unlink(DIR_NAME "/" LATIN1) == 0);
+  CHECK(unlink(DIR_NAME "/" PRIVATE) == 0);
+  puts("test_distinct_names ok");
+}
+
+// The working directory is reported with the bytes it was entered by.
+void test_getcwd() {
+  char before[4096], cwd[4096];
+  CHECK(getcwd(before, sizeof(before)) != NULL);
+  CHECK(mkdir(DIR_NAME "/" LATIN1, 0755) == 0);
+  CHECK(chdir(DIR_NAME "/" LATIN1) == 0);
+  CHECK(getcwd(cwd, sizeof(cwd)) != NULL);
+  const char *suffix = "/" DIR_NAME "/" LATIN1;
+  size_t len = strlen(cwd), suffix_len = strlen(suffix);
+  CHECK(len >= suffix_len && strcmp(cwd + len - suffix_len, suffix) == 0);
+  CHECK(chdir(before) == 0);
+  CHECK(rmdir(DIR_NAME "/" LATIN1) == 0);
+  CHECK(rmdir(DIR_NAME) == 0);
+  puts("test_getcwd ok");
+}
+
+int main() {
+  test_latin1_name();
+  test_distinct_names();
+  test_getcwd();
+  return 0;
+}
@@ -0,0 +1,58 @@
+#define _GNU_SOURCE
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/random.h>
+#include <sys/time.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define ATTEMPTS 6
+
+static long now_us(void) {
+  struct timeval tv;
+  CHECK(gettimeofday(&tv, NULL) == 0);
+  return tv.tv_sec * 1000000L + tv.tv_usec;
+}
+
+// Retry an operation which succeeds once the time is past a deadline,
+// backing off exponentially with random jitter, and print the schedule.
+// It differs from run to run, but not between replays of one recording,
+// see scripts/replay_test.sh.
+static void test_backoff(void) {
+  unsigned int seed;
+  CHECK(getrandom(&seed, sizeof(seed), 0) == sizeof(seed));
+
+  long start = now_us();
+  long deadline = start + 20000;
+  long delay = 1000;
+  int attempt = 0;
+  for (; attempt < ATTEMPTS; attempt++) {
+    long now = now_us();
+    struct timespec mono;
+    CHECK(clock_gettime(CLOCK_MONOTONIC, &mono) == 0);
+    printf("backoff: attempt %d at %ld us, monotonic %ld.%09ld\n", attempt,
+           now - start, (long)mono.tv_sec, mono.tv_nsec);
+    if (now >= deadline)
+      break;
+    long jitter = (seed ^ now) % (delay / 2 + 1);
+    seed = seed * 1103515245 + 12345;
+    usleep(delay + jitter);
+    delay *= 2;
+  }
+  printf("backoff: seed %u, %d attempts\n", seed, attempt);
+  CHECK(attempt < ATTEMPTS);
+  printf("test_backoff ok\n");
+}
+
+int main(void) {
+  test_backoff();
+  return 0;
+}
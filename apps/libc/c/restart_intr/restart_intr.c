@@ -0,0 +1,170 @@
+#define _GNU_SOURCE
+#include <arpa/inet.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <netinet/in.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/file.h>
+#include <sys/socket.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define PATH "/restart_intr_lock"
+
+static volatile sig_atomic_t handled;
+
+static void handler(int signo) { handled = signo; }
+
+static void catch_sigusr1(int flags) {
+  struct sigaction sa = {0};
+  sa.sa_handler = handler;
+  sa.sa_flags = flags;
+  CHECK(sigaction(SIGUSR1, &sa, NULL) == 0);
+  handled = 0;
+}
+
+// A listening TCP socket on the loopback, with its address in `addr`.
+static int listener(struct sockaddr_in *addr) {
+  int fd = socket(AF_INET, SOCK_STREAM, 0);
+  CHECK(fd >= 0);
+  *addr = (struct sockaddr_in){
+      .sin_family = AF_INET,
+      .sin_addr.s_addr = htonl(INADDR_LOOPBACK),
+  };
+  socklen_t len = sizeof(*addr);
+  CHECK(bind(fd, (struct sockaddr *)addr, len) == 0);
+  CHECK(getsockname(fd, (struct sockaddr *)addr, &len) == 0);
+  CHECK(listen(fd, 1) == 0);
+  return fd;
+}
+
+// A child sending SIGUSR1 to this process after a while, then connecting
+// to `addr` if given.
+static pid_t signal_then_connect(const struct sockaddr_in *addr) {
+  pid_t parent = getpid();
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    usleep(50000);
+    kill(parent, SIGUSR1);
+    if (addr) {
+      usleep(50000);
+      int fd = socket(AF_INET, SOCK_STREAM, 0);
+      if (fd < 0 || connect(fd, (const struct sockaddr *)addr, sizeof(*addr)))
+        _exit(1);
+      close(fd);
+    }
+    _exit(0);
+  }
+  return pid;
+}
+
+static void reap(pid_t pid) {
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+// Without SA_RESTART, a handled signal fails a blocking accept with EINTR.
+void test_accept_eintr() {
+  struct sockaddr_in addr;
+  int fd = listener(&addr);
+  catch_sigusr1(0);
+  pid_t pid = signal_then_connect(NULL);
+  CHECK(accept(fd, NULL, NULL) == -1 && errno == EINTR);
+  CHECK(handled == SIGUSR1);
+  reap(pid);
+  close(fd);
+  puts("test_accept_eintr ok");
+}
+
+// With SA_RESTART, accept goes on after the handler, and gets the
+// connection made afterwards.
+void test_accept_restart() {
+  struct sockaddr_in addr;
+  int fd = listener(&addr);
+  catch_sigusr1(SA_RESTART);
+  pid_t pid = signal_then_connect(&addr);
+  int conn = accept(fd, NULL, NULL);
+  CHECK(conn >= 0);
+  CHECK(handled == SIGUSR1);
+  reap(pid);
+  close(conn);
+  close(fd);
+  puts("test_accept_restart ok");
+}
+
+// A child holding an exclusive flock on PATH for a while, sending SIGUSR1
+// to this process meanwhile.
+static pid_t hold_flock(void) {
+  int ready[2];
+  CHECK(pipe(ready) == 0);
+  pid_t parent = getpid();
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    int fd = open(PATH, O_RDWR);
+    if (fd < 0 || flock(fd, LOCK_EX) != 0)
+      _exit(1);
+    write(ready[1], "x", 1);
+    usleep(50000);
+    kill(parent, SIGUSR1);
+    usleep(100000);
+    _exit(0);
+  }
+  char c;
+  CHECK(read(ready[0], &c, 1) == 1);
+  close(ready[0]);
+  close(ready[1]);
+  return pid;
+}
+
+// Without SA_RESTART, a handled signal fails a waiting flock with EINTR.
+void test_flock_eintr() {
+  int fd = open(PATH, O_RDWR | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  catch_sigusr1(0);
+  pid_t pid = hold_flock();
+  CHECK(flock(fd, LOCK_EX | LOCK_NB) == -1 && errno == EWOULDBLOCK);
+  CHECK(flock(fd, LOCK_EX) == -1 && errno == EINTR);
+  CHECK(handled == SIGUSR1);
+  reap(pid);
+  CHECK(flock(fd, LOCK_EX | LOCK_NB) == 0);
+  close(fd);
+  unlink(PATH);
+  puts("test_flock_eintr ok");
+}
+
+// With SA_RESTART, flock goes on waiting after the handler, until the
+// holder exits.
+void test_flock_restart() {
+  int fd = open(PATH, O_RDWR | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  catch_sigusr1(SA_RESTART);
+  pid_t pid = hold_flock();
+  CHECK(flock(fd, LOCK_EX) == 0);
+  CHECK(handled == SIGUSR1);
+  reap(pid);
+  CHECK(flock(fd, LOCK_UN) == 0);
+  close(fd);
+  unlink(PATH);
+  puts("test_flock_restart ok");
+}
+
+int main() {
+  test_accept_eintr();
+  test_accept_restart();
+  test_flock_eintr();
+  test_flock_restart();
+  return 0;
+}
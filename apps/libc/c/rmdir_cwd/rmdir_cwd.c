@@ -0,0 +1,81 @@
+#define _GNU_SOURCE
+#include <dirent.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define TOP "/rmdir_cwd"
+#define SUB TOP "/sub"
+
+// Check the working directory and `dirfd` of a removed directory.
+static int check_removed(int dirfd) {
+  char buf[256];
+  if (getcwd(buf, sizeof(buf)) != NULL || errno != ENOENT) {
+    return 1;
+  }
+  if (open("new", O_WRONLY | O_CREAT, 0644) != -1 || errno != ENOENT) {
+    return 2;
+  }
+  if (mkdir("newdir", 0755) != -1 || errno != ENOENT) {
+    return 3;
+  }
+  if (syscall(SYS_getdents64, dirfd, buf, sizeof(buf)) != 0) {
+    return 4;
+  }
+  if (openat(dirfd, "new", O_WRONLY | O_CREAT, 0644) != -1 ||
+      errno != ENOENT) {
+    return 5;
+  }
+  // An absolute path still leads out.
+  if (chdir("/") != 0 || getcwd(buf, sizeof(buf)) == NULL) {
+    return 6;
+  }
+  return 0;
+}
+
+void test_rm_rf_cwd() {
+  int ready[2], removed[2];
+  if (mkdir(TOP, 0755) != 0 || mkdir(SUB, 0755) != 0 ||
+      close(open(SUB "/file", O_WRONLY | O_CREAT, 0644)) != 0 ||
+      pipe(ready) != 0 || pipe(removed) != 0) {
+    return;
+  }
+  pid_t pid = fork();
+  if (pid == 0) {
+    char byte = 0;
+    close(removed[1]);
+    if (chdir(SUB) != 0) {
+      _exit(10);
+    }
+    int dirfd = open(".", O_RDONLY | O_DIRECTORY);
+    if (dirfd < 0 || write(ready[1], &byte, 1) != 1 ||
+        read(removed[0], &byte, 1) != 1) {
+      _exit(11);
+    }
+    _exit(check_removed(dirfd));
+  }
+  char byte = 0;
+  int status;
+  // `rm -rf` removes the tree bottom-up.
+  int ok = read(ready[0], &byte, 1) == 1 && unlink(SUB "/file") == 0 &&
+           rmdir(SUB) == 0 && rmdir(TOP) == 0 &&
+           // A new directory at the same path is not the removed one.
+           mkdir(TOP, 0755) == 0 && mkdir(SUB, 0755) == 0 &&
+           write(removed[1], &byte, 1) == 1;
+  close(removed[1]);
+  if (waitpid(pid, &status, 0) == pid && ok && WIFEXITED(status) &&
+      WEXITSTATUS(status) == 0) {
+    puts("test_rm_rf_cwd ok");
+  }
+  rmdir(SUB);
+  rmdir(TOP);
+}
+
+int main() {
+  test_rm_rf_cwd();
+  return 0;
+}
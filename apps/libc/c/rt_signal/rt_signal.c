@@ -0,0 +1,205 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <sys/resource.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define COUNT 64
+
+static volatile int received[COUNT];
+static volatile int received_count;
+
+static void handler(int signo, siginfo_t *info, void *ctx) {
+  if (received_count < COUNT) {
+    received[received_count] = info->si_value.sival_int;
+  }
+  received_count++;
+}
+
+static void block(int signo) {
+  sigset_t set;
+  sigemptyset(&set);
+  sigaddset(&set, signo);
+  sigprocmask(SIG_BLOCK, &set, NULL);
+}
+
+static void unblock(int signo) {
+  sigset_t set;
+  sigemptyset(&set);
+  sigaddset(&set, signo);
+  sigprocmask(SIG_UNBLOCK, &set, NULL);
+}
+
+static int queue(pid_t pid, int signo, int value) {
+  union sigval val = {.sival_int = value};
+  return sigqueue(pid, signo, val);
+}
+
+// A child produces the signals, the parent consumes them with sigtimedwait.
+void test_producer_consumer() {
+  block(SIGRTMIN);
+  pid_t parent = getpid();
+  pid_t pid = fork();
+  if (pid == 0) {
+    for (int i = 0; i < COUNT; i++) {
+      if (queue(parent, SIGRTMIN, i) != 0) {
+        _exit(1);
+      }
+    }
+    _exit(0);
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  sigset_t set;
+  sigemptyset(&set);
+  sigaddset(&set, SIGRTMIN);
+  struct timespec timeout = {1, 0};
+  int ok = WIFEXITED(status) && WEXITSTATUS(status) == 0;
+  for (int i = 0; i < COUNT && ok; i++) {
+    siginfo_t info;
+    ok = sigtimedwait(&set, &info, &timeout) == SIGRTMIN &&
+         info.si_value.sival_int == i && info.si_pid == pid &&
+         info.si_code == SI_QUEUE;
+  }
+  // Nothing is left.
+  timeout.tv_sec = 0;
+  ok = ok && sigtimedwait(&set, NULL, &timeout) == -1 && errno == EAGAIN;
+  unblock(SIGRTMIN);
+  if (ok) {
+    puts("test_producer_consumer ok");
+  }
+}
+
+// Handlers take queued signals one at a time, lower numbers first.
+void test_handler_order() {
+  struct sigaction sa = {0};
+  sa.sa_sigaction = handler;
+  sa.sa_flags = SA_SIGINFO;
+  sigaction(SIGRTMIN, &sa, NULL);
+  sigaction(SIGRTMIN + 1, &sa, NULL);
+  block(SIGRTMIN);
+  block(SIGRTMIN + 1);
+  received_count = 0;
+  queue(getpid(), SIGRTMIN + 1, 1000);
+  for (int i = 0; i < COUNT - 1; i++) {
+    queue(getpid(), SIGRTMIN, i);
+  }
+  unblock(SIGRTMIN + 1);
+  unblock(SIGRTMIN);
+  int ok = received_count == COUNT;
+  // SIGRTMIN + 1 was unblocked first, so it came first.
+  ok = ok && received[0] == 1000;
+  for (int i = 1; i < COUNT && ok; i++) {
+    ok = received[i] == i - 1;
+  }
+  if (ok) {
+    puts("test_handler_order ok");
+  }
+}
+
+// A standard signal is pending at most once.
+void test_standard_collapse() {
+  struct sigaction sa = {0};
+  sa.sa_sigaction = handler;
+  sa.sa_flags = SA_SIGINFO;
+  sigaction(SIGUSR1, &sa, NULL);
+  block(SIGUSR1);
+  received_count = 0;
+  for (int i = 0; i < 4; i++) {
+    queue(getpid(), SIGUSR1, i);
+  }
+  unblock(SIGUSR1);
+  if (received_count == 1) {
+    puts("test_standard_collapse ok");
+  }
+}
+
+static int sigpending_limit() {
+  struct rlimit limit = {8, 8};
+  if (setrlimit(RLIMIT_SIGPENDING, &limit) != 0) {
+    return 1;
+  }
+  block(SIGRTMIN);
+  for (int i = 0; i < 8; i++) {
+    if (queue(getpid(), SIGRTMIN, i) != 0) {
+      return 2;
+    }
+  }
+  if (queue(getpid(), SIGRTMIN, 8) != -1 || errno != EAGAIN) {
+    return 3;
+  }
+  sigset_t set;
+  sigemptyset(&set);
+  sigaddset(&set, SIGRTMIN);
+  siginfo_t info;
+  if (sigwaitinfo(&set, &info) != SIGRTMIN || info.si_value.sival_int != 0) {
+    return 4;
+  }
+  return queue(getpid(), SIGRTMIN, 8) == 0 ? 0 : 5;
+}
+
+void test_sigpending_limit() {
+  pid_t pid = fork();
+  if (pid == 0) {
+    _exit(sigpending_limit());
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 0) {
+    puts("test_sigpending_limit ok");
+  }
+}
+
+static int ignore_pending() {
+  struct rlimit limit = {8, 8};
+  struct sigaction sa = {0};
+  sa.sa_sigaction = handler;
+  sa.sa_flags = SA_SIGINFO;
+  if (setrlimit(RLIMIT_SIGPENDING, &limit) != 0 ||
+      sigaction(SIGRTMIN, &sa, NULL) != 0) {
+    return 1;
+  }
+  block(SIGRTMIN);
+  for (int i = 0; i < 8; i++) {
+    if (queue(getpid(), SIGRTMIN, i) != 0) {
+      return 2;
+    }
+  }
+  // Ignoring the signal drops what is pending of it, which no longer counts
+  // against the limit.
+  sigset_t pending;
+  if (signal(SIGRTMIN, SIG_IGN) == SIG_ERR || sigpending(&pending) != 0 ||
+      sigismember(&pending, SIGRTMIN) || sigaction(SIGRTMIN, &sa, NULL) != 0) {
+    return 3;
+  }
+  for (int i = 0; i < 8; i++) {
+    if (queue(getpid(), SIGRTMIN, i) != 0) {
+      return 4;
+    }
+  }
+  return queue(getpid(), SIGRTMIN, 8) == -1 && errno == EAGAIN ? 0 : 5;
+}
+
+void test_ignore_pending() {
+  pid_t pid = fork();
+  if (pid == 0) {
+    _exit(ignore_pending());
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 0) {
+    puts("test_ignore_pending ok");
+  }
+}
+
+int main() {
+  test_producer_consumer();
+  test_handler_order();
+  test_standard_collapse();
+  test_sigpending_limit();
+  test_ignore_pending();
+  return 0;
+}
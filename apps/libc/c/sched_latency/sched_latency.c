@@ -0,0 +1,114 @@
+#define _GNU_SOURCE
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define ASPACE_SIZE (512UL << 20)
+#define TOUCHED_SIZE (4UL << 20)
+#define SLEEPS 100
+// How much longer than without load a sleep may oversleep.
+#define SLACK_NS 5000000L
+
+static long now_ns(void) {
+  struct timespec ts;
+  CHECK(clock_gettime(CLOCK_MONOTONIC, &ts) == 0);
+  return ts.tv_sec * 1000000000L + ts.tv_nsec;
+}
+
+// The most a nanosleep of 1ms overslept, over `SLEEPS` of them.
+static long max_oversleep(void) {
+  long max = 0;
+  for (int i = 0; i < SLEEPS; i++) {
+    struct timespec req = {.tv_sec = 0, .tv_nsec = 1000000};
+    long start = now_ns();
+    CHECK(nanosleep(&req, NULL) == 0);
+    long over = now_ns() - start - req.tv_nsec;
+    if (over > max)
+      max = over;
+  }
+  return max;
+}
+
+// Measure in a child process, which reports through a pipe.
+static pid_t start_sleeper(int *read_fd) {
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    close(fds[0]);
+    long max = max_oversleep();
+    CHECK(write(fds[1], &max, sizeof(max)) == sizeof(max));
+    _exit(0);
+  }
+  close(fds[1]);
+  *read_fd = fds[0];
+  return pid;
+}
+
+static long finish_sleeper(pid_t pid, int read_fd) {
+  long max;
+  CHECK(read(read_fd, &max, sizeof(max)) == sizeof(max));
+  close(read_fd);
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  return max;
+}
+
+static void test_fork_latency(void) {
+  int fd;
+  pid_t sleeper = start_sleeper(&fd);
+  long idle = finish_sleeper(sleeper, fd);
+
+  // A large address space, of which only the start is backed, so it takes
+  // long to go through without taking much memory.
+  char *mem = mmap(NULL, ASPACE_SIZE, PROT_READ | PROT_WRITE,
+                   MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  CHECK(mem != MAP_FAILED);
+  for (size_t off = 0; off < TOUCHED_SIZE; off += 4096)
+    mem[off] = 1;
+
+  sleeper = start_sleeper(&fd);
+  int forks = 0, status;
+  for (;;) {
+    pid_t pid = fork();
+    CHECK(pid >= 0);
+    if (pid == 0)
+      _exit(mem[0] == 1 ? 0 : 1);
+    CHECK(waitpid(pid, &status, 0) == pid);
+    CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+    forks++;
+    pid_t done = waitpid(sleeper, &status, WNOHANG);
+    CHECK(done >= 0);
+    if (done == sleeper)
+      break;
+  }
+  // Reaped above, read what it reported.
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  long max;
+  CHECK(read(fd, &max, sizeof(max)) == sizeof(max));
+  close(fd);
+
+  if (max > idle + SLACK_NS)
+    printf("oversleep %ld ns during %d forks, %ld ns idle\n", max, forks, idle);
+  CHECK(max <= idle + SLACK_NS);
+  munmap(mem, ASPACE_SIZE);
+  printf("test_fork_latency ok\n");
+}
+
+int main(void) {
+  test_fork_latency();
+  return 0;
+}
@@ -0,0 +1,177 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define FILE_PATH "/scm_rights_file"
+#define CONTENT "passed over a socket"
+// `SCM_MAX_FD` of the kernel.
+#define MAX_FDS 253
+
+// Send one byte with the `count` fds in `fds`.
+static ssize_t send_fds(int sock, const int *fds, int count) {
+  char data = 'f';
+  struct iovec iov = {.iov_base = &data, .iov_len = 1};
+  size_t size = CMSG_SPACE(count * sizeof(int));
+  char *control = calloc(1, size);
+  struct msghdr msg = {.msg_iov = &iov,
+                       .msg_iovlen = 1,
+                       .msg_control = control,
+                       .msg_controllen = size};
+  struct cmsghdr *cmsg = CMSG_FIRSTHDR(&msg);
+  cmsg->cmsg_level = SOL_SOCKET;
+  cmsg->cmsg_type = SCM_RIGHTS;
+  cmsg->cmsg_len = CMSG_LEN(count * sizeof(int));
+  memcpy(CMSG_DATA(cmsg), fds, count * sizeof(int));
+  ssize_t ret = sendmsg(sock, &msg, 0);
+  free(control);
+  return ret;
+}
+
+// Receive one byte, with room for `room` fds, storing those received in
+// `fds`. Returns how many were, or -1 if the control data was truncated.
+static int recv_fds(int sock, int *fds, int room, int flags) {
+  char data;
+  struct iovec iov = {.iov_base = &data, .iov_len = 1};
+  union {
+    char buf[CMSG_SPACE(4 * sizeof(int))];
+    struct cmsghdr align;
+  } control;
+  CHECK(room <= 4);
+  struct msghdr msg = {.msg_iov = &iov,
+                       .msg_iovlen = 1,
+                       .msg_control = control.buf,
+                       .msg_controllen = CMSG_SPACE(room * sizeof(int))};
+  CHECK(recvmsg(sock, &msg, flags) == 1 && data == 'f');
+  int count = 0;
+  struct cmsghdr *cmsg = CMSG_FIRSTHDR(&msg);
+  if (cmsg) {
+    CHECK(cmsg->cmsg_level == SOL_SOCKET && cmsg->cmsg_type == SCM_RIGHTS);
+    count = (cmsg->cmsg_len - CMSG_LEN(0)) / sizeof(int);
+    memcpy(fds, CMSG_DATA(cmsg), count * sizeof(int));
+  }
+  return msg.msg_flags & MSG_CTRUNC ? -1 : count;
+}
+
+static void test_pass_file(void) {
+  int file = open(FILE_PATH, O_RDWR | O_CREAT | O_TRUNC, 0644);
+  CHECK(file >= 0);
+  CHECK(write(file, CONTENT, strlen(CONTENT)) == strlen(CONTENT));
+  int sv[2];
+  CHECK(socketpair(AF_UNIX, SOCK_STREAM, 0, sv) == 0);
+  // Kept alive in flight, though closed.
+  CHECK(send_fds(sv[0], &file, 1) == 1);
+  close(file);
+
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    close(sv[0]);
+    int fd;
+    CHECK(recv_fds(sv[1], &fd, 1, MSG_CMSG_CLOEXEC) == 1);
+    CHECK(fcntl(fd, F_GETFD) == FD_CLOEXEC);
+    char buf[64] = {0};
+    // The offset is shared with the sender.
+    CHECK(lseek(fd, 0, SEEK_CUR) == strlen(CONTENT));
+    CHECK(pread(fd, buf, sizeof(buf), 0) == strlen(CONTENT));
+    CHECK(strcmp(buf, CONTENT) == 0);
+    _exit(0);
+  }
+  close(sv[1]);
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  close(sv[0]);
+  unlink(FILE_PATH);
+  printf("test_pass_file ok\n");
+}
+
+static void test_truncated(void) {
+  int sv[2], fds[3], got[4];
+  CHECK(socketpair(AF_UNIX, SOCK_STREAM, 0, sv) == 0);
+  for (int i = 0; i < 3; i++)
+    CHECK((fds[i] = dup(0)) >= 0);
+  CHECK(send_fds(sv[0], fds, 3) == 1);
+  // Room for one, the others are closed.
+  CHECK(recv_fds(sv[1], got, 1, 0) == -1);
+  CHECK(fcntl(got[0], F_GETFD) == 0);
+  close(got[0]);
+
+  // Without any room.
+  CHECK(send_fds(sv[0], fds, 3) == 1);
+  CHECK(recv_fds(sv[1], got, 0, 0) == -1);
+
+  CHECK(send_fds(sv[0], fds, 3) == 1);
+  CHECK(recv_fds(sv[1], got, 3, 0) == 3);
+  for (int i = 0; i < 3; i++) {
+    close(got[i]);
+    close(fds[i]);
+  }
+  close(sv[0]);
+  close(sv[1]);
+  printf("test_truncated ok\n");
+}
+
+static void test_limits(void) {
+  int sv[2], fds[MAX_FDS + 1];
+  CHECK(socketpair(AF_UNIX, SOCK_STREAM, 0, sv) == 0);
+  for (int i = 0; i <= MAX_FDS; i++)
+    fds[i] = 0;
+  CHECK(send_fds(sv[0], fds, MAX_FDS + 1) == -1 && errno == EINVAL);
+  fds[0] = 1000;
+  CHECK(send_fds(sv[0], fds, 1) == -1 && errno == EBADF);
+  CHECK(send_fds(sv[0], fds + 1, MAX_FDS) == 1);
+  close(sv[0]);
+  close(sv[1]);
+  printf("test_limits ok\n");
+}
+
+// Get the number of live files from `/proc/sys/fs/file-nr`.
+static long live_files(void) {
+  char buf[64];
+  int fd = open("/proc/sys/fs/file-nr", O_RDONLY);
+  CHECK(fd >= 0);
+  ssize_t len = read(fd, buf, sizeof(buf) - 1);
+  CHECK(len > 0);
+  close(fd);
+  buf[len] = 0;
+  long count;
+  CHECK(sscanf(buf, "%ld", &count) == 1);
+  return count;
+}
+
+static void test_no_leak(void) {
+  long before = live_files();
+  int sv[2], pipes[2];
+  CHECK(socketpair(AF_UNIX, SOCK_STREAM, 0, sv) == 0);
+  CHECK(pipe(pipes) == 0);
+  CHECK(send_fds(sv[0], pipes, 2) == 1);
+  close(pipes[0]);
+  close(pipes[1]);
+  // Never received.
+  close(sv[0]);
+  close(sv[1]);
+  CHECK(live_files() == before);
+  printf("test_no_leak ok\n");
+}
+
+int main(void) {
+  test_pass_file();
+  test_truncated();
+  test_limits();
+  test_no_leak();
+  return 0;
+}
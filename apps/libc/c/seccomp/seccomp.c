@@ -0,0 +1,143 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <signal.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/prctl.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define PR_SET_SYSCALL_FILTER 0x53595300
+#define FILTER_ALLOW 0
+#define FILTER_ERRNO 1
+#define FILTER_TRAP 2
+#define SYS_SECCOMP 1
+#define MAX_FILTERS 64
+
+static unsigned char bitmap[64];
+
+static void allow(int sysno) { bitmap[sysno / 8] |= 1 << (sysno % 8); }
+
+// Confine the process to the syscalls needed to print and exit, plus
+// `extra` if it is not -1.
+static int confine(int action, int extra) {
+  memset(bitmap, 0, sizeof(bitmap));
+  if (extra != -1) {
+    allow(extra);
+  }
+  allow(SYS_read);
+  allow(SYS_write);
+  allow(SYS_writev);
+  allow(SYS_exit);
+  allow(SYS_exit_group);
+  allow(SYS_rt_sigreturn);
+  allow(SYS_rt_sigprocmask);
+  return prctl(PR_SET_SYSCALL_FILTER, action, bitmap, sizeof(bitmap), 0);
+}
+
+static volatile int trapped;
+
+static void on_sigsys(int signo, siginfo_t *info, void *ctx) {
+  if (info->si_code == SYS_SECCOMP && info->si_syscall == SYS_openat) {
+    trapped = 1;
+  }
+}
+
+// Run `func` in a child and check it exits with 0.
+static int run_child(void (*func)(void)) {
+  pid_t pid = fork();
+  if (pid == 0) {
+    func();
+    _exit(1);
+  }
+  int status;
+  return waitpid(pid, &status, 0) == pid && WIFEXITED(status) && WEXITSTATUS(status) == 0;
+}
+
+static void child_trap(void) {
+  struct sigaction sa = {0};
+  sa.sa_sigaction = on_sigsys;
+  sa.sa_flags = SA_SIGINFO;
+  sigaction(SIGSYS, &sa, NULL);
+  if (confine(FILTER_TRAP, -1) != 0) {
+    return;
+  }
+  int ret = syscall(SYS_openat, AT_FDCWD, "/", O_RDONLY);
+  if (ret == -1 && errno == ENOSYS && trapped) {
+    _exit(0);
+  }
+}
+
+static void child_errno(void) {
+  if (confine(FILTER_ERRNO, -1) != 0) {
+    return;
+  }
+  int ret = syscall(SYS_openat, AT_FDCWD, "/", O_RDONLY);
+  if (ret == -1 && errno == ENOSYS && syscall(SYS_getpid) == -1) {
+    _exit(0);
+  }
+}
+
+// A filter can not be loosened by installing another one.
+static void child_tighten(void) {
+  if (confine(FILTER_ERRNO, SYS_prctl) != 0) {
+    return;
+  }
+  memset(bitmap, 0xff, sizeof(bitmap));
+  if (prctl(PR_SET_SYSCALL_FILTER, FILTER_ALLOW, bitmap, sizeof(bitmap), 0) != -1 &&
+      syscall(SYS_getpid) == -1 && errno == ENOSYS) {
+    _exit(0);
+  }
+}
+
+// Filters are inherited by children.
+static void child_inherit(void) {
+  memset(bitmap, 0xff, sizeof(bitmap));
+  bitmap[SYS_getppid / 8] &= ~(1 << (SYS_getppid % 8));
+  if (prctl(PR_SET_SYSCALL_FILTER, FILTER_ERRNO, bitmap, sizeof(bitmap), 0) != 0) {
+    return;
+  }
+  pid_t pid = fork();
+  if (pid == 0) {
+    _exit(syscall(SYS_getppid) == -1 && errno == ENOSYS ? 0 : 1);
+  }
+  int status;
+  if (waitpid(pid, &status, 0) == pid && WIFEXITED(status) && WEXITSTATUS(status) == 0 &&
+      prctl(PR_GET_SECCOMP) == 2) {
+    _exit(0);
+  }
+}
+
+// The filters of a process are capped, like Linux caps their instructions.
+static void child_limit(void) {
+  memset(bitmap, 0xff, sizeof(bitmap));
+  int installed = 0;
+  while (installed <= MAX_FILTERS &&
+         prctl(PR_SET_SYSCALL_FILTER, FILTER_ERRNO, bitmap, sizeof(bitmap), 0) == 0) {
+    installed++;
+  }
+  if (installed == MAX_FILTERS && errno == ENOMEM && syscall(SYS_getpid) > 0) {
+    _exit(0);
+  }
+}
+
+int main() {
+  if (run_child(child_trap)) {
+    puts("test_seccomp_trap ok");
+  }
+  if (run_child(child_errno)) {
+    puts("test_seccomp_errno ok");
+  }
+  if (run_child(child_tighten)) {
+    puts("test_seccomp_tighten ok");
+  }
+  if (run_child(child_inherit)) {
+    puts("test_seccomp_inherit ok");
+  }
+  if (run_child(child_limit)) {
+    puts("test_seccomp_limit ok");
+  }
+  return 0;
+}
@@ -0,0 +1,155 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <poll.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/resource.h>
+#include <sys/select.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define ROUNDS 2000
+
+static double now(void) {
+  struct timespec ts;
+  clock_gettime(CLOCK_MONOTONIC, &ts);
+  return ts.tv_sec + ts.tv_nsec / 1e9;
+}
+
+// Negative descriptors in a pollfd array are skipped, not reported.
+void test_negative_fds() {
+  int p[2];
+  CHECK(pipe(p) == 0);
+  CHECK(write(p[1], "x", 1) == 1);
+  struct pollfd fds[] = {
+      {.fd = -1, .events = POLLIN},
+      {.fd = p[0], .events = POLLIN},
+      {.fd = -100, .events = POLLIN | POLLOUT},
+  };
+  CHECK(poll(fds, 3, 0) == 1);
+  CHECK(fds[0].revents == 0);
+  CHECK(fds[1].revents == POLLIN);
+  CHECK(fds[2].revents == 0);
+  // Only negative ones wait for the timeout, and time out.
+  fds[1].fd = -p[0] - 1;
+  CHECK(poll(fds, 3, 10) == 0);
+  close(p[0]);
+  close(p[1]);
+  puts("test_negative_fds ok");
+}
+
+// nfds past RLIMIT_NOFILE is refused by select and poll, and a set with a
+// descriptor which is not open by select.
+void test_nfds_limit() {
+  struct rlimit old, lim;
+  CHECK(getrlimit(RLIMIT_NOFILE, &old) == 0);
+  lim = old;
+  lim.rlim_cur = 64;
+  CHECK(setrlimit(RLIMIT_NOFILE, &lim) == 0);
+
+  fd_set set;
+  FD_ZERO(&set);
+  struct timeval tv = {0, 0};
+  CHECK(select(65, &set, NULL, NULL, &tv) == -1 && errno == EINVAL);
+  CHECK(select(-1, &set, NULL, NULL, &tv) == -1 && errno == EINVAL);
+  CHECK(select(64, &set, NULL, NULL, &tv) == 0);
+  static struct pollfd many[65];
+  for (int i = 0; i < 65; i++)
+    many[i].fd = -1;
+  CHECK(poll(many, 65, 0) == -1 && errno == EINVAL);
+  CHECK(poll(many, 64, 0) == 0);
+
+  FD_SET(60, &set);
+  CHECK(select(64, &set, NULL, NULL, &tv) == -1 && errno == EBADF);
+  CHECK(setrlimit(RLIMIT_NOFILE, &old) == 0);
+  puts("test_nfds_limit ok");
+}
+
+// select reports what is ready, leaving only those in the sets.
+void test_ready_sets() {
+  int p[2];
+  CHECK(pipe(p) == 0);
+  fd_set rd, wr;
+  FD_ZERO(&rd);
+  FD_ZERO(&wr);
+  FD_SET(p[0], &rd);
+  FD_SET(p[1], &wr);
+  struct timeval tv = {0, 0};
+  CHECK(select(p[1] + 1, &rd, &wr, NULL, &tv) == 1);
+  CHECK(!FD_ISSET(p[0], &rd));
+  CHECK(FD_ISSET(p[1], &wr));
+
+  CHECK(write(p[1], "x", 1) == 1);
+  FD_SET(p[0], &rd);
+  FD_SET(p[1], &wr);
+  CHECK(select(FD_SETSIZE, &rd, &wr, NULL, &tv) == 2);
+  CHECK(FD_ISSET(p[0], &rd) && FD_ISSET(p[1], &wr));
+
+  // A hangup is readable.
+  close(p[1]);
+  char c;
+  CHECK(read(p[0], &c, 1) == 1);
+  FD_ZERO(&rd);
+  FD_SET(p[0], &rd);
+  CHECK(select(p[0] + 1, &rd, NULL, NULL, NULL) == 1);
+  CHECK(FD_ISSET(p[0], &rd));
+  close(p[0]);
+  puts("test_ready_sets ok");
+}
+
+static double select_cost(int nfds, int fds[4]) {
+  double start = now();
+  for (int i = 0; i < ROUNDS; i++) {
+    fd_set rd;
+    FD_ZERO(&rd);
+    for (int j = 0; j < 4; j++)
+      FD_SET(fds[j], &rd);
+    struct timeval tv = {0, 0};
+    CHECK(select(nfds, &rd, NULL, NULL, &tv) == 4);
+  }
+  return now() - start;
+}
+
+// With 4 descriptors, nfds of FD_SETSIZE costs about as much as nfds just
+// past them.
+void test_large_nfds_cost() {
+  int p[2], q[2];
+  CHECK(pipe(p) == 0 && pipe(q) == 0);
+  CHECK(write(p[1], "x", 1) == 1 && write(q[1], "x", 1) == 1);
+  int fds[4] = {p[0], q[0], dup(p[0]), dup(q[0])};
+  CHECK(fds[2] >= 0 && fds[3] >= 0);
+  int nfds = fds[3] + 1;
+
+  // Warm up, then take the best of a few runs of each.
+  select_cost(nfds, fds);
+  double small = 1e9, large = 1e9;
+  for (int i = 0; i < 3; i++) {
+    double t = select_cost(nfds, fds);
+    small = t < small ? t : small;
+    t = select_cost(FD_SETSIZE, fds);
+    large = t < large ? t : large;
+  }
+  CHECK(large < small * 2 + 0.01);
+
+  for (int i = 0; i < 4; i++)
+    close(fds[i]);
+  close(p[1]);
+  close(q[1]);
+  puts("test_large_nfds_cost ok");
+}
+
+int main() {
+  test_negative_fds();
+  test_nfds_limit();
+  test_ready_sets();
+  test_large_nfds_cost();
+  return 0;
+}
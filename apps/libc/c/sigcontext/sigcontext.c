@@ -0,0 +1,293 @@
+#define _GNU_SOURCE
+#include <setjmp.h>
+#include <signal.h>
+#include <stdio.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+#include <ucontext.h>
+#include <unistd.h>
+
+// Return 1 after loading a byte from `addr`. A handler can make it return 0
+// by skipping the load and clearing the register holding the result.
+int probe(const volatile char *addr);
+
+// Return `x` after loading a byte from `addr`, with `x` in the first FP
+// register meanwhile, where it is returned from.
+double fp_probe(const volatile char *addr, double x);
+
+#if defined(__x86_64__)
+__asm__(".text\n"
+        ".globl probe\n"
+        "probe:\n"
+        "  movl $1, %eax\n"
+        "  movb (%rdi), %cl\n"
+        "  ret\n"
+        ".globl fp_probe\n"
+        "fp_probe:\n"
+        "  movb (%rdi), %cl\n"
+        "  ret\n");
+#define LOAD_SIZE 2
+#define PC(uc) ((uc)->uc_mcontext.gregs[REG_RIP])
+#define RESULT(uc) ((uc)->uc_mcontext.gregs[REG_RAX])
+#define FP_RESULT(uc) ((double *)&(uc)->uc_mcontext.fpregs->_xmm[0])
+#define CLOBBER_FP() __asm__ volatile("xorps %%xmm0, %%xmm0" ::: "xmm0")
+#elif defined(__aarch64__)
+__asm__(".text\n"
+        ".globl probe\n"
+        "probe:\n"
+        "  mov x1, #1\n"
+        "  ldrb w2, [x0]\n"
+        "  mov x0, x1\n"
+        "  ret\n"
+        ".globl fp_probe\n"
+        "fp_probe:\n"
+        "  ldrb w2, [x0]\n"
+        "  ret\n");
+#define LOAD_SIZE 4
+#define PC(uc) ((uc)->uc_mcontext.pc)
+#define RESULT(uc) ((uc)->uc_mcontext.regs[1])
+// The `vregs` of the `fpsimd_context` record, the first of `__reserved`.
+#define FP_RESULT(uc) ((double *)((char *)(uc)->uc_mcontext.__reserved + 16))
+#define CLOBBER_FP() __asm__ volatile("movi d0, #0" ::: "v0")
+#elif defined(__riscv)
+__asm__(".text\n"
+        ".option push\n"
+        ".option norvc\n"
+        ".globl probe\n"
+        "probe:\n"
+        "  li a1, 1\n"
+        "  lb t0, 0(a0)\n"
+        "  mv a0, a1\n"
+        "  ret\n"
+        ".globl fp_probe\n"
+        "fp_probe:\n"
+        "  lb t0, 0(a0)\n"
+        "  ret\n"
+        ".option pop\n");
+#define LOAD_SIZE 4
+#define PC(uc) ((uc)->uc_mcontext.__gregs[0])
+#define RESULT(uc) ((uc)->uc_mcontext.__gregs[11])
+#define FP_RESULT(uc) ((double *)&(uc)->uc_mcontext.__fpregs.__d.__f[10])
+#define CLOBBER_FP() __asm__ volatile("fmv.d.x fa0, zero" ::: "fa0")
+#elif defined(__loongarch64)
+__asm__(".text\n"
+        ".globl probe\n"
+        "probe:\n"
+        "  li.d $a1, 1\n"
+        "  ld.b $t0, $a0, 0\n"
+        "  move $a0, $a1\n"
+        "  jr $ra\n"
+        ".globl fp_probe\n"
+        "fp_probe:\n"
+        "  ld.b $t0, $a0, 0\n"
+        "  jr $ra\n");
+#define LOAD_SIZE 4
+#define PC(uc) ((uc)->uc_mcontext.__pc)
+#define RESULT(uc) ((uc)->uc_mcontext.__gregs[5])
+// The frame holds no FP state on loongarch64 yet.
+#define FP_IN_FRAME 0
+#define FP_RESULT(uc) ((double *)NULL)
+#define CLOBBER_FP()
+#else
+#error "unsupported architecture"
+#endif
+
+#ifndef FP_IN_FRAME
+#define FP_IN_FRAME 1
+#endif
+
+static char *fault_addr;
+static volatile int bad_context;
+static sigjmp_buf env;
+
+// An address with nothing mapped.
+static char *unmapped_page() {
+  char *page = mmap(NULL, 4096, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  munmap(page, 4096);
+  return page;
+}
+
+static void block(int signo) {
+  sigset_t set;
+  sigemptyset(&set);
+  sigaddset(&set, signo);
+  sigprocmask(SIG_BLOCK, &set, NULL);
+}
+
+static int is_blocked(int signo) {
+  sigset_t set;
+  sigprocmask(SIG_BLOCK, NULL, &set);
+  return sigismember(&set, signo);
+}
+
+static void skip_handler(int signo, siginfo_t *info, void *ctx) {
+  ucontext_t *uc = ctx;
+  // The frame holds the mask before the handler, which the handler runs
+  // with SIGSEGV added to.
+  if (info->si_addr != fault_addr || !sigismember(&uc->uc_sigmask, SIGUSR1) ||
+      sigismember(&uc->uc_sigmask, SIGSEGV) || !is_blocked(SIGSEGV)) {
+    bad_context = 1;
+  }
+  PC(uc) += LOAD_SIZE;
+  RESULT(uc) = 0;
+}
+
+static int skip_pc() {
+  struct sigaction sa = {0};
+  sa.sa_sigaction = skip_handler;
+  sa.sa_flags = SA_SIGINFO;
+  sigaction(SIGSEGV, &sa, NULL);
+  block(SIGUSR1);
+  fault_addr = unmapped_page();
+  for (int i = 0; i < 2; i++) {
+    if (probe(fault_addr) != 0) {
+      return 1;
+    }
+  }
+  if (bad_context) {
+    return 2;
+  }
+  // The mask of the frame is restored on return.
+  return is_blocked(SIGUSR1) && !is_blocked(SIGSEGV) ? 0 : 3;
+}
+
+static void jump_handler(int signo, siginfo_t *info, void *ctx) {
+  siglongjmp(env, 1);
+}
+
+static int longjmp_out() {
+  struct sigaction sa = {0};
+  sa.sa_sigaction = jump_handler;
+  sa.sa_flags = SA_SIGINFO;
+  sigaction(SIGSEGV, &sa, NULL);
+  fault_addr = unmapped_page();
+  volatile int faults = 0;
+  sigsetjmp(env, 1);
+  if (faults < 2) {
+    faults++;
+    probe(fault_addr);
+    return 1;
+  }
+  // The mask saved by `sigsetjmp` is back, so the second fault was caught.
+  return is_blocked(SIGSEGV) ? 2 : 0;
+}
+
+static int fault_codes[2];
+static int fault_count;
+
+static void code_handler(int signo, siginfo_t *info, void *ctx) {
+  ucontext_t *uc = ctx;
+  if (fault_count < 2) {
+    fault_codes[fault_count] = info->si_code;
+  }
+  fault_count++;
+  PC(uc) += LOAD_SIZE;
+  RESULT(uc) = 0;
+}
+
+// A fault on an address with nothing mapped is SEGV_MAPERR, one on a mapped
+// page the access is not allowed to SEGV_ACCERR.
+static int fault_code() {
+  struct sigaction sa = {0};
+  sa.sa_sigaction = code_handler;
+  sa.sa_flags = SA_SIGINFO;
+  sigaction(SIGSEGV, &sa, NULL);
+  char *none = mmap(NULL, 4096, PROT_NONE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  if (none == MAP_FAILED || probe(unmapped_page()) != 0 || probe(none) != 0) {
+    return 1;
+  }
+  if (fault_count != 2 || fault_codes[0] != SEGV_MAPERR ||
+      fault_codes[1] != SEGV_ACCERR) {
+    return 2;
+  }
+  return 0;
+}
+
+static stack_t seen_stack;
+
+static void stack_handler(int signo, siginfo_t *info, void *ctx) {
+  ucontext_t *uc = ctx;
+  seen_stack = uc->uc_stack;
+  uc->uc_stack.ss_flags = SS_DISABLE;
+}
+
+// `uc_stack` is the alternate stack of the thread, which it gets back from
+// there on return.
+static int uc_stack() {
+  stack_t alt = {0}, now;
+  alt.ss_size = 65536;
+  alt.ss_sp = mmap(NULL, alt.ss_size, PROT_READ | PROT_WRITE,
+                   MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  struct sigaction sa = {0};
+  sa.sa_sigaction = stack_handler;
+  sa.sa_flags = SA_SIGINFO | SA_ONSTACK;
+  if (alt.ss_sp == MAP_FAILED || sigaltstack(&alt, NULL) != 0 ||
+      sigaction(SIGUSR1, &sa, NULL) != 0 || raise(SIGUSR1) != 0) {
+    return 1;
+  }
+  if (seen_stack.ss_sp != alt.ss_sp || seen_stack.ss_size != alt.ss_size ||
+      seen_stack.ss_flags != 0) {
+    return 2;
+  }
+  return sigaltstack(NULL, &now) == 0 && (now.ss_flags & SS_DISABLE) ? 0 : 3;
+}
+
+static double fp_saved, fp_result;
+static volatile int bad_fp;
+
+static void fp_handler(int signo, siginfo_t *info, void *ctx) {
+  ucontext_t *uc = ctx;
+  double *result = FP_RESULT(uc);
+  if (*result != fp_saved) {
+    bad_fp = 1;
+  }
+  CLOBBER_FP();
+  if (fp_result != fp_saved) {
+    *result = fp_result;
+  }
+  PC(uc) += LOAD_SIZE;
+}
+
+// The FP registers of the code a signal interrupts are saved in the frame,
+// and restored from there as the handler leaves them, whether it uses the
+// FPU itself or changes them.
+static int fp_state() {
+  if (!FP_IN_FRAME) {
+    return 0;
+  }
+  struct sigaction sa = {0};
+  sa.sa_sigaction = fp_handler;
+  sa.sa_flags = SA_SIGINFO;
+  sigaction(SIGSEGV, &sa, NULL);
+  fault_addr = unmapped_page();
+  fp_saved = fp_result = 2.5;
+  if (fp_probe(fault_addr, 2.5) != 2.5) {
+    return 1;
+  }
+  fp_result = -7.25;
+  if (fp_probe(fault_addr, 2.5) != -7.25) {
+    return 2;
+  }
+  return bad_fp ? 3 : 0;
+}
+
+static void run(const char *name, int (*test)()) {
+  pid_t pid = fork();
+  if (pid == 0) {
+    _exit(test());
+  }
+  int status;
+  waitpid(pid, &status, 0);
+  if (WIFEXITED(status) && WEXITSTATUS(status) == 0) {
+    printf("%s ok\n", name);
+  }
+}
+
+int main() {
+  run("test_skip_pc", skip_pc);
+  run("test_longjmp_out", longjmp_out);
+  run("test_fault_code", fault_code);
+  run("test_uc_stack", uc_stack);
+  run("test_fp_state", fp_state);
+  return 0;
+}
@@ -0,0 +1,72 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <pthread.h>
+#include <signal.h>
+#include <stdio.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+static volatile pid_t received_pid = -1;
+
+static void handler(int signo, siginfo_t *info, void *ctx) {
+  received_pid = info->si_pid;
+}
+
+static void install(void) {
+  struct sigaction sa = {0};
+  sa.sa_sigaction = handler;
+  sa.sa_flags = SA_SIGINFO;
+  sigaction(SIGUSR1, &sa, NULL);
+}
+
+void test_kill_pid() {
+  received_pid = -1;
+  kill(getpid(), SIGUSR1);
+  if (received_pid == getpid()) {
+    puts("test_kill_pid ok");
+  }
+}
+
+void test_tgkill_pid() {
+  received_pid = -1;
+  syscall(SYS_tgkill, getpid(), syscall(SYS_gettid), SIGUSR1);
+  if (received_pid == getpid()) {
+    puts("test_tgkill_pid ok");
+  }
+}
+
+static volatile pid_t child_tid;
+
+static void *exit_now(void *arg) {
+  child_tid = syscall(SYS_gettid);
+  return NULL;
+}
+
+// Signal threads while they are exiting: every attempt must either succeed
+// or fail with ESRCH.
+void test_tgkill_exit_race() {
+  for (int i = 0; i < 200; i++) {
+    pthread_t thread;
+    child_tid = 0;
+    if (pthread_create(&thread, NULL, exit_now, NULL) != 0) {
+      return;
+    }
+    while (!child_tid)
+      ;
+    int ret = syscall(SYS_tgkill, getpid(), child_tid, SIGUSR1);
+    if (ret != 0 && errno != ESRCH) {
+      printf("tgkill failed: %d\n", errno);
+      return;
+    }
+    pthread_join(thread, NULL);
+  }
+  puts("test_tgkill_exit_race ok");
+}
+
+int main() {
+  install();
+  test_kill_pid();
+  test_tgkill_pid();
+  test_tgkill_exit_race();
+  return 0;
+}
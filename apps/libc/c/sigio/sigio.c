@@ -0,0 +1,74 @@
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <poll.h>
+#include <signal.h>
+#include <stdio.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+static volatile sig_atomic_t got_fd = -1, got_code, got_band;
+
+static void on_sigio(int sig, siginfo_t *info, void *ctx) {
+  got_fd = info->si_fd;
+  got_code = info->si_code;
+  got_band = info->si_band;
+}
+
+// The owner of the read end of a pipe gets `SIGIO` with the descriptor once
+// a child writes to it.
+void test_pipe_sigio() {
+  struct sigaction sa = {0};
+  sa.sa_sigaction = on_sigio;
+  sa.sa_flags = SA_SIGINFO;
+  sigaction(SIGIO, &sa, NULL);
+
+  int fds[2];
+  if (pipe(fds) < 0 || fcntl(fds[0], F_SETOWN, getpid()) < 0 ||
+      fcntl(fds[0], F_SETFL, O_ASYNC) < 0 ||
+      fcntl(fds[0], F_GETOWN) != getpid()) {
+    return;
+  }
+  pid_t pid = fork();
+  if (pid == 0) {
+    usleep(10000);
+    write(fds[1], "x", 1);
+    _exit(0);
+  }
+  for (int i = 0; i < 100 && got_fd < 0; i++) {
+    usleep(10000);
+  }
+  waitpid(pid, NULL, 0);
+  if (got_fd == fds[0] && got_code == POLL_IN && (got_band & POLLIN)) {
+    puts("test_pipe_sigio ok");
+  }
+  close(fds[0]);
+  close(fds[1]);
+  signal(SIGIO, SIG_DFL);
+}
+
+// Without `O_ASYNC`, the owner gets no signal.
+void test_no_async() {
+  int fds[2];
+  if (pipe(fds) < 0 || fcntl(fds[0], F_SETOWN, getpid()) < 0) {
+    return;
+  }
+  got_fd = -1;
+  struct sigaction sa = {0};
+  sa.sa_sigaction = on_sigio;
+  sa.sa_flags = SA_SIGINFO;
+  sigaction(SIGIO, &sa, NULL);
+  write(fds[1], "x", 1);
+  usleep(10000);
+  if (got_fd < 0) {
+    puts("test_no_async ok");
+  }
+  close(fds[0]);
+  close(fds[1]);
+  signal(SIGIO, SIG_DFL);
+}
+
+int main() {
+  test_pipe_sigio();
+  test_no_async();
+  return 0;
+}
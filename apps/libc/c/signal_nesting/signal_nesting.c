@@ -0,0 +1,318 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <setjmp.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+// `MAX_SIGNAL_NESTING` of the kernel.
+#define MAX_NESTING 64
+#define ALTSTACK_SIZE (1 << 20)
+
+static void set_handler(int signo, void (*handler)(int), int flags) {
+  struct sigaction sa = {0};
+  sa.sa_handler = handler;
+  sa.sa_flags = flags;
+  CHECK(sigaction(signo, &sa, NULL) == 0);
+}
+
+static int is_blocked(int signo) {
+  sigset_t set;
+  CHECK(sigprocmask(SIG_BLOCK, NULL, &set) == 0);
+  return sigismember(&set, signo);
+}
+
+static void set_altstack(void) {
+  stack_t ss = {0};
+  ss.ss_sp = mmap(NULL, ALTSTACK_SIZE, PROT_READ | PROT_WRITE,
+                  MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+  CHECK(ss.ss_sp != MAP_FAILED);
+  ss.ss_size = ALTSTACK_SIZE;
+  CHECK(sigaltstack(&ss, NULL) == 0);
+}
+
+static volatile int depth, max_depth, calls;
+
+static void count_handler(int signo) {
+  calls++;
+  if (++depth > max_depth)
+    max_depth = depth;
+  if (calls < 3)
+    raise(signo);
+  depth--;
+}
+
+static void test_nodefer(void) {
+  // Blocked in its own handler, so it runs again after.
+  set_handler(SIGUSR1, count_handler, 0);
+  calls = max_depth = 0;
+  raise(SIGUSR1);
+  CHECK(calls == 3 && max_depth == 1);
+
+  // Unless SA_NODEFER.
+  set_handler(SIGUSR1, count_handler, SA_NODEFER);
+  calls = max_depth = 0;
+  raise(SIGUSR1);
+  CHECK(calls == 3 && max_depth == 3);
+
+  set_handler(SIGUSR1, SIG_DFL, 0);
+  printf("test_nodefer ok\n");
+}
+
+static uintptr_t outer_sp, inner_sp;
+static int outer_onstack, set_errno;
+
+static void inner_altstack_handler(int signo) {
+  char local;
+  inner_sp = (uintptr_t)&local;
+}
+
+static void outer_altstack_handler(int signo) {
+  char local;
+  outer_sp = (uintptr_t)&local;
+  stack_t old;
+  CHECK(sigaltstack(NULL, &old) == 0);
+  outer_onstack = old.ss_flags & SS_ONSTACK;
+  // Not to be changed while in use.
+  stack_t ss = {.ss_sp = old.ss_sp, .ss_size = old.ss_size};
+  set_errno = sigaltstack(&ss, NULL) == -1 ? errno : 0;
+  raise(SIGUSR2);
+}
+
+static void test_altstack(void) {
+  set_altstack();
+  stack_t ss;
+  CHECK(sigaltstack(NULL, &ss) == 0);
+  CHECK(!(ss.ss_flags & (SS_ONSTACK | SS_DISABLE)));
+  uintptr_t lo = (uintptr_t)ss.ss_sp, hi = lo + ss.ss_size;
+
+  set_handler(SIGUSR1, outer_altstack_handler, SA_ONSTACK);
+  set_handler(SIGUSR2, inner_altstack_handler, SA_ONSTACK);
+  raise(SIGUSR1);
+  CHECK(outer_onstack && set_errno == EPERM);
+  CHECK(outer_sp > lo && outer_sp < hi);
+  // Nested below the outer handler, not over it from the top.
+  CHECK(inner_sp > lo && inner_sp < outer_sp);
+
+  ss.ss_flags = SS_DISABLE;
+  CHECK(sigaltstack(&ss, NULL) == 0);
+  set_handler(SIGUSR1, SIG_DFL, 0);
+  set_handler(SIGUSR2, SIG_DFL, 0);
+  printf("test_altstack ok\n");
+}
+
+static volatile int interrupted;
+static int sleep_result, sleep_errno;
+static struct timespec sleep_rem;
+
+static void interrupt_handler(int signo) { interrupted = 1; }
+
+static void sleeping_handler(int signo) {
+  struct timespec req = {.tv_sec = 0, .tv_nsec = 500000000};
+  sleep_result = nanosleep(&req, &sleep_rem);
+  sleep_errno = errno;
+}
+
+static long now_ms(void) {
+  struct timespec ts;
+  CHECK(clock_gettime(CLOCK_MONOTONIC, &ts) == 0);
+  return ts.tv_sec * 1000 + ts.tv_nsec / 1000000;
+}
+
+static void test_interrupted_sleep(void) {
+  set_handler(SIGUSR1, sleeping_handler, 0);
+  set_handler(SIGUSR2, interrupt_handler, 0);
+  sigset_t set, empty;
+  sigemptyset(&set);
+  sigaddset(&set, SIGUSR1);
+  sigaddset(&set, SIGUSR2);
+  sigemptyset(&empty);
+  CHECK(sigprocmask(SIG_BLOCK, &set, NULL) == 0);
+
+  long start = now_ms();
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    kill(getppid(), SIGUSR1);
+    usleep(50000);
+    kill(getppid(), SIGUSR2);
+    _exit(0);
+  }
+  // The handler of SIGUSR1 sleeps, until SIGUSR2 is handled.
+  CHECK(sigsuspend(&empty) == -1 && errno == EINTR);
+  CHECK(interrupted);
+  CHECK(sleep_result == -1 && sleep_errno == EINTR);
+  CHECK(sleep_rem.tv_sec == 0 && sleep_rem.tv_nsec > 0 &&
+        sleep_rem.tv_nsec < 500000000);
+  CHECK(now_ms() - start < 400);
+  // The mask is back as before sigsuspend.
+  CHECK(is_blocked(SIGUSR1) && is_blocked(SIGUSR2));
+
+  CHECK(waitpid(pid, NULL, 0) == pid);
+  CHECK(sigprocmask(SIG_UNBLOCK, &set, NULL) == 0);
+  set_handler(SIGUSR1, SIG_DFL, 0);
+  set_handler(SIGUSR2, SIG_DFL, 0);
+  printf("test_interrupted_sleep ok\n");
+}
+
+static pid_t peer;
+static int depth_fd;
+
+// Send the peer the signal, and wait in the handler for it to send it back.
+static void storm_handler(int signo) {
+  CHECK(write(depth_fd, "x", 1) == 1);
+  kill(peer, SIGUSR1);
+  pause();
+}
+
+static void storm_child(int fd) {
+  depth_fd = fd;
+  set_altstack();
+  set_handler(SIGUSR1, storm_handler, SA_NODEFER | SA_ONSTACK);
+}
+
+static int count_bytes(int fd) {
+  char buf[256];
+  int count = 0;
+  ssize_t n;
+  while ((n = read(fd, buf, sizeof(buf))) > 0)
+    count += n;
+  CHECK(n == 0);
+  return count;
+}
+
+static void test_storm(void) {
+  int depth_a[2], depth_b[2], ready[2], pids[2];
+  CHECK(pipe(depth_a) == 0 && pipe(depth_b) == 0);
+  CHECK(pipe(ready) == 0 && pipe(pids) == 0);
+
+  pid_t a = fork();
+  CHECK(a >= 0);
+  if (a == 0) {
+    close(depth_a[0]);
+    close(depth_b[0]);
+    close(depth_b[1]);
+    close(ready[0]);
+    close(ready[1]);
+    close(pids[1]);
+    storm_child(depth_a[1]);
+    CHECK(read(pids[0], &peer, sizeof(peer)) == sizeof(peer));
+    kill(peer, SIGUSR1);
+    for (;;)
+      pause();
+  }
+  pid_t b = fork();
+  CHECK(b >= 0);
+  if (b == 0) {
+    close(depth_a[0]);
+    close(depth_a[1]);
+    close(depth_b[0]);
+    close(ready[0]);
+    close(pids[0]);
+    close(pids[1]);
+    storm_child(depth_b[1]);
+    peer = a;
+    CHECK(write(ready[1], "r", 1) == 1);
+    for (;;)
+      pause();
+  }
+  close(depth_a[1]);
+  close(depth_b[1]);
+  close(ready[1]);
+  close(pids[0]);
+  char c;
+  CHECK(read(ready[0], &c, 1) == 1);
+  CHECK(write(pids[1], &b, sizeof(b)) == sizeof(b));
+
+  // The first to go too deep is killed, the other waits for the signal.
+  int status;
+  pid_t first = waitpid(-1, &status, 0);
+  CHECK(first == a || first == b);
+  CHECK(WIFSIGNALED(status) && WTERMSIG(status) == SIGSEGV);
+  CHECK(count_bytes(first == a ? depth_a[0] : depth_b[0]) == MAX_NESTING);
+  pid_t other = first == a ? b : a;
+  kill(other, SIGKILL);
+  CHECK(waitpid(other, &status, 0) == other);
+  CHECK(WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+  CHECK(count_bytes(first == a ? depth_b[0] : depth_a[0]) <= MAX_NESTING);
+
+  close(depth_a[0]);
+  close(depth_b[0]);
+  close(ready[0]);
+  close(pids[1]);
+  printf("test_storm ok\n");
+}
+
+static sigjmp_buf main_env, outer_env;
+static int jump_to_outer, outer_resumed, bad_mask;
+
+static void jumping_handler(int signo) {
+  siglongjmp(jump_to_outer ? outer_env : main_env, 1);
+}
+
+static void outer_handler(int signo) {
+  if (!jump_to_outer) {
+    raise(SIGUSR2);
+    bad_mask = 1;
+    return;
+  }
+  if (sigsetjmp(outer_env, 1) == 0) {
+    raise(SIGUSR2);
+    bad_mask = 1;
+    return;
+  }
+  // Back with the mask of the handler, as saved by sigsetjmp.
+  outer_resumed++;
+  if (!is_blocked(SIGUSR1) || is_blocked(SIGUSR2))
+    bad_mask = 1;
+}
+
+static void test_longjmp(void) {
+  set_handler(SIGUSR1, outer_handler, 0);
+  set_handler(SIGUSR2, jumping_handler, 0);
+
+  // Out of both handlers, many more times than they can nest: their frames
+  // are forgotten.
+  jump_to_outer = 0;
+  for (int i = 0; i < 4 * MAX_NESTING; i++) {
+    if (sigsetjmp(main_env, 1) == 0) {
+      raise(SIGUSR1);
+      CHECK(0);
+    }
+    CHECK(!is_blocked(SIGUSR1) && !is_blocked(SIGUSR2));
+  }
+
+  // Out of the inner one, into the outer one, which returns.
+  jump_to_outer = 1;
+  for (int i = 0; i < 4 * MAX_NESTING; i++) {
+    raise(SIGUSR1);
+    CHECK(!is_blocked(SIGUSR1) && !is_blocked(SIGUSR2));
+  }
+  CHECK(outer_resumed == 4 * MAX_NESTING && !bad_mask);
+
+  set_handler(SIGUSR1, SIG_DFL, 0);
+  set_handler(SIGUSR2, SIG_DFL, 0);
+  printf("test_longjmp ok\n");
+}
+
+int main(void) {
+  test_nodefer();
+  test_altstack();
+  test_interrupted_sleep();
+  test_storm();
+  test_longjmp();
+  return 0;
+}
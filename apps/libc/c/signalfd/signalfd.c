@@ -0,0 +1,139 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <poll.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/signalfd.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+static sigset_t block(int signo) {
+  sigset_t set;
+  sigemptyset(&set);
+  sigaddset(&set, signo);
+  CHECK(sigprocmask(SIG_BLOCK, &set, NULL) == 0);
+  return set;
+}
+
+static void unblock(int signo) {
+  sigset_t set;
+  sigemptyset(&set);
+  sigaddset(&set, signo);
+  CHECK(sigprocmask(SIG_UNBLOCK, &set, NULL) == 0);
+}
+
+// A signal a child sends is read with the pid of the child, and a read
+// waits for it.
+void test_read_from_child() {
+  sigset_t set = block(SIGUSR1);
+  int fd = signalfd(-1, &set, SFD_CLOEXEC);
+  CHECK(fd >= 0);
+  CHECK((fcntl(fd, F_GETFD) & FD_CLOEXEC) != 0);
+
+  pid_t parent = getpid();
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    usleep(100000);
+    kill(parent, SIGUSR1);
+    _exit(0);
+  }
+  struct signalfd_siginfo info;
+  CHECK(read(fd, &info, sizeof(info)) == sizeof(info));
+  CHECK(info.ssi_signo == SIGUSR1);
+  CHECK(info.ssi_code == SI_USER);
+  CHECK(info.ssi_pid == (uint32_t)pid);
+  CHECK(info.ssi_uid == getuid());
+
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  close(fd);
+  unblock(SIGUSR1);
+  puts("test_read_from_child ok");
+}
+
+// A nonblocking signalfd fails with EAGAIN when nothing is pending, and
+// polls readable once something is; a read takes every record that fits.
+void test_nonblocking_poll() {
+  sigset_t set = block(SIGUSR2);
+  sigaddset(&set, SIGUSR1);
+  block(SIGUSR1);
+  int fd = signalfd(-1, &set, SFD_NONBLOCK);
+  CHECK(fd >= 0);
+  CHECK((fcntl(fd, F_GETFL) & O_NONBLOCK) != 0);
+
+  struct signalfd_siginfo info[3];
+  CHECK(read(fd, info, sizeof(info)) == -1 && errno == EAGAIN);
+  struct pollfd pfd = {.fd = fd, .events = POLLIN};
+  CHECK(poll(&pfd, 1, 0) == 0);
+
+  CHECK(raise(SIGUSR2) == 0);
+  CHECK(raise(SIGUSR1) == 0);
+  CHECK(poll(&pfd, 1, 0) == 1 && (pfd.revents & POLLIN));
+  CHECK(read(fd, info, sizeof(info)) == 2 * sizeof(info[0]));
+  CHECK(info[0].ssi_signo == SIGUSR1 && info[1].ssi_signo == SIGUSR2);
+  CHECK(info[0].ssi_pid == (uint32_t)getpid());
+  CHECK(poll(&pfd, 1, 0) == 0);
+
+  // Too small for a record.
+  CHECK(read(fd, info, sizeof(info[0]) - 1) == -1 && errno == EINVAL);
+  close(fd);
+  unblock(SIGUSR1);
+  unblock(SIGUSR2);
+  puts("test_nonblocking_poll ok");
+}
+
+// Passing an existing signalfd changes its mask, and bad arguments fail.
+void test_update_and_errors() {
+  sigset_t set = block(SIGUSR1);
+  block(SIGUSR2);
+  int fd = signalfd(-1, &set, SFD_NONBLOCK);
+  CHECK(fd >= 0);
+
+  sigset_t other;
+  sigemptyset(&other);
+  sigaddset(&other, SIGUSR2);
+  CHECK(signalfd(fd, &other, 0) == fd);
+  CHECK(raise(SIGUSR1) == 0);
+  struct signalfd_siginfo info;
+  CHECK(read(fd, &info, sizeof(info)) == -1 && errno == EAGAIN);
+  CHECK(raise(SIGUSR2) == 0);
+  CHECK(read(fd, &info, sizeof(info)) == sizeof(info));
+  CHECK(info.ssi_signo == SIGUSR2);
+  // SIGUSR1 is still pending, for sigwait.
+  int signo;
+  CHECK(sigwait(&set, &signo) == 0 && signo == SIGUSR1);
+
+  CHECK(syscall(SYS_signalfd4, -1, &set, 4, 0) == -1 && errno == EINVAL);
+  CHECK(syscall(SYS_signalfd4, -1, &set, 8, 0x1) == -1 && errno == EINVAL);
+  int pipefd[2];
+  CHECK(pipe(pipefd) == 0);
+  CHECK(signalfd(pipefd[0], &set, 0) == -1 && errno == EINVAL);
+  CHECK(signalfd(1000, &set, 0) == -1 && errno == EBADF);
+  close(pipefd[0]);
+  close(pipefd[1]);
+  close(fd);
+  unblock(SIGUSR1);
+  unblock(SIGUSR2);
+  puts("test_update_and_errors ok");
+}
+
+int main() {
+  test_read_from_child();
+  test_nonblocking_poll();
+  test_update_and_errors();
+  return 0;
+}
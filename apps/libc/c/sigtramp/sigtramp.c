@@ -0,0 +1,183 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define DEPTH 200
+#define HANDLER_DEPTH 50
+
+// The return address of the last handler run, which is how the handler
+// returns to `rt_sigreturn`.
+static void *volatile handler_return;
+static volatile int handled;
+
+// A frame of the call chain, with a canary for each level, which the
+// frames of the signal must not overwrite.
+static int recurse(int depth, int (*bottom)(void)) {
+  volatile int canary[4] = {depth, ~depth, depth * 3, depth ^ 0x5a5a};
+  int got = depth == 0 ? bottom() : recurse(depth - 1, bottom);
+  CHECK(canary[0] == depth && canary[1] == ~depth && canary[2] == depth * 3 &&
+        canary[3] == (depth ^ 0x5a5a));
+  return got + 1;
+}
+
+static int nothing(void) { return 0; }
+
+static void handler(int sig) {
+  handler_return = __builtin_return_address(0);
+  CHECK(sig == SIGUSR1);
+  CHECK(recurse(HANDLER_DEPTH, nothing) == HANDLER_DEPTH + 1);
+  handled++;
+}
+
+static int raise_usr1(void) {
+  CHECK(raise(SIGUSR1) == 0);
+  return 0;
+}
+
+// The address of the signal trampoline, from `/proc/self/maps`.
+static void *find_sigpage(void) {
+  FILE *f = fopen("/proc/self/maps", "r");
+  CHECK(f != NULL);
+  char line[512];
+  unsigned long start = 0;
+  while (fgets(line, sizeof(line), f)) {
+    if (strstr(line, "[sigpage]")) {
+      CHECK(sscanf(line, "%lx-", &start) == 1);
+      break;
+    }
+  }
+  fclose(f);
+  return (void *)start;
+}
+
+#ifdef __x86_64__
+// A restorer like the one of glibc, which marks that it ran.
+__attribute__((visibility("hidden"))) volatile int restorer_ran;
+void test_restorer_entry(void);
+__asm__(".text\n"
+        ".globl test_restorer_entry\n"
+        "test_restorer_entry:\n"
+        "  movl $1, restorer_ran(%rip)\n"
+        "  mov $15, %eax\n" // rt_sigreturn
+        "  syscall\n");
+
+struct kernel_sigaction {
+  void (*handler)(int);
+  unsigned long flags;
+  void (*restorer)(void);
+  unsigned long mask;
+};
+
+#define KERNEL_SA_RESTORER 0x04000000
+
+// Install `handler` for `SIGUSR1` with the restorer above, as glibc does.
+static void install_handler(void) {
+  struct kernel_sigaction sa = {
+      .handler = handler,
+      .flags = KERNEL_SA_RESTORER,
+      .restorer = test_restorer_entry,
+  };
+  CHECK(syscall(SYS_rt_sigaction, SIGUSR1, &sa, NULL, 8) == 0);
+  restorer_ran = 0;
+}
+
+static void check_return(void) {
+  CHECK(handler_return == (void *)test_restorer_entry);
+  CHECK(restorer_ran);
+}
+#else
+static void install_handler(void) {
+  struct sigaction sa = {0};
+  sa.sa_handler = handler;
+  CHECK(sigaction(SIGUSR1, &sa, NULL) == 0);
+}
+
+// Without `SA_RESTORER`, which musl only sets on some architectures, the
+// handler returns to the trampoline.
+static void check_return(void) {
+#ifndef SA_RESTORER
+  CHECK(handler_return == find_sigpage());
+#endif
+}
+#endif
+
+static void take_signal_deep(void) {
+  handled = 0;
+  CHECK(recurse(DEPTH, raise_usr1) == DEPTH + 1);
+  CHECK(handled == 1);
+  check_return();
+}
+
+// A handler taken deep in a call chain, and itself calling deep, returns
+// through the restorer of its action, or the trampoline without one, to
+// where the signal was taken, with all frames intact.
+void test_deep_stack() {
+  install_handler();
+  take_signal_deep();
+  puts("test_deep_stack ok");
+}
+
+// The trampoline can be read and run, but not made writable.
+void test_readonly() {
+  long size = sysconf(_SC_PAGESIZE);
+  char *page = find_sigpage();
+  CHECK(page != NULL);
+  volatile char first = page[0];
+  (void)first;
+  CHECK(mprotect(page, size, PROT_READ | PROT_WRITE) == -1 && errno == EACCES);
+  CHECK(mprotect(page, size, PROT_READ | PROT_WRITE | PROT_EXEC) == -1 &&
+        errno == EACCES);
+  CHECK(mprotect(page - size, 2 * size, PROT_READ | PROT_WRITE) == -1 &&
+        errno == EACCES);
+  install_handler();
+  take_signal_deep();
+  puts("test_readonly ok");
+}
+
+// A child, and a new image, take and return from signals the same way.
+void test_fork_exec() {
+  install_handler();
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    take_signal_deep();
+    char self[256];
+    ssize_t len = readlink("/proc/self/exe", self, sizeof(self) - 1);
+    CHECK(len > 0);
+    self[len] = '\0';
+    execl(self, self, "--signal", (char *)NULL);
+    _exit(127);
+  }
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  puts("test_fork_exec ok");
+}
+
+int main(int argc, char **argv) {
+  if (argc == 2 && strcmp(argv[1], "--signal") == 0) {
+    CHECK(find_sigpage() != NULL);
+    install_handler();
+    take_signal_deep();
+    return 0;
+  }
+  test_deep_stack();
+  test_readonly();
+  test_fork_exec();
+  return 0;
+}
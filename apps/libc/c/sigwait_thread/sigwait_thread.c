@@ -0,0 +1,150 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <pthread.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/syscall.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+static sigset_t wait_set;
+static int report[2];
+static volatile pid_t helper_tid;
+
+// The signal thread: take the signals of `wait_set` one by one, and report
+// each with its siginfo, until SIGTERM.
+static void *signal_thread(void *arg) {
+  (void)arg;
+  helper_tid = syscall(SYS_gettid);
+  for (;;) {
+    siginfo_t info;
+    int signo = sigwaitinfo(&wait_set, &info);
+    CHECK(signo > 0);
+    CHECK(info.si_signo == signo);
+    CHECK(write(report[1], &info, sizeof(info)) == sizeof(info));
+    if (signo == SIGTERM) {
+      return NULL;
+    }
+  }
+}
+
+static siginfo_t next_report(void) {
+  siginfo_t info;
+  CHECK(read(report[0], &info, sizeof(info)) == sizeof(info));
+  return info;
+}
+
+static void test_signal_thread(void) {
+  sigemptyset(&wait_set);
+  sigaddset(&wait_set, SIGUSR1);
+  sigaddset(&wait_set, SIGUSR2);
+  sigaddset(&wait_set, SIGRTMIN);
+  sigaddset(&wait_set, SIGTERM);
+  sigset_t all;
+  sigfillset(&all);
+  CHECK(pthread_sigmask(SIG_BLOCK, &all, NULL) == 0);
+  CHECK(pipe(report) == 0);
+
+  pthread_t thread;
+  CHECK(pthread_create(&thread, NULL, signal_thread, NULL) == 0);
+  while (helper_tid == 0) {
+    usleep(1000);
+  }
+
+  // Sent to the process, which the main thread blocks too.
+  CHECK(kill(getpid(), SIGUSR1) == 0);
+  siginfo_t info = next_report();
+  CHECK(info.si_signo == SIGUSR1 && info.si_code == SI_USER);
+  CHECK(info.si_pid == getpid());
+
+  CHECK(syscall(SYS_tkill, helper_tid, SIGUSR2) == 0);
+  info = next_report();
+  CHECK(info.si_signo == SIGUSR2);
+
+  union sigval value = {.sival_int = 42};
+  CHECK(sigqueue(getpid(), SIGRTMIN, value) == 0);
+  info = next_report();
+  CHECK(info.si_signo == SIGRTMIN && info.si_code == SI_QUEUE);
+  CHECK(info.si_value.sival_int == 42);
+
+  // Several queued realtime signals are taken one at a time, in order.
+  for (int i = 0; i < 3; i++) {
+    value.sival_int = i;
+    CHECK(sigqueue(getpid(), SIGRTMIN, value) == 0);
+  }
+  for (int i = 0; i < 3; i++) {
+    info = next_report();
+    CHECK(info.si_signo == SIGRTMIN && info.si_value.sival_int == i);
+  }
+
+  CHECK(kill(getpid(), SIGTERM) == 0);
+  CHECK(next_report().si_signo == SIGTERM);
+  CHECK(pthread_join(thread, NULL) == 0);
+  CHECK(pthread_sigmask(SIG_UNBLOCK, &all, NULL) == 0);
+  printf("test_signal_thread ok\n");
+}
+
+static void test_timeout(void) {
+  sigset_t set;
+  sigemptyset(&set);
+  sigaddset(&set, SIGUSR1);
+  CHECK(sigprocmask(SIG_BLOCK, &set, NULL) == 0);
+  struct timespec timeout = {.tv_sec = 0, .tv_nsec = 50000000};
+  errno = 0;
+  CHECK(sigtimedwait(&set, NULL, &timeout) == -1 && errno == EAGAIN);
+  // A zero timeout only polls.
+  timeout.tv_nsec = 0;
+  CHECK(sigtimedwait(&set, NULL, &timeout) == -1 && errno == EAGAIN);
+  CHECK(raise(SIGUSR1) == 0);
+  CHECK(sigtimedwait(&set, NULL, &timeout) == SIGUSR1);
+  timeout.tv_nsec = 1000000000;
+  CHECK(sigtimedwait(&set, NULL, &timeout) == -1 && errno == EINVAL);
+  CHECK(sigprocmask(SIG_UNBLOCK, &set, NULL) == 0);
+  printf("test_timeout ok\n");
+}
+
+static volatile int handled;
+
+static void on_sigusr2(int sig) {
+  (void)sig;
+  handled = 1;
+}
+
+static void *interrupter(void *arg) {
+  usleep(50000);
+  pthread_kill(*(pthread_t *)arg, SIGUSR2);
+  return NULL;
+}
+
+static void test_interrupted(void) {
+  signal(SIGUSR2, on_sigusr2);
+  sigset_t set;
+  sigemptyset(&set);
+  sigaddset(&set, SIGUSR1);
+  CHECK(sigprocmask(SIG_BLOCK, &set, NULL) == 0);
+  pthread_t self = pthread_self(), thread;
+  CHECK(pthread_create(&thread, NULL, interrupter, &self) == 0);
+  struct timespec timeout = {.tv_sec = 5, .tv_nsec = 0};
+  errno = 0;
+  CHECK(sigtimedwait(&set, NULL, &timeout) == -1 && errno == EINTR);
+  CHECK(handled);
+  CHECK(pthread_join(thread, NULL) == 0);
+  CHECK(sigprocmask(SIG_UNBLOCK, &set, NULL) == 0);
+  printf("test_interrupted ok\n");
+}
+
+int main(void) {
+  test_signal_thread();
+  test_timeout();
+  test_interrupted();
+  return 0;
+}
@@ -0,0 +1,240 @@
+#define _GNU_SOURCE
+#include <arpa/inet.h>
+#include <errno.h>
+#include <netinet/in.h>
+#include <pthread.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define ACCEPT_PORT 5570
+#define RECVFROM_PORT 5571
+#define CANARY 0xa5
+#define HUGE_LEN 0x7fffffff
+
+// A buffer for an address, with canary bytes after it which the kernel must
+// never write.
+struct guarded {
+  struct sockaddr_in addr;
+  unsigned char canary[64];
+};
+
+// The value-result lengths tried: none, one byte, exact, and huge.
+static const socklen_t lengths[] = {0, 1, sizeof(struct sockaddr_in),
+                                    HUGE_LEN};
+
+static struct sockaddr_in loopback(int port) {
+  struct sockaddr_in addr = {0};
+  addr.sin_family = AF_INET;
+  addr.sin_port = htons(port);
+  addr.sin_addr.s_addr = htonl(INADDR_LOOPBACK);
+  return addr;
+}
+
+static void guard(struct guarded *buf) { memset(buf, CANARY, sizeof(*buf)); }
+
+// Check that `buf`, given with the length `given`, holds the first bytes of
+// `addr`, and nothing was written past them.
+static void check_written(const struct guarded *buf, socklen_t given,
+                          const struct sockaddr_in *addr) {
+  size_t n = given < sizeof(*addr) ? given : sizeof(*addr);
+  CHECK(memcmp(&buf->addr, addr, n) == 0);
+  const unsigned char *bytes = (const unsigned char *)buf;
+  for (size_t i = n; i < sizeof(*buf); i++)
+    CHECK(bytes[i] == CANARY);
+}
+
+// Check that `buf` holds no more than the first bytes of `addr`, as many
+// as some length given allowed.
+static void check_prefix(const struct guarded *buf,
+                         const struct sockaddr_in *addr) {
+  size_t n = 0;
+  while (n < sizeof(*addr) && memcmp(&buf->addr, addr, n + 1) == 0)
+    n++;
+  check_written(buf, n, addr);
+}
+
+// Check that nothing was written to `buf`.
+static void check_untouched(const struct guarded *buf) {
+  const unsigned char *bytes = (const unsigned char *)buf;
+  for (size_t i = 0; i < sizeof(*buf); i++)
+    CHECK(bytes[i] == CANARY);
+}
+
+// `accept` writes as much of the address of the peer as fits, and its full
+// length.
+void test_accept() {
+  int listener = socket(AF_INET, SOCK_STREAM, 0);
+  CHECK(listener >= 0);
+  struct sockaddr_in addr = loopback(ACCEPT_PORT);
+  CHECK(bind(listener, (struct sockaddr *)&addr, sizeof(addr)) == 0);
+  CHECK(listen(listener, 4) == 0);
+  for (size_t i = 0; i < sizeof(lengths) / sizeof(lengths[0]); i++) {
+    int client = socket(AF_INET, SOCK_STREAM, 0);
+    CHECK(client >= 0);
+    CHECK(connect(client, (struct sockaddr *)&addr, sizeof(addr)) == 0);
+    struct sockaddr_in local;
+    socklen_t local_len = sizeof(local);
+    CHECK(getsockname(client, (struct sockaddr *)&local, &local_len) == 0);
+
+    struct guarded buf;
+    guard(&buf);
+    socklen_t len = lengths[i];
+    int peer = accept(listener, (struct sockaddr *)&buf, &len);
+    CHECK(peer >= 0);
+    CHECK(len == sizeof(struct sockaddr_in));
+    check_written(&buf, lengths[i], &local);
+    CHECK(close(peer) == 0);
+    CHECK(close(client) == 0);
+  }
+  CHECK(close(listener) == 0);
+  puts("test_accept ok");
+}
+
+// So do `getsockname` and `getpeername`, and a negative length fails
+// without writing anything.
+void test_names() {
+  int fd = socket(AF_INET, SOCK_DGRAM, 0);
+  CHECK(fd >= 0);
+  struct sockaddr_in local = loopback(RECVFROM_PORT);
+  CHECK(bind(fd, (struct sockaddr *)&local, sizeof(local)) == 0);
+  struct sockaddr_in remote = loopback(RECVFROM_PORT + 1);
+  CHECK(connect(fd, (struct sockaddr *)&remote, sizeof(remote)) == 0);
+  for (size_t i = 0; i < sizeof(lengths) / sizeof(lengths[0]); i++) {
+    struct guarded buf;
+    guard(&buf);
+    socklen_t len = lengths[i];
+    CHECK(getsockname(fd, (struct sockaddr *)&buf, &len) == 0);
+    CHECK(len == sizeof(struct sockaddr_in));
+    check_written(&buf, lengths[i], &local);
+
+    guard(&buf);
+    len = lengths[i];
+    CHECK(getpeername(fd, (struct sockaddr *)&buf, &len) == 0);
+    CHECK(len == sizeof(struct sockaddr_in));
+    check_written(&buf, lengths[i], &remote);
+  }
+  struct guarded buf;
+  guard(&buf);
+  socklen_t len = (socklen_t)-1;
+  errno = 0;
+  CHECK(getsockname(fd, (struct sockaddr *)&buf, &len) == -1 &&
+        errno == EINVAL);
+  CHECK(len == (socklen_t)-1);
+  check_untouched(&buf);
+  CHECK(close(fd) == 0);
+  puts("test_names ok");
+}
+
+// `recvfrom` and `recvmsg` write the address of the sender alike, and no
+// control message past their buffer, however long it is said to be.
+void test_recv() {
+  int fd = socket(AF_INET, SOCK_DGRAM, 0);
+  CHECK(fd >= 0);
+  struct sockaddr_in addr = loopback(RECVFROM_PORT);
+  CHECK(bind(fd, (struct sockaddr *)&addr, sizeof(addr)) == 0);
+  int sender = socket(AF_INET, SOCK_DGRAM, 0);
+  CHECK(sender >= 0);
+  struct sockaddr_in from = loopback(RECVFROM_PORT + 1);
+  CHECK(bind(sender, (struct sockaddr *)&from, sizeof(from)) == 0);
+  CHECK(connect(sender, (struct sockaddr *)&addr, sizeof(addr)) == 0);
+  for (size_t i = 0; i < sizeof(lengths) / sizeof(lengths[0]); i++) {
+    char data;
+    struct guarded buf;
+    guard(&buf);
+    socklen_t len = lengths[i];
+    CHECK(write(sender, "r", 1) == 1);
+    CHECK(recvfrom(fd, &data, 1, 0, (struct sockaddr *)&buf, &len) == 1);
+    CHECK(data == 'r');
+    CHECK(len == sizeof(struct sockaddr_in));
+    check_written(&buf, lengths[i], &from);
+
+    guard(&buf);
+    unsigned char control[64];
+    memset(control, CANARY, sizeof(control));
+    struct iovec iov = {.iov_base = &data, .iov_len = 1};
+    struct msghdr msg = {.msg_name = &buf,
+                         .msg_namelen = lengths[i],
+                         .msg_iov = &iov,
+                         .msg_iovlen = 1,
+                         .msg_control = control,
+                         .msg_controllen = HUGE_LEN};
+    CHECK(write(sender, "m", 1) == 1);
+    CHECK(recvmsg(fd, &msg, 0) == 1 && data == 'm');
+    CHECK(msg.msg_namelen == sizeof(struct sockaddr_in));
+    check_written(&buf, lengths[i], &from);
+    CHECK(msg.msg_controllen == 0);
+    for (size_t j = 0; j < sizeof(control); j++)
+      CHECK(control[j] == CANARY);
+  }
+
+  char data;
+  struct guarded buf;
+  guard(&buf);
+  struct iovec iov = {.iov_base = &data, .iov_len = 1};
+  struct msghdr msg = {
+      .msg_name = &buf, .msg_namelen = -1, .msg_iov = &iov, .msg_iovlen = 1};
+  errno = 0;
+  CHECK(recvmsg(fd, &msg, MSG_DONTWAIT) == -1 && errno == EINVAL);
+  check_untouched(&buf);
+  CHECK(close(sender) == 0);
+  CHECK(close(fd) == 0);
+  puts("test_recv ok");
+}
+
+static volatile socklen_t racy_len;
+static volatile int racing;
+
+// Flip the length between none, huge, exact and negative, as fast as it
+// can.
+static void *flip(void *arg) {
+  (void)arg;
+  static const socklen_t values[] = {0, HUGE_LEN, sizeof(struct sockaddr_in),
+                                     (socklen_t)-1, 1};
+  for (unsigned i = 0; racing; i++)
+    racy_len = values[i % (sizeof(values) / sizeof(values[0]))];
+  return NULL;
+}
+
+// With another thread changing the length meanwhile, `getsockname` writes
+// at most the address, and either fails with `EINVAL` or reports its full
+// length.
+void test_race() {
+  int fd = socket(AF_INET, SOCK_DGRAM, 0);
+  CHECK(fd >= 0);
+  struct sockaddr_in addr = loopback(RECVFROM_PORT + 2);
+  CHECK(bind(fd, (struct sockaddr *)&addr, sizeof(addr)) == 0);
+  racing = 1;
+  pthread_t thread;
+  CHECK(pthread_create(&thread, NULL, flip, NULL) == 0);
+  for (int i = 0; i < 20000; i++) {
+    struct guarded buf;
+    guard(&buf);
+    errno = 0;
+    int ret = getsockname(fd, (struct sockaddr *)&buf, (socklen_t *)&racy_len);
+    CHECK(ret == 0 || errno == EINVAL);
+    check_prefix(&buf, &addr);
+  }
+  racing = 0;
+  CHECK(pthread_join(thread, NULL) == 0);
+  CHECK(close(fd) == 0);
+  puts("test_race ok");
+}
+
+int main() {
+  test_accept();
+  test_names();
+  test_recv();
+  test_race();
+  return 0;
+}
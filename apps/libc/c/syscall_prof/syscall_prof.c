@@ -0,0 +1,221 @@
+// Profile the syscalls of a command with the latency histograms of
+// /proc/<pid>/syscall_latency, and print the five taking the most time. Run
+// without a command, test the histograms instead, profiling a built-in
+// workload, which `--workload` runs alone.
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define GLOBAL_PATH "/proc/starry/syscall_latency"
+#define SELF_PATH "/proc/self/syscall_latency"
+#define MAX_SYSCALLS 512
+#define TOP 5
+
+#define GETPIDS 1000
+#define SLEEPS 5
+#define SLEEP_MS 10
+
+struct entry {
+  char name[32];
+  unsigned long count;
+  unsigned long total_ns;
+};
+
+static void write_file(const char *path, const char *value) {
+  int fd = open(path, O_WRONLY);
+  CHECK(fd >= 0);
+  CHECK(write(fd, value, strlen(value)) == (ssize_t)strlen(value));
+  close(fd);
+}
+
+// Parse the histograms of `path` into `entries`, returning how many there
+// are.
+static int read_entries(const char *path, struct entry *entries) {
+  FILE *f = fopen(path, "r");
+  CHECK(f != NULL);
+  char line[1024];
+  int n = 0;
+  while (n < MAX_SYSCALLS && fgets(line, sizeof(line), f)) {
+    struct entry *e = &entries[n];
+    CHECK(sscanf(line, "%31s %lu %lu", e->name, &e->count, &e->total_ns) ==
+          3);
+    CHECK(e->count > 0);
+    n++;
+  }
+  fclose(f);
+  return n;
+}
+
+static const struct entry *find(const struct entry *entries, int n,
+                                const char *name) {
+  for (int i = 0; i < n; i++) {
+    if (strcmp(entries[i].name, name) == 0) {
+      return &entries[i];
+    }
+  }
+  return NULL;
+}
+
+static void workload(void) {
+  for (int i = 0; i < GETPIDS; i++) {
+    syscall(SYS_getpid);
+  }
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  char buf[4096] = {0};
+  for (int i = 0; i < 100; i++) {
+    CHECK(write(fds[1], buf, sizeof(buf)) == sizeof(buf));
+    CHECK(read(fds[0], buf, sizeof(buf)) == sizeof(buf));
+  }
+  close(fds[0]);
+  close(fds[1]);
+  struct timespec ts = {.tv_sec = 0, .tv_nsec = SLEEP_MS * 1000000L};
+  for (int i = 0; i < SLEEPS; i++) {
+    CHECK(nanosleep(&ts, NULL) == 0);
+  }
+}
+
+static void check_child(pid_t pid) {
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+static int by_total(const void *a, const void *b) {
+  const struct entry *x = a, *y = b;
+  return x->total_ns < y->total_ns ? 1 : x->total_ns > y->total_ns ? -1 : 0;
+}
+
+// Print the syscalls of all processes recording since the last reset which
+// took the most time, leaving all of them sorted in `entries`. Returns how
+// many there are.
+static int print_top(struct entry *entries) {
+  int n = read_entries(GLOBAL_PATH, entries);
+  qsort(entries, n, sizeof(entries[0]), by_total);
+  int top = n < TOP ? n : TOP;
+  printf("%-20s %10s %14s %10s\n", "syscall", "calls", "total_ns", "avg_ns");
+  for (int i = 0; i < top; i++) {
+    printf("%-20s %10lu %14lu %10lu\n", entries[i].name, entries[i].count,
+           entries[i].total_ns, entries[i].total_ns / entries[i].count);
+  }
+  for (int i = 1; i < top; i++) {
+    CHECK(entries[i - 1].total_ns >= entries[i].total_ns);
+  }
+  return n;
+}
+
+// Run `argv`, or the workload without it, in a child recording its
+// syscalls, which children inherit.
+static pid_t run_profiled(char **argv) {
+  write_file(GLOBAL_PATH, "0");
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    write_file(SELF_PATH, "1");
+    if (argv != NULL) {
+      execvp(argv[0], argv);
+      perror("execvp");
+      _exit(127);
+    }
+    workload();
+    _exit(0);
+  }
+  return pid;
+}
+
+// A process recording its syscalls counts each, and sleeping lands in the
+// buckets of its length.
+void test_histogram() {
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    write_file(SELF_PATH, "1");
+    workload();
+    static struct entry entries[MAX_SYSCALLS];
+    int n = read_entries(SELF_PATH, entries);
+    const struct entry *getpid = find(entries, n, "getpid");
+    CHECK(getpid != NULL && getpid->count == GETPIDS);
+    const struct entry *sleep = find(entries, n, "nanosleep");
+    if (sleep == NULL) {
+      sleep = find(entries, n, "clock_nanosleep");
+    }
+    CHECK(sleep != NULL && sleep->count == SLEEPS);
+    CHECK(sleep->total_ns >= SLEEPS * SLEEP_MS * 1000000UL);
+    CHECK(find(entries, n, "pipe2") != NULL || find(entries, n, "pipe"));
+    _exit(0);
+  }
+  check_child(pid);
+  puts("test_histogram ok");
+}
+
+// A process which does not record has no histograms, and one which stops
+// keeps what it recorded.
+void test_disabled() {
+  static struct entry entries[MAX_SYSCALLS];
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    workload();
+    CHECK(read_entries(SELF_PATH, entries) == 0);
+    write_file(SELF_PATH, "1");
+    syscall(SYS_getpid);
+    write_file(SELF_PATH, "0");
+    workload();
+    const struct entry *getpid =
+        find(entries, read_entries(SELF_PATH, entries), "getpid");
+    CHECK(getpid != NULL && getpid->count == 1);
+    _exit(0);
+  }
+  check_child(pid);
+  puts("test_disabled ok");
+}
+
+// A profiled command is recorded from its `execve` on, and sleeping takes
+// the most time of the workload.
+void test_top5() {
+  char self[256];
+  ssize_t len = readlink("/proc/self/exe", self, sizeof(self) - 1);
+  CHECK(len > 0);
+  self[len] = '\0';
+  char *argv[] = {self, "--workload", NULL};
+  check_child(run_profiled(argv));
+  static struct entry entries[MAX_SYSCALLS];
+  int n = print_top(entries);
+  CHECK(n > 0);
+  CHECK(strstr(entries[0].name, "nanosleep") != NULL);
+  CHECK(find(entries, n, "execve") != NULL);
+  const struct entry *getpid = find(entries, n, "getpid");
+  CHECK(getpid != NULL && getpid->count == GETPIDS);
+  puts("test_top5 ok");
+}
+
+int main(int argc, char **argv) {
+  if (argc == 2 && strcmp(argv[1], "--workload") == 0) {
+    workload();
+    return 0;
+  }
+  if (argc > 1) {
+    static struct entry entries[MAX_SYSCALLS];
+    check_child(run_profiled(argv + 1));
+    print_top(entries);
+    return 0;
+  }
+  test_histogram();
+  test_disabled();
+  test_top5();
+  return 0;
+}
@@ -0,0 +1,106 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <limits.h>
+#include <sched.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/auxv.h>
+#include <sys/resource.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define NAME(name) {name, #name}
+
+static const struct {
+  int name;
+  const char *str;
+} names[] = {
+    NAME(_SC_PAGESIZE),         NAME(_SC_OPEN_MAX),
+    NAME(_SC_NPROCESSORS_ONLN), NAME(_SC_NPROCESSORS_CONF),
+    NAME(_SC_CLK_TCK),          NAME(_SC_PHYS_PAGES),
+    NAME(_SC_ARG_MAX),          NAME(_SC_NGROUPS_MAX),
+    NAME(_SC_LINE_MAX),         NAME(_SC_HOST_NAME_MAX),
+    NAME(_SC_LOGIN_NAME_MAX),   NAME(_SC_IOV_MAX),
+    NAME(_SC_RTSIG_MAX),        NAME(_SC_TTY_NAME_MAX),
+};
+
+// The number of CPUs the calling thread may run on, asked of the kernel.
+static int affinity_cpus(void) {
+  cpu_set_t set;
+  long size = syscall(SYS_sched_getaffinity, 0, sizeof(set), &set);
+  CHECK(size > 0 && size % sizeof(long) == 0 && size <= (long)sizeof(set));
+  // Only the bytes written count.
+  memset((char *)&set + size, 0, sizeof(set) - size);
+  return CPU_COUNT(&set);
+}
+
+// Every name has a positive answer, and the common ones sane values.
+void test_values() {
+  for (size_t i = 0; i < sizeof(names) / sizeof(names[0]); i++) {
+    errno = 0;
+    long value = sysconf(names[i].name);
+    if (value <= 0) {
+      printf("sysconf(%s) = %ld, errno %d\n", names[i].str, value, errno);
+    }
+    CHECK(value > 0);
+  }
+  // 4K, or 16K or 64K for a kernel built with another page size.
+  long page = sysconf(_SC_PAGESIZE);
+  CHECK(page == 4096 || page == 16384 || page == 65536);
+  CHECK(getpagesize() == page);
+  CHECK(sysconf(_SC_OPEN_MAX) >= 1024);
+  CHECK(getdtablesize() == sysconf(_SC_OPEN_MAX));
+  CHECK(sysconf(_SC_NPROCESSORS_ONLN) >= 1);
+  CHECK(sysconf(_SC_NPROCESSORS_CONF) >= sysconf(_SC_NPROCESSORS_ONLN));
+  CHECK(sysconf(_SC_CLK_TCK) == 100);
+  puts("test_values ok");
+}
+
+// The answers agree with the syscalls and the auxv they come from.
+void test_sources() {
+  CHECK(getauxval(AT_PAGESZ) == (unsigned long)sysconf(_SC_PAGESIZE));
+
+  struct rlimit rlim;
+  CHECK(getrlimit(RLIMIT_NOFILE, &rlim) == 0);
+  CHECK(sysconf(_SC_OPEN_MAX) == (long)rlim.rlim_cur);
+  // The limit is what the fd table holds: the last fd below it opens, and
+  // the fd at it does not.
+  int last = rlim.rlim_cur - 1;
+  CHECK(dup2(0, last) == last);
+  CHECK(close(last) == 0);
+  CHECK(dup2(0, last + 1) == -1 && errno == EBADF);
+
+  CHECK(affinity_cpus() == sysconf(_SC_NPROCESSORS_ONLN));
+  puts("test_sources ok");
+}
+
+// The mask must be whole longs holding a bit for each CPU, and of a thread
+// that exists.
+void test_affinity_errors() {
+  cpu_set_t set;
+  CHECK(syscall(SYS_sched_getaffinity, 0, sizeof(long) - 1, &set) == -1 &&
+        errno == EINVAL);
+  CHECK(syscall(SYS_sched_getaffinity, 0, 0, &set) == -1 && errno == EINVAL);
+  CHECK(syscall(SYS_sched_getaffinity, -1, sizeof(set), &set) == -1 &&
+        errno == ESRCH);
+  CHECK(syscall(SYS_sched_getaffinity, 0, sizeof(set), NULL) == -1 &&
+        errno == EFAULT);
+  CHECK(syscall(SYS_sched_getaffinity, getpid(), sizeof(set), &set) > 0);
+  puts("test_affinity_errors ok");
+}
+
+int main() {
+  test_values();
+  test_sources();
+  test_affinity_errors();
+  return 0;
+}
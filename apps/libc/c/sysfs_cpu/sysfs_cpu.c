@@ -0,0 +1,118 @@
+#define _GNU_SOURCE
+#include <dirent.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <sched.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define CPU_DIR "/sys/devices/system/cpu"
+
+// Read the file at `path` into `buf`, as a string.
+static void read_file(const char *path, char *buf, size_t size) {
+  int fd = open(path, O_RDONLY);
+  CHECK(fd >= 0);
+  ssize_t len = read(fd, buf, size - 1);
+  CHECK(len > 0);
+  buf[len] = '\0';
+  CHECK(close(fd) == 0);
+}
+
+// The number of CPUs in the list at `path`, which must be of all of them
+// from 0, as `0` or `0-N`.
+static long list_count(const char *path) {
+  char buf[64];
+  read_file(path, buf, sizeof(buf));
+  long last;
+  if (strcmp(buf, "0\n") == 0)
+    return 1;
+  CHECK(sscanf(buf, "0-%ld\n", &last) == 1);
+  CHECK(last > 0);
+  return last + 1;
+}
+
+static long affinity_count(void) {
+  cpu_set_t set;
+  CHECK(sched_getaffinity(0, sizeof(set), &set) == 0);
+  return CPU_COUNT(&set);
+}
+
+// The lists agree with `sysconf` and `sched_getaffinity`.
+void test_lists() {
+  long cpus = sysconf(_SC_NPROCESSORS_ONLN);
+  CHECK(cpus >= 1);
+  CHECK(list_count(CPU_DIR "/online") == cpus);
+  CHECK(list_count(CPU_DIR "/possible") == cpus);
+  CHECK(list_count(CPU_DIR "/present") == cpus);
+  CHECK(affinity_count() == cpus);
+  puts("test_lists ok");
+}
+
+// There is a directory for each CPU, with its topology, and no other.
+void test_cpu_dirs() {
+  long cpus = sysconf(_SC_NPROCESSORS_ONLN);
+  DIR *dir = opendir(CPU_DIR);
+  CHECK(dir != NULL);
+  long found = 0;
+  struct dirent *entry;
+  while ((entry = readdir(dir)) != NULL) {
+    long cpu;
+    char end;
+    if (sscanf(entry->d_name, "cpu%ld%c", &cpu, &end) != 1)
+      continue;
+    CHECK(entry->d_type == DT_DIR);
+    CHECK(cpu >= 0 && cpu < cpus);
+    found++;
+  }
+  CHECK(closedir(dir) == 0);
+  CHECK(found == cpus);
+
+  char path[128], buf[64];
+  for (long cpu = 0; cpu < cpus; cpu++) {
+    snprintf(path, sizeof(path), CPU_DIR "/cpu%ld/topology/core_id", cpu);
+    read_file(path, buf, sizeof(buf));
+    CHECK(atol(buf) == cpu);
+    snprintf(path, sizeof(path),
+             CPU_DIR "/cpu%ld/topology/physical_package_id", cpu);
+    read_file(path, buf, sizeof(buf));
+    CHECK(strcmp(buf, "0\n") == 0);
+  }
+
+  struct stat st;
+  snprintf(path, sizeof(path), CPU_DIR "/cpu%ld", cpus);
+  CHECK(stat(path, &st) == -1 && errno == ENOENT);
+  CHECK(stat(CPU_DIR "/cpu00", &st) == -1 && errno == ENOENT);
+  CHECK(stat(CPU_DIR "/cpu0", &st) == 0 && S_ISDIR(st.st_mode));
+  puts("test_cpu_dirs ok");
+}
+
+// Nothing can be written.
+void test_read_only() {
+  int fd = open(CPU_DIR "/online", O_WRONLY);
+  if (fd >= 0) {
+    CHECK(write(fd, "0\n", 2) == -1 && errno == EACCES);
+    CHECK(close(fd) == 0);
+  } else {
+    CHECK(errno == EACCES);
+  }
+  CHECK(mkdir(CPU_DIR "/cpu99", 0755) == -1);
+  puts("test_read_only ok");
+}
+
+int main() {
+  test_lists();
+  test_cpu_dirs();
+  test_read_only();
+  return 0;
+}
@@ -0,0 +1,284 @@
+#define _GNU_SOURCE
+#include <arpa/inet.h>
+#include <errno.h>
+#include <netinet/in.h>
+#include <netinet/tcp.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define NODELAY_PORT 5560
+#define NAGLE_PORT 5561
+#define PENDING_PORT 5562
+#define LINGER_PORT 5563
+#define RESET_PORT 5564
+#define SHUT_WR_PORT 5565
+
+#define ROUNDS 100
+// More than the send buffer holds, so that some is still unsent on close.
+#define PENDING_LEN (256 * 1024)
+#define RESET_LEN (16 * 1024)
+#define SETTLE_US 100000
+
+static char buf[PENDING_LEN];
+
+static long now_ns(void) {
+  struct timespec ts;
+  clock_gettime(CLOCK_MONOTONIC, &ts);
+  return ts.tv_sec * 1000000000L + ts.tv_nsec;
+}
+
+static struct sockaddr_in loopback(int port) {
+  struct sockaddr_in addr = {0};
+  addr.sin_family = AF_INET;
+  addr.sin_port = htons(port);
+  addr.sin_addr.s_addr = htonl(INADDR_LOOPBACK);
+  return addr;
+}
+
+static int listen_on(int port) {
+  int fd = socket(AF_INET, SOCK_STREAM, 0);
+  CHECK(fd >= 0);
+  struct sockaddr_in addr = loopback(port);
+  CHECK(bind(fd, (struct sockaddr *)&addr, sizeof(addr)) == 0);
+  CHECK(listen(fd, 1) == 0);
+  return fd;
+}
+
+static int connect_to(int port) {
+  int fd = socket(AF_INET, SOCK_STREAM, 0);
+  CHECK(fd >= 0);
+  struct sockaddr_in addr = loopback(port);
+  CHECK(connect(fd, (struct sockaddr *)&addr, sizeof(addr)) == 0);
+  return fd;
+}
+
+static void set_nodelay(int fd, int on) {
+  CHECK(setsockopt(fd, IPPROTO_TCP, TCP_NODELAY, &on, sizeof(on)) == 0);
+}
+
+static void set_linger(int fd, int onoff, int secs) {
+  struct linger linger = {.l_onoff = onoff, .l_linger = secs};
+  CHECK(setsockopt(fd, SOL_SOCKET, SO_LINGER, &linger, sizeof(linger)) == 0);
+}
+
+// Accept a connection on `listener` in a child, and run `serve` on it,
+// whose result is the exit status of the child.
+static pid_t serve_child(int listener, int (*serve)(int)) {
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    int fd = accept(listener, NULL, NULL);
+    CHECK(fd >= 0);
+    _exit(serve(fd));
+  }
+  return pid;
+}
+
+static void check_child(pid_t pid) {
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+// Answer each request of two bytes with one, until the end.
+static int serve_requests(int fd) {
+  char req[2];
+  for (;;) {
+    size_t got = 0;
+    while (got < sizeof(req)) {
+      ssize_t n = read(fd, req + got, sizeof(req) - got);
+      if (n <= 0) {
+        return got == 0 && n == 0 ? 0 : 1;
+      }
+      got += n;
+    }
+    if (write(fd, "r", 1) != 1) {
+      return 1;
+    }
+  }
+}
+
+// The average round trip of a request written in two parts, in ns.
+static long round_trip(int port, int nodelay) {
+  int listener = listen_on(port);
+  // Inherited by the accepted socket.
+  set_nodelay(listener, nodelay);
+  pid_t pid = serve_child(listener, serve_requests);
+  int fd = connect_to(port);
+  set_nodelay(fd, nodelay);
+  int on = -1;
+  socklen_t len = sizeof(on);
+  CHECK(getsockopt(fd, IPPROTO_TCP, TCP_NODELAY, &on, &len) == 0);
+  CHECK(len == sizeof(on) && on == nodelay);
+
+  char reply;
+  long start = now_ns();
+  for (int i = 0; i < ROUNDS; i++) {
+    CHECK(write(fd, "a", 1) == 1);
+    CHECK(write(fd, "b", 1) == 1);
+    CHECK(read(fd, &reply, 1) == 1 && reply == 'r');
+  }
+  long elapsed = now_ns() - start;
+  close(fd);
+  check_child(pid);
+  close(listener);
+  return elapsed / ROUNDS;
+}
+
+// With `TCP_NODELAY`, the second part of a request is not held back until
+// the first is acknowledged.
+void test_nodelay() {
+  long nagle = round_trip(NAGLE_PORT, 0);
+  long nodelay = round_trip(NODELAY_PORT, 1);
+  printf("tcp_opts: round trip %ld us with Nagle, %ld us with TCP_NODELAY\n",
+         nagle / 1000, nodelay / 1000);
+  CHECK(nodelay < 1000000);
+  puts("test_nodelay ok");
+}
+
+// Read everything until the end, and check it is what `send_pending` sent.
+static int receive_all(int fd) {
+  usleep(SETTLE_US);
+  static char got[PENDING_LEN];
+  size_t total = 0;
+  ssize_t n;
+  while ((n = read(fd, got + total, PENDING_LEN - total)) > 0) {
+    total += n;
+  }
+  return n == 0 && total == PENDING_LEN && memcmp(got, buf, total) == 0 ? 0 : 1;
+}
+
+// Write more than fits in the buffers and close at once. Returns how long
+// closing took, in ns.
+static long send_pending(int port, int linger_secs) {
+  for (int i = 0; i < PENDING_LEN; i++) {
+    buf[i] = i * 7 + i / 4096;
+  }
+  int listener = listen_on(port);
+  pid_t pid = serve_child(listener, receive_all);
+  int fd = connect_to(port);
+  if (linger_secs > 0) {
+    set_linger(fd, 1, linger_secs);
+    struct linger linger;
+    socklen_t len = sizeof(linger);
+    CHECK(getsockopt(fd, SOL_SOCKET, SO_LINGER, &linger, &len) == 0);
+    CHECK(len == sizeof(linger) && linger.l_onoff &&
+          linger.l_linger == linger_secs);
+  }
+  CHECK(write(fd, buf, PENDING_LEN) == PENDING_LEN);
+  long start = now_ns();
+  CHECK(close(fd) == 0);
+  long elapsed = now_ns() - start;
+  check_child(pid);
+  close(listener);
+  return elapsed;
+}
+
+// What is still unsent when a socket is closed reaches the peer.
+void test_close_pending() {
+  send_pending(PENDING_PORT, 0);
+  puts("test_close_pending ok");
+}
+
+// With `SO_LINGER`, closing waits for what is unsent to be acknowledged,
+// which takes less than the timeout.
+void test_linger() {
+  long elapsed = send_pending(LINGER_PORT, 5);
+  CHECK(elapsed < 5000000000L);
+  puts("test_linger ok");
+}
+
+// Read until the connection is reset, after which writing fails too,
+// unlike after an orderly close.
+static int receive_reset(int fd) {
+  usleep(SETTLE_US);
+  ssize_t n;
+  while ((n = read(fd, buf, sizeof(buf))) > 0) {
+  }
+  if (n == -1 && errno != ECONNRESET) {
+    return 1;
+  }
+  n = write(fd, "r", 1);
+  return n == -1 && (errno == ECONNRESET || errno == EPIPE) ? 0 : 1;
+}
+
+// With a linger time of zero, closing resets the connection, and discards
+// what is unsent.
+void test_linger_zero() {
+  signal(SIGPIPE, SIG_IGN);
+  int listener = listen_on(RESET_PORT);
+  pid_t pid = serve_child(listener, receive_reset);
+  int fd = connect_to(RESET_PORT);
+  set_linger(fd, 1, 0);
+  CHECK(write(fd, buf, RESET_LEN) == RESET_LEN);
+  CHECK(close(fd) == 0);
+  check_child(pid);
+  close(listener);
+  puts("test_linger_zero ok");
+}
+
+// Count what is received until the end, then reply with the count.
+static int count_then_reply(int fd) {
+  long total = 0;
+  ssize_t n;
+  while ((n = read(fd, buf, sizeof(buf))) > 0) {
+    total += n;
+  }
+  if (n != 0) {
+    return 1;
+  }
+  char reply[32];
+  int len = snprintf(reply, sizeof(reply), "%ld", total);
+  return write(fd, reply, len) == len ? 0 : 1;
+}
+
+// Shutting down writing ends the stream for the peer, while the reply can
+// still be read.
+void test_shut_wr() {
+  int fd = socket(AF_INET, SOCK_STREAM, 0);
+  CHECK(fd >= 0);
+  CHECK(shutdown(fd, SHUT_WR) == -1 && errno == ENOTCONN);
+  close(fd);
+
+  int listener = listen_on(SHUT_WR_PORT);
+  pid_t pid = serve_child(listener, count_then_reply);
+  fd = connect_to(SHUT_WR_PORT);
+  CHECK(write(fd, buf, 4096) == 4096);
+  CHECK(shutdown(fd, 42) == -1 && errno == EINVAL);
+  CHECK(shutdown(fd, SHUT_WR) == 0);
+  CHECK(write(fd, buf, 1) == -1 && errno == EPIPE);
+  char reply[32] = {0};
+  size_t got = 0;
+  ssize_t n;
+  while ((n = read(fd, reply + got, sizeof(reply) - 1 - got)) > 0) {
+    got += n;
+  }
+  CHECK(n == 0 && strcmp(reply, "4096") == 0);
+  close(fd);
+  check_child(pid);
+  close(listener);
+  puts("test_shut_wr ok");
+}
+
+int main() {
+  test_nodelay();
+  test_close_pending();
+  test_linger();
+  test_linger_zero();
+  test_shut_wr();
+  return 0;
+}
@@ -0,0 +1,139 @@
+#define _GNU_SOURCE
+#include <dirent.h>
+#include <errno.h>
+#include <pthread.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <time.h>
+#include <unistd.h>
+
+static pthread_barrier_t started, done;
+static pid_t tids[2];
+static volatile unsigned long sink;
+
+static void spin(unsigned long rounds) {
+  for (unsigned long i = 0; i < rounds; i++) {
+    sink += i;
+  }
+}
+
+static void *worker(void *arg) {
+  long index = (long)arg;
+  tids[index] = syscall(SYS_gettid);
+  pthread_barrier_wait(&started);
+  spin(index == 0 ? 200000000UL : 2000000UL);
+  pthread_barrier_wait(&done);
+  // Stay alive until the main thread is done reading the clocks.
+  pthread_barrier_wait(&started);
+  return NULL;
+}
+
+static long long clock_ns(clockid_t clock) {
+  struct timespec ts;
+  if (clock_gettime(clock, &ts) != 0) {
+    return -1;
+  }
+  return ts.tv_sec * 1000000000LL + ts.tv_nsec;
+}
+
+static int read_file(const char *path, char *buf, size_t size) {
+  FILE *f = fopen(path, "r");
+  if (!f) {
+    return -1;
+  }
+  size_t len = fread(buf, 1, size - 1, f);
+  buf[len] = 0;
+  fclose(f);
+  return 0;
+}
+
+void test_thread_clock(pthread_t heavy, pthread_t light) {
+  clockid_t heavy_clock, light_clock;
+  if (pthread_getcpuclockid(heavy, &heavy_clock) != 0 ||
+      pthread_getcpuclockid(light, &light_clock) != 0) {
+    return;
+  }
+  long long heavy_ns = clock_ns(heavy_clock);
+  long long light_ns = clock_ns(light_clock);
+  long long self_ns = clock_ns(CLOCK_THREAD_CPUTIME_ID);
+  long long proc_ns = clock_ns(CLOCK_PROCESS_CPUTIME_ID);
+  if (heavy_ns > 0 && light_ns >= 0 && self_ns >= 0 &&
+      heavy_ns > light_ns * 10 && proc_ns >= heavy_ns + light_ns &&
+      proc_ns < (heavy_ns + light_ns) * 2) {
+    puts("test_thread_clock ok");
+  }
+}
+
+void test_process_clock() {
+  clockid_t clock;
+  struct timespec res;
+  if (clock_getcpuclockid(0, &clock) == 0 && clock_ns(clock) > 0 &&
+      clock_getres(clock, &res) == 0 &&
+      clock_getcpuclockid(99999, &clock) == ESRCH) {
+    puts("test_process_clock ok");
+  }
+}
+
+void test_task_dir() {
+  DIR *dir = opendir("/proc/self/task");
+  if (!dir) {
+    return;
+  }
+  int count = 0, found = 0;
+  struct dirent *entry;
+  while ((entry = readdir(dir))) {
+    if (entry->d_name[0] == '.') {
+      continue;
+    }
+    count++;
+    pid_t tid = atoi(entry->d_name);
+    if (tid == getpid() || tid == tids[0] || tid == tids[1]) {
+      found++;
+    }
+  }
+  closedir(dir);
+  if (count == 3 && found == 3) {
+    puts("test_task_dir ok");
+  }
+}
+
+void test_task_stat() {
+  char path[64], buf[1024], expect[64];
+  snprintf(path, sizeof(path), "/proc/self/task/%d/stat", tids[0]);
+  if (read_file(path, buf, sizeof(buf)) != 0 || atoi(buf) != tids[0]) {
+    return;
+  }
+  snprintf(path, sizeof(path), "/proc/self/task/%d/status", tids[0]);
+  if (read_file(path, buf, sizeof(buf)) != 0) {
+    return;
+  }
+  snprintf(expect, sizeof(expect), "Tgid:\t%d\nPid:\t%d\n", getpid(),
+           tids[0]);
+  if (strstr(buf, expect)) {
+    puts("test_task_stat ok");
+  }
+}
+
+int main() {
+  pthread_t threads[2];
+  pthread_barrier_init(&started, NULL, 3);
+  pthread_barrier_init(&done, NULL, 3);
+  for (long i = 0; i < 2; i++) {
+    pthread_create(&threads[i], NULL, worker, (void *)i);
+  }
+  pthread_barrier_wait(&started);
+  pthread_barrier_wait(&done);
+
+  test_thread_clock(threads[0], threads[1]);
+  test_process_clock();
+  test_task_dir();
+  test_task_stat();
+
+  pthread_barrier_wait(&started);
+  for (int i = 0; i < 2; i++) {
+    pthread_join(threads[i], NULL);
+  }
+  return 0;
+}
@@ -0,0 +1,85 @@
+#define _GNU_SOURCE
+#include <stdio.h>
+#include <stdlib.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+// Longer than an idle CPU goes without a timer interrupt.
+#define LONG_SLEEP_S 3
+#define SHORT_SLEEPS 50
+#define SHORT_SLEEP_MS 20
+
+static double now(clockid_t clock) {
+  struct timespec ts;
+  CHECK(clock_gettime(clock, &ts) == 0);
+  return ts.tv_sec + ts.tv_nsec / 1e9;
+}
+
+static double read_uptime(double *idle) {
+  FILE *f = fopen("/proc/uptime", "r");
+  CHECK(f != NULL);
+  double uptime;
+  CHECK(fscanf(f, "%lf %lf", &uptime, idle) == 2);
+  fclose(f);
+  return uptime;
+}
+
+static void sleep_ms(long ms) {
+  struct timespec ts = {.tv_sec = ms / 1000, .tv_nsec = ms % 1000 * 1000000};
+  CHECK(nanosleep(&ts, NULL) == 0);
+}
+
+// With nothing else to run, sleeping long wakes up on time, and the clocks
+// and the uptime all advance by the time slept, most of it idle.
+void test_long_sleep() {
+  double idle_before, idle_after;
+  double uptime_before = read_uptime(&idle_before);
+  double mono_before = now(CLOCK_MONOTONIC);
+  double real_before = now(CLOCK_REALTIME);
+  sleep_ms(LONG_SLEEP_S * 1000);
+  double mono = now(CLOCK_MONOTONIC) - mono_before;
+  double real = now(CLOCK_REALTIME) - real_before;
+  double uptime = read_uptime(&idle_after) - uptime_before;
+  double idle = idle_after - idle_before;
+  printf("tickless: slept %.3fs, %.3fs idle\n", mono, idle);
+
+  CHECK(mono >= LONG_SLEEP_S && mono < LONG_SLEEP_S + 0.1);
+  CHECK(real > mono - 0.01 && real < mono + 0.01);
+  CHECK(uptime > mono - 0.02 && uptime < mono + 0.02);
+  CHECK(idle > LONG_SLEEP_S * 0.8);
+  puts("test_long_sleep ok");
+}
+
+// Sleeping many times in a row oversleeps little each time.
+void test_short_sleeps() {
+  double start = now(CLOCK_MONOTONIC);
+  double longest = 0;
+  for (int i = 0; i < SHORT_SLEEPS; i++) {
+    double before = now(CLOCK_MONOTONIC);
+    sleep_ms(SHORT_SLEEP_MS);
+    double slept = now(CLOCK_MONOTONIC) - before;
+    CHECK(slept >= SHORT_SLEEP_MS / 1e3);
+    if (slept > longest) {
+      longest = slept;
+    }
+  }
+  double total = now(CLOCK_MONOTONIC) - start;
+  printf("tickless: %d sleeps of %dms in %.3fs, longest %.1fms\n",
+         SHORT_SLEEPS, SHORT_SLEEP_MS, total, longest * 1e3);
+  CHECK(total < SHORT_SLEEPS * SHORT_SLEEP_MS / 1e3 * 1.25);
+  puts("test_short_sleeps ok");
+}
+
+int main() {
+  test_long_sleep();
+  test_short_sleeps();
+  return 0;
+}
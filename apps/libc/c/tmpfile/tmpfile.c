@@ -0,0 +1,87 @@
+#define _GNU_SOURCE
+#include <dirent.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define DIR_PATH "/tmp/tmpfile"
+#define NAMED DIR_PATH "/named"
+
+static int count_entries() {
+  DIR *dir = opendir(DIR_PATH);
+  if (dir == NULL) {
+    return -1;
+  }
+  int count = 0;
+  struct dirent *ent;
+  while ((ent = readdir(dir)) != NULL) {
+    if (strcmp(ent->d_name, ".") != 0 && strcmp(ent->d_name, "..") != 0) {
+      count++;
+    }
+  }
+  closedir(dir);
+  return count;
+}
+
+void test_link_tmpfile() {
+  mkdir(DIR_PATH, 0755);
+  unlink(NAMED);
+  int fd = open(DIR_PATH, O_TMPFILE | O_RDWR, 0644);
+  if (fd < 0) {
+    printf("test_link_tmpfile: open: %d\n", errno);
+    return;
+  }
+  struct stat st;
+  if (write(fd, "unnamed", 7) != 7 || fstat(fd, &st) != 0 ||
+      st.st_nlink != 0 || !S_ISREG(st.st_mode) || st.st_size != 7) {
+    return;
+  }
+  // Not visible in the directory.
+  if (count_entries() != 0) {
+    return;
+  }
+  if (linkat(fd, "", AT_FDCWD, NAMED, AT_EMPTY_PATH) != 0) {
+    printf("test_link_tmpfile: linkat: %d\n", errno);
+    return;
+  }
+  close(fd);
+  char buf[16] = {0};
+  fd = open(NAMED, O_RDONLY);
+  if (fd < 0 || read(fd, buf, sizeof(buf)) != 7 || memcmp(buf, "unnamed", 7) ||
+      count_entries() != 1) {
+    return;
+  }
+  close(fd);
+  unlink(NAMED);
+  puts("test_link_tmpfile ok");
+}
+
+void test_excl_tmpfile() {
+  int fd = open(DIR_PATH, O_TMPFILE | O_EXCL | O_WRONLY, 0600);
+  if (fd < 0) {
+    return;
+  }
+  if (linkat(fd, "", AT_FDCWD, NAMED, AT_EMPTY_PATH) != -1 ||
+      errno != ENOENT || access(NAMED, F_OK) == 0) {
+    return;
+  }
+  close(fd);
+  // Gone once closed.
+  if (count_entries() != 0) {
+    return;
+  }
+  // A read-only O_TMPFILE makes no sense.
+  if (open(DIR_PATH, O_TMPFILE | O_RDONLY) != -1 || errno != EINVAL) {
+    return;
+  }
+  puts("test_excl_tmpfile ok");
+}
+
+int main() {
+  test_link_tmpfile();
+  test_excl_tmpfile();
+  return 0;
+}
@@ -0,0 +1,116 @@
+#define _GNU_SOURCE
+#include <dirent.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/statfs.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define TMPFS_MAGIC 0x01021994
+#define MSDOS_SUPER_MAGIC 0x4d44
+// The default size of the tmpfs mounted at boot.
+#define BOOT_SIZE (16 << 20)
+#define SCRATCH "/tmp/tmpfs_boot_scratch"
+#define SCRATCH_SIZE (8 << 20)
+#define MARKER "tmpfs_boot_marker"
+
+static long page;
+
+static long free_blocks(const char *path) {
+  struct statfs st;
+  CHECK(statfs(path, &st) == 0);
+  return st.f_bfree;
+}
+
+static int has_entry(const char *dir, const char *name) {
+  DIR *d = opendir(dir);
+  CHECK(d != NULL);
+  struct dirent *ent;
+  int found = 0;
+  while ((ent = readdir(d)) != NULL)
+    found |= strcmp(ent->d_name, name) == 0;
+  closedir(d);
+  return found;
+}
+
+// /tmp and /run are each a tmpfs of the boot size, and / is the disk.
+void test_boot_mounts() {
+  const char *dirs[] = {"/tmp", "/run"};
+  for (int i = 0; i < 2; i++) {
+    struct statfs st;
+    CHECK(statfs(dirs[i], &st) == 0);
+    CHECK(st.f_type == TMPFS_MAGIC);
+    CHECK(st.f_bsize == page);
+    CHECK((long)st.f_blocks == BOOT_SIZE / page);
+    CHECK(st.f_bfree <= st.f_blocks);
+  }
+  struct statfs st;
+  CHECK(statfs("/", &st) == 0);
+  CHECK(st.f_type == MSDOS_SUPER_MAGIC);
+
+  int fd = open("/tmp", O_RDONLY | O_DIRECTORY);
+  CHECK(fd >= 0);
+  CHECK(fstatfs(fd, &st) == 0);
+  CHECK(st.f_type == TMPFS_MAGIC);
+  close(fd);
+  puts("test_boot_mounts ok");
+}
+
+// A large scratch file takes space from the tmpfs, stays readable once
+// removed while open, and gives the space back once closed.
+void test_scratch_file() {
+  long before = free_blocks("/tmp");
+  int fd = open(SCRATCH, O_RDWR | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  static char buf[1 << 16];
+  for (size_t i = 0; i < sizeof(buf); i++)
+    buf[i] = (char)i;
+  for (int i = 0; i < SCRATCH_SIZE / (int)sizeof(buf); i++)
+    CHECK(write(fd, buf, sizeof(buf)) == sizeof(buf));
+  CHECK(free_blocks("/tmp") == before - SCRATCH_SIZE / page);
+
+  struct statfs st;
+  CHECK(fstatfs(fd, &st) == 0);
+  CHECK(st.f_type == TMPFS_MAGIC);
+
+  CHECK(unlink(SCRATCH) == 0);
+  CHECK(access(SCRATCH, F_OK) != 0);
+  static char back[sizeof(buf)];
+  CHECK(pread(fd, back, sizeof(back), SCRATCH_SIZE - sizeof(back)) ==
+        sizeof(back));
+  CHECK(memcmp(back, buf, sizeof(buf)) == 0);
+  close(fd);
+  CHECK(free_blocks("/tmp") == before);
+  puts("test_scratch_file ok");
+}
+
+// Nothing left in /tmp or /run by the previous boot is there, and this
+// one leaves a marker for the next to check.
+void test_empty_at_boot() {
+  const char *markers[] = {"/tmp/" MARKER, "/run/" MARKER};
+  CHECK(!has_entry("/tmp", MARKER));
+  CHECK(!has_entry("/run", MARKER));
+  for (int i = 0; i < 2; i++) {
+    int fd = open(markers[i], O_WRONLY | O_CREAT | O_EXCL, 0644);
+    CHECK(fd >= 0);
+    close(fd);
+  }
+  puts("test_empty_at_boot ok");
+}
+
+int main() {
+  page = sysconf(_SC_PAGESIZE);
+  test_boot_mounts();
+  test_scratch_file();
+  test_empty_at_boot();
+  return 0;
+}
@@ -0,0 +1,114 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/ioctl.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      _exit(1);                                                                \
+    }                                                                          \
+  } while (0)
+
+static int hangup_pipe[2];
+
+static void on_hangup(int sig) {
+  (void)sig;
+  write(hangup_pipe[1], "h", 1);
+}
+
+// Start a background process group which reads from the terminal.
+static pid_t spawn_background_reader(int ignore_ttin) {
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    setpgid(0, 0);
+    if (ignore_ttin) {
+      signal(SIGTTIN, SIG_IGN);
+    }
+    char c;
+    ssize_t ret = read(0, &c, 1);
+    _exit(ret < 0 ? errno : 0);
+  }
+  setpgid(pid, pid);
+  return pid;
+}
+
+static void test_ttin_stop(void) {
+  pid_t pid = spawn_background_reader(0);
+  int status;
+  CHECK(waitpid(pid, &status, WUNTRACED) == pid);
+  CHECK(WIFSTOPPED(status) && WSTOPSIG(status) == SIGTTIN);
+  CHECK(kill(pid, SIGCONT) == 0);
+  CHECK(waitpid(pid, &status, WCONTINUED) == pid);
+  CHECK(WIFCONTINUED(status));
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == EINTR);
+  printf("test_ttin_stop ok\n");
+}
+
+static void test_ttin_ignored(void) {
+  pid_t pid = spawn_background_reader(1);
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == EIO);
+  printf("test_ttin_ignored ok\n");
+}
+
+// Runs in a new session, which takes the terminal and leaves a process in
+// its foreground group to be hung up when the session leader exits.
+static void session_leader(void) {
+  CHECK(setsid() == getpid());
+  CHECK(getsid(0) == getpid() && getpgid(0) == getpid());
+  pid_t fg;
+  CHECK(ioctl(0, TIOCGPGRP, &fg) < 0 && errno == ENOTTY);
+  CHECK(ioctl(0, TIOCSCTTY, 0) == 0);
+  CHECK(ioctl(0, TIOCGPGRP, &fg) == 0 && fg == getpid());
+  pid_t sid;
+  CHECK(ioctl(0, TIOCGSID, &sid) == 0 && sid == getpid());
+
+  test_ttin_stop();
+  test_ttin_ignored();
+
+  sigset_t mask, old;
+  sigemptyset(&mask);
+  sigaddset(&mask, SIGHUP);
+  sigprocmask(SIG_BLOCK, &mask, &old);
+  signal(SIGHUP, on_hangup);
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    sigsuspend(&old);
+    _exit(0);
+  }
+  _exit(0);
+}
+
+static void test_session_hangup(void) {
+  CHECK(pipe(hangup_pipe) == 0);
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    session_leader();
+  }
+  close(hangup_pipe[1]);
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  char c;
+  CHECK(read(hangup_pipe[0], &c, 1) == 1 && c == 'h');
+  close(hangup_pipe[0]);
+  printf("test_session_hangup ok\n");
+}
+
+int main(void) {
+  // Not a session leader, and not in the session of the terminal.
+  CHECK(ioctl(0, TIOCSCTTY, 0) < 0 && errno == EPERM);
+  test_session_hangup();
+  return 0;
+}
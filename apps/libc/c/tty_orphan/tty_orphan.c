@@ -0,0 +1,171 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/ioctl.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      _exit(1);                                                                \
+    }                                                                          \
+  } while (0)
+
+static int report_pipe[2];
+
+static void on_hangup(int sig) {
+  (void)sig;
+  write(report_pipe[1], "h", 1);
+}
+
+// Become the leader of a new session, controlled by the terminal.
+static void take_terminal(void) {
+  CHECK(setsid() == getpid());
+  CHECK(ioctl(0, TIOCSCTTY, 0) == 0);
+  CHECK(tcgetpgrp(0) == getpid());
+}
+
+// Run `fn` in a child leading a new session, and check it succeeds.
+static void run_session(void (*fn)(void)) {
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    take_terminal();
+    fn();
+    _exit(0);
+  }
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+// The inner shell: it runs a pipeline in the foreground, which the outer
+// shell interrupts, and exits without taking the terminal back.
+static void inner_shell(void) {
+  // Like an interactive shell.
+  signal(SIGTTOU, SIG_IGN);
+  signal(SIGTTIN, SIG_IGN);
+  signal(SIGINT, SIG_IGN);
+  pid_t pipeline = fork();
+  CHECK(pipeline >= 0);
+  if (pipeline == 0) {
+    setpgid(0, 0);
+    signal(SIGINT, SIG_DFL);
+    pause();
+    _exit(0);
+  }
+  setpgid(pipeline, pipeline);
+  CHECK(tcsetpgrp(0, pipeline) == 0);
+  int status;
+  CHECK(waitpid(pipeline, &status, 0) == pipeline);
+  CHECK(WIFSIGNALED(status) && WTERMSIG(status) == SIGINT);
+  _exit(0);
+}
+
+// The outer shell, leading the session: it runs the inner shell in the
+// foreground, and types Ctrl-C once the inner shell has put its pipeline
+// in the foreground. The test cannot type, so it sends `SIGINT` to the
+// foreground process group as the terminal would.
+static void outer_shell(void) {
+  signal(SIGTTOU, SIG_IGN);
+  pid_t inner = fork();
+  CHECK(inner >= 0);
+  if (inner == 0) {
+    setpgid(0, 0);
+    inner_shell();
+  }
+  setpgid(inner, inner);
+  CHECK(tcsetpgrp(0, inner) == 0);
+  pid_t fg;
+  while ((fg = tcgetpgrp(0)) == inner || fg == getpgrp()) {
+    usleep(1000);
+  }
+  CHECK(kill(-fg, SIGINT) == 0);
+
+  int status;
+  CHECK(waitpid(inner, &status, 0) == inner);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  // The foreground process group is gone, and the terminal goes back to
+  // the outer shell.
+  CHECK(tcgetpgrp(0) == getpgrp());
+}
+
+static void test_nested_shells(void) {
+  run_session(outer_shell);
+  printf("test_nested_shells ok\n");
+}
+
+// Fork a process group of two, where the leader exits once the other
+// member has called `fn`, leaving it orphaned. Returns the pid of the
+// leader.
+static pid_t orphan_group(void (*fn)(void)) {
+  pid_t leader = fork();
+  CHECK(leader >= 0);
+  if (leader == 0) {
+    setpgid(0, 0);
+    pid_t member = fork();
+    CHECK(member >= 0);
+    if (member == 0) {
+      fn();
+      _exit(0);
+    }
+    int status;
+    CHECK(waitpid(member, &status, WUNTRACED) == member);
+    _exit(0);
+  }
+  setpgid(leader, leader);
+  int status;
+  CHECK(waitpid(leader, &status, 0) == leader);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  return leader;
+}
+
+static void stop_until_hangup(void) {
+  signal(SIGHUP, on_hangup);
+  raise(SIGSTOP);
+}
+
+static void test_orphaned_stopped(void) {
+  CHECK(pipe(report_pipe) == 0);
+  orphan_group(stop_until_hangup);
+  close(report_pipe[1]);
+  char c;
+  CHECK(read(report_pipe[0], &c, 1) == 1 && c == 'h');
+  close(report_pipe[0]);
+  printf("test_orphaned_stopped ok\n");
+}
+
+static void read_when_orphaned(void) {
+  // Stop so the leader exits, and wait to be continued by the hang up.
+  signal(SIGHUP, SIG_IGN);
+  raise(SIGSTOP);
+  char c;
+  errno = 0;
+  ssize_t ret = read(0, &c, 1);
+  write(report_pipe[1], ret < 0 && errno == EIO ? "e" : "x", 1);
+}
+
+static void orphaned_reader(void) {
+  CHECK(pipe(report_pipe) == 0);
+  orphan_group(read_when_orphaned);
+  close(report_pipe[1]);
+  char c;
+  CHECK(read(report_pipe[0], &c, 1) == 1 && c == 'e');
+  close(report_pipe[0]);
+}
+
+static void test_orphaned_read(void) {
+  run_session(orphaned_reader);
+  printf("test_orphaned_read ok\n");
+}
+
+int main(void) {
+  test_nested_shells();
+  test_orphaned_stopped();
+  test_orphaned_read();
+  return 0;
+}
@@ -0,0 +1,174 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <linux/capability.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+static struct ucred peer_cred(int fd) {
+  struct ucred cred;
+  socklen_t len = sizeof(cred);
+  CHECK(getsockopt(fd, SOL_SOCKET, SO_PEERCRED, &cred, &len) == 0);
+  CHECK(len == sizeof(cred));
+  return cred;
+}
+
+static void test_peercred(void) {
+  int sv[2];
+  CHECK(socketpair(AF_UNIX, SOCK_STREAM, 0, sv) == 0);
+  pid_t parent = getpid();
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    close(sv[0]);
+    // Made by the parent, so the peer is the parent.
+    struct ucred cred = peer_cred(sv[1]);
+    CHECK(cred.pid == parent && cred.uid == getuid() && cred.gid == getgid());
+    CHECK(write(sv[1], "x", 1) == 1);
+    _exit(0);
+  }
+  close(sv[1]);
+  // A snapshot from when the pair was made, not who holds the other end.
+  struct ucred cred = peer_cred(sv[0]);
+  CHECK(cred.pid == parent);
+  char c;
+  CHECK(read(sv[0], &c, 1) == 1 && c == 'x');
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  // Still there once the peer is gone.
+  CHECK(peer_cred(sv[0]).pid == parent);
+  CHECK(read(sv[0], &c, 1) == 0);
+  close(sv[0]);
+  printf("test_peercred ok\n");
+}
+
+// Send one byte, with `cred` if it is not NULL.
+static ssize_t send_cred(int fd, const struct ucred *cred) {
+  char data = 'c';
+  struct iovec iov = {.iov_base = &data, .iov_len = 1};
+  union {
+    char buf[CMSG_SPACE(sizeof(struct ucred))];
+    struct cmsghdr align;
+  } control;
+  struct msghdr msg = {.msg_iov = &iov, .msg_iovlen = 1};
+  if (cred) {
+    msg.msg_control = control.buf;
+    msg.msg_controllen = sizeof(control.buf);
+    struct cmsghdr *cmsg = CMSG_FIRSTHDR(&msg);
+    cmsg->cmsg_level = SOL_SOCKET;
+    cmsg->cmsg_type = SCM_CREDENTIALS;
+    cmsg->cmsg_len = CMSG_LEN(sizeof(struct ucred));
+    memcpy(CMSG_DATA(cmsg), cred, sizeof(*cred));
+  }
+  return sendmsg(fd, &msg, 0);
+}
+
+// Receive one byte, and the credentials it was sent with.
+static struct ucred recv_cred(int fd) {
+  char data;
+  struct iovec iov = {.iov_base = &data, .iov_len = 1};
+  union {
+    char buf[CMSG_SPACE(sizeof(struct ucred))];
+    struct cmsghdr align;
+  } control;
+  struct msghdr msg = {.msg_iov = &iov,
+                       .msg_iovlen = 1,
+                       .msg_control = control.buf,
+                       .msg_controllen = sizeof(control.buf)};
+  CHECK(recvmsg(fd, &msg, 0) == 1 && data == 'c');
+  CHECK(!(msg.msg_flags & MSG_CTRUNC));
+  struct cmsghdr *cmsg = CMSG_FIRSTHDR(&msg);
+  CHECK(cmsg != NULL);
+  CHECK(cmsg->cmsg_level == SOL_SOCKET && cmsg->cmsg_type == SCM_CREDENTIALS);
+  CHECK(cmsg->cmsg_len == CMSG_LEN(sizeof(struct ucred)));
+  struct ucred cred;
+  memcpy(&cred, CMSG_DATA(cmsg), sizeof(cred));
+  return cred;
+}
+
+static void drop_sys_admin(void) {
+  struct __user_cap_header_struct header = {_LINUX_CAPABILITY_VERSION_3, 0};
+  struct __user_cap_data_struct data[2];
+  CHECK(syscall(SYS_capget, &header, data) == 0);
+  data[0].effective &= ~CAP_TO_MASK(CAP_SYS_ADMIN);
+  data[0].permitted &= ~CAP_TO_MASK(CAP_SYS_ADMIN);
+  CHECK(syscall(SYS_capset, &header, data) == 0);
+}
+
+static void test_scm_credentials(void) {
+  int sv[2];
+  CHECK(socketpair(AF_UNIX, SOCK_STREAM, 0, sv) == 0);
+  int on = 1;
+  CHECK(setsockopt(sv[0], SOL_SOCKET, SO_PASSCRED, &on, sizeof(on)) == 0);
+  int value;
+  socklen_t len = sizeof(value);
+  CHECK(getsockopt(sv[0], SOL_SOCKET, SO_PASSCRED, &value, &len) == 0);
+  CHECK(value == 1 && len == sizeof(value));
+
+  pid_t parent = getpid();
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    close(sv[0]);
+    // Without credentials, the real ones are received.
+    CHECK(send_cred(sv[1], NULL) == 1);
+    struct ucred own = {.pid = getpid(), .uid = getuid(), .gid = getgid()};
+    CHECK(send_cred(sv[1], &own) == 1);
+    // Another pid, allowed with CAP_SYS_ADMIN.
+    struct ucred forged = own;
+    forged.pid = parent;
+    CHECK(send_cred(sv[1], &forged) == 1);
+    drop_sys_admin();
+    CHECK(send_cred(sv[1], &forged) == -1 && errno == EPERM);
+    CHECK(send_cred(sv[1], &own) == 1);
+    _exit(0);
+  }
+  close(sv[1]);
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+
+  // One byte at a time, as the credentials of each differ.
+  CHECK(recv_cred(sv[0]).pid == pid);
+  CHECK(recv_cred(sv[0]).pid == pid);
+  CHECK(recv_cred(sv[0]).pid == parent);
+  struct ucred cred = recv_cred(sv[0]);
+  CHECK(cred.pid == pid && cred.uid == getuid() && cred.gid == getgid());
+  char c;
+  CHECK(read(sv[0], &c, 1) == 0);
+  close(sv[0]);
+  printf("test_scm_credentials ok\n");
+}
+
+static void test_errors(void) {
+  int sv[2];
+  CHECK(socketpair(AF_INET, SOCK_STREAM, 0, sv) == -1 && errno == EOPNOTSUPP);
+  CHECK(socketpair(AF_UNIX, SOCK_STREAM | SOCK_CLOEXEC | SOCK_NONBLOCK, 0,
+                   sv) == 0);
+  char c;
+  CHECK(read(sv[0], &c, 1) == -1 && errno == EAGAIN);
+  close(sv[1]);
+  CHECK(read(sv[0], &c, 1) == 0);
+  close(sv[0]);
+  printf("test_errors ok\n");
+}
+
+int main(void) {
+  test_peercred();
+  test_scm_credentials();
+  test_errors();
+  return 0;
+}
@@ -0,0 +1,183 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <linux/capability.h>
+#include <pthread.h>
+#include <sched.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/stat.h>
+#include <sys/syscall.h>
+#include <sys/utsname.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define DIR_PATH "/unshare_dir"
+#define RACE_FDS 64
+
+static int is_open(int fd) { return fcntl(fd, F_GETFD) != -1; }
+
+// Like fork, with `flags` as well.
+static pid_t clone_with(int flags) {
+  return syscall(SYS_clone, flags | SIGCHLD, 0, 0, 0, 0);
+}
+
+static void wait_ok(pid_t pid) {
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+}
+
+static void test_files(void) {
+  int shared = dup(0), closed = dup(0);
+  CHECK(shared >= 0 && closed >= 0);
+  pid_t pid = clone_with(CLONE_FILES);
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    // Still shared: gone from the parent as well.
+    close(closed);
+    CHECK(unshare(CLONE_FILES) == 0);
+    // Now only in the copy.
+    close(shared);
+    CHECK(!is_open(shared));
+    _exit(0);
+  }
+  wait_ok(pid);
+  CHECK(!is_open(closed));
+  CHECK(is_open(shared));
+  close(shared);
+  printf("test_files ok\n");
+}
+
+// Open descriptors while the other thread unshares the table, and check
+// that none is lost in between, as seen from this thread.
+static void *dup_many(void *arg) {
+  int fds[RACE_FDS];
+  for (int i = 0; i < RACE_FDS; i++)
+    fds[i] = dup(0);
+  for (int i = 0; i < RACE_FDS; i++)
+    if (fds[i] < 0 || !is_open(fds[i]))
+      return (void *)1;
+  return NULL;
+}
+
+static void test_race(void) {
+  pid_t pid = clone_with(CLONE_FILES);
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    pthread_t thread;
+    void *lost;
+    CHECK(pthread_create(&thread, NULL, dup_many, NULL) == 0);
+    CHECK(unshare(CLONE_FILES) == 0);
+    CHECK(pthread_join(thread, &lost) == 0 && lost == NULL);
+    _exit(0);
+  }
+  wait_ok(pid);
+  printf("test_race ok\n");
+}
+
+static void test_fs(void) {
+  CHECK(mkdir(DIR_PATH, 0755) == 0);
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    // Not shared anyway.
+    CHECK(unshare(CLONE_FS) == 0);
+    CHECK(chdir(DIR_PATH) == 0);
+    _exit(0);
+  }
+  wait_ok(pid);
+  char cwd[256];
+  CHECK(getcwd(cwd, sizeof(cwd)) && strcmp(cwd, DIR_PATH) != 0);
+  CHECK(rmdir(DIR_PATH) == 0);
+  printf("test_fs ok\n");
+}
+
+static void *wait_pipe(void *arg) {
+  char c;
+  read(*(int *)arg, &c, 1);
+  return NULL;
+}
+
+static void test_fs_shared(void) {
+  CHECK(mkdir(DIR_PATH, 0755) == 0);
+  pid_t pid = clone_with(CLONE_FS);
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    // Still shared: the parent moves as well.
+    CHECK(chdir(DIR_PATH) == 0);
+    // The other thread shares the directory, which cannot be left to it.
+    int fds[2];
+    pthread_t thread;
+    CHECK(pipe(fds) == 0);
+    CHECK(pthread_create(&thread, NULL, wait_pipe, &fds[0]) == 0);
+    CHECK(unshare(CLONE_FS) == -1 && errno == EINVAL);
+    CHECK(write(fds[1], "x", 1) == 1);
+    CHECK(pthread_join(thread, NULL) == 0);
+    CHECK(unshare(CLONE_FS) == 0);
+    // Now only in the copy.
+    CHECK(chdir("/") == 0);
+    _exit(0);
+  }
+  wait_ok(pid);
+  char cwd[256];
+  CHECK(getcwd(cwd, sizeof(cwd)) && strcmp(cwd, DIR_PATH) == 0);
+  CHECK(chdir("/") == 0);
+  CHECK(rmdir(DIR_PATH) == 0);
+  printf("test_fs_shared ok\n");
+}
+
+static void drop_sys_admin(void) {
+  struct __user_cap_header_struct header = {_LINUX_CAPABILITY_VERSION_3, 0};
+  struct __user_cap_data_struct data[2];
+  CHECK(syscall(SYS_capget, &header, data) == 0);
+  data[0].effective &= ~CAP_TO_MASK(CAP_SYS_ADMIN);
+  data[0].permitted &= ~CAP_TO_MASK(CAP_SYS_ADMIN);
+  CHECK(syscall(SYS_capset, &header, data) == 0);
+}
+
+static void test_uts(void) {
+  struct utsname before, after;
+  CHECK(uname(&before) == 0);
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    CHECK(unshare(CLONE_NEWUTS) == 0);
+    CHECK(sethostname("unshared", 8) == 0);
+    struct utsname name;
+    CHECK(uname(&name) == 0 && strcmp(name.nodename, "unshared") == 0);
+    drop_sys_admin();
+    CHECK(unshare(CLONE_NEWUTS) == -1 && errno == EPERM);
+    _exit(0);
+  }
+  wait_ok(pid);
+  CHECK(uname(&after) == 0);
+  CHECK(strcmp(before.nodename, after.nodename) == 0);
+  printf("test_uts ok\n");
+}
+
+static void test_errors(void) {
+  CHECK(unshare(CLONE_PTRACE) == -1 && errno == EINVAL);
+  CHECK(unshare(CLONE_FILES | CLONE_VFORK) == -1 && errno == EINVAL);
+  printf("test_errors ok\n");
+}
+
+int main(void) {
+  test_files();
+  test_race();
+  test_fs();
+  test_fs_shared();
+  test_uts();
+  test_errors();
+  return 0;
+}
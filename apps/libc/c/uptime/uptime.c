@@ -0,0 +1,97 @@
+#define _GNU_SOURCE
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/sysinfo.h>
+#include <sys/times.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+static double read_uptime(double *idle) {
+  FILE *f = fopen("/proc/uptime", "r");
+  CHECK(f != NULL);
+  double uptime;
+  CHECK(fscanf(f, "%lf %lf", &uptime, idle) == 2);
+  fclose(f);
+  return uptime;
+}
+
+static double boottime(void) {
+  struct timespec ts;
+  CHECK(clock_gettime(CLOCK_BOOTTIME, &ts) == 0);
+  return ts.tv_sec + ts.tv_nsec / 1e9;
+}
+
+// The starttime of `/proc/<pid>/stat`, field 22.
+static unsigned long long start_time(pid_t pid) {
+  char path[64], buf[1024];
+  snprintf(path, sizeof(path), "/proc/%d/stat", pid);
+  FILE *f = fopen(path, "r");
+  CHECK(f != NULL);
+  CHECK(fgets(buf, sizeof(buf), f) != NULL);
+  fclose(f);
+  // Skip the command, which may contain spaces, then fields 3 to 21.
+  char *p = strrchr(buf, ')');
+  CHECK(p != NULL);
+  p += 2;
+  for (int field = 3; field < 22; field++) {
+    p = strchr(p, ' ');
+    CHECK(p != NULL);
+    p++;
+  }
+  return strtoull(p, NULL, 10);
+}
+
+static void test_boot_clocks(void) {
+  double idle;
+  double before = boottime();
+  double uptime = read_uptime(&idle);
+  struct sysinfo info;
+  CHECK(sysinfo(&info) == 0);
+  struct tms tms;
+  clock_t ticks = times(&tms);
+  double after = boottime();
+
+  CHECK(uptime >= before - 0.01 && uptime <= after + 0.01);
+  CHECK(idle >= 0);
+  CHECK(info.uptime >= (long)before - 1 && info.uptime <= (long)after + 1);
+  long hz = sysconf(_SC_CLK_TCK);
+  CHECK(ticks >= (clock_t)((before - 0.01) * hz));
+  CHECK(ticks <= (clock_t)((after + 0.01) * hz));
+  printf("test_boot_clocks ok\n");
+}
+
+static void test_start_time(void) {
+  long hz = sysconf(_SC_CLK_TCK);
+  unsigned long long parent = start_time(getpid());
+  CHECK(parent <= (unsigned long long)(boottime() * hz));
+  sleep(1);
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    pause();
+    _exit(0);
+  }
+  unsigned long long child = start_time(pid);
+  CHECK(child >= parent + hz);
+  CHECK(child <= (unsigned long long)(boottime() * hz));
+  kill(pid, SIGKILL);
+  waitpid(pid, NULL, 0);
+  printf("test_start_time ok\n");
+}
+
+int main(void) {
+  test_boot_clocks();
+  test_start_time();
+  return 0;
+}
@@ -0,0 +1,205 @@
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+#define IMAGE "/utime_ns.img"
+#define FAT_MNT "/utime_ns_fat"
+#define TMP_MNT "/utime_ns_tmp"
+#define SECTORS 2048
+
+static void put16(uint8_t *p, uint16_t v) {
+  p[0] = v;
+  p[1] = v >> 8;
+}
+
+// Write an empty 1 MiB FAT12 image, with 4 sectors per cluster.
+static int make_image(const char *path) {
+  static uint8_t sector[512];
+  int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  if (fd < 0) {
+    return -1;
+  }
+  for (int i = 0; i < SECTORS; i++) {
+    memset(sector, 0, sizeof(sector));
+    if (i == 0) {
+      memcpy(sector, "\xeb\x3c\x90MSDOS5.0", 11);
+      put16(sector + 11, 512);  // bytes per sector
+      sector[13] = 4;           // sectors per cluster
+      put16(sector + 14, 1);    // reserved sectors
+      sector[16] = 2;           // FATs
+      put16(sector + 17, 512);  // root entries
+      put16(sector + 19, SECTORS);
+      sector[21] = 0xf8;        // media
+      put16(sector + 22, 2);    // sectors per FAT
+      put16(sector + 24, 32);   // sectors per track
+      put16(sector + 26, 64);   // heads
+      sector[36] = 0x80;        // drive number
+      sector[38] = 0x29;        // extended boot signature
+      memcpy(sector + 39, "\x78\x56\x34\x12NO NAME    FAT12   ", 23);
+      sector[510] = 0x55;
+      sector[511] = 0xaa;
+    } else if (i == 1 || i == 3) {
+      memcpy(sector, "\xf8\xff\xff", 3);
+    }
+    if (write(fd, sector, sizeof(sector)) != sizeof(sector)) {
+      close(fd);
+      return -1;
+    }
+  }
+  return close(fd);
+}
+
+// Set the times of a new file at `path` and check that statx reads back
+// `expected`.
+static int round_trip(const char *path, const struct timespec times[2],
+                      const struct timespec expected[2]) {
+  int fd = open(path, O_WRONLY | O_CREAT, 0644);
+  if (fd < 0) {
+    return 0;
+  }
+  close(fd);
+  struct statx stx;
+  int ok = utimensat(AT_FDCWD, path, times, 0) == 0 &&
+           statx(AT_FDCWD, path, 0, STATX_BASIC_STATS, &stx) == 0 &&
+           stx.stx_atime.tv_sec == expected[0].tv_sec &&
+           stx.stx_atime.tv_nsec == expected[0].tv_nsec &&
+           stx.stx_mtime.tv_sec == expected[1].tv_sec &&
+           stx.stx_mtime.tv_nsec == expected[1].tv_nsec;
+  unlink(path);
+  return ok;
+}
+
+void test_tmpfs_ns() {
+  const struct timespec times[2] = {{1000000001, 123456789},
+                                    {1700000000, 999999999}};
+  if (mkdir(TMP_MNT, 0755) != 0 || mount("tmpfs", TMP_MNT, "tmpfs", 0, NULL)) {
+    return;
+  }
+  int ok = round_trip(TMP_MNT "/file", times, times);
+  if (umount(TMP_MNT) == 0 && ok) {
+    puts("test_tmpfs_ns ok");
+  }
+}
+
+void test_vfat_rounding() {
+  const struct timespec times[2] = {{1000000001, 123456789},
+                                    {1700000000, 999999999}};
+  // vfat keeps the access date and the modification time to 2 seconds,
+  // rounded toward zero.
+  const struct timespec expected[2] = {{999993600, 0}, {1700000000, 0}};
+  if (make_image(IMAGE) != 0 || mkdir(FAT_MNT, 0755) != 0 ||
+      mount(IMAGE, FAT_MNT, "vfat", 0, NULL) != 0) {
+    return;
+  }
+  int ok = round_trip(FAT_MNT "/file", times, expected);
+  if (umount(FAT_MNT) == 0 && ok) {
+    puts("test_vfat_rounding ok");
+  }
+}
+
+// Check that the times set on vfat are in the directory entry, which the
+// next mount reads.
+void test_vfat_persist() {
+  const struct timespec times[2] = {{1000000001, 0}, {1700000001, 0}};
+  struct stat st;
+  if (mount(IMAGE, FAT_MNT, "vfat", 0, NULL) != 0) {
+    return;
+  }
+  int fd = open(FAT_MNT "/kept", O_WRONLY | O_CREAT, 0644);
+  int ok = fd >= 0 && futimens(fd, times) == 0;
+  close(fd);
+  if (umount(FAT_MNT) != 0 || mount(IMAGE, FAT_MNT, "vfat", 0, NULL) != 0) {
+    return;
+  }
+  ok = ok && stat(FAT_MNT "/kept", &st) == 0 &&
+       st.st_atim.tv_sec == 999993600 && st.st_mtim.tv_sec == 1700000000;
+  unlink(FAT_MNT "/kept");
+  if (umount(FAT_MNT) == 0 && ok) {
+    puts("test_vfat_persist ok");
+  }
+}
+
+// Check that the names of a file share its times, and that a link changes
+// its ctime.
+void test_link_times() {
+  const struct timespec times[2] = {{1000000000, 1}, {1100000000, 2}};
+  struct stat st, linked;
+  if (mount("tmpfs", TMP_MNT, "tmpfs", 0, NULL) != 0) {
+    return;
+  }
+  int fd = open(TMP_MNT "/a", O_WRONLY | O_CREAT, 0644);
+  close(fd);
+  int ok = fd >= 0 && utimensat(AT_FDCWD, TMP_MNT "/a", times, 0) == 0 &&
+           stat(TMP_MNT "/a", &st) == 0 &&
+           link(TMP_MNT "/a", TMP_MNT "/b") == 0 &&
+           stat(TMP_MNT "/b", &linked) == 0 &&
+           linked.st_mtim.tv_sec == 1100000000 &&
+           linked.st_mtim.tv_nsec == 2 &&
+           (linked.st_ctim.tv_sec != st.st_ctim.tv_sec ||
+            linked.st_ctim.tv_nsec != st.st_ctim.tv_nsec);
+  if (umount(TMP_MNT) == 0 && ok) {
+    puts("test_link_times ok");
+  }
+}
+
+void test_utime_omit() {
+  const struct timespec set[2] = {{1000000000, 500}, {1000000000, 600}};
+  const struct timespec omit[2] = {{0, UTIME_OMIT}, {1200000000, 700}};
+  struct stat st;
+  if (mount("tmpfs", TMP_MNT, "tmpfs", 0, NULL) != 0) {
+    return;
+  }
+  int fd = open(TMP_MNT "/omit", O_WRONLY | O_CREAT, 0644);
+  int ok = fd >= 0 && futimens(fd, set) == 0 && futimens(fd, omit) == 0 &&
+           fstat(fd, &st) == 0 && st.st_atim.tv_sec == 1000000000 &&
+           st.st_atim.tv_nsec == 500 && st.st_mtim.tv_sec == 1200000000 &&
+           st.st_mtim.tv_nsec == 700;
+  close(fd);
+  if (umount(TMP_MNT) == 0 && ok) {
+    puts("test_utime_omit ok");
+  }
+}
+
+// Check that the times of a file in a renamed directory go with it, and
+// that a new file where it was does not get them.
+void test_dir_rename() {
+  const struct timespec times[2] = {{1000000000, 11}, {1100000000, 22}};
+  struct stat moved, fresh;
+  if (mount("tmpfs", TMP_MNT, "tmpfs", 0, NULL) != 0) {
+    return;
+  }
+  int ok = mkdir(TMP_MNT "/d", 0755) == 0;
+  int fd = open(TMP_MNT "/d/f", O_WRONLY | O_CREAT, 0644);
+  close(fd);
+  ok = ok && fd >= 0 && utimensat(AT_FDCWD, TMP_MNT "/d/f", times, 0) == 0 &&
+       rename(TMP_MNT "/d", TMP_MNT "/e") == 0 &&
+       stat(TMP_MNT "/e/f", &moved) == 0 &&
+       moved.st_atim.tv_sec == 1000000000 && moved.st_atim.tv_nsec == 11 &&
+       moved.st_mtim.tv_sec == 1100000000 && moved.st_mtim.tv_nsec == 22;
+  ok = ok && mkdir(TMP_MNT "/d", 0755) == 0;
+  fd = open(TMP_MNT "/d/f", O_WRONLY | O_CREAT, 0644);
+  close(fd);
+  ok = ok && fd >= 0 && stat(TMP_MNT "/d/f", &fresh) == 0 &&
+       fresh.st_mtim.tv_sec != 1100000000;
+  if (umount(TMP_MNT) == 0 && ok) {
+    puts("test_dir_rename ok");
+  }
+}
+
+int main() {
+  test_tmpfs_ns();
+  test_vfat_rounding();
+  test_vfat_persist();
+  test_link_times();
+  test_utime_omit();
+  test_dir_rename();
+  rmdir(TMP_MNT);
+  rmdir(FAT_MNT);
+  unlink(IMAGE);
+  return 0;
+}
@@ -0,0 +1,109 @@
+#define _GNU_SOURCE
+#include <fcntl.h>
+#include <sched.h>
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <sys/utsname.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+static void set_hostname(const char *name) {
+  CHECK(sethostname(name, strlen(name)) == 0);
+}
+
+static int hostname_is(const char *name) {
+  struct utsname uts;
+  CHECK(uname(&uts) == 0);
+  return strcmp(uts.nodename, name) == 0;
+}
+
+// Read a file of /proc/sys/kernel into `buf`.
+static void read_proc(const char *name, char *buf, size_t size) {
+  char path[64];
+  snprintf(path, sizeof(path), "/proc/sys/kernel/%s", name);
+  int fd = open(path, O_RDONLY);
+  CHECK(fd >= 0);
+  ssize_t len = read(fd, buf, size - 1);
+  CHECK(len >= 0);
+  buf[len] = '\0';
+  close(fd);
+}
+
+// Fork with the clone flags `flags`, have the child set its host name to
+// "child", and return the child's exit status.
+static int fork_and_rename(int flags) {
+  // Like fork, the stack and thread IDs are unused.
+  pid_t pid = syscall(SYS_clone, SIGCHLD | flags, 0, 0, 0, 0);
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    char buf[80];
+    set_hostname("child");
+    read_proc("hostname", buf, sizeof(buf));
+    _exit(hostname_is("child") && strcmp(buf, "child\n") == 0 ? 0 : 1);
+  }
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  return status;
+}
+
+static void test_new_namespace(void) {
+  set_hostname("parent");
+  CHECK(fork_and_rename(CLONE_NEWUTS) == 0);
+  CHECK(hostname_is("parent"));
+  puts("test_new_namespace ok");
+}
+
+static void test_shared_namespace(void) {
+  set_hostname("parent");
+  CHECK(fork_and_rename(0) == 0);
+  CHECK(hostname_is("child"));
+  puts("test_shared_namespace ok");
+}
+
+static void test_proc(void) {
+  char buf[80];
+  int fd = open("/proc/sys/kernel/hostname", O_WRONLY);
+  CHECK(fd >= 0);
+  CHECK(write(fd, "viaproc\n", 8) == 8);
+  close(fd);
+  CHECK(hostname_is("viaproc"));
+
+  CHECK(setdomainname("example", 7) == 0);
+  read_proc("domainname", buf, sizeof(buf));
+  CHECK(strcmp(buf, "example\n") == 0);
+
+  struct utsname uts;
+  CHECK(uname(&uts) == 0);
+  read_proc("ostype", buf, sizeof(buf));
+  CHECK(strncmp(buf, uts.sysname, strlen(uts.sysname)) == 0);
+  read_proc("osrelease", buf, sizeof(buf));
+  CHECK(strncmp(buf, uts.release, strlen(uts.release)) == 0);
+
+  char long_name[66];
+  memset(long_name, 'a', sizeof(long_name));
+  CHECK(sethostname(long_name, sizeof(long_name)) == -1);
+  puts("test_proc ok");
+}
+
+int main(void) {
+  // Keep the names of the namespace the test started in.
+  struct utsname saved;
+  CHECK(uname(&saved) == 0);
+  test_new_namespace();
+  test_shared_namespace();
+  test_proc();
+  set_hostname(saved.nodename);
+  CHECK(setdomainname(saved.domainname, strlen(saved.domainname)) == 0);
+  return 0;
+}
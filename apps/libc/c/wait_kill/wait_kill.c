@@ -0,0 +1,162 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <linux/futex.h>
+#include <signal.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <time.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+// How long a child gets to block before it is signaled.
+#define SETTLE_US 50000
+// How soon a signal must end a wait: one scheduling quantum of 10 ms, with
+// as much again of slack for an emulator.
+#define MAX_DELAY_NS 20000000L
+
+static uint32_t futex_word;
+
+static long now_ns(void) {
+  struct timespec ts;
+  clock_gettime(CLOCK_MONOTONIC, &ts);
+  return ts.tv_sec * 1000000000L + ts.tv_nsec;
+}
+
+static void block_waitpid(void) {
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    // Outlives the wait, and is reaped by init once its parent is killed.
+    sleep(1);
+    _exit(0);
+  }
+  waitpid(pid, NULL, 0);
+}
+
+static void block_futex(void) {
+  syscall(SYS_futex, &futex_word, FUTEX_WAIT, 0, NULL, NULL, 0);
+}
+
+static void block_sigsuspend(void) {
+  sigset_t set;
+  sigemptyset(&set);
+  sigsuspend(&set);
+}
+
+static void block_nanosleep(void) {
+  struct timespec ts = {.tv_sec = 10};
+  nanosleep(&ts, NULL);
+}
+
+static void block_sigtimedwait(void) {
+  sigset_t set;
+  sigemptyset(&set);
+  sigaddset(&set, SIGUSR2);
+  sigprocmask(SIG_BLOCK, &set, NULL);
+  sigwaitinfo(&set, NULL);
+}
+
+static void block_stopped(void) { raise(SIGSTOP); }
+
+// Run `block` in a child, and check that `SIGKILL` ends it in time, whatever
+// the wait it is in.
+static void check_killed(const char *name, void (*block)(void)) {
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    block();
+    _exit(1);
+  }
+  usleep(SETTLE_US);
+  long start = now_ns();
+  CHECK(kill(pid, SIGKILL) == 0);
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  long delay = now_ns() - start;
+  CHECK(WIFSIGNALED(status) && WTERMSIG(status) == SIGKILL);
+  if (delay > MAX_DELAY_NS) {
+    printf("%s: killed after %ld us\n", name, delay / 1000);
+    CHECK(delay <= MAX_DELAY_NS);
+  }
+}
+
+void test_kill_waits() {
+  check_killed("waitpid", block_waitpid);
+  check_killed("futex", block_futex);
+  check_killed("sigsuspend", block_sigsuspend);
+  check_killed("nanosleep", block_nanosleep);
+  check_killed("sigtimedwait", block_sigtimedwait);
+  check_killed("stopped", block_stopped);
+  puts("test_kill_waits ok");
+}
+
+static void on_usr1(int sig) { (void)sig; }
+
+// A handled signal ends an interruptible wait with `EINTR`.
+void test_interrupt_waits() {
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    struct sigaction sa = {.sa_handler = on_usr1};
+    CHECK(sigaction(SIGUSR1, &sa, NULL) == 0);
+    long ret = syscall(SYS_futex, &futex_word, FUTEX_WAIT, 0, NULL, NULL, 0);
+    CHECK(ret == -1 && errno == EINTR);
+    pid_t child = fork();
+    CHECK(child >= 0);
+    if (child == 0) {
+      sleep(1);
+      _exit(0);
+    }
+    CHECK(waitpid(child, NULL, 0) == -1 && errno == EINTR);
+    CHECK(kill(child, SIGKILL) == 0);
+    CHECK(waitpid(child, NULL, 0) == child);
+    _exit(0);
+  }
+  for (int i = 0; i < 2; i++) {
+    usleep(SETTLE_US);
+    CHECK(kill(pid, SIGUSR1) == 0);
+  }
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  puts("test_interrupt_waits ok");
+}
+
+// A stopped process is in a killable wait: other signals stay pending until
+// it is continued.
+void test_stopped_killable() {
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    signal(SIGUSR1, on_usr1);
+    raise(SIGSTOP);
+    _exit(0);
+  }
+  int status;
+  CHECK(waitpid(pid, &status, WUNTRACED) == pid);
+  CHECK(WIFSTOPPED(status));
+  CHECK(kill(pid, SIGUSR1) == 0);
+  usleep(SETTLE_US);
+  CHECK(waitpid(pid, &status, WNOHANG) == 0);
+  CHECK(kill(pid, SIGCONT) == 0);
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  puts("test_stopped_killable ok");
+}
+
+int main() {
+  test_kill_waits();
+  test_interrupt_waits();
+  test_stopped_killable();
+  return 0;
+}
@@ -0,0 +1,90 @@
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+// Fork a child which runs `fn` with `arg`, and return its status.
+static int status_of(void (*fn)(int), int arg) {
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    fn(arg);
+    _exit(0);
+  }
+  int status;
+  CHECK(waitpid(pid, &status, 0) == pid);
+  return status;
+}
+
+static void do_exit(int code) { exit(code); }
+
+static void do_kill(int signo) {
+  kill(getpid(), signo);
+  for (;;) {
+    pause();
+  }
+}
+
+static void test_exited(void) {
+  int status = status_of(do_exit, 0);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  CHECK(!WIFSIGNALED(status) && !WIFSTOPPED(status));
+  status = status_of(do_exit, 3);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 3);
+  status = status_of(do_exit, 255);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 255);
+  // Only the low 8 bits are kept.
+  status = status_of(do_exit, 256 + 7);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 7);
+  puts("test_exited ok");
+}
+
+static void test_signaled(void) {
+  int status = status_of(do_kill, SIGKILL);
+  CHECK(!WIFEXITED(status) && WIFSIGNALED(status));
+  CHECK(WTERMSIG(status) == SIGKILL);
+  status = status_of(do_kill, SIGTERM);
+  CHECK(WIFSIGNALED(status) && WTERMSIG(status) == SIGTERM);
+  puts("test_signaled ok");
+}
+
+static void test_stopped(void) {
+  int fds[2];
+  CHECK(pipe(fds) == 0);
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    char c;
+    raise(SIGSTOP);
+    // Not gone before the parent saw it continue.
+    read(fds[0], &c, 1);
+    _exit(5);
+  }
+  int status;
+  CHECK(waitpid(pid, &status, WUNTRACED) == pid);
+  CHECK(WIFSTOPPED(status) && WSTOPSIG(status) == SIGSTOP);
+  CHECK(!WIFEXITED(status) && !WIFSIGNALED(status));
+  CHECK(kill(pid, SIGCONT) == 0);
+  CHECK(waitpid(pid, &status, WCONTINUED) == pid);
+  CHECK(WIFCONTINUED(status));
+  CHECK(write(fds[1], "x", 1) == 1);
+  CHECK(waitpid(pid, &status, 0) == pid);
+  CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 5);
+  puts("test_stopped ok");
+}
+
+int main(void) {
+  test_exited();
+  test_signaled();
+  test_stopped();
+  return 0;
+}
@@ -0,0 +1,196 @@
+#define _GNU_SOURCE
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mount.h>
+#include <sys/stat.h>
+#include <sys/xattr.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define MNT "/xattr_tmp"
+#define FILE_PATH MNT "/file"
+#define VFAT_SRC "/xattr_src"
+#define VFAT_DST "/xattr_dst"
+
+static void touch(const char *path) {
+  int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+  CHECK(fd >= 0);
+  CHECK(write(fd, "data", 4) == 4);
+  close(fd);
+}
+
+void test_roundtrip() {
+  char buf[16] = {0};
+  CHECK(setxattr(FILE_PATH, "user.a", "hello", 5, 0) == 0);
+  // The size query.
+  CHECK(getxattr(FILE_PATH, "user.a", NULL, 0) == 5);
+  CHECK(getxattr(FILE_PATH, "user.a", buf, sizeof(buf)) == 5);
+  CHECK(memcmp(buf, "hello", 5) == 0);
+  CHECK(getxattr(FILE_PATH, "user.a", buf, 4) == -1 && errno == ERANGE);
+  CHECK(getxattr(FILE_PATH, "user.b", buf, sizeof(buf)) == -1 &&
+        errno == ENODATA);
+
+  // The same through a descriptor, and an empty value.
+  int fd = open(FILE_PATH, O_RDWR);
+  CHECK(fd >= 0);
+  CHECK(fsetxattr(fd, "user.empty", "", 0, 0) == 0);
+  CHECK(fgetxattr(fd, "user.empty", buf, sizeof(buf)) == 0);
+  CHECK(fgetxattr(fd, "user.a", buf, sizeof(buf)) == 5);
+  CHECK(fremovexattr(fd, "user.empty") == 0);
+  close(fd);
+
+  CHECK(lgetxattr(FILE_PATH, "user.a", buf, sizeof(buf)) == 5);
+  CHECK(removexattr(FILE_PATH, "user.a") == 0);
+  CHECK(getxattr(FILE_PATH, "user.a", buf, sizeof(buf)) == -1 &&
+        errno == ENODATA);
+  CHECK(removexattr(FILE_PATH, "user.a") == -1 && errno == ENODATA);
+  puts("test_roundtrip ok");
+}
+
+void test_flags() {
+  char buf[16] = {0};
+  CHECK(setxattr(FILE_PATH, "user.f", "1", 1, XATTR_REPLACE) == -1 &&
+        errno == ENODATA);
+  CHECK(setxattr(FILE_PATH, "user.f", "1", 1, XATTR_CREATE) == 0);
+  CHECK(setxattr(FILE_PATH, "user.f", "2", 1, XATTR_CREATE) == -1 &&
+        errno == EEXIST);
+  CHECK(setxattr(FILE_PATH, "user.f", "22", 2, XATTR_REPLACE) == 0);
+  CHECK(getxattr(FILE_PATH, "user.f", buf, sizeof(buf)) == 2);
+  CHECK(memcmp(buf, "22", 2) == 0);
+  CHECK(setxattr(FILE_PATH, "user.f", "3", 1, 4) == -1 && errno == EINVAL);
+  CHECK(removexattr(FILE_PATH, "user.f") == 0);
+  puts("test_flags ok");
+}
+
+void test_list() {
+  char buf[64];
+  CHECK(listxattr(FILE_PATH, NULL, 0) == 0);
+  CHECK(setxattr(FILE_PATH, "user.x", "1", 1, 0) == 0);
+  CHECK(setxattr(FILE_PATH, "user.yy", "2", 1, 0) == 0);
+  // "user.x\0user.yy\0"
+  CHECK(listxattr(FILE_PATH, NULL, 0) == 15);
+  CHECK(listxattr(FILE_PATH, buf, 14) == -1 && errno == ERANGE);
+  CHECK(llistxattr(FILE_PATH, buf, sizeof(buf)) == 15);
+  CHECK(memcmp(buf, "user.x\0user.yy\0", 15) == 0);
+
+  // Removing the file removes its attributes.
+  CHECK(unlink(FILE_PATH) == 0);
+  touch(FILE_PATH);
+  int fd = open(FILE_PATH, O_RDONLY);
+  CHECK(fd >= 0);
+  CHECK(flistxattr(fd, buf, sizeof(buf)) == 0);
+  close(fd);
+  puts("test_list ok");
+}
+
+void test_namespaces() {
+  char buf[16];
+  CHECK(setxattr(FILE_PATH, "security.x", "1", 1, 0) == -1 &&
+        errno == ENOTSUP);
+  CHECK(setxattr(FILE_PATH, "trusted.x", "1", 1, 0) == -1 &&
+        errno == ENOTSUP);
+  CHECK(getxattr(FILE_PATH, "security.x", buf, sizeof(buf)) == -1 &&
+        errno == ENOTSUP);
+  CHECK(setxattr(FILE_PATH, "other.x", "1", 1, 0) == -1 && errno == ENOTSUP);
+  CHECK(setxattr(FILE_PATH, "user.", "1", 1, 0) == -1 && errno == EINVAL);
+  CHECK(setxattr(FILE_PATH, "", "1", 1, 0) == -1 && errno == ERANGE);
+
+  int pipefd[2];
+  CHECK(pipe(pipefd) == 0);
+  CHECK(fgetxattr(pipefd[0], "user.x", buf, sizeof(buf)) == -1 &&
+        errno == ENOTSUP);
+  close(pipefd[0]);
+  close(pipefd[1]);
+  puts("test_namespaces ok");
+}
+
+void test_limits() {
+  static char value[65537];
+  static char name[300];
+  memset(value, 'v', sizeof(value));
+  CHECK(setxattr(FILE_PATH, "user.big", value, 65537, 0) == -1 &&
+        errno == E2BIG);
+  CHECK(setxattr(FILE_PATH, "user.big", value, 65536, 0) == 0);
+  CHECK(getxattr(FILE_PATH, "user.big", NULL, 0) == 65536);
+  CHECK(removexattr(FILE_PATH, "user.big") == 0);
+
+  memcpy(name, "user.", 5);
+  memset(name + 5, 'n', 251);
+  CHECK(setxattr(FILE_PATH, name, "1", 1, 0) == -1 && errno == ERANGE);
+  name[255] = '\0';
+  CHECK(setxattr(FILE_PATH, name, "1", 1, 0) == 0);
+  CHECK(removexattr(FILE_PATH, name) == 0);
+
+  int i;
+  for (i = 0; i < 1001; i++) {
+    snprintf(name, sizeof(name), "user.%d", i);
+    if (setxattr(FILE_PATH, name, "", 0, 0) != 0) {
+      break;
+    }
+  }
+  CHECK(i == 1000 && errno == ENOSPC);
+  puts("test_limits ok");
+}
+
+// The attributes of a file go with the directory it is in when that is
+// renamed, and a new file where it was does not get them.
+void test_dir_rename() {
+  char buf[16] = {0};
+  CHECK(mkdir(MNT "/d", 0755) == 0);
+  touch(MNT "/d/f");
+  CHECK(setxattr(MNT "/d/f", "user.a", "moved", 5, 0) == 0);
+  CHECK(rename(MNT "/d", MNT "/e") == 0);
+  CHECK(getxattr(MNT "/e/f", "user.a", buf, sizeof(buf)) == 5);
+  CHECK(memcmp(buf, "moved", 5) == 0);
+  CHECK(mkdir(MNT "/d", 0755) == 0);
+  touch(MNT "/d/f");
+  CHECK(listxattr(MNT "/d/f", NULL, 0) == 0);
+  CHECK(unlink(MNT "/d/f") == 0 && rmdir(MNT "/d") == 0);
+  CHECK(unlink(MNT "/e/f") == 0 && rmdir(MNT "/e") == 0);
+  puts("test_dir_rename_xattr ok");
+}
+
+// What `cp -a` does: attributes are not supported on the source, which is
+// taken as there being none to copy, nor on the destination, which is
+// skipped as well.
+void test_copy_vfat() {
+  char buf[16];
+  touch(VFAT_SRC);
+  CHECK(llistxattr(VFAT_SRC, buf, sizeof(buf)) == -1 && errno == ENOTSUP);
+  CHECK(getxattr(VFAT_SRC, "user.a", buf, sizeof(buf)) == -1 &&
+        errno == ENOTSUP);
+  touch(VFAT_DST);
+  CHECK(setxattr(VFAT_DST, "user.a", "1", 1, 0) == -1 && errno == ENOTSUP);
+  CHECK(getxattr(VFAT_DST "_missing", "user.a", buf, sizeof(buf)) == -1 &&
+        errno == ENOENT);
+  unlink(VFAT_SRC);
+  unlink(VFAT_DST);
+  puts("test_copy_vfat ok");
+}
+
+int main() {
+  CHECK(mkdir(MNT, 0755) == 0);
+  CHECK(mount("tmpfs", MNT, "tmpfs", 0, NULL) == 0);
+  touch(FILE_PATH);
+  test_roundtrip();
+  test_flags();
+  test_list();
+  test_namespaces();
+  test_limits();
+  test_dir_rename();
+  test_copy_vfat();
+  unlink(FILE_PATH);
+  umount2(MNT, 0);
+  rmdir(MNT);
+  return 0;
+}
@@ -0,0 +1,107 @@
+#define _GNU_SOURCE
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <sys/mman.h>
+#include <sys/sysinfo.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#define CHECK(cond)                                                            \
+  do {                                                                         \
+    if (!(cond)) {                                                             \
+      printf("%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond);          \
+      exit(1);                                                                 \
+    }                                                                          \
+  } while (0)
+
+#define CHILDREN 200
+#define CHILD_MAP (10 * 1024 * 1024)
+// What the zombies may hold together, which is far less than the memory
+// of a single child.
+#define SLACK (CHILD_MAP / 2)
+
+static long free_memory(void) {
+  struct sysinfo info;
+  CHECK(sysinfo(&info) == 0);
+  return (long)info.freeram * info.mem_unit;
+}
+
+static char proc_state(pid_t pid) {
+  char path[64], buf[256];
+  snprintf(path, sizeof(path), "/proc/%d/stat", pid);
+  FILE *f = fopen(path, "r");
+  CHECK(f != NULL);
+  CHECK(fgets(buf, sizeof(buf), f) != NULL);
+  fclose(f);
+  char *paren = strrchr(buf, ')');
+  CHECK(paren != NULL);
+  return paren[2];
+}
+
+// Fork a child which fills its own mapping, and wait until it is a zombie,
+// without reaping it.
+static pid_t fork_zombie(void) {
+  pid_t pid = fork();
+  CHECK(pid >= 0);
+  if (pid == 0) {
+    char *p = mmap(NULL, CHILD_MAP, PROT_READ | PROT_WRITE,
+                   MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    if (p == MAP_FAILED) {
+      _exit(1);
+    }
+    memset(p, 0x5a, CHILD_MAP);
+    _exit(0);
+  }
+  while (proc_state(pid) != 'Z') {
+    usleep(1000);
+  }
+  return pid;
+}
+
+// Zombies hold no memory of their own, so forking many children which each
+// fill a mapping and exit, before reaping any, takes little more memory
+// than one of them.
+void test_zombies_freed() {
+  static pid_t pids[CHILDREN];
+  long before = free_memory();
+  long lowest = before;
+  for (int i = 0; i < CHILDREN; i++) {
+    pids[i] = fork_zombie();
+    long now = free_memory();
+    if (now < lowest) {
+      lowest = now;
+    }
+  }
+  usleep(100000);
+  long zombies = before - free_memory();
+  printf("zombie_mem: %d zombies hold %ld KiB, lowest free %ld KiB below\n",
+         CHILDREN, zombies / 1024, (before - lowest) / 1024);
+  CHECK(zombies < SLACK);
+
+  for (int i = 0; i < CHILDREN; i++) {
+    int status;
+    CHECK(waitpid(pids[i], &status, 0) == pids[i]);
+    CHECK(WIFEXITED(status) && WEXITSTATUS(status) == 0);
+  }
+  puts("test_zombies_freed ok");
+}
+
+// A zombie still tells its state, and has no memory left.
+void test_zombie_proc() {
+  pid_t pid = fork_zombie();
+  char path[64], buf[4096];
+  snprintf(path, sizeof(path), "/proc/%d/maps", pid);
+  FILE *f = fopen(path, "r");
+  CHECK(f != NULL);
+  CHECK(fread(buf, 1, sizeof(buf), f) == 0);
+  fclose(f);
+  CHECK(waitpid(pid, NULL, 0) == pid);
+  puts("test_zombie_proc ok");
+}
+
+int main() {
+  test_zombies_freed();
+  test_zombie_proc();
+  return 0;
+}
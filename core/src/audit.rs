@@ -0,0 +1,142 @@
+//! Auditing of `execve`: a record of every program run, with its arguments,
+//! for finding out what a test ran.
+//!
+//! Auditing is off until turned on with [`set_exec_audit`], which
+//! `/proc/starry/audit_exec` does. Records go to the kernel log, and the
+//! last [`AUDIT_RECORDS`] of them are kept for `/proc/starry/audit`.
+//!
+//! The accesses a path sandbox denies, see [`crate::sandbox`], are always
+//! recorded, as they are few and tell why a sandboxed test failed.
+
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use alloc::{collections::vec_deque::VecDeque, string::String};
+use axhal::time::{NANOS_PER_MICROS, NANOS_PER_SEC};
+use axprocess::Pid;
+use axsync::Mutex;
+
+use crate::{sandbox::PathAccess, stats};
+
+/// How many records are kept.
+pub const AUDIT_RECORDS: usize = 256;
+/// The longest argument in a record, in bytes before escaping. Longer ones
+/// are cut, ending with `...`.
+const MAX_ARG_LEN: usize = 256;
+/// The longest record, in bytes. The arguments which do not fit are left
+/// out, with `...` in their place.
+const MAX_RECORD_LEN: usize = 4096;
+
+static EXEC_AUDIT: AtomicBool = AtomicBool::new(false);
+static RECORDS: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Whether `execve` is audited.
+pub fn exec_audit_enabled() -> bool {
+    EXEC_AUDIT.load(Ordering::Relaxed)
+}
+
+/// Turn the auditing of `execve` on or off.
+pub fn set_exec_audit(enabled: bool) {
+    EXEC_AUDIT.store(enabled, Ordering::Relaxed);
+}
+
+/// Append `s` to `out` in double quotes, with quotes, backslashes and
+/// bytes which are not printable ASCII escaped, and cut after `limit`
+/// bytes.
+fn push_quoted(out: &mut String, s: &str, limit: usize) {
+    out.push('"');
+    for &b in s.as_bytes().iter().take(limit) {
+        match b {
+            b'"' | b'\\' => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            0x20..0x7f => out.push(b as char),
+            _ => write!(out, "\\x{:02x}", b).unwrap(),
+        }
+    }
+    if s.len() > limit {
+        out.push_str("...");
+    }
+    out.push('"');
+}
+
+/// Record that the process `pid`, child of `ppid`, runs the program at
+/// `exe` with `args`, if auditing is on.
+///
+/// The record is one line of `key=value` fields, like those of the Linux
+/// audit subsystem:
+///
+/// ```text
+/// time=12.345678 type=EXECVE pid=5 ppid=1 uid=0 exe="/bin/sh" argc=2 a0="sh" a1="-c"
+/// ```
+pub fn audit_exec(pid: Pid, ppid: Pid, uid: u32, exe: &str, args: &[String]) {
+    if !exec_audit_enabled() {
+        return;
+    }
+    let mut record = new_record("EXECVE");
+    write!(record, " pid={} ppid={} uid={} exe=", pid, ppid, uid).unwrap();
+    push_quoted(&mut record, exe, MAX_RECORD_LEN);
+    write!(record, " argc={}", args.len()).unwrap();
+    for (i, arg) in args.iter().enumerate() {
+        let mut field = String::new();
+        write!(field, " a{}=", i).unwrap();
+        push_quoted(&mut field, arg, MAX_ARG_LEN);
+        if record.len() + field.len() > MAX_RECORD_LEN {
+            record.push_str(" ...");
+            break;
+        }
+        record.push_str(&field);
+    }
+    keep(record);
+}
+
+/// Record that the process `pid` was denied `access` to `path` by its path
+/// sandbox:
+///
+/// ```text
+/// time=12.345678 type=SANDBOX pid=5 uid=0 access=-w-- path="/etc/passwd"
+/// ```
+pub fn audit_denial(pid: Pid, uid: u32, path: &str, access: PathAccess) {
+    let mut record = new_record("SANDBOX");
+    write!(record, " pid={} uid={} access={} path=", pid, uid, access).unwrap();
+    push_quoted(&mut record, path, MAX_RECORD_LEN);
+    keep(record);
+}
+
+/// Start a record of `kind`, with the uptime.
+fn new_record(kind: &str) -> String {
+    let uptime = stats::uptime_nanos();
+    let mut record = String::new();
+    write!(
+        record,
+        "time={}.{:06} type={}",
+        uptime / NANOS_PER_SEC,
+        uptime % NANOS_PER_SEC / NANOS_PER_MICROS,
+        kind
+    )
+    .unwrap();
+    record
+}
+
+/// Log `record` and keep it, dropping the oldest one kept if need be.
+fn keep(record: String) {
+    info!("audit: {}", record);
+    let mut records = RECORDS.lock();
+    if records.len() >= AUDIT_RECORDS {
+        records.pop_front();
+    }
+    records.push_back(record);
+}
+
+/// The records kept, oldest first, one per line.
+pub fn audit_records() -> String {
+    let mut out = String::new();
+    for record in RECORDS.lock().iter() {
+        out.push_str(record);
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,119 @@
+//! Process credentials.
+//!
+//! Every process runs as root for now, so privilege is only told apart by
+//! the capability sets, see `capabilities(7)`. They start full in the init
+//! process, are copied on fork and can only shrink through `capset`.
+//!
+//! File permissions are checked against the permission bits, with
+//! `CAP_DAC_OVERRIDE` and `CAP_DAC_READ_SEARCH` overriding them. The checks
+//! are permissive by default, only logging what they would deny, until
+//! turned on with `AX_DAC=enforcing` at build time or with
+//! [`set_dac_enforcing`], which `/proc/starry/dac_enforce` does.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Bypass the permission checks of files.
+pub const CAP_DAC_OVERRIDE: u32 = 1;
+/// Bypass the permission checks of reading files, and of reading and
+/// searching directories.
+pub const CAP_DAC_READ_SEARCH: u32 = 2;
+
+/// Claim another group id, like in `SCM_CREDENTIALS`.
+pub const CAP_SETGID: u32 = 6;
+/// Claim another user id, like in `SCM_CREDENTIALS`.
+pub const CAP_SETUID: u32 = 7;
+/// Lock memory beyond `RLIMIT_MEMLOCK`.
+pub const CAP_IPC_LOCK: u32 = 14;
+/// Use `chroot`.
+pub const CAP_SYS_CHROOT: u32 = 18;
+/// Perform a range of administration operations, like `sethostname`.
+pub const CAP_SYS_ADMIN: u32 = 21;
+/// Use `reboot`.
+pub const CAP_SYS_BOOT: u32 = 22;
+/// Raise the priority of processes.
+pub const CAP_SYS_NICE: u32 = 23;
+/// Raise hard resource limits.
+pub const CAP_SYS_RESOURCE: u32 = 24;
+/// Configure auditing.
+pub const CAP_AUDIT_CONTROL: u32 = 30;
+/// Read the audit log.
+pub const CAP_AUDIT_READ: u32 = 37;
+
+/// The highest capability number known to the kernel.
+pub const CAP_LAST_CAP: u32 = 40;
+
+/// All capabilities.
+pub const CAP_FULL_SET: u64 = (1 << (CAP_LAST_CAP + 1)) - 1;
+
+static DAC_ENFORCING: AtomicBool =
+    AtomicBool::new(matches!(option_env!("AX_DAC"), Some("enforcing")));
+
+/// Whether file permission checks are enforced, rather than only logged.
+pub fn dac_enforcing() -> bool {
+    DAC_ENFORCING.load(Ordering::Relaxed)
+}
+
+/// Turn the enforcement of file permission checks on or off.
+pub fn set_dac_enforcing(enforcing: bool) {
+    DAC_ENFORCING.store(enforcing, Ordering::Relaxed);
+}
+
+/// The credentials of a process.
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    /// The capabilities used for permission checks.
+    pub cap_effective: u64,
+    /// The capabilities which can be made effective.
+    pub cap_permitted: u64,
+    /// The capabilities meant to be kept across `execve`.
+    pub cap_inheritable: u64,
+}
+
+impl Credentials {
+    /// The credentials of the init process, with every capability.
+    pub const fn root() -> Self {
+        Self {
+            cap_effective: CAP_FULL_SET,
+            cap_permitted: CAP_FULL_SET,
+            cap_inheritable: 0,
+        }
+    }
+
+    /// Whether the capability `cap` is effective.
+    pub fn has_capability(&self, cap: u32) -> bool {
+        cap <= CAP_LAST_CAP && self.cap_effective & (1 << cap) != 0
+    }
+
+    /// Replace the capability sets, as `capset` does.
+    ///
+    /// Returns `false`, leaving the sets unchanged, if this would gain a
+    /// permitted capability, make a capability effective without permitting
+    /// it, or add an inheritable capability which is neither inheritable
+    /// nor permitted yet.
+    pub fn set_capabilities(&mut self, effective: u64, permitted: u64, inheritable: u64) -> bool {
+        let (effective, permitted, inheritable) = (
+            effective & CAP_FULL_SET,
+            permitted & CAP_FULL_SET,
+            inheritable & CAP_FULL_SET,
+        );
+        if permitted & !self.cap_permitted != 0
+            || effective & !permitted != 0
+            || inheritable & !(self.cap_inheritable | self.cap_permitted) != 0
+        {
+            return false;
+        }
+        self.cap_effective = effective;
+        self.cap_permitted = permitted;
+        self.cap_inheritable = inheritable;
+        true
+    }
+
+    /// Update the capability sets on `execve`.
+    ///
+    /// Root regains its whole bounding set on `execve` under Linux. There is
+    /// no bounding set here, so the permitted set is kept instead, which
+    /// keeps dropped capabilities dropped, and all of it becomes effective.
+    pub fn on_exec(&mut self) {
+        self.cap_effective = self.cap_permitted;
+    }
+}
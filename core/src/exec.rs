@@ -0,0 +1,120 @@
+//! Keeping `execve` apart from `clone` and `exit_group` in one process.
+//!
+//! `execve` replaces the program of the whole process, so it kills the
+//! other threads first and waits for them to be gone. Meanwhile no thread
+//! may be created: `clone` fails with `EAGAIN` once an `execve` started,
+//! and an `execve` waits out the clones already under way. Of two threads
+//! calling `execve`, the later fails, as the earlier kills it. Against
+//! `exit_group`, whichever comes first wins: an `execve` fails in an exiting
+//! process, and a thread calling `exit_group` during an `execve` exits
+//! alone, as it was about to be killed.
+//!
+//! Only `clone`, `execve` and exits take the lock here.
+
+use axerrno::{LinuxError, LinuxResult};
+use spin::Mutex;
+
+use crate::task::{WaitMode, WaitQueueWrapper, WaitResult};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum State {
+    #[default]
+    Running,
+    Exec,
+    GroupExit,
+}
+
+#[derive(Default)]
+struct Inner {
+    state: State,
+    /// The clones under way.
+    clones: usize,
+}
+
+/// The `execve` state of a process.
+#[derive(Default)]
+pub struct ExecGate {
+    inner: Mutex<Inner>,
+    wq: WaitQueueWrapper,
+}
+
+/// A `clone` under way, see [`ExecGate::begin_clone`].
+pub struct CloneGuard<'a>(&'a ExecGate);
+
+impl Drop for CloneGuard<'_> {
+    fn drop(&mut self) {
+        self.0.inner.lock().clones -= 1;
+        self.0.wq.notify_all(false);
+    }
+}
+
+/// An `execve` under way, see [`ExecGate::begin_exec`].
+pub struct ExecGuard<'a>(&'a ExecGate);
+
+impl Drop for ExecGuard<'_> {
+    fn drop(&mut self) {
+        let mut inner = self.0.inner.lock();
+        if inner.state == State::Exec {
+            inner.state = State::Running;
+        }
+    }
+}
+
+impl ExecGate {
+    /// Start a `clone`, which is under way until the guard is dropped.
+    /// Fails with `EAGAIN` during an `execve` or `exit_group`.
+    pub fn begin_clone(&self) -> LinuxResult<CloneGuard<'_>> {
+        let mut inner = self.inner.lock();
+        if inner.state != State::Running {
+            return Err(LinuxError::EAGAIN);
+        }
+        inner.clones += 1;
+        Ok(CloneGuard(self))
+    }
+
+    /// Start an `execve`, which is under way until the guard is dropped,
+    /// once the clones under way are done.
+    ///
+    /// Fails with `EAGAIN` during another `execve` or `exit_group`, which
+    /// kill the caller.
+    pub fn begin_exec(&self) -> LinuxResult<ExecGuard<'_>> {
+        {
+            let mut inner = self.inner.lock();
+            if inner.state != State::Running {
+                return Err(LinuxError::EAGAIN);
+            }
+            inner.state = State::Exec;
+        }
+        // Uninterruptible: a clone under way finishes soon.
+        self.wq.wait_until(WaitMode::Uninterruptible, None, || {
+            self.inner.lock().clones == 0
+        });
+        Ok(ExecGuard(self))
+    }
+
+    /// Start an `exit_group`. Returns `false` during an `execve`, in which
+    /// case the caller exits alone.
+    pub fn begin_group_exit(&self) -> bool {
+        let mut inner = self.inner.lock();
+        if inner.state == State::Exec {
+            return false;
+        }
+        inner.state = State::GroupExit;
+        true
+    }
+
+    /// Tell an `execve` waiting in [`Self::wait_until`] that a thread
+    /// exited.
+    pub fn thread_exited(&self) {
+        self.wq.notify_all(false);
+    }
+
+    /// Wait, in an `execve`, until `cond` holds, which is checked whenever
+    /// a thread exits.
+    ///
+    /// The wait is killable, so that an `exit_group` which came first can
+    /// kill the caller. Returns `false` if it did.
+    pub fn wait_until(&self, cond: impl Fn() -> bool) -> bool {
+        self.wq.wait_until(WaitMode::Killable, None, cond) == WaitResult::Woken
+    }
+}
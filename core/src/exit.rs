@@ -0,0 +1,127 @@
+//! The teardown of a process once its last thread exits.
+//!
+//! It runs in [`ExitStage`]s, in order. Each stage does the work built into
+//! the exit path first, then calls the hooks registered for it with
+//! [`register_exit_hook`], so a feature can release what a process holds
+//! at the right point without editing the exit path.
+//!
+//! The order matters: the files are closed, and what they wrote flushed,
+//! before the parent can see the zombie, and the children are handed over
+//! before it is one. What every thread does on its own exit, clearing
+//! `clear_child_tid` and waking its futex, comes before all of them, while
+//! the address space is whole.
+//!
+//! A zombie keeps only what `wait` and `/proc/<pid>` report of it, like the
+//! exit status and the times: its memory is freed in [`ExitStage::Memory`],
+//! rather than when it is reaped, and the kernel stacks of its threads once
+//! they last switched away, when axtask drops their tasks.
+
+use core::sync::atomic::Ordering;
+
+use alloc::vec::Vec;
+use axprocess::Process;
+use spin::RwLock;
+
+use crate::{
+    observer::{ProcessEvent, notify_process_event},
+    pressure::{self, Pressure},
+    task::{ProcessData, count_reaped},
+};
+
+/// A stage of the teardown of a process, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum ExitStage {
+    /// Let a parent blocked in `vfork` go on. Nothing blocks there yet.
+    Vfork,
+    /// Close the file descriptors, and let go of the working directory.
+    Files,
+    /// Detach the shared memory. None is attached yet.
+    SharedMemory,
+    /// Unmap the user memory, unless another process runs in it, like the
+    /// parent of `vfork`.
+    Memory,
+    /// Hang up the controlling terminal if the process leads its session.
+    Terminal,
+    /// Give the children to init, and hang up the process groups this
+    /// orphans.
+    Children,
+    /// Become a zombie, and tell the parent with the exit signal and by
+    /// waking it in `wait`.
+    Zombie,
+}
+
+impl ExitStage {
+    /// All stages, in order.
+    pub const ALL: [Self; 7] = [
+        Self::Vfork,
+        Self::Files,
+        Self::SharedMemory,
+        Self::Memory,
+        Self::Terminal,
+        Self::Children,
+        Self::Zombie,
+    ];
+}
+
+/// A hook of a stage, given the process torn down.
+pub type ExitHook = fn(&Process);
+
+static HOOKS: RwLock<Vec<(ExitStage, ExitHook)>> = RwLock::new(Vec::new());
+
+/// Call `hook` in `stage` of the teardown of every later process, after the
+/// work built into the stage and the hooks registered before.
+pub fn register_exit_hook(stage: ExitStage, hook: ExitHook) {
+    HOOKS.write().push((stage, hook));
+}
+
+/// Tear down `process`, which the current thread is the last to leave.
+///
+/// `builtin` does the work built into each stage.
+pub fn tear_down(process: &Process, mut builtin: impl FnMut(ExitStage)) {
+    let data = process.data::<ProcessData>();
+    for stage in ExitStage::ALL {
+        builtin(stage);
+        // Not under the lock, as hooks may block.
+        let hooks: Vec<_> = HOOKS
+            .read()
+            .iter()
+            .filter(|(it, _)| *it == stage)
+            .map(|(_, hook)| *hook)
+            .collect();
+        for hook in hooks {
+            hook(process);
+        }
+        if let Some(data) = data {
+            data.exit_progress.store(stage as u8 + 1, Ordering::Release);
+        }
+    }
+}
+
+/// Make `process` a zombie, counted in [`crate::pressure`] until [`reap`].
+///
+/// Counted first, so that a parent reaping it at once does not count it
+/// down before.
+pub fn make_zombie(process: &Process) {
+    pressure::add(Pressure::Zombies, 1);
+    process.exit();
+    notify_process_event(process.pid(), ProcessEvent::Zombie);
+}
+
+/// Free the zombie `process`, which its parent waited for, and count it
+/// towards the next scrub of the process tables.
+pub fn reap(process: &Process) {
+    process.free();
+    pressure::sub(Pressure::Zombies, 1);
+    count_reaped();
+    notify_process_event(process.pid(), ProcessEvent::Reaped);
+}
+
+/// The stage the teardown of a process stopped before, or `None` if it
+/// finished or did not start, for [`ProcessData`] to check when dropped.
+pub(crate) fn unfinished_stage(progress: u8) -> Option<ExitStage> {
+    if progress == 0 {
+        return None;
+    }
+    ExitStage::ALL.get(progress as usize).copied()
+}
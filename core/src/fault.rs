@@ -0,0 +1,321 @@
+//! Fault injection, for testing the paths handling failures which are hard
+//! to cause otherwise: running out of frames or file descriptors, and
+//! errors of block devices.
+//!
+//! Each [`FaultPoint`] has a [`Policy`], off until set through
+//! `/proc/starry/fault_inject` or [`set_policy`]. A point makes the calls
+//! the policy picks fail, counting only the calls of the process it names,
+//! if any. Kernel tasks, like the workers flushing filesystems, are never a
+//! process named.
+//!
+//! Only built with the `fault-inject` feature, which also builds the checks
+//! into `axmm` and `axfs`. Without it, there is nothing to check at any of
+//! the points. With it, a point which is off only loads a flag.
+
+use core::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use axprocess::Pid;
+use axsync::spin::SpinNoIrq;
+use axtask::{TaskExtRef, current};
+
+/// A point where a fault can be injected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultPoint {
+    /// Allocating a frame for an address space, failing as if out of memory.
+    FrameAlloc,
+    /// Allocating a file descriptor, failing with `EMFILE`.
+    FdAlloc,
+    /// Reading from a block device, failing with an I/O error.
+    BlockRead,
+    /// Writing to a block device, failing with an I/O error.
+    BlockWrite,
+    /// Adding a block to the cache of a block device, failing as if out of
+    /// memory.
+    PageCache,
+}
+
+impl FaultPoint {
+    /// All the points, in the order `/proc/starry/fault_inject` lists them.
+    pub const ALL: [Self; 5] = [
+        Self::FrameAlloc,
+        Self::FdAlloc,
+        Self::BlockRead,
+        Self::BlockWrite,
+        Self::PageCache,
+    ];
+
+    /// The name of the point in `/proc/starry/fault_inject`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::FrameAlloc => "frame_alloc",
+            Self::FdAlloc => "fd_alloc",
+            Self::BlockRead => "block_read",
+            Self::BlockWrite => "block_write",
+            Self::PageCache => "page_cache",
+        }
+    }
+
+    /// The point named `name`, see [`FaultPoint::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|point| point.name() == name)
+    }
+}
+
+/// Which calls of a point fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// None.
+    Never,
+    /// Every `n`th, that is calls `n`, `2n` and so on. `n` is not 0.
+    Every(u64),
+    /// Call `m + 1` only, after which no call is counted.
+    OnceAfter(u64),
+}
+
+/// Which calls of a point fail, and of which process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    /// Which of the calls counted fail.
+    pub trigger: Trigger,
+    /// The process whose calls are counted, or `None` for all calls.
+    pub pid: Option<Pid>,
+}
+
+impl Policy {
+    /// The policy of a point which never fails.
+    pub const OFF: Self = Self {
+        trigger: Trigger::Never,
+        pid: None,
+    };
+}
+
+/// Formats the policy as `/proc/starry/fault_inject` takes it: `off`,
+/// `every <n>` or `after <m>`, then `pid <pid>` if for a process.
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.trigger {
+            Trigger::Never => return f.write_str("off"),
+            Trigger::Every(n) => write!(f, "every {}", n)?,
+            Trigger::OnceAfter(m) => write!(f, "after {}", m)?,
+        }
+        if let Some(pid) = self.pid {
+            write!(f, " pid {}", pid)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a policy formatted as [`Policy`] formats it. `every 0` is
+/// rejected.
+impl FromStr for Policy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let mut words = s.split_ascii_whitespace();
+        let number = |words: &mut core::str::SplitAsciiWhitespace| {
+            words
+                .next()
+                .and_then(|word| word.parse::<u64>().ok())
+                .ok_or(())
+        };
+        let trigger = match words.next().ok_or(())? {
+            "off" => Trigger::Never,
+            "every" => Trigger::Every(number(&mut words)?),
+            "after" => Trigger::OnceAfter(number(&mut words)?),
+            _ => return Err(()),
+        };
+        if trigger == Trigger::Every(0) {
+            return Err(());
+        }
+        let pid = match words.next() {
+            None => None,
+            Some("pid") if trigger != Trigger::Never => Some(number(&mut words)? as Pid),
+            Some(_) => return Err(()),
+        };
+        if words.next().is_some() {
+            return Err(());
+        }
+        Ok(Self { trigger, pid })
+    }
+}
+
+/// The policy of a point, and what it did since set.
+#[derive(Debug, Clone, Copy)]
+pub struct PointState {
+    /// The policy.
+    pub policy: Policy,
+    /// The calls counted.
+    pub calls: u64,
+    /// The calls which failed.
+    pub failures: u64,
+}
+
+impl PointState {
+    const fn new() -> Self {
+        Self {
+            policy: Policy::OFF,
+            calls: 0,
+            failures: 0,
+        }
+    }
+}
+
+/// Whether the policy of each point is on, so that the points which are off
+/// skip the lock.
+static ARMED: [AtomicBool; FaultPoint::ALL.len()] =
+    [const { AtomicBool::new(false) }; FaultPoint::ALL.len()];
+
+static STATES: SpinNoIrq<[PointState; FaultPoint::ALL.len()]> =
+    SpinNoIrq::new([const { PointState::new() }; FaultPoint::ALL.len()]);
+
+/// Set the policy of `point`, restarting the counts of its calls.
+pub fn set_policy(point: FaultPoint, policy: Policy) {
+    let mut states = STATES.lock();
+    states[point as usize] = PointState {
+        policy,
+        ..PointState::new()
+    };
+    ARMED[point as usize].store(policy.trigger != Trigger::Never, Ordering::Release);
+}
+
+/// The policy of `point`, and what it did since set.
+pub fn state(point: FaultPoint) -> PointState {
+    STATES.lock()[point as usize]
+}
+
+/// Whether the call at hand of `point` is to fail, counting it if its
+/// policy counts the calls of the current task.
+pub fn should_fail(point: FaultPoint) -> bool {
+    if !ARMED[point as usize].load(Ordering::Acquire) {
+        return false;
+    }
+    let pid = {
+        let curr = current();
+        // Safety: We only check whether the task extended data is null.
+        (!unsafe { curr.task_ext_ptr() }.is_null()).then(|| curr.task_ext().thread.process().pid())
+    };
+    let mut states = STATES.lock();
+    let state = &mut states[point as usize];
+    if state.policy.pid.is_some_and(|target| pid != Some(target)) {
+        return false;
+    }
+    state.calls += 1;
+    let fail = match state.policy.trigger {
+        Trigger::Never => false,
+        Trigger::Every(n) => state.calls % n == 0,
+        Trigger::OnceAfter(m) => state.calls == m + 1,
+    };
+    if fail {
+        state.failures += 1;
+        if matches!(state.policy.trigger, Trigger::OnceAfter(_)) {
+            // The policy stays for reading back, but the point is done.
+            ARMED[point as usize].store(false, Ordering::Release);
+        }
+    }
+    fail
+}
+
+/// Install the checks of the points into `axmm` and `axfs`.
+pub fn init() {
+    axmm::set_frame_fault_hook(|| should_fail(FaultPoint::FrameAlloc));
+    axfs::set_fault_hook(|point| {
+        should_fail(match point {
+            axfs::FaultPoint::BlockRead => FaultPoint::BlockRead,
+            axfs::FaultPoint::BlockWrite => FaultPoint::BlockWrite,
+            axfs::FaultPoint::Cache => FaultPoint::PageCache,
+        })
+    });
+}
+
+/// Check that the failures injected are handled: a populated mapping which
+/// runs out of frames half way is rolled back, a fork which does leaves the
+/// parent as it was, and an I/O error reading `program`, if any, fails to
+/// load it without mapping anything.
+#[cfg(feature = "kernel-tests")]
+pub fn self_test(program: Option<&str>) {
+    use alloc::string::String;
+
+    use axerrno::AxError;
+    use axhal::paging::MappingFlags;
+    use axmm::AreaKind;
+    use memory_addr::{PAGE_SIZE_4K, VirtAddr};
+
+    use crate::mm::{load_user_app, new_user_aspace_empty};
+
+    let used_pages = || axalloc::global_allocator().used_pages();
+    let fail_after = |point, m| {
+        set_policy(
+            point,
+            Policy {
+                trigger: Trigger::OnceAfter(m),
+                pid: None,
+            },
+        )
+    };
+    let flags = MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER;
+    let start = VirtAddr::from_usize(axconfig::plat::USER_HEAP_BASE);
+    let size = 8 * PAGE_SIZE_4K;
+
+    let mut aspace = new_user_aspace_empty().unwrap();
+    // Once beforehand, for the page tables of the range, which stay.
+    aspace
+        .map_alloc(start, size, flags, true, AreaKind::Anonymous)
+        .unwrap();
+    aspace.unmap(start, size).unwrap();
+    let before = used_pages();
+    fail_after(FaultPoint::FrameAlloc, 3);
+    assert_eq!(
+        aspace.map_alloc(start, size, flags, true, AreaKind::Anonymous),
+        Err(AxError::NoMemory)
+    );
+    assert_eq!(state(FaultPoint::FrameAlloc).failures, 1);
+    assert_eq!(aspace.area_count(), 0);
+    assert_eq!(used_pages(), before);
+
+    aspace
+        .map_alloc(start, size, flags, true, AreaKind::Anonymous)
+        .unwrap();
+    let data: [u8; 16] = core::array::from_fn(|i| i as u8);
+    let at = start + size - data.len();
+    aspace.write(at, &data).unwrap();
+    // Once beforehand too, for whatever the heap keeps.
+    drop(aspace.clone_or_err(|| {}).unwrap());
+    let (areas, resident, before) = (aspace.area_count(), aspace.resident_size(), used_pages());
+    fail_after(FaultPoint::FrameAlloc, 5);
+    assert!(matches!(aspace.clone_or_err(|| {}), Err(AxError::NoMemory)));
+    assert_eq!(state(FaultPoint::FrameAlloc).failures, 1);
+    assert_eq!(aspace.area_count(), areas);
+    assert_eq!(aspace.resident_size(), resident);
+    assert_eq!(used_pages(), before);
+    let mut buf = [0; 16];
+    aspace.read(at, &mut buf).unwrap();
+    assert_eq!(buf, data);
+    set_policy(FaultPoint::FrameAlloc, Policy::OFF);
+    drop(aspace);
+
+    if let Some(program) = program {
+        let mut aspace = new_user_aspace_empty().unwrap();
+        set_policy(
+            FaultPoint::BlockRead,
+            Policy {
+                trigger: Trigger::Every(1),
+                pid: None,
+            },
+        );
+        let ret = load_user_app(&mut aspace, program, &[String::from(program)], &[]);
+        let failures = state(FaultPoint::BlockRead).failures;
+        set_policy(FaultPoint::BlockRead, Policy::OFF);
+        // Nothing to fail if all of it is cached already.
+        if failures == 0 {
+            warn!("{} is cached, not checking loading it", program);
+        } else {
+            assert!(matches!(ret, Err(AxError::Io)), "{:?}", ret);
+            assert_eq!(aspace.area_count(), 0);
+        }
+    }
+    info!("fault injection self test passed");
+}
@@ -1,49 +1,135 @@
 //! Futex implementation.
+//!
+//! A futex is told apart by a [`FutexKey`]: the address space the word is
+//! in, and its virtual address there, whether the operation is private or
+//! not. This is what Linux does for private futexes, and here it holds for
+//! all of them, as no memory is shared between address spaces: `fork`
+//! copies every page of the parent, `MAP_SHARED` ones too, and processes
+//! sharing memory, with `CLONE_VM`, share the address space itself.
+//!
+//! Keys are never derived from the physical frame of the word. Such a key
+//! is unstable under copy-on-write: a waiter which only read the word would
+//! queue on the frame it shares with the other process, then the waker,
+//! writing the word, would get a frame of its own and wake nobody. Should
+//! pages come to be shared between address spaces, the futexes in them are
+//! to be keyed by what backs them, like a file and offset, and only those,
+//! keeping the key of all others as it is.
+//!
+//! A priority inheritance futex, as `FUTEX_LOCK_PI` takes it, is a lock
+//! whose word holds the tid of its owner, or 0, along with
+//! [`FUTEX_WAITERS`] and [`FUTEX_OWNER_DIED`]. User space takes and
+//! releases it itself while nobody waits, the kernel hands it over from one
+//! owner to the next otherwise, see [`lock_pi`] and [`unlock_pi`]. Threads
+//! have no priorities here, so there is none to inherit: what there is is
+//! the lock.
 
-use core::ops::Deref;
+use core::{
+    ops::Deref,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+    time::Duration,
+};
 
-use alloc::{collections::btree_map::BTreeMap, sync::Arc};
+use alloc::{
+    collections::{btree_map::BTreeMap, vec_deque::VecDeque},
+    sync::Arc,
+    vec::Vec,
+};
+use axerrno::{LinuxError, LinuxResult};
+use axmm::AddrSpace;
+use axprocess::Pid;
 use axsync::Mutex;
-use axtask::{TaskExtRef, WaitQueue, current};
 
-/// A table mapping memory addresses to futex wait queues.
-pub struct FutexTable(Mutex<BTreeMap<usize, Arc<WaitQueue>>>);
+use crate::{
+    lockcheck::track,
+    pressure::{self, Pressure},
+    task::{WaitMode, WaitQueueWrapper, WaitResult, get_thread},
+};
+
+/// The bit of a PI futex word telling that threads wait in the kernel, so
+/// that its owner unlocks it with `FUTEX_UNLOCK_PI`.
+pub const FUTEX_WAITERS: u32 = 0x8000_0000;
+/// The bit of a futex word telling that its owner died holding it.
+pub const FUTEX_OWNER_DIED: u32 = 0x4000_0000;
+/// The bits of a futex word holding the tid of its owner.
+pub const FUTEX_TID_MASK: u32 = 0x3fff_ffff;
+
+/// What a futex is told apart by, see the module documentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FutexKey {
+    aspace: usize,
+    addr: usize,
+}
+
+impl FutexKey {
+    /// The key of the futex word at `addr` in `aspace`.
+    pub fn new(aspace: &Arc<Mutex<AddrSpace>>, addr: usize) -> Self {
+        Self {
+            aspace: Arc::as_ptr(aspace) as usize,
+            addr,
+        }
+    }
+
+    /// The address of the futex word.
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+}
+
+/// A table mapping futex keys to wait queues.
+pub struct FutexTable(Mutex<BTreeMap<FutexKey, Arc<WaitQueueWrapper>>>);
 impl FutexTable {
     /// Creates a new `FutexTable`.
-    pub fn new() -> Self {
+    const fn new() -> Self {
         Self(Mutex::new(BTreeMap::new()))
     }
 
-    /// Gets the wait queue associated with the given address.
-    pub fn get(&self, addr: usize) -> Option<WaitQueueGuard> {
-        let wq = self.0.lock().get(&addr).cloned()?;
+    /// Gets the wait queue associated with the given key.
+    pub fn get(&'static self, key: FutexKey) -> Option<WaitQueueGuard> {
+        let wq = track("futex_table", self.0.lock()).get(&key).cloned()?;
         Some(WaitQueueGuard {
-            key: addr,
+            table: self,
+            key,
             inner: wq,
         })
     }
 
-    /// Gets the wait queue associated with the given address, or inserts a a
+    /// Gets the wait queue associated with the given key, or inserts a a
     /// new one if it doesn't exist.
-    pub fn get_or_insert(&self, addr: usize) -> WaitQueueGuard {
-        let mut table = self.0.lock();
+    pub fn get_or_insert(&'static self, key: FutexKey) -> WaitQueueGuard {
+        let mut table = track("futex_table", self.0.lock());
         let wq = table
-            .entry(addr)
-            .or_insert_with(|| Arc::new(WaitQueue::new()));
+            .entry(key)
+            .or_insert_with(|| Arc::new(WaitQueueWrapper::new()));
         WaitQueueGuard {
-            key: addr,
+            table: self,
+            key,
             inner: wq.clone(),
         }
     }
 }
 
+/// The futexes of all address spaces.
+pub static FUTEX_TABLE: FutexTable = FutexTable::new();
+
 #[doc(hidden)]
 pub struct WaitQueueGuard {
-    key: usize,
-    inner: Arc<WaitQueue>,
+    table: &'static FutexTable,
+    key: FutexKey,
+    inner: Arc<WaitQueueWrapper>,
 }
+impl WaitQueueGuard {
+    /// Wait on the futex, in `mode` and for at most `timeout`, counted in
+    /// [`crate::pressure`] meanwhile.
+    pub fn futex_wait(&self, mode: WaitMode, timeout: Option<Duration>) -> WaitResult {
+        pressure::add(Pressure::FutexWaiters, 1);
+        let result = self.inner.wait(mode, timeout);
+        pressure::sub(Pressure::FutexWaiters, 1);
+        result
+    }
+}
+
 impl Deref for WaitQueueGuard {
-    type Target = Arc<WaitQueue>;
+    type Target = Arc<WaitQueueWrapper>;
 
     fn deref(&self) -> &Self::Target {
         &self.inner
@@ -51,10 +137,184 @@ impl Deref for WaitQueueGuard {
 }
 impl Drop for WaitQueueGuard {
     fn drop(&mut self) {
-        let curr = current();
-        let mut table = curr.task_ext().process_data().futex_table.0.lock();
+        let mut table = track("futex_table", self.table.0.lock());
         if Arc::strong_count(&self.inner) == 1 && self.inner.is_empty() {
             table.remove(&self.key);
         }
     }
 }
+
+/// The threads waiting for a PI futex, see [`lock_pi`].
+struct PiFutex {
+    /// The tid of the owner, as of when the kernel last saw it.
+    owner: Pid,
+    /// The waiters, in the order they came, each with whether it was handed
+    /// the futex.
+    waiters: VecDeque<(Pid, Arc<AtomicBool>)>,
+    wq: Arc<WaitQueueWrapper>,
+}
+
+/// The PI futexes threads wait for. The words of those are only changed by
+/// the kernel with this held, as user space leaves a word alone once it
+/// has [`FUTEX_WAITERS`] and an owner.
+static PI_FUTEXES: Mutex<BTreeMap<FutexKey, PiFutex>> = Mutex::new(BTreeMap::new());
+
+/// Take the PI futex `key`, whose word is `word`, for the thread `tid`,
+/// waiting for it with `wait` while another thread owns it, unless
+/// `trylock`, when it fails with `EAGAIN`.
+///
+/// `wait` waits on the queue it is given until the condition it is given
+/// holds, which is when the futex was handed to the thread, returning
+/// whether it does, or fails, e.g. with `EINTR`. The futex is taken with
+/// [`FUTEX_OWNER_DIED`] kept, for user space to see the state it protects
+/// may be inconsistent. Fails with `EDEADLK` if `tid` owns it already, and
+/// with `ESRCH` if its owner is no thread.
+pub fn lock_pi(
+    key: FutexKey,
+    word: &AtomicU32,
+    tid: Pid,
+    trylock: bool,
+    mut wait: impl FnMut(&WaitQueueWrapper, &dyn Fn() -> bool) -> LinuxResult<bool>,
+) -> LinuxResult {
+    loop {
+        let (wq, handed) = {
+            let mut table = track("pi_futexes", PI_FUTEXES.lock());
+            let val = word.load(Ordering::Acquire);
+            let owner = val & FUTEX_TID_MASK;
+            if owner == 0 {
+                let waiters = if table.contains_key(&key) {
+                    FUTEX_WAITERS
+                } else {
+                    0
+                };
+                let new = tid | (val & FUTEX_OWNER_DIED) | waiters;
+                if word
+                    .compare_exchange(val, new, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    if let Some(pi) = table.get_mut(&key) {
+                        pi.owner = tid;
+                    }
+                    return Ok(());
+                }
+                continue;
+            }
+            if owner == tid {
+                return Err(LinuxError::EDEADLK);
+            }
+            if get_thread(owner).is_err() {
+                return Err(LinuxError::ESRCH);
+            }
+            if trylock {
+                return Err(LinuxError::EAGAIN);
+            }
+            if val & FUTEX_WAITERS == 0
+                && word
+                    .compare_exchange(
+                        val,
+                        val | FUTEX_WAITERS,
+                        Ordering::AcqRel,
+                        Ordering::Relaxed,
+                    )
+                    .is_err()
+            {
+                continue;
+            }
+            let pi = table.entry(key).or_insert_with(|| PiFutex {
+                owner,
+                waiters: VecDeque::new(),
+                wq: Arc::new(WaitQueueWrapper::new()),
+            });
+            pi.owner = owner;
+            let handed = Arc::new(AtomicBool::new(false));
+            pi.waiters.push_back((tid, handed.clone()));
+            (pi.wq.clone(), handed)
+        };
+
+        pressure::add(Pressure::FutexWaiters, 1);
+        let result = wait(&wq, &|| handed.load(Ordering::Acquire));
+        pressure::sub(Pressure::FutexWaiters, 1);
+
+        let mut table = track("pi_futexes", PI_FUTEXES.lock());
+        // Handed over as the wait ended, which counts.
+        if handed.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        if let Some(pi) = table.get_mut(&key) {
+            pi.waiters.retain(|(waiter, _)| *waiter != tid);
+            if pi.waiters.is_empty() {
+                table.remove(&key);
+            }
+        }
+        return Err(result.err().unwrap_or(LinuxError::ETIMEDOUT));
+    }
+}
+
+/// Release the PI futex `key`, whose word is `word`, which the thread `tid`
+/// owns, handing it to the first thread waiting for it, if any. Fails with
+/// `EPERM` if `tid` does not own it.
+pub fn unlock_pi(key: FutexKey, word: &AtomicU32, tid: Pid) -> LinuxResult {
+    let mut table = track("pi_futexes", PI_FUTEXES.lock());
+    let val = word.load(Ordering::Acquire);
+    if val & FUTEX_TID_MASK != tid {
+        return Err(LinuxError::EPERM);
+    }
+    if table.contains_key(&key) {
+        hand_off(&mut table, key, word, 0);
+    } else {
+        // Only the owner changes a word with an owner, so nothing else does.
+        word.store(0, Ordering::Release);
+    }
+    Ok(())
+}
+
+/// Hand the PI futex `key`, whose word is `word`, to its first waiter, with
+/// the bits `keep` of the word, as Linux does, with [`FUTEX_WAITERS`] set.
+fn hand_off(table: &mut BTreeMap<FutexKey, PiFutex>, key: FutexKey, word: &AtomicU32, keep: u32) {
+    let Some(pi) = table.get_mut(&key) else {
+        return;
+    };
+    let Some((next, handed)) = pi.waiters.pop_front() else {
+        return;
+    };
+    word.store(next | FUTEX_WAITERS | keep, Ordering::Release);
+    pi.owner = next;
+    handed.store(true, Ordering::Release);
+    pi.wq.notify_all(false);
+    if pi.waiters.is_empty() {
+        table.remove(&key);
+    }
+}
+
+/// Hand the PI futexes the thread `tid` owns in `aspace`, and others wait
+/// for, to their first waiters, as the thread exits. `word` gives the word
+/// at an address of `aspace`, if it is mapped.
+///
+/// A futex whose word another thread took meanwhile, once user space or a
+/// robust list released it, stays with that thread.
+pub fn exit_pi_futexes(
+    aspace: &Arc<Mutex<AddrSpace>>,
+    tid: Pid,
+    word: impl Fn(usize) -> Option<&'static AtomicU32>,
+) {
+    let aspace = Arc::as_ptr(aspace) as usize;
+    let mut table = track("pi_futexes", PI_FUTEXES.lock());
+    let owned = table
+        .iter()
+        .filter(|(key, pi)| key.aspace == aspace && pi.owner == tid)
+        .map(|(key, _)| *key)
+        .collect::<Vec<_>>();
+    for key in owned {
+        let Some(word) = word(key.addr) else {
+            continue;
+        };
+        let val = word.load(Ordering::Acquire);
+        let owner = val & FUTEX_TID_MASK;
+        if owner == 0 || owner == tid {
+            hand_off(&mut table, key, word, val & FUTEX_OWNER_DIED);
+        } else if let Some(pi) = table.get_mut(&key) {
+            pi.owner = owner;
+            word.fetch_or(FUTEX_WAITERS, Ordering::AcqRel);
+        }
+    }
+}
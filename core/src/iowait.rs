@@ -0,0 +1,64 @@
+//! Accounting of the time user threads wait for block devices, like the
+//! I/O delay accounting and the `iowait` time of Linux, for telling slow
+//! tests stuck on the disk from ones busy on a CPU.
+//!
+//! The time a thread spends in an access to a block device, including the
+//! wait for the accesses of others, is added to its process and to the
+//! `iowait` time of the CPU. Kernel tasks, like the workers flushing
+//! filesystems, are not accounted for.
+//!
+//! Accounting is only done with the `io-accounting` feature. Without it,
+//! no hooks are installed, and an access to a block device only checks that
+//! there are none.
+
+/// Install the accounting hooks into `axfs`, if accounting is enabled.
+pub fn init() {
+    #[cfg(feature = "io-accounting")]
+    axfs::set_io_wait_hooks(hooks::begin, hooks::end);
+}
+
+#[cfg(feature = "io-accounting")]
+mod hooks {
+    use core::sync::atomic::Ordering;
+
+    use axtask::{TaskExtRef, current};
+
+    use crate::{stats, task::TaskExt};
+
+    /// Run `f` with the task extension of the current task, unless it is a
+    /// kernel task.
+    fn with_current(f: impl FnOnce(&TaskExt)) {
+        let curr = current();
+        // Safety: We only check whether the task extended data is null.
+        if !unsafe { curr.task_ext_ptr() }.is_null() {
+            f(curr.task_ext());
+        }
+    }
+
+    pub fn begin() {
+        with_current(|ext| {
+            // Accesses do not nest, and the uptime is never 0 once tasks
+            // run.
+            let _ = ext.thread_data().io_wait_start.compare_exchange(
+                0,
+                stats::uptime_nanos(),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+        });
+    }
+
+    pub fn end() {
+        with_current(|ext| {
+            let start = ext.thread_data().io_wait_start.swap(0, Ordering::Relaxed);
+            if start == 0 {
+                return;
+            }
+            let waited = stats::uptime_nanos().saturating_sub(start);
+            ext.process_data()
+                .io_delay_ns
+                .fetch_add(waited, Ordering::Relaxed);
+            stats::add_io_wait(waited);
+        });
+    }
+}
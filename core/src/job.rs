@@ -0,0 +1,148 @@
+//! Job control: stopping and continuing processes, and the process groups
+//! and sessions they belong to.
+
+use axprocess::{Process, ProcessGroup};
+use axsignal::Signo;
+use axsync::Mutex;
+
+use crate::task::{ProcessData, WaitMode, WaitQueueWrapper};
+
+/// A change of the job control state of a process, waiting to be reported
+/// by `waitpid`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobEvent {
+    /// Stopped by the signal.
+    Stopped(Signo),
+    /// Continued by `SIGCONT`.
+    Continued,
+}
+
+#[derive(Default)]
+struct JobInner {
+    /// The signal which stopped the process, if it is stopped.
+    stopped: Option<Signo>,
+    /// The last change not reported yet.
+    event: Option<JobEvent>,
+}
+
+/// The job control state of a process.
+///
+/// A stop signal stops the whole process: the thread taking it marks the
+/// process stopped, and every thread waits in the queue on its way back to
+/// user space until `SIGCONT` continues the process.
+#[derive(Default)]
+pub struct JobControl {
+    inner: Mutex<JobInner>,
+    wq: WaitQueueWrapper,
+}
+
+impl JobControl {
+    /// Whether the process is stopped.
+    pub fn is_stopped(&self) -> bool {
+        self.inner.lock().stopped.is_some()
+    }
+
+    /// The signal which stopped the process, if it is stopped.
+    pub fn stopped_by(&self) -> Option<Signo> {
+        self.inner.lock().stopped
+    }
+
+    /// Stop the process for `signo`. Returns `false` if it was already
+    /// stopped.
+    pub fn stop(&self, signo: Signo) -> bool {
+        let mut inner = self.inner.lock();
+        if inner.stopped.is_some() {
+            return false;
+        }
+        inner.stopped = Some(signo);
+        inner.event = Some(JobEvent::Stopped(signo));
+        true
+    }
+
+    /// Continue the process. Returns `false` if it was not stopped.
+    pub fn resume(&self) -> bool {
+        let mut inner = self.inner.lock();
+        if inner.stopped.take().is_none() {
+            return false;
+        }
+        inner.event = Some(JobEvent::Continued);
+        drop(inner);
+        self.wq.notify_all(false);
+        true
+    }
+
+    /// Stop the process for a debugger, like [`Self::stop`] but without a
+    /// change for `waitpid` to report: the parent is not told, as with
+    /// `ptrace`.
+    pub fn trace_stop(&self, signo: Signo) -> bool {
+        let mut inner = self.inner.lock();
+        if inner.stopped.is_some() {
+            return false;
+        }
+        inner.stopped = Some(signo);
+        true
+    }
+
+    /// Continue the process for a debugger, like [`Self::resume`] but
+    /// without a change for `waitpid` to report.
+    pub fn trace_resume(&self) -> bool {
+        if self.inner.lock().stopped.take().is_none() {
+            return false;
+        }
+        self.wq.notify_all(false);
+        true
+    }
+
+    /// Take the last change not reported yet, if `want` accepts it. The
+    /// change is left for later if `keep` is set, like with `WNOWAIT`.
+    pub fn take_event(&self, want: impl Fn(JobEvent) -> bool, keep: bool) -> Option<JobEvent> {
+        let mut inner = self.inner.lock();
+        let event = inner.event.filter(|&it| want(it))?;
+        if !keep {
+            inner.event = None;
+        }
+        Some(event)
+    }
+
+    /// Wait until the process is continued, or the current thread gets
+    /// `SIGKILL`.
+    ///
+    /// The wait is killable: other signals stay pending until the process
+    /// is continued.
+    pub fn wait_resumed(&self) {
+        self.wq
+            .wait_until(WaitMode::Killable, None, || !self.is_stopped());
+    }
+}
+
+/// Whether `proc` leads its session.
+pub fn is_session_leader(proc: &Process) -> bool {
+    proc.group().session().sid() == proc.pid()
+}
+
+/// Whether `group` is orphaned, see POSIX: none of its members has a parent
+/// in another process group of the same session, which could continue the
+/// group if it stops.
+///
+/// Like Linux, zombies are not counted as members, nor is the init process
+/// as a parent.
+pub fn is_orphaned_group(group: &ProcessGroup) -> bool {
+    let sid = group.session().sid();
+    !group.processes().iter().any(|proc| {
+        !proc.is_zombie()
+            && proc.parent().is_some_and(|parent| {
+                let parent_group = parent.group();
+                !parent.is_init()
+                    && parent_group.pgid() != group.pgid()
+                    && parent_group.session().sid() == sid
+            })
+    })
+}
+
+/// Whether a member of `group` is stopped.
+pub fn has_stopped_member(group: &ProcessGroup) -> bool {
+    group.processes().iter().any(|proc| {
+        proc.data::<ProcessData>()
+            .is_some_and(|data| data.job.is_stopped())
+    })
+}
@@ -0,0 +1,107 @@
+//! Histograms of how long syscalls take, for profiling where the time of a
+//! test run goes.
+//!
+//! Recording is off unless turned on for a process, through
+//! `/proc/<pid>/syscall_latency`, and children inherit it. The dispatcher
+//! reads the clock on entry and exit of every syscall anyway, for the time
+//! statistics, so a process with recording off only pays a branch, and one
+//! with it on a lock and a few adds. The syscalls of every process recording
+//! are also added to the system-wide [`GLOBAL`] histograms.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use axtask::{TaskExtRef, current};
+use spin::Mutex;
+
+/// The number of buckets of a [`Histogram`].
+///
+/// Bucket `k` counts the syscalls taking `[2^k, 2^(k+1))` nanoseconds, but
+/// bucket 0 also counts those taking no time, and the last one all those
+/// taking `2^31` nanoseconds (about 2 seconds) or more.
+pub const BUCKETS: usize = 32;
+
+/// The latencies of a syscall.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Histogram {
+    /// The number of calls.
+    pub count: u64,
+    /// The time of all calls, in nanoseconds.
+    pub total_ns: u64,
+    /// The number of calls in each bucket, see [`BUCKETS`].
+    pub buckets: [u64; BUCKETS],
+}
+
+impl Histogram {
+    fn add(&mut self, ns: u64) {
+        self.count += 1;
+        self.total_ns += ns;
+        let bucket = ns.checked_ilog2().unwrap_or(0) as usize;
+        self.buckets[bucket.min(BUCKETS - 1)] += 1;
+    }
+}
+
+/// The latency histograms of the syscalls of a process, or of the system,
+/// by syscall number.
+#[derive(Default)]
+pub struct SyscallLatency {
+    enabled: AtomicBool,
+    histograms: Mutex<BTreeMap<usize, Histogram>>,
+}
+
+impl SyscallLatency {
+    /// Create empty histograms, with recording off.
+    pub const fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            histograms: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Whether syscalls are recorded.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Turn recording on or off. Turning it on starts from empty
+    /// histograms.
+    pub fn set_enabled(&self, enabled: bool) {
+        if enabled {
+            self.clear();
+        }
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Empty the histograms.
+    pub fn clear(&self) {
+        self.histograms.lock().clear();
+    }
+
+    /// Get the histogram of each syscall made, in the order of numbers.
+    pub fn histograms(&self) -> Vec<(usize, Histogram)> {
+        self.histograms
+            .lock()
+            .iter()
+            .map(|(nr, histogram)| (*nr, *histogram))
+            .collect()
+    }
+
+    fn add(&self, nr: usize, ns: u64) {
+        self.histograms.lock().entry(nr).or_default().add(ns);
+    }
+}
+
+/// The histograms of the syscalls of all processes recording them.
+pub static GLOBAL: SyscallLatency = SyscallLatency::new();
+
+/// Record that the syscall `nr` of the current process took `ns`
+/// nanoseconds, if the process records its syscalls.
+pub fn record_syscall(nr: usize, ns: u64) {
+    let curr = current();
+    let latency = &curr.task_ext().process_data().syscall_latency;
+    if !latency.is_enabled() {
+        return;
+    }
+    latency.add(nr, ns);
+    GLOBAL.add(nr, ns);
+}
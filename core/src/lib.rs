@@ -8,7 +8,28 @@
 extern crate axlog;
 extern crate alloc;
 
+pub mod audit;
+pub mod cred;
+pub mod exec;
+pub mod exit;
+#[cfg(feature = "fault-inject")]
+pub mod fault;
 pub mod futex;
+pub mod iowait;
+pub mod job;
+pub mod latency;
+pub mod lockcheck;
 pub mod mm;
+pub mod observer;
+pub mod pid_table;
+pub mod pressure;
+pub mod random;
+pub mod resources;
+pub mod sandbox;
+pub mod seccomp;
+pub mod stats;
 pub mod task;
 mod time;
+pub mod uts;
+pub mod wait;
+pub mod workqueue;
@@ -0,0 +1,117 @@
+//! A debug check that `clone`, `execve` and `exit` commit holding none of
+//! the locks of the process state.
+//!
+//! These paths wait for other threads of the process, or make them wait,
+//! across the point they commit at: a clone holding the lock of the address
+//! space while another thread in `mmap` waits for it, or an exit holding the
+//! futex table while a waiter needs it to wake up, is a circular wait which
+//! only shows under load. The locks worth it, like the address space, are
+//! taken through [`track`], which in debug builds records each as held by
+//! the current thread, along with where it was taken, until its guard is
+//! dropped. [`assert_lock_clean`] then panics at the commit points if any
+//! is still held, naming where each was taken.
+//!
+//! Release builds record nothing, and the guards are the plain ones.
+
+use core::{
+    ops::{Deref, DerefMut},
+    panic::Location,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use alloc::{format, string::String, vec::Vec};
+use axtask::{TaskExtRef, current};
+
+use crate::task::ThreadData;
+
+/// A lock recorded as held, see [`HeldLocks`].
+struct HeldLock {
+    id: u64,
+    name: &'static str,
+    location: &'static Location<'static>,
+}
+
+/// The tracked locks a thread holds, in the order they were taken.
+#[derive(Default)]
+pub struct HeldLocks(spin::Mutex<Vec<HeldLock>>);
+
+/// Run `f` with the data of the current thread, unless it is a kernel task,
+/// which has no thread.
+fn with_current(f: impl FnOnce(&ThreadData)) {
+    let curr = current();
+    // Safety: We only check whether the task extended data is null.
+    if !unsafe { curr.task_ext_ptr() }.is_null() {
+        f(curr.task_ext().thread_data());
+    }
+}
+
+/// The guard of a lock taken through [`track`], which stops recording the
+/// lock as held when dropped.
+pub struct Tracked<G> {
+    guard: G,
+    /// The id of the record, or 0 if there is none.
+    id: u64,
+}
+
+impl<G> Deref for Tracked<G> {
+    type Target = G;
+
+    fn deref(&self) -> &G {
+        &self.guard
+    }
+}
+
+impl<G> DerefMut for Tracked<G> {
+    fn deref_mut(&mut self) -> &mut G {
+        &mut self.guard
+    }
+}
+
+impl<G> Drop for Tracked<G> {
+    fn drop(&mut self) {
+        if self.id == 0 {
+            return;
+        }
+        with_current(|thr| thr.held_locks.0.lock().retain(|held| held.id != self.id));
+    }
+}
+
+/// Record `guard`, of the lock `name` just taken, as held by the current
+/// thread until it is dropped, in debug builds.
+#[track_caller]
+pub fn track<G>(name: &'static str, guard: G) -> Tracked<G> {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+    let mut id = 0;
+    if cfg!(debug_assertions) {
+        let location = Location::caller();
+        with_current(|thr| {
+            id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            thr.held_locks
+                .0
+                .lock()
+                .push(HeldLock { id, name, location });
+        });
+    }
+    Tracked { guard, id }
+}
+
+/// Panic if the current thread holds a tracked lock, at the point `what`
+/// commits, naming the locks held and where they were taken.
+pub fn assert_lock_clean(what: &str) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    with_current(|thr| {
+        let report: String = thr
+            .held_locks
+            .0
+            .lock()
+            .iter()
+            .map(|lock| format!("\n  {} taken at {}", lock.name, lock.location))
+            .collect();
+        if !report.is_empty() {
+            panic!("{what} committing with locks held:{report}");
+        }
+    });
+}
@@ -1,15 +1,78 @@
 //! User address space management.
+//!
+//! The user address space is laid out the same way on every architecture,
+//! from the addresses in the platform config, low to high:
+//!
+//! - the program, loaded at the addresses of its ELF, and its interpreter
+//!   at `USER_INTERP_BASE`;
+//! - the heap, `USER_HEAP_SIZE` bytes reserved at `USER_HEAP_BASE`, which
+//!   `brk` maps on demand;
+//! - the signal trampoline at `SIGNAL_TRAMPOLINE`, above the heap so that
+//!   `brk` can grow it to its full size;
+//! - mappings of `mmap`, placed in the free space outside of the heap,
+//!   first fit from the hint;
+//! - the main thread stack, `USER_STACK_SIZE` bytes below `USER_STACK_TOP`.
+//!
+//! User programs see pages of [`PAGE_SIZE`], which every mapping is aligned
+//! to, while the page tables map 4K pages, the signal trampoline being the
+//! only mapping of a single one.
 
-use core::ffi::CStr;
+use core::{
+    ffi::CStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use alloc::{borrow::ToOwned, string::String, vec, vec::Vec};
+use alloc::{borrow::ToOwned, string::String, sync::Arc, vec, vec::Vec};
 use axerrno::{AxError, AxResult};
 use axhal::{mem::virt_to_phys, paging::MappingFlags};
-use axmm::{AddrSpace, kernel_aspace};
-use kernel_elf_parser::{AuxvEntry, ELFParser, app_stack_region};
-use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr};
+use axmm::{AddrSpace, AreaKind, kernel_aspace};
+use kernel_elf_parser::{AuxvEntry, AuxvType, ELFParser, app_stack_region};
+use memory_addr::{MemoryAddr, PAGE_SIZE_4K, VirtAddr, VirtAddrRange};
 use xmas_elf::{ElfFile, program::SegmentData};
 
+/// The size of a page as user programs see it: the unit of `mmap` and
+/// `brk`, and `AT_PAGESZ`. It is 4K, or 16K or 64K with the `page-16k` or
+/// `page-64k` feature, a run of the 4K pages the page tables map.
+pub const PAGE_SIZE: usize = if cfg!(feature = "page-64k") {
+    0x10000
+} else if cfg!(feature = "page-16k") {
+    0x4000
+} else {
+    PAGE_SIZE_4K
+};
+
+/// The end of the user address space, above which nothing is mapped.
+pub const USER_SPACE_END: usize = axconfig::plat::USER_SPACE_BASE + axconfig::plat::USER_SPACE_SIZE;
+
+/// The end of the lower half of the virtual addresses the paging mode gives
+/// user space: 256 GiB with the 3 levels of Sv39 on riscv64, 128 TiB with
+/// the 4 levels on the others.
+pub const USER_VA_LIMIT: usize = if cfg!(target_arch = "riscv64") {
+    1 << 38
+} else {
+    1 << 47
+};
+
+const _: () = assert!(
+    USER_SPACE_END <= USER_VA_LIMIT,
+    "the user space of the platform config does not fit the paging mode"
+);
+
+const _: () = {
+    use axconfig::plat::{
+        SIGNAL_TRAMPOLINE, USER_HEAP_BASE, USER_HEAP_SIZE, USER_STACK_SIZE, USER_STACK_TOP,
+    };
+    let heap_end = USER_HEAP_BASE + USER_HEAP_SIZE;
+    assert!(
+        SIGNAL_TRAMPOLINE + PAGE_SIZE_4K <= USER_HEAP_BASE || SIGNAL_TRAMPOLINE >= heap_end,
+        "the signal trampoline of the platform config is in the heap"
+    );
+    assert!(
+        heap_end <= USER_STACK_TOP - USER_STACK_SIZE || USER_HEAP_BASE >= USER_STACK_TOP,
+        "the heap of the platform config overlaps the stack"
+    );
+};
+
 /// Creates a new empty user address space.
 pub fn new_user_aspace_empty() -> AxResult<AddrSpace> {
     AddrSpace::new_empty(
@@ -31,6 +94,9 @@ pub fn copy_from_kernel(aspace: &mut AddrSpace) -> AxResult {
 }
 
 /// Map the signal trampoline to the user address space.
+///
+/// Only its 4K page is mapped, however large [`PAGE_SIZE`] is, as the pages
+/// next to it hold other kernel text.
 pub fn map_trampoline(aspace: &mut AddrSpace) -> AxResult {
     let signal_trampoline_paddr = virt_to_phys(axsignal::arch::signal_trampoline_address().into());
     aspace.map_linear(
@@ -47,10 +113,15 @@ pub fn map_trampoline(aspace: &mut AddrSpace) -> AxResult {
 /// # Arguments
 /// - `uspace`: The address space of the user app.
 /// - `elf`: The elf file.
+/// - `path`: The path of the elf file, which names its segments.
 ///
 /// # Returns
 /// - The entry point of the user app.
-fn map_elf(uspace: &mut AddrSpace, elf: &ElfFile) -> AxResult<(VirtAddr, [AuxvEntry; 16])> {
+fn map_elf(
+    uspace: &mut AddrSpace,
+    elf: &ElfFile,
+    path: Arc<str>,
+) -> AxResult<(VirtAddr, [AuxvEntry; 16])> {
     let uspace_base = uspace.base().as_usize();
     let elf_parser = ELFParser::new(
         elf,
@@ -60,68 +131,191 @@ fn map_elf(uspace: &mut AddrSpace, elf: &ElfFile) -> AxResult<(VirtAddr, [AuxvEn
     )
     .map_err(|_| AxError::InvalidData)?;
 
-    for segement in elf_parser.ph_load() {
+    // The end of the pages mapped for the segments so far, and the flags of
+    // the last one.
+    let mut mapped_end = VirtAddr::from(0);
+    let mut prev_flags = MappingFlags::empty();
+    for segment in elf_parser.ph_load() {
         debug!(
             "Mapping ELF segment: [{:#x?}, {:#x?}) flags: {:#x?}",
-            segement.vaddr,
-            segement.vaddr + segement.memsz as usize,
-            segement.flags
+            segment.vaddr,
+            segment.vaddr + segment.memsz as usize,
+            segment.flags
         );
-        let seg_pad = segement.vaddr.align_offset_4k();
-        assert_eq!(seg_pad, segement.offset % PAGE_SIZE_4K);
-
-        let seg_align_size =
-            (segement.memsz as usize + seg_pad + PAGE_SIZE_4K - 1) & !(PAGE_SIZE_4K - 1);
-        uspace.map_alloc(
-            segement.vaddr.align_down_4k(),
-            seg_align_size,
-            segement.flags,
-            true,
-        )?;
-        let seg_data = elf
-            .input
-            .get(segement.offset..segement.offset + segement.filesz as usize)
-            .ok_or(AxError::InvalidData)?;
-        uspace.write(segement.vaddr, seg_data)?;
-        // TDOO: flush the I-cache
+
+        // The file data is copied, not mapped, so the segments need not be
+        // aligned to a page in the file, nor in memory. An ELF aligned to
+        // less than `PAGE_SIZE` has a segment start in the last page of the
+        // one before, which is mapped already, and gets the flags of both.
+        let seg_start = segment.vaddr.align_down(PAGE_SIZE);
+        let data_end = (segment.vaddr + segment.filesz as usize).align_up(PAGE_SIZE);
+        let seg_end = (segment.vaddr + segment.memsz as usize).align_up(PAGE_SIZE);
+        let map_start = seg_start.max(mapped_end).min(seg_end);
+        if map_start > seg_start {
+            uspace.protect(seg_start, map_start - seg_start, prev_flags | segment.flags)?;
+        }
+
+        // The pages holding file data are mapped with their final flags right
+        // away. `write` fills them through the kernel's linear mapping of the
+        // frames, so the user mapping never needs to be writable.
+        if data_end > map_start {
+            uspace.map_alloc(
+                map_start,
+                data_end - map_start,
+                segment.flags,
+                true,
+                AreaKind::File(path.clone()),
+            )?;
+        }
+        if segment.filesz > 0 {
+            let seg_data = elf
+                .input
+                .get(segment.offset..segment.offset + segment.filesz as usize)
+                .ok_or(AxError::InvalidData)?;
+            uspace.write(segment.vaddr, seg_data)?;
+            // TODO: flush the I-cache
+        }
+        // The rest of the BSS is backed by zeroed frames on first access, and
+        // is anonymous memory like on Linux.
+        let bss_start = data_end.max(map_start);
+        if seg_end > bss_start {
+            uspace.map_alloc(
+                bss_start,
+                seg_end - bss_start,
+                segment.flags,
+                false,
+                AreaKind::Anonymous,
+            )?;
+        }
+
+        if cfg!(debug_assertions) {
+            check_segment_flags(uspace, map_start, data_end, segment.flags);
+        }
+        mapped_end = mapped_end.max(seg_end);
+        prev_flags = segment.flags;
     }
 
-    Ok((
-        elf_parser.entry().into(),
-        elf_parser.auxv_vector(PAGE_SIZE_4K),
-    ))
+    Ok((elf_parser.entry().into(), elf_parser.auxv_vector(PAGE_SIZE)))
+}
+
+/// Check that the populated pages in `[start, end)` are mapped with exactly
+/// the permissions in `flags`.
+fn check_segment_flags(uspace: &AddrSpace, start: VirtAddr, end: VirtAddr, flags: MappingFlags) {
+    const PERM: MappingFlags = MappingFlags::READ
+        .union(MappingFlags::WRITE)
+        .union(MappingFlags::EXECUTE)
+        .union(MappingFlags::USER);
+    for vaddr in (start.as_usize()..end.as_usize()).step_by(PAGE_SIZE_4K) {
+        let (_, mapped, _) = uspace
+            .page_table()
+            .query(vaddr.into())
+            .expect("ELF segment page not mapped");
+        assert_eq!(
+            mapped & PERM,
+            flags & PERM,
+            "ELF segment page {:#x} mapped with wrong flags",
+            vaddr
+        );
+    }
+}
+
+/// Check that the 16 bytes at `at_random` are in the initial stack
+/// `stack_data` starting at `sp`, apart from the strings of the arguments
+/// and environment, which overwriting them would clobber.
+///
+/// The stack starts with `argc`, then the pointers to the arguments and to
+/// the environment variables, each list ending with a null pointer.
+///
+/// Fails with [`AxError::InvalidData`], so that `execve` fails with
+/// `ENOEXEC`, if they are not.
+fn check_at_random(stack_data: &[u8], sp: usize, at_random: usize) -> AxResult {
+    const WORD: usize = size_of::<usize>();
+    let end = sp + stack_data.len();
+    if at_random < sp || at_random + 16 > end {
+        error!(
+            "AT_RANDOM {:#x} out of the initial stack [{:#x}, {:#x})",
+            at_random, sp, end
+        );
+        return Err(AxError::InvalidData);
+    }
+    let word = |i: usize| {
+        stack_data
+            .get(i * WORD..(i + 1) * WORD)
+            .map(|it| usize::from_ne_bytes(it.try_into().unwrap()))
+    };
+    // The argument pointers, a null one, then the environment pointers and
+    // a null one.
+    let mut nulls = 0;
+    for i in 1.. {
+        let Some(ptr) = word(i) else {
+            break;
+        };
+        if ptr == 0 {
+            nulls += 1;
+            if nulls == 2 {
+                break;
+            }
+            continue;
+        }
+        let Some(len) = ptr
+            .checked_sub(sp)
+            .and_then(|offset| stack_data.get(offset..))
+            .and_then(|it| it.iter().position(|c| *c == 0))
+        else {
+            continue;
+        };
+        if at_random + 16 > ptr && ptr + len + 1 > at_random {
+            error!(
+                "AT_RANDOM {:#x} overlaps the string at {:#x}",
+                at_random, ptr
+            );
+            return Err(AxError::InvalidData);
+        }
+    }
+    Ok(())
 }
 
 /// Load the user app to the user address space.
 ///
 /// # Arguments
 /// - `uspace`: The address space of the user app.
-/// - `args`: The arguments of the user app. The first argument is the path of the user app.
+/// - `path`: The path of the user app, which may differ from its first
+///   argument.
+/// - `args`: The arguments of the user app.
 /// - `envs`: The environment variables of the user app.
 ///
+/// A script starting with `#!` runs its interpreter with the arguments of
+/// Linux: the interpreter, its optional argument, `path`, then the arguments
+/// after the first. A dynamically linked program runs its interpreter with
+/// `path` as its first argument, so the program sees `path` as `argv[0]`.
+///
 /// # Returns
 /// - The entry point of the user app.
 /// - The stack pointer of the user app.
 pub fn load_user_app(
     uspace: &mut AddrSpace,
+    path: &str,
     args: &[String],
     envs: &[String],
 ) -> AxResult<(VirtAddr, VirtAddr)> {
-    if args.is_empty() {
-        return Err(AxError::InvalidInput);
-    }
-    let file_data = axfs::api::read(args[0].as_str())?;
+    let file_data = axfs::api::read(path)?;
     if file_data.starts_with(b"#!") {
         let head = &file_data[2..file_data.len().min(256)];
         let pos = head.iter().position(|c| *c == b'\n').unwrap_or(head.len());
         let line = core::str::from_utf8(&head[..pos]).map_err(|_| AxError::InvalidData)?;
 
         let new_args: Vec<String> = line
+            .trim_ascii()
             .splitn(2, |c: char| c.is_ascii_whitespace())
             .map(|s| s.trim_ascii().to_owned())
-            .chain(args.iter().cloned())
+            .chain([path.to_owned()])
+            .chain(args.iter().skip(1).cloned())
             .collect();
-        return load_user_app(uspace, &new_args, envs);
+        let interp = new_args[0].as_str();
+        if interp.is_empty() {
+            return Err(AxError::InvalidData);
+        }
+        return load_user_app(uspace, interp, &new_args, envs);
     }
     let elf = ElfFile::new(&file_data).map_err(|_| AxError::InvalidData)?;
 
@@ -150,13 +344,14 @@ pub fn load_user_app(
             interp_path = String::from("/musl/lib/libc.so");
         }
 
-        // Set the first argument to the path of the user app.
-        let mut new_args = vec![interp_path];
-        new_args.extend_from_slice(args);
-        return load_user_app(uspace, &new_args, envs);
+        // The interpreter runs the program at its first argument.
+        let mut new_args = vec![interp_path.clone(), path.to_owned()];
+        new_args.extend(args.iter().skip(1).cloned());
+        return load_user_app(uspace, &interp_path, &new_args, envs);
     }
 
-    let (entry, mut auxv) = map_elf(uspace, &elf)?;
+    let path = axfs::api::canonicalize(path)?;
+    let (entry, auxv) = map_elf(uspace, &elf, path.into())?;
     // The user stack is divided into two parts:
     // `ustack_start` -> `ustack_pointer`: It is the stack space that users actually read and write.
     // `ustack_pointer` -> `ustack_end`: It is the space that contains the arguments, environment variables and auxv passed to the app.
@@ -169,30 +364,266 @@ pub fn load_user_app(
         ustack_start, ustack_end
     );
 
+    let mut auxv = Vec::from(auxv);
+    set_hwcap(&mut auxv);
     let stack_data = app_stack_region(args, envs, &mut auxv, ustack_start, ustack_size);
     uspace.map_alloc(
         ustack_start,
         ustack_size,
         MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
         true,
-    )?;
-
-    let heap_start = VirtAddr::from_usize(axconfig::plat::USER_HEAP_BASE);
-    let heap_size = axconfig::plat::USER_HEAP_SIZE;
-    uspace.map_alloc(
-        heap_start,
-        heap_size,
-        MappingFlags::READ | MappingFlags::WRITE | MappingFlags::USER,
-        true,
+        AreaKind::Stack,
     )?;
 
     let user_sp = ustack_end - stack_data.len();
 
     uspace.write(user_sp, stack_data.as_slice())?;
+    // The parser leaves the same 16 bytes at `AT_RANDOM` for every program,
+    // which the C library seeds its stack canary and pointer guard from, so
+    // they are drawn afresh for each image loaded. Only here, past the
+    // recursion for scripts and interpreters, so once per `execve`. A fork
+    // copies the stack, and keeps them, as it keeps the canary.
+    if let Some(at_random) = auxv.iter().find(|it| it.get_type() == AuxvType::RANDOM) {
+        let at_random = at_random.value();
+        check_at_random(&stack_data, user_sp.as_usize(), at_random)?;
+        let mut random = [0; 16];
+        crate::random::random_bytes(&mut random);
+        uspace.write(VirtAddr::from_usize(at_random), &random)?;
+    }
 
     Ok((entry, user_sp))
 }
 
+/// Report the features of the CPU user space may use in `AT_HWCAP` and
+/// `AT_HWCAP2`, the first of which the parser leaves 0.
+fn set_hwcap(auxv: &mut Vec<AuxvEntry>) {
+    for (ty, value) in [
+        (AuxvType::HWCAP, axhal::hwcap::hwcap()),
+        (AuxvType::HWCAP2, axhal::hwcap::hwcap2()),
+    ] {
+        if let Some(entry) = auxv.iter_mut().find(|it| it.get_type() == ty) {
+            *entry.value_mut_ref() = value;
+            continue;
+        }
+        let end = auxv
+            .iter()
+            .position(|it| it.get_type() == AuxvType::NULL)
+            .unwrap_or(auxv.len());
+        auxv.insert(end, AuxvEntry::new(ty, value));
+    }
+}
+
+/// The range reserved for the heap, which `brk` maps on demand and `mmap`
+/// does not place mappings in unless they are fixed.
+pub fn heap_range() -> VirtAddrRange {
+    VirtAddrRange::from_start_size(
+        axconfig::plat::USER_HEAP_BASE.into(),
+        axconfig::plat::USER_HEAP_SIZE,
+    )
+}
+
+/// The bounds of the user heap, `[bottom, top)`, where `top` is the program
+/// break.
+///
+/// Like the address space, they are shared by the processes created with
+/// `CLONE_VM`, and copied by the others.
+#[derive(Debug)]
+pub struct HeapBounds {
+    bottom: AtomicUsize,
+    top: AtomicUsize,
+}
+
+impl Default for HeapBounds {
+    fn default() -> Self {
+        let base = axconfig::plat::USER_HEAP_BASE;
+        Self {
+            bottom: AtomicUsize::new(base),
+            top: AtomicUsize::new(base),
+        }
+    }
+}
+
+impl HeapBounds {
+    /// A copy of the bounds, for a copy of the address space.
+    pub fn fork(&self) -> Self {
+        Self {
+            bottom: AtomicUsize::new(self.bottom()),
+            top: AtomicUsize::new(self.top()),
+        }
+    }
+
+    /// Get the bottom address of the user heap.
+    pub fn bottom(&self) -> usize {
+        self.bottom.load(Ordering::Acquire)
+    }
+
+    /// Get the top address of the user heap.
+    pub fn top(&self) -> usize {
+        self.top.load(Ordering::Acquire)
+    }
+
+    /// Set the top address of the user heap.
+    pub fn set_top(&self, top: usize) {
+        self.top.store(top, Ordering::Release)
+    }
+
+    /// Empty the heap at the base of the heap area, for a new image.
+    pub fn reset(&self) {
+        let base = axconfig::plat::USER_HEAP_BASE;
+        self.bottom.store(base, Ordering::Release);
+        self.top.store(base, Ordering::Release);
+    }
+}
+
+/// How far a grows-down mapping may grow below its initial start, like the
+/// default `RLIMIT_STACK`.
+pub const MAX_STACK_GROWTH: usize = 8 << 20;
+
+/// The gap kept free below a grows-down mapping, where other mappings are
+/// not placed unless they are fixed, like `stack_guard_gap` on Linux.
+pub const STACK_GUARD_GAP: usize = 256 * PAGE_SIZE;
+
+/// A mapping made with `MAP_GROWSDOWN`.
+#[derive(Debug, Clone)]
+struct GrowsDownArea {
+    /// The current start, which moves down as the mapping grows.
+    start: VirtAddr,
+    /// The end, which is fixed.
+    end: VirtAddr,
+    /// The lowest start the mapping may grow to.
+    limit: VirtAddr,
+    flags: MappingFlags,
+    /// The kind the pages it grows by are mapped with.
+    kind: AreaKind,
+}
+
+/// The grows-down mappings of an address space.
+///
+/// A grows-down mapping is an ordinary lazy mapping of the address space
+/// which is extended downward when a page fault hits between its start and
+/// its growth limit. Only `MAP_GROWSDOWN` makes one: the thread stacks
+/// mapped with `MAP_STACK` keep their size, and no guard gap.
+#[derive(Debug, Default, Clone)]
+pub struct GrowsDownAreas(Vec<GrowsDownArea>);
+
+impl GrowsDownAreas {
+    /// Record that `[start, start + size)` was mapped with `flags`, `kind`
+    /// and `MAP_GROWSDOWN`.
+    pub fn insert(&mut self, start: VirtAddr, size: usize, flags: MappingFlags, kind: AreaKind) {
+        let limit = start.as_usize().saturating_sub(MAX_STACK_GROWTH);
+        self.0.push(GrowsDownArea {
+            start,
+            end: start + size,
+            limit: VirtAddr::from(limit.max(PAGE_SIZE)),
+            flags,
+            kind,
+        });
+    }
+
+    /// Get the start of the grows-down mapping containing `vaddr`.
+    pub fn start_of(&self, vaddr: VirtAddr) -> Option<VirtAddr> {
+        self.0
+            .iter()
+            .find(|area| (area.start..area.end).contains(&vaddr))
+            .map(|area| area.start)
+    }
+
+    /// Extend the grows-down mapping below `vaddr` down to it, if `vaddr`
+    /// is within the growth limit.
+    ///
+    /// Returns `false` if no mapping can grow to `vaddr`, or if it would
+    /// overlap another mapping.
+    pub fn grow(&mut self, aspace: &mut AddrSpace, vaddr: VirtAddr) -> bool {
+        let Some(area) = self
+            .0
+            .iter_mut()
+            .filter(|area| (area.limit..area.start).contains(&vaddr))
+            .min_by_key(|area| area.start)
+        else {
+            return false;
+        };
+        let new_start = vaddr.align_down(PAGE_SIZE);
+        let size = area.start - new_start;
+        if aspace
+            .map_alloc(new_start, size, area.flags, false, area.kind.clone())
+            .is_err()
+        {
+            return false;
+        }
+        area.start = new_start;
+        true
+    }
+
+    /// Find a place for `size` bytes from `hint` upward with
+    /// `find_free_area`, aligned to [`PAGE_SIZE`], keeping out of the heap
+    /// and of the guard gap below every grows-down mapping.
+    pub fn find_free_area(
+        &self,
+        aspace: &AddrSpace,
+        mut hint: VirtAddr,
+        size: usize,
+    ) -> Option<VirtAddr> {
+        let range = VirtAddrRange::new(aspace.base(), aspace.end());
+        let heap = heap_range();
+        // Each retry moves above the heap or one more mapping, or to a page
+        // boundary past the end of a mapping of less than a page, like the
+        // signal trampoline.
+        for _ in 0..=2 * (self.0.len() + 2) {
+            let start = aspace.find_free_area(hint.max(range.start), size, range)?;
+            if !start.is_aligned(PAGE_SIZE) {
+                hint = start.align_up(PAGE_SIZE);
+                continue;
+            }
+            // Near the top, an area which does not fit must not wrap around.
+            let end = VirtAddr::from(start.as_usize().checked_add(size)?);
+            if end > range.end {
+                return None;
+            }
+            if start < heap.end && end > heap.start {
+                hint = heap.end;
+                continue;
+            }
+            match self.0.iter().find(|area| {
+                let gap_start = area.start.as_usize().saturating_sub(STACK_GUARD_GAP);
+                start < area.start && end.as_usize() > gap_start
+            }) {
+                Some(area) => hint = area.end,
+                None => return Some(start),
+            }
+        }
+        None
+    }
+
+    /// Update the mappings for `[start, start + size)` being unmapped.
+    ///
+    /// A mapping whose start is unmapped keeps growing down from the end of
+    /// the hole, and is forgotten once it is unmapped entirely.
+    pub fn unmap(&mut self, start: VirtAddr, size: usize) {
+        let end = start + size;
+        self.0.retain_mut(|area| {
+            if start <= area.start && end > area.start {
+                area.start = end;
+            }
+            area.start < area.end
+        });
+    }
+
+    /// Update the flags used for growth, if `[start, start + size)` covers
+    /// the start of a mapping.
+    pub fn protect(&mut self, start: VirtAddr, size: usize, flags: MappingFlags) {
+        for area in &mut self.0 {
+            if start <= area.start && start + size > area.start {
+                area.flags = flags;
+            }
+        }
+    }
+
+    /// Forget all mappings, e.g. on `execve`.
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
 #[percpu::def_percpu]
 static mut ACCESSING_USER_MEM: bool = false;
 
@@ -0,0 +1,164 @@
+//! Hooks on the lifetime of processes, for tests observing the kernel, and
+//! for the kernel to act once init exits, which powers off the machine.
+//!
+//! Observers are called synchronously, in the context of the task causing
+//! the event, so they must not block. Without observers, reporting an event
+//! is a single atomic load.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::vec::Vec;
+use axprocess::Pid;
+use spin::RwLock;
+
+/// An event in the lifetime of a process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessEvent {
+    /// The process was created, by `clone` or as the first user process.
+    Created,
+    /// The process replaced its program with `execve`.
+    Exec,
+    /// The last thread of the process exited, so it became a zombie.
+    ///
+    /// Init is reported as well, once the kernel task running it is done.
+    Zombie,
+    /// The zombie was reaped by its parent.
+    Reaped,
+}
+
+/// A callback of process events, given the PID of the process.
+pub type ProcessObserver = fn(Pid, ProcessEvent);
+
+static OBSERVERS: RwLock<Vec<ProcessObserver>> = RwLock::new(Vec::new());
+static HAS_OBSERVERS: AtomicBool = AtomicBool::new(false);
+
+/// Call `observer` on every later process event.
+pub fn register_process_observer(observer: ProcessObserver) {
+    OBSERVERS.write().push(observer);
+    HAS_OBSERVERS.store(true, Ordering::Release);
+}
+
+/// Report `event` of the process `pid` to the observers.
+pub fn notify_process_event(pid: Pid, event: ProcessEvent) {
+    if !HAS_OBSERVERS.load(Ordering::Acquire) {
+        return;
+    }
+    for observer in OBSERVERS.read().iter() {
+        observer(pid, event);
+    }
+}
+
+#[cfg(feature = "kernel-tests")]
+pub use self::query::*;
+
+#[cfg(feature = "kernel-tests")]
+mod query {
+    use axerrno::LinuxResult;
+    use axprocess::Pid;
+
+    use crate::task::{get_process, processes};
+
+    /// The number of processes, including zombies.
+    pub fn process_count() -> usize {
+        processes().len()
+    }
+
+    /// The number of zombies, which are not reaped yet.
+    pub fn zombie_count() -> usize {
+        processes().iter().filter(|proc| proc.is_zombie()).count()
+    }
+
+    /// The number of live threads of the process `pid`.
+    pub fn thread_count(pid: Pid) -> LinuxResult<usize> {
+        Ok(get_process(pid)?.threads().len())
+    }
+}
+
+/// Check that a process reports its creation, becoming a zombie and being
+/// reaped, in that order and once each, and that the queries count it, and
+/// that the children of a process exiting are reparented to init.
+///
+/// Run once the init process is made. Panics on the first check which
+/// fails.
+#[cfg(feature = "kernel-tests")]
+pub fn self_test() {
+    use axprocess::init_proc;
+
+    use crate::{
+        exit::{make_zombie, reap},
+        task::add_thread_to_table,
+    };
+
+    // No task gets this id, as the ids of tasks count up from 1, and the
+    // one of the test of `crate::pressure` is above.
+    const PID: Pid = u32::MAX - 1;
+    static EVENTS: RwLock<Vec<ProcessEvent>> = RwLock::new(Vec::new());
+
+    fn record(pid: Pid, event: ProcessEvent) {
+        if pid == PID {
+            EVENTS.write().push(event);
+        }
+    }
+
+    register_process_observer(record);
+    let processes = process_count();
+    let zombies = zombie_count();
+
+    let child = init_proc().fork(PID).build();
+    let thread = child.new_thread(PID).build();
+    add_thread_to_table(&thread);
+    assert_eq!(*EVENTS.read(), [ProcessEvent::Created]);
+    assert_eq!(process_count(), processes + 1);
+    assert_eq!(thread_count(PID), Ok(1));
+
+    // Another thread of the process is not another process.
+    let other = child.new_thread(PID - 1).build();
+    add_thread_to_table(&other);
+    assert_eq!(EVENTS.read().len(), 1);
+    assert_eq!(thread_count(PID), Ok(2));
+    other.exit(0);
+
+    assert!(thread.exit(0));
+    make_zombie(&child);
+    assert_eq!(zombie_count(), zombies + 1);
+    reap(&child);
+    assert_eq!(
+        *EVENTS.read(),
+        [
+            ProcessEvent::Created,
+            ProcessEvent::Zombie,
+            ProcessEvent::Reaped
+        ]
+    );
+    assert_eq!(zombie_count(), zombies);
+    drop((thread, other, child));
+    assert_eq!(process_count(), processes);
+
+    // The orphan goes to init, and is counted until reaped from there.
+    const PARENT: Pid = PID - 2;
+    const ORPHAN: Pid = PID - 3;
+    let parent = init_proc().fork(PARENT).build();
+    let parent_thread = parent.new_thread(PARENT).build();
+    add_thread_to_table(&parent_thread);
+    let orphan = parent.fork(ORPHAN).build();
+    let orphan_thread = orphan.new_thread(ORPHAN).build();
+    add_thread_to_table(&orphan_thread);
+    assert_eq!(process_count(), processes + 2);
+
+    assert!(parent_thread.exit(0));
+    make_zombie(&parent);
+    assert_eq!(orphan.parent().map(|it| it.pid()), Some(init_proc().pid()));
+    assert!(init_proc().children().iter().any(|it| it.pid() == ORPHAN));
+    reap(&parent);
+    assert_eq!(zombie_count(), zombies);
+    assert_eq!(thread_count(ORPHAN), Ok(1));
+
+    assert!(orphan_thread.exit(0));
+    make_zombie(&orphan);
+    assert_eq!(zombie_count(), zombies + 1);
+    reap(&orphan);
+    drop((parent_thread, orphan_thread, parent, orphan));
+    assert_eq!(zombie_count(), zombies);
+    assert_eq!(process_count(), processes);
+    info!("process observer self test passed");
+}
@@ -0,0 +1,176 @@
+//! The tables of threads, processes, process groups and sessions by ID,
+//! which hold them weakly, so that each goes once its last owner drops it.
+//!
+//! The entry of one gone stays behind until scrubbed. A table scrubs itself
+//! on insert once it has taken [`SCRUB_INTERVAL`] inserts since the last
+//! scrub, or as many as it had live entries then if more, which keeps the
+//! cost amortized to a constant per insert. [`crate::task`] also scrubs
+//! them all from low-priority work after processes are reaped, so that a
+//! table nobody inserts into does not keep the dead either.
+//!
+//! Scrubbing cannot lose a live entry to a concurrent insert: it runs under
+//! the write lock of the table like inserts do, an insert replaces a dead
+//! entry of the same ID outright, and a dead entry never comes back to life.
+
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+};
+
+use axprocess::Pid;
+
+/// The fewest inserts between two scrubs.
+pub const SCRUB_INTERVAL: usize = 64;
+
+/// A table of `T` by ID.
+pub struct PidTable<T> {
+    entries: BTreeMap<Pid, Weak<T>>,
+    /// Inserts since the last scrub.
+    inserts: usize,
+    /// The entries the last scrub left.
+    live_at_scrub: usize,
+    /// The dead entries scrubbed so far.
+    scrubbed: u64,
+}
+
+/// The sizes of a [`PidTable`], for `/proc/starry/pressure`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TableStats {
+    /// Entries whose `T` is still there.
+    pub live: usize,
+    /// Entries whose `T` is gone, not scrubbed yet.
+    pub dead: usize,
+    /// The dead entries scrubbed so far.
+    pub scrubbed: u64,
+}
+
+impl<T> PidTable<T> {
+    /// An empty table.
+    pub const fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            inserts: 0,
+            live_at_scrub: 0,
+            scrubbed: 0,
+        }
+    }
+
+    /// Insert `value` under `pid`, replacing what was there, and scrub the
+    /// table if it is due.
+    pub fn insert(&mut self, pid: Pid, value: &Arc<T>) {
+        self.entries.insert(pid, Arc::downgrade(value));
+        self.inserts += 1;
+        if self.inserts >= SCRUB_INTERVAL.max(self.live_at_scrub) {
+            self.scrub();
+        }
+    }
+
+    /// The `T` under `pid`, if still there.
+    pub fn get(&self, pid: Pid) -> Option<Arc<T>> {
+        self.entries.get(&pid)?.upgrade()
+    }
+
+    /// Whether a live `T` is under `pid`.
+    pub fn contains_key(&self, pid: Pid) -> bool {
+        self.entries
+            .get(&pid)
+            .is_some_and(|entry| entry.strong_count() > 0)
+    }
+
+    /// Whether there is an entry under `pid`, live or dead.
+    pub fn has_entry(&self, pid: Pid) -> bool {
+        self.entries.contains_key(&pid)
+    }
+
+    /// The live `T`s, by ID.
+    pub fn values(&self) -> impl Iterator<Item = Arc<T>> + '_ {
+        self.entries.values().filter_map(Weak::upgrade)
+    }
+
+    /// Remove the dead entries, returning how many.
+    pub fn scrub(&mut self) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| entry.strong_count() > 0);
+        let removed = before - self.entries.len();
+        self.scrubbed += removed as u64;
+        self.live_at_scrub = self.entries.len();
+        self.inserts = 0;
+        removed
+    }
+
+    /// The sizes of the table, which counts the dead entries one by one.
+    pub fn stats(&self) -> TableStats {
+        let live = self
+            .entries
+            .values()
+            .filter(|entry| entry.strong_count() > 0)
+            .count();
+        TableStats {
+            live,
+            dead: self.entries.len() - live,
+            scrubbed: self.scrubbed,
+        }
+    }
+}
+
+impl<T> Default for PidTable<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Check that dead entries are scrubbed on insert no later than the
+/// interval, and that live ones and replaced IDs survive.
+///
+/// Panics on the first check which fails.
+#[cfg(feature = "kernel-tests")]
+pub fn self_test() {
+    use alloc::vec::Vec;
+
+    let mut table = PidTable::new();
+    let kept = Arc::new(0);
+    table.insert(1, &kept);
+    for pid in 2..SCRUB_INTERVAL as Pid {
+        table.insert(pid, &Arc::new(pid));
+    }
+    let stats = table.stats();
+    assert_eq!((stats.live, stats.dead), (1, SCRUB_INTERVAL - 2));
+    assert!(table.has_entry(2) && !table.contains_key(2));
+    assert!(table.get(2).is_none());
+
+    // The insert which makes the interval scrubs.
+    let last = Arc::new(0);
+    table.insert(SCRUB_INTERVAL as Pid, &last);
+    let stats = table.stats();
+    assert_eq!(stats.dead, 0);
+    assert_eq!(stats.scrubbed, SCRUB_INTERVAL as u64 - 2);
+    assert!(!table.has_entry(2));
+    assert!(Arc::ptr_eq(&table.get(1).unwrap(), &kept));
+
+    // A dead entry replaced by a live one under the same ID is not scrubbed.
+    let first = Arc::new(0);
+    table.insert(2, &first);
+    drop(first);
+    let second = Arc::new(0);
+    table.insert(2, &second);
+    assert_eq!(table.scrub(), 0);
+    assert!(Arc::ptr_eq(&table.get(2).unwrap(), &second));
+
+    // With more live entries than the interval, scrubs come after as many
+    // inserts as there are.
+    let live: Vec<_> = (0..SCRUB_INTERVAL as Pid * 2).map(Arc::new).collect();
+    for (pid, value) in (1000..).zip(&live) {
+        table.insert(pid, value);
+    }
+    table.scrub();
+    let live_count = table.stats().live;
+    assert!(live_count > SCRUB_INTERVAL);
+    for pid in 1..live_count as Pid {
+        table.insert(5000 + pid, &Arc::new(0));
+    }
+    assert_eq!(table.stats().dead, live_count - 1);
+    table.insert(5000, &last);
+    assert_eq!(table.stats().dead, 0);
+    assert_eq!(table.values().count(), live_count + 1);
+    info!("pid table self test passed");
+}
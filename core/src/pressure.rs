@@ -0,0 +1,236 @@
+//! What user space leaves pending in the kernel: pipes and the bytes
+//! buffered in them, bytes buffered in sockets, zombies not reaped yet,
+//! signals not taken yet and threads waiting on futexes, as
+//! `/proc/starry/pressure` reports it.
+//!
+//! Each is counted with a relaxed atomic where what it counts is made and
+//! dropped, except pending signals: they are queued and taken inside
+//! `axsignal`, so they are summed over the processes when asked for.
+//!
+//! Each has a soft limit, 0 for none. Going over it logs a warning, at most
+//! once every [`WARN_INTERVAL_NANOS`] for each, to point at a leak before it
+//! ends in running out of memory or in a hang. Nothing fails for it.
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use axhal::time::{NANOS_PER_SEC, monotonic_time_nanos};
+use axprocess::Process;
+use axsignal::{SignalSet, Signo};
+
+use crate::task::{ProcessData, ThreadData, processes};
+
+/// What is counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pressure {
+    /// Pipes with either end open.
+    Pipes,
+    /// Bytes written to pipes and not read yet.
+    PipeBytes,
+    /// Bytes sent over Unix sockets and not received yet. What the network
+    /// stack buffers is not counted.
+    SocketBytes,
+    /// Processes which exited and were not waited for yet.
+    Zombies,
+    /// Signals sent and not taken yet, each pending standard signal once
+    /// and each queued realtime one on its own.
+    PendingSignals,
+    /// Threads waiting on futexes.
+    FutexWaiters,
+}
+
+impl Pressure {
+    /// All of them, in the order `/proc/starry/pressure` lists them.
+    pub const ALL: [Self; 6] = [
+        Self::Pipes,
+        Self::PipeBytes,
+        Self::SocketBytes,
+        Self::Zombies,
+        Self::PendingSignals,
+        Self::FutexWaiters,
+    ];
+
+    /// The name in `/proc/starry/pressure`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Pipes => "pipes",
+            Self::PipeBytes => "pipe_bytes",
+            Self::SocketBytes => "socket_bytes",
+            Self::Zombies => "zombies",
+            Self::PendingSignals => "pending_signals",
+            Self::FutexWaiters => "futex_waiters",
+        }
+    }
+
+    /// The one named `name`, see [`Pressure::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|it| it.name() == name)
+    }
+}
+
+/// The least time between two warnings for the same soft limit.
+pub const WARN_INTERVAL_NANOS: u64 = 10 * NANOS_PER_SEC;
+
+static COUNTS: [AtomicUsize; Pressure::ALL.len()] =
+    [const { AtomicUsize::new(0) }; Pressure::ALL.len()];
+
+/// The soft limits, in the order of [`Pressure::ALL`].
+static SOFT_LIMITS: [AtomicUsize; Pressure::ALL.len()] = [
+    AtomicUsize::new(1024),
+    AtomicUsize::new(16 << 20),
+    AtomicUsize::new(16 << 20),
+    AtomicUsize::new(256),
+    AtomicUsize::new(4096),
+    AtomicUsize::new(1024),
+];
+
+/// When each soft limit was last warned about, or 0 if never.
+static LAST_WARNED: [AtomicU64; Pressure::ALL.len()] =
+    [const { AtomicU64::new(0) }; Pressure::ALL.len()];
+
+/// Count `n` more of `kind`, warning if it is now over its soft limit.
+///
+/// [`Pressure::PendingSignals`] is not counted this way, see
+/// [`check_pending_signals`].
+pub fn add(kind: Pressure, n: usize) {
+    if n == 0 {
+        return;
+    }
+    let count = COUNTS[kind as usize].fetch_add(n, Ordering::Relaxed) + n;
+    if !quiet(kind) {
+        warn_if_over(kind, count);
+    }
+}
+
+/// Count `n` fewer of `kind`.
+pub fn sub(kind: Pressure, n: usize) {
+    if n > 0 {
+        COUNTS[kind as usize].fetch_sub(n, Ordering::Relaxed);
+    }
+}
+
+/// How many of `kind` there are.
+pub fn count(kind: Pressure) -> usize {
+    match kind {
+        Pressure::PendingSignals => processes()
+            .iter()
+            .map(|proc| pending_signals_of(proc))
+            .sum(),
+        _ => COUNTS[kind as usize].load(Ordering::Relaxed),
+    }
+}
+
+/// The soft limit of `kind`, 0 for none.
+pub fn soft_limit(kind: Pressure) -> usize {
+    SOFT_LIMITS[kind as usize].load(Ordering::Relaxed)
+}
+
+/// Set the soft limit of `kind`, 0 for none.
+pub fn set_soft_limit(kind: Pressure, limit: usize) {
+    SOFT_LIMITS[kind as usize].store(limit, Ordering::Relaxed);
+}
+
+/// Warn if the signals pending in all processes are over their soft limit,
+/// once a signal was sent.
+///
+/// This sums them over the processes, unless there is no soft limit or it
+/// was warned about lately, so it is only for when a signal was sent.
+pub fn check_pending_signals() {
+    if !quiet(Pressure::PendingSignals) {
+        warn_if_over(Pressure::PendingSignals, count(Pressure::PendingSignals));
+    }
+}
+
+/// The signals pending in `proc`, in its queue or those of its threads.
+pub fn pending_signals_of(proc: &Process) -> usize {
+    let Some(data) = proc.data::<ProcessData>() else {
+        return 0;
+    };
+    let pending = proc
+        .threads()
+        .iter()
+        .filter_map(|thr| thr.data::<ThreadData>())
+        .fold(SignalSet::default(), |set, thr| set | thr.signal.pending());
+    let standard = (1..Signo::SIGRTMIN as u8)
+        .filter_map(Signo::from_repr)
+        .filter(|signo| pending.has(*signo))
+        .count();
+    standard + data.queued_rt_signals.load(Ordering::Relaxed)
+}
+
+/// Whether there is no soft limit for `kind`, or it was warned about less
+/// than [`WARN_INTERVAL_NANOS`] ago.
+fn quiet(kind: Pressure) -> bool {
+    if soft_limit(kind) == 0 {
+        return true;
+    }
+    let last = LAST_WARNED[kind as usize].load(Ordering::Relaxed);
+    last != 0 && monotonic_time_nanos() - last < WARN_INTERVAL_NANOS
+}
+
+fn warn_if_over(kind: Pressure, count: usize) {
+    let limit = soft_limit(kind);
+    if limit == 0 || count <= limit {
+        return;
+    }
+    let last = LAST_WARNED[kind as usize].load(Ordering::Relaxed);
+    let now = monotonic_time_nanos().max(1);
+    // Only the one which moves the time on warns.
+    if LAST_WARNED[kind as usize]
+        .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+        .is_ok()
+    {
+        warn!(
+            "{} at {}, over the soft limit of {}, see /proc/starry/pressure",
+            kind.name(),
+            count,
+            limit
+        );
+    }
+}
+
+/// Check that making and reaping a zombie, and waiting on a futex, count
+/// up and back down.
+///
+/// Run once the init process is made.
+#[cfg(feature = "kernel-tests")]
+pub fn self_test() {
+    use alloc::sync::Arc;
+    use core::time::Duration;
+
+    use axprocess::init_proc;
+    use axsync::Mutex;
+
+    use crate::{
+        exit::{make_zombie, reap},
+        futex::{FUTEX_TABLE, FutexKey},
+        mm::new_user_aspace_empty,
+        task::WaitMode,
+    };
+
+    let zombies = count(Pressure::Zombies);
+    // No task gets this id, as the ids of tasks count up from 1.
+    let child = init_proc().fork(u32::MAX).build();
+    make_zombie(&child);
+    assert_eq!(count(Pressure::Zombies), zombies + 1);
+    reap(&child);
+    assert_eq!(count(Pressure::Zombies), zombies);
+
+    let waiters = count(Pressure::FutexWaiters);
+    let aspace = Arc::new(Mutex::new(new_user_aspace_empty().unwrap()));
+    let key = FutexKey::new(&aspace, 0x1000);
+    let waiter = axtask::spawn(move || {
+        FUTEX_TABLE
+            .get_or_insert(key)
+            .futex_wait(WaitMode::Uninterruptible, Some(Duration::from_secs(1)));
+    });
+    while count(Pressure::FutexWaiters) == waiters {
+        axtask::yield_now();
+    }
+    assert_eq!(count(Pressure::FutexWaiters), waiters + 1);
+    if let Some(wq) = FUTEX_TABLE.get(key) {
+        wq.notify_one(false);
+    }
+    waiter.join();
+    assert_eq!(count(Pressure::FutexWaiters), waiters);
+    info!("pressure self test passed");
+}
@@ -0,0 +1,286 @@
+//! The kernel random number generator, behind `getrandom`, `/dev/urandom`
+//! and the `AT_RANDOM` bytes of new programs.
+//!
+//! It is ChaCha20 under a base key, which is seeded from what entropy there
+//! is: the cycle counter read across a run of timer reads, the time of day,
+//! and the timings of the IRQs handled so far, see [`axhal::irq`]. Each CPU
+//! derives a key of its own from the base and draws from its own stream, so
+//! CPUs do not contend, and replaces its key with output of the stream after
+//! each draw, so that what was drawn cannot be worked out from the state
+//! afterwards. Every [`RESEED_INTERVAL`], the base key is mixed with fresh
+//! timings, and the streams derive new keys on their next draw.
+//!
+//! [`init`] seeds it at boot, but it works before that too, as the first
+//! draw seeds it. Nothing parses the device tree, so its `rng-seed` is left
+//! out. Under QEMU without KVM, the timings may well be the same on every
+//! boot, in which case [`init`] warns that the numbers are predictable.
+
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use axhal::{
+    cpu::this_cpu_id,
+    time::{current_ticks, monotonic_time_nanos, wall_time_nanos},
+};
+use axsync::spin::SpinNoPreempt;
+
+/// How often the base key is mixed with fresh entropy.
+pub const RESEED_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How many times the cycle counter is read when gathering entropy.
+const TICK_SAMPLES: usize = 64;
+
+/// The nonce of the base stream, which the CPU streams derive keys from.
+/// Those of the CPUs are their indices.
+const BASE_NONCE: u64 = u64::MAX;
+
+/// The counter of the base stream used to mix entropy into its key, which
+/// key derivations never reach.
+const RESEED_COUNTER: u64 = u64::MAX;
+
+/// How many bytes are drawn from a stream at a time, so that large draws
+/// let other tasks in between.
+const DRAW_CHUNK: usize = 256;
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+/// The ChaCha20 block at `counter` in the stream `nonce` under `key`, with
+/// the 64-bit counter and nonce of the original design.
+fn chacha20_block(key: &[u32; 8], counter: u64, nonce: u64) -> [u8; 64] {
+    let mut init = [0u32; 16];
+    // "expand 32-byte k"
+    init[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+    init[4..12].copy_from_slice(key);
+    init[12] = counter as u32;
+    init[13] = (counter >> 32) as u32;
+    init[14] = nonce as u32;
+    init[15] = (nonce >> 32) as u32;
+    let mut s = init;
+    for _ in 0..10 {
+        quarter_round(&mut s, 0, 4, 8, 12);
+        quarter_round(&mut s, 1, 5, 9, 13);
+        quarter_round(&mut s, 2, 6, 10, 14);
+        quarter_round(&mut s, 3, 7, 11, 15);
+        quarter_round(&mut s, 0, 5, 10, 15);
+        quarter_round(&mut s, 1, 6, 11, 12);
+        quarter_round(&mut s, 2, 7, 8, 13);
+        quarter_round(&mut s, 3, 4, 9, 14);
+    }
+    let mut out = [0; 64];
+    for (i, word) in out.chunks_exact_mut(4).enumerate() {
+        word.copy_from_slice(&s[i].wrapping_add(init[i]).to_le_bytes());
+    }
+    out
+}
+
+/// A key made of the first half of `block`.
+fn key_of(block: &[u8; 64]) -> [u32; 8] {
+    core::array::from_fn(|i| u32::from_le_bytes(block[i * 4..i * 4 + 4].try_into().unwrap()))
+}
+
+/// The key the CPU streams derive theirs from.
+struct Base {
+    key: [u32; 8],
+    /// The next block of the base stream to derive a key from.
+    counter: u64,
+}
+
+static BASE: SpinNoPreempt<Base> = SpinNoPreempt::new(Base {
+    key: [0; 8],
+    counter: 0,
+});
+
+/// How many times the base key was seeded, for the streams to tell their
+/// keys are stale. Only changed with [`BASE`] locked.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// When the base key was last seeded, in nanoseconds since boot.
+static LAST_SEEDED: AtomicU64 = AtomicU64::new(0);
+
+/// Entropy from the cycle counter read across timer reads, the time of day
+/// and the IRQ timings, with how many different intervals the reads were
+/// apart.
+fn gather() -> ([u32; 8], usize) {
+    let mut pool = [0u64; 4];
+    let mut deltas = [0u64; TICK_SAMPLES];
+    let mut last = current_ticks();
+    for (i, delta) in deltas.iter_mut().enumerate() {
+        // A little work of varying length between the reads, for their
+        // intervals to depend on the caches and the bus.
+        for _ in 0..i % 7 {
+            core::hint::black_box(monotonic_time_nanos());
+        }
+        let now = current_ticks();
+        *delta = now.wrapping_sub(last);
+        last = now;
+        pool[i % 4] = pool[i % 4].rotate_left(13) ^ now;
+    }
+    pool[0] ^= wall_time_nanos();
+    pool[1] ^= axhal::irq::irq_jitter();
+
+    deltas.sort_unstable();
+    let distinct = 1 + deltas.windows(2).filter(|w| w[0] != w[1]).count();
+    let entropy = core::array::from_fn(|i| (pool[i / 2] >> (i % 2 * 32)) as u32);
+    (entropy, distinct)
+}
+
+/// Mix `entropy` into the base key.
+fn seed(base: &mut Base, entropy: [u32; 8]) {
+    for (word, extra) in base.key.iter_mut().zip(entropy) {
+        *word ^= extra;
+    }
+    base.key = key_of(&chacha20_block(&base.key, RESEED_COUNTER, BASE_NONCE));
+    base.counter = 0;
+    GENERATION.fetch_add(1, Ordering::Release);
+    LAST_SEEDED.store(monotonic_time_nanos(), Ordering::Relaxed);
+}
+
+/// Mix fresh entropy into the base key if it is due.
+fn reseed_if_due() {
+    let last = LAST_SEEDED.load(Ordering::Relaxed);
+    let now = monotonic_time_nanos();
+    if GENERATION.load(Ordering::Acquire) != 0
+        && now.saturating_sub(last) < RESEED_INTERVAL.as_nanos() as u64
+    {
+        return;
+    }
+    // Only one CPU gathers the entropy.
+    if LAST_SEEDED
+        .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+        .is_err()
+    {
+        return;
+    }
+    let (entropy, _) = gather();
+    seed(&mut BASE.lock(), entropy);
+}
+
+/// A key derived from the base key, with the generation of the base key.
+fn derive_key() -> ([u32; 8], u64) {
+    let mut base = BASE.lock();
+    if GENERATION.load(Ordering::Relaxed) == 0 {
+        // A draw before `init`, which another CPU may be gathering the
+        // entropy for, but no draw is made from an unseeded key.
+        seed(&mut base, gather().0);
+    }
+    let block = chacha20_block(&base.key, base.counter, BASE_NONCE);
+    base.counter += 1;
+    (key_of(&block), GENERATION.load(Ordering::Relaxed))
+}
+
+/// The output stream of a CPU.
+struct Stream {
+    key: [u32; 8],
+    counter: u64,
+    /// The generation of the base key that `key` was derived from.
+    generation: u64,
+}
+
+impl Stream {
+    const fn new() -> Self {
+        Self {
+            key: [0; 8],
+            counter: 0,
+            generation: 0,
+        }
+    }
+
+    /// Fill `buf` from the stream `nonce`, then replace the key.
+    fn draw(&mut self, nonce: u64, buf: &mut [u8]) {
+        if self.generation != GENERATION.load(Ordering::Acquire) {
+            (self.key, self.generation) = derive_key();
+            self.counter = 0;
+        }
+        for chunk in buf.chunks_mut(64) {
+            let block = chacha20_block(&self.key, self.counter, nonce);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+            self.counter += 1;
+        }
+        self.key = key_of(&chacha20_block(&self.key, self.counter, nonce));
+        self.counter = 0;
+    }
+}
+
+static STREAMS: [SpinNoPreempt<Stream>; axconfig::SMP] =
+    [const { SpinNoPreempt::new(Stream::new()) }; axconfig::SMP];
+
+/// Seed the generator at boot, warning if there is little entropy to seed
+/// it with.
+pub fn init() {
+    let (entropy, distinct) = gather();
+    seed(&mut BASE.lock(), entropy);
+    if distinct < 4 {
+        warn!(
+            "The cycle counter read the same {} ways every time, the random numbers are predictable",
+            distinct
+        );
+    } else {
+        info!("Random number generator seeded");
+    }
+}
+
+/// Fill `buf` with random bytes, fit for cryptography as far as the
+/// entropy at hand goes. It never blocks, and never fails.
+pub fn random_bytes(buf: &mut [u8]) {
+    reseed_if_due();
+    for chunk in buf.chunks_mut(DRAW_CHUNK) {
+        // A task moved to another CPU meanwhile draws from the stream it
+        // locked, which is all the same.
+        let cpu = this_cpu_id();
+        STREAMS[cpu].lock().draw(cpu as u64, chunk);
+    }
+}
+
+/// A random `u64`, see [`random_bytes`].
+pub fn random_u64() -> u64 {
+    let mut buf = [0; 8];
+    random_bytes(&mut buf);
+    u64::from_ne_bytes(buf)
+}
+
+/// Check the block function against the test vector of RFC 8439, that
+/// draws do not repeat, and that the streams of two CPUs differ.
+#[cfg(feature = "kernel-tests")]
+pub fn self_test() {
+    use alloc::collections::btree_set::BTreeSet;
+
+    // RFC 8439 2.3.2, where the 32-bit counter and the first word of the
+    // 96-bit nonce make up the 64-bit counter.
+    let key =
+        core::array::from_fn(|i| u32::from_le_bytes(core::array::from_fn(|j| (i * 4 + j) as u8)));
+    let block = chacha20_block(&key, 1 | (0x0900_0000 << 32), 0x4a00_0000);
+    assert_eq!(
+        block[..16],
+        [
+            0x10, 0xf1, 0xe7, 0xe4, 0xd1, 0x3b, 0x59, 0x15, 0x50, 0x0f, 0xdd, 0x1f, 0xa3, 0x20,
+            0x71, 0xc4
+        ]
+    );
+    assert_eq!(block[60..], [0xa2, 0x50, 0x3c, 0x4e]);
+
+    let mut seen = BTreeSet::new();
+    for _ in 0..10_000 {
+        let mut buf = [0u8; 16];
+        random_bytes(&mut buf);
+        assert!(seen.insert(buf), "repeated random block {:02x?}", buf);
+    }
+
+    let mut outputs = [[0u8; 64]; 2];
+    for (cpu, out) in outputs.iter_mut().enumerate() {
+        Stream::new().draw(cpu as u64, out);
+    }
+    assert_ne!(outputs[0], outputs[1]);
+    assert_ne!(random_u64(), random_u64());
+    info!("random number self test passed");
+}
@@ -0,0 +1,173 @@
+//! Per-process resource limits, see `getrlimit(2)`.
+//!
+//! Limits are inherited across fork and kept across `execve`. Only a few of
+//! them are enforced, by the code using the resource.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axsignal::Signo;
+
+/// CPU time in seconds.
+pub const RLIMIT_CPU: u32 = 0;
+/// The size of a file.
+pub const RLIMIT_FSIZE: u32 = 1;
+/// The size of the data segment.
+pub const RLIMIT_DATA: u32 = 2;
+/// The size of the main thread stack.
+pub const RLIMIT_STACK: u32 = 3;
+/// The size of a core dump.
+pub const RLIMIT_CORE: u32 = 4;
+/// The resident set size.
+pub const RLIMIT_RSS: u32 = 5;
+/// The number of processes.
+pub const RLIMIT_NPROC: u32 = 6;
+/// One more than the largest file descriptor number.
+pub const RLIMIT_NOFILE: u32 = 7;
+/// The size of locked memory.
+pub const RLIMIT_MEMLOCK: u32 = 8;
+/// The size of the address space.
+pub const RLIMIT_AS: u32 = 9;
+/// The number of queued signals.
+pub const RLIMIT_SIGPENDING: u32 = 11;
+/// The bytes of POSIX message queues.
+pub const RLIMIT_MSGQUEUE: u32 = 12;
+
+/// The number of resources.
+pub const RLIM_NLIMITS: usize = 16;
+
+/// No limit.
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+/// The default soft limit of file descriptors, which can be set at build
+/// time with the `AX_FILE_LIMIT` environment variable.
+pub const AX_FILE_LIMIT: u64 = match option_env!("AX_FILE_LIMIT") {
+    Some(limit) => parse_limit(limit),
+    None => 1024,
+};
+
+/// The largest hard limit of file descriptors, like
+/// `/proc/sys/fs/nr_open`.
+pub const NR_OPEN: u64 = 1 << 20;
+
+const fn parse_limit(s: &str) -> u64 {
+    let bytes = s.as_bytes();
+    assert!(!bytes.is_empty(), "AX_FILE_LIMIT is empty");
+    let mut value = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        assert!(bytes[i].is_ascii_digit(), "AX_FILE_LIMIT is not a number");
+        value = value * 10 + (bytes[i] - b'0') as u64;
+        i += 1;
+    }
+    assert!(value <= NR_OPEN, "AX_FILE_LIMIT is above NR_OPEN");
+    value
+}
+
+/// The soft and hard limit of a resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rlimit {
+    /// The limit enforced.
+    pub cur: u64,
+    /// The ceiling of `cur`, which only privileged processes can raise.
+    pub max: u64,
+}
+
+impl Rlimit {
+    const fn new(cur: u64, max: u64) -> Self {
+        Self { cur, max }
+    }
+}
+
+/// The resource limits of a process.
+#[derive(Debug, Clone)]
+pub struct Rlimits([Rlimit; RLIM_NLIMITS]);
+
+impl Default for Rlimits {
+    /// The limits of the init process, like the defaults of Linux.
+    fn default() -> Self {
+        let mut limits = [Rlimit::new(RLIM_INFINITY, RLIM_INFINITY); RLIM_NLIMITS];
+        limits[RLIMIT_STACK as usize].cur = 8 << 20;
+        limits[RLIMIT_CORE as usize].cur = 0;
+        limits[RLIMIT_NOFILE as usize] = Rlimit::new(AX_FILE_LIMIT, AX_FILE_LIMIT.max(4096));
+        limits[RLIMIT_MEMLOCK as usize] = Rlimit::new(8 << 20, 8 << 20);
+        limits[RLIMIT_SIGPENDING as usize] = Rlimit::new(4096, 4096);
+        limits[RLIMIT_MSGQUEUE as usize] = Rlimit::new(819200, 819200);
+        Self(limits)
+    }
+}
+
+impl Rlimits {
+    /// Get the limit of `resource`, or `None` if it is unknown.
+    pub fn get(&self, resource: u32) -> Option<Rlimit> {
+        self.0.get(resource as usize).copied()
+    }
+
+    /// Set the limit of the known `resource`.
+    pub fn set(&mut self, resource: u32, limit: Rlimit) {
+        self.0[resource as usize] = limit;
+    }
+
+    /// Get the soft limit of file descriptors, as a count.
+    pub fn nofile(&self) -> usize {
+        self.0[RLIMIT_NOFILE as usize].cur.min(NR_OPEN) as usize
+    }
+}
+
+/// `RLIMIT_CPU` as CPU time in nanoseconds, cached apart from [`Rlimits`] so
+/// that checking it on every trap is two atomic loads.
+///
+/// Like Linux, the process gets `SIGXCPU` when it reaches the soft limit and
+/// every second after that, and `SIGKILL` when it reaches the hard limit.
+pub struct CpuLimit {
+    /// The CPU time when the next signal is due.
+    next_ns: AtomicU64,
+    /// The hard limit.
+    hard_ns: AtomicU64,
+}
+
+impl Default for CpuLimit {
+    fn default() -> Self {
+        Self {
+            next_ns: AtomicU64::new(u64::MAX),
+            hard_ns: AtomicU64::new(u64::MAX),
+        }
+    }
+}
+
+impl CpuLimit {
+    const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+    /// Take a new `RLIMIT_CPU`, counted from the start of the process.
+    pub fn set(&self, limit: Rlimit) {
+        // Like Linux, a soft limit of 0 acts as 1 second.
+        let soft_ns = limit.cur.max(1).saturating_mul(Self::NANOS_PER_SEC);
+        let hard_ns = limit.max.saturating_mul(Self::NANOS_PER_SEC);
+        self.hard_ns.store(hard_ns, Ordering::Relaxed);
+        self.next_ns.store(soft_ns.min(hard_ns), Ordering::Relaxed);
+    }
+
+    /// Check the CPU time `used_ns` of the process against the limit, and
+    /// return the signal to send, if one is due.
+    ///
+    /// Threads of the process may check at once; only one of them gets the
+    /// signal.
+    pub fn check(&self, used_ns: u64) -> Option<Signo> {
+        let next_ns = self.next_ns.load(Ordering::Relaxed);
+        if used_ns < next_ns {
+            return None;
+        }
+        let hard_ns = self.hard_ns.load(Ordering::Relaxed);
+        let (after, signo) = if used_ns >= hard_ns {
+            (u64::MAX, Signo::SIGKILL)
+        } else {
+            (
+                used_ns.saturating_add(Self::NANOS_PER_SEC).min(hard_ns),
+                Signo::SIGXCPU,
+            )
+        };
+        self.next_ns
+            .compare_exchange(next_ns, after, Ordering::Relaxed, Ordering::Relaxed)
+            .ok()
+            .map(|_| signo)
+    }
+}
@@ -0,0 +1,164 @@
+//! Per-process path sandboxes, a simpler take on Landlock.
+//!
+//! A [`Ruleset`] grants some [`PathAccess`] to the paths under each of a set
+//! of prefixes, and denies every other access. Rulesets are stacked in a
+//! [`Sandbox`] and can never be removed, so installing another ruleset can
+//! only make the process more confined, like the syscall filters of
+//! [`crate::seccomp`].
+//!
+//! Rules match the canonical path a syscall resolved, not the path it was
+//! given, so neither `..` nor a directory opened before the ruleset was
+//! installed leads out of the prefixes.
+
+use core::fmt::{self, Write};
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+
+/// The most rules a ruleset may have.
+pub const MAX_RULES: usize = 64;
+/// The most rulesets a sandbox may stack, like the layers of Landlock.
+pub const MAX_RULESETS: usize = 16;
+
+bitflags::bitflags! {
+    /// What a syscall does with a path.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PathAccess: u32 {
+        /// Open for reading, list, look up or watch.
+        const READ = 1 << 0;
+        /// Open for writing, change the metadata of, remove, or mount on.
+        const WRITE = 1 << 1;
+        /// Make a new file, directory or link at.
+        const CREATE = 1 << 2;
+        /// Run as a program.
+        const EXEC = 1 << 3;
+    }
+}
+
+impl fmt::Display for PathAccess {
+    /// Show the access like `ls` shows modes, e.g. `r-c-`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (flag, letter) in [
+            (Self::READ, 'r'),
+            (Self::WRITE, 'w'),
+            (Self::CREATE, 'c'),
+            (Self::EXEC, 'x'),
+        ] {
+            f.write_char(if self.contains(flag) { letter } else { '-' })?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether the absolute `path` is `prefix` or under it.
+fn is_under(path: &str, prefix: &str) -> bool {
+    let path = path.trim_end_matches('/');
+    path.strip_prefix(prefix.trim_end_matches('/'))
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// The access granted to the paths under a prefix.
+#[derive(Debug, Clone)]
+pub struct PathRule {
+    prefix: String,
+    access: PathAccess,
+}
+
+impl PathRule {
+    /// Grant `access` to `prefix`, an absolute path without `.` and `..`,
+    /// and to every path under it.
+    pub fn new(prefix: String, access: PathAccess) -> Self {
+        Self { prefix, access }
+    }
+}
+
+/// A set of rules, denying whatever none of them grants.
+#[derive(Debug, Clone)]
+pub struct Ruleset(Vec<PathRule>);
+
+impl Ruleset {
+    /// Create a ruleset of `rules`, which may be empty to deny every path.
+    pub fn new(rules: Vec<PathRule>) -> Self {
+        Self(rules)
+    }
+
+    /// Whether the rules grant `access` to `path`, together if need be:
+    /// reading under one prefix and writing under a longer one grant both
+    /// under the longer one.
+    pub fn allows(&self, path: &str, access: PathAccess) -> bool {
+        let granted = self
+            .0
+            .iter()
+            .filter(|rule| is_under(path, &rule.prefix))
+            .fold(PathAccess::empty(), |granted, rule| granted | rule.access);
+        granted.contains(access)
+    }
+}
+
+/// The rulesets installed in a process.
+#[derive(Debug, Clone, Default)]
+pub struct Sandbox(Vec<Arc<Ruleset>>);
+
+impl Sandbox {
+    /// Whether no ruleset is installed.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Whether another ruleset can be installed, see [`MAX_RULESETS`].
+    pub fn is_full(&self) -> bool {
+        self.0.len() >= MAX_RULESETS
+    }
+
+    /// Install `ruleset` on top of the existing ones.
+    pub fn push(&mut self, ruleset: Ruleset) {
+        self.0.push(Arc::new(ruleset));
+    }
+
+    /// Whether every ruleset allows `access` to the canonical `path`.
+    pub fn allows(&self, path: &str, access: PathAccess) -> bool {
+        self.0.iter().all(|ruleset| ruleset.allows(path, access))
+    }
+}
+
+/// Check the matching of prefixes, and that rules add up and rulesets
+/// restrict one another.
+///
+/// Panics on the first check which fails.
+#[cfg(feature = "kernel-tests")]
+pub fn self_test() {
+    use alloc::{format, vec};
+
+    let (read, write, create) = (PathAccess::READ, PathAccess::WRITE, PathAccess::CREATE);
+    let tmp = Ruleset::new(vec![PathRule::new("/tmp".into(), read | create)]);
+    for path in ["/tmp", "/tmp/", "/tmp/a", "/tmp/a/b/"] {
+        assert!(tmp.allows(path, read | create), "{}", path);
+        assert!(!tmp.allows(path, write), "{}", path);
+    }
+    for path in ["/", "/tmpx", "/tm", "/etc/tmp"] {
+        assert!(!tmp.allows(path, read), "{}", path);
+    }
+    assert!(!Ruleset::new(vec![]).allows("/tmp", read));
+
+    let split = Ruleset::new(vec![
+        PathRule::new("/".into(), read),
+        PathRule::new("/tmp/".into(), write),
+    ]);
+    assert!(split.allows("/tmp/a", read | write));
+    assert!(split.allows("/etc", read));
+    assert!(!split.allows("/etc", write));
+
+    let mut sandbox = Sandbox::default();
+    assert!(sandbox.allows("/etc", PathAccess::all()));
+    sandbox.push(split);
+    sandbox.push(tmp);
+    assert!(sandbox.allows("/tmp/a", read));
+    assert!(!sandbox.allows("/tmp/a", write), "taken away by the second");
+    assert!(
+        !sandbox.allows("/tmp/a", create),
+        "not granted by the first"
+    );
+    assert!(!sandbox.allows("/etc", read));
+
+    assert_eq!(format!("{}", read | PathAccess::EXEC), "r--x");
+    info!("path sandbox self test passed");
+}
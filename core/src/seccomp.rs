@@ -0,0 +1,126 @@
+//! Per-process syscall filters, a lightweight take on seccomp.
+//!
+//! A [`SyscallFilter`] allows a set of syscall numbers and applies a default
+//! [`FilterAction`] to every other one. Filters are stacked in a
+//! [`FilterChain`] and can never be removed, so installing another filter
+//! can only make the process more confined. Like Linux caps the
+//! instructions of the filters of a process, at most [`MAX_FILTERS`] can be
+//! stacked.
+
+use alloc::{sync::Arc, vec::Vec};
+
+use axerrno::{LinuxError, LinuxResult};
+
+/// The number of syscalls a filter can tell apart. Larger syscall numbers
+/// always take the default action.
+pub const MAX_SYSCALLS: usize = 512;
+
+/// The most filters a process can have installed, which bounds the work of
+/// checking each syscall. It is Linux's `MAX_INSNS_PER_PATH` of 32768 over
+/// the 512 instructions a classic BPF filter telling apart
+/// [`MAX_SYSCALLS`] syscalls takes.
+pub const MAX_FILTERS: usize = 32768 / MAX_SYSCALLS;
+
+/// What to do with a syscall.
+///
+/// The variants are ordered from the most to the least permissive.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FilterAction {
+    /// Run the syscall.
+    Allow = 0,
+    /// Fail the syscall with `ENOSYS`.
+    Errno = 1,
+    /// Fail the syscall with `ENOSYS` and raise `SIGSYS`.
+    Trap = 2,
+}
+
+impl TryFrom<u32> for FilterAction {
+    type Error = ();
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Allow),
+            1 => Ok(Self::Errno),
+            2 => Ok(Self::Trap),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A set of allowed syscalls, with the action for the others.
+#[derive(Debug, Clone)]
+pub struct SyscallFilter {
+    allowed: [u64; MAX_SYSCALLS / 64],
+    default: FilterAction,
+}
+
+impl SyscallFilter {
+    /// Create a filter which applies `default` to every syscall.
+    pub const fn new(default: FilterAction) -> Self {
+        Self {
+            allowed: [0; MAX_SYSCALLS / 64],
+            default,
+        }
+    }
+
+    /// Create a filter from a bitmap of allowed syscalls, where bit `n % 8`
+    /// of byte `n / 8` stands for syscall `n`.
+    ///
+    /// Bits beyond [`MAX_SYSCALLS`] are ignored.
+    pub fn from_bitmap(bitmap: &[u8], default: FilterAction) -> Self {
+        let mut filter = Self::new(default);
+        for (i, byte) in bitmap.iter().take(MAX_SYSCALLS / 8).enumerate() {
+            filter.allowed[i / 8] |= (*byte as u64) << (i % 8 * 8);
+        }
+        filter
+    }
+
+    /// Allow the syscall `sysno`.
+    pub fn allow(&mut self, sysno: usize) {
+        if sysno < MAX_SYSCALLS {
+            self.allowed[sysno / 64] |= 1 << (sysno % 64);
+        }
+    }
+
+    /// Get the action for the syscall `sysno`.
+    pub fn action(&self, sysno: usize) -> FilterAction {
+        if sysno < MAX_SYSCALLS && self.allowed[sysno / 64] & (1 << (sysno % 64)) != 0 {
+            FilterAction::Allow
+        } else {
+            self.default
+        }
+    }
+}
+
+/// The filters installed in a process.
+#[derive(Debug, Clone, Default)]
+pub struct FilterChain(Vec<Arc<SyscallFilter>>);
+
+impl FilterChain {
+    /// Whether no filter is installed.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Install `filter` on top of the existing ones.
+    ///
+    /// Fails with `ENOMEM` if [`MAX_FILTERS`] are installed already.
+    pub fn push(&mut self, filter: SyscallFilter) -> LinuxResult {
+        if self.0.len() >= MAX_FILTERS {
+            return Err(LinuxError::ENOMEM);
+        }
+        self.0.push(Arc::new(filter));
+        Ok(())
+    }
+
+    /// Get the action for the syscall `sysno`, which is the strictest one
+    /// among all filters.
+    pub fn action(&self, sysno: usize) -> FilterAction {
+        self.0
+            .iter()
+            .map(|filter| filter.action(sysno))
+            .max()
+            .unwrap_or(FilterAction::Allow)
+    }
+}
@@ -0,0 +1,145 @@
+//! System-wide counters, as reported by `/proc/stat` and `/proc/vmstat`.
+//!
+//! Every CPU updates its own counters, on a cache line of their own, with
+//! relaxed atomics, so counting costs about as much as an uncontended add.
+//! Readers sum the counters of all CPUs, which is not a consistent snapshot,
+//! but each total only grows.
+//!
+//! Context switches, IRQs and idle time are counted the same way by
+//! `axtask` and `axhal`.
+//!
+//! This is also where "boot" is defined for everything reporting time since
+//! boot: `/proc`, `sysinfo`, `times` and `CLOCK_BOOTTIME`.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use axhal::{
+    cpu::this_cpu_id,
+    time::{NANOS_PER_SEC, monotonic_time_nanos, wall_time_nanos},
+};
+
+/// The clock ticks per second reported to user space (`USER_HZ`).
+pub const USER_HZ: u64 = 100;
+
+/// The counters of a CPU.
+#[repr(align(64))]
+struct CpuStat {
+    syscalls: AtomicU64,
+    page_faults: AtomicU64,
+    forks: AtomicU64,
+    user_ns: AtomicU64,
+    system_ns: AtomicU64,
+    io_wait_ns: AtomicU64,
+}
+
+static CPU_STATS: [CpuStat; axconfig::SMP] = [const {
+    CpuStat {
+        syscalls: AtomicU64::new(0),
+        page_faults: AtomicU64::new(0),
+        forks: AtomicU64::new(0),
+        user_ns: AtomicU64::new(0),
+        system_ns: AtomicU64::new(0),
+        io_wait_ns: AtomicU64::new(0),
+    }
+}; axconfig::SMP];
+
+fn this_cpu() -> &'static CpuStat {
+    &CPU_STATS[this_cpu_id()]
+}
+
+fn sum(counter: impl Fn(&CpuStat) -> &AtomicU64) -> u64 {
+    CPU_STATS
+        .iter()
+        .map(|stat| counter(stat).load(Ordering::Relaxed))
+        .sum()
+}
+
+/// Count a syscall serviced on this CPU.
+pub fn count_syscall() {
+    this_cpu().syscalls.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count a user page fault handled on this CPU.
+///
+/// All of them are minor faults for now, since no mapping is backed by a
+/// file which has to be read in.
+pub fn count_page_fault() {
+    this_cpu().page_faults.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count a thread created by `clone`, or the first user process.
+pub(crate) fn count_fork() {
+    this_cpu().forks.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Add the time user tasks spent on this CPU in user and kernel mode.
+pub(crate) fn add_cpu_time(user_ns: usize, system_ns: usize) {
+    let stat = this_cpu();
+    stat.user_ns.fetch_add(user_ns as u64, Ordering::Relaxed);
+    stat.system_ns
+        .fetch_add(system_ns as u64, Ordering::Relaxed);
+}
+
+/// Add the time a user task waited for a block device on this CPU, see
+/// [`crate::iowait`].
+pub(crate) fn add_io_wait(ns: u64) {
+    this_cpu().io_wait_ns.fetch_add(ns, Ordering::Relaxed);
+}
+
+/// The number of syscalls serviced since boot.
+pub fn syscalls() -> u64 {
+    sum(|stat| &stat.syscalls)
+}
+
+/// The number of user page faults handled since boot.
+pub fn page_faults() -> u64 {
+    sum(|stat| &stat.page_faults)
+}
+
+/// The number of threads created since boot.
+pub fn forks() -> u64 {
+    sum(|stat| &stat.forks)
+}
+
+/// The time user tasks spent on the CPU `cpu` in user and kernel mode, in
+/// nanoseconds.
+pub fn cpu_time(cpu: usize) -> (u64, u64) {
+    let stat = &CPU_STATS[cpu];
+    (
+        stat.user_ns.load(Ordering::Relaxed),
+        stat.system_ns.load(Ordering::Relaxed),
+    )
+}
+
+/// The time user tasks waited for a block device on the CPU `cpu`, in
+/// nanoseconds.
+///
+/// Devices are polled, so this time is also part of the system time.
+pub fn io_wait_time(cpu: usize) -> u64 {
+    CPU_STATS[cpu].io_wait_ns.load(Ordering::Relaxed)
+}
+
+/// The time since boot, in nanoseconds.
+///
+/// The system never suspends, so this is the monotonic time, and
+/// `CLOCK_BOOTTIME` is the same clock as `CLOCK_MONOTONIC`.
+pub fn uptime_nanos() -> u64 {
+    monotonic_time_nanos()
+}
+
+/// The wall clock time at boot, in nanoseconds since the epoch.
+///
+/// It moves along with the wall clock when the latter is set.
+pub fn boot_time_nanos() -> u64 {
+    wall_time_nanos().saturating_sub(uptime_nanos())
+}
+
+/// The time all CPUs spent idle since boot, in nanoseconds.
+pub fn idle_time_nanos() -> u64 {
+    axtask::idle_time_nanos()
+}
+
+/// Convert `nanos` to clock ticks of [`USER_HZ`].
+pub fn nanos_to_user_ticks(nanos: u64) -> u64 {
+    nanos / (NANOS_PER_SEC / USER_HZ)
+}
@@ -3,7 +3,8 @@
 use core::{
     alloc::Layout,
     cell::RefCell,
-    sync::atomic::{AtomicUsize, Ordering},
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicI32, AtomicU8, AtomicU64, AtomicUsize, Ordering},
     time::Duration,
 };
 
@@ -14,23 +15,40 @@ use alloc::{
 };
 use axerrno::{LinuxError, LinuxResult};
 use axhal::{
-    arch::UspaceContext,
-    time::{NANOS_PER_MICROS, NANOS_PER_SEC, monotonic_time_nanos},
+    arch::{TrapFrame, UspaceContext},
+    time::monotonic_time_nanos,
 };
 use axmm::{AddrSpace, kernel_aspace};
 use axns::{AxNamespace, AxNamespaceIf};
 use axprocess::{Pid, Process, ProcessGroup, Session, Thread};
 use axsignal::{
-    Signo,
+    SignalSet, Signo,
     api::{ProcessSignalManager, SignalActions, ThreadSignalManager},
 };
-use axsync::{Mutex, RawMutex};
-use axtask::{TaskExtRef, TaskInner, WaitQueue, current};
+use axsync::{Mutex, MutexGuard, RawMutex};
+use axtask::{AxTaskRef, TaskExtRef, TaskInner, WaitQueue, WeakAxTaskRef, current};
 use memory_addr::VirtAddrRange;
 use spin::{Once, RwLock};
-use weak_map::WeakMap;
 
-use crate::{futex::FutexTable, time::TimeStat};
+use crate::{
+    cred::Credentials,
+    exec::ExecGate,
+    exit,
+    futex::FutexKey,
+    job::JobControl,
+    latency::SyscallLatency,
+    lockcheck::{HeldLocks, Tracked, track},
+    mm::{GrowsDownAreas, HeapBounds},
+    observer::{ProcessEvent, notify_process_event},
+    pid_table::{PidTable, SCRUB_INTERVAL, TableStats},
+    resources::{CpuLimit, Rlimits},
+    sandbox::Sandbox,
+    seccomp::FilterChain,
+    stats,
+    time::{CpuTime, ProcessTimes, TimeStat},
+    uts::UtsNamespace,
+    workqueue::{Priority, queue_work},
+};
 
 /// Create a new user task.
 pub fn new_user_task(
@@ -59,6 +77,21 @@ pub fn new_user_task(
     )
 }
 
+/// The user registers of `task`, on top of its kernel stack.
+///
+/// Every trap from user space saves them there, on every arch, and returns
+/// to user space with what is there then. So while the task is in the
+/// kernel, this frame is the one copy of them: the frame syscalls are
+/// handed, which `clone` copies, `execve` and `rt_sigreturn` overwrite,
+/// signals are delivered on and a debugger reads. A [`UspaceContext`] only
+/// holds them for the first entry to user space, see [`new_user_task`].
+pub fn user_trap_frame(task: &TaskInner) -> *mut TrapFrame {
+    let kstack_top = task
+        .kernel_stack_top()
+        .expect("a user task has a kernel stack");
+    (kstack_top.as_usize() - size_of::<TrapFrame>()) as *mut TrapFrame
+}
+
 /// Task extended data for the monolithic kernel.
 pub struct TaskExt {
     /// The time statistics
@@ -77,15 +110,23 @@ impl TaskExt {
     }
 
     pub(crate) fn time_stat_from_kernel_to_user(&self, current_tick: usize) {
-        self.time.borrow_mut().switch_into_user_mode(current_tick);
+        let mut time = self.time.borrow_mut();
+        let before = time.output();
+        time.switch_into_user_mode(current_tick);
+        let after = time.output();
+        self.thread_data().cpu_time.add(before, after);
+        self.process_data().times.add(before, after);
+        stats::add_cpu_time(after.0 - before.0, after.1 - before.1);
     }
 
     pub(crate) fn time_stat_from_user_to_kernel(&self, current_tick: usize) {
-        self.time.borrow_mut().switch_into_kernel_mode(current_tick);
-    }
-
-    pub(crate) fn time_stat_output(&self) -> (usize, usize) {
-        self.time.borrow().output()
+        let mut time = self.time.borrow_mut();
+        let before = time.output();
+        time.switch_into_kernel_mode(current_tick);
+        let after = time.output();
+        self.thread_data().cpu_time.add(before, after);
+        self.process_data().times.add(before, after);
+        stats::add_cpu_time(after.0 - before.0, after.1 - before.1);
     }
 
     /// Get the [`ThreadData`] associated with this task.
@@ -102,52 +143,208 @@ impl TaskExt {
 axtask::def_task_ext!(TaskExt);
 
 /// Update the time statistics to reflect a switch from kernel mode to user mode.
-pub fn time_stat_from_kernel_to_user() {
-    let curr_task = current();
-    curr_task
+///
+/// Returns the time of the switch, in nanoseconds of the monotonic clock.
+pub fn time_stat_from_kernel_to_user() -> u64 {
+    let now = monotonic_time_nanos();
+    current()
         .task_ext()
-        .time_stat_from_kernel_to_user(monotonic_time_nanos() as usize);
+        .time_stat_from_kernel_to_user(now as usize);
+    now
 }
 
 /// Update the time statistics to reflect a switch from user mode to kernel mode.
-pub fn time_stat_from_user_to_kernel() {
-    let curr_task = current();
-    curr_task
+///
+/// Returns the time of the switch, in nanoseconds of the monotonic clock.
+pub fn time_stat_from_user_to_kernel() -> u64 {
+    let now = monotonic_time_nanos();
+    current()
         .task_ext()
-        .time_stat_from_user_to_kernel(monotonic_time_nanos() as usize);
+        .time_stat_from_user_to_kernel(now as usize);
+    now
 }
 
-/// Get the time statistics for the current task.
-pub fn time_stat_output() -> (usize, usize, usize, usize) {
+/// Update the time statistics on the way back from any trap from user mode,
+/// counting the time since the last update as user time.
+///
+/// Syscalls update them on entry and exit, but other traps, e.g. the timer
+/// interrupt, do not, so without this a process which never makes a syscall
+/// would not have its time accounted.
+pub fn time_stat_on_user_trap() {
     let curr_task = current();
-    let (utime_ns, stime_ns) = curr_task.task_ext().time_stat_output();
-    (
-        utime_ns / NANOS_PER_SEC as usize,
-        utime_ns / NANOS_PER_MICROS as usize,
-        stime_ns / NANOS_PER_SEC as usize,
-        stime_ns / NANOS_PER_MICROS as usize,
-    )
+    let now = monotonic_time_nanos() as usize;
+    curr_task.task_ext().time_stat_from_user_to_kernel(now);
+    curr_task.task_ext().time_stat_from_kernel_to_user(now);
 }
 
-#[doc(hidden)]
-pub struct WaitQueueWrapper(WaitQueue);
-impl Default for WaitQueueWrapper {
-    fn default() -> Self {
-        Self(WaitQueue::new())
+/// Give up the CPU if the current task should, see [`axtask::need_resched`].
+///
+/// The scheduler does not preempt kernel code, so loops which can run for
+/// long, e.g. over every page of an address space, call it every so often.
+/// It must not be called with a spin lock held, nor inside
+/// [`access_user_memory`](crate::mm::access_user_memory), whose state is per
+/// CPU. Sleeping locks are fine, others waiting for them just wait longer.
+pub fn cond_resched() {
+    if axtask::need_resched() {
+        axtask::yield_now();
     }
 }
-impl axsignal::api::WaitQueue for WaitQueueWrapper {
-    fn wait_timeout(&self, timeout: Option<Duration>) -> bool {
-        if let Some(timeout) = timeout {
-            self.0.wait_timeout(timeout)
+
+/// How a wait in a [`WaitQueueWrapper`] responds to signals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitMode {
+    /// Only ended by a notification or the timeout, for waits inside the
+    /// kernel which must finish.
+    Uninterruptible,
+    /// Also ended by a pending signal the thread does not block, for most
+    /// blocking syscalls, which then fail with `EINTR`.
+    Interruptible,
+    /// Also ended by a pending `SIGKILL`, but not by other signals, e.g.
+    /// those of job control, for waits they must not disturb.
+    Killable,
+}
+
+/// How a wait in a [`WaitQueueWrapper`] ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitResult {
+    /// By a notification, or the condition waited for.
+    Woken,
+    /// By the timeout.
+    TimedOut,
+    /// By a signal, see [`WaitMode`].
+    Interrupted,
+}
+
+/// A wait queue whose waits signals can end, see [`WaitMode`].
+///
+/// A thread in an interruptible or killable wait is registered for
+/// [`ThreadData::wake_for_signal`], so a signal sent to it ends the wait at
+/// once, rather than at the next time it checks.
+#[derive(Default)]
+pub struct WaitQueueWrapper {
+    wq: WaitQueue,
+    /// The notifications so far, for a waiter to tell whether it was
+    /// notified since it started.
+    notified: AtomicU64,
+}
+
+impl WaitQueueWrapper {
+    /// Create a new, empty queue.
+    pub const fn new() -> Self {
+        Self {
+            wq: WaitQueue::new(),
+            notified: AtomicU64::new(0),
+        }
+    }
+
+    /// Wait until notified, or for at most `timeout`, unless a pending
+    /// signal the thread does not block ends the wait.
+    pub fn wait_interruptible(&self, timeout: Option<Duration>) -> WaitResult {
+        self.wait(WaitMode::Interruptible, timeout)
+    }
+
+    /// Wait until notified, or for at most `timeout`, unless `SIGKILL`
+    /// ends the wait.
+    pub fn wait_killable(&self, timeout: Option<Duration>) -> WaitResult {
+        self.wait(WaitMode::Killable, timeout)
+    }
+
+    /// Wait in `mode` until notified, or for at most `timeout`.
+    pub fn wait(&self, mode: WaitMode, timeout: Option<Duration>) -> WaitResult {
+        let ticket = self.notified.load(Ordering::Acquire);
+        self.wait_until(mode, timeout, || {
+            self.notified.load(Ordering::Acquire) != ticket
+        })
+    }
+
+    /// Wait in `mode` until `cond` holds, which is checked whenever the
+    /// queue is notified, or for at most `timeout`.
+    ///
+    /// The wait is [`WaitResult::Woken`] if `cond` holds at the end, even if
+    /// a signal came as well, so that a notification is not lost.
+    pub fn wait_until(
+        &self,
+        mode: WaitMode,
+        timeout: Option<Duration>,
+        cond: impl Fn() -> bool,
+    ) -> WaitResult {
+        let curr = current();
+        let thread = (mode != WaitMode::Uninterruptible).then(|| curr.task_ext().thread_data());
+        let interrupted = || thread.is_some_and(|thread| thread.interrupts_wait(mode));
+        let _wakeup = thread.map(|thread| thread.signal_wakeup.register(&self.wq));
+        // Checked under the lock of the queue, which `wake_for_signal`
+        // takes as well, so a signal sent meanwhile is not missed.
+        let done = || cond() || interrupted();
+        match timeout {
+            Some(timeout) => {
+                self.wq.wait_timeout_until(timeout, done);
+            }
+            None => self.wq.wait_until(done),
+        }
+        if cond() {
+            WaitResult::Woken
+        } else if interrupted() {
+            WaitResult::Interrupted
         } else {
-            self.0.wait();
-            true
+            WaitResult::TimedOut
         }
     }
 
+    /// Wake up one waiter. Returns `false` if there was none.
+    pub fn notify_one(&self, resched: bool) -> bool {
+        self.notified.fetch_add(1, Ordering::Release);
+        self.wq.notify_one(resched)
+    }
+
+    /// Wake up every waiter.
+    pub fn notify_all(&self, resched: bool) {
+        self.notified.fetch_add(1, Ordering::Release);
+        self.wq.notify_all(resched);
+    }
+
+    /// Whether no thread waits in the queue.
+    pub fn is_empty(&self) -> bool {
+        self.wq.is_empty()
+    }
+}
+
+/// The signal manager waits here in `wait_signal`, e.g. for `sigsuspend`,
+/// which is interruptible: a signal sent to the process notifies the queue,
+/// and one sent to the thread alone ends the wait as well.
+impl axsignal::api::WaitQueue for WaitQueueWrapper {
+    fn wait_timeout(&self, timeout: Option<Duration>) -> bool {
+        self.wait_interruptible(timeout) != WaitResult::TimedOut
+    }
+
     fn notify_one(&self) -> bool {
-        self.0.notify_one(false)
+        WaitQueueWrapper::notify_one(self, false)
+    }
+}
+
+/// The queue a thread is in an interruptible or killable wait on, for a
+/// signal sent to it to wake it, see [`WaitQueueWrapper`].
+#[derive(Default)]
+struct SignalWakeup(spin::Mutex<Option<NonNull<WaitQueue>>>);
+
+// SAFETY: The queue is only used with the lock held, and the waiting thread,
+// which borrows it, takes it out under the lock before leaving the wait.
+unsafe impl Send for SignalWakeup {}
+unsafe impl Sync for SignalWakeup {}
+
+impl SignalWakeup {
+    /// Register `wq` until the guard is dropped.
+    fn register(&self, wq: &WaitQueue) -> SignalWakeupGuard<'_> {
+        *self.0.lock() = Some(NonNull::from(wq));
+        SignalWakeupGuard(self)
+    }
+}
+
+/// A registered [`SignalWakeup`], see [`SignalWakeup::register`].
+struct SignalWakeupGuard<'a>(&'a SignalWakeup);
+
+impl Drop for SignalWakeupGuard<'_> {
+    fn drop(&mut self) {
+        *self.0.0.lock() = None;
     }
 }
 
@@ -160,8 +357,140 @@ pub struct ThreadData {
     /// When the thread exits, the kernel clears the word at this address if it is not NULL.
     pub clear_child_tid: AtomicUsize,
 
+    /// The head of the robust futex list of the thread, as `set_robust_list`
+    /// set it, or 0.
+    robust_list: AtomicUsize,
+
     /// The thread-level signal manager
     pub signal: ThreadSignalManager<RawMutex, WaitQueueWrapper>,
+
+    /// The realtime signals in the queue of the thread itself, by number
+    /// from `SIGRTMIN`, which [`ProcessData::queued_rt_signals`] counts too.
+    pub queued_rt_signals: spin::Mutex<[usize; RT_SIGNALS]>,
+
+    /// Whether the thread has entered the exit path.
+    ///
+    /// Signals are only queued while holding this lock, so a sender either
+    /// delivers before the thread starts exiting or observes it as gone.
+    exited: spin::Mutex<bool>,
+
+    /// The task running the thread
+    task: Once<WeakAxTaskRef>,
+    /// The user and system time of the thread
+    cpu_time: CpuTime,
+    /// The uptime when the thread started waiting for a block device, or 0
+    /// if it is not waiting, see [`crate::iowait`].
+    pub(crate) io_wait_start: AtomicU64,
+    /// Whether the user registers of the thread are open to a debugger, as
+    /// it is stopped, see [`Self::while_stopped`].
+    stopped: spin::Mutex<bool>,
+    /// The signal frames the thread is in.
+    pub signal_frames: spin::Mutex<SignalFrames>,
+    /// The queue the thread waits on, if a signal may end the wait.
+    signal_wakeup: SignalWakeup,
+    /// The stack pointer the thread was created with by `clone`, or 0 if
+    /// it runs on the stack of its parent, to name its stack in
+    /// `/proc/<pid>/maps`.
+    initial_sp: AtomicUsize,
+    /// Whether the syscall the thread is in is to be restarted once the
+    /// signal which interrupted it is handled, see [`Self::restart_syscall`].
+    restart_syscall: AtomicBool,
+    /// The tracked locks the thread holds, see [`crate::lockcheck`].
+    pub(crate) held_locks: HeldLocks,
+}
+
+/// The number of realtime signals, from `SIGRTMIN` to 64.
+pub const RT_SIGNALS: usize = 65 - Signo::SIGRTMIN as usize;
+
+/// The most signal frames a thread can be in at once, each taken in the
+/// handler of the last, before it is killed with `SIGSEGV`.
+pub const MAX_SIGNAL_NESTING: usize = 64;
+
+/// A signal frame on the user stack, see [`SignalFrames`].
+struct SignalFrame {
+    /// The stack pointer the handler started with, just below the frame.
+    sp: usize,
+    /// Whether the frame is on the alternate signal stack.
+    on_altstack: bool,
+    /// The address of the `ucontext` in the frame.
+    ucontext: usize,
+    /// Whether the handler was given the `ucontext`, with `SA_SIGINFO`.
+    siginfo: bool,
+}
+
+impl SignalFrame {
+    /// Whether the thread is still in the frame, running at `sp`, on the
+    /// alternate signal stack if `on_altstack`. A frame starting at `sp` is
+    /// left only if `inclusive`.
+    fn is_kept(&self, sp: usize, on_altstack: bool, inclusive: bool) -> bool {
+        if self.on_altstack != on_altstack {
+            // Handlers on the alternate stack run inside those on the other
+            // stack, not the other way round.
+            return !self.on_altstack;
+        }
+        self.sp > sp || (!inclusive && self.sp == sp)
+    }
+}
+
+/// The signal frames a thread is running the handlers of, the innermost
+/// last, to limit how deep they nest.
+///
+/// A handler can leave its frame with `siglongjmp` rather than returning,
+/// so frames are not only dropped by `rt_sigreturn`, but also once the
+/// thread runs above them on the same stack, or off the alternate stack.
+#[derive(Default)]
+pub struct SignalFrames(Vec<SignalFrame>);
+
+impl SignalFrames {
+    /// Drop the frames the thread has left, running at `sp`, on the
+    /// alternate signal stack if `on_altstack`. A frame starting at `sp` is
+    /// dropped only if `inclusive`.
+    fn unwind(&mut self, sp: usize, on_altstack: bool, inclusive: bool) {
+        self.0
+            .retain(|frame| frame.is_kept(sp, on_altstack, inclusive));
+    }
+
+    /// Record a frame starting at `frame_sp` for a signal taken at `sp`,
+    /// each on the alternate signal stack if the corresponding flag is set,
+    /// with the `ucontext` at `ucontext`, which the handler takes if
+    /// `siginfo`.
+    ///
+    /// Returns how many frames the thread is in now.
+    pub fn enter(
+        &mut self,
+        sp: usize,
+        on_altstack: bool,
+        frame_sp: usize,
+        frame_on_altstack: bool,
+        ucontext: usize,
+        siginfo: bool,
+    ) -> usize {
+        self.unwind(sp, on_altstack, false);
+        self.0.push(SignalFrame {
+            sp: frame_sp,
+            on_altstack: frame_on_altstack,
+            ucontext,
+            siginfo,
+        });
+        self.0.len()
+    }
+
+    /// Drop the frame returned from by `rt_sigreturn` at `sp`, on the
+    /// alternate signal stack if `on_altstack`, with the frames inside it.
+    ///
+    /// Returns the address of the `ucontext` of the frame, and whether the
+    /// handler took it, if there is such a frame.
+    pub fn leave(&mut self, sp: usize, on_altstack: bool) -> Option<(usize, bool)> {
+        // The frames inside it were entered after it, so it is the first
+        // of those dropped.
+        let frame = self
+            .0
+            .iter()
+            .find(|frame| !frame.is_kept(sp, on_altstack, true))
+            .map(|frame| (frame.ucontext, frame.siginfo));
+        self.unwind(sp, on_altstack, true);
+        frame
+    }
 }
 
 impl ThreadData {
@@ -170,11 +499,120 @@ impl ThreadData {
     pub fn new(proc: &ProcessData) -> Self {
         Self {
             clear_child_tid: AtomicUsize::new(0),
+            robust_list: AtomicUsize::new(0),
 
             signal: ThreadSignalManager::new(proc.signal.clone()),
+            queued_rt_signals: spin::Mutex::new([0; RT_SIGNALS]),
+
+            exited: spin::Mutex::new(false),
+
+            task: Once::new(),
+            cpu_time: CpuTime::default(),
+            io_wait_start: AtomicU64::new(0),
+            stopped: spin::Mutex::new(false),
+            signal_frames: spin::Mutex::default(),
+            signal_wakeup: SignalWakeup::default(),
+            initial_sp: AtomicUsize::new(0),
+            restart_syscall: AtomicBool::new(false),
+            held_locks: HeldLocks::default(),
         }
     }
 
+    /// Set the task running the thread, once it has been spawned.
+    pub fn set_task(&self, task: &AxTaskRef) {
+        self.task.call_once(|| Arc::downgrade(task));
+    }
+
+    /// Get the task running the thread, unless it is gone.
+    pub fn task(&self) -> Option<AxTaskRef> {
+        self.task.get().and_then(Weak::upgrade)
+    }
+
+    /// Get the user and system time of the thread, in nanoseconds.
+    pub fn cpu_time(&self) -> (usize, usize) {
+        self.cpu_time.get()
+    }
+
+    /// Whether the thread is waiting for a block device.
+    pub fn in_io_wait(&self) -> bool {
+        self.io_wait_start.load(Ordering::Relaxed) != 0
+    }
+
+    /// Run `f`, which waits while the process is stopped, with the user
+    /// registers of the thread in `tf` open to [`Self::with_stopped_frame`].
+    ///
+    /// `tf` is the frame [`user_trap_frame`] gives, borrowed to keep the
+    /// thread off it while a debugger may change it.
+    pub fn while_stopped<R>(&self, tf: &mut TrapFrame, f: impl FnOnce() -> R) -> R {
+        debug_assert_eq!(tf as *mut TrapFrame, user_trap_frame(&current()));
+        *self.stopped.lock() = true;
+        let result = f();
+        *self.stopped.lock() = false;
+        result
+    }
+
+    /// Run `f` on the user registers of the thread, for a debugger, if it is
+    /// stopped. What `f` changes takes effect when the thread goes on.
+    pub fn with_stopped_frame<R>(&self, f: impl FnOnce(&mut TrapFrame) -> R) -> Option<R> {
+        let stopped = self.stopped.lock();
+        if !*stopped {
+            return None;
+        }
+        let task = self.task()?;
+        // SAFETY: The thread waits in `while_stopped`, and takes the lock
+        // before it touches the frame again.
+        Some(f(unsafe { &mut *user_trap_frame(&task) }))
+    }
+
+    /// Whether a pending signal ends a wait of the thread in `mode`.
+    pub fn interrupts_wait(&self, mode: WaitMode) -> bool {
+        match mode {
+            WaitMode::Uninterruptible => false,
+            WaitMode::Interruptible => {
+                let blocked = self.signal.with_blocked_mut(|blocked| *blocked);
+                self.signal.pending() & !blocked != SignalSet::default()
+            }
+            WaitMode::Killable => self.signal.pending().has(Signo::SIGKILL),
+        }
+    }
+
+    /// Wake the thread if it is in an interruptible or killable wait, for a
+    /// signal just sent to it, or to its process, to end the wait.
+    ///
+    /// The wait goes on if the signal does not end it in its mode.
+    pub fn wake_for_signal(&self) {
+        let wakeup = self.signal_wakeup.0.lock();
+        if let (Some(wq), Some(task)) = (*wakeup, self.task()) {
+            // SAFETY: The waiting thread keeps the queue alive while it is
+            // registered, see `SignalWakeup`.
+            unsafe { wq.as_ref() }.notify_task(false, &task);
+        }
+    }
+
+    /// Interrupt the CPU the thread runs on, if it is another one, so that
+    /// the thread takes the signal just sent to it on its way back to user
+    /// space, rather than once that CPU happens to take a timer interrupt.
+    pub fn kick(&self) {
+        let Some(cpu) = self.task().and_then(|task| task.running_cpu()) else {
+            return;
+        };
+        if cpu != axhal::cpu::this_cpu_id() {
+            axhal::irq::kick_cpu(cpu);
+        }
+    }
+
+    /// Mark the thread as exiting. No more signals can be queued to it.
+    pub fn mark_exited(&self) {
+        *self.exited.lock() = true;
+    }
+
+    /// Run `f` unless the thread has entered the exit path, in which case
+    /// `None` is returned.
+    pub fn with_alive<R>(&self, f: impl FnOnce() -> R) -> Option<R> {
+        let exited = self.exited.lock();
+        (!*exited).then(f)
+    }
+
     /// Get the clear child tid field.
     pub fn clear_child_tid(&self) -> usize {
         self.clear_child_tid.load(Ordering::Relaxed)
@@ -185,31 +623,149 @@ impl ThreadData {
         self.clear_child_tid
             .store(clear_child_tid, Ordering::Relaxed);
     }
+
+    /// Get the head of the robust futex list, or 0 if there is none.
+    pub fn robust_list(&self) -> usize {
+        self.robust_list.load(Ordering::Relaxed)
+    }
+
+    /// Set the head of the robust futex list.
+    pub fn set_robust_list(&self, head: usize) {
+        self.robust_list.store(head, Ordering::Relaxed);
+    }
+
+    /// Get the stack pointer the thread was created with, or 0 if it
+    /// started on the stack of its parent.
+    pub fn initial_sp(&self) -> usize {
+        self.initial_sp.load(Ordering::Relaxed)
+    }
+
+    /// Set the stack pointer the thread was created with.
+    pub fn set_initial_sp(&self, sp: usize) {
+        self.initial_sp.store(sp, Ordering::Relaxed);
+    }
+
+    /// Have the syscall the thread is in restarted rather than fail with
+    /// `EINTR`, once the signal which interrupted it is handled.
+    pub fn restart_syscall(&self) {
+        self.restart_syscall.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the syscall the thread is returning from is to be restarted,
+    /// which is only asked once.
+    pub fn take_restart_syscall(&self) -> bool {
+        self.restart_syscall.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// The most bytes of arguments, or of environment, kept by [`ExecArgs`],
+/// `ARG_MAX` of Linux.
+pub const ARG_MAX: usize = 128 * 1024;
+
+/// The arguments and environment a process was started with, for
+/// `/proc/<pid>/cmdline` and `/proc/<pid>/environ`.
+///
+/// Each is kept as null-terminated strings one after another, cut at
+/// [`ARG_MAX`] bytes.
+#[derive(Debug, Default)]
+pub struct ExecArgs {
+    /// The arguments.
+    pub argv: Vec<u8>,
+    /// The environment.
+    pub envp: Vec<u8>,
+}
+
+impl ExecArgs {
+    /// Keep `args` and `envs`.
+    pub fn new(args: &[String], envs: &[String]) -> Self {
+        let join = |strings: &[String]| {
+            let mut block = Vec::new();
+            for s in strings {
+                block.extend_from_slice(s.as_bytes());
+                block.push(0);
+            }
+            block.truncate(ARG_MAX);
+            block
+        };
+        Self {
+            argv: join(args),
+            envp: join(envs),
+        }
+    }
 }
 
 /// Extended data for [`Process`].
 pub struct ProcessData {
     /// The executable path
     pub exe_path: RwLock<String>,
+    /// The arguments and environment of the last `execve`, shared on fork.
+    /// Empty for a process the kernel made without one.
+    pub exec_args: RwLock<Arc<ExecArgs>>,
     /// The virtual memory address space.
     pub aspace: Arc<Mutex<AddrSpace>>,
+    /// The grows-down mappings of `aspace`, shared along with it.
+    pub grows_down: Arc<Mutex<GrowsDownAreas>>,
     /// The resource namespace
     pub ns: AxNamespace,
-    /// The user heap bottom
-    heap_bottom: AtomicUsize,
-    /// The user heap top
-    heap_top: AtomicUsize,
+    /// The bounds of the user heap, shared along with `aspace`.
+    pub heap: Arc<HeapBounds>,
 
     /// The child exit wait queue
-    pub child_exit_wq: WaitQueue,
+    pub child_exit_wq: WaitQueueWrapper,
     /// The exit signal of the thread
     pub exit_signal: Option<Signo>,
 
     /// The process signal manager
     pub signal: Arc<ProcessSignalManager<RawMutex, WaitQueueWrapper>>,
 
-    /// The futex table.
-    pub futex_table: FutexTable,
+    /// Whether the process is stopped, and the changes to report.
+    pub job: JobControl,
+    /// Whether an `execve` or `exit_group` is under way, which keeps them
+    /// apart from `clone`.
+    pub exec_gate: ExecGate,
+
+    /// The number of realtime signals queued for the process and its
+    /// threads, limited by `RLIMIT_SIGPENDING`.
+    pub queued_rt_signals: AtomicUsize,
+
+    /// The syscall filters, inherited across fork and kept across exec.
+    pub syscall_filters: RwLock<FilterChain>,
+
+    /// The path sandbox, inherited across fork and kept across exec.
+    pub sandbox: RwLock<Sandbox>,
+
+    /// The credentials, inherited across fork.
+    pub cred: RwLock<Credentials>,
+
+    /// The resource limits, inherited across fork.
+    pub rlimits: RwLock<Rlimits>,
+
+    /// The nice value, from -20 to 19, inherited across fork.
+    pub nice: AtomicI32,
+
+    /// The UTS namespace, shared on fork unless `CLONE_NEWUTS` is given.
+    pub uts: RwLock<Arc<UtsNamespace>>,
+
+    /// `RLIMIT_CPU` of [`Self::rlimits`], to be updated with it.
+    pub cpu_limit: CpuLimit,
+
+    /// The latencies of the syscalls, if recorded, inherited across fork
+    /// and kept across exec, see [`crate::latency`].
+    pub syscall_latency: SyscallLatency,
+
+    /// The uptime when the process was created, in nanoseconds, see
+    /// [`stats::uptime_nanos`].
+    pub start_time_ns: u64,
+
+    /// The user and system time of all threads, and of the reaped children
+    times: ProcessTimes,
+    /// The time exited threads spent on a CPU, in nanoseconds
+    exited_run_time_ns: AtomicU64,
+    /// The time threads waited for block devices, in nanoseconds
+    pub(crate) io_delay_ns: AtomicU64,
+    /// How many stages of the teardown of the process are done, see
+    /// [`crate::exit`].
+    pub(crate) exit_progress: AtomicU8,
 }
 
 impl ProcessData {
@@ -217,17 +773,20 @@ impl ProcessData {
     pub fn new(
         exe_path: String,
         aspace: Arc<Mutex<AddrSpace>>,
+        heap: Arc<HeapBounds>,
+        grows_down: Arc<Mutex<GrowsDownAreas>>,
         signal_actions: Arc<Mutex<SignalActions>>,
         exit_signal: Option<Signo>,
     ) -> Self {
         Self {
             exe_path: RwLock::new(exe_path),
+            exec_args: RwLock::default(),
             aspace,
+            grows_down,
             ns: AxNamespace::new_thread_local(),
-            heap_bottom: AtomicUsize::new(axconfig::plat::USER_HEAP_BASE),
-            heap_top: AtomicUsize::new(axconfig::plat::USER_HEAP_BASE),
+            heap,
 
-            child_exit_wq: WaitQueue::new(),
+            child_exit_wq: WaitQueueWrapper::new(),
             exit_signal,
 
             signal: Arc::new(ProcessSignalManager::new(
@@ -235,28 +794,70 @@ impl ProcessData {
                 axconfig::plat::SIGNAL_TRAMPOLINE,
             )),
 
-            futex_table: FutexTable::new(),
+            job: JobControl::default(),
+            exec_gate: ExecGate::default(),
+
+            queued_rt_signals: AtomicUsize::new(0),
+
+            syscall_filters: RwLock::new(FilterChain::default()),
+
+            sandbox: RwLock::new(Sandbox::default()),
+
+            cred: RwLock::new(Credentials::root()),
+
+            rlimits: RwLock::new(Rlimits::default()),
+
+            nice: AtomicI32::new(0),
+
+            uts: RwLock::new(UtsNamespace::initial()),
+
+            cpu_limit: CpuLimit::default(),
+
+            syscall_latency: SyscallLatency::new(),
+
+            start_time_ns: stats::uptime_nanos(),
+
+            times: ProcessTimes::default(),
+            exited_run_time_ns: AtomicU64::new(0),
+            io_delay_ns: AtomicU64::new(0),
+            exit_progress: AtomicU8::new(0),
         }
     }
 
-    /// Get the bottom address of the user heap.
-    pub fn get_heap_bottom(&self) -> usize {
-        self.heap_bottom.load(Ordering::Acquire)
+    /// The key of the futex word at `addr` of the process.
+    pub fn futex_key(&self, addr: usize) -> FutexKey {
+        FutexKey::new(&self.aspace, addr)
+    }
+
+    /// Lock [`Self::aspace`], as a tracked lock, see [`crate::lockcheck`].
+    #[track_caller]
+    pub fn lock_aspace(&self) -> Tracked<MutexGuard<'_, AddrSpace>> {
+        track("aspace", self.aspace.lock())
     }
 
-    /// Set the bottom address of the user heap.
-    pub fn set_heap_bottom(&self, bottom: usize) {
-        self.heap_bottom.store(bottom, Ordering::Release)
+    /// Lock [`Self::grows_down`], as a tracked lock, see
+    /// [`crate::lockcheck`]. Taken after [`Self::aspace`] when both are.
+    #[track_caller]
+    pub fn lock_grows_down(&self) -> Tracked<MutexGuard<'_, GrowsDownAreas>> {
+        track("grows_down", self.grows_down.lock())
     }
 
-    /// Get the top address of the user heap.
-    pub fn get_heap_top(&self) -> usize {
-        self.heap_top.load(Ordering::Acquire)
+    /// Get the user and system time of all threads and of the reaped
+    /// children.
+    pub fn times(&self) -> &ProcessTimes {
+        &self.times
     }
 
-    /// Set the top address of the user heap.
-    pub fn set_heap_top(&self, top: usize) {
-        self.heap_top.store(top, Ordering::Release)
+    /// The time the threads waited for block devices, in nanoseconds, see
+    /// [`crate::iowait`].
+    pub fn io_delay_ns(&self) -> u64 {
+        self.io_delay_ns.load(Ordering::Relaxed)
+    }
+
+    /// Record that a thread which spent `time` on a CPU has exited.
+    pub fn add_exited_run_time(&self, time: Duration) {
+        self.exited_run_time_ns
+            .fetch_add(time.as_nanos() as u64, Ordering::Relaxed);
     }
 
     /// Linux manual: A "clone" child is one which delivers no signal, or a
@@ -268,6 +869,9 @@ impl ProcessData {
 
 impl Drop for ProcessData {
     fn drop(&mut self) {
+        if let Some(stage) = exit::unfinished_stage(self.exit_progress.load(Ordering::Acquire)) {
+            warn!("process dropped before its teardown reached {:?}", stage);
+        }
         if !cfg!(target_arch = "aarch64") && !cfg!(target_arch = "loongarch64") {
             // See [`crate::new_user_aspace`]
             let kernel = kernel_aspace().lock();
@@ -301,37 +905,59 @@ impl AxNamespaceIf for AxNamespaceImpl {
     }
 }
 
-static THREAD_TABLE: RwLock<WeakMap<Pid, Weak<Thread>>> = RwLock::new(WeakMap::new());
-static PROCESS_TABLE: RwLock<WeakMap<Pid, Weak<Process>>> = RwLock::new(WeakMap::new());
-static PROCESS_GROUP_TABLE: RwLock<WeakMap<Pid, Weak<ProcessGroup>>> = RwLock::new(WeakMap::new());
-static SESSION_TABLE: RwLock<WeakMap<Pid, Weak<Session>>> = RwLock::new(WeakMap::new());
+static THREAD_TABLE: RwLock<PidTable<Thread>> = RwLock::new(PidTable::new());
+static PROCESS_TABLE: RwLock<PidTable<Process>> = RwLock::new(PidTable::new());
+static PROCESS_GROUP_TABLE: RwLock<PidTable<ProcessGroup>> = RwLock::new(PidTable::new());
+static SESSION_TABLE: RwLock<PidTable<Session>> = RwLock::new(PidTable::new());
+
+/// Processes reaped since the tables were last scrubbed by maintenance work.
+static REAPED: AtomicUsize = AtomicUsize::new(0);
+/// Whether the maintenance work is queued and has not started yet.
+static SCRUB_QUEUED: AtomicBool = AtomicBool::new(false);
 
 /// Add the thread and possibly its process, process group and session to the
 /// corresponding tables.
 pub fn add_thread_to_table(thread: &Arc<Thread>) {
+    stats::count_fork();
+    if insert_thread(thread) {
+        notify_process_event(thread.process().pid(), ProcessEvent::Created);
+    }
+}
+
+/// Insert the thread into the tables, returning whether its process is new.
+fn insert_thread(thread: &Arc<Thread>) -> bool {
     let mut thread_table = THREAD_TABLE.write();
     thread_table.insert(thread.tid(), thread);
 
     let mut process_table = PROCESS_TABLE.write();
     let process = thread.process();
-    if process_table.contains_key(&process.pid()) {
-        return;
+    if process_table.contains_key(process.pid()) {
+        return false;
     }
     process_table.insert(process.pid(), process);
 
     let mut process_group_table = PROCESS_GROUP_TABLE.write();
     let process_group = process.group();
-    if process_group_table.contains_key(&process_group.pgid()) {
-        return;
+    if process_group_table.contains_key(process_group.pgid()) {
+        return true;
     }
     process_group_table.insert(process_group.pgid(), &process_group);
 
     let mut session_table = SESSION_TABLE.write();
     let session = process_group.session();
-    if session_table.contains_key(&session.sid()) {
-        return;
+    if session_table.contains_key(session.sid()) {
+        return true;
     }
     session_table.insert(session.sid(), &session);
+    true
+}
+
+/// Add a process group made by `setsid` or `setpgid`, and its session, to
+/// the corresponding tables.
+pub fn add_process_group_to_table(group: &Arc<ProcessGroup>) {
+    PROCESS_GROUP_TABLE.write().insert(group.pgid(), group);
+    let session = group.session();
+    SESSION_TABLE.write().insert(session.sid(), &session);
 }
 
 /// Lists all processes.
@@ -341,20 +967,95 @@ pub fn processes() -> Vec<Arc<Process>> {
 
 /// Finds the thread with the given TID.
 pub fn get_thread(tid: Pid) -> LinuxResult<Arc<Thread>> {
-    THREAD_TABLE.read().get(&tid).ok_or(LinuxError::ESRCH)
+    THREAD_TABLE.read().get(tid).ok_or(LinuxError::ESRCH)
 }
 /// Finds the process with the given PID.
 pub fn get_process(pid: Pid) -> LinuxResult<Arc<Process>> {
-    PROCESS_TABLE.read().get(&pid).ok_or(LinuxError::ESRCH)
+    PROCESS_TABLE.read().get(pid).ok_or(LinuxError::ESRCH)
 }
 /// Finds the process group with the given PGID.
 pub fn get_process_group(pgid: Pid) -> LinuxResult<Arc<ProcessGroup>> {
     PROCESS_GROUP_TABLE
         .read()
-        .get(&pgid)
+        .get(pgid)
         .ok_or(LinuxError::ESRCH)
 }
 /// Finds the session with the given SID.
 pub fn get_session(sid: Pid) -> LinuxResult<Arc<Session>> {
-    SESSION_TABLE.read().get(&sid).ok_or(LinuxError::ESRCH)
+    SESSION_TABLE.read().get(sid).ok_or(LinuxError::ESRCH)
+}
+
+/// The ID of a new thread, from that of its task, or `EAGAIN` if it is past
+/// the PIDs user space can tell, or if any table still has an entry under
+/// it, even a dead one, so that an ID never names something new while what
+/// it named lingers. Task IDs are not reused, so this only stops a wrap.
+pub fn new_tid(task_id: u64) -> LinuxResult<Pid> {
+    let tid = Pid::try_from(task_id)
+        .ok()
+        .filter(|&tid| tid <= i32::MAX as Pid)
+        .ok_or(LinuxError::EAGAIN)?;
+    if THREAD_TABLE.read().has_entry(tid)
+        || PROCESS_TABLE.read().has_entry(tid)
+        || PROCESS_GROUP_TABLE.read().has_entry(tid)
+        || SESSION_TABLE.read().has_entry(tid)
+    {
+        warn!("ID {} still in the tables, not reused", tid);
+        return Err(LinuxError::EAGAIN);
+    }
+    Ok(tid)
+}
+
+/// Remove the dead entries from all the tables, returning how many.
+pub fn scrub_tables() -> usize {
+    THREAD_TABLE.write().scrub()
+        + PROCESS_TABLE.write().scrub()
+        + PROCESS_GROUP_TABLE.write().scrub()
+        + SESSION_TABLE.write().scrub()
+}
+
+/// Count a process reaped, and every [`SCRUB_INTERVAL`] of them queue
+/// low-priority work to scrub the tables, unless some is queued already.
+pub(crate) fn count_reaped() {
+    if REAPED.fetch_add(1, Ordering::Relaxed) + 1 < SCRUB_INTERVAL
+        || SCRUB_QUEUED.swap(true, Ordering::AcqRel)
+    {
+        return;
+    }
+    REAPED.store(0, Ordering::Relaxed);
+    queue_work(Priority::Low, || {
+        SCRUB_QUEUED.store(false, Ordering::Release);
+        let removed = scrub_tables();
+        debug!("scrubbed {} dead entries from the tables", removed);
+    });
+}
+
+/// The sizes of the tables, by name, as `/proc/starry/pressure` lists them.
+pub fn table_stats() -> [(&'static str, TableStats); 4] {
+    [
+        ("threads", THREAD_TABLE.read().stats()),
+        ("processes", PROCESS_TABLE.read().stats()),
+        ("process_groups", PROCESS_GROUP_TABLE.read().stats()),
+        ("sessions", SESSION_TABLE.read().stats()),
+    ]
+}
+
+/// Get the time the thread has spent on a CPU.
+pub fn thread_run_time(thread: &Thread) -> Duration {
+    thread
+        .data::<ThreadData>()
+        .and_then(ThreadData::task)
+        .map_or(Duration::ZERO, |task| task.cpu_time())
+}
+
+/// Get the time all threads of the process, including the exited ones, have
+/// spent on a CPU.
+pub fn process_run_time(proc: &Process) -> Duration {
+    let exited = proc
+        .data::<ProcessData>()
+        .map_or(0, |data| data.exited_run_time_ns.load(Ordering::Relaxed));
+    proc.threads()
+        .iter()
+        .map(|thread| thread_run_time(thread))
+        .sum::<Duration>()
+        + Duration::from_nanos(exited)
 }
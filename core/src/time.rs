@@ -1,3 +1,10 @@
+//! CPU time accounting: the user and system time of threads and processes,
+//! and the interval timers driven by it.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use axhal::time::monotonic_time_nanos;
+
 numeric_enum_macro::numeric_enum! {
     #[repr(i32)]
     #[allow(non_camel_case_types)]
@@ -23,6 +30,8 @@ impl From<usize> for TimerType {
     }
 }
 
+/// The time accounting of a task, updated on every switch between user and
+/// kernel mode.
 pub struct TimeStat {
     utime_ns: usize,
     stime_ns: usize,
@@ -40,12 +49,14 @@ impl Default for TimeStat {
 }
 
 impl TimeStat {
+    /// Create the accounting of a task starting in kernel mode now.
     pub fn new() -> Self {
+        let now = monotonic_time_nanos() as usize;
         Self {
             utime_ns: 0,
             stime_ns: 0,
-            user_timestamp: 0,
-            kernel_timestamp: 0,
+            user_timestamp: now,
+            kernel_timestamp: now,
             timer_type: TimerType::NONE,
             timer_interval_ns: 0,
             timer_remained_ns: 0,
@@ -63,9 +74,10 @@ impl TimeStat {
         self.kernel_timestamp = current_timestamp;
     }
 
+    /// Account the time since entering user mode as user time.
     pub fn switch_into_kernel_mode(&mut self, current_timestamp: usize) {
         let now_time_ns = current_timestamp;
-        let delta = now_time_ns - self.kernel_timestamp;
+        let delta = now_time_ns - self.user_timestamp;
         self.utime_ns += delta;
         self.kernel_timestamp = now_time_ns;
         if self.timer_type != TimerType::NONE {
@@ -73,6 +85,7 @@ impl TimeStat {
         };
     }
 
+    /// Account the time since entering kernel mode as system time.
     pub fn switch_into_user_mode(&mut self, current_timestamp: usize) {
         let now_time_ns = current_timestamp;
         let delta = now_time_ns - self.kernel_timestamp;
@@ -123,3 +136,66 @@ impl TimeStat {
         }
     }
 }
+
+/// The user and system time accumulated from [`TimeStat`] updates, shared
+/// so that other threads can read it.
+#[derive(Default)]
+pub struct CpuTime {
+    utime_ns: AtomicUsize,
+    stime_ns: AtomicUsize,
+}
+
+impl CpuTime {
+    /// Add the time spent since a [`TimeStat`] went from `before` to
+    /// `after`, both as `(utime_ns, stime_ns)`.
+    pub fn add(&self, before: (usize, usize), after: (usize, usize)) {
+        self.add_nanos((after.0 - before.0, after.1 - before.1));
+    }
+
+    /// Add `(utime_ns, stime_ns)`.
+    fn add_nanos(&self, (utime_ns, stime_ns): (usize, usize)) {
+        self.utime_ns.fetch_add(utime_ns, Ordering::Relaxed);
+        self.stime_ns.fetch_add(stime_ns, Ordering::Relaxed);
+    }
+
+    /// Get the user and system time, in nanoseconds.
+    pub fn get(&self) -> (usize, usize) {
+        (
+            self.utime_ns.load(Ordering::Relaxed),
+            self.stime_ns.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// The CPU time of a process: that of its threads, and that of the children
+/// it reaped, as `times` and `getrusage` report them.
+#[derive(Default)]
+pub struct ProcessTimes {
+    own: CpuTime,
+    children: CpuTime,
+}
+
+impl ProcessTimes {
+    /// Add the time spent by a thread, see [`CpuTime::add`].
+    pub fn add(&self, before: (usize, usize), after: (usize, usize)) {
+        self.own.add(before, after);
+    }
+
+    /// Get the user and system time of the threads, in nanoseconds.
+    pub fn own(&self) -> (usize, usize) {
+        self.own.get()
+    }
+
+    /// Get the user and system time of the reaped children, in nanoseconds.
+    pub fn children(&self) -> (usize, usize) {
+        self.children.get()
+    }
+
+    /// Add the times of a reaped child, which count those of the children
+    /// it reaped in turn.
+    pub fn add_reaped_child(&self, child: &ProcessTimes) {
+        let (own, children) = (child.own(), child.children());
+        self.children
+            .add_nanos((own.0 + children.0, own.1 + children.1));
+    }
+}
@@ -0,0 +1,79 @@
+//! UTS namespaces, which hold the host and domain names reported by
+//! `uname`.
+//!
+//! Every process refers to one, shared with its parent unless it was
+//! created with `CLONE_NEWUTS`, in which case it gets a copy.
+
+use alloc::{sync::Arc, vec::Vec};
+use spin::{Once, RwLock};
+
+/// The longest host or domain name, in bytes, `__NEW_UTS_LEN` of Linux.
+pub const UTS_NAME_LEN: usize = 64;
+
+/// The name of the kernel, the same in every namespace.
+pub const SYSNAME: &str = "Starry";
+/// The release of the kernel.
+pub const RELEASE: &str = "10.0.0";
+/// The version of the kernel.
+pub const VERSION: &str = "10.0.0";
+
+/// The host name of the initial namespace.
+const DEFAULT_HOSTNAME: &[u8] = b"Starry - machine[0]";
+/// The domain name of the initial namespace.
+const DEFAULT_DOMAINNAME: &[u8] = b"https://github.com/oscomp/starry-next";
+
+/// The names of a [`UtsNamespace`].
+#[derive(Debug, Clone)]
+pub struct UtsNames {
+    /// The host name, set by `sethostname`.
+    pub hostname: Vec<u8>,
+    /// The NIS domain name, set by `setdomainname`.
+    pub domainname: Vec<u8>,
+}
+
+/// A UTS namespace.
+#[derive(Debug)]
+pub struct UtsNamespace {
+    names: RwLock<UtsNames>,
+}
+
+impl UtsNamespace {
+    /// The namespace the init process starts in.
+    pub fn initial() -> Arc<Self> {
+        static INITIAL: Once<Arc<UtsNamespace>> = Once::new();
+        INITIAL
+            .call_once(|| {
+                Arc::new(Self {
+                    names: RwLock::new(UtsNames {
+                        hostname: DEFAULT_HOSTNAME.to_vec(),
+                        domainname: DEFAULT_DOMAINNAME.to_vec(),
+                    }),
+                })
+            })
+            .clone()
+    }
+
+    /// A new namespace with the names of this one, for `CLONE_NEWUTS`.
+    pub fn copy(&self) -> Arc<Self> {
+        Arc::new(Self {
+            names: RwLock::new(self.names()),
+        })
+    }
+
+    /// The current names.
+    pub fn names(&self) -> UtsNames {
+        self.names.read().clone()
+    }
+
+    /// Set the host name, which must be at most [`UTS_NAME_LEN`] bytes.
+    pub fn set_hostname(&self, name: &[u8]) {
+        assert!(name.len() <= UTS_NAME_LEN);
+        self.names.write().hostname = name.to_vec();
+    }
+
+    /// Set the domain name, which must be at most [`UTS_NAME_LEN`] bytes.
+    pub fn set_domainname(&self, name: &[u8]) {
+        assert!(name.len() <= UTS_NAME_LEN);
+        self.names.write().domainname = name.to_vec();
+    }
+}
@@ -0,0 +1,79 @@
+//! The status of a child process, as `wait` and its kin report it.
+
+use axsignal::Signo;
+
+use crate::job::JobEvent;
+
+/// How a process exited, or changed its job control state.
+///
+/// It is kept as the exit code of a process, and written to user space,
+/// encoded like Linux does, which the `W*` macros of libc take apart: the
+/// exit code in bits 8 to 15, or the signal which killed the process in bits
+/// 0 to 6 with bit 7 for a core dump, or `0x7f` in the low byte and the
+/// signal in bits 8 to 15 for a stop, or `0xffff` for a continue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitStatus {
+    /// Exited with the code, of which `exit` keeps the low 8 bits.
+    Exited(u8),
+    /// Killed by the signal.
+    Signaled {
+        /// The signal.
+        signo: Signo,
+        /// Whether a core was dumped.
+        core_dump: bool,
+    },
+    /// Stopped by the signal.
+    Stopped(Signo),
+    /// Continued by `SIGCONT`.
+    Continued,
+}
+
+impl WaitStatus {
+    /// The status of a process which called `exit` with `code`.
+    pub const fn exited(code: i32) -> Self {
+        Self::Exited(code as u8)
+    }
+
+    /// The status of a process killed by `signo`, without a core dump.
+    pub const fn signaled(signo: Signo) -> Self {
+        Self::Signaled {
+            signo,
+            core_dump: false,
+        }
+    }
+
+    /// Encode the status for user space.
+    pub const fn encode(self) -> i32 {
+        match self {
+            Self::Exited(code) => (code as i32) << 8,
+            Self::Signaled { signo, core_dump } => signo as i32 | if core_dump { 0x80 } else { 0 },
+            Self::Stopped(signo) => ((signo as i32) << 8) | 0x7f,
+            Self::Continued => 0xffff,
+        }
+    }
+
+    /// Decode a status encoded by [`Self::encode`]. Returns `None` if it is
+    /// not one.
+    pub fn decode(status: i32) -> Option<Self> {
+        let signo = |n: i32| Signo::from_repr(n as u8);
+        match (status & 0xff, status >> 8) {
+            (0, code @ 0..=0xff) => Some(Self::Exited(code as u8)),
+            (0xff, 0xff) => Some(Self::Continued),
+            (0x7f, n @ 1..=0xff) => signo(n).map(Self::Stopped),
+            (low, 0) => signo(low & 0x7f).map(|signo| Self::Signaled {
+                signo,
+                core_dump: low & 0x80 != 0,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl From<JobEvent> for WaitStatus {
+    fn from(event: JobEvent) -> Self {
+        match event {
+            JobEvent::Stopped(signo) => Self::Stopped(signo),
+            JobEvent::Continued => Self::Continued,
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Kernel workers running deferred work.
+//!
+//! Work is a closure queued with a [`Priority`], and run by a pool of kernel
+//! tasks, on their own stacks and outside of any process: it must not use
+//! [`current`](axtask::current) as a user task. Within a priority, work
+//! starts in the order it was queued, and higher priorities go first.
+//!
+//! The workers are spawned by [`init`], and [`shutdown`] drains the queue
+//! before the system powers off. Work queued before [`init`] waits for the
+//! workers. Work can be queued from IRQ handlers, to do what they cannot do
+//! themselves, like sending signals: queueing only takes the lock of the
+//! queue and wakes a worker.
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use alloc::{boxed::Box, collections::vec_deque::VecDeque, format, sync::Arc};
+use axsync::spin::SpinNoIrq;
+use axtask::WaitQueue;
+// Spin locks, since wait conditions take them with preemption disabled.
+use spin::Mutex;
+
+/// The number of workers.
+const WORKERS: usize = 2;
+
+/// The stack size of a worker, large enough for filesystem work.
+const WORKER_STACK_SIZE: usize = 0x40000;
+
+/// The priority of work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Work someone is waiting for.
+    High,
+    /// The default.
+    Normal,
+    /// Work nobody waits for, e.g. writeback.
+    Low,
+}
+
+type Work = Box<dyn FnOnce() + Send>;
+
+struct WorkQueue {
+    /// The queued work, by priority. IRQs are disabled while it is locked,
+    /// since IRQ handlers queue work too.
+    queues: SpinNoIrq<[VecDeque<Work>; 3]>,
+    /// The number of work items queued or running.
+    pending: AtomicUsize,
+    /// Workers wait here for work.
+    work_wq: WaitQueue,
+    /// [`shutdown`] waits here for the queue to drain.
+    idle_wq: WaitQueue,
+    shutdown: AtomicBool,
+}
+
+static QUEUE: WorkQueue = WorkQueue {
+    queues: SpinNoIrq::new([VecDeque::new(), VecDeque::new(), VecDeque::new()]),
+    pending: AtomicUsize::new(0),
+    work_wq: WaitQueue::new(),
+    idle_wq: WaitQueue::new(),
+    shutdown: AtomicBool::new(false),
+};
+
+impl WorkQueue {
+    fn pop(&self) -> Option<Work> {
+        self.queues.lock().iter_mut().find_map(VecDeque::pop_front)
+    }
+
+    fn has_work(&self) -> bool {
+        self.queues.lock().iter().any(|queue| !queue.is_empty())
+    }
+}
+
+fn worker() {
+    loop {
+        QUEUE
+            .work_wq
+            .wait_until(|| QUEUE.has_work() || QUEUE.shutdown.load(Ordering::Acquire));
+        let Some(work) = QUEUE.pop() else {
+            if QUEUE.shutdown.load(Ordering::Acquire) {
+                return;
+            }
+            continue;
+        };
+        work();
+        if QUEUE.pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+            QUEUE.idle_wq.notify_all(false);
+        }
+    }
+}
+
+/// Spawn the workers.
+pub fn init() {
+    for i in 0..WORKERS {
+        axtask::spawn_raw(worker, format!("kworker/{i}"), WORKER_STACK_SIZE);
+    }
+}
+
+/// Queue `work` to run later.
+///
+/// Work queued after [`shutdown`] runs right away, on the caller.
+pub fn queue_work(priority: Priority, work: impl FnOnce() + Send + 'static) {
+    let mut queues = QUEUE.queues.lock();
+    if QUEUE.shutdown.load(Ordering::Acquire) {
+        drop(queues);
+        work();
+        return;
+    }
+    QUEUE.pending.fetch_add(1, Ordering::AcqRel);
+    queues[priority as usize].push_back(Box::new(work));
+    drop(queues);
+    QUEUE.work_wq.notify_one(false);
+}
+
+/// The result of work, which can be waited for.
+pub struct Completion<T> {
+    result: Mutex<Option<T>>,
+    wq: WaitQueue,
+}
+
+impl<T> Completion<T> {
+    fn new() -> Self {
+        Self {
+            result: Mutex::new(None),
+            wq: WaitQueue::new(),
+        }
+    }
+
+    fn complete(&self, result: T) {
+        *self.result.lock() = Some(result);
+        self.wq.notify_all(false);
+    }
+
+    /// Whether the work has finished.
+    pub fn is_done(&self) -> bool {
+        self.result.lock().is_some()
+    }
+
+    /// Wait for the work to finish, and take its result.
+    pub fn wait(&self) -> T {
+        loop {
+            if let Some(result) = self.result.lock().take() {
+                return result;
+            }
+            self.wq.wait_until(|| self.is_done());
+        }
+    }
+}
+
+/// Queue `work`, returning a [`Completion`] to wait for its result.
+pub fn queue_work_completion<T: Send + 'static>(
+    priority: Priority,
+    work: impl FnOnce() -> T + Send + 'static,
+) -> Arc<Completion<T>> {
+    let completion = Arc::new(Completion::new());
+    let done = completion.clone();
+    queue_work(priority, move || done.complete(work()));
+    completion
+}
+
+/// Run `work` on a worker and wait for its result.
+///
+/// This keeps deep work, like the flush of a filesystem, off the kernel
+/// stack of the caller.
+pub fn run_work<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> T {
+    queue_work_completion(Priority::High, work).wait()
+}
+
+/// Run the queued work, then stop the workers.
+pub fn shutdown() {
+    QUEUE
+        .idle_wq
+        .wait_until(|| QUEUE.pending.load(Ordering::Acquire) == 0);
+    let _queues = QUEUE.queues.lock();
+    QUEUE.shutdown.store(true, Ordering::Release);
+    QUEUE.work_wq.notify_all(false);
+}
+
+/// Check that 1000 items queued by several tasks at once all run, in the
+/// order of their priorities and, within one, in the order each task queued
+/// them, and that waiting for a completion returns its result.
+///
+/// Run after [`init`]. Panics on the first check which fails.
+#[cfg(feature = "kernel-tests")]
+pub fn self_test() {
+    use alloc::vec::Vec;
+
+    const PRODUCERS: usize = 4;
+    const ITEMS: usize = 1000;
+    const PRIORITIES: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    static STARTED: AtomicUsize = AtomicUsize::new(0);
+    static QUEUED: AtomicBool = AtomicBool::new(false);
+    static DONE: AtomicBool = AtomicBool::new(false);
+    static GATE: WaitQueue = WaitQueue::new();
+    /// What each item ran as, `(priority, producer, sequence)`, in order.
+    static RAN: Mutex<Vec<(usize, usize, usize)>> = Mutex::new(Vec::new());
+
+    // Hold every worker, so that nothing runs before all is queued, then
+    // let one through, so that the items run one at a time, in the order
+    // they are taken from the queue.
+    for i in 0..WORKERS {
+        queue_work(Priority::High, move || {
+            STARTED.fetch_add(1, Ordering::AcqRel);
+            GATE.notify_all(false);
+            let gate = if i == 0 { &QUEUED } else { &DONE };
+            GATE.wait_until(|| gate.load(Ordering::Acquire));
+        });
+    }
+    GATE.wait_until(|| STARTED.load(Ordering::Acquire) == WORKERS);
+
+    let producers: Vec<_> = (0..PRODUCERS)
+        .map(|producer| {
+            axtask::spawn(move || {
+                for seq in producer * ITEMS / PRODUCERS..(producer + 1) * ITEMS / PRODUCERS {
+                    let priority = PRIORITIES[seq % PRIORITIES.len()];
+                    queue_work(priority, move || {
+                        RAN.lock().push((priority as usize, producer, seq))
+                    });
+                }
+            })
+        })
+        .collect();
+    for producer in producers {
+        producer.join();
+    }
+    let last = queue_work_completion(Priority::Low, || RAN.lock().len());
+    QUEUED.store(true, Ordering::Release);
+    GATE.notify_all(false);
+    assert_eq!(last.wait(), ITEMS);
+    assert!(last.is_done());
+
+    let ran = core::mem::take(&mut *RAN.lock());
+    assert!(ran.is_sorted_by_key(|&(priority, _, _)| priority));
+    for producer in 0..PRODUCERS {
+        for priority in 0..PRIORITIES.len() {
+            let seqs = ran
+                .iter()
+                .filter(|&&(p, q, _)| (p, q) == (priority, producer))
+                .map(|&(_, _, seq)| seq);
+            assert!(seqs.is_sorted());
+        }
+    }
+
+    DONE.store(true, Ordering::Release);
+    GATE.notify_all(false);
+    assert_eq!(run_work(|| 42), 42);
+    info!("workqueue self test passed");
+}
@@ -1,6 +1,6 @@
 #!/bin/bash
 
-TIMEOUT=60s
+TIMEOUT=120s
 EXIT_STATUS=0
 ROOT=$(realpath $(dirname $0))/../
 AX_ROOT=$ROOT/.arceos
@@ -62,7 +62,9 @@ function run_and_compare() {
     local res=$?
     if [ $res == 124 ]; then
         return $S_TIMEOUT
-    elif [ $res -ne 0 ]; then
+    elif [ $res -ne 0 ] && ! grep -aq "^User tasks: " "$actual"; then
+        # QEMU also exits with a failure once the list is done if a program
+        # failed, which the expected output decides on.
         return $S_FAILED
     fi
 
@@ -108,10 +110,8 @@ function test_one() {
 }
 
 # TODO: add more testcases
-test_list=(
-    "nimbos"
-    "libc"
-)
+# TEST_APPS picks some of them, e.g. only `nimbos`
+test_list=(${TEST_APPS:-nimbos libc})
 
 for t in ${test_list[@]}; do
     APP=$t
@@ -0,0 +1,67 @@
+#!/bin/bash
+# Check that the kernel takes QEMU down at once, with an exit status telling
+# how, see `src/power.rs`: after a panic, here from a program of the list
+# which does not exist, and once init is done with a program which failed,
+# here by timing out.
+#
+# The libc testcases must be built first, with `make AX_TESTCASE=libc user_apps`.
+
+TIMEOUT=60s
+ROOT=$(realpath $(dirname $0))/../
+AX_ROOT=$ROOT/.arceos
+EXIT_STATUS=0
+
+if [ -z "$ARCH" ]; then
+    ARCH=x86_64
+fi
+# The statuses QEMU exits with after a panic and after a failure of init.
+case $ARCH in
+    x86_64) PANIC_STATUS=5; FAILED_STATUS=3 ;;
+    riscv64) PANIC_STATUS=1; FAILED_STATUS=1 ;;
+    *) PANIC_STATUS=0; FAILED_STATUS=0 ;;
+esac
+CONFIG_FILE=$(realpath --relative-to=$AX_ROOT "$ROOT/configs/$ARCH.toml")
+ARGS="AX_TESTCASE=libc ARCH=$ARCH ACCEL=n BLK=y NET=y FEATURES=fp_simd LOG=error EXTRA_CONFIG=$CONFIG_FILE"
+OUT_DIR=$(mktemp -d)
+
+# Build and run with the testcase list `list` and the make arguments
+# `extra`, and check that QEMU exits with `status` and printed `pattern`.
+function check() {
+    local name=$1
+    local list=$2
+    local extra=$3
+    local status=$4
+    local pattern=$5
+    make -C "$ROOT" $ARGS AX_TESTCASES_LIST=$list $extra build > "$OUT_DIR/build.log" 2>&1 || {
+        cat "$OUT_DIR/build.log"
+        exit 1
+    }
+    timeout --foreground $TIMEOUT make -C "$ROOT" $ARGS AX_TESTCASES_LIST=$list $extra justrun > "$OUT_DIR/run.log" 2>&1
+    local res=$?
+    # make fails with 2 whatever QEMU exits with, and tells the latter.
+    local actual=0
+    if [ $res == 124 ]; then
+        echo "$name: QEMU still running after $TIMEOUT"
+        EXIT_STATUS=1
+        return
+    elif [ $res -ne 0 ]; then
+        actual=$(grep -ao 'justrun\] Error [0-9]*' "$OUT_DIR/run.log" | head -1 | grep -o '[0-9]*$')
+    fi
+    if [ "$actual" != "$status" ]; then
+        echo "$name: QEMU exited with ${actual:-an unknown status}, not $status:"
+        cat "$OUT_DIR/run.log"
+        EXIT_STATUS=1
+    elif ! grep -aq "$pattern" "$OUT_DIR/run.log"; then
+        echo "$name: \"$pattern\" not printed:"
+        cat "$OUT_DIR/run.log"
+        EXIT_STATUS=1
+    else
+        echo "$name: QEMU exited with $actual"
+    fi
+}
+
+check panic /missing_c, "" $PANIC_STATUS "panicked at"
+check init_failed hang_c, TEST_TIMEOUT=1 $FAILED_STATUS "0 passed, 0 failed, 1 timed out"
+
+rm -rf "$OUT_DIR"
+exit $EXIT_STATUS
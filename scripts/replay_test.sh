@@ -0,0 +1,52 @@
+#!/bin/bash
+# Record a run of a test printing what it sees of the time and randomness,
+# replay it twice, and check the replays print the same as the recording.
+#
+# The libc testcases must be built first, with `make AX_TESTCASE=libc user_apps`.
+
+TIMEOUT=60s
+ROOT=$(realpath $(dirname $0))/../
+AX_ROOT=$ROOT/.arceos
+TESTCASE=replay_backoff_c
+
+if [ -z "$ARCH" ]; then
+    ARCH=x86_64
+fi
+CONFIG_FILE=$(realpath --relative-to=$AX_ROOT "$ROOT/configs/$ARCH.toml")
+ARGS="AX_TESTCASE=libc AX_TESTCASES_LIST=$TESTCASE, ARCH=$ARCH ACCEL=n BLK=y NET=y FEATURES=fp_simd LOG=warn EXTRA_CONFIG=$CONFIG_FILE"
+OUT_DIR=$(mktemp -d)
+
+# Run in `mode`, keeping what the test printed in `out`.
+function run() {
+    local mode=$1
+    local out=$2
+    make -C "$ROOT" $ARGS REPLAY=$mode build > "$OUT_DIR/build.log" 2>&1 || {
+        cat "$OUT_DIR/build.log"
+        exit 1
+    }
+    timeout --foreground $TIMEOUT make -C "$ROOT" $ARGS REPLAY=$mode justrun > "$OUT_DIR/run.log" 2>&1
+    if grep -q "Replay diverged" "$OUT_DIR/run.log"; then
+        grep "Replay diverged" "$OUT_DIR/run.log"
+        exit 1
+    fi
+    grep -a '^backoff:' "$OUT_DIR/run.log" > "$out"
+    if [ ! -s "$out" ]; then
+        echo "$TESTCASE printed nothing when run with REPLAY=$mode:"
+        cat "$OUT_DIR/run.log"
+        exit 1
+    fi
+}
+
+run record "$OUT_DIR/record.out"
+run replay "$OUT_DIR/replay1.out"
+run replay "$OUT_DIR/replay2.out"
+
+for replay in replay1 replay2; do
+    if ! cmp -s "$OUT_DIR/record.out" "$OUT_DIR/$replay.out"; then
+        echo "$replay differs from the recording:"
+        diff "$OUT_DIR/record.out" "$OUT_DIR/$replay.out"
+        exit 1
+    fi
+done
+echo "replays of $TESTCASE match the recording"
+rm -rf "$OUT_DIR"
@@ -0,0 +1,59 @@
+//! Where the wall clock starts at boot.
+//!
+//! Without an RTC, as on the QEMU configs built without the `rtc` feature,
+//! the wall clock would start at the epoch, and a file made would be no
+//! newer than the files of the disk image, so `make` would build the same
+//! outputs again and again. So the first of these which tells it sets the
+//! time of boot, and the log says which:
+//!
+//! - The `CLOCK` make variable, set at build time through `AX_CLOCK`, in
+//!   seconds since the epoch, which pins it, for runs which see the same
+//!   time each. The kernel has no command line to take it from.
+//! - The RTC, which `axhal` reads as it starts.
+//! - The newest modification time of the files of the root filesystem,
+//!   plus [`PAST_NEWEST`], if it is vfat, whose entries have one. ext4, as
+//!   `axfs` mounts it, has none to read, see [`starry_api::file::timestamps`],
+//!   so the clock starts just after the epoch there.
+//!
+//! No device tree or `fw_cfg` timestamp is looked for, as none of the QEMU
+//! configs here has one. Whatever it comes from, the time of boot is the
+//! epoch offset of `axhal`, which the wall time is the monotonic time plus.
+
+use axhal::time::{NANOS_PER_SEC, epochoffset_nanos, set_epochoffset_nanos, wall_time};
+
+/// The time of boot, in seconds since the epoch, set at build time with the
+/// `AX_CLOCK` environment variable. Unset or empty, it is not pinned.
+const PINNED: Option<u64> = match option_env!("AX_CLOCK") {
+    Some(secs) if secs.is_empty() => None,
+    Some(secs) => match u64::from_str_radix(secs, 10) {
+        Ok(secs) => Some(secs),
+        Err(_) => panic!("AX_CLOCK is not a number"),
+    },
+    None => None,
+};
+
+/// How far past the newest file of the root filesystem the clock starts
+/// without an RTC: the 2 seconds vfat rounds times down to, rather than 1,
+/// so that a file made at once is dated after it there too.
+const PAST_NEWEST: u64 = 2 * NANOS_PER_SEC;
+
+/// Set the time of boot, from the first source which tells it, and have
+/// `axfs` date what it writes by the wall clock.
+pub fn init() {
+    let (source, offset) = if let Some(secs) = PINNED {
+        ("AX_CLOCK", secs.saturating_mul(NANOS_PER_SEC))
+    } else if epochoffset_nanos() != 0 {
+        ("the RTC", epochoffset_nanos())
+    } else {
+        let newest = axfs::api::newest_fat_modified("/").unwrap_or_default();
+        let newest = (newest.as_nanos() as u64).saturating_add(PAST_NEWEST);
+        ("the root filesystem", newest)
+    };
+    set_epochoffset_nanos(offset);
+    axfs::set_wall_clock(wall_time);
+    info!(
+        "Wall clock from {}: booted {}s after the epoch",
+        source,
+        offset / NANOS_PER_SEC
+    );
+}
@@ -1,16 +1,35 @@
 use alloc::{string::String, sync::Arc};
-use axfs::{CURRENT_DIR, CURRENT_DIR_PATH, api::set_current_dir};
+use axfs::{
+    CURRENT_DIR, CURRENT_DIR_PATH,
+    api::{current_dir, set_current_dir},
+};
 use axhal::arch::UspaceContext;
 use axprocess::{Pid, init_proc};
 use axsignal::Signo;
 use axsync::Mutex;
-use starry_api::file::FD_TABLE;
+use axtask::{AxTaskRef, TaskExtRef};
+use starry_api::{
+    CWD_MOUNT,
+    file::FD_TABLE,
+    mount_ref,
+    path::{CWD_HANDLE, enter_cwd},
+};
 use starry_core::{
     mm::{copy_from_kernel, load_user_app, map_trampoline, new_user_aspace_empty},
-    task::{ProcessData, TaskExt, ThreadData, add_thread_to_table, new_user_task},
+    task::{
+        ExecArgs, ProcessData, TaskExt, ThreadData, add_process_group_to_table,
+        add_thread_to_table, new_user_task,
+    },
 };
 
-pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
+/// Start the user program `args` as a child of init, without waiting for it,
+/// with the environment `envs`, in the directory `cwd` if given, else in its
+/// own.
+///
+/// A relative `cwd` is looked up from the directory of the program. The
+/// program leads a process group of its own, whose ID is its
+/// PID.
+pub fn spawn_user_app(args: &[String], envs: &[String], cwd: Option<&str>) -> AxTaskRef {
     let mut uspace = new_user_aspace_empty()
         .and_then(|mut it| {
             copy_from_kernel(&mut it)?;
@@ -23,8 +42,15 @@ pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
     let (dir, name) = exe_path.rsplit_once('/').unwrap_or(("", &exe_path));
     set_current_dir(dir).expect("Failed to set current dir");
 
-    let (entry_vaddr, ustack_top) = load_user_app(&mut uspace, args, envs)
+    let (entry_vaddr, ustack_top) = load_user_app(&mut uspace, &exe_path, args, envs)
         .unwrap_or_else(|e| panic!("Failed to load user app: {}", e));
+    if let Some(cwd) = cwd {
+        set_current_dir(cwd).unwrap_or_else(|e| panic!("Failed to enter {:?}: {}", cwd, e));
+    }
+    // As `chdir` does, so that the program sees the directory as entered
+    // and keeps its filesystem in use.
+    enter_cwd().expect("Failed to enter current dir");
+    *CWD_MOUNT.get().lock() = current_dir().ok().and_then(|cwd| mount_ref(&cwd));
 
     let uctx = UspaceContext::new(entry_vaddr.into(), ustack_top, 2333);
 
@@ -35,8 +61,11 @@ pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
         exe_path,
         Arc::new(Mutex::new(uspace)),
         Arc::default(),
+        Arc::default(),
+        Arc::default(),
         Some(Signo::SIGCHLD),
     );
+    *process_data.exec_args.write() = Arc::new(ExecArgs::new(args, envs));
 
     FD_TABLE
         .deref_from(&process_data.ns)
@@ -47,9 +76,20 @@ pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
     CURRENT_DIR_PATH
         .deref_from(&process_data.ns)
         .init_new(CURRENT_DIR_PATH.copy_inner());
+    CWD_HANDLE
+        .deref_from(&process_data.ns)
+        .init_new(CWD_HANDLE.copy_inner());
+    CWD_MOUNT
+        .deref_from(&process_data.ns)
+        .init_new(CWD_MOUNT.copy_inner());
 
     let tid = task.id().as_u64() as Pid;
     let process = init_proc().fork(tid).data(process_data).build();
+    // In a process group of its own, like a shell starts a job, so that
+    // the runner can kill whatever it forked along with it.
+    if let Some(group) = process.create_group() {
+        add_process_group_to_table(&group);
+    }
 
     let thread = process
         .new_thread(tid)
@@ -60,7 +100,6 @@ pub fn run_user_app(args: &[String], envs: &[String]) -> Option<i32> {
     task.init_task_ext(TaskExt::new(thread));
 
     let task = axtask::spawn_task(task);
-
-    // TODO: we need a way to wait on the process but not only the main task
-    task.join()
+    task.task_ext().thread_data().set_task(&task);
+    task
 }
@@ -0,0 +1,656 @@
+//! A stub of the GDB remote serial protocol on the second serial port, for
+//! debugging user processes. It is built with the `gdbstub` feature, on
+//! x86_64 only.
+//!
+//! With QEMU, give the port to GDB with `-serial tcp::1235,server,nowait`
+//! after the console, then in GDB:
+//!
+//! ```text
+//! (gdb) target remote :1235
+//! (gdb) monitor attach 5
+//! (gdb) flushregs
+//! ```
+//!
+//! or `target extended-remote :1235` and `attach 5`. The stub debugs one
+//! process at a time. It stops it with the job control machinery, like a
+//! stop signal but without telling the parent, and reads and writes the
+//! registers its threads saved on the way back to user space. A thread
+//! blocked in a syscall has no registers to show until it comes back.
+//!
+//! Software breakpoints, memory, and `c` and `s` are supported; watchpoints,
+//! `vCont` and the binary `X` packet are not, and GDB does without them.
+
+use alloc::{
+    collections::btree_map::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{fmt::Write, str, time::Duration};
+
+use axhal::{
+    arch::TrapFrame,
+    console::aux,
+    mem::VirtAddr,
+    time::monotonic_time,
+    trap::{USER_DEBUG, register_trap_handler},
+};
+use axprocess::{Pid, Process, Thread};
+use axsignal::{SignalInfo, Signo};
+use axsync::Mutex;
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::SI_KERNEL;
+use starry_api::signal::send_signal_process;
+use starry_core::{
+    task::{ProcessData, ThreadData, get_process, get_thread},
+    wait::WaitStatus,
+};
+
+/// The trap flag of `RFLAGS`, which makes the CPU raise `#DB` after each
+/// instruction.
+const TRAP_FLAG: u64 = 1 << 8;
+/// The flags of `RFLAGS` which GDB may change.
+const USER_FLAGS: u64 = 0xcd5;
+/// The software breakpoint instruction, `int3`.
+const INT3: u8 = 0xcc;
+/// The end of the user half of the address space.
+const USER_END: u64 = 1 << 47;
+/// The longest packet taken, in bytes, told to GDB in `qSupported`.
+const MAX_PACKET: usize = 0x1000;
+/// The byte GDB sends to interrupt the target.
+const INTERRUPT: u8 = 0x03;
+
+/// How often the port is polled, as it raises no interrupt.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+/// How long to wait for a thread of a stopped process to save its
+/// registers.
+const PARK_TIMEOUT: Duration = Duration::from_millis(100);
+const STACK_SIZE: usize = 0x10000;
+
+/// What the stub shares with the threads it debugs.
+struct Shared {
+    /// The process being debugged.
+    target: Option<Pid>,
+    /// The breakpoints inserted, with the bytes they replaced.
+    breakpoints: BTreeMap<usize, u8>,
+    /// The thread which stopped the process last, and whether it was on a
+    /// breakpoint.
+    last_stop: Option<(Pid, bool)>,
+}
+
+static SHARED: Mutex<Shared> = Mutex::new(Shared {
+    target: None,
+    breakpoints: BTreeMap::new(),
+    last_stop: None,
+});
+
+/// Start the stub on the second serial port.
+pub fn init() {
+    aux::init();
+    axtask::spawn_raw(serve, "gdbstub".to_string(), STACK_SIZE);
+    info!("gdbstub: listening on the second serial port");
+}
+
+/// Stop the process for a breakpoint or a single step of the current
+/// thread, if it is being debugged. It parks on its way back to user space.
+#[register_trap_handler(USER_DEBUG)]
+fn handle_user_debug(tf: &mut TrapFrame, single_step: bool) -> bool {
+    let curr = current();
+    let thread = &curr.task_ext().thread;
+    let shared = SHARED.lock();
+    if shared.target != Some(thread.process().pid()) {
+        return false;
+    }
+    // `int3` leaves the PC after itself. Back on the breakpoint, the thread
+    // hits it again if it is still there once it goes on.
+    let swbreak = !single_step && shared.breakpoints.contains_key(&(tf.rip as usize - 1));
+    drop(shared);
+    if swbreak {
+        tf.rip -= 1;
+    }
+    if single_step {
+        tf.rflags &= !TRAP_FLAG;
+    }
+    if curr
+        .task_ext()
+        .process_data()
+        .job
+        .trace_stop(Signo::SIGTRAP)
+    {
+        SHARED.lock().last_stop = Some((thread.tid(), swbreak));
+    }
+    true
+}
+
+fn getchar() -> u8 {
+    loop {
+        if let Some(c) = aux::getchar() {
+            return c;
+        }
+        axtask::sleep(POLL_INTERVAL);
+    }
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|it| it as u8)
+}
+
+fn parse_hex(s: &[u8]) -> Option<usize> {
+    if s.is_empty() || s.len() > 16 {
+        return None;
+    }
+    s.iter()
+        .try_fold(0usize, |acc, &c| Some((acc << 4) | hex_value(c)? as usize))
+}
+
+fn decode_hex(s: &[u8]) -> Option<Vec<u8>> {
+    let chunks = s.chunks_exact(2);
+    if !chunks.remainder().is_empty() {
+        return None;
+    }
+    chunks
+        .map(|it| Some((hex_value(it[0])? << 4) | hex_value(it[1])?))
+        .collect()
+}
+
+fn encode_hex(out: &mut String, bytes: &[u8]) {
+    for b in bytes {
+        write!(out, "{:02x}", b).unwrap();
+    }
+}
+
+/// Parse `addr,len`.
+fn parse_range(s: &[u8]) -> Option<(usize, usize)> {
+    let comma = s.iter().position(|&c| c == b',')?;
+    Some((parse_hex(&s[..comma])?, parse_hex(&s[comma + 1..])?))
+}
+
+/// The number GDB gives `signo`, which differs from Linux for some.
+fn gdb_signal(signo: Signo) -> u8 {
+    match signo {
+        Signo::SIGBUS => 10,
+        Signo::SIGUSR1 => 30,
+        Signo::SIGUSR2 => 31,
+        Signo::SIGCHLD => 20,
+        Signo::SIGCONT => 19,
+        Signo::SIGSTOP => 17,
+        Signo::SIGTSTP => 18,
+        signo => signo as u8,
+    }
+}
+
+/// Read a packet and acknowledge it. Returns `None` for an interrupt.
+fn read_packet() -> Option<Vec<u8>> {
+    loop {
+        match getchar() {
+            INTERRUPT => return None,
+            b'$' => {}
+            _ => continue,
+        }
+        let mut data = Vec::new();
+        let mut sum = 0u8;
+        loop {
+            let c = getchar();
+            if c == b'#' {
+                break;
+            }
+            sum = sum.wrapping_add(c);
+            if data.len() <= MAX_PACKET {
+                data.push(c);
+            }
+        }
+        let checksum = [getchar(), getchar()];
+        if data.len() <= MAX_PACKET && parse_hex(&checksum) == Some(sum as usize) {
+            aux::write_bytes(b"+");
+            return Some(data);
+        }
+        aux::write_bytes(b"-");
+    }
+}
+
+/// Send a packet until GDB acknowledges it.
+fn send_packet(data: &str) {
+    let sum = data.bytes().fold(0u8, |acc, c| acc.wrapping_add(c));
+    let mut packet = String::with_capacity(data.len() + 4);
+    write!(packet, "${}#{:02x}", data, sum).unwrap();
+    loop {
+        aux::write_bytes(packet.as_bytes());
+        match getchar() {
+            b'+' => return,
+            b'-' => continue,
+            // Anything else means GDB went away, or does not acknowledge.
+            _ => return,
+        }
+    }
+}
+
+fn serve() {
+    let mut stub = Stub::default();
+    loop {
+        // An interrupt while the process is not running means nothing.
+        if let Some(packet) = read_packet() {
+            if let Some(reply) = stub.handle(&packet) {
+                send_packet(&reply);
+            }
+        }
+    }
+}
+
+/// The state of the stub kept by its own task.
+#[derive(Default)]
+struct Stub {
+    /// The process being debugged.
+    target: Option<Arc<Process>>,
+    /// The thread chosen with `Hg`, whose registers are read and written.
+    thread: Option<Pid>,
+}
+
+impl Stub {
+    /// Handle a packet, returning the reply, if there is one.
+    fn handle(&mut self, packet: &[u8]) -> Option<String> {
+        let Some((&kind, args)) = packet.split_first() else {
+            return Some(String::new());
+        };
+        let reply = match kind {
+            b'?' => self.stop_reply(),
+            b'g' => self.read_registers(),
+            b'G' => self.write_registers(args),
+            b'm' => self.read_memory(args),
+            b'M' => self.write_memory(args),
+            b'Z' | b'z' => self.breakpoint(args, kind == b'Z'),
+            b'c' => self.resume(false),
+            b's' => self.resume(true),
+            b'H' => self.set_thread(args),
+            b'T' => match self.thread_of(args) {
+                Some(_) => "OK".into(),
+                None => "E03".into(),
+            },
+            b'D' => {
+                self.detach();
+                "OK".into()
+            }
+            b'k' => {
+                self.kill();
+                return None;
+            }
+            b'q' => self.query(args),
+            b'v' => self.verbose(args),
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    fn data(&self) -> Option<&ProcessData> {
+        self.target.as_ref()?.data::<ProcessData>()
+    }
+
+    /// The thread of the process numbered by `s`, in hex.
+    fn thread_of(&self, s: &[u8]) -> Option<Arc<Thread>> {
+        let tid = parse_hex(s)? as Pid;
+        let thread = get_thread(tid).ok()?;
+        (Some(thread.process().pid()) == self.target.as_ref().map(|it| it.pid())).then_some(thread)
+    }
+
+    /// The thread to show: the one chosen, else the one which stopped the
+    /// process, else any.
+    fn current_thread(&self) -> Option<Arc<Thread>> {
+        let proc = self.target.as_ref()?;
+        let last_stop = SHARED.lock().last_stop.map(|(tid, _)| tid);
+        [self.thread, last_stop]
+            .into_iter()
+            .flatten()
+            .filter_map(|tid| get_thread(tid).ok())
+            .find(|thread| thread.process().pid() == proc.pid())
+            .or_else(|| proc.threads().into_iter().next())
+    }
+
+    /// Run `f` on the registers of the current thread, waiting a little for
+    /// it to save them if the process was just stopped.
+    fn with_frame<R>(&self, f: impl FnOnce(&mut TrapFrame) -> R) -> Option<R> {
+        let thread = self.current_thread()?;
+        let data = thread.data::<ThreadData>()?;
+        let deadline = monotonic_time() + PARK_TIMEOUT;
+        let mut f = Some(f);
+        loop {
+            if let Some(result) = data.with_stopped_frame(|tf| f.take().unwrap()(tf)) {
+                return Some(result);
+            }
+            if !self.data()?.job.is_stopped() || monotonic_time() >= deadline {
+                return None;
+            }
+            axtask::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn stop_reply(&mut self) -> String {
+        let Some(data) = self.data() else {
+            // Nothing attached yet. GDB wants a stop all the same.
+            return "S05".into();
+        };
+        let signo = data.job.stopped_by().unwrap_or(Signo::SIGTRAP);
+        let Some(thread) = self.current_thread() else {
+            return "S05".into();
+        };
+        let mut reply = String::new();
+        write!(
+            reply,
+            "T{:02x}thread:{:x};",
+            gdb_signal(signo),
+            thread.tid()
+        )
+        .unwrap();
+        if SHARED.lock().last_stop == Some((thread.tid(), true)) {
+            reply.push_str("swbreak:;");
+        }
+        self.thread = Some(thread.tid());
+        reply
+    }
+
+    /// The registers in the order of the `g` packet of GDB for amd64: the
+    /// general ones and `rip`, then `eflags` and the segments, 32 bits each.
+    /// The FPU ones which follow are left out, which GDB accepts.
+    fn read_registers(&self) -> String {
+        const LEN: usize = 17 * 8 + 7 * 4;
+        let mut reply = String::with_capacity(LEN * 2);
+        let read = self.with_frame(|tf| {
+            for reg in [
+                tf.rax, tf.rbx, tf.rcx, tf.rdx, tf.rsi, tf.rdi, tf.rbp, tf.rsp, tf.r8, tf.r9,
+                tf.r10, tf.r11, tf.r12, tf.r13, tf.r14, tf.r15, tf.rip,
+            ] {
+                encode_hex(&mut reply, &reg.to_le_bytes());
+            }
+            for reg in [tf.rflags, tf.cs, tf.ss, 0, 0, 0, 0] {
+                encode_hex(&mut reply, &(reg as u32).to_le_bytes());
+            }
+        });
+        if read.is_none() {
+            // Unavailable, as the thread has not saved them.
+            reply = "xx".repeat(LEN);
+        }
+        reply
+    }
+
+    fn write_registers(&self, args: &[u8]) -> String {
+        let Some(bytes) = decode_hex(args).filter(|it| it.len() >= 17 * 8 + 4) else {
+            return "E22".into();
+        };
+        let reg = |i: usize| u64::from_le_bytes(bytes[i * 8..i * 8 + 8].try_into().unwrap());
+        if reg(16) >= USER_END {
+            return "E22".into();
+        }
+        let flags = u32::from_le_bytes(bytes[17 * 8..17 * 8 + 4].try_into().unwrap()) as u64;
+        let written = self.with_frame(|tf| {
+            [
+                tf.rax, tf.rbx, tf.rcx, tf.rdx, tf.rsi, tf.rdi, tf.rbp, tf.rsp, tf.r8, tf.r9,
+                tf.r10, tf.r11, tf.r12, tf.r13, tf.r14, tf.r15, tf.rip,
+            ] = core::array::from_fn(reg);
+            tf.rflags = (tf.rflags & !USER_FLAGS) | (flags & USER_FLAGS);
+        });
+        match written {
+            Some(()) => "OK".into(),
+            None => "E03".into(),
+        }
+    }
+
+    fn read_memory(&self, args: &[u8]) -> String {
+        let (Some(data), Some((addr, len))) = (self.data(), parse_range(args)) else {
+            return "E22".into();
+        };
+        let mut buf = alloc::vec![0; len.min(MAX_PACKET / 2)];
+        if data
+            .aspace
+            .lock()
+            .read(VirtAddr::from(addr), &mut buf)
+            .is_err()
+        {
+            return "E14".into();
+        }
+        // Show what the breakpoints replaced.
+        for (&bp, &orig) in SHARED.lock().breakpoints.range(addr..addr + buf.len()) {
+            buf[bp - addr] = orig;
+        }
+        let mut reply = String::with_capacity(buf.len() * 2);
+        encode_hex(&mut reply, &buf);
+        reply
+    }
+
+    fn write_memory(&self, args: &[u8]) -> String {
+        let Some(colon) = args.iter().position(|&c| c == b':') else {
+            return "E22".into();
+        };
+        let (Some(data), Some((addr, len)), Some(bytes)) = (
+            self.data(),
+            parse_range(&args[..colon]),
+            decode_hex(&args[colon + 1..]),
+        ) else {
+            return "E22".into();
+        };
+        if bytes.len() != len {
+            return "E22".into();
+        }
+        match data.aspace.lock().write(VirtAddr::from(addr), &bytes) {
+            Ok(()) => "OK".into(),
+            Err(_) => "E14".into(),
+        }
+    }
+
+    /// Insert or remove a software breakpoint, `0,addr,kind`.
+    fn breakpoint(&self, args: &[u8], insert: bool) -> String {
+        let Some(args) = args.strip_prefix(b"0,") else {
+            // Other kinds are not supported.
+            return String::new();
+        };
+        let (Some(data), Some((addr, _))) = (self.data(), parse_range(args)) else {
+            return "E22".into();
+        };
+        let aspace = data.aspace.lock();
+        let mut shared = SHARED.lock();
+        let result = if insert {
+            if shared.breakpoints.contains_key(&addr) {
+                return "OK".into();
+            }
+            let mut orig = [0];
+            aspace
+                .read(VirtAddr::from(addr), &mut orig)
+                .and_then(|_| aspace.write(VirtAddr::from(addr), &[INT3]))
+                .map(|_| shared.breakpoints.insert(addr, orig[0]))
+        } else {
+            match shared.breakpoints.remove(&addr) {
+                Some(orig) => aspace.write(VirtAddr::from(addr), &[orig]).map(|_| None),
+                None => Ok(None),
+            }
+        };
+        match result {
+            Ok(_) => "OK".into(),
+            Err(_) => "E14".into(),
+        }
+    }
+
+    /// Continue the process, or step the current thread, and wait for it
+    /// to stop again.
+    fn resume(&mut self, step: bool) -> String {
+        let Some(data) = self.data() else {
+            return "E03".into();
+        };
+        if step && self.with_frame(|tf| tf.rflags |= TRAP_FLAG).is_none() {
+            return "E03".into();
+        }
+        SHARED.lock().last_stop = None;
+        data.job.trace_resume();
+        self.wait_stop()
+    }
+
+    /// Wait until the process stops or exits, and report it. An interrupt
+    /// from GDB stops it.
+    fn wait_stop(&mut self) -> String {
+        let Some(proc) = self.target.clone() else {
+            return "E03".into();
+        };
+        let data = proc.data::<ProcessData>().unwrap();
+        loop {
+            if proc.is_zombie() {
+                self.forget();
+                return match WaitStatus::decode(proc.exit_code()) {
+                    Some(WaitStatus::Signaled { signo, .. }) => {
+                        alloc::format!("X{:02x}", gdb_signal(signo))
+                    }
+                    Some(WaitStatus::Exited(code)) => alloc::format!("W{:02x}", code),
+                    _ => "W00".into(),
+                };
+            }
+            if data.job.is_stopped() {
+                // Give the thread to report the time to save its registers.
+                self.with_frame(|_| ());
+                return self.stop_reply();
+            }
+            if aux::getchar() == Some(INTERRUPT) {
+                data.job.trace_stop(Signo::SIGINT);
+            }
+            axtask::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn set_thread(&mut self, args: &[u8]) -> String {
+        let Some((&op, tid)) = args.split_first() else {
+            return "E22".into();
+        };
+        if op != b'g' || tid == b"0" || tid == b"-1" {
+            // `Hc` chooses the thread to step, which is the current one.
+            return "OK".into();
+        }
+        match self.thread_of(tid) {
+            Some(thread) => {
+                self.thread = Some(thread.tid());
+                "OK".into()
+            }
+            None => "E03".into(),
+        }
+    }
+
+    fn query(&mut self, args: &[u8]) -> String {
+        let mut reply = String::new();
+        if args.starts_with(b"Supported") {
+            write!(reply, "PacketSize={:x};swbreak+", MAX_PACKET).unwrap();
+        } else if args == b"C" {
+            if let Some(thread) = self.current_thread() {
+                write!(reply, "QC{:x}", thread.tid()).unwrap();
+            }
+        } else if args == b"fThreadInfo" {
+            let tids: Vec<_> = self
+                .target
+                .as_ref()
+                .map(|it| it.threads())
+                .unwrap_or_default()
+                .iter()
+                .map(|it| alloc::format!("{:x}", it.tid()))
+                .collect();
+            if tids.is_empty() {
+                reply.push('l');
+            } else {
+                write!(reply, "m{}", tids.join(",")).unwrap();
+            }
+        } else if args == b"sThreadInfo" {
+            reply.push('l');
+        } else if args == b"Attached" {
+            reply.push('1');
+        } else if let Some(cmd) = args.strip_prefix(b"Rcmd,") {
+            reply = self.monitor(cmd);
+        }
+        reply
+    }
+
+    fn verbose(&mut self, args: &[u8]) -> String {
+        let Some(pid) = args.strip_prefix(b"Attach;") else {
+            return String::new();
+        };
+        match parse_hex(pid)
+            .ok_or("bad pid")
+            .and_then(|it| self.attach(it as Pid))
+        {
+            Ok(()) => self.stop_reply(),
+            Err(_) => "E01".into(),
+        }
+    }
+
+    /// Run a `monitor` command, given in hex, and show what it says with an
+    /// `O` packet.
+    fn monitor(&mut self, cmd: &[u8]) -> String {
+        let Some(cmd) = decode_hex(cmd) else {
+            return "E22".into();
+        };
+        let cmd = str::from_utf8(&cmd).unwrap_or_default().trim();
+        let (output, ok) = match cmd.split_once(' ') {
+            Some(("attach", pid)) => match pid
+                .trim()
+                .parse()
+                .map_err(|_| "bad pid")
+                .and_then(|it| self.attach(it))
+            {
+                Ok(()) => (alloc::format!("attached to {}\n", pid.trim()), true),
+                Err(err) => (alloc::format!("attach: {}\n", err), false),
+            },
+            _ if cmd == "detach" => {
+                self.detach();
+                ("detached\n".into(), true)
+            }
+            _ => ("commands: attach <pid>, detach\n".into(), cmd == "help"),
+        };
+        let mut packet = String::from("O");
+        encode_hex(&mut packet, output.as_bytes());
+        send_packet(&packet);
+        if ok { "OK".into() } else { "E01".into() }
+    }
+
+    /// Debug the process `pid`, stopping it.
+    fn attach(&mut self, pid: Pid) -> Result<(), &'static str> {
+        let proc = get_process(pid).map_err(|_| "no such process")?;
+        if proc.is_zombie() {
+            return Err("no such process");
+        }
+        let data = proc.data::<ProcessData>().ok_or("not a user process")?;
+        self.detach();
+        {
+            let mut shared = SHARED.lock();
+            shared.target = Some(pid);
+            shared.last_stop = None;
+        }
+        data.job.trace_stop(Signo::SIGTRAP);
+        self.target = Some(proc);
+        self.thread = None;
+        // Wait for a thread to save its registers, for the first `g`.
+        self.with_frame(|_| ());
+        Ok(())
+    }
+
+    /// Stop debugging the process: take the breakpoints out and continue it.
+    fn detach(&mut self) {
+        let Some(data) = self.data() else {
+            return;
+        };
+        let aspace = data.aspace.lock();
+        for (addr, orig) in core::mem::take(&mut SHARED.lock().breakpoints) {
+            let _ = aspace.write(VirtAddr::from(addr), &[orig]);
+        }
+        drop(aspace);
+        data.job.trace_resume();
+        self.forget();
+    }
+
+    /// Kill the process.
+    fn kill(&mut self) {
+        if let Some(proc) = self.target.clone() {
+            let _ = send_signal_process(&proc, SignalInfo::new(Signo::SIGKILL, SI_KERNEL as _));
+        }
+        self.forget();
+    }
+
+    /// Stop debugging the process, leaving it as it is.
+    fn forget(&mut self) {
+        let mut shared = SHARED.lock();
+        shared.target = None;
+        shared.breakpoints.clear();
+        shared.last_stop = None;
+        self.target = None;
+        self.thread = None;
+    }
+}
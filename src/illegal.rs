@@ -0,0 +1,37 @@
+//! Illegal instructions in user space, among them those of the features
+//! the kernel does not report in `AT_HWCAP`, see [`axhal::hwcap`].
+
+use axhal::{
+    arch::TrapFrame,
+    trap::{ILLEGAL_INSTRUCTION, register_trap_handler},
+};
+use axsignal::Signo;
+use axtask::{TaskExtRef, current};
+use linux_raw_sys::general::{ILL_ILLOPC, ILL_ILLOPN};
+use starry_api::{do_exit, signal::send_fault_signal};
+use starry_core::wait::WaitStatus;
+
+/// The code of `SIGILL`: Linux tells an invalid operand for `#UD` on
+/// x86_64, and an invalid opcode elsewhere.
+const ILL_CODE: u32 = if cfg!(target_arch = "x86_64") {
+    ILL_ILLOPN
+} else {
+    ILL_ILLOPC
+};
+
+/// Send `SIGILL` to the thread, to its handler if it has one, or kill the
+/// process with it.
+#[register_trap_handler(ILLEGAL_INSTRUCTION)]
+fn handle_illegal_instruction(tf: &mut TrapFrame) -> bool {
+    if send_fault_signal(Signo::SIGILL, ILL_CODE, tf.ip()) {
+        return true;
+    }
+    let curr = current();
+    warn!(
+        "{} ({:?}): illegal instruction at {:#x}, exit!",
+        curr.id_name(),
+        curr.task_ext().thread,
+        tf.ip()
+    );
+    do_exit(WaitStatus::signaled(Signo::SIGILL), true);
+}
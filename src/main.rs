@@ -7,30 +7,79 @@ extern crate axlog;
 extern crate alloc;
 extern crate axruntime;
 
+mod clock;
 mod entry;
+#[cfg(all(feature = "gdbstub", target_arch = "x86_64"))]
+mod gdb;
+mod illegal;
 mod mm;
+mod power;
+#[cfg(feature = "replay")]
+mod replay;
+mod runner;
 mod syscall;
 
 #[unsafe(no_mangle)]
 fn main() {
+    power::init();
+    clock::init();
+    starry_core::random::init();
+    starry_core::workqueue::init();
+    #[cfg(feature = "kernel-tests")]
+    {
+        starry_core::workqueue::self_test();
+        starry_core::random::self_test();
+        starry_core::sandbox::self_test();
+        starry_core::pid_table::self_test();
+        starry_api::abi::self_test();
+        starry_api::args::self_test();
+        starry_api::errlog::self_test();
+        starry_api::ptr::self_test();
+    }
     // Create a init process
     axprocess::Process::new_init(axtask::current().id().as_u64() as _).build();
+    #[cfg(feature = "kernel-tests")]
+    {
+        starry_core::pressure::self_test();
+        starry_core::observer::self_test();
+        starry_api::file::pipe_self_test();
+        starry_api::file::fd_self_test();
+    }
+    starry_core::iowait::init();
+    #[cfg(feature = "fault-inject")]
+    starry_core::fault::init();
+    starry_api::mount_boot_tmpfs();
+    #[cfg(all(feature = "gdbstub", target_arch = "x86_64"))]
+    gdb::init();
+    #[cfg(feature = "replay")]
+    replay::init();
 
     let testcases = option_env!("AX_TESTCASES_LIST")
         .unwrap_or_else(|| "Please specify the testcases list by making user_apps")
         .split(',')
         .filter(|&x| !x.is_empty());
+    #[cfg(all(feature = "kernel-tests", feature = "fault-inject"))]
+    starry_core::fault::self_test(
+        testcases
+            .clone()
+            .next()
+            .and_then(|testcase| testcase.split_ascii_whitespace().next()),
+    );
 
+    let mut runner = runner::Runner::default();
     for testcase in testcases {
-        let Some(args) = shlex::split(testcase) else {
-            error!("Failed to parse testcase: {:?}", testcase);
-            continue;
-        };
-        if args.is_empty() {
-            continue;
-        }
-        info!("Running user task: {:?}", args);
-        let exit_code = entry::run_user_app(&args, &[]);
-        info!("User task {:?} exited with code: {:?}", args, exit_code);
+        runner.run(testcase);
+    }
+    let passed = runner.finish();
+    if !passed {
+        error!("Some user tasks failed");
+    }
+    #[cfg(feature = "replay")]
+    replay::finish();
+
+    starry_core::workqueue::shutdown();
+    if let Err(err) = axfs::sync_block_devices() {
+        error!("Failed to sync block devices: {:?}", err);
     }
+    power::init_exited(passed)
 }
@@ -3,14 +3,35 @@ use axhal::{
     paging::MappingFlags,
     trap::{PAGE_FAULT, register_trap_handler},
 };
+use axsignal::Signo;
 use axtask::{TaskExtRef, current};
-use linux_raw_sys::general::SIGSEGV;
-use starry_api::do_exit;
-use starry_core::mm::is_accessing_user_memory;
+use linux_raw_sys::general::{SEGV_ACCERR, SEGV_MAPERR};
+use starry_api::{do_exit, signal::send_fault_signal};
+use starry_core::{mm::is_accessing_user_memory, stats, task::ProcessData, wait::WaitStatus};
+
+/// Handle a fault in a lazily allocated area, with the frame allocated and
+/// zeroed outside the lock of the address space, so that the threads of a
+/// process touching fresh memory fault it in in parallel. The lock is only
+/// held to look up the area and to map the frame.
+///
+/// Returns `None` if the fault is of another kind.
+fn handle_lazy_fault(
+    process_data: &ProcessData,
+    vaddr: VirtAddr,
+    access_flags: MappingFlags,
+) -> Option<bool> {
+    let mut fault = process_data
+        .lock_aspace()
+        .prepare_page_fault(vaddr, access_flags)?;
+    if !fault.alloc_frame() {
+        return Some(false);
+    }
+    Some(process_data.lock_aspace().finish_page_fault(fault))
+}
 
 #[register_trap_handler(PAGE_FAULT)]
 fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool) -> bool {
-    warn!(
+    debug!(
         "Page fault at {:#x}, access_flags: {:#x?}",
         vaddr, access_flags
     );
@@ -19,20 +40,39 @@ fn handle_page_fault(vaddr: VirtAddr, access_flags: MappingFlags, is_user: bool)
     }
 
     let curr = current();
-    if !curr
-        .task_ext()
-        .process_data()
-        .aspace
-        .lock()
-        .handle_page_fault(vaddr, access_flags)
-    {
+    let process_data = curr.task_ext().process_data();
+    let handled = handle_lazy_fault(process_data, vaddr, access_flags).unwrap_or_else(|| {
+        let mut aspace = process_data.lock_aspace();
+        // A fault below a grows-down mapping extends it, then is handled
+        // as usual.
+        aspace.handle_page_fault(vaddr, access_flags)
+            || (process_data.lock_grows_down().grow(&mut aspace, vaddr)
+                && aspace.handle_page_fault(vaddr, access_flags))
+    });
+    if handled {
+        stats::count_page_fault();
+    }
+    // A fault of user code goes to its `SIGSEGV` handler, if any, which
+    // runs on the way back to user space. It tells a mapped address the
+    // access was not allowed to from one not mapped at all.
+    if !handled && is_user {
+        let code = if process_data.lock_aspace().is_mapped(vaddr) {
+            SEGV_ACCERR
+        } else {
+            SEGV_MAPERR
+        };
+        if send_fault_signal(Signo::SIGSEGV, code, vaddr.as_usize()) {
+            return true;
+        }
+    }
+    if !handled {
         warn!(
             "{} ({:?}): segmentation fault at {:#x}, exit!",
             curr.id_name(),
             curr.task_ext().thread,
             vaddr
         );
-        do_exit(SIGSEGV as _, true);
+        do_exit(WaitStatus::signaled(Signo::SIGSEGV), true);
     }
     true
 }
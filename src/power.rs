@@ -0,0 +1,127 @@
+//! What becomes of the machine when the kernel panics, and when init is
+//! done, so that a run under CI ends at once, with a status telling how.
+//!
+//! On a panic, the block caches are written back, unless they are in use,
+//! so that the disk image can be looked at afterwards. The log goes to the
+//! console as it is written, and the output of programs still pending is
+//! written out if it can be, then the console is given [`CONSOLE_DRAIN`] to
+//! output the report. Then, as the `PANIC` make
+//! variable says, set at build time through `AX_PANIC`:
+//!
+//! - `poweroff`, the default, powers off with a failure status.
+//! - `reboot` reboots.
+//! - `halt` stops the CPU which panicked, with interrupts off, leaving the
+//!   machine up for a debugger.
+//!
+//! Init is the kernel task running the testcase list, see [`crate::runner`].
+//! Once it is done, it is reported to the process observers as a zombie,
+//! see [`starry_core::observer`], and the one here powers off, with a
+//! success status if every program passed, and with a failure status
+//! otherwise. No signal kills a kernel
+//! task, so there is no death of init by a signal to report.
+//!
+//! QEMU exits with the status, as far as the machine can tell it:
+//!
+//! | Arch          | Passed | Failed | Panic |
+//! |---------------|--------|--------|-------|
+//! | `x86_64`      | 0      | 3      | 5     |
+//! | `riscv64`     | 0      | 1      | 1     |
+//! | `aarch64`     | 0      | 0      | 0     |
+//! | `loongarch64` | 0      | 0      | 0     |
+//!
+//! `x86_64` goes through the `isa-debug-exit` device, which exits with
+//! twice the code plus one, and `riscv64` through the SBI, which has one
+//! failure status only.
+
+use core::{
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use axprocess::{Pid, init_proc};
+use axruntime::PANIC_FAILURE;
+use starry_core::observer::{ProcessEvent, notify_process_event, register_process_observer};
+
+/// The failure code the machine is powered off with once init is done, if
+/// a program did not pass.
+const INIT_FAILURE: u8 = 1;
+
+/// Whether every program passed, set once init is done.
+static PASSED: AtomicBool = AtomicBool::new(false);
+
+/// How long the console is given to output the report of a panic.
+const CONSOLE_DRAIN: Duration = Duration::from_millis(100);
+
+/// What to do after a panic.
+enum PanicAction {
+    PowerOff,
+    Reboot,
+    Halt,
+}
+
+/// What to do after a panic, set at build time with the `AX_PANIC`
+/// environment variable.
+const PANIC_ACTION: PanicAction = match option_env!("AX_PANIC") {
+    None => PanicAction::PowerOff,
+    Some(action) => match action.as_bytes() {
+        b"" | b"poweroff" => PanicAction::PowerOff,
+        b"reboot" => PanicAction::Reboot,
+        b"halt" => PanicAction::Halt,
+        _ => panic!("AX_PANIC is not one of poweroff, reboot and halt"),
+    },
+};
+
+/// Install the panic hook, and the observer powering off once init exits.
+pub fn init() {
+    axruntime::set_panic_hook(on_panic);
+    register_process_observer(on_process_event);
+}
+
+fn on_panic(_info: &PanicInfo) -> ! {
+    for dev in axfs::block_devices() {
+        match dev.try_sync() {
+            Some(Ok(())) => {}
+            Some(Err(err)) => error!("Failed to sync {}: {:?}", dev.name(), err),
+            None => error!("Not syncing {}, which is in use", dev.name()),
+        }
+    }
+    starry_api::file::flush_output_on_panic();
+    axhal::time::busy_wait(CONSOLE_DRAIN);
+    match PANIC_ACTION {
+        PanicAction::PowerOff => axhal::misc::terminate_failure(PANIC_FAILURE),
+        PanicAction::Reboot => axhal::misc::reboot(),
+        PanicAction::Halt => {
+            ax_println!("Halted after the panic");
+            axhal::arch::disable_irqs();
+            loop {
+                axhal::arch::halt();
+            }
+        }
+    }
+}
+
+/// Report that init is done, with `passed` set if every program passed.
+///
+/// Init is a kernel task, which does not go through the teardown of user
+/// processes, so it is reported as a zombie here.
+pub fn init_exited(passed: bool) -> ! {
+    PASSED.store(passed, Ordering::Release);
+    notify_process_event(init_proc().pid(), ProcessEvent::Zombie);
+    unreachable!("Init exited without powering off");
+}
+
+/// Power off once init is a zombie.
+fn on_process_event(pid: Pid, event: ProcessEvent) {
+    if event != ProcessEvent::Zombie || pid != init_proc().pid() {
+        return;
+    }
+    // The output of the programs may be pending still, and its last line
+    // unfinished, and held back.
+    starry_api::file::flush_output();
+    if PASSED.load(Ordering::Acquire) {
+        axhal::misc::terminate()
+    } else {
+        axhal::misc::terminate_failure(INIT_FAILURE)
+    }
+}
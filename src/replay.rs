@@ -0,0 +1,254 @@
+//! Recording the time and randomness user programs see, and replaying them,
+//! to reproduce flaky runs.
+//!
+//! With `AX_REPLAY=record` at build time, every syscall appends a record of
+//! the calling task, the syscall and its result to a log in memory, with
+//! what it wrote to user space if it read the time or randomness. The log is
+//! written to [`LOG_PATH`] once the user programs are done.
+//!
+//! With `AX_REPLAY=replay`, the log is read back at boot, and each task goes
+//! through its own records in order. The syscalls reading the time or
+//! randomness are not run, but write and return what was recorded, and the
+//! others run as usual. A task making another syscall than recorded, or more,
+//! stops the kernel with a report of where it diverged.
+//!
+//! Scheduling is not replayed, so tasks racing on anything else can still
+//! diverge. Tasks are told apart by their ids, which come out the same as
+//! long as the programs create tasks in the same order.
+//!
+//! The log starts with [`MAGIC`] and the name of the architecture in 16
+//! bytes padded with zeros. Each record is then, in little endian, the task
+//! id in 4 bytes, the syscall number in 2, the length of the data in 4, the
+//! result in 8, and the data. `tools/replay_dump.py` prints it.
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    vec::Vec,
+};
+use axerrno::LinuxError;
+use axhal::arch::TrapFrame;
+use axsync::Mutex;
+use linux_raw_sys::general::{timespec, timeval};
+use starry_api::{
+    Tms,
+    ptr::{UserConstPtr, UserPtr},
+};
+use syscalls::Sysno;
+
+/// Where the log is kept, on the root filesystem.
+const LOG_PATH: &str = "/replay.log";
+
+/// The first bytes of the log, with its version.
+const MAGIC: &[u8; 8] = b"STRYRPL1";
+
+/// The length of the architecture name after [`MAGIC`].
+const ARCH_LEN: usize = 16;
+
+/// The length of a record without its data.
+const RECORD_HEADER_LEN: usize = 18;
+
+/// The architecture, which a log is replayed on only if recorded on.
+const ARCH: &str = match option_env!("AX_ARCH") {
+    Some(arch) => arch,
+    None => "",
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Record,
+    Replay,
+}
+
+fn mode() -> Mode {
+    match option_env!("AX_REPLAY") {
+        Some("replay") => Mode::Replay,
+        Some("record") | None => Mode::Record,
+        Some(mode) => panic!("Unknown replay mode {:?}", mode),
+    }
+}
+
+struct Record {
+    sysno: u16,
+    result: i64,
+    data: Vec<u8>,
+}
+
+/// The records of a task to replay.
+#[derive(Default)]
+struct TaskRecords {
+    /// How many were replayed.
+    done: usize,
+    left: VecDeque<Record>,
+}
+
+/// The log being recorded.
+static LOG: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+/// The records to replay, by task.
+static REPLAY: Mutex<BTreeMap<u32, TaskRecords>> = Mutex::new(BTreeMap::new());
+
+fn current_tid() -> u32 {
+    axtask::current().id().as_u64() as u32
+}
+
+/// Whether `sysno` reads the time or randomness, which is replayed rather
+/// than run.
+fn is_replayed(sysno: Sysno) -> bool {
+    matches!(
+        sysno,
+        Sysno::gettimeofday | Sysno::clock_gettime | Sysno::times | Sysno::getrandom
+    )
+}
+
+/// Where a replayed syscall succeeding with `result` wrote, and how much.
+fn output(tf: &TrapFrame, sysno: Sysno, result: isize) -> (usize, usize) {
+    match sysno {
+        Sysno::gettimeofday => (tf.arg0(), size_of::<timeval>()),
+        Sysno::clock_gettime => (tf.arg1(), size_of::<timespec>()),
+        Sysno::times => (tf.arg0(), size_of::<Tms>()),
+        Sysno::getrandom => (tf.arg0(), result as usize),
+        _ => unreachable!(),
+    }
+}
+
+fn append(tid: u32, sysno: Sysno, result: isize, data: &[u8]) {
+    let mut log = LOG.lock();
+    log.extend_from_slice(&tid.to_le_bytes());
+    log.extend_from_slice(&(sysno.id() as u16).to_le_bytes());
+    log.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    log.extend_from_slice(&(result as i64).to_le_bytes());
+    log.extend_from_slice(data);
+}
+
+/// Take the next record of task `tid`, which must be of `sysno`.
+fn next_record(tid: u32, sysno: Sysno) -> Record {
+    let mut replay = REPLAY.lock();
+    let task = replay.entry(tid).or_default();
+    let index = task.done;
+    let Some(record) = task.left.pop_front() else {
+        panic!(
+            "Replay diverged: task {} made syscall {} as its #{}, after all recorded",
+            tid, sysno, index
+        );
+    };
+    if record.sysno as u32 != sysno.id() as u32 {
+        panic!(
+            "Replay diverged: task {} made syscall {} as its #{}, recorded {}",
+            tid,
+            sysno,
+            index,
+            Sysno::from(record.sysno as u32)
+        );
+    }
+    task.done += 1;
+    record
+}
+
+/// Read the log to replay, in replay mode.
+pub fn init() {
+    if mode() != Mode::Replay {
+        info!("Recording syscalls to {}", LOG_PATH);
+        return;
+    }
+    let log = axfs::api::read(LOG_PATH)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {:?}", LOG_PATH, e));
+    let mut arch = [0; ARCH_LEN];
+    arch[..ARCH.len()].copy_from_slice(ARCH.as_bytes());
+    assert!(
+        log.starts_with(MAGIC) && log[MAGIC.len()..].starts_with(&arch),
+        "{} is not a log of {}",
+        LOG_PATH,
+        ARCH
+    );
+
+    let mut replay = REPLAY.lock();
+    let mut rest = &log[MAGIC.len() + ARCH_LEN..];
+    let mut count = 0;
+    while !rest.is_empty() {
+        assert!(rest.len() >= RECORD_HEADER_LEN, "{} is truncated", LOG_PATH);
+        let (header, data) = rest.split_at(RECORD_HEADER_LEN);
+        let tid = u32::from_le_bytes(header[0..4].try_into().unwrap());
+        let len = u32::from_le_bytes(header[6..10].try_into().unwrap()) as usize;
+        assert!(data.len() >= len, "{} is truncated", LOG_PATH);
+        replay.entry(tid).or_default().left.push_back(Record {
+            sysno: u16::from_le_bytes(header[4..6].try_into().unwrap()),
+            result: i64::from_le_bytes(header[10..18].try_into().unwrap()),
+            data: data[..len].to_vec(),
+        });
+        rest = &data[len..];
+        count += 1;
+    }
+    info!("Replaying {} syscalls from {}", count, LOG_PATH);
+}
+
+/// Called before running a syscall. Returns its result if it is replayed
+/// instead.
+pub fn before_syscall(tf: &TrapFrame, sysno: Sysno) -> Option<isize> {
+    let tid = current_tid();
+    match mode() {
+        Mode::Record => {
+            // They do not return to be recorded after.
+            if matches!(sysno, Sysno::exit | Sysno::exit_group) {
+                append(tid, sysno, 0, &[]);
+            }
+            None
+        }
+        Mode::Replay => {
+            let record = next_record(tid, sysno);
+            if !is_replayed(sysno) {
+                return None;
+            }
+            let result = record.result as isize;
+            if result >= 0 {
+                let (addr, _) = output(tf, sysno, result);
+                let Ok(buf) = UserPtr::<u8>::from(addr).get_as_mut_slice(record.data.len()) else {
+                    return Some(-LinuxError::EFAULT.code() as isize);
+                };
+                buf.copy_from_slice(&record.data);
+            }
+            Some(result)
+        }
+    }
+}
+
+/// Called after running a syscall, which returned `result`.
+pub fn after_syscall(tf: &TrapFrame, sysno: Sysno, result: isize) {
+    if mode() != Mode::Record || matches!(sysno, Sysno::exit | Sysno::exit_group) {
+        return;
+    }
+    let data = if is_replayed(sysno) && result >= 0 {
+        let (addr, len) = output(tf, sysno, result);
+        UserConstPtr::<u8>::from(addr)
+            .get_as_slice(len)
+            .unwrap_or_default()
+    } else {
+        &[]
+    };
+    append(current_tid(), sysno, result, data);
+}
+
+/// Write the log recorded, in record mode.
+pub fn finish() {
+    if mode() != Mode::Record {
+        let left = REPLAY
+            .lock()
+            .values()
+            .map(|task| task.left.len())
+            .sum::<usize>();
+        if left != 0 {
+            warn!("{} recorded syscalls were not replayed", left);
+        }
+        return;
+    }
+    let records = LOG.lock();
+    let mut log = Vec::with_capacity(MAGIC.len() + ARCH_LEN + records.len());
+    log.extend_from_slice(MAGIC);
+    let mut arch = [0; ARCH_LEN];
+    arch[..ARCH.len()].copy_from_slice(ARCH.as_bytes());
+    log.extend_from_slice(&arch);
+    log.extend_from_slice(&records);
+    match axfs::api::write(LOG_PATH, &log) {
+        Ok(()) => info!("Recorded {} bytes of syscalls to {}", log.len(), LOG_PATH),
+        Err(e) => error!("Failed to write {}: {:?}", LOG_PATH, e),
+    }
+}
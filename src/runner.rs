@@ -0,0 +1,273 @@
+//! Runs the user programs of the testcase list.
+//!
+//! Each entry of the list is run after the previous one exited, unless
+//! told otherwise, in a small subset of the shell syntax:
+//!
+//! - `prog args &` starts `prog` in the background, and goes on at once.
+//! - `a & b & c` starts `a`, `b` and `c` together, as a parallel group,
+//!   and goes on once all of them exited. With a trailing `&`, the whole
+//!   group is in the background.
+//! - `wait` waits for all programs in the background.
+//! - `cwd=<dir> prog args` runs `prog` in the directory `dir`, rather than
+//!   in the one `prog` is in, which a relative `dir` is from. The program
+//!   is found as without it.
+//! - `env KEY=VAL... prog args` runs `prog` with the environment variables
+//!   `KEY=VAL`. Without it, the environment is empty.
+//!
+//! The prefixes come in any order before the name of the program, e.g.
+//! `cwd=/tmp env TZ=UTC LANG=C prog`. The directory and the environment of
+//! each program which failed are in the summary, to run it again alike.
+//!
+//! Programs still in the background at the end of the list are waited for
+//! too, and every exit code is accounted for in the summary.
+//!
+//! Each program runs in a process group of its own. One still running
+//! [`TEST_TIMEOUT`] after it started is killed, along with everything else
+//! in its group, and the list goes on.
+
+use alloc::{format, string::String, vec::Vec};
+use core::{fmt, time::Duration};
+
+use axhal::time::monotonic_time;
+use axprocess::Pid;
+use axsignal::{SignalInfo, Signo};
+use axtask::AxTaskRef;
+use linux_raw_sys::general::SI_KERNEL;
+use starry_api::{file::flush_output, signal::send_signal_process_group};
+use starry_core::task::get_process_group;
+
+use crate::entry::spawn_user_app;
+
+/// How long a program may run, set at build time with the
+/// `AX_TEST_TIMEOUT` environment variable, in seconds. Unset, or 0, a
+/// program runs for as long as it takes.
+const TEST_TIMEOUT: Option<Duration> = match option_env!("AX_TEST_TIMEOUT") {
+    Some(secs) => match u64::from_str_radix(secs, 10) {
+        Ok(0) => None,
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => panic!("AX_TEST_TIMEOUT is not a number"),
+    },
+    None => None,
+};
+
+/// A program of the testcase list, with what its prefixes set.
+struct Program {
+    args: Vec<String>,
+    /// The working directory, from `cwd=<dir>`.
+    cwd: Option<String>,
+    /// The environment, from `env KEY=VAL...`.
+    envs: Vec<String>,
+}
+
+impl Program {
+    /// Parse the words of a program, failing if there is none after the
+    /// prefixes.
+    fn parse(mut words: &[String]) -> Option<Self> {
+        let mut cwd = None;
+        let mut envs = Vec::new();
+        loop {
+            match words.first() {
+                Some(word) if word.starts_with("cwd=") => {
+                    cwd = Some(String::from(&word["cwd=".len()..]));
+                    words = &words[1..];
+                }
+                Some(word) if word == "env" => {
+                    words = &words[1..];
+                    while words.first().is_some_and(|word| word.contains('=')) {
+                        envs.push(words[0].clone());
+                        words = &words[1..];
+                    }
+                }
+                Some(_) => break,
+                None => return None,
+            }
+        }
+        Some(Self {
+            args: words.to_vec(),
+            cwd,
+            envs,
+        })
+    }
+
+    /// What the prefixes set, as `, in "<dir>", with ["KEY=VAL", ...]`.
+    fn setup(&self) -> String {
+        let mut setup = String::new();
+        if let Some(cwd) = &self.cwd {
+            setup += &format!(", in {:?}", cwd);
+        }
+        if !self.envs.is_empty() {
+            setup += &format!(", with {:?}", self.envs);
+        }
+        setup
+    }
+}
+
+/// A program started, and not yet waited for.
+struct Running {
+    program: Program,
+    task: AxTaskRef,
+    /// When it is killed if still running.
+    deadline: Option<Duration>,
+}
+
+/// How a program ended.
+enum Outcome {
+    /// It exited with 0.
+    Passed,
+    /// It exited with another code.
+    Failed(i32),
+    /// It was killed, still running after [`TEST_TIMEOUT`].
+    TimedOut,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Passed => write!(f, "passed"),
+            Self::Failed(exit_code) => write!(f, "failed with exit code {}", exit_code),
+            Self::TimedOut => write!(f, "timed out after {:?}", TEST_TIMEOUT.unwrap()),
+        }
+    }
+}
+
+/// A line of the testcase list, parsed.
+enum Entry {
+    /// `wait`.
+    Wait,
+    /// A group of programs started together, in the background if
+    /// `background` is set.
+    Group {
+        programs: Vec<Program>,
+        background: bool,
+    },
+}
+
+fn parse(line: &str) -> Option<Entry> {
+    let words = shlex::split(line)?;
+    if words.len() == 1 && words[0] == "wait" {
+        return Some(Entry::Wait);
+    }
+    let background = words.last().is_some_and(|w| w == "&");
+    let programs = words
+        .split(|w| w == "&")
+        .filter(|args| !args.is_empty())
+        .map(Program::parse)
+        .collect::<Option<Vec<_>>>()?;
+    Some(Entry::Group {
+        programs,
+        background,
+    })
+}
+
+#[derive(Default)]
+pub struct Runner {
+    background: Vec<Running>,
+    outcomes: Vec<(Program, Outcome)>,
+}
+
+impl Runner {
+    fn start(&self, program: Program) -> Running {
+        info!("Running user task: {:?}{}", program.args, program.setup());
+        let task = spawn_user_app(&program.args, &program.envs, program.cwd.as_deref());
+        let deadline = TEST_TIMEOUT.map(|timeout| monotonic_time() + timeout);
+        Running {
+            program,
+            task,
+            deadline,
+        }
+    }
+
+    fn reap(&mut self, running: Running) {
+        // TODO: we need a way to wait on the process but not only the main task
+        let exit_code = match running.deadline {
+            Some(deadline) => running
+                .task
+                .join_timeout(deadline.saturating_sub(monotonic_time())),
+            None => running.task.join(),
+        };
+        let outcome = match exit_code {
+            Some(0) => Outcome::Passed,
+            Some(exit_code) => Outcome::Failed(exit_code),
+            None => {
+                kill_group(running.task.id().as_u64() as Pid);
+                running.task.join();
+                Outcome::TimedOut
+            }
+        };
+        // For what it wrote to go out before what comes of it.
+        flush_output();
+        info!("User task {:?} {}", running.program.args, outcome);
+        self.outcomes.push((running.program, outcome));
+    }
+
+    fn wait_background(&mut self) {
+        for running in core::mem::take(&mut self.background) {
+            self.reap(running);
+        }
+    }
+
+    /// Run the entry on `line` of the testcase list.
+    pub fn run(&mut self, line: &str) {
+        match parse(line) {
+            None => error!("Failed to parse testcase: {:?}", line),
+            Some(Entry::Wait) => self.wait_background(),
+            Some(Entry::Group {
+                programs,
+                background,
+            }) => {
+                let group = programs
+                    .into_iter()
+                    .map(|program| self.start(program))
+                    .collect::<Vec<_>>();
+                if background {
+                    self.background.extend(group);
+                } else {
+                    for running in group {
+                        self.reap(running);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Wait for the programs left in the background, and print how each
+    /// program ended, with the directory and the environment of those which
+    /// did not pass, then how many passed, failed and timed out.
+    ///
+    /// Returns whether all of them exited with 0.
+    pub fn finish(mut self) -> bool {
+        self.wait_background();
+        let (mut passed, mut failed, mut timed_out) = (0, 0, 0);
+        for (program, outcome) in &self.outcomes {
+            match outcome {
+                Outcome::Passed => passed += 1,
+                Outcome::Failed(_) => failed += 1,
+                Outcome::TimedOut => timed_out += 1,
+            }
+            match outcome {
+                Outcome::Passed => ax_println!("User task {:?} {}", program.args, outcome),
+                _ => ax_println!(
+                    "User task {:?} {}{}",
+                    program.args,
+                    outcome,
+                    program.setup()
+                ),
+            }
+        }
+        ax_println!(
+            "User tasks: {} passed, {} failed, {} timed out",
+            passed,
+            failed,
+            timed_out
+        );
+        passed == self.outcomes.len()
+    }
+}
+
+/// Kill every process of the group `pgid`, which the program it is named
+/// after leads, and whatever it forked stays in unless it moved out.
+fn kill_group(pgid: Pid) {
+    if let Ok(group) = get_process_group(pgid) {
+        send_signal_process_group(&group, SignalInfo::new(Signo::SIGKILL, SI_KERNEL as _));
+    }
+}
@@ -1,213 +1,449 @@
-use axerrno::LinuxError;
+use axerrno::{LinuxError, LinuxResult};
 use axhal::{
     arch::TrapFrame,
     trap::{SYSCALL, register_trap_handler},
 };
-use starry_api::*;
-use starry_core::task::{time_stat_from_kernel_to_user, time_stat_from_user_to_kernel};
+use axtask::{TaskExtRef, current};
+use starry_api::{abi::SYSCALL_INSN_SIZE, args::SyscallArgs, *};
+use starry_core::{
+    latency, stats,
+    task::{time_stat_from_kernel_to_user, time_stat_from_user_to_kernel, user_trap_frame},
+};
 use syscalls::Sysno;
 
 #[register_trap_handler(SYSCALL)]
 fn handle_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
     let sysno = Sysno::from(syscall_num as u32);
-    info!("Syscall {}", sysno);
-    time_stat_from_user_to_kernel();
-    let result = match sysno {
+    debug!("Syscall {}", sysno);
+    // The handlers take the registers from `tf` alone, see
+    // `user_trap_frame`.
+    debug_assert_eq!(tf as *mut TrapFrame, user_trap_frame(&current()));
+    let entered = time_stat_from_user_to_kernel();
+    stats::count_syscall();
+    if let Err(err) = seccomp::check_syscall(tf, syscall_num) {
+        time_stat_from_kernel_to_user();
+        return -err.code() as _;
+    }
+    #[cfg(feature = "replay")]
+    if let Some(ans) = crate::replay::before_syscall(tf, sysno) {
+        time_stat_from_kernel_to_user();
+        info!("Syscall {:?} replayed {}", sysno, ans);
+        return ans;
+    }
+    let args = SyscallArgs::new(tf);
+    let mut implemented = true;
+    let result = dispatch(tf, sysno, &mut implemented);
+    errlog::log_result(sysno, &args, &result, implemented);
+    let restart = current().task_ext().thread_data().take_restart_syscall();
+    let ans = match result {
+        Err(LinuxError::EINTR) if restart => restart_syscall(tf, syscall_num),
+        result => result.unwrap_or_else(|err| -err.code() as _),
+    };
+    #[cfg(feature = "replay")]
+    crate::replay::after_syscall(tf, sysno, ans);
+    let exited = time_stat_from_kernel_to_user();
+    latency::record_syscall(syscall_num, exited - entered);
+    ans
+}
+
+/// Have the syscall run again once back in user space, see
+/// [`starry_api::blocking`]: move the PC back to the syscall instruction,
+/// and return what the register the result goes to held on entry, the
+/// syscall number on `x86_64` and the first argument elsewhere.
+fn restart_syscall(tf: &mut TrapFrame, syscall_num: usize) -> isize {
+    tf.set_ip(tf.ip() - SYSCALL_INSN_SIZE);
+    if cfg!(target_arch = "x86_64") {
+        syscall_num as _
+    } else {
+        tf.arg0() as _
+    }
+}
+
+/// Run the handler of `sysno`, with the arguments in `tf` decoded to the
+/// types it takes, clearing `implemented` if there is none.
+fn dispatch(tf: &mut TrapFrame, sysno: Sysno, implemented: &mut bool) -> LinuxResult<isize> {
+    let args = SyscallArgs::new(tf);
+    match sysno {
         // fs ctl
-        Sysno::ioctl => sys_ioctl(tf.arg0() as _, tf.arg1() as _, tf.arg2().into()),
-        Sysno::chdir => sys_chdir(tf.arg0().into()),
-        Sysno::mkdirat => sys_mkdirat(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
-        Sysno::getdents64 => sys_getdents64(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        Sysno::ioctl => sys_ioctl(args.fd(0), args.usize(1), args.uptr(2)),
+        Sysno::chdir => sys_chdir(args.cuptr(0)),
+        Sysno::fchdir => sys_fchdir(args.fd(0)),
+        Sysno::mkdirat => sys_mkdirat(args.fd(0), args.cuptr(1), args.uint(2)),
+        Sysno::getdents64 => sys_getdents64(args.fd(0), args.uptr(1), args.usize(2)),
         Sysno::linkat => sys_linkat(
-            tf.arg0() as _,
-            tf.arg1().into(),
-            tf.arg2() as _,
-            tf.arg3().into(),
-            tf.arg4() as _,
+            args.fd(0),
+            args.cuptr(1),
+            args.fd(2),
+            args.cuptr(3),
+            args.int(4),
         ),
         #[cfg(target_arch = "x86_64")]
-        Sysno::link => sys_link(tf.arg0().into(), tf.arg1().into()),
-        Sysno::unlinkat => sys_unlinkat(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        Sysno::link => sys_link(args.cuptr(0), args.cuptr(1)),
+        Sysno::unlinkat => sys_unlinkat(args.fd(0), args.cuptr(1), args.flags32(2)),
         #[cfg(target_arch = "x86_64")]
-        Sysno::unlink => sys_unlink(tf.arg0().into()),
-        Sysno::getcwd => sys_getcwd(tf.arg0().into(), tf.arg1() as _),
+        Sysno::unlink => sys_unlink(args.cuptr(0)),
+        Sysno::renameat2 => sys_renameat2(
+            args.fd(0),
+            args.cuptr(1),
+            args.fd(2),
+            args.cuptr(3),
+            args.flags32(4),
+        ),
+        #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+        Sysno::renameat => sys_renameat(args.fd(0), args.cuptr(1), args.fd(2), args.cuptr(3)),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::rename => sys_rename(args.cuptr(0), args.cuptr(1)),
+        Sysno::getcwd => sys_getcwd(args.uptr(0), args.len(1)?),
+        Sysno::readlinkat => sys_readlinkat(args.fd(0), args.cuptr(1), args.uptr(2), args.usize(3)),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::readlink => sys_readlink(args.cuptr(0), args.uptr(1), args.usize(2)),
+        Sysno::faccessat => sys_faccessat(args.fd(0), args.cuptr(1), args.uint(2), 0),
+        Sysno::faccessat2 => {
+            sys_faccessat(args.fd(0), args.cuptr(1), args.uint(2), args.flags32(3))
+        }
+        #[cfg(target_arch = "x86_64")]
+        Sysno::access => sys_access(args.cuptr(0), args.uint(1)),
+        Sysno::utimensat => {
+            sys_utimensat(args.fd(0), args.cuptr(1), args.cuptr(2), args.flags32(3))
+        }
+        Sysno::fchownat => sys_fchownat(
+            args.fd(0),
+            args.cuptr(1),
+            args.uint(2),
+            args.uint(3),
+            args.flags32(4),
+        ),
+        Sysno::fchmodat => sys_fchmodat(args.fd(0), args.cuptr(1), args.uint(2), 0),
+        Sysno::fchmod => sys_fchmod(args.fd(0), args.uint(1)),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::chmod => sys_chmod(args.cuptr(0), args.uint(1)),
+        Sysno::fchmodat2 => sys_fchmodat(args.fd(0), args.cuptr(1), args.uint(2), args.flags32(3)),
 
         // fd ops
-        Sysno::openat => sys_openat(
-            tf.arg0() as _,
-            tf.arg1().into(),
-            tf.arg2() as _,
-            tf.arg3() as _,
-        ),
+        Sysno::openat => sys_openat(args.fd(0), args.cuptr(1), args.int(2), args.uint(3)),
         #[cfg(target_arch = "x86_64")]
-        Sysno::open => sys_open(tf.arg0().into(), tf.arg1() as _, tf.arg2() as _),
-        Sysno::close => sys_close(tf.arg0() as _),
-        Sysno::dup => sys_dup(tf.arg0() as _),
+        Sysno::open => sys_open(args.cuptr(0), args.int(1), args.uint(2)),
+        Sysno::close => sys_close(args.fd(0)),
+        Sysno::dup => sys_dup(args.fd(0)),
         #[cfg(target_arch = "x86_64")]
-        Sysno::dup2 => sys_dup2(tf.arg0() as _, tf.arg1() as _),
-        Sysno::dup3 => sys_dup2(tf.arg0() as _, tf.arg1() as _),
-        Sysno::fcntl => sys_fcntl(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::dup2 => sys_dup2(args.fd(0), args.fd(1)),
+        Sysno::dup3 => sys_dup3(args.fd(0), args.fd(1), args.int(2)),
+        Sysno::fcntl => sys_fcntl(args.fd(0), args.int(1), args.usize(2)),
+        Sysno::flock => sys_flock(args.fd(0), args.uint(1)),
 
         // io
-        Sysno::read => sys_read(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
-        Sysno::readv => sys_readv(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
-        Sysno::write => sys_write(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
-        Sysno::writev => sys_writev(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
-        Sysno::lseek => sys_lseek(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
+        Sysno::read => sys_read(args.fd(0), args.uptr(1), args.len(2)?),
+        Sysno::readv => sys_readv(args.fd(0), args.uptr(1), args.usize(2)),
+        Sysno::write => sys_write(args.fd(0), args.cuptr(1), args.len(2)?),
+        Sysno::writev => sys_writev(args.fd(0), args.cuptr(1), args.usize(2)),
+        Sysno::lseek => sys_lseek(args.fd(0), args.off(1), args.int(2)),
+        Sysno::fsync => sys_fsync(args.fd(0)),
+        Sysno::fdatasync => sys_fdatasync(args.fd(0)),
+        Sysno::sync => sys_sync(),
+        Sysno::syncfs => sys_syncfs(args.fd(0)),
+        Sysno::fallocate => sys_fallocate(args.fd(0), args.flags32(1), args.off(2), args.off(3)),
 
         // fs mount
         Sysno::mount => sys_mount(
-            tf.arg0().into(),
-            tf.arg1().into(),
-            tf.arg2().into(),
-            tf.arg3() as _,
-            tf.arg4().into(),
-        ) as _,
-        Sysno::umount2 => sys_umount2(tf.arg0().into(), tf.arg1() as _) as _,
+            args.cuptr(0),
+            args.cuptr(1),
+            args.cuptr(2),
+            args.int(3),
+            args.cuptr(4),
+        ),
+        Sysno::umount2 => sys_umount2(args.cuptr(0), args.int(1)),
 
         // pipe
-        Sysno::pipe2 => sys_pipe2(tf.arg0().into(), tf.arg1() as _),
+        Sysno::pipe2 => sys_pipe2(args.uptr(0), args.int(1)),
         #[cfg(target_arch = "x86_64")]
-        Sysno::pipe => sys_pipe2(tf.arg0().into(), 0),
+        Sysno::pipe => sys_pipe2(args.uptr(0), 0),
 
-        // fs stat
+        // inotify
+        Sysno::inotify_init1 => sys_inotify_init1(args.flags32(0)),
         #[cfg(target_arch = "x86_64")]
-        Sysno::stat => sys_stat(tf.arg0().into(), tf.arg1().into()),
-        Sysno::fstat => sys_fstat(tf.arg0() as _, tf.arg1().into()),
+        Sysno::inotify_init => sys_inotify_init1(0),
+        Sysno::inotify_add_watch => sys_inotify_add_watch(args.fd(0), args.cuptr(1), args.uint(2)),
+        Sysno::inotify_rm_watch => sys_inotify_rm_watch(args.fd(0), args.int(1)),
+
+        // xattr
+        Sysno::getxattr => sys_getxattr(args.cuptr(0), args.cuptr(1), args.uptr(2), args.usize(3)),
+        Sysno::lgetxattr => {
+            sys_lgetxattr(args.cuptr(0), args.cuptr(1), args.uptr(2), args.usize(3))
+        }
+        Sysno::fgetxattr => sys_fgetxattr(args.fd(0), args.cuptr(1), args.uptr(2), args.usize(3)),
+        Sysno::setxattr => sys_setxattr(
+            args.cuptr(0),
+            args.cuptr(1),
+            args.cuptr(2),
+            args.usize(3),
+            args.flags32(4),
+        ),
+        Sysno::lsetxattr => sys_lsetxattr(
+            args.cuptr(0),
+            args.cuptr(1),
+            args.cuptr(2),
+            args.usize(3),
+            args.flags32(4),
+        ),
+        Sysno::fsetxattr => sys_fsetxattr(
+            args.fd(0),
+            args.cuptr(1),
+            args.cuptr(2),
+            args.usize(3),
+            args.flags32(4),
+        ),
+        Sysno::listxattr => sys_listxattr(args.cuptr(0), args.uptr(1), args.usize(2)),
+        Sysno::llistxattr => sys_llistxattr(args.cuptr(0), args.uptr(1), args.usize(2)),
+        Sysno::flistxattr => sys_flistxattr(args.fd(0), args.uptr(1), args.usize(2)),
+        Sysno::removexattr => sys_removexattr(args.cuptr(0), args.cuptr(1)),
+        Sysno::lremovexattr => sys_lremovexattr(args.cuptr(0), args.cuptr(1)),
+        Sysno::fremovexattr => sys_fremovexattr(args.fd(0), args.cuptr(1)),
+
+        // net
+        Sysno::socket => sys_socket(args.uint(0), args.uint(1), args.uint(2)),
+        Sysno::bind => sys_bind(args.fd(0), args.cuptr(1), args.uint(2)),
+        Sysno::connect => sys_connect(args.fd(0), args.cuptr(1), args.uint(2)),
+        Sysno::listen => sys_listen(args.fd(0), args.int(1)),
+        Sysno::shutdown => sys_shutdown(args.fd(0), args.uint(1)),
+        Sysno::accept => sys_accept(args.fd(0), args.uptr(1), args.uptr(2)),
+        Sysno::accept4 => sys_accept4(args.fd(0), args.uptr(1), args.uptr(2), args.flags32(3)),
+        Sysno::getsockname => sys_getsockname(args.fd(0), args.uptr(1), args.uptr(2)),
+        Sysno::getpeername => sys_getpeername(args.fd(0), args.uptr(1), args.uptr(2)),
+        Sysno::getsockopt => sys_getsockopt(
+            args.fd(0),
+            args.uint(1),
+            args.uint(2),
+            args.uptr(3),
+            args.uptr(4),
+        ),
+        Sysno::setsockopt => sys_setsockopt(
+            args.fd(0),
+            args.uint(1),
+            args.uint(2),
+            args.cuptr(3),
+            args.uint(4),
+        ),
+        Sysno::socketpair => sys_socketpair(args.uint(0), args.uint(1), args.uint(2), args.uptr(3)),
+        Sysno::sendmsg => sys_sendmsg(args.fd(0), args.cuptr(1), args.flags32(2)),
+        Sysno::recvmsg => sys_recvmsg(args.fd(0), args.uptr(1), args.flags32(2)),
+        Sysno::recvfrom => sys_recvfrom(
+            args.fd(0),
+            args.uptr(1),
+            args.len(2)?,
+            args.flags32(3),
+            args.uptr(4),
+            args.uptr(5),
+        ),
+
+        // poll
+        Sysno::ppoll => sys_ppoll(
+            tf,
+            args.uptr(0),
+            args.usize(1),
+            args.cuptr(2),
+            args.cuptr(3),
+            args.usize(4),
+        ),
         #[cfg(target_arch = "x86_64")]
-        Sysno::lstat => sys_lstat(tf.arg0().into(), tf.arg1().into()),
+        Sysno::poll => sys_poll(args.uptr(0), args.usize(1), args.int(2)),
+        Sysno::pselect6 => sys_pselect6(
+            tf,
+            args.int(0),
+            args.uptr(1),
+            args.uptr(2),
+            args.uptr(3),
+            args.cuptr(4),
+            args.cuptr(5),
+        ),
         #[cfg(target_arch = "x86_64")]
-        Sysno::newfstatat => sys_fstatat(
-            tf.arg0() as _,
-            tf.arg1().into(),
-            tf.arg2().into(),
-            tf.arg3() as _,
+        Sysno::select => sys_select(
+            args.int(0),
+            args.uptr(1),
+            args.uptr(2),
+            args.uptr(3),
+            args.cuptr(4),
         ),
-        #[cfg(not(target_arch = "x86_64"))]
-        Sysno::fstatat => sys_fstatat(
-            tf.arg0() as _,
-            tf.arg1().into(),
-            tf.arg2().into(),
-            tf.arg3() as _,
+
+        // mqueue
+        Sysno::mq_open => sys_mq_open(args.cuptr(0), args.flags32(1), args.uint(2), args.cuptr(3)),
+        Sysno::mq_unlink => sys_mq_unlink(args.cuptr(0)),
+        Sysno::mq_timedsend => sys_mq_timedsend(
+            args.fd(0),
+            args.cuptr(1),
+            args.usize(2),
+            args.uint(3),
+            args.cuptr(4),
         ),
+        Sysno::mq_timedreceive => sys_mq_timedreceive(
+            args.fd(0),
+            args.uptr(1),
+            args.usize(2),
+            args.uptr(3),
+            args.cuptr(4),
+        ),
+        Sysno::mq_notify => sys_mq_notify(args.fd(0), args.cuptr(1)),
+        Sysno::mq_getsetattr => sys_mq_getsetattr(args.fd(0), args.cuptr(1), args.uptr(2)),
+
+        // io_uring
+        #[cfg(feature = "io_uring")]
+        Sysno::io_uring_setup => sys_io_uring_setup(args.uint(0), args.uptr(1)),
+        #[cfg(feature = "io_uring")]
+        Sysno::io_uring_enter => {
+            sys_io_uring_enter(args.fd(0), args.uint(1), args.uint(2), args.flags32(3))
+        }
+
+        // fs stat
+        #[cfg(target_arch = "x86_64")]
+        Sysno::stat => sys_stat(args.cuptr(0), args.uptr(1)),
+        Sysno::fstat => sys_fstat(args.fd(0), args.uptr(1)),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::lstat => sys_lstat(args.cuptr(0), args.uptr(1)),
+        #[cfg(target_arch = "x86_64")]
+        Sysno::newfstatat => sys_fstatat(args.fd(0), args.cuptr(1), args.uptr(2), args.flags32(3)),
+        #[cfg(not(target_arch = "x86_64"))]
+        Sysno::fstatat => sys_fstatat(args.fd(0), args.cuptr(1), args.uptr(2), args.flags32(3)),
         Sysno::statx => sys_statx(
-            tf.arg0() as _,
-            tf.arg1().into(),
-            tf.arg2() as _,
-            tf.arg3() as _,
-            tf.arg4().into(),
+            args.fd(0),
+            args.cuptr(1),
+            args.flags32(2),
+            args.uint(3),
+            args.uptr(4),
         ),
+        Sysno::statfs => sys_statfs(args.cuptr(0), args.uptr(1)),
+        Sysno::fstatfs => sys_fstatfs(args.fd(0), args.uptr(1)),
 
         // mm
-        Sysno::brk => sys_brk(tf.arg0() as _),
+        Sysno::brk => sys_brk(args.usize(0)),
         Sysno::mmap => sys_mmap(
-            tf.arg0(),
-            tf.arg1() as _,
-            tf.arg2() as _,
-            tf.arg3() as _,
-            tf.arg4() as _,
-            tf.arg5() as _,
+            args.usize(0),
+            args.usize(1),
+            args.uint(2),
+            args.flags32(3),
+            args.fd(4),
+            args.isize(5),
         ),
-        Sysno::munmap => sys_munmap(tf.arg0(), tf.arg1() as _),
-        Sysno::mprotect => sys_mprotect(tf.arg0(), tf.arg1() as _, tf.arg2() as _),
+        Sysno::munmap => sys_munmap(args.usize(0), args.usize(1)),
+        Sysno::mprotect => sys_mprotect(args.usize(0), args.usize(1), args.uint(2)),
+        Sysno::msync => sys_msync(args.usize(0), args.usize(1), args.flags32(2)),
+        Sysno::mincore => sys_mincore(args.usize(0), args.usize(1), args.uptr(2)),
+        Sysno::mlock => sys_mlock(args.usize(0), args.usize(1)),
+        Sysno::munlock => sys_munlock(args.usize(0), args.usize(1)),
 
         // task info
         Sysno::getpid => sys_getpid(),
         Sysno::getppid => sys_getppid(),
         Sysno::gettid => sys_gettid(),
+        Sysno::getpgid => sys_getpgid(args.int(0)),
+        Sysno::setpgid => sys_setpgid(args.int(0), args.int(1)),
+        Sysno::getsid => sys_getsid(args.int(0)),
+        Sysno::setsid => sys_setsid(),
 
         // task sched
         Sysno::sched_yield => sys_sched_yield(),
-        Sysno::nanosleep => sys_nanosleep(tf.arg0().into(), tf.arg1().into()),
+        Sysno::nanosleep => sys_nanosleep(args.cuptr(0), args.uptr(1)),
+        Sysno::sched_getaffinity => sys_sched_getaffinity(args.int(0), args.len(1)?, args.uptr(2)),
 
         // task ops
-        Sysno::execve => sys_execve(tf, tf.arg0().into(), tf.arg1().into(), tf.arg2().into()),
-        Sysno::set_tid_address => sys_set_tid_address(tf.arg0()),
+        Sysno::execve => sys_execve(tf, args.cuptr(0), args.cuptr(1), args.cuptr(2)),
+        Sysno::set_tid_address => sys_set_tid_address(args.usize(0)),
+        Sysno::prctl => sys_prctl(
+            args.uint(0),
+            args.usize(1),
+            args.usize(2),
+            args.usize(3),
+            args.usize(4),
+        ),
         #[cfg(target_arch = "x86_64")]
-        Sysno::arch_prctl => sys_arch_prctl(tf, tf.arg0() as _, tf.arg1() as _),
+        Sysno::arch_prctl => sys_arch_prctl(tf, args.int(0), args.usize(1)),
 
         // task management
         Sysno::clone => sys_clone(
             tf,
-            tf.arg0() as _,
-            tf.arg1() as _,
-            tf.arg2(),
-            tf.arg3(),
-            tf.arg4(),
+            args.flags32(0),
+            args.usize(1),
+            args.usize(2),
+            args.usize(3),
+            args.usize(4),
         ),
+        Sysno::clone3 => sys_clone3(tf, args.cuptr(0), args.usize(1)),
         #[cfg(target_arch = "x86_64")]
         Sysno::fork => sys_fork(tf),
-        Sysno::exit => sys_exit(tf.arg0() as _),
-        Sysno::exit_group => sys_exit_group(tf.arg0() as _),
-        Sysno::wait4 => sys_waitpid(tf.arg0() as _, tf.arg1().into(), tf.arg2() as _),
+        Sysno::unshare => sys_unshare(args.flags32(0)),
+        Sysno::exit => sys_exit(args.int(0)),
+        Sysno::exit_group => sys_exit_group(args.int(0)),
+        Sysno::wait4 => sys_waitpid(args.int(0), args.uptr(1), args.uint(2)),
 
         // signal
-        Sysno::rt_sigprocmask => sys_rt_sigprocmask(
-            tf.arg0() as _,
-            tf.arg1().into(),
-            tf.arg2().into(),
-            tf.arg3() as _,
-        ),
-        Sysno::rt_sigaction => sys_rt_sigaction(
-            tf.arg0() as _,
-            tf.arg1().into(),
-            tf.arg2().into(),
-            tf.arg3() as _,
-        ),
-        Sysno::rt_sigpending => sys_rt_sigpending(tf.arg0().into(), tf.arg1() as _),
+        Sysno::rt_sigprocmask => {
+            sys_rt_sigprocmask(args.int(0), args.cuptr(1), args.uptr(2), args.usize(3))
+        }
+        Sysno::rt_sigaction => {
+            sys_rt_sigaction(args.uint(0), args.cuptr(1), args.uptr(2), args.usize(3))
+        }
+        Sysno::rt_sigpending => sys_rt_sigpending(args.uptr(0), args.usize(1)),
         Sysno::rt_sigreturn => sys_rt_sigreturn(tf),
-        Sysno::rt_sigtimedwait => sys_rt_sigtimedwait(
-            tf.arg0().into(),
-            tf.arg1().into(),
-            tf.arg2().into(),
-            tf.arg3() as _,
-        ),
-        Sysno::rt_sigsuspend => sys_rt_sigsuspend(tf, tf.arg0().into(), tf.arg1() as _),
-        Sysno::kill => sys_kill(tf.arg0() as _, tf.arg1() as _),
-        Sysno::tkill => sys_tkill(tf.arg0() as _, tf.arg1() as _),
-        Sysno::tgkill => sys_tgkill(tf.arg0() as _, tf.arg1() as _, tf.arg2() as _),
-        Sysno::rt_sigqueueinfo => sys_rt_sigqueueinfo(
-            tf.arg0() as _,
-            tf.arg1() as _,
-            tf.arg2().into(),
-            tf.arg3() as _,
-        ),
+        Sysno::rt_sigtimedwait => {
+            sys_rt_sigtimedwait(args.cuptr(0), args.uptr(1), args.cuptr(2), args.usize(3))
+        }
+        Sysno::rt_sigsuspend => sys_rt_sigsuspend(tf, args.cuptr(0), args.usize(1)),
+        Sysno::signalfd4 => {
+            sys_signalfd4(args.fd(0), args.cuptr(1), args.usize(2), args.flags32(3))
+        }
+        #[cfg(target_arch = "x86_64")]
+        Sysno::signalfd => sys_signalfd4(args.fd(0), args.cuptr(1), args.usize(2), 0),
+        Sysno::kill => sys_kill(args.int(0), args.uint(1)),
+        Sysno::tkill => sys_tkill(args.int(0), args.uint(1)),
+        Sysno::tgkill => sys_tgkill(args.int(0), args.int(1), args.uint(2)),
+        Sysno::rt_sigqueueinfo => {
+            sys_rt_sigqueueinfo(args.int(0), args.uint(1), args.cuptr(2), args.usize(3))
+        }
         Sysno::rt_tgsigqueueinfo => sys_rt_tgsigqueueinfo(
-            tf.arg0() as _,
-            tf.arg1() as _,
-            tf.arg2() as _,
-            tf.arg3().into(),
-            tf.arg4() as _,
+            args.int(0),
+            args.int(1),
+            args.uint(2),
+            args.cuptr(3),
+            args.usize(4),
         ),
-        Sysno::sigaltstack => sys_sigaltstack(tf.arg0().into(), tf.arg1().into()),
+        Sysno::sigaltstack => sys_sigaltstack(tf, args.cuptr(0), args.uptr(1)),
         Sysno::futex => sys_futex(
-            tf.arg0().into(),
-            tf.arg1() as _,
-            tf.arg2() as _,
-            tf.arg3().into(),
-            tf.arg4().into(),
-            tf.arg5() as _,
+            args.cuptr(0),
+            args.uint(1),
+            args.uint(2),
+            args.cuptr(3),
+            args.uptr(4),
+            args.uint(5),
         ),
+        Sysno::set_robust_list => sys_set_robust_list(args.cuptr(0), args.usize(1)),
+        Sysno::get_robust_list => sys_get_robust_list(args.int(0), args.uptr(1), args.uptr(2)),
 
         // sys
         Sysno::getuid => sys_getuid(),
         Sysno::geteuid => sys_geteuid(),
         Sysno::getgid => sys_getgid(),
         Sysno::getegid => sys_getegid(),
-        Sysno::uname => sys_uname(tf.arg0().into()),
+        Sysno::reboot => sys_reboot(args.uint(0), args.uint(1), args.uint(2), args.usize(3)),
+        Sysno::capget => sys_capget(args.uptr(0), args.uptr(1)),
+        Sysno::capset => sys_capset(args.uptr(0), args.cuptr(1)),
+        Sysno::uname => sys_uname(args.uptr(0)),
+        Sysno::sethostname => sys_sethostname(args.cuptr(0), args.usize(1)),
+        Sysno::setdomainname => sys_setdomainname(args.cuptr(0), args.usize(1)),
+        Sysno::sysinfo => sys_sysinfo(args.uptr(0)),
+        Sysno::getrandom => sys_getrandom(args.uptr(0), args.len(1)?, args.flags32(2)),
+        Sysno::getrusage => sys_getrusage(args.int(0), args.uptr(1)),
+        Sysno::getpriority => sys_getpriority(args.uint(0), args.int(1)),
+        Sysno::setpriority => sys_setpriority(args.uint(0), args.int(1), args.int(2)),
+        Sysno::prlimit64 => sys_prlimit64(args.int(0), args.uint(1), args.cuptr(2), args.uptr(3)),
 
         // time
-        Sysno::gettimeofday => sys_gettimeofday(tf.arg0().into()),
-        Sysno::times => sys_times(tf.arg0().into()),
-        Sysno::clock_gettime => sys_clock_gettime(tf.arg0() as _, tf.arg1().into()),
+        Sysno::gettimeofday => sys_gettimeofday(args.uptr(0)),
+        Sysno::times => sys_times(args.uptr(0)),
+        Sysno::clock_gettime => sys_clock_gettime(args.int(0), args.uptr(1)),
+        Sysno::clock_getres => sys_clock_getres(args.int(0), args.uptr(1)),
 
         _ => {
             warn!("Unimplemented syscall: {}", sysno);
+            *implemented = false;
             Err(LinuxError::ENOSYS)
         }
-    };
-    let ans = result.unwrap_or_else(|err| -err.code() as _);
-    time_stat_from_kernel_to_user();
-    info!("Syscall {:?} return {}", sysno, ans);
-    ans
+    }
 }
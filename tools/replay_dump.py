@@ -0,0 +1,90 @@
+#!/usr/bin/env python3
+"""Print a log recorded by the kernel built with `REPLAY=record`.
+
+The kernel writes it to /replay.log on the disk image, which can be copied
+out with e.g. `mcopy -i .arceos/disk.img ::/replay.log .` for a FAT image.
+
+Usage: tools/replay_dump.py [--task TID] replay.log
+"""
+
+import argparse
+import struct
+import sys
+
+MAGIC = b"STRYRPL1"
+ARCH_LEN = 16
+# Task id, syscall number, length of the data and result.
+RECORD = struct.Struct("<IHIq")
+
+# The syscalls whose data is decoded, by architecture.
+GENERIC = {
+    93: "exit",
+    94: "exit_group",
+    113: "clock_gettime",
+    153: "times",
+    169: "gettimeofday",
+    278: "getrandom",
+}
+SYSCALLS = {
+    "x86_64": {
+        60: "exit",
+        96: "gettimeofday",
+        100: "times",
+        228: "clock_gettime",
+        231: "exit_group",
+        318: "getrandom",
+    },
+    "riscv64": GENERIC,
+    "aarch64": GENERIC,
+    "loongarch64": GENERIC,
+}
+
+
+def decode(name, data):
+    if name in ("gettimeofday", "clock_gettime") and len(data) == 16:
+        sec, frac = struct.unpack("<qq", data)
+        width = 6 if name == "gettimeofday" else 9
+        return f"{sec}.{frac:0{width}}"
+    if name == "times" and len(data) == 32:
+        return "utime {} stime {} cutime {} cstime {}".format(*struct.unpack("<4Q", data))
+    return data.hex()
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--task", type=int, help="only print the records of this task")
+    parser.add_argument("log")
+    args = parser.parse_args()
+
+    with open(args.log, "rb") as f:
+        log = f.read()
+    if not log.startswith(MAGIC):
+        sys.exit(f"{args.log}: not a replay log")
+    arch = log[len(MAGIC) : len(MAGIC) + ARCH_LEN].rstrip(b"\0").decode()
+    names = SYSCALLS.get(arch, {})
+    print(f"arch {arch}")
+
+    # The number of records of each task so far, as the kernel reports
+    # divergences with.
+    counts = {}
+    pos = len(MAGIC) + ARCH_LEN
+    while pos < len(log):
+        if pos + RECORD.size > len(log):
+            sys.exit(f"{args.log}: truncated at byte {pos}")
+        tid, sysno, length, result = RECORD.unpack_from(log, pos)
+        pos += RECORD.size
+        data = log[pos : pos + length]
+        pos += length
+        index = counts.get(tid, 0)
+        counts[tid] = index + 1
+        if args.task is not None and tid != args.task:
+            continue
+        name = names.get(sysno, str(sysno))
+        line = f"task {tid} #{index}: {name} = {result}"
+        if data:
+            line += f" [{decode(name, data)}]"
+        print(line)
+
+
+if __name__ == "__main__":
+    main()